@@ -0,0 +1,104 @@
+//! 比较 zlib 与 zstd 压缩的 pack 的解压速度：`cargo bench --bench inflate`
+//!
+//! 生成一个近似源码仓库的 pack（相似的文本文件与少量二进制数据），分别以两种编码写出，
+//! 统计 pack 大小、压缩耗时，以及 `index_pack` 完整解析与逐个读取对象的耗时。
+
+use std::time::{Duration, Instant};
+
+use monoengine::object::codec::Codec;
+use monoengine::object::{ObjectFormat, ObjectType};
+use monoengine::pack::file::PackFile;
+use monoengine::pack::{index_pack, PackWriter};
+
+/// 生成的对象数
+const OBJECTS: usize = 4000;
+
+/// 每项测量重复的次数，取最快的一次
+const ROUNDS: usize = 5;
+
+/// 生成近似源码的文本与少量不可压缩的二进制数据
+fn objects() -> Vec<Vec<u8>> {
+    let mut state = 0x2545_f491_4f6c_dd1d_u64;
+    let mut next = move || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    };
+    let words = ["fn", "let", "match", "self", "repo", "object", "pack", "MonoResult", "Ok(())", "=>", "{", "}", "//"];
+    (0..OBJECTS)
+        .map(|i| {
+            let len = 512 + (next() % 16384) as usize;
+            if i % 20 == 0 {
+                return (0..len).map(|_| next() as u8).collect();
+            }
+            let mut text = Vec::with_capacity(len + 64);
+            while text.len() < len {
+                let indent = (next() % 4) as usize * 4;
+                text.extend(std::iter::repeat_n(b' ', indent));
+                for _ in 0..(3 + next() % 8) {
+                    text.extend_from_slice(words[(next() % words.len() as u64) as usize].as_bytes());
+                    text.push(b' ');
+                }
+                text.push(b'\n');
+            }
+            text
+        })
+        .collect()
+}
+
+/// 运行 `ROUNDS` 次并返回最快的耗时
+fn fastest(mut f: impl FnMut()) -> Duration {
+    (0..ROUNDS)
+        .map(|_| {
+            let start = Instant::now();
+            f();
+            start.elapsed()
+        })
+        .min()
+        .unwrap()
+}
+
+fn throughput(bytes: usize, elapsed: Duration) -> f64 {
+    bytes as f64 / elapsed.as_secs_f64() / (1 << 20) as f64
+}
+
+fn main() {
+    let objects = objects();
+    let total: usize = objects.iter().map(Vec::len).sum();
+    let dir = tempfile::tempdir().unwrap();
+    println!("{} objects, {:.1} MiB uncompressed", objects.len(), total as f64 / (1 << 20) as f64);
+    println!("{:<6} {:>10} {:>14} {:>14} {:>14}", "codec", "pack MiB", "deflate MiB/s", "index MiB/s", "read MiB/s");
+    for codec in [Codec::Zlib, Codec::Zstd] {
+        let mut written = None;
+        let deflate = fastest(|| {
+            let mut writer = PackWriter::with_codec(Vec::new(), objects.len() as u32, ObjectFormat::Sha1, codec).unwrap();
+            for data in &objects {
+                writer.write(ObjectType::Blob, data).unwrap();
+            }
+            written = Some(writer.finish_indexed().unwrap());
+        });
+        let (pack, index) = written.unwrap();
+        let indexing = fastest(|| {
+            index_pack(&pack, |_| Ok(None)).unwrap();
+        });
+
+        let path = dir.path().join(format!("pack-{}.pack", index.pack_checksum()));
+        std::fs::write(&path, &pack).unwrap();
+        std::fs::write(path.with_extension("idx"), index.encode()).unwrap();
+        let file = PackFile::open(&path).unwrap();
+        let reading = fastest(|| {
+            for entry in index.entries() {
+                file.read_at(entry.offset).unwrap();
+            }
+        });
+        println!(
+            "{:<6} {:>10.2} {:>14.1} {:>14.1} {:>14.1}",
+            format!("{:?}", codec).to_lowercase(),
+            pack.len() as f64 / (1 << 20) as f64,
+            throughput(total, deflate),
+            throughput(total, indexing),
+            throughput(total, reading)
+        );
+    }
+}
@@ -0,0 +1,10 @@
+//! 编译 `proto/` 下的 gRPC 服务定义
+//!
+//! 使用纯 Rust 实现的 protox 解析 proto 文件，构建环境不需要安装 protoc。
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    println!("cargo:rerun-if-changed=proto");
+    let descriptors = protox::compile(["proto/monoengine.proto"], ["proto"])?;
+    tonic_prost_build::configure().compile_fds(descriptors)?;
+    Ok(())
+}
@@ -0,0 +1,351 @@
+//! 审计日志
+//!
+//! 推送、引用更新、配置与策略修改、令牌与公钥的签发和吊销都会追加一条记录到
+//! `.mono/audit.log`（每行一个 JSON 对象），记录操作者、时间与受影响的引用、对象和路径。
+//! 日志只追加不修改，`mono audit` 按时间、操作者、路径与操作类型查询。
+//!
+//! 服务端请求的操作者为请求的身份（见 [`Access`](crate::auth::Access)），本地命令为
+//! `local:<系统用户名>`。写入审计日志失败不会撤销已完成的操作，只记录警告。
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::io::Write;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::common::config::RepoConfig;
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::object::ObjectId;
+use crate::policy::root_tree;
+use crate::refs::RefUpdate;
+use crate::repo::Repository;
+
+/// 审计日志文件，相对于 `.mono`
+pub const AUDIT_FILE: &str = "audit.log";
+/// 一条记录中最多保存的路径数，超出时逐级合并为上级目录
+const MAX_PATHS: usize = 100;
+/// 修改时记为 [`AuditAction::PolicyChange`] 的配置段
+const POLICY_SECTIONS: &[&str] = &["policy", "acl", "auth", "hooks"];
+
+/// 审计的操作类型
+#[derive(clap::ValueEnum, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum AuditAction {
+    /// 通过 git 协议推送
+    Push,
+    /// 推送以外的引用更新，如 gRPC 创建提交、合并队列与本地命令
+    RefUpdate,
+    /// 推送策略、访问控制与认证配置的修改
+    PolicyChange,
+    /// 其他仓库配置的修改
+    ConfigChange,
+    TokenCreate,
+    TokenRevoke,
+    KeyAdd,
+    KeyRemove,
+}
+
+impl AuditAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::Push => "push",
+            AuditAction::RefUpdate => "ref-update",
+            AuditAction::PolicyChange => "policy-change",
+            AuditAction::ConfigChange => "config-change",
+            AuditAction::TokenCreate => "token-create",
+            AuditAction::TokenRevoke => "token-revoke",
+            AuditAction::KeyAdd => "key-add",
+            AuditAction::KeyRemove => "key-remove",
+        }
+    }
+}
+
+impl fmt::Display for AuditAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(self.as_str())
+    }
+}
+
+/// 一条审计记录
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct AuditEvent {
+    /// 发生时间（Unix 秒）
+    pub time: i64,
+    pub actor: String,
+    pub action: AuditAction,
+    /// 操作对象：引用名、令牌 ID、公钥名或配置段名
+    pub target: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub old: Option<ObjectId>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub new: Option<ObjectId>,
+    /// 改动的路径，较多时合并为上级目录
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub paths: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    /// 签名推送的推送证书 ID，证书保存在 `.mono/push-certs`（见 [`crate::server::push_cert`]）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cert: Option<ObjectId>,
+}
+
+impl AuditEvent {
+    pub fn new(time: i64, actor: impl Into<String>, action: AuditAction, target: impl Into<String>) -> AuditEvent {
+        AuditEvent {
+            time,
+            actor: actor.into(),
+            action,
+            target: target.into(),
+            old: None,
+            new: None,
+            paths: Vec::new(),
+            detail: None,
+            cert: None,
+        }
+    }
+
+    pub fn with_detail(mut self, detail: impl Into<String>) -> AuditEvent {
+        self.detail = Some(detail.into());
+        self
+    }
+
+    /// 是否涉及 `path` 或其下的路径；合并后的上级目录同样视为涉及
+    pub fn touches(&self, path: &str) -> bool {
+        let path = path.trim_matches('/');
+        let within = |inner: &str, outer: &str| {
+            outer.is_empty() || inner == outer || inner.strip_prefix(outer).is_some_and(|rest| rest.starts_with('/'))
+        };
+        self.paths.iter().any(|p| within(p, path) || within(path, p))
+    }
+}
+
+/// 本地命令的操作者
+pub fn local_actor() -> String {
+    let user = std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_else(|_| "unknown".to_string());
+    format!("local:{}", user)
+}
+
+/// 路径超过 `max` 条时逐级替换为上级目录，直到不超过 `max` 或全部合并为根目录（空字符串）
+fn compact_paths(mut paths: Vec<String>, max: usize) -> Vec<String> {
+    while paths.len() > max {
+        let depth = paths.iter().map(|p| p.matches('/').count()).max().unwrap_or(0);
+        if depth == 0 {
+            return vec![String::new()];
+        }
+        // 先合并最深的一层
+        paths = paths
+            .into_iter()
+            .map(|p| match p.rsplit_once('/') {
+                Some((parent, _)) if p.matches('/').count() == depth => parent.to_string(),
+                _ => p,
+            })
+            .collect();
+        paths.sort();
+        paths.dedup();
+    }
+    paths
+}
+
+/// 查询条件，未设置的条件不过滤
+#[derive(Debug, Clone, Default)]
+pub struct AuditFilter {
+    /// 只包含不早于该时间（Unix 秒）的记录
+    pub since: Option<i64>,
+    /// 操作者，`*` 结尾时按前缀匹配
+    pub actor: Option<String>,
+    pub path: Option<String>,
+    pub action: Option<AuditAction>,
+}
+
+impl AuditFilter {
+    pub fn matches(&self, event: &AuditEvent) -> bool {
+        self.since.is_none_or(|since| event.time >= since)
+            && self.actor.as_deref().is_none_or(|actor| match actor.strip_suffix('*') {
+                Some(prefix) => event.actor.starts_with(prefix),
+                None => event.actor == actor,
+            })
+            && self.path.as_deref().is_none_or(|path| event.touches(path))
+            && self.action.is_none_or(|action| event.action == action)
+    }
+}
+
+/// 仓库的审计日志
+pub struct AuditLog {
+    path: PathBuf,
+}
+
+impl AuditLog {
+    pub fn new(repo: &Repository) -> AuditLog {
+        AuditLog {
+            path: repo.mono_dir().join(AUDIT_FILE),
+        }
+    }
+
+    /// 追加一条记录；每条记录以一次写入完成，并发追加不会交错
+    pub fn append(&self, event: &AuditEvent) -> MonoResult<()> {
+        let mut line = serde_json::to_vec(event).map_err(|e| MonoError::storage(e.to_string()))?;
+        line.push(b'\n');
+        let mut file = std::fs::OpenOptions::new().create(true).append(true).open(&self.path)?;
+        file.write_all(&line)?;
+        Ok(())
+    }
+
+    /// 追加记录，失败时只记录警告
+    pub fn record(&self, event: &AuditEvent) {
+        if let Err(e) = self.append(event) {
+            tracing::warn!(action = %event.action, target = %event.target, error = %e, "failed to write audit log");
+        }
+    }
+
+    /// 记录一组引用更新，改动的路径由新旧提交的树比较得到
+    pub fn record_ref_updates(&self, repo: &Repository, actor: &str, action: AuditAction, updates: &[RefUpdate], now: i64) {
+        self.record_updates(repo, actor, action, updates, now, |_| {});
+    }
+
+    /// 记录一次签名推送的引用更新，每条记录带有推送证书 ID 与签名者
+    pub fn record_signed_push(&self, repo: &Repository, actor: &str, updates: &[RefUpdate], cert: &ObjectId, signer: &str, now: i64) {
+        self.record_updates(repo, actor, AuditAction::Push, updates, now, |event| {
+            event.cert = Some(*cert);
+            event.detail = Some(format!("signed by {}", signer));
+        });
+    }
+
+    fn record_updates(
+        &self,
+        repo: &Repository,
+        actor: &str,
+        action: AuditAction,
+        updates: &[RefUpdate],
+        now: i64,
+        decorate: impl Fn(&mut AuditEvent),
+    ) {
+        for update in updates {
+            let mut event = AuditEvent::new(now, actor, action, &update.name);
+            decorate(&mut event);
+            event.old = Some(update.old).filter(|id| !id.is_zero());
+            event.new = Some(update.new).filter(|id| !id.is_zero());
+            match changed_paths(repo, update) {
+                Ok(paths) => event.paths = compact_paths(paths, MAX_PATHS),
+                Err(e) => tracing::warn!(name = %update.name, error = %e, "failed to compute changed paths for audit log"),
+            }
+            self.record(&event);
+        }
+    }
+
+    /// 记录仓库配置的修改，每个改动的顶层配置段一条记录
+    pub fn record_config_changes(&self, before: &RepoConfig, after: &RepoConfig, actor: &str, now: i64) {
+        let (Ok(Value::Object(before)), Ok(Value::Object(after))) = (serde_json::to_value(before), serde_json::to_value(after)) else {
+            return;
+        };
+        let sections: BTreeSet<&String> = before.keys().chain(after.keys()).collect();
+        for section in sections {
+            if before.get(section) == after.get(section) {
+                continue;
+            }
+            let action = if POLICY_SECTIONS.contains(&section.as_str()) {
+                AuditAction::PolicyChange
+            } else {
+                AuditAction::ConfigChange
+            };
+            self.record(&AuditEvent::new(now, actor, action, section.as_str()));
+        }
+    }
+
+    /// 按时间顺序返回满足条件的记录，跳过无法解析的行
+    pub fn query(&self, filter: &AuditFilter) -> MonoResult<Vec<AuditEvent>> {
+        let content = match std::fs::read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(content
+            .lines()
+            .filter_map(|line| match serde_json::from_str::<AuditEvent>(line) {
+                Ok(event) => Some(event),
+                Err(e) => {
+                    tracing::warn!(error = %e, "skipping corrupt audit log line");
+                    None
+                }
+            })
+            .filter(|event| filter.matches(event))
+            .collect())
+    }
+}
+
+fn changed_paths(repo: &Repository, update: &RefUpdate) -> MonoResult<Vec<String>> {
+    let tree = |id: &ObjectId| root_tree(repo, id);
+    repo.changed_paths(tree(&update.old)?.as_ref(), tree(&update.new)?.as_ref())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{commit_files, init_repo};
+
+    /// 测试记录引用更新并按操作者、路径、时间与类型查询
+    #[test]
+    fn test_audit_log() {
+        let (_dir, repo) = init_repo();
+        let log = AuditLog::new(&repo);
+        assert!(log.query(&AuditFilter::default()).unwrap().is_empty());
+
+        let base = commit_files(&repo, &[("services/api/main.rs", b"1"), ("docs/a.md", b"a")], &[], "base");
+        let api = commit_files(&repo, &[("services/api/main.rs", b"2"), ("docs/a.md", b"a")], &[base], "api");
+        let update = |old, new| RefUpdate {
+            name: "refs/heads/main".to_string(),
+            old,
+            new,
+        };
+        log.record_ref_updates(&repo, "user:alice", AuditAction::Push, &[update(ObjectId::ZERO, base)], 100);
+        log.record_ref_updates(&repo, "token:1234", AuditAction::RefUpdate, &[update(base, api)], 200);
+        log.record(&AuditEvent::new(300, "local:root", AuditAction::TokenCreate, "abcd1234").with_detail("ci"));
+
+        let all = log.query(&AuditFilter::default()).unwrap();
+        assert_eq!(all.len(), 3);
+        assert_eq!(all[0].old, None);
+        assert_eq!(all[0].paths, ["docs/a.md", "services/api/main.rs"]);
+        assert_eq!(all[1].paths, ["services/api/main.rs"]);
+
+        let query = |filter: AuditFilter| log.query(&filter).unwrap().iter().map(|e| e.time).collect::<Vec<_>>();
+        let path = |path: &str| AuditFilter {
+            path: Some(path.to_string()),
+            ..Default::default()
+        };
+        assert_eq!(query(path("//services/api/")), [100, 200]);
+        assert_eq!(query(path("docs")), [100]);
+        assert_eq!(query(path("services/web")), Vec::<i64>::new());
+        let actor = |actor: &str| AuditFilter {
+            actor: Some(actor.to_string()),
+            ..Default::default()
+        };
+        assert_eq!(query(actor("token:1234")), [200]);
+        assert_eq!(query(actor("local:*")), [300]);
+        let since = AuditFilter {
+            since: Some(200),
+            action: Some(AuditAction::TokenCreate),
+            ..Default::default()
+        };
+        assert_eq!(query(since), [300]);
+    }
+
+    /// 测试路径较多时合并为上级目录
+    #[test]
+    fn test_compact_paths() {
+        let paths = |v: &[&str]| v.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let input = paths(&["a/b/1", "a/b/2", "a/c", "d"]);
+        assert_eq!(compact_paths(input.clone(), 4), input);
+        assert_eq!(compact_paths(input.clone(), 3), paths(&["a/b", "a/c", "d"]));
+        assert_eq!(compact_paths(input.clone(), 2), paths(&["a", "d"]));
+        assert_eq!(compact_paths(input, 1), paths(&[""]));
+        let event = AuditEvent {
+            paths: paths(&["a"]),
+            ..AuditEvent::new(0, "x", AuditAction::Push, "refs/heads/main")
+        };
+        assert!(event.touches("a/b/1") && event.touches("//a/") && !event.touches("ab"));
+    }
+}
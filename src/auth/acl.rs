@@ -0,0 +1,339 @@
+//! 按路径与引用授权的访问控制规则
+//!
+//! `mono.toml` 中的 `[[acl]]` 规则只允许列出的身份读取或修改规则范围内的内容，例如只有
+//! 安全团队可以看到 `//secrets/...`，只有发布机器人可以推送 `refs/heads/release/`：
+//!
+//! ```toml
+//! [[acl]]
+//! name = "secrets"
+//! paths = ["//secrets/..."]
+//! read = ["user:alice"]
+//! write = ["user:alice"]
+//!
+//! [[acl]]
+//! name = "release"
+//! refs = ["refs/heads/release/"]
+//! read = ["*"]
+//! write = ["user:release-bot"]
+//! ```
+//!
+//! 一个操作落在多条规则的范围内时，每条规则都必须允许。没有 `paths` 的规则控制引用本身：
+//! 不可读的引用不会出现在 ls-refs 中。有 `paths` 的规则控制树中的路径：修改时按推送的
+//! 引用匹配 `refs`；读取时不区分引用（对象在引用之间共享），upload-pack 不发送不可读路径下
+//! 的树和 blob，受限的用户需要使用部分克隆，并以稀疏索引排除这些路径
+//! （`git sparse-checkout init --cone --sparse-index`）。拥有 `admin` 权限的身份不受规则限制。
+//!
+//! REST、gRPC 与 GraphQL 的只读接口经由 [`ReadView`] 解析修订与路径，不可读的引用与路径
+//! 与不存在一样返回 not found，目录列表与差异中也不会出现。
+
+use crate::auth::Access;
+use crate::common::config::{AclConfig, Scope};
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::graph::history::History;
+use crate::object::tree::TreeEntry;
+use crate::object::{ObjectId, ObjectType};
+use crate::refs::RefTarget;
+use crate::repo::Repository;
+use crate::sparse::SparsePattern;
+
+/// 一条访问控制规则
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AclRule {
+    pub name: String,
+    /// 为空时匹配全部引用，以 `/` 结尾时匹配该前缀下的全部引用
+    pub refs: Vec<String>,
+    /// 为空时规则控制引用本身
+    pub paths: Vec<SparsePattern>,
+    pub read: Vec<String>,
+    pub write: Vec<String>,
+}
+
+/// 身份是否与模式列表中的某一项匹配，`*` 结尾的模式按前缀匹配
+fn matches_principal(patterns: &[String], principal: &str) -> bool {
+    patterns.iter().any(|pattern| match pattern.strip_suffix('*') {
+        Some(prefix) => principal.starts_with(prefix),
+        None => principal == pattern,
+    })
+}
+
+impl AclRule {
+    /// 校验并转换配置中的规则
+    pub fn from_config(config: &AclConfig) -> MonoResult<AclRule> {
+        if config.name.is_empty() {
+            return Err(MonoError::config("acl rule without a name"));
+        }
+        let paths = config
+            .paths
+            .iter()
+            .map(|path| {
+                path.parse::<SparsePattern>()
+                    .map_err(|e| MonoError::config(format!("acl '{}': {}", config.name, e)))
+            })
+            .collect::<MonoResult<_>>()?;
+        Ok(AclRule {
+            name: config.name.clone(),
+            refs: config.refs.clone(),
+            paths,
+            read: config.read.clone(),
+            write: config.write.clone(),
+        })
+    }
+
+    /// 规则是否覆盖该引用
+    pub fn applies_to(&self, refname: &str) -> bool {
+        self.refs.is_empty()
+            || self.refs.iter().any(|pattern| match pattern.strip_suffix('/') {
+                Some(_) => refname.starts_with(pattern.as_str()),
+                None => refname == pattern,
+            })
+    }
+
+    /// 规则是否覆盖树中的路径
+    fn covers(&self, path: &str) -> bool {
+        self.paths.iter().any(|pattern| pattern.matches(path))
+    }
+
+    fn can_read(&self, principal: &str) -> bool {
+        matches_principal(&self.read, principal) || self.can_write(principal)
+    }
+
+    fn can_write(&self, principal: &str) -> bool {
+        matches_principal(&self.write, principal)
+    }
+}
+
+/// 仓库的全部访问控制规则
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Acl {
+    pub rules: Vec<AclRule>,
+}
+
+impl Acl {
+    pub fn from_config(configs: &[AclConfig]) -> MonoResult<Acl> {
+        Ok(Acl {
+            rules: configs.iter().map(AclRule::from_config).collect::<MonoResult<_>>()?,
+        })
+    }
+
+    /// 读取仓库配置中的规则
+    pub fn load(repo: &Repository) -> MonoResult<Acl> {
+        Acl::from_config(&repo.config().acl)
+    }
+
+    /// 对 `access` 生效的规则，`admin` 权限不受规则限制
+    fn rules_for<'a>(&'a self, access: &Access) -> impl Iterator<Item = &'a AclRule> {
+        let exempt = access.scope == Some(Scope::Admin);
+        self.rules.iter().filter(move |_| !exempt)
+    }
+
+    /// 是否有规则对 `access` 隐藏了引用或路径
+    pub fn restricts(&self, access: &Access) -> bool {
+        self.rules_for(access).any(|rule| !rule.can_read(&access.principal))
+    }
+
+    /// 是否有规则对 `access` 隐藏了引用
+    pub fn hides_refs(&self, access: &Access) -> bool {
+        self.rules_for(access)
+            .any(|rule| rule.paths.is_empty() && !rule.can_read(&access.principal))
+    }
+
+    /// 引用是否可读
+    pub fn can_read_ref(&self, access: &Access, refname: &str) -> bool {
+        self.rules_for(access)
+            .filter(|rule| rule.paths.is_empty() && rule.applies_to(refname))
+            .all(|rule| rule.can_read(&access.principal))
+    }
+
+    /// 树中的路径是否可读
+    pub fn can_read_path(&self, access: &Access, path: &str) -> bool {
+        self.rules_for(access)
+            .filter(|rule| rule.covers(path))
+            .all(|rule| rule.can_read(&access.principal))
+    }
+
+    /// 检查对引用 `refname` 的更新及其改动的路径，不允许时返回 `Err(原因)`
+    pub fn check_write(&self, access: &Access, refname: &str, changed: &[String]) -> Result<(), String> {
+        for rule in self.rules_for(access).filter(|rule| rule.applies_to(refname)) {
+            if rule.can_write(&access.principal) {
+                continue;
+            }
+            if rule.paths.is_empty() {
+                return Err(format!("{} may not update {} (acl '{}')", access.principal, refname, rule.name));
+            }
+            if let Some(path) = changed.iter().find(|path| rule.covers(path)) {
+                return Err(format!("{} may not modify {} (acl '{}')", access.principal, path, rule.name));
+            }
+        }
+        Ok(())
+    }
+
+    /// 检查更新是否需要改动的路径：存在对该引用生效、限制路径且不允许 `access` 修改的规则
+    pub fn needs_paths(&self, access: &Access, refname: &str) -> bool {
+        self.rules_for(access)
+            .any(|rule| !rule.paths.is_empty() && rule.applies_to(refname) && !rule.can_write(&access.principal))
+    }
+}
+
+/// 只读接口中一个请求能看到的引用与路径
+///
+/// 有引用被隐藏时，以对象 ID 给出的修订必须是可读引用指向的对象，或可从其指向的提交到达。
+#[derive(Debug, Clone)]
+pub struct ReadView {
+    acl: Acl,
+    access: Access,
+}
+
+impl ReadView {
+    pub fn new(repo: &Repository, access: Access) -> MonoResult<ReadView> {
+        Ok(ReadView { acl: Acl::load(repo)?, access })
+    }
+
+    pub fn access(&self) -> &Access {
+        &self.access
+    }
+
+    /// 引用是否可读
+    pub fn can_read_ref(&self, name: &str) -> bool {
+        self.acl.can_read_ref(&self.access, name)
+    }
+
+    /// 树中的路径是否可读，路径可以带 `/` 前缀
+    pub fn can_read_path(&self, path: &str) -> bool {
+        self.acl.can_read_path(&self.access, path.trim_matches('/'))
+    }
+
+    /// 路径不可读时与不存在一样返回 not found
+    pub fn check_path(&self, path: &str) -> MonoResult<()> {
+        match self.can_read_path(path) {
+            true => Ok(()),
+            false => Err(MonoError::not_found(format!("path {}", path))),
+        }
+    }
+
+    /// 去掉目录 `dir` 中不可读的条目
+    pub fn filter_entries(&self, dir: &str, entries: &mut Vec<TreeEntry>) {
+        let dir = dir.trim_matches('/');
+        entries.retain(|entry| match dir.is_empty() {
+            true => self.can_read_path(&entry.name),
+            false => self.can_read_path(&format!("{}/{}", dir, entry.name)),
+        });
+    }
+
+    /// 解析修订，经过不可读的引用或不可达的对象 ID 与不存在一样返回 not found
+    ///
+    /// 租户仓库共享对象库，对象 ID 同样必须从租户自己的引用可达。
+    pub fn resolve(&self, repo: &Repository, rev: &str) -> MonoResult<ObjectId> {
+        let hidden = || MonoError::not_found(format!("revision {}", rev));
+        if let Ok(id) = ObjectId::from_hex(rev) {
+            let open = repo.namespace().is_none() && !self.acl.hides_refs(&self.access);
+            return match open || self.reachable(repo, &id)? {
+                true => Ok(id),
+                false => Err(hidden()),
+            };
+        }
+        let Some((mut name, id)) = repo.dwim_ref(rev)? else {
+            return Err(hidden());
+        };
+        // 已经解析成功，符号引用链不会成环
+        loop {
+            if !self.can_read_ref(&name) {
+                return Err(hidden());
+            }
+            match repo.refs().read(&name)? {
+                Some(RefTarget::Symbolic(target)) => name = target,
+                _ => return Ok(id),
+            }
+        }
+    }
+
+    /// 对象是否为可读引用指向的对象，或是可读引用指向的提交的祖先
+    fn reachable(&self, repo: &Repository, id: &ObjectId) -> MonoResult<bool> {
+        let mut tips = Vec::new();
+        for (name, target) in repo.refs().list("refs/")? {
+            if !self.can_read_ref(&name) {
+                continue;
+            }
+            if target == *id {
+                return Ok(true);
+            }
+            if let (commit, ObjectType::Commit) = repo.peel(&target)? {
+                tips.push(commit);
+            }
+        }
+        if repo.objects().read_header(id)?.is_none_or(|(object_type, _)| object_type != ObjectType::Commit) {
+            return Ok(false);
+        }
+        let history = History::new(repo)?;
+        for tip in &tips {
+            if history.is_ancestor(id, tip)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(name: &str, refs: &[&str], paths: &[&str], read: &[&str], write: &[&str]) -> AclConfig {
+        let strings = |v: &[&str]| v.iter().map(|s| s.to_string()).collect();
+        AclConfig {
+            name: name.to_string(),
+            refs: strings(refs),
+            paths: strings(paths),
+            read: strings(read),
+            write: strings(write),
+        }
+    }
+
+    fn access(principal: &str, scope: Scope) -> Access {
+        Access {
+            principal: principal.to_string(),
+            scope: Some(scope),
+            paths: Vec::new(),
+        }
+    }
+
+    /// 测试按路径与引用判断读写权限
+    #[test]
+    fn test_acl() {
+        let acl = Acl::from_config(&[
+            rule("secrets", &[], &["//secrets/..."], &["user:bob"], &["user:alice"]),
+            rule("release", &["refs/heads/release/"], &[], &["*"], &["user:release-bot"]),
+            rule("hidden", &["refs/heads/private"], &[], &["user:*"], &[]),
+        ])
+        .unwrap();
+        let alice = access("user:alice", Scope::Write);
+        let bob = access("user:bob", Scope::Write);
+        let bot = access("user:release-bot", Scope::Write);
+        let anonymous = access("anonymous", Scope::Read);
+
+        assert!(acl.can_read_path(&alice, "secrets/key"));
+        assert!(acl.can_read_path(&bob, "secrets"));
+        assert!(!acl.can_read_path(&bot, "secrets/key"));
+        assert!(acl.can_read_path(&bot, "secrets2/key"));
+        assert!(acl.can_read_path(&bot, "README.md"));
+        assert!(acl.restricts(&bot) && !acl.restricts(&bob) && !acl.restricts(&access("ssh-key", Scope::Admin)));
+
+        assert!(acl.can_read_ref(&bot, "refs/heads/private"));
+        assert!(!acl.can_read_ref(&anonymous, "refs/heads/private"));
+        assert!(acl.can_read_ref(&anonymous, "refs/heads/release/1.0"));
+
+        let changed = vec!["src/main.rs".to_string(), "secrets/key".to_string()];
+        assert_eq!(acl.check_write(&alice, "refs/heads/main", &changed), Ok(()));
+        assert_eq!(
+            acl.check_write(&bob, "refs/heads/main", &changed),
+            Err("user:bob may not modify secrets/key (acl 'secrets')".to_string())
+        );
+        assert_eq!(acl.check_write(&bob, "refs/heads/main", &changed[..1]), Ok(()));
+        assert_eq!(
+            acl.check_write(&alice, "refs/heads/release/1.0", &changed[..1]),
+            Err("user:alice may not update refs/heads/release/1.0 (acl 'release')".to_string())
+        );
+        assert!(acl.needs_paths(&bob, "refs/heads/main") && !acl.needs_paths(&alice, "refs/heads/main"));
+        assert!(Acl::from_config(&[rule("bad", &[], &["secrets"], &[], &[])]).is_err());
+    }
+}
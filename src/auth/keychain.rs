@@ -0,0 +1,65 @@
+//! 保存登录凭据的钥匙串
+//!
+//! `mono login` 取得的刷新令牌保存在操作系统的钥匙串中（macOS Keychain、Windows
+//! 凭据管理器或 Linux 内核密钥环），不写入仓库或家目录下的文件。
+
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+
+/// 钥匙串中条目的服务名
+pub const KEYCHAIN_SERVICE: &str = "monoengine";
+
+/// 按键保存密文的存储
+pub trait SecretStore {
+    fn get(&self, key: &str) -> MonoResult<Option<String>>;
+
+    fn set(&self, key: &str, secret: &str) -> MonoResult<()>;
+
+    /// 删除条目，返回条目是否存在
+    fn delete(&self, key: &str) -> MonoResult<bool>;
+}
+
+/// 操作系统的钥匙串
+pub struct Keychain {
+    service: String,
+}
+
+impl Default for Keychain {
+    fn default() -> Self {
+        Keychain {
+            service: KEYCHAIN_SERVICE.to_string(),
+        }
+    }
+}
+
+impl Keychain {
+    fn entry(&self, key: &str) -> MonoResult<keyring::Entry> {
+        keyring::Entry::new(&self.service, key).map_err(keychain_error)
+    }
+}
+
+fn keychain_error(e: keyring::Error) -> MonoError {
+    MonoError::unavailable(format!("keychain: {}", e))
+}
+
+impl SecretStore for Keychain {
+    fn get(&self, key: &str) -> MonoResult<Option<String>> {
+        match self.entry(key)?.get_password() {
+            Ok(secret) => Ok(Some(secret)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(keychain_error(e)),
+        }
+    }
+
+    fn set(&self, key: &str, secret: &str) -> MonoResult<()> {
+        self.entry(key)?.set_password(secret).map_err(keychain_error)
+    }
+
+    fn delete(&self, key: &str) -> MonoResult<bool> {
+        match self.entry(key)?.delete_credential() {
+            Ok(()) => Ok(true),
+            Err(keyring::Error::NoEntry) => Ok(false),
+            Err(e) => Err(keychain_error(e)),
+        }
+    }
+}
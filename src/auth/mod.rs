@@ -0,0 +1,409 @@
+//! 个人访问令牌与请求的访问权限
+//!
+//! 令牌由 `mono token create` 签发，只在创建时显示一次，仓库中只保存其 SHA-256 摘要
+//! （`.mono/tokens.json`）。每个令牌带有权限范围（见 [`Scope`]），还可以限定可修改的路径前缀：
+//! 推送或创建提交时，改动的文件必须全部位于这些前缀之下。
+//!
+//! HTTP 与 gRPC 请求通过 `Authorization: Bearer <令牌>` 携带令牌，git 客户端也可以将令牌作为
+//! Basic 认证的密码；SSH 客户端可以用令牌作为密码登录。未携带令牌的请求按 `[auth] anonymous`
+//! 配置的权限处理，SSH 公钥登录的用户拥有全部权限。
+//!
+//! 配置了 `[auth.providers]` 时，还接受外部 OIDC 身份提供方签发的 ID 令牌，见 [`oidc`]。
+//! `[[acl]]` 规则进一步按路径与引用限制各身份的读写，见 [`acl`]。
+
+pub mod acl;
+pub mod keychain;
+pub mod oidc;
+
+use std::path::PathBuf;
+
+use base64::prelude::*;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::auth::acl::Acl;
+use crate::common::config::Scope;
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::object::ObjectId;
+use crate::policy::root_tree;
+use crate::queue::LockFile;
+use crate::refs::RefUpdate;
+use crate::repo::Repository;
+
+/// 令牌文件，相对于 `.mono`
+pub const TOKENS_FILE: &str = "tokens.json";
+/// 修改令牌文件期间持有的锁
+const TOKENS_LOCK_FILE: &str = "tokens.lock";
+/// 令牌的前缀，便于密钥扫描工具识别
+pub const TOKEN_PREFIX: &str = "mono_pat_";
+
+/// 已签发的令牌，不包含令牌本身
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    /// 短 ID，用于列出与吊销
+    pub id: String,
+    /// 令牌用途的说明
+    pub name: String,
+    /// 令牌的 SHA-256 摘要（十六进制）
+    pub hash: String,
+    pub scopes: Vec<Scope>,
+    /// 可修改的路径前缀，为空时不限
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub paths: Vec<String>,
+    /// 创建时间（Unix 秒）
+    pub created_at: i64,
+    /// 过期时间（Unix 秒），None 表示不过期
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<i64>,
+}
+
+impl Token {
+    /// 令牌的最高权限
+    pub fn scope(&self) -> Option<Scope> {
+        self.scopes.iter().max().copied()
+    }
+
+    pub fn is_expired(&self, now: i64) -> bool {
+        self.expires_at.is_some_and(|expires_at| now >= expires_at)
+    }
+}
+
+/// 令牌的摘要
+fn digest(secret: &str) -> String {
+    Sha256::digest(secret.as_bytes()).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// 去掉路径前缀两端的 `/`，`//src/` 与 `src` 等价
+fn normalize_prefix(prefix: &str) -> String {
+    prefix.trim_matches('/').to_string()
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct TokenFile {
+    tokens: Vec<Token>,
+}
+
+/// 仓库的令牌存储
+pub struct TokenStore {
+    dir: PathBuf,
+}
+
+impl TokenStore {
+    pub fn new(repo: &Repository) -> TokenStore {
+        TokenStore {
+            dir: repo.mono_dir().to_path_buf(),
+        }
+    }
+
+    fn load(&self) -> MonoResult<TokenFile> {
+        let path = self.dir.join(TOKENS_FILE);
+        match std::fs::read(&path) {
+            Ok(data) => serde_json::from_slice(&data)
+                .map_err(|e| MonoError::storage(format!("corrupt token file {}: {}", path.display(), e))),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(TokenFile::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// 在锁的保护下读取、修改并写回令牌文件
+    fn update<T>(&self, f: impl FnOnce(&mut TokenFile) -> MonoResult<T>) -> MonoResult<T> {
+        let _lock = LockFile::acquire(self.dir.join(TOKENS_LOCK_FILE))?;
+        let mut file = self.load()?;
+        let result = f(&mut file)?;
+        let data = serde_json::to_vec_pretty(&file).map_err(|e| MonoError::storage(e.to_string()))?;
+        let tmp = self.dir.join(format!("{}.tmp", TOKENS_FILE));
+        std::fs::write(&tmp, data)?;
+        std::fs::rename(&tmp, self.dir.join(TOKENS_FILE))?;
+        Ok(result)
+    }
+
+    /// 全部令牌，按创建顺序排列
+    pub fn list(&self) -> MonoResult<Vec<Token>> {
+        Ok(self.load()?.tokens)
+    }
+
+    /// 签发令牌，返回令牌记录与令牌本身；令牌本身不会保存
+    pub fn create(
+        &self,
+        name: &str,
+        scopes: &[Scope],
+        paths: &[String],
+        expires_at: Option<i64>,
+        now: i64,
+    ) -> MonoResult<(Token, String)> {
+        if scopes.is_empty() {
+            return Err(MonoError::usage("a token needs at least one scope"));
+        }
+        let secret: String = rand::random::<[u8; 20]>().iter().map(|b| format!("{:02x}", b)).collect();
+        let secret = format!("{}{}", TOKEN_PREFIX, secret);
+        let mut scopes = scopes.to_vec();
+        scopes.sort();
+        scopes.dedup();
+        let token = Token {
+            id: format!("{:08x}", rand::random::<u32>()),
+            name: name.to_string(),
+            hash: digest(&secret),
+            scopes,
+            paths: paths.iter().map(|p| normalize_prefix(p)).filter(|p| !p.is_empty()).collect(),
+            created_at: now,
+            expires_at,
+        };
+        self.update(|file| {
+            file.tokens.push(token.clone());
+            Ok(())
+        })?;
+        Ok((token, secret))
+    }
+
+    /// 吊销令牌
+    pub fn revoke(&self, id: &str) -> MonoResult<Token> {
+        self.update(|file| {
+            let index = file
+                .tokens
+                .iter()
+                .position(|token| token.id == id)
+                .ok_or_else(|| MonoError::not_found(format!("token {}", id)))?;
+            Ok(file.tokens.remove(index))
+        })
+    }
+
+    /// 查找令牌，不存在或已过期时返回认证错误
+    pub fn authenticate(&self, secret: &str, now: i64) -> MonoResult<Token> {
+        let hash = digest(secret);
+        let token = self
+            .list()?
+            .into_iter()
+            .find(|token| token.hash == hash)
+            .ok_or_else(|| MonoError::auth("invalid access token"))?;
+        if token.is_expired(now) {
+            return Err(MonoError::auth(format!("access token {} has expired", token.id)));
+        }
+        Ok(token)
+    }
+}
+
+/// 一个请求的访问权限
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Access {
+    /// 请求的身份，出现在日志与错误信息中：`anonymous`、SSH 公钥名、`token:<ID>` 或 `user:<用户名>`
+    pub principal: String,
+    /// 最高权限，None 表示无权访问
+    pub scope: Option<Scope>,
+    /// 可修改的路径前缀，为空时不限
+    pub paths: Vec<String>,
+}
+
+impl Access {
+    /// 拥有全部权限
+    pub fn full(principal: impl Into<String>) -> Access {
+        Access {
+            principal: principal.into(),
+            scope: Some(Scope::Admin),
+            paths: Vec::new(),
+        }
+    }
+
+    /// 未携带令牌的请求，权限由 `[auth] anonymous` 决定
+    pub fn anonymous(repo: &Repository) -> Access {
+        Access {
+            principal: "anonymous".to_string(),
+            scope: repo.config().auth.anonymous.scope(),
+            paths: Vec::new(),
+        }
+    }
+
+    pub fn token(token: &Token) -> Access {
+        Access {
+            principal: format!("token:{}", token.id),
+            scope: token.scope(),
+            paths: token.paths.clone(),
+        }
+    }
+
+    pub fn is_anonymous(&self) -> bool {
+        self.principal == "anonymous"
+    }
+
+    /// 检查是否具有 `scope` 权限
+    pub fn require(&self, scope: Scope) -> MonoResult<()> {
+        match self.denial(scope) {
+            Some(reason) => Err(MonoError::auth(reason)),
+            None => Ok(()),
+        }
+    }
+
+    /// 不具有 `scope` 权限时的原因
+    fn denial(&self, scope: Scope) -> Option<String> {
+        if self.scope.is_some_and(|granted| granted >= scope) {
+            None
+        } else if self.is_anonymous() {
+            Some(format!("authentication required for {} access", scope))
+        } else {
+            Some(format!("{} lacks the {} scope", self.principal, scope))
+        }
+    }
+
+    /// 是否可以修改 `path`
+    pub fn allows_path(&self, path: &str) -> bool {
+        let path = path.trim_start_matches('/');
+        self.paths.is_empty()
+            || self
+                .paths
+                .iter()
+                .any(|prefix| path == prefix || path.strip_prefix(prefix.as_str()).is_some_and(|rest| rest.starts_with('/')))
+    }
+
+    /// 检查一条引用更新：删除引用需要 `admin` 权限，改动的文件必须位于可修改的路径前缀之下，
+    /// 并且符合 `[[acl]]` 规则（见 [`acl`]）；不允许时返回 `Ok(Err(原因))`
+    pub fn check_update(&self, repo: &Repository, update: &RefUpdate) -> MonoResult<Result<(), String>> {
+        let required = if update.is_delete() { Scope::Admin } else { Scope::Write };
+        if let Some(reason) = self.denial(required) {
+            return Ok(Err(reason));
+        }
+        let acl = Acl::load(repo)?;
+        if self.paths.is_empty() && !acl.needs_paths(self, &update.name) {
+            return Ok(acl.check_write(self, &update.name, &[]));
+        }
+        let tree = |id: &ObjectId| root_tree(repo, id);
+        let changed = repo.changed_paths(tree(&update.old)?.as_ref(), tree(&update.new)?.as_ref())?;
+        if let Some(path) = changed.iter().find(|path| !self.allows_path(path)) {
+            return Ok(Err(format!("{} may not modify {}", self.principal, path)));
+        }
+        Ok(acl.check_write(self, &update.name, &changed))
+    }
+}
+
+/// 按请求携带的令牌确定访问权限：个人访问令牌或 OIDC ID 令牌，未携带令牌时为匿名访问
+pub fn authenticate(repo: &Repository, token: Option<&str>, now: i64) -> MonoResult<Access> {
+    match token {
+        Some(id_token) if !id_token.starts_with(TOKEN_PREFIX) && oidc::is_jwt(id_token) => {
+            oidc::authenticate(repo, id_token, now)
+        }
+        Some(secret) => Ok(Access::token(&TokenStore::new(repo).authenticate(secret, now)?)),
+        None => Ok(Access::anonymous(repo)),
+    }
+}
+
+/// 从 `Authorization` 头中取出令牌：`Bearer <令牌>`，或 Basic 认证的密码（密码为空时为用户名）
+pub fn parse_authorization(value: &str) -> Option<String> {
+    let (scheme, credentials) = value.trim().split_once(' ')?;
+    let credentials = credentials.trim();
+    if scheme.eq_ignore_ascii_case("bearer") {
+        return Some(credentials.to_string()).filter(|token| !token.is_empty());
+    }
+    if !scheme.eq_ignore_ascii_case("basic") {
+        return None;
+    }
+    let decoded = String::from_utf8(BASE64_STANDARD.decode(credentials).ok()?).ok()?;
+    let (user, password) = decoded.split_once(':').unwrap_or((&decoded, ""));
+    Some(if password.is_empty() { user } else { password }.to_string()).filter(|token| !token.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{commit_files, init_repo};
+
+    /// 测试令牌的签发、认证、过期与吊销，文件中只保存摘要
+    #[test]
+    fn test_tokens() {
+        let (_dir, repo) = init_repo();
+        let store = TokenStore::new(&repo);
+        let (token, secret) = store
+            .create("ci", &[Scope::Write, Scope::Read], &["//services/api/".to_string()], Some(2000), 1000)
+            .unwrap();
+        assert!(secret.starts_with(TOKEN_PREFIX));
+        assert_eq!(token.scopes, [Scope::Read, Scope::Write]);
+        assert_eq!(token.paths, ["services/api"]);
+        let content = std::fs::read_to_string(repo.mono_dir().join(TOKENS_FILE)).unwrap();
+        assert!(!content.contains(&secret));
+
+        assert_eq!(store.authenticate(&secret, 1500).unwrap(), token);
+        assert!(store.authenticate("mono_pat_wrong", 1500).is_err());
+        assert!(store.authenticate(&secret, 2000).unwrap_err().to_string().contains("expired"));
+        assert!(store.create("empty", &[], &[], None, 1000).is_err());
+
+        let access = authenticate(&repo, Some(&secret), 1500).unwrap();
+        assert_eq!(access.principal, format!("token:{}", token.id));
+        assert!(access.require(Scope::Write).is_ok());
+        assert!(access.require(Scope::Admin).is_err());
+
+        assert_eq!(store.revoke(&token.id).unwrap(), token);
+        assert!(store.list().unwrap().is_empty());
+        assert!(store.authenticate(&secret, 1500).is_err());
+        assert!(store.revoke(&token.id).is_err());
+    }
+
+    /// 测试从 Authorization 头中取出令牌
+    #[test]
+    fn test_parse_authorization() {
+        assert_eq!(parse_authorization("Bearer mono_pat_1"), Some("mono_pat_1".to_string()));
+        let basic = |credentials: &str| format!("Basic {}", BASE64_STANDARD.encode(credentials));
+        assert_eq!(parse_authorization(&basic("git:mono_pat_2")), Some("mono_pat_2".to_string()));
+        assert_eq!(parse_authorization(&basic("mono_pat_3:")), Some("mono_pat_3".to_string()));
+        assert_eq!(parse_authorization(&basic(":")), None);
+        assert_eq!(parse_authorization("Digest abc"), None);
+        assert_eq!(parse_authorization("Basic !!!"), None);
+    }
+
+    /// 测试匿名权限以及按路径前缀与删除检查引用更新
+    #[test]
+    fn test_check_update() {
+        let (_dir, mut repo) = init_repo();
+        assert!(Access::anonymous(&repo).require(Scope::Write).is_ok());
+        repo.config_mut().auth.anonymous = crate::common::config::AnonymousAccess::Read;
+        let anonymous = Access::anonymous(&repo);
+        assert!(anonymous.require(Scope::Read).is_ok());
+        assert!(anonymous.require(Scope::Write).unwrap_err().to_string().contains("authentication required"));
+
+        let base = commit_files(&repo, &[("services/api/main.rs", b"1"), ("docs/a.md", b"a")], &[], "base");
+        let api = commit_files(&repo, &[("services/api/main.rs", b"2"), ("docs/a.md", b"a")], &[base], "api");
+        let docs = commit_files(&repo, &[("services/api/main.rs", b"1"), ("docs/a.md", b"b")], &[base], "docs");
+        let update = |new: ObjectId| RefUpdate {
+            name: "refs/heads/main".to_string(),
+            old: base,
+            new,
+        };
+        let access = Access {
+            principal: "token:1234".to_string(),
+            scope: Some(Scope::Write),
+            paths: vec!["services/api".to_string()],
+        };
+        assert!(access.allows_path("services/api/main.rs"));
+        assert!(!access.allows_path("services/api2/main.rs"));
+        assert_eq!(access.check_update(&repo, &update(api)).unwrap(), Ok(()));
+        assert_eq!(
+            access.check_update(&repo, &update(docs)).unwrap(),
+            Err("token:1234 may not modify docs/a.md".to_string())
+        );
+        let delete = RefUpdate {
+            name: "refs/heads/main".to_string(),
+            old: base,
+            new: ObjectId::ZERO,
+        };
+        assert_eq!(
+            access.check_update(&repo, &delete).unwrap(),
+            Err("token:1234 lacks the admin scope".to_string())
+        );
+        assert_eq!(Access::full("alice").check_update(&repo, &delete).unwrap(), Ok(()));
+
+        repo.config_mut().acl = vec![crate::common::config::AclConfig {
+            name: "docs".to_string(),
+            paths: vec!["//docs/...".to_string()],
+            read: vec!["*".to_string()],
+            write: vec!["user:alice".to_string()],
+            ..Default::default()
+        }];
+        let bob = Access {
+            principal: "user:bob".to_string(),
+            scope: Some(Scope::Write),
+            paths: Vec::new(),
+        };
+        assert_eq!(bob.check_update(&repo, &update(api)).unwrap(), Ok(()));
+        assert_eq!(
+            bob.check_update(&repo, &update(docs)).unwrap(),
+            Err("user:bob may not modify docs/a.md (acl 'docs')".to_string())
+        );
+    }
+}
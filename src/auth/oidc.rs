@@ -0,0 +1,589 @@
+//! 外部 OIDC 身份提供方的登录与 ID 令牌校验
+//!
+//! 命令行通过 OAuth2 设备授权流程（RFC 8628）登录：`mono login` 显示验证地址与用户码，
+//! 用户在浏览器中完成登录后取得 ID 令牌与刷新令牌，会话保存在钥匙串中（见 [`SecretStore`]）。
+//! `mono credential` 作为 git 的凭据助手，将 ID 令牌作为 Basic 认证的密码发送，令牌过期时
+//! 自动用刷新令牌换取新的令牌。
+//!
+//! 服务端按 ID 令牌的 `iss` 声明找到 `[auth.providers]` 中的提供方，用提供方公布的公钥
+//! （JWKS，缓存一小时）校验签名、签发者、受众与有效期，再按 `[[auth.users]]` 将身份映射为
+//! 引擎用户。
+
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+use base64::prelude::*;
+use jsonwebtoken::jwk::{Jwk, JwkSet};
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::keychain::SecretStore;
+use super::{normalize_prefix, Access};
+use crate::common::config::{AuthConfig, OidcProviderConfig};
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::repo::Repository;
+
+/// 设备授权流程的 grant_type
+const DEVICE_CODE_GRANT: &str = "urn:ietf:params:oauth:grant-type:device_code";
+/// 请求的超时时间
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+/// 公钥的缓存时间
+const KEYS_TTL: Duration = Duration::from_secs(60 * 60);
+/// 遇到未知的签名密钥时，距上次获取公钥至少间隔这么久才重新获取
+const KEYS_MIN_REFRESH: Duration = Duration::from_secs(60);
+/// ID 令牌在过期前这么多秒就视为过期，避免请求途中过期
+const EXPIRY_MARGIN_SECS: i64 = 60;
+
+/// ID 令牌中的声明
+pub type Claims = serde_json::Map<String, Value>;
+
+/// 提供方的发现文档（`/.well-known/openid-configuration`）中用到的字段
+#[derive(Deserialize, Debug, Clone)]
+pub struct Discovery {
+    pub issuer: String,
+    pub token_endpoint: String,
+    #[serde(default)]
+    pub device_authorization_endpoint: Option<String>,
+    pub jwks_uri: String,
+}
+
+/// 设备授权端点的响应
+#[derive(Deserialize, Debug, Clone)]
+pub struct DeviceAuthorization {
+    pub device_code: String,
+    /// 用户在验证页面输入的用户码
+    pub user_code: String,
+    pub verification_uri: String,
+    /// 已带有用户码的验证地址
+    #[serde(default)]
+    pub verification_uri_complete: Option<String>,
+    /// 设备码的有效秒数
+    pub expires_in: u64,
+    /// 轮询间隔（秒）
+    #[serde(default = "DeviceAuthorization::default_interval")]
+    pub interval: u64,
+}
+
+impl DeviceAuthorization {
+    fn default_interval() -> u64 {
+        5
+    }
+}
+
+/// 令牌端点的成功响应
+#[derive(Deserialize, Debug, Clone)]
+pub struct TokenResponse {
+    #[serde(default)]
+    pub id_token: Option<String>,
+    #[serde(default)]
+    pub refresh_token: Option<String>,
+}
+
+/// 令牌端点的错误响应（RFC 6749 5.2 节）
+#[derive(Deserialize, Debug, Clone)]
+struct OAuthError {
+    error: String,
+    #[serde(default)]
+    error_description: Option<String>,
+}
+
+impl From<OAuthError> for MonoError {
+    fn from(e: OAuthError) -> Self {
+        match e.error_description {
+            Some(description) => MonoError::auth(format!("{}: {}", e.error, description)),
+            None => MonoError::auth(e.error),
+        }
+    }
+}
+
+/// 登录会话，以 JSON 形式保存在钥匙串中
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Session {
+    pub id_token: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
+    /// ID 令牌的过期时间（Unix 秒）
+    pub expires_at: i64,
+}
+
+impl Session {
+    pub fn is_expired(&self, now: i64) -> bool {
+        now + EXPIRY_MARGIN_SECS >= self.expires_at
+    }
+
+    /// ID 令牌中 `claim` 声明的值，仅用于显示
+    pub fn identity(&self, claim: &str) -> Option<String> {
+        unverified_claims(&self.id_token)
+            .ok()?
+            .get(claim)
+            .and_then(Value::as_str)
+            .map(String::from)
+    }
+}
+
+/// 不校验签名，读取 JWT 中的声明
+fn unverified_claims(token: &str) -> MonoResult<Claims> {
+    let invalid = || MonoError::auth("malformed ID token");
+    let payload = token.split('.').nth(1).ok_or_else(invalid)?;
+    let payload = BASE64_URL_SAFE_NO_PAD.decode(payload.trim_end_matches('=')).map_err(|_| invalid())?;
+    serde_json::from_slice(&payload).map_err(|_| invalid())
+}
+
+/// 是否为 JWT 形式的令牌
+pub fn is_jwt(token: &str) -> bool {
+    token.starts_with("eyJ") && token.split('.').count() == 3
+}
+
+/// 签发者 URL 是否相同，忽略末尾的 `/`
+fn same_issuer(a: &str, b: &str) -> bool {
+    a.trim_end_matches('/') == b.trim_end_matches('/')
+}
+
+/// 一个身份提供方的客户端
+pub struct OidcClient {
+    config: OidcProviderConfig,
+    agent: ureq::Agent,
+}
+
+impl OidcClient {
+    pub fn new(config: OidcProviderConfig) -> OidcClient {
+        let agent = ureq::Agent::config_builder()
+            .http_status_as_error(false)
+            .timeout_global(Some(REQUEST_TIMEOUT))
+            .build()
+            .into();
+        OidcClient { config, agent }
+    }
+
+    pub fn config(&self) -> &OidcProviderConfig {
+        &self.config
+    }
+
+    /// 会话在钥匙串中的键，同一提供方与客户端的会话在仓库之间共享
+    fn session_key(&self) -> String {
+        format!("oidc:{}#{}", self.config.issuer.trim_end_matches('/'), self.config.client_id)
+    }
+
+    fn get_json<T: DeserializeOwned>(&self, url: &str) -> MonoResult<T> {
+        let mut response = self
+            .agent
+            .get(url)
+            .header("Accept", "application/json")
+            .call()
+            .map_err(|e| MonoError::unavailable(format!("{}: {}", url, e)))?;
+        let status = response.status();
+        let body = response
+            .body_mut()
+            .read_to_string()
+            .map_err(|e| MonoError::unavailable(format!("{}: {}", url, e)))?;
+        if !status.is_success() {
+            return Err(MonoError::unavailable(format!("{}: HTTP {}", url, status.as_u16())));
+        }
+        serde_json::from_str(&body).map_err(|e| MonoError::protocol(format!("{}: {}", url, e)))
+    }
+
+    /// 向授权服务器提交表单，带上客户端凭据；返回错误响应时为 `Ok(Err(..))`
+    fn post_form<T: DeserializeOwned>(&self, url: &str, form: &[(&str, &str)]) -> MonoResult<Result<T, OAuthError>> {
+        let mut fields = vec![("client_id", self.config.client_id.as_str())];
+        if let Some(secret) = &self.config.client_secret {
+            fields.push(("client_secret", secret));
+        }
+        fields.extend_from_slice(form);
+        let mut response = self
+            .agent
+            .post(url)
+            .header("Accept", "application/json")
+            .send_form(fields)
+            .map_err(|e| MonoError::unavailable(format!("{}: {}", url, e)))?;
+        let status = response.status();
+        let body = response
+            .body_mut()
+            .read_to_string()
+            .map_err(|e| MonoError::unavailable(format!("{}: {}", url, e)))?;
+        if status.is_success() {
+            return serde_json::from_str(&body)
+                .map(Ok)
+                .map_err(|e| MonoError::protocol(format!("{}: {}", url, e)));
+        }
+        match serde_json::from_str::<OAuthError>(&body) {
+            Ok(error) => Ok(Err(error)),
+            Err(_) => Err(MonoError::unavailable(format!("{}: HTTP {}", url, status.as_u16()))),
+        }
+    }
+
+    /// 获取发现文档，文档中的签发者必须与配置一致
+    pub fn discover(&self) -> MonoResult<Discovery> {
+        let url = format!("{}/.well-known/openid-configuration", self.config.issuer.trim_end_matches('/'));
+        let discovery: Discovery = self.get_json(&url)?;
+        if !same_issuer(&discovery.issuer, &self.config.issuer) {
+            return Err(MonoError::protocol(format!(
+                "{} reports issuer {}, expected {}",
+                url, discovery.issuer, self.config.issuer
+            )));
+        }
+        Ok(discovery)
+    }
+
+    /// 开始设备授权流程
+    pub fn device_authorization(&self, discovery: &Discovery) -> MonoResult<DeviceAuthorization> {
+        let url = discovery.device_authorization_endpoint.as_deref().ok_or_else(|| {
+            MonoError::unavailable(format!("{} does not support device authorization", self.config.issuer))
+        })?;
+        let scope = self.config.scopes.join(" ");
+        Ok(self.post_form(url, &[("scope", &scope)])??)
+    }
+
+    /// 轮询令牌端点，直到用户完成登录、拒绝授权或设备码过期
+    pub fn poll_token(&self, discovery: &Discovery, device: &DeviceAuthorization) -> MonoResult<TokenResponse> {
+        let deadline = Instant::now() + Duration::from_secs(device.expires_in);
+        let mut interval = device.interval;
+        loop {
+            std::thread::sleep(Duration::from_secs(interval));
+            let form = [("grant_type", DEVICE_CODE_GRANT), ("device_code", device.device_code.as_str())];
+            match self.post_form(&discovery.token_endpoint, &form)? {
+                Ok(tokens) => return Ok(tokens),
+                Err(e) if e.error == "authorization_pending" => {}
+                Err(e) if e.error == "slow_down" => interval += 5,
+                Err(e) => return Err(e.into()),
+            }
+            if Instant::now() >= deadline {
+                return Err(MonoError::auth("the device code expired before the login was completed"));
+            }
+        }
+    }
+
+    /// 用刷新令牌换取新的令牌
+    pub fn refresh(&self, discovery: &Discovery, refresh_token: &str) -> MonoResult<TokenResponse> {
+        let form = [("grant_type", "refresh_token"), ("refresh_token", refresh_token)];
+        Ok(self.post_form(&discovery.token_endpoint, &form)??)
+    }
+
+    /// 由令牌端点的响应得到会话；响应中没有新的刷新令牌时沿用 `refresh_token`
+    fn session(&self, tokens: TokenResponse, refresh_token: Option<String>) -> MonoResult<Session> {
+        let id_token = tokens
+            .id_token
+            .ok_or_else(|| MonoError::protocol(format!("{} returned no ID token", self.config.issuer)))?;
+        let expires_at = unverified_claims(&id_token)?
+            .get("exp")
+            .and_then(Value::as_i64)
+            .ok_or_else(|| MonoError::protocol("ID token has no exp claim"))?;
+        Ok(Session {
+            id_token,
+            refresh_token: tokens.refresh_token.or(refresh_token),
+            expires_at,
+        })
+    }
+
+    /// 以设备授权流程登录并将会话保存到 `store`；`prompt` 负责提示用户打开验证地址
+    pub fn login(&self, store: &dyn SecretStore, prompt: impl FnOnce(&DeviceAuthorization)) -> MonoResult<Session> {
+        let discovery = self.discover()?;
+        let device = self.device_authorization(&discovery)?;
+        prompt(&device);
+        let session = self.session(self.poll_token(&discovery, &device)?, None)?;
+        self.save(store, &session)?;
+        Ok(session)
+    }
+
+    fn save(&self, store: &dyn SecretStore, session: &Session) -> MonoResult<()> {
+        let data = serde_json::to_string(session).map_err(|e| MonoError::storage(e.to_string()))?;
+        store.set(&self.session_key(), &data)
+    }
+
+    /// 删除保存的会话，返回是否曾经登录
+    pub fn logout(&self, store: &dyn SecretStore) -> MonoResult<bool> {
+        store.delete(&self.session_key())
+    }
+
+    /// 当前会话；ID 令牌即将过期时用刷新令牌换取新的令牌并保存
+    pub fn session_at(&self, store: &dyn SecretStore, now: i64) -> MonoResult<Session> {
+        let not_logged_in = || MonoError::auth(format!("not logged in to {}, run `mono login`", self.config.issuer));
+        let data = store.get(&self.session_key())?.ok_or_else(not_logged_in)?;
+        let session: Session = serde_json::from_str(&data).map_err(|_| not_logged_in())?;
+        if !session.is_expired(now) {
+            return Ok(session);
+        }
+        let refresh_token = session.refresh_token.ok_or_else(not_logged_in)?;
+        let tokens = self.refresh(&self.discover()?, &refresh_token)?;
+        let session = self.session(tokens, Some(refresh_token))?;
+        self.save(store, &session)?;
+        Ok(session)
+    }
+
+    /// 提供方的公钥，缓存 [`KEYS_TTL`]；`refresh` 为真时，若距上次获取已超过
+    /// [`KEYS_MIN_REFRESH`] 则重新获取，用于提供方轮换密钥之后
+    fn keys(&self, refresh: bool) -> MonoResult<JwkSet> {
+        static CACHE: LazyLock<Mutex<HashMap<String, (Instant, JwkSet)>>> = LazyLock::new(Default::default);
+        let issuer = self.config.issuer.trim_end_matches('/').to_string();
+        if let Some((fetched, keys)) = CACHE.lock().unwrap().get(&issuer) {
+            let age = fetched.elapsed();
+            if age < KEYS_TTL && !(refresh && age >= KEYS_MIN_REFRESH) {
+                return Ok(keys.clone());
+            }
+        }
+        let keys: JwkSet = self.get_json(&self.discover()?.jwks_uri)?;
+        CACHE.lock().unwrap().insert(issuer, (Instant::now(), keys.clone()));
+        Ok(keys)
+    }
+
+    /// 校验 ID 令牌并返回其中的声明
+    pub fn verify(&self, id_token: &str, now: i64) -> MonoResult<Claims> {
+        let header = jsonwebtoken::decode_header(id_token).map_err(|e| MonoError::auth(format!("invalid ID token: {}", e)))?;
+        let mut keys = self.keys(false)?;
+        if find_key(&keys, header.kid.as_deref()).is_none() {
+            keys = self.keys(true)?;
+        }
+        verify_id_token(&self.config, &keys, id_token, now)
+    }
+}
+
+/// 按 `kid` 查找签名密钥；令牌未指定 `kid` 时只接受唯一的密钥
+fn find_key<'a>(keys: &'a JwkSet, kid: Option<&str>) -> Option<&'a Jwk> {
+    match kid {
+        Some(kid) => keys.find(kid),
+        None if keys.keys.len() == 1 => keys.keys.first(),
+        None => None,
+    }
+}
+
+/// 用 `keys` 校验 ID 令牌的签名、签发者、受众与有效期（以 `now` 为准），只接受非对称签名
+pub fn verify_id_token(config: &OidcProviderConfig, keys: &JwkSet, id_token: &str, now: i64) -> MonoResult<Claims> {
+    let invalid = |e: jsonwebtoken::errors::Error| MonoError::auth(format!("invalid ID token: {}", e));
+    let header = jsonwebtoken::decode_header(id_token).map_err(invalid)?;
+    if matches!(header.alg, Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512) {
+        return Err(MonoError::auth(format!("ID token signed with unsupported algorithm {:?}", header.alg)));
+    }
+    let jwk = find_key(keys, header.kid.as_deref()).ok_or_else(|| {
+        MonoError::auth(format!(
+            "ID token signed with unknown key {}",
+            header.kid.as_deref().unwrap_or("(none)")
+        ))
+    })?;
+    let key = DecodingKey::from_jwk(jwk).map_err(invalid)?;
+    let mut validation = Validation::new(header.alg);
+    validation.set_issuer(&[config.issuer.as_str(), config.issuer.trim_end_matches('/')]);
+    validation.set_audience(&[config.client_id.as_str()]);
+    validation.set_required_spec_claims(&["exp", "iss", "aud"]);
+    validation.validate_exp = false;
+    let claims = jsonwebtoken::decode::<Claims>(id_token, &key, &validation).map_err(invalid)?.claims;
+    match claims.get("exp").and_then(Value::as_i64) {
+        Some(exp) if exp > now => Ok(claims),
+        _ => Err(MonoError::auth("ID token has expired")),
+    }
+}
+
+/// 将提供方 `provider` 校验过的声明映射为访问权限：`[[auth.users]]` 中的用户为
+/// `user:<name>`，其余用户按提供方的 `default_scope` 处理
+pub fn map_identity(auth: &AuthConfig, provider: &str, claims: &Claims) -> MonoResult<Access> {
+    let config = auth
+        .providers
+        .get(provider)
+        .ok_or_else(|| MonoError::config(format!("unknown identity provider {}", provider)))?;
+    let claim = config.user_claim.as_str();
+    let identity = claims
+        .get(claim)
+        .and_then(Value::as_str)
+        .ok_or_else(|| MonoError::auth(format!("ID token has no {} claim", claim)))?;
+    if claim == "email" && claims.get("email_verified").and_then(Value::as_bool) == Some(false) {
+        return Err(MonoError::auth(format!("email address {} is not verified", identity)));
+    }
+    if let Some(user) = auth.users.iter().find(|user| user.provider == provider && user.identity == identity) {
+        return Ok(Access {
+            principal: format!("user:{}", user.name),
+            scope: Some(user.scope),
+            paths: user.paths.iter().map(|p| normalize_prefix(p)).filter(|p| !p.is_empty()).collect(),
+        });
+    }
+    match config.default_scope {
+        Some(scope) => Ok(Access {
+            principal: format!("{}:{}", provider, identity),
+            scope: Some(scope),
+            paths: Vec::new(),
+        }),
+        None => Err(MonoError::auth(format!("{} is not a user of this repository", identity))),
+    }
+}
+
+/// 按 ID 令牌的签发者找到提供方，校验令牌并映射为访问权限
+pub fn authenticate(repo: &Repository, id_token: &str, now: i64) -> MonoResult<Access> {
+    let auth = &repo.config().auth;
+    let claims = unverified_claims(id_token)?;
+    let issuer = claims
+        .get("iss")
+        .and_then(Value::as_str)
+        .ok_or_else(|| MonoError::auth("ID token has no iss claim"))?;
+    let (name, config) = auth
+        .providers
+        .iter()
+        .find(|(_, config)| same_issuer(&config.issuer, issuer))
+        .ok_or_else(|| MonoError::auth(format!("untrusted token issuer {}", issuer)))?;
+    let claims = OidcClient::new(config.clone()).verify(id_token, now)?;
+    map_identity(auth, name, &claims)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::config::{Scope, UserConfig};
+    use axum::extract::State;
+    use axum::routing::{get, post};
+    use axum::{Form, Json, Router};
+    use jsonwebtoken::{EncodingKey, Header};
+    use russh::keys::ssh_key::private::Ed25519Keypair;
+    use std::collections::BTreeMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    const SEED: [u8; 32] = [7; 32];
+
+    /// 测试用的钥匙串
+    #[derive(Default)]
+    struct MemoryStore(Mutex<BTreeMap<String, String>>);
+
+    impl SecretStore for MemoryStore {
+        fn get(&self, key: &str) -> MonoResult<Option<String>> {
+            Ok(self.0.lock().unwrap().get(key).cloned())
+        }
+
+        fn set(&self, key: &str, secret: &str) -> MonoResult<()> {
+            self.0.lock().unwrap().insert(key.to_string(), secret.to_string());
+            Ok(())
+        }
+
+        fn delete(&self, key: &str) -> MonoResult<bool> {
+            Ok(self.0.lock().unwrap().remove(key).is_some())
+        }
+    }
+
+    fn jwks() -> JwkSet {
+        let public = Ed25519Keypair::from_seed(&SEED).public;
+        serde_json::from_value(serde_json::json!({
+            "keys": [{"kty": "OKP", "crv": "Ed25519", "kid": "k1", "alg": "EdDSA", "x": BASE64_URL_SAFE_NO_PAD.encode(public.0)}]
+        }))
+        .unwrap()
+    }
+
+    /// 用 [`SEED`] 签发 ID 令牌
+    fn sign(claims: Value) -> String {
+        let mut pkcs8 = vec![0x30, 0x2e, 0x02, 0x01, 0x00, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x04, 0x22, 0x04, 0x20];
+        pkcs8.extend_from_slice(&SEED);
+        let mut header = Header::new(Algorithm::EdDSA);
+        header.kid = Some("k1".to_string());
+        jsonwebtoken::encode(&header, &claims, &EncodingKey::from_ed_der(&pkcs8)).unwrap()
+    }
+
+    /// 测试 ID 令牌的校验与身份映射
+    #[test]
+    fn test_verify_and_map() {
+        let config = OidcProviderConfig::new("https://id.example.com/", "mono");
+        let claims = |email: &str, aud: &str, exp: i64| {
+            serde_json::json!({"iss": "https://id.example.com/", "aud": aud, "exp": exp, "email": email, "email_verified": true})
+        };
+        let token = sign(claims("alice@example.com", "mono", 2000));
+        assert!(is_jwt(&token));
+        let verified = verify_id_token(&config, &jwks(), &token, 1000).unwrap();
+        assert_eq!(verified["email"], "alice@example.com");
+        assert!(verify_id_token(&config, &jwks(), &token, 2000).unwrap_err().to_string().contains("expired"));
+        let other = sign(claims("alice@example.com", "other", 2000));
+        assert!(verify_id_token(&config, &jwks(), &other, 1000).is_err());
+        let mut tampered: Vec<&str> = token.split('.').collect();
+        let forged = BASE64_URL_SAFE_NO_PAD.encode(claims("root@example.com", "mono", 2000).to_string());
+        tampered[1] = &forged;
+        assert!(verify_id_token(&config, &jwks(), &tampered.join("."), 1000).is_err());
+
+        let mut auth = AuthConfig::default();
+        auth.providers.insert("okta".to_string(), config);
+        auth.users.push(UserConfig {
+            name: "alice".to_string(),
+            provider: "okta".to_string(),
+            identity: "alice@example.com".to_string(),
+            scope: Scope::Write,
+            paths: vec!["//services/api/".to_string()],
+        });
+        let access = map_identity(&auth, "okta", &verified).unwrap();
+        assert_eq!(access.principal, "user:alice");
+        assert_eq!(access.scope, Some(Scope::Write));
+        assert_eq!(access.paths, ["services/api"]);
+
+        let bob = verify_id_token(&auth.providers["okta"], &jwks(), &sign(claims("bob@example.com", "mono", 2000)), 1000).unwrap();
+        assert!(map_identity(&auth, "okta", &bob).unwrap_err().to_string().contains("not a user"));
+        auth.providers.get_mut("okta").unwrap().default_scope = Some(Scope::Read);
+        let access = map_identity(&auth, "okta", &bob).unwrap();
+        assert_eq!((access.principal.as_str(), access.scope), ("okta:bob@example.com", Some(Scope::Read)));
+        let mut unverified = bob.clone();
+        unverified.insert("email_verified".to_string(), Value::Bool(false));
+        assert!(map_identity(&auth, "okta", &unverified).is_err());
+    }
+
+    /// 测试设备授权流程登录、会话保存、刷新与登出
+    #[test]
+    fn test_device_login() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let listener = runtime.block_on(tokio::net::TcpListener::bind("127.0.0.1:0")).unwrap();
+        let issuer = format!("http://{}", listener.local_addr().unwrap());
+        let polls = Arc::new(AtomicUsize::new(0));
+        let token_issuer = issuer.clone();
+        let token = move |State(polls): State<Arc<AtomicUsize>>, Form(form): Form<HashMap<String, String>>| async move {
+            let id_token = |exp: i64| sign(serde_json::json!({"iss": token_issuer, "aud": "mono", "exp": exp, "email": "alice@example.com"}));
+            match (form["grant_type"].as_str(), form.get("device_code"), form.get("refresh_token")) {
+                (DEVICE_CODE_GRANT, Some(code), _) if code == "dev-1" && form["client_id"] == "mono" => {
+                    if polls.fetch_add(1, Ordering::SeqCst) == 0 {
+                        (axum::http::StatusCode::BAD_REQUEST, Json(serde_json::json!({"error": "authorization_pending"})))
+                    } else {
+                        let body = serde_json::json!({"id_token": id_token(1000), "refresh_token": "refresh-1", "access_token": "a"});
+                        (axum::http::StatusCode::OK, Json(body))
+                    }
+                }
+                ("refresh_token", _, Some(refresh)) if refresh == "refresh-1" => {
+                    (axum::http::StatusCode::OK, Json(serde_json::json!({"id_token": id_token(5000), "access_token": "b"})))
+                }
+                _ => (axum::http::StatusCode::BAD_REQUEST, Json(serde_json::json!({"error": "invalid_grant"}))),
+            }
+        };
+        let discovery = serde_json::json!({
+            "issuer": issuer,
+            "token_endpoint": format!("{}/token", issuer),
+            "device_authorization_endpoint": format!("{}/device", issuer),
+            "jwks_uri": format!("{}/jwks", issuer),
+        });
+        let app = Router::new()
+            .route("/.well-known/openid-configuration", get(move || async move { Json(discovery) }))
+            .route(
+                "/device",
+                post(|| async {
+                    Json(serde_json::json!({
+                        "device_code": "dev-1", "user_code": "ABCD-EFGH",
+                        "verification_uri": "https://id.example.com/activate", "expires_in": 60, "interval": 0
+                    }))
+                }),
+            )
+            .route("/token", post(token))
+            .route("/jwks", get(|| async { Json(jwks()) }))
+            .with_state(polls.clone());
+        runtime.spawn(async move { axum::serve(listener, app).await });
+
+        let client = OidcClient::new(OidcProviderConfig::new(issuer.clone(), "mono"));
+        let store = MemoryStore::default();
+        assert!(client.session_at(&store, 0).unwrap_err().to_string().contains("not logged in"));
+        let mut user_code = None;
+        let session = client.login(&store, |device| user_code = Some(device.user_code.clone())).unwrap();
+        assert_eq!(user_code.as_deref(), Some("ABCD-EFGH"));
+        assert_eq!(polls.load(Ordering::SeqCst), 2);
+        assert_eq!(session.refresh_token.as_deref(), Some("refresh-1"));
+        assert_eq!(session.identity("email").as_deref(), Some("alice@example.com"));
+        assert_eq!(client.session_at(&store, 500).unwrap(), session);
+
+        let refreshed = client.session_at(&store, 990).unwrap();
+        assert_eq!(refreshed.expires_at, 5000);
+        assert_eq!(refreshed.refresh_token.as_deref(), Some("refresh-1"));
+        assert_eq!(client.session_at(&store, 990).unwrap(), refreshed);
+        assert_eq!(client.verify(&refreshed.id_token, 990).unwrap()["email"], "alice@example.com");
+
+        assert!(client.logout(&store).unwrap());
+        assert!(!client.logout(&store).unwrap());
+        runtime.shutdown_background();
+    }
+}
@@ -0,0 +1,237 @@
+//! 大文件检查
+//!
+//! 把大文件直接提交进仓库会让每个克隆与 pack 都变大，这类文件应当用 Git LFS 管理。
+//! `[big_files]` 设置了 `max_blob_size` 后，推送策略（见 [`crate::policy`]）在每次引用更新时检查
+//! 新引入的提交：从现有引用不可达的每个提交与第一个父提交比较，改动过的文件超过大小时拒绝更新，
+//! `action = "warn"` 时只记录警告。LFS 指针文件只有一百多字节，用 LFS 管理的文件不会超过大小。
+//!
+//! `mono big-files --threshold 5MB origin/main..HEAD` 在推送前列出同样的文件，可以放在 pre-push 钩子中。
+
+use std::collections::HashSet;
+
+use serde::Serialize;
+
+use crate::common::config::{BigFileAction, BigFilesConfig};
+use crate::common::errors::{MonoError, PolicyViolation};
+use crate::common::progress::format_bytes;
+use crate::common::MonoResult;
+use crate::graph::history::History;
+use crate::lfs::{Pointer, MAX_POINTER_SIZE};
+use crate::object::{ObjectId, ObjectType};
+use crate::policy;
+use crate::refs::RefUpdate;
+use crate::repo::Repository;
+use crate::sparse::SparsePattern;
+
+/// 推送策略中的规则名
+pub const RULE: &str = "big-files";
+
+/// `mono big-files` 未配置 `max_blob_size` 时的默认阈值
+pub const DEFAULT_THRESHOLD: u64 = 5 << 20;
+
+/// 按 1024 进制解析字节数，如 `5MB`、`512k`、`1GiB`，不带单位时为字节
+pub fn parse_size(text: &str) -> MonoResult<u64> {
+    let text = text.trim();
+    let split = text.find(|c: char| !c.is_ascii_digit()).unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let invalid = || MonoError::usage(format!("invalid size: {}", text));
+    let number: u64 = number.parse().map_err(|_| invalid())?;
+    let shift = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 0,
+        "k" | "kb" | "kib" => 10,
+        "m" | "mb" | "mib" => 20,
+        "g" | "gb" | "gib" => 30,
+        _ => return Err(invalid()),
+    };
+    number.checked_mul(1 << shift).ok_or_else(invalid)
+}
+
+/// 超过大小的文件
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct BigFile {
+    /// 引入该文件内容的提交
+    pub commit: ObjectId,
+    pub path: String,
+    pub blob: ObjectId,
+    pub size: u64,
+}
+
+/// 大文件检查规则
+#[derive(Debug, Clone)]
+pub struct BigFiles {
+    threshold: u64,
+    action: BigFileAction,
+    allow_paths: Vec<SparsePattern>,
+    bypass_option: Option<String>,
+}
+
+impl BigFiles {
+    /// 只按大小检查，不放行任何路径
+    pub fn new(threshold: u64) -> BigFiles {
+        BigFiles { threshold, action: BigFileAction::Reject, allow_paths: Vec::new(), bypass_option: None }
+    }
+
+    /// 按配置构建，未设置 `max_blob_size` 时返回 None
+    pub fn from_config(config: &BigFilesConfig) -> MonoResult<Option<BigFiles>> {
+        if config.max_blob_size == 0 {
+            return Ok(None);
+        }
+        let allow_paths = config
+            .allow_paths
+            .iter()
+            .map(|path| path.parse::<SparsePattern>().map_err(|e| MonoError::config(format!("big_files: {}", e))))
+            .collect::<MonoResult<_>>()?;
+        Ok(Some(BigFiles {
+            threshold: config.max_blob_size,
+            action: config.action,
+            allow_paths,
+            bypass_option: config.bypass_option.clone(),
+        }))
+    }
+
+    pub fn threshold(&self) -> u64 {
+        self.threshold
+    }
+
+    /// 检查一条引用更新，拒绝时返回 [`MonoError::policy`]
+    ///
+    /// `push_options` 为推送者的推送选项，包含 `bypass_option` 时跳过检查。
+    pub fn check(&self, repo: &Repository, update: &RefUpdate, push_options: &[String]) -> MonoResult<()> {
+        if update.is_delete() {
+            return Ok(());
+        }
+        let (tip, object_type) = repo.peel(&update.new)?;
+        if object_type != ObjectType::Commit {
+            return Ok(());
+        }
+        let found = self.find(repo, &[tip], &policy::ref_commits(repo)?)?;
+        let Some(first) = found.first() else {
+            return Ok(());
+        };
+        if self.action == BigFileAction::Warn {
+            for file in &found {
+                tracing::warn!(name = %update.name, commit = %file.commit, path = %file.path, size = file.size, "push adds a large file");
+            }
+            return Ok(());
+        }
+        if let Some(option) = self.bypass_option.as_ref().filter(|option| push_options.contains(option)) {
+            tracing::warn!(name = %update.name, path = %first.path, %option, "large file check bypassed by push option");
+            return Ok(());
+        }
+        let more = match found.len() {
+            1 => String::new(),
+            n => format!(" and {} more", n - 1),
+        };
+        Err(MonoError::policy(PolicyViolation {
+            rule: RULE.to_string(),
+            refname: update.name.clone(),
+            paths: found.iter().map(|file| file.path.clone()).collect(),
+            reason: format!(
+                "commit {} adds a {} file{}, larger than {}; track large files with Git LFS",
+                &first.commit.to_hex()[..7],
+                format_bytes(first.size),
+                more,
+                format_bytes(self.threshold)
+            ),
+        }))
+    }
+
+    /// 从 `tips` 可达、从 `exclude` 不可达的提交中新增或修改的大文件，按提交时间从新到旧，
+    /// 同样的内容只报告一次
+    pub fn find(&self, repo: &Repository, tips: &[ObjectId], exclude: &[ObjectId]) -> MonoResult<Vec<BigFile>> {
+        let history = History::new(repo)?;
+        let mut checked = HashSet::new();
+        let mut found = Vec::new();
+        for commit in history.range(tips, exclude)? {
+            let parent = match commit.parents.first() {
+                Some(parent) => Some(history.commit(parent)?.tree),
+                None => None,
+            };
+            for path in repo.changed_paths(parent.as_ref(), Some(&commit.tree))? {
+                if self.allow_paths.iter().any(|pattern| pattern.matches(&path)) {
+                    continue;
+                }
+                let Some(entry) = repo.find_path(&commit.tree, &path)? else { continue };
+                if !entry.mode.is_blob() || !checked.insert(entry.id) {
+                    continue;
+                }
+                if let Some(size) = self.oversized(repo, &entry.id)? {
+                    found.push(BigFile { commit: commit.id, path, blob: entry.id, size });
+                }
+            }
+        }
+        Ok(found)
+    }
+
+    /// 超过大小时返回大小，LFS 指针文件不计
+    fn oversized(&self, repo: &Repository, id: &ObjectId) -> MonoResult<Option<u64>> {
+        let size = match repo.objects().read_header(id)? {
+            Some((_, size)) if size as u64 > self.threshold => size as u64,
+            _ => return Ok(None),
+        };
+        if size as usize <= MAX_POINTER_SIZE && Pointer::parse(&repo.read_object(id)?.data).is_some() {
+            return Ok(None);
+        }
+        Ok(Some(size))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::errors::MonoErrorKind;
+    use crate::test_utils::{commit_files, init_repo};
+
+    /// 测试带单位的大小
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("100").unwrap(), 100);
+        assert_eq!(parse_size("512k").unwrap(), 512 << 10);
+        assert_eq!(parse_size("5MB").unwrap(), 5 << 20);
+        assert_eq!(parse_size("1 GiB").unwrap(), 1 << 30);
+        assert!(parse_size("5TB").is_err());
+        assert!(parse_size("MB").is_err());
+    }
+
+    /// 测试只检查新引入的提交、放行的路径、警告模式与推送选项
+    #[test]
+    fn test_check() {
+        let (_dir, repo) = init_repo();
+        let config = BigFilesConfig {
+            max_blob_size: 16,
+            allow_paths: vec!["//assets/fonts/...".to_string()],
+            bypass_option: Some("allow-big-files".to_string()),
+            ..BigFilesConfig::default()
+        };
+        let big_files = BigFiles::from_config(&config).unwrap().unwrap();
+        let big: &[u8] = &[7; 64];
+        let base = commit_files(&repo, &[("README", b"docs")], &[], "base");
+        repo.refs()
+            .update(&[RefUpdate { name: "refs/heads/main".to_string(), old: ObjectId::ZERO, new: base }])
+            .unwrap();
+
+        let added = commit_files(&repo, &[("README", b"docs"), ("data/dump.bin", big)], &[base], "add dump");
+        let removed = commit_files(&repo, &[("README", b"docs")], &[added], "remove dump");
+        let found = big_files.find(&repo, &[removed], &[base]).unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!((found[0].commit, found[0].path.as_str(), found[0].size), (added, "data/dump.bin", 64));
+
+        let update = RefUpdate { name: "refs/heads/main".to_string(), old: base, new: removed };
+        let err = big_files.check(&repo, &update, &[]).unwrap_err();
+        let MonoErrorKind::Policy(violation) = err.kind() else {
+            panic!("unexpected error: {}", err);
+        };
+        assert_eq!(violation.rule, RULE);
+        assert_eq!(violation.paths, ["data/dump.bin"]);
+        assert!(violation.reason.contains("Git LFS"), "{}", violation.reason);
+        big_files.check(&repo, &update, &["allow-big-files".to_string()]).unwrap();
+        let warn = BigFiles::from_config(&BigFilesConfig { action: BigFileAction::Warn, ..config }).unwrap().unwrap();
+        warn.check(&repo, &update, &[]).unwrap();
+
+        // 放行的路径与 LFS 指针不检查
+        let pointer = b"version https://git-lfs.github.com/spec/v1\noid sha256:4d7a214614ab2935c943f9e0ff69d22eadbb8f32b1258daaa5e2ca24d17e2393\nsize 12345\n";
+        let fonts = commit_files(&repo, &[("README", b"docs"), ("assets/fonts/a.ttf", big), ("video.mp4", pointer)], &[base], "fonts");
+        big_files.check(&repo, &RefUpdate { name: "refs/heads/main".to_string(), old: base, new: fonts }, &[]).unwrap();
+        assert!(BigFiles::from_config(&BigFilesConfig::default()).unwrap().is_none());
+    }
+}
@@ -0,0 +1,363 @@
+//! 二进制文件的 delta 存储
+//!
+//! 游戏与美术资源这类二进制文件每次修改都是一个新的完整 blob，普通的重新打包按大小与文件名
+//! 哈希在小窗口内挑选基对象，很难把同一文件的各个版本放在一起。`[[binary_delta.paths]]` 配置的目录下，
+//! `mono repack --binary-deltas` 沿历史收集每个路径的各个版本，写入一个单独的 pack：
+//!
+//! - 版本不少于 [`MIN_DICTIONARY_VERSIONS`] 个时，从最近的若干版本训练该路径的字典：各版本按内容
+//!   切块，出现在多个版本中的块按出现次数拼接为字典，字典作为 blob 写在该路径的版本之前；
+//! - 每个版本与字典、同一路径的上一个版本分别生成 delta，取较小者，delta 不到完整对象的一半时不使用。
+//!
+//! 与字典的 delta 只有一层，读取任意版本都只需还原一次；与上一个版本的 delta 链不超过 `max_depth`。
+//! 新 pack 带 `.keep` 标记，之后的增量与几何重新打包不会把它合并进普通 pack（见 [`FsStore::write_kept_pack`]）。
+//! 字典不被任何提交引用，记录在 `objects/info/binary-dictionaries.json` 中作为垃圾回收的起点。
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+
+use crate::common::config::{BinaryDeltaPathConfig, StorageBackend};
+use crate::common::errors::MonoError;
+use crate::common::progress::Progress;
+use crate::common::MonoResult;
+use crate::graph::history::History;
+use crate::object::{ObjectId, ObjectType};
+use crate::pack::delta::DeltaIndex;
+use crate::pack::PackWriter;
+use crate::repo::Repository;
+use crate::rewrite::normalize_prefix;
+use crate::storage::fs::FsStore;
+
+/// 字典清单相对于对象存储目录的路径
+pub const DICTIONARIES_FILE: &str = "info/binary-dictionaries.json";
+
+/// 至少有这么多个版本的路径才训练字典
+pub const MIN_DICTIONARY_VERSIONS: usize = 3;
+
+/// 训练字典使用的最近版本数
+const MAX_SAMPLES: usize = 8;
+
+/// 内容切块的最小与最大长度
+const MIN_CHUNK: usize = 1 << 10;
+const MAX_CHUNK: usize = 16 << 10;
+
+/// 滚动哈希低位全为 0 时切块，平均块长约 4 KiB
+const CHUNK_MASK: u64 = (1 << 12) - 1;
+
+/// 一次整理的结果
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BinaryDeltaReport {
+    /// 处理的路径数
+    pub paths: usize,
+    /// 写入的版本数
+    pub objects: usize,
+    /// 训练的字典数
+    pub dictionaries: usize,
+    /// 以 delta 形式写入的版本数
+    pub deltas: usize,
+    /// 这些版本原先占用的存储
+    pub before: u64,
+    /// 新 pack 的大小，包括字典
+    pub after: u64,
+}
+
+/// 按内容切块，插入或删除数据只影响附近的块
+fn chunks(data: &[u8]) -> Vec<&[u8]> {
+    let mut out = Vec::new();
+    let (mut start, mut hash) = (0, 0u64);
+    for (i, byte) in data.iter().enumerate() {
+        hash = (hash << 1).wrapping_add((u64::from(*byte) + 1).wrapping_mul(0x9e37_79b9_7f4a_7c15));
+        let len = i + 1 - start;
+        if (len >= MIN_CHUNK && hash & CHUNK_MASK == 0) || len >= MAX_CHUNK {
+            out.push(&data[start..=i]);
+            (start, hash) = (i + 1, 0);
+        }
+    }
+    if start < data.len() {
+        out.push(&data[start..]);
+    }
+    out
+}
+
+fn chunk_hash(chunk: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    chunk.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// 由同一路径的若干版本训练字典：出现在至少两个版本中的块按出现的版本数从多到少拼接，
+/// 不超过 `max_size` 字节；没有共同的块时返回 None
+pub fn train_dictionary(samples: &[Vec<u8>], max_size: usize) -> Option<Vec<u8>> {
+    // 块的哈希 -> (出现的版本数, 第一次出现的版本与块)
+    let mut counts: HashMap<u64, (usize, usize, &[u8])> = HashMap::new();
+    for (i, sample) in samples.iter().enumerate() {
+        let mut seen = HashSet::new();
+        for chunk in chunks(sample) {
+            let hash = chunk_hash(chunk);
+            if seen.insert(hash) {
+                counts.entry(hash).or_insert((0, i, chunk)).0 += 1;
+            }
+        }
+    }
+    let mut shared: Vec<(usize, usize, &[u8])> = counts.into_values().filter(|(count, _, _)| *count >= 2).collect();
+    shared.sort_by_key(|(count, sample, chunk)| (std::cmp::Reverse(*count), *sample, chunk.as_ptr() as usize));
+    let mut dictionary = Vec::new();
+    for (_, _, chunk) in shared {
+        if dictionary.len() + chunk.len() > max_size {
+            break;
+        }
+        dictionary.extend_from_slice(chunk);
+    }
+    (!dictionary.is_empty()).then_some(dictionary)
+}
+
+/// 字典清单中的字典，没有清单时为空
+pub fn dictionaries(repo: &Repository) -> MonoResult<Vec<ObjectId>> {
+    let path = dictionaries_path(repo);
+    let data = match std::fs::read(&path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let manifest: BTreeMap<String, ObjectId> =
+        serde_json::from_slice(&data).map_err(|e| MonoError::storage(format!("corrupt {}: {}", path.display(), e)))?;
+    Ok(manifest.into_values().collect())
+}
+
+pub fn dictionaries_path(repo: &Repository) -> PathBuf {
+    repo.objects_dir().join(DICTIONARIES_FILE)
+}
+
+/// 配置中覆盖 `path` 的最长前缀的参数
+fn rule_for<'c>(rules: &'c [(String, BinaryDeltaPathConfig)], path: &str) -> Option<&'c BinaryDeltaPathConfig> {
+    rules
+        .iter()
+        .filter(|(prefix, _)| path.strip_prefix(prefix.as_str()).is_some_and(|rest| rest.starts_with('/')))
+        .max_by_key(|(prefix, _)| prefix.len())
+        .map(|(_, rule)| rule)
+}
+
+/// 从全部引用可达的历史中，配置的目录下每个路径不小于 `min_size` 的版本，从旧到新；
+/// 同样的内容只归入第一次出现的路径
+fn collect_versions(repo: &Repository, rules: &[(String, BinaryDeltaPathConfig)]) -> MonoResult<BTreeMap<String, Vec<ObjectId>>> {
+    let mut tips = Vec::new();
+    for (_, id) in repo.refs().list("refs/")? {
+        if let Ok((commit, ObjectType::Commit)) = repo.peel(&id) {
+            tips.push(commit);
+        }
+    }
+    let history = History::new(repo)?;
+    let mut commits = history.range(&tips, &[])?;
+    commits.reverse();
+    let progress = Progress::new("Scanning history", commits.len() as u64);
+    let mut seen = HashSet::new();
+    let mut versions: BTreeMap<String, Vec<ObjectId>> = BTreeMap::new();
+    for commit in commits {
+        progress.inc(1);
+        let parent = match commit.parents.first() {
+            Some(parent) => Some(history.commit(parent)?.tree),
+            None => None,
+        };
+        for path in repo.changed_paths(parent.as_ref(), Some(&commit.tree))? {
+            let Some(rule) = rule_for(rules, &path) else { continue };
+            let Some(entry) = repo.find_path(&commit.tree, &path)? else { continue };
+            if !entry.mode.is_blob() || seen.contains(&entry.id) {
+                continue;
+            }
+            match repo.objects().read_header(&entry.id)? {
+                Some((_, size)) if size as u64 >= rule.min_size => {}
+                _ => continue,
+            }
+            seen.insert(entry.id);
+            versions.entry(path).or_default().push(entry.id);
+        }
+    }
+    progress.finish();
+    Ok(versions)
+}
+
+/// 按 `[binary_delta]` 配置把二进制文件的各个版本写入带 `.keep` 标记的 pack，仅支持 `fs` 后端
+pub fn optimize(repo: &Repository) -> MonoResult<BinaryDeltaReport> {
+    if repo.config().storage.backend != StorageBackend::Fs {
+        return Err(MonoError::usage("binary deltas require the fs storage backend"));
+    }
+    let mut rules = Vec::new();
+    for rule in &repo.config().binary_delta.paths {
+        rules.push((normalize_prefix(&rule.prefix)?, rule.clone()));
+    }
+    if rules.is_empty() {
+        return Err(MonoError::config("no directories configured in [[binary_delta.paths]]"));
+    }
+    let versions = collect_versions(repo, &rules)?;
+    let mut report = BinaryDeltaReport { paths: versions.len(), ..BinaryDeltaReport::default() };
+    if versions.is_empty() {
+        return Ok(report);
+    }
+    let ids: HashSet<ObjectId> = versions.values().flatten().copied().collect();
+
+    // 先训练字典，pack 头部需要对象总数
+    let format = repo.object_format();
+    let codec = repo.config().storage.compression;
+    let mut trained: BTreeMap<&str, Vec<u8>> = BTreeMap::new();
+    for (path, list) in &versions {
+        let rule = rule_for(&rules, path).expect("collected paths have a rule");
+        if rule.dictionary_size == 0 || list.len() < MIN_DICTIONARY_VERSIONS {
+            continue;
+        }
+        let samples = list[list.len().saturating_sub(MAX_SAMPLES)..]
+            .iter()
+            .map(|id| repo.read_object(id).map(|object| object.data))
+            .collect::<MonoResult<Vec<_>>>()?;
+        if let Some(dictionary) = train_dictionary(&samples, rule.dictionary_size as usize) {
+            // 字典恰好与某个版本相同时没有意义
+            if !ids.contains(&format.hash_object(ObjectType::Blob, &dictionary)) {
+                trained.insert(path, dictionary);
+            }
+        }
+    }
+    report.dictionaries = trained.len();
+    report.before = repo
+        .objects()
+        .list_stored()?
+        .iter()
+        .filter(|object| ids.contains(&object.id))
+        .map(|object| object.size)
+        .sum();
+
+    let progress = Progress::new("Compressing binaries", ids.len() as u64);
+    let mut pack = PackWriter::with_codec(Vec::new(), (ids.len() + trained.len()) as u32, format, codec)?;
+    let mut manifest: BTreeMap<String, ObjectId> = BTreeMap::new();
+    for (path, list) in &versions {
+        let rule = rule_for(&rules, path).expect("collected paths have a rule");
+        let dictionary = match trained.remove(path.as_str()) {
+            Some(data) => {
+                manifest.insert(path.clone(), format.hash_object(ObjectType::Blob, &data));
+                Some((pack.write(ObjectType::Blob, &data)?, DeltaIndex::new(data)))
+            }
+            None => None,
+        };
+        // 上一个版本的偏移、delta 链深度与索引
+        let mut previous: Option<(u64, usize, DeltaIndex)> = None;
+        for id in list {
+            let data = repo.read_object(id)?.data;
+            let mut best: Option<(u64, usize, Vec<u8>)> = None;
+            let candidates = dictionary
+                .iter()
+                .map(|(offset, index)| (*offset, 0, index))
+                .chain(previous.iter().filter(|(_, depth, _)| *depth < rule.max_depth).map(|(offset, depth, index)| (*offset, *depth, index)));
+            for (offset, depth, index) in candidates {
+                let limit = best.as_ref().map_or(data.len() / 2, |(_, _, delta)| delta.len().saturating_sub(1));
+                if let Some(delta) = index.create_delta(&data, limit) {
+                    best = Some((offset, depth + 1, delta));
+                }
+            }
+            let (offset, depth) = match best {
+                Some((base, depth, delta)) => {
+                    report.deltas += 1;
+                    (pack.write_ofs_delta(*id, base, &delta)?, depth)
+                }
+                None => (pack.write(ObjectType::Blob, &data)?, 0),
+            };
+            previous = Some((offset, depth, DeltaIndex::new(data)));
+            report.objects += 1;
+            progress.inc(1);
+        }
+    }
+    progress.finish();
+    let (data, index) = pack.finish_indexed()?;
+    report.after = data.len() as u64;
+    let store = FsStore::new(repo.objects_dir()).with_format(format).with_codec(codec);
+    let path = store.write_kept_pack(&data, &index)?;
+
+    let manifest_path = dictionaries_path(repo);
+    std::fs::create_dir_all(manifest_path.parent().expect("dictionary manifest has a parent"))?;
+    let json = serde_json::to_vec_pretty(&manifest).map_err(|e| MonoError::storage(e.to_string()))?;
+    let tmp = manifest_path.with_file_name(format!(".tmp-{}-binary-dictionaries.json", std::process::id()));
+    std::fs::write(&tmp, json)?;
+    std::fs::rename(&tmp, &manifest_path)?;
+    tracing::info!(path = %path.display(), objects = report.objects, deltas = report.deltas, before = report.before, after = report.after, "wrote binary delta pack");
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::config::BinaryDeltaConfig;
+    use crate::gc;
+    use crate::refs::RefUpdate;
+    use crate::test_utils::{commit_files, init_repo};
+
+    /// 伪随机的二进制内容，普通压缩几乎无效
+    fn noise(seed: u64, len: usize) -> Vec<u8> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+                (state >> 33) as u8
+            })
+            .collect()
+    }
+
+    /// 测试字典由多个版本共有的块组成
+    #[test]
+    fn test_train_dictionary() {
+        let shared = noise(1, 64 << 10);
+        let samples: Vec<Vec<u8>> = (0..4)
+            .map(|i| {
+                let mut data = noise(100 + i, 8 << 10);
+                data.extend_from_slice(&shared);
+                data
+            })
+            .collect();
+        let dictionary = train_dictionary(&samples, 1 << 20).unwrap();
+        assert!(dictionary.len() >= 48 << 10 && dictionary.len() <= 72 << 10, "{}", dictionary.len());
+        assert!(train_dictionary(&samples, 1 << 10).is_none_or(|d| d.len() <= 1 << 10));
+        assert!(train_dictionary(&[noise(1, 8 << 10), noise(2, 8 << 10)], 1 << 20).is_none());
+    }
+
+    /// 测试各版本写入带标记的 pack 后存储变小、内容不变，字典在垃圾回收中保留，普通重新打包不合并该 pack
+    #[test]
+    fn test_optimize() {
+        let (_dir, mut repo) = init_repo();
+        repo.config_mut().binary_delta = BinaryDeltaConfig {
+            paths: vec![BinaryDeltaPathConfig { prefix: "assets".to_string(), min_size: 1024, dictionary_size: 256 << 10, max_depth: 10 }],
+        };
+
+        let base = noise(7, 96 << 10);
+        let mut parent = None;
+        let mut blobs = Vec::new();
+        for i in 0..5u64 {
+            let mut texture = base.clone();
+            texture[(i as usize) * 1000..(i as usize) * 1000 + 64].copy_from_slice(&noise(i, 64));
+            texture.extend_from_slice(&noise(50 + i, 2048));
+            blobs.push(texture.clone());
+            let commit = commit_files(&repo, &[("assets/hero.png", &texture), ("README", b"small")], parent.as_slice(), "edit");
+            parent = Some(commit);
+        }
+        repo.refs()
+            .update(&[RefUpdate { name: "refs/heads/main".to_string(), old: ObjectId::ZERO, new: parent.unwrap() }])
+            .unwrap();
+
+        let report = optimize(&repo).unwrap();
+        assert_eq!((report.paths, report.objects, report.dictionaries), (1, 5, 1));
+        assert_eq!(report.deltas, 5);
+        assert!(report.after * 3 < report.before, "{:?}", report);
+        for blob in &blobs {
+            let id = repo.object_format().hash_object(ObjectType::Blob, blob);
+            assert_eq!(&repo.read_object(&id).unwrap().data, blob);
+        }
+
+        let dictionaries = dictionaries(&repo).unwrap();
+        assert_eq!(dictionaries.len(), 1);
+        assert!(gc::roots(&repo, 0).unwrap().contains(&dictionaries[0]));
+        let store = FsStore::new(repo.objects_dir()).with_format(repo.object_format());
+        let kept = store.packs().unwrap()[0].path().to_path_buf();
+        assert!(kept.with_extension("keep").is_file());
+        // 松散的提交与树打包为另一个 pack
+        store.repack_incremental(1).unwrap();
+        let packs: Vec<PathBuf> = store.packs().unwrap().iter().map(|pack| pack.path().to_path_buf()).collect();
+        assert_eq!(packs.len(), 2);
+        assert!(packs.contains(&kept));
+    }
+}
@@ -0,0 +1,429 @@
+//! 服务端二分查找
+//!
+//! 在本地检出代价过高时由服务端定位引入问题的提交：给定已知正常（good）与已知有问题（bad）的
+//! 提交以及测试命令，沿 bad 的第一父提交链二分，把每个候选提交检出到临时工作目录
+//! `.mono/bisect/<编号>/work` 中运行命令（见 [`crate::runner`]），报告链上第一个有问题的提交。
+//! 问题由合并进来的分支引入时，结果是合入它的合并提交。
+//!
+//! 命令的退出码与 `git bisect run` 相同：0 表示正常，125 表示无法测试、跳过该提交，
+//! 1 到 127 之间的其他值表示有问题；被信号终止、退出码不小于 128 或超时会中止查找。
+//! 命令通过环境变量 `MONO_BISECT_COMMIT`、`MONO_BISECT_GOOD` 与 `MONO_BISECT_BAD` 获得
+//! 当前、正常与有问题的提交。
+//!
+//! 查找记录保存在 `.mono/bisect/<编号>.json`，每测试一个提交写回一次，可以随时查看进度；
+//! 中断后再次运行会从已记录的结果继续。查找通过 REST 接口 `POST /api/v1/bisect`
+//! （见 [`crate::server::api`]，需要 `admin` 权限）或 `mono bisect` 发起。
+
+use std::collections::HashSet;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::graph::history::History;
+use crate::object::ObjectId;
+use crate::queue::LockFile;
+use crate::repo::Repository;
+use crate::runner::{self, Runner};
+
+/// 二分查找数据目录，相对于 `.mono`
+pub const BISECT_DIR: &str = "bisect";
+/// 读写查找记录时持有的锁
+const LOCK_FILE: &str = "lock";
+/// 检出候选提交的目录，位于每次查找自己的目录下
+const WORK_DIR: &str = "work";
+/// 测试命令的输出，位于每次查找自己的目录下
+const LOG_FILE: &str = "run.log";
+/// 测试命令的默认超时（秒）
+pub const DEFAULT_TIMEOUT_SECS: u64 = 600;
+/// 表示跳过当前提交的退出码
+const SKIP_EXIT_CODE: i32 = 125;
+
+/// 查找的状态
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum BisectState {
+    Running,
+    /// 找到了第一个有问题的提交
+    Found,
+    /// 查找被中止，或因跳过的提交无法确定结果
+    Failed,
+}
+
+impl fmt::Display for BisectState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            BisectState::Running => "running",
+            BisectState::Found => "found",
+            BisectState::Failed => "failed",
+        })
+    }
+}
+
+/// 一个提交的测试结果
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum StepResult {
+    Good,
+    Bad,
+    Skip,
+}
+
+impl fmt::Display for StepResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            StepResult::Good => "good",
+            StepResult::Bad => "bad",
+            StepResult::Skip => "skip",
+        })
+    }
+}
+
+/// 测试过的一个提交
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct BisectStep {
+    pub commit: ObjectId,
+    pub result: StepResult,
+    pub exit_code: i32,
+    /// 命令输出的末尾若干行
+    pub output: String,
+}
+
+/// 一次二分查找
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct BisectSession {
+    pub id: u64,
+    pub good: Vec<ObjectId>,
+    pub bad: ObjectId,
+    pub command: String,
+    pub timeout_secs: u64,
+    /// 发起者的身份
+    pub requested_by: String,
+    pub state: BisectState,
+    /// 按测试顺序排列
+    #[serde(default)]
+    pub steps: Vec<BisectStep>,
+    /// 第一个有问题的提交
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub first_bad: Option<ObjectId>,
+    /// 因跳过而无法区分时，第一个有问题的提交在其中之一
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub suspects: Vec<ObjectId>,
+    /// 查找失败的原因
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// 发起时间（Unix 时间戳）
+    pub created_at: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<i64>,
+}
+
+/// 发起查找的参数
+#[derive(Debug, Clone)]
+pub struct NewBisect {
+    pub good: Vec<ObjectId>,
+    pub bad: ObjectId,
+    pub command: String,
+    pub timeout_secs: u64,
+    pub requested_by: String,
+}
+
+/// 仓库的二分查找记录
+pub struct Bisector<'a> {
+    repo: &'a Repository,
+    dir: PathBuf,
+}
+
+impl<'a> Bisector<'a> {
+    pub fn new(repo: &'a Repository) -> Bisector<'a> {
+        Bisector {
+            repo,
+            dir: repo.mono_dir().join(BISECT_DIR),
+        }
+    }
+
+    fn path(&self, id: u64) -> PathBuf {
+        self.dir.join(format!("{}.json", id))
+    }
+
+    /// 读取查找记录
+    pub fn get(&self, id: u64) -> MonoResult<BisectSession> {
+        let path = self.path(id);
+        let data = match std::fs::read(&path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Err(MonoError::not_found(format!("bisect {}", id)));
+            }
+            Err(e) => return Err(e.into()),
+        };
+        serde_json::from_slice(&data).map_err(|e| MonoError::storage(format!("corrupt bisect {}: {}", path.display(), e)))
+    }
+
+    /// 全部查找记录，按编号排列
+    pub fn list(&self) -> MonoResult<Vec<BisectSession>> {
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut ids: Vec<u64> = Vec::new();
+        for entry in entries {
+            let name = entry?.file_name();
+            if let Some(id) = name.to_str().and_then(|name| name.strip_suffix(".json")).and_then(|id| id.parse().ok()) {
+                ids.push(id);
+            }
+        }
+        ids.sort_unstable();
+        ids.into_iter().map(|id| self.get(id)).collect()
+    }
+
+    fn save(&self, session: &BisectSession) -> MonoResult<()> {
+        let data = serde_json::to_vec_pretty(session).map_err(|e| MonoError::storage(e.to_string()))?;
+        let path = self.path(session.id);
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, data)?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    /// 在锁的保护下读取、修改并写回查找记录
+    fn update(&self, id: u64, f: impl FnOnce(&mut BisectSession)) -> MonoResult<BisectSession> {
+        let _lock = LockFile::acquire(self.dir.join(LOCK_FILE))?;
+        let mut session = self.get(id)?;
+        f(&mut session);
+        self.save(&session)?;
+        Ok(session)
+    }
+
+    /// 登记一次查找，检查参数与候选提交后返回，由 [`Bisector::run`] 执行
+    pub fn start(&self, request: NewBisect, now: i64) -> MonoResult<BisectSession> {
+        if request.command.trim().is_empty() {
+            return Err(MonoError::usage("bisect command must not be empty"));
+        }
+        if request.good.is_empty() {
+            return Err(MonoError::usage("at least one good commit is required"));
+        }
+        if request.timeout_secs == 0 {
+            return Err(MonoError::usage("bisect timeout must be positive"));
+        }
+        self.candidates(&request.good, &request.bad)?;
+        std::fs::create_dir_all(&self.dir)?;
+        let _lock = LockFile::acquire(self.dir.join(LOCK_FILE))?;
+        let session = BisectSession {
+            id: self.list()?.last().map_or(0, |session| session.id) + 1,
+            good: request.good,
+            bad: request.bad,
+            command: request.command,
+            timeout_secs: request.timeout_secs,
+            requested_by: request.requested_by,
+            state: BisectState::Running,
+            steps: Vec::new(),
+            first_bad: None,
+            suspects: Vec::new(),
+            reason: None,
+            created_at: now,
+            finished_at: None,
+        };
+        self.save(&session)?;
+        tracing::info!(id = session.id, bad = %session.bad, "bisect started");
+        Ok(session)
+    }
+
+    /// bad 的第一父提交链上、不是任何 good 提交祖先的提交，从旧到新排列，最后一个是 bad
+    fn candidates(&self, good: &[ObjectId], bad: &ObjectId) -> MonoResult<Vec<ObjectId>> {
+        let history = History::new(self.repo)?;
+        for id in good {
+            history.commit(id)?;
+        }
+        let mut chain = Vec::new();
+        let mut current = *bad;
+        loop {
+            let mut reached = false;
+            for id in good {
+                if history.is_ancestor(&current, id)? {
+                    reached = true;
+                    break;
+                }
+            }
+            if reached {
+                break;
+            }
+            chain.push(current);
+            match history.commit(&current)?.parents.first() {
+                Some(parent) => current = *parent,
+                None => {
+                    return Err(MonoError::usage(format!(
+                        "no good commit shares history with the first-parent history of {}",
+                        bad
+                    )))
+                }
+            }
+        }
+        if chain.is_empty() {
+            return Err(MonoError::usage(format!("bad commit {} is an ancestor of a good commit", bad)));
+        }
+        chain.reverse();
+        Ok(chain)
+    }
+
+    /// 执行查找直到找到结果或中止，已有的测试结果不会重复测试
+    pub fn run(&self, id: u64) -> MonoResult<BisectSession> {
+        let session = self.get(id)?;
+        if session.state != BisectState::Running {
+            return Ok(session);
+        }
+        let session_dir = self.dir.join(id.to_string());
+        std::fs::create_dir_all(&session_dir)?;
+        let _run = LockFile::acquire(self.dir.join(format!("{}.run.lock", id)))?;
+        let result = self.search(&session, &session_dir);
+        runner::remove_dir_if_exists(&session_dir.join(WORK_DIR))?;
+        let now = chrono::Utc::now().timestamp();
+        let (state, first_bad, suspects, reason) = match result {
+            Ok(Ok(first_bad)) => (BisectState::Found, Some(first_bad), Vec::new(), None),
+            Ok(Err((suspects, reason))) => (BisectState::Failed, None, suspects, Some(reason)),
+            Err(e) => (BisectState::Failed, None, Vec::new(), Some(e.to_string())),
+        };
+        let session = self.update(id, |session| {
+            session.state = state;
+            session.first_bad = first_bad;
+            session.suspects = suspects;
+            session.reason = reason;
+            session.finished_at = Some(now);
+        })?;
+        tracing::info!(id, state = %session.state, first_bad = ?session.first_bad, "bisect finished");
+        Ok(session)
+    }
+
+    /// 二分查找，返回第一个有问题的提交，无法确定时返回 `Err((可疑提交, 原因))`
+    fn search(&self, session: &BisectSession, dir: &Path) -> MonoResult<Result<ObjectId, (Vec<ObjectId>, String)>> {
+        let chain = self.candidates(&session.good, &session.bad)?;
+        let timeout = Duration::from_secs(session.timeout_secs);
+        let runner = Runner::new(self.repo, dir.join(WORK_DIR), dir.join(LOG_FILE), timeout);
+        let good = session.good.iter().map(ObjectId::to_string).collect::<Vec<_>>().join(" ");
+        let mut steps = session.steps.clone();
+        loop {
+            // chain[..lo] 正常，chain[hi] 有问题，第一个有问题的提交在 chain[lo..=hi] 中
+            let (mut lo, mut hi) = (0, chain.len() - 1);
+            let mut skipped = HashSet::new();
+            for step in &steps {
+                let Some(index) = chain.iter().position(|id| *id == step.commit) else {
+                    continue;
+                };
+                match step.result {
+                    StepResult::Good if index >= lo && index < hi => lo = index + 1,
+                    StepResult::Bad if index >= lo && index < hi => hi = index,
+                    StepResult::Skip => {
+                        skipped.insert(index);
+                    }
+                    _ => {}
+                }
+            }
+            if lo == hi {
+                return Ok(Ok(chain[hi]));
+            }
+            let middle = (lo + hi) / 2;
+            let Some(next) = (lo..hi).filter(|index| !skipped.contains(index)).min_by_key(|index| index.abs_diff(middle)) else {
+                let reason = format!("the first bad commit could not be determined: {} candidates were skipped", hi - lo);
+                return Ok(Err((chain[lo..=hi].to_vec(), reason)));
+            };
+
+            let commit = chain[next];
+            let env = [
+                ("MONO_BISECT_COMMIT", commit.to_string()),
+                ("MONO_BISECT_GOOD", good.clone()),
+                ("MONO_BISECT_BAD", session.bad.to_string()),
+            ];
+            let output = runner.run(&commit, &session.command, &env)?;
+            let exit_code = match output.status.and_then(|status| status.code()) {
+                Some(code) if code < 128 => code,
+                status => {
+                    let reason = match status {
+                        Some(code) => format!("test command aborted with exit code {} on {}", code, commit),
+                        None if output.timed_out() => format!("test command timed out after {}s on {}", timeout.as_secs(), commit),
+                        None => format!("test command was killed by a signal on {}", commit),
+                    };
+                    return Ok(Err((Vec::new(), format!("{}:\n{}", reason, output.tail))));
+                }
+            };
+            let result = match exit_code {
+                0 => StepResult::Good,
+                SKIP_EXIT_CODE => StepResult::Skip,
+                _ => StepResult::Bad,
+            };
+            tracing::info!(id = session.id, %commit, %result, "bisect step");
+            let step = BisectStep {
+                commit,
+                result,
+                exit_code,
+                output: output.tail,
+            };
+            steps = self.update(session.id, |session| session.steps.push(step))?.steps;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{commit_files, init_repo};
+
+    fn request(good: ObjectId, bad: ObjectId, command: &str) -> NewBisect {
+        NewBisect {
+            good: vec![good],
+            bad,
+            command: command.to_string(),
+            timeout_secs: 10,
+            requested_by: "alice".to_string(),
+        }
+    }
+
+    /// 测试找到第一个有问题的提交，跳过无法测试的提交，且不重复已有的测试结果
+    #[test]
+    fn test_bisect() {
+        let (_dir, repo) = init_repo();
+        let mut commits = Vec::new();
+        for i in 0..8 {
+            let status: &[u8] = if i < 5 { b"ok" } else { b"bad" };
+            let counter = i.to_string();
+            let parents: Vec<ObjectId> = commits.last().copied().into_iter().collect();
+            commits.push(commit_files(&repo, &[("status", status), ("n", counter.as_bytes())], &parents, &format!("c{}", i)));
+        }
+        let bisector = Bisector::new(&repo);
+        let session = bisector.start(request(commits[0], commits[7], "grep -q ok status"), 100).unwrap();
+        assert_eq!(session.id, 1);
+        let session = bisector.run(session.id).unwrap();
+        assert_eq!(session.state, BisectState::Found);
+        assert_eq!(session.first_bad, Some(commits[5]));
+        assert!(session.steps.len() <= 3, "{:?}", session.steps);
+        assert!(!repo.mono_dir().join(BISECT_DIR).join("1").join(WORK_DIR).exists());
+
+        // 提交 4 与 5 无法测试，第一个有问题的提交只能确定在 4 到 5 之间
+        let command = "n=$(cat n); if [ $n = 4 ] || [ $n = 5 ]; then exit 125; fi; grep -q ok status";
+        let session = bisector.start(request(commits[0], commits[7], command), 100).unwrap();
+        let session = bisector.run(session.id).unwrap();
+        assert_eq!(session.state, BisectState::Failed);
+        assert_eq!(session.suspects, &commits[4..=6]);
+
+        let session = bisector.start(request(commits[0], commits[7], "exit 0"), 100).unwrap();
+        bisector
+            .update(session.id, |session| {
+                session.steps.push(BisectStep {
+                    commit: commits[4],
+                    result: StepResult::Bad,
+                    exit_code: 1,
+                    output: String::new(),
+                })
+            })
+            .unwrap();
+        let session = bisector.run(session.id).unwrap();
+        assert_eq!(session.first_bad, Some(commits[4]));
+        assert!(session.steps[1..].iter().all(|step| step.result == StepResult::Good));
+
+        assert!(bisector.start(request(commits[7], commits[0], "true"), 100).is_err());
+        assert!(bisector.start(request(commits[0], commits[7], " "), 100).is_err());
+        assert_eq!(bisector.list().unwrap().len(), 3);
+    }
+}
@@ -0,0 +1,304 @@
+//! 逐行追溯文件内容的来源提交（blame）
+//!
+//! 某个提交中文件的 blame 结果称为该提交的一层：与某个父提交中的文件完全相同时直接沿用父提交的层，
+//! 否则与每个父提交的版本逐行比较，未改动的行继承父提交层中的来源，其余的行归属于该提交本身。
+//! 合并提交按父提交顺序取第一个能匹配上的来源。不跟踪重命名，文件在父提交中不存在时视为新增。
+//!
+//! 计算出的层保存在 `.mono/blame/v1/<提交>/<路径哈希>.json`。历史不可变，同一提交与路径的层
+//! 永远不变，缓存无需失效；再次查询时遇到已缓存的层即停止向下遍历，热点文件的查询只需读取一个
+//! 文件。浅仓库的边界提交没有父提交，得出的结果在补全历史后会改变，因此不写入缓存。
+//! 缓存目录可以随时删除。
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::diff::{self, Algorithm};
+use crate::graph::history::History;
+use crate::object::{ObjectId, ObjectType};
+use crate::repo::Repository;
+
+/// blame 缓存相对于 `.mono` 目录的路径，格式变化时升级版本号
+pub const BLAME_DIR: &str = "blame/v1";
+
+/// 一行的来源：引入它的提交及其在该提交文件中的行号（从 1 开始）
+type Origin = (ObjectId, usize);
+
+/// 文件中一段连续行的来源
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlameRange {
+    /// 引入这些行的提交
+    pub commit: ObjectId,
+    /// 在该提交的文件中的起始行号（从 1 开始）
+    pub origin_line: usize,
+    /// 在被查询的文件中的起始行号（从 1 开始）
+    pub line: usize,
+    /// 行数
+    pub len: usize,
+}
+
+/// 文件在某个提交中的 blame 结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Blame {
+    pub commit: ObjectId,
+    pub path: String,
+    /// 按行号排列、相邻且来源连续的行合并为一段
+    pub ranges: Vec<BlameRange>,
+    /// 文件内容按行切分，保留换行符
+    pub lines: Vec<Vec<u8>>,
+}
+
+/// 一层保存到缓存中的形式
+#[derive(Serialize, Deserialize)]
+struct Layer {
+    ranges: Vec<BlameRange>,
+}
+
+/// 尚未计算的层：沿用某个父提交的层，或者与各父提交的版本逐行比较
+enum Pending {
+    Same(ObjectId),
+    Diff { blob: ObjectId, parents: Vec<(ObjectId, ObjectId)> },
+}
+
+/// 按提交缓存层的 blame 计算
+pub struct Blamer<'a> {
+    repo: &'a Repository,
+    history: History<'a>,
+    dir: PathBuf,
+    /// 浅仓库中不写缓存
+    cacheable: bool,
+}
+
+impl<'a> Blamer<'a> {
+    pub fn new(repo: &'a Repository) -> MonoResult<Blamer<'a>> {
+        Ok(Blamer {
+            repo,
+            history: History::new(repo)?,
+            dir: repo.mono_dir().join(BLAME_DIR),
+            cacheable: repo.shallow_commits().is_empty(),
+        })
+    }
+
+    /// `commit` 中文件 `path` 每一行的来源；路径不存在或不是文件时返回 `NotFound`
+    pub fn blame(&self, commit: &ObjectId, path: &str) -> MonoResult<Blame> {
+        let path = path.trim_matches('/');
+        let blob = self
+            .file(&self.history.commit(commit)?.tree, path)?
+            .ok_or_else(|| MonoError::not_found(format!("file {} in {}", path, commit)))?;
+        let origins = self.origins(commit, path)?;
+        let lines = diff::split_lines(&self.read_blob(&blob)?).into_iter().map(<[u8]>::to_vec).collect();
+        Ok(Blame {
+            commit: *commit,
+            path: path.to_string(),
+            ranges: to_ranges(&origins),
+            lines,
+        })
+    }
+
+    /// 计算并缓存 `commit` 的层，必要时先计算它依赖的父提交的层
+    fn origins(&self, commit: &ObjectId, path: &str) -> MonoResult<Arc<Vec<Origin>>> {
+        let mut layers: HashMap<ObjectId, Arc<Vec<Origin>>> = HashMap::new();
+        let mut pending: HashMap<ObjectId, Pending> = HashMap::new();
+        // 显式的栈代替递归，很长的历史也不会耗尽调用栈；第二个值表示依赖是否都已入栈
+        let mut stack = vec![(*commit, false)];
+        while let Some((id, expanded)) = stack.pop() {
+            if layers.contains_key(&id) {
+                continue;
+            }
+            if expanded {
+                let origins = match pending.remove(&id).expect("expanded commits are pending") {
+                    Pending::Same(parent) => layers[&parent].clone(),
+                    Pending::Diff { blob, parents } => {
+                        let origins = Arc::new(self.attribute(&id, &blob, &parents, &layers)?);
+                        self.store(&id, path, &origins)?;
+                        origins
+                    }
+                };
+                layers.insert(id, origins);
+                continue;
+            }
+            if pending.contains_key(&id) {
+                continue;
+            }
+            if let Some(origins) = self.load(&id, path)? {
+                layers.insert(id, Arc::new(origins));
+                continue;
+            }
+            let current = self.history.commit(&id)?;
+            let blob = self
+                .file(&current.tree, path)?
+                .ok_or_else(|| MonoError::not_found(format!("file {} in {}", path, id)))?;
+            let mut parents = Vec::new();
+            for parent in &current.parents {
+                if let Some(parent_blob) = self.file(&self.history.commit(parent)?.tree, path)? {
+                    parents.push((*parent, parent_blob));
+                }
+            }
+            let next = match parents.iter().find(|(_, parent_blob)| *parent_blob == blob) {
+                Some((parent, _)) => Pending::Same(*parent),
+                None => Pending::Diff { blob, parents },
+            };
+            stack.push((id, true));
+            match &next {
+                Pending::Same(parent) => stack.push((*parent, false)),
+                Pending::Diff { parents, .. } => stack.extend(parents.iter().map(|(parent, _)| (*parent, false))),
+            }
+            pending.insert(id, next);
+        }
+        // 被查询的提交通常是分支顶端，即使沿用了父提交的层也缓存一份，下次查询无需向下遍历
+        let origins = layers.remove(commit).expect("requested commit is computed");
+        if !self.layer_path(commit, path).exists() {
+            self.store(commit, path, &origins)?;
+        }
+        Ok(origins)
+    }
+
+    /// 与各父提交的版本逐行比较，得出文件内容与父提交都不同的提交的层
+    fn attribute(
+        &self,
+        commit: &ObjectId,
+        blob: &ObjectId,
+        parents: &[(ObjectId, ObjectId)],
+        layers: &HashMap<ObjectId, Arc<Vec<Origin>>>,
+    ) -> MonoResult<Vec<Origin>> {
+        let data = self.read_blob(blob)?;
+        let lines = diff::split_lines(&data);
+        let mut origins: Vec<Option<Origin>> = vec![None; lines.len()];
+        for (parent, parent_blob) in parents {
+            let parent_data = self.read_blob(parent_blob)?;
+            let parent_lines = diff::split_lines(&parent_data);
+            let parent_origins = &layers[parent];
+            for (line, matched) in diff::match_lines(&parent_lines, &lines, Algorithm::Myers).into_iter().enumerate() {
+                if let (None, Some(old)) = (origins[line], matched) {
+                    origins[line] = parent_origins.get(old).copied();
+                }
+            }
+        }
+        Ok(origins
+            .into_iter()
+            .enumerate()
+            .map(|(line, origin)| origin.unwrap_or((*commit, line + 1)))
+            .collect())
+    }
+
+    /// 路径在树中对应的文件，目录与子模块视为不存在
+    fn file(&self, tree: &ObjectId, path: &str) -> MonoResult<Option<ObjectId>> {
+        Ok(self.repo.find_path(tree, path)?.filter(|entry| entry.mode.is_blob()).map(|entry| entry.id))
+    }
+
+    fn read_blob(&self, id: &ObjectId) -> MonoResult<Vec<u8>> {
+        let object = self.repo.read_object(id)?;
+        if object.object_type != ObjectType::Blob {
+            return Err(MonoError::usage(format!("{} is not a blob", id)));
+        }
+        Ok(object.data)
+    }
+
+    fn layer_path(&self, commit: &ObjectId, path: &str) -> PathBuf {
+        let key: String = Sha256::digest(path.as_bytes()).iter().map(|b| format!("{:02x}", b)).collect();
+        self.dir.join(commit.to_hex()).join(format!("{}.json", key))
+    }
+
+    /// 读取缓存的层，缓存损坏时忽略并重新计算
+    fn load(&self, commit: &ObjectId, path: &str) -> MonoResult<Option<Vec<Origin>>> {
+        let file = self.layer_path(commit, path);
+        let data = match std::fs::read(&file) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        match serde_json::from_slice::<Layer>(&data) {
+            Ok(layer) => Ok(Some(from_ranges(&layer.ranges))),
+            Err(e) => {
+                tracing::warn!(path = %file.display(), error = %e, "ignoring corrupt blame cache");
+                Ok(None)
+            }
+        }
+    }
+
+    fn store(&self, commit: &ObjectId, path: &str, origins: &[Origin]) -> MonoResult<()> {
+        if !self.cacheable {
+            return Ok(());
+        }
+        let file = self.layer_path(commit, path);
+        if let Some(parent) = file.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let layer = Layer { ranges: to_ranges(origins) };
+        let data = serde_json::to_vec(&layer).map_err(|e| MonoError::storage(e.to_string()))?;
+        // 多个进程可能同时写同一层，临时文件名带上进程号，内容相同，谁后改名都可以
+        let tmp = file.with_extension(format!("json.{}.tmp", std::process::id()));
+        std::fs::write(&tmp, data)?;
+        std::fs::rename(&tmp, file)?;
+        Ok(())
+    }
+}
+
+/// 把逐行的来源合并为连续的段
+fn to_ranges(origins: &[Origin]) -> Vec<BlameRange> {
+    let mut ranges: Vec<BlameRange> = Vec::new();
+    for (i, &(commit, origin_line)) in origins.iter().enumerate() {
+        match ranges.last_mut() {
+            Some(last) if last.commit == commit && last.origin_line + last.len == origin_line => last.len += 1,
+            _ => ranges.push(BlameRange {
+                commit,
+                origin_line,
+                line: i + 1,
+                len: 1,
+            }),
+        }
+    }
+    ranges
+}
+
+fn from_ranges(ranges: &[BlameRange]) -> Vec<Origin> {
+    ranges
+        .iter()
+        .flat_map(|range| (0..range.len).map(move |i| (range.commit, range.origin_line + i)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{commit_files, init_repo};
+
+    /// 测试 blame 沿未改动的行追溯到引入它的提交，合并提交取父提交中的来源，结果写入缓存并可复用
+    #[test]
+    fn test_blame() {
+        let (_dir, repo) = init_repo();
+        let first = commit_files(&repo, &[("a.txt", b"one\ntwo\nthree\n")], &[], "first");
+        let second = commit_files(&repo, &[("a.txt", b"one\n2\nthree\nfour\n")], &[first], "second");
+        let side = commit_files(&repo, &[("a.txt", b"zero\none\ntwo\nthree\n")], &[first], "side");
+        let merge = commit_files(&repo, &[("a.txt", b"zero\none\n2\nthree\nfour\n")], &[second, side], "merge");
+        // 只改动其他文件的提交沿用父提交的层
+        let tip = commit_files(
+            &repo,
+            &[("a.txt", b"zero\none\n2\nthree\nfour\n"), ("b.txt", b"b")],
+            &[merge],
+            "tip",
+        );
+
+        let blamer = Blamer::new(&repo).unwrap();
+        let blame = blamer.blame(&tip, "a.txt").unwrap();
+        let origins = from_ranges(&blame.ranges);
+        assert_eq!(origins, vec![(side, 1), (first, 1), (second, 2), (first, 3), (second, 4)]);
+        assert_eq!(blame.lines[2], b"2\n");
+        assert_eq!(blame.ranges.len(), 5);
+
+        // 每个改动了文件的提交与被查询的提交都有缓存；缓存的层被直接使用
+        for commit in [first, second, side, merge, tip] {
+            assert!(blamer.layer_path(&commit, "a.txt").exists());
+        }
+        let forged = vec![(first, 1); 5];
+        blamer.store(&tip, "a.txt", &forged).unwrap();
+        assert_eq!(from_ranges(&blamer.blame(&tip, "a.txt").unwrap().ranges), forged);
+
+        assert!(blamer.blame(&tip, "missing.txt").is_err());
+    }
+}
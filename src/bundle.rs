@@ -0,0 +1,344 @@
+//! git bundle 与 bundle-uri
+//!
+//! bundle 是带有引用列表的 pack 文件，格式与 `git bundle` 相同：
+//!
+//! ```text
+//! # v2 git bundle            （sha256 仓库为 `# v3 git bundle` 加 `@object-format=sha256`）
+//! -<对象名> <说明>           前提提交，应用 bundle 的仓库必须已有这些提交
+//! <对象名> <引用名>
+//!
+//! <pack 数据>
+//! ```
+//!
+//! `mono bundle create` 生成的 bundle 保存在 `.mono/bundles/`，由运维上传到 CDN。配置
+//! `[bundles] base_url` 后 upload-pack 声明协议 v2 的 `bundle-uri` 能力，克隆的客户端先从该地址
+//! 按 `creationToken` 顺序下载 bundle，再向服务端增量获取之后的提交。增量 bundle 以之前所有
+//! bundle 的引用为前提，只包含新的对象。
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Write;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::object::filter::ObjectFilter;
+use crate::object::walk::collect_named_objects;
+use crate::object::{ObjectFormat, ObjectId, ObjectType};
+use crate::pack::deltify::{self, DeltaOptions, PackObject};
+use crate::pack::PackWriter;
+use crate::refs;
+use crate::repo::Repository;
+
+/// bundle 目录，相对于 `.mono`
+pub const BUNDLE_DIR: &str = "bundles";
+/// bundle 列表文件
+const LIST_FILE: &str = "bundles.json";
+
+/// bundle 的头部：前提提交与引用
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BundleHeader {
+    pub format: ObjectFormat,
+    pub prerequisites: Vec<ObjectId>,
+    pub refs: Vec<(String, ObjectId)>,
+}
+
+impl BundleHeader {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = String::new();
+        if self.format == ObjectFormat::Sha1 {
+            out.push_str("# v2 git bundle\n");
+        } else {
+            out.push_str(&format!("# v3 git bundle\n@object-format={}\n", self.format));
+        }
+        for id in &self.prerequisites {
+            out.push_str(&format!("-{}\n", id));
+        }
+        for (name, id) in &self.refs {
+            out.push_str(&format!("{} {}\n", id, name));
+        }
+        out.push('\n');
+        out.into_bytes()
+    }
+
+    /// 解析头部，返回头部与 pack 数据的起始位置
+    pub fn parse(data: &[u8]) -> MonoResult<(BundleHeader, usize)> {
+        let invalid = |msg: &str| MonoError::protocol(format!("invalid bundle: {}", msg));
+        let mut header = BundleHeader {
+            format: ObjectFormat::Sha1,
+            prerequisites: Vec::new(),
+            refs: Vec::new(),
+        };
+        let mut pos = 0;
+        let mut first = true;
+        loop {
+            let end = data[pos..]
+                .iter()
+                .position(|b| *b == b'\n')
+                .ok_or_else(|| invalid("truncated header"))?;
+            let line = std::str::from_utf8(&data[pos..pos + end]).map_err(|_| invalid("header is not utf-8"))?;
+            pos += end + 1;
+            if first {
+                if line != "# v2 git bundle" && line != "# v3 git bundle" {
+                    return Err(invalid("missing signature"));
+                }
+                first = false;
+            } else if line.is_empty() {
+                return Ok((header, pos));
+            } else if let Some(capability) = line.strip_prefix('@') {
+                if let Some(format) = capability.strip_prefix("object-format=") {
+                    header.format = format.parse()?;
+                } else {
+                    return Err(invalid(&format!("unsupported capability {}", capability)));
+                }
+            } else if let Some(rest) = line.strip_prefix('-') {
+                let id = rest.split(' ').next().unwrap_or_default();
+                header.prerequisites.push(id.parse()?);
+            } else {
+                let (id, name) = line.split_once(' ').ok_or_else(|| invalid(&format!("bad ref line {:?}", line)))?;
+                header.refs.push((name.to_string(), id.parse()?));
+            }
+        }
+    }
+}
+
+/// 把 `refs` 可达、`exclude` 不可达的对象写为 bundle，`exclude` 中的提交成为前提
+#[tracing::instrument(skip_all, fields(refs = refs.len(), exclude = exclude.len()))]
+pub fn write_bundle(
+    repo: &Repository,
+    refs: &[(String, ObjectId)],
+    exclude: &[ObjectId],
+    out: &mut dyn Write,
+) -> MonoResult<BundleHeader> {
+    let mut prerequisites = Vec::new();
+    for id in exclude {
+        if repo.objects().read_header(id)?.is_some_and(|(object_type, _)| object_type == ObjectType::Commit) {
+            prerequisites.push(*id);
+        }
+    }
+    let header = BundleHeader {
+        format: repo.object_format(),
+        prerequisites,
+        refs: refs.to_vec(),
+    };
+    out.write_all(&header.encode())?;
+
+    let tips: Vec<ObjectId> = refs.iter().map(|(_, id)| *id).collect();
+    let mut reader = |id: &ObjectId| repo.read_object(id);
+    let objects = collect_named_objects(&tips, exclude, &ObjectFilter::None, &mut reader)?;
+    let mut pack_objects = Vec::with_capacity(objects.len());
+    for (id, object_type, name_hash) in objects {
+        let size = repo.objects().read_header(&id)?.map_or(0, |(_, size)| size);
+        pack_objects.push(PackObject {
+            id,
+            object_type,
+            name_hash,
+            size,
+        });
+    }
+    let mut pack = PackWriter::with_format(out, pack_objects.len() as u32, header.format)?;
+    deltify::write_objects(&mut pack, pack_objects, &mut reader, &DeltaOptions::default())?;
+    pack.finish()?;
+    Ok(header)
+}
+
+/// bundle 默认包含的引用：全部分支与标签
+pub fn branches_and_tags(repo: &Repository) -> MonoResult<Vec<(String, ObjectId)>> {
+    let mut refs = repo.refs().list(refs::HEADS_PREFIX)?;
+    refs.extend(repo.refs().list(refs::TAGS_PREFIX)?);
+    Ok(refs)
+}
+
+/// 把 bundle 中的对象写入仓库，返回头部；不更新引用
+pub fn unbundle(repo: &Repository, data: &[u8]) -> MonoResult<BundleHeader> {
+    let (header, offset) = BundleHeader::parse(data)?;
+    if header.format != repo.object_format() {
+        return Err(MonoError::protocol(format!(
+            "bundle uses {} but the repository uses {}",
+            header.format,
+            repo.object_format()
+        )));
+    }
+    for id in &header.prerequisites {
+        if !repo.objects().contains(id)? {
+            return Err(MonoError::not_found(format!("bundle requires missing commit {}", id)));
+        }
+    }
+    let count = repo.objects().write_pack(&data[offset..])?;
+    tracing::info!(objects = count, refs = header.refs.len(), "unbundled objects");
+    Ok(header)
+}
+
+/// `.mono/bundles/` 中登记的一个 bundle
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct BundleInfo {
+    /// bundle 名，也是 bundle-uri 列表中的 ID
+    pub id: String,
+    /// 相对于 bundle 目录与 `base_url` 的文件名
+    pub file: String,
+    /// 单调递增，客户端按该顺序应用 bundle
+    pub creation_token: u64,
+    pub refs: BTreeMap<String, ObjectId>,
+    pub prerequisites: Vec<ObjectId>,
+    pub size: u64,
+    pub created_at: i64,
+}
+
+/// 仓库中登记的 bundle
+pub struct BundleStore<'a> {
+    repo: &'a Repository,
+    dir: PathBuf,
+}
+
+impl<'a> BundleStore<'a> {
+    pub fn new(repo: &'a Repository) -> BundleStore<'a> {
+        BundleStore {
+            repo,
+            dir: repo.mono_dir().join(BUNDLE_DIR),
+        }
+    }
+
+    /// 按 creationToken 排列的 bundle
+    pub fn list(&self) -> MonoResult<Vec<BundleInfo>> {
+        let data = match std::fs::read(self.dir.join(LIST_FILE)) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        serde_json::from_slice(&data).map_err(|e| MonoError::storage(format!("invalid {}: {}", LIST_FILE, e)))
+    }
+
+    /// 为分支与标签生成 bundle 并登记；`incremental` 时以已有 bundle 的引用为前提，只包含新对象
+    pub fn create(&self, incremental: bool, now: i64) -> MonoResult<BundleInfo> {
+        let mut bundles = self.list()?;
+        let refs = branches_and_tags(self.repo)?;
+        if refs.is_empty() {
+            return Err(MonoError::usage("no branches or tags to bundle"));
+        }
+        let exclude: Vec<ObjectId> = match incremental {
+            true => bundles
+                .iter()
+                .flat_map(|bundle| bundle.refs.values().copied())
+                .collect::<BTreeSet<_>>()
+                .into_iter()
+                .collect(),
+            false => Vec::new(),
+        };
+        if incremental && bundles.is_empty() {
+            return Err(MonoError::usage("no bundle to build on, create a full bundle first"));
+        }
+        if refs.iter().all(|(_, id)| exclude.contains(id)) {
+            return Err(MonoError::usage("nothing new to bundle"));
+        }
+        let creation_token = bundles
+            .iter()
+            .map(|bundle| bundle.creation_token + 1)
+            .max()
+            .unwrap_or(0)
+            .max(now.max(0) as u64);
+        let id = format!("{}-{}", if incremental { "incremental" } else { "full" }, creation_token);
+        let file = format!("{}.bundle", id);
+
+        std::fs::create_dir_all(&self.dir)?;
+        let path = self.dir.join(&file);
+        let tmp = path.with_extension("bundle.tmp");
+        let mut out = std::io::BufWriter::new(std::fs::File::create(&tmp)?);
+        let header = write_bundle(self.repo, &refs, &exclude, &mut out)?;
+        out.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        std::fs::rename(&tmp, &path)?;
+
+        let info = BundleInfo {
+            id,
+            file,
+            creation_token,
+            refs: refs.into_iter().collect(),
+            prerequisites: header.prerequisites,
+            size: std::fs::metadata(&path)?.len(),
+            created_at: now,
+        };
+        bundles.push(info.clone());
+        self.save(&bundles)?;
+        tracing::info!(bundle = %info.id, size = info.size, "created bundle");
+        Ok(info)
+    }
+
+    fn save(&self, bundles: &[BundleInfo]) -> MonoResult<()> {
+        let data = serde_json::to_vec_pretty(bundles).map_err(|e| MonoError::storage(e.to_string()))?;
+        let path = self.dir.join(LIST_FILE);
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, data)?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    /// bundle-uri 命令的响应行；未配置 `[bundles] base_url` 或没有 bundle 时为空
+    pub fn advertisement(&self) -> MonoResult<Vec<String>> {
+        let Some(base_url) = &self.repo.config().bundles.base_url else {
+            return Ok(Vec::new());
+        };
+        let bundles = self.list()?;
+        if bundles.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut lines = vec![
+            "bundle.version=1".to_string(),
+            "bundle.mode=all".to_string(),
+            "bundle.heuristic=creationToken".to_string(),
+        ];
+        for bundle in &bundles {
+            lines.push(format!("bundle.{}.uri={}/{}", bundle.id, base_url.trim_end_matches('/'), bundle.file));
+            lines.push(format!("bundle.{}.creationToken={}", bundle.id, bundle.creation_token));
+        }
+        Ok(lines)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::refs::RefUpdate;
+    use crate::test_utils::{commit_files, init_repo};
+
+    /// 测试生成完整与增量 bundle，在新仓库中按顺序应用，以及 bundle-uri 列表
+    #[test]
+    fn test_bundles() {
+        let (_dir, mut repo) = init_repo();
+        let first = commit_files(&repo, &[("a.txt", b"a")], &[], "first");
+        let update = |repo: &Repository, old, new| {
+            repo.refs()
+                .update(&[RefUpdate {
+                    name: "refs/heads/main".to_string(),
+                    old,
+                    new,
+                }])
+                .unwrap()
+        };
+        update(&repo, ObjectId::ZERO, first);
+        let store = BundleStore::new(&repo);
+        assert!(store.create(true, 100).is_err());
+        let full = store.create(false, 100).unwrap();
+        assert!(full.prerequisites.is_empty());
+        assert!(store.create(true, 100).is_err());
+
+        let second = commit_files(&repo, &[("a.txt", b"b")], &[first], "second");
+        update(&repo, first, second);
+        let incremental = store.create(true, 50).unwrap();
+        assert_eq!(incremental.creation_token, 101);
+        assert_eq!(incremental.prerequisites, vec![first]);
+
+        let (_clone_dir, clone) = init_repo();
+        let read = |info: &BundleInfo| std::fs::read(repo.mono_dir().join(BUNDLE_DIR).join(&info.file)).unwrap();
+        assert!(unbundle(&clone, &read(&incremental)).is_err());
+        unbundle(&clone, &read(&full)).unwrap();
+        let header = unbundle(&clone, &read(&incremental)).unwrap();
+        assert_eq!(header.refs, vec![("refs/heads/main".to_string(), second)]);
+        assert!(clone.read_commit(&second).is_ok());
+
+        assert!(store.advertisement().unwrap().is_empty());
+        repo.config_mut().bundles.base_url = Some("https://cdn.example.com/mono/".to_string());
+        let lines = BundleStore::new(&repo).advertisement().unwrap();
+        assert_eq!(lines[3], "bundle.full-100.uri=https://cdn.example.com/mono/full-100.bundle");
+        assert_eq!(lines[6], "bundle.incremental-101.creationToken=101");
+    }
+}
@@ -0,0 +1,198 @@
+//! 项目依赖图
+//!
+//! 每个项目可以在目录下的 `DEPS` 文件中声明依赖的其他项目：
+//!
+//! ```toml
+//! deps = ["//libs/common", "//libs/proto"]
+//! ```
+//!
+//! 一个项目改动后，所有直接或间接依赖它的项目都需要重新构建和测试。依赖必须指向
+//! [`ProjectLayout`] 中定义的项目，拼写错误的依赖会被报告而不是静默忽略，以免 CI 漏掉构建。
+
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+use serde::{Deserialize, Serialize};
+
+use crate::changed::{changed_since, AffectedProject, ProjectLayout};
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::object::ObjectId;
+use crate::repo::Repository;
+
+/// 项目目录下的依赖声明文件名
+pub const DEPS_FILE: &str = "DEPS";
+
+/// `DEPS` 文件的内容
+#[derive(Deserialize, Debug)]
+struct DepsFile {
+    #[serde(default)]
+    deps: Vec<String>,
+}
+
+/// 项目之间的依赖关系
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DepGraph {
+    /// 项目到其直接依赖
+    deps: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl DepGraph {
+    /// 读取树中全部项目的 `DEPS` 文件
+    pub fn load(repo: &Repository, tree: &ObjectId, layout: &ProjectLayout) -> MonoResult<DepGraph> {
+        let projects = layout.projects(repo, tree)?;
+        let mut graph = DepGraph::default();
+        for project in &projects {
+            graph.deps.insert(project.clone(), BTreeSet::new());
+        }
+        for project in &projects {
+            let path = format!("{}/{}", project, DEPS_FILE);
+            let Some(entry) = repo.find_path(tree, &path)? else {
+                continue;
+            };
+            let content = String::from_utf8_lossy(&repo.read_object(&entry.id)?.data).into_owned();
+            let file: DepsFile =
+                toml::from_str(&content).map_err(|e| MonoError::config(format!("{}: {}", path, e.message())))?;
+            for dep in file.deps {
+                let name = dep.strip_prefix("//").unwrap_or(&dep).trim_end_matches('/');
+                if !graph.deps.contains_key(name) {
+                    return Err(MonoError::config(format!("{}: unknown project {}", path, dep)));
+                }
+                if name != project {
+                    graph.deps.get_mut(project).expect("project registered").insert(name.to_string());
+                }
+            }
+        }
+        Ok(graph)
+    }
+
+    /// 项目的直接依赖
+    pub fn dependencies(&self, project: &str) -> impl Iterator<Item = &String> {
+        self.deps.get(project).into_iter().flatten()
+    }
+
+    /// 依赖 `changed` 中任一项目的全部项目（包括 `changed` 本身），值为到改动项目的依赖链
+    ///
+    /// 依赖链从受影响项目的直接依赖开始，到改动的项目为止；改动的项目自身对应空链。
+    /// 广度优先遍历，因此记录的是最短的依赖链。
+    pub fn reverse_closure<'s>(&self, changed: impl IntoIterator<Item = &'s str>) -> BTreeMap<String, Vec<String>> {
+        let mut dependents: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+        for (project, deps) in &self.deps {
+            for dep in deps {
+                dependents.entry(dep).or_default().push(project);
+            }
+        }
+        let mut via: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        let mut queue = VecDeque::new();
+        for project in changed {
+            if via.insert(project.to_string(), Vec::new()).is_none() {
+                queue.push_back(project.to_string());
+            }
+        }
+        while let Some(project) = queue.pop_front() {
+            for dependent in dependents.get(project.as_str()).into_iter().flatten() {
+                if via.contains_key(*dependent) {
+                    continue;
+                }
+                let mut chain = vec![project.clone()];
+                chain.extend(via[&project].iter().cloned());
+                via.insert(dependent.to_string(), chain);
+                queue.push_back(dependent.to_string());
+            }
+        }
+        via
+    }
+}
+
+/// 受影响的项目
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct ImpactedProject {
+    pub path: String,
+    /// 导致该项目受影响的依赖链，项目本身有改动时为空
+    pub via: Vec<String>,
+}
+
+/// 一段修订范围的影响分析结果
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Impact {
+    pub base: ObjectId,
+    pub head: ObjectId,
+    /// 有文件改动的项目
+    pub changed: Vec<AffectedProject>,
+    /// 有改动的项目及其全部反向依赖，按路径排序
+    pub impacted: Vec<ImpactedProject>,
+    /// 不属于任何项目的改动文件
+    pub other: Vec<String>,
+}
+
+/// 计算 `until` 相对于 `since` 的改动影响的项目，包括传递的反向依赖
+///
+/// 依赖图读取 `until` 中的 `DEPS` 文件。
+pub fn impacted_since(repo: &Repository, since: &ObjectId, until: &ObjectId) -> MonoResult<Impact> {
+    let changes = changed_since(repo, since, until)?;
+    let tree = repo.read_commit(until)?.tree;
+    let graph = DepGraph::load(repo, &tree, &ProjectLayout::load(repo, &tree)?)?;
+    let impacted = graph
+        .reverse_closure(changes.projects.iter().map(|project| project.path.as_str()))
+        .into_iter()
+        .map(|(path, via)| ImpactedProject { path, via })
+        .collect();
+    Ok(Impact {
+        base: changes.base,
+        head: changes.head,
+        changed: changes.projects,
+        impacted,
+        other: changes.other,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::changed::PROJECTS_FILE;
+    use crate::test_utils::{commit_files, init_repo};
+
+    const PROJECTS: &[u8] = br#"roots = ["//services/*", "//libs/*"]"#;
+
+    /// 测试传递的反向依赖与最短依赖链，以及未知依赖的报错
+    #[test]
+    fn test_impacted() {
+        let (_dir, repo) = init_repo();
+        fn files(common: &'static [u8]) -> Vec<(&'static str, &'static [u8])> {
+            vec![
+                (PROJECTS_FILE, PROJECTS),
+                ("libs/common/lib.rs", common),
+                ("libs/proto/DEPS", br#"deps = ["//libs/common"]"#.as_slice()),
+                ("libs/proto/p.proto", b"1".as_slice()),
+                ("services/pay/DEPS", br#"deps = ["//libs/proto", "//libs/common"]"#.as_slice()),
+                ("services/web/DEPS", br#"deps = ["//libs/proto"]"#.as_slice()),
+                ("services/web/main.rs", b"1".as_slice()),
+                ("services/auth/main.rs", b"1".as_slice()),
+            ]
+        }
+        let base = commit_files(&repo, &files(b"1"), &[], "base");
+        let head = commit_files(&repo, &files(b"2"), &[base], "change common");
+
+        let impact = impacted_since(&repo, &base, &head).unwrap();
+        assert_eq!(impact.changed.iter().map(|p| p.path.as_str()).collect::<Vec<_>>(), ["libs/common"]);
+        let impacted: Vec<(&str, Vec<&str>)> = impact
+            .impacted
+            .iter()
+            .map(|p| (p.path.as_str(), p.via.iter().map(String::as_str).collect()))
+            .collect();
+        assert_eq!(
+            impacted,
+            [
+                ("libs/common", vec![]),
+                ("libs/proto", vec!["libs/common"]),
+                ("services/pay", vec!["libs/common"]),
+                ("services/web", vec!["libs/proto", "libs/common"]),
+            ]
+        );
+
+        let mut broken = files(b"3");
+        broken.push(("services/auth/DEPS", br#"deps = ["//libs/comon"]"#.as_slice()));
+        let broken = commit_files(&repo, &broken, &[head], "typo");
+        let err = impacted_since(&repo, &head, &broken).unwrap_err();
+        assert!(err.to_string().contains("unknown project //libs/comon"), "{}", err);
+    }
+}
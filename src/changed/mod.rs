@@ -0,0 +1,268 @@
+//! 变更检测
+//!
+//! 计算一段修订范围影响的项目，CI 据此只构建和测试受影响的服务。项目根目录由仓库根目录的
+//! `projects.toml` 定义，`*` 匹配一层目录：
+//!
+//! ```toml
+//! roots = ["//services/*", "//libs/*", "//tools"]
+//! ```
+//!
+//! 改动的文件归属于匹配的最深的项目根目录；没有该文件时每个顶层目录都是一个项目。
+//! 不属于任何项目的文件（例如根目录下的文件）单独列出，由调用方决定是否触发全量构建。
+//! 项目之间的依赖见 [`deps`]。
+
+pub mod deps;
+
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::graph::history::History;
+use crate::object::ObjectId;
+use crate::repo::Repository;
+
+/// 仓库内项目定义文件名
+pub const PROJECTS_FILE: &str = "projects.toml";
+
+/// 项目根目录模式，例如 `//services/*`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProjectRoot {
+    components: Vec<String>,
+}
+
+impl ProjectRoot {
+    /// 文件所属的项目目录：路径的前几级与模式逐级匹配，且文件位于该目录之下
+    pub fn project_of(&self, path: &str) -> Option<String> {
+        let parts: Vec<&str> = path.split('/').collect();
+        if parts.len() <= self.components.len() {
+            return None;
+        }
+        let matched = self
+            .components
+            .iter()
+            .zip(&parts)
+            .all(|(pattern, part)| pattern == "*" || pattern == part);
+        matched.then(|| parts[..self.components.len()].join("/"))
+    }
+}
+
+impl FromStr for ProjectRoot {
+    type Err = MonoError;
+
+    fn from_str(s: &str) -> MonoResult<ProjectRoot> {
+        let invalid = || MonoError::config(format!("{}: invalid project root: {}", PROJECTS_FILE, s));
+        let path = s.strip_prefix("//").ok_or_else(invalid)?.trim_end_matches('/');
+        let components: Vec<String> = path.split('/').map(str::to_string).collect();
+        if path.is_empty() || components.iter().any(|c| c.is_empty() || c == "." || c == "..") {
+            return Err(invalid());
+        }
+        Ok(ProjectRoot { components })
+    }
+}
+
+/// `projects.toml` 的内容
+#[derive(Deserialize, Debug)]
+struct ProjectsFile {
+    #[serde(default)]
+    roots: Vec<String>,
+}
+
+/// 仓库的项目划分
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProjectLayout {
+    pub roots: Vec<ProjectRoot>,
+}
+
+impl Default for ProjectLayout {
+    /// 每个顶层目录是一个项目
+    fn default() -> Self {
+        ProjectLayout {
+            roots: vec![ProjectRoot {
+                components: vec!["*".to_string()],
+            }],
+        }
+    }
+}
+
+impl ProjectLayout {
+    /// 解析配置文件内容
+    pub fn parse(content: &str) -> MonoResult<ProjectLayout> {
+        let file: ProjectsFile =
+            toml::from_str(content).map_err(|e| MonoError::config(format!("{}: {}", PROJECTS_FILE, e.message())))?;
+        Ok(ProjectLayout {
+            roots: file.roots.iter().map(|root| root.parse()).collect::<MonoResult<_>>()?,
+        })
+    }
+
+    /// 读取树中的项目定义，没有该文件时使用默认划分
+    pub fn load(repo: &Repository, tree: &ObjectId) -> MonoResult<ProjectLayout> {
+        match repo.find_path(tree, PROJECTS_FILE)? {
+            Some(entry) => ProjectLayout::parse(&String::from_utf8_lossy(&repo.read_object(&entry.id)?.data)),
+            None => Ok(ProjectLayout::default()),
+        }
+    }
+
+    /// 树中存在的全部项目目录，按路径排序
+    pub fn projects(&self, repo: &Repository, tree: &ObjectId) -> MonoResult<Vec<String>> {
+        let mut projects = Vec::new();
+        for root in &self.roots {
+            collect_projects(repo, tree, &root.components, "", &mut projects)?;
+        }
+        projects.sort();
+        projects.dedup();
+        Ok(projects)
+    }
+
+    /// 文件所属的项目，多个根目录匹配时取最深的一个
+    pub fn project_of(&self, path: &str) -> Option<String> {
+        self.roots
+            .iter()
+            .filter_map(|root| root.project_of(path))
+            .max_by_key(|project| project.matches('/').count())
+    }
+}
+
+fn collect_projects(
+    repo: &Repository,
+    tree: &ObjectId,
+    components: &[String],
+    prefix: &str,
+    out: &mut Vec<String>,
+) -> MonoResult<()> {
+    let Some((pattern, rest)) = components.split_first() else {
+        out.push(prefix.to_string());
+        return Ok(());
+    };
+    for entry in repo.read_tree(tree)?.entries {
+        if !entry.mode.is_tree() || (pattern != "*" && *pattern != entry.name) {
+            continue;
+        }
+        let path = if prefix.is_empty() {
+            entry.name
+        } else {
+            format!("{}/{}", prefix, entry.name)
+        };
+        collect_projects(repo, &entry.id, rest, &path, out)?;
+    }
+    Ok(())
+}
+
+/// 受影响的项目及其中改动的文件
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct AffectedProject {
+    pub path: String,
+    pub files: Vec<String>,
+}
+
+/// 一段修订范围的变更
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct ChangeSet {
+    /// 比较的起点：`since` 与 `until` 的最近公共祖先
+    pub base: ObjectId,
+    pub head: ObjectId,
+    /// 受影响的项目，按路径排序
+    pub projects: Vec<AffectedProject>,
+    /// 不属于任何项目的改动文件
+    pub other: Vec<String>,
+}
+
+/// 计算 `until` 相对于 `since` 的改动影响的项目
+///
+/// 与 `git diff since...until` 一致，从两者的最近公共祖先开始比较，`since` 上独有的提交
+/// 不计入；没有公共祖先时直接与 `since` 比较。项目划分读取 `until` 中的定义。
+pub fn changed_since(repo: &Repository, since: &ObjectId, until: &ObjectId) -> MonoResult<ChangeSet> {
+    let base = History::new(repo)?
+        .merge_bases(since, until)?
+        .first()
+        .copied()
+        .unwrap_or(*since);
+    let old_tree = repo.read_commit(&base)?.tree;
+    let new_tree = repo.read_commit(until)?.tree;
+    let layout = ProjectLayout::load(repo, &new_tree)?;
+
+    let mut projects: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    let mut other = Vec::new();
+    for path in repo.changed_paths(Some(&old_tree), Some(&new_tree))? {
+        match layout.project_of(&path) {
+            Some(project) => projects.entry(project).or_default().push(path),
+            None => other.push(path),
+        }
+    }
+    Ok(ChangeSet {
+        base,
+        head: *until,
+        projects: projects
+            .into_iter()
+            .map(|(path, files)| AffectedProject { path, files })
+            .collect(),
+        other,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{commit_files, init_repo};
+
+    /// 测试项目根目录模式的匹配，多个模式匹配时取最深的项目
+    #[test]
+    fn test_layout() {
+        let layout = ProjectLayout::parse(r#"roots = ["//services/*", "//services/legacy/*", "//tools"]"#).unwrap();
+        assert_eq!(layout.project_of("services/pay/src/main.rs").as_deref(), Some("services/pay"));
+        assert_eq!(layout.project_of("services/legacy/billing/x").as_deref(), Some("services/legacy/billing"));
+        assert_eq!(layout.project_of("tools/lint.sh").as_deref(), Some("tools"));
+        assert_eq!(layout.project_of("services/README"), None);
+        assert_eq!(layout.project_of("README"), None);
+
+        assert_eq!(ProjectLayout::default().project_of("libs/common/a.rs").as_deref(), Some("libs"));
+        assert!(ProjectLayout::parse(r#"roots = ["services/*"]"#).is_err());
+        assert!(ProjectLayout::parse(r#"roots = ["//a/../b"]"#).is_err());
+    }
+
+    /// 测试从最近公共祖先开始比较，以及使用仓库中的项目定义
+    #[test]
+    fn test_changed_since() {
+        let (_dir, repo) = init_repo();
+        let projects: &[u8] = br#"roots = ["//services/*"]"#;
+        let base = commit_files(
+            &repo,
+            &[(PROJECTS_FILE, projects), ("services/pay/a", b"1"), ("services/auth/a", b"1"), ("libs/x", b"1")],
+            &[],
+            "base",
+        );
+        let main = commit_files(
+            &repo,
+            &[(PROJECTS_FILE, projects), ("services/pay/a", b"1"), ("services/auth/a", b"2"), ("libs/x", b"1")],
+            &[base],
+            "main moves on",
+        );
+        let feature = commit_files(
+            &repo,
+            &[(PROJECTS_FILE, projects), ("services/pay/a", b"2"), ("services/auth/a", b"1"), ("libs/x", b"2")],
+            &[base],
+            "feature",
+        );
+
+        let changes = changed_since(&repo, &main, &feature).unwrap();
+        assert_eq!(changes.base, base);
+        assert_eq!(
+            changes.projects,
+            [AffectedProject {
+                path: "services/pay".to_string(),
+                files: vec!["services/pay/a".to_string()],
+            }]
+        );
+        assert_eq!(changes.other, ["libs/x"]);
+
+        let tree = repo.read_commit(&feature).unwrap().tree;
+        let layout = ProjectLayout::load(&repo, &tree).unwrap();
+        assert_eq!(layout.projects(&repo, &tree).unwrap(), ["services/auth", "services/pay"]);
+        assert_eq!(ProjectLayout::default().projects(&repo, &tree).unwrap(), ["libs", "services"]);
+
+        let changes = changed_since(&repo, &feature, &feature).unwrap();
+        assert!(changes.projects.is_empty() && changes.other.is_empty());
+    }
+}
@@ -0,0 +1,167 @@
+//! 由提交历史生成变更日志
+//!
+//! `mono changelog --since v1.2.0 --path //libs/net` 收集一段修订范围中修改了该目录的提交，
+//! 按 Conventional Commits 的类型分组输出 Markdown 或 JSON。合并提交不列出。
+//! 标题中类型后带 `!` 或正文中有 `BREAKING CHANGE:` 的提交归入不兼容改动；`feat`、`fix` 与
+//! `perf` 各自一组，其余提交归入其他改动。`mono version bump`（见 [`crate::version`]）与
+//! `mono release create --changelog-since` 以同样的变更日志作为发布说明。
+
+use serde::Serialize;
+
+use crate::commit_lint::CONVENTIONAL;
+use crate::common::MonoResult;
+use crate::graph::history::History;
+use crate::object::commit::Commit;
+use crate::object::ObjectId;
+use crate::repo::Repository;
+
+/// 变更日志中的分组，按输出顺序排列
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    /// 不兼容的改动
+    Breaking,
+    /// `feat` 类型的提交
+    Feature,
+    /// `fix` 类型的提交
+    Fix,
+    /// `perf` 类型的提交
+    Performance,
+    Other,
+}
+
+impl ChangeKind {
+    const ALL: [ChangeKind; 5] = [ChangeKind::Breaking, ChangeKind::Feature, ChangeKind::Fix, ChangeKind::Performance, ChangeKind::Other];
+
+    fn heading(self) -> &'static str {
+        match self {
+            ChangeKind::Breaking => "Breaking Changes",
+            ChangeKind::Feature => "Features",
+            ChangeKind::Fix => "Bug Fixes",
+            ChangeKind::Performance => "Performance",
+            ChangeKind::Other => "Other Changes",
+        }
+    }
+}
+
+/// 变更日志中的一个提交
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    pub commit: ObjectId,
+    pub kind: ChangeKind,
+    /// Conventional Commits 标题中的范围，如 `feat(net): ...` 中的 `net`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    /// 去掉类型与范围后的标题
+    pub summary: String,
+    pub author: String,
+}
+
+impl Change {
+    /// 按提交信息归类
+    pub fn from_commit(id: ObjectId, commit: &Commit) -> Change {
+        let subject = commit.summary();
+        let breaking_footer = commit
+            .message
+            .lines()
+            .any(|line| line.starts_with("BREAKING CHANGE:") || line.starts_with("BREAKING-CHANGE:"));
+        let (kind, scope, summary) = match CONVENTIONAL.captures(subject) {
+            Some(captures) => {
+                let (prefix, summary) = subject.split_once(": ").unwrap_or((subject, subject));
+                let kind = match &captures[1] {
+                    _ if prefix.ends_with('!') || breaking_footer => ChangeKind::Breaking,
+                    "feat" => ChangeKind::Feature,
+                    "fix" => ChangeKind::Fix,
+                    "perf" => ChangeKind::Performance,
+                    _ => ChangeKind::Other,
+                };
+                let scope = captures.get(2).map(|scope| scope.as_str().trim_matches(['(', ')']).to_string());
+                (kind, scope, summary)
+            }
+            None if breaking_footer => (ChangeKind::Breaking, None, subject),
+            None => (ChangeKind::Other, None, subject),
+        };
+        Change { commit: id, kind, scope, summary: summary.to_string(), author: commit.author.name.clone() }
+    }
+}
+
+/// 一段修订范围的变更日志
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Changelog {
+    /// 按提交时间从新到旧
+    pub changes: Vec<Change>,
+}
+
+impl Changelog {
+    /// 从 `tips` 可达、从 `exclude` 不可达且修改了 `path` 的提交，空路径不做过滤
+    pub fn generate(repo: &Repository, tips: &[ObjectId], exclude: &[ObjectId], path: &str) -> MonoResult<Changelog> {
+        let path = path.trim_matches('/');
+        let history = History::new(repo)?;
+        let mut changes = Vec::new();
+        for commit in history.range(tips, exclude)? {
+            if commit.parents.len() > 1 || (!path.is_empty() && !history.touches(&commit, path)?) {
+                continue;
+            }
+            changes.push(Change::from_commit(commit.id, &repo.read_commit(&commit.id)?));
+        }
+        Ok(Changelog { changes })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Markdown 格式，每组一个三级标题，没有提交的组省略
+    pub fn markdown(&self) -> String {
+        let mut out = String::new();
+        for kind in ChangeKind::ALL {
+            let changes: Vec<&Change> = self.changes.iter().filter(|change| change.kind == kind).collect();
+            if changes.is_empty() {
+                continue;
+            }
+            if !out.is_empty() {
+                out.push('\n');
+            }
+            out.push_str(&format!("### {}\n\n", kind.heading()));
+            for change in changes {
+                let scope = change.scope.as_deref().map(|scope| format!("**{}:** ", scope)).unwrap_or_default();
+                out.push_str(&format!("- {}{} ({})\n", scope, change.summary, &change.commit.to_hex()[..7]));
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{commit_files, init_repo};
+
+    /// 测试按类型分组、按目录过滤与 Markdown 输出
+    #[test]
+    fn test_generate() {
+        let (_dir, repo) = init_repo();
+        let base = commit_files(&repo, &[("libs/net/a", b"1"), ("web/b", b"1")], &[], "chore: initial import");
+        let feat = commit_files(&repo, &[("libs/net/a", b"2"), ("web/b", b"1")], &[base], "feat(net): add retries");
+        let web = commit_files(&repo, &[("libs/net/a", b"2"), ("web/b", b"2")], &[feat], "fix(web): unrelated");
+        let api = commit_files(&repo, &[("libs/net/a", b"3"), ("web/b", b"2")], &[web], "refactor!: rename Client");
+        let perf = commit_files(&repo, &[("libs/net/a", b"4"), ("web/b", b"2")], &[api], "perf: pool buffers\n\nBREAKING CHANGE: pool size is required\n");
+        let tidy = commit_files(&repo, &[("libs/net/a", b"5"), ("web/b", b"2")], &[perf], "Tidy up");
+
+        let changelog = Changelog::generate(&repo, &[tidy], &[base], "//libs/net").unwrap();
+        let kinds: Vec<(ObjectId, ChangeKind)> = changelog.changes.iter().map(|c| (c.commit, c.kind)).collect();
+        assert_eq!(kinds.len(), 4);
+        for expected in [(feat, ChangeKind::Feature), (api, ChangeKind::Breaking), (perf, ChangeKind::Breaking), (tidy, ChangeKind::Other)] {
+            assert!(kinds.contains(&expected), "{:?}", expected);
+        }
+        let markdown = changelog.markdown();
+        assert!(markdown.starts_with("### Breaking Changes\n\n"), "{}", markdown);
+        assert!(markdown.contains(&format!("### Features\n\n- **net:** add retries ({})\n", &feat.to_hex()[..7])), "{}", markdown);
+        assert!(markdown.ends_with(&format!("### Other Changes\n\n- Tidy up ({})\n", &tidy.to_hex()[..7])), "{}", markdown);
+        assert!(!markdown.contains("unrelated"));
+
+        let all = Changelog::generate(&repo, &[tidy], &[], "").unwrap();
+        assert_eq!(all.changes.len(), 6);
+        assert!(Changelog::generate(&repo, &[web], &[feat], "libs/net").unwrap().is_empty());
+    }
+}
@@ -0,0 +1,200 @@
+//! CI 状态检查
+//!
+//! CI 系统对提交上报检查状态（`pending`、`success` 或 `failure`，可附带详情地址），同一提交上
+//! 同名（`context`）的检查以最后一次上报为准。状态通过 REST 接口
+//! `POST /api/v1/checks`（见 [`crate::server::api`]）或 `mono checks post` 上报，
+//! 保存在 `.mono/checks/<提交>.json`。
+//!
+//! 上报的结果被两处使用：
+//!
+//! - 合并队列：`[queue] required_checks` 中的检查在提交到队列的提交上全部成功后条目才会被处理，
+//!   有检查失败时条目直接失败，仍在进行或尚未上报时条目继续排队；
+//! - 推送策略：`[[policy]]` 规则的 `require_checks` 要求推送的提交上这些检查全部成功。
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::object::ObjectId;
+use crate::queue::LockFile;
+use crate::repo::Repository;
+
+/// 检查状态目录，相对于 `.mono`
+pub const CHECKS_DIR: &str = "checks";
+
+/// 检查的状态
+#[derive(clap::ValueEnum, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckState {
+    Pending,
+    Success,
+    Failure,
+}
+
+impl fmt::Display for CheckState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            CheckState::Pending => "pending",
+            CheckState::Success => "success",
+            CheckState::Failure => "failure",
+        })
+    }
+}
+
+/// 提交上的一项检查
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct CheckStatus {
+    /// 检查名，例如 `ci/build`
+    pub context: String,
+    pub state: CheckState,
+    /// CI 中本次检查的详情页
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub details_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// 上报者的身份
+    pub reporter: String,
+    /// 上报时间（Unix 时间戳）
+    pub updated_at: i64,
+}
+
+/// 必需检查在一个提交上的结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RequiredChecks {
+    /// 全部成功
+    Passed,
+    /// 没有失败，但这些检查仍在进行或尚未上报
+    Pending(Vec<String>),
+    /// 这些检查失败
+    Failed(Vec<CheckStatus>),
+}
+
+/// 仓库的检查状态
+pub struct Checks<'a> {
+    repo: &'a Repository,
+    dir: PathBuf,
+}
+
+impl<'a> Checks<'a> {
+    pub fn new(repo: &'a Repository) -> Checks<'a> {
+        Checks {
+            repo,
+            dir: repo.mono_dir().join(CHECKS_DIR),
+        }
+    }
+
+    fn path(&self, commit: &ObjectId) -> PathBuf {
+        self.dir.join(format!("{}.json", commit))
+    }
+
+    /// 提交上的全部检查，按检查名排序
+    pub fn list(&self, commit: &ObjectId) -> MonoResult<Vec<CheckStatus>> {
+        let path = self.path(commit);
+        let data = match std::fs::read(&path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        serde_json::from_slice(&data).map_err(|e| MonoError::storage(format!("corrupt check status {}: {}", path.display(), e)))
+    }
+
+    /// 上报提交上的一项检查，替换同名检查之前的状态
+    pub fn post(&self, commit: &ObjectId, status: CheckStatus) -> MonoResult<()> {
+        if status.context.is_empty() {
+            return Err(MonoError::usage("check context must not be empty"));
+        }
+        if let Some(url) = &status.details_url {
+            if !url.starts_with("https://") && !url.starts_with("http://") {
+                return Err(MonoError::usage(format!("details url '{}' must be an http(s) url", url)));
+            }
+        }
+        self.repo.read_commit(commit)?;
+        std::fs::create_dir_all(&self.dir)?;
+        let _lock = LockFile::acquire(self.dir.join(format!("{}.lock", commit)))?;
+        let mut checks: BTreeMap<String, CheckStatus> =
+            self.list(commit)?.into_iter().map(|check| (check.context.clone(), check)).collect();
+        tracing::info!(%commit, context = %status.context, state = %status.state, "check status posted");
+        checks.insert(status.context.clone(), status);
+        let checks: Vec<&CheckStatus> = checks.values().collect();
+        let data = serde_json::to_vec_pretty(&checks).map_err(|e| MonoError::storage(e.to_string()))?;
+        let path = self.path(commit);
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, data)?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    /// `required` 中的检查在提交上的结果
+    pub fn required(&self, commit: &ObjectId, required: &[String]) -> MonoResult<RequiredChecks> {
+        if required.is_empty() {
+            return Ok(RequiredChecks::Passed);
+        }
+        let checks = self.list(commit)?;
+        let mut pending = Vec::new();
+        let mut failed = Vec::new();
+        for context in required {
+            match checks.iter().find(|check| &check.context == context) {
+                Some(check) if check.state == CheckState::Success => {}
+                Some(check) if check.state == CheckState::Failure => failed.push(check.clone()),
+                _ => pending.push(context.clone()),
+            }
+        }
+        Ok(if !failed.is_empty() {
+            RequiredChecks::Failed(failed)
+        } else if !pending.is_empty() {
+            RequiredChecks::Pending(pending)
+        } else {
+            RequiredChecks::Passed
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{commit_files, init_repo};
+
+    fn status(context: &str, state: CheckState) -> CheckStatus {
+        CheckStatus {
+            context: context.to_string(),
+            state,
+            details_url: Some("https://ci.example.com/1".to_string()),
+            description: None,
+            reporter: "ci".to_string(),
+            updated_at: 100,
+        }
+    }
+
+    /// 测试上报替换同名检查，以及必需检查的结果
+    #[test]
+    fn test_checks() {
+        let (_dir, repo) = init_repo();
+        let commit = commit_files(&repo, &[("a.txt", b"a")], &[], "init");
+        let checks = Checks::new(&repo);
+        let required = ["ci/build".to_string(), "ci/test".to_string()];
+        assert_eq!(checks.required(&commit, &required).unwrap(), RequiredChecks::Pending(required.to_vec()));
+        assert_eq!(checks.required(&commit, &[]).unwrap(), RequiredChecks::Passed);
+
+        checks.post(&commit, status("ci/test", CheckState::Pending)).unwrap();
+        checks.post(&commit, status("ci/build", CheckState::Success)).unwrap();
+        assert_eq!(checks.required(&commit, &required).unwrap(), RequiredChecks::Pending(vec!["ci/test".to_string()]));
+
+        checks.post(&commit, status("ci/test", CheckState::Failure)).unwrap();
+        let RequiredChecks::Failed(failed) = checks.required(&commit, &required).unwrap() else {
+            panic!("expected failed checks");
+        };
+        assert_eq!(failed, [status("ci/test", CheckState::Failure)]);
+
+        checks.post(&commit, status("ci/test", CheckState::Success)).unwrap();
+        assert_eq!(checks.required(&commit, &required).unwrap(), RequiredChecks::Passed);
+        let listed = checks.list(&commit).unwrap();
+        assert_eq!(listed.iter().map(|check| check.context.as_str()).collect::<Vec<_>>(), ["ci/build", "ci/test"]);
+
+        assert!(checks.post(&commit, status("", CheckState::Success)).is_err());
+        assert!(checks.post(&ObjectId::ZERO, status("ci/build", CheckState::Success)).is_err());
+    }
+}
@@ -1,5 +1,309 @@
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
+use clap_complete::CompleteEnv;
+
+use crate::commands;
+use crate::completion::COMPLETE_ENV;
+use crate::common::config::Config;
+use crate::common::errors::{set_error_format, ErrorFormat};
+use crate::common::progress::{set_progress_format, ProgressFormat};
 use crate::common::MonoResult;
+use crate::logging::LogOptions;
+use crate::telemetry;
+
+/// MonoEngine 命令行入口
+#[derive(Parser, Debug)]
+#[command(name = "mono", version, about = "MonoEngine monorepo engine")]
+pub struct Cli {
+    /// 错误输出格式
+    #[arg(long, global = true, value_enum, default_value_t = ErrorFormat::Text)]
+    pub error_format: ErrorFormat,
+
+    /// 长时间操作的进度输出方式
+    #[arg(long, global = true, value_enum, default_value_t = ProgressFormat::Auto)]
+    pub progress: ProgressFormat,
+
+    /// 不显示进度，等同于 `--progress never`
+    #[arg(long, short = 'q', global = true)]
+    pub quiet: bool,
+
+    #[command(subcommand)]
+    pub command: Option<Commands>,
+}
+
+/// 子命令列表
+#[derive(Subcommand, Debug)]
+pub enum Commands {
+    /// 在目标目录初始化 monorepo 工作区
+    Init(commands::init::InitArgs),
+    /// 克隆远端仓库，支持部分克隆与浅克隆
+    Clone(commands::clone::CloneArgs),
+    /// 从远端获取新的提交，加深或补全浅仓库的历史
+    Fetch(commands::fetch::FetchArgs),
+    /// 管理稀疏检出配置
+    Sparse(commands::sparse::SparseArgs),
+    /// 以 FUSE 文件系统挂载仓库，按需加载文件内容
+    Mount(commands::mount::MountArgs),
+    /// 以 git 服务端的形式提供仓库
+    Serve(commands::serve::ServeArgs),
+    /// 管理允许通过 SSH 访问仓库的公钥
+    Keys(commands::keys::KeysArgs),
+    /// 为本地对象存储中的 pack 生成多包索引
+    MultiPackIndex(commands::multi_pack_index::MultiPackIndexArgs),
+    /// 生成与检查提交图，加速历史查询
+    CommitGraph(commands::commit_graph::CommitGraphArgs),
+    /// 显示提交历史
+    Log(commands::log::LogArgs),
+    /// 查找两个提交的最近公共祖先
+    MergeBase(commands::merge_base::MergeBaseArgs),
+    /// 管理 Git LFS 对象
+    Lfs(commands::lfs::LfsArgs),
+    /// 按 CODEOWNERS 文件查询路径的负责人
+    Owners(commands::owners::OwnersArgs),
+    /// 将子目录的历史导出为独立的提交历史，用于同步下游镜像
+    Split(commands::split::SplitArgs),
+    /// 将其他仓库的完整历史导入到子目录下
+    Absorb(commands::absorb::AbsorbArgs),
+    /// 按路径、文件大小、改名与 mailmap 并行改写全部分支与标签的历史
+    Filter(commands::filter::FilterArgs),
+    /// 列出一段修订范围影响的项目，用于 CI 选择性构建
+    Changed(commands::changed::ChangedArgs),
+    /// 列出一段修订范围影响的项目及依赖它们的全部项目
+    Impacted(commands::impacted::ImpactedArgs),
+    /// 把一段修订范围中的提交按 Conventional Commits 类型分组，输出 Markdown 或 JSON 变更日志
+    Changelog(commands::changelog::ChangelogArgs),
+    /// 按 `[commit_lint]` 规则检查提交信息，与推送时的检查相同
+    LintCommits(commands::lint_commits::LintCommitsArgs),
+    /// 列出一段修订范围中超过大小的文件，与推送时的 `[big_files]` 检查相同，可用于 pre-push 钩子
+    BigFiles(commands::big_files::BigFilesArgs),
+    /// 管理合并队列：提交、查看、取消与处理排队的修订
+    Queue(commands::queue::QueueArgs),
+    /// 创建与列出发布：不可移动的标签、发布说明与附件
+    Release(commands::release::ReleaseArgs),
+    /// 按目录前缀的标签计算项目的下一个语义化版本，创建标签与变更日志
+    Version(commands::version::VersionArgs),
+    /// 管理堆叠分支：创建、变基与推送一串相互依赖的分支
+    Stack(commands::stack::StackArgs),
+    /// 查看 webhook 投递记录，重新投递失败的事件
+    Webhooks(commands::webhooks::WebhooksArgs),
+    /// 管理 HTTP、SSH 与 gRPC 接口的个人访问令牌
+    Token(commands::token::TokenArgs),
+    /// 通过外部 OIDC 身份提供方登录，会话保存在系统钥匙串中
+    Login(commands::login::LoginArgs),
+    /// 删除保存的登录会话
+    Logout(commands::logout::LogoutArgs),
+    /// git 凭据助手，以登录取得的 ID 令牌作为密码
+    Credential(commands::credential::CredentialArgs),
+    /// 按时间、操作者与路径查询推送、引用更新、配置修改与令牌签发的审计日志
+    Audit(commands::audit::AuditArgs),
+    /// 读取与修改系统级、用户级、仓库级与环境变量四层合并的配置
+    Config(commands::config::ConfigArgs),
+    /// 按计划执行增量打包、提交图、可达性位图与缓存预热等维护任务
+    Maintenance(commands::maintenance::MaintenanceArgs),
+    /// 检查对象哈希、树条目、引用、pack 索引与 LFS 指针的完整性
+    Fsck(commands::fsck::FsckArgs),
+    /// 合并松散对象与较小的 pack，支持不重写大 pack 的几何重新打包
+    Repack(commands::repack::RepackArgs),
+    /// 删除引用与引用日志都不可达、且超过宽限期的对象
+    Gc(commands::gc::GcArgs),
+    /// 统计仓库与各目录占用的存储，对照 `[quota]` 配额
+    Du(commands::du::DuArgs),
+    /// 显示引用的修改历史：操作者、时间、新旧值与原因
+    Reflog(commands::reflog::ReflogArgs),
+    /// 按引用日志恢复引用
+    Ref(commands::refs::RefArgs),
+    /// 向只读镜像复制引用与对象，查看各镜像的复制延迟
+    Mirror(commands::mirror::MirrorArgs),
+    /// 显示文件每一行最后一次被修改的提交，结果按提交缓存
+    Blame(commands::blame::BlameArgs),
+    /// 在建立了索引的分支中全文搜索代码
+    Search(commands::search::SearchArgs),
+    /// 查找符号的定义与引用
+    Symbols(commands::symbols::SymbolsArgs),
+    /// 比较两个修订，检测重命名与复制
+    Diff(commands::diff::DiffArgs),
+    /// 在服务端把提交挑选到分支上
+    CherryPick(commands::cherry_pick::CherryPickArgs),
+    /// 在服务端撤销分支上的提交
+    Revert(commands::revert::RevertArgs),
+    /// 与组成虚拟单仓库的上游仓库双向同步
+    Compose(commands::compose::ComposeArgs),
+    /// 把子模块等 git 特有的结构迁移为单仓库的形式
+    Migrate(commands::migrate::MigrateArgs),
+    /// 生成供克隆从 CDN 先行下载的 bundle
+    Bundle(commands::bundle::BundleArgs),
+    /// 把大 pack 上传到 S3/CDN，克隆时以签名地址下载
+    Offload(commands::offload::OffloadArgs),
+    /// 上报与查看提交的 CI 检查状态
+    Checks(commands::checks::ChecksArgs),
+    /// 在服务端检出候选提交并运行测试命令，二分查找第一个有问题的提交
+    Bisect(commands::bisect::BisectArgs),
+    /// 不检出工作区，把修订的树快照导出为 tar 包、zip 包或目录
+    Export(commands::export::ExportArgs),
+    /// 列出工作区相对 HEAD 修改、删除与未跟踪的文件
+    Status(commands::status::StatusArgs),
+    /// 查看与重写与 git 格式相同的暂存区，支持拆分索引与未跟踪文件缓存
+    Index(commands::index::IndexArgs),
+    /// 在终端中交互式浏览引用、提交历史、差异与合并队列
+    Tui(commands::tui::TuiArgs),
+    /// 输出 bash、zsh、fish 或 PowerShell 的补全脚本，补全时查询引用与路径
+    Completions(commands::completions::CompletionsArgs),
+    /// 检查配置、存储、磁盘空间、暂存区、登录会话与时钟偏差，给出修复建议
+    Doctor(commands::doctor::DoctorArgs),
+    /// 管理运行中的服务：不中断连接地重新加载配置，或在维护期间把仓库置为只读
+    Admin(commands::admin::AdminArgs),
+}
+
+/// 解析命令行参数并执行对应的子命令
+///
+/// `args` 为 `None` 时读取进程参数，否则使用给定参数（包含程序名），便于测试。
+pub fn parse(args: Option<Vec<&str>>) -> MonoResult<()> {
+    let args: Vec<String> = match args {
+        Some(args) => args.into_iter().map(String::from).collect(),
+        None => std::env::args().collect(),
+    };
+
+    // 补全脚本以 MONO_COMPLETE 调用时只输出候选
+    let completing = CompleteEnv::with_factory(Cli::command)
+        .var(COMPLETE_ENV)
+        .try_complete(&args, std::env::current_dir().ok().as_deref())?;
+    if completing {
+        return Ok(());
+    }
+
+    // 参数解析失败时同样需要按指定格式输出错误，因此先行扫描 --error-format
+    set_error_format(scan_error_format(&args));
+    let matches = match Cli::command().try_get_matches_from(&args) {
+        Ok(matches) => matches,
+        // --help 与 --version 不属于错误，直接输出到标准输出
+        Err(err) if !err.use_stderr() => {
+            err.print()?;
+            return Ok(());
+        }
+        Err(err) => return Err(err.into()),
+    };
+    let cli = Cli::from_arg_matches(&matches)?;
+    set_error_format(cli.error_format);
+    set_progress_format(if cli.quiet { ProgressFormat::Never } else { cli.progress });
+
+    let config = std::env::current_dir().ok().and_then(|dir| telemetry::find_config(&dir));
+    let log = match (&cli.command, &config) {
+        (Some(Commands::Serve(_)), Some((mono_dir, config))) => LogOptions::server(mono_dir, &config.log)?,
+        _ => LogOptions::stderr()?,
+    };
+    let _telemetry = telemetry::init(config.as_ref().map(|(_, config)| &config.telemetry), &log);
+    // 不认识与已弃用的配置项只警告，配置错误由命令本身打开仓库时报告
+    if let Ok(layered) = Config::load(config.as_ref().map(|(mono_dir, _)| mono_dir.as_path())) {
+        for warning in layered.warnings() {
+            tracing::warn!("{}", warning);
+        }
+    }
+    let span = tracing::info_span!("command", name = matches.subcommand_name().unwrap_or("mono"));
+    let _entered = span.enter();
+
+    match cli.command {
+        Some(command) => match command {
+            Commands::Init(args) => commands::init::execute(args),
+            Commands::Clone(args) => commands::clone::execute(args),
+            Commands::Fetch(args) => commands::fetch::execute(args),
+            Commands::Sparse(args) => commands::sparse::execute(args),
+            Commands::Mount(args) => commands::mount::execute(args),
+            Commands::Serve(args) => commands::serve::execute(args),
+            Commands::Keys(args) => commands::keys::execute(args),
+            Commands::MultiPackIndex(args) => commands::multi_pack_index::execute(args),
+            Commands::CommitGraph(args) => commands::commit_graph::execute(args),
+            Commands::Log(args) => commands::log::execute(args),
+            Commands::MergeBase(args) => commands::merge_base::execute(args),
+            Commands::Lfs(args) => commands::lfs::execute(args),
+            Commands::Owners(args) => commands::owners::execute(args),
+            Commands::Split(args) => commands::split::execute(args),
+            Commands::Absorb(args) => commands::absorb::execute(args),
+            Commands::Filter(args) => commands::filter::execute(args),
+            Commands::Changed(args) => commands::changed::execute(args),
+            Commands::Impacted(args) => commands::impacted::execute(args),
+            Commands::Changelog(args) => commands::changelog::execute(args),
+            Commands::LintCommits(args) => commands::lint_commits::execute(args),
+            Commands::BigFiles(args) => commands::big_files::execute(args),
+            Commands::Queue(args) => commands::queue::execute(args),
+            Commands::Release(args) => commands::release::execute(args),
+            Commands::Version(args) => commands::version::execute(args),
+            Commands::Stack(args) => commands::stack::execute(args),
+            Commands::Webhooks(args) => commands::webhooks::execute(args),
+            Commands::Token(args) => commands::token::execute(args),
+            Commands::Login(args) => commands::login::execute(args),
+            Commands::Logout(args) => commands::logout::execute(args),
+            Commands::Credential(args) => commands::credential::execute(args),
+            Commands::Audit(args) => commands::audit::execute(args),
+            Commands::Config(args) => commands::config::execute(args),
+            Commands::Maintenance(args) => commands::maintenance::execute(args),
+            Commands::Fsck(args) => commands::fsck::execute(args),
+            Commands::Repack(args) => commands::repack::execute(args),
+            Commands::Gc(args) => commands::gc::execute(args),
+            Commands::Du(args) => commands::du::execute(args),
+            Commands::Reflog(args) => commands::reflog::execute(args),
+            Commands::Ref(args) => commands::refs::execute(args),
+            Commands::Mirror(args) => commands::mirror::execute(args),
+            Commands::Blame(args) => commands::blame::execute(args),
+            Commands::Search(args) => commands::search::execute(args),
+            Commands::Symbols(args) => commands::symbols::execute(args),
+            Commands::Diff(args) => commands::diff::execute(args),
+            Commands::CherryPick(args) => commands::cherry_pick::execute(args),
+            Commands::Revert(args) => commands::revert::execute(args),
+            Commands::Compose(args) => commands::compose::execute(args),
+            Commands::Migrate(args) => commands::migrate::execute(args),
+            Commands::Bundle(args) => commands::bundle::execute(args),
+            Commands::Offload(args) => commands::offload::execute(args),
+            Commands::Checks(args) => commands::checks::execute(args),
+            Commands::Bisect(args) => commands::bisect::execute(args),
+            Commands::Export(args) => commands::export::execute(args),
+            Commands::Status(args) => commands::status::execute(args),
+            Commands::Index(args) => commands::index::execute(args),
+            Commands::Tui(args) => commands::tui::execute(args),
+            Commands::Completions(args) => commands::completions::execute(args),
+            Commands::Doctor(args) => commands::doctor::execute(args),
+            Commands::Admin(args) => commands::admin::execute(args),
+        },
+        None => {
+            Cli::command().print_help()?;
+            Ok(())
+        }
+    }
+}
+
+/// 在完整解析之前从原始参数中提取错误输出格式
+fn scan_error_format(args: &[String]) -> ErrorFormat {
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let value = match arg.strip_prefix("--error-format") {
+            Some("") => iter.next().map(String::as_str),
+            Some(rest) => rest.strip_prefix('='),
+            None => None,
+        };
+        if value == Some("json") {
+            return ErrorFormat::Json;
+        }
+    }
+    ErrorFormat::Text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 测试从原始参数中扫描错误输出格式
+    #[test]
+    fn test_scan_error_format() {
+        let args = |v: &[&str]| v.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(scan_error_format(&args(&["mono", "--error-format=json"])), ErrorFormat::Json);
+        assert_eq!(scan_error_format(&args(&["mono", "--error-format", "json"])), ErrorFormat::Json);
+        assert_eq!(scan_error_format(&args(&["mono", "--error-format=text"])), ErrorFormat::Text);
+        assert_eq!(scan_error_format(&args(&["mono"])), ErrorFormat::Text);
+    }
 
-pub fn parse(_args: Option<Vec<&str>>) -> MonoResult<()> {
-    Ok(())
+    /// 测试非法参数被转换为用法错误
+    #[test]
+    fn test_invalid_argument() {
+        let err = parse(Some(vec!["mono", "--no-such-flag"])).unwrap_err();
+        assert_eq!(err.kind().id(), "usage");
+    }
 }
@@ -0,0 +1,86 @@
+//! `mono absorb` 命令：将其他仓库的完整历史导入到子目录下
+
+use clap::Args;
+
+use crate::commands::clone::normalize_url;
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::object::commit::Signature;
+use crate::object::filter::ObjectFilter;
+use crate::refs;
+use crate::repo::Repository;
+use crate::rewrite::absorb::{graft, Absorber};
+use crate::sparse::{self, SparsePattern};
+use crate::transport;
+use crate::worktree;
+
+/// `mono absorb` 的参数
+#[derive(Args, Debug)]
+pub struct AbsorbArgs {
+    /// 被导入的仓库地址，可以是普通的 git 仓库
+    pub url: String,
+    /// 导入到的目录，例如 `//third_party/foo`
+    #[arg(long)]
+    pub into: String,
+    /// 导入该分支的历史，默认使用远端 HEAD 指向的分支
+    #[arg(long = "ref")]
+    pub branch: Option<String>,
+    /// 只更新分支，不检出导入的文件
+    #[arg(long)]
+    pub no_checkout: bool,
+}
+
+/// 执行 `mono absorb`
+pub fn execute(args: AbsorbArgs) -> MonoResult<()> {
+    let repo = Repository::discover(&std::env::current_dir()?)?;
+    let mut absorber = Absorber::new(&repo, &args.into)?;
+    let prefix = absorber.prefix().to_string();
+
+    let url = normalize_url(&args.url)?;
+    let transport = transport::open(&url)?;
+    let remote_refs = transport.list_refs()?;
+    let branch_ref = match (&args.branch, &remote_refs.head) {
+        (Some(branch), _) => format!("{}{}", refs::HEADS_PREFIX, branch),
+        (None, Some(head)) => head.clone(),
+        (None, None) => return Err(MonoError::usage(format!("{} has no HEAD, use --ref", url))),
+    };
+    let tip = remote_refs
+        .refs
+        .iter()
+        .find(|(name, _)| *name == branch_ref)
+        .map(|(_, id)| *id)
+        .ok_or_else(|| MonoError::not_found(format!("remote branch {}", refs::short_name(&branch_ref))))?;
+
+    // 已获取的对象会被跳过，中断后重新执行时只获取剩余部分
+    let stats = transport.fetch(&[tip], &[], &ObjectFilter::None, repo.objects())?;
+    tracing::info!(objects = stats.objects, "fetched objects");
+
+    let absorbed = absorber.absorb(&tip);
+    absorber.save_cache()?;
+    let absorbed = absorbed?;
+    tracing::info!(processed = absorber.processed(), "rewrote history");
+
+    let message = format!(
+        "Absorb {} into //{}\n\nImported {} at {}.\n",
+        url,
+        prefix,
+        refs::short_name(&branch_ref),
+        tip
+    );
+    let head = graft(&repo, &prefix, &absorbed, Signature::committer_from_env()?, &message)?;
+
+    if !args.no_checkout {
+        let pattern: SparsePattern = format!("//{}/...", prefix).parse()?;
+        let spec = match repo.workspace()?.sparse {
+            Some(state) => Some(sparse::resolve_spec(&repo, &state)?),
+            None => None,
+        };
+        let stats = worktree::checkout_commit(&repo, &head, |path, is_dir| {
+            (pattern.matches(path) || (is_dir && pattern.is_ancestor(path)))
+                && spec.as_ref().is_none_or(|s| s.includes(path, is_dir))
+        })?;
+        tracing::info!(files = stats.files, bytes = stats.bytes, "checked out worktree");
+    }
+    println!("Absorbed {} into //{} at {}", url, prefix, head);
+    Ok(())
+}
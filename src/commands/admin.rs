@@ -0,0 +1,100 @@
+//! `mono admin` 命令：管理运行中的 `mono serve`，以及在维护期间把仓库置为只读
+
+use std::process::Command;
+
+use clap::{Args, Subcommand};
+
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::freeze::{self, Freeze};
+use crate::repo::Repository;
+use crate::server::reload;
+
+/// `mono admin` 的参数
+#[derive(Args, Debug)]
+pub struct AdminArgs {
+    #[command(subcommand)]
+    pub command: AdminCommand,
+}
+
+/// `mono admin` 的子命令
+#[derive(Subcommand, Debug)]
+pub enum AdminCommand {
+    /// 让当前仓库运行中的 `mono serve` 重新加载配置，与发送 SIGHUP 相同
+    Reload,
+    /// 把仓库或单个租户置为只读：服务端拒绝推送等写操作，fetch 不受影响
+    Freeze {
+        /// 只冻结该租户，省略时冻结整个仓库
+        #[arg(long = "repo", value_name = "TENANT")]
+        tenant: Option<String>,
+        /// 冻结的原因，出现在拒绝写入的错误中
+        #[arg(long)]
+        reason: Option<String>,
+        /// 建议客户端等待多久再重试（秒）
+        #[arg(long, value_name = "SECS", default_value_t = freeze::DEFAULT_RETRY_AFTER_SECS)]
+        retry_after: u64,
+    },
+    /// 解除 `mono admin freeze`
+    Thaw {
+        /// 只解除该租户的冻结
+        #[arg(long = "repo", value_name = "TENANT")]
+        tenant: Option<String>,
+    },
+}
+
+/// 执行 `mono admin`
+pub fn execute(args: AdminArgs) -> MonoResult<()> {
+    match args.command {
+        AdminCommand::Reload => {
+            let repo = Repository::discover(&std::env::current_dir()?)?;
+            let pid = reload::read_pid_file(repo.mono_dir())?;
+            let status = Command::new("kill")
+                .arg("-HUP")
+                .arg(pid.to_string())
+                .status()
+                .map_err(|e| MonoError::from(e).context("sending SIGHUP"))?;
+            if !status.success() {
+                return Err(MonoError::not_found(format!("mono serve (pid {}) is not running", pid)));
+            }
+            println!("Asked mono serve (pid {}) to reload its configuration", pid);
+        }
+        AdminCommand::Freeze { tenant, reason, retry_after } => {
+            let repo = Repository::discover(&std::env::current_dir()?)?;
+            check_tenant(&repo, tenant.as_deref())?;
+            let record = Freeze {
+                reason,
+                since: chrono::Utc::now().timestamp(),
+                retry_after_secs: retry_after,
+            };
+            freeze::freeze(&repo, tenant.as_deref(), &record)?;
+            println!("Froze {}; writes are rejected until `mono admin thaw`", describe(tenant.as_deref()));
+        }
+        AdminCommand::Thaw { tenant } => {
+            let repo = Repository::discover(&std::env::current_dir()?)?;
+            check_tenant(&repo, tenant.as_deref())?;
+            if freeze::thaw(&repo, tenant.as_deref())? {
+                println!("Thawed {}", describe(tenant.as_deref()));
+            } else {
+                println!("Nothing to thaw: {} is not frozen", describe(tenant.as_deref()));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 只能冻结 `[namespaces] tenants` 中配置的租户
+fn check_tenant(repo: &Repository, tenant: Option<&str>) -> MonoResult<()> {
+    match tenant {
+        Some(tenant) if !repo.config().namespaces.tenants.iter().any(|t| t == tenant) => {
+            Err(MonoError::not_found(format!("tenant {}", tenant)))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn describe(tenant: Option<&str>) -> String {
+    match tenant {
+        Some(tenant) => format!("tenant {}", tenant),
+        None => "the repository".to_string(),
+    }
+}
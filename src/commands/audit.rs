@@ -0,0 +1,108 @@
+//! `mono audit` 命令：查询审计日志
+
+use chrono::{DateTime, NaiveDate, Utc};
+use clap::Args;
+
+use crate::audit::{AuditAction, AuditEvent, AuditFilter, AuditLog};
+use crate::commands::OutputFormat;
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::object::ObjectId;
+use crate::repo::Repository;
+
+/// `mono audit` 的参数
+#[derive(Args, Debug)]
+pub struct AuditArgs {
+    /// 只显示该时间之后的记录：相对时间（如 `30m`、`24h`、`7d`）、日期或 RFC 3339 时间
+    #[arg(long, value_name = "TIME")]
+    pub since: Option<String>,
+    /// 只显示该操作者的记录，`*` 结尾时按前缀匹配，例如 `token:*`
+    #[arg(long)]
+    pub actor: Option<String>,
+    /// 只显示改动了该路径或其下文件的记录
+    #[arg(long)]
+    pub path: Option<String>,
+    /// 只显示该类型的操作
+    #[arg(long, value_enum)]
+    pub action: Option<AuditAction>,
+    /// 至多显示最近的记录数
+    #[arg(short = 'n', long)]
+    pub limit: Option<usize>,
+    /// 输出格式
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+}
+
+/// 执行 `mono audit`
+pub fn execute(args: AuditArgs) -> MonoResult<()> {
+    let repo = Repository::discover(&std::env::current_dir()?)?;
+    let now = Utc::now();
+    let filter = AuditFilter {
+        since: args.since.as_deref().map(|since| parse_since(since, now)).transpose()?,
+        actor: args.actor,
+        path: args.path,
+        action: args.action,
+    };
+    let mut events = AuditLog::new(&repo).query(&filter)?;
+    if let Some(limit) = args.limit {
+        events.drain(..events.len().saturating_sub(limit));
+    }
+    match args.format {
+        OutputFormat::Json => {
+            let json = serde_json::to_string_pretty(&events).map_err(|e| MonoError::usage(e.to_string()))?;
+            println!("{}", json);
+        }
+        OutputFormat::Text => {
+            for event in &events {
+                println!("{}", format_event(event));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 解析 `--since`，返回 Unix 秒
+pub(crate) fn parse_since(since: &str, now: DateTime<Utc>) -> MonoResult<i64> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(since) {
+        return Ok(time.timestamp());
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(since, "%Y-%m-%d") {
+        return Ok(date.and_hms_opt(0, 0, 0).expect("midnight is valid").and_utc().timestamp());
+    }
+    let invalid = || MonoError::usage(format!("invalid time: {} (expected e.g. 30m, 24h, 7d, 2024-01-31)", since));
+    let (amount, unit) = since.split_at_checked(since.len().saturating_sub(1)).ok_or_else(invalid)?;
+    let amount: i64 = amount.parse().map_err(|_| invalid())?;
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return Err(invalid()),
+    };
+    Ok(now.timestamp() - amount * seconds)
+}
+
+/// 一条记录的单行文本：时间、操作者、操作、对象，以及引用的新旧值与改动的路径数
+fn format_event(event: &AuditEvent) -> String {
+    let time = DateTime::from_timestamp(event.time, 0)
+        .map(|time| time.format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_else(|| event.time.to_string());
+    let mut out = format!("{}  {:<20} {:<14} {}", time, event.actor, event.action, event.target);
+    let short = |id: Option<ObjectId>| id.unwrap_or(ObjectId::ZERO).to_hex()[..7].to_string();
+    if event.old.is_some() || event.new.is_some() {
+        out.push_str(&format!(" {}..{}", short(event.old), short(event.new)));
+    }
+    match event.paths.len() {
+        0 => {}
+        1 => out.push_str(&format!(" (//{})", event.paths[0])),
+        n => out.push_str(&format!(" ({} paths)", n)),
+    }
+    if let Some(detail) = &event.detail {
+        out.push_str(&format!(" {}", detail));
+    }
+    if let Some(cert) = event.cert {
+        out.push_str(&format!(" [cert {}]", &cert.to_hex()[..7]));
+    }
+    out
+}
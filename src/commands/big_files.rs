@@ -0,0 +1,66 @@
+//! `mono big-files` 命令：列出一段修订范围中新增的大文件
+
+use clap::Args;
+
+use crate::big_files::{parse_size, BigFiles, DEFAULT_THRESHOLD};
+use crate::commands::OutputFormat;
+use crate::common::errors::{ExitCode, MonoError, MonoErrorKind};
+use crate::common::progress::format_bytes;
+use crate::common::MonoResult;
+use crate::refs;
+use crate::repo::Repository;
+
+/// `mono big-files` 的参数
+#[derive(Args, Debug)]
+pub struct BigFilesArgs {
+    /// `<旧>..<新>` 检查新提交中不在旧提交历史里的提交，单个修订检查其全部历史
+    #[arg(default_value = "HEAD")]
+    pub range: String,
+    /// 超过该大小的文件，如 `5MB`，默认使用 `[big_files] max_blob_size`，未配置时为 5 MiB
+    #[arg(long, value_parser = |s: &str| parse_size(s).map_err(|e| e.to_string()))]
+    pub threshold: Option<u64>,
+    /// 输出格式
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+}
+
+/// 执行 `mono big-files`：有大文件时以 1 退出
+pub fn execute(args: BigFilesArgs) -> MonoResult<()> {
+    let repo = Repository::discover(&std::env::current_dir()?)?;
+    let big_files = match (args.threshold, BigFiles::from_config(&repo.config().big_files)?) {
+        (Some(threshold), _) => BigFiles::new(threshold),
+        (None, Some(configured)) => configured,
+        (None, None) => BigFiles::new(DEFAULT_THRESHOLD),
+    };
+    let (tips, exclude) = match args.range.split_once("..") {
+        Some((old, new)) => {
+            let old = if old.is_empty() { refs::HEAD } else { old };
+            let new = if new.is_empty() { refs::HEAD } else { new };
+            (vec![repo.peel(&repo.resolve_rev(new)?)?.0], vec![repo.peel(&repo.resolve_rev(old)?)?.0])
+        }
+        None => (vec![repo.peel(&repo.resolve_rev(&args.range)?)?.0], Vec::new()),
+    };
+    let mut found = big_files.find(&repo, &tips, &exclude)?;
+    found.sort_by_key(|file| std::cmp::Reverse(file.size));
+
+    match args.format {
+        OutputFormat::Json => {
+            let json = serde_json::to_string_pretty(&found).map_err(|e| MonoError::usage(e.to_string()))?;
+            println!("{}", json);
+        }
+        OutputFormat::Text => {
+            for file in &found {
+                println!("{:>10}  {}  {}", format_bytes(file.size), &file.commit.to_hex()[..7], file.path);
+            }
+        }
+    }
+    if found.is_empty() {
+        return Ok(());
+    }
+    let message = format!(
+        "{} files are larger than {}; track them with Git LFS",
+        found.len(),
+        format_bytes(big_files.threshold())
+    );
+    Err(MonoError::from_kind(MonoErrorKind::Usage(message), ExitCode::Failure.code()))
+}
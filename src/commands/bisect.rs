@@ -0,0 +1,131 @@
+//! `mono bisect` 命令：在服务端二分查找引入问题的提交
+
+use clap::{Args, Subcommand};
+use clap_complete::ArgValueCompleter;
+
+use crate::bisect::{BisectSession, BisectState, Bisector, NewBisect, DEFAULT_TIMEOUT_SECS};
+use crate::commands::OutputFormat;
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::completion::complete_refs;
+use crate::repo::Repository;
+
+/// `mono bisect` 的参数
+#[derive(Args, Debug)]
+pub struct BisectArgs {
+    #[command(subcommand)]
+    pub command: BisectCommand,
+}
+
+/// `mono bisect` 的子命令
+#[derive(Subcommand, Debug)]
+pub enum BisectCommand {
+    /// 发起查找并运行到结束
+    Run(RunArgs),
+    /// 继续被中断的查找，已测试的提交不会重复测试
+    Resume(ShowArgs),
+    /// 显示查找的进度与结果
+    Show(ShowArgs),
+    /// 列出全部查找
+    List(ListArgs),
+}
+
+/// `mono bisect run` 的参数
+#[derive(Args, Debug)]
+pub struct RunArgs {
+    /// 已知有问题的修订
+    #[arg(add = ArgValueCompleter::new(complete_refs))]
+    pub bad: String,
+    /// 已知正常的修订，可以指定多次
+    #[arg(long, required = true, add = ArgValueCompleter::new(complete_refs))]
+    pub good: Vec<String>,
+    /// 每次测试的超时（秒）
+    #[arg(long, default_value_t = DEFAULT_TIMEOUT_SECS)]
+    pub timeout: u64,
+    /// 测试命令：退出码 0 表示正常，125 表示跳过，1 到 127 的其他值表示有问题
+    #[arg(last = true, required = true)]
+    pub command: Vec<String>,
+}
+
+/// `mono bisect show` 与 `mono bisect resume` 的参数
+#[derive(Args, Debug)]
+pub struct ShowArgs {
+    /// 查找编号
+    pub id: u64,
+    /// 输出格式
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+}
+
+/// `mono bisect list` 的参数
+#[derive(Args, Debug)]
+pub struct ListArgs {
+    /// 输出格式
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+}
+
+/// 执行 `mono bisect`
+pub fn execute(args: BisectArgs) -> MonoResult<()> {
+    let repo = Repository::discover(&std::env::current_dir()?)?;
+    let bisector = Bisector::new(&repo);
+    match args.command {
+        BisectCommand::Run(args) => {
+            let good = args.good.iter().map(|rev| repo.resolve_rev(rev)).collect::<MonoResult<Vec<_>>>()?;
+            let request = NewBisect {
+                good,
+                bad: repo.resolve_rev(&args.bad)?,
+                command: args.command.join(" "),
+                timeout_secs: args.timeout,
+                requested_by: std::env::var("USER").unwrap_or_else(|_| "unknown".to_string()),
+            };
+            let session = bisector.start(request, chrono::Utc::now().timestamp())?;
+            println!("bisect {} started", session.id);
+            print_session(&bisector.run(session.id)?, OutputFormat::Text)?;
+        }
+        BisectCommand::Resume(args) => print_session(&bisector.run(args.id)?, args.format)?,
+        BisectCommand::Show(args) => print_session(&bisector.get(args.id)?, args.format)?,
+        BisectCommand::List(args) => {
+            let sessions = bisector.list()?;
+            match args.format {
+                OutputFormat::Json => {
+                    let json = serde_json::to_string_pretty(&sessions).map_err(|e| MonoError::usage(e.to_string()))?;
+                    println!("{}", json);
+                }
+                OutputFormat::Text => {
+                    for session in &sessions {
+                        let first_bad = session.first_bad.map(|id| id.to_string()).unwrap_or_default();
+                        println!("{:<4} {:<8} {:<10} {}", session.id, session.state, session.requested_by, first_bad);
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn print_session(session: &BisectSession, format: OutputFormat) -> MonoResult<()> {
+    if format == OutputFormat::Json {
+        let json = serde_json::to_string_pretty(session).map_err(|e| MonoError::usage(e.to_string()))?;
+        println!("{}", json);
+        return Ok(());
+    }
+    for step in &session.steps {
+        println!("{:<5} {} (exit {})", step.result, step.commit, step.exit_code);
+    }
+    match session.state {
+        BisectState::Running => println!("bisect {} is running", session.id),
+        BisectState::Found => {
+            if let Some(first_bad) = session.first_bad {
+                println!("{} is the first bad commit", first_bad);
+            }
+        }
+        BisectState::Failed => {
+            println!("bisect {} failed: {}", session.id, session.reason.as_deref().unwrap_or_default());
+            for suspect in &session.suspects {
+                println!("  possible first bad commit: {}", suspect);
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,117 @@
+//! `mono blame` 命令：显示文件每一行最后一次被修改的提交
+
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+
+use chrono::{DateTime, FixedOffset};
+use clap::Args;
+use clap_complete::ArgValueCompleter;
+use serde::Serialize;
+
+use crate::blame::{BlameRange, Blamer};
+use crate::commands::log::parse_timezone;
+use crate::commands::OutputFormat;
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::completion::{complete_paths, complete_refs};
+use crate::object::commit::Signature;
+use crate::object::ObjectId;
+use crate::refs;
+use crate::repo::Repository;
+
+/// `mono blame` 的参数
+#[derive(Args, Debug)]
+pub struct BlameArgs {
+    /// 仓库内的文件路径
+    #[arg(add = ArgValueCompleter::new(complete_paths))]
+    pub path: String,
+    /// 查询的修订，默认为 HEAD
+    #[arg(long, default_value = refs::HEAD, add = ArgValueCompleter::new(complete_refs))]
+    pub rev: String,
+    /// 输出格式
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+}
+
+/// JSON 输出中的来源提交
+#[derive(Serialize)]
+struct CommitSummary {
+    author: String,
+    email: String,
+    timestamp: i64,
+    summary: String,
+}
+
+/// JSON 输出
+#[derive(Serialize)]
+struct BlameOutput<'a> {
+    commit: ObjectId,
+    path: &'a str,
+    ranges: &'a [BlameRange],
+    commits: HashMap<ObjectId, CommitSummary>,
+}
+
+/// 执行 `mono blame`
+pub fn execute(args: BlameArgs) -> MonoResult<()> {
+    let repo = Repository::discover(&std::env::current_dir()?)?;
+    let commit = repo.resolve_rev(&args.rev)?;
+    let blame = Blamer::new(&repo)?.blame(&commit, &args.path)?;
+
+    let mut commits = HashMap::new();
+    for range in &blame.ranges {
+        if let Entry::Vacant(entry) = commits.entry(range.commit) {
+            entry.insert(repo.read_commit(&range.commit)?);
+        }
+    }
+    match args.format {
+        OutputFormat::Json => {
+            let output = BlameOutput {
+                commit: blame.commit,
+                path: &blame.path,
+                ranges: &blame.ranges,
+                commits: commits
+                    .iter()
+                    .map(|(id, commit)| {
+                        let summary = CommitSummary {
+                            author: commit.author.name.clone(),
+                            email: commit.author.email.clone(),
+                            timestamp: commit.author.timestamp,
+                            summary: commit.summary().to_string(),
+                        };
+                        (*id, summary)
+                    })
+                    .collect(),
+            };
+            let json = serde_json::to_string_pretty(&output).map_err(|e| MonoError::usage(e.to_string()))?;
+            println!("{}", json);
+        }
+        OutputFormat::Text => {
+            let width = blame.lines.len().to_string().len();
+            for range in &blame.ranges {
+                let commit = &commits[&range.commit];
+                for line in range.line..range.line + range.len {
+                    let content = String::from_utf8_lossy(&blame.lines[line - 1]);
+                    println!(
+                        "{} ({} {} {:>width$}) {}",
+                        &range.commit.to_hex()[..8],
+                        commit.author.name,
+                        format_date(&commit.author),
+                        line,
+                        content.trim_end_matches('\n'),
+                        width = width
+                    );
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 按签名中的时区格式化时间，与 git blame 的默认格式一致，例如 `2023-11-14 22:13:20 +0000`
+fn format_date(sig: &Signature) -> String {
+    let offset = parse_timezone(&sig.timezone).unwrap_or_else(|| FixedOffset::east_opt(0).unwrap());
+    match DateTime::from_timestamp(sig.timestamp, 0) {
+        Some(time) => time.with_timezone(&offset).format("%Y-%m-%d %H:%M:%S %z").to_string(),
+        None => format!("{} {}", sig.timestamp, sig.timezone),
+    }
+}
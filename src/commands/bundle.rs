@@ -0,0 +1,127 @@
+//! `mono bundle` 命令：生成供克隆先行下载的 bundle
+
+use std::path::PathBuf;
+
+use clap::{Args, Subcommand};
+
+use crate::bundle::{self, BundleStore};
+use crate::commands::OutputFormat;
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::object::ObjectId;
+use crate::repo::Repository;
+
+/// `mono bundle` 的参数
+#[derive(Args, Debug)]
+pub struct BundleArgs {
+    #[command(subcommand)]
+    pub command: BundleCommand,
+}
+
+/// `mono bundle` 的子命令
+#[derive(Subcommand, Debug)]
+pub enum BundleCommand {
+    /// 为全部分支与标签生成 bundle，登记到 `.mono/bundles/` 供 bundle-uri 使用
+    Create(CreateArgs),
+    /// 列出登记的 bundle
+    List(ListArgs),
+    /// 把 bundle 文件中的对象写入仓库，并列出其中的引用
+    Unbundle(UnbundleArgs),
+}
+
+/// `mono bundle create` 的参数
+#[derive(Args, Debug)]
+pub struct CreateArgs {
+    /// 只包含已登记的 bundle 之后的新对象
+    #[arg(long, conflicts_with = "output")]
+    pub incremental: bool,
+    /// 写到该文件而不登记，只包含指定的引用（默认全部分支与标签）
+    #[arg(long, short)]
+    pub output: Option<PathBuf>,
+    /// 与 `--output` 一起使用时包含的引用
+    #[arg(long = "ref", requires = "output")]
+    pub refs: Vec<String>,
+}
+
+/// `mono bundle list` 的参数
+#[derive(Args, Debug)]
+pub struct ListArgs {
+    /// 输出格式
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+}
+
+/// `mono bundle unbundle` 的参数
+#[derive(Args, Debug)]
+pub struct UnbundleArgs {
+    /// bundle 文件
+    pub file: PathBuf,
+}
+
+/// 执行 `mono bundle`
+pub fn execute(args: BundleArgs) -> MonoResult<()> {
+    let repo = Repository::discover(&std::env::current_dir()?)?;
+    match args.command {
+        BundleCommand::Create(args) => match &args.output {
+            Some(output) => {
+                let refs = selected_refs(&repo, &args.refs)?;
+                let mut out = std::io::BufWriter::new(std::fs::File::create(output)?);
+                let header = bundle::write_bundle(&repo, &refs, &[], &mut out)?;
+                out.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+                println!("Wrote {} refs to {}", header.refs.len(), output.display());
+            }
+            None => {
+                let info = BundleStore::new(&repo).create(args.incremental, chrono::Utc::now().timestamp())?;
+                println!("Created {} ({} bytes, {} refs)", info.file, info.size, info.refs.len());
+                if repo.config().bundles.base_url.is_none() {
+                    println!("Set [bundles] base_url to advertise it to cloning clients");
+                }
+            }
+        },
+        BundleCommand::List(args) => {
+            let bundles = BundleStore::new(&repo).list()?;
+            match args.format {
+                OutputFormat::Json => {
+                    let json = serde_json::to_string_pretty(&bundles).map_err(|e| MonoError::usage(e.to_string()))?;
+                    println!("{}", json);
+                }
+                OutputFormat::Text => {
+                    for bundle in &bundles {
+                        println!(
+                            "{:<24} token={:<12} refs={:<4} prerequisites={:<4} {} bytes",
+                            bundle.file,
+                            bundle.creation_token,
+                            bundle.refs.len(),
+                            bundle.prerequisites.len(),
+                            bundle.size
+                        );
+                    }
+                }
+            }
+        }
+        BundleCommand::Unbundle(args) => {
+            let header = bundle::unbundle(&repo, &std::fs::read(&args.file)?)?;
+            for (name, id) in &header.refs {
+                println!("{} {}", id, name);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 按名称解析引用，未指定时为全部分支与标签
+fn selected_refs(repo: &Repository, names: &[String]) -> MonoResult<Vec<(String, ObjectId)>> {
+    if names.is_empty() {
+        return bundle::branches_and_tags(repo);
+    }
+    names
+        .iter()
+        .map(|name| {
+            let id = repo
+                .refs()
+                .resolve(name)?
+                .ok_or_else(|| MonoError::not_found(format!("ref not found: {}", name)))?;
+            Ok((name.clone(), id))
+        })
+        .collect()
+}
@@ -0,0 +1,58 @@
+//! `mono changed` 命令：列出一段修订范围影响的项目
+
+use clap::Args;
+
+use crate::changed::changed_since;
+use crate::commands::OutputFormat;
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::repo::Repository;
+
+/// `mono changed` 的参数
+#[derive(Args, Debug)]
+pub struct ChangedArgs {
+    /// 比较的起点，通常是目标分支
+    #[arg(long)]
+    pub since: String,
+    /// 比较的终点
+    #[arg(long, default_value = "HEAD")]
+    pub until: String,
+    /// 输出格式
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+    /// 文本格式下同时列出每个项目中改动的文件
+    #[arg(long)]
+    pub files: bool,
+}
+
+/// 执行 `mono changed`
+pub fn execute(args: ChangedArgs) -> MonoResult<()> {
+    let repo = Repository::discover(&std::env::current_dir()?)?;
+    let since = repo.resolve_rev(&args.since)?;
+    let until = repo.resolve_rev(&args.until)?;
+    let changes = changed_since(&repo, &since, &until)?;
+
+    match args.format {
+        OutputFormat::Json => {
+            let json = serde_json::to_string_pretty(&changes).map_err(|e| MonoError::usage(e.to_string()))?;
+            println!("{}", json);
+        }
+        OutputFormat::Text => {
+            for project in &changes.projects {
+                println!("//{}", project.path);
+                if args.files {
+                    for file in &project.files {
+                        println!("    {}", file);
+                    }
+                }
+            }
+            if args.files && !changes.other.is_empty() {
+                println!("(other)");
+                for file in &changes.other {
+                    println!("    {}", file);
+                }
+            }
+        }
+    }
+    Ok(())
+}
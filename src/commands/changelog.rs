@@ -0,0 +1,48 @@
+//! `mono changelog` 命令：由一段修订范围中的提交生成变更日志
+
+use clap::Args;
+use clap_complete::ArgValueCompleter;
+
+use crate::changelog::Changelog;
+use crate::commands::OutputFormat;
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::completion::complete_refs;
+use crate::repo::Repository;
+
+/// `mono changelog` 的参数
+#[derive(Args, Debug)]
+pub struct ChangelogArgs {
+    /// 范围的起点（不含），通常是上一个版本的标签；省略时包含全部历史
+    #[arg(long, add = ArgValueCompleter::new(complete_refs))]
+    pub since: Option<String>,
+    /// 范围的终点
+    #[arg(long, default_value = "HEAD", add = ArgValueCompleter::new(complete_refs))]
+    pub until: String,
+    /// 只列出修改了该目录的提交，如 `//libs/net`
+    #[arg(long)]
+    pub path: Option<String>,
+    /// 输出格式，文本格式为 Markdown
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+}
+
+/// 执行 `mono changelog`
+pub fn execute(args: ChangelogArgs) -> MonoResult<()> {
+    let repo = Repository::discover(&std::env::current_dir()?)?;
+    let until = repo.resolve_rev(&args.until)?;
+    let exclude = match &args.since {
+        Some(since) => vec![repo.peel(&repo.resolve_rev(since)?)?.0],
+        None => Vec::new(),
+    };
+    let changelog = Changelog::generate(&repo, &[repo.peel(&until)?.0], &exclude, args.path.as_deref().unwrap_or_default())?;
+
+    match args.format {
+        OutputFormat::Json => {
+            let json = serde_json::to_string_pretty(&changelog).map_err(|e| MonoError::usage(e.to_string()))?;
+            println!("{}", json);
+        }
+        OutputFormat::Text => print!("{}", changelog.markdown()),
+    }
+    Ok(())
+}
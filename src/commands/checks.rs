@@ -0,0 +1,97 @@
+//! `mono checks` 命令：上报与查看提交的 CI 检查状态
+
+use clap::{Args, Subcommand};
+use clap_complete::ArgValueCompleter;
+
+use crate::checks::{CheckState, CheckStatus, Checks};
+use crate::commands::OutputFormat;
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::completion::complete_refs;
+use crate::repo::Repository;
+
+/// `mono checks` 的参数
+#[derive(Args, Debug)]
+pub struct ChecksArgs {
+    #[command(subcommand)]
+    pub command: ChecksCommand,
+}
+
+/// `mono checks` 的子命令
+#[derive(Subcommand, Debug)]
+pub enum ChecksCommand {
+    /// 上报提交上一项检查的状态，替换同名检查之前的状态
+    Post(PostArgs),
+    /// 列出提交上的检查
+    List(ListArgs),
+}
+
+/// `mono checks post` 的参数
+#[derive(Args, Debug)]
+pub struct PostArgs {
+    /// 检查的修订
+    #[arg(add = ArgValueCompleter::new(complete_refs))]
+    pub rev: String,
+    /// 检查名，例如 `ci/build`
+    pub context: String,
+    #[arg(value_enum)]
+    pub state: CheckState,
+    /// CI 中本次检查的详情页
+    #[arg(long)]
+    pub details_url: Option<String>,
+    #[arg(long)]
+    pub description: Option<String>,
+}
+
+/// `mono checks list` 的参数
+#[derive(Args, Debug)]
+pub struct ListArgs {
+    /// 修订，默认为 HEAD
+    #[arg(add = ArgValueCompleter::new(complete_refs))]
+    #[arg(default_value = "HEAD")]
+    pub rev: String,
+    /// 输出格式
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+}
+
+/// 执行 `mono checks`
+pub fn execute(args: ChecksArgs) -> MonoResult<()> {
+    let repo = Repository::discover(&std::env::current_dir()?)?;
+    let checks = Checks::new(&repo);
+    match args.command {
+        ChecksCommand::Post(args) => {
+            let commit = repo.resolve_rev(&args.rev)?;
+            let status = CheckStatus {
+                context: args.context,
+                state: args.state,
+                details_url: args.details_url,
+                description: args.description,
+                reporter: std::env::var("USER").unwrap_or_else(|_| "unknown".to_string()),
+                updated_at: chrono::Utc::now().timestamp(),
+            };
+            let summary = format!("{} {} on {}", status.context, status.state, commit);
+            checks.post(&commit, status)?;
+            println!("{}", summary);
+        }
+        ChecksCommand::List(args) => {
+            let statuses = checks.list(&repo.resolve_rev(&args.rev)?)?;
+            match args.format {
+                OutputFormat::Json => {
+                    let json = serde_json::to_string_pretty(&statuses).map_err(|e| MonoError::usage(e.to_string()))?;
+                    println!("{}", json);
+                }
+                OutputFormat::Text => {
+                    for status in &statuses {
+                        let url = status.details_url.as_deref().unwrap_or_default();
+                        println!("{:<8} {:<24} {}", status.state, status.context, url);
+                        if let Some(description) = &status.description {
+                            println!("         {}", description);
+                        }
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,75 @@
+//! `mono cherry-pick` 命令：在服务端把提交挑选到分支上，不需要检出
+
+use clap::Args;
+use clap_complete::ArgValueCompleter;
+
+use crate::audit::local_actor;
+use crate::auth::Access;
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::completion::complete_refs;
+use crate::object::commit::Signature;
+use crate::refs;
+use crate::repo::Repository;
+use crate::rewrite::pick::{pick_onto_branch, PickKind, PickOptions, PickOutcome};
+use crate::sparse;
+
+/// `mono cherry-pick` 的参数
+#[derive(Args, Debug)]
+pub struct CherryPickArgs {
+    /// 挑选的提交
+    #[arg(add = ArgValueCompleter::new(complete_refs))]
+    pub commit: String,
+    /// 目标分支
+    #[arg(long)]
+    pub onto: String,
+    /// 合并提交以第几个父提交为基准
+    #[arg(short, long)]
+    pub mainline: Option<usize>,
+    /// 在提交信息末尾注明来源提交
+    #[arg(short = 'x')]
+    pub record_origin: bool,
+}
+
+/// 执行 `mono cherry-pick`
+pub fn execute(args: CherryPickArgs) -> MonoResult<()> {
+    let options = PickOptions {
+        mainline: args.mainline,
+        record_origin: args.record_origin,
+        ..PickOptions::new(PickKind::CherryPick)
+    };
+    run(&args.commit, &args.onto, &options)
+}
+
+/// 挑选并输出结果，`mono revert` 共用；目标分支是当前分支时同时更新工作区
+pub(crate) fn run(commit: &str, onto: &str, options: &PickOptions) -> MonoResult<()> {
+    let repo = Repository::discover(&std::env::current_dir()?)?;
+    let id = repo.resolve_rev(commit)?;
+    let committer = Signature::committer_from_env()?;
+    let outcome = pick_onto_branch(&repo, &Access::full(local_actor()), &id, onto, &committer, options)?;
+    match outcome {
+        PickOutcome::Picked(new) => {
+            let summary = repo.read_commit(&id)?.summary().to_string();
+            println!("[{} {}] {}", refs::short_name(onto), &new.to_hex()[..7], summary);
+            let branch = format!("{}{}", refs::HEADS_PREFIX, refs::short_name(onto));
+            if repo.refs().head_target()?.as_deref() == Some(branch.as_str()) {
+                let stats = sparse::apply(&repo)?;
+                tracing::info!(files = stats.checked_out, "checked out worktree");
+            }
+            Ok(())
+        }
+        PickOutcome::Empty => Err(MonoError::usage(format!(
+            "{} of {} onto {} is empty, the changes are already there",
+            options.kind, commit, onto
+        ))),
+        PickOutcome::Conflict(conflicts) => {
+            for conflict in &conflicts {
+                match &conflict.other_path {
+                    Some(other) => eprintln!("CONFLICT ({}): {} and {}", conflict.kind, conflict.path, other),
+                    None => eprintln!("CONFLICT ({}): {}", conflict.kind, conflict.path),
+                }
+            }
+            Err(MonoError::usage(format!("{} of {} onto {} has {} conflicts", options.kind, commit, onto, conflicts.len())))
+        }
+    }
+}
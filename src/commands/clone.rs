@@ -0,0 +1,308 @@
+//! `mono clone` 命令：克隆远端仓库，支持部分克隆过滤与浅克隆
+
+use std::path::{Path, PathBuf};
+
+use clap::Args;
+
+use crate::audit;
+use crate::commands::audit::parse_since;
+use crate::common::config::RemoteConfig;
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::object::filter::ObjectFilter;
+use crate::object::shallow::Deepen;
+use crate::refs::{self, HEAD};
+use crate::repo::{InitOptions, Repository};
+use crate::transport;
+use crate::worktree::{self, CheckoutOptions};
+
+/// 克隆时使用的默认远端名
+pub const DEFAULT_REMOTE: &str = "origin";
+
+/// `mono clone` 的参数
+#[derive(Args, Debug)]
+pub struct CloneArgs {
+    /// 远端仓库地址
+    pub url: String,
+
+    /// 目标目录，默认使用远端仓库的目录名
+    pub directory: Option<PathBuf>,
+
+    /// 部分克隆过滤规则：`blob:none` 或 `tree:<depth>`
+    #[arg(long)]
+    pub filter: Option<ObjectFilter>,
+
+    /// 检出指定分支而不是远端 HEAD
+    #[arg(long, short = 'b')]
+    pub branch: Option<String>,
+
+    /// 不检出工作区文件
+    #[arg(long, short = 'n')]
+    pub no_checkout: bool,
+
+    /// 浅克隆：每个引用只获取最近的这么多个提交
+    #[arg(long)]
+    pub depth: Option<u32>,
+
+    /// 浅克隆：只获取该时间之后的提交，例如 `2024-01-31`、`30d`
+    #[arg(long, conflicts_with = "depth")]
+    pub shallow_since: Option<String>,
+
+    /// 检出时并行写入文件的线程数，默认为可用的 CPU 数
+    #[arg(long, short = 'j', default_value_t = 0)]
+    pub jobs: usize,
+}
+
+/// 克隆选项
+#[derive(Debug, Clone, Default)]
+pub struct CloneOptions {
+    pub filter: ObjectFilter,
+    pub branch: Option<String>,
+    pub no_checkout: bool,
+    /// 浅克隆的深度限制
+    pub deepen: Option<Deepen>,
+    pub checkout: CheckoutOptions,
+}
+
+/// 执行 `mono clone`
+pub fn execute(args: CloneArgs) -> MonoResult<()> {
+    let directory = match args.directory {
+        Some(directory) => directory,
+        None => default_directory(&args.url)?,
+    };
+    let deepen = match (args.depth, args.shallow_since) {
+        (Some(0), _) => return Err(MonoError::usage("--depth must be positive")),
+        (Some(depth), _) => Some(Deepen::Depth(depth)),
+        (None, Some(since)) => Some(Deepen::Since(parse_since(&since, chrono::Utc::now())?)),
+        (None, None) => None,
+    };
+    let options = CloneOptions {
+        filter: args.filter.unwrap_or_default(),
+        branch: args.branch,
+        no_checkout: args.no_checkout,
+        deepen,
+        checkout: CheckoutOptions {
+            jobs: args.jobs,
+            progress: true,
+        },
+    };
+    let repo = clone_repository(&args.url, &directory, &options)?;
+    println!("Cloned {} into {}", args.url, repo.root().display());
+    Ok(())
+}
+
+/// 由远端地址推断目标目录名
+fn default_directory(url: &str) -> MonoResult<PathBuf> {
+    url.trim_end_matches('/')
+        .rsplit(['/', '\\', ':'])
+        .next()
+        .map(|name| name.trim_end_matches(".git"))
+        .filter(|name| !name.is_empty())
+        .map(PathBuf::from)
+        .ok_or_else(|| MonoError::usage(format!("cannot infer directory name from {}", url)))
+}
+
+/// 将远端仓库克隆到 `directory`
+///
+/// 远端分支保存为 `refs/remotes/origin/*`，标签原样保存，并为检出的分支创建本地分支。
+/// 使用部分克隆过滤时，远端会被记录为 promisor，缺失的对象在读取时按需获取。
+/// 浅克隆的边界提交记录在 `.mono/shallow` 中，之后可以用 `mono fetch --deepen` 加深。
+pub fn clone_repository(url: &str, directory: &Path, options: &CloneOptions) -> MonoResult<Repository> {
+    if directory.exists() && std::fs::read_dir(directory)?.next().is_some() {
+        return Err(MonoError::usage(format!(
+            "destination path {} already exists and is not empty",
+            directory.display()
+        )));
+    }
+    let url = normalize_url(url)?;
+    let transport = transport::open(&url)?;
+    let remote_refs = transport.list_refs()?;
+
+    let branch = match (&options.branch, &remote_refs.head) {
+        (Some(branch), _) => branch.clone(),
+        (None, Some(head)) => refs::short_name(head).to_string(),
+        (None, None) => InitOptions::default().initial_branch,
+    };
+    let branch_ref = format!("{}{}", refs::HEADS_PREFIX, branch);
+    let branch_tip = remote_refs
+        .refs
+        .iter()
+        .find(|(name, _)| *name == branch_ref)
+        .map(|(_, id)| *id);
+    if options.branch.is_some() && branch_tip.is_none() {
+        return Err(MonoError::not_found(format!("remote branch {}", branch)));
+    }
+
+    std::fs::create_dir_all(directory)?;
+    let init = InitOptions {
+        initial_branch: branch.clone(),
+        ..Default::default()
+    };
+    let mut repo = Repository::init(directory, &init)?;
+
+    let wants: Vec<_> = remote_refs.refs.iter().map(|(_, id)| *id).collect();
+    let stats = match options.deepen {
+        Some(deepen) => {
+            let (stats, update) =
+                transport.fetch_shallow(&wants, &[], &Default::default(), Some(deepen), &options.filter, repo.objects())?;
+            repo.update_shallow(&update)?;
+            stats
+        }
+        None => transport.fetch(&wants, &[], &options.filter, repo.objects())?,
+    };
+    tracing::info!(objects = stats.objects, filter = %options.filter, "fetched objects");
+
+    let logged = repo.with_reflog_identity(&audit::local_actor(), &format!("clone: {}", url));
+    let store = logged.refs();
+    for (name, id) in &remote_refs.refs {
+        if let Some(branch) = name.strip_prefix(refs::HEADS_PREFIX) {
+            store.write(&format!("refs/remotes/{}/{}", DEFAULT_REMOTE, branch), id)?;
+        } else if name.starts_with(refs::TAGS_PREFIX) {
+            store.write(name, id)?;
+        }
+    }
+    if let Some(tip) = branch_tip {
+        store.write(&branch_ref, &tip)?;
+    }
+    store.write_symbolic(HEAD, &branch_ref)?;
+
+    repo.config_mut().remote.insert(
+        DEFAULT_REMOTE.to_string(),
+        RemoteConfig {
+            url,
+            promisor: options.filter.is_partial(),
+            partial_clone_filter: options.filter.is_partial().then(|| options.filter.to_string()),
+        },
+    );
+    repo.save_config()?;
+
+    if let (Some(tip), false) = (branch_tip, options.no_checkout) {
+        let tree = repo.read_commit(&tip)?.tree;
+        let stats = worktree::checkout_tree_with(&repo, &tree, repo.root(), |_, _| true, &options.checkout)?;
+        tracing::info!(files = stats.files, bytes = stats.bytes, "checked out worktree");
+    }
+    Ok(repo)
+}
+
+/// 本地路径转换为绝对路径，保证克隆后的仓库在任何目录下都能访问远端
+pub fn normalize_url(url: &str) -> MonoResult<String> {
+    let path = Path::new(url.strip_prefix("file://").unwrap_or(url));
+    Ok(path.canonicalize()?.to_string_lossy().into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{commit_files, init_repo};
+
+    fn source_repo() -> (tempfile::TempDir, Repository) {
+        let (dir, repo) = init_repo();
+        let first = commit_files(&repo, &[("README", b"hello")], &[], "first");
+        let second = commit_files(
+            &repo,
+            &[("README", b"hello"), ("services/payments/main.rs", b"fn main() {}")],
+            &[first],
+            "second",
+        );
+        repo.refs().write("refs/heads/main", &second).unwrap();
+        repo.refs().write("refs/tags/v1", &first).unwrap();
+        (dir, repo)
+    }
+
+    /// 测试完整克隆复制全部对象、引用并检出工作区
+    #[test]
+    fn test_full_clone() {
+        let (source_dir, source) = source_repo();
+        let target = tempfile::tempdir().unwrap();
+        let dest = target.path().join("clone");
+        let url = source_dir.path().to_str().unwrap();
+
+        let repo = clone_repository(url, &dest, &CloneOptions::default()).unwrap();
+        assert_eq!(repo.objects().list().unwrap(), source.objects().list().unwrap());
+        let main = source.refs().resolve("refs/heads/main").unwrap();
+        assert_eq!(repo.refs().resolve(HEAD).unwrap(), main);
+        assert_eq!(repo.refs().resolve("refs/remotes/origin/main").unwrap(), main);
+        assert!(repo.refs().resolve("refs/tags/v1").unwrap().is_some());
+        assert_eq!(std::fs::read(dest.join("services/payments/main.rs")).unwrap(), b"fn main() {}");
+        assert!(!repo.config().remote["origin"].promisor);
+    }
+
+    /// 测试 blob:none 克隆不传输 blob，并在读取时按需获取
+    #[test]
+    fn test_partial_clone_lazy_fetch() {
+        let (source_dir, source) = source_repo();
+        let target = tempfile::tempdir().unwrap();
+        let dest = target.path().join("partial");
+        let options = CloneOptions {
+            filter: ObjectFilter::BlobNone,
+            no_checkout: true,
+            ..Default::default()
+        };
+
+        let repo = clone_repository(source_dir.path().to_str().unwrap(), &dest, &options).unwrap();
+        let remote = &repo.config().remote["origin"];
+        assert!(remote.promisor);
+        assert_eq!(remote.partial_clone_filter.as_deref(), Some("blob:none"));
+        // 除两个 blob 外的对象都已传输
+        let local = repo.objects().list().unwrap();
+        assert_eq!(local.len(), source.objects().list().unwrap().len() - 2);
+
+        let head = repo.refs().resolve(HEAD).unwrap().unwrap();
+        worktree::checkout_commit(&repo, &head, |_, _| true).unwrap();
+        assert_eq!(std::fs::read(dest.join("README")).unwrap(), b"hello");
+        assert_eq!(repo.objects().list().unwrap().len(), source.objects().list().unwrap().len());
+    }
+
+    /// 测试浅克隆只传输最近的提交，之后可以通过传输层加深
+    #[test]
+    fn test_shallow_clone() {
+        let (source_dir, source) = init_repo();
+        let c1 = commit_files(&source, &[("README", b"1")], &[], "1");
+        let c2 = commit_files(&source, &[("README", b"2")], &[c1], "2");
+        let c3 = commit_files(&source, &[("README", b"3")], &[c2], "3");
+        source.refs().write("refs/heads/main", &c3).unwrap();
+        let target = tempfile::tempdir().unwrap();
+        let options = CloneOptions {
+            deepen: Some(Deepen::Depth(1)),
+            ..Default::default()
+        };
+        let url = source_dir.path().to_str().unwrap();
+
+        let mut repo = clone_repository(url, &target.path().join("shallow"), &options).unwrap();
+        assert_eq!(repo.shallow_commits(), &[c3].into());
+        assert!(repo.read_commit(&c3).unwrap().parents.is_empty());
+        assert_eq!(repo.objects().list().unwrap().len(), 3);
+        assert_eq!(std::fs::read(target.path().join("shallow/README")).unwrap(), b"3");
+
+        let transport = transport::open(url).unwrap();
+        let shallow = repo.shallow_commits().clone();
+        let (stats, update) = transport
+            .fetch_shallow(&[c3], &[c3], &shallow, Some(Deepen::Relative(1)), &ObjectFilter::None, repo.objects())
+            .unwrap();
+        assert_eq!(stats.objects, 3);
+        repo.update_shallow(&update).unwrap();
+        assert_eq!(repo.shallow_commits(), &[c2].into());
+        assert_eq!(repo.read_commit(&c3).unwrap().parents, vec![c2]);
+    }
+
+    /// 测试目标目录非空时拒绝克隆
+    #[test]
+    fn test_clone_into_non_empty() {
+        let (source_dir, _source) = source_repo();
+        let target = tempfile::tempdir().unwrap();
+        std::fs::write(target.path().join("file"), b"x").unwrap();
+        let result = clone_repository(
+            source_dir.path().to_str().unwrap(),
+            target.path(),
+            &CloneOptions::default(),
+        );
+        assert!(result.is_err());
+    }
+
+    /// 测试由远端地址推断目录名
+    #[test]
+    fn test_default_directory() {
+        assert_eq!(default_directory("/srv/repos/mono/").unwrap(), PathBuf::from("mono"));
+        assert_eq!(default_directory("https://host/org/repo.git").unwrap(), PathBuf::from("repo"));
+    }
+}
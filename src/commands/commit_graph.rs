@@ -0,0 +1,41 @@
+//! `mono commit-graph` 命令：生成与检查提交图
+
+use clap::{Args, Subcommand};
+
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::graph::{self, CommitGraph};
+use crate::repo::Repository;
+
+/// `mono commit-graph` 的参数
+#[derive(Args, Debug)]
+pub struct CommitGraphArgs {
+    #[command(subcommand)]
+    pub command: CommitGraphCommand,
+}
+
+/// `mono commit-graph` 的子命令
+#[derive(Subcommand, Debug)]
+pub enum CommitGraphCommand {
+    /// 为所有引用可达的提交写入提交图
+    Write,
+    /// 检查提交图的校验和与世代号
+    Verify,
+}
+
+/// 执行 `mono commit-graph`
+pub fn execute(args: CommitGraphArgs) -> MonoResult<()> {
+    let repo = Repository::discover(&std::env::current_dir()?)?;
+    match args.command {
+        CommitGraphCommand::Write => {
+            let graph = graph::write_commit_graph(&repo)?;
+            println!("Wrote commit-graph with {} commits", graph.len());
+        }
+        CommitGraphCommand::Verify => {
+            let graph = CommitGraph::load(&repo)?.ok_or_else(|| MonoError::not_found("commit-graph"))?;
+            graph.verify()?;
+            println!("commit-graph is valid ({} commits)", graph.len());
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,42 @@
+//! `mono completions` 命令：输出 shell 补全脚本
+
+use clap::{Args, CommandFactory, ValueEnum};
+use clap_complete::env::{Bash, EnvCompleter, Fish, Powershell, Zsh};
+
+use crate::cli::Cli;
+use crate::common::MonoResult;
+use crate::completion::COMPLETE_ENV;
+
+/// 支持补全的 shell
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+    #[value(alias = "pwsh")]
+    Powershell,
+}
+
+/// `mono completions` 的参数
+///
+/// 例如在 `~/.bashrc` 中加入 `source <(mono completions bash)`；脚本在补全时调用当前的
+/// `mono` 可执行文件，升级后重新加载即可。
+#[derive(Args, Debug)]
+pub struct CompletionsArgs {
+    pub shell: Shell,
+}
+
+/// 执行 `mono completions`
+pub fn execute(args: CompletionsArgs) -> MonoResult<()> {
+    let completer: &dyn EnvCompleter = match args.shell {
+        Shell::Bash => &Bash,
+        Shell::Zsh => &Zsh,
+        Shell::Fish => &Fish,
+        Shell::Powershell => &Powershell,
+    };
+    let cmd = Cli::command();
+    let exe = std::env::current_exe()?;
+    let mut stdout = std::io::stdout().lock();
+    completer.write_registration(COMPLETE_ENV, cmd.get_name(), cmd.get_name(), &exe.to_string_lossy(), &mut stdout)?;
+    Ok(())
+}
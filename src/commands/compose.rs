@@ -0,0 +1,114 @@
+//! `mono compose` 命令：与组成虚拟单仓库的各个上游仓库双向同步
+
+use clap::{Args, Subcommand};
+
+use crate::commands::OutputFormat;
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::compose::{ComponentStatus, Composer};
+use crate::object::commit::Signature;
+use crate::refs;
+use crate::repo::Repository;
+use crate::sparse;
+
+/// `mono compose` 的参数
+#[derive(Args, Debug)]
+pub struct ComposeArgs {
+    #[command(subcommand)]
+    pub command: ComposeCommand,
+}
+
+/// `mono compose` 的子命令
+#[derive(Subcommand, Debug)]
+pub enum ComposeCommand {
+    /// 显示每个组件上次同步的位置，以及两端在同步后是否有新改动
+    Status(StatusArgs),
+    /// 从上游导入新提交，并把单仓库中的改动推送到上游
+    Sync(SyncArgs),
+}
+
+/// `mono compose status` 的参数
+#[derive(Args, Debug)]
+pub struct StatusArgs {
+    /// 输出格式
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+}
+
+/// `mono compose sync` 的参数
+#[derive(Args, Debug)]
+pub struct SyncArgs {
+    /// 只同步该目录的组件
+    pub path: Option<String>,
+}
+
+/// 执行 `mono compose`
+pub fn execute(args: ComposeArgs) -> MonoResult<()> {
+    let repo = Repository::discover(&std::env::current_dir()?)?;
+    let composer = Composer::new(&repo);
+    match args.command {
+        ComposeCommand::Status(args) => {
+            let statuses = composer.status()?;
+            match args.format {
+                OutputFormat::Json => {
+                    let json = serde_json::to_string_pretty(&statuses).map_err(|e| MonoError::usage(e.to_string()))?;
+                    println!("{}", json);
+                }
+                OutputFormat::Text => statuses.iter().for_each(print_status),
+            }
+        }
+        ComposeCommand::Sync(args) => {
+            let branch = format!("{}{}", refs::HEADS_PREFIX, repo.config().compose.branch);
+            let old = repo.refs().resolve(&branch)?;
+            let results = composer.sync(args.path.as_deref(), &Signature::committer_from_env()?)?;
+            let mut failed = 0;
+            for (path, result) in &results {
+                match result {
+                    Ok(outcome) if !outcome.conflicts.is_empty() => {
+                        failed += 1;
+                        println!("//{}: conflict, nothing was synced", path);
+                        for conflict in &outcome.conflicts {
+                            println!("    {} {}", conflict.kind, conflict.path);
+                        }
+                    }
+                    Ok(outcome) => println!("//{}: imported {}, exported {}", path, outcome.imported, outcome.exported),
+                    Err(e) => {
+                        failed += 1;
+                        println!("//{}: {}", path, e);
+                    }
+                }
+            }
+            if repo.refs().resolve(&branch)? != old && repo.refs().head_target()?.as_deref() == Some(branch.as_str()) {
+                let stats = sparse::apply(&repo)?;
+                tracing::info!(files = stats.checked_out, "checked out worktree");
+            }
+            if failed > 0 {
+                return Err(MonoError::unavailable(format!("{} of {} components failed to sync", failed, results.len())));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn print_status(status: &ComponentStatus) {
+    let changes = |changed: Option<bool>| match changed {
+        Some(true) => "changed",
+        Some(false) => "clean",
+        None => "?",
+    };
+    let synced = match &status.synced {
+        Some(point) => point.mono.to_hex()[..7].to_string(),
+        None => "never".to_string(),
+    };
+    println!(
+        "//{:<24} synced={:<7} local={:<7} upstream={:<7} {}",
+        status.path,
+        synced,
+        changes(status.local_changes),
+        changes(status.upstream_changes),
+        status.url
+    );
+    if let Some(error) = &status.error {
+        println!("    {}", error);
+    }
+}
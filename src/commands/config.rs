@@ -0,0 +1,161 @@
+//! `mono config` 命令：读取与修改分层配置
+
+use std::path::{Path, PathBuf};
+
+use clap::{Args, Subcommand};
+
+use crate::audit::{self, AuditLog};
+use crate::commands::OutputFormat;
+use crate::common::config::{self, Config, ConfigLayer, ConfigSources, RepoConfig};
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::repo::{Repository, MONO_DIR};
+
+/// `mono config` 的参数
+#[derive(Args, Debug)]
+pub struct ConfigArgs {
+    #[command(subcommand)]
+    pub command: ConfigCommand,
+}
+
+/// `mono config` 的子命令
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommand {
+    /// 显示合并各配置层后某个键的值
+    Get(GetArgs),
+    /// 在一个配置层中设置键的值
+    Set(SetArgs),
+    /// 列出合并后的全部配置项
+    List(ListArgs),
+}
+
+/// `mono config get` 的参数
+#[derive(Args, Debug)]
+pub struct GetArgs {
+    /// 点分隔的键，例如 `storage.backend`
+    pub key: String,
+    /// 同时显示值的来源
+    #[arg(long)]
+    pub show_origin: bool,
+}
+
+/// `mono config set` 的参数
+#[derive(Args, Debug)]
+pub struct SetArgs {
+    /// 点分隔的键，例如 `telemetry.sample_ratio`
+    pub key: String,
+    /// 值，按 TOML 解析（如 `8080`、`true`、`["a", "b"]`），不是合法的 TOML 值时作为字符串
+    pub value: String,
+    /// 写入的配置层
+    #[arg(long, value_enum, default_value_t = ConfigLayer::Repo)]
+    pub layer: ConfigLayer,
+}
+
+/// `mono config list` 的参数
+#[derive(Args, Debug)]
+pub struct ListArgs {
+    /// 同时显示每一项的来源
+    #[arg(long)]
+    pub show_origin: bool,
+    /// 输出格式
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+}
+
+/// 执行 `mono config`
+///
+/// 只查找 `.mono` 目录而不打开仓库，配置有误时仍然可以用 `mono config set` 修正。
+pub fn execute(args: ConfigArgs) -> MonoResult<()> {
+    let cwd = std::env::current_dir()?;
+    let mono_dir = cwd.ancestors().map(|dir| dir.join(MONO_DIR)).find(|dir| dir.is_dir());
+    match args.command {
+        ConfigCommand::Get(args) => {
+            let config = Config::load(mono_dir.as_deref())?;
+            let value = config
+                .value(&args.key)
+                .ok_or_else(|| MonoError::not_found(format!("configuration key not set: {}", args.key)))?;
+            match config.origin(&args.key).filter(|_| args.show_origin) {
+                Some(origin) => println!("{}\t{}", origin.source, display(value)),
+                None => println!("{}", display(value)),
+            }
+        }
+        ConfigCommand::Set(args) => set(args, mono_dir)?,
+        ConfigCommand::List(args) => {
+            let config = Config::load(mono_dir.as_deref())?;
+            let entries = config.entries();
+            match args.format {
+                OutputFormat::Json => {
+                    let items: Vec<serde_json::Value> = entries
+                        .iter()
+                        .map(|(key, value)| {
+                            let origin = config.origin(key);
+                            serde_json::json!({
+                                "key": key,
+                                "value": value,
+                                "layer": origin.map(|origin| origin.layer),
+                                "source": origin.map(|origin| &origin.source),
+                            })
+                        })
+                        .collect();
+                    let json = serde_json::to_string_pretty(&items).map_err(|e| MonoError::usage(e.to_string()))?;
+                    println!("{}", json);
+                }
+                OutputFormat::Text => {
+                    for (key, value) in &entries {
+                        match config.origin(key).filter(|_| args.show_origin) {
+                            Some(origin) => println!("{}\t{}={}", origin.source, key, display(value)),
+                            None => println!("{}={}", key, display(value)),
+                        }
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 写入一个配置层；合并后的配置不合法时恢复原文件并报告错误
+fn set(args: SetArgs, mono_dir: Option<PathBuf>) -> MonoResult<()> {
+    let sources = ConfigSources::discover(mono_dir.as_deref());
+    let path = match sources.path(args.layer) {
+        Some(path) => path.to_path_buf(),
+        None if args.layer == ConfigLayer::Repo => {
+            return Err(MonoError::not_found("not a mono workspace (or any of the parent directories)"));
+        }
+        None => {
+            return Err(MonoError::usage(format!(
+                "cannot write the {} layer, set {} variables instead",
+                args.layer,
+                config::ENV_PREFIX
+            )));
+        }
+    };
+    let original = std::fs::read(&path).ok();
+    let before = RepoConfig::load(&path).ok();
+    let mut table = config::read_table(&path)?.unwrap_or_default();
+    config::set_value(&mut table, &args.key, config::parse_value(&args.value))?;
+    config::write_table(&path, &table)?;
+    if let Err(e) = Config::from_sources(&sources) {
+        match original {
+            Some(original) => std::fs::write(&path, original)?,
+            None => std::fs::remove_file(&path)?,
+        }
+        return Err(e);
+    }
+    let root = mono_dir.as_deref().and_then(Path::parent);
+    if let (ConfigLayer::Repo, Some(root)) = (args.layer, root) {
+        let repo = Repository::open(root)?;
+        if let (Some(before), Ok(after)) = (before, RepoConfig::load(&path)) {
+            AuditLog::new(&repo).record_config_changes(&before, &after, &audit::local_actor(), chrono::Utc::now().timestamp());
+        }
+    }
+    Ok(())
+}
+
+/// 字符串直接输出，其他值按 TOML 语法输出
+fn display(value: &toml::Value) -> String {
+    match value {
+        toml::Value::String(s) => s.clone(),
+        value => value.to_string(),
+    }
+}
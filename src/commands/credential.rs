@@ -0,0 +1,51 @@
+//! `mono credential` 命令：git 凭据助手，提供 `mono login` 取得的 ID 令牌
+//!
+//! 配置方式：`git config credential.https://mono.example.com.helper "!mono credential --provider okta"`。
+//! git 执行 `mono credential ... get` 时输出以 ID 令牌为密码的凭据，令牌即将过期时先用
+//! 刷新令牌换取新的令牌；`store` 与 `erase` 不做任何事，会话只由 `mono login`/`mono logout` 管理。
+
+use std::io::BufRead;
+
+use clap::{Args, ValueEnum};
+
+use crate::auth::keychain::Keychain;
+use crate::commands::login::ProviderArgs;
+use crate::common::MonoResult;
+
+/// 凭据助手的用户名，服务端只使用密码
+const CREDENTIAL_USERNAME: &str = "oauth2";
+
+/// git 凭据助手协议的操作
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CredentialOperation {
+    Get,
+    Store,
+    Erase,
+}
+
+/// `mono credential` 的参数
+#[derive(Args, Debug)]
+pub struct CredentialArgs {
+    #[command(flatten)]
+    pub provider: ProviderArgs,
+    #[arg(value_enum)]
+    pub operation: CredentialOperation,
+}
+
+/// 执行 `mono credential`
+pub fn execute(args: CredentialArgs) -> MonoResult<()> {
+    // git 在标准输入中写入请求的属性，以空行结束；令牌与主机无关，读完即可
+    for line in std::io::stdin().lock().lines() {
+        if line?.is_empty() {
+            break;
+        }
+    }
+    if args.operation != CredentialOperation::Get {
+        return Ok(());
+    }
+    let (_, client) = args.provider.client()?;
+    let session = client.session_at(&Keychain::default(), chrono::Utc::now().timestamp())?;
+    println!("username={}", CREDENTIAL_USERNAME);
+    println!("password={}", session.id_token);
+    Ok(())
+}
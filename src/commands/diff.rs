@@ -0,0 +1,169 @@
+//! `mono diff` 命令：比较两个修订，检测重命名与复制
+
+use clap::Args;
+use clap_complete::ArgValueCompleter;
+
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::completion::complete_refs;
+use crate::diff::tree::{diff_files, write_patch, DiffOptions, FileDiff};
+use crate::diff::word;
+use crate::diff::{Algorithm, DEFAULT_CONTEXT};
+use crate::object::ObjectId;
+use crate::refs;
+use crate::repo::Repository;
+
+/// `--stat` 中改动条的最大宽度
+const STAT_WIDTH: usize = 50;
+
+/// `mono diff` 的参数
+#[derive(Args, Debug)]
+pub struct DiffArgs {
+    /// `<旧>..<新>` 比较两个修订，单个修订与它的第一个父提交比较，默认为 HEAD
+    #[arg(default_value = refs::HEAD, add = ArgValueCompleter::new(complete_refs))]
+    pub range: String,
+    /// 只显示每个文件改动的行数
+    #[arg(long, conflicts_with_all = ["json", "name_status"])]
+    pub stat: bool,
+    /// 以 JSON 输出改动的文件与 hunk
+    #[arg(long, conflicts_with = "name_status")]
+    pub json: bool,
+    /// 只显示改动类型与路径
+    #[arg(long)]
+    pub name_status: bool,
+    /// 逐词显示改动
+    #[arg(long)]
+    pub word_diff: bool,
+    /// 行级差异算法
+    #[arg(long, value_enum, default_value_t = Algorithm::Myers)]
+    pub diff_algorithm: Algorithm,
+    /// 不检测重命名
+    #[arg(long)]
+    pub no_renames: bool,
+    /// 检测复制
+    #[arg(short = 'C', long)]
+    pub find_copies: bool,
+    /// 重命名与复制的相似度阈值（百分比）
+    #[arg(short = 'M', long, default_value_t = 50, value_parser = clap::value_parser!(u8).range(0..=100))]
+    pub rename_threshold: u8,
+    /// 上下文行数
+    #[arg(short = 'U', long, default_value_t = DEFAULT_CONTEXT)]
+    pub unified: usize,
+}
+
+/// 执行 `mono diff`
+pub fn execute(args: DiffArgs) -> MonoResult<()> {
+    let repo = Repository::discover(&std::env::current_dir()?)?;
+    let (old, new) = resolve_range(&repo, &args.range)?;
+    let tree = |commit: Option<ObjectId>| -> MonoResult<Option<ObjectId>> {
+        commit.map(|commit| repo.read_commit(&commit).map(|commit| commit.tree)).transpose()
+    };
+    let options = DiffOptions {
+        algorithm: args.diff_algorithm,
+        find_renames: !args.no_renames,
+        find_copies: args.find_copies,
+        rename_threshold: args.rename_threshold,
+        context: args.unified,
+        ..Default::default()
+    };
+    let diffs = diff_files(&repo, tree(old)?.as_ref(), tree(Some(new))?.as_ref(), &options)?;
+
+    if args.json {
+        let json = serde_json::to_string_pretty(&diffs).map_err(|e| MonoError::usage(e.to_string()))?;
+        println!("{}", json);
+    } else if args.stat {
+        print_stat(&diffs);
+    } else if args.name_status {
+        for diff in &diffs {
+            let change = &diff.change;
+            match (&change.old, change.similarity) {
+                (Some(old), Some(similarity)) => {
+                    println!("{}{:03}\t{}\t{}", change.kind.letter(), similarity, old.path, change.path())
+                }
+                _ => println!("{}\t{}", change.kind.letter(), change.path()),
+            }
+        }
+    } else {
+        let mut out = String::new();
+        for diff in &diffs {
+            if args.word_diff && !diff.binary {
+                // 补丁头照常输出，hunk 换成逐词的格式
+                let mut header = String::new();
+                write_patch(&mut header, &FileDiff { hunks: Vec::new(), ..diff.clone() });
+                out.push_str(&header);
+                if !diff.hunks.is_empty() {
+                    let old_name = diff.change.old.as_ref().map_or("/dev/null".to_string(), |old| format!("a/{}", old.path));
+                    let new_name = diff.change.new.as_ref().map_or("/dev/null".to_string(), |new| format!("b/{}", new.path));
+                    out.push_str(&format!("--- {}\n+++ {}\n", old_name, new_name));
+                }
+                for hunk in &diff.hunks {
+                    word::write_plain(&mut out, hunk, options.algorithm);
+                }
+            } else {
+                write_patch(&mut out, diff);
+            }
+        }
+        print!("{}", out);
+    }
+    Ok(())
+}
+
+/// 解析 `<旧>..<新>` 或单个修订；单个修订没有父提交时与空树比较
+fn resolve_range(repo: &Repository, range: &str) -> MonoResult<(Option<ObjectId>, ObjectId)> {
+    if let Some((old, new)) = range.split_once("..") {
+        let old = if old.is_empty() { refs::HEAD } else { old };
+        let new = if new.is_empty() { refs::HEAD } else { new };
+        return Ok((Some(repo.resolve_rev(old)?), repo.resolve_rev(new)?));
+    }
+    let new = repo.resolve_rev(range)?;
+    let parent = repo.read_commit(&new)?.parents.first().copied();
+    Ok((parent, new))
+}
+
+/// 与 `git diff --stat` 相同的格式
+fn print_stat(diffs: &[FileDiff]) {
+    let names: Vec<String> = diffs
+        .iter()
+        .map(|diff| match (&diff.change.old, diff.change.similarity) {
+            (Some(old), Some(_)) => format!("{} => {}", old.path, diff.change.path()),
+            _ => diff.change.path().to_string(),
+        })
+        .collect();
+    let name_width = names.iter().map(|name| name.chars().count()).max().unwrap_or(0);
+    let max_changes = diffs.iter().map(|diff| diff.insertions + diff.deletions).max().unwrap_or(0);
+    let count_width = max_changes.to_string().len();
+    for (diff, name) in diffs.iter().zip(&names) {
+        if diff.binary {
+            println!(" {:<name_width$} | {:>count_width$}", name, "Bin");
+            continue;
+        }
+        let total = diff.insertions + diff.deletions;
+        // 改动多时按比例缩短，但每种改动至少保留一个字符
+        let scale = |n: usize| {
+            if max_changes <= STAT_WIDTH || n == 0 {
+                n
+            } else {
+                (n * STAT_WIDTH / max_changes).max(1)
+            }
+        };
+        println!(
+            " {:<name_width$} | {:>count_width$} {}{}",
+            name,
+            total,
+            "+".repeat(scale(diff.insertions)),
+            "-".repeat(scale(diff.deletions))
+        );
+    }
+    let insertions: usize = diffs.iter().map(|diff| diff.insertions).sum();
+    let deletions: usize = diffs.iter().map(|diff| diff.deletions).sum();
+    let plural = |n: usize| if n == 1 { "" } else { "s" };
+    println!(
+        " {} file{} changed, {} insertion{}(+), {} deletion{}(-)",
+        diffs.len(),
+        plural(diffs.len()),
+        insertions,
+        plural(insertions),
+        deletions,
+        plural(deletions)
+    );
+}
@@ -0,0 +1,58 @@
+//! `mono doctor` 命令：诊断运行环境并给出修复建议
+
+use clap::Args;
+
+use crate::commands::OutputFormat;
+use crate::common::errors::{ExitCode, MonoError, MonoErrorKind};
+use crate::common::MonoResult;
+use crate::doctor::{doctor, DoctorOptions, Status};
+
+/// `mono doctor` 的参数
+#[derive(Args, Debug)]
+pub struct DoctorArgs {
+    /// 输出格式
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+    /// 跳过需要访问网络的凭据与时钟检查
+    #[arg(long)]
+    pub offline: bool,
+    /// 有警告时同样以非零退出码退出
+    #[arg(long)]
+    pub strict: bool,
+}
+
+/// 执行 `mono doctor`：有检查出错时以 1 退出，`--strict` 时警告也以 1 退出
+pub fn execute(args: DoctorArgs) -> MonoResult<()> {
+    let options = DoctorOptions { offline: args.offline };
+    let report = doctor(&std::env::current_dir()?, &options);
+
+    match args.format {
+        OutputFormat::Json => {
+            let json = serde_json::to_string_pretty(&report).map_err(|e| MonoError::usage(e.to_string()))?;
+            println!("{}", json);
+        }
+        OutputFormat::Text => {
+            for check in &report.checks {
+                println!("{:<8} {:<12} {}", check.status, check.name, check.message);
+                if let Some(fix) = &check.fix {
+                    println!("{:<21} fix: {}", "", fix);
+                }
+            }
+        }
+    }
+
+    let failed = match report.worst() {
+        Some(Status::Error) => true,
+        Some(Status::Warning) => args.strict,
+        _ => false,
+    };
+    if !failed {
+        return Ok(());
+    }
+    let message = format!(
+        "doctor found {} errors and {} warnings",
+        report.count(Status::Error),
+        report.count(Status::Warning)
+    );
+    Err(MonoError::from_kind(MonoErrorKind::Config(message), ExitCode::Failure.code()))
+}
@@ -0,0 +1,84 @@
+//! `mono du` 命令：统计仓库与各目录占用的存储
+
+use clap::Args;
+use serde::Serialize;
+
+use crate::commands::OutputFormat;
+use crate::common::errors::MonoError;
+use crate::common::progress::format_bytes;
+use crate::common::MonoResult;
+use crate::quota::{self, Usage, UsageReport};
+use crate::repo::Repository;
+
+/// `mono du` 的参数
+#[derive(Args, Debug)]
+pub struct DuArgs {
+    /// 只统计这些目录，例如 `assets/textures`
+    #[arg(value_name = "PREFIX")]
+    pub prefixes: Vec<String>,
+    /// 按目录统计的层数，未指定目录时默认为 1，0 时只统计总量
+    #[arg(long)]
+    pub depth: Option<usize>,
+    /// 统计 `[namespaces] tenants` 中的一个租户
+    #[arg(long, value_name = "TENANT")]
+    pub repo: Option<String>,
+    /// 输出格式
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+}
+
+/// JSON 输出
+#[derive(Serialize)]
+struct Output<'a> {
+    #[serde(flatten)]
+    report: &'a UsageReport,
+    /// 超出的配额
+    exceeded: Vec<String>,
+}
+
+/// 执行 `mono du`
+pub fn execute(args: DuArgs) -> MonoResult<()> {
+    let repo = Repository::discover(&std::env::current_dir()?)?;
+    let repo = match &args.repo {
+        Some(tenant) if !repo.config().namespaces.tenants.iter().any(|t| t == tenant) => {
+            return Err(MonoError::not_found(format!("tenant {}", tenant)));
+        }
+        Some(tenant) => repo.with_namespace(tenant)?,
+        None => repo,
+    };
+    let depth = args.depth.unwrap_or(if args.prefixes.is_empty() { 1 } else { 0 });
+    let config = &repo.config().quota;
+    let mut prefixes = args.prefixes.clone();
+    prefixes.extend(config.paths.iter().map(|quota| quota.prefix.clone()));
+    let report = quota::usage(&repo, depth, &prefixes)?;
+    let exceeded = quota::exceeded(config, &report);
+
+    match args.format {
+        OutputFormat::Json => {
+            let output = Output { report: &report, exceeded: exceeded.iter().map(ToString::to_string).collect() };
+            let json = serde_json::to_string_pretty(&output).map_err(|e| MonoError::usage(e.to_string()))?;
+            println!("{}", json);
+        }
+        OutputFormat::Text => {
+            println!("{:>10} {:>10} {:>10}  PATH", "SIZE", "OBJECTS", "LFS");
+            for (prefix, usage) in &report.paths {
+                print_usage(usage, &format!("{}/", prefix));
+            }
+            print_usage(&report.total, "(total)");
+            for exceeded in &exceeded {
+                println!("warning: {}", exceeded);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn print_usage(usage: &Usage, path: &str) {
+    println!(
+        "{:>10} {:>10} {:>10}  {}",
+        format_bytes(usage.bytes()),
+        usage.objects,
+        format_bytes(usage.lfs_bytes),
+        path
+    );
+}
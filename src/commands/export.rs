@@ -0,0 +1,49 @@
+//! `mono export` 命令：不检出工作区，把修订的树快照导出为 tar 包、zip 包或目录
+
+use std::path::PathBuf;
+
+use clap::Args;
+use clap_complete::ArgValueCompleter;
+
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::completion::{complete_paths, complete_refs};
+use crate::export::{export_dir, write_archive, ExportFormat, Snapshot};
+use crate::repo::Repository;
+
+/// `mono export` 的参数
+#[derive(Args, Debug)]
+pub struct ExportArgs {
+    /// 导出的修订
+    #[arg(add = ArgValueCompleter::new(complete_refs))]
+    pub rev: String,
+    /// 只导出该子目录，例如 `//services/api`
+    #[arg(long, add = ArgValueCompleter::new(complete_paths))]
+    pub path: Option<String>,
+    /// 导出格式
+    #[arg(long, value_enum, default_value_t = ExportFormat::Tar)]
+    pub format: ExportFormat,
+    /// 输出文件，省略时写到标准输出；目录格式必须指定，且目录必须不存在或为空
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+/// 执行 `mono export`
+pub fn execute(args: ExportArgs) -> MonoResult<()> {
+    let repo = Repository::discover(&std::env::current_dir()?)?;
+    let snapshot = Snapshot::resolve(&repo, &args.rev, args.path.as_deref())?;
+    let stats = match (args.format, &args.output) {
+        (ExportFormat::Dir, None) => return Err(MonoError::usage("--output is required for directory exports")),
+        (ExportFormat::Dir, Some(dir)) => export_dir(&repo, &snapshot, dir, |_, _| true)?,
+        (format, Some(path)) => {
+            let mut out = std::io::BufWriter::new(std::fs::File::create(path)?);
+            write_archive(&repo, &snapshot, format, &mut out, |_, _| true)?
+        }
+        (format, None) => {
+            let mut out = std::io::BufWriter::new(std::io::stdout().lock());
+            write_archive(&repo, &snapshot, format, &mut out, |_, _| true)?
+        }
+    };
+    tracing::info!(commit = %snapshot.commit, files = stats.files, bytes = stats.bytes, "snapshot exported");
+    Ok(())
+}
@@ -0,0 +1,117 @@
+//! `mono fetch` 命令：从远端获取新的提交并更新远端跟踪分支，支持浅仓库的加深
+
+use clap::Args;
+
+use crate::commands::audit::parse_since;
+use crate::audit;
+use crate::commands::clone::DEFAULT_REMOTE;
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::object::filter::ObjectFilter;
+use crate::object::shallow::{Deepen, INFINITE_DEPTH};
+use crate::refs;
+use crate::repo::Repository;
+use crate::transport;
+
+/// `mono fetch` 的参数
+#[derive(Args, Debug)]
+pub struct FetchArgs {
+    /// 远端名
+    #[arg(default_value = DEFAULT_REMOTE)]
+    pub remote: String,
+
+    /// 把历史限制为每个远端引用最近的这么多个提交
+    #[arg(long, conflicts_with_all = ["deepen", "shallow_since", "unshallow"])]
+    pub depth: Option<u32>,
+
+    /// 在现有的浅提交之下再获取这么多个提交
+    #[arg(long, conflicts_with_all = ["shallow_since", "unshallow"])]
+    pub deepen: Option<u32>,
+
+    /// 把历史加深（或限制）到该时间之后的提交，例如 `2024-01-31`、`30d`
+    #[arg(long, conflicts_with = "unshallow")]
+    pub shallow_since: Option<String>,
+
+    /// 获取全部历史，把浅仓库转换为完整仓库
+    #[arg(long)]
+    pub unshallow: bool,
+}
+
+/// 执行 `mono fetch`
+pub fn execute(args: FetchArgs) -> MonoResult<()> {
+    let mut repo = Repository::discover(&std::env::current_dir()?)?;
+    let remote = repo
+        .config()
+        .remote
+        .get(&args.remote)
+        .cloned()
+        .ok_or_else(|| MonoError::not_found(format!("remote {}", args.remote)))?;
+    let deepen = match (args.depth, args.deepen, args.shallow_since) {
+        (Some(0), _, _) | (_, Some(0), _) => return Err(MonoError::usage("depth must be positive")),
+        (Some(depth), _, _) => Some(Deepen::Depth(depth)),
+        (_, Some(depth), _) => Some(Deepen::Relative(depth)),
+        (_, _, Some(since)) => Some(Deepen::Since(parse_since(&since, chrono::Utc::now())?)),
+        _ if args.unshallow && repo.shallow_commits().is_empty() => {
+            return Err(MonoError::usage("--unshallow on a complete repository does not make sense"));
+        }
+        _ if args.unshallow => Some(Deepen::Depth(INFINITE_DEPTH)),
+        _ => None,
+    };
+    if matches!(deepen, Some(Deepen::Relative(_))) && repo.shallow_commits().is_empty() {
+        return Err(MonoError::usage("--deepen requires a shallow repository"));
+    }
+    let filter: ObjectFilter = match &remote.partial_clone_filter {
+        Some(filter) => filter.parse().map_err(|_| {
+            MonoError::config(format!("invalid partial_clone_filter for remote {}: {}", args.remote, filter))
+        })?,
+        None => ObjectFilter::None,
+    };
+
+    let transport = transport::open(&remote.url)?;
+    let remote_refs = transport.list_refs()?;
+    let mut wants: Vec<_> = remote_refs.refs.iter().map(|(_, id)| *id).collect();
+    wants.sort();
+    wants.dedup();
+    let mut haves = Vec::new();
+    for (_, id) in repo.refs().list("refs/")? {
+        if repo.objects().contains(&id)? {
+            haves.push(id);
+        }
+    }
+
+    let stats = if deepen.is_some() || !repo.shallow_commits().is_empty() {
+        let shallow = repo.shallow_commits().clone();
+        let (stats, update) = transport.fetch_shallow(&wants, &haves, &shallow, deepen, &filter, repo.objects())?;
+        repo.update_shallow(&update)?;
+        stats
+    } else {
+        transport.fetch(&wants, &haves, &filter, repo.objects())?
+    };
+
+    let reason = format!("fetch: {}", args.remote);
+    let logged = repo.with_reflog_identity(&audit::local_actor(), &reason);
+    let store = logged.refs();
+    let mut updated = 0;
+    for (name, id) in &remote_refs.refs {
+        let local = match name.strip_prefix(refs::HEADS_PREFIX) {
+            Some(branch) => format!("refs/remotes/{}/{}", args.remote, branch),
+            None if name.starts_with(refs::TAGS_PREFIX) => name.clone(),
+            None => continue,
+        };
+        if store.resolve(&local)? != Some(*id) {
+            store.write(&local, id)?;
+            updated += 1;
+        }
+    }
+    println!(
+        "Fetched {} objects from {}, updated {} refs{}",
+        stats.objects,
+        args.remote,
+        updated,
+        match repo.shallow_commits().len() {
+            0 => String::new(),
+            n => format!(" ({} shallow commits)", n),
+        }
+    );
+    Ok(())
+}
@@ -0,0 +1,82 @@
+//! `mono filter` 命令：按路径、文件大小、改名与 mailmap 改写全部历史
+
+use std::path::PathBuf;
+
+use clap::Args;
+
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::mailmap::Mailmap;
+use crate::refs;
+use crate::repo::Repository;
+use crate::rewrite::filter::{filter, FilterOptions};
+
+/// `mono filter` 的参数
+#[derive(Args, Debug)]
+pub struct FilterArgs {
+    /// 只保留该路径，可以多次指定
+    #[arg(long)]
+    pub path: Vec<String>,
+    /// 删除该路径，可以多次指定
+    #[arg(long)]
+    pub exclude: Vec<String>,
+    /// 删除超过该字节数的文件
+    #[arg(long, value_name = "BYTES")]
+    pub strip_blobs_bigger_than: Option<u64>,
+    /// 把路径 OLD 移动到 NEW，格式为 `OLD:NEW`，可以多次指定
+    #[arg(long, value_name = "OLD:NEW")]
+    pub rename: Vec<String>,
+    /// 按该 mailmap 文件改写作者与提交者
+    #[arg(long)]
+    pub mailmap: Option<PathBuf>,
+    /// 改写的引用前缀，可以多次指定，默认为全部分支与标签
+    #[arg(long = "ref", value_name = "PREFIX")]
+    pub refs: Vec<String>,
+    /// 并行改写的线程数，默认按 CPU 数
+    #[arg(long, default_value_t = 0)]
+    pub jobs: usize,
+}
+
+/// 执行 `mono filter`
+pub fn execute(args: FilterArgs) -> MonoResult<()> {
+    let repo = Repository::discover(&std::env::current_dir()?)?;
+    let renames = args
+        .rename
+        .iter()
+        .map(|rename| {
+            rename
+                .split_once(':')
+                .map(|(from, to)| (from.to_string(), to.to_string()))
+                .ok_or_else(|| MonoError::usage(format!("invalid --rename {}, expected OLD:NEW", rename)))
+        })
+        .collect::<MonoResult<Vec<_>>>()?;
+    let mailmap = match &args.mailmap {
+        Some(path) => Some(Mailmap::parse(&std::fs::read_to_string(path)?)?),
+        None => None,
+    };
+    let options = FilterOptions {
+        paths: args.path,
+        exclude: args.exclude,
+        max_blob_size: args.strip_blobs_bigger_than,
+        renames,
+        mailmap,
+        refs: args.refs,
+        jobs: args.jobs,
+    };
+    let report = filter(&repo, options)?;
+    println!(
+        "Rewrote {} commits, pruned {} empty commits, stripped {} blobs",
+        report.commits, report.pruned, report.stripped_blobs
+    );
+    for update in &report.updates {
+        let name = refs::short_name(&update.name);
+        match update.new.is_zero() {
+            true => println!("    deleted {}", name),
+            false => println!("    {} {} -> {}", name, &update.old.to_hex()[..12], &update.new.to_hex()[..12]),
+        }
+    }
+    if !report.updates.is_empty() {
+        println!("Old objects are removed by `mono gc` once the reflog expires");
+    }
+    Ok(())
+}
@@ -0,0 +1,64 @@
+//! `mono fsck` 命令：检查对象、引用、pack 与 LFS 指针的完整性
+
+use clap::Args;
+
+use crate::commands::OutputFormat;
+use crate::common::errors::{ExitCode, MonoError, MonoErrorKind};
+use crate::common::MonoResult;
+use crate::fsck::{fsck, Severity};
+use crate::repo::Repository;
+
+/// `mono fsck` 的参数
+#[derive(Args, Debug)]
+pub struct FsckArgs {
+    /// 输出格式
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+    /// 发现错误时的退出码
+    #[arg(long, default_value_t = ExitCode::Storage.code())]
+    pub error_exit_code: i32,
+    /// 只发现警告时的退出码，默认视为成功
+    #[arg(long, default_value_t = ExitCode::Success.code())]
+    pub warning_exit_code: i32,
+}
+
+/// 执行 `mono fsck`
+pub fn execute(args: FsckArgs) -> MonoResult<()> {
+    let repo = Repository::discover(&std::env::current_dir()?)?;
+    let report = fsck(&repo)?;
+
+    match args.format {
+        OutputFormat::Json => {
+            let json = serde_json::to_string_pretty(&report).map_err(|e| MonoError::usage(e.to_string()))?;
+            println!("{}", json);
+        }
+        OutputFormat::Text => {
+            for issue in &report.issues {
+                println!("{}", issue);
+            }
+            println!(
+                "checked {} objects, {} packs and {} LFS pointers: {} errors, {} warnings",
+                report.objects,
+                report.packs,
+                report.lfs_pointers,
+                report.count(Severity::Error),
+                report.count(Severity::Warning)
+            );
+        }
+    }
+
+    let code = match report.max_severity() {
+        Some(Severity::Error) => args.error_exit_code,
+        Some(Severity::Warning) => args.warning_exit_code,
+        None => ExitCode::Success.code(),
+    };
+    if code == ExitCode::Success.code() {
+        return Ok(());
+    }
+    let message = format!(
+        "fsck found {} errors and {} warnings",
+        report.count(Severity::Error),
+        report.count(Severity::Warning)
+    );
+    Err(MonoError::from_kind(MonoErrorKind::Storage(message), code))
+}
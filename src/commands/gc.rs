@@ -0,0 +1,53 @@
+//! `mono gc` 命令：删除不可达且超过宽限期的对象
+
+use std::time::Duration;
+
+use clap::Args;
+
+use crate::common::MonoResult;
+use crate::gc::{self, GcOptions};
+use crate::repo::Repository;
+
+/// `mono gc` 的参数，未指定的参数使用 `[gc]` 配置段
+#[derive(Args, Debug)]
+pub struct GcArgs {
+    /// 只列出将要删除的对象并估算释放的空间，不实际删除
+    #[arg(long)]
+    pub dry_run: bool,
+    /// 不可达对象至少保留的天数
+    #[arg(long, value_name = "DAYS")]
+    pub grace_days: Option<u64>,
+    /// 引用日志的保留天数，期间引用曾经指向的提交视为可达
+    #[arg(long, value_name = "DAYS")]
+    pub reflog_expire_days: Option<u64>,
+}
+
+/// 执行 `mono gc`
+pub fn execute(args: GcArgs) -> MonoResult<()> {
+    let repo = Repository::discover(&std::env::current_dir()?)?;
+    let mut options = GcOptions::from_config(&repo.config().gc);
+    options.dry_run = args.dry_run;
+    if let Some(days) = args.grace_days {
+        options.grace = Duration::from_secs(days * 24 * 60 * 60);
+    }
+    if let Some(days) = args.reflog_expire_days {
+        options.reflog_expire = Duration::from_secs(days * 24 * 60 * 60);
+    }
+    let report = gc::gc(&repo, &options)?;
+    let verb = if options.dry_run { "Would delete" } else { "Deleted" };
+    for object in &report.deleted {
+        println!("{} {} ({} bytes)", verb, object.id, object.size);
+    }
+    println!(
+        "{} {} unreachable objects ({} bytes); kept {} reachable and {} within the grace period",
+        verb,
+        report.deleted.len(),
+        report.deleted_size(),
+        report.reachable,
+        report.retained.len()
+    );
+    if report.reflog_expired > 0 {
+        println!("Expired {} reflog entries", report.reflog_expired);
+    }
+    Ok(())
+}
@@ -0,0 +1,49 @@
+//! `mono impacted` 命令：列出一段修订范围影响的项目及其全部反向依赖
+
+use clap::Args;
+
+use crate::changed::deps::impacted_since;
+use crate::commands::OutputFormat;
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::repo::Repository;
+
+/// `mono impacted` 的参数
+#[derive(Args, Debug)]
+pub struct ImpactedArgs {
+    /// 比较的起点，通常是目标分支
+    #[arg(long)]
+    pub since: String,
+    /// 比较的终点
+    #[arg(long, default_value = "HEAD")]
+    pub until: String,
+    /// 输出格式
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+}
+
+/// 执行 `mono impacted`
+pub fn execute(args: ImpactedArgs) -> MonoResult<()> {
+    let repo = Repository::discover(&std::env::current_dir()?)?;
+    let since = repo.resolve_rev(&args.since)?;
+    let until = repo.resolve_rev(&args.until)?;
+    let impact = impacted_since(&repo, &since, &until)?;
+
+    match args.format {
+        OutputFormat::Json => {
+            let json = serde_json::to_string_pretty(&impact).map_err(|e| MonoError::usage(e.to_string()))?;
+            println!("{}", json);
+        }
+        OutputFormat::Text => {
+            for project in &impact.impacted {
+                if project.via.is_empty() {
+                    println!("//{}", project.path);
+                } else {
+                    let via: Vec<String> = project.via.iter().map(|p| format!("//{}", p)).collect();
+                    println!("//{} (via {})", project.path, via.join(" -> "));
+                }
+            }
+        }
+    }
+    Ok(())
+}
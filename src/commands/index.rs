@@ -0,0 +1,91 @@
+//! `mono index` 命令：查看与重写暂存区
+
+use clap::{Args, Subcommand};
+use clap_complete::ArgValueCompleter;
+use serde_json::json;
+
+use crate::commands::OutputFormat;
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::completion::complete_refs;
+use crate::index::Index;
+use crate::repo::Repository;
+
+/// `mono index` 的参数
+#[derive(Args, Debug)]
+pub struct IndexArgs {
+    #[command(subcommand)]
+    pub command: IndexCommand,
+}
+
+/// `mono index` 的子命令
+#[derive(Subcommand, Debug)]
+pub enum IndexCommand {
+    /// 列出暂存区的条目
+    Ls(LsArgs),
+    /// 把暂存区替换为修订的树
+    ReadTree(ReadTreeArgs),
+    /// 按当前的 `[index]` 配置重写暂存区，例如开启或关闭拆分索引之后
+    Rewrite,
+}
+
+/// `mono index ls` 的参数
+#[derive(Args, Debug)]
+pub struct LsArgs {
+    /// 输出格式
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+}
+
+/// `mono index read-tree` 的参数
+#[derive(Args, Debug)]
+pub struct ReadTreeArgs {
+    /// 修订，默认为 HEAD
+    #[arg(default_value = "HEAD", add = ArgValueCompleter::new(complete_refs))]
+    pub rev: String,
+}
+
+/// 执行 `mono index`
+pub fn execute(args: IndexArgs) -> MonoResult<()> {
+    let repo = Repository::discover(&std::env::current_dir()?)?;
+    let mut index = Index::load(&repo)?;
+    match args.command {
+        IndexCommand::Ls(args) => match args.format {
+            OutputFormat::Json => {
+                let entries: Vec<_> = index
+                    .entries()
+                    .iter()
+                    .map(|entry| {
+                        json!({
+                            "path": entry.path,
+                            "stage": entry.stage,
+                            "mode": format!("{:06o}", entry.mode.0),
+                            "id": entry.id.to_hex(),
+                            "skip_worktree": entry.skip_worktree,
+                        })
+                    })
+                    .collect();
+                let json = serde_json::to_string_pretty(&entries).map_err(|e| MonoError::usage(e.to_string()))?;
+                println!("{}", json);
+            }
+            OutputFormat::Text => {
+                for entry in index.entries() {
+                    println!("{:06o} {} {}\t{}", entry.mode.0, entry.id, entry.stage, entry.path);
+                }
+            }
+        },
+        IndexCommand::ReadTree(args) => {
+            let commit = repo.resolve_rev(&args.rev)?;
+            let tree = repo.read_commit(&commit)?.tree;
+            index.read_tree(&repo, &tree)?;
+            index.save(&repo)?;
+            println!("{} entries from {}", index.entries().len(), commit);
+        }
+        IndexCommand::Rewrite => {
+            index.save(&repo)?;
+            let config = &repo.config().index;
+            println!("index rewritten (version {}, split {})", config.version, config.split);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,94 @@
+//! `mono init` 命令：在目标目录创建 MonoEngine 仓库布局
+
+use std::path::PathBuf;
+
+use clap::Args;
+
+use crate::common::config::{PgConfig, S3Config, StorageBackend, StorageConfig};
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::object::ObjectFormat;
+use crate::repo::{InitOptions, Repository};
+
+/// `mono init` 的参数
+#[derive(Args, Debug)]
+pub struct InitArgs {
+    /// 目标目录，不存在时自动创建
+    #[arg(default_value = ".")]
+    pub directory: PathBuf,
+
+    /// 对象存储后端
+    #[arg(long, value_enum, default_value_t = StorageBackend::Fs)]
+    pub storage: StorageBackend,
+
+    /// S3 存储桶，`--storage s3` 时必填
+    #[arg(long, value_name = "BUCKET")]
+    pub s3_bucket: Option<String>,
+
+    /// S3 对象键的公共前缀
+    #[arg(long, value_name = "PREFIX", requires = "s3_bucket")]
+    pub s3_prefix: Option<String>,
+
+    /// S3 区域
+    #[arg(long, value_name = "REGION", requires = "s3_bucket")]
+    pub s3_region: Option<String>,
+
+    /// S3 兼容服务的端点，例如 `http://127.0.0.1:9000`
+    #[arg(long, value_name = "URL", requires = "s3_bucket")]
+    pub s3_endpoint: Option<String>,
+
+    /// 将引用与提交图元数据保存在 PostgreSQL 中，例如 `postgres://mono@localhost/mono`
+    #[arg(long, value_name = "URL")]
+    pub pg_url: Option<String>,
+
+    /// 仓库在 PostgreSQL 中的名称
+    #[arg(long, value_name = "NAME", requires = "pg_url")]
+    pub pg_repository: Option<String>,
+
+    /// 初始分支名
+    #[arg(long, short = 'b', default_value = "main")]
+    pub initial_branch: String,
+
+    /// 对象格式：`sha1` 或 `sha256`
+    #[arg(long, value_name = "FORMAT", default_value = "sha1")]
+    pub object_format: ObjectFormat,
+
+    /// 兼容的对象格式，只支持该格式的客户端也能获取仓库中的对象
+    #[arg(long, value_name = "FORMAT")]
+    pub compat_object_format: Option<ObjectFormat>,
+}
+
+/// 执行 `mono init`
+pub fn execute(args: InitArgs) -> MonoResult<()> {
+    std::fs::create_dir_all(&args.directory)?;
+    let s3 = match (args.storage, args.s3_bucket) {
+        (StorageBackend::S3, Some(bucket)) => {
+            let mut s3 = S3Config::new(bucket);
+            s3.prefix = args.s3_prefix.unwrap_or_default();
+            s3.region = args.s3_region.unwrap_or(s3.region);
+            s3.endpoint = args.s3_endpoint;
+            Some(s3)
+        }
+        (StorageBackend::S3, None) => return Err(MonoError::usage("--storage s3 requires --s3-bucket")),
+        (_, Some(_)) => return Err(MonoError::usage("--s3-bucket requires --storage s3")),
+        (_, None) => None,
+    };
+    let options = InitOptions {
+        storage: StorageConfig {
+            backend: args.storage,
+            s3,
+            pg: args.pg_url.map(|url| {
+                let mut pg = PgConfig::new(url);
+                pg.repository = args.pg_repository.unwrap_or(pg.repository);
+                pg
+            }),
+            ..Default::default()
+        },
+        initial_branch: args.initial_branch,
+        object_format: args.object_format,
+        compat_object_format: args.compat_object_format,
+    };
+    let repo = Repository::init(&args.directory, &options)?;
+    println!("Initialized empty mono workspace in {}", repo.mono_dir().display());
+    Ok(())
+}
@@ -0,0 +1,79 @@
+//! `mono keys` 命令：管理允许通过 SSH 访问仓库的公钥
+
+use std::io::Read;
+use std::path::PathBuf;
+
+use clap::{Args, Subcommand};
+use russh::keys::PublicKey;
+
+use crate::audit::{self, AuditAction, AuditEvent, AuditLog};
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::repo::Repository;
+use crate::server::keys::AuthorizedKeys;
+
+/// `mono keys` 的参数
+#[derive(Args, Debug)]
+pub struct KeysArgs {
+    #[command(subcommand)]
+    pub command: KeysCommand,
+}
+
+/// `mono keys` 的子命令
+#[derive(Subcommand, Debug)]
+pub enum KeysCommand {
+    /// 添加公钥
+    Add {
+        /// 密钥名，用于日志与删除
+        name: String,
+        /// OpenSSH 格式的公钥文件，`-` 表示从标准输入读取
+        file: PathBuf,
+    },
+    /// 列出所有公钥及其指纹
+    List,
+    /// 按名称删除公钥
+    Remove {
+        name: String,
+    },
+}
+
+/// 执行 `mono keys`
+pub fn execute(args: KeysArgs) -> MonoResult<()> {
+    let repo = Repository::discover(&std::env::current_dir()?)?;
+    let mut keys = AuthorizedKeys::load(&repo)?;
+    let now = chrono::Utc::now().timestamp();
+    match args.command {
+        KeysCommand::Add { name, file } => {
+            let content = if file.as_os_str() == "-" {
+                let mut content = String::new();
+                std::io::stdin().read_to_string(&mut content)?;
+                content
+            } else {
+                std::fs::read_to_string(&file).map_err(|e| MonoError::from(e).context(file.display().to_string()))?
+            };
+            let key = PublicKey::from_openssh(content.trim())
+                .map_err(|e| MonoError::usage(format!("invalid public key: {}", e)))?;
+            keys.add(&name, key)?;
+            keys.save(&repo)?;
+            let added = keys.iter().last().expect("key was just added");
+            let event = AuditEvent::new(now, audit::local_actor(), AuditAction::KeyAdd, &added.name).with_detail(added.fingerprint());
+            AuditLog::new(&repo).record(&event);
+            println!("Added key {} ({})", added.name, added.fingerprint());
+        }
+        KeysCommand::List => {
+            for key in keys.iter() {
+                println!("{}\t{}\t{}", key.name, key.key.algorithm(), key.fingerprint());
+            }
+        }
+        KeysCommand::Remove { name } => {
+            let removed = keys
+                .remove(&name)
+                .ok_or_else(|| MonoError::not_found(format!("key {}", name)))?;
+            keys.save(&repo)?;
+            let event = AuditEvent::new(now, audit::local_actor(), AuditAction::KeyRemove, &removed.name).with_detail(removed.fingerprint());
+            AuditLog::new(&repo).record(&event);
+            println!("Removed key {} ({})", removed.name, removed.fingerprint());
+        }
+    }
+    Ok(())
+}
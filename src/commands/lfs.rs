@@ -0,0 +1,68 @@
+//! `mono lfs` 命令：管理服务端保存的 Git LFS 对象
+
+use std::time::Duration;
+
+use clap::{Args, Subcommand};
+
+use crate::common::MonoResult;
+use crate::lfs::gc::{self, GcOptions};
+use crate::repo::Repository;
+
+/// `mono lfs` 的参数
+#[derive(Args, Debug)]
+pub struct LfsArgs {
+    #[command(subcommand)]
+    pub command: LfsCommand,
+}
+
+/// `mono lfs` 的子命令
+#[derive(Subcommand, Debug)]
+pub enum LfsCommand {
+    /// 删除不再被引用且超过保留期的 LFS 对象
+    Gc(GcArgs),
+}
+
+/// `mono lfs gc` 的参数，未指定的参数使用 `[lfs]` 配置段
+#[derive(Args, Debug)]
+pub struct GcArgs {
+    /// 只列出将要删除的对象，不实际删除
+    #[arg(long)]
+    pub dry_run: bool,
+    /// 未被引用的对象至少保留的天数
+    #[arg(long, value_name = "DAYS")]
+    pub retention_days: Option<u64>,
+    /// 扫描的引用前缀，可重复指定
+    #[arg(long = "ref", value_name = "PREFIX")]
+    pub refs: Vec<String>,
+}
+
+/// 执行 `mono lfs`
+pub fn execute(args: LfsArgs) -> MonoResult<()> {
+    let repo = Repository::discover(&std::env::current_dir()?)?;
+    match args.command {
+        LfsCommand::Gc(args) => {
+            let mut options = GcOptions::from_config(&repo.config().lfs);
+            options.dry_run = args.dry_run;
+            if let Some(days) = args.retention_days {
+                options.retention = Duration::from_secs(days * 24 * 60 * 60);
+            }
+            if !args.refs.is_empty() {
+                options.refs = args.refs;
+            }
+            let report = gc::gc(&repo, &options)?;
+            let verb = if options.dry_run { "Would delete" } else { "Deleted" };
+            for object in &report.deleted {
+                println!("{} {} ({} bytes)", verb, object.oid, object.size);
+            }
+            println!(
+                "{} {} unreferenced objects ({} bytes); kept {} referenced and {} within the retention period",
+                verb,
+                report.deleted.len(),
+                report.deleted_size(),
+                report.referenced,
+                report.retained.len()
+            );
+        }
+    }
+    Ok(())
+}
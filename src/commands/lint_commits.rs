@@ -0,0 +1,61 @@
+//! `mono lint-commits` 命令：按 `[commit_lint]` 规则检查一段修订范围中的提交信息
+
+use clap::Args;
+
+use crate::commands::OutputFormat;
+use crate::commit_lint::CommitLint;
+use crate::common::errors::{ExitCode, MonoError, MonoErrorKind};
+use crate::common::MonoResult;
+use crate::refs;
+use crate::repo::Repository;
+
+/// `mono lint-commits` 的参数
+#[derive(Args, Debug)]
+pub struct LintCommitsArgs {
+    /// `<旧>..<新>` 检查新提交中不在旧提交历史里的提交，单个修订只检查该提交
+    #[arg(default_value = "HEAD")]
+    pub range: String,
+    /// 输出格式
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+}
+
+/// 执行 `mono lint-commits`：有提交违反规则时以 1 退出
+pub fn execute(args: LintCommitsArgs) -> MonoResult<()> {
+    let repo = Repository::discover(&std::env::current_dir()?)?;
+    let Some(lint) = CommitLint::from_config(&repo.config().commit_lint)? else {
+        return Err(MonoError::config("no commit message rules configured in [commit_lint]"));
+    };
+    let (tips, exclude) = match args.range.split_once("..") {
+        Some((old, new)) => {
+            let old = if old.is_empty() { refs::HEAD } else { old };
+            let new = if new.is_empty() { refs::HEAD } else { new };
+            (vec![repo.resolve_rev(new)?], vec![repo.resolve_rev(old)?])
+        }
+        None => {
+            let id = repo.resolve_rev(&args.range)?;
+            (vec![id], repo.read_commit(&id)?.parents)
+        }
+    };
+    let problems = lint.lint_range(&repo, &tips, &exclude)?;
+
+    match args.format {
+        OutputFormat::Json => {
+            let json = serde_json::to_string_pretty(&problems).map_err(|e| MonoError::usage(e.to_string()))?;
+            println!("{}", json);
+        }
+        OutputFormat::Text => {
+            for commit in &problems {
+                println!("{} {}", &commit.commit.to_hex()[..7], commit.summary);
+                for problem in &commit.problems {
+                    println!("    {}", problem);
+                }
+            }
+        }
+    }
+    if problems.is_empty() {
+        return Ok(());
+    }
+    let message = format!("{} commits violate the commit message rules", problems.len());
+    Err(MonoError::from_kind(MonoErrorKind::Usage(message), ExitCode::Failure.code()))
+}
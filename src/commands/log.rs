@@ -0,0 +1,89 @@
+//! `mono log` 命令：按提交时间从新到旧显示提交历史
+
+use chrono::{DateTime, FixedOffset};
+use clap::Args;
+use clap_complete::ArgValueCompleter;
+
+use crate::common::MonoResult;
+use crate::completion::complete_refs;
+use crate::graph::history::History;
+use crate::object::commit::Signature;
+use crate::refs;
+use crate::repo::Repository;
+
+/// `mono log` 的参数
+#[derive(Args, Debug)]
+pub struct LogArgs {
+    /// 起始修订，默认为 HEAD
+    #[arg(add = ArgValueCompleter::new(complete_refs))]
+    pub revisions: Vec<String>,
+    /// 至多显示的提交数
+    #[arg(short = 'n', long)]
+    pub max_count: Option<usize>,
+    /// 每个提交只显示一行：缩写 ID 与标题
+    #[arg(long)]
+    pub oneline: bool,
+}
+
+/// 执行 `mono log`
+pub fn execute(args: LogArgs) -> MonoResult<()> {
+    let repo = Repository::discover(&std::env::current_dir()?)?;
+    let revisions = if args.revisions.is_empty() {
+        vec![refs::HEAD.to_string()]
+    } else {
+        args.revisions
+    };
+    let tips = revisions
+        .iter()
+        .map(|rev| repo.resolve_rev(rev))
+        .collect::<MonoResult<Vec<_>>>()?;
+
+    // 遍历只读取提交图，输出时才解析需要显示的提交对象
+    let history = History::new(&repo)?;
+    for (i, entry) in history.log(&tips, args.max_count)?.iter().enumerate() {
+        let commit = repo.read_commit(&entry.id)?;
+        if args.oneline {
+            println!("{} {}", &entry.id.to_hex()[..7], commit.summary());
+            continue;
+        }
+        if i > 0 {
+            println!();
+        }
+        println!("commit {}", entry.id);
+        if commit.parents.len() > 1 {
+            let parents: Vec<String> = commit.parents.iter().map(|p| p.to_hex()[..7].to_string()).collect();
+            println!("Merge: {}", parents.join(" "));
+        }
+        println!("Author: {} <{}>", commit.author.name, commit.author.email);
+        println!("Date:   {}", format_date(&commit.author));
+        println!();
+        for line in commit.message.trim_end().lines() {
+            println!("    {}", line);
+        }
+    }
+    Ok(())
+}
+
+/// 按签名中的时区格式化时间，与 git 的默认格式一致，例如 `Tue Nov 14 22:13:20 2023 +0000`
+fn format_date(sig: &Signature) -> String {
+    let offset = parse_timezone(&sig.timezone).unwrap_or_else(|| FixedOffset::east_opt(0).unwrap());
+    match DateTime::from_timestamp(sig.timestamp, 0) {
+        Some(time) => time.with_timezone(&offset).format("%a %b %-d %H:%M:%S %Y %z").to_string(),
+        None => format!("{} {}", sig.timestamp, sig.timezone),
+    }
+}
+
+/// 解析 `+0800` 形式的时区偏移
+pub(crate) fn parse_timezone(timezone: &str) -> Option<FixedOffset> {
+    let (sign, digits) = match timezone.split_at_checked(1)? {
+        ("+", digits) => (1, digits),
+        ("-", digits) => (-1, digits),
+        _ => return None,
+    };
+    if digits.len() != 4 || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let hours: i32 = digits[..2].parse().ok()?;
+    let minutes: i32 = digits[2..].parse().ok()?;
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+}
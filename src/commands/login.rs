@@ -0,0 +1,68 @@
+//! `mono login` 命令：通过外部 OIDC 身份提供方登录
+
+use clap::Args;
+
+use crate::auth::keychain::Keychain;
+use crate::auth::oidc::OidcClient;
+use crate::common::config::OidcProviderConfig;
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::repo::Repository;
+
+/// 选择身份提供方的参数，`mono login`、`mono logout` 与 `mono credential` 共用
+#[derive(Args, Debug)]
+pub struct ProviderArgs {
+    /// `[auth.providers]` 中的提供方名称，仓库只配置了一个提供方时可以省略
+    #[arg(long)]
+    pub provider: Option<String>,
+    /// 直接指定签发者 URL，用于不在仓库中的场合（如 git 凭据助手）
+    #[arg(long, requires = "client_id", conflicts_with = "provider")]
+    pub issuer: Option<String>,
+    /// 与 `--issuer` 一起使用的客户端 ID
+    #[arg(long, requires = "issuer")]
+    pub client_id: Option<String>,
+}
+
+impl ProviderArgs {
+    /// 提供方的名称与客户端
+    pub fn client(&self) -> MonoResult<(String, OidcClient)> {
+        if let (Some(issuer), Some(client_id)) = (&self.issuer, &self.client_id) {
+            return Ok((issuer.clone(), OidcClient::new(OidcProviderConfig::new(issuer, client_id))));
+        }
+        let repo = Repository::discover(&std::env::current_dir()?)?;
+        let providers = &repo.config().auth.providers;
+        let (name, config) = match &self.provider {
+            Some(name) => providers
+                .get_key_value(name)
+                .ok_or_else(|| MonoError::usage(format!("unknown identity provider {}", name)))?,
+            None if providers.len() == 1 => providers.iter().next().unwrap(),
+            None if providers.is_empty() => return Err(MonoError::usage("no identity provider is configured in [auth.providers]")),
+            None => return Err(MonoError::usage("several identity providers are configured, choose one with --provider")),
+        };
+        Ok((name.clone(), OidcClient::new(config.clone())))
+    }
+}
+
+/// `mono login` 的参数
+#[derive(Args, Debug)]
+pub struct LoginArgs {
+    #[command(flatten)]
+    pub provider: ProviderArgs,
+}
+
+/// 执行 `mono login`
+pub fn execute(args: LoginArgs) -> MonoResult<()> {
+    let (name, client) = args.provider.client()?;
+    let session = client.login(&Keychain::default(), |device| {
+        match &device.verification_uri_complete {
+            Some(uri) => eprintln!("Open {} to log in (code {})", uri, device.user_code),
+            None => eprintln!("Open {} and enter the code {}", device.verification_uri, device.user_code),
+        }
+        eprintln!("Waiting for the login to complete...");
+    })?;
+    match session.identity(&client.config().user_claim) {
+        Some(identity) => println!("Logged in to {} as {}", name, identity),
+        None => println!("Logged in to {}", name),
+    }
+    Ok(())
+}
@@ -0,0 +1,25 @@
+//! `mono logout` 命令：删除钥匙串中保存的登录会话
+
+use clap::Args;
+
+use crate::auth::keychain::Keychain;
+use crate::commands::login::ProviderArgs;
+use crate::common::MonoResult;
+
+/// `mono logout` 的参数
+#[derive(Args, Debug)]
+pub struct LogoutArgs {
+    #[command(flatten)]
+    pub provider: ProviderArgs,
+}
+
+/// 执行 `mono logout`
+pub fn execute(args: LogoutArgs) -> MonoResult<()> {
+    let (name, client) = args.provider.client()?;
+    if client.logout(&Keychain::default())? {
+        println!("Logged out of {}", name);
+    } else {
+        println!("Not logged in to {}", name);
+    }
+    Ok(())
+}
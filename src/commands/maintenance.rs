@@ -0,0 +1,151 @@
+//! `mono maintenance` 命令：执行、调度与查看维护任务
+
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use clap::{Args, Subcommand};
+
+use crate::commands::OutputFormat;
+use crate::common::errors::{MonoError, MonoErrorKind};
+use crate::common::MonoResult;
+use crate::maintenance::{Maintenance, MaintenanceTask, RunStatus, TaskRun};
+use crate::repo::Repository;
+
+/// 检查到期任务的间隔，计划的精度为分钟
+pub const POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+/// `mono maintenance` 的参数
+#[derive(Args, Debug)]
+pub struct MaintenanceArgs {
+    #[command(subcommand)]
+    pub command: MaintenanceCommand,
+}
+
+/// `mono maintenance` 的子命令
+#[derive(Subcommand, Debug)]
+pub enum MaintenanceCommand {
+    /// 立即执行维护任务，不论是否到期
+    Run(RunArgs),
+    /// 在前台按计划持续执行到期的任务
+    Start,
+    /// 显示各任务的计划、最近一次执行的结果与下次执行时间
+    Status(StatusArgs),
+}
+
+/// `mono maintenance run` 的参数
+#[derive(Args, Debug)]
+pub struct RunArgs {
+    /// 要执行的任务，可以重复指定；省略时执行所有计划不为 `off` 的任务
+    #[arg(long = "task", value_enum)]
+    pub tasks: Vec<MaintenanceTask>,
+}
+
+/// `mono maintenance status` 的参数
+#[derive(Args, Debug)]
+pub struct StatusArgs {
+    /// 输出格式
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+}
+
+/// 执行 `mono maintenance`
+pub fn execute(args: MaintenanceArgs) -> MonoResult<()> {
+    let repo = Repository::discover(&std::env::current_dir()?)?;
+    let maintenance = Maintenance::new(&repo);
+    match args.command {
+        MaintenanceCommand::Run(args) => {
+            let tasks = if args.tasks.is_empty() {
+                let now = Utc::now();
+                let mut enabled = Vec::new();
+                for task in MaintenanceTask::ALL {
+                    if maintenance.next_run(task, None, now)?.is_some() {
+                        enabled.push(task);
+                    }
+                }
+                enabled
+            } else {
+                args.tasks
+            };
+            let results = maintenance.run(&tasks)?;
+            for (task, run) in &results {
+                println!("{}", format_run(*task, run));
+            }
+            if results.iter().any(|(_, run)| run.status == RunStatus::Failed) {
+                return Err(MonoError::storage("some maintenance tasks failed"));
+            }
+        }
+        MaintenanceCommand::Start => {
+            println!("Running maintenance for {} (Ctrl-C to stop)", repo.root().display());
+            loop {
+                match maintenance.run_due(Utc::now()) {
+                    Ok(results) => {
+                        for (task, run) in &results {
+                            println!("{}", format_run(*task, run));
+                        }
+                    }
+                    // 其他进程（例如 `mono serve --maintenance`）正在执行，下一轮再检查
+                    Err(e) if matches!(e.kind(), MonoErrorKind::Unavailable(_)) => {
+                        tracing::debug!(error = %e, "maintenance is running elsewhere");
+                    }
+                    Err(e) => return Err(e),
+                }
+                std::thread::sleep(POLL_INTERVAL);
+            }
+        }
+        MaintenanceCommand::Status(args) => {
+            let runs = maintenance.last_runs()?;
+            let now = Utc::now();
+            let mut rows = Vec::new();
+            for task in MaintenanceTask::ALL {
+                let last = runs.get(&task);
+                let next = maintenance.next_run(task, last, now)?;
+                rows.push((task, last, next));
+            }
+            match args.format {
+                OutputFormat::Json => {
+                    let items: Vec<serde_json::Value> = rows
+                        .iter()
+                        .map(|(task, last, next)| {
+                            serde_json::json!({
+                                "task": task,
+                                "schedule": task.schedule(&repo.config().maintenance),
+                                "last_run": last,
+                                "next_run": next.map(|next| next.timestamp()),
+                            })
+                        })
+                        .collect();
+                    let json = serde_json::to_string_pretty(&items).map_err(|e| MonoError::usage(e.to_string()))?;
+                    println!("{}", json);
+                }
+                OutputFormat::Text => {
+                    for (task, last, next) in rows {
+                        let last = match last {
+                            Some(run) => format!("{} {}", format_time(run.started_at), run.status),
+                            None => "never".to_string(),
+                        };
+                        let next = next.map_or("off".to_string(), |next| format_time(next.max(now).timestamp()));
+                        println!(
+                            "{:<20} {:<14} last: {:<26} next: {}",
+                            task.as_str(),
+                            task.schedule(&repo.config().maintenance),
+                            last,
+                            next
+                        );
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 一次执行的单行摘要
+fn format_run(task: MaintenanceTask, run: &TaskRun) -> String {
+    format!("{:<20} {:<8} {:>6}ms  {}", task.as_str(), run.status.as_str(), run.duration_ms, run.message)
+}
+
+fn format_time(time: i64) -> String {
+    DateTime::from_timestamp(time, 0)
+        .map(|time| time.format("%Y-%m-%d %H:%M UTC").to_string())
+        .unwrap_or_else(|| time.to_string())
+}
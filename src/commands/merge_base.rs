@@ -0,0 +1,34 @@
+//! `mono merge-base` 命令：查找两个提交的最近公共祖先
+
+use clap::Args;
+
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::graph::history::History;
+use crate::repo::Repository;
+
+/// `mono merge-base` 的参数
+#[derive(Args, Debug)]
+pub struct MergeBaseArgs {
+    pub first: String,
+    pub second: String,
+    /// 输出所有最近公共祖先，而不只是其中一个
+    #[arg(long)]
+    pub all: bool,
+}
+
+/// 执行 `mono merge-base`
+pub fn execute(args: MergeBaseArgs) -> MonoResult<()> {
+    let repo = Repository::discover(&std::env::current_dir()?)?;
+    let first = repo.resolve_rev(&args.first)?;
+    let second = repo.resolve_rev(&args.second)?;
+    let bases = History::new(&repo)?.merge_bases(&first, &second)?;
+    if bases.is_empty() {
+        return Err(MonoError::not_found(format!("merge base of {} and {}", args.first, args.second)));
+    }
+    let count = if args.all { bases.len() } else { 1 };
+    for base in &bases[..count] {
+        println!("{}", base);
+    }
+    Ok(())
+}
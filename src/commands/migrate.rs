@@ -0,0 +1,67 @@
+//! `mono migrate` 命令：把导入的仓库中 git 特有的结构迁移为单仓库的形式
+
+use clap::{Args, Subcommand};
+
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::object::commit::Signature;
+use crate::refs;
+use crate::repo::Repository;
+use crate::sparse;
+use crate::submodules;
+
+/// `mono migrate` 的参数
+#[derive(Args, Debug)]
+pub struct MigrateArgs {
+    #[command(subcommand)]
+    pub command: MigrateCommand,
+}
+
+/// `mono migrate` 的子命令
+#[derive(Subcommand, Debug)]
+pub enum MigrateCommand {
+    /// 把子模块换成带历史的子目录，并登记为 `mono compose` 的组件
+    Submodules(SubmodulesArgs),
+}
+
+/// `mono migrate submodules` 的参数
+#[derive(Args, Debug)]
+pub struct SubmodulesArgs {
+    /// 迁移该分支，默认为当前分支
+    #[arg(long)]
+    pub branch: Option<String>,
+    /// 解析 `../` 等相对子模块地址时使用的上级仓库地址
+    #[arg(long)]
+    pub base_url: Option<String>,
+}
+
+/// 执行 `mono migrate`
+pub fn execute(args: MigrateArgs) -> MonoResult<()> {
+    let mut repo = Repository::discover(&std::env::current_dir()?)?;
+    match args.command {
+        MigrateCommand::Submodules(args) => {
+            let head = repo.refs().head_target()?;
+            let branch = match (&args.branch, &head) {
+                (Some(branch), _) => branch.clone(),
+                (None, Some(target)) if target.starts_with(refs::HEADS_PREFIX) => refs::short_name(target).to_string(),
+                _ => return Err(MonoError::usage("HEAD is not on a branch, use --branch")),
+            };
+            let migration = submodules::migrate(&mut repo, &branch, args.base_url.as_deref(), &Signature::committer_from_env()?)?;
+            for submodule in &migration.submodules {
+                println!(
+                    "//{}: {} at {} ({} commits)",
+                    submodule.path,
+                    submodule.url,
+                    &submodule.commit.to_hex()[..7],
+                    submodule.imported
+                );
+            }
+            if head.as_deref() == Some(format!("{}{}", refs::HEADS_PREFIX, branch).as_str()) {
+                let stats = sparse::apply(&repo)?;
+                tracing::info!(files = stats.checked_out, "checked out worktree");
+            }
+            println!("Migrated {} submodules on {} at {}", migration.submodules.len(), branch, migration.commit);
+        }
+    }
+    Ok(())
+}
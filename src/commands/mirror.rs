@@ -0,0 +1,89 @@
+//! `mono mirror` 命令：向只读镜像复制引用与对象，查看复制延迟
+
+use clap::{Args, Subcommand};
+
+use crate::commands::OutputFormat;
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::replication::{MirrorStatus, Replication};
+use crate::repo::Repository;
+
+/// `mono mirror` 的参数
+#[derive(Args, Debug)]
+pub struct MirrorArgs {
+    #[command(subcommand)]
+    pub command: MirrorCommand,
+}
+
+/// `mono mirror` 的子命令
+#[derive(Subcommand, Debug)]
+pub enum MirrorCommand {
+    /// 显示每个镜像落后的引用数、复制延迟与最近一次复制的结果
+    Status(StatusArgs),
+    /// 立即推送到 `push` 方式的镜像并从上游拉取，未运行 `mono serve` 时可由定时任务调用
+    Sync(SyncArgs),
+}
+
+/// `mono mirror status` 的参数
+#[derive(Args, Debug)]
+pub struct StatusArgs {
+    /// 输出格式
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+}
+
+/// `mono mirror sync` 的参数
+#[derive(Args, Debug)]
+pub struct SyncArgs {
+    /// 只复制该镜像，`upstream` 表示从上游拉取
+    pub name: Option<String>,
+}
+
+/// 执行 `mono mirror`
+pub fn execute(args: MirrorArgs) -> MonoResult<()> {
+    let repo = Repository::discover(&std::env::current_dir()?)?;
+    let replication = Replication::new(&repo);
+    let now = chrono::Utc::now().timestamp();
+    match args.command {
+        MirrorCommand::Status(args) => {
+            let statuses = replication.status(now)?;
+            match args.format {
+                OutputFormat::Json => {
+                    let json = serde_json::to_string_pretty(&statuses).map_err(|e| MonoError::usage(e.to_string()))?;
+                    println!("{}", json);
+                }
+                OutputFormat::Text => statuses.iter().for_each(|status| print_status(status, now)),
+            }
+        }
+        MirrorCommand::Sync(args) => {
+            let results = replication.sync(args.name.as_deref(), now)?;
+            let failed = results.iter().filter(|(_, result)| result.is_err()).count();
+            for (name, result) in &results {
+                match result {
+                    Ok(record) => println!("{}: updated {} refs, {} objects", name, record.refs, record.objects),
+                    Err(e) => println!("{}: {}", name, e),
+                }
+            }
+            if failed > 0 {
+                return Err(MonoError::unavailable(format!("{} of {} mirrors failed to sync", failed, results.len())));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn print_status(status: &MirrorStatus, now: i64) {
+    let behind = status.refs_behind.map_or("?".to_string(), |behind| behind.to_string());
+    let lag = status.lag_secs.map_or("?".to_string(), |lag| format!("{}s", lag));
+    let synced = match status.last.as_ref().and_then(|record| record.synced_at) {
+        Some(synced_at) => format!("{}s ago", (now - synced_at).max(0)),
+        None => "never".to_string(),
+    };
+    println!(
+        "{:<12} {:<4} behind={:<4} lag={:<6} synced {:<10} {}",
+        status.name, status.mode, behind, lag, synced, status.url
+    );
+    if let Some(error) = status.error.as_ref().or(status.last.as_ref().and_then(|record| record.error.as_ref())) {
+        println!("    {}", error);
+    }
+}
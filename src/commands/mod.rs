@@ -0,0 +1,69 @@
+pub mod absorb;
+pub mod admin;
+pub mod audit;
+pub mod big_files;
+pub mod bisect;
+pub mod blame;
+pub mod bundle;
+pub mod changed;
+pub mod changelog;
+pub mod checks;
+pub mod cherry_pick;
+pub mod clone;
+pub mod commit_graph;
+pub mod completions;
+pub mod compose;
+pub mod config;
+pub mod credential;
+pub mod diff;
+pub mod doctor;
+pub mod du;
+pub mod export;
+pub mod fetch;
+pub mod filter;
+pub mod fsck;
+pub mod gc;
+pub mod impacted;
+pub mod index;
+pub mod init;
+pub mod keys;
+pub mod lfs;
+pub mod lint_commits;
+pub mod log;
+pub mod login;
+pub mod logout;
+pub mod maintenance;
+pub mod merge_base;
+pub mod migrate;
+pub mod mirror;
+pub mod mount;
+pub mod multi_pack_index;
+pub mod offload;
+pub mod owners;
+pub mod queue;
+pub mod reflog;
+pub mod refs;
+pub mod release;
+pub mod repack;
+pub mod revert;
+pub mod search;
+pub mod serve;
+pub mod sparse;
+pub mod split;
+pub mod stack;
+pub mod status;
+pub mod symbols;
+pub mod token;
+pub mod tui;
+pub mod version;
+pub mod webhooks;
+
+/// 命令输出格式
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    /// 人类可读的文本格式
+    #[default]
+    Text,
+    /// 便于 CI 脚本解析的 JSON 格式
+    Json,
+}
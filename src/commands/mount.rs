@@ -0,0 +1,54 @@
+//! `mono mount` 命令：以 FUSE 文件系统挂载仓库，文件内容在首次读取时按需加载
+
+use std::path::PathBuf;
+
+use clap::Args;
+use clap_complete::ArgValueCompleter;
+
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::completion::complete_refs;
+use crate::repo::Repository;
+use crate::vfs::Vfs;
+
+/// `mono mount` 的参数
+#[derive(Args, Debug)]
+pub struct MountArgs {
+    /// 挂载点，必须是已存在的目录
+    pub mountpoint: PathBuf,
+
+    /// 挂载的修订
+    #[arg(long, default_value = "HEAD", add = ArgValueCompleter::new(complete_refs))]
+    pub rev: String,
+
+    /// 文件内容缓存上限（MiB）
+    #[arg(long, default_value_t = 256)]
+    pub cache_size: usize,
+}
+
+/// 执行 `mono mount`，前台运行直到文件系统被卸载
+pub fn execute(args: MountArgs) -> MonoResult<()> {
+    if !args.mountpoint.is_dir() {
+        return Err(MonoError::usage(format!(
+            "mount point {} is not a directory",
+            args.mountpoint.display()
+        )));
+    }
+    let repo = Repository::discover(&std::env::current_dir()?)?;
+    let rev = repo.resolve_rev(&args.rev)?;
+    let vfs = Vfs::new(repo, &rev)?.with_cache_capacity(args.cache_size << 20);
+    mount(vfs, &args)
+}
+
+#[cfg(all(feature = "fuse", any(target_os = "linux", target_os = "macos")))]
+fn mount(vfs: Vfs, args: &MountArgs) -> MonoResult<()> {
+    println!("Mounted {} at {} (unmount to exit)", args.rev, args.mountpoint.display());
+    crate::vfs::fuse::mount(vfs, &args.mountpoint)
+}
+
+#[cfg(not(all(feature = "fuse", any(target_os = "linux", target_os = "macos"))))]
+fn mount(_vfs: Vfs, _args: &MountArgs) -> MonoResult<()> {
+    Err(MonoError::usage(
+        "mono was built without FUSE support; rebuild with `--features fuse` on Linux or macOS",
+    ))
+}
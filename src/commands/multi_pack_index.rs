@@ -0,0 +1,43 @@
+//! `mono multi-pack-index` 命令：为本地对象存储中的 pack 生成多包索引
+
+use clap::{Args, Subcommand};
+
+use crate::common::config::StorageBackend;
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::repo::Repository;
+use crate::storage::fs::FsStore;
+
+/// `mono multi-pack-index` 的参数
+#[derive(Args, Debug)]
+pub struct MultiPackIndexArgs {
+    #[command(subcommand)]
+    pub command: MultiPackIndexCommand,
+}
+
+/// `mono multi-pack-index` 的子命令
+#[derive(Subcommand, Debug)]
+pub enum MultiPackIndexCommand {
+    /// 为 pack 目录中的全部 pack 写入多包索引
+    Write,
+}
+
+/// 执行 `mono multi-pack-index`
+pub fn execute(args: MultiPackIndexArgs) -> MonoResult<()> {
+    let repo = Repository::discover(&std::env::current_dir()?)?;
+    if repo.config().storage.backend != StorageBackend::Fs {
+        return Err(MonoError::usage("multi-pack-index requires the fs storage backend"));
+    }
+    let store = FsStore::new(repo.objects_dir()).with_format(repo.object_format());
+    match args.command {
+        MultiPackIndexCommand::Write => match store.write_multi_pack_index()? {
+            Some(midx) => println!(
+                "Wrote multi-pack-index for {} objects in {} packs",
+                midx.len(),
+                midx.pack_names().len()
+            ),
+            None => println!("No packs to index"),
+        },
+    }
+    Ok(())
+}
@@ -0,0 +1,63 @@
+//! `mono offload` 命令：把大 pack 上传到 S3/CDN，供 fetch 以签名地址下载
+
+use clap::{Args, Subcommand};
+
+use crate::commands::OutputFormat;
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::offload::Offload;
+use crate::repo::Repository;
+
+/// `mono offload` 的参数
+#[derive(Args, Debug)]
+pub struct OffloadArgs {
+    #[command(subcommand)]
+    pub command: OffloadCommand,
+}
+
+/// `mono offload` 的子命令
+#[derive(Subcommand, Debug)]
+pub enum OffloadCommand {
+    /// 上传新的大 pack，移除已被 repack 删除的 pack 的登记
+    Sync,
+    /// 列出已上传的 pack
+    Status(StatusArgs),
+}
+
+/// `mono offload status` 的参数
+#[derive(Args, Debug)]
+pub struct StatusArgs {
+    /// 输出格式
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+}
+
+/// 执行 `mono offload`
+pub fn execute(args: OffloadArgs) -> MonoResult<()> {
+    let repo = Repository::discover(&std::env::current_dir()?)?;
+    let offload = Offload::new(&repo).ok_or_else(|| MonoError::config("offload requires an [offload.s3] section"))?;
+    match args.command {
+        OffloadCommand::Sync => {
+            let uploaded = offload.sync(chrono::Utc::now().timestamp())?;
+            for pack in &uploaded {
+                println!("Uploaded {} ({} objects, {} bytes)", pack.key(), pack.objects, pack.size);
+            }
+            println!("{} packs uploaded, {} offloaded in total", uploaded.len(), offload.list()?.len());
+        }
+        OffloadCommand::Status(args) => {
+            let packs = offload.list()?;
+            match args.format {
+                OutputFormat::Json => {
+                    let json = serde_json::to_string_pretty(&packs).map_err(|e| MonoError::usage(e.to_string()))?;
+                    println!("{}", json);
+                }
+                OutputFormat::Text => {
+                    for pack in &packs {
+                        println!("{} objects={:<8} {} bytes", pack.key(), pack.objects, pack.size);
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,69 @@
+//! `mono owners` 命令：查询路径的负责人
+
+use clap::Args;
+use clap_complete::ArgValueCompleter;
+
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::completion::{complete_paths, complete_refs};
+use crate::owners::Owners;
+use crate::repo::Repository;
+
+/// `mono owners` 的参数
+#[derive(Args, Debug)]
+pub struct OwnersArgs {
+    /// 相对于仓库根目录的路径
+    #[arg(add = ArgValueCompleter::new(complete_paths))]
+    pub paths: Vec<String>,
+    /// 读取该修订中的所有权文件
+    #[arg(long, default_value = "HEAD", add = ArgValueCompleter::new(complete_refs))]
+    pub rev: String,
+    /// 同时查询该修订与 `--rev` 之间改动的全部文件
+    #[arg(long, value_name = "BASE")]
+    pub changed: Option<String>,
+    /// 按负责人分组输出
+    #[arg(long)]
+    pub by_owner: bool,
+}
+
+/// 执行 `mono owners`
+pub fn execute(args: OwnersArgs) -> MonoResult<()> {
+    let repo = Repository::discover(&std::env::current_dir()?)?;
+    let commit = repo.resolve_rev(&args.rev)?;
+    let tree = repo.read_commit(&commit)?.tree;
+    let mut paths = args.paths;
+    if let Some(base) = &args.changed {
+        let base_tree = repo.read_commit(&repo.resolve_rev(base)?)?.tree;
+        paths.extend(repo.changed_paths(Some(&base_tree), Some(&tree))?);
+    }
+    if paths.is_empty() {
+        return Err(MonoError::usage("no paths given"));
+    }
+
+    let report = Owners::new(&repo, tree).resolve_paths(&paths)?;
+    if args.by_owner {
+        for (owner, paths) in report.by_owner() {
+            println!("{}", owner);
+            for path in paths {
+                println!("    {}", path);
+            }
+        }
+        let unowned = report.unowned();
+        if !unowned.is_empty() {
+            println!("(unowned)");
+            for path in unowned {
+                println!("    {}", path);
+            }
+        }
+    } else {
+        for ownership in &report.paths {
+            let owners = if ownership.owners.is_empty() {
+                "(unowned)".to_string()
+            } else {
+                ownership.owners.join(" ")
+            };
+            println!("{}\t{}", ownership.path, owners);
+        }
+    }
+    Ok(())
+}
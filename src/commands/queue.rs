@@ -0,0 +1,117 @@
+//! `mono queue` 命令：管理服务端的合并队列
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use clap::{Args, Subcommand};
+use clap_complete::ArgValueCompleter;
+
+use crate::common::MonoResult;
+use crate::completion::complete_refs;
+use crate::object::commit::Signature;
+use crate::object::ObjectId;
+use crate::queue::{CommandValidator, EntryState, MergeQueue, QueueEntry, Validator};
+use crate::refs;
+use crate::repo::Repository;
+
+/// `mono queue` 的参数
+#[derive(Args, Debug)]
+pub struct QueueArgs {
+    #[command(subcommand)]
+    pub command: QueueCommand,
+}
+
+/// `mono queue` 的子命令
+#[derive(Subcommand, Debug)]
+pub enum QueueCommand {
+    /// 将已推送的修订提交到队列
+    Submit(SubmitArgs),
+    /// 显示队列中的条目
+    Status(StatusArgs),
+    /// 取消尚未处理的条目
+    Cancel(CancelArgs),
+    /// 处理队列：变基、校验并合入全部排队的条目
+    Run,
+}
+
+/// `mono queue submit` 的参数
+#[derive(Args, Debug)]
+pub struct SubmitArgs {
+    /// 提交到队列的修订，通常是已推送的分支
+    #[arg(add = ArgValueCompleter::new(complete_refs))]
+    pub rev: String,
+    /// 合入的目标分支
+    #[arg(long, default_value = "main")]
+    pub into: String,
+}
+
+/// `mono queue status` 的参数
+#[derive(Args, Debug)]
+pub struct StatusArgs {
+    /// 同时显示已处理的条目
+    #[arg(long)]
+    pub all: bool,
+}
+
+/// `mono queue cancel` 的参数
+#[derive(Args, Debug)]
+pub struct CancelArgs {
+    pub id: u64,
+}
+
+/// 执行 `mono queue`
+pub fn execute(args: QueueArgs) -> MonoResult<()> {
+    let repo = Repository::discover(&std::env::current_dir()?)?;
+    let queue = MergeQueue::new(&repo);
+    match args.command {
+        QueueCommand::Submit(args) => {
+            let user = std::env::var("USER").unwrap_or_else(|_| "unknown".to_string());
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or_default();
+            let entry = queue.submit(&args.rev, &args.into, &user, now)?;
+            println!("Queued #{} ({}) into {}", entry.id, entry.commit, refs::short_name(&entry.target));
+        }
+        QueueCommand::Status(args) => {
+            for entry in queue.entries()? {
+                if args.all || entry.state == EntryState::Queued {
+                    print_entry(&entry);
+                }
+            }
+        }
+        QueueCommand::Cancel(args) => {
+            let entry = queue.cancel(args.id)?;
+            println!("Cancelled #{}", entry.id);
+        }
+        QueueCommand::Run => {
+            let config = repo.config().queue.clone();
+            let rebase_only = |_: &str, _: &ObjectId, _: &ObjectId| -> MonoResult<Result<(), String>> { Ok(Ok(())) };
+            let command;
+            let validator: &dyn Validator = match &config.validate {
+                Some(validate) => {
+                    command = CommandValidator::new(&repo, validate, Duration::from_secs(config.timeout_secs));
+                    &command
+                }
+                None => &rebase_only,
+            };
+            for entry in queue.run(validator, &config, &Signature::committer_from_env()?)? {
+                print_entry(&entry);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn print_entry(entry: &QueueEntry) {
+    let commit = entry.landed.unwrap_or(entry.commit).to_hex();
+    println!(
+        "#{:<5} {:<9} {} {} <- {}",
+        entry.id,
+        entry.state,
+        &commit[..12],
+        refs::short_name(&entry.target),
+        entry.source
+    );
+    if let Some(reason) = &entry.reason {
+        for line in reason.lines() {
+            println!("        {}", line);
+        }
+    }
+}
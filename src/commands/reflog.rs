@@ -0,0 +1,72 @@
+//! `mono reflog` 命令：显示引用的修改历史
+
+use chrono::DateTime;
+use clap::Args;
+
+use crate::commands::OutputFormat;
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::reflog::{self, ReflogEntry};
+use crate::refs;
+use crate::repo::Repository;
+
+/// `mono reflog` 的参数
+#[derive(Args, Debug)]
+pub struct ReflogArgs {
+    /// 引用名、分支名或标签名，默认为 HEAD 指向的分支
+    #[arg(name = "REF")]
+    pub name: Option<String>,
+    /// 至多显示最近的记录数
+    #[arg(short = 'n', long)]
+    pub limit: Option<usize>,
+    /// 输出格式
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+}
+
+/// 执行 `mono reflog`
+pub fn execute(args: ReflogArgs) -> MonoResult<()> {
+    let repo = Repository::discover(&std::env::current_dir()?)?;
+    let name = match &args.name {
+        Some(name) => reflog::resolve_name(&repo, name)?,
+        None => repo
+            .refs()
+            .head_target()?
+            .ok_or_else(|| MonoError::usage("HEAD is detached; specify a ref"))?,
+    };
+    // 与 git 相同，最新的记录在前，第 i 条即 `<ref>@{i}`
+    let mut entries = repo.reflog(&name)?;
+    entries.reverse();
+    if let Some(limit) = args.limit {
+        entries.truncate(limit);
+    }
+    match args.format {
+        OutputFormat::Json => {
+            let json = serde_json::to_string_pretty(&entries).map_err(|e| MonoError::usage(e.to_string()))?;
+            println!("{}", json);
+        }
+        OutputFormat::Text => {
+            for (index, entry) in entries.iter().enumerate() {
+                println!("{}", format_entry(refs::short_name(&name), index, entry));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn format_entry(name: &str, index: usize, entry: &ReflogEntry) -> String {
+    let time = DateTime::from_timestamp(entry.time, 0)
+        .map(|time| time.format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_else(|| entry.time.to_string());
+    format!(
+        "{} {}@{{{}}}: {}  {}..{}  {} {}",
+        &entry.new.to_hex()[..7],
+        name,
+        index,
+        entry.reason,
+        &entry.old.to_hex()[..7],
+        &entry.new.to_hex()[..7],
+        entry.actor,
+        time
+    )
+}
@@ -0,0 +1,45 @@
+//! `mono ref` 命令：按引用日志恢复引用
+
+use clap::{Args, Subcommand};
+
+use crate::common::MonoResult;
+use crate::reflog;
+use crate::repo::Repository;
+
+/// `mono ref` 的参数
+#[derive(Args, Debug)]
+pub struct RefArgs {
+    #[command(subcommand)]
+    pub command: RefCommand,
+}
+
+/// `mono ref` 的子命令
+#[derive(Subcommand, Debug)]
+pub enum RefCommand {
+    /// 把引用恢复为引用日志中较早的值，包括已被删除的分支
+    Restore(RestoreArgs),
+}
+
+/// `mono ref restore` 的参数
+#[derive(Args, Debug)]
+pub struct RestoreArgs {
+    /// 引用名、分支名或标签名
+    #[arg(name = "REF")]
+    pub name: String,
+    /// 恢复为 `<ref>@{N}` 的值，N 为 `mono reflog` 中的序号，默认为上一个值
+    #[arg(default_value_t = 1)]
+    pub entry: usize,
+}
+
+/// 执行 `mono ref`
+pub fn execute(args: RefArgs) -> MonoResult<()> {
+    let repo = Repository::discover(&std::env::current_dir()?)?;
+    match args.command {
+        RefCommand::Restore(args) => {
+            let name = reflog::resolve_name(&repo, &args.name)?;
+            let update = reflog::restore(&repo, &name, args.entry)?;
+            println!("Restored {} to {} (was {})", name, update.new, update.old);
+        }
+    }
+    Ok(())
+}
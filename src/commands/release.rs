@@ -0,0 +1,122 @@
+//! `mono release` 命令：创建与列出发布
+
+use std::path::PathBuf;
+
+use clap::{Args, Subcommand};
+use clap_complete::ArgValueCompleter;
+
+use crate::changelog::Changelog;
+use crate::commands::OutputFormat;
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::completion::complete_refs;
+use crate::object::commit::Signature;
+use crate::release::{NewRelease, Releases};
+use crate::repo::Repository;
+
+/// `mono release` 的参数
+#[derive(Args, Debug)]
+pub struct ReleaseArgs {
+    #[command(subcommand)]
+    pub command: ReleaseCommand,
+}
+
+/// `mono release` 的子命令
+#[derive(Subcommand, Debug)]
+pub enum ReleaseCommand {
+    /// 在修订上创建不可移动的标签与发布记录，附件写入对象存储
+    Create(CreateArgs),
+    /// 按创建时间从新到旧列出发布
+    List(ListArgs),
+}
+
+/// `mono release create` 的参数
+#[derive(Args, Debug)]
+pub struct CreateArgs {
+    /// 标签名，如 `v1.2.3`
+    pub tag: String,
+    /// 发布的修订
+    #[arg(long, default_value = "HEAD", add = ArgValueCompleter::new(complete_refs))]
+    pub rev: String,
+    /// 发布所属的项目目录，如 `services/api`
+    #[arg(long)]
+    pub project: Option<String>,
+    /// 发布标题，默认为标签名
+    #[arg(long)]
+    pub title: Option<String>,
+    /// 从文件读取发布说明
+    #[arg(long, conflicts_with = "changelog_since")]
+    pub notes_file: Option<PathBuf>,
+    /// 以该修订之后的变更日志作为发布说明，指定了 `--project` 时只包含修改了该目录的提交
+    #[arg(long, add = ArgValueCompleter::new(complete_refs))]
+    pub changelog_since: Option<String>,
+    /// 附件，可以多次指定，附件名为文件名
+    #[arg(long)]
+    pub artifact: Vec<PathBuf>,
+}
+
+/// `mono release list` 的参数
+#[derive(Args, Debug)]
+pub struct ListArgs {
+    /// 只列出该目录或其下项目的发布
+    #[arg(long)]
+    pub project: Option<String>,
+    /// 输出格式
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+}
+
+/// 执行 `mono release`
+pub fn execute(args: ReleaseArgs) -> MonoResult<()> {
+    let repo = Repository::discover(&std::env::current_dir()?)?;
+    let releases = Releases::new(&repo);
+    match args.command {
+        ReleaseCommand::Create(args) => {
+            let notes = match (&args.notes_file, &args.changelog_since) {
+                (Some(path), _) => std::fs::read_to_string(path)?,
+                (None, Some(since)) => {
+                    let tip = repo.peel(&repo.resolve_rev(&args.rev)?)?.0;
+                    let since = repo.peel(&repo.resolve_rev(since)?)?.0;
+                    Changelog::generate(&repo, &[tip], &[since], args.project.as_deref().unwrap_or_default())?.markdown()
+                }
+                (None, None) => String::new(),
+            };
+            let mut artifacts = Vec::new();
+            for path in &args.artifact {
+                let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+                    return Err(MonoError::usage(format!("invalid artifact path: {}", path.display())));
+                };
+                artifacts.push((name.to_string(), std::fs::read(path)?));
+            }
+            let release = NewRelease {
+                tag: args.tag,
+                rev: args.rev,
+                project: args.project,
+                title: args.title,
+                notes,
+                artifacts,
+            };
+            let release = releases.create(release, &Signature::committer_from_env()?)?;
+            println!("Released {} at {}", release.tag, &release.commit.to_hex()[..12]);
+            for artifact in &release.artifacts {
+                println!("    {} ({} bytes)", artifact.name, artifact.size);
+            }
+        }
+        ReleaseCommand::List(args) => {
+            let list = releases.list(args.project.as_deref().unwrap_or_default())?;
+            match args.format {
+                OutputFormat::Json => {
+                    let json = serde_json::to_string_pretty(&list).map_err(|e| MonoError::usage(e.to_string()))?;
+                    println!("{}", json);
+                }
+                OutputFormat::Text => {
+                    for release in &list {
+                        let project = release.project.as_deref().map(|p| format!(" //{}", p)).unwrap_or_default();
+                        println!("{:<20} {}{} {}", release.tag, &release.commit.to_hex()[..12], project, release.title);
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
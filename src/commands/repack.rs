@@ -0,0 +1,78 @@
+//! `mono repack` 命令：合并松散对象与较小的 pack，或按 `[binary_delta]` 整理二进制文件
+
+use clap::Args;
+
+use crate::binary_delta;
+use crate::common::config::StorageBackend;
+use crate::common::errors::MonoError;
+use crate::common::progress::format_bytes;
+use crate::common::MonoResult;
+use crate::graph::bitmap;
+use crate::lock;
+use crate::repo::Repository;
+use crate::storage::fs::FsStore;
+
+/// `mono repack` 的参数
+#[derive(Args, Debug)]
+pub struct RepackArgs {
+    /// 几何重新打包：只合并使 pack 大小不再构成该倍数几何级数的小 pack
+    #[arg(long, value_parser = clap::value_parser!(u32).range(2..))]
+    pub geometric: Option<u32>,
+    /// 不使用 `--geometric` 时合并后保留的最多 pack 数，默认取 `maintenance.max_packs`
+    #[arg(long, conflicts_with = "geometric")]
+    pub max_packs: Option<usize>,
+    /// 不更新可达性位图
+    #[arg(long)]
+    pub no_bitmaps: bool,
+    /// 把 `[[binary_delta.paths]]` 目录下二进制文件的各个版本按路径训练字典并做 delta，写入单独保留的 pack
+    #[arg(long, conflicts_with_all = ["geometric", "max_packs"])]
+    pub binary_deltas: bool,
+}
+
+/// 执行 `mono repack`
+pub fn execute(args: RepackArgs) -> MonoResult<()> {
+    let repo = Repository::discover(&std::env::current_dir()?)?;
+    if repo.config().storage.backend != StorageBackend::Fs {
+        return Err(MonoError::usage("repack requires the fs storage backend"));
+    }
+    let _lock = repo.lock(lock::REPACK_LOCK)?;
+    if args.binary_deltas {
+        let report = binary_delta::optimize(&repo)?;
+        if report.objects == 0 {
+            println!("No binary files to compress");
+            return Ok(());
+        }
+        println!(
+            "Compressed {} versions of {} paths ({} deltas, {} dictionaries): {} -> {}",
+            report.objects,
+            report.paths,
+            report.deltas,
+            report.dictionaries,
+            format_bytes(report.before),
+            format_bytes(report.after)
+        );
+        if !args.no_bitmaps {
+            println!("Wrote {} bitmaps", bitmap::write_bitmaps(&repo)?.len());
+        }
+        return Ok(());
+    }
+    let store = FsStore::new(repo.objects_dir())
+        .with_format(repo.object_format())
+        .with_codec(repo.config().storage.compression);
+    let stats = match args.geometric {
+        Some(factor) => store.repack_geometric(factor)?,
+        None => store.repack_incremental(args.max_packs.unwrap_or(repo.config().maintenance.max_packs))?,
+    };
+    if stats.objects == 0 {
+        println!("Nothing to repack");
+        return Ok(());
+    }
+    println!(
+        "Packed {} loose objects and {} packs into a pack of {} objects",
+        stats.loose, stats.packs, stats.objects
+    );
+    if !args.no_bitmaps {
+        println!("Wrote {} bitmaps", bitmap::write_bitmaps(&repo)?.len());
+    }
+    Ok(())
+}
@@ -0,0 +1,32 @@
+//! `mono revert` 命令：在服务端撤销分支上的提交，不需要检出
+
+use clap::Args;
+use clap_complete::ArgValueCompleter;
+
+use crate::commands::cherry_pick::run;
+use crate::common::MonoResult;
+use crate::completion::complete_refs;
+use crate::rewrite::pick::{PickKind, PickOptions};
+
+/// `mono revert` 的参数
+#[derive(Args, Debug)]
+pub struct RevertArgs {
+    /// 撤销的提交
+    #[arg(add = ArgValueCompleter::new(complete_refs))]
+    pub commit: String,
+    /// 目标分支
+    #[arg(long)]
+    pub onto: String,
+    /// 合并提交以第几个父提交为基准
+    #[arg(short, long)]
+    pub mainline: Option<usize>,
+}
+
+/// 执行 `mono revert`
+pub fn execute(args: RevertArgs) -> MonoResult<()> {
+    let options = PickOptions {
+        mainline: args.mainline,
+        ..PickOptions::new(PickKind::Revert)
+    };
+    run(&args.commit, &args.onto, &options)
+}
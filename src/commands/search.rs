@@ -0,0 +1,72 @@
+//! `mono search` 命令：在建立了索引的分支中全文搜索代码
+
+use clap::Args;
+use clap_complete::ArgValueCompleter;
+
+use crate::commands::OutputFormat;
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::completion::complete_paths;
+use crate::repo::Repository;
+use crate::search::{CodeSearch, SearchQuery};
+use crate::sparse::SparsePattern;
+
+/// `mono search` 的参数
+#[derive(Args, Debug)]
+pub struct SearchArgs {
+    /// 正则表达式
+    pub pattern: String,
+    /// 只搜索该路径模式下的文件，例如 `//services/...`，可重复
+    #[arg(long = "path", add = ArgValueCompleter::new(complete_paths))]
+    pub paths: Vec<String>,
+    /// 按字面匹配，不解释正则表达式
+    #[arg(short = 'F', long)]
+    pub fixed_strings: bool,
+    /// 不区分大小写
+    #[arg(short = 'i', long)]
+    pub ignore_case: bool,
+    /// 至多显示的匹配行数
+    #[arg(short = 'n', long, default_value_t = 100)]
+    pub limit: usize,
+    /// 输出格式
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+}
+
+/// 执行 `mono search`
+pub fn execute(args: SearchArgs) -> MonoResult<()> {
+    let repo = Repository::discover(&std::env::current_dir()?)?;
+    let search = CodeSearch::new(&repo);
+    // 索引通常在推送后已更新，这里补上其他方式移动分支后遗漏的更新
+    search.update()?;
+    let query = SearchQuery {
+        pattern: args.pattern,
+        literal: args.fixed_strings,
+        ignore_case: args.ignore_case,
+        paths: args
+            .paths
+            .iter()
+            .map(|path| path.parse())
+            .collect::<MonoResult<Vec<SparsePattern>>>()?,
+        limit: args.limit,
+    };
+    let matches = search.search(&query)?;
+    match args.format {
+        OutputFormat::Json => {
+            let json = serde_json::to_string_pretty(&matches).map_err(|e| MonoError::usage(e.to_string()))?;
+            println!("{}", json);
+        }
+        OutputFormat::Text => {
+            let multiple = search.branches().len() > 1;
+            for found in &matches {
+                if multiple {
+                    let branch = found.branch.strip_prefix(crate::refs::HEADS_PREFIX).unwrap_or(&found.branch);
+                    println!("{}:{}:{}: {}", branch, found.path, found.line_number, found.line);
+                } else {
+                    println!("{}:{}: {}", found.path, found.line_number, found.line);
+                }
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,275 @@
+//! `mono serve` 命令：通过 smart HTTP 或 SSH 协议向标准 git 客户端提供仓库，并可同时提供 gRPC 接口
+//!
+//! 配置了 webhook 时同时在后台定期发送到期的投递；配置了镜像或上游时在后台定期复制（见 [`crate::replication`]）；
+//! 指定 `--maintenance` 时在后台按计划执行维护任务。
+//!
+//! 收到 SIGHUP 或执行 `mono admin reload` 时重新加载配置，不中断进行中的连接，见 [`crate::server::reload`]。
+//! 收到 SIGTERM 时停止接受连接，等进行中的 upload-pack 与 receive-pack 会话在 `--shutdown-timeout`
+//! 内完成，再等引用事务日志写完后以退出码 143 退出，见 [`crate::server::shutdown`]。
+
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::sync::Arc;
+use std::time::Duration;
+
+use clap::Args;
+
+use crate::common::errors::{ExitCode, MonoError, MonoErrorKind};
+use crate::commands::maintenance;
+use crate::common::MonoResult;
+use crate::maintenance::Maintenance;
+use crate::replication::Replication;
+use crate::repo::Repository;
+use crate::server::reload::{self, SharedRepo};
+use crate::server::{blocking, grpc, http, shutdown, ssh};
+use crate::webhooks::{HttpSender, WebhookQueue};
+
+/// 检查到期 webhook 投递的间隔
+const WEBHOOK_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// `mono serve` 的参数
+#[derive(Args, Debug)]
+pub struct ServeArgs {
+    /// HTTP 监听地址，例如 `:8000` 或 `127.0.0.1:8000`
+    #[arg(long, value_name = "ADDR")]
+    pub http: Option<String>,
+
+    /// SSH 监听地址，例如 `:2222`；公钥通过 `mono keys add` 管理
+    #[arg(long, value_name = "ADDR")]
+    pub ssh: Option<String>,
+
+    /// gRPC 监听地址，服务定义见 `proto/monoengine.proto`
+    #[arg(long, value_name = "ADDR")]
+    pub grpc: Option<String>,
+
+    /// 单个请求体的大小上限（MiB），SSH 上一次推送的数据量同样受此限制
+    #[arg(long, default_value_t = http::DEFAULT_MAX_BODY_SIZE >> 20)]
+    pub max_body_size: usize,
+
+    /// 在后台按 `[maintenance]` 的计划执行维护任务，缓存预热直接作用于服务端的对象缓存
+    #[arg(long)]
+    pub maintenance: bool,
+
+    /// 收到 SIGTERM 后等待进行中的会话完成的最长时间（秒），超时后断开剩余连接
+    #[arg(long, value_name = "SECS", default_value_t = 30)]
+    pub shutdown_timeout: u64,
+}
+
+/// 执行 `mono serve`，前台运行直到出错或收到 SIGTERM
+pub fn execute(args: ServeArgs) -> MonoResult<()> {
+    if args.http.is_none() && args.ssh.is_none() && args.grpc.is_none() {
+        return Err(MonoError::usage(
+            "no listener configured; pass --http <ADDR>, --ssh <ADDR> or --grpc <ADDR>",
+        ));
+    }
+    let http_addr = args.http.as_deref().map(parse_listen_addr).transpose()?;
+    let ssh_addr = args.ssh.as_deref().map(parse_listen_addr).transpose()?;
+    let grpc_addr = args.grpc.as_deref().map(parse_listen_addr).transpose()?;
+    let repo = Arc::new(Repository::discover(&std::env::current_dir()?)?);
+    if let Some(addr) = http_addr {
+        println!("Serving {} over http on {}", repo.root().display(), addr);
+    }
+    if let Some(addr) = ssh_addr {
+        println!("Serving {} over ssh on {}", repo.root().display(), addr);
+    }
+    if let Some(addr) = grpc_addr {
+        println!("Serving {} over grpc on {}", repo.root().display(), addr);
+    }
+
+    let _pid = reload::write_pid_file(repo.mono_dir())?;
+    let shared = SharedRepo::new(repo.clone());
+
+    let runtime = tokio::runtime::Runtime::new()?;
+    runtime.block_on(async {
+        let drain = shutdown::drain();
+        // 未配置的监听在开始排空时直接结束
+        let http = async {
+            match http_addr {
+                Some(addr) => http::serve(shared.clone(), addr, args.max_body_size << 20).await,
+                None => {
+                    drain.draining().await;
+                    Ok(())
+                }
+            }
+        };
+        let ssh = async {
+            match ssh_addr {
+                Some(addr) => ssh::serve(shared.clone(), addr, args.max_body_size << 20).await,
+                None => {
+                    drain.draining().await;
+                    Ok(())
+                }
+            }
+        };
+        let grpc = async {
+            match grpc_addr {
+                Some(addr) => grpc::serve(shared.clone(), addr).await,
+                None => {
+                    drain.draining().await;
+                    Ok(())
+                }
+            }
+        };
+        let listeners = async { tokio::try_join!(http, ssh, grpc).map(|_| ()) };
+        tokio::pin!(listeners);
+        let reload = reload_on_hangup(shared.clone());
+        let webhooks = deliver_webhooks(shared.clone());
+        let replication = replicate(repo.clone());
+        let maintenance = async {
+            if args.maintenance {
+                run_maintenance(repo.clone()).await
+            } else {
+                std::future::pending().await
+            }
+        };
+        // 排空之前任一监听或后台任务退出即视为服务结束
+        tokio::select! {
+            result = &mut listeners => return result,
+            result = reload => return result,
+            result = webhooks => return result,
+            result = replication => return result,
+            result = maintenance => return result,
+            result = terminated() => result?,
+        }
+
+        tracing::info!(sessions = drain.sessions(), "shutting down, draining connections");
+        drain.start();
+        let deadline = Duration::from_secs(args.shutdown_timeout);
+        match tokio::time::timeout(deadline, &mut listeners).await {
+            Ok(result) => result?,
+            Err(_) => tracing::warn!(sessions = drain.sessions(), "shutdown timeout elapsed, closing remaining connections"),
+        }
+        let refs = repo.clone();
+        blocking(move || refs.refs().flush()).await?;
+        tracing::info!("shut down");
+        Err(MonoError::from_kind(
+            MonoErrorKind::Unavailable("terminated by SIGTERM".to_string()),
+            ExitCode::Terminated.code(),
+        ))
+    })
+}
+
+/// 收到 SIGTERM 时完成
+#[cfg(unix)]
+async fn terminated() -> MonoResult<()> {
+    tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?.recv().await;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+async fn terminated() -> MonoResult<()> {
+    tokio::signal::ctrl_c().await?;
+    Ok(())
+}
+
+/// 收到 SIGHUP 时重新加载配置；新配置不合法时保留原配置
+#[cfg(unix)]
+async fn reload_on_hangup(repo: SharedRepo) -> MonoResult<()> {
+    let mut hangup = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())?;
+    while hangup.recv().await.is_some() {
+        let repo = repo.clone();
+        match blocking(move || repo.reload()).await {
+            Ok(reload) => tracing::info!(
+                changed = ?reload.changed,
+                restart_required = ?reload.restart_required,
+                "reloaded configuration"
+            ),
+            Err(e) => tracing::warn!(error = %e, "failed to reload configuration, keeping the previous one"),
+        }
+    }
+    std::future::pending().await
+}
+
+#[cfg(not(unix))]
+async fn reload_on_hangup(_repo: SharedRepo) -> MonoResult<()> {
+    std::future::pending().await
+}
+
+/// 定期发送到期的 webhook 投递，未配置 webhook 时跳过；每次按当前配置中的 webhook 发送
+async fn deliver_webhooks(repo: SharedRepo) -> MonoResult<()> {
+    let sender = Arc::new(HttpSender::default());
+    let mut interval = tokio::time::interval(WEBHOOK_POLL_INTERVAL);
+    loop {
+        interval.tick().await;
+        let repo = repo.current();
+        if repo.config().webhooks.is_empty() {
+            continue;
+        }
+        let sender = sender.clone();
+        let delivered = blocking(move || {
+            WebhookQueue::new(&repo).deliver_due(sender.as_ref(), chrono::Utc::now().timestamp())
+        })
+        .await;
+        // 投递失败已记录在投递记录中，这里只可能是存储错误或其他进程正在发送
+        if let Err(e) = delivered {
+            tracing::warn!(error = %e, "failed to deliver webhooks");
+        }
+    }
+}
+
+/// 定期复制到镜像或从上游拉取，并更新复制延迟指标；未配置镜像与上游时不做任何事
+async fn replicate(repo: Arc<Repository>) -> MonoResult<()> {
+    let config = &repo.config().replication;
+    if config.mirrors.is_empty() && config.upstream.is_none() {
+        return std::future::pending().await;
+    }
+    let mut interval = tokio::time::interval(Duration::from_secs(config.interval_secs.max(1)));
+    loop {
+        interval.tick().await;
+        let repo = repo.clone();
+        let replicated = blocking(move || {
+            let replication = Replication::new(&repo);
+            let now = chrono::Utc::now().timestamp();
+            replication.sync(None, now)?;
+            replication.status(now)
+        })
+        .await;
+        // 单个镜像的失败已记录在复制记录中，这里只可能是存储错误或其他进程正在复制
+        if let Err(e) = replicated {
+            tracing::warn!(error = %e, "failed to replicate");
+        }
+    }
+}
+
+/// 定期执行到期的维护任务
+async fn run_maintenance(repo: Arc<Repository>) -> MonoResult<()> {
+    let mut interval = tokio::time::interval(maintenance::POLL_INTERVAL);
+    loop {
+        interval.tick().await;
+        let repo = repo.clone();
+        match blocking(move || Maintenance::new(&repo).run_due(chrono::Utc::now())).await {
+            Ok(results) => {
+                for (task, run) in results {
+                    tracing::info!(task = task.as_str(), status = run.status.as_str(), message = %run.message, "maintenance");
+                }
+            }
+            // 其他进程正在执行维护，或任务状态无法读写；下一轮再试
+            Err(e) => tracing::warn!(error = %e, "failed to run maintenance"),
+        }
+    }
+}
+
+/// 解析监听地址，省略主机时监听所有地址
+fn parse_listen_addr(addr: &str) -> MonoResult<SocketAddr> {
+    let addr = if addr.starts_with(':') {
+        format!("0.0.0.0{}", addr)
+    } else {
+        addr.to_string()
+    };
+    addr.to_socket_addrs()
+        .ok()
+        .and_then(|mut addrs| addrs.next())
+        .ok_or_else(|| MonoError::usage(format!("invalid listen address: {}", addr)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 测试监听地址的解析
+    #[test]
+    fn test_parse_listen_addr() {
+        assert_eq!(parse_listen_addr(":8000").unwrap(), "0.0.0.0:8000".parse().unwrap());
+        assert_eq!(parse_listen_addr("127.0.0.1:9418").unwrap(), "127.0.0.1:9418".parse().unwrap());
+        assert!(parse_listen_addr("8000").is_err());
+    }
+}
@@ -0,0 +1,109 @@
+//! `mono sparse` 命令：管理工作区的稀疏检出
+
+use clap::{Args, Subcommand};
+
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::repo::{Repository, SparseState};
+use crate::sparse::{self, SparseProfiles};
+
+/// `mono sparse` 的参数
+#[derive(Args, Debug)]
+pub struct SparseArgs {
+    #[command(subcommand)]
+    pub command: SparseCommand,
+}
+
+/// `mono sparse` 的子命令
+#[derive(Subcommand, Debug)]
+pub enum SparseCommand {
+    /// 启用稀疏检出，默认只检出根目录下的文件
+    Init {
+        /// 使用仓库中定义的稀疏检出配置
+        #[arg(long)]
+        profile: Option<String>,
+    },
+    /// 替换当前的稀疏检出配置和路径模式
+    Set {
+        /// 使用仓库中定义的稀疏检出配置
+        #[arg(long)]
+        profile: Option<String>,
+        /// 路径模式，例如 //services/payments/...
+        patterns: Vec<String>,
+    },
+    /// 追加路径模式
+    Add {
+        /// 路径模式，例如 //services/payments/...
+        #[arg(required = true)]
+        patterns: Vec<String>,
+    },
+    /// 列出仓库中的稀疏检出配置以及当前生效的模式
+    List,
+    /// 按当前配置重新物化工作区
+    Apply,
+}
+
+/// 执行 `mono sparse`
+pub fn execute(args: SparseArgs) -> MonoResult<()> {
+    let repo = Repository::discover(&std::env::current_dir()?)?;
+    let mut manifest = repo.workspace()?;
+
+    let state = match args.command {
+        SparseCommand::Init { profile } => SparseState {
+            profile,
+            patterns: Vec::new(),
+        },
+        SparseCommand::Set { profile, patterns } => SparseState { profile, patterns },
+        SparseCommand::Add { patterns } => {
+            let mut state = manifest
+                .sparse
+                .clone()
+                .ok_or_else(|| MonoError::usage("sparse checkout is not enabled, run `mono sparse init` first"))?;
+            for pattern in patterns {
+                if !state.patterns.contains(&pattern) {
+                    state.patterns.push(pattern);
+                }
+            }
+            state
+        }
+        SparseCommand::List => return list(&repo, manifest.sparse.as_ref()),
+        SparseCommand::Apply => return apply(&repo),
+    };
+
+    // 先校验模式和配置名，避免写入无效状态
+    sparse::resolve_spec(&repo, &state)?;
+    manifest.sparse = Some(state);
+    repo.save_workspace(&manifest)?;
+    apply(&repo)
+}
+
+fn apply(repo: &Repository) -> MonoResult<()> {
+    let stats = sparse::apply(repo)?;
+    println!(
+        "Checked out {} files, removed {} files",
+        stats.checked_out, stats.removed
+    );
+    Ok(())
+}
+
+fn list(repo: &Repository, state: Option<&SparseState>) -> MonoResult<()> {
+    let profiles = SparseProfiles::load(repo)?;
+    let active = state.and_then(|s| s.profile.as_deref());
+    for (name, profile) in &profiles.profiles {
+        let marker = if Some(name.as_str()) == active { "*" } else { " " };
+        match &profile.description {
+            Some(description) => println!("{} {} - {}", marker, name, description),
+            None => println!("{} {}", marker, name),
+        }
+    }
+    match state {
+        Some(state) => {
+            println!("\nActive patterns:");
+            for pattern in sparse::resolve_spec(repo, state)?.patterns {
+                println!("  {}", pattern);
+            }
+        }
+        None => println!("\nSparse checkout is not enabled"),
+    }
+    Ok(())
+}
@@ -0,0 +1,58 @@
+//! `mono split` 命令：将子目录的历史导出为独立的提交历史
+
+use clap::Args;
+use clap_complete::ArgValueCompleter;
+
+use crate::audit::{self, AuditAction, AuditLog};
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::completion::complete_refs;
+use crate::object::ObjectId;
+use crate::refs::{self, RefUpdate};
+use crate::repo::Repository;
+use crate::rewrite::split::Splitter;
+
+/// `mono split` 的参数
+#[derive(Args, Debug)]
+pub struct SplitArgs {
+    /// 导出的子目录，相对于仓库根目录
+    pub prefix: String,
+    /// 导出该修订及其历史
+    #[arg(long, default_value = "HEAD", add = ArgValueCompleter::new(complete_refs))]
+    pub rev: String,
+    /// 将导出结果写入该分支，重复执行时分支随源历史快进
+    #[arg(long)]
+    pub branch: Option<String>,
+}
+
+/// 执行 `mono split`
+pub fn execute(args: SplitArgs) -> MonoResult<()> {
+    let repo = Repository::discover(&std::env::current_dir()?)?;
+    let commit = repo.resolve_rev(&args.rev)?;
+    let mut splitter = Splitter::new(&repo, &args.prefix)?;
+    let split = splitter.split(&commit)?;
+    splitter.save_cache()?;
+    let split = split.ok_or_else(|| MonoError::not_found(format!("{} in the history of {}", args.prefix, args.rev)))?;
+
+    if let Some(branch) = &args.branch {
+        let name = format!("{}{}", refs::HEADS_PREFIX, branch);
+        if !refs::check_ref_format(&name) {
+            return Err(MonoError::usage(format!("invalid branch name: {}", branch)));
+        }
+        let old = repo.refs().resolve(&name)?.unwrap_or(ObjectId::ZERO);
+        repo.with_reflog_identity(&audit::local_actor(), &format!("split: {}", args.prefix))
+            .refs()
+            .write(&name, &split)?;
+        let update = RefUpdate {
+            name,
+            old,
+            new: split,
+        };
+        let now = chrono::Utc::now().timestamp();
+        AuditLog::new(&repo).record_ref_updates(&repo, &audit::local_actor(), AuditAction::RefUpdate, &[update], now);
+    }
+    let stats = splitter.stats();
+    tracing::info!(processed = stats.processed, created = stats.created, "split history");
+    println!("{}", split);
+    Ok(())
+}
@@ -0,0 +1,134 @@
+//! `mono stack` 命令：管理相互依赖的堆叠分支
+
+use clap::{Args, Subcommand};
+
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::object::commit::Signature;
+use crate::refs;
+use crate::repo::Repository;
+use crate::sparse;
+use crate::stack::{self, StackBranch};
+
+/// `mono stack` 的参数
+#[derive(Args, Debug)]
+pub struct StackArgs {
+    #[command(subcommand)]
+    pub command: StackCommand,
+}
+
+/// `mono stack` 的子命令
+#[derive(Subcommand, Debug)]
+pub enum StackCommand {
+    /// 在当前分支之上创建新分支并切换过去
+    Create(CreateArgs),
+    /// 显示当前分支所在的堆叠
+    List,
+    /// 将当前堆叠中父分支已移动的分支逐层变基
+    Restack,
+    /// 推送当前堆叠的全部分支及其元数据
+    Submit(SubmitArgs),
+}
+
+/// `mono stack create` 的参数
+#[derive(Args, Debug)]
+pub struct CreateArgs {
+    /// 新分支名
+    pub name: String,
+    /// 父分支，默认为当前分支
+    #[arg(long)]
+    pub parent: Option<String>,
+}
+
+/// `mono stack submit` 的参数
+#[derive(Args, Debug)]
+pub struct SubmitArgs {
+    /// 推送到的远端
+    #[arg(long, default_value = "origin")]
+    pub remote: String,
+}
+
+/// 执行 `mono stack`
+pub fn execute(args: StackArgs) -> MonoResult<()> {
+    let repo = Repository::discover(&std::env::current_dir()?)?;
+    let current = repo
+        .refs()
+        .head_target()?
+        .ok_or_else(|| MonoError::usage("HEAD is detached, switch to a branch first"))?;
+    match args.command {
+        StackCommand::Create(args) => {
+            let parent = match args.parent {
+                Some(parent) => format!("{}{}", refs::HEADS_PREFIX, parent),
+                None => current,
+            };
+            let meta = stack::create(&repo, &args.name, &parent)?;
+            repo.refs().write_symbolic(refs::HEAD, &meta.branch)?;
+            println!("Created {} on top of {}", args.name, refs::short_name(&parent));
+        }
+        StackCommand::List => {
+            let branches = stack::stack_of(&repo, &current)?;
+            if branches.is_empty() {
+                println!("{} is not part of a stack", refs::short_name(&current));
+            }
+            print_stack(&repo, &branches, &current)?;
+        }
+        StackCommand::Restack => {
+            let branches = stack::stack_of(&repo, &current)?;
+            let restacked = stack::restack(&repo, &branches, &Signature::committer_from_env()?)?;
+            for r in &restacked {
+                println!("Restacked {}: {} -> {}", refs::short_name(&r.branch), r.old, r.new);
+            }
+            if restacked.iter().any(|r| r.branch == current) {
+                let stats = sparse::apply(&repo)?;
+                tracing::info!(files = stats.checked_out, "checked out worktree");
+            }
+            if restacked.is_empty() {
+                println!("Stack is up to date");
+            }
+        }
+        StackCommand::Submit(args) => {
+            let url = repo
+                .config()
+                .remote
+                .get(&args.remote)
+                .map(|remote| remote.url.clone())
+                .ok_or_else(|| MonoError::config(format!("remote {} is not configured", args.remote)))?;
+            let branches = stack::stack_of(&repo, &current)?;
+            if branches.is_empty() {
+                return Err(MonoError::usage(format!("{} is not part of a stack", refs::short_name(&current))));
+            }
+            let stats = stack::submit(&repo, &branches, &url)?;
+            println!("Submitted {} branches to {} ({} objects)", branches.len(), args.remote, stats.objects);
+        }
+    }
+    Ok(())
+}
+
+/// 按父子关系缩进显示堆叠，当前分支以 `*` 标记
+fn print_stack(repo: &Repository, branches: &[StackBranch], current: &str) -> MonoResult<()> {
+    let mut depth: Vec<(&str, usize)> = Vec::new();
+    for meta in branches {
+        let level = match depth.iter().find(|(name, _)| *name == meta.parent) {
+            Some((_, level)) => level + 1,
+            None => {
+                println!("{}", refs::short_name(&meta.parent));
+                1
+            }
+        };
+        depth.push((&meta.branch, level));
+        let marker = if meta.branch == current { "*" } else { " " };
+        let outdated = match repo.refs().resolve(&meta.parent)? {
+            Some(tip) if tip != meta.base => ", needs restack",
+            _ => "",
+        };
+        println!(
+            "{}{} {} ({} commits{})",
+            "  ".repeat(level),
+            marker,
+            refs::short_name(&meta.branch),
+            meta.commit_count(repo)?,
+            outdated
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,40 @@
+//! `mono status` 命令：列出工作区相对 HEAD 修改、删除与未跟踪的文件
+
+use clap::Args;
+
+use crate::commands::OutputFormat;
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::repo::Repository;
+use crate::status::{fsmonitor, status};
+
+/// `mono status` 的参数
+#[derive(Args, Debug)]
+pub struct StatusArgs {
+    /// 输出格式
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+    /// 不使用 `[status] fsmonitor` 配置的文件变化监视，完整扫描工作区
+    #[arg(long)]
+    pub no_fsmonitor: bool,
+}
+
+/// 执行 `mono status`
+pub fn execute(args: StatusArgs) -> MonoResult<()> {
+    let repo = Repository::discover(&std::env::current_dir()?)?;
+    let monitor = if args.no_fsmonitor { None } else { fsmonitor::from_config(&repo) };
+    let report = status(&repo, monitor.as_deref())?;
+    tracing::debug!(fsmonitor = report.fsmonitor, hashed = report.hashed, "status computed");
+    match args.format {
+        OutputFormat::Json => {
+            let json = serde_json::to_string_pretty(&report.entries).map_err(|e| MonoError::usage(e.to_string()))?;
+            println!("{}", json);
+        }
+        OutputFormat::Text => {
+            for entry in &report.entries {
+                println!("{:>2} {}", entry.status.code(), entry.path);
+            }
+        }
+    }
+    Ok(())
+}
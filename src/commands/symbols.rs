@@ -0,0 +1,102 @@
+//! `mono symbols` 命令：在代码搜索索引中查找符号的定义与引用
+
+use clap::{Args, Subcommand};
+
+use crate::commands::OutputFormat;
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::repo::Repository;
+use crate::search::symbols::SymbolKind;
+use crate::search::CodeSearch;
+use crate::sparse::SparsePattern;
+
+/// `mono symbols` 的参数
+#[derive(Args, Debug)]
+pub struct SymbolsArgs {
+    #[command(subcommand)]
+    pub command: SymbolsCommand,
+}
+
+/// `mono symbols` 的子命令
+#[derive(Subcommand, Debug)]
+pub enum SymbolsCommand {
+    /// 按名字查找符号的定义与引用
+    ///
+    /// Rust、Go、Python、Java、JavaScript 与 TypeScript 由 tree-sitter 解析，引用是同名的标识符，
+    /// 不含注释与字符串。其余语言的定义由正则规则逐行识别，整词出现该名字的行作为文本匹配单独列出。
+    Lookup(LookupArgs),
+}
+
+/// `mono symbols lookup` 的参数
+#[derive(Args, Debug)]
+pub struct LookupArgs {
+    /// 符号名，区分大小写
+    pub name: String,
+    /// 只查找该种类的定义
+    #[arg(long, value_enum)]
+    pub kind: Option<SymbolKind>,
+    /// 只查找该路径模式下的文件，例如 `//services/...`，可重复
+    #[arg(long = "path")]
+    pub paths: Vec<String>,
+    /// 只显示定义
+    #[arg(long)]
+    pub definitions_only: bool,
+    /// 至多显示的引用与文本匹配行数
+    #[arg(short = 'n', long, default_value_t = 100)]
+    pub limit: usize,
+    /// 输出格式
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+}
+
+/// 执行 `mono symbols`
+pub fn execute(args: SymbolsArgs) -> MonoResult<()> {
+    let repo = Repository::discover(&std::env::current_dir()?)?;
+    let search = CodeSearch::new(&repo);
+    match args.command {
+        SymbolsCommand::Lookup(args) => {
+            search.update()?;
+            let paths = args
+                .paths
+                .iter()
+                .map(|path| path.parse())
+                .collect::<MonoResult<Vec<SparsePattern>>>()?;
+            let mut lookup = search.lookup(&args.name, args.kind, &paths, args.limit)?;
+            if args.definitions_only {
+                lookup.references.clear();
+                lookup.textual_matches.clear();
+            }
+            match args.format {
+                OutputFormat::Json => {
+                    let json = serde_json::to_string_pretty(&lookup).map_err(|e| MonoError::usage(e.to_string()))?;
+                    println!("{}", json);
+                }
+                OutputFormat::Text => {
+                    println!("Definitions:");
+                    for definition in &lookup.definitions {
+                        println!(
+                            "  {}:{}: [{}] {}",
+                            definition.path,
+                            definition.line_number,
+                            definition.kind,
+                            definition.line.trim()
+                        );
+                    }
+                    if !args.definitions_only {
+                        println!("References:");
+                        for found in &lookup.references {
+                            println!("  {}:{}: {}", found.path, found.line_number, found.line.trim());
+                        }
+                    }
+                    if !lookup.textual_matches.is_empty() {
+                        println!("Textual matches:");
+                        for found in &lookup.textual_matches {
+                            println!("  {}:{}: {}", found.path, found.line_number, found.line.trim());
+                        }
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
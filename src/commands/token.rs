@@ -0,0 +1,113 @@
+//! `mono token` 命令：签发、查看与吊销个人访问令牌
+
+use clap::{Args, Subcommand};
+
+use crate::auth::{Token, TokenStore};
+use crate::audit::{self, AuditAction, AuditEvent, AuditLog};
+use crate::commands::OutputFormat;
+use crate::common::config::Scope;
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::repo::Repository;
+
+/// `mono token` 的参数
+#[derive(Args, Debug)]
+pub struct TokenArgs {
+    #[command(subcommand)]
+    pub command: TokenCommand,
+}
+
+/// `mono token` 的子命令
+#[derive(Subcommand, Debug)]
+pub enum TokenCommand {
+    /// 签发令牌，令牌只显示这一次
+    Create(CreateArgs),
+    /// 列出已签发的令牌
+    List(ListArgs),
+    /// 吊销令牌
+    Revoke(RevokeArgs),
+}
+
+/// `mono token create` 的参数
+#[derive(Args, Debug)]
+pub struct CreateArgs {
+    /// 令牌用途的说明
+    pub name: String,
+    /// 权限范围，可以重复指定
+    #[arg(long = "scope", value_enum, default_value = "read")]
+    pub scopes: Vec<Scope>,
+    /// 只允许修改该路径前缀下的文件，可以重复指定
+    #[arg(long = "path", value_name = "PREFIX")]
+    pub paths: Vec<String>,
+    /// 有效天数，省略时不过期
+    #[arg(long, value_name = "DAYS")]
+    pub expires_in: Option<u32>,
+}
+
+/// `mono token list` 的参数
+#[derive(Args, Debug)]
+pub struct ListArgs {
+    /// 输出格式
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+}
+
+/// `mono token revoke` 的参数
+#[derive(Args, Debug)]
+pub struct RevokeArgs {
+    pub id: String,
+}
+
+/// 执行 `mono token`
+pub fn execute(args: TokenArgs) -> MonoResult<()> {
+    let repo = Repository::discover(&std::env::current_dir()?)?;
+    let store = TokenStore::new(&repo);
+    let now = chrono::Utc::now().timestamp();
+    match args.command {
+        TokenCommand::Create(args) => {
+            let expires_at = args.expires_in.map(|days| now + i64::from(days) * 24 * 60 * 60);
+            let (token, secret) = store.create(&args.name, &args.scopes, &args.paths, expires_at, now)?;
+            let event = AuditEvent::new(now, audit::local_actor(), AuditAction::TokenCreate, &token.id).with_detail(&token.name);
+            AuditLog::new(&repo).record(&event);
+            println!("Created token {} ({})", token.id, describe(&token, now));
+            println!("{}", secret);
+            eprintln!("Copy the token now, it cannot be shown again");
+        }
+        TokenCommand::List(args) => {
+            let tokens = store.list()?;
+            match args.format {
+                OutputFormat::Json => {
+                    let json = serde_json::to_string_pretty(&tokens).map_err(|e| MonoError::usage(e.to_string()))?;
+                    println!("{}", json);
+                }
+                OutputFormat::Text => {
+                    for token in &tokens {
+                        println!("{} {:<16} {}", token.id, token.name, describe(token, now));
+                    }
+                }
+            }
+        }
+        TokenCommand::Revoke(args) => {
+            let token = store.revoke(&args.id)?;
+            let event = AuditEvent::new(now, audit::local_actor(), AuditAction::TokenRevoke, &token.id).with_detail(&token.name);
+            AuditLog::new(&repo).record(&event);
+            println!("Revoked token {} ({})", token.id, token.name);
+        }
+    }
+    Ok(())
+}
+
+/// 令牌的权限、路径限制与有效期
+fn describe(token: &Token, now: i64) -> String {
+    let scopes: Vec<&str> = token.scopes.iter().map(Scope::as_str).collect();
+    let mut out = scopes.join(",");
+    if !token.paths.is_empty() {
+        out.push_str(&format!(" paths={}", token.paths.join(",")));
+    }
+    match token.expires_at {
+        Some(expires_at) if token.is_expired(now) => out.push_str(&format!(" expired {}d ago", (now - expires_at) / 86400)),
+        Some(expires_at) => out.push_str(&format!(" expires in {}d", (expires_at - now + 86399) / 86400)),
+        None => {}
+    }
+    out
+}
@@ -0,0 +1,33 @@
+//! `mono tui` 命令：在终端中浏览引用、提交历史、差异与合并队列
+
+use std::io::IsTerminal;
+
+use clap::Args;
+
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::repo::Repository;
+use crate::tui::App;
+
+/// `mono tui` 的参数
+#[derive(Args, Debug)]
+pub struct TuiArgs {}
+
+/// 执行 `mono tui`，按 `q` 退出
+pub fn execute(_args: TuiArgs) -> MonoResult<()> {
+    if !std::io::stdout().is_terminal() {
+        return Err(MonoError::usage("mono tui requires an interactive terminal"));
+    }
+    let repo = Repository::discover(&std::env::current_dir()?)?;
+    run(App::new(&repo)?)
+}
+
+#[cfg(feature = "tui")]
+fn run(app: App) -> MonoResult<()> {
+    crate::tui::terminal::run(app)
+}
+
+#[cfg(not(feature = "tui"))]
+fn run(_app: App) -> MonoResult<()> {
+    Err(MonoError::usage("mono was built without terminal UI support; rebuild with `--features tui`"))
+}
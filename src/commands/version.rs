@@ -0,0 +1,100 @@
+//! `mono version` 命令：按项目计算与发布语义化版本
+
+use clap::{ArgGroup, Args, Subcommand};
+use clap_complete::ArgValueCompleter;
+
+use crate::commands::OutputFormat;
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::completion::complete_refs;
+use crate::object::commit::Signature;
+use crate::repo::Repository;
+use crate::version::{normalize_project, Bump, Versions};
+
+/// `mono version` 的参数
+#[derive(Args, Debug)]
+pub struct VersionArgs {
+    #[command(subcommand)]
+    pub command: VersionCommand,
+}
+
+/// `mono version` 的子命令
+#[derive(Subcommand, Debug)]
+pub enum VersionCommand {
+    /// 计算项目的下一个版本，创建标签并以变更日志作为发布说明
+    Bump(BumpArgs),
+    /// 从大到小列出项目已有的版本
+    List(ListArgs),
+}
+
+/// `mono version bump` 的参数
+#[derive(Args, Debug)]
+#[command(group(ArgGroup::new("level").args(["major", "minor", "patch"])))]
+pub struct BumpArgs {
+    /// 项目目录，如 `//services/foo`，`//` 为整个仓库
+    pub project: String,
+    /// 递增主版本号
+    #[arg(long)]
+    pub major: bool,
+    /// 递增次版本号
+    #[arg(long)]
+    pub minor: bool,
+    /// 递增修订号（默认）
+    #[arg(long)]
+    pub patch: bool,
+    /// 发布的修订
+    #[arg(long, default_value = "HEAD", add = ArgValueCompleter::new(complete_refs))]
+    pub rev: String,
+    /// 只显示下一个版本与变更日志，不创建标签
+    #[arg(long)]
+    pub dry_run: bool,
+    /// 输出格式
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+}
+
+/// `mono version list` 的参数
+#[derive(Args, Debug)]
+pub struct ListArgs {
+    /// 项目目录，如 `//services/foo`
+    pub project: String,
+}
+
+/// 执行 `mono version`
+pub fn execute(args: VersionArgs) -> MonoResult<()> {
+    let repo = Repository::discover(&std::env::current_dir()?)?;
+    let versions = Versions::new(&repo);
+    match args.command {
+        VersionCommand::Bump(args) => {
+            let bump = match (args.major, args.minor) {
+                (true, _) => Bump::Major,
+                (_, true) => Bump::Minor,
+                _ => Bump::Patch,
+            };
+            let plan = versions.plan(&args.project, &args.rev, bump)?;
+            if !args.dry_run {
+                versions.bump(&plan, &Signature::committer_from_env()?)?;
+            }
+            match args.format {
+                OutputFormat::Json => {
+                    let json = serde_json::to_string_pretty(&plan).map_err(|e| MonoError::usage(e.to_string()))?;
+                    println!("{}", json);
+                }
+                OutputFormat::Text => {
+                    let previous = plan.previous.map(|v| format!("v{}", v)).unwrap_or_else(|| "(none)".to_string());
+                    let verb = if args.dry_run { "Would tag" } else { "Tagged" };
+                    println!("{} {} at {} (previous {})", verb, plan.tag, &plan.commit.to_hex()[..12], previous);
+                    println!();
+                    print!("{}", plan.changelog.markdown());
+                }
+            }
+        }
+        VersionCommand::List(args) => {
+            let project = normalize_project(&args.project)?;
+            for (version, commit) in versions.list(&project)? {
+                println!("v{:<12} {}", version.to_string(), &commit.to_hex()[..12]);
+            }
+        }
+    }
+    Ok(())
+}
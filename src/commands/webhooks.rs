@@ -0,0 +1,94 @@
+//! `mono webhooks` 命令：查看与重新投递 webhook
+
+use clap::{Args, Subcommand};
+
+use crate::commands::OutputFormat;
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::repo::Repository;
+use crate::webhooks::{Delivery, DeliveryState, HttpSender, WebhookQueue};
+
+/// `mono webhooks` 的参数
+#[derive(Args, Debug)]
+pub struct WebhooksArgs {
+    #[command(subcommand)]
+    pub command: WebhooksCommand,
+}
+
+/// `mono webhooks` 的子命令
+#[derive(Subcommand, Debug)]
+pub enum WebhooksCommand {
+    /// 列出投递记录，默认只显示待发送与失败的投递
+    Deliveries(DeliveriesArgs),
+    /// 将投递重新标记为待发送
+    Redeliver(RedeliverArgs),
+    /// 立即发送全部到期的投递，未运行 `mono serve` 时可由定时任务调用
+    Deliver,
+}
+
+/// `mono webhooks deliveries` 的参数
+#[derive(Args, Debug)]
+pub struct DeliveriesArgs {
+    /// 同时显示已送达的投递
+    #[arg(long)]
+    pub all: bool,
+    /// 输出格式
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+}
+
+/// `mono webhooks redeliver` 的参数
+#[derive(Args, Debug)]
+pub struct RedeliverArgs {
+    pub id: String,
+}
+
+/// 执行 `mono webhooks`
+pub fn execute(args: WebhooksArgs) -> MonoResult<()> {
+    let repo = Repository::discover(&std::env::current_dir()?)?;
+    let webhooks = WebhookQueue::new(&repo);
+    let now = chrono::Utc::now().timestamp();
+    match args.command {
+        WebhooksCommand::Deliveries(args) => {
+            let deliveries: Vec<Delivery> = webhooks
+                .deliveries()?
+                .into_iter()
+                .filter(|delivery| args.all || delivery.state != DeliveryState::Delivered)
+                .collect();
+            match args.format {
+                OutputFormat::Json => {
+                    let json =
+                        serde_json::to_string_pretty(&deliveries).map_err(|e| MonoError::usage(e.to_string()))?;
+                    println!("{}", json);
+                }
+                OutputFormat::Text => deliveries.iter().for_each(|delivery| print_delivery(delivery, now)),
+            }
+        }
+        WebhooksCommand::Redeliver(args) => {
+            let delivery = webhooks.redeliver(&args.id, now)?;
+            println!("Scheduled {} for redelivery to {}", delivery.id, delivery.webhook);
+        }
+        WebhooksCommand::Deliver => {
+            for delivery in webhooks.deliver_due(&HttpSender::default(), now)? {
+                print_delivery(&delivery, now);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn print_delivery(delivery: &Delivery, now: i64) {
+    let retry = match delivery.state {
+        DeliveryState::Pending if delivery.attempts > 0 => {
+            format!(" (retry in {}s)", (delivery.next_attempt_at - now).max(0))
+        }
+        _ => String::new(),
+    };
+    println!(
+        "{} {:<9} {:<12} {:<12} attempts={}{}",
+        delivery.id, delivery.state, delivery.webhook, delivery.event, delivery.attempts, retry
+    );
+    if let Some(error) = &delivery.last_error {
+        println!("    {}", error);
+    }
+}
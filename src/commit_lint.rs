@@ -0,0 +1,243 @@
+//! 提交信息规则
+//!
+//! `[commit_lint]` 配置的规则在推送时作为推送策略（见 [`crate::policy`]）检查每条更新引入的提交，
+//! 即从新值可达、从现有引用都不可达的提交；`mono lint-commits <范围>` 在推送之前于本地检查同样的规则。
+//!
+//! 支持的规则：标题遵循 Conventional Commits 格式、提交信息引用工单号、标题长度上限与
+//! `Signed-off-by:` 尾注。合并提交默认不检查。
+
+use std::sync::LazyLock;
+
+use regex::Regex;
+use serde::Serialize;
+
+use crate::common::config::CommitLintConfig;
+use crate::common::errors::{MonoError, PolicyViolation};
+use crate::common::MonoResult;
+use crate::graph::history::History;
+use crate::object::commit::Commit;
+use crate::object::{ObjectId, ObjectType};
+use crate::policy;
+use crate::refs::{self, RefUpdate};
+use crate::repo::Repository;
+
+/// 未配置 `types` 时 Conventional Commits 允许的类型
+pub const DEFAULT_TYPES: &[&str] = &[
+    "build", "chore", "ci", "docs", "feat", "fix", "perf", "refactor", "revert", "style", "test",
+];
+
+/// 签署提交的尾注
+pub const SIGNED_OFF_BY: &str = "Signed-off-by";
+
+/// 推送策略中的规则名
+const RULE: &str = "commit-lint";
+
+/// Conventional Commits 的标题格式，第一个分组为类型
+pub(crate) static CONVENTIONAL: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^([a-z]+)(\([^()]+\))?!?: \S").expect("conventional commit pattern is valid"));
+
+/// 一个提交违反的规则
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Problems {
+    pub commit: ObjectId,
+    /// 提交信息的标题
+    pub summary: String,
+    pub problems: Vec<String>,
+}
+
+/// 编译后的提交信息规则
+#[derive(Debug, Clone)]
+pub struct CommitLint {
+    refs: Vec<String>,
+    /// Conventional Commits 允许的类型，None 表示不检查格式
+    types: Option<Vec<String>>,
+    ticket: Option<Regex>,
+    max_subject_length: usize,
+    require_sign_off: bool,
+    skip_merges: bool,
+    bypass_option: Option<String>,
+}
+
+impl CommitLint {
+    /// 按配置编译规则，没有配置任何规则时返回 None
+    pub fn from_config(config: &CommitLintConfig) -> MonoResult<Option<CommitLint>> {
+        if !config.conventional && config.ticket_pattern.is_none() && config.max_subject_length == 0 && !config.require_sign_off {
+            return Ok(None);
+        }
+        let ticket = config
+            .ticket_pattern
+            .as_deref()
+            .map(|pattern| {
+                Regex::new(pattern).map_err(|e| MonoError::config(format!("commit_lint: invalid ticket_pattern: {}", e)))
+            })
+            .transpose()?;
+        let types = match config.types.is_empty() {
+            true => DEFAULT_TYPES.iter().map(|t| t.to_string()).collect(),
+            false => config.types.clone(),
+        };
+        Ok(Some(CommitLint {
+            refs: config.refs.clone(),
+            types: config.conventional.then_some(types),
+            ticket,
+            max_subject_length: config.max_subject_length,
+            require_sign_off: config.require_sign_off,
+            skip_merges: config.skip_merges,
+            bypass_option: config.bypass_option.clone(),
+        }))
+    }
+
+    /// 推送时是否检查该引用
+    pub fn applies_to(&self, refname: &str) -> bool {
+        if self.refs.is_empty() {
+            return refname.starts_with(refs::HEADS_PREFIX);
+        }
+        self.refs.iter().any(|pattern| match pattern.strip_suffix('/') {
+            Some(_) => refname.starts_with(pattern.as_str()),
+            None => refname == pattern,
+        })
+    }
+
+    /// 检查一个提交的信息，返回违反的规则
+    pub fn lint(&self, commit: &Commit) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.skip_merges && commit.parents.len() > 1 {
+            return problems;
+        }
+        let subject = commit.summary();
+        if let Some(types) = &self.types {
+            match CONVENTIONAL.captures(subject) {
+                Some(captures) if !types.iter().any(|t| *t == captures[1]) => {
+                    problems.push(format!("type '{}' is not one of {}", &captures[1], types.join(", ")))
+                }
+                Some(_) => {}
+                None => problems.push("subject is not a conventional commit: <type>(<scope>): <description>".to_string()),
+            }
+        }
+        if let Some(ticket) = &self.ticket {
+            if !ticket.is_match(&commit.message) {
+                problems.push(format!("message does not reference a ticket matching {}", ticket.as_str()));
+            }
+        }
+        let length = subject.chars().count();
+        if self.max_subject_length > 0 && length > self.max_subject_length {
+            problems.push(format!("subject is {} characters, longer than {}", length, self.max_subject_length));
+        }
+        if self.require_sign_off
+            && !commit
+                .trailers()
+                .iter()
+                .any(|(key, value)| key.eq_ignore_ascii_case(SIGNED_OFF_BY) && !value.is_empty())
+        {
+            problems.push(format!("missing {} trailer", SIGNED_OFF_BY));
+        }
+        problems
+    }
+
+    /// 检查从 `tips` 可达、从 `exclude` 不可达的提交，按提交时间从新到旧返回有问题的提交
+    pub fn lint_range(&self, repo: &Repository, tips: &[ObjectId], exclude: &[ObjectId]) -> MonoResult<Vec<Problems>> {
+        let mut out = Vec::new();
+        for commit in History::new(repo)?.range(tips, exclude)? {
+            let parsed = repo.read_commit(&commit.id)?;
+            let problems = self.lint(&parsed);
+            if !problems.is_empty() {
+                out.push(Problems { commit: commit.id, summary: parsed.summary().to_string(), problems });
+            }
+        }
+        Ok(out)
+    }
+
+    /// 检查一条引用更新引入的提交，违反规则时返回 [`MonoError::policy`]，只报告最旧的一个提交
+    ///
+    /// `push_options` 为推送者的推送选项，包含 `bypass_option` 时跳过检查。
+    pub fn check(&self, repo: &Repository, update: &RefUpdate, push_options: &[String]) -> MonoResult<()> {
+        if update.is_delete() || !self.applies_to(&update.name) {
+            return Ok(());
+        }
+        let (tip, object_type) = repo.peel(&update.new)?;
+        if object_type != ObjectType::Commit {
+            return Ok(());
+        }
+        let Some(problems) = self.lint_range(repo, &[tip], &policy::ref_commits(repo)?)?.pop() else {
+            return Ok(());
+        };
+        if let Some(option) = self.bypass_option.as_ref().filter(|option| push_options.contains(option)) {
+            tracing::warn!(name = %update.name, commit = %problems.commit, %option, "commit lint bypassed by push option");
+            return Ok(());
+        }
+        Err(MonoError::policy(PolicyViolation {
+            rule: RULE.to_string(),
+            refname: update.name.clone(),
+            paths: Vec::new(),
+            reason: format!("commit {}: {}", &problems.commit.to_hex()[..7], problems.problems.join("; ")),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::errors::MonoErrorKind;
+    use crate::test_utils::{commit_files, init_repo};
+
+    fn commit(message: &str) -> Commit {
+        let (_dir, repo) = init_repo();
+        let id = commit_files(&repo, &[("a", b"1")], &[], message);
+        repo.read_commit(&id).unwrap()
+    }
+
+    /// 测试各条规则与默认不检查合并提交
+    #[test]
+    fn test_lint() {
+        let config = CommitLintConfig {
+            conventional: true,
+            ticket_pattern: Some("[A-Z]+-[0-9]+".to_string()),
+            max_subject_length: 40,
+            require_sign_off: true,
+            ..CommitLintConfig::default()
+        };
+        let lint = CommitLint::from_config(&config).unwrap().unwrap();
+        let good = "feat(search)!: index symbols\n\nRefs: MONO-12\nSigned-off-by: Alice <alice@example.com>\n";
+        assert!(lint.lint(&commit(good)).is_empty());
+        assert_eq!(
+            lint.lint(&commit("Add a feature that is described in far too many words\n")),
+            [
+                "subject is not a conventional commit: <type>(<scope>): <description>",
+                "message does not reference a ticket matching [A-Z]+-[0-9]+",
+                "subject is 53 characters, longer than 40",
+                "missing Signed-off-by trailer",
+            ]
+        );
+        let wip = "wip: MONO-1\n\nSigned-off-by: Alice <alice@example.com>\n";
+        assert_eq!(lint.lint(&commit(wip))[0], format!("type 'wip' is not one of {}", DEFAULT_TYPES.join(", ")));
+
+        let mut merge = commit("Merge branch 'feature'");
+        merge.parents = vec![ObjectId::ZERO, ObjectId::ZERO];
+        assert!(lint.lint(&merge).is_empty());
+        assert!(CommitLint::from_config(&CommitLintConfig::default()).unwrap().is_none());
+    }
+
+    /// 测试推送时只检查受保护引用上新引入的提交
+    #[test]
+    fn test_check() {
+        let (_dir, repo) = init_repo();
+        let config = CommitLintConfig { require_sign_off: true, refs: vec!["refs/heads/main".to_string()], ..CommitLintConfig::default() };
+        let lint = CommitLint::from_config(&config).unwrap().unwrap();
+        let base = commit_files(&repo, &[("a", b"1")], &[], "legacy commit");
+        repo.refs()
+            .update(&[RefUpdate { name: "refs/heads/main".to_string(), old: ObjectId::ZERO, new: base }])
+            .unwrap();
+        let unsigned = commit_files(&repo, &[("a", b"2")], &[base], "change a");
+        let signed = commit_files(&repo, &[("a", b"3")], &[unsigned], "change a again\n\nSigned-off-by: Bob <bob@example.com>\n");
+
+        let update = RefUpdate { name: "refs/heads/main".to_string(), old: base, new: signed };
+        let err = lint.check(&repo, &update, &[]).unwrap_err();
+        let MonoErrorKind::Policy(violation) = err.kind() else {
+            panic!("unexpected error: {}", err);
+        };
+        assert_eq!(violation.reason, format!("commit {}: missing Signed-off-by trailer", &unsigned.to_hex()[..7]));
+        let feature = RefUpdate { name: "refs/heads/feature".to_string(), ..update };
+        lint.check(&repo, &feature, &[]).unwrap();
+        let problems = lint.lint_range(&repo, &[signed], &[unsigned]).unwrap();
+        assert!(problems.is_empty());
+    }
+}
@@ -4,19 +4,258 @@
 //! 提供了错误类型定义、错误转换和错误处理的相关功能。
 //!
 
+use std::backtrace::Backtrace;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use serde::Serialize;
 use thiserror::Error;
+use tracing_error::SpanTrace;
 use anyhow::anyhow;
 
+/// 是否需要为错误采集调试信息
+///
+/// 设置了 `MONO_DEBUG` 或 `RUST_BACKTRACE` 且取值不为 `0` 时启用
+pub fn debug_enabled() -> bool {
+    ["MONO_DEBUG", "RUST_BACKTRACE"]
+        .iter()
+        .any(|var| std::env::var(var).is_ok_and(|v| !v.is_empty() && v != "0"))
+}
+
+/// 错误输出格式
+#[derive(clap::ValueEnum, Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ErrorFormat {
+    /// 人类可读的文本格式
+    #[default]
+    Text,
+    /// 便于 CI 和 IDE 插件解析的 JSON 格式
+    Json,
+}
+
+/// 当前进程使用的错误输出格式，由命令行参数 `--error-format` 设置
+static ERROR_FORMAT: AtomicU8 = AtomicU8::new(0);
+
+/// 设置全局错误输出格式
+pub fn set_error_format(format: ErrorFormat) {
+    ERROR_FORMAT.store(format as u8, Ordering::Relaxed);
+}
+
+/// 获取全局错误输出格式
+pub fn error_format() -> ErrorFormat {
+    match ERROR_FORMAT.load(Ordering::Relaxed) {
+        1 => ErrorFormat::Json,
+        _ => ErrorFormat::Text,
+    }
+}
+
+/// 错误类别
+///
+/// 调用者可以通过匹配类别来区分不同的错误，而不需要比较错误信息字符串。
+/// `Internal` 用于承载尚未迁移到具体类别的 anyhow::Error。
+#[derive(Error, Debug)]
+pub enum MonoErrorKind {
+    /// 文件系统或其他 IO 错误
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    /// 配置缺失或非法
+    #[error("Config error: {0}")]
+    Config(String),
+    /// 传输协议错误
+    #[error("Protocol error: {0}")]
+    Protocol(String),
+    /// 对象或引用存储错误
+    #[error("Storage error: {0}")]
+    Storage(String),
+    /// 认证或授权失败
+    #[error("Auth error: {0}")]
+    Auth(String),
+    /// 命令行用法错误
+    #[error("{0}")]
+    Usage(String),
+    /// 请求的对象、引用或路径不存在
+    #[error("Not found: {0}")]
+    NotFound(String),
+    /// 远端服务暂时不可用，例如超时或返回 5xx，可以稍后重试
+    #[error("Service unavailable: {0}")]
+    Unavailable(String),
+    /// 推送违反了仓库的策略规则
+    #[error("Policy violation: {0}")]
+    Policy(Box<PolicyViolation>),
+    /// 内部错误
+    #[error(transparent)]
+    Internal(#[from] anyhow::Error),
+}
+
+impl MonoErrorKind {
+    /// 返回稳定的错误标识符
+    ///
+    /// 标识符用于机器可读的输出，一经发布不应修改
+    pub fn id(&self) -> &'static str {
+        match self {
+            MonoErrorKind::Io(_) => "io",
+            MonoErrorKind::Config(_) => "config",
+            MonoErrorKind::Protocol(_) => "protocol",
+            MonoErrorKind::Storage(_) => "storage",
+            MonoErrorKind::Auth(_) => "auth",
+            MonoErrorKind::Usage(_) => "usage",
+            MonoErrorKind::NotFound(_) => "not_found",
+            MonoErrorKind::Unavailable(_) => "unavailable",
+            MonoErrorKind::Policy(_) => "policy",
+            MonoErrorKind::Internal(_) => "internal",
+        }
+    }
+}
+
+/// 稳定的退出码注册表
+///
+/// 各错误类别对应固定的退出码，脚本可以依赖这些数值。
+/// 除 `Usage` 沿用 clap 的约定、`Internal` 沿用历史上的 101 外，其余参考 sysexits.h：
+///
+/// | 退出码 | 名称          | 含义 |
+/// |--------|---------------|------|
+/// | 0      | `Success`     | 成功 |
+/// | 1      | `Failure`     | 未归类的一般失败 |
+/// | 2      | `Usage`       | 命令行用法错误 |
+/// | 66     | `NotFound`    | 对象、引用或路径不存在 |
+/// | 73     | `Storage`     | 对象或引用存储错误 |
+/// | 74     | `Io`          | IO 错误 |
+/// | 75     | `Unavailable` | 服务暂时不可用，可稍后重试 |
+/// | 76     | `Protocol`    | 传输协议错误 |
+/// | 77     | `Auth`        | 认证或授权失败 |
+/// | 78     | `Config`      | 配置错误 |
+/// | 79     | `Policy`      | 违反仓库策略 |
+/// | 101    | `Internal`    | 内部错误 |
+/// | 143    | `Terminated`  | 服务收到 SIGTERM 后排空连接退出（128 + 15） |
+///
+/// 新增退出码只能追加，已有数值不得修改。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(i32)]
+pub enum ExitCode {
+    Success = 0,
+    Failure = 1,
+    Usage = 2,
+    NotFound = 66,
+    Storage = 73,
+    Io = 74,
+    Unavailable = 75,
+    Protocol = 76,
+    Auth = 77,
+    Config = 78,
+    Policy = 79,
+    Internal = 101,
+    Terminated = 143,
+}
+
+impl ExitCode {
+    /// 返回退出码数值
+    pub fn code(self) -> i32 {
+        self as i32
+    }
+}
+
+impl From<&MonoErrorKind> for ExitCode {
+    fn from(kind: &MonoErrorKind) -> ExitCode {
+        match kind {
+            MonoErrorKind::Io(_) => ExitCode::Io,
+            MonoErrorKind::Config(_) => ExitCode::Config,
+            MonoErrorKind::Protocol(_) => ExitCode::Protocol,
+            MonoErrorKind::Storage(_) => ExitCode::Storage,
+            MonoErrorKind::Auth(_) => ExitCode::Auth,
+            MonoErrorKind::Usage(_) => ExitCode::Usage,
+            MonoErrorKind::NotFound(_) => ExitCode::NotFound,
+            MonoErrorKind::Unavailable(_) => ExitCode::Unavailable,
+            MonoErrorKind::Policy(_) => ExitCode::Policy,
+            MonoErrorKind::Internal(_) => ExitCode::Internal,
+        }
+    }
+}
+
+/// 违反的策略规则，调用方可以据此向用户说明需要满足的条件
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct PolicyViolation {
+    /// 规则名
+    pub rule: String,
+    /// 被拒绝更新的引用
+    pub refname: String,
+    /// 触发规则的路径
+    pub paths: Vec<String>,
+    /// 未满足的条件
+    pub reason: String,
+}
+
+impl std::fmt::Display for PolicyViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} violates rule '{}': {}", self.refname, self.rule, self.reason)?;
+        match self.paths.as_slice() {
+            [] => Ok(()),
+            [path] => write!(f, " ({})", path),
+            [path, rest @ ..] => write!(f, " ({} and {} more)", path, rest.len()),
+        }
+    }
+}
+
+/// 错误的 JSON 表示
+#[derive(Serialize, Debug)]
+pub struct ErrorReport {
+    /// 错误代码
+    pub code: i32,
+    /// 稳定的错误标识符，见 [`MonoErrorKind::id`]
+    pub id: &'static str,
+    /// 完整的错误信息
+    pub message: String,
+    /// 错误链，由外到内依次为上下文、错误本身以及底层错误
+    pub chain: Vec<String>,
+    /// 违反的策略规则，仅 `policy` 类错误输出
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub policy: Option<PolicyViolation>,
+    /// span trace 与调用栈，仅在启用调试信息时输出
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub debug: Option<String>,
+}
+
 /// MonoEngine 的主要错误类型
 /// 
 /// 该结构体封装了应用程序中可能出现的各种错误，
-/// 包含错误信息和对应的错误代码
+/// 包含错误类别、上下文信息和对应的错误代码
 #[derive(Error, Debug)]
 pub struct MonoError {
-    /// 可选的错误信息，使用 anyhow::Error 提供丰富的错误上下文
-    pub error: Option<anyhow::Error>,
+    /// 错误类别及其详细信息
+    pub kind: MonoErrorKind,
+    /// 上下文信息，按添加顺序保存，最后添加的位于最外层
+    pub context: Vec<String>,
     /// 错误代码，用于程序退出时的状态码
     pub code: i32,
+    /// 调试信息，仅在启用调试信息时采集
+    pub trace: Option<Box<ErrorTrace>>,
+}
+
+/// 创建错误时采集的调试信息
+#[derive(Debug)]
+pub struct ErrorTrace {
+    /// 创建错误时的调用栈
+    pub backtrace: Backtrace,
+    /// 创建错误时所处的 tracing span
+    pub span_trace: SpanTrace,
+}
+
+impl ErrorTrace {
+    /// 采集当前位置的调用栈与 span trace
+    pub fn capture() -> ErrorTrace {
+        ErrorTrace {
+            backtrace: Backtrace::force_capture(),
+            span_trace: SpanTrace::capture(),
+        }
+    }
+}
+
+impl std::fmt::Display for ErrorTrace {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let span_trace = self.span_trace.to_string();
+        if !span_trace.is_empty() {
+            write!(f, "Span trace:\n{}\n\n", span_trace)?;
+        }
+        write!(f, "Backtrace:\n{}", self.backtrace)
+    }
 }
 
 impl MonoError {
@@ -31,22 +270,181 @@ impl MonoError {
     /// 
     /// 返回新创建的 MonoError 实例
     pub fn new(error: anyhow::Error, code: i32) -> MonoError {
+        MonoError::from_kind(MonoErrorKind::Internal(error), code)
+    }
+
+    /// 根据错误类别创建 MonoError 实例
+    ///
+    /// # 参数
+    ///
+    /// * `kind` - 错误类别
+    /// * `code` - 错误代码
+    ///
+    /// # 返回值
+    ///
+    /// 返回新创建的 MonoError 实例
+    pub fn from_kind(kind: MonoErrorKind, code: i32) -> MonoError {
         MonoError {
-            error: Some(error),
+            kind,
+            context: Vec::new(),
             code,
+            trace: debug_enabled().then(|| Box::new(ErrorTrace::capture())),
+        }
+    }
+
+    /// 创建配置错误
+    pub fn config(msg: impl Into<String>) -> MonoError {
+        MonoErrorKind::Config(msg.into()).into()
+    }
+
+    /// 创建协议错误
+    pub fn protocol(msg: impl Into<String>) -> MonoError {
+        MonoErrorKind::Protocol(msg.into()).into()
+    }
+
+    /// 创建存储错误
+    pub fn storage(msg: impl Into<String>) -> MonoError {
+        MonoErrorKind::Storage(msg.into()).into()
+    }
+
+    /// 创建认证错误
+    pub fn auth(msg: impl Into<String>) -> MonoError {
+        MonoErrorKind::Auth(msg.into()).into()
+    }
+
+    /// 创建用法错误
+    pub fn usage(msg: impl Into<String>) -> MonoError {
+        MonoErrorKind::Usage(msg.into()).into()
+    }
+
+    /// 创建资源不存在错误
+    pub fn not_found(msg: impl Into<String>) -> MonoError {
+        MonoErrorKind::NotFound(msg.into()).into()
+    }
+
+    /// 创建服务暂时不可用错误
+    pub fn unavailable(msg: impl Into<String>) -> MonoError {
+        MonoErrorKind::Unavailable(msg.into()).into()
+    }
+
+    /// 创建策略违规错误
+    pub fn policy(violation: PolicyViolation) -> MonoError {
+        MonoErrorKind::Policy(Box::new(violation)).into()
+    }
+
+    /// 判断错误是否为暂时性故障，重试后可能成功
+    ///
+    /// 仅 `Unavailable` 以及超时、连接中断等 IO 错误被视为可重试，
+    /// 其余类别重试也不会改变结果。
+    pub fn is_retryable(&self) -> bool {
+        use std::io::ErrorKind;
+
+        match &self.kind {
+            MonoErrorKind::Unavailable(_) => true,
+            MonoErrorKind::Io(err) => matches!(
+                err.kind(),
+                ErrorKind::TimedOut
+                    | ErrorKind::Interrupted
+                    | ErrorKind::WouldBlock
+                    | ErrorKind::ConnectionReset
+                    | ErrorKind::ConnectionAborted
+                    | ErrorKind::ConnectionRefused
+                    | ErrorKind::BrokenPipe
+                    | ErrorKind::UnexpectedEof
+            ),
+            _ => false,
         }
     }
 
+    /// 返回错误类别
+    pub fn kind(&self) -> &MonoErrorKind {
+        &self.kind
+    }
+
+    /// 为错误附加一层上下文信息
+    pub fn context(mut self, ctx: impl Into<String>) -> MonoError {
+        self.context.push(ctx.into());
+        self
+    }
+
     /// 打印错误信息
     ///
     /// 之前该方法通过 panic! 终止程序，这会在仅需要输出错误时导致
     /// 整个应用崩溃。改为输出到标准错误，调用者可自行决定后续处理。
+    /// 标准错误已关闭时忽略写入失败；错误同时以 `debug` 级别记入日志，见 [`crate::logging`]。
     pub fn print(&self) {
-        if let Some(err) = &self.error {
-            eprintln!("{}:{}", self.code, err);
+        use std::io::Write;
+
+        tracing::debug!(code = self.code, error = %self, "command failed");
+        let mut stderr = std::io::stderr().lock();
+        let _ = writeln!(stderr, "{}:{}", self.code, self);
+        if let Some(trace) = &self.trace {
+            let _ = writeln!(stderr, "\n{}", trace);
+        }
+    }
+
+    /// 按全局错误输出格式将错误写到标准错误
+    pub fn emit(&self) {
+        use std::io::Write;
+
+        match error_format() {
+            ErrorFormat::Text => self.print(),
+            ErrorFormat::Json => {
+                tracing::debug!(code = self.code, error = %self, "command failed");
+                let _ = writeln!(std::io::stderr().lock(), "{}", self.to_json());
+            }
+        }
+    }
+
+    /// 输出错误并以错误代码退出进程
+    ///
+    /// 退出前会刷新标准输出和标准错误，避免丢失已缓冲的内容
+    pub fn exit(&self) -> ! {
+        use std::io::Write;
+
+        self.emit();
+        let _ = std::io::stdout().flush();
+        let _ = std::io::stderr().flush();
+        std::process::exit(self.code)
+    }
+
+    /// 返回由外到内的错误链
+    pub fn chain(&self) -> Vec<String> {
+        let mut chain: Vec<String> = self.context.iter().rev().cloned().collect();
+        match &self.kind {
+            MonoErrorKind::Internal(err) => chain.extend(err.chain().map(|e| e.to_string())),
+            kind => {
+                chain.push(kind.to_string());
+                let mut source = std::error::Error::source(kind);
+                while let Some(err) = source {
+                    chain.push(err.to_string());
+                    source = err.source();
+                }
+            }
+        }
+        chain
+    }
+
+    /// 生成错误报告
+    pub fn report(&self) -> ErrorReport {
+        ErrorReport {
+            code: self.code,
+            id: self.kind.id(),
+            message: self.to_string(),
+            chain: self.chain(),
+            policy: match &self.kind {
+                MonoErrorKind::Policy(violation) => Some(violation.as_ref().clone()),
+                _ => None,
+            },
+            debug: self.trace.as_ref().map(|trace| trace.to_string()),
         }
     }
 
+    /// 序列化为单行 JSON 字符串
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(&self.report()).unwrap_or_else(|_| self.to_string())
+    }
+
     /// 创建未知子命令错误
     /// 
     /// # 参数
@@ -57,10 +455,7 @@ impl MonoError {
     /// 
     /// 返回包含未知子命令错误信息的 MonoError
     pub fn _unknown_subcommand(cmd: impl AsRef<str>) -> MonoError {
-        MonoError {
-            error: anyhow!("Unknown subcommand: {}", cmd.as_ref()).into(),
-            code: 1,
-        }
+        MonoErrorKind::Usage(format!("Unknown subcommand: {}", cmd.as_ref())).into()
     }
 
     /// 创建带有自定义消息的错误
@@ -73,19 +468,29 @@ impl MonoError {
     /// 
     /// 返回包含自定义消息的 MonoError
     pub fn _with_message(msg: impl AsRef<str>) -> MonoError {
-        MonoError {
-            error: anyhow!("Error Message: {}", msg.as_ref()).into(),
-            code: 0,
-        }
+        MonoError::new(anyhow!("Error Message: {}", msg.as_ref()), ExitCode::Failure.code())
     }
 }
 
 /// 为 MonoError 实现 Display trait
 /// 
-/// 允许 MonoError 被格式化为字符串输出
+/// 允许 MonoError 被格式化为字符串输出，上下文信息由外到内依次输出
 impl std::fmt::Display for MonoError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "{}", self.error.as_ref().unwrap())
+        for ctx in self.context.iter().rev() {
+            write!(f, "{}: ", ctx)?;
+        }
+        write!(f, "{}", self.kind)
+    }
+}
+
+/// 从错误类别转换为 MonoError
+///
+/// 错误代码由 [`ExitCode`] 注册表决定
+impl From<MonoErrorKind> for MonoError {
+    fn from(kind: MonoErrorKind) -> MonoError {
+        let code = ExitCode::from(&kind).code();
+        MonoError::from_kind(kind, code)
     }
 }
 
@@ -94,7 +499,14 @@ impl std::fmt::Display for MonoError {
 /// 默认错误代码为 101
 impl From<anyhow::Error> for MonoError {
     fn from(err: anyhow::Error) -> MonoError {
-        MonoError::new(err, 101)
+        MonoError::new(err, ExitCode::Internal.code())
+    }
+}
+
+/// 从 std::io::Error 转换为 MonoError
+impl From<std::io::Error> for MonoError {
+    fn from(err: std::io::Error) -> MonoError {
+        MonoErrorKind::Io(err).into()
     }
 }
 
@@ -104,7 +516,14 @@ impl From<anyhow::Error> for MonoError {
 impl From<clap::Error> for MonoError {
     fn from(err: clap::Error) -> MonoError {
         let code = err.exit_code();
-        MonoError::new(err.into(), code)
+        MonoError::from_kind(MonoErrorKind::Usage(err.to_string()), code)
+    }
+}
+
+/// 从 russh::Error 转换为 MonoError
+impl From<russh::Error> for MonoError {
+    fn from(err: russh::Error) -> MonoError {
+        MonoErrorKind::Protocol(format!("ssh: {}", err)).into()
     }
 }
 
@@ -119,7 +538,7 @@ mod tests {
         let error = anyhow!("测试错误");
         let mono_error = MonoError::new(error, 42);
         
-        assert!(mono_error.error.is_some());
+        assert!(matches!(mono_error.kind, MonoErrorKind::Internal(_)));
         assert_eq!(mono_error.code, 42);
         assert!(mono_error.to_string().contains("测试错误"));
     }
@@ -129,8 +548,8 @@ mod tests {
     fn test_unknown_subcommand() {
         let mono_error = MonoError::_unknown_subcommand("invalid_cmd");
         
-        assert!(mono_error.error.is_some());
-        assert_eq!(mono_error.code, 1);
+        assert!(matches!(mono_error.kind, MonoErrorKind::Usage(_)));
+        assert_eq!(mono_error.code, ExitCode::Usage.code());
         assert!(mono_error.to_string().contains("Unknown subcommand: invalid_cmd"));
     }
 
@@ -139,8 +558,8 @@ mod tests {
     fn test_with_message() {
         let mono_error = MonoError::_with_message("自定义错误消息");
         
-        assert!(mono_error.error.is_some());
-        assert_eq!(mono_error.code, 0);
+        assert!(matches!(mono_error.kind, MonoErrorKind::Internal(_)));
+        assert_eq!(mono_error.code, ExitCode::Failure.code());
         assert!(mono_error.to_string().contains("Error Message: 自定义错误消息"));
     }
 
@@ -160,7 +579,7 @@ mod tests {
         let anyhow_error = anyhow!("anyhow 错误");
         let mono_error: MonoError = anyhow_error.into();
         
-        assert!(mono_error.error.is_some());
+        assert!(matches!(mono_error.kind, MonoErrorKind::Internal(_)));
         assert_eq!(mono_error.code, 101);
         assert!(mono_error.to_string().contains("anyhow 错误"));
     }
@@ -181,7 +600,7 @@ mod tests {
         let exit_code = clap_error.exit_code();
         let mono_error: MonoError = clap_error.into();
 
-        assert!(mono_error.error.is_some());
+        assert!(matches!(mono_error.kind, MonoErrorKind::Usage(_)));
         // clap 错误的代码应该等于其 exit_code
         assert_eq!(mono_error.code, exit_code);
     }
@@ -193,7 +612,7 @@ mod tests {
         let wrapped_error = root_cause.context("包装错误");
         let mono_error = MonoError::new(wrapped_error, 500);
         
-        assert!(mono_error.error.is_some());
+        assert!(matches!(mono_error.kind, MonoErrorKind::Internal(_)));
         assert_eq!(mono_error.code, 500);
         let error_string = mono_error.to_string();
         assert!(error_string.contains("包装错误"));
@@ -206,8 +625,8 @@ mod tests {
         let error2 = MonoError::_unknown_subcommand("cmd");
         let error3 = MonoError::from(anyhow!("错误3"));
 
-        assert_eq!(error1.code, 0);
-        assert_eq!(error2.code, 1);
+        assert_eq!(error1.code, 1);
+        assert_eq!(error2.code, 2);
         assert_eq!(error3.code, 101);
     }
 
@@ -215,9 +634,146 @@ mod tests {
     #[test]
     fn test_print_does_not_panic() {
         let error = MonoError::_with_message("打印测试");
-        let result = std::panic::catch_unwind(|| {
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
             error.print();
-        });
+        }));
         assert!(result.is_ok());
     }
+
+    /// 测试从 std::io::Error 的转换
+    #[test]
+    fn test_from_io_error() {
+        let io_error = std::io::Error::new(std::io::ErrorKind::NotFound, "缺少文件");
+        let mono_error: MonoError = io_error.into();
+
+        assert!(matches!(mono_error.kind(), MonoErrorKind::Io(_)));
+        assert!(mono_error.to_string().contains("缺少文件"));
+    }
+
+    /// 测试按类别构造错误
+    #[test]
+    fn test_kind_constructors() {
+        assert!(matches!(MonoError::config("c").kind, MonoErrorKind::Config(_)));
+        assert!(matches!(MonoError::protocol("p").kind, MonoErrorKind::Protocol(_)));
+        assert!(matches!(MonoError::storage("s").kind, MonoErrorKind::Storage(_)));
+        assert!(matches!(MonoError::auth("a").kind, MonoErrorKind::Auth(_)));
+        assert!(matches!(MonoError::usage("u").kind, MonoErrorKind::Usage(_)));
+        assert!(matches!(MonoError::not_found("n").kind, MonoErrorKind::NotFound(_)));
+    }
+
+    /// 测试上下文信息由外到内输出
+    #[test]
+    fn test_context() {
+        let mono_error = MonoError::storage("写入失败")
+            .context("保存对象")
+            .context("执行推送");
+
+        assert_eq!(mono_error.context.len(), 2);
+        assert_eq!(
+            mono_error.to_string(),
+            "执行推送: 保存对象: Storage error: 写入失败"
+        );
+    }
+
+    /// 测试 JSON 输出包含代码、标识符和错误链
+    #[test]
+    fn test_to_json() {
+        let mono_error = MonoError::new(anyhow!("根本原因").context("包装错误"), 101)
+            .context("执行命令");
+        let value: serde_json::Value = serde_json::from_str(&mono_error.to_json()).unwrap();
+
+        assert_eq!(value["code"], 101);
+        assert_eq!(value["id"], "internal");
+        assert_eq!(value["message"], "执行命令: 包装错误");
+        assert_eq!(
+            value["chain"],
+            serde_json::json!(["执行命令", "包装错误", "根本原因"])
+        );
+    }
+
+    /// 测试错误标识符
+    #[test]
+    fn test_kind_id() {
+        assert_eq!(MonoError::usage("u").kind().id(), "usage");
+        assert_eq!(MonoError::not_found("n").kind().id(), "not_found");
+        let io_error: MonoError = std::io::Error::other("io").into();
+        assert_eq!(io_error.kind().id(), "io");
+    }
+
+    /// 测试退出码注册表的数值保持稳定
+    #[test]
+    fn test_exit_code_registry() {
+        assert_eq!(ExitCode::Success.code(), 0);
+        assert_eq!(ExitCode::Failure.code(), 1);
+        assert_eq!(ExitCode::Usage.code(), 2);
+        assert_eq!(ExitCode::NotFound.code(), 66);
+        assert_eq!(ExitCode::Storage.code(), 73);
+        assert_eq!(ExitCode::Io.code(), 74);
+        assert_eq!(ExitCode::Unavailable.code(), 75);
+        assert_eq!(ExitCode::Protocol.code(), 76);
+        assert_eq!(ExitCode::Auth.code(), 77);
+        assert_eq!(ExitCode::Config.code(), 78);
+        assert_eq!(ExitCode::Policy.code(), 79);
+        assert_eq!(ExitCode::Internal.code(), 101);
+        assert_eq!(ExitCode::Terminated.code(), 143);
+    }
+
+    /// 测试错误类别映射到对应的退出码
+    #[test]
+    fn test_kind_exit_code() {
+        assert_eq!(MonoError::config("c").code, ExitCode::Config.code());
+        assert_eq!(MonoError::protocol("p").code, ExitCode::Protocol.code());
+        assert_eq!(MonoError::storage("s").code, ExitCode::Storage.code());
+        assert_eq!(MonoError::auth("a").code, ExitCode::Auth.code());
+        assert_eq!(MonoError::not_found("n").code, ExitCode::NotFound.code());
+        let io_error: MonoError = std::io::Error::other("io").into();
+        assert_eq!(io_error.code, ExitCode::Io.code());
+    }
+
+    /// 测试策略违规的文本与 JSON 输出
+    #[test]
+    fn test_policy_violation() {
+        let violation = PolicyViolation {
+            rule: "infra".to_string(),
+            refname: "refs/heads/main".to_string(),
+            paths: vec!["infra/a.tf".to_string(), "infra/b.tf".to_string()],
+            reason: "requires 2 approvals, found 1".to_string(),
+        };
+        let mono_error = MonoError::policy(violation);
+        assert_eq!(mono_error.code, ExitCode::Policy.code());
+        assert_eq!(
+            mono_error.to_string(),
+            "Policy violation: refs/heads/main violates rule 'infra': requires 2 approvals, found 1 (infra/a.tf and 1 more)"
+        );
+        let value: serde_json::Value = serde_json::from_str(&mono_error.to_json()).unwrap();
+        assert_eq!(value["id"], "policy");
+        assert_eq!(value["policy"]["rule"], "infra");
+        assert_eq!(value["policy"]["paths"][1], "infra/b.tf");
+        assert!(MonoError::usage("u").report().policy.is_none());
+    }
+
+    /// 测试调试信息的渲染
+    #[test]
+    fn test_error_trace() {
+        let mut mono_error = MonoError::storage("s");
+        mono_error.trace = None;
+        assert!(mono_error.report().debug.is_none());
+
+        mono_error.trace = Some(Box::new(ErrorTrace::capture()));
+        let debug = mono_error.report().debug.unwrap();
+        assert!(debug.contains("Backtrace:"));
+    }
+
+    /// 测试可重试错误的分类
+    #[test]
+    fn test_is_retryable() {
+        use std::io::{Error, ErrorKind};
+
+        assert!(MonoError::unavailable("503").is_retryable());
+        assert!(MonoError::from(Error::new(ErrorKind::TimedOut, "超时")).is_retryable());
+        assert!(MonoError::from(Error::new(ErrorKind::ConnectionReset, "重置")).is_retryable());
+        assert!(!MonoError::from(Error::new(ErrorKind::PermissionDenied, "拒绝")).is_retryable());
+        assert!(!MonoError::not_found("n").is_retryable());
+        assert!(!MonoError::from(anyhow!("内部错误")).is_retryable());
+    }
 }
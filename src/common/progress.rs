@@ -0,0 +1,290 @@
+//! 长时间操作的进度显示
+//!
+//! 克隆、获取、重新打包与垃圾回收等操作在标准错误上报告进度。已知总量时显示进度条、
+//! 百分比与预计剩余时间，例如
+//!
+//! ```text
+//! Receiving objects:  45% [#########           ] (4500/10000), 12.3 MiB | 3.1 MiB/s, ETA 4s
+//! ```
+//!
+//! 总量未知时显示旋转指示与已完成的数量。输出方式由全局参数 `--progress` 决定（见
+//! [`ProgressFormat`]），`--quiet` 等同于 `--progress never`；`json` 每次更新输出一行 JSON，
+//! 便于 CI 与 IDE 插件解析。多个线程可以同时更新同一进度，重绘有最短间隔。
+
+use std::io::{IsTerminal, Write};
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+/// 终端上两次重绘的最短间隔
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+/// 两行 JSON 进度之间的最短间隔
+const JSON_INTERVAL: Duration = Duration::from_millis(500);
+const BAR_WIDTH: usize = 20;
+const SPINNER: [char; 4] = ['|', '/', '-', '\\'];
+
+/// 进度的输出方式
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ProgressFormat {
+    /// 标准错误是终端时显示进度条
+    #[default]
+    Auto,
+    /// 每次更新在标准错误输出一行 JSON
+    Json,
+    /// 不显示进度
+    Never,
+}
+
+/// 当前进程使用的进度输出方式，由命令行参数 `--progress` 与 `--quiet` 设置
+static PROGRESS_FORMAT: AtomicU8 = AtomicU8::new(0);
+
+/// 设置全局进度输出方式
+pub fn set_progress_format(format: ProgressFormat) {
+    PROGRESS_FORMAT.store(format as u8, Ordering::Relaxed);
+}
+
+/// 获取全局进度输出方式
+pub fn progress_format() -> ProgressFormat {
+    match PROGRESS_FORMAT.load(Ordering::Relaxed) {
+        1 => ProgressFormat::Json,
+        2 => ProgressFormat::Never,
+        _ => ProgressFormat::Auto,
+    }
+}
+
+/// 实际的输出目标，保存上次输出的时间
+enum Output {
+    Hidden,
+    Text(Mutex<Option<Instant>>),
+    Json(Mutex<Option<Instant>>),
+}
+
+/// `--progress json` 输出的一行
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct ProgressEvent<'a> {
+    pub title: &'a str,
+    pub done: u64,
+    /// 总量未知时为 None
+    pub total: Option<u64>,
+    pub bytes: u64,
+    pub elapsed_ms: u64,
+    /// 预计剩余时间，无法估计时为 None
+    pub eta_ms: Option<u64>,
+    pub finished: bool,
+}
+
+/// 一项操作的进度
+pub struct Progress {
+    title: String,
+    total: Option<u64>,
+    done: AtomicU64,
+    bytes: AtomicU64,
+    started: Instant,
+    output: Output,
+}
+
+impl Progress {
+    /// 总量已知的进度，按全局输出方式显示
+    pub fn new(title: impl Into<String>, total: u64) -> Progress {
+        Progress::with_total(title.into(), Some(total))
+    }
+
+    /// 总量未知的进度，显示旋转指示
+    pub fn spinner(title: impl Into<String>) -> Progress {
+        Progress::with_total(title.into(), None)
+    }
+
+    /// 不显示的进度，只计数
+    pub fn hidden(total: u64) -> Progress {
+        Progress {
+            output: Output::Hidden,
+            ..Progress::with_total(String::new(), Some(total))
+        }
+    }
+
+    fn with_total(title: String, total: Option<u64>) -> Progress {
+        let output = match progress_format() {
+            ProgressFormat::Auto if std::io::stderr().is_terminal() => Output::Text(Mutex::new(None)),
+            ProgressFormat::Json => Output::Json(Mutex::new(None)),
+            _ => Output::Hidden,
+        };
+        Progress {
+            title,
+            total,
+            done: AtomicU64::new(0),
+            bytes: AtomicU64::new(0),
+            started: Instant::now(),
+            output,
+        }
+    }
+
+    /// 完成 `n` 个单位
+    pub fn inc(&self, n: u64) {
+        self.done.fetch_add(n, Ordering::Relaxed);
+        self.update();
+    }
+
+    /// 记录处理的字节数，与完成的数量一起显示
+    pub fn add_bytes(&self, n: u64) {
+        self.bytes.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub fn done(&self) -> u64 {
+        self.done.load(Ordering::Relaxed)
+    }
+
+    /// 显示最终的进度
+    pub fn finish(&self) {
+        match &self.output {
+            Output::Hidden => {}
+            Output::Text(_) => self.draw_text(true),
+            Output::Json(_) => self.print_json(true),
+        }
+    }
+
+    fn update(&self) {
+        let (last, interval) = match &self.output {
+            Output::Hidden => return,
+            Output::Text(last) => (last, REDRAW_INTERVAL),
+            Output::Json(last) => (last, JSON_INTERVAL),
+        };
+        // 其他线程正在输出时跳过
+        let Ok(mut last) = last.try_lock() else {
+            return;
+        };
+        if last.is_some_and(|last| last.elapsed() < interval) {
+            return;
+        }
+        *last = Some(Instant::now());
+        match &self.output {
+            Output::Text(_) => self.draw_text(false),
+            _ => self.print_json(false),
+        }
+    }
+
+    /// 当前的进度
+    pub fn event(&self, finished: bool) -> ProgressEvent<'_> {
+        let done = self.done();
+        let elapsed = self.started.elapsed();
+        let eta = match self.total {
+            Some(total) if !finished && done > 0 && done < total => {
+                Some(elapsed.mul_f64((total - done) as f64 / done as f64))
+            }
+            _ => None,
+        };
+        ProgressEvent {
+            title: &self.title,
+            done,
+            total: self.total,
+            bytes: self.bytes.load(Ordering::Relaxed),
+            elapsed_ms: elapsed.as_millis() as u64,
+            eta_ms: eta.map(|eta| eta.as_millis() as u64),
+            finished,
+        }
+    }
+
+    /// 终端上显示的一行，不含回车与换行
+    pub fn line(&self, finished: bool) -> String {
+        let event = self.event(finished);
+        let mut line = format!("{}: ", event.title);
+        match event.total {
+            Some(total) => {
+                let percent = (event.done * 100).checked_div(total).unwrap_or(100).min(100);
+                let filled = percent as usize * BAR_WIDTH / 100;
+                line.push_str(&format!(
+                    "{:3}% [{}{}] ({}/{})",
+                    percent,
+                    "#".repeat(filled),
+                    " ".repeat(BAR_WIDTH - filled),
+                    event.done,
+                    total
+                ));
+            }
+            None if finished => line.push_str(&event.done.to_string()),
+            None => {
+                let spin = SPINNER[(event.elapsed_ms / REDRAW_INTERVAL.as_millis() as u64) as usize % SPINNER.len()];
+                line.push_str(&format!("{} {}", spin, event.done));
+            }
+        }
+        if event.bytes > 0 {
+            line.push_str(&format!(", {}", format_bytes(event.bytes)));
+            let secs = event.elapsed_ms as f64 / 1000.0;
+            if secs > 0.0 {
+                line.push_str(&format!(" | {}/s", format_bytes((event.bytes as f64 / secs) as u64)));
+            }
+        }
+        match event.eta_ms {
+            _ if finished => line.push_str(", done."),
+            Some(eta) => line.push_str(&format!(", ETA {}", format_duration(Duration::from_millis(eta)))),
+            None => {}
+        }
+        line
+    }
+
+    fn draw_text(&self, finished: bool) {
+        let mut stderr = std::io::stderr().lock();
+        // 清除上次较长的内容
+        let _ = write!(stderr, "\r{}\x1b[K{}", self.line(finished), if finished { "\n" } else { "" });
+        let _ = stderr.flush();
+    }
+
+    fn print_json(&self, finished: bool) {
+        if let Ok(json) = serde_json::to_string(&self.event(finished)) {
+            eprintln!("{}", json);
+        }
+    }
+}
+
+/// 以 1024 为进制的字节数，例如 `12.3 MiB`
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["KiB", "MiB", "GiB", "TiB", "PiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+/// 精确到秒的时长，例如 `4s`、`2m05s`、`1h02m`
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match secs {
+        0..60 => format!("{}s", secs),
+        60..3600 => format!("{}m{:02}s", secs / 60, secs % 60),
+        _ => format!("{}h{:02}m", secs / 3600, secs % 3600 / 60),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 测试进度行、字节数与时长的格式
+    #[test]
+    fn test_progress_line() {
+        let progress = Progress::hidden(10);
+        progress.inc(5);
+        let line = progress.line(false);
+        assert!(line.starts_with(":  50% [##########          ] (5/10), ETA "), "{}", line);
+        progress.inc(5);
+        progress.add_bytes(2048);
+        let line = progress.line(true);
+        assert!(line.starts_with(": 100% [####################] (10/10), 2.0 KiB"), "{}", line);
+        assert!(line.ends_with(", done."), "{}", line);
+        let event = progress.event(true);
+        assert_eq!((event.done, event.total, event.eta_ms, event.finished), (10, Some(10), None, true));
+
+        assert_eq!(format_bytes(512), "512 B");
+        assert_eq!(format_bytes(3 * 1024 * 1024 + 512 * 1024), "3.5 MiB");
+        assert_eq!(format_duration(Duration::from_secs(4)), "4s");
+        assert_eq!(format_duration(Duration::from_secs(125)), "2m05s");
+        assert_eq!(format_duration(Duration::from_secs(3720)), "1h02m");
+    }
+}
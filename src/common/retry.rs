@@ -0,0 +1,199 @@
+//! 重试策略模块
+//!
+//! 为网络和存储操作提供带抖动的指数退避重试，
+//! 只有 [`MonoError::is_retryable`] 判定为暂时性故障的错误才会被重试。
+
+use std::collections::hash_map::RandomState;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+
+/// 指数退避重试策略
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// 最多尝试的次数（包含第一次）
+    pub max_attempts: u32,
+    /// 第一次重试前的等待时间
+    pub initial_backoff: Duration,
+    /// 单次等待时间的上限
+    pub max_backoff: Duration,
+    /// 每次重试等待时间的增长倍数
+    pub multiplier: f64,
+    /// 抖动比例，取值 0.0 ~ 1.0，实际等待时间在 `[1 - jitter, 1] * backoff` 之间
+    pub jitter: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 5,
+            initial_backoff: Duration::from_millis(200),
+            max_backoff: Duration::from_secs(10),
+            multiplier: 2.0,
+            jitter: 0.5,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// 不进行任何重试的策略
+    pub fn none() -> Self {
+        RetryPolicy {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    /// 计算第 `attempt` 次失败后（从 1 开始）的等待时间，不含抖动
+    pub fn base_backoff(&self, attempt: u32) -> Duration {
+        let exp = self.multiplier.powi(attempt.saturating_sub(1) as i32);
+        let secs = self.initial_backoff.as_secs_f64() * exp;
+        Duration::from_secs_f64(secs.min(self.max_backoff.as_secs_f64()))
+    }
+
+    /// 计算第 `attempt` 次失败后（从 1 开始）的等待时间，包含抖动
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        self.base_backoff(attempt).mul_f64(1.0 - jitter * random_unit())
+    }
+
+    /// 同步执行操作，遇到可重试错误时按策略等待后重试
+    ///
+    /// 闭包参数为当前尝试次数（从 1 开始）
+    pub fn retry<T, F>(&self, mut op: F) -> MonoResult<T>
+    where
+        F: FnMut(u32) -> MonoResult<T>,
+    {
+        let mut attempt = 1;
+        loop {
+            match op(attempt) {
+                Err(err) if self.should_retry(&err, attempt) => {
+                    std::thread::sleep(self.backoff(attempt));
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// 异步执行操作，遇到可重试错误时按策略等待后重试
+    pub async fn retry_async<T, F, Fut>(&self, mut op: F) -> MonoResult<T>
+    where
+        F: FnMut(u32) -> Fut,
+        Fut: Future<Output = MonoResult<T>>,
+    {
+        let mut attempt = 1;
+        loop {
+            match op(attempt).await {
+                Err(err) if self.should_retry(&err, attempt) => {
+                    tokio::time::sleep(self.backoff(attempt)).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn should_retry(&self, err: &MonoError, attempt: u32) -> bool {
+        if !err.is_retryable() || attempt >= self.max_attempts {
+            return false;
+        }
+        tracing::warn!(attempt, error = %err, "transient failure, retrying");
+        true
+    }
+}
+
+/// 返回 [0, 1) 区间内的随机数，仅用于抖动，不要求密码学强度
+fn random_unit() -> f64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or_default(),
+    );
+    (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fast_policy(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(2),
+            ..Default::default()
+        }
+    }
+
+    /// 测试退避时间按倍数增长并受上限约束
+    #[test]
+    fn test_base_backoff() {
+        let policy = RetryPolicy {
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(500),
+            multiplier: 2.0,
+            ..Default::default()
+        };
+        assert_eq!(policy.base_backoff(1), Duration::from_millis(100));
+        assert_eq!(policy.base_backoff(2), Duration::from_millis(200));
+        assert_eq!(policy.base_backoff(3), Duration::from_millis(400));
+        assert_eq!(policy.base_backoff(4), Duration::from_millis(500));
+    }
+
+    /// 测试抖动后的等待时间落在预期区间内
+    #[test]
+    fn test_backoff_jitter_bounds() {
+        let policy = RetryPolicy::default();
+        for attempt in 1..6 {
+            let base = policy.base_backoff(attempt);
+            let actual = policy.backoff(attempt);
+            assert!(actual <= base);
+            assert!(actual >= base.mul_f64(1.0 - policy.jitter));
+        }
+    }
+
+    /// 测试暂时性故障会被重试直到成功
+    #[test]
+    fn test_retry_until_success() {
+        let result = fast_policy(5).retry(|attempt| {
+            if attempt < 3 {
+                Err(MonoError::unavailable("暂时不可用"))
+            } else {
+                Ok(attempt)
+            }
+        });
+        assert_eq!(result.unwrap(), 3);
+    }
+
+    /// 测试不可重试的错误立即返回
+    #[test]
+    fn test_no_retry_on_permanent_error() {
+        let mut calls = 0;
+        let result: MonoResult<()> = fast_policy(5).retry(|_| {
+            calls += 1;
+            Err(MonoError::not_found("ref"))
+        });
+        assert!(result.is_err());
+        assert_eq!(calls, 1);
+    }
+
+    /// 测试达到最大尝试次数后返回最后一次错误
+    #[tokio::test]
+    async fn test_retry_async_gives_up() {
+        let mut calls = 0;
+        let result: MonoResult<()> = fast_policy(3)
+            .retry_async(|_| {
+                calls += 1;
+                async { Err(MonoError::unavailable("超时")) }
+            })
+            .await;
+        assert!(result.unwrap_err().is_retryable());
+        assert_eq!(calls, 3);
+    }
+}
@@ -0,0 +1,124 @@
+//! 命令行补全
+//!
+//! `mono completions <shell>` 输出的脚本在补全时以环境变量 [`COMPLETE_ENV`] 重新调用 `mono`，
+//! 由 clap 按命令定义补全子命令与参数。修订参数通过 [`complete_refs`] 查询引用数据库补全分支、
+//! 标签与 `HEAD`，路径参数通过 [`complete_paths`] 按 HEAD 的树逐级补全，不需要检出工作区。
+//! 补全在当前目录所在的仓库中进行，不在仓库中时没有候选。
+
+use std::ffi::OsStr;
+
+use clap_complete::CompletionCandidate;
+
+use crate::common::MonoResult;
+use crate::object::tree::Tree;
+use crate::refs;
+use crate::repo::Repository;
+
+/// 补全脚本调用 `mono` 时设置的环境变量，取值为 shell 名
+pub const COMPLETE_ENV: &str = "MONO_COMPLETE";
+
+/// 补全修订：分支、标签、远端跟踪分支与 `HEAD`，`a..b` 形式的范围补全 `..` 之后的部分
+pub fn complete_refs(current: &OsStr) -> Vec<CompletionCandidate> {
+    let Some(current) = current.to_str() else {
+        return Vec::new();
+    };
+    let (range, prefix) = match current.rfind("..") {
+        Some(i) => current.split_at(i + 2),
+        None => ("", current),
+    };
+    let Ok(repo) = discover() else {
+        return Vec::new();
+    };
+    ref_candidates(&repo, prefix)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|name| CompletionCandidate::new(format!("{}{}", range, name)))
+        .collect()
+}
+
+/// 补全仓库中的路径，目录以 `/` 结尾以便继续补全
+pub fn complete_paths(current: &OsStr) -> Vec<CompletionCandidate> {
+    let Some(current) = current.to_str() else {
+        return Vec::new();
+    };
+    let Ok(repo) = discover() else {
+        return Vec::new();
+    };
+    path_candidates(&repo, current)
+        .unwrap_or_default()
+        .into_iter()
+        .map(CompletionCandidate::new)
+        .collect()
+}
+
+fn discover() -> MonoResult<Repository> {
+    Repository::discover(&std::env::current_dir()?)
+}
+
+/// 以 `prefix` 开头的引用短名，按名称排序
+pub fn ref_candidates(repo: &Repository, prefix: &str) -> MonoResult<Vec<String>> {
+    let mut names: Vec<String> = repo
+        .refs()
+        .list("refs/")?
+        .into_iter()
+        .map(|(name, _)| refs::short_name(&name).to_string())
+        .collect();
+    names.push(refs::HEAD.to_string());
+    names.retain(|name| name.starts_with(prefix));
+    names.sort();
+    names.dedup();
+    Ok(names)
+}
+
+/// HEAD 的树中以 `prefix` 开头的路径，只列出 `prefix` 最后一个 `/` 所在目录的条目
+pub fn path_candidates(repo: &Repository, prefix: &str) -> MonoResult<Vec<String>> {
+    let Some(head) = repo.head_commit()? else {
+        return Ok(Vec::new());
+    };
+    let (dir, name) = match prefix.rfind('/') {
+        Some(i) => prefix.split_at(i + 1),
+        None => ("", prefix),
+    };
+    let mut tree = repo.read_commit(&head)?.tree;
+    for component in dir.split('/').filter(|component| !component.is_empty()) {
+        let entries = Tree::parse(&repo.read_object(&tree)?.data, tree.format())?.entries;
+        match entries.into_iter().find(|entry| entry.name == component && entry.mode.is_tree()) {
+            Some(entry) => tree = entry.id,
+            None => return Ok(Vec::new()),
+        }
+    }
+    let entries = Tree::parse(&repo.read_object(&tree)?.data, tree.format())?.entries;
+    Ok(entries
+        .into_iter()
+        .filter(|entry| entry.name.starts_with(name))
+        .map(|entry| {
+            let slash = if entry.mode.is_tree() { "/" } else { "" };
+            format!("{}{}{}", dir, entry.name, slash)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{commit_files, init_repo};
+
+    /// 测试引用与路径的候选
+    #[test]
+    fn test_candidates() {
+        let (_dir, repo) = init_repo();
+        let commit = commit_files(&repo, &[("src/main.rs", b""), ("src/lib.rs", b""), ("scripts/x", b"")], &[], "init");
+        assert!(path_candidates(&repo, "").unwrap().is_empty());
+        repo.refs().write("refs/heads/main", &commit).unwrap();
+        repo.refs().write("refs/heads/feature/login", &commit).unwrap();
+        repo.refs().write("refs/tags/v1.0", &commit).unwrap();
+
+        assert_eq!(ref_candidates(&repo, "").unwrap(), ["HEAD", "feature/login", "main", "v1.0"]);
+        assert_eq!(ref_candidates(&repo, "fe").unwrap(), ["feature/login"]);
+
+        assert_eq!(path_candidates(&repo, "s").unwrap(), ["scripts/", "src/"]);
+        assert_eq!(path_candidates(&repo, "src/").unwrap(), ["src/lib.rs", "src/main.rs"]);
+        assert_eq!(path_candidates(&repo, "src/m").unwrap(), ["src/main.rs"]);
+        assert!(path_candidates(&repo, "missing/").unwrap().is_empty());
+    }
+}
@@ -0,0 +1,521 @@
+//! 由多个上游仓库组成的虚拟单仓库
+//!
+//! `[compose]` 配置段是组合清单：每个组件把一个上游仓库的分支放到单仓库分支的一个目录下。
+//!
+//! ```toml
+//! [compose]
+//! branch = "main"
+//!
+//! [[compose.components]]
+//! path = "services/billing"
+//! url = "https://git.example.com/billing.git"
+//! ref = "main"
+//! ```
+//!
+//! `mono compose sync` 双向同步每个组件：
+//!
+//! - 上游的新提交改写为只修改组件目录的单仓库提交（树为第一个父提交的树换上组件目录），
+//!   再通过合并提交接入单仓库分支。首次同步导入上游的完整历史。
+//! - 单仓库中修改了组件目录的提交沿第一父提交链逐个导出为上游提交，推送时以上游分支的旧值
+//!   做比较并交换，不会覆盖上游的新提交。
+//! - 两端都有新改动时三方合并组件目录（见 [`crate::merge`]）：单仓库中是接入上游的合并提交，
+//!   上游得到一个以导出的提交与原上游分支为父提交的对应合并提交，两端的历史都只向前推进。
+//!   合并有冲突时两端都不修改，在任一端解决后重新同步。
+//!
+//! 每个组件最近一次同步时两端的位置保存在 `.mono/compose/<目录>.json`；上游提交到单仓库提交的
+//! 映射保存在同目录的 `.map` 文件中（见 [`CommitMap`]），导出的提交也记入映射，
+//! 之后从上游同步回来时直接对应到原来的单仓库提交，不会重复导入。
+
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::audit::{self, AuditAction, AuditLog};
+use crate::common::config::{ComponentConfig, ComposeConfig};
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::merge::{merge_trees, Conflict, MergeOptions};
+use crate::object::commit::{Commit, Signature};
+use crate::object::filter::ObjectFilter;
+use crate::object::{ObjectId, ObjectType};
+use crate::queue::LockFile;
+use crate::refs::{self, RefUpdate};
+use crate::repo::Repository;
+use crate::rewrite::absorb::insert_tree;
+use crate::rewrite::{normalize_prefix, rewrite_commit, rewrite_history, CommitMap};
+use crate::transport::{self, Transport};
+
+/// 同步状态目录，相对于 `.mono`
+pub const COMPOSE_DIR: &str = "compose";
+/// 同步期间持有的锁
+const SYNC_LOCK_FILE: &str = "sync.lock";
+
+/// 组件最近一次同步时两端的位置，此时单仓库中的组件目录与上游分支的树相同
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SyncPoint {
+    /// 上游分支的提交
+    pub upstream: ObjectId,
+    /// 单仓库分支的提交
+    pub mono: ObjectId,
+    /// 同步的时间（Unix 时间戳）
+    pub synced_at: i64,
+}
+
+/// 一个组件一次同步的结果
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SyncOutcome {
+    /// 从上游导入的提交数
+    pub imported: usize,
+    /// 导出到上游的提交数
+    pub exported: usize,
+    /// 两端的改动冲突时的冲突路径，此时两端都没有修改
+    pub conflicts: Vec<Conflict>,
+}
+
+/// 一个组件的同步状态
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct ComponentStatus {
+    pub path: String,
+    pub url: String,
+    /// 最近一次同步，从未同步过时为 None
+    pub synced: Option<SyncPoint>,
+    /// 单仓库中的组件目录在上次同步后有改动，未同步过时为 None
+    pub local_changes: Option<bool>,
+    /// 上游分支在上次同步后有新提交，未同步过或无法读取上游时为 None
+    pub upstream_changes: Option<bool>,
+    /// 无法读取上游的原因
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// 仓库的组合任务
+pub struct Composer<'a> {
+    repo: &'a Repository,
+    config: ComposeConfig,
+    dir: PathBuf,
+}
+
+impl<'a> Composer<'a> {
+    pub fn new(repo: &'a Repository) -> Composer<'a> {
+        Composer {
+            repo,
+            config: repo.config().compose.clone(),
+            dir: repo.mono_dir().join(COMPOSE_DIR),
+        }
+    }
+
+    fn branch_ref(&self) -> String {
+        format!("{}{}", refs::HEADS_PREFIX, self.config.branch)
+    }
+
+    fn point_path(&self, prefix: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", prefix.replace('%', "%25").replace('/', "%2F")))
+    }
+
+    /// 组件最近一次同步时两端的位置，从未同步过时返回 None
+    pub fn sync_point(&self, path: &str) -> MonoResult<Option<SyncPoint>> {
+        let path = self.point_path(&normalize_prefix(path)?);
+        let data = match std::fs::read(&path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        serde_json::from_slice(&data)
+            .map(Some)
+            .map_err(|e| MonoError::storage(format!("corrupt compose state {}: {}", path.display(), e)))
+    }
+
+    pub(crate) fn save_point(&self, prefix: &str, point: &SyncPoint) -> MonoResult<()> {
+        std::fs::create_dir_all(&self.dir)?;
+        let data = serde_json::to_vec_pretty(point).map_err(|e| MonoError::storage(e.to_string()))?;
+        let path = self.point_path(prefix);
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, data)?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    /// 比较每个组件两端与上次同步时的位置
+    pub fn status(&self) -> MonoResult<Vec<ComponentStatus>> {
+        let head = self.repo.refs().resolve(&self.branch_ref())?;
+        let mut statuses = Vec::new();
+        for component in &self.config.components {
+            let prefix = normalize_prefix(&component.path)?;
+            let synced = self.sync_point(&prefix)?;
+            let mut status = ComponentStatus {
+                path: prefix.clone(),
+                url: component.url.clone(),
+                synced,
+                local_changes: None,
+                upstream_changes: None,
+                error: None,
+            };
+            if let (Some(point), Some(head)) = (&synced, &head) {
+                status.local_changes = Some(self.subtree(&point.mono, &prefix)? != self.subtree(head, &prefix)?);
+            }
+            match transport::open(&component.url).and_then(|transport| upstream_tip(transport.as_ref(), component)) {
+                Ok((_, tip)) => status.upstream_changes = synced.map(|point| point.upstream != tip),
+                Err(e) => status.error = Some(e.to_string()),
+            }
+            statuses.push(status);
+        }
+        Ok(statuses)
+    }
+
+    /// 依次同步每个组件，指定 `only` 时只同步该目录的组件
+    ///
+    /// 返回每个组件的结果，单个组件失败不影响其他组件。同一时间只允许一个进程同步，
+    /// 其他进程正在同步时返回 `Unavailable` 错误。
+    pub fn sync(&self, only: Option<&str>, committer: &Signature) -> MonoResult<Vec<(String, MonoResult<SyncOutcome>)>> {
+        let only = only.map(normalize_prefix).transpose()?;
+        let mut components = Vec::new();
+        for component in &self.config.components {
+            let prefix = normalize_prefix(&component.path)?;
+            if only.as_ref().is_none_or(|only| *only == prefix) {
+                components.push((prefix, component));
+            }
+        }
+        if let Some(only) = only.filter(|_| components.is_empty()) {
+            return Err(MonoError::not_found(format!("compose component {}", only)));
+        }
+        std::fs::create_dir_all(&self.dir)?;
+        let _lock = LockFile::acquire(self.dir.join(SYNC_LOCK_FILE))?;
+        let mut results = Vec::new();
+        for (prefix, component) in components {
+            let result = self.sync_component(&prefix, component, committer);
+            if let Err(e) = &result {
+                tracing::warn!(path = %prefix, error = %e, "compose sync failed");
+            }
+            results.push((prefix, result));
+        }
+        Ok(results)
+    }
+
+    fn sync_component(&self, prefix: &str, component: &ComponentConfig, committer: &Signature) -> MonoResult<SyncOutcome> {
+        let repo = self.repo;
+        let transport = transport::open(&component.url)?;
+        let (upstream_ref, upstream) = upstream_tip(transport.as_ref(), component)?;
+        let point = self.sync_point(prefix)?;
+        if !repo.objects().contains(&upstream)? {
+            let haves: Vec<ObjectId> = point.iter().map(|point| point.upstream).collect();
+            let stats = transport.fetch(&[upstream], &haves, &ObjectFilter::None, repo.objects())?;
+            tracing::info!(path = prefix, objects = stats.objects, "fetched upstream");
+        }
+        let branch_ref = self.branch_ref();
+        let head = repo.refs().resolve(&branch_ref)?;
+        let upstream_tree = repo.read_commit(&upstream)?.tree;
+        let mut map = CommitMap::open(repo, COMPOSE_DIR, prefix)?;
+        let mut outcome = SyncOutcome::default();
+        let now = chrono::Utc::now().timestamp();
+        let message = |detail: &str| {
+            format!(
+                "Sync //{} with {}\n\n{} {} at {}.\n",
+                prefix,
+                component.url,
+                detail,
+                refs::short_name(&upstream_ref),
+                upstream
+            )
+        };
+
+        let Some(point) = point else {
+            // 首次同步：单仓库中还没有该目录时导入上游的完整历史
+            if let Some(head) = &head {
+                match self.subtree(head, prefix)? {
+                    Some(tree) if tree == upstream_tree => {
+                        map.insert(upstream, Some(*head));
+                        map.save()?;
+                        return self.save_point(prefix, &SyncPoint { upstream, mono: *head, synced_at: now }).map(|()| outcome);
+                    }
+                    Some(_) => return Err(MonoError::usage(format!("//{} already exists and differs from {}", prefix, component.url))),
+                    None => {}
+                }
+            }
+            let imported = self.import(&mut map, &upstream, prefix, &mut outcome)?;
+            let new = match head {
+                None => imported,
+                Some(head) => {
+                    let tree = insert_tree(repo, Some(&repo.read_commit(&head)?.tree), prefix, upstream_tree)?;
+                    self.write_merge(tree, head, imported, committer, &message("Imported"))?
+                }
+            };
+            self.update_branch(&branch_ref, head.unwrap_or(ObjectId::ZERO), new, prefix)?;
+            self.save_point(prefix, &SyncPoint { upstream, mono: new, synced_at: now })?;
+            return Ok(outcome);
+        };
+
+        let head = head.ok_or_else(|| MonoError::not_found(format!("branch {}", self.config.branch)))?;
+        let base_tree = self.subtree(&point.mono, prefix)?;
+        let local_tree = self
+            .subtree(&head, prefix)?
+            .ok_or_else(|| MonoError::usage(format!("//{} was removed from {}", prefix, self.config.branch)))?;
+        let local_changed = base_tree != Some(local_tree);
+        let upstream_changed = upstream != point.upstream;
+        if !local_changed && !upstream_changed {
+            return Ok(outcome);
+        }
+
+        let (mut new_head, mut new_upstream) = (head, upstream);
+        if upstream_changed {
+            let merged_tree = if local_changed {
+                let options = MergeOptions {
+                    ours_label: self.config.branch.clone(),
+                    base_label: point.upstream.to_hex()[..7].to_string(),
+                    theirs_label: format!("{} {}", component.url, refs::short_name(&upstream_ref)),
+                    ..MergeOptions::from_config(&repo.config().merge)
+                };
+                let merged = merge_trees(repo, base_tree.as_ref(), Some(&local_tree), Some(&upstream_tree), &options)?;
+                if !merged.is_clean() {
+                    outcome.conflicts = merged
+                        .conflicts
+                        .into_iter()
+                        .map(|conflict| Conflict {
+                            path: format!("{}/{}", prefix, conflict.path),
+                            other_path: conflict.other_path.map(|path| format!("{}/{}", prefix, path)),
+                            ..conflict
+                        })
+                        .collect();
+                    return Ok(outcome);
+                }
+                merged.tree
+            } else {
+                upstream_tree
+            };
+            let imported = self.import(&mut map, &upstream, prefix, &mut outcome)?;
+            let tree = insert_tree(repo, Some(&repo.read_commit(&head)?.tree), prefix, merged_tree)?;
+            new_head = self.write_merge(tree, head, imported, committer, &message("Merged"))?;
+            if local_changed {
+                let exported = self.export(&mut map, &point, &head, prefix, &mut outcome)?;
+                let merge = rewrite_commit(&repo.read_commit(&new_head)?, merged_tree, vec![exported, upstream]);
+                new_upstream = repo.write_object(ObjectType::Commit, &merge.encode())?;
+                map.insert(new_upstream, Some(new_head));
+            }
+        } else {
+            new_upstream = self.export(&mut map, &point, &head, prefix, &mut outcome)?;
+        }
+        // 先保存映射再推送：推送成功而更新分支失败时，下次同步把推送的提交对应回这里的合并提交
+        map.save()?;
+
+        if new_upstream != upstream {
+            let update = RefUpdate {
+                name: upstream_ref.clone(),
+                old: upstream,
+                new: new_upstream,
+            };
+            let stats = transport.push(std::slice::from_ref(&update), repo.objects())?;
+            tracing::info!(path = prefix, commits = outcome.exported, objects = stats.objects, "pushed to upstream");
+        }
+        if new_head != head {
+            self.update_branch(&branch_ref, head, new_head, prefix)?;
+        }
+        self.save_point(prefix, &SyncPoint { upstream: new_upstream, mono: new_head, synced_at: now })?;
+        Ok(outcome)
+    }
+
+    /// 把上游提交 `commit` 的历史导入为组件目录的单仓库历史，返回对应的提交与导入的提交数
+    ///
+    /// 用于以其他方式接入单仓库分支的组件，接入后需以 [`Composer::save_point`] 记录同步位置。
+    pub(crate) fn adopt(&self, prefix: &str, commit: &ObjectId) -> MonoResult<(ObjectId, usize)> {
+        let mut map = CommitMap::open(self.repo, COMPOSE_DIR, prefix)?;
+        let mut outcome = SyncOutcome::default();
+        let imported = self.import(&mut map, commit, prefix, &mut outcome);
+        map.save()?;
+        Ok((imported?, outcome.imported))
+    }
+
+    /// 组件目录在单仓库提交中的树，目录不存在时返回 None
+    fn subtree(&self, commit: &ObjectId, prefix: &str) -> MonoResult<Option<ObjectId>> {
+        let tree = self.repo.read_commit(commit)?.tree;
+        Ok(self.repo.find_path(&tree, prefix)?.filter(|entry| entry.mode.is_tree()).map(|entry| entry.id))
+    }
+
+    /// 把上游提交 `tip` 及其尚未对应的祖先改写为单仓库提交，返回与 `tip` 对应的提交
+    ///
+    /// 改写的提交以第一个父提交的树为基础换上组件目录，没有父提交时只包含组件目录。
+    fn import(&self, map: &mut CommitMap, tip: &ObjectId, prefix: &str, outcome: &mut SyncOutcome) -> MonoResult<ObjectId> {
+        let repo = self.repo;
+        let imported = rewrite_history(repo, tip, map, |parsed, map| {
+            let parents = parsed
+                .parents
+                .iter()
+                .map(|parent| map.get(parent).ok_or_else(|| MonoError::storage(format!("parent {} not imported", parent))))
+                .collect::<MonoResult<Vec<_>>>()?;
+            let base = match parents.first() {
+                Some(parent) => Some(repo.read_commit(parent)?.tree),
+                None => None,
+            };
+            let tree = insert_tree(repo, base.as_ref(), prefix, parsed.tree)?;
+            outcome.imported += 1;
+            Ok(Some(repo.write_object(ObjectType::Commit, &rewrite_commit(parsed, tree, parents).encode())?))
+        })?;
+        Ok(imported.expect("imported commits are never dropped"))
+    }
+
+    /// 把 `point.mono` 之后第一父提交链上修改了组件目录的提交依次导出到 `point.upstream` 之上，
+    /// 返回最后一个导出的提交
+    fn export(
+        &self,
+        map: &mut CommitMap,
+        point: &SyncPoint,
+        head: &ObjectId,
+        prefix: &str,
+        outcome: &mut SyncOutcome,
+    ) -> MonoResult<ObjectId> {
+        let repo = self.repo;
+        let mut changed = Vec::new();
+        let mut id = *head;
+        while id != point.mono {
+            let commit = repo.read_commit(&id)?;
+            let parent = *commit.parents.first().ok_or_else(|| {
+                MonoError::usage(format!(
+                    "{} is no longer in the first-parent history of {}",
+                    point.mono, self.config.branch
+                ))
+            })?;
+            let tree = self.subtree(&id, prefix)?;
+            if tree != self.subtree(&parent, prefix)? {
+                let tree = tree.ok_or_else(|| MonoError::usage(format!("//{} was removed in {}", prefix, id)))?;
+                changed.push((commit, id, tree));
+            }
+            id = parent;
+        }
+        let mut exported = point.upstream;
+        for (commit, id, tree) in changed.into_iter().rev() {
+            let upstream = rewrite_commit(&commit, tree, vec![exported]);
+            exported = repo.write_object(ObjectType::Commit, &upstream.encode())?;
+            map.insert(exported, Some(id));
+            outcome.exported += 1;
+        }
+        Ok(exported)
+    }
+
+    fn write_merge(
+        &self,
+        tree: ObjectId,
+        head: ObjectId,
+        imported: ObjectId,
+        committer: &Signature,
+        message: &str,
+    ) -> MonoResult<ObjectId> {
+        let merge = Commit {
+            tree,
+            parents: vec![head, imported],
+            author: committer.clone(),
+            committer: committer.clone(),
+            extra_headers: Vec::new(),
+            message: message.to_string(),
+        };
+        self.repo.write_object(ObjectType::Commit, &merge.encode())
+    }
+
+    fn update_branch(&self, name: &str, old: ObjectId, new: ObjectId, prefix: &str) -> MonoResult<()> {
+        let update = RefUpdate {
+            name: name.to_string(),
+            old,
+            new,
+        };
+        let actor = audit::local_actor();
+        self.repo
+            .with_reflog_identity(&actor, &format!("compose: sync //{}", prefix))
+            .refs()
+            .update(std::slice::from_ref(&update))?;
+        let now = chrono::Utc::now().timestamp();
+        AuditLog::new(self.repo).record_ref_updates(self.repo, &actor, AuditAction::RefUpdate, &[update], now);
+        Ok(())
+    }
+}
+
+/// 组件同步的上游分支及其最新提交
+fn upstream_tip(transport: &dyn Transport, component: &ComponentConfig) -> MonoResult<(String, ObjectId)> {
+    let remote = transport.list_refs()?;
+    let name = match (&component.branch, &remote.head) {
+        (Some(branch), _) => format!("{}{}", refs::HEADS_PREFIX, branch),
+        (None, Some(head)) => head.clone(),
+        (None, None) => return Err(MonoError::usage(format!("{} has no HEAD, set ref", component.url))),
+    };
+    let tip = remote
+        .refs
+        .iter()
+        .find(|(ref_name, _)| *ref_name == name)
+        .map(|(_, id)| *id)
+        .ok_or_else(|| MonoError::not_found(format!("upstream branch {} of {}", refs::short_name(&name), component.url)))?;
+    Ok((name, tip))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::InitOptions;
+    use crate::test_utils::{commit_files, init_repo};
+
+    /// 测试首次导入、上游与单仓库各自的改动双向同步、两端都有改动时的合并，以及冲突时两端不变
+    #[test]
+    fn test_sync() {
+        let (dir, mut mono) = init_repo();
+        let upstream_root = dir.path().join("billing");
+        let upstream = Repository::init(&upstream_root, &InitOptions::default()).unwrap();
+        let first = commit_files(&upstream, &[("lib.rs", b"1\n2\n3\n4\n5\n")], &[], "first");
+        upstream.refs().write("refs/heads/main", &first).unwrap();
+        mono.config_mut().compose.components = vec![ComponentConfig {
+            path: "//services/billing/...".to_string(),
+            url: upstream_root.to_string_lossy().into_owned(),
+            branch: Some("main".to_string()),
+        }];
+        let base = commit_files(&mono, &[("README", b"mono")], &[], "base");
+        mono.refs().write("refs/heads/main", &base).unwrap();
+        let sig = Signature::new("Bot", "bot@example.com", 1_800_000_000);
+        let composer = Composer::new(&mono);
+        let sync = || composer.sync(None, &sig).unwrap().remove(0).1.unwrap();
+        let file = |commit: &ObjectId, path: &str| {
+            let entry = mono.find_path(&mono.read_commit(commit).unwrap().tree, path).unwrap().unwrap();
+            mono.read_object(&entry.id).unwrap().data
+        };
+        let main = || mono.refs().resolve("refs/heads/main").unwrap().unwrap();
+        let upstream_main = || upstream.refs().resolve("refs/heads/main").unwrap().unwrap();
+
+        assert_eq!(sync().imported, 1);
+        let head = main();
+        assert_eq!(mono.read_commit(&head).unwrap().parents[0], base);
+        assert_eq!(file(&head, "services/billing/lib.rs"), b"1\n2\n3\n4\n5\n");
+        assert_eq!(file(&head, "README"), b"mono");
+        assert_eq!(sync(), SyncOutcome::default());
+
+        // 单仓库的改动导出到上游
+        let mut files: Vec<(&str, &[u8])> = vec![("README", b"mono"), ("services/billing/lib.rs", b"one\n2\n3\n4\n5\n")];
+        let fixed = commit_files(&mono, &files, &[head], "local fix");
+        mono.refs().write("refs/heads/main", &fixed).unwrap();
+        assert_eq!(sync().exported, 1);
+        let exported = upstream.read_commit(&upstream_main()).unwrap();
+        assert_eq!((exported.parents.as_slice(), exported.message.as_str()), ([first].as_slice(), "local fix\n"));
+        assert_eq!(main(), fixed);
+
+        // 上游的提交导回时不重复导入导出的提交；两端都有改动时合并
+        let remote = commit_files(&upstream, &[("lib.rs", b"one\n2\n3\n4\nfive\n")], &[upstream_main()], "remote fix");
+        upstream.refs().write("refs/heads/main", &remote).unwrap();
+        files[1].1 = b"one\n2\nthree\n4\n5\n";
+        let local = commit_files(&mono, &files, &[fixed], "local change");
+        mono.refs().write("refs/heads/main", &local).unwrap();
+        let outcome = sync();
+        assert_eq!((outcome.imported, outcome.exported), (1, 1));
+        let merge = mono.read_commit(&main()).unwrap();
+        assert_eq!(merge.parents[0], local);
+        assert_eq!(mono.read_commit(&merge.parents[1]).unwrap().parents, [fixed]);
+        assert_eq!(file(&main(), "services/billing/lib.rs"), b"one\n2\nthree\n4\nfive\n");
+        let upstream_merge = upstream.read_commit(&upstream_main()).unwrap();
+        assert_eq!(upstream_merge.parents[1], remote);
+        assert_eq!(sync(), SyncOutcome::default());
+
+        // 冲突时两端都不修改
+        let (head, tip) = (main(), upstream_main());
+        let remote = commit_files(&upstream, &[("lib.rs", b"ONE\n2\nthree\n4\nfive\n")], &[tip], "remote");
+        upstream.refs().write("refs/heads/main", &remote).unwrap();
+        files[1].1 = b"uno\n2\nthree\n4\nfive\n";
+        let local = commit_files(&mono, &files, &[head], "local");
+        mono.refs().write("refs/heads/main", &local).unwrap();
+        let outcome = sync();
+        assert_eq!(outcome.conflicts[0].path, "services/billing/lib.rs");
+        assert_eq!((main(), upstream_main()), (local, remote));
+        let status = composer.status().unwrap();
+        assert_eq!((status[0].local_changes, status[0].upstream_changes), (Some(true), Some(true)));
+    }
+}
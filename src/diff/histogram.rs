@@ -0,0 +1,109 @@
+//! histogram 差分算法
+//!
+//! 与 git 的 `--histogram` 相同：统计旧版本中每个元素的出现次数，在新版本中找出出现次数最少的公共
+//! 元素，以它为中心向两侧扩展出最长的相同区间作为锚点，再分别处理锚点两侧的区间。所有公共元素都
+//! 出现超过 [`MAX_CHAIN`] 次时退回到 Myers 算法。
+
+use std::collections::HashMap;
+
+use crate::diff::myers;
+
+/// 参与选择锚点的元素在旧区间中的最大出现次数
+pub const MAX_CHAIN: usize = 64;
+
+/// 两个序列中相同元素的位置对，按位置升序排列
+pub fn matches(a: &[usize], b: &[usize]) -> Vec<(usize, usize)> {
+    let mut pairs = Vec::new();
+    // 用显式的栈代替递归，区间为 (a 起点, a 终点, b 起点, b 终点)
+    let mut ranges = vec![(0, a.len(), 0, b.len())];
+    while let Some((mut a_lo, mut a_hi, mut b_lo, mut b_hi)) = ranges.pop() {
+        while a_lo < a_hi && b_lo < b_hi && a[a_lo] == b[b_lo] {
+            pairs.push((a_lo, b_lo));
+            a_lo += 1;
+            b_lo += 1;
+        }
+        while a_lo < a_hi && b_lo < b_hi && a[a_hi - 1] == b[b_hi - 1] {
+            a_hi -= 1;
+            b_hi -= 1;
+            pairs.push((a_hi, b_hi));
+        }
+        if a_lo == a_hi || b_lo == b_hi {
+            continue;
+        }
+        match anchor(a, b, a_lo, a_hi, b_lo, b_hi) {
+            Some((a_start, b_start, len)) => {
+                pairs.extend((0..len).map(|i| (a_start + i, b_start + i)));
+                ranges.push((a_lo, a_start, b_lo, b_start));
+                ranges.push((a_start + len, a_hi, b_start + len, b_hi));
+            }
+            None => pairs.extend(
+                myers::matches(&a[a_lo..a_hi], &b[b_lo..b_hi])
+                    .into_iter()
+                    .map(|(x, y)| (a_lo + x, b_lo + y)),
+            ),
+        }
+    }
+    pairs.sort_unstable();
+    pairs
+}
+
+/// 相同的区间：(a 起点, b 起点, 长度)
+type Region = (usize, usize, usize);
+
+/// 区间内出现次数最少、其次最长的相同区间
+fn anchor(a: &[usize], b: &[usize], a_lo: usize, a_hi: usize, b_lo: usize, b_hi: usize) -> Option<Region> {
+    let mut positions: HashMap<usize, Vec<usize>> = HashMap::new();
+    for (i, item) in a.iter().enumerate().take(a_hi).skip(a_lo) {
+        positions.entry(*item).or_default().push(i);
+    }
+    // (出现次数, 长度的相反数) 越小越好
+    let mut best: Option<((usize, isize), Region)> = None;
+    let mut y = b_lo;
+    while y < b_hi {
+        let Some(candidates) = positions.get(&b[y]).filter(|candidates| candidates.len() <= MAX_CHAIN) else {
+            y += 1;
+            continue;
+        };
+        let mut next_y = y + 1;
+        for &x in candidates {
+            let (mut start_x, mut start_y) = (x, y);
+            while start_x > a_lo && start_y > b_lo && a[start_x - 1] == b[start_y - 1] {
+                start_x -= 1;
+                start_y -= 1;
+            }
+            let (mut end_x, mut end_y) = (x + 1, y + 1);
+            while end_x < a_hi && end_y < b_hi && a[end_x] == b[end_y] {
+                end_x += 1;
+                end_y += 1;
+            }
+            let count = (start_x..end_x).map(|i| positions[&a[i]].len()).min().unwrap_or(usize::MAX);
+            let len = end_x - start_x;
+            let score = (count, -(len as isize));
+            if best.as_ref().is_none_or(|(best_score, _)| score < *best_score) {
+                best = Some((score, (start_x, start_y, len)));
+            }
+            next_y = next_y.max(end_y);
+        }
+        y = next_y;
+    }
+    best.map(|(_, anchor)| anchor)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 测试以唯一行为锚点：移动的代码块周围重复的行不会被错误配对
+    #[test]
+    fn test_histogram() {
+        // 0 = "{", 1 = "}", 其余为唯一的行
+        let a = [10, 0, 11, 1, 20, 0, 21, 1];
+        let b = [20, 0, 21, 1, 10, 0, 11, 1];
+        let pairs = matches(&a, &b);
+        // 结尾的 "}" 相同；锚点为只出现一次的元素所在的最长区间，另一块被视为删除后重新插入
+        assert_eq!(pairs, vec![(4, 0), (5, 1), (6, 2), (7, 7)]);
+        assert!(pairs.windows(2).all(|w| w[0].0 < w[1].0 && w[0].1 < w[1].1));
+        assert_eq!(matches(&[1, 2, 3], &[1, 2, 3]), vec![(0, 0), (1, 1), (2, 2)]);
+        assert!(matches(&[1], &[2]).is_empty());
+    }
+}
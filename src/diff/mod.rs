@@ -0,0 +1,269 @@
+//! 差异计算
+//!
+//! 行级差异支持 Myers 与 histogram 两种算法（见 [`Algorithm`]），结果表示为两个版本中相同的行对，
+//! 再据此生成带上下文的 hunk（[`hunks`]）与统一格式的补丁文本（[`write_unified`]）。逐词差异见
+//! [`word`]，树之间的差异与重命名、复制检测见 [`tree`]。
+//!
+//! 比较前先去掉两个版本相同的开头与结尾，再把每一行换成编号，算法只比较编号。
+
+use std::collections::HashMap;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+pub mod histogram;
+pub mod myers;
+pub mod tree;
+pub mod word;
+
+/// hunk 默认的上下文行数
+pub const DEFAULT_CONTEXT: usize = 3;
+
+/// 行级差异算法
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Algorithm {
+    /// 最短编辑脚本，与 git 的默认算法相同
+    #[default]
+    Myers,
+    /// 以出现次数最少的公共行为锚点递归划分，移动代码块时结果更易读
+    Histogram,
+}
+
+impl Algorithm {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Algorithm::Myers => "myers",
+            Algorithm::Histogram => "histogram",
+        }
+    }
+}
+
+impl fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(self.as_str())
+    }
+}
+
+/// 按 `\n` 切分行，保留换行符；最后一行可以没有换行符
+pub fn split_lines(data: &[u8]) -> Vec<&[u8]> {
+    data.split_inclusive(|&b| b == b'\n').collect()
+}
+
+/// 把两个版本的元素换成编号，相同的元素编号相同
+pub fn intern<'a, T: AsRef<[u8]> + ?Sized>(old: &[&'a T], new: &[&'a T]) -> (Vec<usize>, Vec<usize>) {
+    let mut ids: HashMap<&'a [u8], usize> = HashMap::new();
+    let mut intern = |items: &[&'a T]| -> Vec<usize> {
+        items
+            .iter()
+            .map(|&item| {
+                let next = ids.len();
+                *ids.entry(T::as_ref(item)).or_insert(next)
+            })
+            .collect()
+    };
+    let old = intern(old);
+    let new = intern(new);
+    (old, new)
+}
+
+/// 两个编号序列中相同元素的位置对，按位置升序排列
+pub fn matches(old: &[usize], new: &[usize], algorithm: Algorithm) -> Vec<(usize, usize)> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let a = &old[prefix..old.len() - suffix];
+    let b = &new[prefix..new.len() - suffix];
+    let middle = match algorithm {
+        Algorithm::Myers => myers::matches(a, b),
+        Algorithm::Histogram => histogram::matches(a, b),
+    };
+    let mut pairs: Vec<(usize, usize)> = (0..prefix).map(|i| (i, i)).collect();
+    pairs.extend(middle.into_iter().map(|(x, y)| (prefix + x, prefix + y)));
+    pairs.extend((0..suffix).rev().map(|i| (old.len() - 1 - i, new.len() - 1 - i)));
+    pairs
+}
+
+/// 逐行比较两个版本，返回新版本每一行在旧版本中未改动时对应的行
+pub fn match_lines<'a>(old: &[&'a [u8]], new: &[&'a [u8]], algorithm: Algorithm) -> Vec<Option<usize>> {
+    let (a, b) = intern(old, new);
+    let mut matched = vec![None; new.len()];
+    for (x, y) in matches(&a, &b, algorithm) {
+        matched[y] = Some(x);
+    }
+    matched
+}
+
+/// 差异中一行的类型
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LineKind {
+    Context,
+    Delete,
+    Insert,
+}
+
+impl LineKind {
+    /// 统一格式中的行首字符
+    pub fn prefix(&self) -> char {
+        match self {
+            LineKind::Context => ' ',
+            LineKind::Delete => '-',
+            LineKind::Insert => '+',
+        }
+    }
+}
+
+/// hunk 中的一行
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct DiffLine {
+    pub kind: LineKind,
+    /// 在旧版本中的行号（从 1 开始），新增的行为 None
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old_line: Option<usize>,
+    /// 在新版本中的行号（从 1 开始），删除的行为 None
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_line: Option<usize>,
+    /// 行内容，保留换行符；文件最后一行可能没有换行符
+    pub content: String,
+}
+
+/// 一段连续的改动及其上下文
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Hunk {
+    /// 统一格式 `@@ -old_start,old_lines +new_start,new_lines @@` 中的四个数
+    pub old_start: usize,
+    pub old_lines: usize,
+    pub new_start: usize,
+    pub new_lines: usize,
+    pub lines: Vec<DiffLine>,
+}
+
+impl Hunk {
+    /// 统一格式的 hunk 头
+    pub fn header(&self) -> String {
+        format!("@@ -{} +{} @@", range(self.old_start, self.old_lines), range(self.new_start, self.new_lines))
+    }
+}
+
+fn range(start: usize, lines: usize) -> String {
+    if lines == 1 {
+        start.to_string()
+    } else {
+        format!("{},{}", start, lines)
+    }
+}
+
+/// 比较两个版本的内容，生成带 `context` 行上下文的 hunk
+pub fn hunks(old: &[u8], new: &[u8], algorithm: Algorithm, context: usize) -> Vec<Hunk> {
+    let old_lines = split_lines(old);
+    let new_lines = split_lines(new);
+    let (a, b) = intern(&old_lines, &new_lines);
+    let pairs = matches(&a, &b, algorithm);
+
+    // 编辑脚本：每一项为 (类型, 旧行下标, 新行下标)，删除排在同一位置的插入之前
+    let mut script: Vec<(LineKind, usize, usize)> = Vec::with_capacity(old_lines.len().max(new_lines.len()));
+    let (mut x, mut y) = (0, 0);
+    for (px, py) in pairs.into_iter().chain(std::iter::once((old_lines.len(), new_lines.len()))) {
+        script.extend((x..px).map(|i| (LineKind::Delete, i, y)));
+        script.extend((y..py).map(|j| (LineKind::Insert, px, j)));
+        if px < old_lines.len() {
+            script.push((LineKind::Context, px, py));
+        }
+        (x, y) = (px + 1, py + 1);
+    }
+
+    let changed: Vec<usize> = (0..script.len()).filter(|&i| script[i].0 != LineKind::Context).collect();
+    let mut hunks = Vec::new();
+    let mut i = 0;
+    while i < changed.len() {
+        // 两处改动之间的上下文不超过两倍时合并为一个 hunk
+        let start = changed[i].saturating_sub(context);
+        let mut last = changed[i];
+        while i + 1 < changed.len() && changed[i + 1] - last <= 2 * context + 1 {
+            i += 1;
+            last = changed[i];
+        }
+        let end = (last + context + 1).min(script.len());
+        let entries = &script[start..end];
+        let lines: Vec<DiffLine> = entries
+            .iter()
+            .map(|&(kind, x, y)| DiffLine {
+                kind,
+                old_line: (kind != LineKind::Insert).then_some(x + 1),
+                new_line: (kind != LineKind::Delete).then_some(y + 1),
+                content: match kind {
+                    LineKind::Insert => String::from_utf8_lossy(new_lines[y]).into_owned(),
+                    _ => String::from_utf8_lossy(old_lines[x]).into_owned(),
+                },
+            })
+            .collect();
+        let old_count = lines.iter().filter(|line| line.kind != LineKind::Insert).count();
+        let new_count = lines.iter().filter(|line| line.kind != LineKind::Delete).count();
+        // 某一侧没有行时，起始行号为改动之前的那一行，与 git 相同
+        let (first_x, first_y) = (entries[0].1, entries[0].2);
+        hunks.push(Hunk {
+            old_start: if old_count == 0 { first_x } else { first_x + 1 },
+            old_lines: old_count,
+            new_start: if new_count == 0 { first_y } else { first_y + 1 },
+            new_lines: new_count,
+            lines,
+        });
+        i += 1;
+    }
+    hunks
+}
+
+/// 以统一格式写出 hunk，没有换行符的最后一行后加上 `\ No newline at end of file`
+pub fn write_unified(out: &mut String, hunks: &[Hunk]) {
+    for hunk in hunks {
+        out.push_str(&hunk.header());
+        out.push('\n');
+        for line in &hunk.lines {
+            out.push(line.kind.prefix());
+            out.push_str(&line.content);
+            if !line.content.ends_with('\n') {
+                out.push_str("\n\\ No newline at end of file\n");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 测试两种算法得出的相同行、hunk 的合并与行号，以及统一格式的输出
+    #[test]
+    fn test_hunks() {
+        let old: Vec<&[u8]> = vec![b"a", b"b", b"c", b"d"];
+        let new: Vec<&[u8]> = vec![b"a", b"x", b"c", b"y", b"d"];
+        for algorithm in [Algorithm::Myers, Algorithm::Histogram] {
+            assert_eq!(match_lines(&old, &new, algorithm), vec![Some(0), None, Some(2), None, Some(3)]);
+        }
+
+        let old: String = (1..=20).map(|i| format!("line {}\n", i)).collect();
+        let new = old.replace("line 2\n", "line two\n").replace("line 18\n", "").replace("line 20\n", "line 20");
+        let hunks = hunks(old.as_bytes(), new.as_bytes(), Algorithm::Myers, DEFAULT_CONTEXT);
+        assert_eq!(hunks.len(), 2);
+        assert_eq!(hunks[0].header(), "@@ -1,5 +1,5 @@");
+        assert_eq!(hunks[1].header(), "@@ -15,6 +15,5 @@");
+        assert_eq!(hunks[0].lines[1].kind, LineKind::Delete);
+        assert_eq!(hunks[0].lines[2].new_line, Some(2));
+
+        let mut patch = String::new();
+        write_unified(&mut patch, &hunks[1..]);
+        assert_eq!(
+            patch,
+            "@@ -15,6 +15,5 @@\n line 15\n line 16\n line 17\n-line 18\n line 19\n-line 20\n+line 20\n\\ No newline at end of file\n"
+        );
+
+        let added = super::hunks(b"", b"one\n", Algorithm::Histogram, DEFAULT_CONTEXT);
+        assert_eq!(added[0].header(), "@@ -0,0 +1 @@");
+        assert!(super::hunks(b"same\n", b"same\n", Algorithm::Myers, DEFAULT_CONTEXT).is_empty());
+    }
+}
@@ -0,0 +1,74 @@
+//! Myers 差分算法
+//!
+//! 按编辑距离逐步扩展每条对角线上走得最远的位置，找到最短编辑脚本后沿记录回溯出相同的元素。
+//! 第 d 步的记录只保存对角线 -d..=d，内存为编辑距离的平方；编辑距离超过 [`MAX_EDIT_COST`]
+//! 时放弃搜索，把整个区间视为替换。
+
+/// 允许的最大编辑距离
+pub const MAX_EDIT_COST: usize = 1024;
+
+/// 两个序列中相同元素的位置对，按位置升序排列；编辑距离超过 [`MAX_EDIT_COST`] 时返回空
+pub fn matches(a: &[usize], b: &[usize]) -> Vec<(usize, usize)> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max = (a.len() + b.len()).min(MAX_EDIT_COST) as isize;
+    if n == 0 || m == 0 {
+        return Vec::new();
+    }
+    // v[k + offset] 是第 k 条对角线上走得最远的 x；trace[d] 保存第 d 步之后对角线 -d..=d 上的值
+    let offset = max + 1;
+    let mut v = vec![0isize; 2 * offset as usize + 1];
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+    let mut end = None;
+    'search: for d in 0..=max {
+        for k in (-d..=d).step_by(2) {
+            let mut x = if k == -d || (k != d && v[(k - 1 + offset) as usize] < v[(k + 1 + offset) as usize]) {
+                v[(k + 1 + offset) as usize]
+            } else {
+                v[(k - 1 + offset) as usize] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[(k + offset) as usize] = x;
+            if x >= n && y >= m {
+                trace.push(v[(offset - d) as usize..=(offset + d) as usize].to_vec());
+                end = Some(d);
+                break 'search;
+            }
+        }
+        trace.push(v[(offset - d) as usize..=(offset + d) as usize].to_vec());
+    }
+    let Some(cost) = end else {
+        return Vec::new();
+    };
+
+    // 从终点沿 trace 回溯，收集每一步之后斜线上的相同元素
+    let mut pairs = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for d in (1..=cost).rev() {
+        let previous = &trace[(d - 1) as usize];
+        let at = |k: isize| previous[(k + d - 1) as usize];
+        let k = x - y;
+        let prev_k = if k == -d || (k != d && at(k - 1) < at(k + 1)) { k + 1 } else { k - 1 };
+        let prev_x = at(prev_k);
+        let prev_y = prev_x - prev_k;
+        // 这一步的插入或删除之后沿斜线走到 (x, y)
+        let (next_x, next_y) = if prev_k == k + 1 { (prev_x, prev_y + 1) } else { (prev_x + 1, prev_y) };
+        while x > next_x && y > next_y {
+            x -= 1;
+            y -= 1;
+            pairs.push((x as usize, y as usize));
+        }
+        x = prev_x;
+        y = prev_y;
+    }
+    while x > 0 && y > 0 {
+        x -= 1;
+        y -= 1;
+        pairs.push((x as usize, y as usize));
+    }
+    pairs.reverse();
+    pairs
+}
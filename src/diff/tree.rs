@@ -0,0 +1,469 @@
+//! 树之间的差异与重命名、复制检测
+//!
+//! 先按路径比较两棵树得到新增、删除与修改的文件，再检测重命名与复制：
+//!
+//! - 重命名：删除的文件与新增的文件配对，内容完全相同的优先，其余按相似度从高到低配对；
+//! - 复制：新增的文件与修改前的文件（以及已用于重命名的删除文件）配对，源文件保留。
+//!
+//! 相似度为两个版本共有的行的字节数占较大一方字节数的百分比，达到 `rename_threshold` 才算配对，
+//! 与 git 的默认阈值 50% 相同。待比较的文件对超过 `rename_limit` 的平方时只做完全相同的配对。
+
+use std::collections::HashMap;
+use std::fmt;
+
+use serde::{Serialize, Serializer};
+
+use crate::common::MonoResult;
+use crate::diff::{hunks, split_lines, write_unified, Algorithm, Hunk, LineKind, DEFAULT_CONTEXT};
+use crate::object::tree::FileMode;
+use crate::object::ObjectId;
+use crate::repo::Repository;
+
+/// 判断二进制文件时检查的前缀长度，与 git 相同
+const BINARY_CHECK_LEN: usize = 8000;
+
+/// 比较选项
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiffOptions {
+    pub algorithm: Algorithm,
+    /// 检测重命名
+    pub find_renames: bool,
+    /// 检测复制，同时检测重命名
+    pub find_copies: bool,
+    /// 相似度阈值（百分比）
+    pub rename_threshold: u8,
+    /// 参与相似度比较的文件数上限
+    pub rename_limit: usize,
+    /// hunk 的上下文行数
+    pub context: usize,
+}
+
+impl Default for DiffOptions {
+    fn default() -> Self {
+        DiffOptions {
+            algorithm: Algorithm::default(),
+            find_renames: true,
+            find_copies: false,
+            rename_threshold: 50,
+            rename_limit: 1000,
+            context: DEFAULT_CONTEXT,
+        }
+    }
+}
+
+/// 改动的类型
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Added,
+    Deleted,
+    Modified,
+    Renamed,
+    Copied,
+}
+
+impl ChangeKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChangeKind::Added => "added",
+            ChangeKind::Deleted => "deleted",
+            ChangeKind::Modified => "modified",
+            ChangeKind::Renamed => "renamed",
+            ChangeKind::Copied => "copied",
+        }
+    }
+
+    /// `--name-status` 中使用的字母
+    pub fn letter(&self) -> char {
+        match self {
+            ChangeKind::Added => 'A',
+            ChangeKind::Deleted => 'D',
+            ChangeKind::Modified => 'M',
+            ChangeKind::Renamed => 'R',
+            ChangeKind::Copied => 'C',
+        }
+    }
+}
+
+impl fmt::Display for ChangeKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(self.as_str())
+    }
+}
+
+/// 文件的一个版本
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct FileVersion {
+    pub path: String,
+    /// 八进制文件模式，例如 `100644`
+    #[serde(serialize_with = "serialize_mode")]
+    pub mode: FileMode,
+    pub id: ObjectId,
+}
+
+fn serialize_mode<S: Serializer>(mode: &FileMode, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&format!("{:06o}", mode.0))
+}
+
+/// 一个文件的改动
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct FileChange {
+    pub kind: ChangeKind,
+    /// 改动前的版本，新增时为 None
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old: Option<FileVersion>,
+    /// 改动后的版本，删除时为 None
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new: Option<FileVersion>,
+    /// 重命名与复制的相似度（百分比）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub similarity: Option<u8>,
+}
+
+impl FileChange {
+    /// 改动后的路径，删除时为改动前的路径
+    pub fn path(&self) -> &str {
+        self.new.as_ref().or(self.old.as_ref()).map_or("", |version| version.path.as_str())
+    }
+}
+
+/// 一个文件的改动及其内容差异
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct FileDiff {
+    #[serde(flatten)]
+    pub change: FileChange,
+    /// 任一版本为二进制文件时不比较内容
+    pub binary: bool,
+    pub insertions: usize,
+    pub deletions: usize,
+    pub hunks: Vec<Hunk>,
+}
+
+/// 比较两棵树，None 表示空树；结果按改动后的路径排序
+pub fn diff_trees(
+    repo: &Repository,
+    old: Option<&ObjectId>,
+    new: Option<&ObjectId>,
+    options: &DiffOptions,
+) -> MonoResult<Vec<FileChange>> {
+    let version = |tree: Option<&ObjectId>, path: &str| -> MonoResult<Option<FileVersion>> {
+        let Some(tree) = tree else { return Ok(None) };
+        Ok(repo
+            .find_path(tree, path)?
+            .filter(|entry| !entry.mode.is_tree())
+            .map(|entry| FileVersion {
+                path: path.to_string(),
+                mode: entry.mode,
+                id: entry.id,
+            }))
+    };
+    let mut changes = Vec::new();
+    for path in repo.changed_paths(old, new)? {
+        let (old, new) = (version(old, &path)?, version(new, &path)?);
+        let kind = match (&old, &new) {
+            (None, _) => ChangeKind::Added,
+            (_, None) => ChangeKind::Deleted,
+            _ => ChangeKind::Modified,
+        };
+        changes.push(FileChange {
+            kind,
+            old,
+            new,
+            similarity: None,
+        });
+    }
+    if options.find_renames || options.find_copies {
+        detect_renames(repo, &mut changes, options)?;
+    }
+    changes.sort_by(|a, b| a.path().cmp(b.path()));
+    Ok(changes)
+}
+
+/// 比较两棵树并计算每个文件的内容差异
+pub fn diff_files(
+    repo: &Repository,
+    old: Option<&ObjectId>,
+    new: Option<&ObjectId>,
+    options: &DiffOptions,
+) -> MonoResult<Vec<FileDiff>> {
+    diff_trees(repo, old, new, options)?
+        .into_iter()
+        .map(|change| diff_file(repo, change, options))
+        .collect()
+}
+
+/// 计算一个改动的内容差异；子模块只比较指向的提交
+pub fn diff_file(repo: &Repository, change: FileChange, options: &DiffOptions) -> MonoResult<FileDiff> {
+    let content = |version: &Option<FileVersion>| -> MonoResult<Vec<u8>> {
+        match version {
+            Some(version) if version.mode.is_gitlink() => Ok(format!("Subproject commit {}\n", version.id).into_bytes()),
+            Some(version) => Ok(repo.read_object(&version.id)?.data),
+            None => Ok(Vec::new()),
+        }
+    };
+    let (old, new) = (content(&change.old)?, content(&change.new)?);
+    let binary = is_binary(&old) || is_binary(&new);
+    let hunks = if binary || old == new {
+        Vec::new()
+    } else {
+        hunks(&old, &new, options.algorithm, options.context)
+    };
+    let count = |kind: LineKind| hunks.iter().flat_map(|hunk| &hunk.lines).filter(|line| line.kind == kind).count();
+    Ok(FileDiff {
+        insertions: count(LineKind::Insert),
+        deletions: count(LineKind::Delete),
+        change,
+        binary,
+        hunks,
+    })
+}
+
+/// 以 `git diff` 的格式写出一个文件的补丁，包括重命名、复制与模式变化的扩展头
+pub fn write_patch(out: &mut String, diff: &FileDiff) {
+    let change = &diff.change;
+    let old_path = change.old.as_ref().map_or(change.path(), |old| old.path.as_str());
+    let new_path = change.path();
+    out.push_str(&format!("diff --git a/{} b/{}\n", old_path, new_path));
+    let abbrev = |id: &ObjectId| id.to_hex()[..7].to_string();
+    match (&change.old, &change.new) {
+        (None, Some(new)) => out.push_str(&format!("new file mode {:06o}\n", new.mode.0)),
+        (Some(old), None) => out.push_str(&format!("deleted file mode {:06o}\n", old.mode.0)),
+        (Some(old), Some(new)) => {
+            if let Some(similarity) = change.similarity {
+                let verb = if change.kind == ChangeKind::Copied { "copy" } else { "rename" };
+                out.push_str(&format!("similarity index {}%\n", similarity));
+                out.push_str(&format!("{} from {}\n{} to {}\n", verb, old.path, verb, new.path));
+            }
+            if old.mode != new.mode {
+                out.push_str(&format!("old mode {:06o}\nnew mode {:06o}\n", old.mode.0, new.mode.0));
+            }
+        }
+        (None, None) => {}
+    }
+    let old_id = change.old.as_ref().map_or_else(|| "0000000".to_string(), |old| abbrev(&old.id));
+    let new_id = change.new.as_ref().map_or_else(|| "0000000".to_string(), |new| abbrev(&new.id));
+    if old_id == new_id {
+        return;
+    }
+    match (&change.old, &change.new) {
+        (Some(old), Some(new)) if old.mode == new.mode => out.push_str(&format!("index {}..{} {:06o}\n", old_id, new_id, new.mode.0)),
+        _ => out.push_str(&format!("index {}..{}\n", old_id, new_id)),
+    }
+    let old_name = change.old.as_ref().map_or_else(|| "/dev/null".to_string(), |old| format!("a/{}", old.path));
+    let new_name = change.new.as_ref().map_or_else(|| "/dev/null".to_string(), |new| format!("b/{}", new.path));
+    if diff.binary {
+        out.push_str(&format!("Binary files {} and {} differ\n", old_name, new_name));
+        return;
+    }
+    if diff.hunks.is_empty() {
+        return;
+    }
+    out.push_str(&format!("--- {}\n+++ {}\n", old_name, new_name));
+    write_unified(out, &diff.hunks);
+}
+
+/// 前 8000 字节中含有 NUL 字节的内容视为二进制，与 git 相同
+pub(crate) fn is_binary(data: &[u8]) -> bool {
+    data[..data.len().min(BINARY_CHECK_LEN)].contains(&0)
+}
+
+/// 把删除与新增配对为重命名，必要时把新增与修改前的文件配对为复制
+fn detect_renames(repo: &Repository, changes: &mut Vec<FileChange>, options: &DiffOptions) -> MonoResult<()> {
+    let is_file = |version: &Option<FileVersion>| version.as_ref().is_some_and(|version| !version.mode.is_gitlink());
+    let added: Vec<usize> = (0..changes.len())
+        .filter(|&i| changes[i].kind == ChangeKind::Added && is_file(&changes[i].new))
+        .collect();
+    // 源文件：删除的文件，以及检测复制时修改前的文件
+    let sources: Vec<usize> = (0..changes.len())
+        .filter(|&i| {
+            is_file(&changes[i].old)
+                && (changes[i].kind == ChangeKind::Deleted || (options.find_copies && changes[i].kind == ChangeKind::Modified))
+        })
+        .collect();
+    if added.is_empty() || sources.is_empty() {
+        return Ok(());
+    }
+
+    // (源, 目标, 相似度)，先配对内容完全相同的文件
+    let id = |version: &Option<FileVersion>| version.as_ref().map(|version| version.id);
+    let mut pairs: Vec<(usize, usize, u8)> = Vec::new();
+    for &target in &added {
+        if let Some(&source) = sources.iter().find(|&&source| id(&changes[source].old) == id(&changes[target].new)) {
+            pairs.push((source, target, 100));
+        }
+    }
+    let exact: Vec<usize> = pairs.iter().map(|(_, target, _)| *target).collect();
+    let inexact: Vec<usize> = added.iter().copied().filter(|target| !exact.contains(target)).collect();
+    if !inexact.is_empty() && inexact.len().max(sources.len()) <= options.rename_limit {
+        let mut signatures: HashMap<ObjectId, Signature> = HashMap::new();
+        let mut signature = |id: ObjectId| -> MonoResult<Signature> {
+            if let Some(signature) = signatures.get(&id) {
+                return Ok(signature.clone());
+            }
+            let data = repo.read_object(&id)?.data;
+            let signature = Signature::new(&data);
+            signatures.insert(id, signature.clone());
+            Ok(signature)
+        };
+        let mut scored = Vec::new();
+        for &target in &inexact {
+            let target_signature = signature(id(&changes[target].new).expect("added files have a new version"))?;
+            if target_signature.binary {
+                continue;
+            }
+            for &source in &sources {
+                let source_signature = signature(id(&changes[source].old).expect("sources have an old version"))?;
+                let score = source_signature.similarity(&target_signature);
+                if score >= options.rename_threshold {
+                    scored.push((source, target, score));
+                }
+            }
+        }
+        // 相似度从高到低，相同时按路径排列，结果稳定
+        scored.sort_by(|a, b| b.2.cmp(&a.2).then(a.1.cmp(&b.1)).then(a.0.cmp(&b.0)));
+        for (source, target, score) in scored {
+            if !pairs.iter().any(|(_, paired, _)| *paired == target) {
+                pairs.push((source, target, score));
+            }
+        }
+    }
+
+    // 每个删除的文件只能重命名一次，之后的配对都是复制；不检测复制时丢弃这些配对
+    let mut renamed: Vec<usize> = Vec::new();
+    let mut removed = Vec::new();
+    pairs.sort_by(|a, b| b.2.cmp(&a.2).then(a.1.cmp(&b.1)));
+    for (source, target, score) in pairs {
+        let is_rename = changes[source].kind == ChangeKind::Deleted && !renamed.contains(&source);
+        if !is_rename && !options.find_copies {
+            continue;
+        }
+        if is_rename {
+            renamed.push(source);
+            removed.push(source);
+        }
+        changes[target].kind = if is_rename { ChangeKind::Renamed } else { ChangeKind::Copied };
+        changes[target].old = changes[source].old.clone();
+        changes[target].similarity = Some(score);
+    }
+    removed.sort_unstable();
+    for index in removed.into_iter().rev() {
+        changes.remove(index);
+    }
+    Ok(())
+}
+
+/// 用于计算相似度的内容摘要：每种行的出现次数与字节数
+#[derive(Clone)]
+struct Signature {
+    lines: HashMap<u64, (usize, usize)>,
+    size: usize,
+    binary: bool,
+}
+
+impl Signature {
+    fn new(data: &[u8]) -> Signature {
+        use std::hash::{DefaultHasher, Hash, Hasher};
+
+        let mut lines: HashMap<u64, (usize, usize)> = HashMap::new();
+        for line in split_lines(data) {
+            let mut hasher = DefaultHasher::new();
+            line.hash(&mut hasher);
+            let entry = lines.entry(hasher.finish()).or_default();
+            entry.0 += 1;
+            entry.1 = line.len();
+        }
+        Signature {
+            lines,
+            size: data.len(),
+            binary: is_binary(data),
+        }
+    }
+
+    /// 共有的行的字节数占较大一方的百分比
+    fn similarity(&self, other: &Signature) -> u8 {
+        let size = self.size.max(other.size);
+        if size == 0 {
+            return 100;
+        }
+        let common: usize = self
+            .lines
+            .iter()
+            .filter_map(|(hash, (count, len))| other.lines.get(hash).map(|(other_count, _)| count.min(other_count) * len))
+            .sum();
+        (common * 100 / size) as u8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{commit_files, init_repo};
+
+    /// 测试完全相同与相似内容的重命名、复制检测，以及内容差异的统计
+    #[test]
+    fn test_diff_trees() {
+        let (_dir, repo) = init_repo();
+        let body: String = (1..=10).map(|i| format!("line {}\n", i)).collect();
+        let first = commit_files(
+            &repo,
+            &[("a.txt", body.as_bytes()), ("b.txt", b"unrelated\n"), ("c.txt", b"old c\n"), ("bin", b"\0\x01")],
+            &[],
+            "first",
+        );
+        let moved = body.replace("line 10\n", "line ten\n");
+        let second = commit_files(
+            &repo,
+            &[
+                ("dir/a.txt", moved.as_bytes()),
+                ("b2.txt", b"unrelated\n"),
+                ("c.txt", b"new c\n"),
+                ("copy-of-c.txt", b"old c\n"),
+                ("bin", b"\0\x02"),
+            ],
+            &[first],
+            "second",
+        );
+        let tree = |id: &ObjectId| repo.read_commit(id).unwrap().tree;
+        let (old, new) = (tree(&first), tree(&second));
+
+        let changes = diff_trees(&repo, Some(&old), Some(&new), &DiffOptions::default()).unwrap();
+        let summary: Vec<(ChangeKind, &str, Option<u8>)> =
+            changes.iter().map(|change| (change.kind, change.path(), change.similarity)).collect();
+        assert_eq!(
+            summary,
+            vec![
+                (ChangeKind::Renamed, "b2.txt", Some(100)),
+                (ChangeKind::Modified, "bin", None),
+                (ChangeKind::Modified, "c.txt", None),
+                (ChangeKind::Added, "copy-of-c.txt", None),
+                (ChangeKind::Renamed, "dir/a.txt", Some(87)),
+            ]
+        );
+        assert_eq!(changes[4].old.as_ref().unwrap().path, "a.txt");
+
+        let options = DiffOptions {
+            find_copies: true,
+            ..Default::default()
+        };
+        let changes = diff_trees(&repo, Some(&old), Some(&new), &options).unwrap();
+        let copy = changes.iter().find(|change| change.path() == "copy-of-c.txt").unwrap();
+        assert_eq!((copy.kind, copy.old.as_ref().unwrap().path.as_str()), (ChangeKind::Copied, "c.txt"));
+
+        let options = DiffOptions {
+            find_renames: false,
+            ..Default::default()
+        };
+        let diffs = diff_files(&repo, Some(&old), Some(&new), &options).unwrap();
+        assert_eq!(diffs.len(), 7);
+        let bin = diffs.iter().find(|diff| diff.change.path() == "bin").unwrap();
+        assert!(bin.binary && bin.hunks.is_empty());
+        let added = diffs.iter().find(|diff| diff.change.path() == "dir/a.txt").unwrap();
+        assert_eq!((added.insertions, added.deletions), (10, 0));
+
+        let renamed = diff_file(&repo, changes.into_iter().find(|change| change.path() == "dir/a.txt").unwrap(), &options).unwrap();
+        let mut patch = String::new();
+        write_patch(&mut patch, &renamed);
+        assert!(patch.starts_with("diff --git a/a.txt b/dir/a.txt\nsimilarity index 87%\nrename from a.txt\nrename to dir/a.txt\nindex "));
+        assert!(patch.ends_with("--- a/a.txt\n+++ b/dir/a.txt\n@@ -7,4 +7,4 @@\n line 7\n line 8\n line 9\n-line 10\n+line ten\n"));
+    }
+}
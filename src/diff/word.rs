@@ -0,0 +1,152 @@
+//! 逐词差异
+//!
+//! 把 hunk 中相邻的删除行与插入行各自拼接后切分为词（连续的字母数字与下划线、连续的空白，
+//! 或单个其他字符），再用行级差异相同的算法比较词序列。输出格式与 `git diff --word-diff=plain`
+//! 相同：删除的部分写作 `[-…-]`，插入的部分写作 `{+…+}`。
+
+use serde::Serialize;
+
+use crate::diff::{intern, matches, Algorithm, Hunk, LineKind};
+
+/// 逐词差异中的一段
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "kind", content = "text", rename_all = "lowercase")]
+pub enum WordChunk {
+    Equal(String),
+    Delete(String),
+    Insert(String),
+}
+
+/// 切分为词，拼接后与原文相同
+pub fn tokenize(text: &str) -> Vec<&str> {
+    let class = |c: char| {
+        if c.is_alphanumeric() || c == '_' {
+            0
+        } else if c.is_whitespace() {
+            1
+        } else {
+            2
+        }
+    };
+    let mut tokens = Vec::new();
+    let mut start = 0;
+    let mut previous: Option<u8> = None;
+    for (i, c) in text.char_indices() {
+        let current = class(c);
+        // 标点各自成词
+        if previous.is_some_and(|previous| previous != current || current == 2) {
+            tokens.push(&text[start..i]);
+            start = i;
+        }
+        previous = Some(current);
+    }
+    if start < text.len() {
+        tokens.push(&text[start..]);
+    }
+    tokens
+}
+
+/// 逐词比较两段文本，相邻的同类段合并
+pub fn diff(old: &str, new: &str, algorithm: Algorithm) -> Vec<WordChunk> {
+    let old_tokens = tokenize(old);
+    let new_tokens = tokenize(new);
+    let (a, b) = intern(&old_tokens, &new_tokens);
+    let mut chunks: Vec<WordChunk> = Vec::new();
+    let mut push = |chunk: WordChunk| match (chunks.last_mut(), chunk) {
+        (Some(WordChunk::Equal(last)), WordChunk::Equal(text))
+        | (Some(WordChunk::Delete(last)), WordChunk::Delete(text))
+        | (Some(WordChunk::Insert(last)), WordChunk::Insert(text)) => last.push_str(&text),
+        (_, chunk) => chunks.push(chunk),
+    };
+    let (mut x, mut y) = (0, 0);
+    for (px, py) in matches(&a, &b, algorithm).into_iter().chain(std::iter::once((a.len(), b.len()))) {
+        if x < px {
+            push(WordChunk::Delete(old_tokens[x..px].concat()));
+        }
+        if y < py {
+            push(WordChunk::Insert(new_tokens[y..py].concat()));
+        }
+        if px < a.len() {
+            push(WordChunk::Equal(old_tokens[px].to_string()));
+        }
+        (x, y) = (px + 1, py + 1);
+    }
+    chunks
+}
+
+/// 以 `--word-diff=plain` 的格式写出一个 hunk
+pub fn write_plain(out: &mut String, hunk: &Hunk, algorithm: Algorithm) {
+    out.push_str(&hunk.header());
+    out.push('\n');
+    let mut i = 0;
+    while i < hunk.lines.len() {
+        if hunk.lines[i].kind == LineKind::Context {
+            out.push_str(&hunk.lines[i].content);
+            if !hunk.lines[i].content.ends_with('\n') {
+                out.push('\n');
+            }
+            i += 1;
+            continue;
+        }
+        let (mut old, mut new) = (String::new(), String::new());
+        while i < hunk.lines.len() && hunk.lines[i].kind != LineKind::Context {
+            match hunk.lines[i].kind {
+                LineKind::Delete => old.push_str(&hunk.lines[i].content),
+                _ => new.push_str(&hunk.lines[i].content),
+            }
+            i += 1;
+        }
+        for chunk in diff(&old, &new, algorithm) {
+            match chunk {
+                WordChunk::Equal(text) => out.push_str(&text),
+                // 换行符放在标记之外，保持逐行的排版
+                WordChunk::Delete(text) => wrap(out, &text, "[-", "-]"),
+                WordChunk::Insert(text) => wrap(out, &text, "{+", "+}"),
+            }
+        }
+        if !out.ends_with('\n') {
+            out.push('\n');
+        }
+    }
+}
+
+fn wrap(out: &mut String, text: &str, open: &str, close: &str) {
+    for (i, line) in text.split('\n').enumerate() {
+        if i > 0 {
+            out.push('\n');
+        }
+        if !line.is_empty() {
+            out.push_str(open);
+            out.push_str(line);
+            out.push_str(close);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diff::{hunks, DEFAULT_CONTEXT};
+
+    /// 测试词的切分、逐词比较与 plain 格式输出
+    #[test]
+    fn test_word_diff() {
+        assert_eq!(tokenize("let x = foo(1);"), vec!["let", " ", "x", " ", "=", " ", "foo", "(", "1", ")", ";"]);
+        assert_eq!(
+            diff("let x = foo(1);", "let y = foo(1, 2);", Algorithm::Myers),
+            vec![
+                WordChunk::Equal("let ".to_string()),
+                WordChunk::Delete("x".to_string()),
+                WordChunk::Insert("y".to_string()),
+                WordChunk::Equal(" = foo(1".to_string()),
+                WordChunk::Insert(", 2".to_string()),
+                WordChunk::Equal(");".to_string()),
+            ]
+        );
+
+        let hunks = hunks(b"a\nlet x = 1;\nb\n", b"a\nlet y = 1;\nb\n", Algorithm::Myers, DEFAULT_CONTEXT);
+        let mut out = String::new();
+        write_plain(&mut out, &hunks[0], Algorithm::Myers);
+        assert_eq!(out, "@@ -1,3 +1,3 @@\na\nlet [-x-]{+y+} = 1;\nb\n");
+    }
+}
@@ -0,0 +1,461 @@
+//! 运行环境诊断（`mono doctor`）
+//!
+//! 依次检查：
+//!
+//! - `config`：各配置层合并后的配置可以解析且取值合法，不认识与已弃用的键给出警告
+//! - `storage`：对象存储与引用数据库可以访问，S3 与 PostgreSQL 后端会实际发出请求
+//! - `disk-space`：`.mono` 所在文件系统的剩余空间
+//! - `index`：暂存区文件（含拆分索引的共享索引）可以读取，条目指向的对象存在
+//! - `credentials`：`[auth.providers]` 中每个身份提供方都有有效的登录会话
+//! - `clock-skew`：本机时间与远端、身份提供方和 S3 返回的 `Date` 相差不大，时钟偏差过大
+//!   会使令牌校验与 S3 签名失败
+//!
+//! 每项检查给出状态与说明，出现问题时附带可以直接执行的修复建议。前一项失败使后续检查
+//! 无法进行时，后续检查标记为跳过。
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::path::Path;
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::auth::keychain::Keychain;
+use crate::auth::oidc::OidcClient;
+use crate::common::config::{Config, RepoConfig, StorageBackend};
+use crate::common::progress::format_bytes;
+use crate::index::Index;
+use crate::repo::{Repository, CONFIG_FILE, MONO_DIR};
+
+/// 剩余空间低于该值时警告
+const DISK_WARNING_BYTES: u64 = 5 << 30;
+/// 剩余空间低于该值时报错，写入 pack 或检出很可能失败
+const DISK_ERROR_BYTES: u64 = 512 << 20;
+/// 对象存储单次探测超过该时间时警告
+const SLOW_STORAGE: Duration = Duration::from_secs(2);
+/// 时钟偏差超过该值时警告
+const SKEW_WARNING_SECS: i64 = 30;
+/// 时钟偏差超过该值时报错，与 S3 签名允许的偏差相同
+const SKEW_ERROR_SECS: i64 = 300;
+/// 查询时间的 HTTP 请求超时
+const HTTP_TIMEOUT: Duration = Duration::from_secs(5);
+/// 最多向几个主机查询时间
+const MAX_TIME_SOURCES: usize = 3;
+
+/// 检查结果的状态，按严重程度排序
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    /// 前置检查失败或不适用
+    Skipped,
+    Ok,
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(match self {
+            Status::Skipped => "skipped",
+            Status::Ok => "ok",
+            Status::Warning => "warning",
+            Status::Error => "error",
+        })
+    }
+}
+
+/// 一项检查的结果
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Check {
+    /// 稳定的检查名，例如 `disk-space`
+    pub name: &'static str,
+    pub status: Status,
+    pub message: String,
+    /// 修复建议
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fix: Option<String>,
+}
+
+impl Check {
+    fn new(name: &'static str, status: Status, message: impl Into<String>) -> Check {
+        Check {
+            name,
+            status,
+            message: message.into(),
+            fix: None,
+        }
+    }
+
+    fn with_fix(mut self, fix: impl Into<String>) -> Check {
+        self.fix = Some(fix.into());
+        self
+    }
+}
+
+/// 诊断结果
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct DoctorReport {
+    pub checks: Vec<Check>,
+}
+
+impl DoctorReport {
+    /// 最严重的状态，没有检查时为 None
+    pub fn worst(&self) -> Option<Status> {
+        self.checks.iter().map(|check| check.status).max()
+    }
+
+    pub fn count(&self, status: Status) -> usize {
+        self.checks.iter().filter(|check| check.status == status).count()
+    }
+}
+
+/// 诊断选项
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DoctorOptions {
+    /// 跳过需要访问网络的凭据与时钟检查
+    pub offline: bool,
+}
+
+/// 诊断 `start` 所在的工作区
+pub fn doctor(start: &Path, options: &DoctorOptions) -> DoctorReport {
+    let mut report = DoctorReport::default();
+    let Some(mono_dir) = start
+        .ancestors()
+        .map(|dir| dir.join(MONO_DIR))
+        .find(|dir| dir.join(CONFIG_FILE).is_file())
+    else {
+        report.checks.push(
+            Check::new("config", Status::Error, format!("{} is not inside a mono workspace", start.display()))
+                .with_fix("run `mono init` or `mono clone <url>`, or change to a workspace directory"),
+        );
+        skip(&mut report, &["storage", "disk-space", "index", "credentials", "clock-skew"]);
+        return report;
+    };
+
+    let loaded = Config::load(Some(&mono_dir))
+        .and_then(|layered| Ok((layered.repo_config()?, layered.warnings().to_vec())));
+    let config = match loaded {
+        Ok((config, warnings)) => {
+            report.checks.push(match warnings.first() {
+                None => Check::new("config", Status::Ok, "configuration is valid"),
+                Some(first) => Check::new(
+                    "config",
+                    Status::Warning,
+                    format!("{} unknown or deprecated keys, first: {}", warnings.len(), first),
+                )
+                .with_fix("remove unknown keys and rename deprecated ones with `mono config set`"),
+            });
+            config
+        }
+        Err(e) => {
+            report.checks.push(
+                Check::new("config", Status::Error, e.to_string())
+                    .with_fix("fix the value with `mono config set <key> <value>` or edit .mono/mono.toml"),
+            );
+            skip(&mut report, &["storage"]);
+            report.checks.push(check_disk_space(&mono_dir));
+            skip(&mut report, &["index", "credentials", "clock-skew"]);
+            return report;
+        }
+    };
+
+    let repo = match Repository::discover(start) {
+        Ok(repo) => Some(repo),
+        Err(e) => {
+            report.checks.push(
+                Check::new("storage", Status::Error, format!("cannot open repository: {}", e))
+                    .with_fix(storage_fix(&config)),
+            );
+            None
+        }
+    };
+    if let Some(repo) = &repo {
+        report.checks.push(check_storage(repo, &config));
+    }
+    report.checks.push(check_disk_space(&mono_dir));
+    match &repo {
+        Some(repo) => report.checks.push(check_index(repo)),
+        None => skip(&mut report, &["index"]),
+    }
+    if options.offline {
+        skip(&mut report, &["credentials", "clock-skew"]);
+    } else {
+        report.checks.push(check_credentials(&config));
+        report.checks.push(check_clock_skew(&config));
+    }
+    report
+}
+
+fn skip(report: &mut DoctorReport, names: &[&'static str]) {
+    for name in names {
+        report.checks.push(Check::new(name, Status::Skipped, "not checked"));
+    }
+}
+
+fn storage_fix(config: &RepoConfig) -> &'static str {
+    match (config.storage.backend, &config.storage.pg) {
+        (_, Some(_)) => "check that PostgreSQL is reachable with the credentials in [storage.pg]",
+        (StorageBackend::S3, None) => "check [storage.s3] and that the AWS credentials in the environment can access the bucket",
+        (StorageBackend::Fs, None) => "check the permissions of .mono/objects and .mono/refs",
+    }
+}
+
+/// 读取一个不存在的对象并列出分支，确认对象存储与引用数据库可以访问
+fn check_storage(repo: &Repository, config: &RepoConfig) -> Check {
+    let started = Instant::now();
+    let result = repo
+        .objects()
+        .contains(&repo.object_format().zero())
+        .and_then(|_| repo.refs().list("refs/heads/"));
+    let elapsed = started.elapsed();
+    let backend = match config.storage.backend {
+        StorageBackend::Fs => "fs",
+        StorageBackend::S3 => "s3",
+    };
+    match result {
+        Err(e) => Check::new("storage", Status::Error, format!("{} storage is not accessible: {}", backend, e))
+            .with_fix(storage_fix(config)),
+        Ok(_) if elapsed > SLOW_STORAGE => Check::new(
+            "storage",
+            Status::Warning,
+            format!("{} storage answered in {} ms", backend, elapsed.as_millis()),
+        )
+        .with_fix("check the network latency to the storage backend, or enable [storage] disk_cache_size"),
+        Ok(branches) => Check::new(
+            "storage",
+            Status::Ok,
+            format!("{} storage is reachable, {} branches", backend, branches.len()),
+        ),
+    }
+}
+
+/// 用 `df` 查询剩余空间，没有 `df` 的平台跳过
+fn check_disk_space(mono_dir: &Path) -> Check {
+    let free = Command::new("df")
+        .arg("-Pk")
+        .arg(mono_dir)
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| parse_df(&String::from_utf8_lossy(&output.stdout)));
+    let Some((free, total)) = free else {
+        return Check::new("disk-space", Status::Skipped, "cannot determine free disk space");
+    };
+    let percent = (free * 100).checked_div(total).unwrap_or(100);
+    let message = format!("{} free ({}%) on the filesystem of {}", format_bytes(free), percent, mono_dir.display());
+    let fix = "free disk space, or run `mono gc` and `mono repack` to drop unreachable objects and loose files";
+    if free < DISK_ERROR_BYTES {
+        Check::new("disk-space", Status::Error, message).with_fix(fix)
+    } else if free < DISK_WARNING_BYTES {
+        Check::new("disk-space", Status::Warning, message).with_fix(fix)
+    } else {
+        Check::new("disk-space", Status::Ok, message)
+    }
+}
+
+/// 解析 `df -P` 的输出，返回剩余与总字节数
+fn parse_df(output: &str) -> Option<(u64, u64)> {
+    let fields: Vec<&str> = output.lines().nth(1)?.split_whitespace().collect();
+    let total: u64 = fields.get(1)?.parse().ok()?;
+    let free: u64 = fields.get(3)?.parse().ok()?;
+    Some((free * 1024, total * 1024))
+}
+
+fn check_index(repo: &Repository) -> Check {
+    let fix = "rebuild the index from HEAD with `mono index read-tree`";
+    let index = match Index::load(repo) {
+        Ok(index) => index,
+        Err(e) => return Check::new("index", Status::Error, format!("cannot read the index: {}", e)).with_fix(fix),
+    };
+    let mut missing = 0;
+    for entry in index.entries() {
+        match repo.objects().contains(&entry.id) {
+            Ok(true) => {}
+            Ok(false) => missing += 1,
+            Err(e) => return Check::new("index", Status::Error, format!("cannot check index entries: {}", e)),
+        }
+    }
+    let promisor = repo.config().remote.values().any(|remote| remote.promisor);
+    match missing {
+        0 => Check::new(
+            "index",
+            Status::Ok,
+            format!("{} entries, version {}", index.entries().len(), index.version()),
+        ),
+        // 部分克隆的仓库缺少 blob 是正常的
+        _ if promisor => Check::new(
+            "index",
+            Status::Ok,
+            format!("{} entries, {} blobs not fetched yet", index.entries().len(), missing),
+        ),
+        _ => Check::new(
+            "index",
+            Status::Error,
+            format!("{} of {} entries point to missing objects", missing, index.entries().len()),
+        )
+        .with_fix(fix),
+    }
+}
+
+/// 每个身份提供方都应有可以使用（必要时可以刷新）的会话
+fn check_credentials(config: &RepoConfig) -> Check {
+    if config.auth.providers.is_empty() {
+        return Check::new("credentials", Status::Skipped, "no identity provider is configured");
+    }
+    let keychain = Keychain::default();
+    let now = Utc::now().timestamp();
+    let mut failed = Vec::new();
+    for (name, provider) in &config.auth.providers {
+        if let Err(e) = OidcClient::new(provider.clone()).session_at(&keychain, now) {
+            failed.push((name, e));
+        }
+    }
+    match failed.first() {
+        None => Check::new(
+            "credentials",
+            Status::Ok,
+            format!("logged in to {} identity providers", config.auth.providers.len()),
+        ),
+        Some((name, e)) => Check::new(
+            "credentials",
+            Status::Warning,
+            format!("{} of {} identity providers have no valid session: {}: {}", failed.len(), config.auth.providers.len(), name, e),
+        )
+        .with_fix(format!("run `mono login --provider {}`", name)),
+    }
+}
+
+/// 用 HTTP 响应的 `Date` 头估计本机时钟与远端的偏差
+fn check_clock_skew(config: &RepoConfig) -> Check {
+    let sources = time_sources(config);
+    if sources.is_empty() {
+        return Check::new("clock-skew", Status::Skipped, "no HTTP remote, identity provider or S3 endpoint to compare with");
+    }
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        .timeout_global(Some(HTTP_TIMEOUT))
+        .http_status_as_error(false)
+        .build()
+        .into();
+    let mut worst: Option<(i64, String)> = None;
+    for url in sources.iter().take(MAX_TIME_SOURCES) {
+        let started = Utc::now();
+        let Ok(response) = agent.head(url).call() else {
+            continue;
+        };
+        let Some(date) = response
+            .headers()
+            .get("date")
+            .and_then(|date| date.to_str().ok())
+            .and_then(|date| DateTime::parse_from_rfc2822(date).ok())
+        else {
+            continue;
+        };
+        // 以请求的中点作为对方生成 Date 的时间，Date 只精确到秒
+        let local = started + (Utc::now() - started) / 2;
+        let skew = (local.timestamp() - date.timestamp()).abs();
+        if worst.as_ref().is_none_or(|(worst, _)| skew > *worst) {
+            worst = Some((skew, url.clone()));
+        }
+    }
+    let Some((skew, url)) = worst else {
+        return Check::new("clock-skew", Status::Skipped, "no time source answered with a Date header");
+    };
+    let message = format!("local clock differs from {} by {}s", url, skew);
+    let fix = "enable time synchronisation, e.g. `timedatectl set-ntp true` or chrony";
+    if skew > SKEW_ERROR_SECS {
+        Check::new("clock-skew", Status::Error, message).with_fix(fix)
+    } else if skew > SKEW_WARNING_SECS {
+        Check::new("clock-skew", Status::Warning, message).with_fix(fix)
+    } else {
+        Check::new("clock-skew", Status::Ok, message)
+    }
+}
+
+/// 可以查询时间的 HTTP 地址：远端、身份提供方与 S3 端点，去重后按地址排序
+fn time_sources(config: &RepoConfig) -> Vec<String> {
+    let mut urls: BTreeSet<String> = BTreeSet::new();
+    urls.extend(config.remote.values().map(|remote| remote.url.clone()));
+    urls.extend(config.auth.providers.values().map(|provider| provider.issuer.clone()));
+    if let Some(s3) = &config.storage.s3 {
+        urls.insert(match &s3.endpoint {
+            Some(endpoint) => endpoint.clone(),
+            None => format!("https://s3.{}.amazonaws.com", s3.region),
+        });
+    }
+    urls.into_iter()
+        .filter(|url| url.starts_with("http://") || url.starts_with("https://"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::config::RemoteConfig;
+    use crate::test_utils::{commit_files, init_repo};
+
+    fn status(report: &DoctorReport, name: &str) -> Status {
+        report.checks.iter().find(|check| check.name == name).unwrap().status
+    }
+
+    /// 测试健康的仓库、损坏的索引与无效的配置
+    #[test]
+    fn test_doctor() {
+        let (dir, mut repo) = init_repo();
+        let options = DoctorOptions { offline: true };
+        let report = doctor(dir.path(), &options);
+        assert_eq!(status(&report, "config"), Status::Ok);
+        assert_eq!(status(&report, "storage"), Status::Ok);
+        assert_eq!(status(&report, "index"), Status::Ok);
+        assert_eq!(status(&report, "credentials"), Status::Skipped);
+        assert_eq!(report.checks.len(), 6);
+
+        // 索引条目指向不存在的对象
+        let commit = commit_files(&repo, &[("a.txt", b"a")], &[], "init");
+        let mut index = Index::new(2);
+        index.read_tree(&repo, &repo.read_commit(&commit).unwrap().tree).unwrap();
+        let mut entry = index.entries()[0].clone();
+        entry.id = repo.object_format().hash_object(crate::object::ObjectType::Blob, b"missing");
+        index.add(entry);
+        index.save(&repo).unwrap();
+        let check = doctor(dir.path(), &options).checks.into_iter().find(|check| check.name == "index").unwrap();
+        assert_eq!(check.status, Status::Error);
+        assert!(check.fix.unwrap().contains("read-tree"));
+
+        std::fs::write(repo.mono_dir().join(crate::index::INDEX_FILE), b"garbage").unwrap();
+        assert_eq!(status(&doctor(dir.path(), &options), "index"), Status::Error);
+
+        repo.config_mut().index.version = 9;
+        repo.save_config().unwrap();
+        let report = doctor(dir.path(), &options);
+        assert_eq!(status(&report, "config"), Status::Error);
+        assert_eq!(status(&report, "storage"), Status::Skipped);
+        assert_eq!(report.worst(), Some(Status::Error));
+
+        let outside = tempfile::tempdir().unwrap();
+        assert_eq!(status(&doctor(outside.path(), &options), "config"), Status::Error);
+    }
+
+    /// 测试 `df` 输出的解析与时间来源的选择
+    #[test]
+    fn test_disk_and_time_sources() {
+        let output = "Filesystem 1024-blocks Used Available Capacity Mounted on\n/dev/sda1 1000 400 600 40% /\n";
+        assert_eq!(parse_df(output), Some((600 * 1024, 1000 * 1024)));
+        assert_eq!(parse_df("Filesystem\n"), None);
+
+        let mut config = RepoConfig::default();
+        for (name, url) in [("origin", "https://mono.example.com/repo"), ("local", "/srv/mono"), ("ssh", "ssh://git@host/repo")] {
+            config.remote.insert(
+                name.to_string(),
+                RemoteConfig {
+                    url: url.to_string(),
+                    promisor: false,
+                    partial_clone_filter: None,
+                },
+            );
+        }
+        assert_eq!(time_sources(&config), ["https://mono.example.com/repo"]);
+        assert_eq!(check_clock_skew(&RepoConfig::default()).status, Status::Skipped);
+    }
+}
@@ -0,0 +1,519 @@
+//! 导出树快照
+//!
+//! 把某个修订（或其中的子目录）的树物化为 tar 包、zip 包或目录，供 CI 与二分查找等只需要
+//! 一份文件快照的场景使用：不需要工作区，也不写索引，归档边遍历树边从对象存储读取 blob
+//! 并写出，`mono export` 写到标准输出，REST 接口 `GET /api/v1/archive`（见
+//! [`crate::server::api`]）直接作为响应体发送。
+//!
+//! 与 `git archive` 一致，归档中全部条目的修改时间为提交者时间，符号链接保留为链接，
+//! 子模块导出为空目录；`.mono` 与 `.git` 等保留名称被跳过。tar 包使用 ustar 格式，
+//! 超过 100 字节的路径与链接目标写入 pax 扩展头；zip 包不支持 zip64，超过 4 GiB 或
+//! 65535 个条目时报错。
+
+use std::io::Write;
+use std::path::Path;
+
+use chrono::{Datelike, Timelike};
+use flate2::write::DeflateEncoder;
+use flate2::{Compression, Crc};
+use serde::Deserialize;
+
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::object::tree::{check_entry_name, FileMode, Tree};
+use crate::object::ObjectId;
+use crate::repo::{is_reserved_name, Repository};
+use crate::rewrite::normalize_prefix;
+use crate::worktree::{self, CheckoutStats};
+
+/// tar 的块大小
+const TAR_BLOCK: usize = 512;
+/// ustar 头中名称与链接目标字段的长度
+const TAR_NAME_LEN: usize = 100;
+/// ustar 头中 11 位八进制大小字段能表示的最大值
+const TAR_MAX_SIZE: u64 = 0o77777777777;
+
+/// 导出格式
+#[derive(clap::ValueEnum, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Tar,
+    Zip,
+    /// 写入本地目录
+    Dir,
+}
+
+impl ExportFormat {
+    /// 归档的 MIME 类型，目录格式为 None
+    pub fn content_type(&self) -> Option<&'static str> {
+        match self {
+            ExportFormat::Tar => Some("application/x-tar"),
+            ExportFormat::Zip => Some("application/zip"),
+            ExportFormat::Dir => None,
+        }
+    }
+}
+
+/// 要导出的树
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    pub commit: ObjectId,
+    /// 修订的根树或子目录的树
+    pub tree: ObjectId,
+    /// 归档条目的修改时间（Unix 时间戳），即提交者时间
+    pub mtime: i64,
+}
+
+impl Snapshot {
+    /// 修订中 `path` 子目录的快照，`path` 接受 `//dir` 与 `dir/` 等写法，省略时为根目录
+    pub fn resolve(repo: &Repository, rev: &str, path: Option<&str>) -> MonoResult<Snapshot> {
+        let commit_id = repo.resolve_rev(rev)?;
+        let commit = repo.read_commit(&commit_id)?;
+        let tree = match path.filter(|path| !path.trim_matches('/').is_empty()) {
+            None => commit.tree,
+            Some(path) => {
+                let dir = normalize_prefix(path)?;
+                repo.find_path(&commit.tree, &dir)?
+                    .filter(|entry| entry.mode.is_tree())
+                    .ok_or_else(|| MonoError::not_found(format!("directory {} in {}", dir, rev)))?
+                    .id
+            }
+        };
+        Ok(Snapshot {
+            commit: commit_id,
+            tree,
+            mtime: commit.committer.timestamp,
+        })
+    }
+}
+
+/// 按 `format` 把快照写成归档，`include(路径, 是否目录)` 返回 false 的条目被跳过
+pub fn write_archive<F>(repo: &Repository, snapshot: &Snapshot, format: ExportFormat, out: &mut dyn Write, include: F) -> MonoResult<CheckoutStats>
+where
+    F: Fn(&str, bool) -> bool,
+{
+    let stats = match format {
+        ExportFormat::Tar => {
+            let mut tar = TarWriter { out, mtime: snapshot.mtime };
+            let stats = walk(repo, &snapshot.tree, &include, &mut tar)?;
+            tar.out.write_all(&[0; TAR_BLOCK * 2])?;
+            stats
+        }
+        ExportFormat::Zip => {
+            let mut zip = ZipWriter::new(out, snapshot.mtime);
+            let stats = walk(repo, &snapshot.tree, &include, &mut zip)?;
+            zip.finish()?;
+            stats
+        }
+        ExportFormat::Dir => return Err(MonoError::usage("directory exports are written with export_dir")),
+    };
+    out.flush()?;
+    Ok(stats)
+}
+
+/// 把快照写入目录，目录必须不存在或为空
+pub fn export_dir<F>(repo: &Repository, snapshot: &Snapshot, dir: &Path, include: F) -> MonoResult<CheckoutStats>
+where
+    F: Fn(&str, bool) -> bool,
+{
+    if dir.exists() && std::fs::read_dir(dir)?.next().is_some() {
+        return Err(MonoError::usage(format!("{} is not empty", dir.display())));
+    }
+    worktree::checkout_tree_to(repo, &snapshot.tree, dir, include)
+}
+
+/// 归档中的一个条目
+trait ArchiveSink {
+    fn directory(&mut self, path: &str) -> MonoResult<()>;
+    fn file(&mut self, path: &str, mode: FileMode, data: &[u8]) -> MonoResult<()>;
+}
+
+/// 逐个目录遍历树：先写出目录中的条目，再按顺序进入各个子目录，目录条目总是先于其内容
+fn walk<F>(repo: &Repository, tree: &ObjectId, include: &F, sink: &mut dyn ArchiveSink) -> MonoResult<CheckoutStats>
+where
+    F: Fn(&str, bool) -> bool,
+{
+    let mut stats = CheckoutStats::default();
+    let mut stack = vec![(String::new(), *tree)];
+    while let Some((prefix, tree_id)) = stack.pop() {
+        let tree = Tree::parse(&repo.read_object(&tree_id)?.data, tree_id.format())?;
+        let mut subtrees = Vec::new();
+        for entry in tree.entries {
+            check_entry_name(&tree_id, &entry.name)?;
+            if is_reserved_name(&entry.name) {
+                continue;
+            }
+            let path = format!("{}{}", prefix, entry.name);
+            if !include(&path, entry.mode.is_tree()) {
+                continue;
+            }
+            if entry.mode.is_tree() || entry.mode.is_gitlink() {
+                sink.directory(&format!("{}/", path))?;
+                if entry.mode.is_tree() {
+                    subtrees.push((format!("{}/", path), entry.id));
+                }
+            } else {
+                let data = repo.read_object(&entry.id)?.data;
+                sink.file(&path, entry.mode, &data)?;
+                stats.files += 1;
+                stats.bytes += data.len() as u64;
+            }
+        }
+        // 栈顶是第一个子目录，保持树中的顺序
+        stack.extend(subtrees.into_iter().rev());
+    }
+    Ok(stats)
+}
+
+/// ustar 格式的 tar 包
+struct TarWriter<'a> {
+    out: &'a mut dyn Write,
+    mtime: i64,
+}
+
+impl TarWriter<'_> {
+    fn entry(&mut self, path: &str, mode: u32, kind: u8, link: &str, data: &[u8]) -> MonoResult<()> {
+        let mut pax = Vec::new();
+        if path.len() > TAR_NAME_LEN {
+            pax_record(&mut pax, "path", path);
+        }
+        if link.len() > TAR_NAME_LEN {
+            pax_record(&mut pax, "linkpath", link);
+        }
+        if data.len() as u64 > TAR_MAX_SIZE {
+            pax_record(&mut pax, "size", &data.len().to_string());
+        }
+        if !pax.is_empty() {
+            self.header("pax_header", 0o644, pax.len() as u64, b'x', "")?;
+            self.data(&pax)?;
+        }
+        self.header(path, mode, data.len() as u64, kind, link)?;
+        self.data(data)
+    }
+
+    fn header(&mut self, path: &str, mode: u32, size: u64, kind: u8, link: &str) -> MonoResult<()> {
+        let mut header = [0u8; TAR_BLOCK];
+        let field = |header: &mut [u8; TAR_BLOCK], offset: usize, len: usize, value: &[u8]| {
+            let n = value.len().min(len);
+            header[offset..offset + n].copy_from_slice(&value[..n]);
+        };
+        field(&mut header, 0, TAR_NAME_LEN, path.as_bytes());
+        field(&mut header, 100, 8, format!("{:07o}\0", mode).as_bytes());
+        field(&mut header, 108, 8, b"0000000\0");
+        field(&mut header, 116, 8, b"0000000\0");
+        field(&mut header, 124, 12, format!("{:011o}\0", size.min(TAR_MAX_SIZE)).as_bytes());
+        field(&mut header, 136, 12, format!("{:011o}\0", self.mtime.max(0)).as_bytes());
+        header[156] = kind;
+        field(&mut header, 157, TAR_NAME_LEN, link.as_bytes());
+        field(&mut header, 257, 8, b"ustar\x0000");
+        field(&mut header, 265, 32, b"root");
+        field(&mut header, 297, 32, b"root");
+        // 校验和按校验和字段为空格计算
+        header[148..156].fill(b' ');
+        let checksum: u32 = header.iter().map(|&b| b as u32).sum();
+        field(&mut header, 148, 8, format!("{:06o}\0 ", checksum).as_bytes());
+        self.out.write_all(&header)?;
+        Ok(())
+    }
+
+    fn data(&mut self, data: &[u8]) -> MonoResult<()> {
+        self.out.write_all(data)?;
+        let padding = (TAR_BLOCK - data.len() % TAR_BLOCK) % TAR_BLOCK;
+        self.out.write_all(&[0; TAR_BLOCK][..padding])?;
+        Ok(())
+    }
+}
+
+impl ArchiveSink for TarWriter<'_> {
+    fn directory(&mut self, path: &str) -> MonoResult<()> {
+        self.entry(path, 0o755, b'5', "", &[])
+    }
+
+    fn file(&mut self, path: &str, mode: FileMode, data: &[u8]) -> MonoResult<()> {
+        if mode == FileMode::SYMLINK {
+            let target = String::from_utf8_lossy(data).into_owned();
+            return self.entry(path, 0o777, b'2', &target, &[]);
+        }
+        let permissions = if mode == FileMode::EXECUTABLE { 0o755 } else { 0o644 };
+        self.entry(path, permissions, b'0', "", data)
+    }
+}
+
+/// pax 扩展头中的一条记录：`<长度> <键>=<值>\n`，长度包括长度字段本身
+fn pax_record(out: &mut Vec<u8>, key: &str, value: &str) {
+    let rest = key.len() + value.len() + 3;
+    let mut len = rest + 1;
+    while len != rest + len.to_string().len() {
+        len = rest + len.to_string().len();
+    }
+    out.extend_from_slice(format!("{} {}={}\n", len, key, value).as_bytes());
+}
+
+/// zip 包的中央目录条目
+struct ZipEntry {
+    name: String,
+    method: u16,
+    crc: u32,
+    compressed: u32,
+    size: u32,
+    /// 高 16 位为 Unix 文件模式
+    external: u32,
+    offset: u32,
+}
+
+/// 不支持 zip64 的 zip 包
+struct ZipWriter<'a> {
+    out: &'a mut dyn Write,
+    written: u64,
+    time: u16,
+    date: u16,
+    entries: Vec<ZipEntry>,
+}
+
+/// 通用标志：文件名为 UTF-8
+const ZIP_UTF8: u16 = 0x0800;
+/// 解压需要的版本：2.0
+const ZIP_VERSION: u16 = 20;
+/// 创建者的系统为 Unix，外部属性中保存文件模式
+const ZIP_MADE_BY: u16 = (3 << 8) | ZIP_VERSION;
+const ZIP_STORED: u16 = 0;
+const ZIP_DEFLATED: u16 = 8;
+
+impl<'a> ZipWriter<'a> {
+    fn new(out: &'a mut dyn Write, mtime: i64) -> ZipWriter<'a> {
+        let (time, date) = dos_time(mtime);
+        ZipWriter {
+            out,
+            written: 0,
+            time,
+            date,
+            entries: Vec::new(),
+        }
+    }
+
+    fn write(&mut self, data: &[u8]) -> MonoResult<()> {
+        self.out.write_all(data)?;
+        self.written += data.len() as u64;
+        Ok(())
+    }
+
+    fn entry(&mut self, name: &str, mode: u32, data: &[u8]) -> MonoResult<()> {
+        let too_large = || MonoError::usage("snapshot is too large for the zip format; export it as tar instead");
+        if self.entries.len() >= u16::MAX as usize {
+            return Err(too_large());
+        }
+        let offset = u32::try_from(self.written).map_err(|_| too_large())?;
+        let size = u32::try_from(data.len()).map_err(|_| too_large())?;
+        let mut crc = Crc::new();
+        crc.update(data);
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data)?;
+        let deflated = encoder.finish()?;
+        let (method, body) = if deflated.len() < data.len() { (ZIP_DEFLATED, &deflated[..]) } else { (ZIP_STORED, data) };
+        let entry = ZipEntry {
+            name: name.to_string(),
+            method,
+            crc: crc.sum(),
+            compressed: body.len() as u32,
+            size,
+            external: mode << 16 | if name.ends_with('/') { 0x10 } else { 0 },
+            offset,
+        };
+
+        let mut header = Vec::with_capacity(30 + name.len());
+        header.extend_from_slice(&0x04034b50u32.to_le_bytes());
+        header.extend_from_slice(&ZIP_VERSION.to_le_bytes());
+        header.extend_from_slice(&ZIP_UTF8.to_le_bytes());
+        header.extend_from_slice(&entry.method.to_le_bytes());
+        header.extend_from_slice(&self.time.to_le_bytes());
+        header.extend_from_slice(&self.date.to_le_bytes());
+        header.extend_from_slice(&entry.crc.to_le_bytes());
+        header.extend_from_slice(&entry.compressed.to_le_bytes());
+        header.extend_from_slice(&entry.size.to_le_bytes());
+        header.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        header.extend_from_slice(&0u16.to_le_bytes());
+        header.extend_from_slice(name.as_bytes());
+        self.write(&header)?;
+        self.write(body)?;
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    /// 写出中央目录与目录结束记录
+    fn finish(&mut self) -> MonoResult<()> {
+        let too_large = || MonoError::usage("snapshot is too large for the zip format; export it as tar instead");
+        let start = u32::try_from(self.written).map_err(|_| too_large())?;
+        let entries = std::mem::take(&mut self.entries);
+        for entry in &entries {
+            let mut record = Vec::with_capacity(46 + entry.name.len());
+            record.extend_from_slice(&0x02014b50u32.to_le_bytes());
+            record.extend_from_slice(&ZIP_MADE_BY.to_le_bytes());
+            record.extend_from_slice(&ZIP_VERSION.to_le_bytes());
+            record.extend_from_slice(&ZIP_UTF8.to_le_bytes());
+            record.extend_from_slice(&entry.method.to_le_bytes());
+            record.extend_from_slice(&self.time.to_le_bytes());
+            record.extend_from_slice(&self.date.to_le_bytes());
+            record.extend_from_slice(&entry.crc.to_le_bytes());
+            record.extend_from_slice(&entry.compressed.to_le_bytes());
+            record.extend_from_slice(&entry.size.to_le_bytes());
+            record.extend_from_slice(&(entry.name.len() as u16).to_le_bytes());
+            // 扩展字段、注释、起始磁盘号与内部属性
+            record.extend_from_slice(&[0; 8]);
+            record.extend_from_slice(&entry.external.to_le_bytes());
+            record.extend_from_slice(&entry.offset.to_le_bytes());
+            record.extend_from_slice(entry.name.as_bytes());
+            self.write(&record)?;
+        }
+        let size = u32::try_from(self.written - start as u64).map_err(|_| too_large())?;
+        let mut end = Vec::with_capacity(22);
+        end.extend_from_slice(&0x06054b50u32.to_le_bytes());
+        end.extend_from_slice(&[0; 4]);
+        end.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        end.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        end.extend_from_slice(&size.to_le_bytes());
+        end.extend_from_slice(&start.to_le_bytes());
+        end.extend_from_slice(&0u16.to_le_bytes());
+        self.write(&end)
+    }
+}
+
+impl ArchiveSink for ZipWriter<'_> {
+    fn directory(&mut self, path: &str) -> MonoResult<()> {
+        self.entry(path, 0o40755, &[])
+    }
+
+    fn file(&mut self, path: &str, mode: FileMode, data: &[u8]) -> MonoResult<()> {
+        let mode = match mode {
+            FileMode::SYMLINK => 0o120777,
+            FileMode::EXECUTABLE => 0o100755,
+            _ => 0o100644,
+        };
+        self.entry(path, mode, data)
+    }
+}
+
+/// Unix 时间戳转换为 MS-DOS 的时间与日期，超出 1980 到 2107 年的按边界处理
+fn dos_time(timestamp: i64) -> (u16, u16) {
+    let Some(time) = chrono::DateTime::from_timestamp(timestamp, 0) else {
+        return (0, (1 << 5) | 1);
+    };
+    if time.year() < 1980 {
+        return (0, (1 << 5) | 1);
+    }
+    if time.year() > 2107 {
+        return ((23 << 11) | (59 << 5) | 29, (127 << 9) | (12 << 5) | 31);
+    }
+    let dos_time = (time.hour() << 11) | (time.minute() << 5) | (time.second() / 2);
+    let dos_date = (((time.year() - 1980) as u32) << 9) | (time.month() << 5) | time.day();
+    (dos_time as u16, dos_date as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{commit_files, init_repo};
+
+    /// 按 ustar 头解析 tar 包中的条目：(路径, 类型, 内容)，pax 扩展头中的路径覆盖头中的名称
+    fn read_tar(data: &[u8]) -> Vec<(String, u8, Vec<u8>)> {
+        let mut entries = Vec::new();
+        let mut pos = 0;
+        let mut long_path = None;
+        while data[pos..pos + TAR_BLOCK].iter().any(|&b| b != 0) {
+            let header = &data[pos..pos + TAR_BLOCK];
+            let text = |range: std::ops::Range<usize>| {
+                String::from_utf8(header[range].iter().copied().take_while(|&b| b != 0).collect()).unwrap()
+            };
+            let size = u64::from_str_radix(text(124..135).as_str(), 8).unwrap() as usize;
+            let body = data[pos + TAR_BLOCK..pos + TAR_BLOCK + size].to_vec();
+            pos += TAR_BLOCK + size.div_ceil(TAR_BLOCK) * TAR_BLOCK;
+            if header[156] == b'x' {
+                let record = String::from_utf8(body).unwrap();
+                long_path = record.split_once("path=").map(|(_, path)| path.trim_end().to_string());
+                continue;
+            }
+            entries.push((long_path.take().unwrap_or_else(|| text(0..100)), header[156], body));
+        }
+        entries
+    }
+
+    /// 测试 tar 包的条目顺序、文件模式、子目录快照与长路径
+    #[test]
+    fn test_tar() {
+        let (_dir, repo) = init_repo();
+        let long = format!("src/{}.rs", "x".repeat(120));
+        let commit = commit_files(
+            &repo,
+            &[("README.md", b"hello"), ("src/lib.rs", b"fn a() {}"), (long.as_str(), b"long"), ("src/secret/key", b"k")],
+            &[],
+            "init",
+        );
+        let snapshot = Snapshot::resolve(&repo, &commit.to_hex(), None).unwrap();
+        let mut out = Vec::new();
+        let stats = write_archive(&repo, &snapshot, ExportFormat::Tar, &mut out, |path, _| path != "src/secret").unwrap();
+        assert_eq!(stats.files, 3);
+        assert_eq!(out.len() % TAR_BLOCK, 0);
+        let entries = read_tar(&out);
+        let paths: Vec<&str> = entries.iter().map(|(path, _, _)| path.as_str()).collect();
+        assert_eq!(paths, ["README.md", "src/", "src/lib.rs", long.as_str()]);
+        assert_eq!(entries[1].1, b'5');
+        assert_eq!(entries[3].2, b"long");
+
+        let snapshot = Snapshot::resolve(&repo, &commit.to_hex(), Some("//src/secret/...")).unwrap();
+        let mut out = Vec::new();
+        write_archive(&repo, &snapshot, ExportFormat::Tar, &mut out, |_, _| true).unwrap();
+        assert_eq!(read_tar(&out), [("key".to_string(), b'0', b"k".to_vec())]);
+        assert!(Snapshot::resolve(&repo, &commit.to_hex(), Some("//README.md")).is_err());
+    }
+
+    /// 测试 zip 包的本地头与中央目录，以及目录导出
+    #[test]
+    fn test_zip_and_dir() {
+        let (dir, repo) = init_repo();
+        let content = b"compressible ".repeat(100);
+        let commit = commit_files(&repo, &[("a/b.txt", content.as_slice()), ("c.txt", b"c")], &[], "init");
+        let snapshot = Snapshot::resolve(&repo, &commit.to_hex(), None).unwrap();
+        let mut out = Vec::new();
+        write_archive(&repo, &snapshot, ExportFormat::Zip, &mut out, |_, _| true).unwrap();
+        assert_eq!(&out[..4], b"PK\x03\x04");
+        let end = &out[out.len() - 22..];
+        assert_eq!(&end[..4], b"PK\x05\x06");
+        assert_eq!(u16::from_le_bytes([end[10], end[11]]), 3);
+        let central = u32::from_le_bytes([end[16], end[17], end[18], end[19]]) as usize;
+        assert_eq!(&out[central..central + 4], b"PK\x01\x02");
+        assert!(out.len() < content.len());
+
+        let target = dir.path().join("export");
+        let stats = export_dir(&repo, &snapshot, &target, |_, _| true).unwrap();
+        assert_eq!(stats.files, 2);
+        assert_eq!(std::fs::read(target.join("a/b.txt")).unwrap(), content);
+        assert!(export_dir(&repo, &snapshot, &target, |_, _| true).is_err());
+    }
+
+    /// 测试条目名含 `..` 的树不会写入归档
+    #[test]
+    fn test_archive_rejects_traversal() {
+        use crate::object::commit::{Commit, Signature};
+        use crate::object::tree::TreeEntry;
+        use crate::object::ObjectType;
+
+        let (_dir, repo) = init_repo();
+        let blob = repo.write_object(ObjectType::Blob, b"evil").unwrap();
+        let tree = Tree { entries: vec![TreeEntry::new(FileMode::BLOB, "../evil.txt", blob)] };
+        let tree = repo.write_object(ObjectType::Tree, &tree.encode()).unwrap();
+        let sig = Signature::new("A U Thor", "author@example.com", 1_700_000_000);
+        let commit = Commit {
+            tree,
+            parents: Vec::new(),
+            author: sig.clone(),
+            committer: sig,
+            extra_headers: Vec::new(),
+            message: "evil\n".to_string(),
+        };
+        let commit = repo.write_object(ObjectType::Commit, &commit.encode()).unwrap();
+        let snapshot = Snapshot::resolve(&repo, &commit.to_hex(), None).unwrap();
+        for format in [ExportFormat::Tar, ExportFormat::Zip] {
+            let err = write_archive(&repo, &snapshot, format, &mut Vec::new(), |_, _| true).unwrap_err();
+            assert!(err.to_string().contains("invalid entry name"), "{}", err);
+        }
+    }
+}
@@ -0,0 +1,168 @@
+//! 只读维护模式
+//!
+//! `mono admin freeze` 在迁移或重新打包期间把仓库置为只读：服务端拒绝推送、LFS 上传、REST 与 gRPC 的
+//! 写操作，返回 `Unavailable` 错误并提示多久后重试（HTTP 同时带 `Retry-After`），fetch 不受影响。
+//! `mono admin thaw` 恢复写入。状态保存在 `.mono/frozen.json`；配置了租户时可以只冻结一个租户，
+//! 保存在 `.mono/frozen/<租户>.json`。每次写请求都重新读取状态，运行中的 `mono serve` 不需要重新加载。
+//!
+//! 本地命令不受影响，维护本身就在本地执行。
+
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::refs;
+use crate::repo::Repository;
+
+/// 未指定时建议客户端等待的秒数
+pub const DEFAULT_RETRY_AFTER_SECS: u64 = 300;
+
+/// 一次冻结
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Freeze {
+    /// 冻结的原因，出现在拒绝写入的错误中
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// 冻结的时间（Unix 秒）
+    pub since: i64,
+    /// 建议客户端等待多久再重试
+    pub retry_after_secs: u64,
+}
+
+/// 写入被拒绝：仓库或租户处于只读维护模式
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frozen {
+    /// 被冻结的租户，None 表示整个仓库
+    pub tenant: Option<String>,
+    pub freeze: Freeze,
+}
+
+impl std::fmt::Display for Frozen {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match &self.tenant {
+            Some(tenant) => write!(f, "repository {} is read-only for maintenance", tenant)?,
+            None => write!(f, "repository is read-only for maintenance")?,
+        }
+        if let Some(reason) = &self.freeze.reason {
+            write!(f, " ({})", reason)?;
+        }
+        write!(f, "; retry after {}s", self.freeze.retry_after_secs)
+    }
+}
+
+impl From<Frozen> for MonoError {
+    fn from(frozen: Frozen) -> MonoError {
+        MonoError::unavailable(frozen.to_string())
+    }
+}
+
+fn path(repo: &Repository, tenant: Option<&str>) -> MonoResult<PathBuf> {
+    Ok(match tenant {
+        Some(tenant) => {
+            refs::check_namespace(tenant)?;
+            repo.mono_dir().join("frozen").join(format!("{}.json", tenant))
+        }
+        None => repo.mono_dir().join("frozen.json"),
+    })
+}
+
+fn read(repo: &Repository, tenant: Option<&str>) -> MonoResult<Option<Freeze>> {
+    let path = path(repo, tenant)?;
+    let data = match std::fs::read(&path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    serde_json::from_slice(&data)
+        .map(Some)
+        .map_err(|e| MonoError::storage(format!("corrupt freeze record {}: {}", path.display(), e)))
+}
+
+/// 冻结整个仓库或 `tenant`，已冻结时更新原因与重试时间
+pub fn freeze(repo: &Repository, tenant: Option<&str>, freeze: &Freeze) -> MonoResult<()> {
+    let path = path(repo, tenant)?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let data = serde_json::to_vec_pretty(freeze).map_err(|e| MonoError::storage(e.to_string()))?;
+    let tmp = path.with_extension("json.tmp");
+    std::fs::write(&tmp, data)?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+/// 解除冻结，原本没有冻结时返回 false
+pub fn thaw(repo: &Repository, tenant: Option<&str>) -> MonoResult<bool> {
+    match std::fs::remove_file(path(repo, tenant)?) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// 仓库（在命名空间中时先看整个仓库，再看所在的租户）的冻结状态
+pub fn frozen(repo: &Repository) -> MonoResult<Option<Frozen>> {
+    if let Some(freeze) = read(repo, None)? {
+        return Ok(Some(Frozen { tenant: None, freeze }));
+    }
+    let Some(tenant) = repo.namespace() else {
+        return Ok(None);
+    };
+    Ok(read(repo, Some(tenant))?.map(|freeze| Frozen {
+        tenant: Some(tenant.to_string()),
+        freeze,
+    }))
+}
+
+/// 写操作之前调用，冻结时返回 `Unavailable` 错误
+pub fn check(repo: &Repository) -> MonoResult<()> {
+    match frozen(repo)? {
+        Some(frozen) => Err(frozen.into()),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::init_repo;
+
+    /// 测试冻结整个仓库与单个租户，以及解除冻结
+    #[test]
+    fn test_freeze() {
+        let (_dir, repo) = init_repo();
+        let payments = repo.with_namespace("payments").unwrap();
+        let search = repo.with_namespace("search").unwrap();
+        assert!(check(&repo).is_ok());
+
+        let migration = Freeze {
+            reason: Some("storage migration".to_string()),
+            since: 1,
+            retry_after_secs: 600,
+        };
+        freeze(&repo, Some("payments"), &migration).unwrap();
+        assert!(check(&repo).is_ok());
+        assert!(check(&search).is_ok());
+        let err = check(&payments).unwrap_err();
+        assert!(err.is_retryable());
+        assert_eq!(
+            err.to_string(),
+            "Service unavailable: repository payments is read-only for maintenance (storage migration); retry after 600s"
+        );
+
+        let repack = Freeze { reason: None, since: 2, retry_after_secs: 60 };
+        freeze(&repo, None, &repack).unwrap();
+        let frozen = frozen(&search).unwrap().unwrap();
+        assert_eq!(frozen.tenant, None);
+        assert_eq!(frozen.to_string(), "repository is read-only for maintenance; retry after 60s");
+
+        assert!(thaw(&repo, None).unwrap());
+        assert!(!thaw(&repo, None).unwrap());
+        assert!(check(&repo).is_ok());
+        assert!(thaw(&repo, Some("payments")).unwrap());
+        assert!(check(&payments).is_ok());
+        assert!(freeze(&repo, Some("../x"), &repack).is_err());
+    }
+}
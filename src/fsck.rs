@@ -0,0 +1,442 @@
+//! 仓库完整性检查（`mono fsck`）
+//!
+//! 依次检查：
+//!
+//! - 对象：内容的哈希与对象 ID 一致，提交、树与标签可以解析
+//! - 树条目：名称与模式合法、没有重复、按 git 的规则排序，指向的对象存在且类型与模式相符
+//! - 提交与标签：树、父提交与标签目标存在且类型正确，浅提交的父提交不检查
+//! - 引用：指向的对象存在，分支指向提交
+//! - pack：`fs` 后端中每个 pack 的校验和、索引中的 CRC32 与对象 ID 与 pack 内容一致，
+//!   多包索引引用的 pack 都存在
+//! - LFS：指针文件引用的大文件存在且大小与指针一致
+//!
+//! 每个问题带有严重级别与稳定的检查名，便于脚本按级别决定退出码。部分克隆的仓库缺少对象
+//! 是正常的，不报告缺失的对象。
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::common::config::StorageBackend;
+use crate::common::MonoResult;
+use crate::lfs::Pointer;
+use crate::object::commit::Commit;
+use crate::object::tag::Tag;
+use crate::object::tree::{compare_entries, is_valid_name, FileMode, Tree};
+use crate::object::{ObjectId, ObjectType};
+use crate::pack::file::PackFile;
+use crate::pack::midx::{MultiPackIndex, MIDX_FILE};
+use crate::pack::crc32;
+use crate::refs::{RefTarget, HEAD, HEADS_PREFIX};
+use crate::repo::{is_reserved_name, Repository};
+
+/// 问题的严重级别
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// 不影响读取，但 git 可能拒绝或行为不一致
+    Warning,
+    /// 数据损坏或缺失
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// 检查发现的一个问题
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Issue {
+    pub severity: Severity,
+    /// 稳定的检查名，例如 `hash-mismatch`
+    pub check: &'static str,
+    /// 出问题的对象、引用或文件
+    pub subject: String,
+    pub message: String,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {} {}: {}", self.severity, self.check, self.subject, self.message)
+    }
+}
+
+/// 检查结果
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct FsckReport {
+    /// 检查的对象数
+    pub objects: usize,
+    /// 检查的 pack 数
+    pub packs: usize,
+    /// 检查的 LFS 指针数
+    pub lfs_pointers: usize,
+    pub issues: Vec<Issue>,
+}
+
+impl FsckReport {
+    /// 某一级别的问题数
+    pub fn count(&self, severity: Severity) -> usize {
+        self.issues.iter().filter(|issue| issue.severity == severity).count()
+    }
+
+    /// 最严重的问题级别，没有问题时返回 None
+    pub fn max_severity(&self) -> Option<Severity> {
+        self.issues.iter().map(|issue| issue.severity).max()
+    }
+
+    fn push(&mut self, severity: Severity, check: &'static str, subject: impl ToString, message: impl Into<String>) {
+        self.issues.push(Issue {
+            severity,
+            check,
+            subject: subject.to_string(),
+            message: message.into(),
+        });
+    }
+}
+
+/// 对象引用的另一个对象：引用方、被引用的对象、期望的类型（子模块为 None）与说明
+type Link = (ObjectId, ObjectId, Option<ObjectType>, &'static str);
+
+/// 检查仓库的完整性
+pub fn fsck(repo: &Repository) -> MonoResult<FsckReport> {
+    let mut report = FsckReport::default();
+    let mut types = HashMap::new();
+    let mut links: Vec<Link> = Vec::new();
+    let store = repo.objects();
+
+    for id in store.list()? {
+        check_object(repo, &id, &mut report, &mut types, &mut links)?;
+    }
+
+    // 部分克隆按需获取对象，缺少对象是正常的
+    let partial = repo.config().promisor_remote().is_some();
+    for link in links {
+        let actual = types.get(&link.1).copied();
+        check_link(&mut report, link, actual, partial);
+    }
+
+    check_refs(repo, &types, &mut report)?;
+    if repo.config().storage.backend == StorageBackend::Fs {
+        check_packs(&repo.objects_dir().join("pack"), &mut report)?;
+    }
+    Ok(report)
+}
+
+/// 检查推送收到的对象 `ids`：对象本身的检查与 [`fsck`] 相同，引用的对象需要在收到的对象或仓库中
+///
+/// `repo` 的对象存储需要同时能读到收到的对象与仓库已有的对象，见 [`crate::storage::quarantine`]。
+/// 不检查引用与 pack。
+pub fn fsck_incoming(repo: &Repository, ids: &[ObjectId]) -> MonoResult<FsckReport> {
+    let mut report = FsckReport::default();
+    let mut types = HashMap::new();
+    let mut links: Vec<Link> = Vec::new();
+    for id in ids {
+        check_object(repo, id, &mut report, &mut types, &mut links)?;
+    }
+    let partial = repo.config().promisor_remote().is_some();
+    for link in links {
+        let actual = match types.get(&link.1) {
+            Some(object_type) => Some(*object_type),
+            None => repo.objects().read_header(&link.1)?.map(|(object_type, _)| object_type),
+        };
+        check_link(&mut report, link, actual, partial);
+    }
+    Ok(report)
+}
+
+/// 检查一个对象：内容的哈希与 ID 一致、可以解析，并记录它引用的其他对象
+fn check_object(
+    repo: &Repository,
+    id: &ObjectId,
+    report: &mut FsckReport,
+    types: &mut HashMap<ObjectId, ObjectType>,
+    links: &mut Vec<Link>,
+) -> MonoResult<()> {
+    let store = repo.objects();
+    report.objects += 1;
+    let object = match store.read(id) {
+        Ok(Some(object)) => object,
+        Ok(None) => {
+            report.push(Severity::Error, "missing-object", id, "listed by the object store but cannot be read");
+            return Ok(());
+        }
+        Err(e) => {
+            report.push(Severity::Error, "unreadable-object", id, e.to_string());
+            return Ok(());
+        }
+    };
+    let actual = object.id_in(id.format());
+    if actual != *id {
+        report.push(Severity::Error, "hash-mismatch", id, format!("content hashes to {}", actual));
+        return Ok(());
+    }
+    types.insert(*id, object.object_type);
+    match object.object_type {
+        ObjectType::Commit => match Commit::parse(&object.data) {
+            Ok(commit) => {
+                links.push((*id, commit.tree, Some(ObjectType::Tree), "tree"));
+                // 浅提交的父提交不在本地
+                if !repo.shallow_commits().contains(id) {
+                    links.extend(commit.parents.iter().map(|parent| (*id, *parent, Some(ObjectType::Commit), "parent")));
+                }
+            }
+            Err(e) => report.push(Severity::Error, "malformed-object", id, e.to_string()),
+        },
+        ObjectType::Tree => match Tree::parse(&object.data, id.format()) {
+            Ok(tree) => check_tree(id, &tree, report, links),
+            Err(e) => report.push(Severity::Error, "malformed-object", id, e.to_string()),
+        },
+        ObjectType::Tag => match Tag::parse(&object.data) {
+            Ok(tag) => links.push((*id, tag.object, Some(tag.object_type), "tag target")),
+            Err(e) => report.push(Severity::Error, "malformed-object", id, e.to_string()),
+        },
+        ObjectType::Blob => {
+            if let Some(pointer) = Pointer::parse(&object.data) {
+                report.lfs_pointers += 1;
+                match store.lfs_size(&pointer.oid)? {
+                    None => report.push(
+                        Severity::Warning,
+                        "lfs-missing",
+                        id,
+                        format!("LFS object {} is not in the store", pointer.oid),
+                    ),
+                    Some(size) if size != pointer.size => report.push(
+                        Severity::Error,
+                        "lfs-size-mismatch",
+                        id,
+                        format!("LFS object {} has {} bytes, pointer says {}", pointer.oid, size, pointer.size),
+                    ),
+                    Some(_) => {}
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 检查被引用的对象存在且类型正确，`actual` 为被引用对象的实际类型
+fn check_link(report: &mut FsckReport, (from, to, expected, what): Link, actual: Option<ObjectType>, partial: bool) {
+    match (actual, expected) {
+        (None, None) => {}
+        (None, Some(_)) if partial => {}
+        (None, Some(_)) => report.push(Severity::Error, "missing-object", from, format!("{} {} is missing", what, to)),
+        (Some(actual), Some(expected)) if actual != expected => report.push(
+            Severity::Error,
+            "wrong-object-type",
+            from,
+            format!("{} {} is a {}, expected a {}", what, to, actual, expected),
+        ),
+        _ => {}
+    }
+}
+
+fn check_tree(id: &ObjectId, tree: &Tree, report: &mut FsckReport, links: &mut Vec<Link>) {
+    for (i, entry) in tree.entries.iter().enumerate() {
+        let name = &entry.name;
+        if !is_valid_name(name) {
+            report.push(Severity::Error, "bad-tree-entry", id, format!("invalid entry name {:?}", name));
+        } else if is_reserved_name(name) {
+            report.push(Severity::Warning, "reserved-tree-entry", id, format!("entry {} is never checked out", name));
+        }
+        if ![FileMode::TREE, FileMode::BLOB, FileMode::EXECUTABLE, FileMode::SYMLINK, FileMode::GITLINK].contains(&entry.mode)
+        {
+            report.push(Severity::Warning, "bad-file-mode", id, format!("entry {} has mode {:o}", name, entry.mode.0));
+        }
+        if let Some(previous) = i.checked_sub(1).map(|i| &tree.entries[i]) {
+            if previous.name == *name {
+                report.push(Severity::Error, "duplicate-tree-entry", id, format!("entry {} appears twice", name));
+            } else if compare_entries(previous, entry).is_gt() {
+                report.push(Severity::Warning, "tree-not-sorted", id, format!("entry {} is out of order", name));
+            }
+        }
+        links.push((*id, entry.id, entry.mode.object_type(), "entry"));
+    }
+}
+
+fn check_refs(repo: &Repository, types: &HashMap<ObjectId, ObjectType>, report: &mut FsckReport) -> MonoResult<()> {
+    let mut refs = repo.refs().list("refs/")?;
+    if let Some(RefTarget::Direct(id)) = repo.refs().read(HEAD)? {
+        refs.push((HEAD.to_string(), id));
+    }
+    for (name, id) in refs {
+        match types.get(&id) {
+            None => report.push(Severity::Error, "bad-ref", &name, format!("points to missing object {}", id)),
+            Some(object_type) if *object_type != ObjectType::Commit && (name == HEAD || name.starts_with(HEADS_PREFIX)) => {
+                report.push(Severity::Warning, "ref-not-commit", &name, format!("points to {} {}", object_type, id))
+            }
+            Some(_) => {}
+        }
+    }
+    Ok(())
+}
+
+/// 检查 pack 目录中的全部 pack 与多包索引
+fn check_packs(dir: &Path, report: &mut FsckReport) -> MonoResult<()> {
+    let mut paths = Vec::new();
+    match std::fs::read_dir(dir) {
+        Ok(entries) => {
+            for entry in entries {
+                paths.push(entry?.path());
+            }
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+    }
+    paths.sort();
+    for path in &paths {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        if path.extension().is_some_and(|ext| ext == "pack") {
+            report.packs += 1;
+            if let Err(e) = check_pack(path, report) {
+                report.push(Severity::Error, "pack-index", &name, e.to_string());
+            }
+        } else if path.extension().is_some_and(|ext| ext == "idx") && !path.with_extension("pack").exists() {
+            report.push(Severity::Warning, "pack-index", &name, "index without a pack");
+        }
+    }
+
+    let midx_path = dir.join(MIDX_FILE);
+    let data = match std::fs::read(&midx_path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+    match MultiPackIndex::parse(&data) {
+        Ok(midx) => {
+            for name in midx.pack_names() {
+                if !dir.join(name).with_extension("pack").exists() {
+                    report.push(Severity::Error, "multi-pack-index", MIDX_FILE, format!("pack {} does not exist", name));
+                }
+            }
+        }
+        Err(e) => report.push(Severity::Error, "multi-pack-index", MIDX_FILE, e.to_string()),
+    }
+    Ok(())
+}
+
+/// 顺序读一遍 pack：校验结尾的校验和与每个条目的 CRC32，再确认索引中每个对象的 ID
+fn check_pack(path: &Path, report: &mut FsckReport) -> MonoResult<()> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+    let pack = PackFile::open(path)?;
+    let mut entries = pack.index().entries().to_vec();
+    entries.sort_by_key(|entry| entry.offset);
+    let len = std::fs::metadata(path)?.len();
+    let format = pack.index().format();
+    let end = len - format.id_len() as u64;
+
+    let mut file = BufReader::new(File::open(path)?);
+    let mut hasher = format.hasher();
+    let mut header = [0u8; 12];
+    file.read_exact(&mut header)?;
+    hasher.update(&header);
+    let mut pos = header.len() as u64;
+    for (i, entry) in entries.iter().enumerate() {
+        let next = entries.get(i + 1).map_or(end, |next| next.offset);
+        if entry.offset != pos || next <= pos || next > end {
+            report.push(Severity::Error, "pack-index", &name, format!("object {} has invalid offset {}", entry.id, entry.offset));
+            return Ok(());
+        }
+        let mut raw = vec![0u8; (next - pos) as usize];
+        file.read_exact(&mut raw)?;
+        hasher.update(&raw);
+        if crc32(&raw) != entry.crc32 {
+            report.push(Severity::Error, "pack-crc", &name, format!("object {} does not match its CRC32", entry.id));
+        }
+        pos = next;
+    }
+    if pos != end {
+        report.push(Severity::Error, "pack-index", &name, format!("{} trailing bytes not covered by the index", end - pos));
+        return Ok(());
+    }
+    if hasher.finish() != *pack.index().pack_checksum() {
+        report.push(Severity::Error, "pack-checksum", &name, "pack content does not match its checksum");
+    }
+
+    for entry in &entries {
+        match pack.read_at(entry.offset) {
+            Ok(object) if object.id_in(format) == entry.id => {}
+            Ok(object) => report.push(
+                Severity::Error,
+                "pack-index",
+                &name,
+                format!("object at offset {} is {}, index says {}", entry.offset, object.id_in(format), entry.id),
+            ),
+            Err(e) => report.push(Severity::Error, "pack-index", &name, format!("object {}: {}", entry.id, e)),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lfs::LfsOid;
+    use crate::object::loose::LooseStore;
+    use crate::object::tree::TreeEntry;
+    use crate::object::RawObject;
+    use crate::pack::encode_pack;
+    use crate::test_utils::{commit_files, init_repo};
+
+    /// 测试完好的仓库没有问题，以及各类损坏分别以对应的检查名与级别报告
+    #[test]
+    fn test_fsck() {
+        let (_dir, repo) = init_repo();
+        let commit = commit_files(&repo, &[("a.txt", b"a")], &[], "init");
+        repo.refs().write("refs/heads/main", &commit).unwrap();
+        let oid = LfsOid::hash(b"big");
+        repo.objects().write_lfs(&oid, b"big").unwrap();
+        repo.write_object(ObjectType::Blob, Pointer { oid, size: 3 }.encode().as_bytes()).unwrap();
+        let report = fsck(&repo).unwrap();
+        assert_eq!(report.issues, Vec::new());
+        assert_eq!((report.objects, report.lfs_pointers), (4, 1));
+
+        let missing = ObjectId::hash_object(ObjectType::Blob, b"missing");
+        let entry = TreeEntry::new(FileMode::BLOB, "x", missing);
+        let tree = Tree { entries: vec![entry.clone(), entry] };
+        repo.write_object(ObjectType::Tree, &tree.encode()).unwrap();
+        repo.refs().write("refs/heads/broken", &missing).unwrap();
+        repo.write_object(ObjectType::Blob, Pointer { oid, size: 5 }.encode().as_bytes()).unwrap();
+        let absent = Pointer { oid: LfsOid::hash(b"absent"), size: 6 };
+        repo.write_object(ObjectType::Blob, absent.encode().as_bytes()).unwrap();
+        // 用另一个对象的内容覆盖松散对象
+        let loose = LooseStore::new(repo.objects_dir());
+        let a = repo.write_object(ObjectType::Blob, b"first").unwrap();
+        let b = repo.write_object(ObjectType::Blob, b"second").unwrap();
+        std::fs::remove_file(loose.object_path(&a)).unwrap();
+        std::fs::copy(loose.object_path(&b), loose.object_path(&a)).unwrap();
+        // 修改 pack 中间的一个字节
+        let packed = RawObject::new(ObjectType::Blob, b"packed content".to_vec());
+        repo.objects().write_pack(&encode_pack([&packed].into_iter()).unwrap()).unwrap();
+        let pack_dir = repo.objects_dir().join("pack");
+        let pack = std::fs::read_dir(&pack_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .find(|path| path.extension().is_some_and(|ext| ext == "pack"))
+            .unwrap();
+        let mut data = std::fs::read(&pack).unwrap();
+        data[14] ^= 0xff;
+        std::fs::write(&pack, data).unwrap();
+
+        let report = fsck(&repo).unwrap();
+        let found = |check: &str| report.issues.iter().filter(|issue| issue.check == check).count();
+        assert_eq!(found("duplicate-tree-entry"), 1);
+        assert_eq!(found("missing-object"), 2);
+        assert_eq!(found("bad-ref"), 1);
+        assert_eq!(found("lfs-size-mismatch"), 1);
+        assert_eq!(found("lfs-missing"), 1);
+        assert_eq!(found("hash-mismatch"), 1);
+        assert_eq!(found("pack-crc"), 1);
+        assert_eq!(found("pack-checksum"), 1);
+        assert_eq!(report.max_severity(), Some(Severity::Error));
+        assert_eq!(report.count(Severity::Warning), 1);
+    }
+}
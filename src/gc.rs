@@ -0,0 +1,248 @@
+//! 对象的垃圾回收
+//!
+//! 从全部引用、HEAD、合并队列中等待合入的提交，以及[引用日志](crate::reflog)中未过期的记录出发
+//! 遍历可达对象，其余对象在最后写入时间超过宽限期后从对象存储中删除。引用日志记录了每次修改前后的值，
+//! 保留期内被强制推送覆盖或删除的提交仍然可以恢复，过期的记录在回收时一并清理。
+//! 宽限期保护推送过程中已经写入、引用尚未更新的对象。
+
+use std::collections::HashSet;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::binary_delta;
+use crate::common::config::GcConfig;
+use crate::common::errors::MonoError;
+use crate::common::progress::Progress;
+use crate::common::MonoResult;
+use crate::graph::{self, bitmap};
+use crate::lock;
+use crate::object::filter::ObjectFilter;
+use crate::object::walk::collect_shallow_objects;
+use crate::object::ObjectId;
+use crate::queue::{EntryState, MergeQueue};
+use crate::reflog::Reflog;
+use crate::repo::Repository;
+use crate::storage::StoredObject;
+
+/// 垃圾回收参数
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GcOptions {
+    /// 不可达对象在最后写入后至少保留的时长
+    pub grace: Duration,
+    /// 引用日志的保留时长，更早的记录不再作为可达性的起点
+    pub reflog_expire: Duration,
+    /// 只报告将要删除的对象，不实际删除
+    pub dry_run: bool,
+}
+
+impl GcOptions {
+    /// 使用仓库 `[gc]` 配置段中的参数
+    pub fn from_config(config: &GcConfig) -> GcOptions {
+        GcOptions {
+            grace: Duration::from_secs(config.grace_days * 24 * 60 * 60),
+            reflog_expire: Duration::from_secs(config.reflog_expire_days * 24 * 60 * 60),
+            dry_run: false,
+        }
+    }
+}
+
+/// 垃圾回收的结果
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GcReport {
+    /// 存储中可达的对象数
+    pub reachable: usize,
+    /// 不可达但仍在宽限期内的对象
+    pub retained: Vec<StoredObject>,
+    /// 已删除的对象，`dry_run` 时为将要删除的对象
+    pub deleted: Vec<StoredObject>,
+    /// 清理的过期引用日志记录数，`dry_run` 时不清理
+    pub reflog_expired: usize,
+}
+
+impl GcReport {
+    /// 删除（或将要删除）的对象占用的总空间
+    pub fn deleted_size(&self) -> u64 {
+        self.deleted.iter().map(|object| object.size).sum()
+    }
+}
+
+/// 可达性遍历的起点：引用、HEAD、排队中的提交、二进制 delta 存储的字典，以及 `since`（Unix 秒）之后引用日志记录的新旧值
+pub fn roots(repo: &Repository, since: i64) -> MonoResult<Vec<ObjectId>> {
+    let mut roots: Vec<ObjectId> = repo.refs().list("refs/")?.into_iter().map(|(_, id)| id).collect();
+    roots.extend(repo.head_commit()?);
+    roots.extend(binary_delta::dictionaries(repo)?);
+    for entry in MergeQueue::new(repo).entries()? {
+        if entry.state == EntryState::Queued {
+            roots.push(entry.commit);
+        }
+    }
+    let log = Reflog::new(repo.mono_dir());
+    for name in log.names()? {
+        for entry in log.read(&name)? {
+            if entry.time < since {
+                continue;
+            }
+            // 之前的回收可能已经删除了过期记录引用的对象
+            for id in [entry.old, entry.new] {
+                if !id.is_zero() && repo.objects().contains(&id)? {
+                    roots.push(id);
+                }
+            }
+        }
+    }
+    roots.sort();
+    roots.dedup();
+    Ok(roots)
+}
+
+/// 从 `roots` 可达的全部本地对象
+///
+/// 只读取本地对象：部分克隆中缺失的 blob 不影响遍历，也不会从 promisor 远端获取。
+pub fn reachable_objects(repo: &Repository, roots: &[ObjectId]) -> MonoResult<HashSet<ObjectId>> {
+    reachable_objects_with(repo, roots, &Progress::hidden(0))
+}
+
+/// 同 [`reachable_objects`]，每读取一个对象更新一次 `progress`
+fn reachable_objects_with(repo: &Repository, roots: &[ObjectId], progress: &Progress) -> MonoResult<HashSet<ObjectId>> {
+    let mut reader = |id: &ObjectId| {
+        progress.inc(1);
+        repo.objects()
+            .read(id)?
+            .ok_or_else(|| MonoError::not_found(format!("object {}", id)))
+    };
+    let (objects, _) = collect_shallow_objects(
+        roots,
+        &[],
+        repo.shallow_commits().clone(),
+        HashSet::new(),
+        &ObjectFilter::None,
+        None,
+        &mut reader,
+    )?;
+    Ok(objects.into_iter().map(|(id, _, _)| id).collect())
+}
+
+/// 删除不可达且超过宽限期的对象
+///
+/// 删除后重新生成已有的提交图与可达性位图，避免其中残留已删除的提交。
+pub fn gc(repo: &Repository, options: &GcOptions) -> MonoResult<GcReport> {
+    if repo.namespace().is_some() {
+        return Err(MonoError::usage("gc must run on the whole repository, not inside a namespace"));
+    }
+    // 与其他实例的重新打包互斥，避免删除正在被重写进新 pack 的对象
+    let _lock = repo.lock(lock::REPACK_LOCK)?;
+    let now = SystemTime::now();
+    // 先列出对象再遍历：遍历期间新写入的对象不在列表中，或仍在宽限期内
+    let objects = repo.objects().list_stored()?;
+    let since = now.checked_sub(options.reflog_expire).unwrap_or(UNIX_EPOCH);
+    let since = since.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() as i64;
+    let progress = Progress::spinner("Counting objects");
+    let reachable = reachable_objects_with(repo, &roots(repo, since)?, &progress)?;
+    progress.finish();
+    let cutoff = now.checked_sub(options.grace).unwrap_or(UNIX_EPOCH);
+
+    let mut report = GcReport::default();
+    for object in objects {
+        if reachable.contains(&object.id) {
+            report.reachable += 1;
+        } else if object.modified > cutoff {
+            report.retained.push(object);
+        } else {
+            report.deleted.push(object);
+        }
+    }
+    if options.dry_run {
+        return Ok(report);
+    }
+    report.reflog_expired = Reflog::new(repo.mono_dir()).expire(since)?;
+    if report.deleted.is_empty() {
+        return Ok(report);
+    }
+
+    let ids: Vec<ObjectId> = report.deleted.iter().map(|object| object.id).collect();
+    let progress = Progress::new("Deleting objects", ids.len() as u64);
+    repo.objects().delete(&ids)?;
+    progress.inc(ids.len() as u64);
+    progress.add_bytes(report.deleted_size());
+    progress.finish();
+    tracing::info!(objects = ids.len(), size = report.deleted_size(), "deleted unreachable objects");
+    if graph::graph_path(repo).is_file() {
+        graph::write_commit_graph(repo)?;
+    }
+    if bitmap::bitmap_path(repo).is_file() {
+        bitmap::write_bitmaps(repo)?;
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::ObjectType;
+    use crate::reflog::ReflogEntry;
+    use crate::refs::RefUpdate;
+    use crate::storage::fs::FsStore;
+    use crate::test_utils::{commit_files, init_repo};
+
+    /// 测试引用与未过期的引用日志可达的对象保留，过期的日志记录被清理，不可达对象按宽限期删除，dry run 不删除
+    #[test]
+    fn test_gc() {
+        let (_dir, repo) = init_repo();
+        let first = commit_files(&repo, &[("a.txt", b"one")], &[], "first");
+        let main = commit_files(&repo, &[("a.txt", b"two")], &[first], "second");
+        let rewritten = commit_files(&repo, &[("a.txt", b"rewritten")], &[], "force pushed away");
+        let expired = commit_files(&repo, &[("a.txt", b"expired")], &[], "old reflog entry");
+        let orphan = repo.write_object(ObjectType::Blob, b"never referenced").unwrap();
+        repo.refs()
+            .update(&[RefUpdate {
+                name: "refs/heads/main".to_string(),
+                old: ObjectId::ZERO,
+                new: main,
+            }])
+            .unwrap();
+
+        let log = Reflog::new(repo.mono_dir());
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+        let entry = |old, new, time| ReflogEntry {
+            old,
+            new,
+            actor: "user:alice".to_string(),
+            time,
+            reason: "push".to_string(),
+        };
+        log.append("refs/heads/old", &entry(expired, rewritten, now - 100 * 24 * 60 * 60)).unwrap();
+        log.append("refs/heads/old", &entry(rewritten, ObjectId::ZERO, now)).unwrap();
+
+        // 全部对象都在宽限期内
+        let mut options = GcOptions::from_config(&GcConfig::default());
+        let report = gc(&repo, &options).unwrap();
+        assert!(report.deleted.is_empty());
+        assert!(report.retained.iter().any(|object| object.id == orphan));
+        assert_eq!(report.reflog_expired, 1);
+
+        options.grace = Duration::ZERO;
+        options.dry_run = true;
+        let report = gc(&repo, &options).unwrap();
+        let mut deleted: Vec<ObjectId> = report.deleted.iter().map(|object| object.id).collect();
+        deleted.sort();
+        let expired_tree = repo.read_commit(&expired).unwrap().tree;
+        let expired_blob = ObjectId::hash_object(ObjectType::Blob, b"expired");
+        let mut expected = vec![expired, expired_tree, expired_blob, orphan];
+        expected.sort();
+        assert_eq!(deleted, expected);
+        assert!(report.deleted_size() > 0);
+        assert!(repo.objects().contains(&orphan).unwrap());
+
+        // 删除打包的对象需要重写 pack
+        FsStore::new(repo.objects_dir()).repack_incremental(1).unwrap();
+        options.dry_run = false;
+        let report = gc(&repo, &options).unwrap();
+        assert_eq!(report.deleted.len(), 4);
+        for id in &expected {
+            assert!(!repo.objects().contains(id).unwrap());
+        }
+        for id in [first, main, rewritten] {
+            assert!(repo.objects().contains(&id).unwrap());
+        }
+        assert!(gc(&repo, &options).unwrap().deleted.is_empty());
+    }
+}
@@ -0,0 +1,449 @@
+//! 可达性位图
+//!
+//! 为每个引用指向的提交预先记录其可达的全部对象。clone/fetch 计算要发送的对象时，
+//! 对 want 与 have 两侧各取位图的并集再求差，不必遍历整个对象图；只有位图生成之后的新提交
+//! 才需要遍历，遍历中遇到已在集合中的树整棵跳过。对象不可变，位图生成后一直正确，
+//! 过时只意味着需要遍历的提交变多。
+//!
+//! 位图与具体的存储后端无关，和提交图一样保存在本地对象目录的 `info/` 下：
+//!
+//! ```text
+//! "MBMP" | 版本 1 | 对象数 N | 位图数 M | 排序后的对象 ID × N | 名称哈希 × N
+//!        | 类型位图 × 4 | (提交 ID | 位图) × M | 校验和
+//! ```
+//!
+//! 第 i 位对应第 i 个对象；名称哈希用于生成 pack 时挑选 delta 候选。四个类型位图依次标记
+//! 提交、树、blob 与标签，提交的位图按提交 ID 排序。所有位图都是 [`ewah`](super::ewah) 编码。
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::SystemTime;
+
+use sha1::{Digest, Sha1};
+
+use crate::common::errors::{MonoError, MonoErrorKind};
+use crate::common::MonoResult;
+use crate::graph::ewah::Bitset;
+use crate::object::commit::Commit;
+use crate::object::filter::ObjectFilter;
+use crate::object::tag::Tag;
+use crate::object::tree::Tree;
+use crate::object::walk::{collect_named_objects, name_hash, NamedObject, ObjectReader};
+use crate::object::{ObjectId, ObjectType, OBJECT_ID_LEN};
+use crate::pack::index::read_u32;
+use crate::repo::Repository;
+
+/// 位图文件相对于对象存储目录的路径
+pub const BITMAP_FILE: &str = "info/reachability-bitmaps";
+
+const BITMAP_SIGNATURE: &[u8; 4] = b"MBMP";
+const BITMAP_VERSION: u32 = 1;
+const HEADER_LEN: usize = 16;
+/// 类型位图的顺序
+const TYPES: [ObjectType; 4] = [ObjectType::Commit, ObjectType::Tree, ObjectType::Blob, ObjectType::Tag];
+
+/// 已加载的位图，按文件路径缓存，文件被重新生成后再次读取
+type BitmapCache = HashMap<PathBuf, ((SystemTime, u64), Arc<ReachabilityBitmaps>)>;
+
+static CACHE: LazyLock<Mutex<BitmapCache>> = LazyLock::new(Default::default);
+
+/// 已加载的可达性位图
+#[derive(Debug, Clone)]
+pub struct ReachabilityBitmaps {
+    object_count: usize,
+    /// 各类型的对象，顺序同 [`TYPES`]
+    types: [Bitset; 4],
+    /// 提交及其位图在文件中的起始位置
+    bitmaps: HashMap<ObjectId, usize>,
+    data: Vec<u8>,
+}
+
+/// 一组提交可达的对象
+///
+/// 位图覆盖的对象用位表示，位图生成之后新增的对象单独记录类型与名称哈希。
+#[derive(Debug, Clone, Default)]
+pub struct Reachable {
+    bits: Bitset,
+    extra: HashMap<ObjectId, (ObjectType, u32)>,
+}
+
+impl Reachable {
+    /// 对象数
+    pub fn len(&self) -> usize {
+        self.bits.count() + self.extra.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// 对象的位编号与已有的提交位图
+trait BitmapIndex {
+    fn position(&self, id: &ObjectId) -> Option<usize>;
+    fn bitmap(&self, commit: &ObjectId) -> MonoResult<Option<Bitset>>;
+}
+
+impl ReachabilityBitmaps {
+    /// 由排序后的对象表与各提交的位图生成位图文件
+    fn build(objects: &[NamedObject], bitmaps: &BTreeMap<ObjectId, Vec<u8>>) -> MonoResult<ReachabilityBitmaps> {
+        let mut out = Vec::new();
+        out.extend_from_slice(BITMAP_SIGNATURE);
+        out.extend_from_slice(&BITMAP_VERSION.to_be_bytes());
+        out.extend_from_slice(&(objects.len() as u32).to_be_bytes());
+        out.extend_from_slice(&(bitmaps.len() as u32).to_be_bytes());
+        for (id, _, _) in objects {
+            out.extend_from_slice(id.as_bytes());
+        }
+        for (_, _, hash) in objects {
+            out.extend_from_slice(&hash.to_be_bytes());
+        }
+        for object_type in TYPES {
+            let bits: Bitset = objects
+                .iter()
+                .enumerate()
+                .filter(|(_, (_, t, _))| *t == object_type)
+                .map(|(i, _)| i)
+                .collect();
+            out.extend_from_slice(&bits.encode(objects.len()));
+        }
+        for (commit, bitmap) in bitmaps {
+            out.extend_from_slice(commit.as_bytes());
+            out.extend_from_slice(bitmap);
+        }
+        let checksum = Sha1::digest(&out);
+        out.extend_from_slice(&checksum);
+        ReachabilityBitmaps::parse(out)
+    }
+
+    /// 解析位图文件并检查校验和
+    pub fn parse(data: Vec<u8>) -> MonoResult<ReachabilityBitmaps> {
+        let corrupt = |msg: &str| MonoError::storage(format!("corrupt reachability bitmaps: {}", msg));
+        if data.len() < HEADER_LEN + OBJECT_ID_LEN || &data[..4] != BITMAP_SIGNATURE {
+            return Err(corrupt("missing header"));
+        }
+        let version = read_u32(&data, 4);
+        if version != BITMAP_VERSION {
+            return Err(corrupt(&format!("unsupported version {}", version)));
+        }
+        let body_len = data.len() - OBJECT_ID_LEN;
+        if Sha1::digest(&data[..body_len]).as_slice() != &data[body_len..] {
+            return Err(corrupt("checksum mismatch"));
+        }
+        let (object_count, bitmap_count) = (read_u32(&data, 8) as usize, read_u32(&data, 12) as usize);
+        let mut at = HEADER_LEN + object_count * (OBJECT_ID_LEN + 4);
+        if at > body_len {
+            return Err(corrupt("truncated object table"));
+        }
+        let mut types: [Bitset; 4] = Default::default();
+        for bits in &mut types {
+            let (decoded, len) = Bitset::decode(&data[at..body_len])?;
+            *bits = decoded;
+            at += len;
+        }
+        let mut bitmaps = HashMap::with_capacity(bitmap_count);
+        for _ in 0..bitmap_count {
+            if at + OBJECT_ID_LEN > body_len {
+                return Err(corrupt("truncated bitmap"));
+            }
+            let commit = ObjectId::from_bytes(&data[at..at + OBJECT_ID_LEN])?;
+            let (_, len) = Bitset::decode(&data[at + OBJECT_ID_LEN..body_len])?;
+            bitmaps.insert(commit, at + OBJECT_ID_LEN);
+            at += OBJECT_ID_LEN + len;
+        }
+        Ok(ReachabilityBitmaps {
+            object_count,
+            types,
+            bitmaps,
+            data,
+        })
+    }
+
+    /// 读取仓库的位图，文件不存在时返回 None
+    ///
+    /// 同一进程中的请求共享已加载的位图，文件的修改时间或大小变化后重新读取。
+    pub fn load(repo: &Repository) -> MonoResult<Option<Arc<ReachabilityBitmaps>>> {
+        let path = bitmap_path(repo);
+        let metadata = match std::fs::metadata(&path) {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                CACHE.lock().expect("bitmap cache poisoned").remove(&path);
+                return Ok(None);
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let version = (metadata.modified()?, metadata.len());
+        if let Some((cached, bitmaps)) = CACHE.lock().expect("bitmap cache poisoned").get(&path) {
+            if *cached == version {
+                return Ok(Some(bitmaps.clone()));
+            }
+        }
+        let data = std::fs::read(&path)?;
+        let bitmaps = Arc::new(ReachabilityBitmaps::parse(data).map_err(|e| e.context(path.display().to_string()))?);
+        CACHE
+            .lock()
+            .expect("bitmap cache poisoned")
+            .insert(path, (version, bitmaps.clone()));
+        Ok(Some(bitmaps))
+    }
+
+    /// 有位图的提交数
+    pub fn len(&self) -> usize {
+        self.bitmaps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bitmaps.is_empty()
+    }
+
+    /// 位图覆盖的对象数
+    pub fn object_count(&self) -> usize {
+        self.object_count
+    }
+
+    fn object_at(&self, i: usize) -> ObjectId {
+        let at = HEADER_LEN + i * OBJECT_ID_LEN;
+        ObjectId::from_bytes(&self.data[at..at + OBJECT_ID_LEN]).expect("object table entry has the id length")
+    }
+
+    fn name_hash_at(&self, i: usize) -> u32 {
+        read_u32(&self.data, HEADER_LEN + self.object_count * OBJECT_ID_LEN + i * 4)
+    }
+
+    fn type_at(&self, i: usize) -> ObjectType {
+        TYPES
+            .into_iter()
+            .zip(&self.types)
+            .find(|(_, bits)| bits.get(i))
+            .map_or(ObjectType::Blob, |(object_type, _)| object_type)
+    }
+
+    /// 提交可达的全部对象，提交没有位图时返回 None
+    pub fn get(&self, commit: &ObjectId) -> MonoResult<Option<Vec<ObjectId>>> {
+        Ok(self.bitmap(commit)?.map(|bits| bits.ones().map(|i| self.object_at(i)).collect()))
+    }
+
+    /// 从 `tips` 可达的全部对象：有位图的提交直接取位图，其余提交逐个遍历，不存在的对象被忽略
+    pub fn reachable<R: ObjectReader>(&self, tips: &[ObjectId], reader: &mut R) -> MonoResult<Reachable> {
+        let mut out = Reachable::default();
+        fill(self, tips, reader, &mut out)?;
+        Ok(out)
+    }
+
+    /// `want` 中不在 `have` 中的对象，带类型与名称哈希，可以直接用于生成 pack
+    pub fn objects(&self, want: &Reachable, have: &Reachable) -> Vec<NamedObject> {
+        let mut bits = want.bits.clone();
+        bits.and_not(&have.bits);
+        let mut out: Vec<NamedObject> = bits
+            .ones()
+            .map(|i| (self.object_at(i), self.type_at(i), self.name_hash_at(i)))
+            .collect();
+        out.extend(
+            want.extra
+                .iter()
+                .filter(|(id, _)| !have.extra.contains_key(id))
+                .map(|(id, (object_type, hash))| (*id, *object_type, *hash)),
+        );
+        out
+    }
+
+    /// 集合中全部对象的 ID
+    pub fn ids(&self, reachable: &Reachable) -> HashSet<ObjectId> {
+        let mut out: HashSet<ObjectId> = reachable.bits.ones().map(|i| self.object_at(i)).collect();
+        out.extend(reachable.extra.keys());
+        out
+    }
+}
+
+impl BitmapIndex for ReachabilityBitmaps {
+    fn position(&self, id: &ObjectId) -> Option<usize> {
+        let (mut low, mut high) = (0, self.object_count);
+        while low < high {
+            let mid = (low + high) / 2;
+            let at = HEADER_LEN + mid * OBJECT_ID_LEN;
+            match self.data[at..at + OBJECT_ID_LEN].cmp(id.as_bytes()) {
+                std::cmp::Ordering::Less => low = mid + 1,
+                std::cmp::Ordering::Equal => return Some(mid),
+                std::cmp::Ordering::Greater => high = mid,
+            }
+        }
+        None
+    }
+
+    fn bitmap(&self, commit: &ObjectId) -> MonoResult<Option<Bitset>> {
+        let Some(&at) = self.bitmaps.get(commit) else {
+            return Ok(None);
+        };
+        let (bits, _) = Bitset::decode(&self.data[at..self.data.len() - OBJECT_ID_LEN])
+            .map_err(|e| e.context(format!("bitmap for {}", commit)))?;
+        Ok(Some(bits))
+    }
+}
+
+/// 生成中的位图
+struct Builder {
+    positions: HashMap<ObjectId, usize>,
+    bitmaps: BTreeMap<ObjectId, Vec<u8>>,
+}
+
+impl BitmapIndex for Builder {
+    fn position(&self, id: &ObjectId) -> Option<usize> {
+        self.positions.get(id).copied()
+    }
+
+    fn bitmap(&self, commit: &ObjectId) -> MonoResult<Option<Bitset>> {
+        self.bitmaps
+            .get(commit)
+            .map(|data| Bitset::decode(data).map(|(bits, _)| bits))
+            .transpose()
+    }
+}
+
+/// 把从 `tips` 可达的对象加入 `out`
+///
+/// 已在集合中的对象连同其可达的对象一定都已在集合中，遇到时直接跳过；blob 由树条目的模式
+/// 得到类型，不读取内容。
+fn fill<I, R>(index: &I, tips: &[ObjectId], reader: &mut R, out: &mut Reachable) -> MonoResult<()>
+where
+    I: BitmapIndex + ?Sized,
+    R: ObjectReader,
+{
+    let mut pending: Vec<(ObjectId, u32)> = tips.iter().map(|id| (*id, 0)).collect();
+    while let Some((id, hash)) = pending.pop() {
+        let position = index.position(&id);
+        let seen = match position {
+            Some(i) => out.bits.get(i),
+            None => out.extra.contains_key(&id),
+        };
+        if seen {
+            continue;
+        }
+        if let Some(bits) = index.bitmap(&id)? {
+            out.bits.or(&bits);
+            continue;
+        }
+        let object = match reader.read_object(&id) {
+            Ok(object) => object,
+            Err(e) if matches!(e.kind(), MonoErrorKind::NotFound(_)) => continue,
+            Err(e) => return Err(e),
+        };
+        match object.object_type {
+            ObjectType::Commit => {
+                let commit = Commit::parse(&object.data)?;
+                pending.push((commit.tree, 0));
+                pending.extend(commit.parents.into_iter().map(|parent| (parent, 0)));
+            }
+            ObjectType::Tag => pending.push((Tag::parse(&object.data)?.object, 0)),
+            ObjectType::Tree => {
+                for entry in Tree::parse(&object.data, id.format())?.entries {
+                    match entry.mode.object_type() {
+                        Some(ObjectType::Tree) => pending.push((entry.id, name_hash(&entry.name))),
+                        Some(ObjectType::Blob) => match index.position(&entry.id) {
+                            Some(i) => out.bits.set(i),
+                            None => {
+                                out.extra.insert(entry.id, (ObjectType::Blob, name_hash(&entry.name)));
+                            }
+                        },
+                        _ => {}
+                    }
+                }
+            }
+            ObjectType::Blob => {}
+        }
+        match position {
+            Some(i) => out.bits.set(i),
+            None => {
+                out.extra.insert(id, (object.object_type, hash));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 仓库位图文件的路径
+pub fn bitmap_path(repo: &Repository) -> PathBuf {
+    repo.objects_dir().join(BITMAP_FILE)
+}
+
+/// 为所有引用（及 HEAD）指向的提交写入位图，返回写入的位图
+///
+/// 先遍历一次得到全部对象，再按提交时间从早到晚逐个生成，较新的提交遍历到已有位图的祖先即停止。
+pub fn write_bitmaps(repo: &Repository) -> MonoResult<ReachabilityBitmaps> {
+    crate::graph::check_object_format(repo, "reachability bitmaps")?;
+    let mut refs: Vec<ObjectId> = repo.refs().list("refs/")?.into_iter().map(|(_, id)| id).collect();
+    refs.extend(repo.head_commit()?);
+    let mut tips = Vec::new();
+    for id in refs {
+        let (id, object_type) = repo.peel(&id)?;
+        if object_type == ObjectType::Commit && !tips.iter().any(|(tip, _)| *tip == id) {
+            tips.push((id, repo.read_commit(&id)?.committer.timestamp));
+        }
+    }
+    tips.sort_by_key(|(id, time)| (*time, *id));
+
+    let mut reader = |id: &ObjectId| repo.read_object(id);
+    let commits: Vec<ObjectId> = tips.iter().map(|(id, _)| *id).collect();
+    let mut objects = collect_named_objects(&commits, &[], &ObjectFilter::None, &mut reader)?;
+    objects.sort_by_key(|(id, _, _)| *id);
+    let mut builder = Builder {
+        positions: objects.iter().enumerate().map(|(i, (id, _, _))| (*id, i)).collect(),
+        bitmaps: BTreeMap::new(),
+    };
+    for commit in commits {
+        let mut reachable = Reachable::default();
+        fill(&builder, &[commit], &mut reader, &mut reachable)?;
+        let encoded = reachable.bits.encode(objects.len());
+        builder.bitmaps.insert(commit, encoded);
+    }
+
+    let bitmaps = ReachabilityBitmaps::build(&objects, &builder.bitmaps)?;
+    let path = bitmap_path(repo);
+    std::fs::create_dir_all(path.parent().expect("bitmap path has a parent"))?;
+    let tmp = path.with_file_name(format!(".tmp-{}-reachability-bitmaps", std::process::id()));
+    std::fs::write(&tmp, &bitmaps.data)?;
+    std::fs::rename(&tmp, &path)?;
+    tracing::debug!(commits = bitmaps.len(), objects = bitmaps.object_count(), "wrote reachability bitmaps");
+    Ok(bitmaps)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::walk::collect_objects;
+    use crate::test_utils::{commit_files, init_repo};
+
+    /// 测试位图记录引用可达的对象，位图之后的新提交通过遍历补全，want 与 have 的差集与遍历一致
+    #[test]
+    fn test_reachability_bitmaps() {
+        let (_dir, repo) = init_repo();
+        let first = commit_files(&repo, &[("a.txt", b"a")], &[], "first");
+        repo.refs().write("refs/heads/main", &first).unwrap();
+        let second = commit_files(&repo, &[("a.txt", b"a"), ("src/b.txt", b"b")], &[first], "second");
+        repo.refs().write("refs/heads/topic", &second).unwrap();
+        let bitmaps = write_bitmaps(&repo).unwrap();
+        assert_eq!(bitmaps.len(), 2);
+        let objects = bitmaps.get(&first).unwrap().unwrap();
+        assert_eq!(objects.len(), 3);
+        assert_eq!(bitmaps.get(&second).unwrap().unwrap().len(), 7);
+
+        let loaded = ReachabilityBitmaps::load(&repo).unwrap().unwrap();
+        assert_eq!(loaded.get(&first).unwrap().unwrap(), objects);
+        let third = commit_files(&repo, &[("a.txt", b"a"), ("src/b.txt", b"c")], &[second], "third");
+        let mut reader = |id: &ObjectId| repo.read_object(id);
+        let reachable = loaded.reachable(&[third, ObjectId::ZERO], &mut reader).unwrap();
+        let walked = collect_objects(&[third], &[], &ObjectFilter::None, &mut reader).unwrap();
+        assert_eq!(loaded.ids(&reachable), walked.into_iter().map(|(id, _)| id).collect());
+
+        let have = loaded.reachable(&[first], &mut reader).unwrap();
+        let mut sent = loaded.objects(&reachable, &have);
+        let mut expected = collect_named_objects(&[third], &[first], &ObjectFilter::None, &mut reader).unwrap();
+        sent.sort_by_key(|(id, _, _)| *id);
+        expected.sort_by_key(|(id, _, _)| *id);
+        assert_eq!(sent, expected);
+
+        let mut corrupt = loaded.data.clone();
+        corrupt[HEADER_LEN] ^= 1;
+        assert!(ReachabilityBitmaps::parse(corrupt).is_err());
+    }
+}
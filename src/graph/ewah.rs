@@ -0,0 +1,186 @@
+//! EWAH 压缩的位集合
+//!
+//! 与 git 的 `.bitmap` 文件使用相同的编码：64 位字序列由若干组“游程字 + 字面字”组成，
+//! 游程字的最低位为游程的填充位，接下来 32 位为全 0 或全 1 字的个数，高 31 位为其后
+//! 字面字的个数。序列化格式为：
+//!
+//! ```text
+//! 位数（4 字节） | 字数 K（4 字节） | 字 × K（各 8 字节） | 最后一个游程字的序号（4 字节）
+//! ```
+//!
+//! 可达性位图中大段连续的对象要么全部可达、要么全不可达，压缩后通常只有未压缩时的很小一部分。
+//! 运算时先展开为 [`Bitset`]。
+
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::pack::index::read_u32;
+
+const MAX_RUN: u64 = (1 << 32) - 1;
+const MAX_LITERALS: u64 = (1 << 31) - 1;
+
+/// 未压缩的位集合
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Bitset {
+    words: Vec<u64>,
+}
+
+impl Bitset {
+    pub fn new() -> Bitset {
+        Bitset::default()
+    }
+
+    pub fn set(&mut self, i: usize) {
+        let word = i / 64;
+        if word >= self.words.len() {
+            self.words.resize(word + 1, 0);
+        }
+        self.words[word] |= 1 << (i % 64);
+    }
+
+    pub fn get(&self, i: usize) -> bool {
+        self.words.get(i / 64).is_some_and(|word| word & (1 << (i % 64)) != 0)
+    }
+
+    /// 并入 `other` 的全部位
+    pub fn or(&mut self, other: &Bitset) {
+        if other.words.len() > self.words.len() {
+            self.words.resize(other.words.len(), 0);
+        }
+        for (word, other) in self.words.iter_mut().zip(&other.words) {
+            *word |= other;
+        }
+    }
+
+    /// 清除 `other` 中已设置的位
+    pub fn and_not(&mut self, other: &Bitset) {
+        for (word, other) in self.words.iter_mut().zip(&other.words) {
+            *word &= !other;
+        }
+    }
+
+    /// 已设置的位数
+    pub fn count(&self) -> usize {
+        self.words.iter().map(|word| word.count_ones() as usize).sum()
+    }
+
+    /// 按从小到大的顺序遍历已设置的位
+    pub fn ones(&self) -> impl Iterator<Item = usize> + '_ {
+        self.words.iter().enumerate().flat_map(|(i, &word)| {
+            let mut rest = word;
+            std::iter::from_fn(move || {
+                if rest == 0 {
+                    return None;
+                }
+                let bit = rest.trailing_zeros() as usize;
+                rest &= rest - 1;
+                Some(i * 64 + bit)
+            })
+        })
+    }
+
+    /// 按 EWAH 格式压缩，`bit_len` 为位集合的长度
+    pub fn encode(&self, bit_len: usize) -> Vec<u8> {
+        let words = &self.words[..self.words.len().min(bit_len.div_ceil(64))];
+        let mut out: Vec<u64> = Vec::new();
+        let mut last_rlw = 0;
+        let mut i = 0;
+        while i < words.len() || out.is_empty() {
+            last_rlw = out.len();
+            out.push(0);
+            let fill = words.get(i).copied().filter(|&word| word == 0 || word == u64::MAX);
+            let mut run = 0;
+            if let Some(fill) = fill {
+                while i < words.len() && words[i] == fill && run < MAX_RUN {
+                    run += 1;
+                    i += 1;
+                }
+            }
+            let mut literals = 0;
+            while i < words.len() && words[i] != 0 && words[i] != u64::MAX && literals < MAX_LITERALS {
+                out.push(words[i]);
+                literals += 1;
+                i += 1;
+            }
+            out[last_rlw] = u64::from(fill == Some(u64::MAX)) | (run << 1) | (literals << 33);
+        }
+
+        let mut data = Vec::with_capacity(12 + out.len() * 8);
+        data.extend_from_slice(&(bit_len as u32).to_be_bytes());
+        data.extend_from_slice(&(out.len() as u32).to_be_bytes());
+        for word in &out {
+            data.extend_from_slice(&word.to_be_bytes());
+        }
+        data.extend_from_slice(&(last_rlw as u32).to_be_bytes());
+        data
+    }
+
+    /// 解压 `data` 开头的 EWAH 位图，返回位集合及其占用的字节数
+    pub fn decode(data: &[u8]) -> MonoResult<(Bitset, usize)> {
+        let corrupt = |msg: &str| MonoError::storage(format!("corrupt ewah bitmap: {}", msg));
+        if data.len() < 8 {
+            return Err(corrupt("truncated header"));
+        }
+        let bit_len = read_u32(data, 0) as usize;
+        let count = read_u32(data, 4) as usize;
+        let len = 8 + count * 8 + 4;
+        if data.len() < len {
+            return Err(corrupt("truncated words"));
+        }
+        let mut compressed = data[8..8 + count * 8]
+            .chunks(8)
+            .map(|word| u64::from_be_bytes(word.try_into().expect("8-byte chunk")));
+        let mut words = Vec::with_capacity(bit_len.div_ceil(64));
+        while let Some(rlw) = compressed.next() {
+            let fill = if rlw & 1 == 1 { u64::MAX } else { 0 };
+            let run = ((rlw >> 1) & MAX_RUN) as usize;
+            let literals = (rlw >> 33) as usize;
+            if words.len() + run + literals > bit_len.div_ceil(64) {
+                return Err(corrupt("more words than bits"));
+            }
+            words.resize(words.len() + run, fill);
+            for _ in 0..literals {
+                words.push(compressed.next().ok_or_else(|| corrupt("truncated literal words"))?);
+            }
+        }
+        Ok((Bitset { words }, len))
+    }
+}
+
+impl FromIterator<usize> for Bitset {
+    fn from_iter<I: IntoIterator<Item = usize>>(iter: I) -> Bitset {
+        let mut bits = Bitset::new();
+        for i in iter {
+            bits.set(i);
+        }
+        bits
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 测试游程与字面字混合时的压缩、解压与集合运算
+    #[test]
+    fn test_ewah_roundtrip() {
+        let bit_len = 64 * 200 + 5;
+        let ones: Vec<usize> = (0..64 * 50).chain([64 * 120 + 3, 64 * 120 + 40]).chain(64 * 200..bit_len).collect();
+        let bits: Bitset = ones.iter().copied().collect();
+        let data = bits.encode(bit_len);
+        // 未压缩时为 201 个字
+        assert!(data.len() < 100);
+        let (decoded, len) = Bitset::decode(&data).unwrap();
+        assert_eq!(len, data.len());
+        assert_eq!(decoded.ones().collect::<Vec<_>>(), ones);
+
+        let (empty, _) = Bitset::decode(&Bitset::new().encode(bit_len)).unwrap();
+        assert_eq!(empty.count(), 0);
+        assert!(Bitset::decode(&data[..data.len() - 1]).is_err());
+
+        let mut union = empty;
+        union.or(&decoded);
+        union.and_not(&[64 * 120 + 3].into_iter().collect());
+        assert_eq!(union.count(), ones.len() - 1);
+        assert!(!union.get(64 * 120 + 3) && union.get(64 * 120 + 40));
+    }
+}
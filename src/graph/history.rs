@@ -0,0 +1,447 @@
+//! 历史查询：提交日志、祖先判断与合并基
+//!
+//! 提交在提交图中时直接使用其中记录的父提交、提交时间与世代号；
+//! 提交图生成之后才写入的提交则读取并解析提交对象，世代号视为无穷大，
+//! 查询结果不变，只是无法据此剪枝。
+
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BinaryHeap, HashMap, HashSet};
+
+use crate::common::MonoResult;
+use crate::graph::{CommitGraph, GraphCommit, GENERATION_INFINITY};
+use crate::object::tree::FileMode;
+use crate::object::ObjectId;
+use crate::repo::Repository;
+
+/// 从第一个提交可达
+const PARENT1: u8 = 1;
+/// 从第二个提交可达
+const PARENT2: u8 = 2;
+/// 已被某个公共祖先覆盖，不可能是最近的合并基
+const STALE: u8 = 4;
+/// 已加入结果
+const RESULT: u8 = 8;
+
+/// 仓库历史，优先使用提交图回答查询
+pub struct History<'a> {
+    repo: &'a Repository,
+    graph: Option<CommitGraph>,
+}
+
+impl<'a> History<'a> {
+    /// 打开仓库的历史；提交图损坏时记录警告并退回到读取提交对象
+    pub fn new(repo: &'a Repository) -> MonoResult<History<'a>> {
+        let graph = match CommitGraph::load(repo) {
+            Ok(graph) => graph,
+            Err(e) => {
+                tracing::warn!(error = %e, "ignoring unreadable commit-graph");
+                None
+            }
+        };
+        Ok(History::with_graph(repo, graph))
+    }
+
+    /// 使用给定的提交图，None 表示总是读取提交对象
+    pub fn with_graph(repo: &'a Repository, graph: Option<CommitGraph>) -> History<'a> {
+        History { repo, graph }
+    }
+
+    pub fn graph(&self) -> Option<&CommitGraph> {
+        self.graph.as_ref()
+    }
+
+    /// 读取提交的父提交、提交时间与世代号
+    pub fn commit(&self, id: &ObjectId) -> MonoResult<GraphCommit> {
+        if let Some(graph) = &self.graph {
+            if let Some(commit) = graph.get(id)? {
+                return Ok(commit);
+            }
+        }
+        Ok(GraphCommit::from_commit(*id, &self.repo.read_commit(id)?))
+    }
+
+    /// 提交的世代号，不在提交图中时返回 None
+    pub fn generation(&self, id: &ObjectId) -> MonoResult<Option<u32>> {
+        match &self.graph {
+            Some(graph) => Ok(graph.get(id)?.map(|commit| commit.generation)),
+            None => Ok(None),
+        }
+    }
+
+    /// 从 `tips` 可达的提交，按提交时间从新到旧排列，至多返回 `limit` 个
+    pub fn log(&self, tips: &[ObjectId], limit: Option<usize>) -> MonoResult<Vec<GraphCommit>> {
+        self.walk(tips, limit, |_| Ok(true))
+    }
+
+    /// 与 [`History::log`] 相同，但只返回修改了 `path` 的提交；空路径不做过滤
+    pub fn log_path(&self, tips: &[ObjectId], path: &str, limit: Option<usize>) -> MonoResult<Vec<GraphCommit>> {
+        let path = path.trim_matches('/');
+        if path.is_empty() {
+            return self.log(tips, limit);
+        }
+        self.walk(tips, limit, |commit| self.touches(commit, path))
+    }
+
+    /// 提交是否修改了 `path`：根提交中存在该路径，或与每个父提交中的该路径都不同
+    pub fn touches(&self, commit: &GraphCommit, path: &str) -> MonoResult<bool> {
+        let entry = |tree: &ObjectId| -> MonoResult<Option<(FileMode, ObjectId)>> {
+            Ok(self.repo.find_path(tree, path)?.map(|e| (e.mode, e.id)))
+        };
+        let current = entry(&commit.tree)?;
+        if commit.parents.is_empty() {
+            return Ok(current.is_some());
+        }
+        for parent in &commit.parents {
+            if entry(&self.commit(parent)?.tree)? == current {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// `dir` 目录下每个条目最后一次被修改的提交，从 `tip` 开始按提交时间从新到旧查找
+    ///
+    /// 一次遍历同时查找全部条目，找齐后立即结束；该目录与某个父提交中完全相同的提交直接跳过。
+    /// 条目是否被修改的判断与 [`History::touches`] 相同。`dir` 不是目录时返回空表。
+    pub fn last_commits(&self, tip: &ObjectId, dir: &str) -> MonoResult<BTreeMap<String, ObjectId>> {
+        let dir = dir.trim_matches('/');
+        let dir_tree = |commit: &GraphCommit| -> MonoResult<Option<ObjectId>> {
+            Ok(self.repo.find_path(&commit.tree, dir)?.filter(|e| e.mode.is_tree()).map(|e| e.id))
+        };
+        let entries = |tree: Option<ObjectId>| -> MonoResult<HashMap<String, (FileMode, ObjectId)>> {
+            let Some(tree) = tree else {
+                return Ok(HashMap::new());
+            };
+            Ok(self.repo.read_tree(&tree)?.entries.into_iter().map(|e| (e.name, (e.mode, e.id))).collect())
+        };
+
+        let tip = self.commit(tip)?;
+        let mut pending: HashSet<String> = entries(dir_tree(&tip)?)?.into_keys().collect();
+        let mut found = BTreeMap::new();
+        let mut seen = HashSet::from([tip.id]);
+        let mut queue = BinaryHeap::from([ByTime(tip)]);
+        while !pending.is_empty() {
+            let Some(ByTime(commit)) = queue.pop() else { break };
+            let mut parents = Vec::with_capacity(commit.parents.len());
+            for parent in &commit.parents {
+                let parent = self.commit(parent)?;
+                parents.push(dir_tree(&parent)?);
+                if seen.insert(parent.id) {
+                    queue.push(ByTime(parent));
+                }
+            }
+            let current = dir_tree(&commit)?;
+            if current.is_none() || parents.contains(&current) {
+                continue;
+            }
+            let current = entries(current)?;
+            let parents = parents.into_iter().map(entries).collect::<MonoResult<Vec<_>>>()?;
+            pending.retain(|name| {
+                let entry = current.get(name);
+                let touched = match parents.is_empty() {
+                    true => entry.is_some(),
+                    false => parents.iter().all(|parent| parent.get(name) != entry),
+                };
+                if touched {
+                    found.insert(name.clone(), commit.id);
+                }
+                !touched
+            });
+        }
+        Ok(found)
+    }
+
+    /// 按提交时间从新到旧遍历，收集 `filter` 接受的提交直到达到 `limit` 个
+    fn walk<F>(&self, tips: &[ObjectId], limit: Option<usize>, mut filter: F) -> MonoResult<Vec<GraphCommit>>
+    where
+        F: FnMut(&GraphCommit) -> MonoResult<bool>,
+    {
+        let limit = limit.unwrap_or(usize::MAX);
+        let mut seen = HashSet::new();
+        let mut queue = BinaryHeap::new();
+        for tip in tips {
+            if seen.insert(*tip) {
+                queue.push(ByTime(self.commit(tip)?));
+            }
+        }
+        let mut out = Vec::new();
+        while out.len() < limit {
+            let Some(ByTime(commit)) = queue.pop() else { break };
+            for parent in &commit.parents {
+                if seen.insert(*parent) {
+                    queue.push(ByTime(self.commit(parent)?));
+                }
+            }
+            if filter(&commit)? {
+                out.push(commit);
+            }
+        }
+        Ok(out)
+    }
+
+    /// 从 `tips` 可达、从 `exclude` 不可达的提交（`git rev-list <tips> --not <exclude>`），按提交时间从新到旧排列
+    pub fn range(&self, tips: &[ObjectId], exclude: &[ObjectId]) -> MonoResult<Vec<GraphCommit>> {
+        let mut excluded: HashSet<ObjectId> = HashSet::new();
+        let mut stack = exclude.to_vec();
+        while let Some(id) = stack.pop() {
+            if excluded.insert(id) {
+                stack.extend(self.commit(&id)?.parents);
+            }
+        }
+        // 不需要的提交视为已经访问过，遍历不会越过它们
+        let mut queue = BinaryHeap::new();
+        for tip in tips {
+            if excluded.insert(*tip) {
+                queue.push(ByTime(self.commit(tip)?));
+            }
+        }
+        let mut out = Vec::new();
+        while let Some(ByTime(commit)) = queue.pop() {
+            for parent in &commit.parents {
+                if excluded.insert(*parent) {
+                    queue.push(ByTime(self.commit(parent)?));
+                }
+            }
+            out.push(commit);
+        }
+        Ok(out)
+    }
+
+    /// `ancestor` 是否为 `descendant` 本身或其祖先
+    ///
+    /// 世代号不大于 `ancestor` 的其他提交不可能以它为祖先，遍历到这些提交时直接跳过。
+    pub fn is_ancestor(&self, ancestor: &ObjectId, descendant: &ObjectId) -> MonoResult<bool> {
+        if ancestor == descendant {
+            return Ok(true);
+        }
+        let cutoff = match self.commit(ancestor)?.generation {
+            GENERATION_INFINITY => 0,
+            generation => generation,
+        };
+        let mut seen = HashSet::from([*descendant]);
+        let mut stack = vec![*descendant];
+        while let Some(id) = stack.pop() {
+            let commit = self.commit(&id)?;
+            if commit.generation <= cutoff {
+                continue;
+            }
+            for parent in commit.parents {
+                if parent == *ancestor {
+                    return Ok(true);
+                }
+                if seen.insert(parent) {
+                    stack.push(parent);
+                }
+            }
+        }
+        Ok(false)
+    }
+
+    /// 两个提交的最近公共祖先，可能有多个（交叉合并），按提交时间从新到旧排列
+    ///
+    /// 从两端同时向下着色，队列按世代号优先出队，保证处理一个提交前已处理完它的所有后代；
+    /// 队列中只剩被覆盖的提交时即可结束，不需要走到根提交。
+    pub fn merge_bases(&self, a: &ObjectId, b: &ObjectId) -> MonoResult<Vec<ObjectId>> {
+        if a == b {
+            return Ok(vec![*a]);
+        }
+        let mut flags: HashMap<ObjectId, u8> = HashMap::from([(*a, PARENT1), (*b, PARENT2)]);
+        let mut queue = BinaryHeap::from([ByGeneration(self.commit(a)?), ByGeneration(self.commit(b)?)]);
+        let mut candidates = Vec::new();
+        while queue.iter().any(|item| flags[&item.0.id] & STALE == 0) {
+            let ByGeneration(commit) = queue.pop().expect("queue has a non-stale commit");
+            let current = flags.get_mut(&commit.id).expect("queued commits are flagged");
+            let mut paint = *current & (PARENT1 | PARENT2 | STALE);
+            if paint & (PARENT1 | PARENT2) == PARENT1 | PARENT2 {
+                if *current & RESULT == 0 {
+                    *current |= RESULT;
+                    candidates.push(commit.clone());
+                }
+                paint |= STALE;
+            }
+            for parent in &commit.parents {
+                let parent_flags = flags.entry(*parent).or_insert(0);
+                if *parent_flags & paint == paint {
+                    continue;
+                }
+                *parent_flags |= paint;
+                queue.push(ByGeneration(self.commit(parent)?));
+            }
+        }
+
+        candidates.retain(|commit| flags[&commit.id] & STALE == 0);
+        candidates.sort_by_key(|commit| std::cmp::Reverse(ByTime(commit.clone())));
+        // 去掉是其他候选祖先的候选
+        let mut bases: Vec<ObjectId> = Vec::with_capacity(candidates.len());
+        for (i, candidate) in candidates.iter().enumerate() {
+            let mut redundant = false;
+            for (j, other) in candidates.iter().enumerate() {
+                if i != j && self.is_ancestor(&candidate.id, &other.id)? {
+                    redundant = true;
+                    break;
+                }
+            }
+            if !redundant {
+                bases.push(candidate.id);
+            }
+        }
+        Ok(bases)
+    }
+
+    /// 两个提交的一个最近公共祖先，没有公共历史时返回 None
+    pub fn merge_base(&self, a: &ObjectId, b: &ObjectId) -> MonoResult<Option<ObjectId>> {
+        Ok(self.merge_bases(a, b)?.into_iter().next())
+    }
+}
+
+/// 按提交时间排序，时间相同时世代号大的优先
+#[derive(Debug, PartialEq, Eq)]
+struct ByTime(GraphCommit);
+
+impl Ord for ByTime {
+    fn cmp(&self, other: &ByTime) -> Ordering {
+        (self.0.commit_time, self.0.generation, self.0.id).cmp(&(other.0.commit_time, other.0.generation, other.0.id))
+    }
+}
+
+impl PartialOrd for ByTime {
+    fn partial_cmp(&self, other: &ByTime) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// 按世代号排序，世代号相同（例如都不在提交图中）时按提交时间
+#[derive(Debug, PartialEq, Eq)]
+struct ByGeneration(GraphCommit);
+
+impl Ord for ByGeneration {
+    fn cmp(&self, other: &ByGeneration) -> Ordering {
+        (self.0.generation, self.0.commit_time, self.0.id).cmp(&(other.0.generation, other.0.commit_time, other.0.id))
+    }
+}
+
+impl PartialOrd for ByGeneration {
+    fn partial_cmp(&self, other: &ByGeneration) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::write_commit_graph;
+    use crate::refs::RefUpdate;
+    use crate::test_utils::{commit_files, init_repo};
+
+    fn update(name: &str, id: ObjectId) -> RefUpdate {
+        RefUpdate {
+            name: name.to_string(),
+            old: ObjectId::ZERO,
+            new: id,
+        }
+    }
+
+    /// 测试日志顺序、数量限制、路径过滤以及提交图生成后新增的提交
+    #[test]
+    fn test_log() {
+        let (_dir, repo) = init_repo();
+        let root = commit_files(&repo, &[("a", b"1")], &[], "r");
+        let left = commit_files(&repo, &[("a", b"2")], &[root], "ll");
+        let right = commit_files(&repo, &[("a", b"3")], &[root], "rrr");
+        let merge = commit_files(&repo, &[("a", b"4")], &[left, right], "merge");
+        repo.refs().update(&[update("refs/heads/main", merge)]).unwrap();
+
+        let graph = write_commit_graph(&repo).unwrap();
+        assert_eq!(graph.len(), 4);
+        let history = History::new(&repo).unwrap();
+        assert!(history.graph().is_some());
+        assert_eq!(history.generation(&merge).unwrap(), Some(3));
+
+        let tip = commit_files(&repo, &[("a", b"5")], &[merge], "tip after the commit-graph");
+        assert_eq!(history.generation(&tip).unwrap(), None);
+        let ids = |commits: Vec<GraphCommit>| commits.into_iter().map(|c| c.id).collect::<Vec<_>>();
+        assert_eq!(ids(history.log(&[tip], None).unwrap()), [tip, merge, right, left, root]);
+        assert_eq!(ids(history.log(&[tip], Some(2)).unwrap()), [tip, merge]);
+        assert_eq!(ids(history.log(&[left, right], None).unwrap()), [right, left, root]);
+
+        // 只修改 b 的提交不出现在 a 的历史中
+        let other = commit_files(&repo, &[("a", b"5"), ("b", b"1")], &[tip], "add b");
+        assert_eq!(ids(history.log_path(&[other], "a", Some(3)).unwrap()), [tip, merge, right]);
+        assert_eq!(ids(history.log_path(&[other], "/b", None).unwrap()), [other]);
+        assert_eq!(ids(history.log_path(&[other], "", Some(1)).unwrap()), [other]);
+    }
+
+    /// 测试一次遍历查找目录下每个条目最后修改的提交
+    #[test]
+    fn test_last_commits() {
+        let (_dir, repo) = init_repo();
+        let root = commit_files(&repo, &[("README", b"1"), ("src/a.rs", b"a"), ("src/b.rs", b"b")], &[], "root");
+        let change_a = commit_files(&repo, &[("README", b"1"), ("src/a.rs", b"a2"), ("src/b.rs", b"b")], &[root], "a");
+        let readme = commit_files(&repo, &[("README", b"2"), ("src/a.rs", b"a2"), ("src/b.rs", b"b")], &[change_a], "readme");
+        let add_c = commit_files(
+            &repo,
+            &[("README", b"2"), ("src/a.rs", b"a2"), ("src/b.rs", b"b"), ("src/c/d.rs", b"d")],
+            &[readme],
+            "c",
+        );
+
+        let history = History::with_graph(&repo, None);
+        let last = history.last_commits(&add_c, "src/").unwrap();
+        let expected = BTreeMap::from([
+            ("a.rs".to_string(), change_a),
+            ("b.rs".to_string(), root),
+            ("c".to_string(), add_c),
+        ]);
+        assert_eq!(last, expected);
+        let last = history.last_commits(&add_c, "").unwrap();
+        assert_eq!(last["README"], readme);
+        assert_eq!(last["src"], add_c);
+        assert!(history.last_commits(&add_c, "README").unwrap().is_empty());
+    }
+
+    /// 测试合并基与祖先判断，有无提交图结果一致
+    #[test]
+    fn test_merge_base() {
+        let (_dir, repo) = init_repo();
+        let root = commit_files(&repo, &[("a", b"1")], &[], "root");
+        let x = commit_files(&repo, &[("a", b"x")], &[root], "x");
+        let y = commit_files(&repo, &[("a", b"y")], &[root], "y");
+        // 交叉合并：两个合并提交的父提交相同，合并基有两个
+        let m1 = commit_files(&repo, &[("a", b"m1")], &[x, y], "m1");
+        let m2 = commit_files(&repo, &[("a", b"m2")], &[y, x], "m2");
+        let a = commit_files(&repo, &[("a", b"a")], &[m1], "a");
+        let b = commit_files(&repo, &[("a", b"b")], &[m2], "b");
+        let other = commit_files(&repo, &[("b", b"1")], &[], "unrelated");
+        repo.refs()
+            .update(&[
+                update("refs/heads/a", a),
+                update("refs/heads/b", b),
+            ])
+            .unwrap();
+
+        let graph = write_commit_graph(&repo).unwrap();
+        for history in [History::with_graph(&repo, Some(graph)), History::with_graph(&repo, None)] {
+            let mut bases = history.merge_bases(&a, &b).unwrap();
+            bases.sort();
+            let mut expected = vec![x, y];
+            expected.sort();
+            assert_eq!(bases, expected);
+            assert_eq!(history.merge_base(&a, &m1).unwrap(), Some(m1));
+            assert_eq!(history.merge_base(&x, &y).unwrap(), Some(root));
+            assert_eq!(history.merge_base(&a, &other).unwrap(), None);
+
+            assert!(history.is_ancestor(&root, &a).unwrap());
+            assert!(history.is_ancestor(&y, &m1).unwrap());
+            assert!(history.is_ancestor(&a, &a).unwrap());
+            assert!(!history.is_ancestor(&a, &root).unwrap());
+            assert!(!history.is_ancestor(&m2, &a).unwrap());
+            assert!(!history.is_ancestor(&other, &b).unwrap());
+
+            let ids = |commits: Vec<GraphCommit>| commits.into_iter().map(|c| c.id).collect::<HashSet<_>>();
+            assert_eq!(ids(history.range(&[a], &[x]).unwrap()), HashSet::from([a, m1, y]));
+            assert_eq!(ids(history.range(&[a, b], &[m1]).unwrap()), HashSet::from([a, b, m2]));
+            assert!(history.range(&[x], &[a]).unwrap().is_empty());
+            assert_eq!(history.range(&[root], &[]).unwrap().len(), 1);
+        }
+    }
+}
@@ -0,0 +1,539 @@
+//! 提交图（commit-graph）
+//!
+//! 把所有可达提交的树、父提交、提交时间与世代号预先写入一个按对象 ID 排序的文件，
+//! 历史遍历时不必逐个解压和解析提交对象。文件格式与 git 的 `objects/info/commit-graph` 相同：
+//!
+//! ```text
+//! "CGPH" | 版本 1 | 哈希版本 1 | 块数 | 0 | 块表 | OIDF | OIDL | CDAT | [EDGE] | 校验和
+//! ```
+//!
+//! - `OIDF` / `OIDL`：fanout 表与排序后的提交 ID
+//! - `CDAT`：每个提交 36 字节，依次为树 ID、两个父提交的序号、世代号（高 30 位）
+//!   与提交时间（共 34 位）
+//! - `EDGE`：章鱼合并的第二个及之后的父提交序号，最后一项的最高位为 1
+//!
+//! 世代号为拓扑层级：没有父提交的提交为 1，其余为父提交的最大值加 1。
+//! 祖先的世代号一定小于后代，查询时可据此提前结束遍历。
+//!
+//! 写入提交图时同时写入提交尾注的索引（见 [`trailers`]）。
+
+pub mod bitmap;
+pub mod ewah;
+pub mod history;
+pub mod trailers;
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+use sha1::{Digest, Sha1};
+
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::object::commit::Commit;
+use crate::object::{ObjectFormat, ObjectId, ObjectType, OBJECT_ID_LEN};
+use crate::pack::index::{build_fanout, fanout_range, read_u32};
+use crate::repo::Repository;
+
+/// 提交图文件相对于对象存储目录的路径
+pub const COMMIT_GRAPH_FILE: &str = "info/commit-graph";
+
+/// 不在提交图中的提交的世代号，保证不会因世代号而被提前剪枝
+pub const GENERATION_INFINITY: u32 = u32::MAX;
+
+/// 提交图能够记录的最大世代号，更深的提交按该值记录
+pub const GENERATION_MAX: u32 = 0x3fff_ffff;
+
+const GRAPH_SIGNATURE: &[u8; 4] = b"CGPH";
+const GRAPH_VERSION: u8 = 1;
+/// 对象 ID 的哈希版本，1 表示 SHA-1
+const HASH_VERSION_SHA1: u8 = 1;
+
+const CHUNK_OID_FANOUT: &[u8; 4] = b"OIDF";
+const CHUNK_OID_LOOKUP: &[u8; 4] = b"OIDL";
+const CHUNK_COMMIT_DATA: &[u8; 4] = b"CDAT";
+const CHUNK_EXTRA_EDGES: &[u8; 4] = b"EDGE";
+
+const HEADER_LEN: usize = 8;
+const CHUNK_ENTRY_LEN: usize = 12;
+const COMMIT_DATA_LEN: usize = OBJECT_ID_LEN + 16;
+
+/// 父提交槽位为空
+const PARENT_NONE: u32 = 0x7000_0000;
+/// 第二个父提交槽位指向 `EDGE` 块，或 `EDGE` 中的最后一项
+const PARENT_EXTRA: u32 = 0x8000_0000;
+
+/// 提交图中一个提交的信息
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GraphCommit {
+    pub id: ObjectId,
+    pub tree: ObjectId,
+    pub parents: Vec<ObjectId>,
+    /// 拓扑层级，不在提交图中时为 [`GENERATION_INFINITY`]
+    pub generation: u32,
+    /// 提交者时间戳（秒）
+    pub commit_time: i64,
+}
+
+impl GraphCommit {
+    /// 由提交对象构建，世代号未知
+    pub fn from_commit(id: ObjectId, commit: &Commit) -> GraphCommit {
+        GraphCommit {
+            id,
+            tree: commit.tree,
+            parents: commit.parents.clone(),
+            generation: GENERATION_INFINITY,
+            commit_time: commit.committer.timestamp,
+        }
+    }
+}
+
+/// 提交图
+///
+/// 文件内容整体保存在内存中，查询时按需解码单个提交，打开大仓库的提交图不需要逐项解析。
+#[derive(Clone, PartialEq, Eq)]
+pub struct CommitGraph {
+    data: Vec<u8>,
+    fanout: [u32; 256],
+    ids_at: usize,
+    commits_at: usize,
+    edges: std::ops::Range<usize>,
+}
+
+impl std::fmt::Debug for CommitGraph {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CommitGraph").field("commits", &self.len()).finish()
+    }
+}
+
+impl CommitGraph {
+    /// 由一组提交构建提交图并计算世代号，每个提交的父提交都必须包含在内
+    pub fn new(commits: impl IntoIterator<Item = (ObjectId, Commit)>) -> MonoResult<CommitGraph> {
+        let commits: BTreeMap<ObjectId, Commit> = commits.into_iter().collect();
+        let ids: Vec<ObjectId> = commits.keys().copied().collect();
+        let positions: HashMap<ObjectId, u32> = ids.iter().enumerate().map(|(i, id)| (*id, i as u32)).collect();
+        let position = |id: &ObjectId, child: &ObjectId| {
+            positions
+                .get(id)
+                .copied()
+                .ok_or_else(|| MonoError::not_found(format!("parent {} of commit {} is missing from the commit graph", id, child)))
+        };
+
+        // 用显式栈做后序遍历计算世代号，避免长历史导致递归过深；
+        // 展开中的提交都在当前路径上，再次遇到说明存在环
+        let mut generations = vec![0u32; ids.len()];
+        let mut expanding = vec![false; ids.len()];
+        for start in 0..ids.len() {
+            let mut stack = vec![start];
+            while let Some(&pos) = stack.last() {
+                if generations[pos] != 0 {
+                    stack.pop();
+                    continue;
+                }
+                let parents = commits[&ids[pos]]
+                    .parents
+                    .iter()
+                    .map(|p| position(p, &ids[pos]).map(|p| p as usize))
+                    .collect::<MonoResult<Vec<usize>>>()?;
+                if expanding[pos] {
+                    let generation = parents.iter().map(|&p| generations[p].saturating_add(1)).max().unwrap_or(1);
+                    generations[pos] = generation.min(GENERATION_MAX);
+                    expanding[pos] = false;
+                    stack.pop();
+                    continue;
+                }
+                expanding[pos] = true;
+                for parent in parents {
+                    if expanding[parent] {
+                        return Err(MonoError::storage(format!("commit {} is its own ancestor", ids[pos])));
+                    }
+                    if generations[parent] == 0 {
+                        stack.push(parent);
+                    }
+                }
+            }
+        }
+
+        let mut oids = Vec::with_capacity(ids.len() * OBJECT_ID_LEN);
+        let mut data = Vec::with_capacity(ids.len() * COMMIT_DATA_LEN);
+        let mut edges: Vec<u8> = Vec::new();
+        for (pos, id) in ids.iter().enumerate() {
+            let commit = &commits[id];
+            oids.extend_from_slice(id.as_bytes());
+            data.extend_from_slice(commit.tree.as_bytes());
+            let parents: Vec<u32> = commit.parents.iter().map(|p| position(p, id)).collect::<MonoResult<_>>()?;
+            let first = parents.first().copied().unwrap_or(PARENT_NONE);
+            let second = match parents.len() {
+                0 | 1 => PARENT_NONE,
+                2 => parents[1],
+                _ => {
+                    let start = (edges.len() / 4) as u32;
+                    for (i, parent) in parents[1..].iter().enumerate() {
+                        let last = if i == parents.len() - 2 { PARENT_EXTRA } else { 0 };
+                        edges.extend_from_slice(&(parent | last).to_be_bytes());
+                    }
+                    PARENT_EXTRA | start
+                }
+            };
+            data.extend_from_slice(&first.to_be_bytes());
+            data.extend_from_slice(&second.to_be_bytes());
+            // 时间戳只保留 34 位，早于 1970 年的时间按 0 记录
+            let time = commit.committer.timestamp.clamp(0, (1 << 34) - 1) as u64;
+            data.extend_from_slice(&((generations[pos] << 2) | (time >> 32) as u32).to_be_bytes());
+            data.extend_from_slice(&(time as u32).to_be_bytes());
+        }
+
+        let mut fanout = Vec::with_capacity(256 * 4);
+        for n in build_fanout(ids.iter()) {
+            fanout.extend_from_slice(&n.to_be_bytes());
+        }
+        let mut chunks: Vec<(&[u8; 4], Vec<u8>)> = vec![
+            (CHUNK_OID_FANOUT, fanout),
+            (CHUNK_OID_LOOKUP, oids),
+            (CHUNK_COMMIT_DATA, data),
+        ];
+        if !edges.is_empty() {
+            chunks.push((CHUNK_EXTRA_EDGES, edges));
+        }
+
+        let mut out = Vec::new();
+        out.extend_from_slice(GRAPH_SIGNATURE);
+        out.push(GRAPH_VERSION);
+        out.push(HASH_VERSION_SHA1);
+        out.push(chunks.len() as u8);
+        out.push(0);
+        let mut offset = (HEADER_LEN + (chunks.len() + 1) * CHUNK_ENTRY_LEN) as u64;
+        for (id, content) in &chunks {
+            out.extend_from_slice(*id);
+            out.extend_from_slice(&offset.to_be_bytes());
+            offset += content.len() as u64;
+        }
+        out.extend_from_slice(&[0; 4]);
+        out.extend_from_slice(&offset.to_be_bytes());
+        for (_, content) in &chunks {
+            out.extend_from_slice(content);
+        }
+        let checksum = Sha1::digest(&out);
+        out.extend_from_slice(&checksum);
+        CommitGraph::parse(out)
+    }
+
+    /// 解析提交图文件
+    ///
+    /// 只检查文件结构，不计算校验和，打开大文件时的开销与提交数无关；完整检查见 [`CommitGraph::verify`]。
+    pub fn parse(data: Vec<u8>) -> MonoResult<CommitGraph> {
+        let corrupt = |msg: &str| MonoError::storage(format!("corrupt commit-graph: {}", msg));
+        if data.len() < HEADER_LEN + CHUNK_ENTRY_LEN + OBJECT_ID_LEN || &data[..4] != GRAPH_SIGNATURE {
+            return Err(corrupt("missing header"));
+        }
+        if data[4] != GRAPH_VERSION {
+            return Err(corrupt(&format!("unsupported version {}", data[4])));
+        }
+        if data[5] != HASH_VERSION_SHA1 {
+            return Err(corrupt(&format!("unsupported hash version {}", data[5])));
+        }
+        if data[7] != 0 {
+            return Err(corrupt("split commit-graph chains are not supported"));
+        }
+
+        let body_len = data.len() - OBJECT_ID_LEN;
+        let chunk_count = data[6] as usize;
+        let table_end = HEADER_LEN + (chunk_count + 1) * CHUNK_ENTRY_LEN;
+        if body_len < table_end {
+            return Err(corrupt("truncated chunk table"));
+        }
+        let offset_at = |i: usize| {
+            let at = HEADER_LEN + i * CHUNK_ENTRY_LEN + 4;
+            u64::from_be_bytes(data[at..at + 8].try_into().unwrap()) as usize
+        };
+        let mut chunks: BTreeMap<[u8; 4], std::ops::Range<usize>> = BTreeMap::new();
+        for i in 0..chunk_count {
+            let at = HEADER_LEN + i * CHUNK_ENTRY_LEN;
+            let id: [u8; 4] = data[at..at + 4].try_into().unwrap();
+            let (start, end) = (offset_at(i), offset_at(i + 1));
+            if start < table_end || start > end || end > body_len {
+                return Err(corrupt("chunk out of range"));
+            }
+            chunks.insert(id, start..end);
+        }
+        let chunk = |id: &[u8; 4]| {
+            chunks
+                .get(id)
+                .cloned()
+                .ok_or_else(|| corrupt(&format!("missing {} chunk", String::from_utf8_lossy(id))))
+        };
+
+        let fanout_chunk = chunk(CHUNK_OID_FANOUT)?;
+        if fanout_chunk.len() != 256 * 4 {
+            return Err(corrupt("invalid fanout chunk"));
+        }
+        let mut fanout = [0u32; 256];
+        for (i, slot) in fanout.iter_mut().enumerate() {
+            *slot = read_u32(&data, fanout_chunk.start + i * 4);
+        }
+        if fanout.windows(2).any(|w| w[0] > w[1]) {
+            return Err(corrupt("fanout table is not monotonic"));
+        }
+        let count = fanout[255] as usize;
+        let ids = chunk(CHUNK_OID_LOOKUP)?;
+        let commits = chunk(CHUNK_COMMIT_DATA)?;
+        if ids.len() != count * OBJECT_ID_LEN || commits.len() != count * COMMIT_DATA_LEN {
+            return Err(corrupt("commit count does not match fanout"));
+        }
+        let edges = chunks.get(CHUNK_EXTRA_EDGES).cloned().unwrap_or(0..0);
+        if edges.len() % 4 != 0 {
+            return Err(corrupt("invalid extra edges chunk"));
+        }
+        Ok(CommitGraph {
+            fanout,
+            ids_at: ids.start,
+            commits_at: commits.start,
+            edges,
+            data,
+        })
+    }
+
+    /// 读取仓库的提交图，文件不存在时返回 None
+    pub fn load(repo: &Repository) -> MonoResult<Option<CommitGraph>> {
+        let path = graph_path(repo);
+        match std::fs::read(&path) {
+            Ok(data) => CommitGraph::parse(data).map(Some).map_err(|e| e.context(path.display().to_string())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// 检查校验和、提交 ID 的顺序以及父提交与世代号的一致性
+    pub fn verify(&self) -> MonoResult<()> {
+        let corrupt = |msg: String| MonoError::storage(format!("corrupt commit-graph: {}", msg));
+        let (body, trailer) = self.data.split_at(self.data.len() - OBJECT_ID_LEN);
+        if Sha1::digest(body).as_slice() != trailer {
+            return Err(corrupt("checksum mismatch".to_string()));
+        }
+        for pos in 0..self.len() as u32 {
+            let commit = self.commit_at(pos)?;
+            if pos > 0 && self.id_at(pos - 1) >= commit.id {
+                return Err(corrupt("commit ids are not sorted".to_string()));
+            }
+            let mut expected = 1;
+            for parent in &commit.parents {
+                let parent = self.get(parent)?.ok_or_else(|| corrupt(format!("parent of {} is missing", commit.id)))?;
+                expected = expected.max(parent.generation.saturating_add(1));
+            }
+            if commit.generation != expected.min(GENERATION_MAX) {
+                return Err(corrupt(format!("wrong generation for {}", commit.id)));
+            }
+        }
+        Ok(())
+    }
+
+    /// 查找提交在提交图中的序号
+    pub fn find(&self, id: &ObjectId) -> Option<u32> {
+        let range = fanout_range(&self.fanout, id);
+        let (mut low, mut high) = (range.start, range.end);
+        while low < high {
+            let mid = (low + high) / 2;
+            match self.id_bytes(mid).cmp(id.as_bytes()) {
+                std::cmp::Ordering::Less => low = mid + 1,
+                std::cmp::Ordering::Greater => high = mid,
+                std::cmp::Ordering::Equal => return Some(mid as u32),
+            }
+        }
+        None
+    }
+
+    /// 查找提交，不在提交图中时返回 None
+    pub fn get(&self, id: &ObjectId) -> MonoResult<Option<GraphCommit>> {
+        match self.find(id) {
+            Some(pos) => self.commit_at(pos).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// 序号为 `pos` 的提交 ID
+    pub fn id_at(&self, pos: u32) -> ObjectId {
+        ObjectId::from_bytes(self.id_bytes(pos as usize)).expect("commit-graph ids have a fixed length")
+    }
+
+    /// 解码序号为 `pos` 的提交
+    pub fn commit_at(&self, pos: u32) -> MonoResult<GraphCommit> {
+        let at = self.commits_at + pos as usize * COMMIT_DATA_LEN;
+        let entry = &self.data[at..at + COMMIT_DATA_LEN];
+        let mut parents = Vec::new();
+        let first = read_u32(entry, OBJECT_ID_LEN);
+        let second = read_u32(entry, OBJECT_ID_LEN + 4);
+        if first != PARENT_NONE {
+            parents.push(self.parent_id(first)?);
+        }
+        if second & PARENT_EXTRA != 0 {
+            let mut at = self.edges.start + (second & !PARENT_EXTRA) as usize * 4;
+            loop {
+                if at + 4 > self.edges.end {
+                    return Err(MonoError::storage("corrupt commit-graph: extra edge out of range"));
+                }
+                let edge = read_u32(&self.data, at);
+                parents.push(self.parent_id(edge & !PARENT_EXTRA)?);
+                if edge & PARENT_EXTRA != 0 {
+                    break;
+                }
+                at += 4;
+            }
+        } else if second != PARENT_NONE {
+            parents.push(self.parent_id(second)?);
+        }
+        let generation_and_time = read_u32(entry, OBJECT_ID_LEN + 8);
+        let time = ((generation_and_time & 3) as i64) << 32 | read_u32(entry, OBJECT_ID_LEN + 12) as i64;
+        Ok(GraphCommit {
+            id: self.id_at(pos),
+            tree: ObjectId::from_bytes(&entry[..OBJECT_ID_LEN])?,
+            parents,
+            generation: generation_and_time >> 2,
+            commit_time: time,
+        })
+    }
+
+    /// 编码后的文件内容
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn len(&self) -> usize {
+        self.fanout[255] as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn id_bytes(&self, pos: usize) -> &[u8] {
+        let at = self.ids_at + pos * OBJECT_ID_LEN;
+        &self.data[at..at + OBJECT_ID_LEN]
+    }
+
+    fn parent_id(&self, pos: u32) -> MonoResult<ObjectId> {
+        if pos as usize >= self.len() {
+            return Err(MonoError::storage("corrupt commit-graph: parent position out of range"));
+        }
+        Ok(self.id_at(pos))
+    }
+}
+
+/// 仓库提交图文件的路径
+pub fn graph_path(repo: &Repository) -> PathBuf {
+    repo.objects_dir().join(COMMIT_GRAPH_FILE)
+}
+
+/// 提交图与可达性位图中的对象 ID 固定为 SHA-1，其他对象格式的仓库不生成这些文件
+pub(crate) fn check_object_format(repo: &Repository, file: &str) -> MonoResult<()> {
+    match repo.object_format() {
+        ObjectFormat::Sha1 => Ok(()),
+        format => Err(MonoError::unavailable(format!(
+            "{} is not supported for the {} object format",
+            file, format
+        ))),
+    }
+}
+
+/// 为所有引用（及 HEAD）可达的提交写入提交图与尾注索引，返回写入的提交图
+pub fn write_commit_graph(repo: &Repository) -> MonoResult<CommitGraph> {
+    check_object_format(repo, "commit-graph")?;
+    let mut tips: Vec<ObjectId> = repo.refs().list("refs/")?.into_iter().map(|(_, id)| id).collect();
+    tips.extend(repo.head_commit()?);
+
+    let mut commits: HashMap<ObjectId, Commit> = HashMap::new();
+    let mut stack = Vec::new();
+    for tip in tips {
+        // 指向树或 blob 的标签不参与提交图
+        let (id, object_type) = repo.peel(&tip)?;
+        if object_type == ObjectType::Commit {
+            stack.push(id);
+        }
+    }
+    while let Some(id) = stack.pop() {
+        if commits.contains_key(&id) {
+            continue;
+        }
+        let commit = repo.read_commit(&id)?;
+        stack.extend(commit.parents.iter().filter(|p| !commits.contains_key(p)).copied());
+        commits.insert(id, commit);
+    }
+
+    let trailers = trailers::TrailerIndex::new(&commits);
+    let graph = CommitGraph::new(commits)?;
+    write_file(&graph_path(repo), graph.as_bytes())?;
+    trailers.write(repo)?;
+    tracing::debug!(commits = graph.len(), trailers = trailers.len(), "wrote commit-graph");
+    Ok(graph)
+}
+
+/// 先写入临时文件再重命名，读者不会看到写了一半的提交图
+fn write_file(path: &Path, data: &[u8]) -> MonoResult<()> {
+    std::fs::create_dir_all(path.parent().expect("commit-graph path has a parent"))?;
+    let tmp = path.with_file_name(format!(".tmp-{}-commit-graph", std::process::id()));
+    std::fs::write(&tmp, data)?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::commit::Signature;
+
+    fn commit(parents: &[ObjectId], time: i64) -> (ObjectId, Commit) {
+        let sig = Signature::new("A U Thor", "author@example.com", time);
+        let commit = Commit {
+            tree: ObjectId::hash_object(ObjectType::Tree, b""),
+            parents: parents.to_vec(),
+            author: sig.clone(),
+            committer: sig,
+            extra_headers: Vec::new(),
+            message: format!("commit at {}\n", time),
+        };
+        (commit.id(), commit)
+    }
+
+    /// 测试世代号计算、章鱼合并的父提交与编码往返
+    #[test]
+    fn test_commit_graph_roundtrip() {
+        let root = commit(&[], 100);
+        let a = commit(&[root.0], 200);
+        let b = commit(&[root.0], 300);
+        let c = commit(&[a.0], 1 << 33);
+        let octopus = commit(&[c.0, b.0, a.0], 500);
+        let all = [root.clone(), a.clone(), b.clone(), c.clone(), octopus.clone()];
+        let graph = CommitGraph::new(all.clone()).unwrap();
+        assert_eq!(graph.len(), 5);
+        graph.verify().unwrap();
+
+        let parsed = CommitGraph::parse(graph.as_bytes().to_vec()).unwrap();
+        assert_eq!(parsed, graph);
+        for (id, expected) in &all {
+            let found = parsed.get(id).unwrap().unwrap();
+            assert_eq!(found.tree, expected.tree);
+            assert_eq!(found.parents, expected.parents);
+            assert_eq!(found.commit_time, expected.committer.timestamp);
+        }
+        let generation = |id: &ObjectId| parsed.get(id).unwrap().unwrap().generation;
+        assert_eq!(generation(&root.0), 1);
+        assert_eq!(generation(&b.0), 2);
+        assert_eq!(generation(&c.0), 3);
+        assert_eq!(generation(&octopus.0), 4);
+        assert_eq!(parsed.get(&ObjectId::hash_object(ObjectType::Blob, b"x")).unwrap(), None);
+    }
+
+    /// 测试父提交缺失与损坏的文件返回错误
+    #[test]
+    fn test_corrupt_commit_graph() {
+        let root = commit(&[], 100);
+        let child = commit(&[root.0], 200);
+        assert!(CommitGraph::new([child.clone()]).is_err());
+
+        let graph = CommitGraph::new([root, child]).unwrap();
+        let mut data = graph.as_bytes().to_vec();
+        let last = data.len() - 1;
+        data[last] ^= 1;
+        assert!(CommitGraph::parse(data.clone()).unwrap().verify().is_err());
+        assert!(CommitGraph::parse(data[..40].to_vec()).is_err());
+        assert!(CommitGraph::parse(b"CGPH".to_vec()).is_err());
+    }
+}
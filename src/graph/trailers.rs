@@ -0,0 +1,212 @@
+//! 提交尾注索引
+//!
+//! 写入提交图时同时把每个提交信息末尾的尾注（`Reviewed-by:`、`Change-Id:`、`Fixes:` 等）写入
+//! `objects/info/commit-trailers.json`，按提交 ID 排序，只记录带尾注的提交。追溯工具据此按提交
+//! 查询结构化的元数据，或反查带某个尾注的提交，例如同一 `Change-Id` 的各次提交、修复某个提交的提交。
+//! 接口见 [`crate::server::api`]。
+//!
+//! 单个提交的元数据不在索引中时直接解析提交；按尾注反查只覆盖写入提交图时可达的提交。
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::object::commit::Commit;
+use crate::object::ObjectId;
+use crate::repo::Repository;
+
+/// 尾注索引文件相对于对象存储目录的路径
+pub const TRAILERS_FILE: &str = "info/commit-trailers.json";
+
+/// 评审者
+pub const REVIEWED_BY: &str = "Reviewed-by";
+
+/// Gerrit 风格的变更 ID，同一变更的各次修改共用
+pub const CHANGE_ID: &str = "Change-Id";
+
+/// 被修复的提交，如 `Fixes: 1234567890ab ("subject")`，或被修复的工单
+pub const FIXES: &str = "Fixes";
+
+/// 一条尾注
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Trailer {
+    pub key: String,
+    pub value: String,
+}
+
+/// 一个提交的元数据
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Metadata {
+    pub change_id: Option<String>,
+    pub reviewed_by: Vec<String>,
+    pub fixes: Vec<String>,
+    /// 全部尾注，按在提交信息中的顺序
+    pub trailers: Vec<Trailer>,
+}
+
+impl Metadata {
+    /// 由尾注构建，键不区分大小写
+    pub fn from_trailers(trailers: Vec<Trailer>) -> Metadata {
+        let values = |key: &str| -> Vec<String> {
+            trailers.iter().filter(|t| t.key.eq_ignore_ascii_case(key)).map(|t| t.value.clone()).collect()
+        };
+        Metadata {
+            change_id: values(CHANGE_ID).into_iter().next(),
+            reviewed_by: values(REVIEWED_BY),
+            fixes: values(FIXES),
+            trailers,
+        }
+    }
+
+    pub fn from_commit(commit: &Commit) -> Metadata {
+        Metadata::from_trailers(parse_trailers(commit))
+    }
+}
+
+fn parse_trailers(commit: &Commit) -> Vec<Trailer> {
+    commit
+        .trailers()
+        .into_iter()
+        .map(|(key, value)| Trailer { key: key.to_string(), value })
+        .collect()
+}
+
+/// 尾注的值是否匹配查询：完全相同、第一个词相同，或第一个词与查询是同一个提交 ID 的不同缩写
+pub fn value_matches(value: &str, query: &str) -> bool {
+    if value == query {
+        return true;
+    }
+    let first = value.split_whitespace().next().unwrap_or_default();
+    if first == query {
+        return true;
+    }
+    let is_hex = |s: &str| s.len() >= 7 && s.bytes().all(|b| b.is_ascii_hexdigit());
+    is_hex(first) && is_hex(query) && {
+        let (first, query) = (first.to_ascii_lowercase(), query.to_ascii_lowercase());
+        first.starts_with(&query) || query.starts_with(&first)
+    }
+}
+
+/// 尾注索引
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct TrailerIndex {
+    commits: BTreeMap<ObjectId, Vec<Trailer>>,
+}
+
+impl TrailerIndex {
+    /// 由一组提交构建，没有尾注的提交不记录
+    pub fn new<'c>(commits: impl IntoIterator<Item = (&'c ObjectId, &'c Commit)>) -> TrailerIndex {
+        let commits = commits
+            .into_iter()
+            .map(|(id, commit)| (*id, parse_trailers(commit)))
+            .filter(|(_, trailers)| !trailers.is_empty())
+            .collect();
+        TrailerIndex { commits }
+    }
+
+    /// 读取索引，不存在时返回 None
+    pub fn load(repo: &Repository) -> MonoResult<Option<TrailerIndex>> {
+        let path = trailers_path(repo);
+        match std::fs::read(&path) {
+            Ok(data) => serde_json::from_slice(&data)
+                .map(Some)
+                .map_err(|e| MonoError::storage(format!("corrupt {}: {}", path.display(), e))),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// 写入索引，先写临时文件再重命名
+    pub fn write(&self, repo: &Repository) -> MonoResult<()> {
+        let path = trailers_path(repo);
+        std::fs::create_dir_all(path.parent().expect("trailer index path has a parent"))?;
+        let data = serde_json::to_vec(self).map_err(|e| MonoError::storage(e.to_string()))?;
+        let tmp = path.with_file_name(format!(".tmp-{}-commit-trailers.json", std::process::id()));
+        std::fs::write(&tmp, data)?;
+        std::fs::rename(&tmp, &path)?;
+        Ok(())
+    }
+
+    /// 带尾注的提交数
+    pub fn len(&self) -> usize {
+        self.commits.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commits.is_empty()
+    }
+
+    /// 提交的尾注，不在索引中时返回 None
+    pub fn get(&self, id: &ObjectId) -> Option<&[Trailer]> {
+        self.commits.get(id).map(Vec::as_slice)
+    }
+
+    /// 带有键为 `key`（不区分大小写）且值匹配 `value` 的尾注的提交，按提交 ID 排序；
+    /// `value` 的匹配规则见 [`value_matches`]
+    pub fn find(&self, key: &str, value: &str) -> Vec<ObjectId> {
+        self.commits
+            .iter()
+            .filter(|(_, trailers)| trailers.iter().any(|t| t.key.eq_ignore_ascii_case(key) && value_matches(&t.value, value)))
+            .map(|(id, _)| *id)
+            .collect()
+    }
+}
+
+/// 读取提交的元数据，优先使用索引
+pub fn metadata(repo: &Repository, index: Option<&TrailerIndex>, id: &ObjectId) -> MonoResult<Metadata> {
+    match index.and_then(|index| index.get(id)) {
+        Some(trailers) => Ok(Metadata::from_trailers(trailers.to_vec())),
+        None => Ok(Metadata::from_commit(&repo.read_commit(id)?)),
+    }
+}
+
+pub fn trailers_path(repo: &Repository) -> PathBuf {
+    repo.objects_dir().join(TRAILERS_FILE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::write_commit_graph;
+    use crate::refs::RefUpdate;
+    use crate::test_utils::{commit_files, init_repo};
+
+    /// 测试写入提交图时生成尾注索引，按尾注反查提交，以及提交 ID 缩写的匹配
+    #[test]
+    fn test_index() {
+        let (_dir, repo) = init_repo();
+        let bug = commit_files(&repo, &[("a", b"1")], &[], "add parser\n\nChange-Id: I1111\n");
+        let fix = commit_files(
+            &repo,
+            &[("a", b"2")],
+            &[bug],
+            &format!(
+                "fix parser\n\nFixes: {} (\"add parser\")\nReviewed-by: Alice <alice@example.com>\nreviewed-by: Bob <bob@example.com>\nChange-Id: I2222\n",
+                &bug.to_hex()[..12]
+            ),
+        );
+        let plain = commit_files(&repo, &[("a", b"3")], &[fix], "no trailers");
+        repo.refs()
+            .update(&[RefUpdate { name: "refs/heads/main".to_string(), old: ObjectId::ZERO, new: plain }])
+            .unwrap();
+        write_commit_graph(&repo).unwrap();
+
+        let index = TrailerIndex::load(&repo).unwrap().unwrap();
+        assert_eq!(index.len(), 2);
+        let meta = metadata(&repo, Some(&index), &fix).unwrap();
+        assert_eq!(meta.change_id.as_deref(), Some("I2222"));
+        assert_eq!(meta.reviewed_by, ["Alice <alice@example.com>", "Bob <bob@example.com>"]);
+        assert_eq!(meta.fixes.len(), 1);
+        assert_eq!(meta.trailers.len(), 4);
+        assert_eq!(metadata(&repo, Some(&index), &plain).unwrap(), Metadata::default());
+
+        assert_eq!(index.find("fixes", &bug.to_hex()), [fix]);
+        assert_eq!(index.find("Fixes", &bug.to_hex()[..7]), [fix]);
+        assert_eq!(index.find("change-id", "I1111"), [bug]);
+        assert!(index.find("Change-Id", "I11").is_empty());
+        assert!(!value_matches("1234567", "123456"));
+    }
+}
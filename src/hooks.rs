@@ -0,0 +1,331 @@
+//! 服务端钩子
+//!
+//! 与 git 的服务端钩子对应，receive-pack 在推送的三个阶段执行钩子（见 [`HookStage`]）：
+//! `pre-receive` 可以拒绝整个推送，`update` 可以拒绝单个引用，`post-receive` 用于通知。
+//! 钩子可以是实现 [`Hook`] 的 trait 对象，也可以是 `mono.toml` 中配置的外部命令：
+//!
+//! ```toml
+//! [[hooks]]
+//! name = "commit-message"
+//! stage = "pre-receive"
+//! command = "scripts/check-push.sh"
+//! timeout_secs = 10
+//! ```
+//!
+//! 外部命令从标准输入读取一个 JSON 对象（见 [`HookInput`]），退出码为 0 表示接受；
+//! 推送者以 `git push -o <选项>` 传入的推送选项既在 JSON 的 `push_options` 中，也与 git 相同地
+//! 通过 `GIT_PUSH_OPTION_COUNT` 与 `GIT_PUSH_OPTION_<序号>` 环境变量传给命令，例如 `-o skip-ci`；
+//! 否则以标准错误（为空时为标准输出）的最后一行作为拒绝原因。钩子执行失败或超时同样视为拒绝，
+//! 避免检查失效时放行推送。
+
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::common::config::{HookConfig, HookStage};
+use crate::common::MonoResult;
+use crate::refs::RefUpdate;
+use crate::repo::Repository;
+use crate::search::CodeSearch;
+use crate::webhooks::WebhookQueue;
+
+/// 传给钩子的推送内容，外部命令从标准输入读取它的 JSON 形式
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct HookInput {
+    pub stage: HookStage,
+    /// 仓库根目录
+    pub repository: PathBuf,
+    /// `update` 阶段只包含当前引用，其余阶段包含全部待更新（或已更新）的引用
+    pub updates: Vec<RefUpdate>,
+    /// 推送选项，没有时为空
+    pub push_options: Vec<String>,
+}
+
+/// 一个服务端钩子
+pub trait Hook: Send + Sync {
+    /// 钩子名，出现在日志与拒绝原因中
+    fn name(&self) -> &str;
+
+    /// 执行的阶段
+    fn stage(&self) -> HookStage;
+
+    /// 执行钩子，拒绝时返回 `Ok(Err(原因))`
+    fn run(&self, input: &HookInput) -> MonoResult<Result<(), String>>;
+}
+
+/// 通过 `sh -c` 在仓库根目录运行的外部命令钩子
+pub struct CommandHook {
+    name: String,
+    stage: HookStage,
+    command: String,
+    timeout: Duration,
+}
+
+impl CommandHook {
+    pub fn new(name: impl Into<String>, stage: HookStage, command: impl Into<String>, timeout: Duration) -> CommandHook {
+        CommandHook {
+            name: name.into(),
+            stage,
+            command: command.into(),
+            timeout,
+        }
+    }
+
+    pub fn from_config(config: &HookConfig) -> CommandHook {
+        CommandHook::new(
+            config.name.clone(),
+            config.stage,
+            config.command.clone(),
+            Duration::from_secs(config.timeout_secs),
+        )
+    }
+}
+
+impl Hook for CommandHook {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn stage(&self) -> HookStage {
+        self.stage
+    }
+
+    fn run(&self, input: &HookInput) -> MonoResult<Result<(), String>> {
+        let stdin = serde_json::to_vec(input).map_err(anyhow::Error::from)?;
+        let mut command = Command::new("sh");
+        command
+            .arg("-c")
+            .arg(&self.command)
+            .current_dir(&input.repository)
+            .env("MONO_HOOK_STAGE", input.stage.as_str())
+            .env("GIT_PUSH_OPTION_COUNT", input.push_options.len().to_string());
+        for (i, option) in input.push_options.iter().enumerate() {
+            command.env(format!("GIT_PUSH_OPTION_{}", i), option);
+        }
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        // 输入与输出在单独的线程中读写，钩子不读取输入或输出较多时不会阻塞等待
+        let mut writer = child.stdin.take().expect("piped stdin");
+        let writer = std::thread::spawn(move || {
+            let _ = writer.write_all(&stdin);
+        });
+        let mut stdout = child.stdout.take().expect("piped stdout");
+        let stdout = std::thread::spawn(move || {
+            let mut out = Vec::new();
+            let _ = stdout.read_to_end(&mut out);
+            out
+        });
+        let mut stderr = child.stderr.take().expect("piped stderr");
+        let stderr = std::thread::spawn(move || {
+            let mut out = Vec::new();
+            let _ = stderr.read_to_end(&mut out);
+            out
+        });
+
+        let started = Instant::now();
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break Some(status);
+            }
+            if started.elapsed() >= self.timeout {
+                let _ = child.kill();
+                let _ = child.wait();
+                break None;
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        };
+        // 超时后命令启动的子进程可能仍持有输出管道，不等待读取线程结束
+        let Some(status) = status else {
+            return Ok(Err(format!("timed out after {}s", self.timeout.as_secs())));
+        };
+        let _ = writer.join();
+        let stdout = stdout.join().unwrap_or_default();
+        let stderr = stderr.join().unwrap_or_default();
+
+        let last_line = |output: &[u8]| {
+            String::from_utf8_lossy(output)
+                .lines()
+                .rev()
+                .map(str::trim)
+                .find(|line| !line.is_empty())
+                .map(str::to_string)
+        };
+        if status.success() {
+            return Ok(Ok(()));
+        }
+        tracing::info!(
+            hook = %self.name,
+            %status,
+            stdout = %String::from_utf8_lossy(&stdout),
+            stderr = %String::from_utf8_lossy(&stderr),
+            "hook declined"
+        );
+        Ok(Err(last_line(&stderr)
+            .or_else(|| last_line(&stdout))
+            .unwrap_or_else(|| format!("exited with {}", status))))
+    }
+}
+
+/// 仓库的全部钩子
+#[derive(Default)]
+pub struct Hooks {
+    hooks: Vec<Box<dyn Hook>>,
+}
+
+impl Hooks {
+    /// 仓库配置中的外部命令钩子；配置了 webhook 时还包括记录推送事件的内置钩子
+    pub fn load(repo: &Repository) -> Hooks {
+        let mut hooks = Hooks::default();
+        for config in &repo.config().hooks {
+            hooks.register(Box::new(CommandHook::from_config(config)));
+        }
+        let webhooks = WebhookQueue::new(repo);
+        if !webhooks.is_empty() {
+            hooks.register(Box::new(webhooks));
+        }
+        hooks
+    }
+
+    /// 追加一个钩子，同一阶段的钩子按注册顺序执行
+    pub fn register(&mut self, hook: Box<dyn Hook>) {
+        self.hooks.push(hook);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hooks.is_empty()
+    }
+
+    /// 依次执行该阶段的钩子，第一个拒绝的钩子的原因作为结果
+    fn run_stage(&self, repo: &Repository, stage: HookStage, updates: &[RefUpdate], push_options: &[String]) -> Result<(), String> {
+        let input = HookInput {
+            stage,
+            repository: repo.root().to_path_buf(),
+            updates: updates.to_vec(),
+            push_options: push_options.to_vec(),
+        };
+        for hook in self.hooks.iter().filter(|hook| hook.stage() == stage) {
+            let result = hook.run(&input).unwrap_or_else(|e| {
+                tracing::error!(hook = hook.name(), error = %e, "hook failed");
+                Err("hook failed".to_string())
+            });
+            if let Err(reason) = result {
+                return Err(format!("{} hook {} declined: {}", stage, hook.name(), reason));
+            }
+        }
+        Ok(())
+    }
+
+    /// 在更新任何引用之前对整个推送执行 `pre-receive` 钩子
+    pub fn pre_receive(&self, repo: &Repository, updates: &[RefUpdate], push_options: &[String]) -> Result<(), String> {
+        self.run_stage(repo, HookStage::PreReceive, updates, push_options)
+    }
+
+    /// 对单个引用执行 `update` 钩子
+    pub fn update(&self, repo: &Repository, update: &RefUpdate, push_options: &[String]) -> Result<(), String> {
+        self.run_stage(repo, HookStage::Update, std::slice::from_ref(update), push_options)
+    }
+
+    /// 对已更新的引用执行 `post-receive` 钩子，拒绝只记录到日志；之后增量更新被推送分支的搜索索引
+    pub fn post_receive(&self, repo: &Repository, updates: &[RefUpdate], push_options: &[String]) {
+        if let Err(reason) = self.run_stage(repo, HookStage::PostReceive, updates, push_options) {
+            tracing::warn!(reason = %reason, "post-receive hook failed");
+        }
+        CodeSearch::new(repo).update_refs(updates);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::ObjectId;
+    use crate::test_utils::init_repo;
+
+    fn update(name: &str) -> RefUpdate {
+        RefUpdate {
+            name: name.to_string(),
+            old: ObjectId::ZERO,
+            new: ObjectId::hash_object(crate::object::ObjectType::Blob, name.as_bytes()),
+        }
+    }
+
+    /// 测试外部命令钩子读取 JSON 输入，以退出码决定结果，以及超时视为拒绝
+    #[test]
+    fn test_command_hook() {
+        let (_dir, repo) = init_repo();
+        let updates = [update("refs/heads/main"), update("refs/heads/wip")];
+        let input = HookInput {
+            stage: HookStage::PreReceive,
+            repository: repo.root().to_path_buf(),
+            updates: updates.to_vec(),
+            push_options: Vec::new(),
+        };
+        let script = "cat > push.json; if grep -q refs/heads/wip push.json; then echo 'no wip branches' >&2; exit 1; fi";
+        let hook = CommandHook::new("no-wip", HookStage::PreReceive, script, Duration::from_secs(10));
+        assert_eq!(hook.run(&input).unwrap(), Err("no wip branches".to_string()));
+        let json: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(repo.root().join("push.json")).unwrap()).unwrap();
+        assert_eq!(json["stage"], "pre-receive");
+        assert_eq!(json["updates"][1]["name"], "refs/heads/wip");
+        assert_eq!(json["updates"][1]["old"], ObjectId::ZERO.to_string());
+
+        let input = HookInput {
+            updates: updates[..1].to_vec(),
+            ..input
+        };
+        assert_eq!(hook.run(&input).unwrap(), Ok(()));
+
+        let script = "[ \"$GIT_PUSH_OPTION_COUNT\" = 2 ] && [ \"$GIT_PUSH_OPTION_1\" = queue=priority ] || { echo \"$GIT_PUSH_OPTION_0\"; exit 1; }";
+        let options = CommandHook::new("options", HookStage::PreReceive, script, Duration::from_secs(10));
+        assert_eq!(options.run(&input).unwrap(), Err("exited with exit status: 1".to_string()));
+        let input = HookInput {
+            push_options: vec!["skip-ci".to_string(), "queue=priority".to_string()],
+            ..input
+        };
+        assert_eq!(options.run(&input).unwrap(), Ok(()));
+
+        let slow = CommandHook::new("slow", HookStage::PreReceive, "sleep 5", Duration::from_millis(200));
+        assert_eq!(slow.run(&input).unwrap(), Err("timed out after 0s".to_string()));
+    }
+
+    struct DenyPrefix(&'static str);
+
+    impl Hook for DenyPrefix {
+        fn name(&self) -> &str {
+            "deny-prefix"
+        }
+
+        fn stage(&self) -> HookStage {
+            HookStage::Update
+        }
+
+        fn run(&self, input: &HookInput) -> MonoResult<Result<(), String>> {
+            Ok(match input.updates.iter().any(|u| u.name.starts_with(self.0)) {
+                true => Err(format!("{} is read-only", self.0)),
+                false => Ok(()),
+            })
+        }
+    }
+
+    /// 测试按阶段执行 trait 对象钩子，拒绝原因包含阶段与钩子名
+    #[test]
+    fn test_hooks() {
+        let (_dir, repo) = init_repo();
+        let mut hooks = Hooks::load(&repo);
+        assert!(hooks.is_empty());
+        hooks.register(Box::new(DenyPrefix("refs/tags/")));
+        let tag = update("refs/tags/v1");
+        assert_eq!(hooks.pre_receive(&repo, std::slice::from_ref(&tag), &[]), Ok(()));
+        assert_eq!(
+            hooks.update(&repo, &tag, &[]),
+            Err("update hook deny-prefix declined: refs/tags/ is read-only".to_string())
+        );
+        assert_eq!(hooks.update(&repo, &update("refs/heads/main"), &[]), Ok(()));
+    }
+}
@@ -0,0 +1,738 @@
+//! 暂存区（索引）
+//!
+//! 读写与 git 格式相同的索引文件 `.mono/index`：文件头 `DIRC`、版本号与条目数，按路径与合并
+//! 阶段排列的条目，若干扩展，最后是前面全部内容的校验和。支持版本 2 至 4（版本 3 增加扩展
+//! 标志位，版本 4 对路径做前缀压缩），以及以下扩展：
+//!
+//! - `link`：拆分索引，见 [`split`]；
+//! - `UNTR`：未跟踪文件缓存，见 [`untracked`]；
+//! - 其他可选扩展（如缓存树 `TREE`）原样保留，条目变化后丢弃 `TREE`；记录文件内偏移的
+//!   `EOIE` 与 `IEOT` 在读取时丢弃。
+//!
+//! 与 git 相同，不认识的必需扩展（签名首字母不是大写字母）会使读取失败。
+
+pub mod split;
+pub mod untracked;
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::common::config::IndexConfig;
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::object::tree::FileMode;
+use crate::object::{ObjectFormat, ObjectId};
+use crate::pack::{encode_offset, read_offset};
+use crate::queue::LockFile;
+use crate::repo::Repository;
+use split::{Link, SharedIndex};
+use untracked::UntrackedCache;
+
+/// 索引文件名，相对于 `.mono`
+pub const INDEX_FILE: &str = "index";
+
+const SIGNATURE: &[u8; 4] = b"DIRC";
+const CACHE_TREE: &[u8; 4] = b"TREE";
+/// 只对读取原文件有意义的扩展
+const DROPPED: [&[u8; 4]; 2] = [b"EOIE", b"IEOT"];
+
+const FLAG_ASSUME_VALID: u16 = 0x8000;
+const FLAG_EXTENDED: u16 = 0x4000;
+const FLAG_NAME_MASK: u16 = 0x0fff;
+const FLAG_SKIP_WORKTREE: u16 = 0x4000;
+const FLAG_INTENT_TO_ADD: u16 = 0x2000;
+
+/// 条目记录的文件状态，用于不读取内容判断文件是否变化
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StatData {
+    pub ctime: u32,
+    pub ctime_nanos: u32,
+    pub mtime: u32,
+    pub mtime_nanos: u32,
+    pub dev: u32,
+    pub ino: u32,
+    pub uid: u32,
+    pub gid: u32,
+    /// 文件大小的低 32 位
+    pub size: u32,
+}
+
+impl StatData {
+    pub fn from_metadata(meta: &std::fs::Metadata) -> StatData {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            StatData {
+                ctime: meta.ctime() as u32,
+                ctime_nanos: meta.ctime_nsec() as u32,
+                mtime: meta.mtime() as u32,
+                mtime_nanos: meta.mtime_nsec() as u32,
+                dev: meta.dev() as u32,
+                ino: meta.ino() as u32,
+                uid: meta.uid(),
+                gid: meta.gid(),
+                size: meta.len() as u32,
+            }
+        }
+        #[cfg(not(unix))]
+        {
+            let mtime = meta
+                .modified()
+                .ok()
+                .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+                .unwrap_or_default();
+            StatData {
+                mtime: mtime.as_secs() as u32,
+                mtime_nanos: mtime.subsec_nanos(),
+                size: meta.len() as u32,
+                ..StatData::default()
+            }
+        }
+    }
+
+    /// 读取不含文件模式的 36 字节状态，未跟踪文件缓存使用这种格式
+    fn read(reader: &mut Reader) -> MonoResult<StatData> {
+        Ok(StatData {
+            ctime: reader.u32()?,
+            ctime_nanos: reader.u32()?,
+            mtime: reader.u32()?,
+            mtime_nanos: reader.u32()?,
+            dev: reader.u32()?,
+            ino: reader.u32()?,
+            uid: reader.u32()?,
+            gid: reader.u32()?,
+            size: reader.u32()?,
+        })
+    }
+
+    fn write(&self, out: &mut Vec<u8>) {
+        for value in [
+            self.ctime,
+            self.ctime_nanos,
+            self.mtime,
+            self.mtime_nanos,
+            self.dev,
+            self.ino,
+            self.uid,
+            self.gid,
+            self.size,
+        ] {
+            out.extend_from_slice(&value.to_be_bytes());
+        }
+    }
+}
+
+/// 索引条目
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexEntry {
+    pub path: String,
+    /// 合并阶段：0 为正常条目，1 至 3 为冲突时的共同祖先、我方与对方
+    pub stage: u8,
+    pub mode: FileMode,
+    pub id: ObjectId,
+    pub stat: StatData,
+    pub assume_valid: bool,
+    /// 稀疏检出排除的条目
+    pub skip_worktree: bool,
+    /// `add -N` 添加的条目
+    pub intent_to_add: bool,
+}
+
+impl IndexEntry {
+    pub fn new(path: impl Into<String>, mode: FileMode, id: ObjectId) -> IndexEntry {
+        IndexEntry {
+            path: path.into(),
+            stage: 0,
+            mode,
+            id,
+            stat: StatData::default(),
+            assume_valid: false,
+            skip_worktree: false,
+            intent_to_add: false,
+        }
+    }
+
+    fn key(&self) -> (&str, u8) {
+        (&self.path, self.stage)
+    }
+
+    fn is_extended(&self) -> bool {
+        self.skip_worktree || self.intent_to_add
+    }
+}
+
+/// 原样保留的扩展
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Extension {
+    signature: [u8; 4],
+    data: Vec<u8>,
+}
+
+/// 单个索引文件的内容，拆分索引的共享部分与变化部分各是一个这样的文件
+#[derive(Debug, Clone)]
+struct IndexFile {
+    version: u32,
+    entries: Vec<IndexEntry>,
+    extensions: Vec<Extension>,
+}
+
+impl IndexFile {
+    fn parse(data: &[u8], format: ObjectFormat) -> MonoResult<IndexFile> {
+        let hash_len = format.id_len();
+        if data.len() < 12 + hash_len {
+            return Err(corrupt("truncated file"));
+        }
+        let (body, checksum) = data.split_at(data.len() - hash_len);
+        // 与 git 的 index.skipHash 相同，全零的校验和表示未计算
+        if checksum.iter().any(|&byte| byte != 0) && format.digest(body).as_bytes() != checksum {
+            return Err(corrupt("checksum mismatch"));
+        }
+        let mut reader = Reader::new(body);
+        if reader.take(4)? != SIGNATURE {
+            return Err(corrupt("bad signature"));
+        }
+        let version = reader.u32()?;
+        if !(2..=4).contains(&version) {
+            return Err(MonoError::storage(format!("unsupported index version {}", version)));
+        }
+        let count = reader.u32()? as usize;
+        let mut entries = Vec::with_capacity(count.min(body.len() / 62));
+        let mut previous = Vec::new();
+        for _ in 0..count {
+            entries.push(read_entry(&mut reader, version, format, &mut previous)?);
+        }
+        let mut extensions = Vec::new();
+        while !reader.is_empty() {
+            let signature: [u8; 4] = reader.take(4)?.try_into().expect("four bytes");
+            let len = reader.u32()? as usize;
+            let data = reader.take(len)?;
+            if DROPPED.contains(&&signature) {
+                continue;
+            }
+            if !signature[0].is_ascii_uppercase() && &signature != split::LINK {
+                return Err(MonoError::storage(format!(
+                    "unsupported required index extension {}",
+                    String::from_utf8_lossy(&signature)
+                )));
+            }
+            extensions.push(Extension {
+                signature,
+                data: data.to_vec(),
+            });
+        }
+        Ok(IndexFile {
+            version,
+            entries,
+            extensions,
+        })
+    }
+
+    /// 编码为完整的文件内容，末尾为校验和
+    fn encode(&self, format: ObjectFormat) -> Vec<u8> {
+        // 版本 2 不能表示扩展标志位
+        let version = if self.version == 2 && self.entries.iter().any(IndexEntry::is_extended) {
+            3
+        } else {
+            self.version
+        };
+        let mut out = Vec::new();
+        out.extend_from_slice(SIGNATURE);
+        out.extend_from_slice(&version.to_be_bytes());
+        out.extend_from_slice(&(self.entries.len() as u32).to_be_bytes());
+        let mut previous = Vec::new();
+        for entry in &self.entries {
+            write_entry(&mut out, entry, version, &mut previous);
+        }
+        for extension in &self.extensions {
+            out.extend_from_slice(&extension.signature);
+            out.extend_from_slice(&(extension.data.len() as u32).to_be_bytes());
+            out.extend_from_slice(&extension.data);
+        }
+        let checksum = format.digest(&out);
+        out.extend_from_slice(checksum.as_bytes());
+        out
+    }
+
+    fn extension(&self, signature: &[u8; 4]) -> Option<&[u8]> {
+        self.extensions
+            .iter()
+            .find(|extension| &extension.signature == signature)
+            .map(|extension| extension.data.as_slice())
+    }
+}
+
+fn read_entry(reader: &mut Reader, version: u32, format: ObjectFormat, previous: &mut Vec<u8>) -> MonoResult<IndexEntry> {
+    let start = reader.pos;
+    let ctime = reader.u32()?;
+    let ctime_nanos = reader.u32()?;
+    let mtime = reader.u32()?;
+    let mtime_nanos = reader.u32()?;
+    let dev = reader.u32()?;
+    let ino = reader.u32()?;
+    let mode = FileMode(reader.u32()?);
+    let uid = reader.u32()?;
+    let gid = reader.u32()?;
+    let size = reader.u32()?;
+    let id = ObjectId::from_bytes(reader.take(format.id_len())?)?;
+    let flags = reader.u16()?;
+    let extended = if flags & FLAG_EXTENDED != 0 {
+        if version < 3 {
+            return Err(corrupt("extended flags in a version 2 index"));
+        }
+        reader.u16()?
+    } else {
+        0
+    };
+
+    let header = reader.pos - start;
+    let name = if version >= 4 {
+        let strip = reader.varint()?;
+        if strip > previous.len() {
+            return Err(corrupt("bad path prefix"));
+        }
+        previous.truncate(previous.len() - strip);
+        previous.extend_from_slice(reader.cstr()?);
+        previous.clone()
+    } else {
+        let len = usize::from(flags & FLAG_NAME_MASK);
+        let name = if len < usize::from(FLAG_NAME_MASK) {
+            reader.take(len)?.to_vec()
+        } else {
+            reader.cstr_peek()?.to_vec()
+        };
+        // 条目以 1 至 8 个 NUL 补齐到 8 字节的倍数
+        let entry_len = (header + name.len() + 8) & !7;
+        reader.pos = start;
+        reader.take(entry_len)?;
+        name
+    };
+    let path = String::from_utf8(name).map_err(|_| corrupt("path is not UTF-8"))?;
+    Ok(IndexEntry {
+        path,
+        stage: ((flags >> 12) & 0x3) as u8,
+        mode,
+        id,
+        stat: StatData {
+            ctime,
+            ctime_nanos,
+            mtime,
+            mtime_nanos,
+            dev,
+            ino,
+            uid,
+            gid,
+            size,
+        },
+        assume_valid: flags & FLAG_ASSUME_VALID != 0,
+        skip_worktree: extended & FLAG_SKIP_WORKTREE != 0,
+        intent_to_add: extended & FLAG_INTENT_TO_ADD != 0,
+    })
+}
+
+fn write_entry(out: &mut Vec<u8>, entry: &IndexEntry, version: u32, previous: &mut Vec<u8>) {
+    let start = out.len();
+    let stat = &entry.stat;
+    for value in [
+        stat.ctime,
+        stat.ctime_nanos,
+        stat.mtime,
+        stat.mtime_nanos,
+        stat.dev,
+        stat.ino,
+        entry.mode.0,
+        stat.uid,
+        stat.gid,
+        stat.size,
+    ] {
+        out.extend_from_slice(&value.to_be_bytes());
+    }
+    out.extend_from_slice(entry.id.as_bytes());
+    let path = entry.path.as_bytes();
+    let mut flags = (path.len().min(usize::from(FLAG_NAME_MASK)) as u16) | (u16::from(entry.stage & 0x3) << 12);
+    if entry.assume_valid {
+        flags |= FLAG_ASSUME_VALID;
+    }
+    if entry.is_extended() {
+        flags |= FLAG_EXTENDED;
+    }
+    out.extend_from_slice(&flags.to_be_bytes());
+    if entry.is_extended() {
+        let mut extended = 0;
+        if entry.skip_worktree {
+            extended |= FLAG_SKIP_WORKTREE;
+        }
+        if entry.intent_to_add {
+            extended |= FLAG_INTENT_TO_ADD;
+        }
+        out.extend_from_slice(&extended.to_be_bytes());
+    }
+
+    if version >= 4 {
+        let common = previous.iter().zip(path).take_while(|(a, b)| a == b).count();
+        out.extend_from_slice(&encode_offset((previous.len() - common) as u64));
+        out.extend_from_slice(&path[common..]);
+        out.push(0);
+        previous.clear();
+        previous.extend_from_slice(path);
+    } else {
+        out.extend_from_slice(path);
+        let len = out.len() - start;
+        out.resize(start + ((len + 8) & !7), 0);
+    }
+}
+
+/// 暂存区
+#[derive(Debug, Clone)]
+pub struct Index {
+    version: u32,
+    /// 按路径与合并阶段排列
+    entries: Vec<IndexEntry>,
+    /// 未跟踪文件缓存
+    pub untracked: Option<UntrackedCache>,
+    extensions: Vec<Extension>,
+    /// 读取或上次写入时使用的共享索引
+    base: Option<SharedIndex>,
+}
+
+impl Index {
+    pub fn new(version: u32) -> Index {
+        Index {
+            version,
+            entries: Vec::new(),
+            untracked: None,
+            extensions: Vec::new(),
+            base: None,
+        }
+    }
+
+    /// 读取仓库的索引，不存在时返回空索引
+    pub fn load(repo: &Repository) -> MonoResult<Index> {
+        let path = repo.mono_dir().join(INDEX_FILE);
+        if !path.is_file() {
+            return Ok(Index::new(repo.config().index.version));
+        }
+        Index::read(&path, repo.object_format())
+    }
+
+    /// 按仓库的 `[index]` 配置写入索引
+    pub fn save(&mut self, repo: &Repository) -> MonoResult<()> {
+        let path = repo.mono_dir().join(INDEX_FILE);
+        self.write(&path, repo.object_format(), &repo.config().index)
+    }
+
+    /// 读取索引文件，拆分索引从同一目录读取共享索引
+    pub fn read(path: &Path, format: ObjectFormat) -> MonoResult<Index> {
+        let file = IndexFile::parse(&std::fs::read(path)?, format)?;
+        let untracked = file.extension(untracked::SIGNATURE).map(|data| UntrackedCache::parse(data, format)).transpose()?;
+        let (entries, base) = match file.extension(split::LINK) {
+            Some(data) => {
+                let link = Link::parse(data, format)?;
+                let dir = path.parent().unwrap_or(Path::new("."));
+                let base = SharedIndex::read(dir, &link.base, format)?;
+                (split::merge(&base, &link, file.entries)?, Some(base))
+            }
+            None => (file.entries, None),
+        };
+        let extensions = file
+            .extensions
+            .into_iter()
+            .filter(|extension| &extension.signature != split::LINK && &extension.signature != untracked::SIGNATURE)
+            .collect();
+        Ok(Index {
+            version: file.version,
+            entries,
+            untracked,
+            extensions,
+            base,
+        })
+    }
+
+    /// 写入索引文件；配置了拆分索引时，变化不多则只写入相对共享索引的变化
+    pub fn write(&mut self, path: &Path, format: ObjectFormat, config: &IndexConfig) -> MonoResult<()> {
+        let dir = path.parent().unwrap_or(Path::new("."));
+        let _lock = LockFile::acquire(path.with_extension("lock"))?;
+        self.version = config.version;
+        let mut extensions = Vec::new();
+        let entries = if config.split {
+            let delta = self
+                .base
+                .as_ref()
+                .filter(|base| base.exists(dir))
+                .and_then(|base| split::diff(base, &self.entries, config.max_percent_change));
+            let (link, entries) = match delta {
+                Some(delta) => delta,
+                None => {
+                    let base = SharedIndex::write(dir, self.version, self.entries.clone(), format)?;
+                    let link = Link::new(base.id, base.entries.len());
+                    self.base = Some(base);
+                    (link, Vec::new())
+                }
+            };
+            extensions.push(Extension {
+                signature: *split::LINK,
+                data: link.encode(),
+            });
+            entries
+        } else {
+            self.base = None;
+            self.entries.clone()
+        };
+        if let Some(untracked) = &self.untracked {
+            extensions.push(Extension {
+                signature: *untracked::SIGNATURE,
+                data: untracked.encode(),
+            });
+        }
+        extensions.extend(self.extensions.iter().cloned());
+        let file = IndexFile {
+            version: self.version,
+            entries,
+            extensions,
+        };
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, file.encode(format))?;
+        std::fs::rename(&tmp, path)?;
+        split::remove_unused(dir, self.base.as_ref().map(|base| &base.id))
+    }
+
+    /// 读取时的格式版本
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    pub fn entries(&self) -> &[IndexEntry] {
+        &self.entries
+    }
+
+    pub fn get(&self, path: &str, stage: u8) -> Option<&IndexEntry> {
+        self.find(path, stage).ok().map(|i| &self.entries[i])
+    }
+
+    /// 加入或替换条目；正常条目与同一路径的冲突条目互相替换
+    pub fn add(&mut self, entry: IndexEntry) {
+        let path = entry.path.clone();
+        self.entries.retain(|old| old.path != path || ((old.stage == 0) == (entry.stage == 0) && old.stage != entry.stage));
+        let pos = self.find(&entry.path, entry.stage).unwrap_or_else(|pos| pos);
+        self.entries.insert(pos, entry);
+        self.changed(&path);
+    }
+
+    /// 删除路径的全部条目，返回是否存在
+    pub fn remove(&mut self, path: &str) -> bool {
+        let len = self.entries.len();
+        self.entries.retain(|entry| entry.path != path);
+        let removed = self.entries.len() != len;
+        if removed {
+            self.changed(path);
+        }
+        removed
+    }
+
+    /// 把条目替换为树的内容，路径、blob 与模式都未变的条目保留原有的文件状态
+    pub fn read_tree(&mut self, repo: &Repository, tree: &ObjectId) -> MonoResult<()> {
+        let mut old: BTreeMap<String, IndexEntry> = self
+            .entries
+            .drain(..)
+            .filter(|entry| entry.stage == 0)
+            .map(|entry| (entry.path.clone(), entry))
+            .collect();
+        let mut entries = Vec::new();
+        let mut changed = Vec::new();
+        let mut stack = vec![(String::new(), *tree)];
+        while let Some((prefix, tree_id)) = stack.pop() {
+            for entry in repo.read_tree(&tree_id)?.entries {
+                let path = format!("{}{}", prefix, entry.name);
+                if entry.mode.is_tree() {
+                    stack.push((format!("{}/", path), entry.id));
+                    continue;
+                }
+                match old.remove(&path) {
+                    Some(old) if old.id == entry.id && old.mode == entry.mode => entries.push(old),
+                    _ => {
+                        changed.push(path.clone());
+                        entries.push(IndexEntry::new(path, entry.mode, entry.id));
+                    }
+                }
+            }
+        }
+        changed.extend(old.into_keys());
+        entries.sort_by(|a, b| a.key().cmp(&b.key()));
+        self.entries = entries;
+        for path in changed {
+            self.changed(&path);
+        }
+        Ok(())
+    }
+
+    fn find(&self, path: &str, stage: u8) -> Result<usize, usize> {
+        self.entries.binary_search_by(|entry| entry.key().cmp(&(path, stage)))
+    }
+
+    /// 路径变化后缓存树不再有效，未跟踪文件缓存中所在的目录需要重新读取
+    fn changed(&mut self, path: &str) {
+        self.extensions.retain(|extension| &extension.signature != CACHE_TREE);
+        if let Some(untracked) = &mut self.untracked {
+            untracked.invalidate(path);
+        }
+    }
+}
+
+fn corrupt(msg: &str) -> MonoError {
+    MonoError::storage(format!("corrupt index: {}", msg))
+}
+
+/// 按字节读取索引内容，越界时返回错误
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Reader<'a> {
+        Reader { data, pos: 0 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+
+    fn take(&mut self, n: usize) -> MonoResult<&'a [u8]> {
+        let end = self.pos.checked_add(n).filter(|&end| end <= self.data.len()).ok_or_else(|| corrupt("truncated data"))?;
+        let data = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(data)
+    }
+
+    fn u16(&mut self) -> MonoResult<u16> {
+        Ok(u16::from_be_bytes(self.take(2)?.try_into().expect("two bytes")))
+    }
+
+    fn u32(&mut self) -> MonoResult<u32> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into().expect("four bytes")))
+    }
+
+    fn varint(&mut self) -> MonoResult<usize> {
+        read_offset(self.data, &mut self.pos).ok_or_else(|| corrupt("bad varint"))
+    }
+
+    /// 以 NUL 结尾的字节串，不含 NUL
+    fn cstr(&mut self) -> MonoResult<&'a [u8]> {
+        let s = self.cstr_peek()?;
+        self.pos += 1;
+        Ok(s)
+    }
+
+    /// 同 [`Reader::cstr`]，但停在 NUL 上
+    fn cstr_peek(&mut self) -> MonoResult<&'a [u8]> {
+        let len = self.data[self.pos.min(self.data.len())..]
+            .iter()
+            .position(|&byte| byte == 0)
+            .ok_or_else(|| corrupt("unterminated string"))?;
+        self.take(len)
+    }
+
+    fn id(&mut self, format: ObjectFormat) -> MonoResult<ObjectId> {
+        ObjectId::from_bytes(self.take(format.id_len())?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::ObjectType;
+
+    fn blob(data: &str) -> ObjectId {
+        ObjectFormat::Sha1.hash_object(ObjectType::Blob, data.as_bytes())
+    }
+
+    fn sample() -> Index {
+        let mut index = Index::new(2);
+        let mut entry = IndexEntry::new("src/lib.rs", FileMode::BLOB, blob("lib"));
+        entry.stat.mtime = 1_700_000_000;
+        entry.stat.size = 3;
+        index.add(entry);
+        index.add(IndexEntry::new("bin/run", FileMode::EXECUTABLE, blob("run")));
+        let mut sparse = IndexEntry::new("docs/guide.md", FileMode::BLOB, blob("guide"));
+        sparse.skip_worktree = true;
+        index.add(sparse);
+        index.add(IndexEntry::new(format!("deep/{}", "x".repeat(5000)), FileMode::BLOB, blob("x")));
+        for stage in 1..=3 {
+            let mut conflict = IndexEntry::new("src/main.rs", FileMode::BLOB, blob(&stage.to_string()));
+            conflict.stage = stage;
+            index.add(conflict);
+        }
+        index
+    }
+
+    /// 测试各版本的读写、条目顺序、保留与丢弃的扩展以及校验和
+    #[test]
+    fn test_read_write() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(INDEX_FILE);
+        let mut index = sample();
+        let paths: Vec<(&str, u8)> = index.entries().iter().map(IndexEntry::key).collect();
+        assert_eq!(paths[0], ("bin/run", 0));
+        assert_eq!(&paths[4..], [("src/main.rs", 1), ("src/main.rs", 2), ("src/main.rs", 3)]);
+
+        index.extensions.push(Extension {
+            signature: *CACHE_TREE,
+            data: b"cached".to_vec(),
+        });
+        for version in 2..=4 {
+            let config = IndexConfig {
+                version,
+                ..IndexConfig::default()
+            };
+            index.write(&path, ObjectFormat::Sha1, &config).unwrap();
+            let read = Index::read(&path, ObjectFormat::Sha1).unwrap();
+            // 版本 2 不能表示 skip-worktree，自动升级为版本 3
+            assert_eq!(read.version(), version.max(3));
+            assert_eq!(read.entries(), index.entries());
+            assert_eq!(read.extensions, index.extensions);
+        }
+
+        // 修改条目后丢弃缓存树，冲突条目被正常条目替换
+        index.add(IndexEntry::new("src/main.rs", FileMode::BLOB, blob("resolved")));
+        assert!(index.extensions.is_empty());
+        assert!(index.get("src/main.rs", 1).is_none());
+        assert_eq!(index.get("src/main.rs", 0).unwrap().id, blob("resolved"));
+        assert!(index.remove("bin/run") && !index.remove("bin/run"));
+
+        let mut data = std::fs::read(&path).unwrap();
+        data[20] ^= 1;
+        std::fs::write(&path, &data).unwrap();
+        assert!(Index::read(&path, ObjectFormat::Sha1).is_err());
+    }
+
+    /// 测试读取 git 写入的版本 2 索引
+    #[test]
+    fn test_git_format() {
+        let id = blob("hello\n");
+        let mut data = Vec::new();
+        data.extend_from_slice(b"DIRC\0\0\0\x02\0\0\0\x01");
+        data.extend_from_slice(&[0; 24]);
+        data.extend_from_slice(&0o100644u32.to_be_bytes());
+        data.extend_from_slice(&[0; 8]);
+        data.extend_from_slice(&6u32.to_be_bytes());
+        data.extend_from_slice(id.as_bytes());
+        data.extend_from_slice(&5u16.to_be_bytes());
+        data.extend_from_slice(b"a.txt\0\0\0\0\0");
+        assert_eq!(data.len(), 12 + 72);
+        let checksum = ObjectFormat::Sha1.digest(&data);
+        data.extend_from_slice(checksum.as_bytes());
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(INDEX_FILE);
+        std::fs::write(&path, &data).unwrap();
+        let mut index = Index::read(&path, ObjectFormat::Sha1).unwrap();
+        assert_eq!(index.entries(), [IndexEntry {
+            stat: StatData {
+                size: 6,
+                ..StatData::default()
+            },
+            ..IndexEntry::new("a.txt", FileMode::BLOB, id)
+        }]);
+        index.write(&path, ObjectFormat::Sha1, &IndexConfig::default()).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), data);
+    }
+}
@@ -0,0 +1,297 @@
+//! 拆分索引
+//!
+//! 几百万条目的索引每次更新都重写整个文件代价很高。拆分后大部分条目写入很少变化的共享索引
+//! `.mono/sharedindex.<校验和>`，`.mono/index` 只记录相对共享索引的变化。`link` 扩展的内容为：
+//!
+//! ```text
+//! 共享索引的校验和 | 删除位图 | 替换位图
+//! ```
+//!
+//! 两个位图都是 EWAH 编码（见 [`crate::graph::ewah`]），第 n 位对应共享索引的第 n 个条目。
+//! `.mono/index` 中依次是按替换位图顺序替换共享条目的条目（路径为空，沿用共享条目的路径）
+//! 与新增的条目。变化的条目超过共享索引条目数的 `[index] max_percent_change` 时重写共享索引。
+
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use super::{corrupt, IndexEntry, IndexFile, Reader};
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::graph::ewah::Bitset;
+use crate::object::{ObjectFormat, ObjectId};
+
+/// 共享索引文件名的前缀，后接共享索引的校验和
+pub const SHARED_PREFIX: &str = "sharedindex.";
+
+pub(super) const LINK: &[u8; 4] = b"link";
+
+/// 共享索引
+#[derive(Debug, Clone)]
+pub(super) struct SharedIndex {
+    /// 共享索引文件的校验和
+    pub(super) id: ObjectId,
+    pub(super) entries: Vec<IndexEntry>,
+}
+
+impl SharedIndex {
+    fn path(dir: &Path, id: &ObjectId) -> PathBuf {
+        dir.join(format!("{}{}", SHARED_PREFIX, id.to_hex()))
+    }
+
+    pub(super) fn exists(&self, dir: &Path) -> bool {
+        SharedIndex::path(dir, &self.id).is_file()
+    }
+
+    pub(super) fn read(dir: &Path, id: &ObjectId, format: ObjectFormat) -> MonoResult<SharedIndex> {
+        let data = match std::fs::read(SharedIndex::path(dir, id)) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Err(MonoError::storage(format!("shared index {} is missing", id)));
+            }
+            Err(e) => return Err(e.into()),
+        };
+        if !data.ends_with(id.as_bytes()) {
+            return Err(corrupt("shared index checksum does not match its name"));
+        }
+        let file = IndexFile::parse(&data, format)?;
+        if file.extension(LINK).is_some() {
+            return Err(corrupt("shared index links to another shared index"));
+        }
+        Ok(SharedIndex {
+            id: *id,
+            entries: file.entries,
+        })
+    }
+
+    /// 把全部条目写入新的共享索引
+    pub(super) fn write(dir: &Path, version: u32, entries: Vec<IndexEntry>, format: ObjectFormat) -> MonoResult<SharedIndex> {
+        let file = IndexFile {
+            version,
+            entries,
+            extensions: Vec::new(),
+        };
+        let data = file.encode(format);
+        let id = ObjectId::from_bytes(&data[data.len() - format.id_len()..])?;
+        let path = SharedIndex::path(dir, &id);
+        if !path.is_file() {
+            let tmp = path.with_extension("tmp");
+            std::fs::write(&tmp, &data)?;
+            std::fs::rename(&tmp, &path)?;
+        }
+        Ok(SharedIndex {
+            id,
+            entries: file.entries,
+        })
+    }
+}
+
+/// `link` 扩展
+#[derive(Debug, Clone)]
+pub(super) struct Link {
+    pub(super) base: ObjectId,
+    delete: Bitset,
+    replace: Bitset,
+    /// 共享索引的条目数，即位图的长度
+    len: usize,
+}
+
+impl Link {
+    /// 不含任何变化的链接
+    pub(super) fn new(base: ObjectId, len: usize) -> Link {
+        Link {
+            base,
+            delete: Bitset::new(),
+            replace: Bitset::new(),
+            len,
+        }
+    }
+
+    pub(super) fn parse(data: &[u8], format: ObjectFormat) -> MonoResult<Link> {
+        let mut reader = Reader::new(data);
+        let base = reader.id(format)?;
+        // git 在没有变化时可能省略两个位图
+        if reader.is_empty() {
+            return Ok(Link::new(base, 0));
+        }
+        let (delete, used) = Bitset::decode(&data[reader.pos..])?;
+        reader.take(used)?;
+        let (replace, used) = Bitset::decode(&data[reader.pos..])?;
+        reader.take(used)?;
+        if !reader.is_empty() {
+            return Err(corrupt("trailing data in link extension"));
+        }
+        Ok(Link {
+            base,
+            delete,
+            replace,
+            len: 0,
+        })
+    }
+
+    pub(super) fn encode(&self) -> Vec<u8> {
+        let mut out = self.base.as_bytes().to_vec();
+        out.extend_from_slice(&self.delete.encode(self.len));
+        out.extend_from_slice(&self.replace.encode(self.len));
+        out
+    }
+}
+
+/// 按 `link` 扩展把拆分索引中的条目与共享索引合并
+pub(super) fn merge(base: &SharedIndex, link: &Link, entries: Vec<IndexEntry>) -> MonoResult<Vec<IndexEntry>> {
+    let mut merged: Vec<Option<IndexEntry>> = base.entries.iter().cloned().map(Some).collect();
+    let mut entries = entries.into_iter();
+    for pos in link.replace.ones() {
+        let mut entry = entries.next().ok_or_else(|| corrupt("missing replacement entry"))?;
+        let old = merged
+            .get(pos)
+            .and_then(Option::as_ref)
+            .ok_or_else(|| corrupt("replace bitmap out of range"))?;
+        if !entry.path.is_empty() {
+            return Err(corrupt("replacement entry has a path"));
+        }
+        entry.path = old.path.clone();
+        merged[pos] = Some(entry);
+    }
+    for pos in link.delete.ones() {
+        *merged.get_mut(pos).ok_or_else(|| corrupt("delete bitmap out of range"))? = None;
+    }
+    // 新增的条目与共享条目路径相同时替换共享条目
+    let sorted: BTreeMap<(String, u8), IndexEntry> = merged
+        .into_iter()
+        .flatten()
+        .chain(entries)
+        .map(|entry| ((entry.path.clone(), entry.stage), entry))
+        .collect();
+    Ok(sorted.into_values().collect())
+}
+
+/// 计算 `entries` 相对共享索引的变化，变化超过 `max_percent_change` 时返回 None
+pub(super) fn diff(base: &SharedIndex, entries: &[IndexEntry], max_percent_change: u32) -> Option<(Link, Vec<IndexEntry>)> {
+    let mut link = Link::new(base.id, base.entries.len());
+    let mut replaced = Vec::new();
+    let mut added = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < base.entries.len() || j < entries.len() {
+        let order = match (base.entries.get(i), entries.get(j)) {
+            (Some(old), Some(new)) => old.key().cmp(&new.key()),
+            (Some(_), None) => Ordering::Less,
+            (None, _) => Ordering::Greater,
+        };
+        match order {
+            Ordering::Less => {
+                link.delete.set(i);
+                i += 1;
+            }
+            Ordering::Greater => {
+                added.push(entries[j].clone());
+                j += 1;
+            }
+            Ordering::Equal => {
+                if base.entries[i] != entries[j] {
+                    link.replace.set(i);
+                    replaced.push(IndexEntry {
+                        path: String::new(),
+                        ..entries[j].clone()
+                    });
+                }
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+    let changes = link.delete.count() + replaced.len() + added.len();
+    if changes * 100 > base.entries.len() * max_percent_change as usize {
+        return None;
+    }
+    replaced.extend(added);
+    Some((link, replaced))
+}
+
+/// 删除 `keep` 以外的共享索引
+pub(super) fn remove_unused(dir: &Path, keep: Option<&ObjectId>) -> MonoResult<()> {
+    let keep = keep.map(|id| format!("{}{}", SHARED_PREFIX, id.to_hex()));
+    for entry in std::fs::read_dir(dir)? {
+        let name = entry?.file_name();
+        let Some(name) = name.to_str() else {
+            continue;
+        };
+        if name.starts_with(SHARED_PREFIX) && Some(name) != keep.as_deref() {
+            match std::fs::remove_file(dir.join(name)) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::Index;
+    use super::*;
+    use crate::common::config::IndexConfig;
+    use crate::object::tree::FileMode;
+    use crate::object::ObjectType;
+
+    fn entry(i: usize, content: &str) -> IndexEntry {
+        let id = ObjectFormat::Sha1.hash_object(ObjectType::Blob, content.as_bytes());
+        IndexEntry::new(format!("dir{}/file{}.rs", i % 7, i), FileMode::BLOB, id)
+    }
+
+    fn shared_files(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .filter(|name| name.starts_with(SHARED_PREFIX))
+            .collect();
+        names.sort();
+        names
+    }
+
+    /// 测试拆分索引只写入变化，变化过多时重写共享索引
+    #[test]
+    fn test_split_index() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(super::super::INDEX_FILE);
+        let config = IndexConfig {
+            version: 4,
+            split: true,
+            ..IndexConfig::default()
+        };
+        let mut index = Index::new(4);
+        for i in 0..100 {
+            index.add(entry(i, "v1"));
+        }
+        index.write(&path, ObjectFormat::Sha1, &config).unwrap();
+        let shared = shared_files(dir.path());
+        assert_eq!(shared.len(), 1);
+        let full_size = std::fs::metadata(dir.path().join(&shared[0])).unwrap().len();
+
+        // 替换、删除与新增各一个条目，共享索引不变
+        index.add(entry(3, "v2"));
+        index.remove(&entry(4, "").path);
+        index.add(entry(100, "v1"));
+        index.write(&path, ObjectFormat::Sha1, &config).unwrap();
+        assert_eq!(shared_files(dir.path()), shared);
+        assert!(std::fs::metadata(&path).unwrap().len() * 10 < full_size);
+        let read = Index::read(&path, ObjectFormat::Sha1).unwrap();
+        assert_eq!(read.entries(), index.entries());
+
+        // 变化超过 20% 时重写共享索引并删除旧的
+        let mut index = read;
+        for i in 0..30 {
+            index.add(entry(i, "v3"));
+        }
+        index.write(&path, ObjectFormat::Sha1, &config).unwrap();
+        let rewritten = shared_files(dir.path());
+        assert_eq!(rewritten.len(), 1);
+        assert_ne!(rewritten, shared);
+        assert_eq!(Index::read(&path, ObjectFormat::Sha1).unwrap().entries(), index.entries());
+
+        // 取消拆分后删除共享索引
+        index.write(&path, ObjectFormat::Sha1, &IndexConfig::default()).unwrap();
+        assert!(shared_files(dir.path()).is_empty());
+        assert_eq!(Index::read(&path, ObjectFormat::Sha1).unwrap().entries(), index.entries());
+    }
+}
@@ -0,0 +1,329 @@
+//! 未跟踪文件缓存
+//!
+//! `UNTR` 扩展记录上次查找未跟踪文件时每个目录的结果，目录未变化时可以直接沿用，不必重新
+//! 读取目录。格式与 git 相同：
+//!
+//! ```text
+//! 环境描述的长度（变长整数） | 环境描述
+//! $GIT_DIR/info/exclude 的文件状态 | core.excludesFile 的文件状态 | 目录标志（4 字节）
+//! $GIT_DIR/info/exclude 的哈希 | core.excludesFile 的哈希 | 目录中排除规则文件的文件名（以 NUL 结尾）
+//! 目录块数（变长整数），为 0 时扩展到此结束
+//! 目录块 × N（深度优先）：未跟踪条目数 | 子目录数 | 目录名 | 未跟踪条目 × 未跟踪条目数
+//! 有效位图 | 只检查位图 | 排除规则文件位图（EWAH）
+//! 目录的文件状态 × 有效位图中的 1 | 排除规则文件的哈希 × 排除规则文件位图中的 1 | NUL
+//! ```
+//!
+//! 文件状态为不含文件模式的 36 字节，全局排除规则文件的哈希全零表示文件不存在。
+
+use super::{corrupt, Reader, StatData};
+use crate::common::MonoResult;
+use crate::graph::ewah::Bitset;
+use crate::object::{ObjectFormat, ObjectId};
+use crate::pack::encode_offset;
+
+pub(super) const SIGNATURE: &[u8; 4] = b"UNTR";
+
+/// 未跟踪的目录也作为条目列出，此时路径变化会影响祖先目录的结果
+const DIR_SHOW_OTHER_DIRECTORIES: u32 = 1 << 1;
+
+/// 排除规则文件的状态与哈希
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExcludeStat {
+    pub stat: StatData,
+    /// 文件内容的 blob 哈希，全零表示文件不存在
+    pub id: ObjectId,
+}
+
+/// 一个目录的缓存结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UntrackedDir {
+    /// 目录名，根目录为空
+    pub name: String,
+    /// 目录中未跟踪的文件与目录（目录以 `/` 结尾）
+    pub untracked: Vec<String>,
+    pub dirs: Vec<UntrackedDir>,
+    /// 缓存的结果是否有效
+    pub valid: bool,
+    /// 只检查了目录中是否有未跟踪文件
+    pub check_only: bool,
+    /// 读取目录时目录的文件状态，只在结果有效时记录
+    pub stat: StatData,
+    /// 目录中排除规则文件的 blob 哈希，文件不存在时为 None
+    pub exclude_id: Option<ObjectId>,
+}
+
+impl UntrackedDir {
+    pub fn new(name: impl Into<String>) -> UntrackedDir {
+        UntrackedDir {
+            name: name.into(),
+            untracked: Vec::new(),
+            dirs: Vec::new(),
+            valid: false,
+            check_only: false,
+            stat: StatData::default(),
+            exclude_id: None,
+        }
+    }
+
+    fn invalidate(&mut self) {
+        self.valid = false;
+        self.check_only = false;
+        self.stat = StatData::default();
+        self.untracked.clear();
+    }
+
+    /// 使 `path` 所在目录的结果失效，返回祖先目录是否也需要失效
+    fn invalidate_path(&mut self, path: &str, show_dirs: bool) -> bool {
+        let Some((name, rest)) = path.split_once('/') else {
+            self.invalidate();
+            return show_dirs;
+        };
+        let propagate = match self.dirs.iter_mut().find(|dir| dir.name == name) {
+            Some(dir) => dir.invalidate_path(rest, show_dirs),
+            // 子目录没有缓存，本目录中它的未跟踪条目也可能变化
+            None => true,
+        };
+        if propagate {
+            self.invalidate();
+        }
+        propagate && show_dirs
+    }
+
+    /// 深度优先访问全部目录
+    fn visit<'a>(&'a self, out: &mut Vec<&'a UntrackedDir>) {
+        out.push(self);
+        for dir in &self.dirs {
+            dir.visit(out);
+        }
+    }
+
+    fn visit_mut(&mut self, f: &mut impl FnMut(&mut UntrackedDir)) {
+        f(self);
+        for dir in &mut self.dirs {
+            dir.visit_mut(f);
+        }
+    }
+
+    fn read(reader: &mut Reader, depth: usize) -> MonoResult<UntrackedDir> {
+        if depth > 4096 {
+            return Err(corrupt("untracked cache is too deep"));
+        }
+        let untracked_len = reader.varint()?;
+        let dirs_len = reader.varint()?;
+        let mut dir = UntrackedDir::new(string(reader.cstr()?)?);
+        for _ in 0..untracked_len {
+            dir.untracked.push(string(reader.cstr()?)?);
+        }
+        for _ in 0..dirs_len {
+            dir.dirs.push(UntrackedDir::read(reader, depth + 1)?);
+        }
+        Ok(dir)
+    }
+
+    fn write(&self, out: &mut Vec<u8>) {
+        // 无效的目录不写出未跟踪条目
+        let untracked = if self.valid { self.untracked.as_slice() } else { &[] };
+        out.extend_from_slice(&encode_offset(untracked.len() as u64));
+        out.extend_from_slice(&encode_offset(self.dirs.len() as u64));
+        for name in std::iter::once(&self.name).chain(untracked) {
+            out.extend_from_slice(name.as_bytes());
+            out.push(0);
+        }
+        for dir in &self.dirs {
+            dir.write(out);
+        }
+    }
+}
+
+/// 未跟踪文件缓存
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UntrackedCache {
+    /// 缓存适用的环境描述，由若干以 NUL 结尾的字符串组成
+    pub ident: Vec<u8>,
+    pub info_exclude: ExcludeStat,
+    pub excludes_file: ExcludeStat,
+    pub dir_flags: u32,
+    /// 目录中排除规则文件的文件名，通常为 `.gitignore`
+    pub exclude_per_dir: String,
+    pub root: Option<UntrackedDir>,
+}
+
+impl UntrackedCache {
+    /// 使 `path` 所在目录的缓存失效
+    pub fn invalidate(&mut self, path: &str) {
+        let show_dirs = self.dir_flags & DIR_SHOW_OTHER_DIRECTORIES != 0;
+        if let Some(root) = &mut self.root {
+            root.invalidate_path(path, show_dirs);
+        }
+    }
+
+    pub(super) fn parse(data: &[u8], format: ObjectFormat) -> MonoResult<UntrackedCache> {
+        let mut reader = Reader::new(data);
+        let len = reader.varint()?;
+        let ident = reader.take(len)?.to_vec();
+        let info_stat = StatData::read(&mut reader)?;
+        let excludes_stat = StatData::read(&mut reader)?;
+        let dir_flags = reader.u32()?;
+        let info_exclude = ExcludeStat {
+            stat: info_stat,
+            id: reader.id(format)?,
+        };
+        let excludes_file = ExcludeStat {
+            stat: excludes_stat,
+            id: reader.id(format)?,
+        };
+        let exclude_per_dir = string(reader.cstr()?)?;
+        let mut cache = UntrackedCache {
+            ident,
+            info_exclude,
+            excludes_file,
+            dir_flags,
+            exclude_per_dir,
+            root: None,
+        };
+        let count = reader.varint()?;
+        if count == 0 {
+            return Ok(cache);
+        }
+
+        let mut root = UntrackedDir::read(&mut reader, 0)?;
+        let mut dirs = Vec::new();
+        root.visit(&mut dirs);
+        if dirs.len() != count {
+            return Err(corrupt("untracked cache directory count mismatch"));
+        }
+        let mut bitmaps = Vec::new();
+        for _ in 0..3 {
+            let (bitmap, used) = Bitset::decode(&data[reader.pos..])?;
+            reader.take(used)?;
+            bitmaps.push(bitmap);
+        }
+        if bitmaps.iter().any(|bitmap| bitmap.ones().any(|i| i >= count)) {
+            return Err(corrupt("untracked cache bitmap out of range"));
+        }
+        let mut stats = Vec::new();
+        for _ in bitmaps[0].ones() {
+            stats.push(StatData::read(&mut reader)?);
+        }
+        let mut ids = Vec::new();
+        for _ in bitmaps[2].ones() {
+            ids.push(reader.id(format)?);
+        }
+        if reader.take(1)? != [0] || !reader.is_empty() {
+            return Err(corrupt("bad untracked cache trailer"));
+        }
+
+        let mut i = 0;
+        let mut stats = stats.into_iter();
+        let mut ids = ids.into_iter();
+        root.visit_mut(&mut |dir| {
+            dir.valid = bitmaps[0].get(i);
+            dir.check_only = bitmaps[1].get(i);
+            if dir.valid {
+                dir.stat = stats.next().expect("one stat per valid directory");
+            }
+            dir.exclude_id = bitmaps[2].get(i).then(|| ids.next().expect("one id per bit"));
+            i += 1;
+        });
+        cache.root = Some(root);
+        Ok(cache)
+    }
+
+    pub(super) fn encode(&self) -> Vec<u8> {
+        let mut out = encode_offset(self.ident.len() as u64);
+        out.extend_from_slice(&self.ident);
+        self.info_exclude.stat.write(&mut out);
+        self.excludes_file.stat.write(&mut out);
+        out.extend_from_slice(&self.dir_flags.to_be_bytes());
+        out.extend_from_slice(self.info_exclude.id.as_bytes());
+        out.extend_from_slice(self.excludes_file.id.as_bytes());
+        out.extend_from_slice(self.exclude_per_dir.as_bytes());
+        out.push(0);
+        let Some(root) = &self.root else {
+            out.extend_from_slice(&encode_offset(0));
+            return out;
+        };
+
+        let mut dirs = Vec::new();
+        root.visit(&mut dirs);
+        out.extend_from_slice(&encode_offset(dirs.len() as u64));
+        root.write(&mut out);
+        let mut valid = Bitset::new();
+        let mut check_only = Bitset::new();
+        let mut with_exclude = Bitset::new();
+        let mut stats = Vec::new();
+        let mut ids = Vec::new();
+        for (i, dir) in dirs.iter().enumerate() {
+            if dir.valid {
+                valid.set(i);
+                dir.stat.write(&mut stats);
+            }
+            if dir.check_only {
+                check_only.set(i);
+            }
+            if let Some(id) = dir.exclude_id {
+                with_exclude.set(i);
+                ids.extend_from_slice(id.as_bytes());
+            }
+        }
+        for bitmap in [valid, check_only, with_exclude] {
+            out.extend_from_slice(&bitmap.encode(dirs.len()));
+        }
+        out.extend_from_slice(&stats);
+        out.extend_from_slice(&ids);
+        out.push(0);
+        out
+    }
+}
+
+fn string(data: &[u8]) -> MonoResult<String> {
+    String::from_utf8(data.to_vec()).map_err(|_| corrupt("path is not UTF-8"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 测试编码往返与按路径失效
+    #[test]
+    fn test_untracked_cache() {
+        let format = ObjectFormat::Sha1;
+        let mut src = UntrackedDir::new("src");
+        src.valid = true;
+        src.untracked = vec!["scratch.rs".to_string()];
+        src.stat.mtime = 42;
+        src.exclude_id = Some(format.digest(b"*.o\n"));
+        let mut docs = UntrackedDir::new("docs");
+        docs.valid = true;
+        docs.check_only = true;
+        let mut root = UntrackedDir::new("");
+        root.valid = true;
+        root.untracked = vec!["build/".to_string(), "notes.txt".to_string()];
+        root.dirs = vec![src, docs];
+        let mut cache = UntrackedCache {
+            ident: b"location /repo, system Linux\0".to_vec(),
+            info_exclude: ExcludeStat {
+                stat: StatData::default(),
+                id: format.zero(),
+            },
+            excludes_file: ExcludeStat {
+                stat: StatData::default(),
+                id: format.zero(),
+            },
+            dir_flags: DIR_SHOW_OTHER_DIRECTORIES,
+            exclude_per_dir: ".gitignore".to_string(),
+            root: Some(root),
+        };
+        assert_eq!(UntrackedCache::parse(&cache.encode(), format).unwrap(), cache);
+
+        // 显示未跟踪目录时，子目录中的变化使根目录也失效，兄弟目录不受影响
+        cache.invalidate("src/new.rs");
+        let root = cache.root.as_ref().unwrap();
+        assert!(!root.valid && root.untracked.is_empty());
+        assert!(!root.dirs[0].valid && root.dirs[1].valid);
+        assert_eq!(UntrackedCache::parse(&cache.encode(), format).unwrap(), cache);
+
+        cache.root = None;
+        assert_eq!(UntrackedCache::parse(&cache.encode(), format).unwrap(), cache);
+    }
+}
@@ -0,0 +1,337 @@
+//! 引用更新的预写日志（WAL）
+//!
+//! 文件引用数据库逐个文件写入引用，进程在一次多引用更新（例如合并队列合入的一批分支）中途崩溃时
+//! 会留下只改了一部分的引用。[`JournaledRefStore`] 在改动任何引用之前，先把整个事务——每个引用的
+//! 旧值与新值——写入 `.mono/journal/<事务 ID>.json` 并落盘，全部引用写完后删除该记录，写入记录即
+//! 提交点。事务开始前还会检查新值指向的对象都已写入对象存储，引用不会指向不存在的对象。
+//!
+//! 打开仓库时若发现遗留的记录，说明上次事务没有完成：新值指向的对象都还在对象存储中时重新应用
+//! 全部新值（前滚），否则把已经改动的引用恢复为旧值（回滚），两种情况都不会留下部分更新。
+//! 前滚的修改以 `journal recovery` 为原因补记引用日志。
+//!
+//! 事务之间通过 `.mono/journal/lock` 上的文件锁跨进程互斥，持有锁的进程退出后锁自动释放。
+//! PostgreSQL 引用数据库在数据库事务中更新，不使用该日志。
+
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::audit;
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::object::ObjectId;
+use crate::reflog::{Reflog, ReflogEntry};
+use crate::refs::{self, RefStore, RefTarget, RefUpdate};
+use crate::storage::ObjectStore;
+
+/// 日志目录，相对于 `.mono`
+pub const JOURNAL_DIR: &str = "journal";
+/// 事务之间互斥的锁文件
+const LOCK_FILE: &str = "lock";
+/// 前滚时记入引用日志的原因
+const RECOVERY_REASON: &str = "journal recovery";
+
+/// 一条事务记录
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+struct Transaction {
+    id: String,
+    updates: Vec<RefUpdate>,
+}
+
+/// 遗留事务的处理结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Recovery {
+    /// 重新应用了全部新值
+    RolledForward,
+    /// 缺少新值指向的对象，已改动的引用恢复为旧值
+    RolledBack,
+}
+
+/// 仓库的引用事务日志
+#[derive(Debug, Clone)]
+pub struct Journal {
+    dir: PathBuf,
+    mono_dir: PathBuf,
+}
+
+impl Journal {
+    pub fn new(mono_dir: &Path) -> Journal {
+        Journal {
+            dir: mono_dir.join(JOURNAL_DIR),
+            mono_dir: mono_dir.to_path_buf(),
+        }
+    }
+
+    /// 获取跨进程的事务锁，返回的文件关闭时释放
+    fn lock(&self) -> MonoResult<File> {
+        std::fs::create_dir_all(&self.dir)?;
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(self.dir.join(LOCK_FILE))?;
+        file.lock()?;
+        Ok(file)
+    }
+
+    /// 遗留的事务记录，按事务 ID 排序
+    fn pending(&self) -> MonoResult<Vec<PathBuf>> {
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut paths = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "json") {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+        Ok(paths)
+    }
+
+    /// 写入事务记录并落盘
+    fn begin(&self, updates: &[RefUpdate]) -> MonoResult<PathBuf> {
+        let now = chrono::Utc::now();
+        let transaction = Transaction {
+            id: format!("{}-{}", now.timestamp_nanos_opt().unwrap_or_default(), std::process::id()),
+            updates: updates.to_vec(),
+        };
+        let data = serde_json::to_vec_pretty(&transaction).map_err(|e| MonoError::storage(e.to_string()))?;
+        let path = self.dir.join(format!("{}.json", transaction.id));
+        let tmp = path.with_extension("json.tmp");
+        let mut file = File::create(&tmp)?;
+        file.write_all(&data)?;
+        file.sync_all()?;
+        std::fs::rename(&tmp, &path)?;
+        // 目录项也要落盘，否则崩溃后记录可能消失；不是所有平台都能打开目录
+        if let Ok(dir) = File::open(&self.dir) {
+            let _ = dir.sync_all();
+        }
+        Ok(path)
+    }
+
+    /// 处理遗留的事务，返回每个事务的 ID 与处理结果；调用方需持有事务锁
+    fn recover_locked(&self, refs: &dyn RefStore, objects: &dyn ObjectStore) -> MonoResult<Vec<(String, Recovery)>> {
+        let mut recovered = Vec::new();
+        for path in self.pending()? {
+            let data = std::fs::read(&path)?;
+            let transaction: Transaction = serde_json::from_slice(&data)
+                .map_err(|e| MonoError::storage(format!("corrupt journal entry {}: {}", path.display(), e)))?;
+            let mut complete = true;
+            for update in transaction.updates.iter().filter(|update| !update.is_delete()) {
+                if !objects.contains(&update.new)? {
+                    complete = false;
+                    break;
+                }
+            }
+            let recovery = if complete {
+                self.roll_forward(refs, &transaction.updates)?;
+                Recovery::RolledForward
+            } else {
+                roll_back(refs, &transaction.updates)?;
+                Recovery::RolledBack
+            };
+            tracing::warn!(transaction = %transaction.id, ?recovery, "recovered interrupted ref transaction");
+            std::fs::remove_file(&path)?;
+            recovered.push((transaction.id, recovery));
+        }
+        Ok(recovered)
+    }
+
+    /// 处理遗留的事务，没有遗留记录时不获取锁
+    pub fn recover(&self, refs: &dyn RefStore, objects: &dyn ObjectStore) -> MonoResult<Vec<(String, Recovery)>> {
+        if self.pending()?.is_empty() {
+            return Ok(Vec::new());
+        }
+        let _lock = self.lock()?;
+        self.recover_locked(refs, objects)
+    }
+
+    /// 把每个引用置为新值并补记引用日志；中断的事务还没有记录任何日志
+    fn roll_forward(&self, refs: &dyn RefStore, updates: &[RefUpdate]) -> MonoResult<()> {
+        let log = Reflog::new(&self.mono_dir);
+        let actor = audit::local_actor();
+        for update in updates {
+            if current(refs, &update.name)? != Some(update.new).filter(|id| !id.is_zero()) {
+                set(refs, &update.name, &update.new)?;
+            }
+            let entry = ReflogEntry {
+                old: update.old,
+                new: update.new,
+                actor: actor.clone(),
+                time: chrono::Utc::now().timestamp(),
+                reason: RECOVERY_REASON.to_string(),
+            };
+            if let Err(e) = log.append(&update.name, &entry) {
+                tracing::warn!(name = %update.name, error = %e, "failed to write reflog");
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 引用的直接目标，符号引用与不存在的引用为 None
+fn current(refs: &dyn RefStore, name: &str) -> MonoResult<Option<ObjectId>> {
+    Ok(match refs.read(name)? {
+        Some(RefTarget::Direct(id)) => Some(id),
+        _ => None,
+    })
+}
+
+/// 把引用置为 `id`，全零表示删除
+fn set(refs: &dyn RefStore, name: &str, id: &ObjectId) -> MonoResult<()> {
+    if id.is_zero() {
+        refs.delete(name)
+    } else {
+        refs.write(name, id)
+    }
+}
+
+/// 把已经改为新值的引用恢复为旧值
+fn roll_back(refs: &dyn RefStore, updates: &[RefUpdate]) -> MonoResult<()> {
+    for update in updates {
+        let new = Some(update.new).filter(|id| !id.is_zero());
+        if current(refs, &update.name)? == new {
+            set(refs, &update.name, &update.old)?;
+        }
+    }
+    Ok(())
+}
+
+/// 通过预写日志原子地应用多引用更新的引用数据库
+#[derive(Debug)]
+pub struct JournaledRefStore {
+    inner: Arc<dyn RefStore>,
+    objects: Arc<dyn ObjectStore>,
+    journal: Journal,
+}
+
+impl JournaledRefStore {
+    /// 在 `inner` 之上启用日志，并处理上次遗留的事务
+    pub fn open(inner: Arc<dyn RefStore>, objects: Arc<dyn ObjectStore>, mono_dir: &Path) -> MonoResult<JournaledRefStore> {
+        let journal = Journal::new(mono_dir);
+        journal.recover(inner.as_ref(), objects.as_ref())?;
+        Ok(JournaledRefStore { inner, objects, journal })
+    }
+}
+
+impl RefStore for JournaledRefStore {
+    fn read(&self, name: &str) -> MonoResult<Option<RefTarget>> {
+        self.inner.read(name)
+    }
+
+    fn write(&self, name: &str, id: &ObjectId) -> MonoResult<()> {
+        self.inner.write(name, id)
+    }
+
+    fn write_symbolic(&self, name: &str, target: &str) -> MonoResult<()> {
+        self.inner.write_symbolic(name, target)
+    }
+
+    fn delete(&self, name: &str) -> MonoResult<()> {
+        self.inner.delete(name)
+    }
+
+    fn list(&self, prefix: &str) -> MonoResult<Vec<(String, ObjectId)>> {
+        self.inner.list(prefix)
+    }
+
+    /// 校验旧值与新值指向的对象后写入事务记录，再逐个改动引用；改动失败时立即回滚
+    fn update(&self, updates: &[RefUpdate]) -> MonoResult<()> {
+        let _lock = self.journal.lock()?;
+        // 其他进程可能在持有锁时崩溃
+        self.journal.recover_locked(self.inner.as_ref(), self.objects.as_ref())?;
+        for update in updates {
+            refs::check_name(&update.name)?;
+            let current = self.inner.resolve(&update.name)?;
+            if !update.expects(current.as_ref()) {
+                return Err(refs::stale_ref_error(update, current.as_ref()));
+            }
+            if !update.is_delete() && !self.objects.contains(&update.new)? {
+                return Err(MonoError::not_found(format!("object {} for {}", update.new, update.name)));
+            }
+        }
+        if updates.len() < 2 {
+            // 单个引用的写入本身是原子的
+            return self.inner.update(updates);
+        }
+        let record = self.journal.begin(updates)?;
+        if let Err(e) = self.inner.update(updates) {
+            // 回滚失败时保留记录，下次打开仓库时再处理
+            roll_back(self.inner.as_ref(), updates)?;
+            std::fs::remove_file(&record)?;
+            return Err(e);
+        }
+        std::fs::remove_file(&record)?;
+        Ok(())
+    }
+
+    /// 获取事务锁即等到进行中的事务写完，再处理其他进程遗留的记录
+    fn flush(&self) -> MonoResult<()> {
+        let _lock = self.journal.lock()?;
+        self.journal.recover_locked(self.inner.as_ref(), self.objects.as_ref())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::ObjectType;
+    use crate::refs::FileRefStore;
+    use crate::storage::memory::MemoryStore;
+
+    /// 测试遗留的事务在对象齐全时前滚、缺少对象时回滚，新值指向不存在的对象时拒绝更新
+    #[test]
+    fn test_recovery() {
+        let dir = tempfile::tempdir().unwrap();
+        let refs: Arc<dyn RefStore> = Arc::new(FileRefStore::new(dir.path()));
+        let objects: Arc<dyn ObjectStore> = Arc::new(MemoryStore::new());
+        let old = objects.write(ObjectType::Blob, b"old").unwrap();
+        let new = objects.write(ObjectType::Blob, b"new").unwrap();
+        refs.write("refs/heads/a", &old).unwrap();
+        refs.write("refs/heads/b", &old).unwrap();
+        let update = |name: &str, old: ObjectId, new: ObjectId| RefUpdate {
+            name: name.to_string(),
+            old,
+            new,
+        };
+
+        // 记录写入后只改了第一个引用就崩溃
+        let journal = Journal::new(dir.path());
+        std::fs::create_dir_all(dir.path().join(JOURNAL_DIR)).unwrap();
+        let updates = vec![update("refs/heads/a", old, new), update("refs/heads/b", old, ObjectId::ZERO)];
+        journal.begin(&updates).unwrap();
+        refs.write("refs/heads/a", &new).unwrap();
+        let store = JournaledRefStore::open(refs.clone(), objects.clone(), dir.path()).unwrap();
+        assert_eq!(store.resolve("refs/heads/a").unwrap(), Some(new));
+        assert_eq!(store.resolve("refs/heads/b").unwrap(), None);
+        assert!(journal.pending().unwrap().is_empty());
+        assert_eq!(Reflog::new(dir.path()).read("refs/heads/b").unwrap()[0].reason, RECOVERY_REASON);
+
+        // 新值指向的对象丢失时恢复旧值
+        let missing = ObjectId::hash_object(ObjectType::Blob, b"missing");
+        journal
+            .begin(&[update("refs/heads/a", new, old), update("refs/heads/c", ObjectId::ZERO, missing)])
+            .unwrap();
+        refs.write("refs/heads/a", &old).unwrap();
+        refs.write("refs/heads/c", &missing).unwrap();
+        let recovered = journal.recover(refs.as_ref(), objects.as_ref()).unwrap();
+        assert_eq!(recovered[0].1, Recovery::RolledBack);
+        assert_eq!(store.resolve("refs/heads/a").unwrap(), Some(new));
+        assert_eq!(store.resolve("refs/heads/c").unwrap(), None);
+
+        let err = store.update(&[update("refs/heads/a", new, old), update("refs/heads/c", ObjectId::ZERO, missing)]);
+        assert!(err.is_err());
+        assert_eq!(store.resolve("refs/heads/a").unwrap(), Some(new));
+        store.update(&[update("refs/heads/a", new, old), update("refs/heads/b", ObjectId::ZERO, new)]).unwrap();
+        assert_eq!(store.list("refs/").unwrap().len(), 2);
+        assert!(journal.pending().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,189 @@
+//! LFS 对象的垃圾回收
+//!
+//! 从配置的引用出发遍历全部可达的 blob，解析其中的指针文件得到仍被引用的 LFS 对象；
+//! 其余对象在最后写入时间超过保留期后删除。git-lfs 推送时先上传对象再推送指针，
+//! 保留期保证这段时间内刚上传、尚未被引用的对象不会被误删。
+
+use std::collections::BTreeSet;
+use std::time::{Duration, SystemTime};
+
+use crate::common::config::LfsConfig;
+use crate::common::MonoResult;
+use crate::lfs::{LfsObject, LfsOid, Pointer, MAX_POINTER_SIZE};
+use crate::object::filter::ObjectFilter;
+use crate::object::walk::collect_objects;
+use crate::object::{ObjectId, ObjectType};
+use crate::repo::Repository;
+
+/// 垃圾回收参数
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GcOptions {
+    /// 扫描的引用前缀
+    pub refs: Vec<String>,
+    /// 未被引用的对象在最后写入后至少保留的时长
+    pub retention: Duration,
+    /// 只报告将要删除的对象，不实际删除
+    pub dry_run: bool,
+}
+
+impl GcOptions {
+    /// 使用仓库 `[lfs]` 配置段中的参数
+    pub fn from_config(config: &LfsConfig) -> GcOptions {
+        GcOptions {
+            refs: config.gc_refs.clone(),
+            retention: Duration::from_secs(config.retention_days * 24 * 60 * 60),
+            dry_run: false,
+        }
+    }
+}
+
+/// 垃圾回收的结果
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GcReport {
+    /// 存储中仍被引用的对象数
+    pub referenced: usize,
+    /// 未被引用但仍在保留期内的对象
+    pub retained: Vec<LfsObject>,
+    /// 已删除的对象，`dry_run` 时为将要删除的对象
+    pub deleted: Vec<LfsObject>,
+}
+
+impl GcReport {
+    /// 删除（或将要删除）的对象的总大小
+    pub fn deleted_size(&self) -> u64 {
+        self.deleted.iter().map(|object| object.size).sum()
+    }
+}
+
+/// 以 `prefixes` 开头的引用可达的指针文件所引用的 LFS 对象
+pub fn referenced_oids(repo: &Repository, prefixes: &[String]) -> MonoResult<BTreeSet<LfsOid>> {
+    let mut tips: Vec<ObjectId> = Vec::new();
+    for prefix in prefixes {
+        tips.extend(repo.refs().list(prefix)?.into_iter().map(|(_, id)| id));
+    }
+    tips.sort();
+    tips.dedup();
+
+    let objects = collect_objects(&tips, &[], &ObjectFilter::None, &mut |id: &ObjectId| repo.read_object(id))?;
+    let mut oids = BTreeSet::new();
+    for (id, object_type) in objects {
+        if object_type != ObjectType::Blob {
+            continue;
+        }
+        // 先读对象头跳过大文件；对象不在本地时（部分克隆）读取完整对象以免漏掉指针
+        if let Some((_, size)) = repo.objects().read_header(&id)? {
+            if size > MAX_POINTER_SIZE {
+                continue;
+            }
+        }
+        if let Some(pointer) = Pointer::parse(&repo.read_object(&id)?.data) {
+            oids.insert(pointer.oid);
+        }
+    }
+    tracing::debug!(tips = tips.len(), pointers = oids.len(), "scanned lfs pointers");
+    Ok(oids)
+}
+
+/// 删除未被引用且超过保留期的 LFS 对象
+pub fn gc(repo: &Repository, options: &GcOptions) -> MonoResult<GcReport> {
+    // 先列出对象再扫描引用：扫描期间新推送的指针所引用的对象不在列表中，或仍在保留期内
+    let objects = repo.objects().list_lfs()?;
+    let referenced = referenced_oids(repo, &options.refs)?;
+    let cutoff = SystemTime::now().checked_sub(options.retention).unwrap_or(SystemTime::UNIX_EPOCH);
+
+    let mut report = GcReport::default();
+    for object in objects {
+        if referenced.contains(&object.oid) {
+            report.referenced += 1;
+        } else if object.modified > cutoff {
+            report.retained.push(object);
+        } else {
+            if !options.dry_run {
+                repo.objects().delete_lfs(&object.oid)?;
+                tracing::info!(oid = %object.oid, size = object.size, "deleted unreferenced lfs object");
+            }
+            report.deleted.push(object);
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::refs::RefUpdate;
+    use crate::test_utils::{commit_files, init_repo};
+
+    fn store(repo: &Repository, data: &[u8]) -> Pointer {
+        let pointer = Pointer {
+            oid: LfsOid::hash(data),
+            size: data.len() as u64,
+        };
+        repo.objects().write_lfs(&pointer.oid, data).unwrap();
+        pointer
+    }
+
+    /// 测试被引用的对象保留，未被引用的对象按保留期删除，dry run 不删除
+    #[test]
+    fn test_gc() {
+        let (_dir, repo) = init_repo();
+        let kept = store(&repo, b"referenced from main");
+        let old = store(&repo, b"referenced from an old commit");
+        let tagged = store(&repo, b"referenced from a tag");
+        let orphan = store(&repo, b"uploaded but never pushed");
+
+        let first = commit_files(&repo, &[("big.bin", old.encode().as_bytes())], &[], "first");
+        let main = commit_files(
+            &repo,
+            &[("big.bin", kept.encode().as_bytes()), ("README", b"not a pointer")],
+            &[first],
+            "second",
+        );
+        let other = commit_files(&repo, &[("asset", tagged.encode().as_bytes())], &[], "other");
+        repo.refs()
+            .update(&[
+                RefUpdate {
+                    name: "refs/heads/main".to_string(),
+                    old: ObjectId::ZERO,
+                    new: main,
+                },
+                RefUpdate {
+                    name: "refs/tags/v1".to_string(),
+                    old: ObjectId::ZERO,
+                    new: other,
+                },
+            ])
+            .unwrap();
+
+        let referenced = referenced_oids(&repo, &["refs/heads/".to_string()]).unwrap();
+        assert_eq!(referenced, BTreeSet::from([kept.oid, old.oid]));
+
+        // 刚写入的对象仍在保留期内
+        let mut options = GcOptions::from_config(&LfsConfig::default());
+        let report = gc(&repo, &options).unwrap();
+        assert_eq!(report.referenced, 3);
+        assert_eq!(report.retained.iter().map(|o| o.oid).collect::<Vec<_>>(), [orphan.oid]);
+        assert!(report.deleted.is_empty());
+
+        options.retention = Duration::ZERO;
+        options.dry_run = true;
+        let report = gc(&repo, &options).unwrap();
+        assert_eq!(report.deleted.iter().map(|o| o.oid).collect::<Vec<_>>(), [orphan.oid]);
+        assert_eq!(report.deleted_size(), orphan.size);
+        assert!(repo.objects().read_lfs(&orphan.oid).unwrap().is_some());
+
+        // 只扫描分支时标签引用的对象也会被删除
+        options.dry_run = false;
+        options.refs = vec!["refs/heads/".to_string()];
+        let report = gc(&repo, &options).unwrap();
+        let mut deleted: Vec<LfsOid> = report.deleted.iter().map(|o| o.oid).collect();
+        deleted.sort();
+        let mut expected = vec![orphan.oid, tagged.oid];
+        expected.sort();
+        assert_eq!(deleted, expected);
+        let remaining: Vec<LfsOid> = repo.objects().list_lfs().unwrap().iter().map(|o| o.oid).collect();
+        let mut expected = vec![kept.oid, old.oid];
+        expected.sort();
+        assert_eq!(remaining, expected);
+    }
+}
@@ -0,0 +1,163 @@
+//! Git LFS 大文件对象
+//!
+//! LFS 对象不进入 git 对象库：仓库中只提交一个记录 SHA-256 与大小的指针文件，
+//! 内容由 LFS 服务端（见 [`crate::server::lfs`]）单独保存在 [`crate::storage::ObjectStore`] 中。
+//! 不再被任何指针引用的对象由 [`gc`] 清理。
+
+pub mod gc;
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::SystemTime;
+
+use sha2::{Digest, Sha256};
+
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+
+/// LFS 对象 ID 的字节长度
+pub const LFS_OID_LEN: usize = 32;
+
+/// 指针文件的长度上限，更大的 blob 不会被当作指针，与 git-lfs 一致
+pub const MAX_POINTER_SIZE: usize = 1024;
+
+/// 指针文件第一行声明的规范版本
+pub const POINTER_VERSION: &str = "https://git-lfs.github.com/spec/v1";
+
+/// LFS 对象 ID，即对象内容的 SHA-256
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct LfsOid([u8; LFS_OID_LEN]);
+
+impl LfsOid {
+    /// 计算内容的对象 ID
+    pub fn hash(data: &[u8]) -> LfsOid {
+        LfsOid(Sha256::digest(data).into())
+    }
+
+    /// 从十六进制字符串解析，只接受小写，与 git-lfs 的写法一致
+    pub fn from_hex(hex: &str) -> MonoResult<LfsOid> {
+        let invalid = || MonoError::usage(format!("invalid lfs oid: {}", hex));
+        if hex.len() != LFS_OID_LEN * 2 || !hex.bytes().all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b)) {
+            return Err(invalid());
+        }
+        let mut bytes = [0u8; LFS_OID_LEN];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).map_err(|_| invalid())?;
+        }
+        Ok(LfsOid(bytes))
+    }
+
+    /// 十六进制表示
+    pub fn to_hex(&self) -> String {
+        self.0.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    /// 按 git-lfs 的本地布局得到的相对路径 `ab/cd/abcd...`
+    pub fn path(&self) -> String {
+        let hex = self.to_hex();
+        format!("{}/{}/{}", &hex[..2], &hex[2..4], hex)
+    }
+}
+
+impl fmt::Display for LfsOid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.to_hex())
+    }
+}
+
+impl fmt::Debug for LfsOid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "LfsOid({})", self.to_hex())
+    }
+}
+
+impl FromStr for LfsOid {
+    type Err = MonoError;
+
+    fn from_str(s: &str) -> MonoResult<LfsOid> {
+        LfsOid::from_hex(s)
+    }
+}
+
+/// 对象存储中的一个 LFS 对象
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LfsObject {
+    pub oid: LfsOid,
+    pub size: u64,
+    /// 最后写入时间
+    pub modified: SystemTime,
+}
+
+/// 提交到仓库中代替大文件的指针文件
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pointer {
+    pub oid: LfsOid,
+    pub size: u64,
+}
+
+impl Pointer {
+    /// 解析指针文件，内容不是合法的指针时返回 None
+    ///
+    /// 第一行必须是 `version`，其余每行一个 `key value`，至少包含 `oid sha256:<hex>` 与 `size`。
+    pub fn parse(data: &[u8]) -> Option<Pointer> {
+        if data.len() > MAX_POINTER_SIZE {
+            return None;
+        }
+        let text = std::str::from_utf8(data).ok()?;
+        let mut lines = text.lines();
+        if lines.next()? != format!("version {}", POINTER_VERSION) {
+            return None;
+        }
+        let (mut oid, mut size) = (None, None);
+        for line in lines.filter(|line| !line.is_empty()) {
+            match line.split_once(' ')? {
+                ("oid", value) => oid = Some(LfsOid::from_hex(value.strip_prefix("sha256:")?).ok()?),
+                ("size", value) => size = Some(value.parse().ok()?),
+                _ => {}
+            }
+        }
+        Some(Pointer { oid: oid?, size: size? })
+    }
+
+    /// 编码为指针文件的内容
+    pub fn encode(&self) -> String {
+        format!("version {}\noid sha256:{}\nsize {}\n", POINTER_VERSION, self.oid, self.size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 测试对象 ID 的计算、解析与存储路径
+    #[test]
+    fn test_lfs_oid() {
+        let oid = LfsOid::hash(b"hello");
+        let hex = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+        assert_eq!(oid.to_hex(), hex);
+        assert_eq!(hex.parse::<LfsOid>().unwrap(), oid);
+        assert_eq!(oid.path(), format!("2c/f2/{}", hex));
+        assert!(LfsOid::from_hex(&hex.to_uppercase()).is_err());
+        assert!(LfsOid::from_hex(&hex[1..]).is_err());
+        assert!(LfsOid::from_hex("../../../../etc/passwd").is_err());
+    }
+
+    /// 测试指针文件的解析与编码
+    #[test]
+    fn test_pointer() {
+        let pointer = Pointer {
+            oid: LfsOid::hash(b"hello"),
+            size: 5,
+        };
+        let encoded = pointer.encode();
+        assert!(encoded.starts_with("version https://git-lfs.github.com/spec/v1\noid sha256:2cf24dba"));
+        assert_eq!(Pointer::parse(encoded.as_bytes()), Some(pointer));
+
+        let extended = encoded.replace("size 5\n", "ext-0-foo sha256:00\nsize 5\n");
+        assert_eq!(Pointer::parse(extended.as_bytes()), Some(pointer));
+        assert_eq!(Pointer::parse(encoded.replace("size 5", "size x").as_bytes()), None);
+        assert_eq!(Pointer::parse(encoded.replace("sha256:", "sha1:").as_bytes()), None);
+        assert_eq!(Pointer::parse(&encoded.as_bytes()[encoded.find('\n').unwrap() + 1..]), None);
+        assert_eq!(Pointer::parse(b"plain text file"), None);
+    }
+}
@@ -0,0 +1,74 @@
+//! MonoEngine 库入口
+//!
+//! 二进制程序 `main.rs` 仅负责启动，所有子系统都通过该库对外暴露，
+//! 以便其他工具和测试直接调用。
+
+pub mod audit;
+pub mod auth;
+pub mod big_files;
+pub mod binary_delta;
+pub mod bisect;
+pub mod blame;
+pub mod bundle;
+pub mod changed;
+pub mod changelog;
+pub mod checks;
+pub mod cli;
+pub mod commands;
+pub mod common;
+pub mod commit_lint;
+pub mod completion;
+pub mod compose;
+pub mod diff;
+pub mod doctor;
+pub mod export;
+pub mod freeze;
+pub mod fsck;
+pub mod gc;
+pub mod graph;
+pub mod hooks;
+pub mod index;
+pub mod journal;
+pub mod lfs;
+pub mod lock;
+pub mod logging;
+pub mod mailmap;
+pub mod maintenance;
+pub mod merge;
+pub mod metrics;
+pub mod notifications;
+pub mod object;
+pub mod offload;
+pub mod owners;
+pub mod pack;
+pub mod pktline;
+pub mod policy;
+pub mod queue;
+pub mod quota;
+pub mod reflog;
+pub mod release;
+pub mod refs;
+pub mod replication;
+pub mod repo;
+pub mod review;
+pub mod rewrite;
+pub mod runner;
+pub mod search;
+pub mod secrets;
+pub mod signing;
+pub mod server;
+pub mod sparse;
+pub mod stack;
+pub mod status;
+pub mod storage;
+pub mod submodules;
+pub mod telemetry;
+pub mod transport;
+pub mod tui;
+pub mod version;
+pub mod vfs;
+pub mod webhooks;
+pub mod worktree;
+
+#[cfg(test)]
+pub(crate) mod test_utils;
@@ -0,0 +1,610 @@
+//! 分布式锁
+//!
+//! 多个服务实例共享同一个仓库时，引用更新与重新打包、gc 等改写对象存储的操作需要在实例之间
+//! 串行化。锁服务在 `mono.toml` 中配置：
+//!
+//! ```toml
+//! [locks]
+//! backend = "redis"            # local、postgres 或 redis
+//! url = "redis://locks.internal:6379/0"
+//! timeout_secs = 30
+//! ```
+//!
+//! - `local`（默认）：`.mono/locks` 下的文件锁，只在共享同一文件系统的进程之间有效
+//! - `postgres`：会话级 advisory lock，持有锁的连接断开后由数据库释放；`url` 省略时使用 `[storage.pg]`
+//! - `redis`：`SET NX PX` 写入带过期时间的键，持有期间后台线程续期，释放时只删除自己写入的键，
+//!   持有者崩溃后最多 `ttl_secs` 秒释放
+//!
+//! 锁名带有仓库标识（配置了 `[storage.pg]` 时为其中的仓库名，否则为仓库根目录名），不同仓库的锁
+//! 互不影响。使用 `postgres` 或 `redis` 后端时，引用的写入、删除与多引用更新都在 [`REFS_LOCK`] 下进行。
+
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::common::config::{LockBackend, LocksConfig, RepoConfig};
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::object::ObjectId;
+use crate::refs::{RefStore, RefTarget, RefUpdate};
+
+/// 锁文件目录，相对于 `.mono`
+pub const LOCKS_DIR: &str = "locks";
+/// 串行化引用更新的锁
+pub const REFS_LOCK: &str = "refs";
+/// 串行化重新打包与 gc 的锁
+pub const REPACK_LOCK: &str = "repack";
+/// 轮询等待锁的最长间隔
+const MAX_POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// Redis 默认端口
+const REDIS_PORT: u16 = 6379;
+/// Redis 回复中字符串与数组的最大长度，锁命令的回复都很短
+const MAX_REDIS_REPLY_LEN: i64 = 64 * 1024;
+/// 只删除自己持有的锁
+const REDIS_RELEASE: &str = "if redis.call('get', KEYS[1]) == ARGV[1] then return redis.call('del', KEYS[1]) else return 0 end";
+/// 只为自己持有的锁续期
+const REDIS_RENEW: &str =
+    "if redis.call('get', KEYS[1]) == ARGV[1] then return redis.call('pexpire', KEYS[1], ARGV[2]) else return 0 end";
+
+/// 持有中的锁，离开作用域时释放
+pub struct LockGuard {
+    release: Option<Box<dyn FnOnce() + Send>>,
+}
+
+impl LockGuard {
+    fn new(release: impl FnOnce() + Send + 'static) -> LockGuard {
+        LockGuard {
+            release: Some(Box::new(release)),
+        }
+    }
+}
+
+impl fmt::Debug for LockGuard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LockGuard").finish_non_exhaustive()
+    }
+}
+
+impl Drop for LockGuard {
+    fn drop(&mut self) {
+        if let Some(release) = self.release.take() {
+            release();
+        }
+    }
+}
+
+/// 锁服务
+pub trait LockService: Send + Sync + fmt::Debug {
+    /// 尝试获取名为 `name` 的锁，已被其他持有者持有时返回 None
+    fn try_acquire(&self, name: &str) -> MonoResult<Option<LockGuard>>;
+
+    /// 获取锁，最多等待 `timeout`，超时返回 `Unavailable` 错误
+    fn acquire(&self, name: &str, timeout: Duration) -> MonoResult<LockGuard> {
+        let deadline = Instant::now() + timeout;
+        let mut interval = Duration::from_millis(10);
+        loop {
+            if let Some(guard) = self.try_acquire(name)? {
+                return Ok(guard);
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(timed_out(name));
+            }
+            std::thread::sleep(interval.min(deadline - now));
+            interval = (interval * 2).min(MAX_POLL_INTERVAL);
+        }
+    }
+}
+
+fn timed_out(name: &str) -> MonoError {
+    MonoError::unavailable(format!("timed out waiting for lock {}", name))
+}
+
+/// 按配置打开仓库的锁服务
+pub fn open(config: &RepoConfig, mono_dir: &Path) -> MonoResult<Arc<dyn LockService>> {
+    let repository = match &config.storage.pg {
+        Some(pg) => pg.repository.clone(),
+        None => mono_dir
+            .parent()
+            .and_then(Path::file_name)
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+    };
+    let locks = &config.locks;
+    Ok(match locks.backend {
+        LockBackend::Local => Arc::new(LocalLocks::new(mono_dir)),
+        LockBackend::Postgres => {
+            let url = locks
+                .url
+                .clone()
+                .or_else(|| config.storage.pg.as_ref().map(|pg| pg.url.clone()))
+                .ok_or_else(|| MonoError::config("locks backend postgres requires locks.url or a [storage.pg] section"))?;
+            Arc::new(PgLocks { url, repository })
+        }
+        LockBackend::Redis => {
+            let url = locks
+                .url
+                .as_deref()
+                .ok_or_else(|| MonoError::config("locks backend redis requires locks.url"))?;
+            Arc::new(RedisLocks::new(url, &repository, Duration::from_secs(locks.ttl_secs.max(1)))?)
+        }
+    })
+}
+
+/// 基于文件锁的本机锁服务
+#[derive(Debug, Clone)]
+pub struct LocalLocks {
+    dir: PathBuf,
+}
+
+impl LocalLocks {
+    pub fn new(mono_dir: &Path) -> LocalLocks {
+        LocalLocks {
+            dir: mono_dir.join(LOCKS_DIR),
+        }
+    }
+}
+
+impl LockService for LocalLocks {
+    fn try_acquire(&self, name: &str) -> MonoResult<Option<LockGuard>> {
+        std::fs::create_dir_all(&self.dir)?;
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(self.dir.join(format!("{}.lock", name)))?;
+        match file.try_lock() {
+            // 文件关闭时释放锁
+            Ok(()) => Ok(Some(LockGuard::new(move || drop(file)))),
+            Err(std::fs::TryLockError::WouldBlock) => Ok(None),
+            Err(std::fs::TryLockError::Error(e)) => Err(e.into()),
+        }
+    }
+}
+
+/// 基于 PostgreSQL advisory lock 的锁服务，每个持有中的锁占用一个连接
+pub struct PgLocks {
+    url: String,
+    repository: String,
+}
+
+impl fmt::Debug for PgLocks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // 连接串中可能包含密码
+        f.debug_struct("PgLocks")
+            .field("repository", &self.repository)
+            .finish_non_exhaustive()
+    }
+}
+
+fn pg_error(err: postgres::Error) -> MonoError {
+    MonoError::unavailable(format!("postgres: {}", err))
+}
+
+impl PgLocks {
+    fn connect(&self) -> MonoResult<postgres::Client> {
+        postgres::Client::connect(&self.url, postgres::NoTls)
+            .map_err(|e| MonoError::unavailable(format!("connecting to postgres: {}", e)))
+    }
+
+    fn key(&self, name: &str) -> String {
+        format!("mono:{}:{}", self.repository, name)
+    }
+
+    /// 持有锁的连接关闭前先显式释放，连接异常断开时由数据库释放
+    fn guard(mut client: postgres::Client, key: String) -> LockGuard {
+        LockGuard::new(move || {
+            if let Err(e) = client.execute("SELECT pg_advisory_unlock(hashtext($1))", &[&key]) {
+                tracing::warn!(lock = %key, error = %e, "failed to release lock");
+            }
+        })
+    }
+}
+
+impl LockService for PgLocks {
+    fn try_acquire(&self, name: &str) -> MonoResult<Option<LockGuard>> {
+        let mut client = self.connect()?;
+        let key = self.key(name);
+        let acquired: bool = client
+            .query_one("SELECT pg_try_advisory_lock(hashtext($1))", &[&key])
+            .map_err(pg_error)?
+            .get(0);
+        Ok(acquired.then(|| PgLocks::guard(client, key)))
+    }
+
+    /// 由数据库排队等待，超时由 `lock_timeout` 控制
+    fn acquire(&self, name: &str, timeout: Duration) -> MonoResult<LockGuard> {
+        let mut client = self.connect()?;
+        let key = self.key(name);
+        client
+            .batch_execute(&format!("SET lock_timeout = {}", timeout.as_millis().max(1)))
+            .map_err(pg_error)?;
+        match client.execute("SELECT pg_advisory_lock(hashtext($1))", &[&key]) {
+            Ok(_) => Ok(PgLocks::guard(client, key)),
+            Err(e) if e.code() == Some(&postgres::error::SqlState::LOCK_NOT_AVAILABLE) => Err(timed_out(name)),
+            Err(e) => Err(pg_error(e)),
+        }
+    }
+}
+
+/// Redis 服务器的地址与认证信息
+#[derive(Clone, PartialEq, Eq)]
+struct RedisUrl {
+    host: String,
+    port: u16,
+    password: Option<String>,
+    db: Option<u32>,
+}
+
+impl fmt::Debug for RedisUrl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RedisUrl")
+            .field("host", &self.host)
+            .field("port", &self.port)
+            .field("db", &self.db)
+            .finish_non_exhaustive()
+    }
+}
+
+impl RedisUrl {
+    fn parse(url: &str) -> MonoResult<RedisUrl> {
+        let invalid = || MonoError::config(format!("invalid redis url: {}", url));
+        let rest = url.strip_prefix("redis://").ok_or_else(invalid)?;
+        let (rest, db) = match rest.split_once('/') {
+            Some((rest, "")) => (rest, None),
+            Some((rest, db)) => (rest, Some(db.parse().map_err(|_| invalid())?)),
+            None => (rest, None),
+        };
+        let (password, address) = match rest.rsplit_once('@') {
+            // 与 redis-cli 相同，用户名部分可以省略
+            Some((auth, address)) => (Some(auth.rsplit(':').next().unwrap_or(auth).to_string()), address),
+            None => (None, rest),
+        };
+        let (host, port) = match address.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse().map_err(|_| invalid())?),
+            None => (address, REDIS_PORT),
+        };
+        if host.is_empty() {
+            return Err(invalid());
+        }
+        Ok(RedisUrl {
+            host: host.to_string(),
+            port,
+            password: password.filter(|password| !password.is_empty()),
+            db,
+        })
+    }
+}
+
+/// RESP 协议的回复
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Reply {
+    Status(String),
+    Integer(i64),
+    Bulk(Option<Vec<u8>>),
+    Array(Option<Vec<Reply>>),
+}
+
+/// 到 Redis 服务器的一个连接，只实现锁需要的命令
+struct RedisConnection {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl RedisConnection {
+    fn connect(url: &RedisUrl, timeout: Duration) -> MonoResult<RedisConnection> {
+        let unavailable = |e: std::io::Error| MonoError::unavailable(format!("redis {}:{}: {}", url.host, url.port, e));
+        let stream = TcpStream::connect((url.host.as_str(), url.port)).map_err(unavailable)?;
+        stream.set_read_timeout(Some(timeout)).map_err(unavailable)?;
+        stream.set_write_timeout(Some(timeout)).map_err(unavailable)?;
+        let mut connection = RedisConnection {
+            reader: BufReader::new(stream.try_clone().map_err(unavailable)?),
+            writer: stream,
+        };
+        if let Some(password) = &url.password {
+            connection.command(&["AUTH", password])?;
+        }
+        if let Some(db) = url.db {
+            connection.command(&["SELECT", &db.to_string()])?;
+        }
+        Ok(connection)
+    }
+
+    fn command(&mut self, args: &[&str]) -> MonoResult<Reply> {
+        let mut request = format!("*{}\r\n", args.len());
+        for arg in args {
+            request.push_str(&format!("${}\r\n{}\r\n", arg.len(), arg));
+        }
+        self.writer
+            .write_all(request.as_bytes())
+            .map_err(|e| MonoError::unavailable(format!("redis: {}", e)))?;
+        self.read_reply()
+    }
+
+    fn read_line(&mut self) -> MonoResult<String> {
+        let mut line = String::new();
+        self.reader
+            .read_line(&mut line)
+            .map_err(|e| MonoError::unavailable(format!("redis: {}", e)))?;
+        if !line.ends_with("\r\n") {
+            return Err(MonoError::unavailable("redis: connection closed"));
+        }
+        line.truncate(line.len() - 2);
+        Ok(line)
+    }
+
+    fn read_reply(&mut self) -> MonoResult<Reply> {
+        let line = self.read_line()?;
+        let invalid = || MonoError::protocol(format!("invalid redis reply: {}", line));
+        let (kind, rest) = line.split_at_checked(1).ok_or_else(invalid)?;
+        match kind {
+            "+" => Ok(Reply::Status(rest.to_string())),
+            "-" => Err(MonoError::storage(format!("redis: {}", rest))),
+            ":" => rest.parse().map(Reply::Integer).map_err(|_| invalid()),
+            "$" => {
+                let len: i64 = rest.parse().map_err(|_| invalid())?;
+                if len == -1 {
+                    return Ok(Reply::Bulk(None));
+                }
+                if !(0..=MAX_REDIS_REPLY_LEN).contains(&len) {
+                    return Err(invalid());
+                }
+                let mut data = vec![0; len as usize + 2];
+                self.reader
+                    .read_exact(&mut data)
+                    .map_err(|e| MonoError::unavailable(format!("redis: {}", e)))?;
+                data.truncate(len as usize);
+                Ok(Reply::Bulk(Some(data)))
+            }
+            "*" => {
+                let len: i64 = rest.parse().map_err(|_| invalid())?;
+                if len == -1 {
+                    return Ok(Reply::Array(None));
+                }
+                if !(0..=MAX_REDIS_REPLY_LEN).contains(&len) {
+                    return Err(invalid());
+                }
+                let items = (0..len).map(|_| self.read_reply()).collect::<MonoResult<_>>()?;
+                Ok(Reply::Array(Some(items)))
+            }
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// 基于 Redis 的锁服务
+#[derive(Debug)]
+pub struct RedisLocks {
+    url: RedisUrl,
+    repository: String,
+    ttl: Duration,
+}
+
+impl RedisLocks {
+    pub fn new(url: &str, repository: &str, ttl: Duration) -> MonoResult<RedisLocks> {
+        Ok(RedisLocks {
+            url: RedisUrl::parse(url)?,
+            repository: repository.to_string(),
+            ttl,
+        })
+    }
+
+    fn key(&self, name: &str) -> String {
+        format!("mono:lock:{}:{}", self.repository, name)
+    }
+}
+
+impl LockService for RedisLocks {
+    fn try_acquire(&self, name: &str) -> MonoResult<Option<LockGuard>> {
+        let mut connection = RedisConnection::connect(&self.url, self.ttl)?;
+        let key = self.key(name);
+        let token: String = rand::random::<[u8; 16]>().iter().map(|b| format!("{:02x}", b)).collect();
+        let ttl_ms = self.ttl.as_millis().to_string();
+        if connection.command(&["SET", &key, &token, "NX", "PX", &ttl_ms])? == Reply::Bulk(None) {
+            return Ok(None);
+        }
+        let connection = Arc::new(Mutex::new(connection));
+        // 每隔三分之一个过期时间续期一次，续期失败说明锁已过期并可能被他人取得
+        let (stop, stopped) = mpsc::channel::<()>();
+        let renewer = {
+            let (connection, key, token) = (connection.clone(), key.clone(), token.clone());
+            let interval = self.ttl / 3;
+            std::thread::spawn(move || loop {
+                match stopped.recv_timeout(interval) {
+                    Err(RecvTimeoutError::Timeout) => {}
+                    _ => return,
+                }
+                let mut connection = connection.lock().unwrap_or_else(|e| e.into_inner());
+                match connection.command(&["EVAL", REDIS_RENEW, "1", &key, &token, &ttl_ms]) {
+                    Ok(Reply::Integer(1)) => {}
+                    Ok(_) => {
+                        tracing::error!(lock = %key, "lock expired before it was released");
+                        return;
+                    }
+                    Err(e) => tracing::warn!(lock = %key, error = %e, "failed to renew lock"),
+                }
+            })
+        };
+        Ok(Some(LockGuard::new(move || {
+            drop(stop);
+            let _ = renewer.join();
+            let mut connection = connection.lock().unwrap_or_else(|e| e.into_inner());
+            if let Err(e) = connection.command(&["EVAL", REDIS_RELEASE, "1", &key, &token]) {
+                tracing::warn!(lock = %key, error = %e, "failed to release lock");
+            }
+        })))
+    }
+}
+
+/// 在锁服务的锁下写入、删除引用与执行多引用更新的引用数据库
+#[derive(Debug)]
+pub struct LockedRefStore {
+    inner: Arc<dyn RefStore>,
+    locks: Arc<dyn LockService>,
+    timeout: Duration,
+}
+
+impl LockedRefStore {
+    pub fn new(inner: Arc<dyn RefStore>, locks: Arc<dyn LockService>, config: &LocksConfig) -> LockedRefStore {
+        LockedRefStore {
+            inner,
+            locks,
+            timeout: Duration::from_secs(config.timeout_secs),
+        }
+    }
+}
+
+impl RefStore for LockedRefStore {
+    fn read(&self, name: &str) -> MonoResult<Option<RefTarget>> {
+        self.inner.read(name)
+    }
+
+    fn write(&self, name: &str, id: &ObjectId) -> MonoResult<()> {
+        let _guard = self.locks.acquire(REFS_LOCK, self.timeout)?;
+        self.inner.write(name, id)
+    }
+
+    fn write_symbolic(&self, name: &str, target: &str) -> MonoResult<()> {
+        let _guard = self.locks.acquire(REFS_LOCK, self.timeout)?;
+        self.inner.write_symbolic(name, target)
+    }
+
+    fn delete(&self, name: &str) -> MonoResult<()> {
+        let _guard = self.locks.acquire(REFS_LOCK, self.timeout)?;
+        self.inner.delete(name)
+    }
+
+    fn list(&self, prefix: &str) -> MonoResult<Vec<(String, ObjectId)>> {
+        self.inner.list(prefix)
+    }
+
+    fn update(&self, updates: &[RefUpdate]) -> MonoResult<()> {
+        let _guard = self.locks.acquire(REFS_LOCK, self.timeout)?;
+        self.inner.update(updates)
+    }
+
+    fn flush(&self) -> MonoResult<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    /// 测试本机锁互斥与释放、Redis 地址解析，以及通过模拟的 Redis 服务器获取与释放锁
+    #[test]
+    fn test_locks() {
+        let dir = tempfile::tempdir().unwrap();
+        let locks = LocalLocks::new(dir.path());
+        let guard = locks.try_acquire(REPACK_LOCK).unwrap().unwrap();
+        assert!(locks.try_acquire(REPACK_LOCK).unwrap().is_none());
+        assert!(locks.try_acquire(REFS_LOCK).unwrap().is_some());
+        assert!(locks.acquire(REPACK_LOCK, Duration::from_millis(30)).is_err());
+        drop(guard);
+        assert!(locks.acquire(REPACK_LOCK, Duration::from_millis(30)).is_ok());
+
+        let url = RedisUrl::parse("redis://:secret@locks.internal:6380/2").unwrap();
+        assert_eq!((url.host.as_str(), url.port, url.password.as_deref(), url.db), ("locks.internal", 6380, Some("secret"), Some(2)));
+        assert_eq!(RedisUrl::parse("redis://localhost").unwrap().port, REDIS_PORT);
+        assert!(RedisUrl::parse("http://localhost").is_err());
+
+        // 第一次 SET 成功，第二次返回空值表示已被持有，随后是释放脚本的回复
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = std::thread::spawn(move || {
+            let mut commands = Vec::new();
+            for reply in ["+OK\r\n", "$-1\r\n"] {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let count: usize = line.trim_end()[1..].parse().unwrap();
+                let mut args = Vec::new();
+                for _ in 0..count {
+                    let (mut len, mut arg) = (String::new(), String::new());
+                    reader.read_line(&mut len).unwrap();
+                    reader.read_line(&mut arg).unwrap();
+                    args.push(arg.trim_end().to_string());
+                }
+                commands.push(args);
+                (&stream).write_all(reply.as_bytes()).unwrap();
+                if reply == "+OK\r\n" {
+                    // 持有者释放时发送 EVAL
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    commands.push(vec![line.trim_end().to_string()]);
+                    (&stream).write_all(b":1\r\n").unwrap();
+                    let mut rest = Vec::new();
+                    let _ = reader.read_to_end(&mut rest);
+                }
+            }
+            commands
+        });
+        let redis = RedisLocks::new(&format!("redis://127.0.0.1:{}", port), "repo", Duration::from_secs(30)).unwrap();
+        let guard = redis.try_acquire(REFS_LOCK).unwrap().unwrap();
+        drop(guard);
+        assert!(redis.try_acquire(REFS_LOCK).unwrap().is_none());
+        let commands = server.join().unwrap();
+        assert_eq!(commands[0][..4], ["SET", "mono:lock:repo:refs", &commands[0][2], "NX"]);
+        assert_eq!(commands[1], ["*5"]);
+    }
+
+    /// 测试超长或非法长度的 Redis 回复在分配内存前被拒绝
+    #[test]
+    fn test_redis_reply_len() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = RedisUrl::parse(&format!("redis://127.0.0.1:{}", listener.local_addr().unwrap().port())).unwrap();
+        let replies = ["$-1\r\n", "*-1\r\n", "$-2\r\n", "$9223372036854775807\r\n", "*4294967296\r\n"];
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            for reply in replies {
+                stream.write_all(reply.as_bytes()).unwrap();
+            }
+            let mut rest = Vec::new();
+            let _ = stream.read_to_end(&mut rest);
+        });
+        let mut connection = RedisConnection::connect(&url, Duration::from_secs(5)).unwrap();
+        assert_eq!(connection.read_reply().unwrap(), Reply::Bulk(None));
+        assert_eq!(connection.read_reply().unwrap(), Reply::Array(None));
+        for _ in 0..3 {
+            assert!(connection.read_reply().is_err());
+        }
+        drop(connection);
+        server.join().unwrap();
+    }
+
+    /// 测试写入、删除引用与多引用更新都在引用锁下进行
+    #[test]
+    fn test_locked_ref_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let locks = Arc::new(LocalLocks::new(dir.path()));
+        let refs = LockedRefStore {
+            inner: Arc::new(crate::refs::FileRefStore::new(dir.path())),
+            locks: locks.clone(),
+            timeout: Duration::ZERO,
+        };
+        let id = ObjectId::from_hex("1111111111111111111111111111111111111111").unwrap();
+        refs.write("refs/heads/main", &id).unwrap();
+
+        let guard = locks.try_acquire(REFS_LOCK).unwrap().unwrap();
+        assert!(refs.write("refs/heads/main", &id).is_err());
+        assert!(refs.write_symbolic("HEAD", "refs/heads/main").is_err());
+        assert!(refs.delete("refs/heads/main").is_err());
+        let update = RefUpdate {
+            name: "refs/heads/main".to_string(),
+            old: id,
+            new: ObjectId::ZERO,
+        };
+        assert!(refs.update(&[update]).is_err());
+        drop(guard);
+        refs.delete("refs/heads/main").unwrap();
+        assert!(refs.read("refs/heads/main").unwrap().is_none());
+    }
+}
@@ -0,0 +1,225 @@
+//! 日志输出
+//!
+//! 各模块通过 `tracing` 的 `error!`、`warn!`、`info!` 与 `debug!` 记录日志，这里按环境变量
+//! `MONO_LOG` 过滤后输出。`MONO_LOG` 由逗号分隔的指令组成，每条为一个级别或 `模块=级别`，
+//! 不含 `::` 的模块名指本项目的顶层模块：
+//!
+//! ```text
+//! MONO_LOG=server=debug,storage=warn
+//! MONO_LOG=info,pack::deltify=trace,h2::codec=warn
+//! ```
+//!
+//! 普通命令输出到标准错误，未设置 `MONO_LOG` 时只显示警告与错误。`mono serve` 写入
+//! `[log] dir` 下按 `[log] rotation` 轮转的日志文件，未设置 `MONO_LOG` 时使用 `[log] filter`：
+//!
+//! ```toml
+//! [log]
+//! dir = "logs"          # 相对 .mono 目录
+//! rotation = "daily"    # hourly、daily 或 never
+//! max_files = 7         # 保留的日志文件数，0 表示不删除
+//! filter = "info"
+//! ```
+//!
+//! `mono serve` 重新加载配置时（见 [`crate::server::reload`]）按新的 `[log] filter` 调整过滤，
+//! 日志目录与轮转方式只在启动时读取。
+
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+
+use serde::{Deserialize, Serialize};
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation as AppenderRotation};
+use tracing_subscriber::filter::{LevelFilter, Targets};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+use crate::common::config::LogConfig;
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+
+/// 日志过滤指令所在的环境变量
+pub const LOG_ENV: &str = "MONO_LOG";
+
+/// 普通命令未设置 `MONO_LOG` 时的过滤指令
+pub const DEFAULT_FILTER: &str = "warn";
+
+/// 服务端日志文件名的前缀，轮转后追加日期与 `.log`
+const FILE_PREFIX: &str = "mono-serve";
+
+/// 替换当前日志层过滤指令的回调，由最近一次创建的日志层设置
+type Reload = Box<dyn Fn(Targets) -> bool + Send + Sync>;
+
+static RELOAD: Mutex<Option<Reload>> = Mutex::new(None);
+
+/// 日志文件的轮转周期
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Rotation {
+    Hourly,
+    #[default]
+    Daily,
+    /// 始终写入同一个文件
+    Never,
+}
+
+/// 日志的去向
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LogOutput {
+    Stderr,
+    /// 写入目录中轮转的日志文件
+    Files {
+        dir: PathBuf,
+        rotation: Rotation,
+        max_files: usize,
+    },
+}
+
+/// 日志的过滤与去向
+#[derive(Debug, Clone)]
+pub struct LogOptions {
+    pub filter: Targets,
+    pub output: LogOutput,
+}
+
+impl LogOptions {
+    /// 普通命令：输出到标准错误
+    pub fn stderr() -> MonoResult<LogOptions> {
+        let spec = std::env::var(LOG_ENV).unwrap_or_else(|_| DEFAULT_FILTER.to_string());
+        Ok(LogOptions {
+            filter: parse_filter(&spec)?,
+            output: LogOutput::Stderr,
+        })
+    }
+
+    /// 服务端：按 `[log]` 配置写入 `mono_dir` 下的日志文件
+    pub fn server(mono_dir: &Path, config: &LogConfig) -> MonoResult<LogOptions> {
+        let spec = std::env::var(LOG_ENV).unwrap_or_else(|_| config.filter.clone());
+        Ok(LogOptions {
+            filter: parse_filter(&spec)?,
+            output: LogOutput::Files {
+                dir: mono_dir.join(&config.dir),
+                rotation: config.rotation,
+                max_files: config.max_files,
+            },
+        })
+    }
+
+    /// 创建输出日志的层，写入文件时返回的 guard 释放前会写完缓冲的日志
+    pub fn layer<S>(&self) -> MonoResult<(Box<dyn Layer<S> + Send + Sync>, Option<WorkerGuard>)>
+    where
+        S: tracing::Subscriber + for<'a> LookupSpan<'a> + Send + Sync,
+    {
+        let (filter, handle) = tracing_subscriber::reload::Layer::new(self.filter.clone());
+        let reload: Reload = Box::new(move |filter| handle.reload(filter).is_ok());
+        *RELOAD.lock().unwrap_or_else(PoisonError::into_inner) = Some(reload);
+        match &self.output {
+            LogOutput::Stderr => {
+                let layer = tracing_subscriber::fmt::layer()
+                    .with_writer(std::io::stderr)
+                    .with_ansi(std::io::stderr().is_terminal())
+                    .without_time()
+                    .with_filter(filter)
+                    .boxed();
+                Ok((layer, None))
+            }
+            LogOutput::Files { dir, rotation, max_files } => {
+                let rotation = match rotation {
+                    Rotation::Hourly => AppenderRotation::HOURLY,
+                    Rotation::Daily => AppenderRotation::DAILY,
+                    Rotation::Never => AppenderRotation::NEVER,
+                };
+                let mut builder = RollingFileAppender::builder()
+                    .rotation(rotation)
+                    .filename_prefix(FILE_PREFIX)
+                    .filename_suffix("log");
+                if *max_files > 0 {
+                    builder = builder.max_log_files(*max_files);
+                }
+                std::fs::create_dir_all(dir)?;
+                let appender = builder
+                    .build(dir)
+                    .map_err(|e| MonoError::config(format!("log directory {}: {}", dir.display(), e)))?;
+                let (writer, guard) = tracing_appender::non_blocking(appender);
+                let layer = tracing_subscriber::fmt::layer()
+                    .with_writer(writer)
+                    .with_ansi(false)
+                    .with_filter(filter)
+                    .boxed();
+                Ok((layer, Some(guard)))
+            }
+        }
+    }
+}
+
+/// 按 `[log] filter` 调整正在使用的日志过滤，设置了 `MONO_LOG` 时环境变量优先，不做改变
+///
+/// 返回过滤是否被替换；还没有创建日志层时返回 false。
+pub fn reload_filter(config: &LogConfig) -> MonoResult<bool> {
+    if std::env::var_os(LOG_ENV).is_some() {
+        return Ok(false);
+    }
+    let filter = parse_filter(&config.filter)?;
+    let reload = RELOAD.lock().unwrap_or_else(PoisonError::into_inner);
+    Ok(reload.as_ref().is_some_and(|reload| reload(filter)))
+}
+
+/// 解析 `MONO_LOG` 格式的过滤指令
+///
+/// 只有模块名的指令启用该模块的全部级别，与 `RUST_LOG` 相同。
+pub fn parse_filter(spec: &str) -> MonoResult<Targets> {
+    let mut filter = Targets::new();
+    for directive in spec.split(',').map(str::trim).filter(|directive| !directive.is_empty()) {
+        let (target, level) = match directive.split_once('=') {
+            Some((target, level)) => (Some(target.trim()), parse_level(level.trim(), directive)?),
+            None => match parse_level(directive, directive) {
+                Ok(level) => (None, level),
+                Err(_) => (Some(directive), LevelFilter::TRACE),
+            },
+        };
+        filter = match target {
+            None => filter.with_default(level),
+            Some("") => return Err(invalid(directive)),
+            Some(target) if target.contains("::") => filter.with_target(target, level),
+            Some(target) => filter.with_target(format!("{}::{}", env!("CARGO_CRATE_NAME"), target), level),
+        };
+    }
+    Ok(filter)
+}
+
+fn parse_level(level: &str, directive: &str) -> MonoResult<LevelFilter> {
+    level.parse().map_err(|_| invalid(directive))
+}
+
+fn invalid(directive: &str) -> MonoError {
+    MonoError::config(format!("invalid {} directive: {}", LOG_ENV, directive))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing::Level;
+
+    /// 测试过滤指令的解析与模块名的补全
+    #[test]
+    fn test_parse_filter() {
+        let filter = parse_filter("server=debug, storage=warn,h2::codec=error,info").unwrap();
+        assert!(filter.would_enable("monoengine::server::http", &Level::DEBUG));
+        assert!(!filter.would_enable("monoengine::server::http", &Level::TRACE));
+        assert!(!filter.would_enable("monoengine::storage::fs", &Level::INFO));
+        assert!(filter.would_enable("monoengine::storage::fs", &Level::WARN));
+        assert!(!filter.would_enable("h2::codec", &Level::WARN));
+        assert!(filter.would_enable("monoengine::pack", &Level::INFO));
+        assert!(!filter.would_enable("monoengine::pack", &Level::DEBUG));
+
+        let filter = parse_filter("gc").unwrap();
+        assert!(filter.would_enable("monoengine::gc", &Level::TRACE));
+        assert!(!filter.would_enable("monoengine::pack", &Level::ERROR));
+
+        assert!(parse_filter("").unwrap().default_level().is_none());
+        for spec in ["server=loud", "=debug", "server=debug,=info"] {
+            let err = parse_filter(spec).unwrap_err();
+            assert!(err.to_string().contains("MONO_LOG"), "{}", err);
+        }
+    }
+}
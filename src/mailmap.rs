@@ -0,0 +1,117 @@
+//! mailmap：把提交中的作者名字与邮箱映射为规范的身份
+//!
+//! 格式与 git 的 `.mailmap` 相同，每行一条，`#` 之后为注释：
+//!
+//! ```text
+//! Proper Name <commit@email>
+//! <proper@email> <commit@email>
+//! Proper Name <proper@email> <commit@email>
+//! Proper Name <proper@email> Commit Name <commit@email>
+//! ```
+//!
+//! 邮箱比较不区分大小写；同时给出了提交中的名字的条目优先于只给出邮箱的条目。
+
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+
+/// 一条映射
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Entry {
+    proper_name: Option<String>,
+    proper_email: Option<String>,
+    commit_name: Option<String>,
+    commit_email: String,
+}
+
+/// 解析后的 mailmap
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Mailmap {
+    entries: Vec<Entry>,
+}
+
+impl Mailmap {
+    pub fn parse(text: &str) -> MonoResult<Mailmap> {
+        let mut entries = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split_once('#').map_or(line, |(line, _)| line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            let invalid = || MonoError::usage(format!("invalid mailmap line {}: {}", i + 1, line));
+            // 依次取出 `名字 <邮箱>` 片段，名字可以为空
+            let mut parts = Vec::new();
+            let mut rest = line;
+            while let Some(open) = rest.find('<') {
+                let close = rest[open..].find('>').ok_or_else(invalid)? + open;
+                let name = rest[..open].trim();
+                parts.push(((!name.is_empty()).then(|| name.to_string()), rest[open + 1..close].trim().to_string()));
+                rest = &rest[close + 1..];
+            }
+            if !rest.trim().is_empty() {
+                return Err(invalid());
+            }
+            let entry = match parts.as_slice() {
+                [(Some(name), email)] => {
+                    Entry { proper_name: Some(name.clone()), proper_email: None, commit_name: None, commit_email: email.clone() }
+                }
+                [(proper_name, proper_email), (commit_name, commit_email)] => Entry {
+                    proper_name: proper_name.clone(),
+                    proper_email: Some(proper_email.clone()),
+                    commit_name: commit_name.clone(),
+                    commit_email: commit_email.clone(),
+                },
+                _ => return Err(invalid()),
+            };
+            entries.push(entry);
+        }
+        Ok(Mailmap { entries })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// 映射一个身份，没有匹配的条目时原样返回
+    pub fn map(&self, name: &str, email: &str) -> (String, String) {
+        let matches = |entry: &&Entry| entry.commit_email.eq_ignore_ascii_case(email);
+        let entry = self
+            .entries
+            .iter()
+            .filter(matches)
+            .find(|entry| entry.commit_name.as_deref() == Some(name))
+            .or_else(|| self.entries.iter().filter(matches).find(|entry| entry.commit_name.is_none()));
+        match entry {
+            Some(entry) => (
+                entry.proper_name.clone().unwrap_or_else(|| name.to_string()),
+                entry.proper_email.clone().unwrap_or_else(|| email.to_string()),
+            ),
+            None => (name.to_string(), email.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 测试四种条目格式、注释与按名字匹配的优先级
+    #[test]
+    fn test_mailmap() {
+        let mailmap = Mailmap::parse(
+            "# 团队成员\n\
+             Alice Liddell <alice@example.com>\n\
+             <bob@example.com> <bob@old.example.com>\n\
+             Carol <carol@example.com> <shared@example.com>\n\
+             Dave <dave@example.com> dave <SHARED@example.com>  # 共用邮箱\n",
+        )
+        .unwrap();
+        let map = |name: &str, email: &str| mailmap.map(name, email);
+        assert_eq!(map("alice", "Alice@Example.com"), ("Alice Liddell".to_string(), "Alice@Example.com".to_string()));
+        assert_eq!(map("Bob", "bob@old.example.com"), ("Bob".to_string(), "bob@example.com".to_string()));
+        assert_eq!(map("dave", "shared@example.com"), ("Dave".to_string(), "dave@example.com".to_string()));
+        assert_eq!(map("someone", "shared@example.com"), ("Carol".to_string(), "carol@example.com".to_string()));
+        assert_eq!(map("Eve", "eve@example.com"), ("Eve".to_string(), "eve@example.com".to_string()));
+        assert!(Mailmap::parse("Alice <alice@example.com").is_err());
+        assert!(Mailmap::parse("<a@example.com> <b@example.com> <c@example.com>").is_err());
+    }
+}
@@ -1,7 +1,4 @@
-mod cli;
-mod common;
-
-use crate::cli::parse;
+use monoengine::cli::parse;
 
 #[cfg(not(target_os = "windows"))]
 #[global_allocator]
@@ -14,8 +11,8 @@ static GLOBAL_ALLOCATOR: mimalloc::MiMalloc = mimalloc::MiMalloc;
 fn main() {
     let result = parse(None);
 
-    // If there was an error, print it
+    // If there was an error, print it and exit with its registered code
     if let Err(e) = result {
-        e.print();
+        e.exit();
     }
-}
\ No newline at end of file
+}
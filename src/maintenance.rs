@@ -0,0 +1,462 @@
+//! 后台维护
+//!
+//! 与 `git maintenance` 类似，按 `[maintenance]` 中的计划定期执行维护任务：
+//!
+//! - `incremental-repack`：把松散对象与较小的 pack 合并为一个 pack（仅 `fs` 后端，S3 与数据库
+//!   后端逐个保存对象，没有 pack 可整理），之后重新生成可达性位图
+//! - `commit-graph`：重新生成提交图
+//! - `bitmaps`：为引用指向的提交生成可达性位图
+//! - `cache-warm`：预读引用指向的提交与上层目录树，填充对象缓存（未启用缓存时跳过）
+//!
+//! 任务由 `mono maintenance start` 或 `mono serve --maintenance` 在前台进程中调度，
+//! 缓存预热只有在 `mono serve` 中执行才能让服务端的缓存受益。每次执行的结果记录在
+//! `.mono/maintenance/state.json`，到期判断以上次执行时间为准，进程重启后不会重复执行。
+
+use std::collections::{BTreeMap, HashSet};
+use std::path::PathBuf;
+use std::time::Instant;
+
+use chrono::{DateTime, Datelike, Duration, NaiveDate, TimeZone, Timelike, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::common::config::{MaintenanceConfig, StorageBackend};
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::graph::{self, bitmap};
+use crate::lock;
+use crate::object::tree::Tree;
+use crate::object::{ObjectFormat, ObjectId, ObjectType};
+use crate::queue::LockFile;
+use crate::repo::Repository;
+use crate::storage::fs::FsStore;
+
+/// 维护数据目录，相对于 `.mono`
+pub const MAINTENANCE_DIR: &str = "maintenance";
+/// 各任务最近一次执行的结果
+const STATE_FILE: &str = "state.json";
+/// 执行任务期间持有的锁，避免多个进程同时维护
+const RUN_LOCK_FILE: &str = "run.lock";
+/// 预热缓存时读取的目录树深度，根树为 0
+const WARM_DEPTH: usize = 2;
+
+/// 维护任务，按执行顺序排列
+#[derive(clap::ValueEnum, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "kebab-case")]
+pub enum MaintenanceTask {
+    /// 合并松散对象与较小的 pack
+    IncrementalRepack,
+    /// 重新生成提交图
+    CommitGraph,
+    /// 生成可达性位图
+    Bitmaps,
+    /// 预读常用对象填充缓存
+    CacheWarm,
+}
+
+impl MaintenanceTask {
+    pub const ALL: [MaintenanceTask; 4] = [
+        MaintenanceTask::IncrementalRepack,
+        MaintenanceTask::CommitGraph,
+        MaintenanceTask::Bitmaps,
+        MaintenanceTask::CacheWarm,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MaintenanceTask::IncrementalRepack => "incremental-repack",
+            MaintenanceTask::CommitGraph => "commit-graph",
+            MaintenanceTask::Bitmaps => "bitmaps",
+            MaintenanceTask::CacheWarm => "cache-warm",
+        }
+    }
+
+    /// 任务在配置中的计划
+    pub fn schedule<'c>(&self, config: &'c MaintenanceConfig) -> &'c str {
+        match self {
+            MaintenanceTask::IncrementalRepack => &config.incremental_repack,
+            MaintenanceTask::CommitGraph => &config.commit_graph,
+            MaintenanceTask::Bitmaps => &config.bitmaps,
+            MaintenanceTask::CacheWarm => &config.cache_warm,
+        }
+    }
+
+    /// 任务不适用于该仓库时返回原因
+    fn unsupported(&self, repo: &Repository) -> Option<&'static str> {
+        let storage = &repo.config().storage;
+        match self {
+            MaintenanceTask::IncrementalRepack if storage.backend != StorageBackend::Fs => {
+                Some("only the fs storage backend keeps packs")
+            }
+            MaintenanceTask::CacheWarm if storage.cache_size == 0 && storage.disk_cache_size == 0 => {
+                Some("the object cache is disabled")
+            }
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for MaintenanceTask {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// cron 风格的执行计划，时间按 UTC 计算
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Schedule {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// 日与周都有限制时满足其一即可，与 cron 一致
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl Schedule {
+    /// 解析五段 cron 表达式（分 时 日 月 周）或 `@hourly` 等简写，`off` 返回 None
+    ///
+    /// 每段支持 `*`、数字、`a-b` 范围、`,` 分隔的列表与 `/n` 步长；周日可写作 0 或 7。
+    pub fn parse(spec: &str) -> MonoResult<Option<Schedule>> {
+        let spec = match spec.trim() {
+            "off" => return Ok(None),
+            "@hourly" => "0 * * * *",
+            "@daily" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            spec => spec,
+        };
+        let invalid = |msg: &str| MonoError::config(format!("invalid schedule {:?}: {}", spec, msg));
+        let fields: Vec<&str> = spec.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(invalid("expected 5 fields: minute hour day month weekday"));
+        };
+        let field = |text: &str, min: u32, max: u32| parse_field(text, min, max).map_err(|msg| invalid(&msg));
+        let mut weekdays = field(weekday, 0, 7)?;
+        // 7 与 0 都表示周日
+        if weekdays & (1 << 7) != 0 {
+            weekdays |= 1;
+        }
+        Ok(Some(Schedule {
+            minutes: field(minute, 0, 59)?,
+            hours: field(hour, 0, 23)?,
+            days: field(day, 1, 31)?,
+            months: field(month, 1, 12)?,
+            weekdays,
+            any_day: day == "*",
+            any_weekday: weekday == "*",
+        }))
+    }
+
+    fn matches_day(&self, date: NaiveDate) -> bool {
+        let day = self.days & (1 << date.day()) != 0;
+        let weekday = self.weekdays & (1 << date.weekday().num_days_from_sunday()) != 0;
+        match (self.any_day, self.any_weekday) {
+            (false, false) => day || weekday,
+            _ => day && weekday,
+        }
+    }
+
+    /// `after` 之后（不含）第一个满足计划的整分钟，四年内都不满足（如 2 月 31 日）时返回 None
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let mut time = after.with_second(0)?.with_nanosecond(0)? + Duration::minutes(1);
+        let limit = time + Duration::days(4 * 366);
+        while time < limit {
+            let date = time.date_naive();
+            if self.months & (1 << date.month()) == 0 {
+                let (year, month) = if date.month() == 12 { (date.year() + 1, 1) } else { (date.year(), date.month() + 1) };
+                time = Utc.from_utc_datetime(&NaiveDate::from_ymd_opt(year, month, 1)?.and_hms_opt(0, 0, 0)?);
+            } else if !self.matches_day(date) {
+                time = Utc.from_utc_datetime(&date.succ_opt()?.and_hms_opt(0, 0, 0)?);
+            } else if self.hours & (1 << time.hour()) == 0 {
+                time = time.with_minute(0)? + Duration::hours(1);
+            } else if self.minutes & (1 << time.minute()) == 0 {
+                time += Duration::minutes(1);
+            } else {
+                return Some(time);
+            }
+        }
+        None
+    }
+}
+
+/// 解析 cron 表达式的一段，返回取值的位集合
+fn parse_field(text: &str, min: u32, max: u32) -> Result<u64, String> {
+    let mut bits = 0u64;
+    for part in text.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().map_err(|_| format!("invalid step in {:?}", part))?),
+            None => (part, 1),
+        };
+        let number = |s: &str| s.parse::<u32>().map_err(|_| format!("invalid value {:?}", s));
+        let (start, end) = match range {
+            "*" => (min, max),
+            range => match range.split_once('-') {
+                Some((start, end)) => (number(start)?, number(end)?),
+                // `5/15` 表示从 5 开始每 15 个
+                None if part.contains('/') => (number(range)?, max),
+                None => (number(range)?, number(range)?),
+            },
+        };
+        if step == 0 || start < min || end > max || start > end {
+            return Err(format!("{:?} is out of range {}-{}", part, min, max));
+        }
+        for value in (start..=end).step_by(step as usize) {
+            bits |= 1 << value;
+        }
+    }
+    Ok(bits)
+}
+
+/// 一次执行的结果
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RunStatus {
+    Ok,
+    /// 任务不适用于当前的存储配置
+    Skipped,
+    Failed,
+}
+
+impl RunStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RunStatus::Ok => "ok",
+            RunStatus::Skipped => "skipped",
+            RunStatus::Failed => "failed",
+        }
+    }
+}
+
+impl std::fmt::Display for RunStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// 任务最近一次执行的记录
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TaskRun {
+    /// 开始时间（Unix 秒）
+    pub started_at: i64,
+    pub duration_ms: u64,
+    pub status: RunStatus,
+    /// 执行摘要、跳过原因或错误信息
+    pub message: String,
+}
+
+/// 仓库的维护任务调度
+pub struct Maintenance<'r> {
+    repo: &'r Repository,
+    dir: PathBuf,
+}
+
+impl<'r> Maintenance<'r> {
+    pub fn new(repo: &'r Repository) -> Maintenance<'r> {
+        Maintenance {
+            repo,
+            dir: repo.mono_dir().join(MAINTENANCE_DIR),
+        }
+    }
+
+    /// 各任务最近一次执行的记录
+    pub fn last_runs(&self) -> MonoResult<BTreeMap<MaintenanceTask, TaskRun>> {
+        match std::fs::read(self.dir.join(STATE_FILE)) {
+            Ok(data) => serde_json::from_slice(&data).map_err(|e| MonoError::storage(format!("maintenance state: {}", e))),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// 任务下次到期的时间：从未执行过的任务立即到期，计划为 `off` 时返回 None
+    pub fn next_run(&self, task: MaintenanceTask, last: Option<&TaskRun>, now: DateTime<Utc>) -> MonoResult<Option<DateTime<Utc>>> {
+        let Some(schedule) = Schedule::parse(task.schedule(&self.repo.config().maintenance))? else {
+            return Ok(None);
+        };
+        let Some(last) = last else {
+            return Ok(Some(now));
+        };
+        let started = DateTime::from_timestamp(last.started_at, 0).unwrap_or(now);
+        Ok(schedule.next_after(started))
+    }
+
+    /// 在 `now` 时已到期的任务
+    pub fn due(&self, now: DateTime<Utc>) -> MonoResult<Vec<MaintenanceTask>> {
+        let runs = self.last_runs()?;
+        let mut due = Vec::new();
+        for task in MaintenanceTask::ALL {
+            if self.next_run(task, runs.get(&task), now)?.is_some_and(|next| next <= now) {
+                due.push(task);
+            }
+        }
+        Ok(due)
+    }
+
+    /// 执行到期的任务
+    pub fn run_due(&self, now: DateTime<Utc>) -> MonoResult<Vec<(MaintenanceTask, TaskRun)>> {
+        let due = self.due(now)?;
+        if due.is_empty() {
+            return Ok(Vec::new());
+        }
+        self.run(&due)
+    }
+
+    /// 依次执行任务并记录结果；单个任务失败不影响其他任务
+    ///
+    /// 同一时间只允许一个进程执行维护，其他进程正在执行时返回 `Unavailable` 错误。
+    pub fn run(&self, tasks: &[MaintenanceTask]) -> MonoResult<Vec<(MaintenanceTask, TaskRun)>> {
+        std::fs::create_dir_all(&self.dir)?;
+        let _lock = LockFile::acquire(self.dir.join(RUN_LOCK_FILE))?;
+        let mut runs = self.last_runs()?;
+        let mut results = Vec::new();
+        for &task in tasks {
+            let _span = tracing::info_span!("maintenance", task = task.as_str()).entered();
+            let started_at = Utc::now().timestamp();
+            let start = Instant::now();
+            let (status, message) = match task.unsupported(self.repo) {
+                Some(reason) => (RunStatus::Skipped, reason.to_string()),
+                None => match self.execute(task) {
+                    Ok(summary) => (RunStatus::Ok, summary),
+                    Err(e) => {
+                        tracing::warn!(error = %e, "maintenance task failed");
+                        (RunStatus::Failed, e.to_string())
+                    }
+                },
+            };
+            let run = TaskRun {
+                started_at,
+                duration_ms: start.elapsed().as_millis() as u64,
+                status,
+                message,
+            };
+            runs.insert(task, run.clone());
+            self.save(&runs)?;
+            results.push((task, run));
+        }
+        Ok(results)
+    }
+
+    fn execute(&self, task: MaintenanceTask) -> MonoResult<String> {
+        let repo = self.repo;
+        match task {
+            MaintenanceTask::IncrementalRepack => {
+                let _lock = repo.lock(lock::REPACK_LOCK)?;
+                let store = FsStore::new(repo.objects_dir())
+                    .with_format(repo.object_format())
+                    .with_codec(repo.config().storage.compression);
+                let config = &repo.config().maintenance;
+                let stats = match config.geometric_factor {
+                    Some(factor) => store.repack_geometric(factor)?,
+                    None => store.repack_incremental(config.max_packs)?,
+                };
+                // 重新打包后顺带更新位图，新写入的对象不必在每次 fetch 时遍历；位图只支持 SHA-1
+                let bitmaps = match repo.object_format() {
+                    ObjectFormat::Sha1 => bitmap::write_bitmaps(repo)?.len(),
+                    _ => 0,
+                };
+                Ok(format!(
+                    "packed {} loose objects and {} packs into {} objects, wrote {} bitmaps",
+                    stats.loose, stats.packs, stats.objects, bitmaps
+                ))
+            }
+            MaintenanceTask::CommitGraph => {
+                let graph = graph::write_commit_graph(repo)?;
+                Ok(format!("wrote commit-graph with {} commits", graph.len()))
+            }
+            MaintenanceTask::Bitmaps => {
+                let bitmaps = bitmap::write_bitmaps(repo)?;
+                Ok(format!("wrote {} bitmaps over {} objects", bitmaps.len(), bitmaps.object_count()))
+            }
+            MaintenanceTask::CacheWarm => Ok(format!("read {} objects", warm_cache(repo)?)),
+        }
+    }
+
+    fn save(&self, runs: &BTreeMap<MaintenanceTask, TaskRun>) -> MonoResult<()> {
+        let data = serde_json::to_vec_pretty(runs).map_err(|e| MonoError::storage(e.to_string()))?;
+        let path = self.dir.join(STATE_FILE);
+        let tmp = path.with_file_name(format!(".tmp-{}-{}", std::process::id(), STATE_FILE));
+        std::fs::write(&tmp, data)?;
+        std::fs::rename(&tmp, &path)?;
+        Ok(())
+    }
+}
+
+/// 读取引用指向的提交及其上层目录树，返回读取的对象数
+fn warm_cache(repo: &Repository) -> MonoResult<usize> {
+    let mut tips: Vec<ObjectId> = repo.refs().list("refs/")?.into_iter().map(|(_, id)| id).collect();
+    tips.extend(repo.head_commit()?);
+    let mut seen = HashSet::new();
+    let mut trees = Vec::new();
+    for tip in tips {
+        let (id, object_type) = repo.peel(&tip)?;
+        if object_type == ObjectType::Commit && seen.insert(id) {
+            trees.push((repo.read_commit(&id)?.tree, 0));
+        }
+    }
+    while let Some((id, depth)) = trees.pop() {
+        if !seen.insert(id) {
+            continue;
+        }
+        let tree = Tree::parse(&repo.read_object(&id)?.data, id.format())?;
+        if depth < WARM_DEPTH {
+            trees.extend(
+                tree.entries
+                    .iter()
+                    .filter(|entry| entry.mode.object_type() == Some(ObjectType::Tree))
+                    .map(|entry| (entry.id, depth + 1)),
+            );
+        }
+    }
+    Ok(seen.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{commit_files, init_repo};
+
+    fn time(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    /// 测试 cron 表达式的解析与下次执行时间的计算
+    #[test]
+    fn test_schedule() {
+        let next = |spec: &str, after: &str| Schedule::parse(spec).unwrap().unwrap().next_after(time(after)).unwrap();
+        assert_eq!(next("@hourly", "2024-01-31T23:59:30Z"), time("2024-02-01T00:00:00Z"));
+        assert_eq!(next("*/15 * * * *", "2024-01-01T10:15:00Z"), time("2024-01-01T10:30:00Z"));
+        assert_eq!(next("30 3 * * 1-5", "2024-01-05T04:00:00Z"), time("2024-01-08T03:30:00Z"));
+        assert_eq!(next("0 0 1 * 7", "2024-01-01T00:00:00Z"), time("2024-01-07T00:00:00Z"));
+        assert_eq!(next("0 12 29 2 *", "2024-03-01T00:00:00Z"), time("2028-02-29T12:00:00Z"));
+        assert_eq!(Schedule::parse("0 0 31 2 *").unwrap().unwrap().next_after(time("2024-01-01T00:00:00Z")), None);
+        assert_eq!(Schedule::parse("off").unwrap(), None);
+        assert!(Schedule::parse("60 * * * *").is_err());
+        assert!(Schedule::parse("* * *").is_err());
+    }
+
+    /// 测试到期任务的执行与记录，以及不适用于存储配置的任务被跳过
+    #[test]
+    fn test_run_due() {
+        let (_dir, mut repo) = init_repo();
+        let commit = commit_files(&repo, &[("src/lib.rs", b"fn main() {}")], &[], "first");
+        repo.refs().write("refs/heads/main", &commit).unwrap();
+        repo.config_mut().storage.cache_size = 0;
+        let maintenance = Maintenance::new(&repo);
+        let now = time("2024-01-01T10:00:00Z");
+        assert_eq!(maintenance.due(now).unwrap(), MaintenanceTask::ALL);
+
+        let results = maintenance.run_due(now).unwrap();
+        let status: Vec<RunStatus> = results.iter().map(|(_, run)| run.status).collect();
+        assert_eq!(status, [RunStatus::Ok, RunStatus::Ok, RunStatus::Ok, RunStatus::Skipped]);
+        assert!(bitmap::ReachabilityBitmaps::load(&repo).unwrap().unwrap().get(&commit).unwrap().is_some());
+
+        // 刚执行过，按计划在之后到期
+        let runs = maintenance.last_runs().unwrap();
+        assert_eq!(runs.len(), 4);
+        let started = DateTime::from_timestamp(runs[&MaintenanceTask::CommitGraph].started_at, 0).unwrap();
+        assert!(maintenance.due(started).unwrap().is_empty());
+        let next = maintenance.next_run(MaintenanceTask::CommitGraph, runs.get(&MaintenanceTask::CommitGraph), started);
+        assert_eq!(next.unwrap().unwrap().minute(), 0);
+    }
+}
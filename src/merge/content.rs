@@ -0,0 +1,196 @@
+//! 文本的三方合并
+//!
+//! 与 diff3 相同：分别比较共同祖先与两侧，三者都没有改动的行是稳定行，稳定行之间的区段逐个解决：
+//! 只有一侧改动时取改动的一侧，两侧改成相同内容时直接采用，否则写出冲突标记。标记的格式由
+//! [`ConflictStyle`] 决定。
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::diff::{intern, matches, split_lines, Algorithm};
+use crate::merge::MergeOptions;
+
+/// 冲突标记的长度，与 git 相同
+pub const MARKER_SIZE: usize = 7;
+
+/// 冲突标记的格式
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ConflictStyle {
+    /// 只写出两侧的内容，两侧相同的开头与结尾移到标记之外
+    #[default]
+    Merge,
+    /// 同时写出共同祖先的内容，两侧的内容保持原样
+    Diff3,
+    /// 同时写出共同祖先的内容，两侧相同的开头与结尾移到标记之外
+    Zdiff3,
+}
+
+impl ConflictStyle {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConflictStyle::Merge => "merge",
+            ConflictStyle::Diff3 => "diff3",
+            ConflictStyle::Zdiff3 => "zdiff3",
+        }
+    }
+}
+
+impl fmt::Display for ConflictStyle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(self.as_str())
+    }
+}
+
+/// 三方合并的结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContentMerge {
+    /// 合并后的内容，有冲突时包含冲突标记
+    pub data: Vec<u8>,
+    /// 冲突的区段数
+    pub conflicts: usize,
+}
+
+impl ContentMerge {
+    pub fn is_clean(&self) -> bool {
+        self.conflicts == 0
+    }
+}
+
+/// 以 `base` 为共同祖先合并 `ours` 与 `theirs` 两个版本的内容
+pub fn merge(base: &[u8], ours: &[u8], theirs: &[u8], options: &MergeOptions) -> ContentMerge {
+    let (b, o, t) = (split_lines(base), split_lines(ours), split_lines(theirs));
+    let ours_matched = base_matches(&b, &o, options.algorithm);
+    let theirs_matched = base_matches(&b, &t, options.algorithm);
+
+    let mut merged = ContentMerge {
+        data: Vec::with_capacity(ours.len().max(theirs.len())),
+        conflicts: 0,
+    };
+    let (mut i, mut j, mut k) = (0, 0, 0);
+    loop {
+        while i < b.len() && ours_matched[i] == Some(j) && theirs_matched[i] == Some(k) {
+            merged.data.extend_from_slice(b[i]);
+            (i, j, k) = (i + 1, j + 1, k + 1);
+        }
+        // 下一个稳定行，没有时区段延伸到结尾
+        let next = (i..b.len()).find_map(|x| Some((x, ours_matched[x]?, theirs_matched[x]?)));
+        let (ni, nj, nk) = next.unwrap_or((b.len(), o.len(), t.len()));
+        if (ni, nj, nk) == (i, j, k) {
+            break;
+        }
+        let (base, ours, theirs) = (&b[i..ni], &o[j..nj], &t[k..nk]);
+        if ours == base {
+            push_lines(&mut merged.data, theirs);
+        } else if theirs == base || ours == theirs {
+            push_lines(&mut merged.data, ours);
+        } else {
+            write_conflict(&mut merged.data, base, ours, theirs, options);
+            merged.conflicts += 1;
+        }
+        (i, j, k) = (ni, nj, nk);
+    }
+    merged
+}
+
+/// 共同祖先的每一行在另一个版本中未改动时对应的行
+fn base_matches(base: &[&[u8]], other: &[&[u8]], algorithm: Algorithm) -> Vec<Option<usize>> {
+    let (a, b) = intern(base, other);
+    let mut matched = vec![None; base.len()];
+    for (x, y) in matches(&a, &b, algorithm) {
+        matched[x] = Some(y);
+    }
+    matched
+}
+
+fn push_lines(out: &mut Vec<u8>, lines: &[&[u8]]) {
+    for line in lines {
+        out.extend_from_slice(line);
+    }
+}
+
+fn write_conflict(out: &mut Vec<u8>, base: &[&[u8]], ours: &[&[u8]], theirs: &[&[u8]], options: &MergeOptions) {
+    let (prefix, suffix) = match options.style {
+        ConflictStyle::Diff3 => (0, 0),
+        ConflictStyle::Merge | ConflictStyle::Zdiff3 => {
+            let prefix = ours.iter().zip(theirs).take_while(|(a, b)| a == b).count();
+            let suffix = ours[prefix..]
+                .iter()
+                .rev()
+                .zip(theirs[prefix..].iter().rev())
+                .take_while(|(a, b)| a == b)
+                .count();
+            (prefix, suffix)
+        }
+    };
+    push_lines(out, &ours[..prefix]);
+    push_marker(out, b'<', &options.ours_label);
+    push_lines(out, &ours[prefix..ours.len() - suffix]);
+    if options.style != ConflictStyle::Merge {
+        push_marker(out, b'|', &options.base_label);
+        push_lines(out, base);
+    }
+    push_marker(out, b'=', "");
+    push_lines(out, &theirs[prefix..theirs.len() - suffix]);
+    push_marker(out, b'>', &options.theirs_label);
+    push_lines(out, &ours[ours.len() - suffix..]);
+}
+
+/// 写出一行冲突标记；前面的内容没有以换行符结尾时先补上换行符
+fn push_marker(out: &mut Vec<u8>, marker: u8, label: &str) {
+    if out.last().is_some_and(|&b| b != b'\n') {
+        out.push(b'\n');
+    }
+    out.extend(std::iter::repeat_n(marker, MARKER_SIZE));
+    if !label.is_empty() {
+        out.push(b' ');
+        out.extend_from_slice(label.as_bytes());
+    }
+    out.push(b'\n');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 测试不重叠的改动自动合并，以及三种格式的冲突标记
+    #[test]
+    fn test_merge() {
+        let options = MergeOptions::default();
+        let base = b"a\nb\nc\nd\ne\n";
+        let clean = merge(base, b"A\nb\nc\nd\ne\n", b"a\nb\nc\nd\nE\nf\n", &options);
+        assert!(clean.is_clean());
+        assert_eq!(clean.data, b"A\nb\nc\nd\nE\nf\n");
+        // 两侧改成相同的内容不算冲突
+        assert!(merge(base, b"a\nx\nc\nd\ne\n", b"a\nx\nc\nd\ne\n", &options).is_clean());
+
+        let (ours, theirs) = (b"a\nx\ny\nz\nd\ne\n", b"a\nx\nw\nz\nd\ne\n");
+        let conflict = merge(base, ours, theirs, &options);
+        assert_eq!(conflict.conflicts, 1);
+        assert_eq!(
+            String::from_utf8(conflict.data).unwrap(),
+            "a\nx\n<<<<<<< ours\ny\n=======\nw\n>>>>>>> theirs\nz\nd\ne\n"
+        );
+        let diff3 = MergeOptions {
+            style: ConflictStyle::Diff3,
+            ..MergeOptions::default()
+        };
+        assert_eq!(
+            String::from_utf8(merge(base, ours, theirs, &diff3).data).unwrap(),
+            "a\n<<<<<<< ours\nx\ny\nz\n||||||| base\nb\nc\n=======\nx\nw\nz\n>>>>>>> theirs\nd\ne\n"
+        );
+        let zdiff3 = MergeOptions {
+            style: ConflictStyle::Zdiff3,
+            ..MergeOptions::default()
+        };
+        assert_eq!(
+            String::from_utf8(merge(base, ours, theirs, &zdiff3).data).unwrap(),
+            "a\nx\n<<<<<<< ours\ny\n||||||| base\nb\nc\n=======\nw\n>>>>>>> theirs\nz\nd\ne\n"
+        );
+
+        // 没有换行符的最后一行在标记前补上换行符
+        let tail = merge(b"1", b"2", b"3", &options);
+        assert_eq!(tail.data, b"<<<<<<< ours\n2\n=======\n3\n>>>>>>> theirs\n");
+    }
+}
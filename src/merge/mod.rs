@@ -0,0 +1,561 @@
+//! 三方合并
+//!
+//! 不需要工作区：直接在对象库中合并三棵树，结果写为新的树对象，冲突的文件以带冲突标记的
+//! 内容写入结果树，同时在 [`MergeResult::conflicts`] 中列出，与 `git merge-tree --write-tree`
+//! 相同。合并分两步：
+//!
+//! 1. 分别比较共同祖先与两侧（见 [`crate::diff::tree`]），检测重命名，按共同祖先中的路径
+//!    对齐两侧的改动：只有一侧改动的文件取改动的一侧，一侧重命名时另一侧的修改跟随到新路径；
+//! 2. 两侧都修改的文本文件逐行合并（见 [`content`]），二进制文件与子模块直接报告冲突。
+//!
+//! 以我方的树为基础写入对方的改动，未改动的子树不会被读取。文件与目录互相占位时，占位的
+//! 文件移到 `<路径>~<标签>`。
+//!
+//! 合并队列与服务端合并按 `mono.toml` 的 `[merge]` 配置段设置选项（见 [`MergeOptions::from_config`]）：
+//!
+//! ```toml
+//! [merge]
+//! conflict_style = "zdiff3"
+//! algorithm = "histogram"
+//! renames = true
+//! rename_threshold = 50
+//! ```
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::common::config::MergeConfig;
+use crate::common::MonoResult;
+use crate::diff::tree::{diff_trees, is_binary, ChangeKind, DiffOptions, FileVersion};
+use crate::diff::Algorithm;
+use crate::graph::history::History;
+use crate::object::tree::{FileMode, Tree, TreeEdit};
+use crate::object::{ObjectId, ObjectType};
+use crate::repo::Repository;
+
+pub mod content;
+
+pub use content::ConflictStyle;
+
+/// 合并选项
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MergeOptions {
+    /// 逐行合并使用的差异算法
+    pub algorithm: Algorithm,
+    pub style: ConflictStyle,
+    /// 检测重命名
+    pub find_renames: bool,
+    /// 重命名的相似度阈值（百分比）
+    pub rename_threshold: u8,
+    /// 冲突标记中我方、共同祖先与对方的标签
+    pub ours_label: String,
+    pub base_label: String,
+    pub theirs_label: String,
+}
+
+impl Default for MergeOptions {
+    fn default() -> Self {
+        MergeOptions {
+            algorithm: Algorithm::default(),
+            style: ConflictStyle::default(),
+            find_renames: true,
+            rename_threshold: DiffOptions::default().rename_threshold,
+            ours_label: "ours".to_string(),
+            base_label: "base".to_string(),
+            theirs_label: "theirs".to_string(),
+        }
+    }
+}
+
+impl MergeOptions {
+    /// 按 `[merge]` 配置段设置算法、冲突格式与重命名检测
+    pub fn from_config(config: &MergeConfig) -> MergeOptions {
+        MergeOptions {
+            algorithm: config.algorithm,
+            style: config.conflict_style,
+            find_renames: config.renames,
+            rename_threshold: config.rename_threshold,
+            ..MergeOptions::default()
+        }
+    }
+
+    fn diff_options(&self) -> DiffOptions {
+        DiffOptions {
+            algorithm: self.algorithm,
+            find_renames: self.find_renames,
+            find_copies: false,
+            rename_threshold: self.rename_threshold,
+            ..DiffOptions::default()
+        }
+    }
+}
+
+/// 冲突的类型
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConflictKind {
+    /// 两侧修改了文本文件的同一处，结果中包含冲突标记
+    Content,
+    /// 两侧修改了同一个二进制文件或子模块，结果中保留我方的版本
+    Binary,
+    /// 两侧把文件模式改成了不同的值，结果中保留我方的模式
+    Mode,
+    /// 两侧在同一路径新增了不同的文件
+    AddAdd,
+    /// 一侧修改、另一侧删除，结果中保留修改后的版本
+    ModifyDelete,
+    /// 一侧重命名、另一侧删除，结果中保留重命名后的版本
+    RenameDelete,
+    /// 两侧把同一个文件重命名到不同路径，结果中两个路径都保留
+    RenameRename,
+    /// 一侧的文件与另一侧的目录同名，文件移到 `other_path`
+    FileDirectory,
+}
+
+impl ConflictKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConflictKind::Content => "content",
+            ConflictKind::Binary => "binary",
+            ConflictKind::Mode => "mode",
+            ConflictKind::AddAdd => "add-add",
+            ConflictKind::ModifyDelete => "modify-delete",
+            ConflictKind::RenameDelete => "rename-delete",
+            ConflictKind::RenameRename => "rename-rename",
+            ConflictKind::FileDirectory => "file-directory",
+        }
+    }
+}
+
+impl fmt::Display for ConflictKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(self.as_str())
+    }
+}
+
+/// 一处冲突
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    pub kind: ConflictKind,
+    /// 结果树中冲突的路径
+    pub path: String,
+    /// 涉及的另一个路径：重命名到的另一个路径，或被移开的文件的新路径
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub other_path: Option<String>,
+}
+
+/// 三方合并的结果
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct MergeResult {
+    /// 合并后的树，冲突的文件以带冲突标记的内容写入
+    pub tree: ObjectId,
+    /// 按路径排序的冲突
+    pub conflicts: Vec<Conflict>,
+}
+
+impl MergeResult {
+    pub fn is_clean(&self) -> bool {
+        self.conflicts.is_empty()
+    }
+
+    /// 冲突的路径，按路径排序并去重
+    pub fn conflict_paths(&self) -> Vec<String> {
+        let paths: BTreeSet<&String> = self.conflicts.iter().map(|conflict| &conflict.path).collect();
+        paths.into_iter().cloned().collect()
+    }
+}
+
+/// 以 `base` 为共同祖先合并 `ours` 与 `theirs` 三棵树，None 表示空树
+pub fn merge_trees(
+    repo: &Repository,
+    base: Option<&ObjectId>,
+    ours: Option<&ObjectId>,
+    theirs: Option<&ObjectId>,
+    options: &MergeOptions,
+) -> MonoResult<MergeResult> {
+    let clean = |tree: Option<&ObjectId>| -> MonoResult<MergeResult> {
+        let tree = match tree {
+            Some(tree) => *tree,
+            None => repo.write_object(ObjectType::Tree, &Tree::default().encode())?,
+        };
+        Ok(MergeResult {
+            tree,
+            conflicts: Vec::new(),
+        })
+    };
+    if ours == theirs || base == theirs {
+        return clean(ours);
+    }
+    if base == ours {
+        return clean(theirs);
+    }
+    let mut merger = TreeMerger {
+        repo,
+        ours,
+        options,
+        edits: BTreeMap::new(),
+        conflicts: Vec::new(),
+    };
+    merger.merge(base, theirs)?;
+    let TreeMerger { edits, mut conflicts, .. } = merger;
+    let edits: Vec<TreeEdit> = edits.into_iter().map(|(path, entry)| TreeEdit { path, entry }).collect();
+    let tree = repo.edit_tree(ours, &edits)?;
+    conflicts.sort_by(|a, b| a.path.cmp(&b.path).then(a.other_path.cmp(&b.other_path)));
+    let mut result = clean(tree.as_ref())?;
+    result.conflicts = conflicts;
+    Ok(result)
+}
+
+/// 合并两个提交的树，共同祖先由提交历史求出
+///
+/// 有多个最近公共祖先（交叉合并）时，与 git 的 ort 策略相同，先把它们合并为一棵虚拟的
+/// 共同祖先树，其中的冲突保留冲突标记；没有公共历史时以空树为共同祖先。
+pub fn merge_commits(repo: &Repository, ours: &ObjectId, theirs: &ObjectId, options: &MergeOptions) -> MonoResult<MergeResult> {
+    let history = History::new(repo)?;
+    let bases = history.merge_bases(ours, theirs)?;
+    let base = virtual_base(repo, &history, &bases, options)?;
+    let ours = repo.read_commit(ours)?.tree;
+    let theirs = repo.read_commit(theirs)?.tree;
+    merge_trees(repo, base.as_ref(), Some(&ours), Some(&theirs), options)
+}
+
+fn virtual_base(
+    repo: &Repository,
+    history: &History,
+    bases: &[ObjectId],
+    options: &MergeOptions,
+) -> MonoResult<Option<ObjectId>> {
+    let Some((first, rest)) = bases.split_first() else {
+        return Ok(None);
+    };
+    let mut tree = repo.read_commit(first)?.tree;
+    for base in rest {
+        let inner = virtual_base(repo, history, &history.merge_bases(first, base)?, options)?;
+        let other = repo.read_commit(base)?.tree;
+        tree = merge_trees(repo, inner.as_ref(), Some(&tree), Some(&other), options)?.tree;
+    }
+    Ok(Some(tree))
+}
+
+/// 一侧相对共同祖先的改动
+#[derive(Default)]
+struct SideChanges {
+    /// 共同祖先中的路径到改动后的版本，None 表示删除
+    changed: BTreeMap<String, Option<FileVersion>>,
+    /// 共同祖先的版本
+    base: BTreeMap<String, FileVersion>,
+    /// 新增的文件
+    added: Vec<FileVersion>,
+}
+
+impl SideChanges {
+    fn new(repo: &Repository, base: Option<&ObjectId>, side: Option<&ObjectId>, options: &MergeOptions) -> MonoResult<SideChanges> {
+        let mut changes = SideChanges::default();
+        for change in diff_trees(repo, base, side, &options.diff_options())? {
+            match change.old {
+                Some(old) if change.kind != ChangeKind::Copied => {
+                    changes.changed.insert(old.path.clone(), change.new);
+                    changes.base.insert(old.path.clone(), old);
+                }
+                _ => changes.added.extend(change.new),
+            }
+        }
+        Ok(changes)
+    }
+}
+
+/// 在我方的树上累积对方的改动
+struct TreeMerger<'a> {
+    repo: &'a Repository,
+    ours: Option<&'a ObjectId>,
+    options: &'a MergeOptions,
+    /// 相对我方树的修改，None 表示删除
+    edits: BTreeMap<String, Option<(FileMode, ObjectId)>>,
+    conflicts: Vec<Conflict>,
+}
+
+impl TreeMerger<'_> {
+    fn merge(&mut self, base: Option<&ObjectId>, theirs: Option<&ObjectId>) -> MonoResult<()> {
+        let ours_changes = SideChanges::new(self.repo, base, self.ours, self.options)?;
+        let theirs_changes = SideChanges::new(self.repo, base, theirs, self.options)?;
+
+        // 先处理原地的修改与删除，再写入新路径上的文件，以便判断路径是否已被腾空
+        let mut placements: Vec<FileVersion> = Vec::new();
+        for (path, theirs) in &theirs_changes.changed {
+            let base = &theirs_changes.base[path];
+            let Some(ours) = ours_changes.changed.get(path) else {
+                // 只有对方改动：我方的树中仍是共同祖先的版本
+                match theirs {
+                    Some(theirs) if theirs.path == *path => {
+                        self.edits.insert(path.clone(), Some((theirs.mode, theirs.id)));
+                    }
+                    _ => {
+                        self.edits.insert(path.clone(), None);
+                        placements.extend(theirs.clone());
+                    }
+                }
+                continue;
+            };
+            match (ours, theirs) {
+                (None, None) => {}
+                (Some(ours), None) => {
+                    let kind = if ours.path == *path { ConflictKind::ModifyDelete } else { ConflictKind::RenameDelete };
+                    self.conflict(kind, &ours.path, None);
+                }
+                (None, Some(theirs)) => {
+                    let kind = if theirs.path == *path { ConflictKind::ModifyDelete } else { ConflictKind::RenameDelete };
+                    self.conflict(kind, &theirs.path, None);
+                    placements.push(theirs.clone());
+                }
+                (Some(ours), Some(theirs)) if ours.path != theirs.path && ours.path != *path && theirs.path != *path => {
+                    self.conflict(ConflictKind::RenameRename, &ours.path, Some(&theirs.path));
+                    placements.push(theirs.clone());
+                }
+                (Some(ours), Some(theirs)) => {
+                    let (mode, id) = self.merge_file(base, ours, theirs)?;
+                    if theirs.path == ours.path || theirs.path == *path {
+                        if (mode, id) != (ours.mode, ours.id) {
+                            self.edits.insert(ours.path.clone(), Some((mode, id)));
+                        }
+                    } else {
+                        // 对方重命名：我方修改后的内容跟随到新路径
+                        self.edits.insert(ours.path.clone(), None);
+                        placements.push(FileVersion {
+                            path: theirs.path.clone(),
+                            mode,
+                            id,
+                        });
+                    }
+                }
+            }
+        }
+        placements.extend(theirs_changes.added);
+        for version in placements {
+            self.place(version)?;
+        }
+        self.drop_replaced_directories();
+        Ok(())
+    }
+
+    /// 两侧都修改的文件：分别合并文件模式与内容
+    fn merge_file(&mut self, base: &FileVersion, ours: &FileVersion, theirs: &FileVersion) -> MonoResult<(FileMode, ObjectId)> {
+        let mode = if ours.mode == theirs.mode || base.mode == theirs.mode {
+            ours.mode
+        } else if base.mode == ours.mode {
+            theirs.mode
+        } else {
+            self.conflict(ConflictKind::Mode, &ours.path, None);
+            ours.mode
+        };
+        let id = if ours.id == theirs.id || base.id == theirs.id {
+            ours.id
+        } else if base.id == ours.id {
+            theirs.id
+        } else {
+            self.merge_content(Some(base), ours, theirs, ConflictKind::Content)?
+        };
+        Ok((mode, id))
+    }
+
+    /// 逐行合并两侧的内容，冲突时记为 `kind`；二进制文件与子模块保留我方的版本
+    fn merge_content(
+        &mut self,
+        base: Option<&FileVersion>,
+        ours: &FileVersion,
+        theirs: &FileVersion,
+        kind: ConflictKind,
+    ) -> MonoResult<ObjectId> {
+        if [Some(ours), Some(theirs), base].into_iter().flatten().any(|version| version.mode.is_gitlink()) {
+            self.conflict(ConflictKind::Binary, &ours.path, None);
+            return Ok(ours.id);
+        }
+        let read = |version: Option<&FileVersion>| -> MonoResult<Vec<u8>> {
+            match version {
+                Some(version) => Ok(self.repo.read_object(&version.id)?.data),
+                None => Ok(Vec::new()),
+            }
+        };
+        let (base_data, ours_data, theirs_data) = (read(base)?, read(Some(ours))?, read(Some(theirs))?);
+        if is_binary(&base_data) || is_binary(&ours_data) || is_binary(&theirs_data) {
+            self.conflict(ConflictKind::Binary, &ours.path, None);
+            return Ok(ours.id);
+        }
+        let merged = content::merge(&base_data, &ours_data, &theirs_data, self.options);
+        if !merged.is_clean() {
+            self.conflict(kind, &ours.path, None);
+        }
+        self.repo.write_object(ObjectType::Blob, &merged.data)
+    }
+
+    /// 在结果中写入对方的文件，路径已被占用时按新增冲突处理
+    fn place(&mut self, version: FileVersion) -> MonoResult<()> {
+        if let Some(current) = self.current(&version.path)? {
+            if (current.mode, current.id) == (version.mode, version.id) {
+                return Ok(());
+            }
+            let id = self.merge_content(None, &current, &version, ConflictKind::AddAdd)?;
+            self.edits.insert(version.path, Some((current.mode, id)));
+            return Ok(());
+        }
+
+        // 路径上的某一级在我方是文件：把文件移开
+        let mut end = 0;
+        while let Some(offset) = version.path[end..].find('/') {
+            end += offset;
+            let prefix = &version.path[..end];
+            if let Some(current) = self.current(prefix)? {
+                let moved = self.free_path(&format!("{}~{}", prefix, self.options.ours_label))?;
+                self.edits.insert(prefix.to_string(), None);
+                self.edits.insert(moved.clone(), Some((current.mode, current.id)));
+                self.conflict(ConflictKind::FileDirectory, prefix, Some(&moved));
+            }
+            end += 1;
+        }
+
+        // 路径在我方是仍有文件的目录：把对方的文件移开
+        if self.has_files_under(&version.path)? {
+            let moved = self.free_path(&format!("{}~{}", version.path, self.options.theirs_label))?;
+            self.conflict(ConflictKind::FileDirectory, &version.path, Some(&moved));
+            self.edits.insert(moved, Some((version.mode, version.id)));
+            return Ok(());
+        }
+        self.edits.insert(version.path, Some((version.mode, version.id)));
+        Ok(())
+    }
+
+    /// 结果中 `path` 处的文件，考虑已累积的修改
+    fn current(&self, path: &str) -> MonoResult<Option<FileVersion>> {
+        if let Some(entry) = self.edits.get(path) {
+            return Ok(entry.map(|(mode, id)| FileVersion {
+                path: path.to_string(),
+                mode,
+                id,
+            }));
+        }
+        let Some(ours) = self.ours else { return Ok(None) };
+        Ok(self
+            .repo
+            .find_path(ours, path)?
+            .filter(|entry| !entry.mode.is_tree())
+            .map(|entry| FileVersion {
+                path: path.to_string(),
+                mode: entry.mode,
+                id: entry.id,
+            }))
+    }
+
+    /// 结果中 `path` 是否为仍有文件的目录
+    fn has_files_under(&self, path: &str) -> MonoResult<bool> {
+        let prefix = format!("{}/", path);
+        if self.edits.range(prefix.clone()..).take_while(|(p, _)| p.starts_with(&prefix)).any(|(_, e)| e.is_some()) {
+            return Ok(true);
+        }
+        let Some(ours) = self.ours else { return Ok(false) };
+        let Some(dir) = self.repo.find_path(ours, path)?.filter(|entry| entry.mode.is_tree()) else {
+            return Ok(false);
+        };
+        for file in self.repo.changed_paths(Some(&dir.id), None)? {
+            if self.edits.get(&format!("{}{}", prefix, file)) != Some(&None) {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// 在 `path` 后追加序号，直到结果中没有同名的文件或目录
+    fn free_path(&self, path: &str) -> MonoResult<String> {
+        let mut candidate = path.to_string();
+        let mut n = 0;
+        while self.current(&candidate)?.is_some() || self.has_files_under(&candidate)? {
+            n += 1;
+            candidate = format!("{}_{}", path, n);
+        }
+        Ok(candidate)
+    }
+
+    /// 目录被同名文件替换时，去掉对目录中文件的删除；[`Repository::edit_tree`] 替换条目时已一并删除
+    fn drop_replaced_directories(&mut self) {
+        let files: Vec<String> = self.edits.iter().filter(|(_, e)| e.is_some()).map(|(p, _)| format!("{}/", p)).collect();
+        for prefix in files {
+            self.edits.retain(|path, entry| entry.is_some() || !path.starts_with(&prefix));
+        }
+    }
+
+    fn conflict(&mut self, kind: ConflictKind, path: &str, other_path: Option<&str>) {
+        self.conflicts.push(Conflict {
+            kind,
+            path: path.to_string(),
+            other_path: other_path.map(str::to_string),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{commit_files, init_repo};
+
+    /// 测试内容级合并、重命名跟随、各类冲突的记录，以及冲突标记写入结果树
+    #[test]
+    fn test_merge_trees() {
+        let (_dir, repo) = init_repo();
+        let body: String = (1..=10).map(|i| format!("line {}\n", i)).collect();
+        let tree = |files: &[(&str, &[u8])]| {
+            let commit = commit_files(&repo, files, &[], &format!("{:?}", files));
+            repo.read_commit(&commit).unwrap().tree
+        };
+        let read = |tree: &ObjectId, path: &str| -> Option<String> {
+            let entry = repo.find_path(tree, path).unwrap()?;
+            Some(String::from_utf8(repo.read_object(&entry.id).unwrap().data).unwrap())
+        };
+
+        let base = tree(&[("a.txt", body.as_bytes()), ("gone", b"1\n"), ("keep", b"1\n"), ("d/x", b"1\n")]);
+        let ours_body = body.replace("line 2\n", "line two\n");
+        let ours = tree(&[("a.txt", ours_body.as_bytes()), ("gone", b"2\n"), ("keep", b"1\n"), ("d/x", b"1\n"), ("new", b"ours\n")]);
+        // 对方重命名 a.txt 并修改另一处，删除我方修改过的文件，把目录 d 换成文件
+        let theirs_body = body.replace("line 9\n", "line nine\n");
+        let theirs = tree(&[("b.txt", theirs_body.as_bytes()), ("keep", b"1\n"), ("d", b"file\n"), ("new", b"theirs\n")]);
+
+        let result = merge_trees(&repo, Some(&base), Some(&ours), Some(&theirs), &MergeOptions::default()).unwrap();
+        let summary: Vec<(ConflictKind, &str)> = result.conflicts.iter().map(|c| (c.kind, c.path.as_str())).collect();
+        assert_eq!(summary, vec![(ConflictKind::ModifyDelete, "gone"), (ConflictKind::AddAdd, "new")]);
+        assert_eq!(result.conflict_paths(), ["gone", "new"]);
+        assert_eq!(read(&result.tree, "a.txt"), None);
+        assert_eq!(read(&result.tree, "b.txt").unwrap(), body.replace("line 2\n", "line two\n").replace("line 9\n", "line nine\n"));
+        assert_eq!(read(&result.tree, "gone").unwrap(), "2\n");
+        assert_eq!(read(&result.tree, "d").unwrap(), "file\n");
+        assert_eq!(read(&result.tree, "new").unwrap(), "<<<<<<< ours\nours\n=======\ntheirs\n>>>>>>> theirs\n");
+
+        // 我方仍在目录 d 中新增文件时，对方的文件 d 移开
+        let ours = tree(&[("a.txt", body.as_bytes()), ("gone", b"1\n"), ("keep", b"1\n"), ("d/x", b"1\n"), ("d/y", b"2\n")]);
+        let result = merge_trees(&repo, Some(&base), Some(&ours), Some(&theirs), &MergeOptions::default()).unwrap();
+        let clash = result.conflicts.iter().find(|c| c.kind == ConflictKind::FileDirectory).unwrap();
+        assert_eq!((clash.path.as_str(), clash.other_path.as_deref()), ("d", Some("d~theirs")));
+        assert_eq!(read(&result.tree, "d~theirs").unwrap(), "file\n");
+        assert_eq!(read(&result.tree, "d/y").unwrap(), "2\n");
+
+        // 一侧没有改动时直接采用另一侧
+        let result = merge_trees(&repo, Some(&base), Some(&base), Some(&theirs), &MergeOptions::default()).unwrap();
+        assert!(result.is_clean());
+        assert_eq!(result.tree, theirs);
+    }
+
+    /// 测试按提交合并：以最近公共祖先为共同祖先，没有公共历史时以空树为共同祖先
+    #[test]
+    fn test_merge_commits() {
+        let (_dir, repo) = init_repo();
+        let base = commit_files(&repo, &[("f", b"a\nb\nc\n")], &[], "base");
+        let ours = commit_files(&repo, &[("f", b"A\nb\nc\n")], &[base], "ours");
+        let theirs = commit_files(&repo, &[("f", b"a\nb\nC\n")], &[base], "theirs");
+        let result = merge_commits(&repo, &ours, &theirs, &MergeOptions::default()).unwrap();
+        assert!(result.is_clean());
+        let f = repo.find_path(&result.tree, "f").unwrap().unwrap();
+        assert_eq!(repo.read_object(&f.id).unwrap().data, b"A\nb\nC\n");
+
+        let unrelated = commit_files(&repo, &[("f", b"x\n")], &[], "unrelated");
+        let result = merge_commits(&repo, &ours, &unrelated, &MergeOptions::default()).unwrap();
+        assert_eq!(result.conflicts[0].kind, ConflictKind::AddAdd);
+    }
+}
@@ -0,0 +1,287 @@
+//! Prometheus 指标
+//!
+//! 服务端与存储层在处理请求时更新进程内的指标，`mono serve --http` 在 `/metrics` 以
+//! Prometheus 文本格式导出（与其他 HTTP 接口一样需要 `read` 权限）：
+//!
+//! - `mono_requests_total` 与 `mono_request_duration_seconds`：按协议、操作与结果统计的请求数与耗时
+//! - `mono_pack_bytes_served_total` 与 `mono_pack_objects_served_total`：fetch 发送的 pack 大小与对象数
+//! - `mono_pack_bytes_received_total`：推送收到的 pack 大小
+//! - `mono_object_cache_requests_total`：对象缓存各层（`memory`、`disk`）的命中与未命中次数，
+//!   命中率为二者之比
+//! - `mono_object_read_duration_seconds`：缓存未命中时从存储后端读取对象的耗时
+//! - `mono_replication_lag_seconds` 与 `mono_replication_refs_behind`：各镜像最早一次尚未复制的
+//!   修改距今的秒数与落后的引用数（见 [`crate::replication`]）
+//! - `mono_rate_limit_requests_total` 与 `mono_rate_limit_clients`：各限流维度放行与拒绝的请求数，
+//!   以及正在计数的客户端数（见 [`crate::server::ratelimit`]）
+//! - `mono_admission_requests_total` 与 `mono_admission_large_fetches`：fetch 准入控制的结果，
+//!   以及正在执行的大请求数（见 [`crate::server::admission`]）
+//!
+//! 指标由 `metrics` feature 控制（默认开启），关闭后这里的函数都是空操作，
+//! 不链接 Prometheus 客户端，`/metrics` 也不会注册。
+
+use std::time::Duration;
+
+#[cfg(feature = "metrics")]
+mod imp {
+    use std::sync::LazyLock;
+    use std::time::Duration;
+
+    use prometheus::{
+        Encoder, Histogram, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry,
+        TextEncoder,
+    };
+
+    struct Metrics {
+        registry: Registry,
+        requests: IntCounterVec,
+        request_duration: HistogramVec,
+        pack_bytes_served: IntCounter,
+        pack_objects_served: IntCounter,
+        pack_bytes_received: IntCounter,
+        object_cache: IntCounterVec,
+        object_read_duration: Histogram,
+        replication_lag: IntGaugeVec,
+        replication_refs_behind: IntGaugeVec,
+        rate_limit_requests: IntCounterVec,
+        rate_limit_clients: IntGaugeVec,
+        admission_requests: IntCounterVec,
+        admission_large_fetches: IntGauge,
+    }
+
+    impl Metrics {
+        fn new() -> prometheus::Result<Metrics> {
+            let registry = Registry::new();
+            let requests = IntCounterVec::new(
+                Opts::new("mono_requests_total", "Requests handled, by protocol, operation and status"),
+                &["protocol", "operation", "status"],
+            )?;
+            let request_duration = HistogramVec::new(
+                HistogramOpts::new("mono_request_duration_seconds", "Request latency in seconds"),
+                &["protocol", "operation"],
+            )?;
+            let pack_bytes_served = IntCounter::new("mono_pack_bytes_served_total", "Bytes of pack data sent to fetching clients")?;
+            let pack_objects_served = IntCounter::new("mono_pack_objects_served_total", "Objects sent to fetching clients")?;
+            let pack_bytes_received = IntCounter::new("mono_pack_bytes_received_total", "Bytes of pack data received from pushes")?;
+            let object_cache = IntCounterVec::new(
+                Opts::new("mono_object_cache_requests_total", "Object cache lookups, by tier and result"),
+                &["tier", "result"],
+            )?;
+            let object_read_duration = Histogram::with_opts(
+                HistogramOpts::new("mono_object_read_duration_seconds", "Latency of object reads that missed the cache")
+                    .buckets(prometheus::exponential_buckets(0.0001, 4.0, 8)?),
+            )?;
+            let replication_lag = IntGaugeVec::new(
+                Opts::new("mono_replication_lag_seconds", "Age of the oldest ref update not yet replicated, by mirror"),
+                &["mirror"],
+            )?;
+            let replication_refs_behind = IntGaugeVec::new(
+                Opts::new("mono_replication_refs_behind", "Refs that differ between the primary and a mirror"),
+                &["mirror"],
+            )?;
+            let rate_limit_requests = IntCounterVec::new(
+                Opts::new("mono_rate_limit_requests_total", "Requests checked against a rate limit, by dimension and result"),
+                &["dimension", "result"],
+            )?;
+            let rate_limit_clients = IntGaugeVec::new(
+                Opts::new("mono_rate_limit_clients", "Clients with a token bucket being tracked, by dimension"),
+                &["dimension"],
+            )?;
+            let admission_requests = IntCounterVec::new(
+                Opts::new("mono_admission_requests_total", "Fetches checked by admission control, by decision"),
+                &["decision"],
+            )?;
+            let admission_large_fetches = IntGauge::new("mono_admission_large_fetches", "Large fetches currently being served")?;
+            registry.register(Box::new(requests.clone()))?;
+            registry.register(Box::new(request_duration.clone()))?;
+            registry.register(Box::new(pack_bytes_served.clone()))?;
+            registry.register(Box::new(pack_objects_served.clone()))?;
+            registry.register(Box::new(pack_bytes_received.clone()))?;
+            registry.register(Box::new(object_cache.clone()))?;
+            registry.register(Box::new(object_read_duration.clone()))?;
+            registry.register(Box::new(replication_lag.clone()))?;
+            registry.register(Box::new(replication_refs_behind.clone()))?;
+            registry.register(Box::new(rate_limit_requests.clone()))?;
+            registry.register(Box::new(rate_limit_clients.clone()))?;
+            registry.register(Box::new(admission_requests.clone()))?;
+            registry.register(Box::new(admission_large_fetches.clone()))?;
+            Ok(Metrics {
+                registry,
+                requests,
+                request_duration,
+                pack_bytes_served,
+                pack_objects_served,
+                pack_bytes_received,
+                object_cache,
+                object_read_duration,
+                replication_lag,
+                replication_refs_behind,
+                rate_limit_requests,
+                rate_limit_clients,
+                admission_requests,
+                admission_large_fetches,
+            })
+        }
+    }
+
+    static METRICS: LazyLock<Metrics> = LazyLock::new(|| Metrics::new().expect("metric definitions are valid"));
+
+    pub fn observe_request(protocol: &str, operation: &str, status: &str, elapsed: Duration) {
+        METRICS.requests.with_label_values(&[protocol, operation, status]).inc();
+        METRICS
+            .request_duration
+            .with_label_values(&[protocol, operation])
+            .observe(elapsed.as_secs_f64());
+    }
+
+    pub fn pack_served(bytes: u64, objects: u64) {
+        METRICS.pack_bytes_served.inc_by(bytes);
+        METRICS.pack_objects_served.inc_by(objects);
+    }
+
+    pub fn pack_received(bytes: u64) {
+        METRICS.pack_bytes_received.inc_by(bytes);
+    }
+
+    pub fn object_cache_lookup(tier: &str, hit: bool) {
+        let result = if hit { "hit" } else { "miss" };
+        METRICS.object_cache.with_label_values(&[tier, result]).inc();
+    }
+
+    pub fn observe_object_read(elapsed: Duration) {
+        METRICS.object_read_duration.observe(elapsed.as_secs_f64());
+    }
+
+    pub fn replication_status(mirror: &str, lag_secs: i64, refs_behind: u64) {
+        METRICS.replication_lag.with_label_values(&[mirror]).set(lag_secs);
+        METRICS.replication_refs_behind.with_label_values(&[mirror]).set(refs_behind as i64);
+    }
+
+    pub fn rate_limit_checked(dimension: &str, allowed: bool) {
+        let result = if allowed { "allowed" } else { "throttled" };
+        METRICS.rate_limit_requests.with_label_values(&[dimension, result]).inc();
+    }
+
+    pub fn rate_limit_clients(dimension: &str, clients: usize) {
+        METRICS.rate_limit_clients.with_label_values(&[dimension]).set(clients as i64);
+    }
+
+    pub fn admission_decided(decision: &str) {
+        METRICS.admission_requests.with_label_values(&[decision]).inc();
+    }
+
+    pub fn admission_large_fetches(active: usize) {
+        METRICS.admission_large_fetches.set(active as i64);
+    }
+
+    pub fn render() -> Option<String> {
+        let mut out = Vec::new();
+        if let Err(e) = TextEncoder::new().encode(&METRICS.registry.gather(), &mut out) {
+            tracing::warn!(error = %e, "failed to encode metrics");
+            return None;
+        }
+        String::from_utf8(out).ok()
+    }
+}
+
+#[cfg(not(feature = "metrics"))]
+mod imp {
+    use std::time::Duration;
+
+    pub fn observe_request(_protocol: &str, _operation: &str, _status: &str, _elapsed: Duration) {}
+
+    pub fn pack_served(_bytes: u64, _objects: u64) {}
+
+    pub fn pack_received(_bytes: u64) {}
+
+    pub fn object_cache_lookup(_tier: &str, _hit: bool) {}
+
+    pub fn observe_object_read(_elapsed: Duration) {}
+
+    pub fn replication_status(_mirror: &str, _lag_secs: i64, _refs_behind: u64) {}
+
+    pub fn rate_limit_checked(_dimension: &str, _allowed: bool) {}
+
+    pub fn rate_limit_clients(_dimension: &str, _clients: usize) {}
+
+    pub fn admission_decided(_decision: &str) {}
+
+    pub fn admission_large_fetches(_active: usize) {}
+
+    pub fn render() -> Option<String> {
+        None
+    }
+}
+
+/// 记录一次请求，`status` 为结果的简短描述，如 HTTP 状态码或 `ok`/`error`
+pub fn observe_request(protocol: &str, operation: &str, status: &str, elapsed: Duration) {
+    imp::observe_request(protocol, operation, status, elapsed)
+}
+
+/// 记录 fetch 发送的一个 pack
+pub fn pack_served(bytes: u64, objects: u64) {
+    imp::pack_served(bytes, objects)
+}
+
+/// 记录推送收到的一个 pack
+pub fn pack_received(bytes: u64) {
+    imp::pack_received(bytes)
+}
+
+/// 记录一次对象缓存查找，`tier` 为缓存层
+pub fn object_cache_lookup(tier: &str, hit: bool) {
+    imp::object_cache_lookup(tier, hit)
+}
+
+/// 记录一次从存储后端读取对象的耗时
+pub fn observe_object_read(elapsed: Duration) {
+    imp::observe_object_read(elapsed)
+}
+
+/// 记录镜像的复制延迟
+pub fn replication_status(mirror: &str, lag_secs: i64, refs_behind: u64) {
+    imp::replication_status(mirror, lag_secs, refs_behind)
+}
+
+/// 记录一次限流检查，`dimension` 为限流维度
+pub fn rate_limit_checked(dimension: &str, allowed: bool) {
+    imp::rate_limit_checked(dimension, allowed)
+}
+
+/// 记录限流维度正在计数的客户端数
+pub fn rate_limit_clients(dimension: &str, clients: usize) {
+    imp::rate_limit_clients(dimension, clients)
+}
+
+/// 记录一次 fetch 准入控制的结果：`admitted`、`queued`、`timed_out` 或 `rejected`
+pub fn admission_decided(decision: &str) {
+    imp::admission_decided(decision)
+}
+
+/// 记录正在执行的大请求数
+pub fn admission_large_fetches(active: usize) {
+    imp::admission_large_fetches(active)
+}
+
+/// 以 Prometheus 文本格式导出全部指标，未启用指标时返回 None
+pub fn render() -> Option<String> {
+    imp::render()
+}
+
+#[cfg(all(test, feature = "metrics"))]
+mod tests {
+    use super::*;
+
+    /// 测试导出的文本包含记录的指标
+    #[test]
+    fn test_render() {
+        observe_request("http", "/git-upload-pack", "200", Duration::from_millis(5));
+        pack_served(1024, 3);
+        object_cache_lookup("memory", true);
+        object_cache_lookup("disk", false);
+        let text = render().unwrap();
+        assert!(text.contains(r#"mono_requests_total{operation="/git-upload-pack",protocol="http",status="200"}"#));
+        assert!(text.contains("mono_request_duration_seconds_bucket"));
+        assert!(text.contains(r#"mono_object_cache_requests_total{result="hit",tier="memory"}"#));
+        assert!(text.contains("# TYPE mono_pack_bytes_served_total counter"));
+    }
+}
@@ -0,0 +1,420 @@
+//! 邮件与聊天通知
+//!
+//! 变更请求的新建、评论与批准，合并队列条目的合入与失败，以及被推送策略拒绝的推送会通知
+//! 相关的人，在 `mono.toml` 的 `[notifications]` 中配置（见 [`NotificationsConfig`]）：
+//!
+//! - 聊天频道（`[[notifications.chat]]`，Slack incoming webhook 或 Matrix 房间）收到订阅的
+//!   全部事件；
+//! - 事件涉及的用户（变更请求的作者与评审人、队列条目的提交者、被拒绝的推送者）按
+//!   `[notifications.users.<用户>]` 中的偏好通过 `[notifications.smtp]` 收到邮件，触发事件的
+//!   用户自己不会收到。
+//!
+//! 每种渠道是一个 [`Notifier`]。通知同步发送，失败只记录到日志，不影响触发事件的操作；
+//! 需要可靠投递时使用 webhook（见 [`crate::webhooks`]）。
+//!
+//! [`NotificationsConfig`]: crate::common::config::NotificationsConfig
+
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use base64::Engine;
+
+use crate::common::config::{ChatConfig, ChatKind, NotificationKind, SmtpConfig, UserNotificationConfig};
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::repo::Repository;
+
+/// Matrix 访问令牌的环境变量
+pub const MATRIX_TOKEN_ENV: &str = "MONO_MATRIX_TOKEN";
+/// 单次请求与 SMTP 会话中每一步的超时时间
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 一条通知
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Notification {
+    pub kind: NotificationKind,
+    /// 标题，用作邮件主题
+    pub subject: String,
+    pub body: String,
+    /// 事件涉及的用户身份
+    pub recipients: Vec<String>,
+    /// 触发事件的用户身份，不会收到通知
+    pub actor: Option<String>,
+}
+
+impl Notification {
+    /// 去掉触发者并去重后的收件人
+    pub fn recipients(&self) -> Vec<&str> {
+        let mut recipients: Vec<&str> = Vec::new();
+        for recipient in &self.recipients {
+            if Some(recipient) != self.actor.as_ref() && !recipients.contains(&recipient.as_str()) {
+                recipients.push(recipient);
+            }
+        }
+        recipients
+    }
+}
+
+/// 一种通知渠道
+pub trait Notifier: Send + Sync {
+    /// 渠道名，出现在日志中
+    fn name(&self) -> &str;
+
+    /// 发送通知，渠道不关心该事件时直接返回
+    fn send(&self, repository: &str, notification: &Notification) -> MonoResult<()>;
+}
+
+/// 发送到 Slack 或 Matrix 的聊天消息
+pub struct ChatNotifier {
+    config: ChatConfig,
+    agent: ureq::Agent,
+}
+
+impl ChatNotifier {
+    pub fn new(config: ChatConfig) -> ChatNotifier {
+        let agent = ureq::Agent::config_builder().timeout_global(Some(TIMEOUT)).build().into();
+        ChatNotifier { config, agent }
+    }
+}
+
+impl Notifier for ChatNotifier {
+    fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    fn send(&self, repository: &str, notification: &Notification) -> MonoResult<()> {
+        if !self.config.subscribes(notification.kind) {
+            return Ok(());
+        }
+        let text = format!("[{}] {}\n{}", repository, notification.subject, notification.body);
+        let (url, body) = match self.config.kind {
+            ChatKind::Slack => (self.config.url.clone(), serde_json::json!({ "text": text })),
+            ChatKind::Matrix => {
+                let room = self.config.room.as_deref().unwrap_or_default();
+                let txn = format!("{}-{:08x}", chrono::Utc::now().timestamp_millis(), rand::random::<u32>());
+                let url = matrix_url(&self.config.url, room, &txn);
+                (url, serde_json::json!({ "msgtype": "m.text", "body": text }))
+            }
+        };
+        let body = body.to_string();
+        let request = match self.config.kind {
+            ChatKind::Slack => self.agent.post(&url).header("Content-Type", "application/json").send(body.as_str()),
+            ChatKind::Matrix => {
+                let token = std::env::var(MATRIX_TOKEN_ENV)
+                    .map_err(|_| MonoError::config(format!("matrix notifications require {}", MATRIX_TOKEN_ENV)))?;
+                self.agent
+                    .put(&url)
+                    .header("Content-Type", "application/json")
+                    .header("Authorization", format!("Bearer {}", token))
+                    .send(body.as_str())
+            }
+        };
+        request.map_err(|e| MonoError::unavailable(format!("{}: {}", self.config.name, e)))?;
+        Ok(())
+    }
+}
+
+/// Matrix 发送房间消息的地址
+fn matrix_url(homeserver: &str, room: &str, txn: &str) -> String {
+    let room: String = room
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect();
+    format!(
+        "{}/_matrix/client/v3/rooms/{}/send/m.room.message/{}",
+        homeserver.trim_end_matches('/'),
+        room,
+        txn
+    )
+}
+
+/// 按用户偏好发送的邮件
+pub struct EmailNotifier {
+    smtp: SmtpConfig,
+    users: BTreeMap<String, UserNotificationConfig>,
+}
+
+impl EmailNotifier {
+    pub fn new(smtp: SmtpConfig, users: BTreeMap<String, UserNotificationConfig>) -> EmailNotifier {
+        EmailNotifier { smtp, users }
+    }
+
+    /// 接收该通知的邮箱，同一用户的不同身份只计一次
+    fn addresses(&self, notification: &Notification) -> Vec<String> {
+        let mut addresses: Vec<String> = Vec::new();
+        for recipient in notification.recipients() {
+            let Some(preferences) = user_preferences(&self.users, recipient) else { continue };
+            if let Some(email) = preferences.email.as_ref().filter(|_| preferences.subscribes(notification.kind)) {
+                if !addresses.contains(email) {
+                    addresses.push(email.clone());
+                }
+            }
+        }
+        addresses
+    }
+}
+
+impl Notifier for EmailNotifier {
+    fn name(&self) -> &str {
+        "email"
+    }
+
+    fn send(&self, repository: &str, notification: &Notification) -> MonoResult<()> {
+        let to = self.addresses(notification);
+        if to.is_empty() {
+            return Ok(());
+        }
+        let subject = format!("[{}] {}", repository, notification.subject);
+        send_mail(&self.smtp, &to, &subject, &notification.body)
+    }
+}
+
+/// 用户身份对应的偏好：先按完整身份查找，再按去掉 `user:` 前缀的用户名查找
+fn user_preferences<'a>(
+    users: &'a BTreeMap<String, UserNotificationConfig>,
+    principal: &str,
+) -> Option<&'a UserNotificationConfig> {
+    users.get(principal).or_else(|| users.get(principal.strip_prefix("user:")?))
+}
+
+/// 通过 SMTP 发送纯文本邮件
+pub fn send_mail(smtp: &SmtpConfig, to: &[String], subject: &str, body: &str) -> MonoResult<()> {
+    let address = smtp
+        .server
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| MonoError::config(format!("cannot resolve smtp server {}", smtp.server)))?;
+    let stream = TcpStream::connect_timeout(&address, TIMEOUT)?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    let mut session = SmtpSession {
+        reader: BufReader::new(stream.try_clone()?),
+        writer: stream,
+    };
+    session.expect(220)?;
+    session.command("EHLO mono", 250)?;
+    session.command(&format!("MAIL FROM:<{}>", smtp.from), 250)?;
+    for address in to {
+        session.command(&format!("RCPT TO:<{}>", address), 250)?;
+    }
+    session.command("DATA", 354)?;
+    let message = format_message(&smtp.from, to, subject, body, &chrono::Utc::now().to_rfc2822());
+    session.writer.write_all(message.as_bytes())?;
+    session.command(".", 250)?;
+    session.command("QUIT", 221)?;
+    Ok(())
+}
+
+struct SmtpSession {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl SmtpSession {
+    fn command(&mut self, line: &str, code: u16) -> MonoResult<()> {
+        self.writer.write_all(format!("{}\r\n", line).as_bytes())?;
+        self.expect(code)
+    }
+
+    /// 读取一个（可能多行的）响应，状态码不是 `code` 时返回错误；`250` 同时接受 `251`
+    fn expect(&mut self, code: u16) -> MonoResult<()> {
+        loop {
+            let mut line = String::new();
+            if self.reader.read_line(&mut line)? == 0 {
+                return Err(MonoError::unavailable("smtp server closed the connection"));
+            }
+            let status: u16 = line.get(..3).and_then(|status| status.parse().ok()).unwrap_or_default();
+            if status != code && !(code == 250 && status == 251) {
+                return Err(MonoError::unavailable(format!("smtp: {}", line.trim_end())));
+            }
+            if line.as_bytes().get(3) != Some(&b'-') {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// 邮件内容（不含结尾的 `.`），非 ASCII 的主题按 RFC 2047 编码，以 `.` 开头的行加倍
+fn format_message(from: &str, to: &[String], subject: &str, body: &str, date: &str) -> String {
+    let subject = if subject.is_ascii() {
+        subject.to_string()
+    } else {
+        format!("=?UTF-8?B?{}?=", base64::engine::general_purpose::STANDARD.encode(subject))
+    };
+    let mut message = format!(
+        "From: {}\r\nTo: {}\r\nSubject: {}\r\nDate: {}\r\nMIME-Version: 1.0\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Transfer-Encoding: 8bit\r\n\r\n",
+        from,
+        to.join(", "),
+        subject,
+        date
+    );
+    for line in body.lines() {
+        if line.starts_with('.') {
+            message.push('.');
+        }
+        message.push_str(line);
+        message.push_str("\r\n");
+    }
+    message
+}
+
+/// 仓库配置的全部通知渠道
+pub struct Notifications {
+    repository: String,
+    notifiers: Vec<Box<dyn Notifier>>,
+}
+
+impl Notifications {
+    pub fn new(repo: &Repository) -> Notifications {
+        let config = &repo.config().notifications;
+        let mut notifiers: Vec<Box<dyn Notifier>> = Vec::new();
+        if let Some(smtp) = &config.smtp {
+            notifiers.push(Box::new(EmailNotifier::new(smtp.clone(), config.users.clone())));
+        }
+        for chat in &config.chat {
+            notifiers.push(Box::new(ChatNotifier::new(chat.clone())));
+        }
+        Notifications::with_notifiers(repo, notifiers)
+    }
+
+    /// 使用指定的通知渠道
+    pub fn with_notifiers(repo: &Repository, notifiers: Vec<Box<dyn Notifier>>) -> Notifications {
+        let repository = repo
+            .root()
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        Notifications { repository, notifiers }
+    }
+
+    /// 通过全部渠道发送，返回失败的渠道数；失败记录到日志
+    pub fn send(&self, notification: &Notification) -> usize {
+        let mut failed = 0;
+        for notifier in &self.notifiers {
+            if let Err(e) = notifier.send(&self.repository, notification) {
+                tracing::warn!(notifier = notifier.name(), kind = %notification.kind, error = %e, "failed to send notification");
+                failed += 1;
+            }
+        }
+        failed
+    }
+
+    /// 发送通知，未配置任何渠道时什么也不做
+    pub fn notify(repo: &Repository, notification: Notification) {
+        let config = &repo.config().notifications;
+        if config.smtp.is_none() && config.chat.is_empty() {
+            return;
+        }
+        Notifications::new(repo).send(&notification);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::test_utils::init_repo;
+
+    fn notification(kind: NotificationKind) -> Notification {
+        Notification {
+            kind,
+            subject: "Review requested: #1 Add feature".to_string(),
+            body: "alice asked for your review\n.hidden".to_string(),
+            recipients: vec!["user:bob".to_string(), "alice".to_string(), "carol".to_string(), "bob".to_string()],
+            actor: Some("alice".to_string()),
+        }
+    }
+
+    /// 测试收件人按偏好筛选，以及通过 SMTP 发送的会话与邮件内容
+    #[test]
+    fn test_email() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let server = listener.local_addr().unwrap().to_string();
+        let transcript = Arc::new(Mutex::new(String::new()));
+        let recorded = transcript.clone();
+        let handle = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut writer = stream;
+            writer.write_all(b"220 localhost ready\r\n").unwrap();
+            let mut data = false;
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap() == 0 {
+                    break;
+                }
+                recorded.lock().unwrap().push_str(&line);
+                let reply: &[u8] = if data {
+                    if line != ".\r\n" {
+                        continue;
+                    }
+                    data = false;
+                    b"250 queued\r\n"
+                } else if line.starts_with("EHLO") {
+                    b"250-localhost\r\n250 8BITMIME\r\n"
+                } else if line.starts_with("DATA") {
+                    data = true;
+                    b"354 go ahead\r\n"
+                } else if line.starts_with("QUIT") {
+                    writer.write_all(b"221 bye\r\n").unwrap();
+                    break;
+                } else {
+                    b"250 ok\r\n"
+                };
+                writer.write_all(reply).unwrap();
+            }
+        });
+
+        let users: BTreeMap<String, UserNotificationConfig> = [
+            ("bob", Some("bob@example.com"), vec![NotificationKind::Review]),
+            ("alice", Some("alice@example.com"), vec![]),
+            ("carol", Some("carol@example.com"), vec![NotificationKind::MergeQueue]),
+        ]
+        .into_iter()
+        .map(|(name, email, events)| {
+            let preferences = UserNotificationConfig {
+                email: email.map(str::to_string),
+                events,
+            };
+            (name.to_string(), preferences)
+        })
+        .collect();
+        let smtp = SmtpConfig {
+            server,
+            from: "mono@example.com".to_string(),
+        };
+        let notifier = EmailNotifier::new(smtp, users);
+        // 触发者 alice 与不接收评审通知的 carol 不在收件人中，bob 只收到一封
+        assert_eq!(notifier.addresses(&notification(NotificationKind::Review)), ["bob@example.com"]);
+        assert!(notifier.addresses(&notification(NotificationKind::PolicyViolation)).is_empty());
+
+        let (_dir, repo) = init_repo();
+        let notifications = Notifications::with_notifiers(&repo, vec![Box::new(notifier)]);
+        assert_eq!(notifications.send(&notification(NotificationKind::Review)), 0);
+        handle.join().unwrap();
+        let transcript = transcript.lock().unwrap();
+        assert!(transcript.contains("MAIL FROM:<mono@example.com>\r\n"), "{}", transcript);
+        assert!(transcript.contains("RCPT TO:<bob@example.com>\r\n"), "{}", transcript);
+        assert!(transcript.contains("Subject: ["), "{}", transcript);
+        assert!(transcript.contains("\r\n..hidden\r\n"), "{}", transcript);
+    }
+
+    /// 测试 Matrix 地址中的房间 ID 编码与非 ASCII 邮件主题的编码
+    #[test]
+    fn test_format() {
+        assert_eq!(
+            matrix_url("https://matrix.example.com/", "!abc:example.com", "1-2"),
+            "https://matrix.example.com/_matrix/client/v3/rooms/%21abc%3Aexample.com/send/m.room.message/1-2"
+        );
+        let message = format_message("a@example.com", &["b@example.com".to_string()], "评审", "body", "date");
+        assert!(message.contains("Subject: =?UTF-8?B?6K+E5a6h?=\r\n"), "{}", message);
+        assert!(message.ends_with("\r\n\r\nbody\r\n"));
+    }
+}
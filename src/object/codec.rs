@@ -0,0 +1,188 @@
+//! 对象压缩编码
+//!
+//! 松散对象与 pack 条目默认以 zlib 压缩，与 git 兼容。`[storage] compression = "zstd"` 时新写入的
+//! 松散对象与重新打包的 pack 改用 zstd：体积略大于 zlib，但压缩与解压都快得多，以 fetch 为主的服务端读取
+//! 对象与生成 pack 占用的 CPU 明显减少，见 `cargo bench --bench inflate`。
+//!
+//! zstd 帧以魔数 `28 B5 2F FD` 开头，而 zlib 流第一个字节的低 4 位总是 8，读取松散对象时按开头的字节
+//! 识别编码，两种编码的对象可以混合存在，切换配置不需要改写已有对象。pack 的编码记录在头部的版本号中，
+//! 见 [`crate::pack`]。使用 zstd 的仓库不能再由 git 直接读取，upload-pack 只向声明支持的客户端发送 zstd pack。
+
+use std::io::{self, BufRead, Read, Write};
+
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+
+use crate::common::MonoResult;
+
+/// zstd 帧的魔数
+pub const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// zstd 的压缩级别，即 zstd 的默认级别
+const ZSTD_LEVEL: i32 = 3;
+
+/// 按对象头中声明的长度预分配缓冲区的上限
+///
+/// 声明的长度来自客户端推送或远端发送的数据，不能直接用于分配；更长的内容在解压过程中逐步扩容。
+pub const MAX_PREALLOC: usize = 16 << 20;
+
+/// 对象压缩编码
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Codec {
+    /// git 使用的 zlib
+    #[default]
+    Zlib,
+    /// zstd，解压更快，git 不能读取
+    Zstd,
+}
+
+impl Codec {
+    /// 由压缩数据开头的字节识别编码
+    pub fn detect(head: &[u8]) -> Codec {
+        if head.starts_with(&ZSTD_MAGIC) {
+            Codec::Zstd
+        } else {
+            Codec::Zlib
+        }
+    }
+
+    /// 压缩 `parts` 依次拼接后的内容
+    pub fn compress(self, parts: &[&[u8]]) -> MonoResult<Vec<u8>> {
+        let mut out = Vec::with_capacity(parts.iter().map(|part| part.len()).sum::<usize>() / 2 + 32);
+        self.compress_into(&mut out, parts)?;
+        Ok(out)
+    }
+
+    /// 压缩 `parts` 依次拼接后的内容并追加到 `out`
+    pub fn compress_into(self, out: &mut Vec<u8>, parts: &[&[u8]]) -> MonoResult<()> {
+        match self {
+            Codec::Zlib => {
+                let mut encoder = flate2::write::ZlibEncoder::new(out, Compression::default());
+                for part in parts {
+                    encoder.write_all(part)?;
+                }
+                encoder.finish()?;
+            }
+            Codec::Zstd => {
+                let mut encoder = zstd::stream::write::Encoder::new(out, ZSTD_LEVEL)?;
+                encoder.set_pledged_src_size(Some(parts.iter().map(|part| part.len() as u64).sum()))?;
+                encoder.include_contentsize(true)?;
+                for part in parts {
+                    encoder.write_all(part)?;
+                }
+                encoder.finish()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// 解压从 `input` 开始的一个压缩流，不读取流结束之后的数据
+    pub fn decoder<'a, R: BufRead + 'a>(self, input: R) -> MonoResult<Box<dyn Read + 'a>> {
+        Ok(match self {
+            Codec::Zlib => Box::new(flate2::bufread::ZlibDecoder::new(input)),
+            Codec::Zstd => Box::new(zstd::stream::read::Decoder::with_buffer(input)?.single_frame()),
+        })
+    }
+
+    /// 解压 `data` 开头的一个压缩流，`size` 为解压后的长度；返回内容与压缩流的长度
+    pub fn decompress_exact(self, data: &[u8], size: usize) -> io::Result<(Vec<u8>, usize)> {
+        match self {
+            Codec::Zlib => inflate_zlib(data, size),
+            Codec::Zstd => {
+                let len = zstd::zstd_safe::find_frame_compressed_size(data)
+                    .map_err(|code| io::Error::other(zstd::zstd_safe::get_error_name(code)))?;
+                let frame = &data[..len];
+                // 帧头记录了内容长度时先与声明的长度核对，再以有上限的缓冲区流式解压
+                if let Ok(Some(content_size)) = zstd::zstd_safe::get_frame_content_size(frame) {
+                    if content_size != size as u64 {
+                        return Err(io::Error::other("size mismatch"));
+                    }
+                }
+                let limit = size.checked_add(1).ok_or_else(|| io::Error::other("size too large"))?;
+                let mut out = Vec::with_capacity(limit.min(MAX_PREALLOC));
+                zstd::stream::read::Decoder::with_buffer(frame)?
+                    .single_frame()
+                    .take(limit as u64)
+                    .read_to_end(&mut out)?;
+                if out.len() != size {
+                    return Err(io::Error::other("size mismatch"));
+                }
+                Ok((out, len))
+            }
+        }
+    }
+}
+
+/// 解压 `data` 开头的 zlib 流，并找出流结束的位置
+fn inflate_zlib(data: &[u8], size: usize) -> io::Result<(Vec<u8>, usize)> {
+    use flate2::{Decompress, FlushDecompress, Status};
+
+    let mut decompress = Decompress::new(true);
+    // 多解压一个字节，以便发现实际内容比声明的更长
+    let limit = size.checked_add(1).ok_or_else(|| io::Error::other("size too large"))?;
+    let mut out = Vec::with_capacity(limit.min(MAX_PREALLOC));
+    loop {
+        if out.len() == out.capacity() {
+            out.reserve((limit - out.len()).min(out.capacity()));
+        }
+        let (consumed, produced) = (decompress.total_in() as usize, out.len());
+        let status = decompress
+            .decompress_vec(&data[consumed..], &mut out, FlushDecompress::Finish)
+            .map_err(io::Error::other)?;
+        if status == Status::StreamEnd {
+            break;
+        }
+        if out.len() > size {
+            return Err(io::Error::other("size mismatch"));
+        }
+        if decompress.total_in() as usize == consumed && out.len() == produced {
+            return Err(io::Error::other("truncated"));
+        }
+    }
+    if out.len() != size {
+        return Err(io::Error::other("size mismatch"));
+    }
+    Ok((out, decompress.total_in() as usize))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 测试两种编码的压缩、识别与解压，流结束之后的数据不被读取
+    #[test]
+    fn test_roundtrip() {
+        let data: Vec<u8> = (0..10_000u32).flat_map(|i| (i % 251).to_le_bytes()).collect();
+        for codec in [Codec::Zlib, Codec::Zstd] {
+            let mut compressed = codec.compress(&[b"blob 40000\0", &data]).unwrap();
+            assert_eq!(Codec::detect(&compressed), codec);
+            let len = compressed.len();
+            compressed.extend_from_slice(b"trailing");
+
+            let mut out = Vec::new();
+            codec.decoder(&compressed[..]).unwrap().read_to_end(&mut out).unwrap();
+            assert_eq!(&out[..11], b"blob 40000\0");
+            assert_eq!(&out[11..], &data[..]);
+
+            let (out, consumed) = codec.decompress_exact(&compressed, data.len() + 11).unwrap();
+            assert_eq!(consumed, len);
+            assert_eq!(&out[11..], &data[..]);
+            assert!(codec.decompress_exact(&compressed, data.len()).is_err());
+            assert!(codec.decompress_exact(&compressed[..len / 2], data.len() + 11).is_err());
+            // 声明的长度远大于实际内容时返回错误，不按声明的长度分配
+            assert!(codec.decompress_exact(&compressed, usize::MAX).is_err());
+            assert!(codec.decompress_exact(&compressed, 1 << 60).is_err());
+        }
+
+        // 帧头不带内容长度的 zstd 帧同样逐步解压并核对长度
+        let mut frame = Vec::new();
+        let mut encoder = zstd::stream::write::Encoder::new(&mut frame, ZSTD_LEVEL).unwrap();
+        encoder.include_contentsize(false).unwrap();
+        encoder.write_all(&data).unwrap();
+        encoder.finish().unwrap();
+        assert_eq!(Codec::Zstd.decompress_exact(&frame, data.len()).unwrap(), (data.clone(), frame.len()));
+        assert!(Codec::Zstd.decompress_exact(&frame, 1 << 60).is_err());
+        assert!(Codec::Zstd.decompress_exact(&frame, data.len() - 1).is_err());
+    }
+}
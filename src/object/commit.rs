@@ -0,0 +1,257 @@
+//! 提交对象的解析与编码
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::object::{ObjectId, ObjectType};
+
+/// 作者或提交者签名，例如 `Name <email> 1700000000 +0800`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signature {
+    pub name: String,
+    pub email: String,
+    /// Unix 时间戳（秒）
+    pub timestamp: i64,
+    /// 时区偏移，保持原始写法，例如 `+0800`
+    pub timezone: String,
+}
+
+impl Signature {
+    pub fn new(name: impl Into<String>, email: impl Into<String>, timestamp: i64) -> Signature {
+        Signature {
+            name: name.into(),
+            email: email.into(),
+            timestamp,
+            timezone: "+0000".to_string(),
+        }
+    }
+
+    /// 服务端生成提交时使用的提交者：`GIT_COMMITTER_NAME` 与 `GIT_COMMITTER_EMAIL`，
+    /// 未设置时使用当前用户名，时间为当前时间
+    pub fn committer_from_env() -> MonoResult<Signature> {
+        let name = std::env::var("GIT_COMMITTER_NAME")
+            .or_else(|_| std::env::var("USER"))
+            .map_err(|_| MonoError::config("committer identity unknown, set GIT_COMMITTER_NAME"))?;
+        let email = std::env::var("GIT_COMMITTER_EMAIL").unwrap_or_else(|_| format!("{}@localhost", name));
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_err(|e| MonoError::config(e.to_string()))?;
+        Ok(Signature::new(name, email, now.as_secs() as i64))
+    }
+}
+
+impl fmt::Display for Signature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} <{}> {} {}", self.name, self.email, self.timestamp, self.timezone)
+    }
+}
+
+impl FromStr for Signature {
+    type Err = MonoError;
+
+    fn from_str(s: &str) -> MonoResult<Signature> {
+        let invalid = || MonoError::storage(format!("invalid signature: {}", s));
+        let open = s.find('<').ok_or_else(invalid)?;
+        let close = s.rfind('>').ok_or_else(invalid)?;
+        if close < open {
+            return Err(invalid());
+        }
+        let mut tail = s[close + 1..].split_whitespace();
+        let timestamp = tail.next().and_then(|t| t.parse().ok()).ok_or_else(invalid)?;
+        let timezone = tail.next().unwrap_or("+0000").to_string();
+        Ok(Signature {
+            name: s[..open].trim_end().to_string(),
+            email: s[open + 1..close].to_string(),
+            timestamp,
+            timezone,
+        })
+    }
+}
+
+/// 提交对象
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Commit {
+    pub tree: ObjectId,
+    pub parents: Vec<ObjectId>,
+    pub author: Signature,
+    pub committer: Signature,
+    /// committer 之后的其他头部（如 encoding、gpgsig），值中保留换行
+    pub extra_headers: Vec<(String, String)>,
+    pub message: String,
+}
+
+impl Commit {
+    /// 解析提交对象的内容
+    pub fn parse(data: &[u8]) -> MonoResult<Commit> {
+        let text = String::from_utf8_lossy(data);
+        let (header, message) = match text.find("\n\n") {
+            Some(pos) => (&text[..pos], &text[pos + 2..]),
+            None => (text.as_ref(), ""),
+        };
+
+        // 以空格开头的行是上一个头部的续行
+        let mut headers: Vec<(String, String)> = Vec::new();
+        for line in header.lines() {
+            if let Some(cont) = line.strip_prefix(' ') {
+                let last = headers
+                    .last_mut()
+                    .ok_or_else(|| MonoError::storage("corrupt commit: dangling continuation"))?;
+                last.1.push('\n');
+                last.1.push_str(cont);
+            } else {
+                let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+                headers.push((key.to_string(), value.to_string()));
+            }
+        }
+
+        let mut tree = None;
+        let mut parents = Vec::new();
+        let mut author = None;
+        let mut committer = None;
+        let mut extra_headers = Vec::new();
+        for (key, value) in headers {
+            match key.as_str() {
+                "tree" if tree.is_none() => tree = Some(value.parse()?),
+                "parent" => parents.push(value.parse()?),
+                "author" if author.is_none() => author = Some(value.parse()?),
+                "committer" if committer.is_none() => committer = Some(value.parse()?),
+                _ => extra_headers.push((key, value)),
+            }
+        }
+
+        let missing = |field: &str| MonoError::storage(format!("corrupt commit: missing {}", field));
+        Ok(Commit {
+            tree: tree.ok_or_else(|| missing("tree"))?,
+            parents,
+            author: author.ok_or_else(|| missing("author"))?,
+            committer: committer.ok_or_else(|| missing("committer"))?,
+            extra_headers,
+            message: message.to_string(),
+        })
+    }
+
+    /// 编码为提交对象的内容
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = format!("tree {}\n", self.tree);
+        for parent in &self.parents {
+            out.push_str(&format!("parent {}\n", parent));
+        }
+        out.push_str(&format!("author {}\n", self.author));
+        out.push_str(&format!("committer {}\n", self.committer));
+        for (key, value) in &self.extra_headers {
+            out.push_str(&format!("{} {}\n", key, value.replace('\n', "\n ")));
+        }
+        out.push('\n');
+        out.push_str(&self.message);
+        out.into_bytes()
+    }
+
+    /// 计算提交对象 ID
+    pub fn id(&self) -> ObjectId {
+        ObjectId::hash_object(ObjectType::Commit, &self.encode())
+    }
+
+    /// 提交信息的第一行
+    pub fn summary(&self) -> &str {
+        self.message.lines().next().unwrap_or("")
+    }
+
+    /// 提交信息末尾的尾注，例如 `Approved-by: Alice <alice@example.com>`
+    ///
+    /// 与 git 一致，尾注位于最后一段且该段不是标题；段中每行都必须是 `Key: value` 形式，
+    /// 以空白开头的行是上一条尾注的续行。
+    pub fn trailers(&self) -> Vec<(&str, String)> {
+        let message = self.message.trim_end();
+        let Some((_, last)) = message.rsplit_once("\n\n") else {
+            return Vec::new();
+        };
+        let mut trailers: Vec<(&str, String)> = Vec::new();
+        for line in last.lines() {
+            if line.starts_with([' ', '\t']) {
+                match trailers.last_mut() {
+                    Some((_, value)) => {
+                        value.push(' ');
+                        value.push_str(line.trim());
+                    }
+                    None => return Vec::new(),
+                }
+                continue;
+            }
+            match line.split_once(':') {
+                Some((key, value)) if !key.is_empty() && key.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-') => {
+                    trailers.push((key, value.trim().to_string()));
+                }
+                _ => return Vec::new(),
+            }
+        }
+        trailers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COMMIT: &str = "tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904\n\
+        author A U Thor <author@example.com> 1700000000 +0800\n\
+        committer C O Mitter <committer@example.com> 1700000001 -0500\n\
+        \n\
+        initial commit\n";
+
+    /// 测试提交对象的解析与 ID 计算
+    #[test]
+    fn test_parse_commit() {
+        let commit = Commit::parse(COMMIT.as_bytes()).unwrap();
+        assert_eq!(commit.tree.to_hex(), "4b825dc642cb6eb9a060e54bf8d69288fbee4904");
+        assert!(commit.parents.is_empty());
+        assert_eq!(commit.author.name, "A U Thor");
+        assert_eq!(commit.author.email, "author@example.com");
+        assert_eq!(commit.author.timestamp, 1700000000);
+        assert_eq!(commit.committer.timezone, "-0500");
+        assert_eq!(commit.summary(), "initial commit");
+        assert_eq!(commit.encode(), COMMIT.as_bytes());
+    }
+
+    /// 测试带有多行头部的提交能够原样编码
+    #[test]
+    fn test_multiline_header_roundtrip() {
+        let data = "tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904\n\
+            parent 4b825dc642cb6eb9a060e54bf8d69288fbee4904\n\
+            author A <a@example.com> 1 +0000\n\
+            committer A <a@example.com> 1 +0000\n\
+            gpgsig -----BEGIN PGP SIGNATURE-----\n \n abc\n -----END PGP SIGNATURE-----\n\
+            \n\
+            signed\n";
+        let commit = Commit::parse(data.as_bytes()).unwrap();
+        assert_eq!(commit.parents.len(), 1);
+        assert_eq!(commit.extra_headers[0].0, "gpgsig");
+        assert!(commit.extra_headers[0].1.contains("\nabc\n"));
+        assert_eq!(commit.encode(), data.as_bytes());
+    }
+
+    /// 测试尾注的解析：只认最后一段，续行合并，非尾注行使整段无效
+    #[test]
+    fn test_trailers() {
+        let mut commit = Commit::parse(COMMIT.as_bytes()).unwrap();
+        assert!(commit.trailers().is_empty());
+
+        commit.message = "subject\n\nbody: not a trailer\n\nApproved-by: Alice\nReviewed-by: Bob\n  <bob@example.com>\n".to_string();
+        assert_eq!(
+            commit.trailers(),
+            vec![("Approved-by", "Alice".to_string()), ("Reviewed-by", "Bob <bob@example.com>".to_string())]
+        );
+        commit.message = "subject\n\nApproved-by: Alice\nsome prose\n".to_string();
+        assert!(commit.trailers().is_empty());
+        commit.message = "Approved-by: Alice\n".to_string();
+        assert!(commit.trailers().is_empty());
+    }
+
+    /// 测试缺少必需头部时返回错误
+    #[test]
+    fn test_parse_missing_tree() {
+        let data = "author A <a@example.com> 1 +0000\ncommitter A <a@example.com> 1 +0000\n\nmsg";
+        assert!(Commit::parse(data.as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,268 @@
+//! 对象格式之间的兼容映射
+//!
+//! 配置了 `[core] compat_object_format` 的仓库为对象维护另一种格式下的名字，协议层据此为只支持
+//! 该格式的客户端转换对象名与对象内容，对应 git 哈希迁移方案中的 compat map。
+//!
+//! 对象在兼容格式下的内容由原对象改写得到：blob 不变，树条目、提交的 `tree` 与 `parent`、
+//! 标签的 `object` 换成被引用对象的兼容名，其余字节（包括签名等头部）原样保留，因此被引用的对象
+//! 需要先完成转换。映射按需计算，结果追加到 `.mono/compat-map`，每行为 `<原名> <兼容名>`，
+//! 客户端之后发来的兼容名据此查回原名。
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::object::tree::Tree;
+use crate::object::{ObjectFormat, ObjectId, ObjectType, RawObject};
+use crate::repo::Repository;
+
+/// 兼容映射文件相对于 `.mono` 目录的路径
+pub const COMPAT_MAP_FILE: &str = "compat-map";
+
+/// 已计算的对象名映射，两个方向
+#[derive(Debug, Default)]
+struct Names {
+    to_compat: HashMap<ObjectId, ObjectId>,
+    to_native: HashMap<ObjectId, ObjectId>,
+}
+
+impl Names {
+    fn insert(&mut self, native: ObjectId, compat: ObjectId) {
+        self.to_compat.insert(native, compat);
+        self.to_native.insert(compat, native);
+    }
+}
+
+/// 仓库对象格式与兼容格式之间的映射
+#[derive(Debug)]
+pub struct CompatMap {
+    path: PathBuf,
+    compat: ObjectFormat,
+    names: RwLock<Names>,
+}
+
+impl CompatMap {
+    /// 打开仓库的兼容映射，未配置兼容格式时返回 None
+    pub fn open(repo: &Repository) -> MonoResult<Option<CompatMap>> {
+        match repo.config().core.compat_object_format {
+            Some(compat) => CompatMap::load(&repo.mono_dir().join(COMPAT_MAP_FILE), compat).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// 读取映射文件，文件不存在时为空映射
+    pub fn load(path: &Path, compat: ObjectFormat) -> MonoResult<CompatMap> {
+        let mut names = Names::default();
+        let data = match std::fs::read_to_string(path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e.into()),
+        };
+        for line in data.lines() {
+            let (native, compat_id) = line
+                .split_once(' ')
+                .ok_or_else(|| MonoError::storage(format!("{}: invalid line: {}", path.display(), line)))?;
+            names.insert(native.parse()?, compat_id.parse()?);
+        }
+        Ok(CompatMap {
+            path: path.to_path_buf(),
+            compat,
+            names: RwLock::new(names),
+        })
+    }
+
+    /// 兼容格式
+    pub fn compat_format(&self) -> ObjectFormat {
+        self.compat
+    }
+
+    /// 兼容名对应的原名，尚未转换过的对象返回 None
+    pub fn to_native(&self, compat: &ObjectId) -> Option<ObjectId> {
+        self.names.read().unwrap_or_else(|e| e.into_inner()).to_native.get(compat).copied()
+    }
+
+    /// 对象的兼容名，必要时先转换它引用的全部对象并记录到映射文件
+    pub fn to_compat(&self, repo: &Repository, id: &ObjectId) -> MonoResult<ObjectId> {
+        if let Some(compat) = self.lookup(id) {
+            return Ok(compat);
+        }
+        let mut added = Vec::new();
+        // 显式的栈代替递归，很长的历史也不会耗尽调用栈；第二个值表示依赖是否都已入栈
+        let mut stack = vec![(*id, false)];
+        let mut pending: HashMap<ObjectId, RawObject> = HashMap::new();
+        while let Some((id, expanded)) = stack.pop() {
+            if self.lookup(&id).is_some() {
+                continue;
+            }
+            if expanded {
+                let object = pending.remove(&id).expect("expanded objects are pending");
+                let converted = self.convert(&object)?;
+                let compat = converted.id_in(self.compat);
+                self.names.write().unwrap_or_else(|e| e.into_inner()).insert(id, compat);
+                added.push((id, compat));
+                continue;
+            }
+            if pending.contains_key(&id) {
+                continue;
+            }
+            let object = repo.read_object(&id)?;
+            stack.push((id, true));
+            for dependency in references(&object, id.format())? {
+                if self.lookup(&dependency).is_none() {
+                    stack.push((dependency, false));
+                }
+            }
+            pending.insert(id, object);
+        }
+        self.append(&added)?;
+        self.lookup(id)
+            .ok_or_else(|| MonoError::storage(format!("object {} could not be converted", id)))
+    }
+
+    /// 对象在兼容格式下的内容，引用的对象必须已经有兼容名
+    pub fn convert(&self, object: &RawObject) -> MonoResult<RawObject> {
+        let mut name = |id: &ObjectId| {
+            self.lookup(id)
+                .ok_or_else(|| MonoError::not_found(format!("compat name of object {}", id)))
+        };
+        let data = match object.object_type {
+            ObjectType::Blob => object.data.clone(),
+            ObjectType::Tree => {
+                let format = self.native_format();
+                let mut tree = Tree::parse(&object.data, format)?;
+                for entry in &mut tree.entries {
+                    entry.id = name(&entry.id)?;
+                }
+                tree.encode()
+            }
+            ObjectType::Commit | ObjectType::Tag => rewrite_headers(&object.data, &mut name)?,
+        };
+        Ok(RawObject::new(object.object_type, data))
+    }
+
+    /// 仓库自身的对象格式，即兼容格式之外的另一种
+    fn native_format(&self) -> ObjectFormat {
+        match self.compat {
+            ObjectFormat::Sha1 => ObjectFormat::Sha256,
+            ObjectFormat::Sha256 => ObjectFormat::Sha1,
+        }
+    }
+
+    fn lookup(&self, id: &ObjectId) -> Option<ObjectId> {
+        self.names.read().unwrap_or_else(|e| e.into_inner()).to_compat.get(id).copied()
+    }
+
+    /// 追加新计算的映射；多个进程同时转换同一对象时会写入相同的行，读取时后者覆盖前者
+    fn append(&self, added: &[(ObjectId, ObjectId)]) -> MonoResult<()> {
+        if added.is_empty() {
+            return Ok(());
+        }
+        let mut lines = String::new();
+        for (native, compat) in added {
+            lines.push_str(&format!("{} {}\n", native, compat));
+        }
+        let mut file = std::fs::OpenOptions::new().create(true).append(true).open(&self.path)?;
+        file.write_all(lines.as_bytes())?;
+        Ok(())
+    }
+}
+
+/// 对象直接引用的其他对象；子模块指向的提交不在仓库中，同样需要已有映射
+fn references(object: &RawObject, format: ObjectFormat) -> MonoResult<Vec<ObjectId>> {
+    match object.object_type {
+        ObjectType::Blob => Ok(Vec::new()),
+        ObjectType::Tree => Ok(Tree::parse(&object.data, format)?
+            .entries
+            .into_iter()
+            .filter(|entry| !entry.mode.is_gitlink())
+            .map(|entry| entry.id)
+            .collect()),
+        ObjectType::Commit | ObjectType::Tag => {
+            let mut ids = Vec::new();
+            rewrite_headers(&object.data, &mut |id: &ObjectId| {
+                ids.push(*id);
+                Ok(*id)
+            })?;
+            Ok(ids)
+        }
+    }
+}
+
+/// 改写提交与标签头部中 `tree`、`parent`、`object` 行的对象名，其余字节不变
+fn rewrite_headers(data: &[u8], name: &mut dyn FnMut(&ObjectId) -> MonoResult<ObjectId>) -> MonoResult<Vec<u8>> {
+    let mut out = Vec::with_capacity(data.len() + 64);
+    let mut rest = data;
+    while !rest.is_empty() {
+        let end = rest.iter().position(|&b| b == b'\n').map_or(rest.len(), |i| i + 1);
+        let (line, next) = rest.split_at(end);
+        rest = next;
+        // 空行之后是提交说明
+        if line == b"\n" {
+            out.extend_from_slice(line);
+            out.extend_from_slice(rest);
+            break;
+        }
+        let text = std::str::from_utf8(line).unwrap_or_default();
+        let header = ["tree ", "parent ", "object "]
+            .into_iter()
+            .find_map(|key| text.strip_prefix(key).map(|value| (key, value.trim_end_matches('\n'))));
+        match header {
+            Some((key, value)) => {
+                let id: ObjectId = value.parse()?;
+                out.extend_from_slice(format!("{}{}\n", key, name(&id)?).as_bytes());
+            }
+            None => out.extend_from_slice(line),
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::commit::Commit;
+    use crate::repo::InitOptions;
+    use crate::test_utils::{commit_files, write_tree};
+
+    /// 测试 SHA-256 仓库中的对象转换为 SHA-1 名字后内容一致、引用被改写，映射可从文件恢复
+    #[test]
+    fn test_compat_map() {
+        let dir = tempfile::tempdir().unwrap();
+        let options = InitOptions {
+            object_format: ObjectFormat::Sha256,
+            compat_object_format: Some(ObjectFormat::Sha1),
+            ..Default::default()
+        };
+        let repo = Repository::init(dir.path(), &options).unwrap();
+        let first = commit_files(&repo, &[("a.txt", b"one")], &[], "first");
+        let second = commit_files(&repo, &[("a.txt", b"two"), ("dir/b.txt", b"b")], &[first], "second");
+        assert_eq!(second.format(), ObjectFormat::Sha256);
+
+        let map = CompatMap::open(&repo).unwrap().unwrap();
+        let compat = map.to_compat(&repo, &second).unwrap();
+        assert_eq!(compat.format(), ObjectFormat::Sha1);
+        assert_eq!(map.to_native(&compat), Some(second));
+
+        // 转换后的提交引用兼容名，且与 SHA-1 仓库中相同内容的提交名字一致
+        let converted = map.convert(&repo.read_object(&second).unwrap()).unwrap();
+        let commit = Commit::parse(&converted.data).unwrap();
+        assert_eq!(commit.parents, vec![map.to_compat(&repo, &first).unwrap()]);
+        let sha1_dir = tempfile::tempdir().unwrap();
+        let sha1_repo = Repository::init(sha1_dir.path(), &InitOptions::default()).unwrap();
+        let sha1_first = Commit {
+            tree: write_tree(&sha1_repo, &[("a.txt", b"one")]),
+            ..repo.read_commit(&first).unwrap()
+        };
+        let sha1_first = sha1_repo.write_object(ObjectType::Commit, &sha1_first.encode()).unwrap();
+        assert_eq!(map.to_compat(&repo, &first).unwrap(), sha1_first);
+        let blob = ObjectFormat::Sha256.hash_object(ObjectType::Blob, b"b");
+        assert_eq!(map.to_compat(&repo, &blob).unwrap(), ObjectId::hash_object(ObjectType::Blob, b"b"));
+
+        let reloaded = CompatMap::open(&repo).unwrap().unwrap();
+        assert_eq!(reloaded.to_native(&compat), Some(second));
+        assert!(map.to_compat(&repo, &ObjectFormat::Sha256.hash_object(ObjectType::Blob, b"missing")).is_err());
+    }
+}
@@ -0,0 +1,96 @@
+//! 部分克隆的对象过滤规则，语义与 git 的 `--filter` 一致
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::object::ObjectType;
+
+/// 对象过滤规则
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ObjectFilter {
+    /// 不过滤
+    #[default]
+    None,
+    /// `blob:none`：忽略所有 blob
+    BlobNone,
+    /// `tree:<depth>`：忽略距根树深度不小于 depth 的树和 blob
+    TreeDepth(u64),
+}
+
+impl ObjectFilter {
+    /// 判断位于 `depth` 层的对象是否需要传输
+    ///
+    /// 根树的深度为 0，其直接条目的深度为 1，提交和标签不受过滤影响。
+    pub fn includes(&self, object_type: ObjectType, depth: u64) -> bool {
+        match (self, object_type) {
+            (_, ObjectType::Commit | ObjectType::Tag) => true,
+            (ObjectFilter::None, _) => true,
+            (ObjectFilter::BlobNone, ObjectType::Blob) => false,
+            (ObjectFilter::BlobNone, _) => true,
+            (ObjectFilter::TreeDepth(max), _) => depth < *max,
+        }
+    }
+
+    /// 是否为部分克隆
+    pub fn is_partial(&self) -> bool {
+        *self != ObjectFilter::None
+    }
+}
+
+impl fmt::Display for ObjectFilter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ObjectFilter::None => f.write_str("none"),
+            ObjectFilter::BlobNone => f.write_str("blob:none"),
+            ObjectFilter::TreeDepth(depth) => write!(f, "tree:{}", depth),
+        }
+    }
+}
+
+impl FromStr for ObjectFilter {
+    type Err = MonoError;
+
+    fn from_str(s: &str) -> MonoResult<ObjectFilter> {
+        match s {
+            "none" => Ok(ObjectFilter::None),
+            "blob:none" => Ok(ObjectFilter::BlobNone),
+            _ => s
+                .strip_prefix("tree:")
+                .and_then(|depth| depth.parse().ok())
+                .map(ObjectFilter::TreeDepth)
+                .ok_or_else(|| MonoError::usage(format!("unsupported filter: {}", s))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 测试过滤规则的解析与格式化
+    #[test]
+    fn test_parse_filter() {
+        assert_eq!("blob:none".parse::<ObjectFilter>().unwrap(), ObjectFilter::BlobNone);
+        assert_eq!("tree:2".parse::<ObjectFilter>().unwrap(), ObjectFilter::TreeDepth(2));
+        assert_eq!(ObjectFilter::TreeDepth(3).to_string(), "tree:3");
+        assert!("tree:x".parse::<ObjectFilter>().is_err());
+        assert!("sparse:oid=abc".parse::<ObjectFilter>().is_err());
+    }
+
+    /// 测试各规则按深度决定是否包含对象
+    #[test]
+    fn test_includes() {
+        assert!(ObjectFilter::None.includes(ObjectType::Blob, 10));
+        assert!(!ObjectFilter::BlobNone.includes(ObjectType::Blob, 1));
+        assert!(ObjectFilter::BlobNone.includes(ObjectType::Tree, 5));
+
+        let filter = ObjectFilter::TreeDepth(1);
+        assert!(filter.includes(ObjectType::Commit, 0));
+        assert!(filter.includes(ObjectType::Tree, 0));
+        assert!(!filter.includes(ObjectType::Tree, 1));
+        assert!(!filter.includes(ObjectType::Blob, 1));
+        assert!(!ObjectFilter::TreeDepth(0).includes(ObjectType::Tree, 0));
+    }
+}
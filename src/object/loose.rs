@@ -0,0 +1,236 @@
+//! 松散对象存储
+//!
+//! 每个对象压缩后保存在 `objects/<前两位>/<其余十六进制位>`，默认以 zlib 压缩，与 git 格式一致；
+//! 读取时按开头的字节识别压缩编码（见 [`codec`](crate::object::codec)）。
+
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::object::codec::Codec;
+use crate::object::{ObjectFormat, ObjectId, ObjectType, RawObject};
+
+/// 基于目录的松散对象存储
+#[derive(Debug, Clone)]
+pub struct LooseStore {
+    dir: PathBuf,
+    format: ObjectFormat,
+    codec: Codec,
+}
+
+impl LooseStore {
+    /// 以 `objects` 目录创建 SHA-1 格式的存储
+    pub fn new(dir: impl Into<PathBuf>) -> LooseStore {
+        LooseStore {
+            dir: dir.into(),
+            format: ObjectFormat::Sha1,
+            codec: Codec::Zlib,
+        }
+    }
+
+    /// 使用 `format` 格式计算写入对象的 ID
+    pub fn with_format(mut self, format: ObjectFormat) -> LooseStore {
+        self.format = format;
+        self
+    }
+
+    /// 以 `codec` 压缩写入的对象
+    pub fn with_codec(mut self, codec: Codec) -> LooseStore {
+        self.codec = codec;
+        self
+    }
+
+    /// 存储根目录
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// 写入对象使用的对象格式
+    pub fn format(&self) -> ObjectFormat {
+        self.format
+    }
+
+    /// 对象文件路径
+    pub fn object_path(&self, id: &ObjectId) -> PathBuf {
+        let hex = id.to_hex();
+        self.dir.join(&hex[..2]).join(&hex[2..])
+    }
+
+    /// 是否存在指定对象
+    pub fn contains(&self, id: &ObjectId) -> bool {
+        self.object_path(id).is_file()
+    }
+
+    /// 读取对象，不存在时返回 None
+    pub fn read(&self, id: &ObjectId) -> MonoResult<Option<RawObject>> {
+        let compressed = match std::fs::read(self.object_path(id)) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        decompress(id, &compressed).map(Some)
+    }
+
+    /// 只解压对象头，读取对象类型与大小，不存在时返回 None
+    pub fn read_header(&self, id: &ObjectId) -> MonoResult<Option<(ObjectType, usize)>> {
+        let file = match std::fs::File::open(self.object_path(id)) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let corrupt = |msg: &str| MonoError::storage(format!("corrupt object {}: {}", id, msg));
+        // 对象头不超过 "commit " 加上 usize 的十进制位数
+        let mut header = Vec::new();
+        let mut reader = BufReader::new(file);
+        let codec = Codec::detect(reader.fill_buf()?);
+        codec
+            .decoder(reader)?
+            .take(32)
+            .read_to_end(&mut header)
+            .map_err(|e| corrupt(&e.to_string()))?;
+        let nul = header
+            .iter()
+            .position(|&b| b == 0)
+            .ok_or_else(|| corrupt("missing header"))?;
+        let header = std::str::from_utf8(&header[..nul]).map_err(|_| corrupt("invalid header"))?;
+        let (object_type, size) = header.split_once(' ').ok_or_else(|| corrupt("invalid header"))?;
+        let size = size.parse().map_err(|_| corrupt("invalid size"))?;
+        Ok(Some((object_type.parse()?, size)))
+    }
+
+    /// 写入对象并返回其 ID，对象已存在时直接返回
+    pub fn write(&self, object_type: ObjectType, data: &[u8]) -> MonoResult<ObjectId> {
+        let id = self.format.hash_object(object_type, data);
+        let path = self.object_path(&id);
+        if path.is_file() {
+            return Ok(id);
+        }
+        let parent = path.parent().expect("object path always has a parent");
+        std::fs::create_dir_all(parent)?;
+
+        let compressed = compress(self.codec, object_type, data)?;
+
+        // 先写临时文件再重命名，避免并发读到不完整的对象
+        let tmp = parent.join(format!(".tmp-{}-{}", std::process::id(), &id.to_hex()[2..]));
+        std::fs::write(&tmp, compressed)?;
+        std::fs::rename(&tmp, &path)?;
+        Ok(id)
+    }
+
+    /// 列出存储中的所有对象 ID
+    pub fn list(&self) -> MonoResult<Vec<ObjectId>> {
+        let mut ids = Vec::new();
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(ids),
+            Err(e) => return Err(e.into()),
+        };
+        for entry in entries {
+            let entry = entry?;
+            let prefix = entry.file_name().to_string_lossy().into_owned();
+            if prefix.len() != 2 || !entry.file_type()?.is_dir() {
+                continue;
+            }
+            for object in std::fs::read_dir(entry.path())? {
+                let name = object?.file_name().to_string_lossy().into_owned();
+                match ObjectId::from_hex(&format!("{}{}", prefix, name)) {
+                    Ok(id) if id.format() == self.format => ids.push(id),
+                    _ => {}
+                }
+            }
+        }
+        ids.sort();
+        Ok(ids)
+    }
+}
+
+/// 按松散对象格式压缩对象：以 `codec` 压缩的 `"<type> <size>\0<content>"`
+pub fn compress(codec: Codec, object_type: ObjectType, data: &[u8]) -> MonoResult<Vec<u8>> {
+    codec.compress(&[&object_type.header(data.len()), data])
+}
+
+/// 解压松散对象，压缩编码由开头的字节识别，`id` 仅用于错误信息
+pub fn decompress(id: &ObjectId, compressed: &[u8]) -> MonoResult<RawObject> {
+    let mut data = Vec::new();
+    Codec::detect(compressed)
+        .decoder(compressed)?
+        .read_to_end(&mut data)
+        .map_err(|e| MonoError::storage(format!("corrupt object {}: {}", id, e)))?;
+    decode_loose(id, data)
+}
+
+/// 解析解压后的松散对象，并校验对象头中的长度
+fn decode_loose(id: &ObjectId, mut data: Vec<u8>) -> MonoResult<RawObject> {
+    let corrupt = |msg: &str| MonoError::storage(format!("corrupt object {}: {}", id, msg));
+    let nul = data
+        .iter()
+        .position(|&b| b == 0)
+        .ok_or_else(|| corrupt("missing header"))?;
+    let header = std::str::from_utf8(&data[..nul]).map_err(|_| corrupt("invalid header"))?;
+    let (object_type, size) = header.split_once(' ').ok_or_else(|| corrupt("invalid header"))?;
+    let object_type: ObjectType = object_type.parse()?;
+    let size: usize = size.parse().map_err(|_| corrupt("invalid size"))?;
+    let content = data.split_off(nul + 1);
+    if content.len() != size {
+        return Err(corrupt("size mismatch"));
+    }
+    Ok(RawObject::new(object_type, content))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 测试写入后可以读回对象
+    #[test]
+    fn test_write_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = LooseStore::new(dir.path());
+        let id = store.write(ObjectType::Blob, b"hello").unwrap();
+
+        assert_eq!(id.to_hex(), "b6fc4c620b67d95f953a5c1c1230aaab5db5a1b0");
+        assert!(store.contains(&id));
+        let object = store.read(&id).unwrap().unwrap();
+        assert_eq!(object.object_type, ObjectType::Blob);
+        assert_eq!(object.data, b"hello");
+        assert_eq!(store.list().unwrap(), vec![id]);
+        assert_eq!(store.read_header(&id).unwrap(), Some((ObjectType::Blob, 5)));
+    }
+
+    /// 测试以 zstd 写入的对象与 zlib 对象混合读取
+    #[test]
+    fn test_zstd() {
+        let dir = tempfile::tempdir().unwrap();
+        let zlib = LooseStore::new(dir.path());
+        let zstd = LooseStore::new(dir.path()).with_codec(Codec::Zstd);
+        let old = zlib.write(ObjectType::Blob, b"old").unwrap();
+        let new = zstd.write(ObjectType::Blob, b"new").unwrap();
+        assert_eq!(Codec::detect(&std::fs::read(zstd.object_path(&new)).unwrap()), Codec::Zstd);
+        for store in [&zlib, &zstd] {
+            assert_eq!(store.read(&old).unwrap().unwrap().data, b"old");
+            assert_eq!(store.read(&new).unwrap().unwrap().data, b"new");
+            assert_eq!(store.read_header(&new).unwrap(), Some((ObjectType::Blob, 3)));
+        }
+    }
+
+    /// 测试读取不存在的对象
+    #[test]
+    fn test_read_missing() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = LooseStore::new(dir.path());
+        let id = ObjectId::hash_object(ObjectType::Blob, b"missing");
+        assert!(store.read(&id).unwrap().is_none());
+        assert!(store.list().unwrap().is_empty());
+    }
+
+    /// 测试损坏的对象文件返回存储错误
+    #[test]
+    fn test_read_corrupt() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = LooseStore::new(dir.path());
+        let id = store.write(ObjectType::Blob, b"hello").unwrap();
+        std::fs::write(store.object_path(&id), b"not zlib").unwrap();
+        assert!(store.read(&id).is_err());
+    }
+}
@@ -0,0 +1,357 @@
+//! Git 对象模型
+//!
+//! 对象以 git 兼容的格式编码和寻址：对象 ID 为 `"<type> <size>\0<content>"` 的哈希，
+//! 哈希算法由仓库的对象格式（`[core] object_format`，见 [`ObjectFormat`]）决定，默认为 SHA-1。
+//! 配置了兼容格式的仓库通过 [`compat`] 在两种格式的对象名之间转换。
+
+pub mod codec;
+pub mod commit;
+pub mod compat;
+pub mod filter;
+pub mod loose;
+pub mod shallow;
+pub mod tag;
+pub mod tree;
+pub mod walk;
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use sha2::Sha256;
+
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+
+/// SHA-1 对象 ID 的字节长度
+pub const OBJECT_ID_LEN: usize = 20;
+
+/// 对象 ID 的最大字节长度（SHA-256）
+pub const MAX_OBJECT_ID_LEN: usize = 32;
+
+/// 对象格式：计算对象 ID 使用的哈希算法
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ObjectFormat {
+    #[default]
+    Sha1,
+    Sha256,
+}
+
+impl ObjectFormat {
+    /// 协议与配置中使用的格式名
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ObjectFormat::Sha1 => "sha1",
+            ObjectFormat::Sha256 => "sha256",
+        }
+    }
+
+    /// 对象 ID 的字节长度
+    pub fn id_len(&self) -> usize {
+        match self {
+            ObjectFormat::Sha1 => OBJECT_ID_LEN,
+            ObjectFormat::Sha256 => MAX_OBJECT_ID_LEN,
+        }
+    }
+
+    /// 该格式的全零 ID
+    pub fn zero(&self) -> ObjectId {
+        ObjectId {
+            bytes: [0; MAX_OBJECT_ID_LEN],
+            len: self.id_len() as u8,
+        }
+    }
+
+    /// 计算对象 ID
+    pub fn hash_object(&self, object_type: ObjectType, data: &[u8]) -> ObjectId {
+        let mut hasher = self.hasher();
+        hasher.update(&object_type.header(data.len()));
+        hasher.update(data);
+        hasher.finish()
+    }
+
+    /// 计算任意内容的摘要，用于 pack 与索引文件末尾的校验和
+    pub fn digest(&self, data: &[u8]) -> ObjectId {
+        let mut hasher = self.hasher();
+        hasher.update(data);
+        hasher.finish()
+    }
+
+    /// 增量计算摘要
+    pub fn hasher(&self) -> Hasher {
+        match self {
+            ObjectFormat::Sha1 => Hasher::Sha1(Sha1::new()),
+            ObjectFormat::Sha256 => Hasher::Sha256(Sha256::new()),
+        }
+    }
+}
+
+impl fmt::Display for ObjectFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ObjectFormat {
+    type Err = MonoError;
+
+    fn from_str(s: &str) -> MonoResult<ObjectFormat> {
+        match s {
+            "sha1" => Ok(ObjectFormat::Sha1),
+            "sha256" => Ok(ObjectFormat::Sha256),
+            _ => Err(MonoError::protocol(format!("unsupported object format: {}", s))),
+        }
+    }
+}
+
+/// 按对象格式增量计算摘要，见 [`ObjectFormat::hasher`]
+#[derive(Clone)]
+pub enum Hasher {
+    Sha1(Sha1),
+    Sha256(Sha256),
+}
+
+impl Hasher {
+    /// 追加内容
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Sha1(hasher) => hasher.update(data),
+            Hasher::Sha256(hasher) => hasher.update(data),
+        }
+    }
+
+    /// 得到摘要
+    pub fn finish(self) -> ObjectId {
+        let digest = match self {
+            Hasher::Sha1(hasher) => hasher.finalize().to_vec(),
+            Hasher::Sha256(hasher) => hasher.finalize().to_vec(),
+        };
+        ObjectId::from_bytes(&digest).expect("digest has a valid object id length")
+    }
+}
+
+/// 对象 ID，长度由对象格式决定
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ObjectId {
+    bytes: [u8; MAX_OBJECT_ID_LEN],
+    len: u8,
+}
+
+impl ObjectId {
+    /// SHA-1 的全零 ID，在协议中表示“不存在”
+    pub const ZERO: ObjectId = ObjectId {
+        bytes: [0; MAX_OBJECT_ID_LEN],
+        len: OBJECT_ID_LEN as u8,
+    };
+
+    /// 从原始字节构造对象 ID，长度决定对象格式
+    pub fn from_bytes(bytes: &[u8]) -> MonoResult<ObjectId> {
+        if bytes.len() != OBJECT_ID_LEN && bytes.len() != MAX_OBJECT_ID_LEN {
+            return Err(MonoError::protocol(format!("invalid object id length: {}", bytes.len())));
+        }
+        let mut id = ObjectId {
+            bytes: [0; MAX_OBJECT_ID_LEN],
+            len: bytes.len() as u8,
+        };
+        id.bytes[..bytes.len()].copy_from_slice(bytes);
+        Ok(id)
+    }
+
+    /// 从十六进制字符串解析对象 ID，长度决定对象格式
+    pub fn from_hex(hex: &str) -> MonoResult<ObjectId> {
+        let invalid = || MonoError::protocol(format!("invalid object id: {}", hex));
+        if hex.len() != OBJECT_ID_LEN * 2 && hex.len() != MAX_OBJECT_ID_LEN * 2 {
+            return Err(invalid());
+        }
+        let mut bytes = [0u8; MAX_OBJECT_ID_LEN];
+        for (i, byte) in bytes[..hex.len() / 2].iter_mut().enumerate() {
+            *byte = u8::from_str_radix(hex.get(i * 2..i * 2 + 2).ok_or_else(invalid)?, 16)
+                .map_err(|_| invalid())?;
+        }
+        ObjectId::from_bytes(&bytes[..hex.len() / 2])
+    }
+
+    /// 计算 SHA-1 格式的对象 ID，其他格式见 [`ObjectFormat::hash_object`]
+    pub fn hash_object(object_type: ObjectType, data: &[u8]) -> ObjectId {
+        ObjectFormat::Sha1.hash_object(object_type, data)
+    }
+
+    /// 对象 ID 所属的对象格式
+    pub fn format(&self) -> ObjectFormat {
+        if usize::from(self.len) == MAX_OBJECT_ID_LEN {
+            ObjectFormat::Sha256
+        } else {
+            ObjectFormat::Sha1
+        }
+    }
+
+    /// 原始字节
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..usize::from(self.len)]
+    }
+
+    /// 十六进制表示
+    pub fn to_hex(&self) -> String {
+        self.as_bytes().iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    /// 是否为全零 ID
+    pub fn is_zero(&self) -> bool {
+        self.as_bytes().iter().all(|b| *b == 0)
+    }
+}
+
+impl Default for ObjectId {
+    fn default() -> ObjectId {
+        ObjectId::ZERO
+    }
+}
+
+impl fmt::Display for ObjectId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.to_hex())
+    }
+}
+
+impl fmt::Debug for ObjectId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ObjectId({})", self.to_hex())
+    }
+}
+
+impl FromStr for ObjectId {
+    type Err = MonoError;
+
+    fn from_str(s: &str) -> MonoResult<ObjectId> {
+        ObjectId::from_hex(s)
+    }
+}
+
+/// 序列化为十六进制字符串
+impl serde::Serialize for ObjectId {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_hex())
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for ObjectId {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<ObjectId, D::Error> {
+        let hex = String::deserialize(deserializer)?;
+        ObjectId::from_hex(&hex).map_err(serde::de::Error::custom)
+    }
+}
+
+/// 对象类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ObjectType {
+    Commit,
+    Tree,
+    Blob,
+    Tag,
+}
+
+impl ObjectType {
+    /// git 中使用的类型名
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ObjectType::Commit => "commit",
+            ObjectType::Tree => "tree",
+            ObjectType::Blob => "blob",
+            ObjectType::Tag => "tag",
+        }
+    }
+
+    /// 对象头 `"<type> <size>\0"`
+    pub fn header(&self, size: usize) -> Vec<u8> {
+        format!("{} {}\0", self.as_str(), size).into_bytes()
+    }
+}
+
+impl fmt::Display for ObjectType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ObjectType {
+    type Err = MonoError;
+
+    fn from_str(s: &str) -> MonoResult<ObjectType> {
+        match s {
+            "commit" => Ok(ObjectType::Commit),
+            "tree" => Ok(ObjectType::Tree),
+            "blob" => Ok(ObjectType::Blob),
+            "tag" => Ok(ObjectType::Tag),
+            _ => Err(MonoError::storage(format!("unknown object type: {}", s))),
+        }
+    }
+}
+
+/// 已读取到内存中的对象
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawObject {
+    pub object_type: ObjectType,
+    pub data: Vec<u8>,
+}
+
+impl RawObject {
+    pub fn new(object_type: ObjectType, data: Vec<u8>) -> RawObject {
+        RawObject { object_type, data }
+    }
+
+    /// 计算 SHA-1 格式的对象 ID
+    pub fn id(&self) -> ObjectId {
+        ObjectId::hash_object(self.object_type, &self.data)
+    }
+
+    /// 计算 `format` 格式的对象 ID
+    pub fn id_in(&self, format: ObjectFormat) -> ObjectId {
+        format.hash_object(self.object_type, &self.data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 测试对象 ID 与 git 的计算结果一致
+    #[test]
+    fn test_hash_object() {
+        // echo -n "hello" | git hash-object --stdin
+        let id = ObjectId::hash_object(ObjectType::Blob, b"hello");
+        assert_eq!(id.to_hex(), "b6fc4c620b67d95f953a5c1c1230aaab5db5a1b0");
+        // 空树
+        let empty_tree = ObjectId::hash_object(ObjectType::Tree, b"");
+        assert_eq!(empty_tree.to_hex(), "4b825dc642cb6eb9a060e54bf8d69288fbee4904");
+        // git init --object-format=sha256; echo -n "hello" | git hash-object --stdin
+        let id = ObjectFormat::Sha256.hash_object(ObjectType::Blob, b"hello");
+        assert_eq!(id.to_hex(), "8aec4e4876f854f688d0ebfc8f37598f38e5fd6903cccc850ca36591175aeb60");
+        assert_eq!(id.format(), ObjectFormat::Sha256);
+        assert_eq!(ObjectId::from_hex(&id.to_hex()).unwrap(), id);
+    }
+
+    /// 测试十六进制解析与格式化
+    #[test]
+    fn test_hex_roundtrip() {
+        let hex = "b6fc4c620b67d95f953a5c1c1230aaab5db5a1b0";
+        let id: ObjectId = hex.parse().unwrap();
+        assert_eq!(id.to_string(), hex);
+        assert_eq!(ObjectId::from_bytes(id.as_bytes()).unwrap(), id);
+        assert!(ObjectId::from_hex("xyz").is_err());
+        assert!(ObjectId::from_hex(&"g".repeat(40)).is_err());
+        assert!(ObjectId::ZERO.is_zero());
+        assert!(ObjectFormat::Sha256.zero().is_zero());
+        assert_ne!(ObjectFormat::Sha256.zero(), ObjectId::ZERO);
+    }
+
+    /// 测试对象类型的解析
+    #[test]
+    fn test_object_type() {
+        for t in [ObjectType::Commit, ObjectType::Tree, ObjectType::Blob, ObjectType::Tag] {
+            assert_eq!(t.as_str().parse::<ObjectType>().unwrap(), t);
+        }
+        assert!("unknown".parse::<ObjectType>().is_err());
+    }
+}
@@ -0,0 +1,240 @@
+//! 浅克隆：只获取最近的历史
+//!
+//! 浅仓库在 `.mono/shallow` 中记录边界上的提交（浅提交），读取这些提交时当作没有父提交，
+//! 边界之下的历史不在本地。获取时客户端发送现有的浅提交与深度限制（[`Deepen`]），
+//! 服务端算出新的边界（[`ShallowUpdate`]），只发送边界之上的对象。
+//!
+//! 深度的含义与 git 相同：`--depth 1` 只包含 want 指向的提交本身。
+
+use std::collections::{HashSet, VecDeque};
+use std::fmt;
+use std::path::Path;
+
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::object::commit::Commit;
+use crate::object::walk::ObjectReader;
+use crate::object::{ObjectId, ObjectType};
+
+/// 浅仓库记录浅提交的文件，相对于 `.mono`
+pub const SHALLOW_FILE: &str = "shallow";
+
+/// 不限深度，用于把浅仓库补全为完整仓库（与 git 的 `--unshallow` 相同）
+pub const INFINITE_DEPTH: u32 = i32::MAX as u32;
+
+/// 浅获取的深度限制
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Deepen {
+    /// 从 want 起保留的提交层数
+    Depth(u32),
+    /// 在现有浅提交之下再获取的层数
+    Relative(u32),
+    /// 只保留提交时间不早于该时间（Unix 秒）的提交
+    Since(i64),
+}
+
+impl fmt::Display for Deepen {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Deepen::Depth(depth) => write!(f, "depth {}", depth),
+            Deepen::Relative(depth) => write!(f, "deepen {}", depth),
+            Deepen::Since(time) => write!(f, "since {}", time),
+        }
+    }
+}
+
+/// 一次浅获取后客户端浅提交的变化
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ShallowUpdate {
+    /// 新的浅提交
+    pub shallow: Vec<ObjectId>,
+    /// 父提交将被发送、不再是浅提交的提交
+    pub unshallow: Vec<ObjectId>,
+}
+
+impl ShallowUpdate {
+    /// 更新后的浅提交
+    pub fn apply(&self, shallow: &HashSet<ObjectId>) -> HashSet<ObjectId> {
+        let mut out: HashSet<ObjectId> = shallow.difference(&self.unshallow.iter().copied().collect()).copied().collect();
+        out.extend(&self.shallow);
+        out
+    }
+
+    /// 除 want 之外需要遍历的起点：不再浅的提交的父提交
+    ///
+    /// 不再浅的提交本身客户端已经有了，从 want 遍历时会被当作已有对象跳过。
+    pub fn extra_tips<R: ObjectReader>(&self, reader: &mut R) -> MonoResult<Vec<ObjectId>> {
+        let mut tips = Vec::new();
+        for id in &self.unshallow {
+            tips.extend(read_commit(id, reader)?.parents);
+        }
+        Ok(tips)
+    }
+}
+
+fn read_commit<R: ObjectReader>(id: &ObjectId, reader: &mut R) -> MonoResult<Commit> {
+    let object = reader.read_object(id)?;
+    if object.object_type != ObjectType::Commit {
+        return Err(MonoError::usage(format!("{} is not a commit", id)));
+    }
+    Commit::parse(&object.data)
+}
+
+/// 按深度限制计算新的浅提交边界，`shallow` 为客户端现有的浅提交
+///
+/// 不是提交的 want（例如树）不受深度限制。
+pub fn shallow_update<R: ObjectReader>(
+    wants: &[ObjectId],
+    deepen: Deepen,
+    shallow: &HashSet<ObjectId>,
+    reader: &mut R,
+) -> MonoResult<ShallowUpdate> {
+    let mut commits = Vec::new();
+    for id in wants {
+        if reader.read_object(id)?.object_type == ObjectType::Commit {
+            commits.push(*id);
+        }
+    }
+    let mut update = ShallowUpdate::default();
+    match deepen {
+        Deepen::Depth(depth) => walk_depth(&commits, 1, depth.max(1), shallow, reader, &mut update)?,
+        Deepen::Relative(depth) => {
+            // 现有的浅提交为第 0 层，其父提交从第 1 层开始计数
+            let mut start: Vec<ObjectId> = shallow.iter().copied().collect();
+            start.sort();
+            walk_depth(&start, 0, depth, shallow, reader, &mut update)?;
+        }
+        Deepen::Since(since) => {
+            let mut seen = HashSet::new();
+            let mut pending = commits;
+            while let Some(id) = pending.pop() {
+                if !seen.insert(id) {
+                    continue;
+                }
+                let commit = read_commit(&id, reader)?;
+                let mut parents = Vec::new();
+                for parent in &commit.parents {
+                    parents.push((*parent, read_commit(parent, reader)?.committer.timestamp));
+                }
+                // 有任何一个父提交早于限制即成为边界，边界之下的父提交都不发送
+                if parents.iter().any(|(_, time)| *time < since) {
+                    if !shallow.contains(&id) {
+                        update.shallow.push(id);
+                    }
+                    continue;
+                }
+                if shallow.contains(&id) && !parents.is_empty() {
+                    update.unshallow.push(id);
+                }
+                pending.extend(parents.into_iter().map(|(parent, _)| parent));
+            }
+        }
+    }
+    Ok(update)
+}
+
+/// 从 `start`（位于第 `first` 层）按层遍历，第 `depth` 层有父提交的提交成为浅提交
+fn walk_depth<R: ObjectReader>(
+    start: &[ObjectId],
+    first: u32,
+    depth: u32,
+    shallow: &HashSet<ObjectId>,
+    reader: &mut R,
+    update: &mut ShallowUpdate,
+) -> MonoResult<()> {
+    let mut seen: HashSet<ObjectId> = start.iter().copied().collect();
+    let mut queue: VecDeque<(ObjectId, u32)> = start.iter().map(|id| (*id, first)).collect();
+    while let Some((id, level)) = queue.pop_front() {
+        let commit = read_commit(&id, reader)?;
+        if commit.parents.is_empty() {
+            continue;
+        }
+        if level >= depth {
+            if !shallow.contains(&id) {
+                update.shallow.push(id);
+            }
+            continue;
+        }
+        if shallow.contains(&id) {
+            update.unshallow.push(id);
+        }
+        for parent in commit.parents {
+            if seen.insert(parent) {
+                queue.push_back((parent, level + 1));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 读取仓库的浅提交，不是浅仓库时为空
+pub fn read_shallow(mono_dir: &Path) -> MonoResult<HashSet<ObjectId>> {
+    let text = match std::fs::read_to_string(mono_dir.join(SHALLOW_FILE)) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(HashSet::new()),
+        Err(e) => return Err(e.into()),
+    };
+    text.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.trim().parse::<ObjectId>())
+        .collect::<MonoResult<_>>()
+        .map_err(|e| e.context(format!("invalid {} file", SHALLOW_FILE)))
+}
+
+/// 写入仓库的浅提交，集合为空时删除文件
+pub fn write_shallow(mono_dir: &Path, shallow: &HashSet<ObjectId>) -> MonoResult<()> {
+    let path = mono_dir.join(SHALLOW_FILE);
+    if shallow.is_empty() {
+        return match std::fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        };
+    }
+    let mut ids: Vec<&ObjectId> = shallow.iter().collect();
+    ids.sort();
+    let text: String = ids.iter().map(|id| format!("{}\n", id)).collect();
+    std::fs::write(path, text)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{commit_files, init_repo};
+
+    /// 测试按深度、相对深度与时间计算浅提交边界，以及浅提交文件的读写
+    #[test]
+    fn test_shallow_update() {
+        let (_dir, repo) = init_repo();
+        // 提交时间依次递增：c1 最早
+        let c1 = commit_files(&repo, &[("a", b"1")], &[], "1");
+        let c2 = commit_files(&repo, &[("a", b"2")], &[c1], "22");
+        let c3 = commit_files(&repo, &[("a", b"3")], &[c2], "333");
+        let c4 = commit_files(&repo, &[("a", b"4")], &[c3], "4444");
+        let mut reader = |id: &ObjectId| repo.read_object(id);
+        let none = HashSet::new();
+
+        let update = shallow_update(&[c4], Deepen::Depth(2), &none, &mut reader).unwrap();
+        assert_eq!(update, ShallowUpdate { shallow: vec![c3], unshallow: vec![] });
+        // 深度超过历史时没有浅提交
+        let update = shallow_update(&[c4], Deepen::Depth(10), &none, &mut reader).unwrap();
+        assert_eq!(update, ShallowUpdate::default());
+
+        let shallow: HashSet<ObjectId> = [c3].into();
+        let update = shallow_update(&[c4], Deepen::Depth(3), &shallow, &mut reader).unwrap();
+        assert_eq!(update, ShallowUpdate { shallow: vec![c2], unshallow: vec![c3] });
+        assert_eq!(update.apply(&shallow), [c2].into());
+        assert_eq!(update.extra_tips(&mut reader).unwrap(), vec![c2]);
+        let update = shallow_update(&[c4], Deepen::Relative(1), &shallow, &mut reader).unwrap();
+        assert_eq!(update, ShallowUpdate { shallow: vec![c2], unshallow: vec![c3] });
+
+        let since = repo.read_commit(&c3).unwrap().committer.timestamp;
+        let update = shallow_update(&[c4], Deepen::Since(since), &none, &mut reader).unwrap();
+        assert_eq!(update, ShallowUpdate { shallow: vec![c3], unshallow: vec![] });
+
+        write_shallow(repo.mono_dir(), &shallow).unwrap();
+        assert_eq!(read_shallow(repo.mono_dir()).unwrap(), shallow);
+        write_shallow(repo.mono_dir(), &HashSet::new()).unwrap();
+        assert!(!repo.mono_dir().join(SHALLOW_FILE).exists());
+    }
+}
@@ -0,0 +1,92 @@
+//! 附注标签对象的解析与编码
+
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::object::commit::Signature;
+use crate::object::{ObjectId, ObjectType};
+
+/// 附注标签对象
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tag {
+    /// 标签指向的对象
+    pub object: ObjectId,
+    /// 标签指向的对象类型
+    pub object_type: ObjectType,
+    /// 标签名
+    pub name: String,
+    /// 打标签的人，早期的标签可能没有该字段
+    pub tagger: Option<Signature>,
+    pub message: String,
+}
+
+impl Tag {
+    /// 解析标签对象的内容
+    pub fn parse(data: &[u8]) -> MonoResult<Tag> {
+        let text = String::from_utf8_lossy(data);
+        let (header, message) = match text.find("\n\n") {
+            Some(pos) => (&text[..pos], &text[pos + 2..]),
+            None => (text.as_ref(), ""),
+        };
+        let mut object = None;
+        let mut object_type = None;
+        let mut name = None;
+        let mut tagger = None;
+        for line in header.lines() {
+            match line.split_once(' ') {
+                Some(("object", value)) => object = Some(value.parse()?),
+                Some(("type", value)) => object_type = Some(value.parse()?),
+                Some(("tag", value)) => name = Some(value.to_string()),
+                Some(("tagger", value)) => tagger = Some(value.parse()?),
+                _ => {}
+            }
+        }
+        let missing = |field: &str| MonoError::storage(format!("corrupt tag: missing {}", field));
+        Ok(Tag {
+            object: object.ok_or_else(|| missing("object"))?,
+            object_type: object_type.ok_or_else(|| missing("type"))?,
+            name: name.ok_or_else(|| missing("tag"))?,
+            tagger,
+            message: message.to_string(),
+        })
+    }
+
+    /// 编码为标签对象的内容
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = format!(
+            "object {}\ntype {}\ntag {}\n",
+            self.object, self.object_type, self.name
+        );
+        if let Some(tagger) = &self.tagger {
+            out.push_str(&format!("tagger {}\n", tagger));
+        }
+        out.push('\n');
+        out.push_str(&self.message);
+        out.into_bytes()
+    }
+
+    /// 计算标签对象 ID
+    pub fn id(&self) -> ObjectId {
+        ObjectId::hash_object(ObjectType::Tag, &self.encode())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 测试标签对象的解析与编码
+    #[test]
+    fn test_tag_roundtrip() {
+        let data = "object 4b825dc642cb6eb9a060e54bf8d69288fbee4904\n\
+            type commit\n\
+            tag v1.0.0\n\
+            tagger A <a@example.com> 1700000000 +0000\n\
+            \n\
+            release 1.0.0\n";
+        let tag = Tag::parse(data.as_bytes()).unwrap();
+        assert_eq!(tag.name, "v1.0.0");
+        assert_eq!(tag.object_type, ObjectType::Commit);
+        assert_eq!(tag.tagger.as_ref().unwrap().email, "a@example.com");
+        assert_eq!(tag.encode(), data.as_bytes());
+    }
+}
@@ -0,0 +1,230 @@
+//! 树对象的解析与编码
+
+use std::cmp::Ordering;
+
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::object::{ObjectFormat, ObjectId, ObjectType};
+
+/// 目录条目的文件模式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FileMode(pub u32);
+
+impl FileMode {
+    pub const TREE: FileMode = FileMode(0o040000);
+    pub const BLOB: FileMode = FileMode(0o100644);
+    pub const EXECUTABLE: FileMode = FileMode(0o100755);
+    pub const SYMLINK: FileMode = FileMode(0o120000);
+    pub const GITLINK: FileMode = FileMode(0o160000);
+
+    /// 是否为子目录
+    pub fn is_tree(&self) -> bool {
+        self.0 & 0o170000 == 0o040000
+    }
+
+    /// 是否为子模块
+    pub fn is_gitlink(&self) -> bool {
+        self.0 & 0o170000 == 0o160000
+    }
+
+    /// 是否指向 blob（普通文件或符号链接）
+    pub fn is_blob(&self) -> bool {
+        !self.is_tree() && !self.is_gitlink()
+    }
+
+    /// 条目指向的对象类型，子模块返回 None
+    pub fn object_type(&self) -> Option<ObjectType> {
+        if self.is_tree() {
+            Some(ObjectType::Tree)
+        } else if self.is_gitlink() {
+            None
+        } else {
+            Some(ObjectType::Blob)
+        }
+    }
+}
+
+/// 树中的一个条目
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeEntry {
+    pub mode: FileMode,
+    pub name: String,
+    pub id: ObjectId,
+}
+
+impl TreeEntry {
+    pub fn new(mode: FileMode, name: impl Into<String>, id: ObjectId) -> TreeEntry {
+        TreeEntry {
+            mode,
+            name: name.into(),
+            id,
+        }
+    }
+}
+
+/// 对树中一个路径的修改，见 [`crate::repo::Repository::edit_tree`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeEdit {
+    /// `/` 分隔的相对路径
+    pub path: String,
+    /// 写入的文件模式与对象，None 表示删除
+    pub entry: Option<(FileMode, ObjectId)>,
+}
+
+/// 树对象
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Tree {
+    pub entries: Vec<TreeEntry>,
+}
+
+impl Tree {
+    /// 解析 `format` 格式的树对象的内容
+    pub fn parse(data: &[u8], format: ObjectFormat) -> MonoResult<Tree> {
+        let corrupt = |msg: &str| MonoError::storage(format!("corrupt tree: {}", msg));
+        let mut entries = Vec::new();
+        let mut rest = data;
+        while !rest.is_empty() {
+            let space = rest
+                .iter()
+                .position(|&b| b == b' ')
+                .ok_or_else(|| corrupt("missing mode"))?;
+            let mode = std::str::from_utf8(&rest[..space])
+                .ok()
+                .and_then(|m| u32::from_str_radix(m, 8).ok())
+                .ok_or_else(|| corrupt("invalid mode"))?;
+            rest = &rest[space + 1..];
+            let nul = rest
+                .iter()
+                .position(|&b| b == 0)
+                .ok_or_else(|| corrupt("missing name terminator"))?;
+            let name = String::from_utf8_lossy(&rest[..nul]).into_owned();
+            rest = &rest[nul + 1..];
+            if rest.len() < format.id_len() {
+                return Err(corrupt("truncated entry"));
+            }
+            let id = ObjectId::from_bytes(&rest[..format.id_len()])?;
+            rest = &rest[format.id_len()..];
+            entries.push(TreeEntry::new(FileMode(mode), name, id));
+        }
+        Ok(Tree { entries })
+    }
+
+    /// 编码为树对象的内容
+    pub fn encode(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        for entry in &self.entries {
+            data.extend_from_slice(format!("{:o} {}\0", entry.mode.0, entry.name).as_bytes());
+            data.extend_from_slice(entry.id.as_bytes());
+        }
+        data
+    }
+
+    /// 计算 SHA-1 格式的树对象 ID
+    pub fn id(&self) -> ObjectId {
+        ObjectId::hash_object(ObjectType::Tree, &self.encode())
+    }
+
+    /// 按 git 的规则排序：子目录名视为带有结尾的 `/`
+    pub fn sort(&mut self) {
+        self.entries.sort_by(compare_entries);
+    }
+
+    /// 按名称查找条目
+    pub fn get(&self, name: &str) -> Option<&TreeEntry> {
+        self.entries.iter().find(|entry| entry.name == name)
+    }
+}
+
+/// 条目名能否作为路径中的一段：不能为空、`.` 或 `..`，也不能含有 `/` 或 NUL
+pub fn is_valid_name(name: &str) -> bool {
+    !(name.is_empty() || name == "." || name == ".." || name.contains(['/', '\0']))
+}
+
+/// 写入文件系统或归档前校验树 `tree` 中的条目名，拒绝可能指向目标目录之外的名字
+pub fn check_entry_name(tree: &ObjectId, name: &str) -> MonoResult<()> {
+    if is_valid_name(name) {
+        Ok(())
+    } else {
+        Err(MonoError::storage(format!("tree {} has invalid entry name {:?}", tree, name)))
+    }
+}
+
+/// git 的树条目排序规则
+pub fn compare_entries(a: &TreeEntry, b: &TreeEntry) -> Ordering {
+    let key = |e: &TreeEntry| {
+        let mut key = e.name.as_bytes().to_vec();
+        if e.mode.is_tree() {
+            key.push(b'/');
+        }
+        key
+    };
+    key(a).cmp(&key(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 测试树对象编码后能解析回相同内容，且 ID 与 git 一致
+    #[test]
+    fn test_tree_roundtrip() {
+        let blob = ObjectId::hash_object(ObjectType::Blob, b"hello");
+        let mut tree = Tree {
+            entries: vec![
+                TreeEntry::new(FileMode::BLOB, "hello.txt", blob),
+                TreeEntry::new(FileMode::EXECUTABLE, "run.sh", blob),
+            ],
+        };
+        tree.sort();
+        let parsed = Tree::parse(&tree.encode(), ObjectFormat::Sha1).unwrap();
+        assert_eq!(parsed, tree);
+        // printf 'hello' > hello.txt; cp hello.txt run.sh; chmod +x run.sh; git write-tree
+        assert_eq!(tree.id().to_hex(), "9ae5a2b0c125d8c97c28e93fbae4efad783371dc");
+    }
+
+    /// 测试子目录按带 `/` 的名字参与排序
+    #[test]
+    fn test_tree_sort() {
+        let id = ObjectId::ZERO;
+        let mut tree = Tree {
+            entries: vec![
+                TreeEntry::new(FileMode::TREE, "foo", id),
+                TreeEntry::new(FileMode::BLOB, "foo.txt", id),
+                TreeEntry::new(FileMode::BLOB, "foo-bar", id),
+            ],
+        };
+        tree.sort();
+        let names: Vec<_> = tree.entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, vec!["foo-bar", "foo.txt", "foo"]);
+    }
+
+    /// 测试文件模式的分类
+    #[test]
+    fn test_file_mode() {
+        assert!(FileMode::TREE.is_tree());
+        assert!(FileMode::BLOB.is_blob());
+        assert!(FileMode::SYMLINK.is_blob());
+        assert!(FileMode::GITLINK.is_gitlink());
+        assert_eq!(FileMode::GITLINK.object_type(), None);
+        assert_eq!(FileMode::TREE.object_type(), Some(ObjectType::Tree));
+    }
+
+    /// 测试条目名的校验规则
+    #[test]
+    fn test_valid_name() {
+        for name in ["a.txt", ".gitignore", "..a", "a..b"] {
+            assert!(is_valid_name(name), "{}", name);
+        }
+        for name in ["", ".", "..", "a/b", "/etc", "a\0b"] {
+            assert!(!is_valid_name(name), "{:?}", name);
+        }
+        assert!(check_entry_name(&ObjectId::ZERO, "..").unwrap_err().to_string().contains("invalid entry name"));
+    }
+
+    /// 测试截断的树对象返回错误
+    #[test]
+    fn test_parse_corrupt() {
+        assert!(Tree::parse(b"100644 a\0short", ObjectFormat::Sha1).is_err());
+        assert!(Tree::parse(b"abc a\0", ObjectFormat::Sha1).is_err());
+    }
+}
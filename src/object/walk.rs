@@ -0,0 +1,403 @@
+//! 对象可达性遍历
+//!
+//! 从一组起点（提交、标签或树）出发，收集需要传输的对象，
+//! 支持排除对端已有的对象以及按 [`ObjectFilter`] 过滤。
+
+use std::collections::{HashMap, HashSet};
+
+use crate::common::errors::MonoErrorKind;
+use crate::common::MonoResult;
+use crate::object::commit::Commit;
+use crate::object::filter::ObjectFilter;
+use crate::object::tag::Tag;
+use crate::object::tree::Tree;
+use crate::object::{ObjectId, ObjectType, RawObject};
+
+/// 收集到的对象：ID、类型与文件名的 [`name_hash`]
+pub type NamedObject = (ObjectId, ObjectType, u32);
+
+/// 遍历过程中读取对象的函数，对象不存在时应返回 NotFound 错误
+pub trait ObjectReader {
+    fn read_object(&mut self, id: &ObjectId) -> MonoResult<RawObject>;
+}
+
+impl<F> ObjectReader for F
+where
+    F: FnMut(&ObjectId) -> MonoResult<RawObject>,
+{
+    fn read_object(&mut self, id: &ObjectId) -> MonoResult<RawObject> {
+        self(id)
+    }
+}
+
+/// 收集从 `tips` 可达、但从 `exclude` 不可达的对象
+///
+/// 返回的对象按发现顺序排列。`exclude` 中对方声称拥有但本地不存在的对象会被忽略。
+pub fn collect_objects<R: ObjectReader>(
+    tips: &[ObjectId],
+    exclude: &[ObjectId],
+    filter: &ObjectFilter,
+    reader: &mut R,
+) -> MonoResult<Vec<(ObjectId, ObjectType)>> {
+    let objects = collect_named_objects(tips, exclude, filter, reader)?;
+    Ok(objects.into_iter().map(|(id, object_type, _)| (id, object_type)).collect())
+}
+
+/// 与 [`collect_objects`] 相同，同时返回对象在树中文件名的 [`name_hash`]，
+/// 提交、标签与根树为 0，生成 pack 时据此把可能相似的对象排在一起
+pub fn collect_named_objects<R: ObjectReader>(
+    tips: &[ObjectId],
+    exclude: &[ObjectId],
+    filter: &ObjectFilter,
+    reader: &mut R,
+) -> MonoResult<Vec<NamedObject>> {
+    let uninteresting = uninteresting(exclude, reader)?;
+    let mut walker = Walker::new(*filter, uninteresting, false);
+    walker.walk(tips, reader)?;
+    Ok(walker.out)
+}
+
+/// 与 [`collect_named_objects`] 相同，但跳过 `hidden` 返回真的路径（相对于根树）下的树和 blob，
+/// 返回值的第二项表示是否跳过了对象。同一对象在其他未隐藏的路径下出现时仍会返回
+pub fn collect_visible_objects<R: ObjectReader>(
+    tips: &[ObjectId],
+    exclude: &[ObjectId],
+    filter: &ObjectFilter,
+    hidden: &dyn Fn(&str) -> bool,
+    reader: &mut R,
+) -> MonoResult<(Vec<NamedObject>, bool)> {
+    let uninteresting = uninteresting(exclude, reader)?;
+    let mut walker = Walker::new(*filter, uninteresting, false);
+    walker.hidden = Some(hidden);
+    walker.walk(tips, reader)?;
+    Ok((walker.out, walker.skipped))
+}
+
+/// 与 [`collect_visible_objects`] 相同，但直接给出对端已有的对象（例如由可达性位图算出），
+/// 不再从 `exclude` 遍历；`hidden` 为 None 时不跟踪路径
+pub fn collect_objects_excluding<R: ObjectReader>(
+    tips: &[ObjectId],
+    uninteresting: HashSet<ObjectId>,
+    filter: &ObjectFilter,
+    hidden: Option<&dyn Fn(&str) -> bool>,
+    reader: &mut R,
+) -> MonoResult<(Vec<NamedObject>, bool)> {
+    let mut walker = Walker::new(*filter, uninteresting, false);
+    walker.hidden = hidden;
+    walker.walk(tips, reader)?;
+    Ok((walker.out, walker.skipped))
+}
+
+/// 浅获取的遍历：不遍历 `shallow` 中提交的父提交；对端已有的对象从 `exclude` 出发遍历，
+/// 同样不越过对端获取前的浅提交 `exclude_shallow`（对端没有其下的历史）
+pub fn collect_shallow_objects<R: ObjectReader>(
+    tips: &[ObjectId],
+    exclude: &[ObjectId],
+    shallow: HashSet<ObjectId>,
+    exclude_shallow: HashSet<ObjectId>,
+    filter: &ObjectFilter,
+    hidden: Option<&dyn Fn(&str) -> bool>,
+    reader: &mut R,
+) -> MonoResult<(Vec<NamedObject>, bool)> {
+    let mut uninteresting = HashSet::new();
+    if !exclude.is_empty() {
+        let mut walker = Walker::new(ObjectFilter::None, HashSet::new(), true);
+        walker.shallow = exclude_shallow;
+        walker.walk(exclude, reader)?;
+        uninteresting = walker.out.into_iter().map(|(id, _, _)| id).collect();
+    }
+    let mut walker = Walker::new(*filter, uninteresting, false);
+    walker.shallow = shallow;
+    walker.hidden = hidden;
+    walker.walk(tips, reader)?;
+    Ok((walker.out, walker.skipped))
+}
+
+/// 从 `exclude` 可达的对象，不存在的对象会被忽略
+fn uninteresting<R: ObjectReader>(exclude: &[ObjectId], reader: &mut R) -> MonoResult<HashSet<ObjectId>> {
+    if exclude.is_empty() {
+        return Ok(HashSet::new());
+    }
+    let mut walker = Walker::new(ObjectFilter::None, HashSet::new(), true);
+    walker.walk(exclude, reader)?;
+    Ok(walker.out.into_iter().map(|(id, _, _)| id).collect())
+}
+
+/// 文件名的哈希，主要由最后几个字符决定，扩展名相同的文件哈希值相近，与 git 的算法一致
+pub fn name_hash(name: &str) -> u32 {
+    name.bytes()
+        .filter(|b| !b.is_ascii_whitespace())
+        .fold(0u32, |hash, b| (hash >> 2).wrapping_add((b as u32) << 24))
+}
+
+struct Walker<'h> {
+    filter: ObjectFilter,
+    uninteresting: HashSet<ObjectId>,
+    /// 为 true 时跳过不存在的对象而不是报错
+    ignore_missing: bool,
+    seen: HashSet<ObjectId>,
+    /// 已遍历的树及其最小深度，同一棵树在更浅的位置出现时需要重新遍历
+    tree_depth: HashMap<ObjectId, u64>,
+    out: Vec<NamedObject>,
+    /// 不可见的路径，设置时才跟踪树条目的路径
+    hidden: Option<&'h dyn Fn(&str) -> bool>,
+    /// 是否因路径不可见跳过了对象
+    skipped: bool,
+    /// 浅提交，不遍历其父提交
+    shallow: HashSet<ObjectId>,
+}
+
+impl<'h> Walker<'h> {
+    fn new(filter: ObjectFilter, uninteresting: HashSet<ObjectId>, ignore_missing: bool) -> Walker<'h> {
+        Walker {
+            filter,
+            uninteresting,
+            ignore_missing,
+            seen: HashSet::new(),
+            tree_depth: HashMap::new(),
+            out: Vec::new(),
+            hidden: None,
+            skipped: false,
+            shallow: HashSet::new(),
+        }
+    }
+
+    /// 树条目的路径，不跟踪路径时为空
+    fn entry_path(&self, dir: &str, name: &str) -> String {
+        match self.hidden {
+            None => String::new(),
+            Some(_) if dir.is_empty() => name.to_string(),
+            Some(_) => format!("{}/{}", dir, name),
+        }
+    }
+
+    /// 路径是否不可见，不可见时记录跳过了对象
+    fn hides(&mut self, path: &str) -> bool {
+        let hidden = self.hidden.is_some_and(|hidden| hidden(path));
+        self.skipped |= hidden;
+        hidden
+    }
+
+    fn read<R: ObjectReader>(&self, id: &ObjectId, reader: &mut R) -> MonoResult<Option<RawObject>> {
+        match reader.read_object(id) {
+            Ok(object) => Ok(Some(object)),
+            Err(e) if self.ignore_missing && matches!(e.kind(), MonoErrorKind::NotFound(_)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn walk<R: ObjectReader>(&mut self, tips: &[ObjectId], reader: &mut R) -> MonoResult<()> {
+        let mut pending: Vec<ObjectId> = tips.iter().rev().copied().collect();
+        while let Some(id) = pending.pop() {
+            if self.uninteresting.contains(&id) || self.seen.contains(&id) {
+                continue;
+            }
+            let Some(object) = self.read(&id, reader)? else {
+                continue;
+            };
+            match object.object_type {
+                ObjectType::Commit => {
+                    self.seen.insert(id);
+                    self.out.push((id, ObjectType::Commit, 0));
+                    let commit = Commit::parse(&object.data)?;
+                    self.walk_tree(commit.tree, reader)?;
+                    if !self.shallow.contains(&id) {
+                        pending.extend(commit.parents.iter().rev());
+                    }
+                }
+                ObjectType::Tag => {
+                    self.seen.insert(id);
+                    self.out.push((id, ObjectType::Tag, 0));
+                    pending.push(Tag::parse(&object.data)?.object);
+                }
+                ObjectType::Tree => self.walk_tree(id, reader)?,
+                ObjectType::Blob => {
+                    self.seen.insert(id);
+                    self.out.push((id, ObjectType::Blob, 0));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn walk_tree<R: ObjectReader>(&mut self, root: ObjectId, reader: &mut R) -> MonoResult<()> {
+        let mut stack = vec![(root, 0u64, 0u32, String::new())];
+        while let Some((id, depth, hash, path)) = stack.pop() {
+            if self.uninteresting.contains(&id) || !self.filter.includes(ObjectType::Tree, depth) {
+                continue;
+            }
+            match self.tree_depth.get(&id) {
+                Some(&seen_depth) if seen_depth <= depth => continue,
+                Some(_) => {}
+                None => self.out.push((id, ObjectType::Tree, hash)),
+            }
+            self.tree_depth.insert(id, depth);
+            let Some(object) = self.read(&id, reader)? else {
+                continue;
+            };
+            for entry in Tree::parse(&object.data, id.format())?.entries {
+                let entry_path = self.entry_path(&path, &entry.name);
+                match entry.mode.object_type() {
+                    Some(_) if self.hides(&entry_path) => {}
+                    Some(ObjectType::Tree) => stack.push((entry.id, depth + 1, name_hash(&entry.name), entry_path)),
+                    Some(ObjectType::Blob)
+                        if self.filter.includes(ObjectType::Blob, depth + 1)
+                            && !self.uninteresting.contains(&entry.id)
+                            && self.seen.insert(entry.id) =>
+                    {
+                        self.out.push((entry.id, ObjectType::Blob, name_hash(&entry.name)));
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::errors::MonoError;
+    use crate::object::commit::Signature;
+    use crate::object::tree::{FileMode, TreeEntry};
+
+    #[derive(Default)]
+    struct MemoryObjects(HashMap<ObjectId, RawObject>);
+
+    impl MemoryObjects {
+        fn put(&mut self, object_type: ObjectType, data: Vec<u8>) -> ObjectId {
+            let object = RawObject::new(object_type, data);
+            let id = object.id();
+            self.0.insert(id, object);
+            id
+        }
+
+        fn commit(&mut self, tree: ObjectId, parents: Vec<ObjectId>, msg: &str) -> ObjectId {
+            let sig = Signature::new("A", "a@example.com", 1);
+            let commit = Commit {
+                tree,
+                parents,
+                author: sig.clone(),
+                committer: sig,
+                extra_headers: Vec::new(),
+                message: msg.to_string(),
+            };
+            self.put(ObjectType::Commit, commit.encode())
+        }
+    }
+
+    impl ObjectReader for MemoryObjects {
+        fn read_object(&mut self, id: &ObjectId) -> MonoResult<RawObject> {
+            self.0
+                .get(id)
+                .cloned()
+                .ok_or_else(|| MonoError::not_found(id.to_string()))
+        }
+    }
+
+    /// 构造两层目录：root/{a.txt, dir/{b.txt}}，返回 (objects, commit, blobs)
+    fn fixture() -> (MemoryObjects, ObjectId, ObjectId, ObjectId) {
+        let mut objects = MemoryObjects::default();
+        let a = objects.put(ObjectType::Blob, b"a".to_vec());
+        let b = objects.put(ObjectType::Blob, b"b".to_vec());
+        let sub = Tree {
+            entries: vec![TreeEntry::new(FileMode::BLOB, "b.txt", b)],
+        };
+        let sub_id = objects.put(ObjectType::Tree, sub.encode());
+        let mut root = Tree {
+            entries: vec![
+                TreeEntry::new(FileMode::BLOB, "a.txt", a),
+                TreeEntry::new(FileMode::TREE, "dir", sub_id),
+            ],
+        };
+        root.sort();
+        let root_id = objects.put(ObjectType::Tree, root.encode());
+        let commit = objects.commit(root_id, vec![], "init");
+        (objects, commit, a, b)
+    }
+
+    fn count(objects: &[(ObjectId, ObjectType)], object_type: ObjectType) -> usize {
+        objects.iter().filter(|(_, t)| *t == object_type).count()
+    }
+
+    /// 测试不过滤时收集全部对象
+    #[test]
+    fn test_collect_all() {
+        let (mut objects, commit, a, b) = fixture();
+        let result = collect_objects(&[commit], &[], &ObjectFilter::None, &mut objects).unwrap();
+        assert_eq!(result.len(), 5);
+        assert_eq!(result[0], (commit, ObjectType::Commit));
+        assert!(result.contains(&(a, ObjectType::Blob)));
+        assert!(result.contains(&(b, ObjectType::Blob)));
+
+        let named = collect_named_objects(&[commit], &[], &ObjectFilter::None, &mut objects).unwrap();
+        assert!(named.contains(&(commit, ObjectType::Commit, 0)));
+        assert!(named.contains(&(a, ObjectType::Blob, name_hash("a.txt"))));
+        assert!(named.iter().any(|(_, t, hash)| *t == ObjectType::Tree && *hash == name_hash("dir")));
+        // 扩展名相同的文件哈希值的高位相同
+        assert_eq!(name_hash("a.txt") >> 24, name_hash("b.txt") >> 24);
+        assert_eq!(name_hash("a .txt"), name_hash("a.txt"));
+    }
+
+    /// 测试 blob:none 与 tree:<depth> 过滤
+    #[test]
+    fn test_collect_filtered() {
+        let (mut objects, commit, _, _) = fixture();
+        let result = collect_objects(&[commit], &[], &ObjectFilter::BlobNone, &mut objects).unwrap();
+        assert_eq!(count(&result, ObjectType::Blob), 0);
+        assert_eq!(count(&result, ObjectType::Tree), 2);
+
+        let result = collect_objects(&[commit], &[], &ObjectFilter::TreeDepth(1), &mut objects).unwrap();
+        assert_eq!(count(&result, ObjectType::Tree), 1);
+        assert_eq!(count(&result, ObjectType::Blob), 0);
+
+        let result = collect_objects(&[commit], &[], &ObjectFilter::TreeDepth(2), &mut objects).unwrap();
+        assert_eq!(count(&result, ObjectType::Tree), 2);
+        assert_eq!(count(&result, ObjectType::Blob), 1);
+    }
+
+    /// 测试跳过不可见路径下的树和 blob
+    #[test]
+    fn test_collect_visible() {
+        let (mut objects, commit, a, b) = fixture();
+        let hidden = |path: &str| path == "dir";
+        let (result, skipped) = collect_visible_objects(&[commit], &[], &ObjectFilter::None, &hidden, &mut objects).unwrap();
+        assert!(skipped);
+        assert_eq!(result.len(), 3);
+        assert!(result.iter().any(|(id, _, _)| *id == a));
+        assert!(!result.iter().any(|(id, _, _)| *id == b));
+
+        let nothing = |path: &str| path == "dir/c.txt";
+        let (result, skipped) = collect_visible_objects(&[commit], &[], &ObjectFilter::None, &nothing, &mut objects).unwrap();
+        assert!(!skipped);
+        assert_eq!(result.len(), 5);
+    }
+
+    /// 测试排除对端已有的历史
+    #[test]
+    fn test_collect_with_exclude() {
+        let (mut objects, first, _, _) = fixture();
+        let c = objects.put(ObjectType::Blob, b"c".to_vec());
+        let tree = Tree {
+            entries: vec![TreeEntry::new(FileMode::BLOB, "c.txt", c)],
+        };
+        let tree_id = objects.put(ObjectType::Tree, tree.encode());
+        let second = objects.commit(tree_id, vec![first], "second");
+
+        let result = collect_objects(&[second], &[first], &ObjectFilter::None, &mut objects).unwrap();
+        assert_eq!(
+            result,
+            vec![
+                (second, ObjectType::Commit),
+                (tree_id, ObjectType::Tree),
+                (c, ObjectType::Blob)
+            ]
+        );
+
+        // 对端声称拥有但本地不存在的对象被忽略
+        let unknown = ObjectId::hash_object(ObjectType::Commit, b"unknown");
+        let result = collect_objects(&[second], &[unknown], &ObjectFilter::None, &mut objects).unwrap();
+        assert_eq!(result.len(), 8);
+    }
+}
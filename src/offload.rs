@@ -0,0 +1,233 @@
+//! 把大 pack 卸载到 S3/CDN
+//!
+//! 大量 CI 同时克隆时，服务端的 CPU 与出口带宽主要花在重复发送同一批基础对象上。配置
+//! `[offload]` 后，`mono offload sync` 把不小于 `min_pack_size` 的本地 pack 上传到
+//! `[offload.s3]` 存储桶的 `packs/` 下，并登记在 `.mono/offload.json`。客户端 fetch 时带有
+//! `packfile-uris https` 参数（git 的 `fetch.uriProtocols`）的，upload-pack 对完整包含在本次
+//! 响应中的已登记 pack 只返回签名地址，客户端自行下载，内联的 pack 只包含其余对象。
+//!
+//! 签名地址默认为 S3 预签名地址；配置 `cdn_url` 时为 CDN 地址
+//! `<cdn_url>/packs/pack-<校验和>.pack?expires=<时间戳>&signature=<签名>`，签名为以
+//! `MONO_OFFLOAD_SECRET` 为密钥对 `/packs/pack-<校验和>.pack?expires=<时间戳>` 计算的
+//! HMAC-SHA256（十六进制），由 CDN 边缘校验。
+//!
+//! repack 删除的本地 pack 不再被返回，下次 sync 时从登记中移除。以 zstd 压缩的 pack（见
+//! [`crate::object::codec`]）git 不能读取，不上传。
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use crate::common::config::OffloadConfig;
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::object::codec::Codec;
+use crate::object::ObjectId;
+use crate::pack::file::PackFile;
+use crate::pack::index::PackIndex;
+use crate::repo::Repository;
+use crate::storage::fs::list_packs;
+use crate::storage::s3::S3Store;
+
+/// 登记文件，相对于 `.mono`
+pub const OFFLOAD_FILE: &str = "offload.json";
+/// CDN 签名密钥的环境变量
+pub const CDN_SECRET_ENV: &str = "MONO_OFFLOAD_SECRET";
+
+/// 已上传的 pack
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct OffloadedPack {
+    /// pack 的校验和，也是协议中的 pack 名
+    pub checksum: ObjectId,
+    pub size: u64,
+    pub objects: usize,
+    pub uploaded_at: i64,
+}
+
+impl OffloadedPack {
+    /// 存储桶中的键，不含 `[offload.s3] prefix`
+    pub fn key(&self) -> String {
+        format!("packs/pack-{}.pack", self.checksum)
+    }
+}
+
+/// 仓库的 pack 卸载
+pub struct Offload<'a> {
+    repo: &'a Repository,
+    config: &'a OffloadConfig,
+}
+
+impl<'a> Offload<'a> {
+    /// 未配置 `[offload.s3]` 时返回 None
+    pub fn new(repo: &'a Repository) -> Option<Offload<'a>> {
+        let config = &repo.config().offload;
+        config.s3.as_ref()?;
+        Some(Offload { repo, config })
+    }
+
+    fn path(&self) -> PathBuf {
+        self.repo.mono_dir().join(OFFLOAD_FILE)
+    }
+
+    /// 已登记的 pack
+    pub fn list(&self) -> MonoResult<Vec<OffloadedPack>> {
+        let data = match std::fs::read(self.path()) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        serde_json::from_slice(&data).map_err(|e| MonoError::storage(format!("invalid {}: {}", OFFLOAD_FILE, e)))
+    }
+
+    fn save(&self, packs: &[OffloadedPack]) -> MonoResult<()> {
+        let data = serde_json::to_vec_pretty(packs).map_err(|e| MonoError::storage(e.to_string()))?;
+        let path = self.path();
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, data)?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    fn store(&self) -> MonoResult<S3Store> {
+        let config = self.config.s3.clone().ok_or_else(|| MonoError::config("offload requires [offload.s3]"))?;
+        S3Store::from_env(config)
+    }
+
+    /// 上传新的大 pack 到存储桶，移除本地已不存在的 pack 的登记，返回新上传的 pack
+    pub fn sync(&self, now: i64) -> MonoResult<Vec<OffloadedPack>> {
+        let store = self.store()?;
+        self.sync_with(&mut |key, path| store.put(key, &std::fs::read(path)?), now)
+    }
+
+    /// 与 [`Offload::sync`] 相同，但用 `upload(键, pack 路径)` 上传
+    pub fn sync_with(&self, upload: &mut dyn FnMut(&str, &Path) -> MonoResult<()>, now: i64) -> MonoResult<Vec<OffloadedPack>> {
+        let local = self.local_packs()?;
+        let mut packs = self.list()?;
+        packs.retain(|pack| local.iter().any(|(_, index)| index.pack_checksum() == &pack.checksum));
+        let mut uploaded = Vec::new();
+        for (path, index) in local {
+            let size = std::fs::metadata(&path)?.len();
+            if size < self.config.min_pack_size || packs.iter().any(|pack| &pack.checksum == index.pack_checksum()) {
+                continue;
+            }
+            if PackFile::open(&path)?.codec() != Codec::Zlib {
+                continue;
+            }
+            let pack = OffloadedPack {
+                checksum: *index.pack_checksum(),
+                size,
+                objects: index.len(),
+                uploaded_at: now,
+            };
+            upload(&pack.key(), &path)?;
+            tracing::info!(pack = %pack.checksum, size, "offloaded pack");
+            packs.push(pack.clone());
+            uploaded.push(pack);
+        }
+        self.save(&packs)?;
+        Ok(uploaded)
+    }
+
+    /// 本地的 pack 文件与索引
+    fn local_packs(&self) -> MonoResult<Vec<(PathBuf, PackIndex)>> {
+        let mut packs = Vec::new();
+        for (idx, _) in list_packs(&self.repo.objects_dir().join("pack"))? {
+            let index = PackIndex::parse(&std::fs::read(&idx)?)?;
+            packs.push((idx.with_extension("pack"), index));
+        }
+        Ok(packs)
+    }
+
+    /// 已登记且对象全部在 `objects` 中的 pack，以及这些 pack 的对象
+    pub fn select(&self, objects: &HashSet<ObjectId>) -> MonoResult<(Vec<OffloadedPack>, HashSet<ObjectId>)> {
+        let packs = self.list()?;
+        let mut selected = Vec::new();
+        let mut covered = HashSet::new();
+        if packs.is_empty() {
+            return Ok((selected, covered));
+        }
+        for (_, index) in self.local_packs()? {
+            let Some(pack) = packs.iter().find(|pack| &pack.checksum == index.pack_checksum()) else {
+                continue;
+            };
+            let ids: Vec<ObjectId> = index.entries().iter().map(|entry| entry.id).collect();
+            // 已被其他选中的 pack 覆盖的对象不能再次计入，否则客户端会收到重复对象
+            if ids.iter().all(|id| objects.contains(id) && !covered.contains(id)) {
+                covered.extend(ids);
+                selected.push(pack.clone());
+            }
+        }
+        Ok((selected, covered))
+    }
+
+    /// pack 的签名下载地址，`now` 为 Unix 时间戳
+    pub fn url(&self, pack: &OffloadedPack, now: i64) -> MonoResult<String> {
+        let Some(cdn_url) = &self.config.cdn_url else {
+            return Ok(self.store()?.presign_get(&pack.key(), self.config.expires_secs));
+        };
+        let secret = std::env::var(CDN_SECRET_ENV)
+            .map_err(|_| MonoError::config(format!("offload.cdn_url requires {}", CDN_SECRET_ENV)))?;
+        Ok(cdn_sign(cdn_url, &pack.key(), secret.as_bytes(), now + self.config.expires_secs as i64))
+    }
+}
+
+/// 生成 CDN 签名地址
+fn cdn_sign(cdn_url: &str, key: &str, secret: &[u8], expires: i64) -> String {
+    let resource = format!("/{}?expires={}", key, expires);
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("hmac accepts keys of any length");
+    mac.update(resource.as_bytes());
+    let signature: String = mac.finalize().into_bytes().iter().map(|b| format!("{:02x}", b)).collect();
+    format!("{}{}&signature={}", cdn_url.trim_end_matches('/'), resource, signature)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::config::S3Config;
+    use crate::pack::encode_pack;
+    use crate::test_utils::{commit_files, init_repo};
+
+    /// 测试上传大 pack、按本次响应的对象选择 pack，以及 CDN 签名地址
+    #[test]
+    fn test_offload() {
+        let (_source_dir, source) = init_repo();
+        let first = commit_files(&source, &[("a.txt", b"a")], &[], "first");
+        commit_files(&source, &[("a.txt", b"b")], &[first], "second");
+        let ids = source.objects().list().unwrap();
+        let objects: Vec<_> = ids.iter().map(|id| source.read_object(id).unwrap()).collect();
+
+        let (_dir, mut repo) = init_repo();
+        repo.objects().write_pack(&encode_pack(objects.iter()).unwrap()).unwrap();
+        assert!(Offload::new(&repo).is_none());
+        repo.config_mut().offload.s3 = Some(S3Config::new("packs"));
+        repo.config_mut().offload.cdn_url = Some("https://cdn.example.com/".to_string());
+        repo.config_mut().offload.min_pack_size = 0;
+        let offload = Offload::new(&repo).unwrap();
+
+        let mut uploads = Vec::new();
+        let mut upload = |key: &str, path: &Path| {
+            uploads.push((key.to_string(), path.to_path_buf()));
+            Ok(())
+        };
+        let uploaded = offload.sync_with(&mut upload, 100).unwrap();
+        assert_eq!(uploaded.len(), 1);
+        assert_eq!(uploaded[0].objects, ids.len());
+        assert!(uploads[0].1.exists());
+        assert!(offload.sync_with(&mut |_, _| panic!("uploaded twice"), 200).unwrap().is_empty());
+
+        let all: HashSet<ObjectId> = ids.iter().copied().collect();
+        let (selected, covered) = offload.select(&all).unwrap();
+        assert_eq!(selected, uploaded);
+        assert_eq!(covered, all);
+        let partial: HashSet<ObjectId> = ids[1..].iter().copied().collect();
+        assert!(offload.select(&partial).unwrap().0.is_empty());
+
+        let url = cdn_sign("https://cdn.example.com/", &uploaded[0].key(), b"secret", 3700);
+        let expected = format!("https://cdn.example.com/packs/pack-{}.pack?expires=3700&signature=", uploaded[0].checksum);
+        assert!(url.starts_with(&expected), "{}", url);
+        assert_eq!(url.len(), expected.len() + 64);
+    }
+}
@@ -0,0 +1,440 @@
+//! 基于路径的代码所有权
+//!
+//! 任意目录下都可以放置 `CODEOWNERS` 文件，每行一条规则：路径模式后跟若干负责人
+//! （`@user`、`@org/team` 或邮箱），`#` 开始注释：
+//!
+//! ```text
+//! *.proto         @org/api-reviewers
+//! /migrations/    @dba
+//! generated/      # 没有负责人：显式声明无人负责
+//! ```
+//!
+//! 模式相对于 `CODEOWNERS` 所在目录，语法与 GitHub 的 CODEOWNERS 相同：不含 `/` 的模式
+//! 匹配任意深度的文件或目录名，含 `/` 的模式从所在目录开始匹配，以 `/` 结尾的模式只匹配目录，
+//! `*` 与 `?` 不跨越 `/`，`**` 匹配任意层目录。匹配到目录即匹配其下的全部文件，
+//! 但以 `/*` 结尾的模式只匹配目录中的直接文件。
+//!
+//! 查找一个路径的负责人时从最近的目录向上逐级查找，使用第一个有规则匹配的 `CODEOWNERS`；
+//! 同一文件中后面的规则优先。
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::object::ObjectId;
+use crate::repo::Repository;
+
+/// 所有权文件名
+pub const OWNERS_FILE: &str = "CODEOWNERS";
+
+/// 所有权规则的路径模式
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OwnerPattern {
+    text: String,
+    /// 以 `/` 分隔的各段，可能包含通配符
+    segments: Vec<String>,
+    /// 从所在目录开始匹配，否则匹配任意深度的名称
+    anchored: bool,
+    /// 只匹配目录
+    dir_only: bool,
+}
+
+impl OwnerPattern {
+    /// 相对于所有权文件所在目录的文件路径是否匹配
+    pub fn matches(&self, path: &str) -> bool {
+        let parts: Vec<&str> = path.split('/').collect();
+        if !self.anchored {
+            // 最后一段是文件名，只匹配目录的模式不能匹配它
+            let candidates = if self.dir_only { parts.len() - 1 } else { parts.len() };
+            return parts[..candidates].iter().any(|part| glob_match(&self.segments[0], part));
+        }
+        if self.segments.last().is_some_and(|last| last == "*") {
+            return !self.dir_only && match_segments(&self.segments, &parts);
+        }
+        (1..=parts.len())
+            .filter(|&len| !self.dir_only || len < parts.len())
+            .any(|len| match_segments(&self.segments, &parts[..len]))
+    }
+}
+
+impl FromStr for OwnerPattern {
+    type Err = MonoError;
+
+    fn from_str(s: &str) -> MonoResult<OwnerPattern> {
+        let invalid = || MonoError::usage(format!("invalid owners pattern: {}", s));
+        if s.starts_with('!') {
+            return Err(MonoError::usage(format!("negated owners patterns are not supported: {}", s)));
+        }
+        let dir_only = s.ends_with('/');
+        let trimmed = s.strip_suffix('/').unwrap_or(s);
+        let anchored = trimmed.contains('/');
+        let body = trimmed.strip_prefix('/').unwrap_or(trimmed);
+        let segments: Vec<String> = body.split('/').map(str::to_string).collect();
+        if segments.iter().any(|segment| segment.is_empty() || segment == "." || segment == "..") {
+            return Err(invalid());
+        }
+        Ok(OwnerPattern {
+            text: s.to_string(),
+            segments,
+            anchored,
+            dir_only,
+        })
+    }
+}
+
+impl fmt::Display for OwnerPattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+/// 逐段匹配，`**` 匹配零个或多个段
+fn match_segments(pattern: &[String], parts: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => parts.is_empty(),
+        Some((first, rest)) if first == "**" => (0..=parts.len()).any(|skip| match_segments(rest, &parts[skip..])),
+        Some((first, rest)) => parts
+            .split_first()
+            .is_some_and(|(part, parts)| glob_match(first, part) && match_segments(rest, parts)),
+    }
+}
+
+/// 单段内的通配符匹配：`*` 匹配任意字符序列，`?` 匹配单个字符
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // 最近一个 `*` 的位置及其当前匹配到的文本位置，失配时回溯
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    p = star_p + 1;
+                    t = star_t + 1;
+                    star = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// 一条所有权规则
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OwnerRule {
+    pub pattern: OwnerPattern,
+    /// 负责人，为空表示匹配的路径无人负责
+    pub owners: Vec<String>,
+    /// 规则在文件中的行号，从 1 开始
+    pub line: usize,
+}
+
+/// 一个目录下的所有权文件
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OwnersFile {
+    /// 所在目录，空字符串表示仓库根目录
+    pub dir: String,
+    pub rules: Vec<OwnerRule>,
+}
+
+impl OwnersFile {
+    /// 解析 `dir` 目录下所有权文件的内容
+    pub fn parse(dir: &str, content: &str) -> MonoResult<OwnersFile> {
+        let path = join(dir, OWNERS_FILE);
+        let mut rules = Vec::new();
+        for (i, line) in content.lines().enumerate() {
+            let mut tokens = line.split_whitespace().take_while(|token| !token.starts_with('#'));
+            let Some(pattern) = tokens.next() else {
+                continue;
+            };
+            let pattern = pattern
+                .parse()
+                .map_err(|e: MonoError| MonoError::config(format!("{}:{}: {}", path, i + 1, e)))?;
+            let owners: Vec<String> = tokens.map(str::to_string).collect();
+            if let Some(owner) = owners.iter().find(|owner| !is_valid_owner(owner)) {
+                return Err(MonoError::config(format!("{}:{}: invalid owner {}", path, i + 1, owner)));
+            }
+            rules.push(OwnerRule {
+                pattern,
+                owners,
+                line: i + 1,
+            });
+        }
+        Ok(OwnersFile {
+            dir: dir.to_string(),
+            rules,
+        })
+    }
+
+    /// 文件在仓库中的路径
+    pub fn path(&self) -> String {
+        join(&self.dir, OWNERS_FILE)
+    }
+
+    /// 匹配仓库路径 `path` 的最后一条规则，路径不在所在目录下时返回 None
+    pub fn matching_rule(&self, path: &str) -> Option<&OwnerRule> {
+        let relative = if self.dir.is_empty() {
+            path
+        } else {
+            path.strip_prefix(self.dir.as_str())?.strip_prefix('/')?
+        };
+        self.rules.iter().rev().find(|rule| rule.pattern.matches(relative))
+    }
+}
+
+/// 负责人写作 `@user`、`@org/team` 或邮箱
+fn is_valid_owner(owner: &str) -> bool {
+    match owner.strip_prefix('@') {
+        Some(name) => !name.is_empty() && !name.contains('@'),
+        None => owner.split_once('@').is_some_and(|(user, domain)| !user.is_empty() && domain.contains('.')),
+    }
+}
+
+fn join(dir: &str, name: &str) -> String {
+    if dir.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}/{}", dir, name)
+    }
+}
+
+fn parent(path: &str) -> &str {
+    path.rsplit_once('/').map_or("", |(dir, _)| dir)
+}
+
+/// 决定所有权的规则所在位置
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuleSource {
+    /// 所有权文件在仓库中的路径
+    pub file: String,
+    pub line: usize,
+}
+
+/// 一个路径的负责人
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ownership {
+    pub path: String,
+    /// 负责人，为空表示无人负责
+    pub owners: Vec<String>,
+    /// 匹配的规则，没有任何规则匹配时为 None
+    pub source: Option<RuleSource>,
+}
+
+/// 一组路径的负责人
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OwnersReport {
+    pub paths: Vec<Ownership>,
+}
+
+impl OwnersReport {
+    /// 每个负责人及其负责的路径，用于按负责人分派评审
+    pub fn by_owner(&self) -> BTreeMap<&str, Vec<&str>> {
+        let mut owners: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+        for ownership in &self.paths {
+            for owner in &ownership.owners {
+                owners.entry(owner).or_default().push(&ownership.path);
+            }
+        }
+        owners
+    }
+
+    /// 无人负责的路径
+    pub fn unowned(&self) -> Vec<&str> {
+        self.paths
+            .iter()
+            .filter(|ownership| ownership.owners.is_empty())
+            .map(|ownership| ownership.path.as_str())
+            .collect()
+    }
+}
+
+/// 某棵树中的代码所有权，所有权文件在首次用到时读取
+pub struct Owners<'a> {
+    repo: &'a Repository,
+    tree: ObjectId,
+    /// 已读取的目录，值为 None 表示该目录下没有所有权文件
+    files: HashMap<String, Option<OwnersFile>>,
+}
+
+impl<'a> Owners<'a> {
+    pub fn new(repo: &'a Repository, tree: ObjectId) -> Owners<'a> {
+        Owners {
+            repo,
+            tree,
+            files: HashMap::new(),
+        }
+    }
+
+    /// 使用提交中的所有权文件
+    pub fn at_commit(repo: &'a Repository, commit: &ObjectId) -> MonoResult<Owners<'a>> {
+        Ok(Owners::new(repo, repo.read_commit(commit)?.tree))
+    }
+
+    /// 目录下的所有权文件
+    fn file(&mut self, dir: &str) -> MonoResult<Option<&OwnersFile>> {
+        if !self.files.contains_key(dir) {
+            let file = match self.repo.find_path(&self.tree, &join(dir, OWNERS_FILE))? {
+                Some(entry) if entry.mode.is_blob() => {
+                    let data = self.repo.read_object(&entry.id)?.data;
+                    Some(OwnersFile::parse(dir, &String::from_utf8_lossy(&data))?)
+                }
+                _ => None,
+            };
+            self.files.insert(dir.to_string(), file);
+        }
+        Ok(self.files[dir].as_ref())
+    }
+
+    /// 查找路径的负责人，路径相对于仓库根目录，不要求在树中存在（例如已删除的文件）
+    pub fn resolve(&mut self, path: &str) -> MonoResult<Ownership> {
+        let path = path.trim_matches('/');
+        let mut dir = parent(path);
+        loop {
+            if let Some(file) = self.file(dir)? {
+                if let Some(rule) = file.matching_rule(path) {
+                    return Ok(Ownership {
+                        path: path.to_string(),
+                        owners: rule.owners.clone(),
+                        source: Some(RuleSource {
+                            file: file.path(),
+                            line: rule.line,
+                        }),
+                    });
+                }
+            }
+            if dir.is_empty() {
+                break;
+            }
+            dir = parent(dir);
+        }
+        Ok(Ownership {
+            path: path.to_string(),
+            owners: Vec::new(),
+            source: None,
+        })
+    }
+
+    /// 查找一组路径的负责人
+    pub fn resolve_paths<S: AsRef<str>>(&mut self, paths: &[S]) -> MonoResult<OwnersReport> {
+        let paths = paths
+            .iter()
+            .map(|path| self.resolve(path.as_ref()))
+            .collect::<MonoResult<_>>()?;
+        Ok(OwnersReport { paths })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{commit_files, init_repo};
+
+    fn matches(pattern: &str, path: &str) -> bool {
+        pattern.parse::<OwnerPattern>().unwrap().matches(path)
+    }
+
+    /// 测试模式语法：名称匹配、锚定、目录模式与通配符
+    #[test]
+    fn test_pattern() {
+        assert!(matches("*", "a/b/c.rs"));
+        assert!(matches("*.rs", "src/lib.rs"));
+        assert!(!matches("*.rs", "src/lib.rs.orig"));
+        assert!(matches("build", "a/build/out.o"));
+        assert!(matches("build", "a/build"));
+        assert!(matches("build/", "a/build/out.o"));
+        assert!(!matches("build/", "a/build"));
+        assert!(matches("/docs", "docs/guide/intro.md"));
+        assert!(!matches("/docs", "src/docs/intro.md"));
+        assert!(matches("docs/*", "docs/intro.md"));
+        assert!(!matches("docs/*", "docs/guide/intro.md"));
+        assert!(matches("apps/", "apps/web/index.ts"));
+        assert!(matches("**/logs", "deep/nested/logs/today.log"));
+        assert!(matches("**/logs", "logs/today.log"));
+        assert!(matches("src/**/test_?.rs", "src/test_a.rs"));
+        assert!(matches("src/**/test_?.rs", "src/a/b/test_b.rs"));
+        assert!(!matches("src/**/test_?.rs", "src/a/test_bc.rs"));
+        assert!(matches("a*c*e", "abcde"));
+        assert!(!matches("a*c*e", "abcdf"));
+
+        for invalid in ["!keep", "a//b", "../x", "/"] {
+            assert!(invalid.parse::<OwnerPattern>().is_err(), "{}", invalid);
+        }
+    }
+
+    /// 测试文件解析：注释、无人负责的规则与非法负责人
+    #[test]
+    fn test_parse() {
+        let file = OwnersFile::parse(
+            "services",
+            "# 默认负责人\n* @org/platform  # 平台组\n\n*.proto @alice bob@example.com\ngenerated/\n",
+        )
+        .unwrap();
+        assert_eq!(file.path(), "services/CODEOWNERS");
+        assert_eq!(file.rules.len(), 3);
+        assert_eq!(file.rules[0].owners, ["@org/platform"]);
+        assert_eq!(file.rules[1].line, 4);
+        assert!(file.rules[2].owners.is_empty());
+        assert_eq!(file.matching_rule("services/api/v1.proto").unwrap().line, 4);
+        assert_eq!(file.matching_rule("services/api/main.rs").unwrap().line, 2);
+        assert!(file.matching_rule("servicesx/main.rs").is_none());
+
+        let err = OwnersFile::parse("", "* @ok\n*.rs alice\n").unwrap_err();
+        assert!(err.to_string().contains("CODEOWNERS:2"), "{}", err);
+    }
+
+    /// 测试逐级向上查找最近的所有权文件，并按负责人汇总
+    #[test]
+    fn test_resolve() {
+        let (_dir, repo) = init_repo();
+        let commit = commit_files(
+            &repo,
+            &[
+                ("CODEOWNERS", b"* @org/platform\n/docs/ @writers\n"),
+                ("services/payments/CODEOWNERS", b"*.rs @payments\nvendor/\n"),
+                ("services/payments/src/lib.rs", b""),
+                ("services/payments/README.md", b""),
+                ("docs/index.md", b""),
+            ],
+            &[],
+            "init",
+        );
+        let mut owners = Owners::at_commit(&repo, &commit).unwrap();
+
+        let lib = owners.resolve("services/payments/src/lib.rs").unwrap();
+        assert_eq!(lib.owners, ["@payments"]);
+        assert_eq!(
+            lib.source,
+            Some(RuleSource {
+                file: "services/payments/CODEOWNERS".to_string(),
+                line: 1,
+            })
+        );
+        // 最近的文件中没有规则匹配时使用上级目录的文件
+        assert_eq!(owners.resolve("services/payments/README.md").unwrap().owners, ["@org/platform"]);
+        // 不存在的路径同样可以查询，显式声明无人负责的规则优先于上级目录
+        let vendored = owners.resolve("services/payments/vendor/dep.rs").unwrap();
+        assert!(vendored.owners.is_empty());
+        assert_eq!(vendored.source.unwrap().line, 2);
+
+        let report = owners
+            .resolve_paths(&["docs/index.md", "services/payments/src/lib.rs", "services/payments/vendor/x", "Cargo.toml"])
+            .unwrap();
+        let by_owner = report.by_owner();
+        assert_eq!(by_owner["@writers"], ["docs/index.md"]);
+        assert_eq!(by_owner["@payments"], ["services/payments/src/lib.rs"]);
+        assert_eq!(by_owner["@org/platform"], ["Cargo.toml"]);
+        assert_eq!(report.unowned(), ["services/payments/vendor/x"]);
+    }
+}
@@ -0,0 +1,295 @@
+//! git delta 指令的解析与应用
+//!
+//! delta 以基对象长度和结果长度（均为 7 位变长整数）开头，之后是一系列指令：
+//! 最高位为 1 时从基对象复制一段数据，否则插入紧随其后的若干字节。
+//!
+//! 生成 delta 时先按固定长度的块为基对象建立哈希索引，再用滚动哈希扫描目标对象，
+//! 命中的块向前后尽量延伸为一条复制指令，其余字节作为插入指令。
+
+use std::collections::HashMap;
+
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::object::codec::MAX_PREALLOC;
+
+/// 建立索引与匹配的块长度，短于该长度的重复内容不生成复制指令
+const BLOCK: usize = 16;
+
+/// 每个哈希值至多记录的基对象位置，避免大量重复内容（例如全零）使匹配退化
+const MAX_BUCKET: usize = 64;
+
+/// 单条复制指令的最大长度，更长的匹配拆成多条，与所有 pack 版本兼容
+const MAX_COPY: usize = 0x10000;
+
+/// 单条插入指令的最大长度
+const MAX_INSERT: usize = 0x7f;
+
+/// 滚动哈希的乘数
+const HASH_BASE: u32 = 0x0100_0193;
+
+/// 将 delta 应用到基对象上，返回重建的对象内容
+pub fn apply_delta(base: &[u8], delta: &[u8]) -> MonoResult<Vec<u8>> {
+    let corrupt = |msg: &str| MonoError::protocol(format!("corrupt delta: {}", msg));
+    let mut pos = 0;
+    let base_len = read_size(delta, &mut pos).ok_or_else(|| corrupt("truncated header"))?;
+    let result_len = read_size(delta, &mut pos).ok_or_else(|| corrupt("truncated header"))?;
+    if base_len != base.len() as u64 {
+        return Err(corrupt("base size mismatch"));
+    }
+
+    let mut out = Vec::with_capacity(result_len.min(MAX_PREALLOC as u64) as usize);
+    while pos < delta.len() {
+        let op = delta[pos];
+        pos += 1;
+        if op & 0x80 != 0 {
+            // 复制指令：低 4 位标记偏移量的字节，接下来 3 位标记长度的字节
+            let mut offset = 0usize;
+            let mut size = 0usize;
+            for i in 0..7 {
+                if op & (1 << i) == 0 {
+                    continue;
+                }
+                let byte = *delta.get(pos).ok_or_else(|| corrupt("truncated copy"))? as usize;
+                pos += 1;
+                if i < 4 {
+                    offset |= byte << (i * 8);
+                } else {
+                    size |= byte << ((i - 4) * 8);
+                }
+            }
+            if size == 0 {
+                size = 0x10000;
+            }
+            let chunk = offset
+                .checked_add(size)
+                .and_then(|end| base.get(offset..end))
+                .ok_or_else(|| corrupt("copy out of range"))?;
+            out.extend_from_slice(chunk);
+            if out.len() as u64 > result_len {
+                return Err(corrupt("result size mismatch"));
+            }
+        } else if op != 0 {
+            let chunk = delta
+                .get(pos..pos + op as usize)
+                .ok_or_else(|| corrupt("truncated insert"))?;
+            out.extend_from_slice(chunk);
+            pos += op as usize;
+        } else {
+            return Err(corrupt("reserved opcode"));
+        }
+    }
+    if out.len() as u64 != result_len {
+        return Err(corrupt("result size mismatch"));
+    }
+    Ok(out)
+}
+
+/// 从 delta 头部读取结果长度，只需要 delta 开头的若干字节
+pub fn result_size(delta: &[u8]) -> Option<u64> {
+    let mut pos = 0;
+    read_size(delta, &mut pos)?;
+    read_size(delta, &mut pos)
+}
+
+/// 基对象的块索引，同一个基对象可以与多个目标对象比较
+pub struct DeltaIndex {
+    base: Vec<u8>,
+    blocks: HashMap<u32, Vec<u32>>,
+}
+
+impl DeltaIndex {
+    /// 为基对象每个对齐的块建立索引
+    pub fn new(base: Vec<u8>) -> DeltaIndex {
+        let mut blocks: HashMap<u32, Vec<u32>> = HashMap::new();
+        for (i, block) in base.chunks_exact(BLOCK).enumerate() {
+            let offsets = blocks.entry(block_hash(block)).or_default();
+            if offsets.len() < MAX_BUCKET {
+                offsets.push((i * BLOCK) as u32);
+            }
+        }
+        DeltaIndex { base, blocks }
+    }
+
+    /// 基对象内容
+    pub fn base(&self) -> &[u8] {
+        &self.base
+    }
+
+    /// 生成把基对象变为 `target` 的 delta，结果超过 `max_size` 字节时放弃并返回 None
+    pub fn create_delta(&self, target: &[u8], max_size: usize) -> Option<Vec<u8>> {
+        let base = &self.base;
+        let mut out = Vec::with_capacity(max_size.min(target.len()) + 16);
+        write_size(&mut out, base.len() as u64);
+        write_size(&mut out, target.len() as u64);
+
+        // 滚动哈希移出窗口首字节时需要乘以 HASH_BASE^(BLOCK-1)
+        let top = (1..BLOCK).fold(1u32, |acc, _| acc.wrapping_mul(HASH_BASE));
+        let mut pending = 0;
+        let mut pos = 0;
+        let mut hash = target.get(..BLOCK).map(block_hash);
+        while let Some(h) = hash {
+            if let Some((start, len)) = self.longest_match(h, target, pos) {
+                // 向前延伸，吃掉尚未输出的插入内容
+                let mut back = 0;
+                while back < pos - pending && back < start && base[start - back - 1] == target[pos - back - 1] {
+                    back += 1;
+                }
+                write_insert(&mut out, &target[pending..pos - back]);
+                write_copy(&mut out, start - back, len + back);
+                if out.len() > max_size {
+                    return None;
+                }
+                pos += len;
+                pending = pos;
+                hash = target.get(pos..pos + BLOCK).map(block_hash);
+                continue;
+            }
+            hash = target.get(pos + BLOCK).map(|&next| {
+                h.wrapping_sub((target[pos] as u32).wrapping_mul(top))
+                    .wrapping_mul(HASH_BASE)
+                    .wrapping_add(next as u32)
+            });
+            pos += 1;
+        }
+        write_insert(&mut out, &target[pending..]);
+        (out.len() <= max_size).then_some(out)
+    }
+
+    /// 在基对象中查找与 `target[pos..]` 开头一致的最长内容，返回基对象中的起点与长度
+    fn longest_match(&self, hash: u32, target: &[u8], pos: usize) -> Option<(usize, usize)> {
+        let mut best: Option<(usize, usize)> = None;
+        for &start in self.blocks.get(&hash)? {
+            let start = start as usize;
+            let len = self.base[start..]
+                .iter()
+                .zip(&target[pos..])
+                .take_while(|(a, b)| a == b)
+                .count();
+            if len >= BLOCK && best.is_none_or(|(_, best_len)| len > best_len) {
+                best = Some((start, len));
+            }
+        }
+        best
+    }
+}
+
+/// 一个块的哈希，与 [`DeltaIndex::create_delta`] 中的滚动哈希一致
+fn block_hash(block: &[u8]) -> u32 {
+    block
+        .iter()
+        .fold(0u32, |h, &b| h.wrapping_mul(HASH_BASE).wrapping_add(b as u32))
+}
+
+fn write_size(out: &mut Vec<u8>, mut size: u64) {
+    while size >= 0x80 {
+        out.push((size & 0x7f) as u8 | 0x80);
+        size >>= 7;
+    }
+    out.push(size as u8);
+}
+
+fn write_insert(out: &mut Vec<u8>, data: &[u8]) {
+    for chunk in data.chunks(MAX_INSERT) {
+        out.push(chunk.len() as u8);
+        out.extend_from_slice(chunk);
+    }
+}
+
+fn write_copy(out: &mut Vec<u8>, mut offset: usize, mut len: usize) {
+    while len > 0 {
+        let size = len.min(MAX_COPY);
+        let at = out.len();
+        out.push(0x80);
+        for i in 0..4 {
+            let byte = (offset >> (i * 8)) as u8;
+            if byte != 0 {
+                out[at] |= 1 << i;
+                out.push(byte);
+            }
+        }
+        // 长度 0x10000 省略全部长度字节
+        if size != MAX_COPY {
+            for i in 0..3 {
+                let byte = (size >> (i * 8)) as u8;
+                if byte != 0 {
+                    out[at] |= 1 << (4 + i);
+                    out.push(byte);
+                }
+            }
+        }
+        offset += size;
+        len -= size;
+    }
+}
+
+/// 读取 delta 头部的 7 位变长整数
+fn read_size(data: &[u8], pos: &mut usize) -> Option<u64> {
+    let mut size = 0u64;
+    let mut shift = 0;
+    loop {
+        let byte = *data.get(*pos)?;
+        *pos += 1;
+        size |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Some(size);
+        }
+        shift += 7;
+        if shift > 63 {
+            return None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 测试复制与插入指令
+    #[test]
+    fn test_apply_delta() {
+        let base = b"hello world";
+        // 基对象 11 字节，结果 13 字节：复制 "hello "，插入 "there!!"
+        let delta = [11, 13, 0x90, 6, 7, b't', b'h', b'e', b'r', b'e', b'!', b'!'];
+        assert_eq!(apply_delta(base, &delta).unwrap(), b"hello there!!");
+    }
+
+    /// 测试生成的 delta 能还原目标对象，且相似内容的 delta 远小于目标对象
+    #[test]
+    fn test_create_delta() {
+        let base: Vec<u8> = (0..200_000u32).flat_map(|i| format!("line {}\n", i).into_bytes()).collect();
+        let mut target = base.clone();
+        target.splice(1000..1010, b"changed!".iter().copied());
+        target.extend_from_slice(b"appended at the end\n");
+        target.drain(500_000..500_100);
+
+        let index = DeltaIndex::new(base.clone());
+        let delta = index.create_delta(&target, target.len()).unwrap();
+        assert!(delta.len() < 200, "delta is {} bytes", delta.len());
+        assert_eq!(apply_delta(&base, &delta).unwrap(), target);
+        assert_eq!(result_size(&delta), Some(target.len() as u64));
+
+        // 毫无关联或过短的内容也能得到正确的 delta，超过上限时放弃
+        for target in [&b"completely different content"[..], b"", b"line"] {
+            let delta = index.create_delta(target, usize::MAX).unwrap();
+            assert_eq!(apply_delta(&base, &delta).unwrap(), target);
+        }
+        assert!(index.create_delta(b"completely different content", 10).is_none());
+        let empty = DeltaIndex::new(Vec::new());
+        assert_eq!(apply_delta(b"", &empty.create_delta(&target, usize::MAX).unwrap()).unwrap(), target);
+    }
+
+    /// 测试损坏的 delta 返回错误
+    #[test]
+    fn test_corrupt_delta() {
+        let base = b"hello";
+        // 基对象长度不符
+        assert!(apply_delta(base, &[4, 1, 1, b'x']).is_err());
+        // 复制越界
+        assert!(apply_delta(base, &[5, 10, 0x91, 2, 10]).is_err());
+        // 结果长度不符
+        assert!(apply_delta(base, &[5, 2, 1, b'x']).is_err());
+        assert!(apply_delta(base, &[5, 3, 0x90, 5]).is_err());
+        // 声明的结果长度接近 2^63 时不按声明的长度分配
+        assert!(apply_delta(base, &[5, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f, 0x90, 5]).is_err());
+    }
+}
@@ -0,0 +1,216 @@
+//! 生成 pack 时的 delta 压缩
+//!
+//! 与 git 的 pack-objects 相同的思路：对象先按类型、文件名哈希与大小（从大到小）排序，
+//! 让同一路径的不同版本相邻；每个对象与排在它前面、仍在滑动窗口内的同类对象逐一尝试生成 delta，
+//! 取最小的一个，足够小时以 delta 形式写出，否则写出完整对象。
+//! 窗口中的对象总在当前对象之前写出，因此可以边排序后的遍历边写 pack，内存中只保留窗口内的对象。
+
+use std::collections::VecDeque;
+use std::io::Write;
+
+use crate::common::MonoResult;
+use crate::object::walk::ObjectReader;
+use crate::object::{ObjectId, ObjectType, OBJECT_ID_LEN};
+use crate::pack::delta::DeltaIndex;
+use crate::pack::{type_code, PackWriter};
+
+/// 小于该长度的对象直接写出，delta 头部与基对象引用的开销已接近对象本身
+const MIN_DELTA_SIZE: usize = 64;
+
+/// 超过该长度的对象不参与 delta 压缩，避免窗口占用过多内存
+const MAX_DELTA_SIZE: usize = 64 << 20;
+
+/// delta 压缩参数
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeltaOptions {
+    /// 每个对象尝试的候选基对象数，0 表示不做 delta 压缩
+    pub window: usize,
+    /// delta 链的最大长度，链越长读取时需要还原的次数越多
+    pub max_depth: usize,
+    /// 是否使用 OFS_DELTA，对端不支持时改用 REF_DELTA
+    pub ofs_delta: bool,
+}
+
+impl Default for DeltaOptions {
+    fn default() -> DeltaOptions {
+        DeltaOptions {
+            window: 10,
+            max_depth: 50,
+            ofs_delta: true,
+        }
+    }
+}
+
+/// 待写入 pack 的对象
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PackObject {
+    pub id: ObjectId,
+    pub object_type: ObjectType,
+    /// 对象在树中文件名的哈希，见 [`crate::object::walk::name_hash`]
+    pub name_hash: u32,
+    /// 对象大小，仅用于排序，未知时为 0
+    pub size: usize,
+}
+
+/// delta 压缩的统计
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DeltaStats {
+    pub objects: usize,
+    /// 以 delta 形式写出的对象数
+    pub deltas: usize,
+    /// 写出的对象内容总长度（压缩前），delta 对象按 delta 长度计
+    pub written: u64,
+    /// 所有对象完整内容的总长度
+    pub total: u64,
+}
+
+/// 窗口中的候选基对象
+struct Candidate {
+    id: ObjectId,
+    object_type: ObjectType,
+    offset: u64,
+    depth: usize,
+    index: DeltaIndex,
+}
+
+/// 对 `objects` 做 delta 压缩后写入 `pack`，`pack` 头部声明的对象数应为 `objects.len()`
+pub fn write_objects<W: Write, R: ObjectReader>(
+    pack: &mut PackWriter<W>,
+    mut objects: Vec<PackObject>,
+    reader: &mut R,
+    options: &DeltaOptions,
+) -> MonoResult<DeltaStats> {
+    objects.sort_by_key(|o| (type_code(o.object_type), o.name_hash, std::cmp::Reverse(o.size), o.id));
+
+    let mut stats = DeltaStats::default();
+    let mut window: VecDeque<Candidate> = VecDeque::with_capacity(options.window);
+    for object in &objects {
+        let raw = reader.read_object(&object.id)?;
+        let data = raw.data;
+        stats.objects += 1;
+        stats.total += data.len() as u64;
+
+        let deltify = options.window > 0 && (MIN_DELTA_SIZE..=MAX_DELTA_SIZE).contains(&data.len());
+        let mut best: Option<(&Candidate, Vec<u8>)> = None;
+        if deltify {
+            for candidate in &window {
+                if candidate.object_type != raw.object_type || candidate.depth >= options.max_depth {
+                    continue;
+                }
+                // 基对象远小于目标时几乎不可能得到有用的 delta
+                if candidate.index.base().len() < data.len() / 32 {
+                    continue;
+                }
+                // delta 至少要比完整对象小一半；链越深要求越严格，越靠近链首的基对象越优先
+                let limit = (data.len() / 2).saturating_sub(OBJECT_ID_LEN) * (options.max_depth - candidate.depth)
+                    / (options.max_depth + 1);
+                let limit = best.as_ref().map_or(limit, |(_, delta)| limit.min(delta.len().saturating_sub(1)));
+                if let Some(delta) = candidate.index.create_delta(&data, limit) {
+                    best = Some((candidate, delta));
+                }
+            }
+        }
+
+        let (offset, depth) = match best {
+            Some((base, delta)) => {
+                stats.deltas += 1;
+                stats.written += delta.len() as u64;
+                let offset = if options.ofs_delta {
+                    pack.write_ofs_delta(object.id, base.offset, &delta)?
+                } else {
+                    pack.write_ref_delta(object.id, &base.id, &delta)?
+                };
+                (offset, base.depth + 1)
+            }
+            None => {
+                stats.written += data.len() as u64;
+                (pack.write(raw.object_type, &data)?, 0)
+            }
+        };
+
+        if deltify {
+            if window.len() == options.window {
+                window.pop_back();
+            }
+            window.push_front(Candidate {
+                id: object.id,
+                object_type: raw.object_type,
+                offset,
+                depth,
+                index: DeltaIndex::new(data),
+            });
+        }
+    }
+    tracing::debug!(
+        objects = stats.objects,
+        deltas = stats.deltas,
+        written = stats.written,
+        total = stats.total,
+        "deltified pack objects"
+    );
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::common::errors::MonoError;
+    use crate::object::walk::name_hash;
+    use crate::object::RawObject;
+    use crate::pack::index_pack;
+
+    /// 测试相似版本以 delta 写出，pack 能完整还原，且不使用 OFS_DELTA 时同样有效
+    #[test]
+    fn test_write_objects() {
+        let mut versions = Vec::new();
+        let mut content: Vec<u8> = (0..2000u32).flat_map(|i| format!("fn item_{}() {{}}\n", i).into_bytes()).collect();
+        for i in 0..5 {
+            content.extend_from_slice(format!("// revision {}\n", i).as_bytes());
+            versions.push(RawObject::new(ObjectType::Blob, content.clone()));
+        }
+        versions.push(RawObject::new(ObjectType::Blob, b"tiny".to_vec()));
+        versions.push(RawObject::new(ObjectType::Tree, Vec::new()));
+        let store: HashMap<ObjectId, RawObject> = versions.iter().map(|o| (o.id(), o.clone())).collect();
+        let objects: Vec<PackObject> = versions
+            .iter()
+            .map(|o| PackObject {
+                id: o.id(),
+                object_type: o.object_type,
+                name_hash: name_hash("lib.rs"),
+                size: o.data.len(),
+            })
+            .collect();
+
+        for ofs_delta in [true, false] {
+            let options = DeltaOptions {
+                ofs_delta,
+                ..DeltaOptions::default()
+            };
+            let mut reader = |id: &ObjectId| store.get(id).cloned().ok_or_else(|| MonoError::not_found(id.to_string()));
+            let mut pack = PackWriter::new(objects.len() as u32);
+            let stats = write_objects(&mut pack, objects.clone(), &mut reader, &options).unwrap();
+            assert_eq!(stats.objects, 7);
+            assert_eq!(stats.deltas, 4);
+            assert!(stats.written * 4 < stats.total, "{:?}", stats);
+
+            let indexed = index_pack(&pack.finish().unwrap(), |_| Ok(None)).unwrap();
+            assert!(!indexed.thin);
+            let mut unpacked = indexed.objects;
+            unpacked.sort_by_key(|o| o.id());
+            let mut expected = versions.clone();
+            expected.sort_by_key(|o| o.id());
+            assert_eq!(unpacked, expected);
+        }
+
+        // 窗口为 0 时不做 delta 压缩
+        let options = DeltaOptions {
+            window: 0,
+            ..DeltaOptions::default()
+        };
+        let mut reader = |id: &ObjectId| Ok(store[id].clone());
+        let mut pack = PackWriter::new(objects.len() as u32);
+        assert_eq!(write_objects(&mut pack, objects, &mut reader, &options).unwrap().deltas, 0);
+    }
+}
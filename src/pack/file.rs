@@ -0,0 +1,307 @@
+//! 磁盘上的 pack 文件
+//!
+//! `pack-<校验和>.pack` 与同名的 `.idx` 成对保存。读取对象时先通过索引得到偏移，
+//! 再沿 delta 链找到基对象并依次应用 delta，不需要把整个 pack 读入内存。对象的压缩编码在打开时由头部确定。
+
+use std::fmt;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::object::codec::{Codec, MAX_PREALLOC};
+use crate::object::{ObjectFormat, ObjectId, ObjectType, RawObject, MAX_OBJECT_ID_LEN, OBJECT_ID_LEN};
+use crate::pack::delta;
+use crate::pack::index::PackIndex;
+use crate::pack::{header_codec, object_type, read_entry_header, read_offset, OFS_DELTA, REF_DELTA};
+
+/// delta 链的最大长度，防止损坏的 pack 造成死循环
+pub(crate) const MAX_DELTA_DEPTH: usize = 4096;
+
+/// 对象头与基对象信息的最大长度：对象头至多 10 字节，其后至多是 32 字节的基对象 ID
+const MAX_ENTRY_HEADER: usize = 10 + MAX_OBJECT_ID_LEN;
+
+/// 读取 delta 结果长度时需要解压的字节数，足以容纳两个 64 位变长整数
+const DELTA_HEADER_PEEK: usize = 20;
+
+/// pack 条目的基对象
+pub(crate) enum EntryBase {
+    Object(ObjectType),
+    Ofs(u64),
+    Ref(ObjectId),
+}
+
+/// 一个带索引的 pack 文件，支持并发读取
+pub struct PackFile {
+    path: PathBuf,
+    file: Mutex<File>,
+    index: PackIndex,
+    codec: Codec,
+}
+
+impl fmt::Debug for PackFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PackFile")
+            .field("path", &self.path)
+            .field("objects", &self.index.len())
+            .finish()
+    }
+}
+
+impl PackFile {
+    /// 打开 pack 及同名的 `.idx`，并确认两者属于同一个 pack
+    pub fn open(path: impl Into<PathBuf>) -> MonoResult<PackFile> {
+        let path = path.into();
+        let idx_path = path.with_extension("idx");
+        let index = PackIndex::parse(&std::fs::read(&idx_path)?).map_err(|e| e.context(idx_path.display().to_string()))?;
+        let corrupt = |msg: &str| MonoError::storage(format!("{}: corrupt pack: {}", path.display(), msg));
+
+        let mut file = File::open(&path)?;
+        if file.metadata()?.len() < (12 + OBJECT_ID_LEN) as u64 {
+            return Err(corrupt("truncated"));
+        }
+        let mut header = [0u8; 12];
+        file.read_exact(&mut header)?;
+        let codec = header_codec(&header).ok_or_else(|| corrupt("missing pack header"))?;
+        if u32::from_be_bytes(header[8..12].try_into().unwrap()) as usize != index.len() {
+            return Err(corrupt("object count does not match its index"));
+        }
+        let mut trailer = vec![0u8; index.format().id_len()];
+        file.seek(SeekFrom::End(-(trailer.len() as i64)))?;
+        file.read_exact(&mut trailer)?;
+        if trailer != index.pack_checksum().as_bytes() {
+            return Err(corrupt("checksum does not match its index"));
+        }
+        Ok(PackFile {
+            path,
+            file: Mutex::new(file),
+            index,
+            codec,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn index(&self) -> &PackIndex {
+        &self.index
+    }
+
+    /// pack 中对象的压缩编码
+    pub fn codec(&self) -> Codec {
+        self.codec
+    }
+
+    pub fn contains(&self, id: &ObjectId) -> bool {
+        self.index.find(id).is_some()
+    }
+
+    /// 读取对象，不在该 pack 中时返回 None
+    pub fn read(&self, id: &ObjectId) -> MonoResult<Option<RawObject>> {
+        match self.index.find(id) {
+            Some(entry) => self.read_at(entry.offset).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// 读取对象类型与大小，不在该 pack 中时返回 None
+    pub fn read_header(&self, id: &ObjectId) -> MonoResult<Option<(ObjectType, usize)>> {
+        match self.index.find(id) {
+            Some(entry) => self.read_header_at(entry.offset).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// 读取 `offset` 处对象的类型与大小：delta 对象只解压 delta 的头部，并沿链查找基对象的类型
+    pub fn read_header_at(&self, offset: u64) -> MonoResult<(ObjectType, usize)> {
+        let (mut base, size, prefix) = self.read_entry(offset, Some(DELTA_HEADER_PEEK))?;
+        let size = match base {
+            EntryBase::Object(_) => size,
+            _ => delta::result_size(&prefix).ok_or_else(|| self.corrupt(offset, "truncated delta header"))? as usize,
+        };
+        for _ in 0..MAX_DELTA_DEPTH {
+            let next = match base {
+                EntryBase::Object(object_type) => return Ok((object_type, size)),
+                EntryBase::Ofs(offset) => offset,
+                EntryBase::Ref(id) => self.base_offset(&id)?,
+            };
+            base = self.read_entry(next, Some(0))?.0;
+        }
+        Err(self.corrupt(offset, "delta chain too long"))
+    }
+
+    /// 读取 `offset` 处的对象并还原 delta，偏移通常来自索引或多包索引
+    pub fn read_at(&self, offset: u64) -> MonoResult<RawObject> {
+        let mut deltas = Vec::new();
+        let mut next = offset;
+        let mut object = loop {
+            if deltas.len() > MAX_DELTA_DEPTH {
+                return Err(self.corrupt(offset, "delta chain too long"));
+            }
+            let (base, _, data) = self.read_entry(next, None)?;
+            match base {
+                EntryBase::Object(object_type) => break RawObject::new(object_type, data),
+                EntryBase::Ofs(base_offset) => next = base_offset,
+                EntryBase::Ref(id) => next = self.base_offset(&id)?,
+            }
+            deltas.push(data);
+        };
+        while let Some(delta) = deltas.pop() {
+            object = RawObject::new(object.object_type, delta::apply_delta(&object.data, &delta)?);
+        }
+        Ok(object)
+    }
+
+    /// pack 中 REF_DELTA 基对象的偏移；磁盘上的 pack 必须是自包含的
+    fn base_offset(&self, id: &ObjectId) -> MonoResult<u64> {
+        self.index
+            .find(id)
+            .map(|entry| entry.offset)
+            .ok_or_else(|| MonoError::storage(format!("{}: missing delta base {}", self.path.display(), id)))
+    }
+
+    /// 打开还没有索引的 `format` 格式 pack，只能经由 [`PackFile::read_entry`] 按偏移读取条目，
+    /// 供 [`crate::pack::indexer`] 还原 delta
+    pub(crate) fn open_unindexed(path: impl Into<PathBuf>, format: ObjectFormat) -> MonoResult<PackFile> {
+        let path = path.into();
+        let mut file = File::open(&path)?;
+        let mut header = [0u8; 12];
+        file.read_exact(&mut header)?;
+        let codec = header_codec(&header)
+            .ok_or_else(|| MonoError::storage(format!("{}: corrupt pack: missing pack header", path.display())))?;
+        Ok(PackFile {
+            path,
+            file: Mutex::new(file),
+            index: PackIndex::new(Vec::new(), format.zero()),
+            codec,
+        })
+    }
+
+    /// 读取一个条目：基对象信息、声明的长度与解压后的内容
+    ///
+    /// `limit` 为 Some 时至多解压这么多字节，用于只读取头部的场景。
+    pub(crate) fn read_entry(&self, offset: u64, limit: Option<usize>) -> MonoResult<(EntryBase, usize, Vec<u8>)> {
+        let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+        file.seek(SeekFrom::Start(offset))?;
+        let mut head = Vec::with_capacity(MAX_ENTRY_HEADER);
+        (&mut *file).take(MAX_ENTRY_HEADER as u64).read_to_end(&mut head)?;
+
+        let mut pos = 0;
+        let (kind, size) = read_entry_header(&head, &mut pos).ok_or_else(|| self.corrupt(offset, "truncated object header"))?;
+        let base = match kind {
+            OFS_DELTA => {
+                let distance = read_offset(&head, &mut pos).ok_or_else(|| self.corrupt(offset, "truncated delta"))?;
+                let base_offset = offset
+                    .checked_sub(distance as u64)
+                    .filter(|_| distance > 0)
+                    .ok_or_else(|| self.corrupt(offset, "delta base out of range"))?;
+                EntryBase::Ofs(base_offset)
+            }
+            REF_DELTA => {
+                let id_len = self.index.format().id_len();
+                let id = head
+                    .get(pos..pos + id_len)
+                    .ok_or_else(|| self.corrupt(offset, "truncated delta"))?;
+                pos += id_len;
+                EntryBase::Ref(ObjectId::from_bytes(id)?)
+            }
+            kind => EntryBase::Object(object_type(kind).ok_or_else(|| self.corrupt(offset, &format!("unknown object type {}", kind)))?),
+        };
+
+        let want = limit.map_or(size, |limit| limit.min(size));
+        let mut data = Vec::with_capacity(want.min(MAX_PREALLOC));
+        if want > 0 {
+            file.seek(SeekFrom::Start(offset + pos as u64))?;
+            // 完整读取时多读一个字节，以便发现实际内容比声明的更长
+            let take = if limit.is_some() { want } else { size.saturating_add(1) };
+            self.codec
+                .decoder(BufReader::new(&mut *file))?
+                .take(take as u64)
+                .read_to_end(&mut data)
+                .map_err(|e| self.corrupt(offset, &e.to_string()))?;
+            if data.len() != want {
+                return Err(self.corrupt(offset, "size mismatch"));
+            }
+        }
+        Ok((base, size, data))
+    }
+
+    fn corrupt(&self, offset: u64, msg: &str) -> MonoError {
+        MonoError::storage(format!("{}: corrupt pack object at {}: {}", self.path.display(), offset, msg))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::ObjectFormat;
+    use crate::pack::PackWriter;
+
+    /// 测试按 ID 读取完整对象、OFS_DELTA 与 REF_DELTA 链上的对象
+    #[test]
+    fn test_pack_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = RawObject::new(ObjectType::Blob, b"hello world".to_vec());
+        let rust = RawObject::new(ObjectType::Blob, b"hello rust!".to_vec());
+        let there = RawObject::new(ObjectType::Blob, b"hello there".to_vec());
+        let tree = RawObject::new(ObjectType::Tree, Vec::new());
+
+        let mut writer = PackWriter::new(4);
+        let base_offset = writer.write(base.object_type, &base.data).unwrap();
+        writer
+            .write_ofs_delta(rust.id(), base_offset, &[11, 11, 0x90, 6, 5, b'r', b'u', b's', b't', b'!'])
+            .unwrap();
+        writer
+            .write_ref_delta(there.id(), &rust.id(), &[11, 11, 0x90, 6, 5, b't', b'h', b'e', b'r', b'e'])
+            .unwrap();
+        writer.write(tree.object_type, &tree.data).unwrap();
+        let (pack, index) = writer.finish_indexed().unwrap();
+        let path = dir.path().join(format!("pack-{}.pack", index.pack_checksum()));
+        std::fs::write(&path, &pack).unwrap();
+        std::fs::write(path.with_extension("idx"), index.encode()).unwrap();
+
+        let file = PackFile::open(&path).unwrap();
+        for object in [&base, &rust, &there, &tree] {
+            assert_eq!(file.read(&object.id()).unwrap().as_ref(), Some(object));
+            assert_eq!(
+                file.read_header(&object.id()).unwrap(),
+                Some((object.object_type, object.data.len()))
+            );
+        }
+        let missing = ObjectId::hash_object(ObjectType::Blob, b"missing");
+        assert!(!file.contains(&missing));
+        assert_eq!(file.read(&missing).unwrap(), None);
+
+        // 索引与 pack 不匹配时拒绝打开
+        let other = PackWriter::new(0).finish_indexed().unwrap().1;
+        std::fs::write(path.with_extension("idx"), other.encode()).unwrap();
+        assert!(PackFile::open(&path).is_err());
+    }
+
+    /// 测试按头部的扩展标记读取 zstd 压缩的 pack
+    #[test]
+    fn test_zstd_pack_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = RawObject::new(ObjectType::Blob, b"hello world".to_vec());
+        let rust = RawObject::new(ObjectType::Blob, b"hello rust!".to_vec());
+        let mut writer = PackWriter::with_codec(Vec::new(), 2, ObjectFormat::Sha1, Codec::Zstd).unwrap();
+        let base_offset = writer.write(base.object_type, &base.data).unwrap();
+        writer
+            .write_ofs_delta(rust.id(), base_offset, &[11, 11, 0x90, 6, 5, b'r', b'u', b's', b't', b'!'])
+            .unwrap();
+        let (pack, index) = writer.finish_indexed().unwrap();
+        let path = dir.path().join(format!("pack-{}.pack", index.pack_checksum()));
+        std::fs::write(&path, &pack).unwrap();
+        std::fs::write(path.with_extension("idx"), index.encode()).unwrap();
+
+        let file = PackFile::open(&path).unwrap();
+        assert_eq!(file.codec(), Codec::Zstd);
+        for object in [&base, &rust] {
+            assert_eq!(file.read(&object.id()).unwrap().as_ref(), Some(object));
+            assert_eq!(file.read_header(&object.id()).unwrap(), Some((ObjectType::Blob, 11)));
+        }
+    }
+}
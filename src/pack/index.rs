@@ -0,0 +1,256 @@
+//! pack 索引（`.idx` 第 2 版）
+//!
+//! 索引按对象 ID 排序，记录每个对象在 pack 中的偏移和原始数据的 CRC32，
+//! 通过 256 项的 fanout 表先按首字节缩小范围，再二分查找：
+//!
+//! ```text
+//! ff 74 4f 63 | 版本 2 | fanout[256] | ID[n] | CRC32[n] | 偏移[n] | 64 位偏移[m] | pack 校验和 | 索引校验和
+//! ```
+//!
+//! 偏移超过 31 位时，4 字节偏移的最高位置 1，其余位为 64 位偏移表中的下标。
+//! ID 与两个校验和的长度由对象格式决定，与 git 一样由结尾的校验和区分 SHA-1 与 SHA-256。
+
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::object::{ObjectFormat, ObjectId, OBJECT_ID_LEN};
+
+/// 索引文件签名
+pub const IDX_SIGNATURE: &[u8; 4] = b"\xfftOc";
+
+/// 支持的索引版本
+pub const IDX_VERSION: u32 = 2;
+
+const LARGE_OFFSET: u32 = 0x8000_0000;
+
+/// 索引中的一个对象
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IndexEntry {
+    pub id: ObjectId,
+    /// 对象头在 pack 中的偏移
+    pub offset: u64,
+    /// 对象在 pack 中原始数据（对象头与压缩内容）的 CRC32
+    pub crc32: u32,
+}
+
+/// pack 索引
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackIndex {
+    fanout: [u32; 256],
+    entries: Vec<IndexEntry>,
+    pack_checksum: ObjectId,
+}
+
+impl PackIndex {
+    /// 由 pack 中的对象构建索引，`pack_checksum` 为 pack 结尾的校验和
+    pub fn new(mut entries: Vec<IndexEntry>, pack_checksum: ObjectId) -> PackIndex {
+        entries.sort_by_key(|e| e.id);
+        entries.dedup_by(|a, b| a.id == b.id);
+        let fanout = build_fanout(entries.iter().map(|e| &e.id));
+        PackIndex {
+            fanout,
+            entries,
+            pack_checksum,
+        }
+    }
+
+    /// 解析索引文件并校验结尾的校验和
+    pub fn parse(data: &[u8]) -> MonoResult<PackIndex> {
+        let corrupt = |msg: &str| MonoError::storage(format!("corrupt pack index: {}", msg));
+        let header_len = 8 + 256 * 4;
+        if data.len() < header_len + 2 * OBJECT_ID_LEN || &data[..4] != IDX_SIGNATURE {
+            return Err(corrupt("missing header"));
+        }
+        let version = read_u32(data, 4);
+        if version != IDX_VERSION {
+            return Err(corrupt(&format!("unsupported version {}", version)));
+        }
+        let format = crate::pack::detect_format(data).ok_or_else(|| corrupt("checksum mismatch"))?;
+        let id_len = format.id_len();
+        let (body, _) = data.split_at(data.len() - id_len);
+
+        let mut fanout = [0u32; 256];
+        for (i, slot) in fanout.iter_mut().enumerate() {
+            *slot = read_u32(data, 8 + i * 4);
+        }
+        if fanout.windows(2).any(|w| w[0] > w[1]) {
+            return Err(corrupt("fanout table is not monotonic"));
+        }
+        // 对象数来自文件头部，确认文件能容纳这么多条目之后才按它分配
+        let count = fanout[255] as usize;
+        let tables_len = count.checked_mul(id_len + 8).and_then(|len| len.checked_add(header_len + id_len));
+        if tables_len.is_none_or(|len| len > body.len()) {
+            return Err(corrupt("truncated"));
+        }
+        let ids_at = header_len;
+        let crcs_at = ids_at + count * id_len;
+        let offsets_at = crcs_at + count * 4;
+        let large_at = offsets_at + count * 4;
+        let large_count = (body.len() - large_at - id_len) / 8;
+
+        let mut entries = Vec::with_capacity(count);
+        for i in 0..count {
+            let id = ObjectId::from_bytes(&data[ids_at + i * id_len..ids_at + (i + 1) * id_len])?;
+            let small = read_u32(data, offsets_at + i * 4);
+            let offset = if small & LARGE_OFFSET == 0 {
+                small as u64
+            } else {
+                let index = (small & !LARGE_OFFSET) as usize;
+                if index >= large_count {
+                    return Err(corrupt("large offset out of range"));
+                }
+                u64::from_be_bytes(data[large_at + index * 8..large_at + index * 8 + 8].try_into().unwrap())
+            };
+            entries.push(IndexEntry {
+                id,
+                offset,
+                crc32: read_u32(data, crcs_at + i * 4),
+            });
+        }
+        if entries.windows(2).any(|w| w[0].id >= w[1].id) {
+            return Err(corrupt("object ids are not sorted"));
+        }
+        let pack_checksum = ObjectId::from_bytes(&body[body.len() - id_len..])?;
+        Ok(PackIndex {
+            fanout,
+            entries,
+            pack_checksum,
+        })
+    }
+
+    /// 编码为索引文件
+    pub fn encode(&self) -> Vec<u8> {
+        let count = self.entries.len();
+        let id_len = self.format().id_len();
+        let mut out = Vec::with_capacity(8 + 256 * 4 + count * (id_len + 8) + 2 * id_len);
+        out.extend_from_slice(IDX_SIGNATURE);
+        out.extend_from_slice(&IDX_VERSION.to_be_bytes());
+        for n in self.fanout {
+            out.extend_from_slice(&n.to_be_bytes());
+        }
+        for entry in &self.entries {
+            out.extend_from_slice(entry.id.as_bytes());
+        }
+        for entry in &self.entries {
+            out.extend_from_slice(&entry.crc32.to_be_bytes());
+        }
+        let mut large = Vec::new();
+        for entry in &self.entries {
+            let small = if entry.offset < LARGE_OFFSET as u64 {
+                entry.offset as u32
+            } else {
+                large.push(entry.offset);
+                LARGE_OFFSET | (large.len() - 1) as u32
+            };
+            out.extend_from_slice(&small.to_be_bytes());
+        }
+        for offset in large {
+            out.extend_from_slice(&offset.to_be_bytes());
+        }
+        out.extend_from_slice(self.pack_checksum.as_bytes());
+        let checksum = self.format().digest(&out);
+        out.extend_from_slice(checksum.as_bytes());
+        out
+    }
+
+    /// 对象格式，与 pack 校验和的格式相同
+    pub fn format(&self) -> ObjectFormat {
+        self.pack_checksum.format()
+    }
+
+    /// 查找对象
+    pub fn find(&self, id: &ObjectId) -> Option<&IndexEntry> {
+        let range = &self.entries[fanout_range(&self.fanout, id)];
+        range.binary_search_by(|e| e.id.cmp(id)).ok().map(|i| &range[i])
+    }
+
+    /// 按对象 ID 排序的全部条目
+    pub fn entries(&self) -> &[IndexEntry] {
+        &self.entries
+    }
+
+    /// 对应 pack 的校验和，也是 pack 文件名的一部分
+    pub fn pack_checksum(&self) -> &ObjectId {
+        &self.pack_checksum
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// 由排序后的对象 ID 构建 fanout 表：第 i 项为首字节不大于 i 的对象数
+pub(crate) fn build_fanout<'a>(ids: impl Iterator<Item = &'a ObjectId>) -> [u32; 256] {
+    let mut fanout = [0u32; 256];
+    for id in ids {
+        fanout[id.as_bytes()[0] as usize] += 1;
+    }
+    for i in 1..256 {
+        fanout[i] += fanout[i - 1];
+    }
+    fanout
+}
+
+/// 首字节与 `id` 相同的对象在排序列表中的范围
+pub(crate) fn fanout_range(fanout: &[u32; 256], id: &ObjectId) -> std::ops::Range<usize> {
+    let first = id.as_bytes()[0] as usize;
+    let start = if first == 0 { 0 } else { fanout[first - 1] as usize };
+    start..fanout[first] as usize
+}
+
+pub(crate) fn read_u32(data: &[u8], at: usize) -> u32 {
+    u32::from_be_bytes(data[at..at + 4].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::ObjectType;
+
+    fn entry(content: &[u8], offset: u64) -> IndexEntry {
+        IndexEntry {
+            id: ObjectId::hash_object(ObjectType::Blob, content),
+            offset,
+            crc32: offset as u32 ^ 0xdead_beef,
+        }
+    }
+
+    /// 测试编码与解析往返，包括需要 64 位偏移的对象
+    #[test]
+    fn test_index_roundtrip() {
+        let entries = vec![entry(b"a", 12), entry(b"b", 300), entry(b"c", 5 << 31), entry(b"d", u64::MAX >> 1)];
+        let checksum = ObjectId::hash_object(ObjectType::Blob, b"pack");
+        let index = PackIndex::new(entries.clone(), checksum);
+        let parsed = PackIndex::parse(&index.encode()).unwrap();
+        assert_eq!(parsed, index);
+        assert_eq!(parsed.pack_checksum(), &checksum);
+        for expected in &entries {
+            assert_eq!(parsed.find(&expected.id), Some(expected));
+        }
+        assert!(parsed.find(&ObjectId::hash_object(ObjectType::Blob, b"e")).is_none());
+        assert!(parsed.entries().windows(2).all(|w| w[0].id < w[1].id));
+    }
+
+    /// 测试损坏的索引返回错误
+    #[test]
+    fn test_corrupt_index() {
+        let index = PackIndex::new(vec![entry(b"a", 12)], ObjectId::ZERO);
+        let mut data = index.encode();
+        assert!(PackIndex::parse(&data[..data.len() - 1]).is_err());
+        data[8 + 255 * 4 + 3] ^= 1;
+        assert!(PackIndex::parse(&data).is_err());
+        assert!(PackIndex::parse(b"\xfftOc").is_err());
+
+        // 校验和正确但头部声明了 2^32 - 1 个对象
+        let mut data = index.encode();
+        data[8 + 255 * 4..8 + 256 * 4].copy_from_slice(&u32::MAX.to_be_bytes());
+        let body = data.len() - OBJECT_ID_LEN;
+        let checksum = ObjectFormat::Sha1.digest(&data[..body]);
+        data[body..].copy_from_slice(checksum.as_bytes());
+        let err = PackIndex::parse(&data).unwrap_err();
+        assert!(err.to_string().contains("truncated"), "{}", err);
+    }
+}
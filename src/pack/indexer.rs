@@ -0,0 +1,499 @@
+//! 在磁盘上为 pack 建立索引
+//!
+//! [`index_pack`](crate::pack::index_pack) 把整个 pack 与还原后的全部对象放在内存中，推送的 pack 可能有
+//! 数 GB，[`index_pack_file`] 改为直接处理转存到磁盘上的文件，与 `git index-pack` 相同分两遍进行：
+//!
+//! 1. 以固定大小的缓冲区顺序读取一遍，解压每个条目只为找到它的结尾，完整对象的 ID 边解压边计算，
+//!    同时计算每个条目的 CRC32 与整个 pack 的校验和；
+//! 2. 按偏移读取 delta 链还原 delta 对象，得到它们的 ID。
+//!
+//! 内存中只有每个条目的偏移与基对象信息、正在还原的一条 delta 链，以及有上限的基对象缓存。
+//! thin pack 引用的 pack 之外的基对象被追加到文件末尾，并重写头部的对象数与结尾的校验和，
+//! 得到可以直接保存的自包含 pack，与 `git index-pack --fix-thin` 相同。
+
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use flate2::Crc;
+
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::object::{Hasher, ObjectFormat, ObjectId, RawObject};
+use crate::pack::file::{EntryBase, PackFile, MAX_DELTA_DEPTH};
+use crate::pack::index::{IndexEntry, PackIndex};
+use crate::pack::{crc32, encode_entry, header_codec, object_type, read_entry_header, read_offset, type_code, OFS_DELTA, REF_DELTA};
+
+/// 顺序读取 pack 的缓冲区大小
+const SCAN_BUFFER: usize = 64 << 10;
+
+/// 还原 delta 时缓存的基对象总大小上限
+const BASE_CACHE_BYTES: usize = 64 << 20;
+
+/// 对象头与 OFS_DELTA 距离的最大字节数
+const MAX_VARINT: usize = 10;
+
+/// 建立了索引的 pack 文件
+#[derive(Debug, Clone)]
+pub struct IndexedPackFile {
+    pub index: PackIndex,
+    /// 是否追加了 pack 之外的基对象
+    pub thin: bool,
+}
+
+/// 为 `path` 处的 `format` 格式 pack 建立索引，`base` 查找 pack 之外的 REF_DELTA 基对象
+///
+/// 用到了 pack 之外的基对象时把它们追加到文件中，返回的索引对应追加后的文件。
+pub fn index_pack_file<F>(path: &Path, format: ObjectFormat, mut base: F) -> MonoResult<IndexedPackFile>
+where
+    F: FnMut(&ObjectId) -> MonoResult<Option<RawObject>>,
+{
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    let scan = scan(&mut file, len, format)?;
+    let trailer_len = format.id_len();
+    let mut trailer = vec![0u8; trailer_len];
+    file.seek(SeekFrom::End(-(trailer_len as i64)))?;
+    file.read_exact(&mut trailer)?;
+    if trailer != scan.checksum.as_bytes() {
+        return Err(corrupt(format!("checksum mismatch, expected a {} pack", format)));
+    }
+    drop(file);
+
+    let pack = PackFile::open_unindexed(path, format)?;
+    let mut resolver = Resolver {
+        pack: &pack,
+        by_id: HashMap::new(),
+        external: HashMap::new(),
+        cache: HashMap::new(),
+        cache_bytes: 0,
+    };
+    let by_offset: HashMap<u64, usize> = scan.entries.iter().enumerate().map(|(i, entry)| (entry.offset, i)).collect();
+    let mut ids: Vec<Option<ObjectId>> = scan.entries.iter().map(|entry| entry.id).collect();
+    for (entry, id) in scan.entries.iter().zip(&ids) {
+        if let Some(id) = id {
+            resolver.by_id.insert(*id, entry.offset);
+        }
+    }
+    // 反复处理基对象已就绪的 delta，直到全部还原或不再有进展
+    let mut pending = ids.iter().filter(|id| id.is_none()).count();
+    while pending > 0 {
+        let mut progressed = false;
+        for (index, entry) in scan.entries.iter().enumerate() {
+            if ids[index].is_some() {
+                continue;
+            }
+            let ready = match &entry.link {
+                Some(Link::Ofs(offset)) => {
+                    let base_index = *by_offset
+                        .get(offset)
+                        .ok_or_else(|| corrupt(format!("no object at delta base offset {}", offset)))?;
+                    ids[base_index].is_some()
+                }
+                Some(Link::Ref(id)) => resolver.by_id.contains_key(id) || resolver.external(id, &mut base)?,
+                None => unreachable!("base objects are identified while scanning"),
+            };
+            if !ready {
+                continue;
+            }
+            let id = resolver.resolve(entry.offset)?.id_in(format);
+            resolver.by_id.insert(id, entry.offset);
+            ids[index] = Some(id);
+            pending -= 1;
+            progressed = true;
+        }
+        if !progressed {
+            return Err(corrupt(format!("{} deltas with missing base objects", pending)));
+        }
+    }
+
+    let mut entries: Vec<IndexEntry> = scan
+        .entries
+        .iter()
+        .zip(ids)
+        .map(|(entry, id)| IndexEntry {
+            id: id.expect("all entries resolved"),
+            offset: entry.offset,
+            crc32: entry.crc32,
+        })
+        .collect();
+    // 同时在 pack 中的基对象不再追加
+    let bases: Vec<RawObject> = resolver
+        .external
+        .into_iter()
+        .filter(|(id, _)| !resolver.by_id.contains_key(id))
+        .filter_map(|(_, object)| object)
+        .collect();
+    drop(pack);
+    let thin = !bases.is_empty();
+    let checksum = if thin {
+        append_bases(path, format, &bases, &mut entries)?
+    } else {
+        scan.checksum
+    };
+    Ok(IndexedPackFile {
+        index: PackIndex::new(entries, checksum),
+        thin,
+    })
+}
+
+fn corrupt(msg: String) -> MonoError {
+    MonoError::protocol(format!("corrupt pack: {}", msg))
+}
+
+/// delta 条目的基对象
+enum Link {
+    Ofs(u64),
+    Ref(ObjectId),
+}
+
+/// 第一遍读取得到的条目，完整对象已有 ID
+struct ScannedEntry {
+    offset: u64,
+    crc32: u32,
+    id: Option<ObjectId>,
+    link: Option<Link>,
+}
+
+/// 第一遍读取的结果
+struct Scan {
+    entries: Vec<ScannedEntry>,
+    /// 按 pack 内容计算的校验和
+    checksum: ObjectId,
+}
+
+/// 顺序读取一遍长度为 `len` 的 pack，不读取结尾的校验和
+fn scan<R: Read>(input: R, len: u64, format: ObjectFormat) -> MonoResult<Scan> {
+    let body_len = len
+        .checked_sub(format.id_len() as u64)
+        .filter(|body_len| *body_len >= 12)
+        .ok_or_else(|| corrupt("missing pack header".to_string()))?;
+    let mut scanner = Scanner {
+        input: BufReader::with_capacity(SCAN_BUFFER, input.take(body_len)),
+        pos: 0,
+        pack: format.hasher(),
+        crc: Crc::new(),
+    };
+    let mut header = [0u8; 12];
+    scanner.read_exact(&mut header).map_err(|_| corrupt("missing pack header".to_string()))?;
+    let codec = header_codec(&header).ok_or_else(|| corrupt("missing pack header".to_string()))?;
+    let count = u32::from_be_bytes(header[8..12].try_into().unwrap()) as usize;
+
+    // 头部的对象数不可信，每个条目至少占两个字节
+    let mut entries = Vec::with_capacity(count.min(body_len as usize / 2));
+    for _ in 0..count {
+        let offset = scanner.pos;
+        scanner.crc = Crc::new();
+        let truncated = |_| corrupt(format!("truncated object at {}", offset));
+        let (kind, size) = read_varint(&mut scanner, read_entry_header).map_err(truncated)?;
+        let link = match kind {
+            OFS_DELTA => {
+                let distance = read_varint(&mut scanner, read_offset).map_err(truncated)? as u64;
+                let base_offset = offset
+                    .checked_sub(distance)
+                    .filter(|_| distance > 0)
+                    .ok_or_else(|| corrupt(format!("delta base out of range at {}", offset)))?;
+                Some(Link::Ofs(base_offset))
+            }
+            REF_DELTA => {
+                let mut id = vec![0u8; format.id_len()];
+                scanner.read_exact(&mut id).map_err(truncated)?;
+                Some(Link::Ref(ObjectId::from_bytes(&id)?))
+            }
+            _ => None,
+        };
+
+        // 多解压一个字节，以便发现实际内容比声明的更长
+        let mut decoder = codec.decoder(&mut scanner)?.take((size as u64).saturating_add(1));
+        let inflated = |e: io::Error| corrupt(format!("object at {}: {}", offset, e));
+        let (id, actual) = match link {
+            None => {
+                let object_type = object_type(kind).ok_or_else(|| corrupt(format!("unknown object type {} at {}", kind, offset)))?;
+                let mut hasher = HashWriter(format.hasher());
+                hasher.0.update(&object_type.header(size));
+                let actual = io::copy(&mut decoder, &mut hasher).map_err(inflated)?;
+                (Some(hasher.0.finish()), actual)
+            }
+            Some(_) => (None, io::copy(&mut decoder, &mut io::sink()).map_err(inflated)?),
+        };
+        drop(decoder);
+        if actual != size as u64 {
+            return Err(corrupt(format!("object at {}: size mismatch", offset)));
+        }
+        entries.push(ScannedEntry {
+            offset,
+            crc32: scanner.crc.sum(),
+            id,
+            link,
+        });
+    }
+    if scanner.pos != body_len {
+        return Err(corrupt("trailing data after objects".to_string()));
+    }
+    Ok(Scan {
+        entries,
+        checksum: scanner.pack.finish(),
+    })
+}
+
+/// 逐字节读取变长整数，直到 `parse` 能从已读的字节中解析出结果
+fn read_varint<R: Read, T>(input: &mut R, parse: fn(&[u8], &mut usize) -> Option<T>) -> io::Result<T> {
+    let mut bytes = Vec::with_capacity(MAX_VARINT);
+    while bytes.len() < MAX_VARINT {
+        let mut byte = [0u8];
+        input.read_exact(&mut byte)?;
+        bytes.push(byte[0]);
+        if let Some(value) = parse(&bytes, &mut 0) {
+            return Ok(value);
+        }
+    }
+    Err(io::Error::other("varint too long"))
+}
+
+/// 记录读取位置，并为读过的数据计算整个 pack 的校验和与当前条目的 CRC32
+struct Scanner<R> {
+    input: R,
+    pos: u64,
+    pack: Hasher,
+    crc: Crc,
+}
+
+impl<R: BufRead> Read for Scanner<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<R: BufRead> BufRead for Scanner<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.input.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        if amt == 0 {
+            return;
+        }
+        // 缓冲区中还有数据时 fill_buf 不会再次读取
+        if let Ok(buf) = self.input.fill_buf() {
+            let consumed = &buf[..amt.min(buf.len())];
+            self.pack.update(consumed);
+            self.crc.update(consumed);
+        }
+        self.input.consume(amt);
+        self.pos += amt as u64;
+    }
+}
+
+/// 把写入的内容交给哈希
+struct HashWriter(Hasher);
+
+impl Write for HashWriter {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.0.update(data);
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// 按偏移还原对象
+struct Resolver<'a> {
+    pack: &'a PackFile,
+    /// 已还原对象的偏移
+    by_id: HashMap<ObjectId, u64>,
+    /// pack 之外的基对象，每个只查找一次
+    external: HashMap<ObjectId, Option<RawObject>>,
+    cache: HashMap<u64, RawObject>,
+    cache_bytes: usize,
+}
+
+impl Resolver<'_> {
+    /// pack 之外是否有对象 `id`
+    fn external<F>(&mut self, id: &ObjectId, base: &mut F) -> MonoResult<bool>
+    where
+        F: FnMut(&ObjectId) -> MonoResult<Option<RawObject>>,
+    {
+        if !self.external.contains_key(id) {
+            self.external.insert(*id, base(id)?);
+        }
+        Ok(self.external[id].is_some())
+    }
+
+    /// 还原 `offset` 处的对象，delta 链上的基对象必须已经还原或在 pack 之外
+    fn resolve(&mut self, offset: u64) -> MonoResult<RawObject> {
+        let mut deltas = Vec::new();
+        let mut next = offset;
+        let mut object = loop {
+            if let Some(object) = self.cache.get(&next) {
+                break object.clone();
+            }
+            if deltas.len() > MAX_DELTA_DEPTH {
+                return Err(corrupt(format!("delta chain too long at {}", offset)));
+            }
+            let (base, _, data) = self.pack.read_entry(next, None)?;
+            match base {
+                EntryBase::Object(object_type) => break RawObject::new(object_type, data),
+                EntryBase::Ofs(base_offset) => next = base_offset,
+                EntryBase::Ref(id) => match (self.by_id.get(&id), self.external.get(&id)) {
+                    (Some(base_offset), _) => next = *base_offset,
+                    (None, Some(Some(object))) => {
+                        deltas.push(data);
+                        break object.clone();
+                    }
+                    _ => return Err(corrupt(format!("missing delta base {}", id))),
+                },
+            }
+            deltas.push(data);
+        };
+        while let Some(delta) = deltas.pop() {
+            object = RawObject::new(object.object_type, crate::pack::delta::apply_delta(&object.data, &delta)?);
+        }
+        // 后续的 delta 通常以刚还原的对象为基；缓存满时整体清空
+        if object.data.len() <= BASE_CACHE_BYTES / 4 {
+            if self.cache_bytes + object.data.len() > BASE_CACHE_BYTES {
+                self.cache.clear();
+                self.cache_bytes = 0;
+            }
+            self.cache_bytes += object.data.len();
+            self.cache.insert(offset, object.clone());
+        }
+        Ok(object)
+    }
+}
+
+/// 把 pack 之外的基对象追加到文件末尾，更新头部的对象数并重新计算校验和，返回新的校验和
+fn append_bases(path: &Path, format: ObjectFormat, bases: &[RawObject], entries: &mut Vec<IndexEntry>) -> MonoResult<ObjectId> {
+    let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+    let mut header = [0u8; 12];
+    file.read_exact(&mut header)?;
+    let codec = header_codec(&header).ok_or_else(|| corrupt("missing pack header".to_string()))?;
+    let count = u32::from_be_bytes(header[8..12].try_into().unwrap())
+        .checked_add(bases.len() as u32)
+        .ok_or_else(|| corrupt("too many objects".to_string()))?;
+
+    let mut offset = file.metadata()?.len() - format.id_len() as u64;
+    file.set_len(offset)?;
+    file.seek(SeekFrom::Start(offset))?;
+    for object in bases {
+        let entry = encode_entry(codec, type_code(object.object_type), &[], &object.data)?;
+        file.write_all(&entry)?;
+        entries.push(IndexEntry {
+            id: object.id_in(format),
+            offset,
+            crc32: crc32(&entry),
+        });
+        offset += entry.len() as u64;
+    }
+    file.seek(SeekFrom::Start(8))?;
+    file.write_all(&count.to_be_bytes())?;
+
+    file.seek(SeekFrom::Start(0))?;
+    let mut hasher = HashWriter(format.hasher());
+    io::copy(&mut BufReader::with_capacity(SCAN_BUFFER, &mut file).take(offset), &mut hasher)?;
+    let checksum = hasher.0.finish();
+    file.seek(SeekFrom::Start(offset))?;
+    file.write_all(checksum.as_bytes())?;
+    file.sync_data()?;
+    Ok(checksum)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::codec::Codec;
+    use crate::object::ObjectType;
+    use crate::pack::{index_pack, PackWriter};
+
+    /// 记录每次读取请求的大小
+    struct CountingReader<R> {
+        inner: R,
+        total: u64,
+        largest: usize,
+    }
+
+    impl<R: Read> Read for CountingReader<R> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = self.inner.read(buf)?;
+            self.total += n as u64;
+            self.largest = self.largest.max(n);
+            Ok(n)
+        }
+    }
+
+    /// 不可压缩的内容
+    fn noise(len: usize, seed: u64) -> Vec<u8> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+                (state >> 56) as u8
+            })
+            .collect()
+    }
+
+    /// 测试与内存中建立的索引一致，pack 只以有限大小的缓冲区顺序读取一遍
+    #[test]
+    fn test_index_pack_file() {
+        let dir = tempfile::tempdir().unwrap();
+        for codec in [Codec::Zlib, Codec::Zstd] {
+            let large = RawObject::new(ObjectType::Blob, noise(4 << 20, 1));
+            let mut changed = large.data.clone();
+            changed[1000..1010].copy_from_slice(b"0123456789");
+            let changed = RawObject::new(ObjectType::Blob, changed);
+            let small = RawObject::new(ObjectType::Tree, Vec::new());
+            let mut writer = PackWriter::with_codec(Vec::new(), 3, ObjectFormat::Sha1, codec).unwrap();
+            let base = writer.write(large.object_type, &large.data).unwrap();
+            let delta = crate::pack::delta::DeltaIndex::new(large.data.clone()).create_delta(&changed.data, usize::MAX).unwrap();
+            writer.write_ofs_delta(changed.id(), base, &delta).unwrap();
+            writer.write(small.object_type, &small.data).unwrap();
+            let (pack, index) = writer.finish_indexed().unwrap();
+
+            let mut reader = CountingReader { inner: &pack[..], total: 0, largest: 0 };
+            let scanned = scan(&mut reader, pack.len() as u64, ObjectFormat::Sha1).unwrap();
+            assert_eq!(reader.total, (pack.len() - 20) as u64);
+            assert!(reader.largest <= SCAN_BUFFER, "read {} bytes at once", reader.largest);
+            assert_eq!(scanned.entries.len(), 3);
+
+            let path = dir.path().join("incoming.pack");
+            std::fs::write(&path, &pack).unwrap();
+            let indexed = index_pack_file(&path, ObjectFormat::Sha1, |_| Ok(None)).unwrap();
+            assert!(!indexed.thin);
+            assert_eq!(indexed.index, index);
+            assert_eq!(std::fs::read(&path).unwrap(), pack);
+            assert!(index_pack_file(&path, ObjectFormat::Sha256, |_| Ok(None)).is_err());
+
+            let mut truncated = pack.clone();
+            truncated.truncate(pack.len() / 2);
+            std::fs::write(&path, &truncated).unwrap();
+            assert!(index_pack_file(&path, ObjectFormat::Sha1, |_| Ok(None)).is_err());
+        }
+    }
+
+    /// 测试 thin pack 的基对象被追加到文件中，得到自包含的 pack
+    #[test]
+    fn test_fix_thin() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = RawObject::new(ObjectType::Blob, b"hello world".to_vec());
+        let target = RawObject::new(ObjectType::Blob, b"hello rust!".to_vec());
+        let mut writer = PackWriter::new(1);
+        writer.write_ref_delta(target.id(), &base.id(), &[11, 11, 0x90, 6, 5, b'r', b'u', b's', b't', b'!']).unwrap();
+        let path = dir.path().join("incoming.pack");
+        std::fs::write(&path, writer.finish().unwrap()).unwrap();
+
+        assert!(index_pack_file(&path, ObjectFormat::Sha1, |_| Ok(None)).is_err());
+        let indexed = index_pack_file(&path, ObjectFormat::Sha1, |id| Ok((*id == base.id()).then(|| base.clone()))).unwrap();
+        assert!(indexed.thin);
+        assert_eq!(indexed.index.len(), 2);
+        let fixed = index_pack(&std::fs::read(&path).unwrap(), |_| Ok(None)).unwrap();
+        assert!(!fixed.thin);
+        assert_eq!(fixed.objects, vec![target, base]);
+        assert_eq!(fixed.index, indexed.index);
+    }
+}
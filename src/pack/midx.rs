@@ -0,0 +1,343 @@
+//! 多包索引（multi-pack-index）
+//!
+//! 将 pack 目录中多个 `.idx` 合并为一个按对象 ID 排序的索引，查找对象时只需一次二分查找，
+//! 不必逐个扫描每个 pack 的索引。文件格式与 git 的 `objects/pack/multi-pack-index` 相同：
+//!
+//! ```text
+//! "MIDX" | 版本 1 | 哈希版本 1 | 块数 | 0 | pack 数 | 块表 | PNAM | OIDF | OIDL | OOFF | [LOFF] | 校验和
+//! ```
+//!
+//! - `PNAM`：按字典序排列、以 NUL 结尾的 `.idx` 文件名，补齐到 4 字节对齐
+//! - `OIDF` / `OIDL`：fanout 表与排序后的对象 ID
+//! - `OOFF`：每个对象所在 pack 的序号与偏移，偏移最高位为 1 时指向 `LOFF` 中的 64 位偏移
+
+use std::collections::BTreeMap;
+
+use sha1::{Digest, Sha1};
+
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::object::{ObjectId, OBJECT_ID_LEN};
+use crate::pack::index::{build_fanout, fanout_range, read_u32, PackIndex};
+
+/// 多包索引的文件名，位于 pack 目录下
+pub const MIDX_FILE: &str = "multi-pack-index";
+
+const MIDX_SIGNATURE: &[u8; 4] = b"MIDX";
+const MIDX_VERSION: u8 = 1;
+/// 对象 ID 的哈希版本，1 表示 SHA-1
+const HASH_VERSION_SHA1: u8 = 1;
+
+const CHUNK_PACK_NAMES: &[u8; 4] = b"PNAM";
+const CHUNK_OID_FANOUT: &[u8; 4] = b"OIDF";
+const CHUNK_OID_LOOKUP: &[u8; 4] = b"OIDL";
+const CHUNK_OBJECT_OFFSETS: &[u8; 4] = b"OOFF";
+const CHUNK_LARGE_OFFSETS: &[u8; 4] = b"LOFF";
+
+const LARGE_OFFSET: u32 = 0x8000_0000;
+const HEADER_LEN: usize = 12;
+const CHUNK_ENTRY_LEN: usize = 12;
+
+/// 多包索引中的一个对象
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MidxEntry {
+    pub id: ObjectId,
+    /// 所在 pack 在 [`MultiPackIndex::pack_names`] 中的序号
+    pub pack: u32,
+    /// 对象在该 pack 中的偏移
+    pub offset: u64,
+}
+
+/// 多包索引
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MultiPackIndex {
+    pack_names: Vec<String>,
+    fanout: [u32; 256],
+    entries: Vec<MidxEntry>,
+}
+
+impl MultiPackIndex {
+    /// 由各 pack 的 `.idx` 文件名与索引构建；同一对象出现在多个 pack 中时取列表中靠前的 pack
+    pub fn new<'a>(packs: impl IntoIterator<Item = (String, &'a PackIndex)>) -> MultiPackIndex {
+        let packs: Vec<(String, &PackIndex)> = packs.into_iter().collect();
+        // pack 序号按文件名的字典序分配，与调用方给出的优先顺序无关
+        let mut order: Vec<usize> = (0..packs.len()).collect();
+        order.sort_by(|&a, &b| packs[a].0.cmp(&packs[b].0));
+        let mut pack_ids = vec![0u32; packs.len()];
+        for (pack_id, &priority) in order.iter().enumerate() {
+            pack_ids[priority] = pack_id as u32;
+        }
+
+        let mut chosen: BTreeMap<ObjectId, MidxEntry> = BTreeMap::new();
+        for (priority, (_, index)) in packs.iter().enumerate() {
+            for entry in index.entries() {
+                chosen.entry(entry.id).or_insert(MidxEntry {
+                    id: entry.id,
+                    pack: pack_ids[priority],
+                    offset: entry.offset,
+                });
+            }
+        }
+        let entries: Vec<MidxEntry> = chosen.into_values().collect();
+        MultiPackIndex {
+            pack_names: order.iter().map(|&i| packs[i].0.clone()).collect(),
+            fanout: build_fanout(entries.iter().map(|e| &e.id)),
+            entries,
+        }
+    }
+
+    /// 解析多包索引文件并校验结尾的校验和
+    pub fn parse(data: &[u8]) -> MonoResult<MultiPackIndex> {
+        let corrupt = |msg: &str| MonoError::storage(format!("corrupt multi-pack-index: {}", msg));
+        if data.len() < HEADER_LEN + CHUNK_ENTRY_LEN + OBJECT_ID_LEN || &data[..4] != MIDX_SIGNATURE {
+            return Err(corrupt("missing header"));
+        }
+        if data[4] != MIDX_VERSION {
+            return Err(corrupt(&format!("unsupported version {}", data[4])));
+        }
+        if data[5] != HASH_VERSION_SHA1 {
+            return Err(corrupt(&format!("unsupported hash version {}", data[5])));
+        }
+        if data[7] != 0 {
+            return Err(corrupt("incremental multi-pack-index chains are not supported"));
+        }
+        let (body, trailer) = data.split_at(data.len() - OBJECT_ID_LEN);
+        if Sha1::digest(body).as_slice() != trailer {
+            return Err(corrupt("checksum mismatch"));
+        }
+
+        // 块表：每项为 4 字节标识与 8 字节偏移，最后一项的偏移为最后一个块的结尾
+        let chunk_count = data[6] as usize;
+        let pack_count = read_u32(data, 8) as usize;
+        let table_end = HEADER_LEN + (chunk_count + 1) * CHUNK_ENTRY_LEN;
+        if body.len() < table_end {
+            return Err(corrupt("truncated chunk table"));
+        }
+        let offset_at = |i: usize| {
+            let at = HEADER_LEN + i * CHUNK_ENTRY_LEN + 4;
+            u64::from_be_bytes(data[at..at + 8].try_into().unwrap()) as usize
+        };
+        let mut chunks: BTreeMap<[u8; 4], &[u8]> = BTreeMap::new();
+        for i in 0..chunk_count {
+            let at = HEADER_LEN + i * CHUNK_ENTRY_LEN;
+            let id: [u8; 4] = data[at..at + 4].try_into().unwrap();
+            let (start, end) = (offset_at(i), offset_at(i + 1));
+            if start < table_end || start > end || end > body.len() {
+                return Err(corrupt("chunk out of range"));
+            }
+            chunks.insert(id, &body[start..end]);
+        }
+        let chunk = |id: &[u8; 4]| {
+            chunks
+                .get(id)
+                .copied()
+                .ok_or_else(|| corrupt(&format!("missing {} chunk", String::from_utf8_lossy(id))))
+        };
+
+        let pack_names: Vec<String> = chunk(CHUNK_PACK_NAMES)?
+            .split(|b| *b == 0)
+            .filter(|name| !name.is_empty())
+            .map(|name| String::from_utf8_lossy(name).into_owned())
+            .collect();
+        if pack_names.len() != pack_count {
+            return Err(corrupt("pack count does not match pack names"));
+        }
+
+        let fanout_chunk = chunk(CHUNK_OID_FANOUT)?;
+        if fanout_chunk.len() != 256 * 4 {
+            return Err(corrupt("invalid fanout chunk"));
+        }
+        let mut fanout = [0u32; 256];
+        for (i, slot) in fanout.iter_mut().enumerate() {
+            *slot = read_u32(fanout_chunk, i * 4);
+        }
+        if fanout.windows(2).any(|w| w[0] > w[1]) {
+            return Err(corrupt("fanout table is not monotonic"));
+        }
+        // 对象数来自文件头部，确认各个块恰好容纳这么多条目之后才按它分配
+        let count = fanout[255] as usize;
+        let ids = chunk(CHUNK_OID_LOOKUP)?;
+        let offsets = chunk(CHUNK_OBJECT_OFFSETS)?;
+        if count.checked_mul(OBJECT_ID_LEN) != Some(ids.len()) || count.checked_mul(8) != Some(offsets.len()) {
+            return Err(corrupt("object count does not match fanout"));
+        }
+        let large = chunks.get(CHUNK_LARGE_OFFSETS).copied().unwrap_or_default();
+
+        let mut entries = Vec::with_capacity(count);
+        for i in 0..count {
+            let pack = read_u32(offsets, i * 8);
+            if pack as usize >= pack_count {
+                return Err(corrupt("pack id out of range"));
+            }
+            let small = read_u32(offsets, i * 8 + 4);
+            let offset = if small & LARGE_OFFSET == 0 {
+                small as u64
+            } else {
+                let at = (small & !LARGE_OFFSET) as usize * 8;
+                let bytes = large.get(at..at + 8).ok_or_else(|| corrupt("large offset out of range"))?;
+                u64::from_be_bytes(bytes.try_into().unwrap())
+            };
+            entries.push(MidxEntry {
+                id: ObjectId::from_bytes(&ids[i * OBJECT_ID_LEN..(i + 1) * OBJECT_ID_LEN])?,
+                pack,
+                offset,
+            });
+        }
+        if entries.windows(2).any(|w| w[0].id >= w[1].id) {
+            return Err(corrupt("object ids are not sorted"));
+        }
+        Ok(MultiPackIndex {
+            pack_names,
+            fanout,
+            entries,
+        })
+    }
+
+    /// 编码为多包索引文件
+    pub fn encode(&self) -> Vec<u8> {
+        let mut names = Vec::new();
+        for name in &self.pack_names {
+            names.extend_from_slice(name.as_bytes());
+            names.push(0);
+        }
+        names.resize(names.len().next_multiple_of(4), 0);
+
+        let mut fanout = Vec::with_capacity(256 * 4);
+        for n in self.fanout {
+            fanout.extend_from_slice(&n.to_be_bytes());
+        }
+        let mut ids = Vec::with_capacity(self.entries.len() * OBJECT_ID_LEN);
+        let mut offsets = Vec::with_capacity(self.entries.len() * 8);
+        let mut large = Vec::new();
+        for entry in &self.entries {
+            ids.extend_from_slice(entry.id.as_bytes());
+            offsets.extend_from_slice(&entry.pack.to_be_bytes());
+            let small = if entry.offset < LARGE_OFFSET as u64 {
+                entry.offset as u32
+            } else {
+                large.extend_from_slice(&entry.offset.to_be_bytes());
+                LARGE_OFFSET | (large.len() / 8 - 1) as u32
+            };
+            offsets.extend_from_slice(&small.to_be_bytes());
+        }
+
+        let mut chunks: Vec<(&[u8; 4], Vec<u8>)> = vec![
+            (CHUNK_PACK_NAMES, names),
+            (CHUNK_OID_FANOUT, fanout),
+            (CHUNK_OID_LOOKUP, ids),
+            (CHUNK_OBJECT_OFFSETS, offsets),
+        ];
+        if !large.is_empty() {
+            chunks.push((CHUNK_LARGE_OFFSETS, large));
+        }
+
+        let mut out = Vec::new();
+        out.extend_from_slice(MIDX_SIGNATURE);
+        out.push(MIDX_VERSION);
+        out.push(HASH_VERSION_SHA1);
+        out.push(chunks.len() as u8);
+        out.push(0);
+        out.extend_from_slice(&(self.pack_names.len() as u32).to_be_bytes());
+        let mut offset = (HEADER_LEN + (chunks.len() + 1) * CHUNK_ENTRY_LEN) as u64;
+        for (id, content) in &chunks {
+            out.extend_from_slice(*id);
+            out.extend_from_slice(&offset.to_be_bytes());
+            offset += content.len() as u64;
+        }
+        out.extend_from_slice(&[0; 4]);
+        out.extend_from_slice(&offset.to_be_bytes());
+        for (_, content) in &chunks {
+            out.extend_from_slice(content);
+        }
+        let checksum = Sha1::digest(&out);
+        out.extend_from_slice(&checksum);
+        out
+    }
+
+    /// 查找对象所在的 pack 与偏移
+    pub fn find(&self, id: &ObjectId) -> Option<&MidxEntry> {
+        let range = &self.entries[fanout_range(&self.fanout, id)];
+        range.binary_search_by(|e| e.id.cmp(id)).ok().map(|i| &range[i])
+    }
+
+    /// 覆盖的 pack 的 `.idx` 文件名，按字典序排列
+    pub fn pack_names(&self) -> &[String] {
+        &self.pack_names
+    }
+
+    /// 按对象 ID 排序的全部条目
+    pub fn entries(&self) -> &[MidxEntry] {
+        &self.entries
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::ObjectType;
+    use crate::pack::index::IndexEntry;
+
+    fn index(objects: &[(&[u8], u64)]) -> PackIndex {
+        let entries = objects
+            .iter()
+            .map(|(content, offset)| IndexEntry {
+                id: ObjectId::hash_object(ObjectType::Blob, content),
+                offset: *offset,
+                crc32: 0,
+            })
+            .collect();
+        PackIndex::new(entries, ObjectId::ZERO)
+    }
+
+    /// 测试合并多个 pack 的索引、重复对象的取舍与编码往返
+    #[test]
+    fn test_midx_roundtrip() {
+        let newer = index(&[(b"a", 12), (b"shared", 40)]);
+        let older = index(&[(b"b", 12), (b"shared", 99), (b"huge", 6 << 31)]);
+        let midx = MultiPackIndex::new([("pack-b.idx".to_string(), &newer), ("pack-a.idx".to_string(), &older)]);
+        assert_eq!(midx.pack_names(), ["pack-a.idx", "pack-b.idx"]);
+        assert_eq!(midx.len(), 4);
+
+        let parsed = MultiPackIndex::parse(&midx.encode()).unwrap();
+        assert_eq!(parsed, midx);
+        let find = |content: &[u8]| *parsed.find(&ObjectId::hash_object(ObjectType::Blob, content)).unwrap();
+        // 重复对象取列表中靠前的 pack，即字典序第二个
+        assert_eq!((find(b"shared").pack, find(b"shared").offset), (1, 40));
+        assert_eq!((find(b"b").pack, find(b"b").offset), (0, 12));
+        assert_eq!(find(b"huge").offset, 6 << 31);
+        assert!(parsed.find(&ObjectId::hash_object(ObjectType::Blob, b"missing")).is_none());
+    }
+
+    /// 测试损坏的多包索引返回错误
+    #[test]
+    fn test_corrupt_midx() {
+        let midx = MultiPackIndex::new([("pack-a.idx".to_string(), &index(&[(b"a", 12)]))]);
+        let mut data = midx.encode();
+        assert!(MultiPackIndex::parse(&data[..data.len() - 1]).is_err());
+        data[12] ^= 1;
+        assert!(MultiPackIndex::parse(&data).is_err());
+        assert!(MultiPackIndex::parse(b"MIDX").is_err());
+
+        // 校验和正确但扇出表声明了 2^32 - 1 个对象
+        let mut data = midx.encode();
+        let fanout_at = (0..data[6] as usize)
+            .map(|i| HEADER_LEN + i * CHUNK_ENTRY_LEN)
+            .find(|at| &data[*at..*at + 4] == CHUNK_OID_FANOUT)
+            .map(|at| u64::from_be_bytes(data[at + 4..at + 12].try_into().unwrap()) as usize)
+            .unwrap();
+        data[fanout_at + 255 * 4..fanout_at + 256 * 4].copy_from_slice(&u32::MAX.to_be_bytes());
+        let body = data.len() - OBJECT_ID_LEN;
+        let checksum = Sha1::digest(&data[..body]);
+        data[body..].copy_from_slice(&checksum);
+        let err = MultiPackIndex::parse(&data).unwrap_err();
+        assert!(err.to_string().contains("object count"), "{}", err);
+    }
+}
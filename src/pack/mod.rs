@@ -0,0 +1,589 @@
+//! git packfile 的编码与解析
+//!
+//! pack 由 `PACK` 签名、版本号和对象数开头，之后是逐个 zlib 压缩的对象，
+//! 最后以全部内容的哈希结尾，哈希算法与对象格式相同（SHA-1 或 SHA-256）。对象可以是完整对象，也可以是相对于
+//! 同一 pack 中某个偏移（OFS_DELTA）或某个对象 ID（REF_DELTA）的 delta。
+//!
+//! 版本号的高 16 位是 mono 的扩展标记：带 [`PACK_EXT_ZSTD`] 的 pack 中的对象以 zstd 压缩（见
+//! [`codec`](crate::object::codec)）。git 会以不支持的版本拒绝这样的 pack，而不是把内容当作 zlib 解析。
+//!
+//! 保存在磁盘上的 pack 配有按对象 ID 排序的索引（见 [`index`]），由 [`file::PackFile`] 随机读取；
+//! pack 较多时由多包索引（见 [`midx`]）统一查找对象所在的 pack。推送收到的 pack 由 [`indexer`]
+//! 直接在磁盘上建立索引，不读入内存。
+
+pub mod delta;
+pub mod deltify;
+pub mod file;
+pub mod index;
+pub mod indexer;
+pub mod midx;
+
+use std::collections::HashMap;
+use std::io::Write;
+
+use flate2::Crc;
+
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::object::codec::Codec;
+use crate::object::{Hasher, ObjectFormat, ObjectId, ObjectType, RawObject, OBJECT_ID_LEN};
+use crate::pack::index::{IndexEntry, PackIndex};
+
+/// pack 文件签名
+pub const PACK_SIGNATURE: &[u8; 4] = b"PACK";
+
+/// 写入时使用的 pack 版本
+pub const PACK_VERSION: u32 = 2;
+
+/// 版本号中的扩展标记：对象以 zstd 压缩
+pub const PACK_EXT_ZSTD: u32 = 1 << 16;
+
+const OFS_DELTA: u8 = 6;
+const REF_DELTA: u8 = 7;
+
+/// pack 头部中的对象类型编号
+pub(crate) fn type_code(object_type: ObjectType) -> u8 {
+    match object_type {
+        ObjectType::Commit => 1,
+        ObjectType::Tree => 2,
+        ObjectType::Blob => 3,
+        ObjectType::Tag => 4,
+    }
+}
+
+/// pack 头部类型编号对应的对象类型，delta 类型返回 None
+fn object_type(code: u8) -> Option<ObjectType> {
+    match code {
+        1 => Some(ObjectType::Commit),
+        2 => Some(ObjectType::Tree),
+        3 => Some(ObjectType::Blob),
+        4 => Some(ObjectType::Tag),
+        _ => None,
+    }
+}
+
+/// 由 pack 头部的 12 个字节得到对象的压缩编码，签名或版本不受支持时返回 None
+pub fn header_codec(header: &[u8]) -> Option<Codec> {
+    if header.len() < 12 || &header[..4] != PACK_SIGNATURE {
+        return None;
+    }
+    let version = u32::from_be_bytes(header[4..8].try_into().unwrap());
+    match (version & 0xffff, version & !0xffff) {
+        (2 | 3, 0) => Some(Codec::Zlib),
+        (2 | 3, PACK_EXT_ZSTD) => Some(Codec::Zstd),
+        _ => None,
+    }
+}
+
+/// 按顺序写出 pack，边写边计算校验和，输出可以是内存缓冲区，也可以直接是网络连接
+pub struct PackWriter<W: Write = Vec<u8>> {
+    out: W,
+    format: ObjectFormat,
+    codec: Codec,
+    hasher: Hasher,
+    offset: u64,
+    remaining: u32,
+    entries: Vec<IndexEntry>,
+}
+
+impl PackWriter<Vec<u8>> {
+    /// 创建包含 `count` 个对象、写入内存的 pack
+    pub fn new(count: u32) -> PackWriter<Vec<u8>> {
+        PackWriter::with_writer(Vec::new(), count).expect("writing to a Vec cannot fail")
+    }
+}
+
+impl<W: Write> PackWriter<W> {
+    /// 创建包含 `count` 个对象的 SHA-1 格式 pack，立即写出头部
+    pub fn with_writer(out: W, count: u32) -> MonoResult<PackWriter<W>> {
+        PackWriter::with_format(out, count, ObjectFormat::Sha1)
+    }
+
+    /// 创建包含 `count` 个 `format` 格式对象的 pack，立即写出头部
+    pub fn with_format(out: W, count: u32, format: ObjectFormat) -> MonoResult<PackWriter<W>> {
+        PackWriter::with_codec(out, count, format, Codec::Zlib)
+    }
+
+    /// 与 [`PackWriter::with_format`] 相同，但对象以 `codec` 压缩；zstd 记录在头部的版本号中
+    pub fn with_codec(out: W, count: u32, format: ObjectFormat, codec: Codec) -> MonoResult<PackWriter<W>> {
+        let mut writer = PackWriter {
+            out,
+            format,
+            codec,
+            hasher: format.hasher(),
+            offset: 0,
+            remaining: count,
+            entries: Vec::with_capacity(count as usize),
+        };
+        let mut header = Vec::with_capacity(12);
+        header.extend_from_slice(PACK_SIGNATURE);
+        let version = match codec {
+            Codec::Zlib => PACK_VERSION,
+            Codec::Zstd => PACK_VERSION | PACK_EXT_ZSTD,
+        };
+        header.extend_from_slice(&version.to_be_bytes());
+        header.extend_from_slice(&count.to_be_bytes());
+        writer.emit(&header)?;
+        Ok(writer)
+    }
+
+    /// 追加一个完整对象，返回其在 pack 中的偏移
+    pub fn write(&mut self, object_type: ObjectType, data: &[u8]) -> MonoResult<u64> {
+        let id = self.format.hash_object(object_type, data);
+        self.write_entry(id, type_code(object_type), &[], data)
+    }
+
+    /// 追加一个以同一 pack 中 `base_offset` 处对象为基的 delta，`id` 为还原后对象的 ID
+    pub fn write_ofs_delta(&mut self, id: ObjectId, base_offset: u64, delta: &[u8]) -> MonoResult<u64> {
+        let distance = self
+            .offset
+            .checked_sub(base_offset)
+            .filter(|d| *d > 0)
+            .ok_or_else(|| MonoError::protocol(format!("delta base {} is not before {}", base_offset, self.offset)))?;
+        self.write_entry(id, OFS_DELTA, &encode_offset(distance), delta)
+    }
+
+    /// 追加一个以对象 `base` 为基的 delta，`base` 不在 pack 中时得到 thin pack
+    pub fn write_ref_delta(&mut self, id: ObjectId, base: &ObjectId, delta: &[u8]) -> MonoResult<u64> {
+        self.write_entry(id, REF_DELTA, base.as_bytes(), delta)
+    }
+
+    fn write_entry(&mut self, id: ObjectId, kind: u8, prefix: &[u8], data: &[u8]) -> MonoResult<u64> {
+        if self.remaining == 0 {
+            return Err(MonoError::protocol("more objects than declared in pack header"));
+        }
+        self.remaining -= 1;
+
+        let entry = encode_entry(self.codec, kind, prefix, data)?;
+        let offset = self.offset;
+        self.entries.push(IndexEntry {
+            id,
+            offset,
+            crc32: crc32(&entry),
+        });
+        self.emit(&entry)?;
+        Ok(offset)
+    }
+
+    fn emit(&mut self, data: &[u8]) -> MonoResult<()> {
+        self.out.write_all(data)?;
+        self.hasher.update(data);
+        self.offset += data.len() as u64;
+        Ok(())
+    }
+
+    fn finish_checksum(&mut self) -> MonoResult<ObjectId> {
+        if self.remaining != 0 {
+            return Err(MonoError::protocol(format!(
+                "pack is missing {} declared objects",
+                self.remaining
+            )));
+        }
+        let checksum = self.hasher.clone().finish();
+        self.out.write_all(checksum.as_bytes())?;
+        Ok(checksum)
+    }
+
+    /// 已写出的字节数，不含结尾校验和
+    pub fn bytes_written(&self) -> u64 {
+        self.offset
+    }
+
+    /// 写入结尾校验和并返回底层输出
+    pub fn finish(mut self) -> MonoResult<W> {
+        self.finish_checksum()?;
+        Ok(self.out)
+    }
+
+    /// 写入结尾校验和，同时返回该 pack 的索引
+    pub fn finish_indexed(mut self) -> MonoResult<(W, PackIndex)> {
+        let checksum = self.finish_checksum()?;
+        Ok((self.out, PackIndex::new(self.entries, checksum)))
+    }
+}
+
+/// 编码一个 pack 条目：对象头、基对象信息 `prefix` 与压缩后的内容
+pub(crate) fn encode_entry(codec: Codec, kind: u8, prefix: &[u8], data: &[u8]) -> MonoResult<Vec<u8>> {
+    let mut entry = Vec::with_capacity(data.len() / 2 + 32);
+    let mut size = data.len() as u64;
+    let mut byte = (kind << 4) | (size & 0x0f) as u8;
+    size >>= 4;
+    while size != 0 {
+        entry.push(byte | 0x80);
+        byte = (size & 0x7f) as u8;
+        size >>= 7;
+    }
+    entry.push(byte);
+    entry.extend_from_slice(prefix);
+    codec.compress_into(&mut entry, &[data])?;
+    Ok(entry)
+}
+
+/// OFS_DELTA 的基对象距离编码，与 [`read_offset`] 相反；索引文件中的变长整数也使用这种编码
+pub(crate) fn encode_offset(mut distance: u64) -> Vec<u8> {
+    let mut out = vec![(distance & 0x7f) as u8];
+    distance >>= 7;
+    while distance != 0 {
+        distance -= 1;
+        out.push(0x80 | (distance & 0x7f) as u8);
+        distance >>= 7;
+    }
+    out.reverse();
+    out
+}
+
+pub(crate) fn crc32(data: &[u8]) -> u32 {
+    let mut crc = Crc::new();
+    crc.update(data);
+    crc.sum()
+}
+
+/// 将一组对象编码为 pack
+pub fn encode_pack<'a, I>(objects: I) -> MonoResult<Vec<u8>>
+where
+    I: ExactSizeIterator<Item = &'a RawObject>,
+{
+    let mut writer = PackWriter::new(objects.len() as u32);
+    for object in objects {
+        writer.write(object.object_type, &object.data)?;
+    }
+    writer.finish()
+}
+
+/// pack 中一个尚未解析 delta 的条目
+enum Entry {
+    Base(ObjectType, Vec<u8>),
+    OfsDelta(usize, Vec<u8>),
+    RefDelta(ObjectId, Vec<u8>),
+}
+
+/// 解析 pack 并还原其中的全部对象
+///
+/// `base` 用于查找 pack 之外的 REF_DELTA 基对象（thin pack），找不到时返回 `Ok(None)`。
+/// 返回的对象与 pack 中的顺序一致。
+pub fn decode_pack<F>(pack: &[u8], base: F) -> MonoResult<Vec<RawObject>>
+where
+    F: FnMut(&ObjectId) -> MonoResult<Option<RawObject>>,
+{
+    Ok(index_pack(pack, base)?.objects)
+}
+
+/// 解析后的 pack 及其索引
+#[derive(Debug, Clone)]
+pub struct IndexedPack {
+    /// 与 pack 中顺序一致的对象
+    pub objects: Vec<RawObject>,
+    pub index: PackIndex,
+    /// 是否引用了 pack 之外的基对象；thin pack 不能单独保存
+    pub thin: bool,
+}
+
+/// 由结尾的校验和判断以 `data` 结尾的文件使用的对象格式，校验和与两种格式都不符时返回 None
+pub(crate) fn detect_format(data: &[u8]) -> Option<ObjectFormat> {
+    [ObjectFormat::Sha1, ObjectFormat::Sha256].into_iter().find(|format| {
+        data.len() >= format.id_len() && {
+            let (body, trailer) = data.split_at(data.len() - format.id_len());
+            format.digest(body).as_bytes() == trailer
+        }
+    })
+}
+
+/// 解析 pack，还原全部对象并生成索引，`base` 的含义与 [`decode_pack`] 相同
+///
+/// 对象格式由 pack 结尾的校验和确定，索引中的对象 ID 使用同一格式。
+pub fn index_pack<F>(pack: &[u8], mut base: F) -> MonoResult<IndexedPack>
+where
+    F: FnMut(&ObjectId) -> MonoResult<Option<RawObject>>,
+{
+    let corrupt = |msg: String| MonoError::protocol(format!("corrupt pack: {}", msg));
+    if pack.len() < 12 + OBJECT_ID_LEN || &pack[..4] != PACK_SIGNATURE {
+        return Err(corrupt("missing pack header".to_string()));
+    }
+    let version = u32::from_be_bytes(pack[4..8].try_into().unwrap());
+    let codec = header_codec(pack).ok_or_else(|| corrupt(format!("unsupported version {:#x}", version)))?;
+    let count = u32::from_be_bytes(pack[8..12].try_into().unwrap()) as usize;
+    let format = detect_format(pack).ok_or_else(|| corrupt("checksum mismatch".to_string()))?;
+    let (body, trailer) = pack.split_at(pack.len() - format.id_len());
+
+    // 头部的对象数不可信，每个条目至少占两个字节，预分配不超过 pack 能容纳的条目数
+    let capacity = count.min(body.len() / 2);
+    let mut entries = Vec::with_capacity(capacity);
+    let mut positions = Vec::with_capacity(capacity);
+    let mut offsets = HashMap::with_capacity(capacity);
+    let mut pos = 12;
+    for index in 0..count {
+        let start = pos;
+        let (kind, size) = read_entry_header(body, &mut pos).ok_or_else(|| corrupt(format!("truncated object at {}", start)))?;
+        let entry = match kind {
+            OFS_DELTA => {
+                let distance = read_offset(body, &mut pos).ok_or_else(|| corrupt(format!("truncated delta at {}", start)))?;
+                let base_offset = start
+                    .checked_sub(distance)
+                    .ok_or_else(|| corrupt(format!("delta base out of range at {}", start)))?;
+                Entry::OfsDelta(base_offset, inflate(body, &mut pos, size, codec)?)
+            }
+            REF_DELTA => {
+                let id = body
+                    .get(pos..pos + format.id_len())
+                    .ok_or_else(|| corrupt(format!("truncated delta at {}", start)))?;
+                let id = ObjectId::from_bytes(id)?;
+                pos += format.id_len();
+                Entry::RefDelta(id, inflate(body, &mut pos, size, codec)?)
+            }
+            _ => {
+                let object_type = object_type(kind).ok_or_else(|| corrupt(format!("unknown object type {} at {}", kind, start)))?;
+                Entry::Base(object_type, inflate(body, &mut pos, size, codec)?)
+            }
+        };
+        offsets.insert(start, index);
+        positions.push((start as u64, crc32(&body[start..pos])));
+        entries.push(entry);
+    }
+    if pos != body.len() {
+        return Err(corrupt("trailing data after objects".to_string()));
+    }
+
+    // 反复处理基对象已就绪的 delta，直到全部还原或不再有进展
+    let mut resolved: Vec<Option<RawObject>> = vec![None; count];
+    let mut by_id = HashMap::new();
+    let mut ids = vec![format.zero(); count];
+    let mut pending = 0;
+    for (index, entry) in entries.iter_mut().enumerate() {
+        if let Entry::Base(object_type, data) = entry {
+            let object = RawObject::new(*object_type, std::mem::take(data));
+            ids[index] = object.id_in(format);
+            by_id.insert(ids[index], index);
+            resolved[index] = Some(object);
+        } else {
+            pending += 1;
+        }
+    }
+    // pack 之外的基对象只查找一次
+    let mut external: HashMap<ObjectId, Option<RawObject>> = HashMap::new();
+    let mut thin = false;
+    while pending > 0 {
+        let mut progressed = false;
+        for index in 0..count {
+            if resolved[index].is_some() {
+                continue;
+            }
+            let (base_object, delta) = match &entries[index] {
+                Entry::OfsDelta(offset, delta) => {
+                    let base_index = *offsets
+                        .get(offset)
+                        .ok_or_else(|| corrupt(format!("no object at delta base offset {}", offset)))?;
+                    (resolved[base_index].as_ref(), delta)
+                }
+                Entry::RefDelta(id, delta) => match by_id.get(id) {
+                    Some(&base_index) => (resolved[base_index].as_ref(), delta),
+                    None => {
+                        if !external.contains_key(id) {
+                            external.insert(*id, base(id)?);
+                        }
+                        thin |= external[id].is_some();
+                        (external[id].as_ref(), delta)
+                    }
+                },
+                Entry::Base(..) => unreachable!("base objects are resolved up front"),
+            };
+            let Some(base_object) = base_object else {
+                continue;
+            };
+            let object = RawObject::new(base_object.object_type, delta::apply_delta(&base_object.data, delta)?);
+            ids[index] = object.id_in(format);
+            by_id.insert(ids[index], index);
+            resolved[index] = Some(object);
+            pending -= 1;
+            progressed = true;
+        }
+        if !progressed {
+            return Err(corrupt(format!("{} deltas with missing base objects", pending)));
+        }
+    }
+    let objects: Vec<RawObject> = resolved.into_iter().map(|object| object.expect("all entries resolved")).collect();
+    let index_entries = ids
+        .into_iter()
+        .zip(positions)
+        .map(|(id, (offset, crc32))| IndexEntry {
+            id,
+            offset,
+            crc32,
+        })
+        .collect();
+    Ok(IndexedPack {
+        objects,
+        index: PackIndex::new(index_entries, ObjectId::from_bytes(trailer)?),
+        thin,
+    })
+}
+
+/// 读取对象头：类型与解压后的长度
+fn read_entry_header(data: &[u8], pos: &mut usize) -> Option<(u8, usize)> {
+    let mut byte = *data.get(*pos)?;
+    *pos += 1;
+    let kind = (byte >> 4) & 0x07;
+    let mut size = (byte & 0x0f) as usize;
+    let mut shift = 4;
+    while byte & 0x80 != 0 {
+        byte = *data.get(*pos)?;
+        *pos += 1;
+        if shift > 57 {
+            return None;
+        }
+        size |= ((byte & 0x7f) as usize) << shift;
+        shift += 7;
+    }
+    Some((kind, size))
+}
+
+/// 读取 OFS_DELTA 的基对象距离，编码方式与对象头不同：每个后续字节前先加 1
+pub(crate) fn read_offset(data: &[u8], pos: &mut usize) -> Option<usize> {
+    let mut byte = *data.get(*pos)?;
+    *pos += 1;
+    let mut offset = (byte & 0x7f) as usize;
+    while byte & 0x80 != 0 {
+        byte = *data.get(*pos)?;
+        *pos += 1;
+        offset = offset.checked_add(1)?.checked_mul(128)? | (byte & 0x7f) as usize;
+    }
+    Some(offset)
+}
+
+/// 解压从 `pos` 开始的压缩流，并前移到流结束的位置
+fn inflate(data: &[u8], pos: &mut usize, size: usize, codec: Codec) -> MonoResult<Vec<u8>> {
+    let (out, len) = codec
+        .decompress_exact(&data[*pos..], size)
+        .map_err(|e| MonoError::protocol(format!("corrupt pack object at {}: {}", pos, e)))?;
+    *pos += len;
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+
+    use super::*;
+
+    fn objects() -> Vec<RawObject> {
+        vec![
+            RawObject::new(ObjectType::Blob, b"hello world".to_vec()),
+            RawObject::new(ObjectType::Blob, vec![b'x'; 1000]),
+            RawObject::new(ObjectType::Tree, Vec::new()),
+        ]
+    }
+
+    /// 测试编码后可以解码出相同的对象
+    #[test]
+    fn test_roundtrip() {
+        let objects = objects();
+        let pack = encode_pack(objects.iter()).unwrap();
+        assert_eq!(&pack[..4], PACK_SIGNATURE);
+        let decoded = decode_pack(&pack, |_| Ok(None)).unwrap();
+        assert_eq!(decoded, objects);
+
+        // SHA-256 格式的 pack 以 32 字节的校验和结尾，索引中的 ID 同样是 SHA-256
+        let mut writer = PackWriter::with_format(Vec::new(), objects.len() as u32, ObjectFormat::Sha256).unwrap();
+        for object in &objects {
+            writer.write(object.object_type, &object.data).unwrap();
+        }
+        let (pack, index) = writer.finish_indexed().unwrap();
+        assert_eq!(detect_format(&pack), Some(ObjectFormat::Sha256));
+        let indexed = index_pack(&pack, |_| Ok(None)).unwrap();
+        assert_eq!(indexed.objects, objects);
+        assert_eq!(indexed.index, index);
+        assert!(indexed.index.find(&objects[0].id_in(ObjectFormat::Sha256)).is_some());
+    }
+
+    /// 测试 zstd 压缩的 pack：头部带扩展标记，解析时按头部选择编码
+    #[test]
+    fn test_zstd() {
+        let objects = objects();
+        let mut writer = PackWriter::with_codec(Vec::new(), objects.len() as u32, ObjectFormat::Sha1, Codec::Zstd).unwrap();
+        let base = writer.write(objects[1].object_type, &objects[1].data).unwrap();
+        let copy = RawObject::new(ObjectType::Blob, vec![b'x'; 999]);
+        writer.write_ofs_delta(copy.id(), base, &[0xe8, 0x07, 0xe7, 0x07, 0xb0, 0xe7, 0x03]).unwrap();
+        writer.write(objects[2].object_type, &objects[2].data).unwrap();
+        let (pack, index) = writer.finish_indexed().unwrap();
+        assert_eq!(header_codec(&pack), Some(Codec::Zstd));
+        assert_eq!(&pack[4..8], &(PACK_VERSION | PACK_EXT_ZSTD).to_be_bytes());
+        let indexed = index_pack(&pack, |_| Ok(None)).unwrap();
+        assert_eq!(indexed.objects, vec![objects[1].clone(), copy, objects[2].clone()]);
+        assert_eq!(indexed.index, index);
+
+        // 未知的扩展标记被拒绝
+        let mut unknown = pack.clone();
+        unknown[5] = 0x02;
+        assert!(header_codec(&unknown).is_none());
+        assert_eq!(header_codec(&encode_pack(objects.iter()).unwrap()), Some(Codec::Zlib));
+    }
+
+    /// 追加一个 delta 条目并重新计算校验和
+    fn append_delta(pack: &[u8], header: &[u8], delta: &[u8]) -> Vec<u8> {
+        let mut body = pack[..pack.len() - OBJECT_ID_LEN].to_vec();
+        let count = u32::from_be_bytes(body[8..12].try_into().unwrap()) + 1;
+        body[8..12].copy_from_slice(&count.to_be_bytes());
+        body.extend_from_slice(header);
+        let mut encoder = ZlibEncoder::new(&mut body, Compression::default());
+        encoder.write_all(delta).unwrap();
+        encoder.finish().unwrap();
+        let checksum = ObjectFormat::Sha1.digest(&body);
+        body.extend_from_slice(checksum.as_bytes());
+        body
+    }
+
+    /// 测试 OFS_DELTA 与 REF_DELTA（包括 pack 之外的基对象）
+    #[test]
+    fn test_deltas() {
+        let base = RawObject::new(ObjectType::Blob, b"hello world".to_vec());
+        let pack = encode_pack([base.clone()].iter()).unwrap();
+        // 复制 "hello " 并插入 "rust!"
+        let delta = [11, 11, 0x90, 6, 5, b'r', b'u', b's', b't', b'!'];
+        let expected = RawObject::new(ObjectType::Blob, b"hello rust!".to_vec());
+
+        // OFS_DELTA 头：类型 6、长度 10，基对象位于 12 字节处
+        let ofs_start = pack.len() - OBJECT_ID_LEN;
+        let distance = (ofs_start - 12) as u8;
+        let with_ofs = append_delta(&pack, &[(OFS_DELTA << 4) | 10, distance], &delta);
+        let decoded = decode_pack(&with_ofs, |_| Ok(None)).unwrap();
+        assert_eq!(decoded[1], expected);
+
+        // 只包含 REF_DELTA 的 thin pack，基对象由调用方提供
+        let empty = PackWriter::new(0).finish().unwrap();
+        let mut header = vec![(REF_DELTA << 4) | 10];
+        header.extend_from_slice(base.id().as_bytes());
+        let thin = append_delta(&empty, &header, &delta);
+        assert!(decode_pack(&thin, |_| Ok(None)).is_err());
+        let decoded = decode_pack(&thin, |id| Ok((*id == base.id()).then(|| base.clone()))).unwrap();
+        assert_eq!(decoded, vec![expected]);
+    }
+
+    /// 测试校验和错误与截断
+    #[test]
+    fn test_corrupt_pack() {
+        let mut pack = encode_pack(objects().iter()).unwrap();
+        assert!(decode_pack(&pack[..pack.len() - 1], |_| Ok(None)).is_err());
+        let last = pack.len() - 1;
+        pack[last] ^= 0xff;
+        assert!(decode_pack(&pack, |_| Ok(None)).is_err());
+        assert!(decode_pack(b"PACK", |_| Ok(None)).is_err());
+    }
+
+    /// 测试头部声明的对象数与对象长度过大时返回错误，不按声明的大小分配
+    #[test]
+    fn test_oversized_entry() {
+        let empty = PackWriter::new(0).finish().unwrap();
+        // 声明约 2^60 字节的 blob，实际内容只有两个字节
+        let header = [0xbf, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f];
+        let pack = append_delta(&empty, &header, b"hi");
+        let err = index_pack(&pack, |_| Ok(None)).unwrap_err();
+        assert!(err.to_string().contains("size mismatch"), "{}", err);
+
+        // 声明 2^32 - 1 个对象的空 pack
+        let mut body = empty[..12].to_vec();
+        body[8..12].copy_from_slice(&u32::MAX.to_be_bytes());
+        let checksum = ObjectFormat::Sha1.digest(&body);
+        body.extend_from_slice(checksum.as_bytes());
+        assert!(index_pack(&body, |_| Ok(None)).is_err());
+    }
+}
@@ -0,0 +1,296 @@
+//! git pkt-line 编码
+//!
+//! 每个数据包以 4 位十六进制长度（包含长度本身）开头，`0000`、`0001`、`0002`
+//! 分别为 flush、delim 与 response-end 特殊包。
+
+use std::io::{self, Read, Write};
+
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+
+/// 单个数据包的最大长度
+pub const MAX_PKT_LEN: usize = 65520;
+
+/// 单个数据包可携带的最大数据长度
+pub const MAX_DATA_LEN: usize = MAX_PKT_LEN - 4;
+
+/// 边带通道：数据、进度与错误
+pub const BAND_DATA: u8 = 1;
+pub const BAND_PROGRESS: u8 = 2;
+pub const BAND_ERROR: u8 = 3;
+
+/// 解析出的数据包
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Packet<'a> {
+    Flush,
+    Delim,
+    ResponseEnd,
+    Data(&'a [u8]),
+}
+
+impl<'a> Packet<'a> {
+    /// 去掉结尾换行的文本内容，特殊包返回 None
+    pub fn text(&self) -> Option<&'a str> {
+        match self {
+            Packet::Data(data) => std::str::from_utf8(data).ok().map(|s| s.strip_suffix('\n').unwrap_or(s)),
+            _ => None,
+        }
+    }
+}
+
+/// 从内存缓冲区依次读取数据包
+#[derive(Debug, Clone)]
+pub struct PktReader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> PktReader<'a> {
+    pub fn new(buf: &'a [u8]) -> PktReader<'a> {
+        PktReader { buf, pos: 0 }
+    }
+
+    /// 读取下一个数据包，缓冲区耗尽时返回 None
+    pub fn read(&mut self) -> MonoResult<Option<Packet<'a>>> {
+        if self.pos == self.buf.len() {
+            return Ok(None);
+        }
+        let header = self
+            .buf
+            .get(self.pos..self.pos + 4)
+            .ok_or_else(|| MonoError::protocol("truncated pkt-line header"))?;
+        let len = std::str::from_utf8(header)
+            .ok()
+            .and_then(|s| usize::from_str_radix(s, 16).ok())
+            .ok_or_else(|| MonoError::protocol(format!("invalid pkt-line length {:?}", String::from_utf8_lossy(header))))?;
+        let packet = match len {
+            0 => Packet::Flush,
+            1 => Packet::Delim,
+            2 => Packet::ResponseEnd,
+            3 => return Err(MonoError::protocol("invalid pkt-line length 0003")),
+            _ => {
+                let data = self
+                    .buf
+                    .get(self.pos + 4..self.pos + len)
+                    .ok_or_else(|| MonoError::protocol("truncated pkt-line"))?;
+                self.pos += len;
+                return Ok(Some(Packet::Data(data)));
+            }
+        };
+        self.pos += 4;
+        Ok(Some(packet))
+    }
+
+    /// 读取下一个数据包，缓冲区耗尽视为协议错误
+    pub fn next_packet(&mut self) -> MonoResult<Packet<'a>> {
+        self.read()?
+            .ok_or_else(|| MonoError::protocol("unexpected end of pkt-line stream"))
+    }
+
+    /// 尚未读取的原始数据，例如 receive-pack 命令之后的 pack 数据
+    pub fn remaining(&self) -> &'a [u8] {
+        &self.buf[self.pos..]
+    }
+}
+
+/// 从流中读取数据包直到 flush，返回包括 flush 在内的原始数据，流中随后的数据不被读取
+///
+/// 用于先读出 receive-pack 的命令部分，再把其后的 pack 数据直接转存到磁盘。
+pub fn read_until_flush(input: &mut dyn Read) -> MonoResult<Vec<u8>> {
+    let mut out = Vec::new();
+    loop {
+        let mut header = [0u8; 4];
+        input
+            .read_exact(&mut header)
+            .map_err(|_| MonoError::protocol("unexpected end of pkt-line stream"))?;
+        out.extend_from_slice(&header);
+        let len = std::str::from_utf8(&header)
+            .ok()
+            .and_then(|s| usize::from_str_radix(s, 16).ok())
+            .ok_or_else(|| MonoError::protocol(format!("invalid pkt-line length {:?}", String::from_utf8_lossy(&header))))?;
+        match len {
+            0 => return Ok(out),
+            1 | 2 => continue,
+            3 => return Err(MonoError::protocol("invalid pkt-line length 0003")),
+            _ => {
+                let start = out.len();
+                out.resize(start + len - 4, 0);
+                input
+                    .read_exact(&mut out[start..])
+                    .map_err(|_| MonoError::protocol("truncated pkt-line"))?;
+            }
+        }
+    }
+}
+
+/// 将数据包写入内存缓冲区
+#[derive(Debug, Clone, Default)]
+pub struct PktWriter {
+    buf: Vec<u8>,
+}
+
+impl PktWriter {
+    pub fn new() -> PktWriter {
+        PktWriter::default()
+    }
+
+    /// 写入一个数据包，数据超过单包上限时返回错误
+    pub fn write(&mut self, data: &[u8]) -> MonoResult<()> {
+        if data.len() > MAX_DATA_LEN {
+            return Err(MonoError::protocol(format!("pkt-line too long: {} bytes", data.len())));
+        }
+        self.buf.extend_from_slice(format!("{:04x}", data.len() + 4).as_bytes());
+        self.buf.extend_from_slice(data);
+        Ok(())
+    }
+
+    /// 写入一行文本，自动补上结尾换行
+    pub fn write_line(&mut self, line: &str) -> MonoResult<()> {
+        if line.ends_with('\n') {
+            self.write(line.as_bytes())
+        } else {
+            self.write(format!("{}\n", line).as_bytes())
+        }
+    }
+
+    /// 通过边带通道写入任意长度的数据，按单包上限自动分片
+    pub fn write_sideband(&mut self, band: u8, data: &[u8]) -> MonoResult<()> {
+        let mut packet = Vec::with_capacity(MAX_DATA_LEN);
+        for chunk in data.chunks(MAX_DATA_LEN - 1) {
+            packet.clear();
+            packet.push(band);
+            packet.extend_from_slice(chunk);
+            self.write(&packet)?;
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) {
+        self.buf.extend_from_slice(b"0000");
+    }
+
+    pub fn delim(&mut self) {
+        self.buf.extend_from_slice(b"0001");
+    }
+
+    pub fn into_inner(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+/// 将写入的数据按单包上限切分为边带数据包，直接写入底层输出，用于边生成边发送 pack
+pub struct SidebandWriter<W: Write> {
+    out: W,
+    /// 以通道编号开头的待发送数据包
+    packet: Vec<u8>,
+}
+
+impl<W: Write> SidebandWriter<W> {
+    pub fn new(out: W, band: u8) -> SidebandWriter<W> {
+        let mut packet = Vec::with_capacity(MAX_DATA_LEN);
+        packet.push(band);
+        SidebandWriter { out, packet }
+    }
+
+    fn emit(&mut self) -> io::Result<()> {
+        if self.packet.len() > 1 {
+            self.out.write_all(format!("{:04x}", self.packet.len() + 4).as_bytes())?;
+            self.out.write_all(&self.packet)?;
+            self.packet.truncate(1);
+        }
+        Ok(())
+    }
+
+    /// 发送剩余数据并返回底层输出
+    pub fn finish(mut self) -> io::Result<W> {
+        self.emit()?;
+        Ok(self.out)
+    }
+}
+
+impl<W: Write> Write for SidebandWriter<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let n = data.len().min(MAX_DATA_LEN - self.packet.len());
+        self.packet.extend_from_slice(&data[..n]);
+        if self.packet.len() == MAX_DATA_LEN {
+            self.emit()?;
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.emit()?;
+        self.out.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 测试写入后可以按相同顺序读回，以及从流中读取到 flush
+    #[test]
+    fn test_roundtrip() {
+        let mut writer = PktWriter::new();
+        writer.write_line("command=ls-refs").unwrap();
+        writer.delim();
+        writer.write(b"peel").unwrap();
+        writer.flush();
+        let buf = writer.into_inner();
+        assert_eq!(buf, b"0014command=ls-refs\n00010008peel0000");
+
+        let mut reader = PktReader::new(&buf);
+        assert_eq!(reader.next_packet().unwrap().text(), Some("command=ls-refs"));
+        assert_eq!(reader.next_packet().unwrap(), Packet::Delim);
+        assert_eq!(reader.next_packet().unwrap(), Packet::Data(b"peel"));
+        assert_eq!(reader.next_packet().unwrap(), Packet::Flush);
+        assert!(reader.read().unwrap().is_none());
+
+        // 从流中读到 flush 为止，之后的数据留在流中
+        let data = [&buf[..], b"PACK"].concat();
+        let mut stream = &data[..];
+        assert_eq!(read_until_flush(&mut stream).unwrap(), buf);
+        assert_eq!(stream, b"PACK");
+        assert!(read_until_flush(&mut &buf[..4]).is_err());
+    }
+
+    /// 测试边带数据按单包上限分片
+    #[test]
+    fn test_sideband_chunks() {
+        let data = vec![7u8; MAX_DATA_LEN * 2];
+        let mut writer = PktWriter::new();
+        writer.write_sideband(BAND_DATA, &data).unwrap();
+        let buf = writer.into_inner();
+
+        let mut reader = PktReader::new(&buf);
+        let mut received = Vec::new();
+        while let Some(Packet::Data(packet)) = reader.read().unwrap() {
+            assert_eq!(packet[0], BAND_DATA);
+            received.extend_from_slice(&packet[1..]);
+        }
+        assert_eq!(received, data);
+    }
+
+    /// 测试流式边带输出与一次性写入的结果相同
+    #[test]
+    fn test_sideband_writer() {
+        let data: Vec<u8> = (0..MAX_DATA_LEN * 2 + 10).map(|i| i as u8).collect();
+        let mut expected = PktWriter::new();
+        expected.write_sideband(BAND_DATA, &data).unwrap();
+
+        let mut writer = SidebandWriter::new(Vec::new(), BAND_DATA);
+        for chunk in data.chunks(1000) {
+            writer.write_all(chunk).unwrap();
+        }
+        assert_eq!(writer.finish().unwrap(), expected.into_inner());
+    }
+
+    /// 测试截断和非法长度返回协议错误
+    #[test]
+    fn test_malformed() {
+        assert!(PktReader::new(b"00").read().is_err());
+        assert!(PktReader::new(b"zzzz").read().is_err());
+        assert!(PktReader::new(b"0010abc").read().is_err());
+        assert!(PktReader::new(b"0003").read().is_err());
+    }
+}
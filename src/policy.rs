@@ -0,0 +1,378 @@
+//! 推送策略
+//!
+//! `mono.toml` 中的 `[[policy]]` 规则按目录保护分支，例如要求修改 `//infra/...` 的推送
+//! 带有两个批准，或禁止直接推送修改 `//payments/...` 的提交：
+//!
+//! ```toml
+//! [[policy]]
+//! name = "payments-frozen"
+//! refs = ["refs/heads/main", "refs/heads/release/"]
+//! paths = ["//payments/..."]
+//! deny_direct_push = true
+//! ```
+//!
+//! receive-pack 在更新引用前比较新旧提交的树，改动的路径落在规则范围内时检查其条件；
+//! 批准记录为推送的提交中的 `Approved-by:` 尾注，`require_checks` 要求 CI 已在推送的提交上
+//! 上报这些检查成功（见 [`crate::checks`]）。规则保存在服务端配置而不是仓库中，
+//! 推送者无法通过修改仓库内容绕过。规则可以设置 `bypass_option`，紧急情况下由 admin 以
+//! `git push -o <选项>` 跳过，跳过会记录到日志。
+//!
+//! 规则之前先检查标签保护：发布的标签与 `[release] protected_tags` 中的标签不能移动或删除
+//! （见 [`crate::release`]）。全部规则之后检查 `[commit_lint]` 中的提交信息规则（见 [`crate::commit_lint`]）、
+//! `[signing] require_signed` 要求的签名（见 [`crate::signing`]）与 `[big_files]` 限制的文件大小
+//! （见 [`crate::big_files`]），启用 `[secrets]` 时最后扫描更新引入的提交中的凭据（见 [`crate::secrets`]）。
+
+use std::collections::BTreeSet;
+
+use crate::big_files::BigFiles;
+use crate::checks::{Checks, RequiredChecks};
+use crate::commit_lint::CommitLint;
+use crate::common::config::PolicyConfig;
+use crate::common::errors::{MonoError, PolicyViolation};
+use crate::common::MonoResult;
+use crate::object::{ObjectId, ObjectType};
+use crate::refs::RefUpdate;
+use crate::repo::Repository;
+use crate::release;
+use crate::secrets::SecretScanner;
+use crate::signing::Verifier;
+use crate::sparse::SparsePattern;
+
+/// 记录批准人的尾注
+pub const APPROVED_BY: &str = "Approved-by";
+
+/// 一条推送策略规则
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PolicyRule {
+    pub name: String,
+    /// 受保护的引用，以 `/` 结尾时匹配该前缀下的全部引用
+    pub refs: Vec<String>,
+    pub paths: Vec<SparsePattern>,
+    pub require_approvals: usize,
+    pub deny_direct_push: bool,
+    /// 推送的提交上必须成功的 CI 检查
+    pub require_checks: Vec<String>,
+    /// 跳过本规则的推送选项
+    pub bypass_option: Option<String>,
+}
+
+impl PolicyRule {
+    /// 校验并转换配置中的规则
+    pub fn from_config(config: &PolicyConfig) -> MonoResult<PolicyRule> {
+        let invalid = |msg: &str| MonoError::config(format!("policy '{}': {}", config.name, msg));
+        if config.name.is_empty() {
+            return Err(MonoError::config("policy without a name"));
+        }
+        if config.refs.is_empty() || config.paths.is_empty() {
+            return Err(invalid("refs and paths must not be empty"));
+        }
+        if config.require_approvals == 0 && !config.deny_direct_push && config.require_checks.is_empty() {
+            return Err(invalid("one of require_approvals, deny_direct_push or require_checks must be set"));
+        }
+        if config.require_checks.iter().any(|check| check.is_empty()) {
+            return Err(invalid("require_checks must not contain empty names"));
+        }
+        if config.bypass_option.as_ref().is_some_and(|option| option.is_empty()) {
+            return Err(invalid("bypass_option must not be empty"));
+        }
+        let paths = config
+            .paths
+            .iter()
+            .map(|path| path.parse::<SparsePattern>().map_err(|e| invalid(&e.to_string())))
+            .collect::<MonoResult<_>>()?;
+        Ok(PolicyRule {
+            name: config.name.clone(),
+            refs: config.refs.clone(),
+            paths,
+            require_approvals: config.require_approvals,
+            deny_direct_push: config.deny_direct_push,
+            require_checks: config.require_checks.clone(),
+            bypass_option: config.bypass_option.clone(),
+        })
+    }
+
+    /// 规则是否保护该引用
+    pub fn applies_to(&self, refname: &str) -> bool {
+        self.refs.iter().any(|pattern| match pattern.strip_suffix('/') {
+            Some(_) => refname.starts_with(pattern.as_str()),
+            None => refname == pattern,
+        })
+    }
+
+    /// 改动路径中受规则保护的部分
+    pub fn protected_paths<'p>(&self, changed: &'p [String]) -> Vec<&'p String> {
+        changed
+            .iter()
+            .filter(|path| self.paths.iter().any(|pattern| pattern.matches(path)))
+            .collect()
+    }
+}
+
+/// 仓库的全部推送策略
+#[derive(Debug, Clone, Default)]
+pub struct Policy {
+    /// `[release] protected_tags`，在全部规则之前检查
+    pub protected_tags: Vec<String>,
+    pub rules: Vec<PolicyRule>,
+    /// `[commit_lint]` 中的提交信息规则，在全部规则之后检查
+    pub commit_lint: Option<CommitLint>,
+    /// 配置了 `[signing] require_signed` 时的签名验证，在提交信息规则之后检查
+    pub signing: Option<Verifier>,
+    /// 配置了 `[big_files] max_blob_size` 时的大文件检查，在签名之后检查
+    pub big_files: Option<BigFiles>,
+    /// 启用 `[secrets]` 时的凭据扫描，最后对每条更新执行
+    pub secrets: Option<SecretScanner>,
+}
+
+impl Policy {
+    pub fn from_config(configs: &[PolicyConfig]) -> MonoResult<Policy> {
+        Ok(Policy {
+            protected_tags: Vec::new(),
+            rules: configs.iter().map(PolicyRule::from_config).collect::<MonoResult<_>>()?,
+            commit_lint: None,
+            signing: None,
+            big_files: None,
+            secrets: None,
+        })
+    }
+
+    /// 读取仓库配置中的受保护标签、策略、提交信息规则、签名要求、大文件限制与凭据扫描规则
+    pub fn load(repo: &Repository) -> MonoResult<Policy> {
+        let mut policy = Policy::from_config(&repo.config().policy)?;
+        policy.protected_tags = repo.config().release.protected_tags.clone();
+        policy.commit_lint = CommitLint::from_config(&repo.config().commit_lint)?;
+        if !repo.config().signing.require_signed.is_empty() {
+            policy.signing = Some(Verifier::load(repo)?);
+        }
+        policy.big_files = BigFiles::from_config(&repo.config().big_files)?;
+        policy.secrets = SecretScanner::from_config(&repo.config().secrets)?;
+        Ok(policy)
+    }
+
+    /// 检查一条引用更新，违反规则时返回 [`MonoError::policy`]，按配置顺序报告第一条违反的规则
+    ///
+    /// `push_options` 为推送者的推送选项，包含规则的 `bypass_option` 时跳过该规则。
+    pub fn check(&self, repo: &Repository, update: &RefUpdate, push_options: &[String]) -> MonoResult<()> {
+        release::check_update(repo, &self.protected_tags, update)?;
+        self.check_rules(repo, update, push_options)?;
+        if let Some(commit_lint) = &self.commit_lint {
+            commit_lint.check(repo, update, push_options)?;
+        }
+        if let Some(signing) = &self.signing {
+            signing.check(repo, update, push_options)?;
+        }
+        if let Some(big_files) = &self.big_files {
+            big_files.check(repo, update, push_options)?;
+        }
+        match &self.secrets {
+            Some(secrets) => secrets.check(repo, update, push_options),
+            None => Ok(()),
+        }
+    }
+
+    fn check_rules(&self, repo: &Repository, update: &RefUpdate, push_options: &[String]) -> MonoResult<()> {
+        let rules: Vec<&PolicyRule> = self.rules.iter().filter(|rule| rule.applies_to(&update.name)).collect();
+        if rules.is_empty() {
+            return Ok(());
+        }
+        let changed = repo.changed_paths(root_tree(repo, &update.old)?.as_ref(), root_tree(repo, &update.new)?.as_ref())?;
+        let approvals = approvals(repo, &update.new)?;
+        for rule in rules {
+            let paths = rule.protected_paths(&changed);
+            if paths.is_empty() {
+                continue;
+            }
+            if let Some(option) = rule.bypass_option.as_ref().filter(|option| push_options.contains(option)) {
+                tracing::warn!(rule = %rule.name, name = %update.name, %option, "policy bypassed by push option");
+                continue;
+            }
+            let reason = if rule.deny_direct_push {
+                "direct pushes are not allowed".to_string()
+            } else if approvals.len() < rule.require_approvals {
+                format!("requires {} approvals, found {}", rule.require_approvals, approvals.len())
+            } else {
+                match Checks::new(repo).required(&update.new, &rule.require_checks)? {
+                    RequiredChecks::Passed => continue,
+                    RequiredChecks::Pending(pending) => format!("required checks have not passed: {}", pending.join(", ")),
+                    RequiredChecks::Failed(failed) => {
+                        let names: Vec<&str> = failed.iter().map(|check| check.context.as_str()).collect();
+                        format!("required checks failed: {}", names.join(", "))
+                    }
+                }
+            };
+            return Err(MonoError::policy(PolicyViolation {
+                rule: rule.name.clone(),
+                refname: update.name.clone(),
+                paths: paths.into_iter().cloned().collect(),
+                reason,
+            }));
+        }
+        Ok(())
+    }
+}
+
+/// 引用指向的根树，零 ID 或不指向提交与树的引用视为空树
+pub(crate) fn root_tree(repo: &Repository, id: &ObjectId) -> MonoResult<Option<ObjectId>> {
+    if id.is_zero() {
+        return Ok(None);
+    }
+    match repo.peel(id)? {
+        (commit, ObjectType::Commit) => Ok(Some(repo.read_commit(&commit)?.tree)),
+        (tree, ObjectType::Tree) => Ok(Some(tree)),
+        _ => Ok(None),
+    }
+}
+
+/// 现有引用指向的提交，更新引入的提交是从新值可达、从这些提交都不可达的提交
+pub(crate) fn ref_commits(repo: &Repository) -> MonoResult<Vec<ObjectId>> {
+    let mut commits = Vec::new();
+    for (_, id) in repo.refs().list("refs/")? {
+        if let Ok((commit, ObjectType::Commit)) = repo.peel(&id) {
+            commits.push(commit);
+        }
+    }
+    Ok(commits)
+}
+
+/// 推送的提交上不同的批准人
+fn approvals(repo: &Repository, id: &ObjectId) -> MonoResult<BTreeSet<String>> {
+    if id.is_zero() {
+        return Ok(BTreeSet::new());
+    }
+    let (commit, object_type) = repo.peel(id)?;
+    if object_type != ObjectType::Commit {
+        return Ok(BTreeSet::new());
+    }
+    Ok(repo
+        .read_commit(&commit)?
+        .trailers()
+        .into_iter()
+        .filter(|(key, value)| key.eq_ignore_ascii_case(APPROVED_BY) && !value.is_empty())
+        .map(|(_, value)| value)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::errors::MonoErrorKind;
+    use crate::test_utils::{commit_files, init_repo};
+
+    fn rule(name: &str, paths: &[&str], require_approvals: usize, deny_direct_push: bool) -> PolicyConfig {
+        PolicyConfig {
+            name: name.to_string(),
+            refs: vec!["refs/heads/main".to_string(), "refs/heads/release/".to_string()],
+            paths: paths.iter().map(|p| p.to_string()).collect(),
+            require_approvals,
+            deny_direct_push,
+            require_checks: Vec::new(),
+            bypass_option: None,
+        }
+    }
+
+    fn update(name: &str, old: ObjectId, new: ObjectId) -> RefUpdate {
+        RefUpdate {
+            name: name.to_string(),
+            old,
+            new,
+        }
+    }
+
+    /// 测试规则配置的校验与引用匹配
+    #[test]
+    fn test_rule() {
+        let parsed = PolicyRule::from_config(&rule("infra", &["//infra/..."], 2, false)).unwrap();
+        assert!(parsed.applies_to("refs/heads/main"));
+        assert!(parsed.applies_to("refs/heads/release/1.0"));
+        assert!(!parsed.applies_to("refs/heads/main2"));
+        assert!(!parsed.applies_to("refs/heads/feature"));
+
+        assert!(PolicyRule::from_config(&rule("noop", &["//infra/..."], 0, false)).is_err());
+        assert!(PolicyRule::from_config(&rule("bad", &["infra"], 1, false)).is_err());
+        assert!(PolicyRule::from_config(&rule("empty", &[], 1, false)).is_err());
+    }
+
+    /// 测试批准数不足与禁止直接推送的规则，以及不受保护的引用和路径
+    #[test]
+    fn test_check() {
+        let (_dir, repo) = init_repo();
+        let policy = Policy::from_config(&[
+            rule("infra-review", &["//infra/..."], 2, false),
+            rule("payments-frozen", &["//payments/..."], 0, true),
+        ])
+        .unwrap();
+
+        let base = commit_files(&repo, &[("infra/main.tf", b"1"), ("payments/pay.rs", b"1")], &[], "base");
+        let infra = commit_files(&repo, &[("infra/main.tf", b"2"), ("payments/pay.rs", b"1")], &[base], "infra");
+        let docs = commit_files(
+            &repo,
+            &[("infra/main.tf", b"1"), ("payments/pay.rs", b"1"), ("README", b"docs")],
+            &[base],
+            "docs",
+        );
+
+        // 未改动受保护路径，或推送到不受保护的引用
+        policy.check(&repo, &update("refs/heads/main", base, docs), &[]).unwrap();
+        policy.check(&repo, &update("refs/heads/feature", base, infra), &[]).unwrap();
+
+        let err = policy.check(&repo, &update("refs/heads/main", base, infra), &[]).unwrap_err();
+        let MonoErrorKind::Policy(violation) = err.kind() else {
+            panic!("unexpected error: {}", err);
+        };
+        assert_eq!(violation.rule, "infra-review");
+        assert_eq!(violation.paths, ["infra/main.tf"]);
+        assert_eq!(violation.reason, "requires 2 approvals, found 0");
+
+        // 重复的批准人只计一次
+        let mut commit = repo.read_commit(&infra).unwrap();
+        commit.message = "infra\n\nApproved-by: Alice\nApproved-by: Alice\n".to_string();
+        let once = repo.write_object(ObjectType::Commit, &commit.encode()).unwrap();
+        let err = policy.check(&repo, &update("refs/heads/main", base, once), &[]).unwrap_err();
+        assert!(err.to_string().contains("found 1"), "{}", err);
+        commit.message = "infra\n\nApproved-by: Alice\napproved-by: Bob\n".to_string();
+        let approved = repo.write_object(ObjectType::Commit, &commit.encode()).unwrap();
+        policy.check(&repo, &update("refs/heads/main", base, approved), &[]).unwrap();
+
+        // 新建受保护的分支时与空树比较
+        let err = policy.check(&repo, &update("refs/heads/release/1", ObjectId::ZERO, docs), &[]).unwrap_err();
+        assert!(err.to_string().contains("rule 'infra-review'"), "{}", err);
+
+        let payments = commit_files(&repo, &[("infra/main.tf", b"1"), ("payments/pay.rs", b"2")], &[base], "payments");
+        let err = policy.check(&repo, &update("refs/heads/main", base, payments), &[]).unwrap_err();
+        let MonoErrorKind::Policy(violation) = err.kind() else {
+            panic!("unexpected error: {}", err);
+        };
+        assert_eq!(violation.rule, "payments-frozen");
+        assert_eq!(violation.reason, "direct pushes are not allowed");
+    }
+
+    /// 测试要求 CI 检查的规则
+    #[test]
+    fn test_require_checks() {
+        use crate::checks::{CheckState, CheckStatus};
+
+        let (_dir, repo) = init_repo();
+        let mut config = rule("infra-ci", &["//infra/..."], 0, false);
+        config.require_checks = vec!["ci/build".to_string()];
+        let policy = Policy::from_config(&[config]).unwrap();
+        let base = commit_files(&repo, &[("infra/main.tf", b"1")], &[], "base");
+        let infra = commit_files(&repo, &[("infra/main.tf", b"2")], &[base], "infra");
+
+        let err = policy.check(&repo, &update("refs/heads/main", base, infra), &[]).unwrap_err();
+        assert!(err.to_string().contains("required checks have not passed: ci/build"), "{}", err);
+        let mut status = CheckStatus {
+            context: "ci/build".to_string(),
+            state: CheckState::Failure,
+            details_url: None,
+            description: None,
+            reporter: "ci".to_string(),
+            updated_at: 100,
+        };
+        Checks::new(&repo).post(&infra, status.clone()).unwrap();
+        let err = policy.check(&repo, &update("refs/heads/main", base, infra), &[]).unwrap_err();
+        assert!(err.to_string().contains("required checks failed: ci/build"), "{}", err);
+        status.state = CheckState::Success;
+        Checks::new(&repo).post(&infra, status).unwrap();
+        policy.check(&repo, &update("refs/heads/main", base, infra), &[]).unwrap();
+    }
+}
@@ -0,0 +1,576 @@
+//! 合并队列
+//!
+//! 开发者将提交推送到服务端后提交到队列，由队列而不是开发者更新目标分支：队列按提交顺序
+//! 取出一批条目，依次变基到目标分支的最新提交上，对结果运行校验命令，通过后一次性快进
+//! 目标分支。批次校验失败时逐个重试以找出导致失败的条目，其余条目照常合入。这样目标分支
+//! 上的每个提交都经过了与最终内容一致的校验，不会出现各自通过、合在一起却失败的情况。
+//! 配置了 `[queue] required_checks` 时，只有 CI 已在提交上报告这些检查全部成功的条目才会
+//! 被处理（见 [`crate::checks`]）。
+//!
+//! 队列状态保存在 `.mono/queue/state.json`，读写由锁文件串行化；`mono queue run`
+//! 同一时间只允许一个实例运行，通常由定时任务调用。条目的每次状态变化都会触发
+//! `merge-queue` webhook 事件（见 [`crate::webhooks`]）。
+
+use std::fmt;
+use std::fs::OpenOptions;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::audit::{AuditAction, AuditLog};
+use crate::checks::{Checks, RequiredChecks};
+use crate::common::config::{NotificationKind, QueueConfig};
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::notifications::{Notification, Notifications};
+use crate::object::commit::Signature;
+use crate::object::ObjectId;
+use crate::refs::{self, RefUpdate};
+use crate::repo::Repository;
+use crate::rewrite::rebase::{rebase, RebaseOutcome};
+use crate::runner::Runner;
+use crate::webhooks::{WebhookEvent, WebhookQueue};
+
+/// 队列数据目录，相对于 `.mono`
+pub const QUEUE_DIR: &str = "queue";
+/// 队列状态文件名
+const STATE_FILE: &str = "state.json";
+/// 读写队列状态时持有的锁
+const LOCK_FILE: &str = "lock";
+/// `mono queue run` 运行期间持有的锁
+const RUN_LOCK_FILE: &str = "run.lock";
+/// 校验命令的工作目录
+const WORK_DIR: &str = "work";
+/// 校验命令的输出
+const LOG_FILE: &str = "validate.log";
+/// 合入批次时审计日志中的操作者
+const QUEUE_ACTOR: &str = "merge-queue";
+
+/// 队列条目的状态
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryState {
+    Queued,
+    Landed,
+    Failed,
+    Cancelled,
+}
+
+impl fmt::Display for EntryState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(match self {
+            EntryState::Queued => "queued",
+            EntryState::Landed => "landed",
+            EntryState::Failed => "failed",
+            EntryState::Cancelled => "cancelled",
+        })
+    }
+}
+
+/// 队列中的一个条目
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct QueueEntry {
+    pub id: u64,
+    /// 提交时给出的修订，例如 `feature`
+    pub source: String,
+    pub commit: ObjectId,
+    /// 目标分支全名
+    pub target: String,
+    pub submitted_by: String,
+    /// 提交时间（Unix 时间戳）
+    pub submitted_at: i64,
+    pub state: EntryState,
+    /// 合入后目标分支上对应的提交
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub landed: Option<ObjectId>,
+    /// 失败的原因
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// `state.json` 的内容
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct QueueState {
+    next_id: u64,
+    entries: Vec<QueueEntry>,
+}
+
+impl QueueState {
+    fn entry_mut(&mut self, id: u64) -> MonoResult<&mut QueueEntry> {
+        self.entries
+            .iter_mut()
+            .find(|entry| entry.id == id)
+            .ok_or_else(|| MonoError::not_found(format!("queue entry {}", id)))
+    }
+}
+
+/// 以独占方式创建的锁文件，释放时删除
+pub(crate) struct LockFile(PathBuf);
+
+impl LockFile {
+    pub(crate) fn acquire(path: PathBuf) -> MonoResult<LockFile> {
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(_) => Ok(LockFile(path)),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                Err(MonoError::unavailable(format!("{} is locked by another process", path.display())))
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+impl Drop for LockFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// 合入前对候选提交的校验
+pub trait Validator {
+    /// 校验将 `target` 从 `base` 快进到 `candidate`，未通过时返回 `Ok(Err(原因))`
+    fn validate(&self, target: &str, base: &ObjectId, candidate: &ObjectId) -> MonoResult<Result<(), String>>;
+}
+
+impl<F> Validator for F
+where
+    F: Fn(&str, &ObjectId, &ObjectId) -> MonoResult<Result<(), String>>,
+{
+    fn validate(&self, target: &str, base: &ObjectId, candidate: &ObjectId) -> MonoResult<Result<(), String>> {
+        self(target, base, candidate)
+    }
+}
+
+/// 在候选提交的检出目录中运行 shell 命令的校验
+///
+/// 命令通过环境变量 `MONO_QUEUE_TARGET`、`MONO_QUEUE_BASE` 与 `MONO_QUEUE_HEAD` 获得目标分支、
+/// 当前分支位置与候选提交，输出保存在 `.mono/queue/validate.log`。
+pub struct CommandValidator<'a> {
+    repo: &'a Repository,
+    command: String,
+    timeout: Duration,
+}
+
+impl<'a> CommandValidator<'a> {
+    pub fn new(repo: &'a Repository, command: impl Into<String>, timeout: Duration) -> CommandValidator<'a> {
+        CommandValidator {
+            repo,
+            command: command.into(),
+            timeout,
+        }
+    }
+}
+
+impl Validator for CommandValidator<'_> {
+    fn validate(&self, target: &str, base: &ObjectId, candidate: &ObjectId) -> MonoResult<Result<(), String>> {
+        let dir = self.repo.mono_dir().join(QUEUE_DIR);
+        let runner = Runner::new(self.repo, dir.join(WORK_DIR), dir.join(LOG_FILE), self.timeout);
+        let env = [
+            ("MONO_QUEUE_TARGET", target.to_string()),
+            ("MONO_QUEUE_BASE", base.to_string()),
+            ("MONO_QUEUE_HEAD", candidate.to_string()),
+        ];
+        let output = runner.run(candidate, &self.command, &env)?;
+        Ok(match output.status {
+            Some(status) if status.success() => Ok(()),
+            Some(status) => Err(format!("validation failed ({}):\n{}", status, output.tail)),
+            None => Err(format!("validation timed out after {}s:\n{}", self.timeout.as_secs(), output.tail)),
+        })
+    }
+}
+
+/// 一批条目的处理结果
+enum BatchOutcome {
+    /// 校验通过，`tips` 为每个条目变基后的提交
+    Passed { base: ObjectId, tips: Vec<ObjectId> },
+    /// 条目无法合入，与批次中的其他条目无关
+    EntryFailed { id: u64, reason: String },
+    /// 批次整体校验失败
+    Rejected(String),
+}
+
+/// 仓库的合并队列
+pub struct MergeQueue<'a> {
+    repo: &'a Repository,
+    dir: PathBuf,
+}
+
+impl<'a> MergeQueue<'a> {
+    pub fn new(repo: &'a Repository) -> MergeQueue<'a> {
+        MergeQueue {
+            repo,
+            dir: repo.mono_dir().join(QUEUE_DIR),
+        }
+    }
+
+    fn load(&self) -> MonoResult<QueueState> {
+        let path = self.dir.join(STATE_FILE);
+        match std::fs::read(&path) {
+            Ok(data) => serde_json::from_slice(&data)
+                .map_err(|e| MonoError::storage(format!("corrupt queue state {}: {}", path.display(), e))),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(QueueState::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// 在锁的保护下读取、修改并写回队列状态
+    fn update<T>(&self, f: impl FnOnce(&mut QueueState) -> MonoResult<T>) -> MonoResult<T> {
+        std::fs::create_dir_all(&self.dir)?;
+        let _lock = LockFile::acquire(self.dir.join(LOCK_FILE))?;
+        let mut state = self.load()?;
+        let result = f(&mut state)?;
+        let data = serde_json::to_vec_pretty(&state).map_err(|e| MonoError::storage(e.to_string()))?;
+        let tmp = self.dir.join(format!("{}.tmp", STATE_FILE));
+        std::fs::write(&tmp, data)?;
+        std::fs::rename(&tmp, self.dir.join(STATE_FILE))?;
+        Ok(result)
+    }
+
+    /// 全部条目，按提交顺序排列
+    pub fn entries(&self) -> MonoResult<Vec<QueueEntry>> {
+        Ok(self.load()?.entries)
+    }
+
+    /// 将修订 `source` 提交到队列，合入分支 `target`
+    pub fn submit(&self, source: &str, target: &str, submitted_by: &str, now: i64) -> MonoResult<QueueEntry> {
+        let commit = self.repo.resolve_rev(source)?;
+        let target = if target.starts_with(refs::HEADS_PREFIX) {
+            target.to_string()
+        } else {
+            format!("{}{}", refs::HEADS_PREFIX, target)
+        };
+        if self.repo.refs().resolve(&target)?.is_none() {
+            return Err(MonoError::not_found(format!("target branch {}", refs::short_name(&target))));
+        }
+        self.update(|state| {
+            state.next_id += 1;
+            let entry = QueueEntry {
+                id: state.next_id,
+                source: source.to_string(),
+                commit,
+                target,
+                submitted_by: submitted_by.to_string(),
+                submitted_at: now,
+                state: EntryState::Queued,
+                landed: None,
+                reason: None,
+            };
+            state.entries.push(entry.clone());
+            Ok(entry)
+        })
+        .inspect(|entry| self.notify(entry))
+    }
+
+    /// 取消尚未处理的条目
+    pub fn cancel(&self, id: u64) -> MonoResult<QueueEntry> {
+        self.update(|state| {
+            let entry = state.entry_mut(id)?;
+            if entry.state != EntryState::Queued {
+                return Err(MonoError::usage(format!("queue entry {} is already {}", id, entry.state)));
+            }
+            entry.state = EntryState::Cancelled;
+            Ok(entry.clone())
+        })
+        .inspect(|entry| self.notify(entry))
+    }
+
+    /// 处理队列中的全部条目，返回本次合入或失败的条目
+    ///
+    /// 必需检查失败的条目直接失败，检查尚未全部成功的条目留在队列中等待下次运行。
+    pub fn run(&self, validator: &dyn Validator, config: &QueueConfig, committer: &Signature) -> MonoResult<Vec<QueueEntry>> {
+        std::fs::create_dir_all(&self.dir)?;
+        let _lock = LockFile::acquire(self.dir.join(RUN_LOCK_FILE))?;
+        let batch_size = config.batch_size.max(1);
+        let mut size = batch_size;
+        let mut finished = Vec::new();
+        loop {
+            let queued = self.ready(&config.required_checks, &mut finished)?;
+            let Some(target) = queued.first().map(|entry| entry.target.clone()) else {
+                break;
+            };
+            let batch: Vec<QueueEntry> = queued.into_iter().filter(|entry| entry.target == target).take(size).collect();
+            tracing::info!(target = %target, entries = batch.len(), "processing merge queue batch");
+
+            match self.try_batch(&target, &batch, validator, committer)? {
+                BatchOutcome::Passed { base, tips } => {
+                    if let Some(landed) = self.land(&target, &batch, &base, &tips)? {
+                        landed.iter().for_each(|entry| self.notify(entry));
+                        finished.extend(landed);
+                    }
+                }
+                BatchOutcome::EntryFailed { id, reason } => finished.push(self.fail(id, reason)?),
+                BatchOutcome::Rejected(reason) if batch.len() == 1 => {
+                    finished.push(self.fail(batch[0].id, reason)?);
+                    size = batch_size;
+                }
+                // 逐个重试，直到找出导致失败的条目
+                BatchOutcome::Rejected(_) => size = 1,
+            }
+        }
+        Ok(finished)
+    }
+
+    /// 必需检查全部成功的排队条目，检查失败的条目标记为失败并加入 `finished`
+    fn ready(&self, required: &[String], finished: &mut Vec<QueueEntry>) -> MonoResult<Vec<QueueEntry>> {
+        let checks = Checks::new(self.repo);
+        let mut ready = Vec::new();
+        for entry in self.entries()?.into_iter().filter(|entry| entry.state == EntryState::Queued) {
+            match checks.required(&entry.commit, required)? {
+                RequiredChecks::Passed => ready.push(entry),
+                RequiredChecks::Pending(pending) => {
+                    tracing::debug!(id = entry.id, checks = %pending.join(", "), "merge queue entry waits for checks");
+                }
+                RequiredChecks::Failed(failed) => {
+                    let reasons: Vec<String> = failed
+                        .iter()
+                        .map(|check| match &check.details_url {
+                            Some(url) => format!("{} ({})", check.context, url),
+                            None => check.context.clone(),
+                        })
+                        .collect();
+                    finished.push(self.fail(entry.id, format!("required checks failed: {}", reasons.join(", ")))?);
+                }
+            }
+        }
+        Ok(ready)
+    }
+
+    /// 将一批条目依次变基到目标分支上并校验结果
+    fn try_batch(
+        &self,
+        target: &str,
+        batch: &[QueueEntry],
+        validator: &dyn Validator,
+        committer: &Signature,
+    ) -> MonoResult<BatchOutcome> {
+        let Some(base) = self.repo.refs().resolve(target)? else {
+            return Ok(BatchOutcome::EntryFailed {
+                id: batch[0].id,
+                reason: format!("target branch {} does not exist", refs::short_name(target)),
+            });
+        };
+        let mut tip = base;
+        let mut tips = Vec::with_capacity(batch.len());
+        for entry in batch {
+            match rebase(self.repo, &tip, &entry.commit, committer)? {
+                RebaseOutcome::Rebased(rebased) => tip = rebased,
+                RebaseOutcome::Conflict { commit, paths } => {
+                    return Ok(BatchOutcome::EntryFailed {
+                        id: entry.id,
+                        reason: format!("conflict in {} while rebasing {}", paths.join(", "), commit),
+                    });
+                }
+            }
+            tips.push(tip);
+        }
+        Ok(match validator.validate(target, &base, &tip)? {
+            Ok(()) => BatchOutcome::Passed { base, tips },
+            Err(reason) => BatchOutcome::Rejected(reason),
+        })
+    }
+
+    /// 快进目标分支并标记条目为已合入
+    ///
+    /// 校验期间有条目被取消或目标分支被其他方式更新时放弃本批，由下一轮重新组批，返回 None。
+    fn land(&self, target: &str, batch: &[QueueEntry], base: &ObjectId, tips: &[ObjectId]) -> MonoResult<Option<Vec<QueueEntry>>> {
+        self.update(|state| {
+            for entry in batch {
+                if state.entry_mut(entry.id)?.state != EntryState::Queued {
+                    return Ok(None);
+                }
+            }
+            if self.repo.refs().resolve(target)? != Some(*base) {
+                return Ok(None);
+            }
+            let tip = *tips.last().expect("batch is not empty");
+            let update = RefUpdate {
+                name: target.to_string(),
+                old: *base,
+                new: tip,
+            };
+            let reason = format!("merge queue: land {} entries", batch.len());
+            self.repo.with_reflog_identity(QUEUE_ACTOR, &reason).refs().update(std::slice::from_ref(&update))?;
+            let now = chrono::Utc::now().timestamp();
+            AuditLog::new(self.repo).record_ref_updates(self.repo, QUEUE_ACTOR, AuditAction::RefUpdate, &[update], now);
+            tracing::info!(target = %target, %tip, entries = batch.len(), "landed merge queue batch");
+            let mut landed = Vec::with_capacity(batch.len());
+            for (entry, tip) in batch.iter().zip(tips) {
+                let entry = state.entry_mut(entry.id)?;
+                entry.state = EntryState::Landed;
+                entry.landed = Some(*tip);
+                landed.push(entry.clone());
+            }
+            Ok(Some(landed))
+        })
+    }
+
+    fn fail(&self, id: u64, reason: String) -> MonoResult<QueueEntry> {
+        tracing::info!(id, reason = %reason, "merge queue entry failed");
+        self.update(|state| {
+            let entry = state.entry_mut(id)?;
+            if entry.state == EntryState::Queued {
+                entry.state = EntryState::Failed;
+                entry.reason = Some(reason);
+            }
+            Ok(entry.clone())
+        })
+        .inspect(|entry| self.notify(entry))
+    }
+
+    /// 触发条目状态变化的 webhook 事件，条目合入或失败时通知提交者
+    fn notify(&self, entry: &QueueEntry) {
+        WebhookQueue::new(self.repo).notify(&WebhookEvent::MergeQueue { entry: entry.clone() });
+        if !matches!(entry.state, EntryState::Landed | EntryState::Failed) {
+            return;
+        }
+        let body = match (&entry.landed, &entry.reason) {
+            (Some(landed), _) => format!("{} landed on {} as {}", entry.source, refs::short_name(&entry.target), landed),
+            (None, reason) => format!(
+                "{} failed to land on {}:\n\n{}",
+                entry.source,
+                refs::short_name(&entry.target),
+                reason.as_deref().unwrap_or_default()
+            ),
+        };
+        let notification = Notification {
+            kind: NotificationKind::MergeQueue,
+            subject: format!("Merge queue #{} {}", entry.id, entry.state),
+            body,
+            recipients: vec![entry.submitted_by.clone()],
+            actor: None,
+        };
+        Notifications::notify(self.repo, notification);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{commit_files, init_repo};
+
+    /// 测试批次合入、冲突条目失败，以及批次校验失败后逐个重试只拒绝出错的条目
+    #[test]
+    fn test_run() {
+        let (_dir, repo) = init_repo();
+        let base = commit_files(&repo, &[("a", b"1"), ("b", b"1"), ("c", b"1")], &[], "base");
+        repo.refs().write("refs/heads/main", &base).unwrap();
+        let one = commit_files(&repo, &[("a", b"2"), ("b", b"1"), ("c", b"1")], &[base], "one");
+        let two = commit_files(&repo, &[("a", b"1"), ("b", b"2"), ("c", b"1")], &[base], "two");
+        let conflict = commit_files(&repo, &[("a", b"3"), ("b", b"1"), ("c", b"1")], &[base], "conflict");
+        let broken = commit_files(&repo, &[("a", b"1"), ("b", b"1"), ("c", b"broken")], &[base], "broken");
+        let three = commit_files(&repo, &[("a", b"1"), ("b", b"1"), ("c", b"1"), ("d", b"1")], &[base], "three");
+
+        let queue = MergeQueue::new(&repo);
+        for (source, id) in [(one, 1), (two, 2), (conflict, 3), (broken, 4), (three, 5)] {
+            assert_eq!(queue.submit(&source.to_hex(), "main", "dev", 0).unwrap().id, id);
+        }
+        assert!(queue.submit(&one.to_hex(), "missing", "dev", 0).is_err());
+        queue.submit(&one.to_hex(), "main", "dev", 0).unwrap();
+        assert_eq!(queue.cancel(6).unwrap().state, EntryState::Cancelled);
+        assert!(queue.cancel(6).is_err());
+
+        // 文件 c 的内容为 broken 时校验失败
+        let validator = |_: &str, _: &ObjectId, candidate: &ObjectId| -> MonoResult<Result<(), String>> {
+            let tree = repo.read_commit(candidate)?.tree;
+            let c = repo.find_path(&tree, "c")?.expect("c exists");
+            Ok(match repo.read_object(&c.id)?.data.as_slice() {
+                b"broken" => Err("c is broken".to_string()),
+                _ => Ok(()),
+            })
+        };
+        let config = QueueConfig {
+            batch_size: 3,
+            ..Default::default()
+        };
+        let committer = Signature::new("Queue", "queue@example.com", 1_800_000_000);
+        let finished = queue.run(&validator, &config, &committer).unwrap();
+        let states: Vec<(u64, EntryState)> = finished.iter().map(|entry| (entry.id, entry.state)).collect();
+        assert_eq!(
+            states,
+            [
+                (3, EntryState::Failed),
+                (1, EntryState::Landed),
+                (2, EntryState::Landed),
+                (4, EntryState::Failed),
+                (5, EntryState::Landed),
+            ]
+        );
+        assert!(finished[0].reason.as_ref().unwrap().starts_with("conflict in a"));
+        assert_eq!(finished[3].reason.as_deref(), Some("c is broken"));
+
+        // 目标分支依次包含三个合入的条目
+        let head = repo.refs().resolve("refs/heads/main").unwrap().unwrap();
+        assert_eq!(Some(head), finished[4].landed);
+        let messages: Vec<String> = crate::graph::history::History::new(&repo)
+            .unwrap()
+            .log(&[head], None)
+            .unwrap()
+            .iter()
+            .map(|commit| repo.read_commit(&commit.id).unwrap().message)
+            .collect();
+        assert_eq!(messages, ["three\n", "two\n", "one\n", "base\n"]);
+        assert!(queue.run(&validator, &config, &committer).unwrap().is_empty());
+    }
+
+    /// 测试必需检查：成功的条目合入，失败的条目失败，未完成的条目继续排队
+    #[test]
+    fn test_required_checks() {
+        use crate::checks::{CheckState, CheckStatus};
+
+        let (_dir, repo) = init_repo();
+        let base = commit_files(&repo, &[("a", b"1")], &[], "base");
+        repo.refs().write("refs/heads/main", &base).unwrap();
+        let passed = commit_files(&repo, &[("a", b"1"), ("b", b"1")], &[base], "passed");
+        let failed = commit_files(&repo, &[("a", b"1"), ("c", b"1")], &[base], "failed");
+        let pending = commit_files(&repo, &[("a", b"1"), ("d", b"1")], &[base], "pending");
+        let checks = Checks::new(&repo);
+        for (commit, state) in [(passed, CheckState::Success), (failed, CheckState::Failure), (pending, CheckState::Pending)] {
+            let status = CheckStatus {
+                context: "ci/test".to_string(),
+                state,
+                details_url: Some("https://ci.example.com/1".to_string()),
+                description: None,
+                reporter: "ci".to_string(),
+                updated_at: 0,
+            };
+            checks.post(&commit, status).unwrap();
+        }
+
+        let queue = MergeQueue::new(&repo);
+        for source in [passed, failed, pending] {
+            queue.submit(&source.to_hex(), "main", "dev", 0).unwrap();
+        }
+        let config = QueueConfig {
+            required_checks: vec!["ci/test".to_string()],
+            ..Default::default()
+        };
+        let accept = |_: &str, _: &ObjectId, _: &ObjectId| -> MonoResult<Result<(), String>> { Ok(Ok(())) };
+        let committer = Signature::new("Queue", "queue@example.com", 1_800_000_000);
+        let finished = queue.run(&accept, &config, &committer).unwrap();
+        let states: Vec<(u64, EntryState)> = finished.iter().map(|entry| (entry.id, entry.state)).collect();
+        assert_eq!(states, [(2, EntryState::Failed), (1, EntryState::Landed)]);
+        assert_eq!(finished[0].reason.as_deref(), Some("required checks failed: ci/test (https://ci.example.com/1)"));
+        assert_eq!(queue.entries().unwrap()[2].state, EntryState::Queued);
+    }
+
+    /// 测试命令校验：在候选提交的检出目录中运行并报告失败输出
+    #[test]
+    fn test_command_validator() {
+        let (_dir, repo) = init_repo();
+        let good = commit_files(&repo, &[("status", b"ok")], &[], "good");
+        let bad = commit_files(&repo, &[("status", b"bad")], &[], "bad");
+        let validator = CommandValidator::new(
+            &repo,
+            "echo checking $MONO_QUEUE_HEAD; grep -q ok status",
+            Duration::from_secs(10),
+        );
+        assert_eq!(validator.validate("refs/heads/main", &good, &good).unwrap(), Ok(()));
+        let reason = validator.validate("refs/heads/main", &good, &bad).unwrap().unwrap_err();
+        assert!(reason.contains(&format!("checking {}", bad)), "{}", reason);
+        assert!(!repo.mono_dir().join(QUEUE_DIR).join(WORK_DIR).exists());
+
+        let slow = CommandValidator::new(&repo, "sleep 5", Duration::from_millis(200));
+        let reason = slow.validate("refs/heads/main", &good, &good).unwrap().unwrap_err();
+        assert!(reason.starts_with("validation timed out"), "{}", reason);
+    }
+}
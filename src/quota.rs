@@ -0,0 +1,335 @@
+//! 存储用量统计与仓库配额
+//!
+//! 用量从引用与 HEAD 出发遍历可达的对象：每个对象按在存储中占用的空间（压缩后，pack 中可能是 delta）
+//! 计入一次，LFS 指针按指向对象的大小另外计入。在命名空间中只遍历租户自己的引用。
+//!
+//! 按路径前缀统计时，前缀下的每个不同 blob 都计入该前缀，不论出现在哪个提交中；同一个 blob 可以计入
+//! 多个前缀，因此各前缀之和可能超过总量。
+//!
+//! `[quota]` 配置了配额时，receive-pack 在推送的对象移入主存储之前按更新后的引用重新统计：超过软配额
+//! 时记录警告，超过硬配额且用量比推送前增加时拒绝推送。
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use serde::Serialize;
+
+use crate::common::config::QuotaConfig;
+use crate::common::progress::format_bytes;
+use crate::common::MonoResult;
+use crate::lfs::{LfsOid, Pointer, MAX_POINTER_SIZE};
+use crate::object::commit::Commit;
+use crate::object::tag::Tag;
+use crate::object::tree::Tree;
+use crate::object::{ObjectId, ObjectType};
+use crate::refs::RefUpdate;
+use crate::repo::Repository;
+use crate::rewrite::normalize_prefix;
+
+/// 一组对象占用的存储
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Usage {
+    pub objects: u64,
+    /// 对象在存储中占用的字节数
+    pub packed_bytes: u64,
+    pub lfs_objects: u64,
+    pub lfs_bytes: u64,
+}
+
+impl Usage {
+    /// 对象与 LFS 对象的总字节数，与配额比较的值
+    pub fn bytes(&self) -> u64 {
+        self.packed_bytes + self.lfs_bytes
+    }
+}
+
+/// 一次统计的结果
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct UsageReport {
+    /// 全部可达对象
+    pub total: Usage,
+    /// 按目录前缀（不带结尾的 `/`）统计的用量
+    pub paths: BTreeMap<String, Usage>,
+}
+
+/// 存储中每个对象占用的字节数，统计前一次性列出
+pub fn stored_sizes(repo: &Repository) -> MonoResult<HashMap<ObjectId, u64>> {
+    Ok(repo.objects().list_stored()?.into_iter().map(|object| (object.id, object.size)).collect())
+}
+
+/// 统计仓库（在命名空间中时为租户）当前的用量
+///
+/// 路径前缀包括 `depth` 层以内的每个目录与 `prefixes` 中列出的目录。
+pub fn usage(repo: &Repository, depth: usize, prefixes: &[String]) -> MonoResult<UsageReport> {
+    let mut tips: Vec<ObjectId> = repo.refs().list("refs/")?.into_iter().map(|(_, id)| id).collect();
+    tips.extend(repo.head_commit()?);
+    measure(repo, &tips, &stored_sizes(repo)?, depth, prefixes)
+}
+
+/// 统计从 `tips` 可达的对象，对象大小取自 `sizes`；部分克隆或浅克隆中缺失的对象跳过
+pub fn measure(
+    repo: &Repository,
+    tips: &[ObjectId],
+    sizes: &HashMap<ObjectId, u64>,
+    depth: usize,
+    prefixes: &[String],
+) -> MonoResult<UsageReport> {
+    let prefixes = prefixes.iter().map(|prefix| normalize_prefix(prefix)).collect::<MonoResult<HashSet<_>>>()?;
+    let mut walk = Walk {
+        repo,
+        sizes,
+        depth,
+        prefixes,
+        report: UsageReport::default(),
+        seen: HashSet::new(),
+        trees: HashSet::new(),
+        pointers: HashMap::new(),
+        lfs: HashSet::new(),
+        charged: HashSet::new(),
+    };
+    let mut pending: Vec<ObjectId> = tips.to_vec();
+    while let Some(id) = pending.pop() {
+        if !walk.count(&id) {
+            continue;
+        }
+        let Some(object) = repo.objects().read(&id)? else { continue };
+        match object.object_type {
+            ObjectType::Tag => pending.push(Tag::parse(&object.data)?.object),
+            ObjectType::Commit => {
+                let commit = Commit::parse(&object.data)?;
+                if !repo.shallow_commits().contains(&id) {
+                    pending.extend(commit.parents);
+                }
+                walk.tree(commit.tree, String::new())?;
+            }
+            ObjectType::Tree => walk.tree(id, String::new())?,
+            ObjectType::Blob => walk.blob(&id, "")?,
+        }
+    }
+    Ok(walk.report)
+}
+
+/// 一次统计的遍历状态
+struct Walk<'a> {
+    repo: &'a Repository,
+    sizes: &'a HashMap<ObjectId, u64>,
+    depth: usize,
+    prefixes: HashSet<String>,
+    report: UsageReport,
+    /// 已计入总量的对象
+    seen: HashSet<ObjectId>,
+    /// 已遍历的（树，所在目录）
+    trees: HashSet<(ObjectId, String)>,
+    /// blob 是否为 LFS 指针
+    pointers: HashMap<ObjectId, Option<Pointer>>,
+    /// 已计入总量的 LFS 对象
+    lfs: HashSet<LfsOid>,
+    /// 已计入前缀的（前缀，blob）
+    charged: HashSet<(String, ObjectId)>,
+}
+
+impl Walk<'_> {
+    /// 把对象计入总量，已经计入过时返回 false
+    fn count(&mut self, id: &ObjectId) -> bool {
+        if !self.seen.insert(*id) {
+            return false;
+        }
+        if let Some(size) = self.sizes.get(id) {
+            self.report.total.objects += 1;
+            self.report.total.packed_bytes += size;
+        }
+        true
+    }
+
+    /// 遍历目录 `dir`（空或以 `/` 结尾）下的树
+    fn tree(&mut self, root: ObjectId, dir: String) -> MonoResult<()> {
+        let mut pending = vec![(root, dir)];
+        while let Some((id, dir)) = pending.pop() {
+            if !self.trees.insert((id, dir.clone())) {
+                continue;
+            }
+            self.count(&id);
+            let Some(object) = self.repo.objects().read(&id)? else { continue };
+            for entry in Tree::parse(&object.data, id.format())?.entries {
+                if entry.mode.is_tree() {
+                    pending.push((entry.id, format!("{}{}/", dir, entry.name)));
+                } else if entry.mode.is_blob() {
+                    self.blob(&entry.id, &format!("{}{}", dir, entry.name))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// 把路径为 `path` 的 blob 计入总量与所在的各个前缀
+    fn blob(&mut self, id: &ObjectId, path: &str) -> MonoResult<()> {
+        let new = self.count(id);
+        let Some(&size) = self.sizes.get(id) else { return Ok(()) };
+        let pointer = self.pointer(id)?;
+        if let (true, Some(pointer)) = (new, pointer) {
+            if self.lfs.insert(pointer.oid) {
+                self.report.total.lfs_objects += 1;
+                self.report.total.lfs_bytes += pointer.size;
+            }
+        }
+        let dirs = path.match_indices('/').map(|(i, _)| &path[..i]);
+        for (level, prefix) in dirs.enumerate() {
+            if level >= self.depth && !self.prefixes.contains(prefix) {
+                continue;
+            }
+            if !self.charged.insert((prefix.to_string(), *id)) {
+                continue;
+            }
+            let usage = self.report.paths.entry(prefix.to_string()).or_default();
+            usage.objects += 1;
+            usage.packed_bytes += size;
+            if let Some(pointer) = pointer {
+                usage.lfs_objects += 1;
+                usage.lfs_bytes += pointer.size;
+            }
+        }
+        Ok(())
+    }
+
+    fn pointer(&mut self, id: &ObjectId) -> MonoResult<Option<Pointer>> {
+        if let Some(pointer) = self.pointers.get(id) {
+            return Ok(*pointer);
+        }
+        let pointer = match self.repo.objects().read_header(id)? {
+            Some((_, size)) if size <= MAX_POINTER_SIZE => {
+                self.repo.objects().read(id)?.and_then(|object| Pointer::parse(&object.data))
+            }
+            _ => None,
+        };
+        self.pointers.insert(*id, pointer);
+        Ok(pointer)
+    }
+}
+
+/// 超出的一项配额
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Exceeded {
+    /// 路径前缀，None 表示整个仓库
+    pub prefix: Option<String>,
+    pub used: u64,
+    pub limit: u64,
+    /// 超出的是否为硬配额
+    pub hard: bool,
+}
+
+impl std::fmt::Display for Exceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let kind = if self.hard { "hard" } else { "soft" };
+        match &self.prefix {
+            Some(prefix) => write!(f, "{} quota for {}/ exceeded", kind, prefix)?,
+            None => write!(f, "repository {} quota exceeded", kind)?,
+        }
+        write!(f, ": {} used of {}", format_bytes(self.used), format_bytes(self.limit))
+    }
+}
+
+/// 用量超出的配额，同一项同时超出软硬配额时只返回硬配额
+pub fn exceeded(config: &QuotaConfig, report: &UsageReport) -> Vec<Exceeded> {
+    let mut quotas = vec![(None, report.total.bytes(), config.soft_bytes, config.hard_bytes)];
+    for quota in &config.paths {
+        let prefix = normalize_prefix(&quota.prefix).unwrap_or_else(|_| quota.prefix.clone());
+        let used = report.paths.get(&prefix).map(Usage::bytes).unwrap_or_default();
+        quotas.push((Some(prefix), used, quota.soft_bytes, quota.hard_bytes));
+    }
+    let mut exceeded = Vec::new();
+    for (prefix, used, soft, hard) in quotas {
+        if hard > 0 && used > hard {
+            exceeded.push(Exceeded { prefix, used, limit: hard, hard: true });
+        } else if soft > 0 && used > soft {
+            exceeded.push(Exceeded { prefix, used, limit: soft, hard: false });
+        }
+    }
+    exceeded
+}
+
+/// 检查应用 `updates` 之后的用量，返回拒绝推送的硬配额；超出的软配额只记录警告
+///
+/// `repo` 为能读到推送对象的视图，`sizes` 中包含推送的对象。已经超出硬配额的仓库仍然可以推送
+/// 不增加用量的更新，例如删除分支。
+pub fn check_push(repo: &Repository, sizes: &HashMap<ObjectId, u64>, updates: &[RefUpdate]) -> MonoResult<Vec<Exceeded>> {
+    let config = &repo.config().quota;
+    if !config.is_enabled() || updates.is_empty() {
+        return Ok(Vec::new());
+    }
+    let prefixes: Vec<String> = config.paths.iter().map(|quota| quota.prefix.clone()).collect();
+    let current: BTreeMap<String, ObjectId> = repo.refs().list("refs/")?.into_iter().collect();
+    let mut updated = current.clone();
+    for update in updates {
+        match update.is_delete() {
+            true => updated.remove(&update.name),
+            false => updated.insert(update.name.clone(), update.new),
+        };
+    }
+    let tips: Vec<ObjectId> = updated.into_values().collect();
+    let after = measure(repo, &tips, sizes, 0, &prefixes)?;
+    let (hard, soft): (Vec<Exceeded>, Vec<Exceeded>) = exceeded(config, &after).into_iter().partition(|e| e.hard);
+    for exceeded in &soft {
+        tracing::warn!(prefix = exceeded.prefix.as_deref(), used = exceeded.used, limit = exceeded.limit, "{}", exceeded);
+    }
+    if hard.is_empty() {
+        return Ok(hard);
+    }
+    let tips: Vec<ObjectId> = current.into_values().collect();
+    let before = measure(repo, &tips, sizes, 0, &prefixes)?;
+    let used = |prefix: &Option<String>, report: &UsageReport| match prefix {
+        Some(prefix) => report.paths.get(prefix).map(Usage::bytes).unwrap_or_default(),
+        None => report.total.bytes(),
+    };
+    Ok(hard.into_iter().filter(|e| e.used > used(&e.prefix, &before)).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::config::PathQuotaConfig;
+    use crate::test_utils::{commit_files, init_repo};
+
+    /// 测试总量与按前缀的用量：同一 blob 在总量中只计一次，在不同前缀中分别计入，LFS 指针计入指向对象的大小
+    #[test]
+    fn test_usage() {
+        let (_dir, repo) = init_repo();
+        let pointer = Pointer { oid: LfsOid::hash(b"texture"), size: 5000 };
+        let first = commit_files(
+            &repo,
+            &[("src/a.rs", b"fn main() {}"), ("assets/big.png", pointer.encode().as_bytes())],
+            &[],
+            "first",
+        );
+        let second = commit_files(
+            &repo,
+            &[("src/a.rs", b"fn main() { run() }"), ("src/copy/a.rs", b"fn main() {}"), ("assets/big.png", pointer.encode().as_bytes())],
+            &[first],
+            "second",
+        );
+        repo.refs()
+            .update(&[RefUpdate { name: "refs/heads/main".to_string(), old: ObjectId::ZERO, new: second }])
+            .unwrap();
+
+        let report = usage(&repo, 1, &["src/copy".to_string()]).unwrap();
+        let sizes = stored_sizes(&repo).unwrap();
+        assert_eq!(report.total.objects as usize, sizes.len());
+        assert_eq!(report.total.packed_bytes, sizes.values().sum::<u64>());
+        assert_eq!((report.total.lfs_objects, report.total.lfs_bytes), (1, 5000));
+        assert_eq!(report.paths.keys().collect::<Vec<_>>(), ["assets", "src", "src/copy"]);
+        assert_eq!(report.paths["src"].objects, 2);
+        assert_eq!(report.paths["src/copy"].objects, 1);
+        assert_eq!(report.paths["assets"].lfs_bytes, 5000);
+
+        let config = QuotaConfig {
+            soft_bytes: 1,
+            paths: vec![PathQuotaConfig { prefix: "assets/".to_string(), soft_bytes: 0, hard_bytes: 4096 }],
+            ..QuotaConfig::default()
+        };
+        let exceeded = exceeded(&config, &report);
+        assert_eq!(exceeded.len(), 2);
+        assert!(!exceeded[0].hard);
+        assert!(exceeded[1].to_string().starts_with("hard quota for assets/ exceeded: "), "{}", exceeded[1]);
+
+        let tenant = repo.with_namespace("payments").unwrap();
+        assert_eq!(usage(&tenant, 1, &[]).unwrap(), UsageReport::default());
+    }
+}
@@ -0,0 +1,361 @@
+//! 引用日志（reflog）
+//!
+//! 每个引用的每次修改都追加到 `.mono/logs/<引用名>`，格式与 git 相同：
+//!
+//! ```text
+//! <旧值> <新值> <操作者> <Unix 时间> +0000\t<原因>
+//! ```
+//!
+//! 记录由 [`LoggedRefStore`] 在引用数据库之上统一写入，调用方不需要各自记录；操作者与原因
+//! 通过 [`Repository::with_reflog_identity`] 指定。与 git 不同，删除引用时保留其日志，
+//! 被删除的分支可以用 `mono ref restore` 恢复。`mono gc` 把未过期记录中的新旧值都视为可达。
+//! 使用命名空间时日志按底层数据库中的引用名保存，各租户的日志互不可见。
+
+use std::fmt;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use serde::Serialize;
+
+use crate::audit::{self, AuditAction, AuditLog};
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::object::ObjectId;
+use crate::refs::{self, RefStore, RefTarget, RefUpdate};
+use crate::repo::Repository;
+
+/// 引用日志目录，相对于 `.mono`
+pub const LOGS_DIR: &str = "logs";
+
+/// 一条引用日志记录
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct ReflogEntry {
+    /// 修改前的值，全零表示引用被创建
+    pub old: ObjectId,
+    /// 修改后的值，全零表示引用被删除
+    pub new: ObjectId,
+    pub actor: String,
+    /// 修改时间（Unix 秒）
+    pub time: i64,
+    pub reason: String,
+}
+
+impl ReflogEntry {
+    fn parse(line: &str) -> MonoResult<ReflogEntry> {
+        let invalid = || MonoError::storage(format!("invalid reflog entry: {}", line));
+        let (head, reason) = line.split_once('\t').unwrap_or((line, ""));
+        let mut fields: Vec<&str> = head.split(' ').collect();
+        if fields.len() < 5 {
+            return Err(invalid());
+        }
+        fields.pop();
+        let time = fields.pop().and_then(|time| time.parse().ok()).ok_or_else(invalid)?;
+        Ok(ReflogEntry {
+            old: fields[0].parse()?,
+            new: fields[1].parse()?,
+            actor: fields[2..].join(" "),
+            time,
+            reason: reason.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for ReflogEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // 原因中的换行会破坏按行存储的格式
+        let reason = self.reason.replace(['\n', '\t'], " ");
+        write!(f, "{} {} {} {} +0000\t{}", self.old, self.new, self.actor, self.time, reason)
+    }
+}
+
+/// 仓库的引用日志
+#[derive(Debug, Clone)]
+pub struct Reflog {
+    dir: PathBuf,
+}
+
+impl Reflog {
+    /// 以仓库元数据目录（`.mono`）创建
+    pub fn new(mono_dir: &Path) -> Reflog {
+        Reflog {
+            dir: mono_dir.join(LOGS_DIR),
+        }
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(name)
+    }
+
+    /// 追加一条记录；每条记录以一次写入完成，并发追加不会交错
+    pub fn append(&self, name: &str, entry: &ReflogEntry) -> MonoResult<()> {
+        let path = self.path(name);
+        std::fs::create_dir_all(path.parent().expect("reflog path has a parent"))?;
+        let mut file = std::fs::OpenOptions::new().create(true).append(true).open(&path)?;
+        file.write_all(format!("{}\n", entry).as_bytes())?;
+        Ok(())
+    }
+
+    /// 引用的全部记录，按时间从旧到新；没有日志时返回空列表
+    pub fn read(&self, name: &str) -> MonoResult<Vec<ReflogEntry>> {
+        let content = match std::fs::read_to_string(self.path(name)) {
+            Ok(content) => content,
+            Err(e) if matches!(e.kind(), std::io::ErrorKind::NotFound | std::io::ErrorKind::IsADirectory) => {
+                return Ok(Vec::new())
+            }
+            Err(e) => return Err(e.into()),
+        };
+        Ok(content
+            .lines()
+            .filter_map(|line| match ReflogEntry::parse(line) {
+                Ok(entry) => Some(entry),
+                Err(e) => {
+                    tracing::warn!(name, error = %e, "skipping corrupt reflog entry");
+                    None
+                }
+            })
+            .collect())
+    }
+
+    /// 是否有该引用的日志
+    pub fn exists(&self, name: &str) -> bool {
+        self.path(name).is_file()
+    }
+
+    /// 有日志的全部引用名，包括已删除的引用，按名称排序
+    pub fn names(&self) -> MonoResult<Vec<String>> {
+        let mut names = Vec::new();
+        collect_logs(&self.dir, &self.dir, &mut names)?;
+        names.sort();
+        Ok(names)
+    }
+
+    /// 删除早于 `before`（Unix 秒）的记录，返回删除的记录数；记录全部删除的日志文件一并删除
+    pub fn expire(&self, before: i64) -> MonoResult<usize> {
+        let mut expired = 0;
+        for name in self.names()? {
+            let entries = self.read(&name)?;
+            let kept: Vec<&ReflogEntry> = entries.iter().filter(|entry| entry.time >= before).collect();
+            if kept.len() == entries.len() {
+                continue;
+            }
+            expired += entries.len() - kept.len();
+            let path = self.path(&name);
+            if kept.is_empty() {
+                std::fs::remove_file(&path)?;
+                continue;
+            }
+            let content: String = kept.iter().map(|entry| format!("{}\n", entry)).collect();
+            let tmp = path.with_file_name(format!(".tmp-{}", std::process::id()));
+            std::fs::write(&tmp, content)?;
+            std::fs::rename(&tmp, &path)?;
+        }
+        Ok(expired)
+    }
+}
+
+/// 递归收集目录下的日志文件名
+fn collect_logs(base: &Path, dir: &Path, out: &mut Vec<String>) -> MonoResult<()> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+    for entry in entries {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_logs(base, &path, out)?;
+        } else if let Ok(relative) = path.strip_prefix(base) {
+            let name = relative.to_string_lossy().replace(std::path::MAIN_SEPARATOR, "/");
+            if !name.rsplit('/').next().is_some_and(|file| file.starts_with(".tmp-")) {
+                out.push(name);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 把每次修改记入引用日志的引用数据库
+///
+/// 符号引用的修改不改变任何引用指向的对象，不记录。写日志失败只记录警告，不影响已经完成的修改。
+#[derive(Debug, Clone)]
+pub struct LoggedRefStore {
+    inner: Arc<dyn RefStore>,
+    log: Reflog,
+    actor: String,
+    reason: String,
+}
+
+impl LoggedRefStore {
+    pub fn new(inner: Arc<dyn RefStore>, log: Reflog, actor: &str, reason: &str) -> LoggedRefStore {
+        LoggedRefStore {
+            inner,
+            log,
+            actor: actor.to_string(),
+            reason: reason.to_string(),
+        }
+    }
+
+    fn current(&self, name: &str) -> MonoResult<ObjectId> {
+        match self.inner.read(name)? {
+            Some(RefTarget::Direct(id)) => Ok(id),
+            _ => Ok(ObjectId::ZERO),
+        }
+    }
+
+    fn record(&self, name: &str, old: ObjectId, new: ObjectId) {
+        let entry = ReflogEntry {
+            old,
+            new,
+            actor: self.actor.clone(),
+            time: chrono::Utc::now().timestamp(),
+            reason: self.reason.clone(),
+        };
+        if let Err(e) = self.log.append(name, &entry) {
+            tracing::warn!(name, error = %e, "failed to write reflog");
+        }
+    }
+}
+
+impl RefStore for LoggedRefStore {
+    fn read(&self, name: &str) -> MonoResult<Option<RefTarget>> {
+        self.inner.read(name)
+    }
+
+    fn write(&self, name: &str, id: &ObjectId) -> MonoResult<()> {
+        let old = self.current(name)?;
+        self.inner.write(name, id)?;
+        self.record(name, old, *id);
+        Ok(())
+    }
+
+    fn write_symbolic(&self, name: &str, target: &str) -> MonoResult<()> {
+        self.inner.write_symbolic(name, target)
+    }
+
+    fn delete(&self, name: &str) -> MonoResult<()> {
+        let old = self.current(name)?;
+        self.inner.delete(name)?;
+        if !old.is_zero() {
+            self.record(name, old, ObjectId::ZERO);
+        }
+        Ok(())
+    }
+
+    fn list(&self, prefix: &str) -> MonoResult<Vec<(String, ObjectId)>> {
+        self.inner.list(prefix)
+    }
+
+    fn update(&self, updates: &[RefUpdate]) -> MonoResult<()> {
+        self.inner.update(updates)?;
+        for update in updates {
+            self.record(&update.name, update.old, update.new);
+        }
+        Ok(())
+    }
+
+    fn flush(&self) -> MonoResult<()> {
+        self.inner.flush()
+    }
+}
+
+/// 命令行中的引用名对应的完整引用名：依次尝试原名、分支与标签，取第一个存在或有日志的
+pub fn resolve_name(repo: &Repository, name: &str) -> MonoResult<String> {
+    let candidates = [
+        name.to_string(),
+        format!("{}{}", refs::HEADS_PREFIX, name),
+        format!("{}{}", refs::TAGS_PREFIX, name),
+    ];
+    for candidate in candidates.iter().filter(|c| refs::check_name(c).is_ok()) {
+        if repo.refs().read(candidate)?.is_some() || !repo.reflog(candidate)?.is_empty() {
+            return Ok(candidate.clone());
+        }
+    }
+    Err(MonoError::not_found(format!("ref {}", name)))
+}
+
+/// 把引用恢复为日志中的第 `index` 个值（`<ref>@{index}`，0 为最新），返回执行的更新
+///
+/// 恢复本身也作为一次修改记入引用日志与审计日志，可以再次恢复回去。
+pub fn restore(repo: &Repository, name: &str, index: usize) -> MonoResult<RefUpdate> {
+    let entries = repo.reflog(name)?;
+    let entry = entries
+        .iter()
+        .rev()
+        .nth(index)
+        .ok_or_else(|| MonoError::not_found(format!("{}@{{{}}}: reflog has only {} entries", name, index, entries.len())))?;
+    if entry.new.is_zero() {
+        return Err(MonoError::usage(format!("{} did not exist at {}@{{{}}}", name, name, index)));
+    }
+    if !repo.objects().contains(&entry.new)? {
+        return Err(MonoError::not_found(format!("object {} of {}@{{{}}} was garbage collected", entry.new, name, index)));
+    }
+    let old = match repo.refs().read(name)? {
+        Some(RefTarget::Direct(id)) => id,
+        Some(RefTarget::Symbolic(target)) => {
+            return Err(MonoError::usage(format!("{} is a symbolic ref to {}", name, target)))
+        }
+        None => ObjectId::ZERO,
+    };
+    let update = RefUpdate {
+        name: name.to_string(),
+        old,
+        new: entry.new,
+    };
+    let actor = audit::local_actor();
+    let reason = format!("restore: {}@{{{}}}", name, index);
+    repo.with_reflog_identity(&actor, &reason).refs().update(std::slice::from_ref(&update))?;
+    let now = chrono::Utc::now().timestamp();
+    AuditLog::new(repo).record_ref_updates(repo, &actor, AuditAction::RefUpdate, std::slice::from_ref(&update), now);
+    Ok(update)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{commit_files, init_repo};
+
+    /// 测试写入、批量更新与删除都记入日志，删除后仍可从日志恢复，过期记录被清理
+    #[test]
+    fn test_reflog() {
+        let (_dir, repo) = init_repo();
+        let first = commit_files(&repo, &[("a.txt", b"one")], &[], "first");
+        let second = commit_files(&repo, &[("a.txt", b"two")], &[first], "second");
+        let main = "refs/heads/main";
+
+        repo.refs().write(main, &first).unwrap();
+        repo.with_reflog_identity("user:alice", "push")
+            .refs()
+            .update(&[RefUpdate {
+                name: main.to_string(),
+                old: first,
+                new: second,
+            }])
+            .unwrap();
+        repo.refs().delete(main).unwrap();
+
+        let entries = repo.reflog(main).unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!((entries[0].old, entries[0].new), (ObjectId::ZERO, first));
+        assert_eq!((entries[1].old, entries[1].new), (first, second));
+        assert_eq!((entries[1].actor.as_str(), entries[1].reason.as_str()), ("user:alice", "push"));
+        assert_eq!((entries[2].old, entries[2].new), (second, ObjectId::ZERO));
+        assert_eq!(resolve_name(&repo, "main").unwrap(), main);
+
+        assert!(restore(&repo, main, 0).is_err());
+        let update = restore(&repo, main, 1).unwrap();
+        assert_eq!((update.old, update.new), (ObjectId::ZERO, second));
+        assert_eq!(repo.refs().resolve(main).unwrap(), Some(second));
+        assert_eq!(repo.reflog(main).unwrap().last().unwrap().reason, "restore: refs/heads/main@{1}");
+
+        let tenant = repo.with_namespace("acme").unwrap();
+        tenant.refs().write(main, &first).unwrap();
+        assert_eq!(tenant.reflog(main).unwrap().len(), 1);
+        assert_eq!(repo.reflog(main).unwrap().len(), 4);
+
+        let log = Reflog::new(repo.mono_dir());
+        assert_eq!(log.names().unwrap(), vec![main.to_string(), "refs/namespaces/acme/refs/heads/main".to_string()]);
+        assert_eq!(log.expire(i64::MAX).unwrap(), 5);
+        assert!(log.names().unwrap().is_empty());
+    }
+}
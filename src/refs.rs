@@ -0,0 +1,571 @@
+//! 引用（ref）数据库
+//!
+//! 引用以与 git 相同的格式保存在 `.mono` 目录下：松散引用位于 `refs/` 中，
+//! 打包引用位于 `packed-refs` 文件中，`HEAD` 通常是指向分支的符号引用。
+//! 其他后端（如 PostgreSQL）通过实现 [`RefStore`] 接入。
+//!
+//! 与 git 的 `GIT_NAMESPACE` 相同，[`NamespacedRefStore`] 把一个逻辑仓库的引用保存在
+//! `refs/namespaces/<名称>/` 之下，多个租户共享同一个对象存储，但各自只能看到和修改自己的引用。
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::object::ObjectId;
+
+/// HEAD 引用名
+pub const HEAD: &str = "HEAD";
+/// 分支引用前缀
+pub const HEADS_PREFIX: &str = "refs/heads/";
+/// 标签引用前缀
+pub const TAGS_PREFIX: &str = "refs/tags/";
+/// 命名空间引用前缀
+pub const NAMESPACES_PREFIX: &str = "refs/namespaces/";
+
+/// 符号引用最多允许的嵌套层数
+const MAX_SYMREF_DEPTH: usize = 5;
+
+/// 引用的目标
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RefTarget {
+    /// 直接指向对象
+    Direct(ObjectId),
+    /// 指向另一个引用
+    Symbolic(String),
+}
+
+impl RefTarget {
+    fn parse(content: &str) -> MonoResult<RefTarget> {
+        let content = content.trim();
+        match content.strip_prefix("ref:") {
+            Some(target) => Ok(RefTarget::Symbolic(target.trim().to_string())),
+            None => Ok(RefTarget::Direct(content.parse()?)),
+        }
+    }
+}
+
+/// 一条带旧值校验的引用更新，与 git 推送命令的语义一致
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RefUpdate {
+    pub name: String,
+    /// 期望的当前值，全零表示引用必须不存在
+    pub old: ObjectId,
+    /// 新值，全零表示删除
+    pub new: ObjectId,
+}
+
+impl RefUpdate {
+    pub fn is_delete(&self) -> bool {
+        self.new.is_zero()
+    }
+
+    /// 引用的当前值是否与 `old` 相符，任一格式的全零 ID 都表示引用不存在
+    pub fn expects(&self, current: Option<&ObjectId>) -> bool {
+        match current {
+            Some(current) => *current == self.old,
+            None => self.old.is_zero(),
+        }
+    }
+}
+
+/// 引用数据库
+pub trait RefStore: Send + Sync + fmt::Debug {
+    /// 读取引用的原始目标，不解析符号引用
+    fn read(&self, name: &str) -> MonoResult<Option<RefTarget>>;
+
+    /// 更新引用指向的对象
+    fn write(&self, name: &str, id: &ObjectId) -> MonoResult<()>;
+
+    /// 将引用设置为指向另一个引用的符号引用
+    fn write_symbolic(&self, name: &str, target: &str) -> MonoResult<()>;
+
+    /// 删除引用
+    fn delete(&self, name: &str) -> MonoResult<()>;
+
+    /// 列出以 `prefix` 开头的全部直接引用，按名称排序
+    fn list(&self, prefix: &str) -> MonoResult<Vec<(String, ObjectId)>>;
+
+    /// 应用一组更新：任一引用的当前值与 `old` 不符时返回错误且不做任何修改
+    fn update(&self, updates: &[RefUpdate]) -> MonoResult<()>;
+
+    /// 等待进行中的多引用更新完成，并处理中断遗留的事务记录；服务端退出前调用
+    fn flush(&self) -> MonoResult<()> {
+        Ok(())
+    }
+
+    /// 解析引用最终指向的对象，引用不存在或未出生时返回 None
+    fn resolve(&self, name: &str) -> MonoResult<Option<ObjectId>> {
+        let mut name = name.to_string();
+        for _ in 0..MAX_SYMREF_DEPTH {
+            match self.read(&name)? {
+                Some(RefTarget::Direct(id)) => return Ok(Some(id)),
+                Some(RefTarget::Symbolic(target)) => name = target,
+                None => return Ok(None),
+            }
+        }
+        Err(MonoError::storage(format!("symbolic ref loop at {}", name)))
+    }
+
+    /// HEAD 指向的分支全名，HEAD 分离时返回 None
+    fn head_target(&self) -> MonoResult<Option<String>> {
+        match self.read(HEAD)? {
+            Some(RefTarget::Symbolic(target)) => Ok(Some(target)),
+            _ => Ok(None),
+        }
+    }
+}
+
+/// 检查引用名：只允许 HEAD 与 `refs/` 下的合法名称
+pub fn check_name(name: &str) -> MonoResult<()> {
+    if name == HEAD || (check_ref_format(name) && name.starts_with("refs/")) {
+        Ok(())
+    } else {
+        Err(MonoError::usage(format!("invalid ref name: {}", name)))
+    }
+}
+
+/// 更新中的旧值与当前值不符时的错误
+pub fn stale_ref_error(update: &RefUpdate, current: Option<&ObjectId>) -> MonoError {
+    MonoError::storage(format!(
+        "ref {} changed concurrently: expected {}, found {}",
+        update.name,
+        update.old,
+        current.unwrap_or(&ObjectId::ZERO)
+    ))
+}
+
+/// 同一进程内串行化文件引用的批量更新
+static FILE_UPDATE_LOCK: Mutex<()> = Mutex::new(());
+
+/// 基于文件的引用数据库
+#[derive(Debug, Clone)]
+pub struct FileRefStore {
+    dir: PathBuf,
+}
+
+impl FileRefStore {
+    /// 以仓库元数据目录（`.mono`）创建引用数据库
+    pub fn new(dir: impl Into<PathBuf>) -> FileRefStore {
+        FileRefStore { dir: dir.into() }
+    }
+
+    fn ref_path(&self, name: &str) -> PathBuf {
+        self.dir.join(name)
+    }
+
+    /// 读取 `packed-refs` 中的全部引用
+    fn packed_refs(&self) -> MonoResult<Vec<(String, ObjectId)>> {
+        let content = match std::fs::read_to_string(self.dir.join("packed-refs")) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut refs = Vec::new();
+        for line in content.lines() {
+            if line.starts_with('#') || line.starts_with('^') || line.is_empty() {
+                continue;
+            }
+            if let Some((id, name)) = line.split_once(' ') {
+                refs.push((name.to_string(), id.parse()?));
+            }
+        }
+        Ok(refs)
+    }
+
+    fn write_raw(&self, name: &str, content: &str) -> MonoResult<()> {
+        check_name(name)?;
+        let path = self.ref_path(name);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let lock = path.with_file_name(format!(
+            "{}.lock",
+            path.file_name().unwrap_or_default().to_string_lossy()
+        ));
+        std::fs::write(&lock, content)?;
+        std::fs::rename(&lock, &path)?;
+        Ok(())
+    }
+}
+
+impl RefStore for FileRefStore {
+    /// 读取引用的原始目标，不解析符号引用
+    fn read(&self, name: &str) -> MonoResult<Option<RefTarget>> {
+        check_name(name)?;
+        match std::fs::read_to_string(self.ref_path(name)) {
+            Ok(content) => return RefTarget::parse(&content).map(Some),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            // 目录同名（例如读取 refs/heads）视为不存在
+            Err(_) if self.ref_path(name).is_dir() => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+        Ok(self
+            .packed_refs()?
+            .into_iter()
+            .find(|(packed, _)| packed == name)
+            .map(|(_, id)| RefTarget::Direct(id)))
+    }
+
+    /// 更新引用指向的对象
+    fn write(&self, name: &str, id: &ObjectId) -> MonoResult<()> {
+        self.write_raw(name, &format!("{}\n", id))
+    }
+
+    /// 将引用设置为指向另一个引用的符号引用
+    fn write_symbolic(&self, name: &str, target: &str) -> MonoResult<()> {
+        check_name(target)?;
+        self.write_raw(name, &format!("ref: {}\n", target))
+    }
+
+    /// 删除引用，同时从 `packed-refs` 中移除
+    fn delete(&self, name: &str) -> MonoResult<()> {
+        check_name(name)?;
+        match std::fs::remove_file(self.ref_path(name)) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+        let packed = self.packed_refs()?;
+        if packed.iter().any(|(packed, _)| packed == name) {
+            let content: String = packed
+                .iter()
+                .filter(|(packed, _)| packed != name)
+                .map(|(packed, id)| format!("{} {}\n", id, packed))
+                .collect();
+            std::fs::write(self.dir.join("packed-refs"), content)?;
+        }
+        Ok(())
+    }
+
+    /// 列出以 `prefix` 开头的全部直接引用，按名称排序
+    fn list(&self, prefix: &str) -> MonoResult<Vec<(String, ObjectId)>> {
+        let mut refs = std::collections::BTreeMap::new();
+        for (name, id) in self.packed_refs()? {
+            if name.starts_with(prefix) {
+                refs.insert(name, id);
+            }
+        }
+        let mut loose = Vec::new();
+        collect_loose(&self.dir, &self.dir.join("refs"), &mut loose)?;
+        for name in loose {
+            if !name.starts_with(prefix) {
+                continue;
+            }
+            if let Some(RefTarget::Direct(id)) = self.read(&name)? {
+                refs.insert(name, id);
+            }
+        }
+        Ok(refs.into_iter().collect())
+    }
+
+    /// 在进程内锁的保护下先校验全部旧值再逐个写入；跨进程的互斥与崩溃后的原子性由
+    /// [`crate::journal::JournaledRefStore`] 保证
+    fn update(&self, updates: &[RefUpdate]) -> MonoResult<()> {
+        let _guard = FILE_UPDATE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        for update in updates {
+            let current = self.resolve(&update.name)?;
+            if !update.expects(current.as_ref()) {
+                return Err(stale_ref_error(update, current.as_ref()));
+            }
+        }
+        for update in updates {
+            if update.is_delete() {
+                self.delete(&update.name)?;
+            } else {
+                self.write(&update.name, &update.new)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 检查命名空间名：必须是单级的合法引用名组成部分
+pub fn check_namespace(name: &str) -> MonoResult<()> {
+    if !name.contains('/') && check_ref_format(&format!("{}{}", NAMESPACES_PREFIX, name)) {
+        Ok(())
+    } else {
+        Err(MonoError::usage(format!("invalid namespace: {}", name)))
+    }
+}
+
+/// 把引用限制在一个命名空间内的引用数据库
+///
+/// 读写的引用名都是命名空间内的逻辑名（如 `refs/heads/main`），在底层数据库中加上
+/// `refs/namespaces/<名称>/` 前缀；符号引用的目标同样在命名空间内，指向命名空间之外的符号引用
+/// 视为损坏。命名空间中还没有 HEAD 时沿用底层数据库 HEAD 指向的分支名。
+#[derive(Debug, Clone)]
+pub struct NamespacedRefStore {
+    inner: Arc<dyn RefStore>,
+    namespace: String,
+    prefix: String,
+}
+
+impl NamespacedRefStore {
+    pub fn new(inner: Arc<dyn RefStore>, namespace: &str) -> MonoResult<NamespacedRefStore> {
+        check_namespace(namespace)?;
+        Ok(NamespacedRefStore {
+            inner,
+            namespace: namespace.to_string(),
+            prefix: format!("{}{}/", NAMESPACES_PREFIX, namespace),
+        })
+    }
+
+    /// 命名空间名
+    pub fn namespace(&self) -> &str {
+        &self.namespace
+    }
+
+    /// 逻辑引用名在底层数据库中的名称
+    fn physical(&self, name: &str) -> MonoResult<String> {
+        check_name(name)?;
+        Ok(format!("{}{}", self.prefix, name))
+    }
+
+    /// 底层数据库中的引用名对应的逻辑名
+    fn logical<'a>(&self, name: &'a str) -> MonoResult<&'a str> {
+        name.strip_prefix(&self.prefix).ok_or_else(|| {
+            MonoError::storage(format!("ref {} is outside namespace {}", name, self.namespace))
+        })
+    }
+}
+
+impl RefStore for NamespacedRefStore {
+    fn read(&self, name: &str) -> MonoResult<Option<RefTarget>> {
+        match self.inner.read(&self.physical(name)?)? {
+            Some(RefTarget::Symbolic(target)) => Ok(Some(RefTarget::Symbolic(self.logical(&target)?.to_string()))),
+            None if name == HEAD => match self.inner.read(HEAD)? {
+                Some(RefTarget::Symbolic(target)) => Ok(Some(RefTarget::Symbolic(target))),
+                _ => Ok(None),
+            },
+            target => Ok(target),
+        }
+    }
+
+    fn write(&self, name: &str, id: &ObjectId) -> MonoResult<()> {
+        self.inner.write(&self.physical(name)?, id)
+    }
+
+    fn write_symbolic(&self, name: &str, target: &str) -> MonoResult<()> {
+        self.inner.write_symbolic(&self.physical(name)?, &self.physical(target)?)
+    }
+
+    fn delete(&self, name: &str) -> MonoResult<()> {
+        self.inner.delete(&self.physical(name)?)
+    }
+
+    fn list(&self, prefix: &str) -> MonoResult<Vec<(String, ObjectId)>> {
+        self.inner
+            .list(&format!("{}{}", self.prefix, prefix))?
+            .into_iter()
+            .map(|(name, id)| Ok((self.logical(&name)?.to_string(), id)))
+            .collect()
+    }
+
+    fn update(&self, updates: &[RefUpdate]) -> MonoResult<()> {
+        let updates = updates
+            .iter()
+            .map(|update| {
+                Ok(RefUpdate {
+                    name: self.physical(&update.name)?,
+                    ..update.clone()
+                })
+            })
+            .collect::<MonoResult<Vec<_>>>()?;
+        self.inner.update(&updates)
+    }
+
+    fn flush(&self) -> MonoResult<()> {
+        self.inner.flush()
+    }
+}
+
+/// 递归收集目录下的松散引用名
+fn collect_loose(base: &Path, dir: &Path, out: &mut Vec<String>) -> MonoResult<()> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+    for entry in entries {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_loose(base, &path, out)?;
+        } else if let Ok(relative) = path.strip_prefix(base) {
+            let name = relative.to_string_lossy().replace(std::path::MAIN_SEPARATOR, "/");
+            if !name.ends_with(".lock") {
+                out.push(name);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 按 git check-ref-format 的规则检查引用名是否合法
+pub fn check_ref_format(name: &str) -> bool {
+    if name.is_empty() || name == "@" || name.starts_with('/') || name.ends_with('/') {
+        return false;
+    }
+    if name.ends_with('.') || name.contains("..") || name.contains("//") || name.contains("@{") {
+        return false;
+    }
+    if name
+        .chars()
+        .any(|c| c.is_ascii_control() || matches!(c, ' ' | '~' | '^' | ':' | '?' | '*' | '[' | '\\'))
+    {
+        return false;
+    }
+    name.split('/')
+        .all(|component| !component.starts_with('.') && !component.ends_with(".lock"))
+}
+
+/// 去掉 `refs/heads/` 或 `refs/tags/` 前缀后的短名
+pub fn short_name(full_name: &str) -> &str {
+    full_name
+        .strip_prefix(HEADS_PREFIX)
+        .or_else(|| full_name.strip_prefix(TAGS_PREFIX))
+        .unwrap_or(full_name)
+}
+
+/// 检查分支名是否合法
+pub fn check_branch_name(name: &str) -> bool {
+    !name.starts_with('-') && check_ref_format(&format!("{}{}", HEADS_PREFIX, name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 测试合法与非法的引用名
+    #[test]
+    fn test_check_ref_format() {
+        assert!(check_ref_format("refs/heads/main"));
+        assert!(check_ref_format("refs/heads/feature/a-b_c"));
+        assert!(!check_ref_format("refs/heads/a..b"));
+        assert!(!check_ref_format("refs/heads/.hidden"));
+        assert!(!check_ref_format("refs/heads/main.lock"));
+        assert!(!check_ref_format("refs/heads/with space"));
+        assert!(!check_ref_format("refs/heads/"));
+        assert!(!check_ref_format("refs//heads"));
+        assert!(!check_ref_format("refs/heads/a@{1}"));
+    }
+
+    /// 测试分支名检查
+    #[test]
+    fn test_check_branch_name() {
+        assert!(check_branch_name("main"));
+        assert!(check_branch_name("release/1.0"));
+        assert!(!check_branch_name("-main"));
+        assert!(!check_branch_name(""));
+    }
+
+    /// 测试引用的写入、解析、列举与删除
+    #[test]
+    fn test_file_ref_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FileRefStore::new(dir.path());
+        let id = ObjectId::hash_object(crate::object::ObjectType::Blob, b"x");
+
+        store.write_symbolic(HEAD, "refs/heads/main").unwrap();
+        assert_eq!(store.resolve(HEAD).unwrap(), None);
+        assert_eq!(store.head_target().unwrap().as_deref(), Some("refs/heads/main"));
+
+        store.write("refs/heads/main", &id).unwrap();
+        store.write("refs/tags/v1", &id).unwrap();
+        assert_eq!(store.resolve(HEAD).unwrap(), Some(id));
+        assert_eq!(
+            store.list("refs/heads/").unwrap(),
+            vec![("refs/heads/main".to_string(), id)]
+        );
+        assert_eq!(store.list("refs/").unwrap().len(), 2);
+
+        store.delete("refs/tags/v1").unwrap();
+        assert_eq!(store.read("refs/tags/v1").unwrap(), None);
+        assert!(store.write("refs/heads/bad..name", &id).is_err());
+    }
+
+    /// 测试批量更新在任一旧值不符时不做任何修改
+    #[test]
+    fn test_file_ref_store_update() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FileRefStore::new(dir.path());
+        let a = ObjectId::hash_object(crate::object::ObjectType::Blob, b"a");
+        let b = ObjectId::hash_object(crate::object::ObjectType::Blob, b"b");
+        let update = |name: &str, old, new| RefUpdate {
+            name: name.to_string(),
+            old,
+            new,
+        };
+        store.write("refs/heads/main", &a).unwrap();
+
+        let err = store
+            .update(&[update("refs/heads/new", ObjectId::ZERO, a), update("refs/heads/main", b, a)])
+            .unwrap_err();
+        assert!(err.to_string().contains("changed concurrently"));
+        assert_eq!(store.read("refs/heads/new").unwrap(), None);
+
+        store
+            .update(&[update("refs/heads/new", ObjectId::ZERO, a), update("refs/heads/main", a, b)])
+            .unwrap();
+        assert_eq!(store.resolve("refs/heads/main").unwrap(), Some(b));
+        store.update(&[update("refs/heads/new", a, ObjectId::ZERO)]).unwrap();
+        assert_eq!(store.read("refs/heads/new").unwrap(), None);
+    }
+
+    /// 测试命名空间之间的引用互相隔离，HEAD 默认沿用底层数据库的分支名
+    #[test]
+    fn test_namespaced_ref_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let root: Arc<dyn RefStore> = Arc::new(FileRefStore::new(dir.path()));
+        let a = NamespacedRefStore::new(root.clone(), "a").unwrap();
+        let b = NamespacedRefStore::new(root.clone(), "b").unwrap();
+        let x = ObjectId::hash_object(crate::object::ObjectType::Blob, b"x");
+        let y = ObjectId::hash_object(crate::object::ObjectType::Blob, b"y");
+        root.write_symbolic(HEAD, "refs/heads/main").unwrap();
+
+        a.write("refs/heads/main", &x).unwrap();
+        assert_eq!(a.resolve(HEAD).unwrap(), Some(x));
+        assert_eq!(b.resolve(HEAD).unwrap(), None);
+        assert_eq!(root.resolve("refs/namespaces/a/refs/heads/main").unwrap(), Some(x));
+        assert_eq!(a.list("refs/").unwrap(), vec![("refs/heads/main".to_string(), x)]);
+        assert!(b.list("refs/").unwrap().is_empty());
+
+        b.write_symbolic(HEAD, "refs/heads/dev").unwrap();
+        b.update(&[RefUpdate {
+            name: "refs/heads/dev".to_string(),
+            old: ObjectId::ZERO,
+            new: y,
+        }])
+        .unwrap();
+        assert_eq!(b.head_target().unwrap().as_deref(), Some("refs/heads/dev"));
+        assert_eq!(b.resolve(HEAD).unwrap(), Some(y));
+        assert_eq!(a.read("refs/heads/dev").unwrap(), None);
+
+        // 指向命名空间之外的符号引用视为损坏
+        root.write_symbolic("refs/namespaces/a/refs/heads/escape", "refs/heads/main").unwrap();
+        assert!(a.read("refs/heads/escape").is_err());
+        assert!(NamespacedRefStore::new(root.clone(), "a/b").is_err());
+        assert!(NamespacedRefStore::new(root, "..").is_err());
+    }
+
+    /// 测试读取 packed-refs 中的引用
+    #[test]
+    fn test_packed_refs() {
+        let dir = tempfile::tempdir().unwrap();
+        let id = ObjectId::hash_object(crate::object::ObjectType::Blob, b"x");
+        std::fs::write(
+            dir.path().join("packed-refs"),
+            format!("# pack-refs with: peeled\n{} refs/heads/packed\n^{}\n", id, id),
+        )
+        .unwrap();
+        let store = FileRefStore::new(dir.path());
+        assert_eq!(store.resolve("refs/heads/packed").unwrap(), Some(id));
+        assert_eq!(store.list("refs/heads/").unwrap().len(), 1);
+
+        store.delete("refs/heads/packed").unwrap();
+        assert!(store.list("refs/").unwrap().is_empty());
+    }
+}
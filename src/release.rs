@@ -0,0 +1,375 @@
+//! 发布与受保护的标签
+//!
+//! `mono release create v1.2.3` 在修订上创建附注标签 `refs/tags/v1.2.3`，同时写入发布记录
+//! `refs/releases/v1.2.3`：一个没有父提交的提交，树中的 `release.json` 保存标题、说明、所属项目与附件清单，
+//! `artifacts/` 目录保存附件。附件与发布记录都是对象存储中的普通对象，由引用保持可达，
+//! 随复制同步到镜像，不会被垃圾回收。发布可以属于仓库中的一个项目目录（如 `services/api`），
+//! 通过 `GET /api/v1/releases?path=` 按目录列出（见 [`crate::server::api`]）。
+//!
+//! 发布的标签创建后不能移动或删除，`[release] protected_tags` 中的标签同样如此；`refs/releases/`
+//! 只能由 `mono release create` 写入。这些限制作为推送策略（见 [`crate::policy`]）在推送时检查。
+
+use std::collections::BTreeSet;
+
+use serde::{Deserialize, Serialize};
+
+use crate::audit::{self, AuditAction, AuditLog};
+use crate::common::errors::{MonoError, PolicyViolation};
+use crate::common::MonoResult;
+use crate::object::commit::{Commit, Signature};
+use crate::object::tag::Tag;
+use crate::object::tree::{FileMode, Tree, TreeEntry};
+use crate::object::{ObjectId, ObjectType};
+use crate::refs::{self, RefUpdate};
+use crate::repo::Repository;
+use crate::rewrite::normalize_prefix;
+
+/// 发布记录的引用前缀
+pub const RELEASES_PREFIX: &str = "refs/releases/";
+
+/// 发布记录树中的元数据文件
+const RELEASE_FILE: &str = "release.json";
+
+/// 发布记录树中的附件目录
+const ARTIFACTS_DIR: &str = "artifacts";
+
+/// 推送策略中的规则名
+const RULE: &str = "protected-tag";
+
+/// 发布的一个附件
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Artifact {
+    pub name: String,
+    /// 附件内容的 blob
+    pub id: ObjectId,
+    pub size: u64,
+}
+
+/// 一个发布
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Release {
+    /// 标签名，不含 `refs/tags/`
+    pub tag: String,
+    /// 发布的提交
+    pub commit: ObjectId,
+    /// 发布所属的项目目录，整个仓库的发布为 None
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub project: Option<String>,
+    pub title: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub notes: String,
+    /// 创建者，格式为 `名字 <邮箱>`
+    pub author: String,
+    pub created_at: i64,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub artifacts: Vec<Artifact>,
+}
+
+impl Release {
+    /// 是否属于 `path` 目录或其下的项目，空路径匹配全部发布
+    pub fn within(&self, path: &str) -> bool {
+        let path = path.trim_matches('/');
+        if path.is_empty() {
+            return true;
+        }
+        self.project
+            .as_deref()
+            .is_some_and(|project| project == path || project.strip_prefix(path).is_some_and(|rest| rest.starts_with('/')))
+    }
+}
+
+/// 新建发布的参数
+#[derive(Debug, Clone, Default)]
+pub struct NewRelease {
+    pub tag: String,
+    /// 发布的修订
+    pub rev: String,
+    pub project: Option<String>,
+    /// 省略时使用标签名
+    pub title: Option<String>,
+    pub notes: String,
+    /// 附件名与内容
+    pub artifacts: Vec<(String, Vec<u8>)>,
+}
+
+/// 仓库的发布
+pub struct Releases<'a> {
+    repo: &'a Repository,
+}
+
+impl<'a> Releases<'a> {
+    pub fn new(repo: &'a Repository) -> Releases<'a> {
+        Releases { repo }
+    }
+
+    /// 创建标签与发布记录，二者在一次原子更新中写入
+    pub fn create(&self, release: NewRelease, tagger: &Signature) -> MonoResult<Release> {
+        let tag_ref = format!("{}{}", refs::TAGS_PREFIX, release.tag);
+        let release_ref = format!("{}{}", RELEASES_PREFIX, release.tag);
+        if release.tag.is_empty() || !refs::check_ref_format(&tag_ref) {
+            return Err(MonoError::usage(format!("invalid tag name: {}", release.tag)));
+        }
+        let store = self.repo.refs();
+        if store.resolve(&tag_ref)?.is_some() || store.resolve(&release_ref)?.is_some() {
+            return Err(MonoError::usage(format!("tag {} already exists", release.tag)));
+        }
+        let (commit, object_type) = self.repo.peel(&self.repo.resolve_rev(&release.rev)?)?;
+        if object_type != ObjectType::Commit {
+            return Err(MonoError::usage(format!("{} is not a commit", release.rev)));
+        }
+        let project = match &release.project {
+            Some(project) => {
+                let project = normalize_prefix(project)?;
+                let tree = self.repo.read_commit(&commit)?.tree;
+                match self.repo.find_path(&tree, &project)? {
+                    Some(entry) if entry.mode == FileMode::TREE => Some(project),
+                    _ => return Err(MonoError::not_found(format!("directory {} in {}", project, release.rev))),
+                }
+            }
+            None => None,
+        };
+        let artifacts = self.write_artifacts(&release.artifacts)?;
+
+        let title = release.title.clone().unwrap_or_else(|| release.tag.clone());
+        let message = match release.notes.trim() {
+            "" => format!("{}\n", title),
+            notes => format!("{}\n\n{}\n", title, notes),
+        };
+        let tag = Tag {
+            object: commit,
+            object_type: ObjectType::Commit,
+            name: release.tag.clone(),
+            tagger: Some(tagger.clone()),
+            message,
+        };
+        let tag_id = self.repo.write_object(ObjectType::Tag, &tag.encode())?;
+        let record = Release {
+            tag: release.tag.clone(),
+            commit,
+            project,
+            title,
+            notes: release.notes.trim().to_string(),
+            author: format!("{} <{}>", tagger.name, tagger.email),
+            created_at: tagger.timestamp,
+            artifacts,
+        };
+        let record_id = self.write_record(&record, tagger)?;
+        let updates = [
+            RefUpdate { name: tag_ref, old: ObjectId::ZERO, new: tag_id },
+            RefUpdate { name: release_ref, old: ObjectId::ZERO, new: record_id },
+        ];
+        let actor = audit::local_actor();
+        self.repo
+            .with_reflog_identity(&actor, &format!("release {}", record.tag))
+            .refs()
+            .update(&updates)?;
+        let now = chrono::Utc::now().timestamp();
+        AuditLog::new(self.repo).record_ref_updates(self.repo, &actor, AuditAction::RefUpdate, &updates, now);
+        Ok(record)
+    }
+
+    /// 附件写入对象存储
+    fn write_artifacts(&self, artifacts: &[(String, Vec<u8>)]) -> MonoResult<Vec<Artifact>> {
+        let max_size = self.repo.config().release.max_artifact_size;
+        let mut names = BTreeSet::new();
+        let mut out = Vec::new();
+        for (name, data) in artifacts {
+            if name.is_empty() || name.contains('/') || name == "." || name == ".." {
+                return Err(MonoError::usage(format!("invalid artifact name: {}", name)));
+            }
+            if !names.insert(name.as_str()) {
+                return Err(MonoError::usage(format!("duplicate artifact: {}", name)));
+            }
+            if data.len() as u64 > max_size {
+                return Err(MonoError::usage(format!("artifact {} is larger than release.max_artifact_size ({} bytes)", name, max_size)));
+            }
+            let id = self.repo.write_object(ObjectType::Blob, data)?;
+            out.push(Artifact { name: name.clone(), id, size: data.len() as u64 });
+        }
+        Ok(out)
+    }
+
+    /// 写入发布记录的提交
+    fn write_record(&self, record: &Release, tagger: &Signature) -> MonoResult<ObjectId> {
+        let json = serde_json::to_vec_pretty(record).map_err(|e| MonoError::storage(e.to_string()))?;
+        let mut tree = Tree::default();
+        tree.entries.push(TreeEntry::new(FileMode::BLOB, RELEASE_FILE.to_string(), self.repo.write_object(ObjectType::Blob, &json)?));
+        if !record.artifacts.is_empty() {
+            let mut artifacts = Tree::default();
+            for artifact in &record.artifacts {
+                artifacts.entries.push(TreeEntry::new(FileMode::BLOB, artifact.name.clone(), artifact.id));
+            }
+            artifacts.sort();
+            let id = self.repo.write_object(ObjectType::Tree, &artifacts.encode())?;
+            tree.entries.push(TreeEntry::new(FileMode::TREE, ARTIFACTS_DIR.to_string(), id));
+        }
+        tree.sort();
+        let commit = Commit {
+            tree: self.repo.write_object(ObjectType::Tree, &tree.encode())?,
+            parents: Vec::new(),
+            author: tagger.clone(),
+            committer: tagger.clone(),
+            extra_headers: Vec::new(),
+            message: format!("Release {}\n", record.tag),
+        };
+        self.repo.write_object(ObjectType::Commit, &commit.encode())
+    }
+
+    /// 读取发布
+    pub fn get(&self, tag: &str) -> MonoResult<Release> {
+        let id = self
+            .repo
+            .refs()
+            .resolve(&format!("{}{}", RELEASES_PREFIX, tag))?
+            .ok_or_else(|| MonoError::not_found(format!("release {}", tag)))?;
+        self.read(&id)
+    }
+
+    fn read(&self, id: &ObjectId) -> MonoResult<Release> {
+        let tree = self.repo.read_commit(id)?.tree;
+        let entry = self
+            .repo
+            .find_path(&tree, RELEASE_FILE)?
+            .ok_or_else(|| MonoError::storage(format!("corrupt release record {}: missing {}", id, RELEASE_FILE)))?;
+        serde_json::from_slice(&self.repo.read_object(&entry.id)?.data)
+            .map_err(|e| MonoError::storage(format!("corrupt release record {}: {}", id, e)))
+    }
+
+    /// 属于 `path` 目录或其下项目的发布，按创建时间从新到旧排列
+    pub fn list(&self, path: &str) -> MonoResult<Vec<Release>> {
+        let mut releases = Vec::new();
+        for (_, id) in self.repo.refs().list(RELEASES_PREFIX)? {
+            let release = self.read(&id)?;
+            if release.within(path) {
+                releases.push(release);
+            }
+        }
+        releases.sort_by(|a, b| b.created_at.cmp(&a.created_at).then_with(|| a.tag.cmp(&b.tag)));
+        Ok(releases)
+    }
+
+    /// 读取附件内容
+    pub fn artifact(&self, tag: &str, name: &str) -> MonoResult<Vec<u8>> {
+        let release = self.get(tag)?;
+        let artifact = release
+            .artifacts
+            .iter()
+            .find(|artifact| artifact.name == name)
+            .ok_or_else(|| MonoError::not_found(format!("artifact {} in release {}", name, tag)))?;
+        Ok(self.repo.read_object(&artifact.id)?.data)
+    }
+}
+
+/// 标签是否受 `protected_tags` 中的模式保护
+fn is_protected(patterns: &[String], refname: &str) -> bool {
+    patterns.iter().any(|pattern| match pattern.strip_suffix('*') {
+        Some(prefix) => refname.starts_with(prefix),
+        None if pattern.ends_with('/') => refname.starts_with(pattern.as_str()),
+        None => refname == pattern,
+    })
+}
+
+/// 检查一条引用更新，移动或删除受保护的标签、推送发布记录时返回 [`MonoError::policy`]
+pub fn check_update(repo: &Repository, protected_tags: &[String], update: &RefUpdate) -> MonoResult<()> {
+    let violation = |reason: String| {
+        Err(MonoError::policy(PolicyViolation { rule: RULE.to_string(), refname: update.name.clone(), paths: Vec::new(), reason }))
+    };
+    if update.name.starts_with(RELEASES_PREFIX) {
+        return violation("release records are created by mono release create and cannot be pushed".to_string());
+    }
+    let Some(tag) = update.name.strip_prefix(refs::TAGS_PREFIX) else {
+        return Ok(());
+    };
+    if update.old.is_zero() {
+        return Ok(());
+    }
+    let released = repo.refs().resolve(&format!("{}{}", RELEASES_PREFIX, tag))?.is_some();
+    if released || is_protected(protected_tags, &update.name) {
+        let kind = if released { "released tag" } else { "protected tag" };
+        return violation(format!("{} {} cannot be moved or deleted", kind, tag));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::errors::MonoErrorKind;
+    use crate::test_utils::{commit_files, init_repo};
+
+    fn tagger() -> Signature {
+        Signature::new("Release Bot", "release@example.com", 1_700_000_000)
+    }
+
+    /// 测试创建发布：标签、发布记录与附件，以及按项目目录列出
+    #[test]
+    fn test_create() {
+        let (_dir, repo) = init_repo();
+        let commit = commit_files(&repo, &[("services/api/main.rs", b"fn main() {}"), ("libs/core/lib.rs", b"")], &[], "init");
+        repo.refs()
+            .update(&[RefUpdate { name: "refs/heads/main".to_string(), old: ObjectId::ZERO, new: commit }])
+            .unwrap();
+        let releases = Releases::new(&repo);
+        let new = NewRelease {
+            tag: "api/v1.2.3".to_string(),
+            rev: "main".to_string(),
+            project: Some("//services/api/".to_string()),
+            notes: "Fixes a crash.\n".to_string(),
+            artifacts: vec![("api-linux-amd64".to_string(), b"\x7fELF".to_vec())],
+            ..NewRelease::default()
+        };
+        let release = releases.create(new.clone(), &tagger()).unwrap();
+        assert_eq!(release.project.as_deref(), Some("services/api"));
+        assert_eq!((release.title.as_str(), release.notes.as_str()), ("api/v1.2.3", "Fixes a crash."));
+        assert_eq!(releases.get("api/v1.2.3").unwrap(), release);
+        assert_eq!(releases.artifact("api/v1.2.3", "api-linux-amd64").unwrap(), b"\x7fELF");
+
+        let tag_id = repo.refs().resolve("refs/tags/api/v1.2.3").unwrap().unwrap();
+        let tag = Tag::parse(&repo.read_object(&tag_id).unwrap().data).unwrap();
+        assert_eq!((tag.object, tag.message.as_str()), (commit, "api/v1.2.3\n\nFixes a crash.\n"));
+        // 附件由发布记录保持可达
+        let reachable = crate::gc::reachable_objects(&repo, &crate::gc::roots(&repo, 0).unwrap()).unwrap();
+        assert!(reachable.contains(&release.artifacts[0].id));
+
+        let err = releases.create(new, &tagger()).unwrap_err();
+        assert!(err.to_string().contains("already exists"), "{}", err);
+        let whole = NewRelease { tag: "v2".to_string(), rev: "main".to_string(), ..NewRelease::default() };
+        releases.create(whole, &tagger()).unwrap();
+        let tags = |path: &str| releases.list(path).unwrap().into_iter().map(|r| r.tag).collect::<Vec<_>>();
+        assert_eq!(tags(""), ["api/v1.2.3", "v2"]);
+        assert_eq!(tags("services"), ["api/v1.2.3"]);
+        assert!(tags("libs/core").is_empty());
+
+        let missing = NewRelease { tag: "v3".to_string(), rev: "main".to_string(), project: Some("services/web".to_string()), ..NewRelease::default() };
+        assert!(releases.create(missing, &tagger()).is_err());
+        let bad = NewRelease { tag: "v4".to_string(), rev: "main".to_string(), artifacts: vec![("a/b".to_string(), Vec::new())], ..NewRelease::default() };
+        assert!(releases.create(bad, &tagger()).is_err());
+        assert!(repo.refs().resolve("refs/tags/v4").unwrap().is_none());
+    }
+
+    /// 测试发布的标签与配置保护的标签不能移动或删除，发布记录不能推送
+    #[test]
+    fn test_check_update() {
+        let (_dir, repo) = init_repo();
+        let commit = commit_files(&repo, &[("a", b"1")], &[], "init");
+        let other = commit_files(&repo, &[("a", b"2")], &[commit], "second");
+        let release = NewRelease { tag: "v1".to_string(), rev: commit.to_hex(), ..NewRelease::default() };
+        Releases::new(&repo).create(release, &tagger()).unwrap();
+        let tag = repo.refs().resolve("refs/tags/v1").unwrap().unwrap();
+
+        let update = |name: &str, old: ObjectId, new: ObjectId| RefUpdate { name: name.to_string(), old, new };
+        let reason = |update: RefUpdate| match check_update(&repo, &["refs/tags/stable-*".to_string()], &update) {
+            Ok(()) => None,
+            Err(e) => match e.kind() {
+                MonoErrorKind::Policy(violation) => Some(violation.reason.clone()),
+                _ => panic!("unexpected error: {}", e),
+            },
+        };
+        assert_eq!(reason(update("refs/tags/v1", tag, ObjectId::ZERO)).as_deref(), Some("released tag v1 cannot be moved or deleted"));
+        assert_eq!(reason(update("refs/tags/stable-1", commit, other)).as_deref(), Some("protected tag stable-1 cannot be moved or deleted"));
+        assert!(reason(update("refs/releases/v9", ObjectId::ZERO, commit)).is_some());
+        assert_eq!(reason(update("refs/tags/stable-2", ObjectId::ZERO, commit)), None);
+        assert_eq!(reason(update("refs/tags/nightly", commit, other)), None);
+        assert_eq!(reason(update("refs/heads/main", commit, other)), None);
+    }
+}
@@ -0,0 +1,413 @@
+//! 向只读镜像复制引用与对象
+//!
+//! 主节点在 `[[replication.mirrors]]` 中列出镜像：`push` 方式由主节点把与镜像不同的引用连同所需的
+//! 对象推送过去；`pull` 方式的镜像在自己的配置中以 `[replication] upstream` 指向主节点，定期拉取。
+//! 两种方式都把镜像的引用置为与主节点完全相同，包括强制更新与删除：
+//!
+//! ```toml
+//! [replication]
+//! interval_secs = 10
+//!
+//! [[replication.mirrors]]
+//! name = "eu"
+//! url = "/srv/mirrors/eu/monorepo"
+//! ```
+//!
+//! `mono serve` 按 `interval_secs` 在后台复制，`mono mirror sync` 立即复制一次。配置了 `upstream`
+//! 的仓库是只读镜像，服务端拒绝推送与创建提交，fetch 流量可以按地域分散到各个镜像。
+//!
+//! 每次复制的结果保存在 `.mono/replication/<镜像名>.json`，从上游拉取的记录名为 `upstream`。
+//! `mono mirror status` 比较两端的引用报告延迟：落后的引用数，以及最早一次尚未复制的修改距今的秒数。
+//! 主节点从自己的引用日志得到修改时间；镜像看不到上游的日志，以上次成功复制的时间代替。
+//! 延迟同时导出为 `mono_replication_lag_seconds` 与 `mono_replication_refs_behind` 指标。
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::audit::{self, AuditAction, AuditLog};
+use crate::common::config::{MirrorConfig, ReplicationMode};
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::metrics;
+use crate::object::filter::ObjectFilter;
+use crate::object::ObjectId;
+use crate::queue::LockFile;
+use crate::refs::RefUpdate;
+use crate::repo::Repository;
+use crate::transport;
+
+/// 复制记录目录，相对于 `.mono`
+pub const REPLICATION_DIR: &str = "replication";
+/// 从上游拉取的复制记录名
+pub const UPSTREAM: &str = "upstream";
+/// 复制期间持有的锁
+const SYNC_LOCK_FILE: &str = "sync.lock";
+
+/// 最近一次复制的结果
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct SyncRecord {
+    /// 最近一次复制的时间（Unix 时间戳）
+    pub attempted_at: i64,
+    /// 最近一次成功复制的时间
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub synced_at: Option<i64>,
+    /// 最近一次成功复制更新的引用数
+    #[serde(default)]
+    pub refs: usize,
+    /// 最近一次成功复制写入的对象数
+    #[serde(default)]
+    pub objects: usize,
+    /// 最近一次复制失败的原因，成功后清除
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// 一个镜像的复制状态
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct MirrorStatus {
+    pub name: String,
+    pub url: String,
+    pub mode: ReplicationMode,
+    /// 与主节点不同的引用数，无法读取镜像引用时为 None
+    pub refs_behind: Option<usize>,
+    /// 最早一次尚未复制的修改距今的秒数，无法得知时为 None
+    pub lag_secs: Option<i64>,
+    /// 最近一次复制的结果
+    pub last: Option<SyncRecord>,
+    /// 无法读取镜像引用的原因
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// 把 `target` 的引用改为与 `source` 相同所需的更新，`source` 中没有的引用被删除
+pub fn diff_refs(source: &[(String, ObjectId)], target: &[(String, ObjectId)], zero: ObjectId) -> Vec<RefUpdate> {
+    let current: HashMap<&str, ObjectId> = target.iter().map(|(name, id)| (name.as_str(), *id)).collect();
+    let mut updates: Vec<RefUpdate> = source
+        .iter()
+        .filter(|(name, id)| current.get(name.as_str()) != Some(id))
+        .map(|(name, id)| RefUpdate {
+            name: name.clone(),
+            old: current.get(name.as_str()).copied().unwrap_or(zero),
+            new: *id,
+        })
+        .collect();
+    let wanted: HashMap<&str, ObjectId> = source.iter().map(|(name, id)| (name.as_str(), *id)).collect();
+    updates.extend(
+        target
+            .iter()
+            .filter(|(name, _)| !wanted.contains_key(name.as_str()))
+            .map(|(name, id)| RefUpdate {
+                name: name.clone(),
+                old: *id,
+                new: zero,
+            }),
+    );
+    updates
+}
+
+/// 仓库是只读镜像时返回拒绝修改引用的原因
+pub fn read_only_reason(repo: &Repository) -> Option<String> {
+    repo.config()
+        .replication
+        .upstream
+        .as_ref()
+        .map(|upstream| format!("read-only mirror of {}", upstream))
+}
+
+/// 仓库的复制任务
+pub struct Replication<'a> {
+    repo: &'a Repository,
+    dir: PathBuf,
+}
+
+impl<'a> Replication<'a> {
+    pub fn new(repo: &'a Repository) -> Replication<'a> {
+        Replication {
+            repo,
+            dir: repo.mono_dir().join(REPLICATION_DIR),
+        }
+    }
+
+    fn record_path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", name))
+    }
+
+    /// 最近一次复制的结果，从未复制过时返回 None
+    pub fn record(&self, name: &str) -> MonoResult<Option<SyncRecord>> {
+        let path = self.record_path(name);
+        let data = match std::fs::read(&path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        serde_json::from_slice(&data)
+            .map(Some)
+            .map_err(|e| MonoError::storage(format!("corrupt replication record {}: {}", path.display(), e)))
+    }
+
+    fn save_record(&self, name: &str, record: &SyncRecord) -> MonoResult<()> {
+        std::fs::create_dir_all(&self.dir)?;
+        let data = serde_json::to_vec_pretty(record).map_err(|e| MonoError::storage(e.to_string()))?;
+        let path = self.record_path(name);
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, data)?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    /// 复制到 `push` 方式的镜像并从上游拉取；指定 `only` 时只复制该镜像（上游为 `upstream`）
+    ///
+    /// 返回每个镜像的结果，单个镜像失败不影响其他镜像。同一时间只允许一个进程复制，
+    /// 其他进程正在复制时返回 `Unavailable` 错误。
+    pub fn sync(&self, only: Option<&str>, now: i64) -> MonoResult<Vec<(String, MonoResult<SyncRecord>)>> {
+        let config = &self.repo.config().replication;
+        let mirrors: Vec<&MirrorConfig> = config
+            .mirrors
+            .iter()
+            .filter(|mirror| mirror.mode == ReplicationMode::Push)
+            .filter(|mirror| only.is_none_or(|name| mirror.name == name))
+            .collect();
+        let pull = config.upstream.is_some() && only.is_none_or(|name| name == UPSTREAM);
+        if let Some(name) = only.filter(|_| mirrors.is_empty() && !pull) {
+            return Err(MonoError::not_found(format!("push mirror or upstream {}", name)));
+        }
+        std::fs::create_dir_all(&self.dir)?;
+        let _lock = LockFile::acquire(self.dir.join(SYNC_LOCK_FILE))?;
+        let mut results = Vec::new();
+        for mirror in mirrors {
+            let result = self.attempt(&mirror.name, now, || self.push(&mirror.url));
+            results.push((mirror.name.clone(), result));
+        }
+        if pull {
+            results.push((UPSTREAM.to_string(), self.attempt(UPSTREAM, now, || self.pull())));
+        }
+        Ok(results)
+    }
+
+    /// 执行一次复制并记录结果
+    fn attempt(
+        &self,
+        name: &str,
+        now: i64,
+        sync: impl FnOnce() -> MonoResult<(usize, usize)>,
+    ) -> MonoResult<SyncRecord> {
+        let mut record = self.record(name)?.unwrap_or_default();
+        record.attempted_at = now;
+        let result = sync();
+        match &result {
+            Ok((refs, objects)) => {
+                record.synced_at = Some(now);
+                record.refs = *refs;
+                record.objects = *objects;
+                record.error = None;
+            }
+            Err(e) => {
+                tracing::warn!(mirror = name, error = %e, "replication failed");
+                record.error = Some(e.to_string());
+            }
+        }
+        self.save_record(name, &record)?;
+        result.map(|_| record)
+    }
+
+    /// 把本地与镜像不同的引用及所需对象推送到镜像，返回更新的引用数与写入的对象数
+    fn push(&self, url: &str) -> MonoResult<(usize, usize)> {
+        let transport = transport::open(url)?;
+        let local = self.repo.refs().list("refs/")?;
+        let updates = diff_refs(&local, &transport.list_refs()?.refs, self.repo.object_format().zero());
+        if updates.is_empty() {
+            return Ok((0, 0));
+        }
+        let stats = transport.push(&updates, self.repo.objects())?;
+        tracing::info!(url, refs = updates.len(), objects = stats.objects, "pushed to mirror");
+        Ok((updates.len(), stats.objects))
+    }
+
+    /// 从上游获取不同的引用及所需对象并更新本地引用，返回更新的引用数与写入的对象数
+    fn pull(&self) -> MonoResult<(usize, usize)> {
+        let upstream = self
+            .repo
+            .config()
+            .replication
+            .upstream
+            .as_deref()
+            .ok_or_else(|| MonoError::usage("no upstream configured; set [replication] upstream"))?;
+        let transport = transport::open(upstream)?;
+        let remote = transport.list_refs()?;
+        let local = self.repo.refs().list("refs/")?;
+        let updates = diff_refs(&remote.refs, &local, self.repo.object_format().zero());
+        let store = self.repo.objects();
+        let mut wants = Vec::new();
+        for update in updates.iter().filter(|update| !update.is_delete()) {
+            if !store.contains(&update.new)? {
+                wants.push(update.new);
+            }
+        }
+        let haves: Vec<ObjectId> = local.iter().map(|(_, id)| *id).collect();
+        let objects = if wants.is_empty() {
+            0
+        } else {
+            transport.fetch(&wants, &haves, &ObjectFilter::None, store)?.objects
+        };
+        let actor = audit::local_actor();
+        let logged = self.repo.with_reflog_identity(&actor, &format!("replicate from {}", upstream));
+        if !updates.is_empty() {
+            logged.refs().update(&updates)?;
+            let now = chrono::Utc::now().timestamp();
+            AuditLog::new(self.repo).record_ref_updates(self.repo, &actor, AuditAction::RefUpdate, &updates, now);
+        }
+        if let Some(head) = remote.head {
+            if self.repo.refs().head_target()?.as_ref() != Some(&head) {
+                logged.refs().write_symbolic("HEAD", &head)?;
+            }
+        }
+        tracing::info!(upstream, refs = updates.len(), objects, "pulled from upstream");
+        Ok((updates.len(), objects))
+    }
+
+    /// 全部镜像与上游的复制状态，同时更新延迟指标
+    pub fn status(&self, now: i64) -> MonoResult<Vec<MirrorStatus>> {
+        let config = &self.repo.config().replication;
+        let zero = self.repo.object_format().zero();
+        let local = self.repo.refs().list("refs/")?;
+        let mut statuses = Vec::new();
+        for mirror in &config.mirrors {
+            let last = self.record(&mirror.name)?;
+            let remote = transport::open(&mirror.url).and_then(|transport| transport.list_refs());
+            let mut status = MirrorStatus {
+                name: mirror.name.clone(),
+                url: mirror.url.clone(),
+                mode: mirror.mode,
+                refs_behind: None,
+                lag_secs: None,
+                last,
+                error: None,
+            };
+            match remote {
+                Ok(remote) => {
+                    let behind = diff_refs(&local, &remote.refs, zero);
+                    status.refs_behind = Some(behind.len());
+                    status.lag_secs = self.lag(&behind, status.last.as_ref(), now, true)?;
+                }
+                Err(e) => status.error = Some(e.to_string()),
+            }
+            statuses.push(status);
+        }
+        if let Some(upstream) = &config.upstream {
+            let last = self.record(UPSTREAM)?;
+            let remote = transport::open(upstream).and_then(|transport| transport.list_refs());
+            let mut status = MirrorStatus {
+                name: UPSTREAM.to_string(),
+                url: upstream.clone(),
+                mode: ReplicationMode::Pull,
+                refs_behind: None,
+                lag_secs: None,
+                last,
+                error: None,
+            };
+            match remote {
+                Ok(remote) => {
+                    let behind = diff_refs(&remote.refs, &local, zero);
+                    status.refs_behind = Some(behind.len());
+                    status.lag_secs = self.lag(&behind, status.last.as_ref(), now, false)?;
+                }
+                Err(e) => status.error = Some(e.to_string()),
+            }
+            statuses.push(status);
+        }
+        for status in &statuses {
+            if let (Some(behind), Some(lag)) = (status.refs_behind, status.lag_secs) {
+                metrics::replication_status(&status.name, lag, behind as u64);
+            }
+        }
+        Ok(statuses)
+    }
+
+    /// 最早一次尚未复制的修改距今的秒数
+    ///
+    /// `reflog` 为真时每个落后的引用取本地引用日志中最近一次修改的时间，没有日志的引用与
+    /// 拉取方向都以上次成功复制的时间代替；从未成功复制过则无法得知。
+    fn lag(&self, behind: &[RefUpdate], last: Option<&SyncRecord>, now: i64, reflog: bool) -> MonoResult<Option<i64>> {
+        if behind.is_empty() {
+            return Ok(Some(0));
+        }
+        let synced_at = last.and_then(|record| record.synced_at);
+        let mut oldest: Option<i64> = None;
+        for update in behind {
+            let changed_at = if reflog {
+                self.repo.reflog(&update.name)?.last().map(|entry| entry.time).or(synced_at)
+            } else {
+                synced_at
+            };
+            let Some(changed_at) = changed_at else {
+                return Ok(None);
+            };
+            oldest = Some(oldest.map_or(changed_at, |oldest| oldest.min(changed_at)));
+        }
+        Ok(oldest.map(|oldest| (now - oldest).max(0)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::config::ReplicationConfig;
+    use crate::repo::InitOptions;
+    use crate::test_utils::{commit_files, init_repo};
+
+    /// 测试推送与拉取两种方式把镜像的引用置为与主节点相同，落后时报告延迟，镜像只读
+    #[test]
+    fn test_replication() {
+        let (dir, mut primary) = init_repo();
+        let push_root = dir.path().join("push-mirror");
+        let pull_root = dir.path().join("pull-mirror");
+        let push_mirror = Repository::init(&push_root, &InitOptions::default()).unwrap();
+        let mut pull_mirror = Repository::init(&pull_root, &InitOptions::default()).unwrap();
+        primary.config_mut().replication = ReplicationConfig {
+            mirrors: vec![MirrorConfig {
+                name: "eu".to_string(),
+                url: push_root.to_string_lossy().into_owned(),
+                mode: ReplicationMode::Push,
+            }],
+            ..Default::default()
+        };
+        pull_mirror.config_mut().replication.upstream = Some(primary.root().to_string_lossy().into_owned());
+        assert!(read_only_reason(&pull_mirror).is_some());
+        assert!(read_only_reason(&primary).is_none());
+
+        let first = commit_files(&primary, &[("a.txt", b"one")], &[], "first");
+        primary.refs().write("refs/heads/main", &first).unwrap();
+        primary.refs().write("refs/heads/old", &first).unwrap();
+        let status = Replication::new(&primary).status(1_000).unwrap();
+        assert_eq!(status[0].refs_behind, Some(2));
+        assert!(status[0].lag_secs.is_some());
+
+        let results = Replication::new(&primary).sync(None, 1_000).unwrap();
+        assert_eq!(results[0].1.as_ref().unwrap().refs, 2);
+        assert_eq!(push_mirror.refs().list("refs/").unwrap(), primary.refs().list("refs/").unwrap());
+        let status = Replication::new(&primary).status(1_000).unwrap();
+        assert_eq!((status[0].refs_behind, status[0].lag_secs), (Some(0), Some(0)));
+
+        // 强制更新与删除同样复制到镜像
+        let second = commit_files(&primary, &[("a.txt", b"two")], &[], "second");
+        primary.refs().write("refs/heads/main", &second).unwrap();
+        primary.refs().delete("refs/heads/old").unwrap();
+        let results = Replication::new(&primary).sync(Some("eu"), 2_000).unwrap();
+        assert_eq!(results[0].1.as_ref().unwrap().refs, 2);
+        assert_eq!(push_mirror.refs().list("refs/").unwrap(), vec![("refs/heads/main".to_string(), second)]);
+        assert!(push_mirror.read_object(&second).is_ok());
+        assert!(Replication::new(&primary).sync(Some("missing"), 2_000).is_err());
+
+        let results = Replication::new(&pull_mirror).sync(None, 3_000).unwrap();
+        assert_eq!(results[0].0, UPSTREAM);
+        assert_eq!(pull_mirror.refs().list("refs/").unwrap(), vec![("refs/heads/main".to_string(), second)]);
+        let record = Replication::new(&pull_mirror).record(UPSTREAM).unwrap().unwrap();
+        assert_eq!((record.synced_at, record.refs), (Some(3_000), 1));
+        let third = commit_files(&primary, &[("a.txt", b"three")], &[second], "third");
+        primary.refs().write("refs/heads/main", &third).unwrap();
+        let status = Replication::new(&pull_mirror).status(3_500).unwrap();
+        assert_eq!((status[0].refs_behind, status[0].lag_secs), (Some(1), Some(500)));
+    }
+}
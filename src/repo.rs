@@ -0,0 +1,853 @@
+//! 仓库布局模块
+//!
+//! 一个 MonoEngine 工作区在根目录下包含 `.mono` 目录，其布局如下：
+//!
+//! ```text
+//! .mono/
+//! ├── HEAD            当前分支，格式与 git 相同
+//! ├── mono.toml       仓库配置
+//! ├── workspace.toml  工作区清单
+//! ├── shallow         浅克隆的边界提交（仅浅仓库）
+//! ├── objects/        对象存储
+//! │   └── pack/
+//! ├── logs/           引用日志，见 [`crate::reflog`]
+//! ├── journal/        未完成的多引用更新，见 [`crate::journal`]
+//! ├── locks/          本机锁文件，见 [`crate::lock`]
+//! ├── blame/          按提交缓存的 blame 结果，可随时删除，见 [`crate::blame`]
+//! ├── search/         代码搜索索引，见 [`crate::search`]
+//! ├── compose/        虚拟单仓库各组件的同步状态，见 [`crate::compose`]
+//! ├── bundles/        供克隆先行下载的 bundle，见 [`crate::bundle`]
+//! ├── checks/         CI 对提交上报的检查状态，见 [`crate::checks`]
+//! ├── reviews/        代码评审的变更请求，见 [`crate::review`]
+//! ├── bisect/         服务端二分查找的记录与临时工作目录，见 [`crate::bisect`]
+//! ├── index           暂存区，格式与 git 相同，见 [`crate::index`]
+//! ├── sharedindex.*   拆分索引的共享部分（仅开启拆分索引时）
+//! ├── status-cache    `mono status` 的文件状态缓存，可随时删除，见 [`crate::status`]
+//! ├── offload.json    已上传到 S3/CDN 的 pack，见 [`crate::offload`]
+//! ├── quarantine/     推送中尚未校验的对象，见 [`crate::storage::quarantine`]
+//! └── refs/           引用数据库
+//!     ├── heads/
+//!     └── tags/
+//! ```
+//!
+//! `objects`、`refs` 与 `HEAD` 与 git 的布局保持兼容，便于互操作。
+
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::audit::{self, AuditLog};
+use crate::common::config::{self, Config, CoreConfig, LockBackend, LocksConfig, RepoConfig, StorageConfig};
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::lock::{self, LockGuard, LockService};
+use crate::object::commit::Commit;
+use crate::object::shallow::{self, ShallowUpdate};
+use crate::object::tag::Tag;
+use crate::object::tree::{FileMode, Tree, TreeEdit, TreeEntry};
+use crate::object::{ObjectFormat, ObjectId, ObjectType, RawObject};
+use crate::reflog;
+use crate::refs::{self, RefStore};
+use crate::storage::scheduler::{FetchScheduler, ScheduledStore};
+use crate::storage::{self, ObjectStore};
+use crate::transport;
+
+/// 仓库元数据目录名
+pub const MONO_DIR: &str = ".mono";
+/// 仓库配置文件名
+pub const CONFIG_FILE: &str = "mono.toml";
+/// 工作区清单文件名
+pub const WORKSPACE_FILE: &str = "workspace.toml";
+/// 指定引用命名空间的环境变量，与 git 的 `GIT_NAMESPACE` 相同
+pub const NAMESPACE_ENV: &str = "MONO_NAMESPACE";
+
+/// 是否为工作区中保留的路径组成部分
+///
+/// 与 git 拒绝检出 `.git` 一致，树中名为 `.mono` 或 `.git` 的条目不会被写入工作区，
+/// 以免覆盖仓库元数据。
+pub fn is_reserved_name(name: &str) -> bool {
+    name.eq_ignore_ascii_case(MONO_DIR) || name.eq_ignore_ascii_case(".git")
+}
+
+/// 工作区清单，记录工作区自身的元信息
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct WorkspaceManifest {
+    /// 工作区名称，默认为根目录名
+    pub name: String,
+    /// 稀疏检出状态，未启用时为 None
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sparse: Option<SparseState>,
+}
+
+/// 工作区的稀疏检出状态
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct SparseState {
+    /// 当前使用的稀疏检出配置名
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    /// 在配置之外额外包含的路径模式
+    #[serde(default)]
+    pub patterns: Vec<String>,
+}
+
+impl WorkspaceManifest {
+    /// 从 TOML 文件加载工作区清单
+    pub fn load(path: &Path) -> MonoResult<WorkspaceManifest> {
+        let content = std::fs::read_to_string(path)?;
+        toml::from_str(&content)
+            .map_err(|e| MonoError::config(format!("{}: {}", path.display(), e.message())))
+    }
+
+    /// 将工作区清单写入 TOML 文件
+    pub fn save(&self, path: &Path) -> MonoResult<()> {
+        let content = toml::to_string_pretty(self)
+            .map_err(|e| MonoError::config(e.to_string()))?;
+        std::fs::write(path, content)?;
+        Ok(())
+    }
+}
+
+/// 初始化仓库的选项
+#[derive(Debug, Clone)]
+pub struct InitOptions {
+    /// 对象存储配置
+    pub storage: StorageConfig,
+    /// 初始分支名
+    pub initial_branch: String,
+    /// 对象格式
+    pub object_format: ObjectFormat,
+    /// 协议层额外支持的兼容对象格式
+    pub compat_object_format: Option<ObjectFormat>,
+}
+
+impl Default for InitOptions {
+    fn default() -> Self {
+        InitOptions {
+            storage: StorageConfig::default(),
+            initial_branch: "main".to_string(),
+            object_format: ObjectFormat::default(),
+            compat_object_format: None,
+        }
+    }
+}
+
+/// 一个已初始化的 MonoEngine 仓库
+#[derive(Debug, Clone)]
+pub struct Repository {
+    root: PathBuf,
+    mono_dir: PathBuf,
+    config: RepoConfig,
+    objects: Arc<dyn ObjectStore>,
+    /// 限制对象存储后端的并发调用数，与 `objects` 共享名额
+    scheduler: FetchScheduler,
+    /// 底层引用数据库，不记录引用日志
+    ref_db: Arc<dyn RefStore>,
+    /// 在 `ref_db` 之上记录引用日志、按需限制在命名空间内的引用数据库
+    refs: Arc<dyn RefStore>,
+    /// 浅提交，读取时当作没有父提交
+    shallow: HashSet<ObjectId>,
+    /// 引用所在的命名空间，None 表示不使用命名空间
+    namespace: Option<String>,
+    /// 与其他服务实例共享的锁服务
+    locks: Arc<dyn LockService>,
+}
+
+/// 使用共享锁服务时在锁下执行多引用更新；本机锁已由引用数据库自身保证
+fn locked_refs(ref_db: Arc<dyn RefStore>, locks: &Arc<dyn LockService>, config: &LocksConfig) -> Arc<dyn RefStore> {
+    match config.backend {
+        LockBackend::Local => ref_db,
+        _ => Arc::new(lock::LockedRefStore::new(ref_db, locks.clone(), config)),
+    }
+}
+
+/// 以本地用户记录引用日志的引用数据库
+fn logged_refs(ref_db: Arc<dyn RefStore>, mono_dir: &Path) -> Arc<dyn RefStore> {
+    let log = reflog::Reflog::new(mono_dir);
+    Arc::new(reflog::LoggedRefStore::new(ref_db, log, &audit::local_actor(), "update"))
+}
+
+/// [`Repository::edit_tree`] 在某一层目录中处理的修改：剩余的相对路径与写入的条目
+type PathEdit<'a> = (&'a str, Option<(FileMode, ObjectId)>);
+
+impl Repository {
+    /// 在 `root` 目录下创建仓库布局
+    ///
+    /// 目标目录不存在时会被创建；若已经是 MonoEngine 仓库则返回错误。
+    pub fn init(root: &Path, options: &InitOptions) -> MonoResult<Repository> {
+        if !refs::check_branch_name(&options.initial_branch) {
+            return Err(MonoError::usage(format!(
+                "invalid branch name: {}",
+                options.initial_branch
+            )));
+        }
+
+        let mono_dir = root.join(MONO_DIR);
+        if mono_dir.exists() {
+            return Err(MonoError::usage(format!(
+                "{} is already a mono workspace",
+                root.display()
+            )));
+        }
+        std::fs::create_dir_all(root)?;
+        let root = root.canonicalize()?;
+        let mono_dir = root.join(MONO_DIR);
+
+        if options.compat_object_format == Some(options.object_format) {
+            return Err(MonoError::usage(format!(
+                "compat object format must differ from the object format {}",
+                options.object_format
+            )));
+        }
+        // 先连接存储，避免配置有误时留下不完整的仓库布局
+        let scheduler = FetchScheduler::new(options.storage.max_concurrency);
+        let objects = storage::open(&options.storage, options.object_format, &mono_dir, &scheduler)?;
+        let ref_store = storage::open_refs(&options.storage, &mono_dir, objects.clone())?;
+
+        for dir in ["objects/pack", "refs/heads", "refs/tags"] {
+            std::fs::create_dir_all(mono_dir.join(dir))?;
+        }
+        // 共享的引用数据库中可能已有其他节点初始化的 HEAD
+        if ref_store.read(refs::HEAD)?.is_none() {
+            ref_store.write_symbolic(refs::HEAD, &format!("{}{}", refs::HEADS_PREFIX, options.initial_branch))?;
+        }
+
+        let config = RepoConfig {
+            core: CoreConfig {
+                object_format: options.object_format,
+                compat_object_format: options.compat_object_format,
+                ..Default::default()
+            },
+            storage: options.storage.clone(),
+            ..Default::default()
+        };
+        config.save(&mono_dir.join(CONFIG_FILE))?;
+
+        let manifest = WorkspaceManifest {
+            name: root
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_else(|| "mono".to_string()),
+            sparse: None,
+        };
+        manifest.save(&mono_dir.join(WORKSPACE_FILE))?;
+
+        Ok(Repository {
+            refs: logged_refs(ref_store.clone(), &mono_dir),
+            ref_db: ref_store,
+            objects,
+            scheduler,
+            locks: lock::open(&config, &mono_dir)?,
+            mono_dir,
+            root,
+            config,
+            shallow: HashSet::new(),
+            namespace: None,
+        })
+    }
+
+    /// 打开 `root` 目录下的仓库
+    ///
+    /// 设置了 `MONO_NAMESPACE` 环境变量时只能看到该命名空间中的引用。
+    pub fn open(root: &Path) -> MonoResult<Repository> {
+        let mono_dir = root.join(MONO_DIR);
+        if !mono_dir.is_dir() {
+            return Err(MonoError::not_found(format!(
+                "not a mono workspace: {}",
+                root.display()
+            )));
+        }
+        let config = Config::load(Some(&mono_dir))?.repo_config()?;
+        let scheduler = FetchScheduler::new(config.storage.max_concurrency);
+        let objects = storage::open(&config.storage, config.core.object_format, &mono_dir, &scheduler)?;
+        let locks = lock::open(&config, &mono_dir)?;
+        let ref_db = locked_refs(storage::open_refs(&config.storage, &mono_dir, objects.clone())?, &locks, &config.locks);
+        let repo = Repository {
+            refs: logged_refs(ref_db.clone(), &mono_dir),
+            ref_db,
+            objects,
+            scheduler,
+            locks,
+            root: root.to_path_buf(),
+            shallow: shallow::read_shallow(&mono_dir)?,
+            mono_dir,
+            config,
+            namespace: None,
+        };
+        match std::env::var(NAMESPACE_ENV) {
+            Ok(namespace) if !namespace.is_empty() => repo.with_namespace(&namespace),
+            _ => Ok(repo),
+        }
+    }
+
+    /// 从 `start` 开始逐级向上查找仓库
+    pub fn discover(start: &Path) -> MonoResult<Repository> {
+        let start = start.canonicalize()?;
+        for dir in start.ancestors() {
+            if dir.join(MONO_DIR).is_dir() {
+                return Repository::open(dir);
+            }
+        }
+        Err(MonoError::not_found(format!(
+            "not a mono workspace (or any of the parent directories): {}",
+            start.display()
+        )))
+    }
+
+    /// 工作区根目录
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// `.mono` 元数据目录
+    pub fn mono_dir(&self) -> &Path {
+        &self.mono_dir
+    }
+
+    /// 对象存储目录
+    pub fn objects_dir(&self) -> PathBuf {
+        self.mono_dir.join("objects")
+    }
+
+    /// 引用数据库目录
+    pub fn refs_dir(&self) -> PathBuf {
+        self.mono_dir.join("refs")
+    }
+
+    /// 仓库配置
+    pub fn config(&self) -> &RepoConfig {
+        &self.config
+    }
+
+    /// 仓库的对象格式
+    pub fn object_format(&self) -> ObjectFormat {
+        self.config.core.object_format
+    }
+
+    /// 可修改的仓库配置，修改后需调用 [`Repository::save_config`] 持久化
+    pub fn config_mut(&mut self) -> &mut RepoConfig {
+        &mut self.config
+    }
+
+    /// 将仓库配置写回 `mono.toml`，改动的配置段记入审计日志
+    ///
+    /// [`Repository::config`] 是各配置层合并后的结果，只有与当前磁盘上各层合并结果不同的
+    /// 配置段写入仓库层，来自用户级、系统级配置与环境变量的值不会被抄进仓库。
+    pub fn save_config(&self) -> MonoResult<()> {
+        let path = self.mono_dir.join(CONFIG_FILE);
+        let previous = RepoConfig::load(&path).ok();
+        let sections = |config: &RepoConfig| toml::Table::try_from(config).map_err(|e| MonoError::config(e.to_string()));
+        let current = sections(&self.config)?;
+        let layered = sections(&Config::load(Some(&self.mono_dir))?.repo_config()?)?;
+        let mut table = config::read_table(&path)?.unwrap_or_default();
+        for key in current.keys().chain(layered.keys()) {
+            if current.get(key) == layered.get(key) {
+                continue;
+            }
+            match current.get(key) {
+                Some(section) => table.insert(key.clone(), section.clone()),
+                None => table.remove(key),
+            };
+        }
+        config::write_table(&path, &table)?;
+        if let (Some(previous), Ok(saved)) = (previous, RepoConfig::load(&path)) {
+            AuditLog::new(self).record_config_changes(&previous, &saved, &audit::local_actor(), chrono::Utc::now().timestamp());
+        }
+        Ok(())
+    }
+
+    /// 对象存储
+    pub fn objects(&self) -> &dyn ObjectStore {
+        self.objects.as_ref()
+    }
+
+    /// 对象存储的共享句柄
+    pub(crate) fn object_store(&self) -> Arc<dyn ObjectStore> {
+        self.objects.clone()
+    }
+
+    /// 对象存储的异步接口，在 tokio 中使用；同一仓库的所有句柄共享 `[storage] max_concurrency` 的并发限制
+    pub fn async_objects(&self) -> ScheduledStore {
+        ScheduledStore::new(self.objects.clone(), self.scheduler.clone())
+    }
+
+    /// 替换对象存储后端，例如在测试中使用内存存储
+    pub fn with_object_store(mut self, objects: Arc<dyn ObjectStore>) -> Repository {
+        self.objects = objects;
+        self
+    }
+
+    /// 获取与其他服务实例共享的锁，最多等待 `[locks] timeout_secs`
+    pub fn lock(&self, name: &str) -> MonoResult<LockGuard> {
+        self.locks
+            .acquire(name, std::time::Duration::from_secs(self.config.locks.timeout_secs))
+    }
+
+    /// 引用数据库
+    pub fn refs(&self) -> &dyn RefStore {
+        self.refs.as_ref()
+    }
+
+    /// 共享对象存储、只能看到 `namespace` 中引用的仓库，用于在一个仓库中托管多个租户
+    pub fn with_namespace(&self, namespace: &str) -> MonoResult<Repository> {
+        if let Some(current) = &self.namespace {
+            return Err(MonoError::usage(format!("repository is already in namespace {}", current)));
+        }
+        Ok(Repository {
+            refs: Arc::new(refs::NamespacedRefStore::new(self.refs.clone(), namespace)?),
+            namespace: Some(namespace.to_string()),
+            ..self.clone()
+        })
+    }
+
+    /// 引用所在的命名空间
+    pub fn namespace(&self) -> Option<&str> {
+        self.namespace.as_deref()
+    }
+
+    /// 通过 [`Repository::refs`] 修改引用时以 `actor` 与 `reason` 记入引用日志的仓库
+    ///
+    /// 未指定时操作者为本地用户，原因为 `update`。
+    pub fn with_reflog_identity(&self, actor: &str, reason: &str) -> Repository {
+        let log = reflog::Reflog::new(&self.mono_dir);
+        let logged: Arc<dyn RefStore> = Arc::new(reflog::LoggedRefStore::new(self.ref_db.clone(), log, actor, reason));
+        let refs: Arc<dyn RefStore> = match &self.namespace {
+            Some(namespace) => Arc::new(
+                refs::NamespacedRefStore::new(logged, namespace).expect("namespace was checked when it was set"),
+            ),
+            None => logged,
+        };
+        Repository { refs, ..self.clone() }
+    }
+
+    /// 引用的日志，按时间从旧到新；使用命名空间时 `name` 是命名空间内的引用名
+    pub fn reflog(&self, name: &str) -> MonoResult<Vec<reflog::ReflogEntry>> {
+        let name = match &self.namespace {
+            Some(namespace) => {
+                refs::check_name(name)?;
+                format!("{}{}/{}", refs::NAMESPACES_PREFIX, namespace, name)
+            }
+            None => name.to_string(),
+        };
+        reflog::Reflog::new(&self.mono_dir).read(&name)
+    }
+
+    /// 读取对象
+    ///
+    /// 本地不存在且配置了 promisor 远端时（部分克隆），从远端按需获取并保存到本地。
+    pub fn read_object(&self, id: &ObjectId) -> MonoResult<RawObject> {
+        let store = self.objects();
+        if let Some(object) = store.read(id)? {
+            return Ok(object);
+        }
+        if let Some((name, remote)) = self.config.promisor_remote() {
+            tracing::debug!(%id, remote = %name, "fetching missing object from promisor remote");
+            let object = transport::open(&remote.url)?
+                .fetch_object(id)
+                .map_err(|e| e.context(format!("fetching {} from remote {}", id, name)))?;
+            store.write(object.object_type, &object.data)?;
+            return Ok(object);
+        }
+        Err(MonoError::not_found(format!("object {}", id)))
+    }
+
+    /// 写入对象
+    pub fn write_object(&self, object_type: ObjectType, data: &[u8]) -> MonoResult<ObjectId> {
+        self.objects().write(object_type, data)
+    }
+
+    /// 读取并解析提交对象
+    pub fn read_commit(&self, id: &ObjectId) -> MonoResult<Commit> {
+        let object = self.read_object(id)?;
+        if object.object_type != ObjectType::Commit {
+            return Err(MonoError::usage(format!("{} is not a commit", id)));
+        }
+        let mut commit = Commit::parse(&object.data)?;
+        // 浅提交的父提交不在本地
+        if self.shallow.contains(id) {
+            commit.parents.clear();
+        }
+        Ok(commit)
+    }
+
+    /// 浅提交，不是浅仓库时为空
+    pub fn shallow_commits(&self) -> &HashSet<ObjectId> {
+        &self.shallow
+    }
+
+    /// 按一次浅获取的结果更新并保存浅提交
+    pub fn update_shallow(&mut self, update: &ShallowUpdate) -> MonoResult<()> {
+        let shallow = update.apply(&self.shallow);
+        shallow::write_shallow(&self.mono_dir, &shallow)?;
+        self.shallow = shallow;
+        Ok(())
+    }
+
+    /// 解开附注标签，返回最终指向的对象及其类型
+    pub fn peel(&self, id: &ObjectId) -> MonoResult<(ObjectId, ObjectType)> {
+        let mut id = *id;
+        loop {
+            let object = self.read_object(&id)?;
+            if object.object_type != ObjectType::Tag {
+                return Ok((id, object.object_type));
+            }
+            id = Tag::parse(&object.data)?.object;
+        }
+    }
+
+    /// 读取并解析树对象
+    pub fn read_tree(&self, id: &ObjectId) -> MonoResult<Tree> {
+        let object = self.read_object(id)?;
+        if object.object_type != ObjectType::Tree {
+            return Err(MonoError::usage(format!("{} is not a tree", id)));
+        }
+        Tree::parse(&object.data, id.format())
+    }
+
+    /// HEAD 指向的提交，仓库尚无提交时返回 None
+    pub fn head_commit(&self) -> MonoResult<Option<ObjectId>> {
+        self.refs().resolve(refs::HEAD)
+    }
+
+    /// 解析修订：完整的对象 ID、完整引用名、分支名或标签名
+    pub fn resolve_rev(&self, rev: &str) -> MonoResult<ObjectId> {
+        if let Ok(id) = ObjectId::from_hex(rev) {
+            return Ok(id);
+        }
+        match self.dwim_ref(rev)? {
+            Some((_, id)) => Ok(id),
+            None => Err(MonoError::not_found(format!("revision {}", rev))),
+        }
+    }
+
+    /// 按完整引用名、分支名、标签名的顺序查找修订对应的引用，返回引用全名与其指向的对象
+    pub fn dwim_ref(&self, rev: &str) -> MonoResult<Option<(String, ObjectId)>> {
+        if !refs::check_ref_format(rev) {
+            return Err(MonoError::usage(format!("invalid revision: {}", rev)));
+        }
+        let store = self.refs();
+        let candidates = [
+            rev.to_string(),
+            format!("{}{}", refs::HEADS_PREFIX, rev),
+            format!("{}{}", refs::TAGS_PREFIX, rev),
+        ];
+        for name in candidates.into_iter().filter(|name| name == refs::HEAD || name.starts_with("refs/")) {
+            if let Some(id) = store.resolve(&name)? {
+                return Ok(Some((name, id)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// 在树中按 `/` 分隔的相对路径查找条目，空路径返回根树本身
+    pub fn find_path(&self, tree: &ObjectId, path: &str) -> MonoResult<Option<TreeEntry>> {
+        let mut entry = TreeEntry::new(FileMode::TREE, "", *tree);
+        for component in path.split('/').filter(|c| !c.is_empty()) {
+            if !entry.mode.is_tree() {
+                return Ok(None);
+            }
+            match self.read_tree(&entry.id)?.get(component) {
+                Some(child) => entry = child.clone(),
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(entry))
+    }
+
+    /// 在 `base` 上应用修改并写入新树，`base` 为 None 时从空树开始；返回 None 表示结果为空树
+    ///
+    /// 写入路径上缺少的目录会自动创建，删除后变空的目录一并删除。删除不存在的路径、
+    /// 路径经过文件或包含 `.`、`..` 等非法组成部分时报错。
+    pub fn edit_tree(&self, base: Option<&ObjectId>, edits: &[TreeEdit]) -> MonoResult<Option<ObjectId>> {
+        let mut normalized = Vec::with_capacity(edits.len());
+        for edit in edits {
+            let path = edit.path.trim_start_matches('/');
+            if path.split('/').any(|c| c.is_empty() || c == "." || c == "..") {
+                return Err(MonoError::usage(format!("invalid path: {}", edit.path)));
+            }
+            normalized.push((path, edit.entry));
+        }
+        self.edit_level(base, &normalized, "")
+    }
+
+    fn edit_level(
+        &self,
+        base: Option<&ObjectId>,
+        edits: &[PathEdit],
+        prefix: &str,
+    ) -> MonoResult<Option<ObjectId>> {
+        let mut tree = match base {
+            Some(id) => self.read_tree(id)?,
+            None => Tree::default(),
+        };
+        let mut nested: BTreeMap<&str, Vec<PathEdit>> = BTreeMap::new();
+        for &(path, entry) in edits {
+            if let Some((name, rest)) = path.split_once('/') {
+                nested.entry(name).or_default().push((rest, entry));
+                continue;
+            }
+            let existed = tree.get(path).is_some();
+            tree.entries.retain(|e| e.name != path);
+            match entry {
+                Some((mode, id)) => tree.entries.push(TreeEntry::new(mode, path, id)),
+                None if !existed => return Err(MonoError::not_found(format!("path {}{}", prefix, path))),
+                None => {}
+            }
+        }
+        for (name, edits) in nested {
+            let existing = match tree.get(name) {
+                Some(entry) if entry.mode.is_tree() => Some(entry.id),
+                Some(_) => return Err(MonoError::usage(format!("{}{} is not a directory", prefix, name))),
+                None => None,
+            };
+            let subtree = self.edit_level(existing.as_ref(), &edits, &format!("{}{}/", prefix, name))?;
+            tree.entries.retain(|e| e.name != name);
+            if let Some(id) = subtree {
+                tree.entries.push(TreeEntry::new(FileMode::TREE, name, id));
+            }
+        }
+        if tree.entries.is_empty() {
+            return Ok(None);
+        }
+        tree.sort();
+        Ok(Some(self.write_object(ObjectType::Tree, &tree.encode())?))
+    }
+
+    /// 两棵树之间新增、删除或内容、模式改变的文件路径，按路径排序；None 表示空树
+    ///
+    /// 内容相同的子树直接跳过，不读取其中的条目；子模块按文件处理。
+    pub fn changed_paths(&self, old: Option<&ObjectId>, new: Option<&ObjectId>) -> MonoResult<Vec<String>> {
+        let mut paths = Vec::new();
+        self.diff_trees(old, new, "", &mut paths)?;
+        paths.sort();
+        Ok(paths)
+    }
+
+    fn diff_trees(&self, old: Option<&ObjectId>, new: Option<&ObjectId>, prefix: &str, out: &mut Vec<String>) -> MonoResult<()> {
+        if old == new {
+            return Ok(());
+        }
+        let entries = |id: Option<&ObjectId>| -> MonoResult<BTreeMap<String, TreeEntry>> {
+            let Some(id) = id else {
+                return Ok(BTreeMap::new());
+            };
+            Ok(self.read_tree(id)?.entries.into_iter().map(|e| (e.name.clone(), e)).collect())
+        };
+        let (old, new) = (entries(old)?, entries(new)?);
+        let names: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+        for name in names {
+            let (a, b) = (old.get(name), new.get(name));
+            if a.map(|e| (e.mode, e.id)) == b.map(|e| (e.mode, e.id)) {
+                continue;
+            }
+            let path = format!("{}{}", prefix, name);
+            let subtree = |e: Option<&TreeEntry>| e.filter(|e| e.mode.is_tree()).map(|e| e.id);
+            let (old_tree, new_tree) = (subtree(a), subtree(b));
+            if old_tree.is_some() || new_tree.is_some() {
+                self.diff_trees(old_tree.as_ref(), new_tree.as_ref(), &format!("{}/", path), out)?;
+            }
+            // 目录与文件互换时文件一侧同样算作改动
+            if a.is_some_and(|e| !e.mode.is_tree()) || b.is_some_and(|e| !e.mode.is_tree()) {
+                out.push(path);
+            }
+        }
+        Ok(())
+    }
+
+    /// 读取工作区清单
+    pub fn workspace(&self) -> MonoResult<WorkspaceManifest> {
+        WorkspaceManifest::load(&self.mono_dir.join(WORKSPACE_FILE))
+    }
+
+    /// 写回工作区清单
+    pub fn save_workspace(&self, manifest: &WorkspaceManifest) -> MonoResult<()> {
+        manifest.save(&self.mono_dir.join(WORKSPACE_FILE))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::config::StorageBackend;
+    use crate::common::errors::MonoErrorKind;
+
+    /// 测试初始化后生成完整的目录布局
+    #[test]
+    fn test_init_layout() {
+        let dir = tempfile::tempdir().unwrap();
+        let options = InitOptions {
+            initial_branch: "trunk".to_string(),
+            ..Default::default()
+        };
+        let repo = Repository::init(dir.path(), &options).unwrap();
+
+        let mono_dir = repo.mono_dir();
+        assert!(mono_dir.join("objects/pack").is_dir());
+        assert!(mono_dir.join("refs/heads").is_dir());
+        assert!(mono_dir.join("refs/tags").is_dir());
+        assert_eq!(
+            std::fs::read_to_string(mono_dir.join("HEAD")).unwrap(),
+            "ref: refs/heads/trunk\n"
+        );
+        assert_eq!(repo.config().storage.backend, StorageBackend::Fs);
+        let expected_name = dir.path().canonicalize().unwrap();
+        assert_eq!(
+            repo.workspace().unwrap().name,
+            expected_name.file_name().unwrap().to_string_lossy()
+        );
+    }
+
+    /// 测试重复初始化返回错误
+    #[test]
+    fn test_init_twice() {
+        let dir = tempfile::tempdir().unwrap();
+        Repository::init(dir.path(), &InitOptions::default()).unwrap();
+        let err = Repository::init(dir.path(), &InitOptions::default()).unwrap_err();
+        assert!(matches!(err.kind(), MonoErrorKind::Usage(_)));
+    }
+
+    /// 测试非法的初始分支名
+    #[test]
+    fn test_init_invalid_branch() {
+        let dir = tempfile::tempdir().unwrap();
+        let options = InitOptions {
+            initial_branch: "bad..name".to_string(),
+            ..Default::default()
+        };
+        assert!(Repository::init(dir.path(), &options).is_err());
+        assert!(!dir.path().join(MONO_DIR).exists());
+    }
+
+    /// 测试 S3 后端缺少配置时不留下仓库布局
+    #[test]
+    fn test_init_invalid_storage() {
+        let dir = tempfile::tempdir().unwrap();
+        let options = InitOptions {
+            storage: StorageConfig {
+                backend: StorageBackend::S3,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let err = Repository::init(dir.path(), &options).unwrap_err();
+        assert!(matches!(err.kind(), MonoErrorKind::Config(_)));
+        assert!(!dir.path().join(MONO_DIR).exists());
+    }
+
+    /// 测试从子目录向上查找仓库
+    #[test]
+    fn test_discover() {
+        let dir = tempfile::tempdir().unwrap();
+        Repository::init(dir.path(), &InitOptions::default()).unwrap();
+        let nested = dir.path().join("services/payments");
+        std::fs::create_dir_all(&nested).unwrap();
+
+        let repo = Repository::discover(&nested).unwrap();
+        assert_eq!(repo.root(), dir.path().canonicalize().unwrap());
+
+        let other = tempfile::tempdir().unwrap();
+        let err = Repository::discover(other.path()).unwrap_err();
+        assert!(matches!(err.kind(), MonoErrorKind::NotFound(_)));
+    }
+
+    /// 测试按路径查找树条目
+    #[test]
+    fn test_find_path() {
+        let (_dir, repo) = crate::test_utils::init_repo();
+        let commit = crate::test_utils::commit_files(&repo, &[("a/b/c.txt", b"c")], &[], "init");
+        let tree = repo.read_commit(&commit).unwrap().tree;
+
+        let entry = repo.find_path(&tree, "a/b/c.txt").unwrap().unwrap();
+        assert_eq!(repo.read_object(&entry.id).unwrap().data, b"c");
+        assert!(repo.find_path(&tree, "a/b").unwrap().unwrap().mode.is_tree());
+        assert_eq!(repo.find_path(&tree, "").unwrap().unwrap().id, tree);
+        assert!(repo.find_path(&tree, "a/missing").unwrap().is_none());
+        assert!(repo.find_path(&tree, "a/b/c.txt/d").unwrap().is_none());
+    }
+
+    /// 测试两棵树之间的改动路径，包括目录与文件互换
+    #[test]
+    fn test_changed_paths() {
+        let (_dir, repo) = crate::test_utils::init_repo();
+        let old = crate::test_utils::write_tree(
+            &repo,
+            &[("a/b/c.txt", b"c"), ("a/d.txt", b"d"), ("same/x", b"x"), ("e", b"e"), ("f/g", b"g")],
+        );
+        let new = crate::test_utils::write_tree(
+            &repo,
+            &[("a/b/c.txt", b"c2"), ("a/h.txt", b"h"), ("same/x", b"x"), ("e/i", b"i"), ("f", b"f")],
+        );
+        assert_eq!(
+            repo.changed_paths(Some(&old), Some(&new)).unwrap(),
+            ["a/b/c.txt", "a/d.txt", "a/h.txt", "e", "e/i", "f", "f/g"]
+        );
+        assert!(repo.changed_paths(Some(&old), Some(&old)).unwrap().is_empty());
+        assert_eq!(repo.changed_paths(None, Some(&new)).unwrap().len(), 5);
+    }
+
+    /// 测试在树上写入与删除文件，自动创建与清理目录
+    #[test]
+    fn test_edit_tree() {
+        let (_dir, repo) = crate::test_utils::init_repo();
+        let base = crate::test_utils::write_tree(&repo, &[("a/b/c.txt", b"c"), ("a/d.txt", b"d"), ("e", b"e")]);
+        let blob = repo.write_object(ObjectType::Blob, b"new").unwrap();
+        let edit = |path: &str, entry: Option<(FileMode, ObjectId)>| TreeEdit {
+            path: path.to_string(),
+            entry,
+        };
+        let edited = repo
+            .edit_tree(
+                Some(&base),
+                &[
+                    edit("a/b/c.txt", None),
+                    edit("//x/y/z", Some((FileMode::EXECUTABLE, blob))),
+                    edit("e", Some((FileMode::BLOB, blob))),
+                ],
+            )
+            .unwrap();
+        let expected = crate::test_utils::write_tree(&repo, &[("a/d.txt", b"d"), ("e", b"new"), ("x/y/z", b"new")]);
+        // write_tree 写入的都是普通文件，只比较除 x/y/z 外的部分
+        assert_eq!(repo.find_path(&edited.unwrap(), "x/y/z").unwrap().unwrap().mode, FileMode::EXECUTABLE);
+        assert_eq!(
+            repo.edit_tree(edited.as_ref(), &[edit("x/y/z", Some((FileMode::BLOB, blob)))]).unwrap(),
+            Some(expected)
+        );
+
+        assert_eq!(repo.edit_tree(Some(&expected), &[edit("a", None), edit("e", None), edit("x", None)]).unwrap(), None);
+        let err = repo.edit_tree(Some(&base), &[edit("a/missing", None)]).unwrap_err();
+        assert!(matches!(err.kind(), MonoErrorKind::NotFound(_)));
+        assert!(repo.edit_tree(Some(&base), &[edit("e/f", Some((FileMode::BLOB, blob)))]).is_err());
+        assert!(repo.edit_tree(Some(&base), &[edit("a/../e", None)]).is_err());
+    }
+
+    /// 测试按对象 ID、分支名与标签名解析修订
+    #[test]
+    fn test_resolve_rev() {
+        let (_dir, repo) = crate::test_utils::init_repo();
+        let commit = crate::test_utils::commit_files(&repo, &[("a.txt", b"a")], &[], "init");
+        repo.refs().write("refs/heads/main", &commit).unwrap();
+        repo.refs().write("refs/tags/v1", &commit).unwrap();
+
+        assert_eq!(repo.resolve_rev("HEAD").unwrap(), commit);
+        assert_eq!(repo.resolve_rev("main").unwrap(), commit);
+        assert_eq!(repo.resolve_rev("v1").unwrap(), commit);
+        assert_eq!(repo.resolve_rev(&commit.to_hex()).unwrap(), commit);
+        let err = repo.resolve_rev("missing").unwrap_err();
+        assert!(matches!(err.kind(), MonoErrorKind::NotFound(_)));
+    }
+
+    /// 测试替换对象存储后，对象读写都经过新的后端
+    #[test]
+    fn test_with_object_store() {
+        let (_dir, repo) = crate::test_utils::init_repo();
+        let memory = Arc::new(crate::storage::memory::MemoryStore::new());
+        let repo = repo.with_object_store(memory.clone());
+        let commit = crate::test_utils::commit_files(&repo, &[("a.txt", b"a")], &[], "init");
+
+        assert_eq!(repo.read_commit(&commit).unwrap().message, "init\n");
+        assert_eq!(memory.len(), 3);
+        assert!(storage::open(&repo.config().storage, repo.object_format(), repo.mono_dir(), &FetchScheduler::new(1))
+            .unwrap().list().unwrap().is_empty());
+    }
+}
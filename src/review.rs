@@ -0,0 +1,616 @@
+//! 代码评审
+//!
+//! 变更请求（change request）把源分支上的改动提请合入目标分支，记录评论与批准。每个变更请求
+//! 保存为 `.mono/reviews/<编号>.json`，读写由锁文件串行化，通过 REST 接口
+//! （见 [`crate::server::api`]）供网页使用。
+//!
+//! 评论可以锚定到文件的某一行，锚点记录评论时源分支指向的提交。源分支更新后
+//! [`ReviewStore::refresh`] 逐行比较锚定的文件，把锚点移到新提交中对应的行；该行被修改或
+//! 删除时锚点标记为过时，保留原来的位置。比较的是文件内容而不是提交历史，强制推送与变基后
+//! 锚点同样能找到新位置：文件被重命名时跟随新路径，代码块被移动时跟随其中内容唯一的行（见
+//! [`remap_line`]）。receive-pack 在推送更新分支后立即刷新以其为源分支的变更请求，锚点在
+//! 被覆盖的提交可能被回收之前完成移动。批准针对批准时的提交，源分支更新后需要重新批准。
+//! 目标分支包含源分支的提交后，变更请求自动标记为已合入。
+
+use std::fmt;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::common::config::NotificationKind;
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::diff::tree::{diff_trees, ChangeKind, DiffOptions};
+use crate::diff::{self, Algorithm};
+use crate::graph::history::History;
+use crate::notifications::{Notification, Notifications};
+use crate::object::ObjectId;
+use crate::queue::LockFile;
+use crate::refs::{self, RefUpdate};
+use crate::repo::Repository;
+
+/// 评审数据目录，相对于 `.mono`
+pub const REVIEWS_DIR: &str = "reviews";
+/// 读写变更请求时持有的锁
+const LOCK_FILE: &str = "lock";
+
+/// 变更请求的状态
+#[derive(clap::ValueEnum, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ReviewState {
+    Open,
+    Merged,
+    Closed,
+}
+
+impl fmt::Display for ReviewState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(match self {
+            ReviewState::Open => "open",
+            ReviewState::Merged => "merged",
+            ReviewState::Closed => "closed",
+        })
+    }
+}
+
+/// 评论锚定的位置
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Anchor {
+    /// 锚点所在的提交，随源分支的更新而移动
+    pub commit: ObjectId,
+    pub path: String,
+    /// 行号，从 1 开始
+    pub line: usize,
+    /// 锚定的行已被修改或删除，`commit` 与 `line` 为最后一次有效的位置
+    #[serde(default)]
+    pub outdated: bool,
+}
+
+/// 一条评论
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Comment {
+    pub id: u64,
+    pub author: String,
+    pub body: String,
+    /// 行内评论的位置，整体评论时为 None
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub anchor: Option<Anchor>,
+    pub created_at: i64,
+}
+
+/// 一次批准
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Approval {
+    pub reviewer: String,
+    /// 批准时源分支指向的提交
+    pub commit: ObjectId,
+    pub approved_at: i64,
+}
+
+/// 变更请求
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ChangeRequest {
+    pub id: u64,
+    pub title: String,
+    #[serde(default)]
+    pub description: String,
+    pub author: String,
+    /// 被请求评审的用户
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reviewers: Vec<String>,
+    /// 源分支的完整引用名
+    pub source: String,
+    /// 目标分支的完整引用名
+    pub target: String,
+    /// 最近一次看到的源分支提交
+    pub head: ObjectId,
+    pub state: ReviewState,
+    pub created_at: i64,
+    pub updated_at: i64,
+    #[serde(default)]
+    pub comments: Vec<Comment>,
+    #[serde(default)]
+    pub approvals: Vec<Approval>,
+}
+
+impl ChangeRequest {
+    /// 对当前提交有效的批准人
+    pub fn approvers(&self) -> Vec<&str> {
+        self.approvals
+            .iter()
+            .filter(|approval| approval.commit == self.head)
+            .map(|approval| approval.reviewer.as_str())
+            .collect()
+    }
+}
+
+/// 新建变更请求的参数
+#[derive(Debug, Clone)]
+pub struct NewChangeRequest {
+    pub title: String,
+    pub description: String,
+    pub author: String,
+    /// 被请求评审的用户，新建后收到通知
+    pub reviewers: Vec<String>,
+    /// 源分支，可以省略 `refs/heads/` 前缀
+    pub source: String,
+    /// 目标分支，可以省略 `refs/heads/` 前缀
+    pub target: String,
+}
+
+/// 仓库的变更请求
+pub struct ReviewStore<'a> {
+    repo: &'a Repository,
+    dir: PathBuf,
+}
+
+impl<'a> ReviewStore<'a> {
+    pub fn new(repo: &'a Repository) -> ReviewStore<'a> {
+        ReviewStore {
+            repo,
+            dir: repo.mono_dir().join(REVIEWS_DIR),
+        }
+    }
+
+    fn path(&self, id: u64) -> PathBuf {
+        self.dir.join(format!("{}.json", id))
+    }
+
+    /// 读取变更请求
+    pub fn get(&self, id: u64) -> MonoResult<ChangeRequest> {
+        let path = self.path(id);
+        let data = match std::fs::read(&path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Err(MonoError::not_found(format!("change request {}", id)));
+            }
+            Err(e) => return Err(e.into()),
+        };
+        serde_json::from_slice(&data).map_err(|e| MonoError::storage(format!("corrupt change request {}: {}", path.display(), e)))
+    }
+
+    /// 全部变更请求，按编号排列
+    pub fn list(&self) -> MonoResult<Vec<ChangeRequest>> {
+        let mut ids = self.ids()?;
+        ids.sort_unstable();
+        ids.into_iter().map(|id| self.get(id)).collect()
+    }
+
+    fn ids(&self) -> MonoResult<Vec<u64>> {
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut ids = Vec::new();
+        for entry in entries {
+            let name = entry?.file_name();
+            if let Some(id) = name.to_str().and_then(|name| name.strip_suffix(".json")).and_then(|id| id.parse().ok()) {
+                ids.push(id);
+            }
+        }
+        Ok(ids)
+    }
+
+    fn save(&self, review: &ChangeRequest) -> MonoResult<()> {
+        let data = serde_json::to_vec_pretty(review).map_err(|e| MonoError::storage(e.to_string()))?;
+        let path = self.path(review.id);
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, data)?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    /// 在锁的保护下读取、修改并写回变更请求
+    fn update<T>(&self, id: u64, f: impl FnOnce(&mut ChangeRequest) -> MonoResult<T>) -> MonoResult<(ChangeRequest, T)> {
+        std::fs::create_dir_all(&self.dir)?;
+        let _lock = LockFile::acquire(self.dir.join(LOCK_FILE))?;
+        let mut review = self.get(id)?;
+        let result = f(&mut review)?;
+        self.save(&review)?;
+        Ok((review, result))
+    }
+
+    fn branch(&self, name: &str) -> MonoResult<(String, ObjectId)> {
+        let name = if name.starts_with(refs::HEADS_PREFIX) {
+            name.to_string()
+        } else {
+            format!("{}{}", refs::HEADS_PREFIX, name)
+        };
+        let id = self
+            .repo
+            .refs()
+            .resolve(&name)?
+            .ok_or_else(|| MonoError::not_found(format!("branch {}", refs::short_name(&name))))?;
+        Ok((name, id))
+    }
+
+    /// 新建变更请求
+    pub fn create(&self, request: NewChangeRequest, now: i64) -> MonoResult<ChangeRequest> {
+        if request.title.trim().is_empty() {
+            return Err(MonoError::usage("change request title must not be empty"));
+        }
+        let (source, head) = self.branch(&request.source)?;
+        let (target, _) = self.branch(&request.target)?;
+        if source == target {
+            return Err(MonoError::usage("source and target branches must differ"));
+        }
+        std::fs::create_dir_all(&self.dir)?;
+        let _lock = LockFile::acquire(self.dir.join(LOCK_FILE))?;
+        let review = ChangeRequest {
+            id: self.ids()?.into_iter().max().unwrap_or(0) + 1,
+            title: request.title,
+            description: request.description,
+            author: request.author,
+            reviewers: request.reviewers,
+            source,
+            target,
+            head,
+            state: ReviewState::Open,
+            created_at: now,
+            updated_at: now,
+            comments: Vec::new(),
+            approvals: Vec::new(),
+        };
+        self.save(&review)?;
+        tracing::info!(id = review.id, source = %review.source, target = %review.target, "change request created");
+        let body = format!(
+            "{} requested your review of {} into {}\n\n{}",
+            review.author,
+            refs::short_name(&review.source),
+            refs::short_name(&review.target),
+            review.description
+        );
+        self.notify(&review, "Review requested", body, review.reviewers.clone(), &review.author);
+        Ok(review)
+    }
+
+    /// 添加评论，`anchor` 为当前提交中的 `(路径, 行号)`
+    pub fn comment(
+        &self,
+        id: u64,
+        author: &str,
+        body: &str,
+        anchor: Option<(&str, usize)>,
+        now: i64,
+    ) -> MonoResult<Comment> {
+        if body.trim().is_empty() {
+            return Err(MonoError::usage("comment must not be empty"));
+        }
+        let (review, comment) = self.update(id, |review| {
+            let anchor = match anchor {
+                Some((path, line)) => {
+                    let path = path.trim_matches('/');
+                    let lines = self.line_count(&review.head, path)?.ok_or_else(|| {
+                        MonoError::not_found(format!("file {} in change request {}", path, review.id))
+                    })?;
+                    if line == 0 || line > lines {
+                        return Err(MonoError::usage(format!("{} has {} lines, cannot comment on line {}", path, lines, line)));
+                    }
+                    Some(Anchor {
+                        commit: review.head,
+                        path: path.to_string(),
+                        line,
+                        outdated: false,
+                    })
+                }
+                None => None,
+            };
+            let comment = Comment {
+                id: review.comments.iter().map(|comment| comment.id).max().unwrap_or(0) + 1,
+                author: author.to_string(),
+                body: body.to_string(),
+                anchor,
+                created_at: now,
+            };
+            review.comments.push(comment.clone());
+            review.updated_at = now;
+            Ok(comment)
+        })?;
+        let location = match &comment.anchor {
+            Some(anchor) => format!(" on {}:{}", anchor.path, anchor.line),
+            None => String::new(),
+        };
+        let body = format!("{} commented{}:\n\n{}", author, location, body);
+        let mut recipients = review.reviewers.clone();
+        recipients.push(review.author.clone());
+        self.notify(&review, "New comment", body, recipients, author);
+        Ok(comment)
+    }
+
+    /// 批准当前提交，同一评审人之前的批准被替换；作者不能批准自己的变更请求
+    pub fn approve(&self, id: u64, reviewer: &str, now: i64) -> MonoResult<ChangeRequest> {
+        let (review, ()) = self.update(id, |review| {
+            if review.state != ReviewState::Open {
+                return Err(MonoError::usage(format!("change request {} is {}", review.id, review.state)));
+            }
+            if review.author == reviewer {
+                return Err(MonoError::usage("authors cannot approve their own change requests"));
+            }
+            review.approvals.retain(|approval| approval.reviewer != reviewer);
+            review.approvals.push(Approval {
+                reviewer: reviewer.to_string(),
+                commit: review.head,
+                approved_at: now,
+            });
+            review.updated_at = now;
+            Ok(())
+        })?;
+        let body = format!("{} approved {}", reviewer, review.head);
+        self.notify(&review, "Approved", body, vec![review.author.clone()], reviewer);
+        Ok(review)
+    }
+
+    /// 关闭或重新打开变更请求
+    pub fn set_state(&self, id: u64, state: ReviewState, now: i64) -> MonoResult<ChangeRequest> {
+        if state == ReviewState::Merged {
+            return Err(MonoError::usage("change requests are marked merged when the target branch contains them"));
+        }
+        let (review, ()) = self.update(id, |review| {
+            if review.state == ReviewState::Merged {
+                return Err(MonoError::usage(format!("change request {} is already merged", review.id)));
+            }
+            review.state = state;
+            review.updated_at = now;
+            Ok(())
+        })?;
+        Ok(review)
+    }
+
+    /// 刷新源分支或目标分支在 `updates` 中的打开的变更请求，返回刷新的数量
+    pub fn refresh_updated(&self, updates: &[RefUpdate], now: i64) -> MonoResult<usize> {
+        let mut refreshed = 0;
+        for review in self.list()? {
+            let updated = updates.iter().any(|update| update.name == review.source || update.name == review.target);
+            if review.state == ReviewState::Open && updated {
+                self.refresh(review.id, now)?;
+                refreshed += 1;
+            }
+        }
+        Ok(refreshed)
+    }
+
+    /// 按源分支与目标分支的当前位置更新变更请求：源分支移动时移动评论锚点，
+    /// 目标分支包含源分支的提交时标记为已合入
+    pub fn refresh(&self, id: u64, now: i64) -> MonoResult<ChangeRequest> {
+        let review = self.get(id)?;
+        if review.state != ReviewState::Open {
+            return Ok(review);
+        }
+        let head = self.repo.refs().resolve(&review.source)?;
+        let target = self.repo.refs().resolve(&review.target)?;
+        let merged = match target {
+            Some(target) => History::new(self.repo)?.is_ancestor(&head.unwrap_or(review.head), &target)?,
+            None => false,
+        };
+        if head.is_none_or(|head| head == review.head) && !merged {
+            return Ok(review);
+        }
+        let (review, ()) = self.update(id, |review| {
+            if let Some(head) = head.filter(|head| *head != review.head) {
+                for anchor in review.comments.iter_mut().filter_map(|comment| comment.anchor.as_mut()) {
+                    self.move_anchor(anchor, &head)?;
+                }
+                tracing::info!(id = review.id, old = %review.head, new = %head, "change request updated");
+                review.head = head;
+            }
+            if merged {
+                review.state = ReviewState::Merged;
+            }
+            review.updated_at = now;
+            Ok(())
+        })?;
+        Ok(review)
+    }
+
+    /// 把锚点移到 `head` 中对应的行，该行已不存在时标记为过时
+    ///
+    /// 新提交中没有锚定的文件时按重命名检测找到新路径。锚点所在的提交已被回收时无法比较，
+    /// 同样标记为过时。
+    fn move_anchor(&self, anchor: &mut Anchor, head: &ObjectId) -> MonoResult<()> {
+        if anchor.outdated {
+            return Ok(());
+        }
+        if !self.repo.objects().contains(&anchor.commit)? {
+            anchor.outdated = true;
+            return Ok(());
+        }
+        let Some(old) = self.blob(&anchor.commit, &anchor.path)? else {
+            anchor.outdated = true;
+            return Ok(());
+        };
+        let (path, new) = match self.blob(head, &anchor.path)? {
+            Some(new) => (anchor.path.clone(), Some(new)),
+            None => match self.renamed(&anchor.commit, head, &anchor.path)? {
+                Some(path) => {
+                    let new = self.blob(head, &path)?;
+                    (path, new)
+                }
+                None => (anchor.path.clone(), None),
+            },
+        };
+        let line = match new {
+            Some(new) if new == old => Some(anchor.line),
+            Some(new) => {
+                let old = self.repo.read_object(&old)?.data;
+                let new = self.repo.read_object(&new)?.data;
+                remap_line(&diff::split_lines(&old), &diff::split_lines(&new), anchor.line)
+            }
+            None => None,
+        };
+        match line {
+            Some(line) => {
+                anchor.commit = *head;
+                anchor.path = path;
+                anchor.line = line;
+            }
+            None => anchor.outdated = true,
+        }
+        Ok(())
+    }
+
+    /// `path` 从提交 `old` 到 `head` 被重命名后的路径
+    fn renamed(&self, old: &ObjectId, head: &ObjectId, path: &str) -> MonoResult<Option<String>> {
+        let old_tree = self.repo.read_commit(old)?.tree;
+        let new_tree = self.repo.read_commit(head)?.tree;
+        let changes = diff_trees(self.repo, Some(&old_tree), Some(&new_tree), &DiffOptions::default())?;
+        Ok(changes
+            .into_iter()
+            .filter(|change| change.kind == ChangeKind::Renamed)
+            .find(|change| change.old.as_ref().is_some_and(|old| old.path == path))
+            .and_then(|change| change.new)
+            .map(|new| new.path))
+    }
+
+    /// 通知变更请求的相关用户
+    fn notify(&self, review: &ChangeRequest, what: &str, body: String, recipients: Vec<String>, actor: &str) {
+        let notification = Notification {
+            kind: NotificationKind::Review,
+            subject: format!("{}: #{} {}", what, review.id, review.title),
+            body,
+            recipients,
+            actor: Some(actor.to_string()),
+        };
+        Notifications::notify(self.repo, notification);
+    }
+
+    /// 提交中文件的 blob，不存在或不是文件时返回 None
+    fn blob(&self, commit: &ObjectId, path: &str) -> MonoResult<Option<ObjectId>> {
+        let tree = self.repo.read_commit(commit)?.tree;
+        Ok(self.repo.find_path(&tree, path)?.filter(|entry| !entry.mode.is_tree()).map(|entry| entry.id))
+    }
+
+    fn line_count(&self, commit: &ObjectId, path: &str) -> MonoResult<Option<usize>> {
+        match self.blob(commit, path)? {
+            Some(blob) => Ok(Some(diff::split_lines(&self.repo.read_object(&blob)?.data).len())),
+            None => Ok(None),
+        }
+    }
+}
+
+/// 旧版本第 `line` 行（从 1 开始）在新版本中的行号，该行被修改或删除时返回 None
+///
+/// 先按行级差异找未改动的行；变基后代码块被移动时差异把它视为删除与新增，此时若该行的内容
+/// 在两个版本中都只出现一次，则认为它随代码块移到了新位置。空白行不参与这一步。
+pub fn remap_line(old: &[&[u8]], new: &[&[u8]], line: usize) -> Option<usize> {
+    let index = line.checked_sub(1).filter(|index| *index < old.len())?;
+    let matched = diff::match_lines(old, new, Algorithm::Histogram);
+    if let Some(position) = matched.iter().position(|matched| *matched == Some(index)) {
+        return Some(position + 1);
+    }
+    let content = old[index];
+    if content.trim_ascii().is_empty() || old.iter().filter(|other| **other == content).count() != 1 {
+        return None;
+    }
+    let mut positions = new.iter().enumerate().filter(|(_, other)| **other == content);
+    match (positions.next(), positions.next()) {
+        (Some((position, _)), None) if matched[position].is_none() => Some(position + 1),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{commit_files, init_repo};
+
+    /// 测试评论锚点随源分支移动、批准在源分支更新后失效，以及合入后的状态
+    #[test]
+    fn test_review() {
+        let (_dir, repo) = init_repo();
+        let base = commit_files(&repo, &[("a.txt", b"one\ntwo\nthree\n")], &[], "base");
+        let first = commit_files(&repo, &[("a.txt", b"one\ntwo\nthree\nfour\n")], &[base], "first");
+        repo.refs().write("refs/heads/main", &base).unwrap();
+        repo.refs().write("refs/heads/feature", &first).unwrap();
+
+        let store = ReviewStore::new(&repo);
+        let new = |source: &str| NewChangeRequest {
+            title: "Add four".to_string(),
+            description: String::new(),
+            author: "alice".to_string(),
+            reviewers: Vec::new(),
+            source: source.to_string(),
+            target: "main".to_string(),
+        };
+        assert!(store.create(new("missing"), 0).is_err());
+        let review = store.create(new("feature"), 0).unwrap();
+        assert_eq!((review.id, review.head, review.state), (1, first, ReviewState::Open));
+
+        let moved = store.comment(1, "bob", "typo", Some(("a.txt", 3)), 10).unwrap();
+        let removed = store.comment(1, "bob", "drop this", Some(("a.txt", 2)), 10).unwrap();
+        store.comment(1, "bob", "looks fine", None, 10).unwrap();
+        assert!(store.comment(1, "bob", "past the end", Some(("a.txt", 5)), 10).is_err());
+        assert!(store.approve(1, "alice", 20).is_err());
+        assert_eq!(store.approve(1, "bob", 20).unwrap().approvers(), ["bob"]);
+
+        // 源分支删除第二行并在开头插入一行：第三行移到第三行，第二行的锚点过时
+        let second = commit_files(&repo, &[("a.txt", b"zero\none\nthree\nfour\n")], &[first], "second");
+        repo.refs().write("refs/heads/feature", &second).unwrap();
+        let review = store.refresh(1, 30).unwrap();
+        assert_eq!(review.head, second);
+        assert!(review.approvers().is_empty());
+        let anchor = |id: u64| review.comments.iter().find(|c| c.id == id).unwrap().anchor.clone().unwrap();
+        assert_eq!(
+            anchor(moved.id),
+            Anchor {
+                commit: second,
+                path: "a.txt".to_string(),
+                line: 3,
+                outdated: false
+            }
+        );
+        assert!(anchor(removed.id).outdated);
+        assert_eq!(anchor(removed.id).commit, first);
+
+        repo.refs().write("refs/heads/main", &second).unwrap();
+        assert_eq!(store.refresh(1, 40).unwrap().state, ReviewState::Merged);
+        assert!(store.set_state(1, ReviewState::Closed, 50).is_err());
+        assert_eq!(store.list().unwrap().len(), 1);
+    }
+
+    /// 测试强制推送变基后的分支：重命名的文件与移动的代码块中的锚点找到新位置
+    #[test]
+    fn test_anchor_across_rebase() {
+        let (_dir, repo) = init_repo();
+        let old: &[u8] = b"fn a() {\n    alpha();\n}\n\nfn b() {\n    beta();\n}\n";
+        let base = commit_files(&repo, &[("src/lib.rs", b"")], &[], "base");
+        let first = commit_files(&repo, &[("src/lib.rs", old)], &[base], "first");
+        repo.refs().write("refs/heads/main", &base).unwrap();
+        repo.refs().write("refs/heads/feature", &first).unwrap();
+        let store = ReviewStore::new(&repo);
+        let request = NewChangeRequest {
+            title: "Add a and b".to_string(),
+            description: String::new(),
+            author: "alice".to_string(),
+            reviewers: Vec::new(),
+            source: "feature".to_string(),
+            target: "main".to_string(),
+        };
+        store.create(request, 0).unwrap();
+        let beta = store.comment(1, "bob", "beta?", Some(("src/lib.rs", 6)), 0).unwrap();
+        let alpha = store.comment(1, "bob", "alpha?", Some(("src/lib.rs", 2)), 0).unwrap();
+
+        // 变基到新的基础提交上，文件改名且两个函数交换位置，alpha 所在行被修改
+        let new: &[u8] = b"fn b() {\n    beta();\n}\n\nfn a() {\n    alpha(1);\n}\n";
+        let main = commit_files(&repo, &[("README", b"readme"), ("src/lib.rs", b"")], &[base], "main");
+        let rebased = commit_files(&repo, &[("README", b"readme"), ("src/core.rs", new)], &[main], "rebased");
+        let update = RefUpdate {
+            name: "refs/heads/feature".to_string(),
+            old: first,
+            new: rebased,
+        };
+        repo.refs().write(&update.name, &rebased).unwrap();
+        assert_eq!(store.refresh_updated(&[update], 10).unwrap(), 1);
+
+        let review = store.get(1).unwrap();
+        let anchor = |id: u64| review.comments.iter().find(|c| c.id == id).unwrap().anchor.clone().unwrap();
+        assert_eq!((anchor(beta.id).path.as_str(), anchor(beta.id).line, anchor(beta.id).commit), ("src/core.rs", 2, rebased));
+        assert!(anchor(alpha.id).outdated);
+        assert_eq!((anchor(alpha.id).path.as_str(), anchor(alpha.id).commit), ("src/lib.rs", first));
+
+        let lines = |data: &'static [u8]| diff::split_lines(data);
+        assert_eq!(remap_line(&lines(b"a\nx\nb\n"), &lines(b"x\na\nb\n"), 1), Some(2));
+        assert_eq!(remap_line(&lines(b"a\n\nb\n"), &lines(b"b\n\na\n"), 2), None);
+        assert_eq!(remap_line(&lines(b"a\n"), &lines(b"a\n"), 2), None);
+    }
+}
@@ -0,0 +1,216 @@
+//! 导入其他仓库的历史
+//!
+//! 将另一个仓库的完整历史移到子目录下：每个提交的根树被包装为 `<prefix>/` 下的子树，
+//! 父提交替换为改写后的提交，作者、提交者与提交信息保持不变，因此导入后 `log` 与 `blame`
+//! 仍能看到原始的作者信息。改写后的历史通过一个合并提交接入当前分支。
+//!
+//! 与 [`split`](super::split) 相同，原提交到改写提交的映射缓存在 `.mono/absorb/` 下，
+//! 大仓库的导入中断后重新执行只处理尚未改写的提交。
+
+use crate::audit::{self, AuditAction, AuditLog};
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::object::commit::{Commit, Signature};
+use crate::object::tree::{FileMode, TreeEntry};
+use crate::object::{ObjectId, ObjectType};
+use crate::refs::{self, RefUpdate};
+use crate::repo::Repository;
+use crate::rewrite::{normalize_prefix, rewrite_commit, rewrite_history, CommitMap};
+
+/// 保存映射缓存的目录，相对于 `.mono`
+pub const ABSORB_DIR: &str = "absorb";
+
+/// 将其他仓库的历史改写到子目录下
+pub struct Absorber<'a> {
+    repo: &'a Repository,
+    prefix: String,
+    map: CommitMap,
+    /// 本次改写（未命中缓存）的提交数
+    processed: usize,
+}
+
+impl<'a> Absorber<'a> {
+    /// 打开子目录的导入状态并读取已有的映射缓存
+    pub fn new(repo: &'a Repository, prefix: &str) -> MonoResult<Absorber<'a>> {
+        let prefix = normalize_prefix(prefix)?;
+        Ok(Absorber {
+            repo,
+            map: CommitMap::open(repo, ABSORB_DIR, &prefix)?,
+            prefix,
+            processed: 0,
+        })
+    }
+
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    pub fn processed(&self) -> usize {
+        self.processed
+    }
+
+    /// 将新增的映射追加到缓存文件
+    pub fn save_cache(&mut self) -> MonoResult<()> {
+        self.map.save()
+    }
+
+    /// 改写 `commit` 及其全部祖先，返回与 `commit` 对应的改写提交
+    ///
+    /// 提交对象需要已经获取到本仓库中。
+    pub fn absorb(&mut self, commit: &ObjectId) -> MonoResult<ObjectId> {
+        let (repo, prefix, processed) = (self.repo, self.prefix.as_str(), &mut self.processed);
+        let absorbed = rewrite_history(repo, commit, &mut self.map, |parsed, map| {
+            let parents = parsed
+                .parents
+                .iter()
+                .map(|parent| map.get(parent).ok_or_else(|| MonoError::storage(format!("parent {} not absorbed", parent))))
+                .collect::<MonoResult<Vec<_>>>()?;
+            let tree = insert_tree(repo, None, prefix, parsed.tree)?;
+            let absorbed = rewrite_commit(parsed, tree, parents);
+            *processed += 1;
+            Ok(Some(repo.write_object(ObjectType::Commit, &absorbed.encode())?))
+        })?;
+        Ok(absorbed.expect("absorbed commits are never dropped"))
+    }
+}
+
+/// 将树 `subtree` 放到 `base` 中的 `path` 处，返回新的根树；`base` 为 None 时从空树开始
+///
+/// `path` 的上级路径中有同名文件时报错。
+pub fn insert_tree(repo: &Repository, base: Option<&ObjectId>, path: &str, subtree: ObjectId) -> MonoResult<ObjectId> {
+    let (name, rest) = match path.split_once('/') {
+        Some((name, rest)) => (name, Some(rest)),
+        None => (path, None),
+    };
+    let mut tree = match base {
+        Some(base) => repo.read_tree(base)?,
+        None => Default::default(),
+    };
+    let existing = match tree.get(name) {
+        Some(entry) if !entry.mode.is_tree() => {
+            return Err(MonoError::usage(format!("{} is not a directory", name)));
+        }
+        entry => entry.map(|entry| entry.id),
+    };
+    let id = match rest {
+        Some(rest) => insert_tree(repo, existing.as_ref(), rest, subtree)?,
+        None => subtree,
+    };
+    tree.entries.retain(|entry| entry.name != name);
+    tree.entries.push(TreeEntry::new(FileMode::TREE, name, id));
+    tree.sort();
+    repo.write_object(ObjectType::Tree, &tree.encode())
+}
+
+/// 通过合并提交将改写后的历史 `absorbed` 接入 HEAD 所在的分支，返回新的 HEAD 提交
+///
+/// 合并提交的树为 HEAD 的树加上 `prefix` 子目录；仓库还没有提交时直接将分支指向 `absorbed`。
+/// HEAD 中已经存在 `prefix` 时报错，避免覆盖已有内容。
+pub fn graft(
+    repo: &Repository,
+    prefix: &str,
+    absorbed: &ObjectId,
+    committer: Signature,
+    message: &str,
+) -> MonoResult<ObjectId> {
+    let target = repo.refs().head_target()?.unwrap_or_else(|| refs::HEAD.to_string());
+    let head = repo.head_commit()?;
+    let new = match head {
+        None => *absorbed,
+        Some(head) => {
+            let head_tree = repo.read_commit(&head)?.tree;
+            if repo.find_path(&head_tree, prefix)?.is_some() {
+                return Err(MonoError::usage(format!("//{} already exists", prefix)));
+            }
+            let subtree = repo
+                .find_path(&repo.read_commit(absorbed)?.tree, prefix)?
+                .ok_or_else(|| MonoError::storage(format!("//{} missing from {}", prefix, absorbed)))?;
+            let merge = Commit {
+                tree: insert_tree(repo, Some(&head_tree), prefix, subtree.id)?,
+                parents: vec![head, *absorbed],
+                author: committer.clone(),
+                committer,
+                extra_headers: Vec::new(),
+                message: message.to_string(),
+            };
+            repo.write_object(ObjectType::Commit, &merge.encode())?
+        }
+    };
+    let update = RefUpdate {
+        name: target,
+        old: head.unwrap_or(ObjectId::ZERO),
+        new,
+    };
+    repo.with_reflog_identity(&audit::local_actor(), "absorb")
+        .refs()
+        .update(std::slice::from_ref(&update))?;
+    let now = chrono::Utc::now().timestamp();
+    AuditLog::new(repo).record_ref_updates(repo, &audit::local_actor(), AuditAction::RefUpdate, &[update], now);
+    Ok(new)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{commit_files, init_repo};
+
+    /// 测试历史改写到子目录下、保留作者信息，以及缓存使中断后的导入只处理剩余的提交
+    #[test]
+    fn test_absorb() {
+        let (_dir, repo) = init_repo();
+        let first = commit_files(&repo, &[("README", b"foo")], &[], "first");
+        let side = commit_files(&repo, &[("README", b"foo"), ("a.c", b"1")], &[first], "side");
+        let main = commit_files(&repo, &[("README", b"foo 2")], &[first], "main");
+        let merge = commit_files(&repo, &[("README", b"foo 2"), ("a.c", b"1")], &[main, side], "merge");
+
+        let mut absorber = Absorber::new(&repo, "//third_party/foo/...").unwrap();
+        let absorbed = absorber.absorb(&side).unwrap();
+        assert_eq!(absorber.processed(), 2);
+        absorber.save_cache().unwrap();
+
+        let commit = repo.read_commit(&absorbed).unwrap();
+        let original = repo.read_commit(&side).unwrap();
+        assert_eq!((&commit.author, &commit.committer, &commit.message), (&original.author, &original.committer, &original.message));
+        let tree = repo.read_tree(&commit.tree).unwrap();
+        assert_eq!(tree.entries.len(), 1);
+        let foo = repo.find_path(&commit.tree, "third_party/foo").unwrap().unwrap();
+        assert_eq!(foo.id, original.tree);
+
+        // 新实例从缓存继续，只改写剩余的提交
+        let mut resumed = Absorber::new(&repo, "third_party/foo").unwrap();
+        let tip = resumed.absorb(&merge).unwrap();
+        assert_eq!(resumed.processed(), 2);
+        let parents = repo.read_commit(&tip).unwrap().parents;
+        assert_eq!(parents[1], absorbed);
+        assert_eq!(repo.read_commit(&parents[0]).unwrap().parents, repo.read_commit(&absorbed).unwrap().parents);
+    }
+
+    /// 测试合并提交接入当前分支，空仓库直接指向导入的历史，已存在的子目录报错
+    #[test]
+    fn test_graft() {
+        let (_dir, repo) = init_repo();
+        let foo = commit_files(&repo, &[("lib.rs", b"foo")], &[], "foo");
+        let mut absorber = Absorber::new(&repo, "third_party/foo").unwrap();
+        let absorbed = absorber.absorb(&foo).unwrap();
+        let sig = Signature::new("Importer", "importer@example.com", 1_700_000_000);
+
+        // 空仓库
+        let head = graft(&repo, "third_party/foo", &absorbed, sig.clone(), "absorb\n").unwrap();
+        assert_eq!(head, absorbed);
+        assert_eq!(repo.head_commit().unwrap(), Some(absorbed));
+        assert!(graft(&repo, "third_party/foo", &absorbed, sig.clone(), "absorb\n").is_err());
+
+        let base = commit_files(&repo, &[("README", b"mono"), ("third_party/bar/x", b"1")], &[], "base");
+        repo.refs().write(&repo.refs().head_target().unwrap().unwrap(), &base).unwrap();
+        let head = graft(&repo, "third_party/foo", &absorbed, sig.clone(), "absorb\n").unwrap();
+        let merge = repo.read_commit(&head).unwrap();
+        assert_eq!(merge.parents, [base, absorbed]);
+        assert_eq!(merge.committer, sig);
+        assert_eq!(repo.head_commit().unwrap(), Some(head));
+        for path in ["README", "third_party/bar/x", "third_party/foo/lib.rs"] {
+            assert!(repo.find_path(&merge.tree, path).unwrap().is_some(), "{}", path);
+        }
+
+        assert!(graft(&repo, "README/foo", &absorbed, sig, "absorb\n").is_err());
+    }
+}
@@ -0,0 +1,456 @@
+//! 历史改写：`mono filter`
+//!
+//! 与 git-filter-repo 类似，直接在对象存储上改写分支与标签的全部历史：
+//!
+//! - `--path`：只保留这些路径，`--exclude`：删除这些路径
+//! - `--strip-blobs-bigger-than`：删除超过大小的文件
+//! - `--rename old:new`：移动目录或文件，在路径过滤之后执行
+//! - `--mailmap`：按 mailmap 改写作者与提交者（见 [`crate::mailmap`]）
+//!
+//! 每个提交的新树只取决于原树，因此先由多个线程并行改写全部树，相同的子树只改写一次；
+//! 再按拓扑顺序依次写入提交。改写后变空的提交被丢弃，原本就为空的提交保留。
+//! 改写完成后更新分支与标签，附注标签重新生成，原提交到新提交的映射写入
+//! `.mono/filter/commit.map`。旧对象在引用日志过期后由 `mono gc` 删除。
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use crate::audit::{self, AuditAction, AuditLog};
+use crate::common::errors::MonoError;
+use crate::common::progress::Progress;
+use crate::common::MonoResult;
+use crate::mailmap::Mailmap;
+use crate::object::commit::{Commit, Signature};
+use crate::object::tag::Tag;
+use crate::object::tree::{FileMode, Tree, TreeEdit, TreeEntry};
+use crate::object::{ObjectId, ObjectType};
+use crate::refs::{self, RefUpdate};
+use crate::repo::Repository;
+use crate::rewrite::{normalize_prefix, rewrite_commit, CommitMap};
+
+/// 保存提交映射的目录，相对于 `.mono`
+pub const FILTER_DIR: &str = "filter";
+
+/// 提交映射的文件名（不含 `.map`）
+const COMMIT_MAP: &str = "commit";
+
+/// 默认改写的引用前缀
+pub const DEFAULT_REFS: &[&str] = &[refs::HEADS_PREFIX, refs::TAGS_PREFIX];
+
+/// 改写选项
+#[derive(Debug, Clone, Default)]
+pub struct FilterOptions {
+    /// 只保留的路径，为空时保留全部
+    pub paths: Vec<String>,
+    /// 删除的路径
+    pub exclude: Vec<String>,
+    /// 删除超过该字节数的文件
+    pub max_blob_size: Option<u64>,
+    /// 路径改名，按顺序执行
+    pub renames: Vec<(String, String)>,
+    pub mailmap: Option<Mailmap>,
+    /// 改写的引用前缀，为空时使用 [`DEFAULT_REFS`]
+    pub refs: Vec<String>,
+    /// 并行改写树的线程数，0 表示按 CPU 数
+    pub jobs: usize,
+}
+
+impl FilterOptions {
+    /// 规范化路径，检查改名的格式
+    fn normalized(mut self) -> MonoResult<FilterOptions> {
+        let normalize = |paths: &[String]| paths.iter().map(|p| normalize_prefix(p)).collect::<MonoResult<Vec<_>>>();
+        self.paths = normalize(&self.paths)?;
+        self.exclude = normalize(&self.exclude)?;
+        self.renames = self
+            .renames
+            .iter()
+            .map(|(from, to)| Ok((normalize_prefix(from)?, normalize_prefix(to)?)))
+            .collect::<MonoResult<_>>()?;
+        if self.paths.is_empty() && self.exclude.is_empty() && self.max_blob_size.is_none() && self.renames.is_empty() && self.mailmap.is_none() {
+            return Err(MonoError::usage("nothing to filter: give --path, --exclude, --strip-blobs-bigger-than, --rename or --mailmap"));
+        }
+        if self.refs.is_empty() {
+            self.refs = DEFAULT_REFS.iter().map(|prefix| prefix.to_string()).collect();
+        }
+        Ok(self)
+    }
+
+    fn rewrites_trees(&self) -> bool {
+        !self.paths.is_empty() || !self.exclude.is_empty() || self.max_blob_size.is_some() || !self.renames.is_empty()
+    }
+}
+
+/// 一次改写的统计
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FilterReport {
+    /// 改写的原提交数
+    pub commits: usize,
+    /// 改写后变空而丢弃的提交数
+    pub pruned: usize,
+    /// 因超过大小而删除的不同文件数
+    pub stripped_blobs: usize,
+    /// 更新或删除的引用
+    pub updates: Vec<RefUpdate>,
+}
+
+/// `path` 是否等于 `prefix` 或在其下
+fn under(path: &str, prefix: &str) -> bool {
+    path == prefix || path.strip_prefix(prefix).is_some_and(|rest| rest.starts_with('/'))
+}
+
+/// 并行改写树时共享的状态
+struct TreeFilter<'a> {
+    repo: &'a Repository,
+    options: &'a FilterOptions,
+    /// (目录路径, 原树) 到改写结果的缓存，None 表示改写后为空
+    trees: Mutex<HashMap<(String, ObjectId), Option<ObjectId>>>,
+    /// 文件是否超过大小限制
+    oversized: Mutex<HashMap<ObjectId, bool>>,
+}
+
+impl TreeFilter<'_> {
+    /// 改写根树，返回 None 表示结果为空
+    fn filter_root(&self, tree: &ObjectId) -> MonoResult<Option<ObjectId>> {
+        let Some(mut root) = self.filter_tree(tree, "", self.options.paths.is_empty())? else {
+            return Ok(None);
+        };
+        for (from, to) in &self.options.renames {
+            let Some(entry) = self.repo.find_path(&root, from)? else { continue };
+            let edits = [
+                TreeEdit { path: from.clone(), entry: None },
+                TreeEdit { path: to.clone(), entry: Some((entry.mode, entry.id)) },
+            ];
+            match self.repo.edit_tree(Some(&root), &edits)? {
+                Some(id) => root = id,
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(root))
+    }
+
+    /// 改写 `dir` 目录（以 `/` 结尾，根目录为空），`included` 表示该目录已在保留的路径之内
+    fn filter_tree(&self, id: &ObjectId, dir: &str, included: bool) -> MonoResult<Option<ObjectId>> {
+        let key = (dir.to_string(), *id);
+        if let Some(result) = self.trees.lock().expect("tree cache lock poisoned").get(&key) {
+            return Ok(*result);
+        }
+        let tree = self.repo.read_tree(id)?;
+        let mut entries = Vec::with_capacity(tree.entries.len());
+        for entry in tree.entries {
+            let path = format!("{}{}", dir, entry.name);
+            if self.options.exclude.iter().any(|exclude| under(&path, exclude)) {
+                continue;
+            }
+            let included = included || self.options.paths.iter().any(|keep| under(&path, keep));
+            if entry.mode.is_tree() {
+                // 未包含的目录只在其下有保留的路径时进入
+                if !included && !self.options.paths.iter().any(|keep| under(keep, &path)) {
+                    continue;
+                }
+                if let Some(id) = self.filter_tree(&entry.id, &format!("{}/", path), included)? {
+                    entries.push(TreeEntry::new(FileMode::TREE, entry.name, id));
+                }
+                continue;
+            }
+            if !included || (entry.mode.is_blob() && self.is_oversized(&entry.id)?) {
+                continue;
+            }
+            entries.push(entry);
+        }
+        let result = match entries.is_empty() {
+            true => None,
+            false => Some(self.repo.write_object(ObjectType::Tree, &Tree { entries }.encode())?),
+        };
+        self.trees.lock().expect("tree cache lock poisoned").insert(key, result);
+        Ok(result)
+    }
+
+    fn is_oversized(&self, id: &ObjectId) -> MonoResult<bool> {
+        let Some(limit) = self.options.max_blob_size else {
+            return Ok(false);
+        };
+        if let Some(oversized) = self.oversized.lock().expect("blob cache lock poisoned").get(id) {
+            return Ok(*oversized);
+        }
+        let oversized = match self.repo.objects().read_header(id)? {
+            Some((_, size)) => size as u64 > limit,
+            None => return Err(MonoError::not_found(format!("object {}", id))),
+        };
+        self.oversized.lock().expect("blob cache lock poisoned").insert(*id, oversized);
+        Ok(oversized)
+    }
+}
+
+/// 改写历史并更新引用
+pub fn filter(repo: &Repository, options: FilterOptions) -> MonoResult<FilterReport> {
+    let options = options.normalized()?;
+    let mut tips = Vec::new();
+    for prefix in &options.refs {
+        tips.extend(repo.refs().list(prefix)?);
+    }
+    let commits = topo_order(repo, &tips)?;
+
+    // 并行改写每个提交的树
+    let filter = TreeFilter { repo, options: &options, trees: Mutex::new(HashMap::new()), oversized: Mutex::new(HashMap::new()) };
+    let trees = match options.rewrites_trees() {
+        true => filter_trees(&filter, &commits, options.jobs)?,
+        false => commits.iter().map(|(_, commit)| Some(commit.tree)).collect(),
+    };
+
+    // 按拓扑顺序写入提交
+    let commit_map = repo.mono_dir().join(FILTER_DIR).join(format!("{}.map", COMMIT_MAP));
+    if commit_map.exists() {
+        std::fs::remove_file(&commit_map)?;
+    }
+    let mut map = CommitMap::open(repo, FILTER_DIR, COMMIT_MAP)?;
+    let progress = Progress::new("Writing commits", commits.len() as u64);
+    let mut report = FilterReport { commits: commits.len(), ..FilterReport::default() };
+    let mut new_trees: HashMap<ObjectId, ObjectId> = HashMap::new();
+    for (commit, tree) in commits.iter().zip(trees) {
+        let (rewritten, pruned) = rewrite_one(repo, &options, commit, tree, &map, &mut new_trees)?;
+        report.pruned += pruned as usize;
+        map.insert(commit.0, rewritten);
+        progress.inc(1);
+    }
+    progress.finish();
+    map.save()?;
+    report.stripped_blobs = filter.oversized.lock().expect("blob cache lock poisoned").values().filter(|v| **v).count();
+    report.updates = update_refs(repo, &tips, &map)?;
+    Ok(report)
+}
+
+/// 从 `tips` 可达的全部提交，父提交在前
+fn topo_order(repo: &Repository, tips: &[(String, ObjectId)]) -> MonoResult<Vec<(ObjectId, Commit)>> {
+    let mut seen: HashMap<ObjectId, bool> = HashMap::new();
+    let mut order = Vec::new();
+    let mut stack = Vec::new();
+    for (_, tip) in tips {
+        let (id, object_type) = repo.peel(tip)?;
+        if object_type == ObjectType::Commit {
+            stack.push((id, false));
+        }
+    }
+    let progress = Progress::spinner("Reading commits");
+    while let Some((id, expanded)) = stack.pop() {
+        if expanded {
+            order.push((id, repo.read_commit(&id)?));
+            progress.inc(1);
+            continue;
+        }
+        if seen.insert(id, true).is_some() {
+            continue;
+        }
+        stack.push((id, true));
+        let commit = repo.read_commit(&id)?;
+        stack.extend(commit.parents.iter().filter(|p| !seen.contains_key(*p)).map(|p| (*p, false)));
+    }
+    progress.finish();
+    Ok(order)
+}
+
+/// 由多个线程改写提交的树，结果与 `commits` 一一对应
+fn filter_trees(filter: &TreeFilter, commits: &[(ObjectId, Commit)], jobs: usize) -> MonoResult<Vec<Option<ObjectId>>> {
+    let jobs = match jobs {
+        0 => std::thread::available_parallelism().map_or(1, |n| n.get()),
+        jobs => jobs,
+    };
+    let progress = Progress::new("Rewriting trees", commits.len() as u64);
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<ObjectId>>> = Mutex::new(vec![None; commits.len()]);
+    std::thread::scope(|scope| -> MonoResult<()> {
+        let workers: Vec<_> = (0..jobs.min(commits.len()).max(1))
+            .map(|_| {
+                scope.spawn(|| -> MonoResult<()> {
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        let Some((_, commit)) = commits.get(i) else {
+                            return Ok(());
+                        };
+                        let tree = filter.filter_root(&commit.tree)?;
+                        results.lock().expect("results lock poisoned")[i] = tree;
+                        progress.inc(1);
+                    }
+                })
+            })
+            .collect();
+        for worker in workers {
+            worker.join().map_err(|_| MonoError::storage("filter worker panicked"))??;
+        }
+        Ok(())
+    })?;
+    progress.finish();
+    Ok(results.into_inner().expect("results lock poisoned"))
+}
+
+/// 写入一个改写后的提交，返回改写结果与是否因变空而丢弃；丢弃的提交映射为其唯一的父提交，
+/// 没有父提交时为 None
+fn rewrite_one(
+    repo: &Repository,
+    options: &FilterOptions,
+    (id, commit): &(ObjectId, Commit),
+    tree: Option<ObjectId>,
+    map: &CommitMap,
+    new_trees: &mut HashMap<ObjectId, ObjectId>,
+) -> MonoResult<(Option<ObjectId>, bool)> {
+    let mut parents: Vec<ObjectId> = Vec::new();
+    for parent in &commit.parents {
+        if let Some(new) = map.get(parent) {
+            if !parents.contains(&new) {
+                parents.push(new);
+            }
+        }
+    }
+    let tree = match tree {
+        Some(tree) => tree,
+        None if parents.is_empty() => return Ok((None, true)),
+        None => repo.write_object(ObjectType::Tree, &Tree::default().encode())?,
+    };
+    if let [parent] = parents.as_slice() {
+        // 原本就为空的提交保留；合并的其他父提交都被丢弃时同样不再需要该合并
+        let was_empty = commit.parents.len() == 1 && repo.read_commit(&commit.parents[0])?.tree == commit.tree;
+        if !was_empty && new_trees.get(parent) == Some(&tree) {
+            return Ok((Some(*parent), true));
+        }
+    }
+    let (author, committer) = match &options.mailmap {
+        Some(mailmap) => (map_signature(mailmap, &commit.author), map_signature(mailmap, &commit.committer)),
+        None => (commit.author.clone(), commit.committer.clone()),
+    };
+    // 完全没有变化的提交保持原样，签名仍然有效
+    let new = if tree == commit.tree && parents == commit.parents && author == commit.author && committer == commit.committer {
+        *id
+    } else {
+        let rewritten = Commit { author, committer, ..rewrite_commit(commit, tree, parents) };
+        repo.write_object(ObjectType::Commit, &rewritten.encode())?
+    };
+    new_trees.insert(new, tree);
+    Ok((Some(new), false))
+}
+
+fn map_signature(mailmap: &Mailmap, signature: &Signature) -> Signature {
+    let (name, email) = mailmap.map(&signature.name, &signature.email);
+    Signature { name, email, ..signature.clone() }
+}
+
+/// 按映射更新引用：改写后为空的引用被删除，附注标签重新生成
+fn update_refs(repo: &Repository, tips: &[(String, ObjectId)], map: &CommitMap) -> MonoResult<Vec<RefUpdate>> {
+    let mut updates = Vec::new();
+    for (name, old) in tips {
+        let object = repo.read_object(old)?;
+        let new = match object.object_type {
+            ObjectType::Commit => map.get(old).unwrap_or(ObjectId::ZERO),
+            ObjectType::Tag => {
+                let mut tag = Tag::parse(&object.data)?;
+                let (target, target_type) = repo.peel(&tag.object)?;
+                match (target_type, map.get(&target)) {
+                    (ObjectType::Commit, Some(new)) if tag.object == target => {
+                        tag.object = new;
+                        repo.write_object(ObjectType::Tag, &tag.encode())?
+                    }
+                    (ObjectType::Commit, None) => ObjectId::ZERO,
+                    // 指向树、blob 或其他标签的标签保持不变
+                    _ => *old,
+                }
+            }
+            _ => *old,
+        };
+        if new != *old {
+            updates.push(RefUpdate { name: name.clone(), old: *old, new });
+        }
+    }
+    if updates.is_empty() {
+        return Ok(updates);
+    }
+    let actor = audit::local_actor();
+    repo.with_reflog_identity(&actor, "filter").refs().update(&updates)?;
+    let now = chrono::Utc::now().timestamp();
+    AuditLog::new(repo).record_ref_updates(repo, &actor, AuditAction::RefUpdate, &updates, now);
+    Ok(updates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{commit_files, init_repo};
+
+    fn paths(repo: &Repository, commit: &ObjectId) -> Vec<String> {
+        let tree = repo.read_commit(commit).unwrap().tree;
+        let mut paths = repo.changed_paths(None, Some(&tree)).unwrap();
+        paths.sort();
+        paths
+    }
+
+    /// 测试路径过滤、删除大文件、改名与空提交的丢弃，以及分支与附注标签的更新
+    #[test]
+    fn test_filter() {
+        let (_dir, repo) = init_repo();
+        let big = vec![b'x'; 2048];
+        let first = commit_files(&repo, &[("src/lib.rs", b"1"), ("docs/a.md", b"a"), ("assets/big.bin", &big)], &[], "first");
+        let docs = commit_files(&repo, &[("src/lib.rs", b"1"), ("docs/a.md", b"b"), ("assets/big.bin", &big)], &[first], "docs only");
+        let second = commit_files(&repo, &[("src/lib.rs", b"2"), ("docs/a.md", b"b"), ("assets/big.bin", &big)], &[docs], "second");
+        let tag = Tag {
+            object: second,
+            object_type: ObjectType::Commit,
+            name: "v1".to_string(),
+            tagger: Some(Signature::new("A U Thor", "author@example.com", 1_700_000_000)),
+            message: "v1\n".to_string(),
+        };
+        let tag_id = repo.write_object(ObjectType::Tag, &tag.encode()).unwrap();
+        repo.refs()
+            .update(&[
+                RefUpdate { name: "refs/heads/main".to_string(), old: ObjectId::ZERO, new: second },
+                RefUpdate { name: "refs/heads/docs".to_string(), old: ObjectId::ZERO, new: docs },
+                RefUpdate { name: "refs/tags/v1".to_string(), old: ObjectId::ZERO, new: tag_id },
+            ])
+            .unwrap();
+
+        let options = FilterOptions {
+            exclude: vec!["//docs".to_string()],
+            max_blob_size: Some(1024),
+            renames: vec![("src".to_string(), "lib/core".to_string())],
+            jobs: 2,
+            ..FilterOptions::default()
+        };
+        let report = filter(&repo, options).unwrap();
+        assert_eq!((report.commits, report.pruned, report.stripped_blobs, report.updates.len()), (3, 1, 1, 3));
+
+        let main = repo.refs().resolve("refs/heads/main").unwrap().unwrap();
+        assert_eq!(paths(&repo, &main), ["lib/core/lib.rs"]);
+        let parent = repo.read_commit(&main).unwrap().parents[0];
+        // 只修改了 docs 的提交改写后变空，docs 分支指向它的父提交的改写结果
+        assert_eq!(repo.read_commit(&parent).unwrap().message, "first\n");
+        assert_eq!(repo.refs().resolve("refs/heads/docs").unwrap(), Some(parent));
+        let new_tag = Tag::parse(&repo.read_object(&repo.refs().resolve("refs/tags/v1").unwrap().unwrap()).unwrap().data).unwrap();
+        assert_eq!((new_tag.object, new_tag.message.as_str()), (main, "v1\n"));
+
+        let map = CommitMap::open(&repo, FILTER_DIR, COMMIT_MAP).unwrap();
+        assert_eq!(map.get(&second), Some(main));
+        assert_eq!(map.get(&docs), Some(parent));
+    }
+
+    /// 测试只保留指定路径与按 mailmap 改写身份，全部内容被过滤掉的分支被删除
+    #[test]
+    fn test_filter_paths_and_mailmap() {
+        let (_dir, repo) = init_repo();
+        let first = commit_files(&repo, &[("services/api/main.rs", b"1"), ("web/index.html", b"1")], &[], "first");
+        let web = commit_files(&repo, &[("web/index.html", b"2")], &[], "web only");
+        repo.refs().write("refs/heads/main", &first).unwrap();
+        repo.refs().write("refs/heads/web", &web).unwrap();
+
+        let options = FilterOptions {
+            paths: vec!["services/api".to_string()],
+            mailmap: Some(Mailmap::parse("Alice <alice@example.com> <author@example.com>").unwrap()),
+            ..FilterOptions::default()
+        };
+        let report = filter(&repo, options).unwrap();
+        assert_eq!(report.pruned, 1);
+        let main = repo.refs().resolve("refs/heads/main").unwrap().unwrap();
+        assert_eq!(paths(&repo, &main), ["services/api/main.rs"]);
+        let commit = repo.read_commit(&main).unwrap();
+        assert_eq!((commit.author.name.as_str(), commit.committer.email.as_str()), ("Alice", "alice@example.com"));
+        assert_eq!(repo.refs().resolve("refs/heads/web").unwrap(), None);
+
+        assert!(filter(&repo, FilterOptions::default()).is_err());
+    }
+}
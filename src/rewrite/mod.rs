@@ -0,0 +1,180 @@
+//! 历史改写
+//!
+//! [`split`] 将子目录的历史导出为独立的提交历史，[`absorb`] 反过来将其他仓库的历史
+//! 移到子目录下导入。两者都按后序遍历逐个改写提交，原提交到改写结果的映射追加保存在
+//! `.mono/<dir>/<prefix>.map` 中：再次执行时只处理新增的提交，中断后也能从上次保存处继续。
+//! [`rebase`] 将一组提交重放到新的基础提交上，供合并队列使用；[`pick`] 在服务端把单个提交
+//! cherry-pick 或 revert 到分支上。[`filter`] 按路径、文件大小、改名与 mailmap
+//! 并行改写全部分支与标签的历史。
+
+pub mod absorb;
+pub mod filter;
+pub mod pick;
+pub mod rebase;
+pub mod split;
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::PathBuf;
+
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::object::commit::Commit;
+use crate::object::ObjectId;
+use crate::repo::Repository;
+
+/// 改写过程中每处理这么多提交保存一次映射
+pub const SAVE_INTERVAL: usize = 1000;
+
+/// 改写后签名失效的头部
+const SIGNATURE_HEADERS: &[&str] = &["gpgsig", "gpgsig-sha256", "mergetag"];
+
+/// 规范化子目录路径：接受 `//dir/...`、`//dir` 与 `dir/` 等写法，返回不含首尾 `/` 的相对路径
+pub fn normalize_prefix(prefix: &str) -> MonoResult<String> {
+    let path = prefix.strip_suffix("...").unwrap_or(prefix).trim_matches('/');
+    if path.is_empty() || path.split('/').any(|c| c.is_empty() || c == "." || c == "..") {
+        return Err(MonoError::usage(format!("invalid path prefix: {}", prefix)));
+    }
+    Ok(path.to_string())
+}
+
+/// 以新的树和父提交复制提交，保留作者、提交者与提交信息，去掉失效的签名
+pub fn rewrite_commit(commit: &Commit, tree: ObjectId, parents: Vec<ObjectId>) -> Commit {
+    Commit {
+        tree,
+        parents,
+        author: commit.author.clone(),
+        committer: commit.committer.clone(),
+        extra_headers: commit
+            .extra_headers
+            .iter()
+            .filter(|(key, _)| !SIGNATURE_HEADERS.contains(&key.as_str()))
+            .cloned()
+            .collect(),
+        message: commit.message.clone(),
+    }
+}
+
+/// 原提交到改写结果的映射，None 表示该提交在改写后被丢弃
+pub struct CommitMap {
+    path: PathBuf,
+    map: HashMap<ObjectId, Option<ObjectId>>,
+    /// 尚未写入缓存文件的映射
+    pending: Vec<(ObjectId, Option<ObjectId>)>,
+}
+
+impl CommitMap {
+    /// 读取 `.mono/<dir>/` 下子目录的映射缓存，子目录路径中的 `/` 编码为 `%2F`
+    pub fn open(repo: &Repository, dir: &str, prefix: &str) -> MonoResult<CommitMap> {
+        let name = prefix.replace('%', "%25").replace('/', "%2F");
+        let path = repo.mono_dir().join(dir).join(format!("{}.map", name));
+        let mut map = HashMap::new();
+        let content = match std::fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e.into()),
+        };
+        for line in content.lines() {
+            let corrupt = || MonoError::storage(format!("corrupt commit map {}: {}", path.display(), line));
+            let (original, rewritten) = line.split_once(' ').ok_or_else(corrupt)?;
+            let original: ObjectId = original.parse().map_err(|_| corrupt())?;
+            let rewritten: ObjectId = rewritten.parse().map_err(|_| corrupt())?;
+            map.insert(original, (!rewritten.is_zero()).then_some(rewritten));
+        }
+        Ok(CommitMap {
+            path,
+            map,
+            pending: Vec::new(),
+        })
+    }
+
+    pub fn contains(&self, original: &ObjectId) -> bool {
+        self.map.contains_key(original)
+    }
+
+    /// 改写结果，提交未处理或被丢弃时返回 None
+    pub fn get(&self, original: &ObjectId) -> Option<ObjectId> {
+        self.map.get(original).copied().flatten()
+    }
+
+    pub fn insert(&mut self, original: ObjectId, rewritten: Option<ObjectId>) {
+        self.map.insert(original, rewritten);
+        self.pending.push((original, rewritten));
+    }
+
+    /// 将新增的映射追加到缓存文件
+    pub fn save(&mut self) -> MonoResult<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        std::fs::create_dir_all(self.path.parent().expect("map path has a parent"))?;
+        let mut out = String::new();
+        for (original, rewritten) in &self.pending {
+            out.push_str(&format!("{} {}\n", original, rewritten.unwrap_or(ObjectId::ZERO)));
+        }
+        std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?
+            .write_all(out.as_bytes())?;
+        self.pending.clear();
+        Ok(())
+    }
+}
+
+/// 改写 `tip` 及其全部尚未映射的祖先，返回 `tip` 的改写结果
+///
+/// 后序遍历，`rewrite` 处理一个提交时其父提交都已在映射中。每处理 [`SAVE_INTERVAL`]
+/// 个提交保存一次映射；失败时先保存已完成的部分再返回错误。
+pub fn rewrite_history<F>(repo: &Repository, tip: &ObjectId, map: &mut CommitMap, mut rewrite: F) -> MonoResult<Option<ObjectId>>
+where
+    F: FnMut(&Commit, &CommitMap) -> MonoResult<Option<ObjectId>>,
+{
+    let result = walk(repo, tip, map, &mut rewrite);
+    if result.is_err() {
+        map.save()?;
+    }
+    result
+}
+
+fn walk(
+    repo: &Repository,
+    tip: &ObjectId,
+    map: &mut CommitMap,
+    rewrite: &mut dyn FnMut(&Commit, &CommitMap) -> MonoResult<Option<ObjectId>>,
+) -> MonoResult<Option<ObjectId>> {
+    let mut stack = vec![(*tip, false)];
+    while let Some((id, expanded)) = stack.pop() {
+        if map.contains(&id) {
+            continue;
+        }
+        let parsed = repo.read_commit(&id)?;
+        if !expanded {
+            stack.push((id, true));
+            stack.extend(parsed.parents.iter().filter(|p| !map.contains(p)).map(|p| (*p, false)));
+            continue;
+        }
+        let rewritten = rewrite(&parsed, map)?;
+        map.insert(id, rewritten);
+        if map.pending.len() >= SAVE_INTERVAL {
+            map.save()?;
+        }
+    }
+    Ok(map.get(tip))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 测试子目录路径的各种写法
+    #[test]
+    fn test_normalize_prefix() {
+        assert_eq!(normalize_prefix("//third_party/foo").unwrap(), "third_party/foo");
+        assert_eq!(normalize_prefix("//third_party/foo/...").unwrap(), "third_party/foo");
+        assert_eq!(normalize_prefix("/lib/").unwrap(), "lib");
+        assert!(normalize_prefix("//...").is_err());
+        assert!(normalize_prefix("a//b").is_err());
+        assert!(normalize_prefix("a/../b").is_err());
+    }
+}
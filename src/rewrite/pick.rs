@@ -0,0 +1,281 @@
+//! 服务端的 cherry-pick 与 revert
+//!
+//! 不需要工作区：以被挑选提交的父提交为共同祖先，把它带来的改动三方合并（见 [`crate::merge`]）
+//! 到目标提交上；revert 反过来以提交本身为共同祖先、以父提交为对方。合并提交需要用 `mainline`
+//! 指定以第几个父提交为基准，与 `git cherry-pick -m` 相同。
+//!
+//! [`pick_onto_branch`] 在分支的最新提交上创建新提交并更新分支，检查与 gRPC 创建提交相同：
+//! 访问权限、推送策略与钩子，以比较并交换的方式更新引用，并记录审计日志。
+
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::audit::{AuditAction, AuditLog};
+use crate::auth::Access;
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::hooks::Hooks;
+use crate::merge::{merge_trees, Conflict, MergeOptions};
+use crate::object::commit::{Commit, Signature};
+use crate::object::{ObjectId, ObjectType};
+use crate::policy::Policy;
+use crate::refs::{self, RefUpdate};
+use crate::replication;
+use crate::repo::Repository;
+use crate::rewrite::rewrite_commit;
+
+/// 挑选的方式
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum PickKind {
+    /// 把提交的改动应用到目标上
+    CherryPick,
+    /// 在目标上撤销提交的改动
+    Revert,
+}
+
+impl PickKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PickKind::CherryPick => "cherry-pick",
+            PickKind::Revert => "revert",
+        }
+    }
+}
+
+impl fmt::Display for PickKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(self.as_str())
+    }
+}
+
+/// 挑选选项
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PickOptions {
+    pub kind: PickKind,
+    /// 合并提交以第几个父提交（从 1 开始）为基准，非合并提交不能指定
+    pub mainline: Option<usize>,
+    /// cherry-pick 时在提交信息末尾注明来源提交，与 `git cherry-pick -x` 相同
+    pub record_origin: bool,
+}
+
+impl PickOptions {
+    pub fn new(kind: PickKind) -> PickOptions {
+        PickOptions {
+            kind,
+            mainline: None,
+            record_origin: false,
+        }
+    }
+}
+
+/// 一次挑选的结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PickOutcome {
+    /// 新创建的提交
+    Picked(ObjectId),
+    /// 改动已经包含在目标中，没有创建提交
+    Empty,
+    /// 合并冲突，没有创建提交
+    Conflict(Vec<Conflict>),
+}
+
+/// 在 `onto` 之上挑选 `commit`，创建以 `onto` 为父提交的新提交
+///
+/// cherry-pick 保留原作者与提交信息；revert 的作者与提交者都是 `committer`。
+pub fn pick(
+    repo: &Repository,
+    commit: &ObjectId,
+    onto: &ObjectId,
+    committer: &Signature,
+    options: &PickOptions,
+) -> MonoResult<PickOutcome> {
+    let picked = repo.read_commit(commit)?;
+    let parents = &picked.parents;
+    let parent = match options.mainline {
+        None if parents.len() > 1 => {
+            return Err(MonoError::usage(format!("commit {} is a merge but no mainline was given", commit)));
+        }
+        None => parents.first().copied(),
+        Some(mainline) if parents.len() > 1 && (1..=parents.len()).contains(&mainline) => Some(parents[mainline - 1]),
+        Some(mainline) => {
+            return Err(MonoError::usage(format!("commit {} has no parent {} to use as mainline", commit, mainline)));
+        }
+    };
+    let parent_tree = match &parent {
+        Some(parent) => Some(repo.read_commit(parent)?.tree),
+        None => None,
+    };
+    let onto_tree = repo.read_commit(onto)?.tree;
+
+    let abbrev = |id: &ObjectId| id.to_hex()[..7].to_string();
+    let subject = format!("{} ({})", abbrev(commit), picked.summary());
+    let parent_label = format!("parent of {}", subject);
+    let (base, theirs, base_label, theirs_label) = match options.kind {
+        PickKind::CherryPick => (parent_tree, Some(picked.tree), parent_label, subject),
+        PickKind::Revert => (Some(picked.tree), parent_tree, subject, parent_label),
+    };
+    let merge_options = MergeOptions {
+        ours_label: abbrev(onto),
+        base_label,
+        theirs_label,
+        ..MergeOptions::from_config(&repo.config().merge)
+    };
+    let merged = merge_trees(repo, base.as_ref(), Some(&onto_tree), theirs.as_ref(), &merge_options)?;
+    if !merged.is_clean() {
+        return Ok(PickOutcome::Conflict(merged.conflicts));
+    }
+    if merged.tree == onto_tree {
+        return Ok(PickOutcome::Empty);
+    }
+
+    let new = match options.kind {
+        PickKind::CherryPick => {
+            let mut message = picked.message.clone();
+            if options.record_origin {
+                if !message.ends_with('\n') {
+                    message.push('\n');
+                }
+                message.push_str(&format!("\n(cherry picked from commit {})\n", commit));
+            }
+            Commit {
+                committer: committer.clone(),
+                message,
+                ..rewrite_commit(&picked, merged.tree, vec![*onto])
+            }
+        }
+        PickKind::Revert => {
+            let mut message = format!("Revert \"{}\"\n\nThis reverts commit {}", picked.summary(), commit);
+            match (options.mainline, &parent) {
+                (Some(_), Some(parent)) => message.push_str(&format!(", reversing\nchanges made to {}.\n", parent)),
+                _ => message.push_str(".\n"),
+            }
+            Commit {
+                tree: merged.tree,
+                parents: vec![*onto],
+                author: committer.clone(),
+                committer: committer.clone(),
+                extra_headers: Vec::new(),
+                message,
+            }
+        }
+    };
+    Ok(PickOutcome::Picked(repo.write_object(ObjectType::Commit, &new.encode())?))
+}
+
+/// 在分支 `branch` 的最新提交上挑选 `commit` 并更新分支，`branch` 可以省略 `refs/heads/` 前缀
+///
+/// 只读镜像、分支不存在时报错；权限不足或钩子拒绝时返回 [`MonoError::auth`]，违反推送策略时
+/// 返回 [`MonoError::policy`]；挑选期间分支被其他方式更新时引用更新失败，可以重试。
+pub fn pick_onto_branch(
+    repo: &Repository,
+    access: &Access,
+    commit: &ObjectId,
+    branch: &str,
+    committer: &Signature,
+    options: &PickOptions,
+) -> MonoResult<PickOutcome> {
+    let branch = if branch.starts_with(refs::HEADS_PREFIX) {
+        branch.to_string()
+    } else {
+        format!("{}{}", refs::HEADS_PREFIX, branch)
+    };
+    if !refs::check_ref_format(&branch) {
+        return Err(MonoError::usage(format!("invalid branch name: {}", branch)));
+    }
+    if let Some(reason) = replication::read_only_reason(repo) {
+        return Err(MonoError::usage(reason));
+    }
+    let tip = repo
+        .refs()
+        .resolve(&branch)?
+        .ok_or_else(|| MonoError::not_found(format!("branch {}", refs::short_name(&branch))))?;
+    let outcome = pick(repo, commit, &tip, committer, options)?;
+    let PickOutcome::Picked(id) = outcome else {
+        return Ok(outcome);
+    };
+
+    let update = RefUpdate {
+        name: branch,
+        old: tip,
+        new: id,
+    };
+    if let Err(reason) = access.check_update(repo, &update)? {
+        return Err(MonoError::auth(reason));
+    }
+    Policy::load(repo)?.check(repo, &update, &[])?;
+    let hooks = Hooks::load(repo);
+    let updates = std::slice::from_ref(&update);
+    if let Err(reason) = hooks.pre_receive(repo, updates, &[]).and_then(|()| hooks.update(repo, &update, &[])) {
+        return Err(MonoError::auth(reason));
+    }
+    let reason = format!("{}: {}", options.kind, repo.read_commit(commit)?.summary());
+    repo.with_reflog_identity(&access.principal, &reason).refs().update(updates)?;
+    AuditLog::new(repo).record_ref_updates(repo, &access.principal, AuditAction::RefUpdate, updates, chrono::Utc::now().timestamp());
+    hooks.post_receive(repo, updates, &[]);
+    tracing::info!(kind = %options.kind, branch = %update.name, %commit, new = %id, "picked commit onto branch");
+    Ok(PickOutcome::Picked(id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merge::ConflictKind;
+    use crate::test_utils::{commit_files, init_repo};
+
+    /// 测试 cherry-pick 与 revert 的内容、提交信息与分支更新，以及冲突、空改动和合并提交的处理
+    #[test]
+    fn test_pick() {
+        let (_dir, repo) = init_repo();
+        let base = commit_files(&repo, &[("a", b"1\n2\n3\n"), ("b", b"x\n")], &[], "base");
+        let main = commit_files(&repo, &[("a", b"1\n2\n3\n"), ("b", b"y\n")], &[base], "main");
+        let fix = commit_files(&repo, &[("a", b"1\n2\nfixed\n"), ("b", b"x\n")], &[base], "fix the bug");
+        let release = commit_files(&repo, &[("a", b"one\n2\n3\n"), ("b", b"x\n")], &[base], "release");
+        repo.refs().write("refs/heads/release", &release).unwrap();
+        let committer = Signature::new("Bot", "bot@example.com", 1_800_000_000);
+        let access = Access::full("test");
+
+        let options = PickOptions {
+            record_origin: true,
+            ..PickOptions::new(PickKind::CherryPick)
+        };
+        let PickOutcome::Picked(picked) = pick_onto_branch(&repo, &access, &fix, "release", &committer, &options).unwrap() else {
+            panic!("cherry-pick did not apply");
+        };
+        assert_eq!(repo.refs().resolve("refs/heads/release").unwrap(), Some(picked));
+        let commit = repo.read_commit(&picked).unwrap();
+        assert_eq!(commit.parents, [release]);
+        assert_eq!(commit.author, repo.read_commit(&fix).unwrap().author);
+        assert_eq!(commit.committer, committer);
+        assert_eq!(commit.message, format!("fix the bug\n\n(cherry picked from commit {})\n", fix));
+        let a = repo.find_path(&commit.tree, "a").unwrap().unwrap();
+        assert_eq!(repo.read_object(&a.id).unwrap().data, b"one\n2\nfixed\n");
+
+        // 再次挑选时改动已包含在分支中
+        assert_eq!(pick_onto_branch(&repo, &access, &fix, "release", &committer, &options).unwrap(), PickOutcome::Empty);
+
+        let revert = PickOptions::new(PickKind::Revert);
+        let PickOutcome::Picked(reverted) = pick(&repo, &main, &main, &committer, &revert).unwrap() else {
+            panic!("revert did not apply");
+        };
+        let commit = repo.read_commit(&reverted).unwrap();
+        assert_eq!(commit.tree, repo.read_commit(&base).unwrap().tree);
+        assert_eq!(commit.message, format!("Revert \"main\"\n\nThis reverts commit {}.\n", main));
+        assert_eq!(commit.author, committer);
+
+        let conflicting = commit_files(&repo, &[("a", b"1\n2\n3\n"), ("b", b"z\n")], &[base], "conflict");
+        let PickOutcome::Conflict(conflicts) = pick(&repo, &conflicting, &main, &committer, &options).unwrap() else {
+            panic!("expected a conflict");
+        };
+        assert_eq!((conflicts[0].kind, conflicts[0].path.as_str()), (ConflictKind::Content, "b"));
+
+        let merge = commit_files(&repo, &[("a", b"1\n2\nfixed\n"), ("b", b"y\n")], &[main, fix], "merge");
+        assert!(pick(&repo, &merge, &release, &committer, &options).is_err());
+        let mainline = PickOptions {
+            mainline: Some(1),
+            ..PickOptions::new(PickKind::CherryPick)
+        };
+        assert!(matches!(pick(&repo, &merge, &release, &committer, &mainline).unwrap(), PickOutcome::Picked(_)));
+    }
+}
@@ -0,0 +1,135 @@
+//! 变基
+//!
+//! 将一组提交逐个重放到新的基础提交上，每次重放是一次三方合并（见 [`crate::merge`]），
+//! 算法与重命名检测按 `[merge]` 配置段设置，任何冲突都会中止变基。与 `git rebase` 相同，
+//! 合并提交被跳过，重放后没有改动的提交被丢弃。
+
+use std::collections::HashSet;
+
+use crate::common::MonoResult;
+use crate::graph::history::History;
+use crate::merge::{merge_trees, MergeOptions};
+use crate::object::commit::{Commit, Signature};
+use crate::object::{ObjectId, ObjectType};
+use crate::repo::Repository;
+use crate::rewrite::rewrite_commit;
+
+/// 从 `tip` 可达而从 `onto` 不可达的非合并提交，父提交在前
+pub fn commits_to_rebase(repo: &Repository, onto: &ObjectId, tip: &ObjectId) -> MonoResult<Vec<ObjectId>> {
+    let history = History::new(repo)?;
+    let mut seen = HashSet::new();
+    let mut order = Vec::new();
+    let mut stack = vec![(*tip, false)];
+    while let Some((id, expanded)) = stack.pop() {
+        if expanded {
+            order.push(id);
+            continue;
+        }
+        if !seen.insert(id) || history.is_ancestor(&id, onto)? {
+            continue;
+        }
+        stack.push((id, true));
+        stack.extend(history.commit(&id)?.parents.iter().rev().map(|p| (*p, false)));
+    }
+    let mut commits = Vec::with_capacity(order.len());
+    for id in order {
+        if history.commit(&id)?.parents.len() <= 1 {
+            commits.push(id);
+        }
+    }
+    Ok(commits)
+}
+
+/// 一次变基的结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RebaseOutcome {
+    /// 变基后的最新提交；没有需要重放的改动时为 `onto` 本身
+    Rebased(ObjectId),
+    /// 重放 `commit` 时冲突
+    Conflict { commit: ObjectId, paths: Vec<String> },
+}
+
+/// 将 `tip` 上独有的提交重放到 `onto` 上
+///
+/// 作者与提交信息保持不变，提交者改为 `committer`。
+pub fn rebase(repo: &Repository, onto: &ObjectId, tip: &ObjectId, committer: &Signature) -> MonoResult<RebaseOutcome> {
+    rebase_onto(repo, onto, tip, onto, committer)
+}
+
+/// 将从 `tip` 可达而从 `upstream` 不可达的提交重放到 `onto` 上，与 `git rebase --onto` 相同
+pub fn rebase_onto(
+    repo: &Repository,
+    upstream: &ObjectId,
+    tip: &ObjectId,
+    onto: &ObjectId,
+    committer: &Signature,
+) -> MonoResult<RebaseOutcome> {
+    let options = MergeOptions::from_config(&repo.config().merge);
+    let mut head = *onto;
+    let mut head_tree = repo.read_commit(onto)?.tree;
+    for id in commits_to_rebase(repo, upstream, tip)? {
+        let commit = repo.read_commit(&id)?;
+        let base = match commit.parents.first() {
+            Some(parent) => Some(repo.read_commit(parent)?.tree),
+            None => None,
+        };
+        let merged = merge_trees(repo, base.as_ref(), Some(&head_tree), Some(&commit.tree), &options)?;
+        if !merged.is_clean() {
+            return Ok(RebaseOutcome::Conflict {
+                commit: id,
+                paths: merged.conflict_paths(),
+            });
+        }
+        let tree = merged.tree;
+        if tree == head_tree {
+            continue;
+        }
+        let rebased = Commit {
+            committer: committer.clone(),
+            ..rewrite_commit(&commit, tree, vec![head])
+        };
+        head = repo.write_object(ObjectType::Commit, &rebased.encode())?;
+        head_tree = tree;
+    }
+    Ok(RebaseOutcome::Rebased(head))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{commit_files, init_repo};
+
+    /// 测试变基保留作者与提交信息、跳过已包含的改动，以及冲突时报告冲突的提交
+    #[test]
+    fn test_rebase() {
+        let (_dir, repo) = init_repo();
+        let base = commit_files(&repo, &[("a", b"1"), ("b", b"1")], &[], "base");
+        let main = commit_files(&repo, &[("a", b"2"), ("b", b"1")], &[base], "main");
+        let one = commit_files(&repo, &[("a", b"1"), ("b", b"2")], &[base], "feature one");
+        let two = commit_files(&repo, &[("a", b"2"), ("b", b"2")], &[one], "feature two");
+        assert_eq!(commits_to_rebase(&repo, &main, &two).unwrap(), [one, two]);
+
+        let committer = Signature::new("Queue", "queue@example.com", 1_800_000_000);
+        let RebaseOutcome::Rebased(head) = rebase(&repo, &main, &two, &committer).unwrap() else {
+            panic!("unexpected conflict");
+        };
+        // 第二个提交的改动已在 main 上，重放后被丢弃
+        let rebased = repo.read_commit(&head).unwrap();
+        assert_eq!(rebased.parents, [main]);
+        assert_eq!(rebased.message, "feature one\n");
+        assert_eq!(rebased.author, repo.read_commit(&one).unwrap().author);
+        assert_eq!(rebased.committer, committer);
+        let a = |commit: &Commit| repo.find_path(&commit.tree, "a").unwrap().unwrap().id;
+        assert_eq!(a(&rebased), a(&repo.read_commit(&main).unwrap()));
+
+        let conflict = commit_files(&repo, &[("a", b"3"), ("b", b"1")], &[base], "conflict");
+        assert_eq!(
+            rebase(&repo, &main, &conflict, &committer).unwrap(),
+            RebaseOutcome::Conflict {
+                commit: conflict,
+                paths: vec!["a".to_string()],
+            }
+        );
+        assert_eq!(rebase(&repo, &main, &base, &committer).unwrap(), RebaseOutcome::Rebased(main));
+    }
+}
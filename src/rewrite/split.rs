@@ -0,0 +1,169 @@
+//! 子目录历史导出
+//!
+//! 与 `git subtree split` 相同，为修改过子目录的每个提交生成一个以该子目录为根树的新提交，
+//! 作者、提交者与提交信息保持不变，得到可以独立推送的仓库历史。没有修改子目录的提交被跳过，
+//! 合并提交中互为祖先的父提交只保留一个。结果是确定的：同一段历史总是得到相同的提交，
+//! 因此下游镜像可以一直快进更新。
+//!
+//! 原提交到导出提交的映射按目录缓存在 `.mono/split/` 下，再次导出时只处理新增的提交。
+
+use crate::common::MonoResult;
+use crate::graph::history::History;
+use crate::object::commit::Commit;
+use crate::object::{ObjectId, ObjectType};
+use crate::repo::Repository;
+use crate::rewrite::{normalize_prefix, rewrite_commit, rewrite_history, CommitMap};
+
+/// 保存映射缓存的目录，相对于 `.mono`
+pub const SPLIT_DIR: &str = "split";
+
+/// 一次导出的统计
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SplitStats {
+    /// 本次处理（未命中缓存）的原提交数
+    pub processed: usize,
+    /// 新生成的提交数
+    pub created: usize,
+}
+
+/// 将一个子目录的历史导出为独立的提交历史
+pub struct Splitter<'a> {
+    repo: &'a Repository,
+    prefix: String,
+    /// 原提交到导出提交的映射，None 表示该提交及其祖先都不包含子目录
+    map: CommitMap,
+    stats: SplitStats,
+}
+
+impl<'a> Splitter<'a> {
+    /// 打开子目录的导出状态并读取已有的映射缓存
+    pub fn new(repo: &'a Repository, prefix: &str) -> MonoResult<Splitter<'a>> {
+        let prefix = normalize_prefix(prefix)?;
+        Ok(Splitter {
+            repo,
+            map: CommitMap::open(repo, SPLIT_DIR, &prefix)?,
+            prefix,
+            stats: SplitStats::default(),
+        })
+    }
+
+    pub fn stats(&self) -> SplitStats {
+        self.stats
+    }
+
+    /// 将新增的映射追加到缓存文件
+    pub fn save_cache(&mut self) -> MonoResult<()> {
+        self.map.save()
+    }
+
+    /// 导出 `commit` 及其全部祖先，返回与 `commit` 对应的导出提交；历史中没有该子目录时返回 None
+    pub fn split(&mut self, commit: &ObjectId) -> MonoResult<Option<ObjectId>> {
+        let (repo, prefix, stats) = (self.repo, self.prefix.as_str(), &mut self.stats);
+        rewrite_history(repo, commit, &mut self.map, |parsed, map| {
+            stats.processed += 1;
+            split_one(repo, prefix, parsed, map, stats)
+        })
+    }
+}
+
+fn split_one(
+    repo: &Repository,
+    prefix: &str,
+    commit: &Commit,
+    map: &CommitMap,
+    stats: &mut SplitStats,
+) -> MonoResult<Option<ObjectId>> {
+    let mut parents: Vec<ObjectId> = Vec::new();
+    for parent in &commit.parents {
+        if let Some(split) = map.get(parent) {
+            if !parents.contains(&split) {
+                parents.push(split);
+            }
+        }
+    }
+    // 去掉是其他父提交祖先的父提交，避免生成多余的合并
+    if parents.len() > 1 {
+        let history = History::with_graph(repo, None);
+        let mut reduced = Vec::with_capacity(parents.len());
+        for (i, parent) in parents.iter().enumerate() {
+            let mut redundant = false;
+            for (j, other) in parents.iter().enumerate() {
+                if i != j && history.is_ancestor(parent, other)? {
+                    redundant = true;
+                    break;
+                }
+            }
+            if !redundant {
+                reduced.push(*parent);
+            }
+        }
+        parents = reduced;
+    }
+
+    let tree = match repo.find_path(&commit.tree, prefix)? {
+        Some(entry) if entry.mode.is_tree() => entry.id,
+        // 子目录被删除或尚未创建：沿用父提交的导出结果
+        _ => return Ok(parents.first().copied()),
+    };
+    if let [parent] = parents.as_slice() {
+        if repo.read_commit(parent)?.tree == tree {
+            return Ok(Some(*parent));
+        }
+    }
+
+    stats.created += 1;
+    let split = rewrite_commit(commit, tree, parents);
+    Ok(Some(repo.write_object(ObjectType::Commit, &split.encode())?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{commit_files, init_repo};
+
+    /// 测试只修改其他目录的提交被跳过，合并与删除子目录的处理，以及缓存与增量导出
+    #[test]
+    fn test_split() {
+        let (_dir, repo) = init_repo();
+        let before = commit_files(&repo, &[("other/a", b"1")], &[], "before lib");
+        let add = commit_files(&repo, &[("other/a", b"1"), ("lib/x.rs", b"1")], &[before], "add lib");
+        let other = commit_files(&repo, &[("other/a", b"2"), ("lib/x.rs", b"1")], &[add], "touch other");
+        let side = commit_files(&repo, &[("other/a", b"1"), ("lib/y.rs", b"1"), ("lib/x.rs", b"1")], &[add], "side");
+        let merge = commit_files(
+            &repo,
+            &[("other/a", b"2"), ("lib/y.rs", b"1"), ("lib/x.rs", b"1")],
+            &[other, side],
+            "merge",
+        );
+
+        let mut splitter = Splitter::new(&repo, "/lib/").unwrap();
+        let split = splitter.split(&merge).unwrap().unwrap();
+        assert_eq!(splitter.stats(), SplitStats { processed: 5, created: 2 });
+        splitter.save_cache().unwrap();
+
+        // 合并的另一侧没有修改 lib，因此合并被折叠为 side 的导出提交
+        let head = repo.read_commit(&split).unwrap();
+        assert_eq!(head.message, "side\n");
+        let lib = repo.find_path(&repo.read_commit(&merge).unwrap().tree, "lib").unwrap().unwrap();
+        assert_eq!(head.tree, lib.id);
+        let root = repo.read_commit(&head.parents[0]).unwrap();
+        assert_eq!(root.message, "add lib\n");
+        assert!(root.parents.is_empty());
+        assert_eq!(splitter.split(&before).unwrap(), None);
+
+        // 新实例从缓存读取映射，只处理新增的提交；结果与不使用缓存时相同
+        let removed = commit_files(&repo, &[("other/a", b"3")], &[merge], "remove lib");
+        let readd = commit_files(&repo, &[("lib/x.rs", b"2")], &[removed], "re-add lib");
+        let mut cached = Splitter::new(&repo, "lib").unwrap();
+        let next = cached.split(&readd).unwrap().unwrap();
+        assert_eq!(cached.stats(), SplitStats { processed: 2, created: 1 });
+        assert_eq!(repo.read_commit(&next).unwrap().parents, [split]);
+        cached.save_cache().unwrap();
+
+        std::fs::remove_dir_all(repo.mono_dir().join(SPLIT_DIR)).unwrap();
+        let mut fresh = Splitter::new(&repo, "lib").unwrap();
+        assert_eq!(fresh.split(&readd).unwrap(), Some(next));
+
+        assert!(Splitter::new(&repo, "a/../b").is_err());
+    }
+}
@@ -0,0 +1,103 @@
+//! 在服务端检出提交并运行命令
+//!
+//! 合并队列的校验命令（见 [`crate::queue`]）与服务端二分查找（见 [`crate::bisect`]）都需要
+//! 把某个提交检出到临时工作目录、在其中运行 shell 命令并取得结果：命令以 `sh -c` 运行，
+//! 标准输入为空，标准输出与标准错误写入日志文件，超时后被终止；工作目录在命令结束后删除。
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
+use std::time::{Duration, Instant};
+
+use crate::common::MonoResult;
+use crate::object::ObjectId;
+use crate::repo::Repository;
+use crate::worktree;
+
+/// 结果中保留的输出末尾行数
+const LOG_TAIL_LINES: usize = 20;
+
+/// 一次运行的结果
+#[derive(Debug, Clone)]
+pub struct RunOutput {
+    /// 命令的退出状态，超时被终止时为 None
+    pub status: Option<ExitStatus>,
+    /// 输出的末尾若干行
+    pub tail: String,
+}
+
+impl RunOutput {
+    pub fn success(&self) -> bool {
+        self.status.is_some_and(|status| status.success())
+    }
+
+    pub fn timed_out(&self) -> bool {
+        self.status.is_none()
+    }
+}
+
+/// 在提交的检出目录中运行命令
+pub struct Runner<'a> {
+    repo: &'a Repository,
+    work: PathBuf,
+    log: PathBuf,
+    timeout: Duration,
+}
+
+impl<'a> Runner<'a> {
+    /// 提交检出到 `work`，命令输出写入 `log`
+    pub fn new(repo: &'a Repository, work: PathBuf, log: PathBuf, timeout: Duration) -> Runner<'a> {
+        Runner { repo, work, log, timeout }
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// 检出 `commit` 并运行 `command`，`env` 为额外的环境变量
+    pub fn run(&self, commit: &ObjectId, command: &str, env: &[(&str, String)]) -> MonoResult<RunOutput> {
+        remove_dir_if_exists(&self.work)?;
+        std::fs::create_dir_all(&self.work)?;
+        let tree = self.repo.read_commit(commit)?.tree;
+        worktree::checkout_tree_to(self.repo, &tree, &self.work, |_, _| true)?;
+
+        if let Some(parent) = self.log.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let log = std::fs::File::create(&self.log)?;
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .current_dir(&self.work)
+            .envs(env.iter().map(|(name, value)| (name, value)))
+            .stdin(Stdio::null())
+            .stdout(Stdio::from(log.try_clone()?))
+            .stderr(Stdio::from(log))
+            .spawn()?;
+        let started = Instant::now();
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break Some(status);
+            }
+            if started.elapsed() >= self.timeout {
+                let _ = child.kill();
+                let _ = child.wait();
+                break None;
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        };
+        remove_dir_if_exists(&self.work)?;
+
+        let output = std::fs::read_to_string(&self.log).unwrap_or_default();
+        let lines: Vec<&str> = output.lines().collect();
+        let tail = lines[lines.len().saturating_sub(LOG_TAIL_LINES)..].join("\n");
+        Ok(RunOutput { status, tail })
+    }
+}
+
+pub(crate) fn remove_dir_if_exists(path: &Path) -> MonoResult<()> {
+    match std::fs::remove_dir_all(path) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
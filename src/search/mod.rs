@@ -0,0 +1,615 @@
+//! 全文代码搜索
+//!
+//! 为 `mono.toml` 中配置的分支顶端的文件内容建立三元组（trigram）索引：
+//!
+//! ```toml
+//! [search]
+//! branches = ["main", "release/2.0"]
+//! max_file_size = 1048576
+//! ```
+//!
+//! 每个分支一个索引文件 `.mono/search/<引用名哈希>.json`，记录建立索引时的提交、每个路径对应的
+//! blob，以及每个三元组出现在哪些 blob 中。三元组取自转为 ASCII 小写后的内容，区分与不区分大小写的
+//! 查询共用同一份索引。二进制文件（前 8000 字节中含有 NUL）与超过 `max_file_size` 的文件不建立索引。
+//!
+//! 查询先从正则表达式中提取每个匹配必定以之开头的字面量，用它们的三元组筛选出候选 blob，再逐行
+//! 匹配候选文件；提取不出足够长的字面量时扫描全部已索引的文件。分支移动后只重新读取两次提交之间
+//! 改动的文件：推送后由 [`crate::hooks::Hooks::post_receive`] 更新，查询前也会先补上遗漏的更新。
+//!
+//! 索引同时记录每个文件中的符号定义（见 [`symbols`]）。[`CodeSearch::lookup`] 按名字查找定义，
+//! 再用三元组筛选出包含该名字的文件，解析语法树找出同名标识符作为引用，注释与字符串中的同名文本不计入；
+//! 引用按名字而不是按作用域匹配，不同作用域中的同名标识符同样会被列出。没有语法库的语言退回到整词的
+//! 文本匹配，单独列出。
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::path::PathBuf;
+
+use regex::{Regex, RegexBuilder};
+use regex_syntax::hir::literal::Extractor;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::object::{ObjectId, ObjectType};
+use crate::refs::{self, RefUpdate};
+use crate::repo::Repository;
+use crate::sparse::SparsePattern;
+use symbols::{Symbol, SymbolKind};
+
+pub mod symbols;
+
+/// 搜索索引相对于 `.mono` 目录的路径
+pub const SEARCH_DIR: &str = "search";
+
+/// 索引格式版本，读到其他版本的索引时重建
+const INDEX_VERSION: u32 = 3;
+
+/// 判断二进制文件时检查的前缀长度，与 git 相同
+const BINARY_CHECK_LEN: usize = 8000;
+
+/// 三元组，三个字节依次放在低 24 位
+type Trigram = u32;
+
+/// 一个分支的索引
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct Index {
+    #[serde(default)]
+    version: u32,
+    /// 完整引用名
+    branch: String,
+    /// 建立索引时分支指向的提交
+    commit: Option<ObjectId>,
+    /// 已索引的路径及其 blob 在 `blobs` 中的位置
+    files: BTreeMap<String, u32>,
+    blobs: Vec<ObjectId>,
+    /// 三元组出现在哪些 blob 中，按位置升序排列
+    postings: HashMap<Trigram, Vec<u32>>,
+    /// 每个文件中的符号定义，只包含支持的语言且有定义的文件
+    #[serde(default)]
+    symbols: BTreeMap<String, Vec<Symbol>>,
+}
+
+impl Index {
+    fn new(branch: &str) -> Index {
+        Index {
+            version: INDEX_VERSION,
+            branch: branch.to_string(),
+            ..Default::default()
+        }
+    }
+}
+
+/// 一次查询
+#[derive(Debug, Clone)]
+pub struct SearchQuery {
+    /// 正则表达式，`literal` 为 true 时按字面匹配
+    pub pattern: String,
+    pub literal: bool,
+    pub ignore_case: bool,
+    /// 只搜索这些路径模式下的文件，为空时搜索整个仓库
+    pub paths: Vec<SparsePattern>,
+    /// 至多返回的匹配行数
+    pub limit: usize,
+}
+
+/// 匹配的一行
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct SearchMatch {
+    pub branch: String,
+    pub path: String,
+    /// 行号，从 1 开始
+    pub line_number: usize,
+    /// 去掉换行符的行内容
+    pub line: String,
+}
+
+/// 一个符号定义的位置
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Definition {
+    pub branch: String,
+    pub path: String,
+    pub name: String,
+    pub kind: SymbolKind,
+    pub line_number: usize,
+    pub line: String,
+}
+
+/// 符号的定义、引用与文本匹配
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct SymbolLookup {
+    pub definitions: Vec<Definition>,
+    /// 语法树中出现同名标识符的行，定义处的名字不算引用
+    pub references: Vec<SearchMatch>,
+    /// 没有语法库的语言中整词出现该名字的行，不含定义所在的行；包括注释与字符串
+    pub textual_matches: Vec<SearchMatch>,
+}
+
+/// 仓库的代码搜索索引
+pub struct CodeSearch<'a> {
+    repo: &'a Repository,
+    dir: PathBuf,
+}
+
+impl<'a> CodeSearch<'a> {
+    pub fn new(repo: &'a Repository) -> CodeSearch<'a> {
+        CodeSearch {
+            repo,
+            dir: repo.mono_dir().join(SEARCH_DIR),
+        }
+    }
+
+    /// 配置中建立索引的分支的完整引用名
+    pub fn branches(&self) -> Vec<String> {
+        self.repo
+            .config()
+            .search
+            .branches
+            .iter()
+            .map(|branch| {
+                if branch.starts_with("refs/") {
+                    branch.clone()
+                } else {
+                    format!("{}{}", refs::HEADS_PREFIX, branch)
+                }
+            })
+            .collect()
+    }
+
+    /// 更新全部分支的索引，返回重新读取的文件数
+    pub fn update(&self) -> MonoResult<usize> {
+        let mut updated = 0;
+        for branch in self.branches() {
+            updated += self.update_branch(&branch)?;
+        }
+        Ok(updated)
+    }
+
+    /// 推送之后更新被推送的分支的索引，失败只记录警告
+    pub fn update_refs(&self, updates: &[RefUpdate]) {
+        for branch in self.branches() {
+            if !updates.iter().any(|update| update.name == branch) {
+                continue;
+            }
+            if let Err(e) = self.update_branch(&branch) {
+                tracing::warn!(branch = %branch, error = %e, "failed to update search index");
+            }
+        }
+    }
+
+    /// 把分支的索引更新到分支当前指向的提交，只读取两次提交之间改动的文件
+    ///
+    /// 上次索引的提交已不存在（例如被 gc 清理）时重建整个索引。
+    pub fn update_branch(&self, branch: &str) -> MonoResult<usize> {
+        let mut index = self
+            .load(branch)?
+            .filter(|index| index.version == INDEX_VERSION)
+            .unwrap_or_else(|| Index::new(branch));
+        let tip = self.repo.refs().resolve(branch)?;
+        if index.commit == tip {
+            return Ok(0);
+        }
+        let old_tree = match index.commit {
+            Some(commit) => match self.repo.read_commit(&commit) {
+                Ok(commit) => Some(commit.tree),
+                Err(e) => {
+                    tracing::info!(branch = %branch, error = %e, "rebuilding search index");
+                    index = Index::new(branch);
+                    None
+                }
+            },
+            None => None,
+        };
+        let new_tree = match tip {
+            Some(tip) => Some(self.repo.read_commit(&tip)?.tree),
+            None => None,
+        };
+        let max_file_size = self.repo.config().search.max_file_size;
+        let mut slots: HashMap<ObjectId, u32> = index.blobs.iter().enumerate().map(|(i, id)| (*id, i as u32)).collect();
+        let mut updated = 0;
+        for path in self.repo.changed_paths(old_tree.as_ref(), new_tree.as_ref())? {
+            index.files.remove(&path);
+            index.symbols.remove(&path);
+            let Some(tree) = &new_tree else { continue };
+            let Some(entry) = self.repo.find_path(tree, &path)?.filter(|entry| entry.mode.is_blob()) else {
+                continue;
+            };
+            updated += 1;
+            let object = self.repo.read_object(&entry.id)?;
+            if object.object_type != ObjectType::Blob || object.data.len() as u64 > max_file_size || is_binary(&object.data) {
+                continue;
+            }
+            let symbols = symbols::extract(&path, &object.data);
+            if !symbols.is_empty() {
+                index.symbols.insert(path.clone(), symbols);
+            }
+            if let Some(slot) = slots.get(&entry.id) {
+                index.files.insert(path, *slot);
+                continue;
+            }
+            let slot = index.blobs.len() as u32;
+            index.blobs.push(entry.id);
+            slots.insert(entry.id, slot);
+            for trigram in trigrams(&object.data) {
+                index.postings.entry(trigram).or_default().push(slot);
+            }
+            index.files.insert(path, slot);
+        }
+        index.commit = tip;
+        compact(&mut index);
+        self.save(&index)?;
+        Ok(updated)
+    }
+
+    /// 在全部分支的索引中查找，结果按分支、路径与行号排列
+    pub fn search(&self, query: &SearchQuery) -> MonoResult<Vec<SearchMatch>> {
+        let branches = self.branches();
+        if branches.is_empty() {
+            return Err(MonoError::config("no branches are indexed for search; set [search] branches in mono.toml"));
+        }
+        let pattern = if query.literal {
+            regex::escape(&query.pattern)
+        } else {
+            query.pattern.clone()
+        };
+        let regex = RegexBuilder::new(&pattern)
+            .case_insensitive(query.ignore_case)
+            .build()
+            .map_err(|e| MonoError::usage(format!("invalid pattern: {}", e)))?;
+        let required = required_trigrams(&pattern);
+
+        let mut matches = Vec::new();
+        for branch in branches {
+            let Some(index) = self.load(&branch)? else { continue };
+            let candidates = candidates(&index, required.as_deref());
+            for (path, slot) in &index.files {
+                if !candidates.as_ref().is_none_or(|candidates| candidates.contains(slot)) {
+                    continue;
+                }
+                if !query.paths.is_empty() && !query.paths.iter().any(|pattern| pattern.matches(path)) {
+                    continue;
+                }
+                let data = self.repo.read_object(&index.blobs[*slot as usize])?.data;
+                if !search_blob(&regex, &branch, path, &data, query.limit, &mut matches) {
+                    return Ok(matches);
+                }
+            }
+        }
+        Ok(matches)
+    }
+
+    /// 按名字查找符号的定义与引用，可按种类与路径过滤定义；引用与文本匹配合计至多返回 `limit` 行
+    pub fn lookup(&self, name: &str, kind: Option<SymbolKind>, paths: &[SparsePattern], limit: usize) -> MonoResult<SymbolLookup> {
+        let mut lookup = SymbolLookup::default();
+        let word = Regex::new(&format!(r"\b{}\b", regex::escape(name))).map_err(|e| MonoError::usage(e.to_string()))?;
+        let required = required_trigrams(&regex::escape(name));
+        for branch in self.branches() {
+            let Some(index) = self.load(&branch)? else { continue };
+            for (path, symbols) in &index.symbols {
+                if !paths.is_empty() && !paths.iter().any(|pattern| pattern.matches(path)) {
+                    continue;
+                }
+                let mut found = symbols
+                    .iter()
+                    .filter(|symbol| symbol.name == name && kind.is_none_or(|kind| symbol.kind == kind))
+                    .peekable();
+                if found.peek().is_none() {
+                    continue;
+                }
+                let data = self.repo.read_object(&index.blobs[index.files[path] as usize])?.data;
+                let text = String::from_utf8_lossy(&data);
+                let lines: Vec<&str> = text.lines().collect();
+                for symbol in found {
+                    lookup.definitions.push(Definition {
+                        branch: branch.clone(),
+                        path: path.clone(),
+                        name: symbol.name.clone(),
+                        kind: symbol.kind,
+                        line_number: symbol.line,
+                        line: lines.get(symbol.line - 1).map_or_else(String::new, |line| line.to_string()),
+                    });
+                }
+            }
+            let candidates = candidates(&index, required.as_deref());
+            for (path, slot) in &index.files {
+                let remaining = limit.saturating_sub(lookup.references.len() + lookup.textual_matches.len());
+                if remaining == 0 {
+                    break;
+                }
+                if !candidates.as_ref().is_none_or(|candidates| candidates.contains(slot)) {
+                    continue;
+                }
+                if !paths.is_empty() && !paths.iter().any(|pattern| pattern.matches(path)) {
+                    continue;
+                }
+                let data = self.repo.read_object(&index.blobs[*slot as usize])?.data;
+                let text = String::from_utf8_lossy(&data);
+                let lines: Vec<&str> = text.lines().collect();
+                let (line_numbers, found) = match symbols::references(path, &data, name) {
+                    Some(line_numbers) => (line_numbers, &mut lookup.references),
+                    None => {
+                        let defined: HashSet<usize> = index
+                            .symbols
+                            .get(path)
+                            .into_iter()
+                            .flatten()
+                            .filter(|symbol| symbol.name == name)
+                            .map(|symbol| symbol.line)
+                            .collect();
+                        let line_numbers = (1..=lines.len())
+                            .filter(|n| !defined.contains(n) && word.is_match(lines[n - 1]))
+                            .collect();
+                        (line_numbers, &mut lookup.textual_matches)
+                    }
+                };
+                for line_number in line_numbers.into_iter().take(remaining) {
+                    found.push(SearchMatch {
+                        branch: branch.clone(),
+                        path: path.clone(),
+                        line_number,
+                        line: lines.get(line_number - 1).map_or_else(String::new, |line| line.to_string()),
+                    });
+                }
+            }
+        }
+        Ok(lookup)
+    }
+
+    fn index_path(&self, branch: &str) -> PathBuf {
+        let key: String = Sha256::digest(branch.as_bytes()).iter().map(|b| format!("{:02x}", b)).collect();
+        self.dir.join(format!("{}.json", key))
+    }
+
+    fn load(&self, branch: &str) -> MonoResult<Option<Index>> {
+        let path = self.index_path(branch);
+        let data = match std::fs::read(&path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        serde_json::from_slice(&data)
+            .map(Some)
+            .map_err(|e| MonoError::storage(format!("corrupt search index {}: {}", path.display(), e)))
+    }
+
+    fn save(&self, index: &Index) -> MonoResult<()> {
+        std::fs::create_dir_all(&self.dir)?;
+        let data = serde_json::to_vec(index).map_err(|e| MonoError::storage(e.to_string()))?;
+        let path = self.index_path(&index.branch);
+        let tmp = path.with_extension(format!("json.{}.tmp", std::process::id()));
+        std::fs::write(&tmp, data)?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+}
+
+/// 逐行匹配一个文件，达到数量上限时返回 false
+fn search_blob(regex: &Regex, branch: &str, path: &str, data: &[u8], limit: usize, matches: &mut Vec<SearchMatch>) -> bool {
+    let text = String::from_utf8_lossy(data);
+    for (i, line) in text.lines().enumerate() {
+        if matches.len() >= limit {
+            return false;
+        }
+        if regex.is_match(line) {
+            matches.push(SearchMatch {
+                branch: branch.to_string(),
+                path: path.to_string(),
+                line_number: i + 1,
+                line: line.to_string(),
+            });
+        }
+    }
+    matches.len() < limit
+}
+
+/// 丢弃不再被任何路径引用的 blob，重新编号
+fn compact(index: &mut Index) {
+    let used: BTreeSet<u32> = index.files.values().copied().collect();
+    if used.len() == index.blobs.len() {
+        return;
+    }
+    let remap: HashMap<u32, u32> = used.iter().enumerate().map(|(new, old)| (*old, new as u32)).collect();
+    index.blobs = used.iter().map(|slot| index.blobs[*slot as usize]).collect();
+    for slot in index.files.values_mut() {
+        *slot = remap[slot];
+    }
+    index.postings.retain(|_, slots| {
+        slots.retain_mut(|slot| match remap.get(slot) {
+            Some(new) => {
+                *slot = *new;
+                true
+            }
+            None => false,
+        });
+        !slots.is_empty()
+    });
+}
+
+fn is_binary(data: &[u8]) -> bool {
+    data[..data.len().min(BINARY_CHECK_LEN)].contains(&0)
+}
+
+/// 内容中出现的全部三元组，取自 ASCII 小写形式
+fn trigrams(data: &[u8]) -> HashSet<Trigram> {
+    data.windows(3)
+        .map(|w| {
+            let [a, b, c] = [w[0], w[1], w[2]].map(|byte| byte.to_ascii_lowercase() as u32);
+            (a << 16) | (b << 8) | c
+        })
+        .collect()
+}
+
+/// 每个匹配必定以其中之一开头的字面量各自的三元组；提取不出或有字面量短于三个字节时返回 None
+fn required_trigrams(pattern: &str) -> Option<Vec<HashSet<Trigram>>> {
+    let hir = regex_syntax::Parser::new().parse(pattern).ok()?;
+    let seq = Extractor::new().extract(&hir);
+    let literals = seq.literals()?;
+    if literals.is_empty() || literals.iter().any(|literal| literal.as_bytes().len() < 3) {
+        return None;
+    }
+    Some(literals.iter().map(|literal| trigrams(literal.as_bytes())).collect())
+}
+
+/// 至少包含一组字面量的全部三元组的 blob；None 表示需要扫描全部 blob
+fn candidates(index: &Index, required: Option<&[HashSet<Trigram>]>) -> Option<HashSet<u32>> {
+    let required = required?;
+    let mut candidates = HashSet::new();
+    for trigrams in required {
+        let mut lists: Vec<&Vec<u32>> = Vec::with_capacity(trigrams.len());
+        for trigram in trigrams {
+            match index.postings.get(trigram) {
+                Some(list) => lists.push(list),
+                None => {
+                    lists.clear();
+                    break;
+                }
+            }
+        }
+        // 从最短的列表开始求交集
+        lists.sort_by_key(|list| list.len());
+        let Some((first, rest)) = lists.split_first() else { continue };
+        candidates.extend(
+            first
+                .iter()
+                .filter(|slot| rest.iter().all(|list| list.binary_search(slot).is_ok())),
+        );
+    }
+    Some(candidates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{commit_files, init_repo};
+
+    fn query(pattern: &str, literal: bool) -> SearchQuery {
+        SearchQuery {
+            pattern: pattern.to_string(),
+            literal,
+            ignore_case: false,
+            paths: Vec::new(),
+            limit: 100,
+        }
+    }
+
+    /// 测试建立索引、按正则与路径查找、查找符号，分支移动后增量更新并丢弃不再引用的 blob
+    #[test]
+    fn test_search() {
+        let (_dir, mut repo) = init_repo();
+        repo.config_mut().search.branches = vec!["main".to_string()];
+        let first = commit_files(
+            &repo,
+            &[
+                ("services/api/main.rs", b"fn main() {\n    serve_http();\n}\n"),
+                ("libs/http/lib.rs", b"pub fn serve_http() {}\n"),
+                ("image.png", b"PNG\0serve_http"),
+            ],
+            &[],
+            "first",
+        );
+        repo.refs()
+            .update(&[RefUpdate {
+                name: "refs/heads/main".to_string(),
+                old: ObjectId::ZERO,
+                new: first,
+            }])
+            .unwrap();
+        let search = CodeSearch::new(&repo);
+        assert_eq!(search.update().unwrap(), 3);
+        assert_eq!(search.update().unwrap(), 0);
+
+        let found = search.search(&query("serve_http", true)).unwrap();
+        let paths: Vec<(&str, usize)> = found.iter().map(|m| (m.path.as_str(), m.line_number)).collect();
+        assert_eq!(paths, vec![("libs/http/lib.rs", 1), ("services/api/main.rs", 2)]);
+        let mut scoped = query(r"serve_\w+\(", false);
+        scoped.paths = vec!["//services/...".parse().unwrap()];
+        let found = search.search(&scoped).unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].line, "    serve_http();");
+        let mut insensitive = query("SERVE_HTTP", true);
+        insensitive.ignore_case = true;
+        assert_eq!(search.search(&insensitive).unwrap().len(), 2);
+
+        // 符号查找返回定义，引用不含定义处的名字
+        let lookup = search.lookup("serve_http", None, &[], 10).unwrap();
+        assert_eq!(lookup.definitions.len(), 1);
+        assert_eq!(lookup.definitions[0].path, "libs/http/lib.rs");
+        assert_eq!(lookup.definitions[0].kind, SymbolKind::Function);
+        let references: Vec<(&str, usize)> = lookup.references.iter().map(|m| (m.path.as_str(), m.line_number)).collect();
+        assert_eq!(references, vec![("services/api/main.rs", 2)]);
+        assert!(lookup.textual_matches.is_empty());
+        assert!(search.lookup("serve_http", Some(SymbolKind::Struct), &[], 10).unwrap().definitions.is_empty());
+
+        // 推送后只重新读取改动的文件，旧内容不再能被找到
+        let second = commit_files(
+            &repo,
+            &[
+                ("services/api/main.rs", b"fn main() {\n    serve_grpc();\n}\n"),
+                ("libs/http/lib.rs", b"pub fn serve_http() {}\n"),
+            ],
+            &[first],
+            "second",
+        );
+        let update = RefUpdate {
+            name: "refs/heads/main".to_string(),
+            old: first,
+            new: second,
+        };
+        repo.refs().update(std::slice::from_ref(&update)).unwrap();
+        search.update_refs(&[update]);
+        assert_eq!(search.update().unwrap(), 0);
+        let index = search.load("refs/heads/main").unwrap().unwrap();
+        assert_eq!(index.commit, Some(second));
+        assert_eq!(index.blobs.len(), 2);
+        assert_eq!(search.search(&query("serve_http", true)).unwrap().len(), 1);
+        assert_eq!(search.search(&query("serve_grpc", true)).unwrap()[0].path, "services/api/main.rs");
+        assert!(search.search(&query("(", false)).is_err());
+    }
+
+    /// 测试引用来自语法树，注释与字符串不计入；没有语法库的语言退回到文本匹配，引用数受上限约束
+    #[test]
+    fn test_lookup() {
+        let (_dir, mut repo) = init_repo();
+        repo.config_mut().search.branches = vec!["main".to_string()];
+        let commit = commit_files(
+            &repo,
+            &[
+                (
+                    "services/api/server.rs",
+                    b"// FooService handles requests\nuse crate::FooService;\n\nfn main() {\n    let s = \"FooService\";\n    FooService::new(s).run();\n}\n",
+                ),
+                ("services/api/service.go", b"package api\n\ntype FooService struct{}\n\nfunc New() *FooService { return &FooService{} }\n"),
+                ("proto/api.proto", b"// FooService API\nservice FooService {\n  rpc Get(Req) returns (Resp);\n}\n"),
+            ],
+            &[],
+            "first",
+        );
+        repo.refs()
+            .update(&[RefUpdate {
+                name: "refs/heads/main".to_string(),
+                old: ObjectId::ZERO,
+                new: commit,
+            }])
+            .unwrap();
+        let search = CodeSearch::new(&repo);
+        search.update().unwrap();
+
+        let lookup = search.lookup("FooService", None, &[], 10).unwrap();
+        let definitions: Vec<(&str, usize, SymbolKind)> =
+            lookup.definitions.iter().map(|d| (d.path.as_str(), d.line_number, d.kind)).collect();
+        assert_eq!(
+            definitions,
+            vec![("proto/api.proto", 2, SymbolKind::Service), ("services/api/service.go", 3, SymbolKind::Struct)]
+        );
+        let references: Vec<(&str, usize)> = lookup.references.iter().map(|m| (m.path.as_str(), m.line_number)).collect();
+        assert_eq!(references, vec![("services/api/server.rs", 2), ("services/api/server.rs", 6), ("services/api/service.go", 5)]);
+        let matches: Vec<(&str, usize)> = lookup.textual_matches.iter().map(|m| (m.path.as_str(), m.line_number)).collect();
+        assert_eq!(matches, vec![("proto/api.proto", 1)]);
+
+        let limited = search.lookup("FooService", None, &[], 2).unwrap();
+        assert_eq!(limited.references.len() + limited.textual_matches.len(), 2);
+        let scoped = search.lookup("FooService", None, &["//services/...".parse().unwrap()], 10).unwrap();
+        assert_eq!(scoped.definitions.len(), 1);
+        assert!(scoped.textual_matches.is_empty());
+    }
+}
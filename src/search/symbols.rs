@@ -0,0 +1,422 @@
+//! 按语言提取符号的定义与引用
+//!
+//! 有 tree-sitter 语法库的语言（Rust、Go、Python、Java、JavaScript、TypeScript）先解析为语法树：定义是
+//! 函数、类型、常量等声明节点的名字，引用是与符号同名的其他标识符节点，注释与字符串不是标识符，其中出现的
+//! 名字不会被当作引用。其余语言（C/C++、Kotlin、Scala、Protobuf）是一组逐行匹配的正则规则，与
+//! universal-ctags 的正则解析器相同：只识别行首的定义语法，一行只取第一条匹配的规则，不提供引用。
+//! 同名的方法与函数一并归为 [`SymbolKind::Function`]。
+
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::sync::LazyLock;
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use tree_sitter::{Node, Parser, Tree};
+/// 符号的种类
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum SymbolKind {
+    Function,
+    Class,
+    Struct,
+    Enum,
+    Interface,
+    Trait,
+    Type,
+    Constant,
+    Module,
+    Message,
+    Service,
+}
+
+impl SymbolKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SymbolKind::Function => "function",
+            SymbolKind::Class => "class",
+            SymbolKind::Struct => "struct",
+            SymbolKind::Enum => "enum",
+            SymbolKind::Interface => "interface",
+            SymbolKind::Trait => "trait",
+            SymbolKind::Type => "type",
+            SymbolKind::Constant => "constant",
+            SymbolKind::Module => "module",
+            SymbolKind::Message => "message",
+            SymbolKind::Service => "service",
+        }
+    }
+}
+
+impl fmt::Display for SymbolKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(self.as_str())
+    }
+}
+
+/// 文件中的一个符号定义
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    /// 定义所在的行号，从 1 开始
+    pub line: usize,
+}
+
+/// 支持的语言
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Language {
+    Rust,
+    Go,
+    Python,
+    Java,
+    JavaScript,
+    TypeScript,
+    Tsx,
+    /// Kotlin 与 Scala
+    Kotlin,
+    Cpp,
+    Protobuf,
+}
+
+impl Language {
+    /// 按扩展名识别语言
+    fn of(path: &str) -> Option<Language> {
+        let extension = path.rsplit_once('.')?.1;
+        Some(match extension {
+            "rs" => Language::Rust,
+            "go" => Language::Go,
+            "py" | "pyi" => Language::Python,
+            "java" => Language::Java,
+            "js" | "jsx" | "mjs" | "cjs" => Language::JavaScript,
+            "ts" | "mts" | "cts" => Language::TypeScript,
+            "tsx" => Language::Tsx,
+            "kt" | "scala" => Language::Kotlin,
+            "c" | "h" | "cc" | "cpp" | "cxx" | "hh" | "hpp" => Language::Cpp,
+            "proto" => Language::Protobuf,
+            _ => return None,
+        })
+    }
+
+    /// 语言的 tree-sitter 语法，没有语法库的语言返回 None
+    fn grammar(&self) -> Option<tree_sitter::Language> {
+        Some(match self {
+            Language::Rust => tree_sitter_rust::LANGUAGE.into(),
+            Language::Go => tree_sitter_go::LANGUAGE.into(),
+            Language::Python => tree_sitter_python::LANGUAGE.into(),
+            Language::Java => tree_sitter_java::LANGUAGE.into(),
+            Language::JavaScript => tree_sitter_javascript::LANGUAGE.into(),
+            Language::TypeScript => tree_sitter_typescript::LANGUAGE_TYPESCRIPT.into(),
+            Language::Tsx => tree_sitter_typescript::LANGUAGE_TSX.into(),
+            Language::Kotlin | Language::Cpp | Language::Protobuf => return None,
+        })
+    }
+
+    /// 节点是定义时返回符号的种类与保存名字的字段
+    fn definition(&self, node: Node) -> Option<(SymbolKind, &'static str)> {
+        use SymbolKind::*;
+        let kind = match (self, node.kind()) {
+            (Language::Rust, "function_item" | "function_signature_item" | "macro_definition") => Function,
+            (Language::Rust, "struct_item" | "union_item") => Struct,
+            (Language::Rust, "enum_item") => Enum,
+            (Language::Rust, "trait_item") => Trait,
+            (Language::Rust, "type_item" | "associated_type") => Type,
+            (Language::Rust, "const_item" | "static_item") => Constant,
+            (Language::Rust, "mod_item") => Module,
+            (Language::Go, "function_declaration" | "method_declaration") => Function,
+            (Language::Go, "type_spec") => match node.child_by_field_name("type")?.kind() {
+                "struct_type" => Struct,
+                "interface_type" => Interface,
+                _ => Type,
+            },
+            (Language::Go, "type_alias") => Type,
+            // 函数体中的常量与变量是局部的，不是定义
+            (Language::Go, "const_spec" | "var_spec") if !in_function(node) => Constant,
+            (Language::Python, "function_definition") => Function,
+            (Language::Python, "class_definition") => Class,
+            (Language::Python, "assignment") => return python_constant(node).then_some((Constant, "left")),
+            (Language::Java, "method_declaration" | "constructor_declaration") => Function,
+            (Language::Java, "class_declaration" | "record_declaration") => Class,
+            (Language::Java, "interface_declaration" | "annotation_type_declaration") => Interface,
+            (Language::Java, "enum_declaration") => Enum,
+            (Language::JavaScript | Language::TypeScript | Language::Tsx, kind) => match kind {
+                "function_declaration"
+                | "generator_function_declaration"
+                | "method_definition"
+                | "function_signature"
+                | "method_signature"
+                | "abstract_method_signature" => Function,
+                "class_declaration" | "abstract_class_declaration" => Class,
+                "interface_declaration" => Interface,
+                "enum_declaration" => Enum,
+                "type_alias_declaration" => Type,
+                "internal_module" | "module" => Module,
+                // `const handler = async (req) => {}`
+                "variable_declarator"
+                    if node
+                        .child_by_field_name("value")
+                        .is_some_and(|value| matches!(value.kind(), "arrow_function" | "function_expression")) =>
+                {
+                    Function
+                }
+                _ => return None,
+            },
+            _ => return None,
+        };
+        Some((kind, "name"))
+    }
+
+    /// 没有语法库的语言的规则，每条规则的第一个捕获组是符号名
+    fn rules(&self) -> &'static [(SymbolKind, &'static str)] {
+        use SymbolKind::*;
+        match self {
+            Language::Kotlin => &[
+                (Class, r"^\s*(?:(?:public|protected|private|internal|static|final|abstract|sealed|open|data)\s+)*class\s+(\w+)"),
+                (Interface, r"^\s*(?:(?:public|protected|private|internal|static|sealed)\s+)*interface\s+(\w+)"),
+                (Trait, r"^\s*(?:sealed\s+)?trait\s+(\w+)"),
+                (Enum, r"^\s*(?:(?:public|protected|private|internal|static)\s+)*enum\s+(?:class\s+)?(\w+)"),
+                (Class, r"^\s*(?:(?:public|protected|private|internal|static|final|case)\s+)*(?:record|object)\s+(\w+)"),
+                (Function, r"^\s*(?:(?:public|protected|private|internal|override|suspend)\s+)*fun\s+(?:<[^>]*>\s*)?(?:\w+\.)?(\w+)"),
+                (Function, r"^\s*(?:(?:private|protected|override)\s+)*def\s+(\w+)"),
+            ],
+            Language::Cpp => &[
+                (Class, r"^\s*(?:template\s*<[^>]*>\s*)?class\s+(\w+)\s*(?:final\s*)?[:{]?\s*$"),
+                (Struct, r"^\s*(?:typedef\s+)?struct\s+(\w+)\s*[:{]?\s*$"),
+                (Enum, r"^\s*(?:typedef\s+)?enum\s+(?:class\s+)?(\w+)"),
+                (Module, r"^\s*namespace\s+(\w+)"),
+                (Constant, r"^\s*#\s*define\s+(\w+)"),
+                (Function, r"^(?:[\w:*&<>,]+\s+)+\**&?(\w+)\s*\([^;]*\)\s*(?:const\s*)?\{?\s*$"),
+            ],
+            Language::Protobuf => &[
+                (Message, r"^\s*message\s+(\w+)"),
+                (Service, r"^\s*service\s+(\w+)"),
+                (Function, r"^\s*rpc\s+(\w+)"),
+                (Enum, r"^\s*enum\s+(\w+)"),
+            ],
+            _ => &[],
+        }
+    }
+}
+
+/// 节点是否在 Go 的函数体中
+fn in_function(node: Node) -> bool {
+    std::iter::successors(node.parent(), Node::parent)
+        .any(|node| matches!(node.kind(), "function_declaration" | "method_declaration" | "func_literal"))
+}
+
+/// 模块顶层对全大写名字的赋值，Python 约定为常量
+fn python_constant(node: Node) -> bool {
+    let top_level = node
+        .parent()
+        .filter(|parent| parent.kind() == "expression_statement")
+        .and_then(|parent| parent.parent())
+        .is_some_and(|module| module.kind() == "module");
+    top_level && node.child_by_field_name("left").is_some_and(|left| left.kind() == "identifier")
+}
+
+/// 编译后的一种语言的规则
+type CompiledRules = Vec<(SymbolKind, Regex)>;
+
+/// 编译后的各语言规则
+static RULES: LazyLock<Vec<(Language, CompiledRules)>> = LazyLock::new(|| {
+    [Language::Kotlin, Language::Cpp, Language::Protobuf]
+        .into_iter()
+        .map(|language| {
+            let rules = language
+                .rules()
+                .iter()
+                .map(|(kind, pattern)| (*kind, Regex::new(pattern).expect("symbol rules are valid")))
+                .collect();
+            (language, rules)
+        })
+        .collect()
+});
+
+/// Python 常量名
+static CONSTANT_NAME: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^[A-Z][A-Z0-9_]*$").expect("valid regex"));
+
+/// 文件中的符号定义，不支持的语言返回空
+pub fn extract(path: &str, data: &[u8]) -> Vec<Symbol> {
+    let Some(language) = Language::of(path) else {
+        return Vec::new();
+    };
+    if language.grammar().is_none() {
+        return extract_by_rules(language, data);
+    }
+    let mut symbols = Vec::new();
+    walk(language, data, |node, definition| {
+        if let Some(kind) = definition {
+            if let Ok(name) = node.utf8_text(data) {
+                // Python 的赋值都会被解析，只保留符合常量命名的
+                if language != Language::Python || kind != SymbolKind::Constant || CONSTANT_NAME.is_match(name) {
+                    symbols.push(Symbol { name: name.to_string(), kind, line: node.start_position().row + 1 });
+                }
+            }
+        }
+    });
+    symbols
+}
+
+/// 文件中引用 `name` 的行号，升序排列；定义处的名字不算引用，注释与字符串中的同名文本也不算
+///
+/// 语言没有语法库时返回 None，调用方只能退回到文本匹配。
+pub fn references(path: &str, data: &[u8], name: &str) -> Option<Vec<usize>> {
+    let language = Language::of(path)?;
+    language.grammar()?;
+    let mut lines = BTreeSet::new();
+    walk(language, data, |node, definition| {
+        if definition.is_none() && &data[node.byte_range()] == name.as_bytes() {
+            lines.insert(node.start_position().row + 1);
+        }
+    });
+    Some(lines.into_iter().collect())
+}
+
+fn extract_by_rules(language: Language, data: &[u8]) -> Vec<Symbol> {
+    let rules = &RULES.iter().find(|(l, _)| *l == language).expect("languages without a grammar have rules").1;
+    let text = String::from_utf8_lossy(data);
+    let mut symbols = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let found = rules
+            .iter()
+            .find_map(|(kind, regex)| regex.captures(line).map(|captures| (*kind, captures[1].to_string())));
+        if let Some((kind, name)) = found {
+            symbols.push(Symbol { name, kind, line: i + 1 });
+        }
+    }
+    symbols
+}
+
+fn parse(grammar: &tree_sitter::Language, data: &[u8]) -> Option<Tree> {
+    let mut parser = Parser::new();
+    parser.set_language(grammar).ok()?;
+    parser.parse(data, None)
+}
+
+/// 按源码顺序访问语法树中的标识符，定义的名字带有符号的种类
+///
+/// 无法解析的文件不访问任何节点；语法错误只影响出错的局部，其余部分照常访问。
+fn walk(language: Language, data: &[u8], mut visit: impl FnMut(Node, Option<SymbolKind>)) {
+    let Some(tree) = language.grammar().and_then(|grammar| parse(&grammar, data)) else {
+        return;
+    };
+    // 先序遍历时定义节点总在其名字之前访问
+    let mut names: HashMap<usize, SymbolKind> = HashMap::new();
+    let mut cursor = tree.walk();
+    loop {
+        let node = cursor.node();
+        if let Some((kind, field)) = language.definition(node) {
+            let mut children = node.walk();
+            for name in node.children_by_field_name(field, &mut children) {
+                if is_identifier(name) {
+                    names.insert(name.id(), kind);
+                }
+            }
+        }
+        if is_identifier(node) {
+            visit(node, names.get(&node.id()).copied());
+        }
+        if cursor.goto_first_child() || cursor.goto_next_sibling() {
+            continue;
+        }
+        loop {
+            if !cursor.goto_parent() {
+                return;
+            }
+            if cursor.goto_next_sibling() {
+                break;
+            }
+        }
+    }
+}
+
+/// 标识符叶子节点：`identifier`、`type_identifier`、`field_identifier` 等
+fn is_identifier(node: Node) -> bool {
+    node.is_named() && node.child_count() == 0 && node.kind().ends_with("identifier")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 测试各语言的定义被识别，调用、注释与局部变量不被当作定义
+    #[test]
+    fn test_extract() {
+        let names = |path: &str, source: &str| -> Vec<(String, SymbolKind, usize)> {
+            extract(path, source.as_bytes()).into_iter().map(|s| (s.name, s.kind, s.line)).collect()
+        };
+        let rust = "pub struct FooService {\n}\n\nimpl FooService {\n    pub async fn serve(&self) {}\n}\n\npub(crate) const LIMIT: usize = 1;\nfoo_service();\n";
+        assert_eq!(
+            names("src/foo.rs", rust),
+            vec![
+                ("FooService".to_string(), SymbolKind::Struct, 1),
+                ("serve".to_string(), SymbolKind::Function, 5),
+                ("LIMIT".to_string(), SymbolKind::Constant, 8),
+            ]
+        );
+        let go = "type FooService struct {}\ntype Handler interface {}\nfunc (s *FooService) Serve() {}\n";
+        assert_eq!(
+            names("foo.go", go),
+            vec![
+                ("FooService".to_string(), SymbolKind::Struct, 1),
+                ("Handler".to_string(), SymbolKind::Interface, 2),
+                ("Serve".to_string(), SymbolKind::Function, 3),
+            ]
+        );
+        let python = "class FooService:\n    # def commented\n    async def serve(self):\n        return serve()\n";
+        assert_eq!(
+            names("foo.py", python),
+            vec![("FooService".to_string(), SymbolKind::Class, 1), ("serve".to_string(), SymbolKind::Function, 3)]
+        );
+        let ts = "export class FooService {}\nexport const handler = async (req) => {}\n";
+        assert_eq!(
+            names("foo.ts", ts),
+            vec![("FooService".to_string(), SymbolKind::Class, 1), ("handler".to_string(), SymbolKind::Function, 2)]
+        );
+        let proto = "service FooService {\n  rpc Get(Req) returns (Resp);\n}\n";
+        assert_eq!(
+            names("api.proto", proto),
+            vec![("FooService".to_string(), SymbolKind::Service, 1), ("Get".to_string(), SymbolKind::Function, 2)]
+        );
+        let go = "package api\n\nconst Limit = 1\n\nfunc run() {\n\tvar local = 2\n}\n";
+        assert_eq!(
+            names("limit.go", go),
+            vec![("Limit".to_string(), SymbolKind::Constant, 3), ("run".to_string(), SymbolKind::Function, 5)]
+        );
+        let python = "MAX_SIZE = 10\nlower = 1\n\ndef f():\n    LOCAL = 2\n";
+        assert_eq!(
+            names("limits.py", python),
+            vec![("MAX_SIZE".to_string(), SymbolKind::Constant, 1), ("f".to_string(), SymbolKind::Function, 4)]
+        );
+        let java = "public interface Handler {}\n\nclass FooService implements Handler {\n  void serve() { serve(); }\n}\n";
+        assert_eq!(
+            names("FooService.java", java),
+            vec![
+                ("Handler".to_string(), SymbolKind::Interface, 1),
+                ("FooService".to_string(), SymbolKind::Class, 3),
+                ("serve".to_string(), SymbolKind::Function, 4),
+            ]
+        );
+        let kotlin = "data class FooService(val name: String)\nfun main() {}\n";
+        assert_eq!(
+            names("Foo.kt", kotlin),
+            vec![("FooService".to_string(), SymbolKind::Class, 1), ("main".to_string(), SymbolKind::Function, 2)]
+        );
+        assert!(extract("README.md", b"fn main() {}").is_empty());
+    }
+
+    /// 测试引用是同名的标识符：定义处的名字、注释与字符串不算，一行中出现多次只记一次
+    #[test]
+    fn test_references() {
+        let rust = "/// Builds a FooService\nstruct FooService;\n\nimpl FooService {\n    fn new() -> FooService { FooService }\n}\n\nfn main() {\n    println!(\"FooService\");\n    let foo_service = FooService::new();\n}\n";
+        assert_eq!(references("src/foo.rs", rust.as_bytes(), "FooService"), Some(vec![4, 5, 10]));
+        let python = "class FooService:\n    pass\n\n# FooService()\nservice = FooService()\n";
+        assert_eq!(references("foo.py", python.as_bytes(), "FooService"), Some(vec![5]));
+        let ts = "import { FooService } from './foo';\nconst s: FooService = new FooService(); // FooService\n";
+        assert_eq!(references("foo.ts", ts.as_bytes(), "FooService"), Some(vec![1, 2]));
+        let java = "class Main {\n  FooService service = new FooService();\n  String name = \"FooService\";\n}\n";
+        assert_eq!(references("Main.java", java.as_bytes(), "FooService"), Some(vec![2]));
+        assert_eq!(references("api.proto", b"service FooService {}\n", "FooService"), None);
+        assert_eq!(references("README.md", b"FooService", "FooService"), None);
+    }
+}
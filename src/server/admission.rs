@@ -0,0 +1,166 @@
+//! fetch 的准入控制
+//!
+//! upload-pack 算出要发送的对象后、开始写 pack 之前，按对象数与原始大小估算 pack 的成本：
+//! 超过 `max_objects` 或 `max_bytes` 的请求直接拒绝，提示客户端改用浅克隆或部分克隆；超过
+//! `large_objects` 或 `large_bytes` 的大请求排队，同时最多执行 `max_large` 个，排队超过
+//! `queue_timeout_secs` 时拒绝，客户端可以稍后重试。配置错误的 CI 反复完整克隆时，
+//! 其他客户端的增量 fetch 不受影响。
+
+use std::sync::{Condvar, LazyLock, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use crate::common::config::AdmissionConfig;
+use crate::common::errors::MonoError;
+use crate::common::progress::format_bytes;
+use crate::common::MonoResult;
+use crate::metrics;
+
+/// 服务进程的大请求名额，各传输层共用
+static LARGE: LazyLock<Slots> = LazyLock::new(Slots::default);
+
+/// 估算的 pack 成本
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cost {
+    pub objects: u64,
+    /// 对象的原始大小之和，压缩与 delta 之后的 pack 通常小得多
+    pub bytes: u64,
+}
+
+/// 检查 `principal` 的 fetch 能否执行；大请求返回的名额在 pack 写完之前不能释放
+pub fn admit(config: &AdmissionConfig, principal: &str, cost: Cost) -> MonoResult<Option<LargeFetch<'static>>> {
+    admit_with(&LARGE, config, principal, cost)
+}
+
+fn admit_with<'a>(
+    slots: &'a Slots,
+    config: &AdmissionConfig,
+    principal: &str,
+    cost: Cost,
+) -> MonoResult<Option<LargeFetch<'a>>> {
+    let exceeds = |limit: u64, value: u64| limit > 0 && value > limit;
+    if config.exempt.iter().any(|exempt| exempt == principal) {
+        metrics::admission_decided("admitted");
+        return Ok(None);
+    }
+    let limit = if exceeds(config.max_objects, cost.objects) {
+        Some(format!("{} objects", config.max_objects))
+    } else if exceeds(config.max_bytes, cost.bytes) {
+        Some(format_bytes(config.max_bytes))
+    } else {
+        None
+    };
+    if let Some(limit) = limit {
+        metrics::admission_decided("rejected");
+        tracing::info!(principal, objects = cost.objects, bytes = cost.bytes, "rejected fetch over the admission limit");
+        return Err(MonoError::usage(format!(
+            "fetch of {} objects ({}) exceeds the server limit of {}; fetch less history with --depth or use a partial clone with --filter=blob:none",
+            cost.objects,
+            format_bytes(cost.bytes),
+            limit
+        )));
+    }
+    if !exceeds(config.large_objects, cost.objects) && !exceeds(config.large_bytes, cost.bytes) {
+        metrics::admission_decided("admitted");
+        return Ok(None);
+    }
+    let timeout = Duration::from_secs(config.queue_timeout_secs);
+    match slots.acquire(config.max_large, timeout) {
+        Some((fetch, false)) => {
+            metrics::admission_decided("admitted");
+            Ok(Some(fetch))
+        }
+        Some((fetch, true)) => {
+            metrics::admission_decided("queued");
+            Ok(Some(fetch))
+        }
+        None => {
+            metrics::admission_decided("timed_out");
+            tracing::info!(principal, objects = cost.objects, bytes = cost.bytes, "large fetch timed out in the admission queue");
+            Err(MonoError::unavailable(format!(
+                "server busy: {} large fetches in progress; retry later",
+                config.max_large
+            )))
+        }
+    }
+}
+
+/// 大请求的并发名额
+#[derive(Debug, Default)]
+struct Slots {
+    active: Mutex<usize>,
+    freed: Condvar,
+}
+
+impl Slots {
+    /// 等待一个名额，超时返回 None；返回值中的布尔值表示是否排过队
+    fn acquire(&self, limit: usize, timeout: Duration) -> Option<(LargeFetch<'_>, bool)> {
+        let deadline = Instant::now() + timeout;
+        let mut active = self.active.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut queued = false;
+        while *active >= limit {
+            queued = true;
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return None;
+            }
+            active = self.freed.wait_timeout(active, remaining).unwrap_or_else(PoisonError::into_inner).0;
+        }
+        *active += 1;
+        metrics::admission_large_fetches(*active);
+        Some((LargeFetch(self), queued))
+    }
+}
+
+/// 正在执行的大请求，释放时归还名额
+#[derive(Debug)]
+pub struct LargeFetch<'a>(&'a Slots);
+
+impl Drop for LargeFetch<'_> {
+    fn drop(&mut self) {
+        let mut active = self.0.active.lock().unwrap_or_else(PoisonError::into_inner);
+        *active -= 1;
+        metrics::admission_large_fetches(*active);
+        self.0.freed.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 测试超过上限的请求被拒绝，大请求按名额排队，豁免的身份不受限制
+    #[test]
+    fn test_admission() {
+        let slots = Slots::default();
+        let config = AdmissionConfig {
+            large_objects: 100,
+            max_large: 1,
+            queue_timeout_secs: 0,
+            max_bytes: 1 << 20,
+            exempt: vec!["token:mirror".to_string()],
+            ..AdmissionConfig::default()
+        };
+        let small = Cost { objects: 10, bytes: 100 };
+        let large = Cost { objects: 1000, bytes: 1000 };
+        let huge = Cost { objects: 10, bytes: 2 << 20 };
+
+        assert!(admit_with(&slots, &config, "user:alice", small).unwrap().is_none());
+        let err = admit_with(&slots, &config, "user:alice", huge).unwrap_err();
+        assert!(err.to_string().contains("exceeds the server limit of 1.0 MiB"), "{}", err);
+        assert!(admit_with(&slots, &config, "token:mirror", huge).unwrap().is_none());
+
+        let first = admit_with(&slots, &config, "user:alice", large).unwrap().unwrap();
+        let busy = admit_with(&slots, &config, "user:bob", large).unwrap_err();
+        assert!(busy.is_retryable());
+        assert!(admit_with(&slots, &config, "user:bob", small).unwrap().is_none());
+
+        let config = AdmissionConfig { queue_timeout_secs: 5, ..config };
+        std::thread::scope(|scope| {
+            let waiter = scope.spawn(|| admit_with(&slots, &config, "user:bob", large).map(|fetch| fetch.is_some()));
+            std::thread::sleep(Duration::from_millis(20));
+            drop(first);
+            assert!(waiter.join().unwrap().unwrap());
+        });
+        assert_eq!(*slots.active.lock().unwrap(), 0);
+    }
+}
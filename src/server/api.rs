@@ -0,0 +1,2021 @@
+//! REST/JSON 仓库接口
+//!
+//! 供网页与外部工具浏览仓库，以及在服务端创建 cherry-pick 与 revert 提交，接口描述由 [`ApiDoc`] 生成，
+//! 通过 `GET /api/v1/openapi.json` 获取，可据此生成客户端代码：
+//!
+//! - `GET /api/v1/refs?prefix=`：引用列表
+//! - `GET /api/v1/commit?rev=`：提交详情
+//! - `GET /api/v1/log?rev=&path=&limit=`：提交历史，可按路径过滤
+//! - `GET /api/v1/tree?rev=&path=`：目录内容
+//! - `GET /api/v1/blob?rev=&path=`：文件原始内容
+//! - `GET /api/v1/diff?base=&head=`：两个修订之间改动的文件
+//! - `GET /api/v1/blame?rev=&path=`：文件每一行的来源提交
+//! - `GET /api/v1/archive?rev=&path=&format=tar|zip`：修订或其子目录的 tar/zip 快照，边生成边发送
+//!   （见 [`crate::export`]），`[[acl]]` 规则对请求的身份隐藏的路径不会出现在其中
+//! - `POST /api/v1/cherry-pick`：把提交挑选到分支上（见 [`crate::rewrite::pick`]），需要 `write` 权限
+//! - `POST /api/v1/revert`：在分支上撤销提交，需要 `write` 权限
+//! - `GET /api/v1/checks?rev=`：提交上的 CI 检查（见 [`crate::checks`]）
+//! - `GET /api/v1/verify?rev=`：提交或附注标签的签名验证结果（见 [`crate::signing`]）
+//! - `GET /api/v1/metadata?rev=`：提交尾注中的评审者、变更 ID 与修复的提交（见 [`crate::graph::trailers`]）
+//! - `GET /api/v1/trailers?key=&value=`：带有该尾注的提交，如 `key=Change-Id&value=I1234`
+//! - `GET /api/v1/releases?path=`：该目录或其下项目的发布，从新到旧（见 [`crate::release`]）
+//! - `GET /api/v1/releases/artifact?tag=&name=`：发布附件的原始内容
+//! - `POST /api/v1/checks`：CI 上报提交的检查状态，需要 `write` 权限
+//! - `GET /api/v1/reviews?state=`、`GET /api/v1/reviews/{id}`：变更请求（见 [`crate::review`]）
+//! - `POST /api/v1/reviews`：新建变更请求；`POST /api/v1/reviews/{id}/comments`、
+//!   `POST /api/v1/reviews/{id}/approvals` 与 `POST /api/v1/reviews/{id}/state`：评论、批准、
+//!   关闭或重新打开，需要 `write` 权限，作者与评审人为请求的身份
+//! - `POST /api/v1/bisect`：在服务端二分查找引入问题的提交（见 [`crate::bisect`]），查找在后台
+//!   运行；测试命令在服务端执行，需要 `admin` 权限
+//! - `GET /api/v1/bisect`、`GET /api/v1/bisect/{id}`：查找的进度与结果
+//!
+//! 分支名可能包含 `/`，修订与路径都通过查询参数传递；省略修订时使用 HEAD。`[[acl]]` 规则对请求的身份
+//! 隐藏的引用与路径按不存在处理，也不会出现在引用列表、目录内容与差异中（见 [`ReadView`]）。
+//! 出错时返回 [`ErrorReport`] 的 JSON 形式，状态码与 git HTTP 服务一致。
+
+use std::sync::Arc;
+
+use async_graphql::{Enum, SimpleObject};
+use axum::extract::{Path, Query, State};
+use axum::Extension;
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, OpenApi, ToSchema};
+
+use crate::auth::acl::{Acl, ReadView};
+use crate::auth::Access;
+use crate::bisect::{self, BisectSession, BisectState, BisectStep, Bisector, NewBisect, StepResult};
+use crate::blame::{BlameRange, Blamer};
+use crate::checks::{CheckState, CheckStatus, Checks};
+use crate::common::errors::{ErrorReport, MonoError, MonoErrorKind};
+use crate::common::config::Scope;
+use crate::common::MonoResult;
+use crate::export::{write_archive, ExportFormat, Snapshot};
+use crate::graph::history::History;
+use crate::graph::trailers::{self, Metadata, Trailer, TrailerIndex};
+use crate::merge::Conflict;
+use crate::object::commit::{Commit, Signature};
+use crate::object::tree::{FileMode, TreeEntry};
+use crate::object::{ObjectId, ObjectType};
+use crate::refs;
+use crate::release::{Artifact, Release, Releases};
+use crate::repo::Repository;
+use crate::review::{Anchor, Approval, ChangeRequest, Comment, NewChangeRequest, ReviewState, ReviewStore};
+use crate::rewrite::pick::{pick_onto_branch, PickKind, PickOptions, PickOutcome};
+use crate::server::blocking;
+use crate::server::http::stream_body;
+use crate::signing::{SignatureFormat, SignatureStatus, Verification, Verifier};
+
+/// `log` 未指定数量时返回的提交数
+const DEFAULT_LOG_LIMIT: usize = 100;
+
+/// `log` 单次最多返回的提交数
+const MAX_LOG_LIMIT: usize = 1000;
+
+/// OpenAPI 文档
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "monoengine", description = "Repository API"),
+    paths(
+        list_refs, get_commit, list_log, get_tree, get_blob, get_diff, get_blame, get_archive, cherry_pick, revert, list_checks, post_check,
+        verify, get_metadata, find_trailers, list_releases, get_artifact, list_reviews, get_review, create_review, comment_review, approve_review, set_review_state, list_bisects, get_bisect,
+        start_bisect
+    ),
+    components(schemas(
+        RefInfo, SignatureInfo, CommitInfo, EntryKind, EntryInfo, TreeInfo, ChangeStatus, FileChange, DiffInfo, BlameRangeInfo,
+        BlameInfo, PickRequest, CommitterInput, PickInfo, ConflictInfo, PickConflictInfo, CheckInfo, CheckRequest,
+        VerificationInfo, TrailerInfo, MetadataInfo, ReleaseInfo, ArtifactInfo, ReviewInfo, CommentInfo, AnchorInfo, ApprovalInfo, ReviewRequest, CommentRequest, ReviewStateRequest,
+        BisectInfo, BisectStepInfo, BisectRequest, ApiError
+    ))
+)]
+pub struct ApiDoc;
+
+/// 引用及其指向的对象
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct RefInfo {
+    /// 完整引用名，例如 `refs/heads/main`
+    pub name: String,
+    #[schema(value_type = String)]
+    pub target: ObjectId,
+}
+
+/// 作者或提交者
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema, SimpleObject)]
+#[graphql(name = "Signature")]
+pub struct SignatureInfo {
+    pub name: String,
+    pub email: String,
+    /// Unix 时间戳（秒）
+    pub timestamp: i64,
+    /// 时区偏移，例如 `+0800`
+    pub timezone: String,
+}
+
+impl From<&Signature> for SignatureInfo {
+    fn from(signature: &Signature) -> SignatureInfo {
+        SignatureInfo {
+            name: signature.name.clone(),
+            email: signature.email.clone(),
+            timestamp: signature.timestamp,
+            timezone: signature.timezone.clone(),
+        }
+    }
+}
+
+/// 提交
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct CommitInfo {
+    #[schema(value_type = String)]
+    pub id: ObjectId,
+    #[schema(value_type = String)]
+    pub tree: ObjectId,
+    #[schema(value_type = Vec<String>)]
+    pub parents: Vec<ObjectId>,
+    pub author: SignatureInfo,
+    pub committer: SignatureInfo,
+    pub message: String,
+}
+
+impl CommitInfo {
+    pub fn new(id: ObjectId, commit: &Commit) -> CommitInfo {
+        CommitInfo {
+            id,
+            tree: commit.tree,
+            parents: commit.parents.clone(),
+            author: SignatureInfo::from(&commit.author),
+            committer: SignatureInfo::from(&commit.committer),
+            message: commit.message.clone(),
+        }
+    }
+}
+
+/// 目录条目的类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema, Enum)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryKind {
+    File,
+    Directory,
+    Symlink,
+    Submodule,
+}
+
+impl EntryKind {
+    pub fn of(mode: FileMode) -> EntryKind {
+        if mode.is_tree() {
+            EntryKind::Directory
+        } else if mode.is_gitlink() {
+            EntryKind::Submodule
+        } else if mode == FileMode::SYMLINK {
+            EntryKind::Symlink
+        } else {
+            EntryKind::File
+        }
+    }
+}
+
+/// 目录条目
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct EntryInfo {
+    pub name: String,
+    pub kind: EntryKind,
+    /// 八进制文件模式，例如 `100644`
+    pub mode: String,
+    #[schema(value_type = String)]
+    pub id: ObjectId,
+}
+
+impl From<&TreeEntry> for EntryInfo {
+    fn from(entry: &TreeEntry) -> EntryInfo {
+        EntryInfo {
+            name: entry.name.clone(),
+            kind: EntryKind::of(entry.mode),
+            mode: format!("{:06o}", entry.mode.0),
+            id: entry.id,
+        }
+    }
+}
+
+/// 目录内容
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct TreeInfo {
+    #[schema(value_type = String)]
+    pub id: ObjectId,
+    pub entries: Vec<EntryInfo>,
+}
+
+/// 文件的改动类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeStatus {
+    Added,
+    Modified,
+    Deleted,
+}
+
+/// 改动的文件，`old` 与 `new` 分别为改动前后的对象，不存在时省略
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct FileChange {
+    pub path: String,
+    pub status: ChangeStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<String>)]
+    pub old: Option<ObjectId>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<String>)]
+    pub new: Option<ObjectId>,
+}
+
+/// 两个修订之间的改动
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct DiffInfo {
+    #[schema(value_type = String)]
+    pub base: ObjectId,
+    #[schema(value_type = String)]
+    pub head: ObjectId,
+    pub files: Vec<FileChange>,
+}
+
+/// 一段来源相同且行号连续的行
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct BlameRangeInfo {
+    /// 引入这些行的提交
+    #[schema(value_type = String)]
+    pub commit: ObjectId,
+    /// 在该提交的文件中的起始行号（从 1 开始）
+    pub origin_line: usize,
+    /// 在被查询的文件中的起始行号（从 1 开始）
+    pub line: usize,
+    pub len: usize,
+}
+
+impl From<&BlameRange> for BlameRangeInfo {
+    fn from(range: &BlameRange) -> BlameRangeInfo {
+        BlameRangeInfo {
+            commit: range.commit,
+            origin_line: range.origin_line,
+            line: range.line,
+            len: range.len,
+        }
+    }
+}
+
+/// 文件的 blame 结果，`commits` 包含各段引用的全部提交
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct BlameInfo {
+    #[schema(value_type = String)]
+    pub commit: ObjectId,
+    pub path: String,
+    pub ranges: Vec<BlameRangeInfo>,
+    pub commits: Vec<CommitInfo>,
+}
+
+/// cherry-pick 与 revert 的请求
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct PickRequest {
+    /// 挑选或撤销的提交
+    pub commit: String,
+    /// 目标分支，可以省略 `refs/heads/` 前缀
+    pub branch: String,
+    /// 合并提交以第几个父提交（从 1 开始）为基准
+    #[serde(default)]
+    pub mainline: Option<usize>,
+    /// cherry-pick 时在提交信息末尾注明来源提交
+    #[serde(default)]
+    pub record_origin: bool,
+    pub committer: CommitterInput,
+}
+
+/// 新提交的提交者，时间为服务端的当前时间
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct CommitterInput {
+    pub name: String,
+    pub email: String,
+}
+
+/// 创建的提交
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct PickInfo {
+    /// 完整分支名
+    pub branch: String,
+    /// 分支更新前指向的提交
+    #[schema(value_type = String)]
+    pub old: ObjectId,
+    /// 新创建的提交，分支已指向它
+    #[schema(value_type = String)]
+    pub commit: ObjectId,
+}
+
+/// 一处合并冲突
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct ConflictInfo {
+    /// 冲突类型，例如 `content`、`modify-delete`
+    pub kind: String,
+    pub path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub other_path: Option<String>,
+}
+
+impl From<&Conflict> for ConflictInfo {
+    fn from(conflict: &Conflict) -> ConflictInfo {
+        ConflictInfo {
+            kind: conflict.kind.to_string(),
+            path: conflict.path.clone(),
+            other_path: conflict.other_path.clone(),
+        }
+    }
+}
+
+/// 冲突时的响应，分支没有更新
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct PickConflictInfo {
+    pub conflicts: Vec<ConflictInfo>,
+}
+
+/// 提交上的一项 CI 检查
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct CheckInfo {
+    /// 检查名，例如 `ci/build`
+    pub context: String,
+    /// `pending`、`success` 或 `failure`
+    #[schema(value_type = String)]
+    pub state: CheckState,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// 上报者的身份
+    pub reporter: String,
+    /// Unix 时间戳（秒）
+    pub updated_at: i64,
+}
+
+impl From<CheckStatus> for CheckInfo {
+    fn from(status: CheckStatus) -> CheckInfo {
+        CheckInfo {
+            context: status.context,
+            state: status.state,
+            details_url: status.details_url,
+            description: status.description,
+            reporter: status.reporter,
+            updated_at: status.updated_at,
+        }
+    }
+}
+
+/// 提交或附注标签的签名验证结果
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct VerificationInfo {
+    #[schema(value_type = String)]
+    pub id: ObjectId,
+    /// `commit` 或 `tag`
+    pub object_type: String,
+    /// `unsigned`、`good`、`bad`、`unknown-key` 或 `unverified`
+    #[schema(value_type = String)]
+    pub status: SignatureStatus,
+    /// `ssh` 或 `gpg`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<String>)]
+    pub format: Option<SignatureFormat>,
+    /// 签名者：SSH 为 allowed signers 中的 principal，GPG 为公钥的用户 ID
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signer: Option<String>,
+    /// 签名的公钥指纹
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+}
+
+impl VerificationInfo {
+    pub fn new(id: ObjectId, object_type: ObjectType, verification: Verification) -> VerificationInfo {
+        VerificationInfo {
+            id,
+            object_type: object_type.to_string(),
+            status: verification.status,
+            format: verification.format,
+            signer: verification.signer,
+            key: verification.key,
+        }
+    }
+}
+
+/// 提交信息中的一条尾注
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct TrailerInfo {
+    pub key: String,
+    pub value: String,
+}
+
+impl From<Trailer> for TrailerInfo {
+    fn from(trailer: Trailer) -> TrailerInfo {
+        TrailerInfo { key: trailer.key, value: trailer.value }
+    }
+}
+
+/// 由提交尾注解析出的元数据
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct MetadataInfo {
+    #[schema(value_type = String)]
+    pub id: ObjectId,
+    /// `Change-Id:` 尾注
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub change_id: Option<String>,
+    /// `Reviewed-by:` 尾注
+    pub reviewed_by: Vec<String>,
+    /// `Fixes:` 尾注
+    pub fixes: Vec<String>,
+    /// 全部尾注，按在提交信息中的顺序
+    pub trailers: Vec<TrailerInfo>,
+}
+
+impl MetadataInfo {
+    pub fn new(id: ObjectId, metadata: Metadata) -> MetadataInfo {
+        MetadataInfo {
+            id,
+            change_id: metadata.change_id,
+            reviewed_by: metadata.reviewed_by,
+            fixes: metadata.fixes,
+            trailers: metadata.trailers.into_iter().map(TrailerInfo::from).collect(),
+        }
+    }
+}
+
+/// 发布的附件
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct ArtifactInfo {
+    pub name: String,
+    #[schema(value_type = String)]
+    pub id: ObjectId,
+    pub size: u64,
+}
+
+impl From<Artifact> for ArtifactInfo {
+    fn from(artifact: Artifact) -> ArtifactInfo {
+        ArtifactInfo { name: artifact.name, id: artifact.id, size: artifact.size }
+    }
+}
+
+/// 一个发布
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct ReleaseInfo {
+    /// 标签名，不含 `refs/tags/`
+    pub tag: String,
+    #[schema(value_type = String)]
+    pub commit: ObjectId,
+    /// 发布所属的项目目录
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub project: Option<String>,
+    pub title: String,
+    pub notes: String,
+    /// 创建者，格式为 `名字 <邮箱>`
+    pub author: String,
+    /// Unix 时间戳（秒）
+    pub created_at: i64,
+    pub artifacts: Vec<ArtifactInfo>,
+}
+
+impl From<Release> for ReleaseInfo {
+    fn from(release: Release) -> ReleaseInfo {
+        ReleaseInfo {
+            tag: release.tag,
+            commit: release.commit,
+            project: release.project,
+            title: release.title,
+            notes: release.notes,
+            author: release.author,
+            created_at: release.created_at,
+            artifacts: release.artifacts.into_iter().map(ArtifactInfo::from).collect(),
+        }
+    }
+}
+
+/// 上报检查状态的请求，同名检查之前的状态被替换
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct CheckRequest {
+    /// 检查的提交
+    pub commit: String,
+    pub context: String,
+    /// `pending`、`success` 或 `failure`
+    #[schema(value_type = String)]
+    pub state: CheckState,
+    #[serde(default)]
+    pub details_url: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+/// 服务端二分查找
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct BisectInfo {
+    pub id: u64,
+    #[schema(value_type = Vec<String>)]
+    pub good: Vec<ObjectId>,
+    #[schema(value_type = String)]
+    pub bad: ObjectId,
+    pub command: String,
+    pub timeout_secs: u64,
+    pub requested_by: String,
+    /// `running`、`found` 或 `failed`
+    #[schema(value_type = String)]
+    pub state: BisectState,
+    pub steps: Vec<BisectStepInfo>,
+    /// 第一个有问题的提交
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<String>)]
+    pub first_bad: Option<ObjectId>,
+    /// 因跳过而无法区分时，第一个有问题的提交在其中之一
+    #[schema(value_type = Vec<String>)]
+    pub suspects: Vec<ObjectId>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    pub created_at: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<i64>,
+}
+
+impl From<BisectSession> for BisectInfo {
+    fn from(session: BisectSession) -> BisectInfo {
+        BisectInfo {
+            id: session.id,
+            good: session.good,
+            bad: session.bad,
+            command: session.command,
+            timeout_secs: session.timeout_secs,
+            requested_by: session.requested_by,
+            state: session.state,
+            steps: session.steps.into_iter().map(BisectStepInfo::from).collect(),
+            first_bad: session.first_bad,
+            suspects: session.suspects,
+            reason: session.reason,
+            created_at: session.created_at,
+            finished_at: session.finished_at,
+        }
+    }
+}
+
+/// 测试过的一个提交
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct BisectStepInfo {
+    #[schema(value_type = String)]
+    pub commit: ObjectId,
+    /// `good`、`bad` 或 `skip`
+    #[schema(value_type = String)]
+    pub result: StepResult,
+    pub exit_code: i32,
+    /// 命令输出的末尾若干行
+    pub output: String,
+}
+
+impl From<BisectStep> for BisectStepInfo {
+    fn from(step: BisectStep) -> BisectStepInfo {
+        BisectStepInfo {
+            commit: step.commit,
+            result: step.result,
+            exit_code: step.exit_code,
+            output: step.output,
+        }
+    }
+}
+
+/// 发起二分查找的请求
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct BisectRequest {
+    /// 已知正常的修订
+    pub good: Vec<String>,
+    /// 已知有问题的修订
+    pub bad: String,
+    /// 在候选提交的检出目录中运行的测试命令
+    pub command: String,
+    /// 每次测试的超时（秒），默认 600
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+}
+
+/// 变更请求
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct ReviewInfo {
+    pub id: u64,
+    pub title: String,
+    pub description: String,
+    pub author: String,
+    /// 被请求评审的用户
+    pub reviewers: Vec<String>,
+    /// 源分支的完整引用名
+    pub source: String,
+    /// 目标分支的完整引用名
+    pub target: String,
+    /// 源分支当前的提交
+    #[schema(value_type = String)]
+    pub head: ObjectId,
+    /// `open`、`merged` 或 `closed`
+    #[schema(value_type = String)]
+    pub state: ReviewState,
+    pub created_at: i64,
+    pub updated_at: i64,
+    pub comments: Vec<CommentInfo>,
+    /// 全部批准，包括源分支更新前的
+    pub approvals: Vec<ApprovalInfo>,
+    /// 对当前提交有效的批准人
+    pub approvers: Vec<String>,
+}
+
+impl From<ChangeRequest> for ReviewInfo {
+    fn from(review: ChangeRequest) -> ReviewInfo {
+        ReviewInfo {
+            approvers: review.approvers().into_iter().map(str::to_string).collect(),
+            id: review.id,
+            title: review.title,
+            description: review.description,
+            author: review.author,
+            reviewers: review.reviewers,
+            source: review.source,
+            target: review.target,
+            head: review.head,
+            state: review.state,
+            created_at: review.created_at,
+            updated_at: review.updated_at,
+            comments: review.comments.into_iter().map(CommentInfo::from).collect(),
+            approvals: review.approvals.into_iter().map(ApprovalInfo::from).collect(),
+        }
+    }
+}
+
+/// 一条评论
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct CommentInfo {
+    pub id: u64,
+    pub author: String,
+    pub body: String,
+    /// 行内评论的位置
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub anchor: Option<AnchorInfo>,
+    pub created_at: i64,
+}
+
+impl From<Comment> for CommentInfo {
+    fn from(comment: Comment) -> CommentInfo {
+        CommentInfo {
+            id: comment.id,
+            author: comment.author,
+            body: comment.body,
+            anchor: comment.anchor.map(AnchorInfo::from),
+            created_at: comment.created_at,
+        }
+    }
+}
+
+/// 行内评论的位置，随源分支的更新而移动
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct AnchorInfo {
+    #[schema(value_type = String)]
+    pub commit: ObjectId,
+    pub path: String,
+    /// 行号，从 1 开始
+    pub line: usize,
+    /// 锚定的行已被修改或删除
+    pub outdated: bool,
+}
+
+impl From<Anchor> for AnchorInfo {
+    fn from(anchor: Anchor) -> AnchorInfo {
+        AnchorInfo {
+            commit: anchor.commit,
+            path: anchor.path,
+            line: anchor.line,
+            outdated: anchor.outdated,
+        }
+    }
+}
+
+/// 一次批准
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct ApprovalInfo {
+    pub reviewer: String,
+    /// 批准时源分支的提交
+    #[schema(value_type = String)]
+    pub commit: ObjectId,
+    pub approved_at: i64,
+}
+
+impl From<Approval> for ApprovalInfo {
+    fn from(approval: Approval) -> ApprovalInfo {
+        ApprovalInfo {
+            reviewer: approval.reviewer,
+            commit: approval.commit,
+            approved_at: approval.approved_at,
+        }
+    }
+}
+
+/// 新建变更请求的请求
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct ReviewRequest {
+    pub title: String,
+    #[serde(default)]
+    pub description: String,
+    /// 请求评审的用户，收到通知（见 [`crate::notifications`]）
+    #[serde(default)]
+    pub reviewers: Vec<String>,
+    /// 源分支，可以省略 `refs/heads/` 前缀
+    pub source: String,
+    /// 目标分支，可以省略 `refs/heads/` 前缀
+    pub target: String,
+}
+
+/// 添加评论的请求，`path` 与 `line` 同时给出时为当前提交中该行的行内评论
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct CommentRequest {
+    pub body: String,
+    #[serde(default)]
+    pub path: Option<String>,
+    /// 行号，从 1 开始
+    #[serde(default)]
+    pub line: Option<usize>,
+}
+
+/// 关闭或重新打开变更请求的请求
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct ReviewStateRequest {
+    /// `open` 或 `closed`
+    #[schema(value_type = String)]
+    pub state: ReviewState,
+}
+
+/// 错误响应，与 `--format json` 输出的错误相同
+#[derive(Serialize, ToSchema)]
+#[allow(dead_code)]
+struct ApiError {
+    /// 错误代码
+    code: i32,
+    /// 稳定的错误标识符，例如 `not_found`
+    id: String,
+    message: String,
+    chain: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct RefsQuery {
+    /// 只列出以此开头的引用，默认为 `refs/`
+    prefix: Option<String>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct RevQuery {
+    /// 修订，默认为 HEAD
+    rev: Option<String>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct LogQuery {
+    /// 起始修订，默认为 HEAD
+    rev: Option<String>,
+    /// 只返回修改了该路径的提交
+    path: Option<String>,
+    /// 最多返回的提交数，默认 100，至多 1000
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct PathQuery {
+    /// 修订，默认为 HEAD
+    rev: Option<String>,
+    /// 相对仓库根目录的路径，默认为根目录
+    path: Option<String>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct ArchiveQuery {
+    /// 修订，默认为 HEAD
+    rev: Option<String>,
+    /// 只导出该子目录，默认为根目录
+    path: Option<String>,
+    /// `tar`（默认）或 `zip`
+    #[param(value_type = Option<String>)]
+    format: Option<ExportFormat>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct TrailersQuery {
+    /// 尾注的键，不区分大小写，如 `Change-Id`
+    key: String,
+    /// 尾注的值；值的第一个词相同，或同为提交 ID 的不同缩写也算匹配
+    value: String,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct ReleasesQuery {
+    /// 只列出该目录或其下项目的发布，默认为全部发布
+    path: Option<String>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct ArtifactQuery {
+    /// 发布的标签名
+    tag: String,
+    /// 附件名
+    name: String,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct ReviewsQuery {
+    /// 只列出该状态（`open`、`merged` 或 `closed`）的变更请求
+    #[param(value_type = Option<String>)]
+    state: Option<ReviewState>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct DiffQuery {
+    /// 比较的起点
+    base: String,
+    /// 比较的终点，默认为 HEAD
+    head: Option<String>,
+}
+
+/// 处理请求时的错误，按错误类型映射为 HTTP 状态码，响应体为 JSON
+struct ApiResponseError(MonoError);
+
+impl From<MonoError> for ApiResponseError {
+    fn from(err: MonoError) -> ApiResponseError {
+        ApiResponseError(err)
+    }
+}
+
+impl IntoResponse for ApiResponseError {
+    fn into_response(self) -> Response {
+        let status = match self.0.kind() {
+            MonoErrorKind::NotFound(_) => StatusCode::NOT_FOUND,
+            MonoErrorKind::Usage(_) | MonoErrorKind::Protocol(_) => StatusCode::BAD_REQUEST,
+            MonoErrorKind::Auth(_) | MonoErrorKind::Policy(_) => StatusCode::FORBIDDEN,
+            MonoErrorKind::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        if status.is_server_error() {
+            tracing::error!(error = %self.0, "api request failed");
+        }
+        // 调试信息包含服务端的调用栈，不返回给客户端
+        let report = ErrorReport {
+            debug: None,
+            ..self.0.report()
+        };
+        (status, Json(report)).into_response()
+    }
+}
+
+type ApiResult<T> = Result<T, ApiResponseError>;
+
+/// 构建 REST 接口的路由
+pub fn router() -> Router<Arc<Repository>> {
+    Router::new()
+        .route("/api/v1/openapi.json", get(openapi))
+        .route("/api/v1/refs", get(list_refs))
+        .route("/api/v1/commit", get(get_commit))
+        .route("/api/v1/log", get(list_log))
+        .route("/api/v1/tree", get(get_tree))
+        .route("/api/v1/blob", get(get_blob))
+        .route("/api/v1/diff", get(get_diff))
+        .route("/api/v1/blame", get(get_blame))
+        .route("/api/v1/archive", get(get_archive))
+        .route("/api/v1/cherry-pick", post(cherry_pick))
+        .route("/api/v1/revert", post(revert))
+        .route("/api/v1/checks", get(list_checks).post(post_check))
+        .route("/api/v1/verify", get(verify))
+        .route("/api/v1/metadata", get(get_metadata))
+        .route("/api/v1/trailers", get(find_trailers))
+        .route("/api/v1/releases", get(list_releases))
+        .route("/api/v1/releases/artifact", get(get_artifact))
+        .route("/api/v1/reviews", get(list_reviews).post(create_review))
+        .route("/api/v1/reviews/{id}", get(get_review))
+        .route("/api/v1/reviews/{id}/comments", post(comment_review))
+        .route("/api/v1/reviews/{id}/approvals", post(approve_review))
+        .route("/api/v1/reviews/{id}/state", post(set_review_state))
+        .route("/api/v1/bisect", get(list_bisects).post(start_bisect))
+        .route("/api/v1/bisect/{id}", get(get_bisect))
+}
+
+/// 列出二分查找，按编号排列
+#[utoipa::path(get, path = "/api/v1/bisect", responses((status = 200, body = [BisectInfo])))]
+async fn list_bisects(State(repo): State<Arc<Repository>>) -> ApiResult<Json<Vec<BisectInfo>>> {
+    let sessions = blocking(move || Ok(Bisector::new(&repo).list()?.into_iter().map(BisectInfo::from).collect())).await?;
+    Ok(Json(sessions))
+}
+
+/// 读取二分查找的进度与结果
+#[utoipa::path(
+    get,
+    path = "/api/v1/bisect/{id}",
+    params(("id" = u64, Path, description = "查找编号")),
+    responses((status = 200, body = BisectInfo), (status = 404, body = ApiError))
+)]
+async fn get_bisect(State(repo): State<Arc<Repository>>, Path(id): Path<u64>) -> ApiResult<Json<BisectInfo>> {
+    let session = blocking(move || Bisector::new(&repo).get(id)).await?;
+    Ok(Json(session.into()))
+}
+
+/// 发起二分查找，登记后立即返回，查找在后台运行
+#[utoipa::path(
+    post,
+    path = "/api/v1/bisect",
+    request_body = BisectRequest,
+    responses(
+        (status = 200, body = BisectInfo),
+        (status = 400, body = ApiError),
+        (status = 403, body = ApiError),
+        (status = 404, body = ApiError)
+    )
+)]
+async fn start_bisect(
+    State(repo): State<Arc<Repository>>,
+    Extension(access): Extension<Access>,
+    Json(request): Json<BisectRequest>,
+) -> ApiResult<Json<BisectInfo>> {
+    let runner_repo = repo.clone();
+    let session = blocking(move || {
+        // 测试命令在服务端执行
+        access.require(Scope::Admin)?;
+        let good = request.good.iter().map(|rev| repo.resolve_rev(rev)).collect::<MonoResult<Vec<_>>>()?;
+        let request = NewBisect {
+            good,
+            bad: repo.resolve_rev(&request.bad)?,
+            command: request.command,
+            timeout_secs: request.timeout_secs.unwrap_or(bisect::DEFAULT_TIMEOUT_SECS),
+            requested_by: access.principal,
+        };
+        Bisector::new(&repo).start(request, chrono::Utc::now().timestamp())
+    })
+    .await?;
+    let id = session.id;
+    let span = tracing::Span::current();
+    tokio::task::spawn_blocking(move || {
+        let _entered = span.enter();
+        if let Err(e) = Bisector::new(&runner_repo).run(id) {
+            tracing::warn!(id, error = %e, "bisect failed");
+        }
+    });
+    Ok(Json(session.into()))
+}
+
+/// 修订或其子目录的归档，不可读的路径被跳过
+#[utoipa::path(
+    get,
+    path = "/api/v1/archive",
+    params(ArchiveQuery),
+    responses(
+        (status = 200, description = "tar 或 zip 包", content_type = "application/octet-stream"),
+        (status = 400, body = ApiError),
+        (status = 404, body = ApiError)
+    )
+)]
+async fn get_archive(
+    State(repo): State<Arc<Repository>>,
+    Extension(access): Extension<Access>,
+    Query(query): Query<ArchiveQuery>,
+) -> ApiResult<Response> {
+    let format = query.format.unwrap_or(ExportFormat::Tar);
+    let Some(content_type) = format.content_type() else {
+        return Err(MonoError::usage("archive format must be tar or zip").into());
+    };
+    let (snapshot, prefix) = blocking({
+        let repo = repo.clone();
+        let access = access.clone();
+        move || {
+            let view = ReadView::new(&repo, access)?;
+            let commit = resolve(&repo, &view, query.rev.as_deref())?;
+            if let Some(path) = &query.path {
+                view.check_path(path)?;
+            }
+            let snapshot = Snapshot::resolve(&repo, &commit.to_hex(), query.path.as_deref())?;
+            let prefix = query.path.as_deref().map(crate::rewrite::normalize_prefix).transpose()?;
+            Ok((snapshot, prefix))
+        }
+    })
+    .await?;
+    let filename = format!("{}.{}", snapshot.commit, if format == ExportFormat::Zip { "zip" } else { "tar" });
+    let body = stream_body("archive", move |writer| {
+        let acl = Acl::load(&repo)?;
+        let include = |path: &str, _: bool| match &prefix {
+            Some(prefix) => acl.can_read_path(&access, &format!("{}/{}", prefix, path)),
+            None => acl.can_read_path(&access, path),
+        };
+        write_archive(&repo, &snapshot, format, writer, include)?;
+        Ok(())
+    })
+    .await?;
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", filename)),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+/// 解析请求身份可见的修订，省略时表示 HEAD
+fn resolve(repo: &Repository, view: &ReadView, rev: Option<&str>) -> MonoResult<ObjectId> {
+    view.resolve(repo, rev.unwrap_or(refs::HEAD))
+}
+
+/// 查找修订中请求身份可读的路径，省略时为根目录
+fn find(repo: &Repository, view: &ReadView, rev: Option<&str>, path: Option<&str>) -> MonoResult<TreeEntry> {
+    let tree = repo.read_commit(&resolve(repo, view, rev)?)?.tree;
+    let path = path.unwrap_or_default().trim_matches('/');
+    view.check_path(path)?;
+    repo.find_path(&tree, path)?
+        .ok_or_else(|| MonoError::not_found(format!("path {} in {}", path, rev.unwrap_or(refs::HEAD))))
+}
+
+async fn openapi() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+/// 列出引用
+#[utoipa::path(
+    get,
+    path = "/api/v1/refs",
+    params(RefsQuery),
+    responses((status = 200, body = [RefInfo]))
+)]
+async fn list_refs(
+    State(repo): State<Arc<Repository>>,
+    Extension(access): Extension<Access>,
+    Query(query): Query<RefsQuery>,
+) -> ApiResult<Json<Vec<RefInfo>>> {
+    let refs = blocking(move || {
+        let view = ReadView::new(&repo, access)?;
+        let prefix = query.prefix.as_deref().unwrap_or("refs/");
+        Ok(repo
+            .refs()
+            .list(prefix)?
+            .into_iter()
+            .filter(|(name, _)| view.can_read_ref(name))
+            .map(|(name, target)| RefInfo { name, target })
+            .collect())
+    })
+    .await?;
+    Ok(Json(refs))
+}
+
+/// 读取提交
+#[utoipa::path(
+    get,
+    path = "/api/v1/commit",
+    params(RevQuery),
+    responses((status = 200, body = CommitInfo), (status = 404, body = ApiError))
+)]
+async fn get_commit(
+    State(repo): State<Arc<Repository>>,
+    Extension(access): Extension<Access>,
+    Query(query): Query<RevQuery>,
+) -> ApiResult<Json<CommitInfo>> {
+    let commit = blocking(move || {
+        let view = ReadView::new(&repo, access)?;
+        let id = resolve(&repo, &view, query.rev.as_deref())?;
+        Ok(CommitInfo::new(id, &repo.read_commit(&id)?))
+    })
+    .await?;
+    Ok(Json(commit))
+}
+
+/// 提交历史，按提交时间从新到旧排列
+#[utoipa::path(
+    get,
+    path = "/api/v1/log",
+    params(LogQuery),
+    responses((status = 200, body = [CommitInfo]), (status = 404, body = ApiError))
+)]
+async fn list_log(
+    State(repo): State<Arc<Repository>>,
+    Extension(access): Extension<Access>,
+    Query(query): Query<LogQuery>,
+) -> ApiResult<Json<Vec<CommitInfo>>> {
+    let commits = blocking(move || {
+        let view = ReadView::new(&repo, access)?;
+        let tip = resolve(&repo, &view, query.rev.as_deref())?;
+        let path = query.path.as_deref().unwrap_or_default();
+        view.check_path(path)?;
+        let limit = query.limit.unwrap_or(DEFAULT_LOG_LIMIT).min(MAX_LOG_LIMIT);
+        let log = History::new(&repo)?.log_path(&[tip], path, Some(limit))?;
+        let mut commits = Vec::with_capacity(log.len());
+        for entry in log {
+            commits.push(CommitInfo::new(entry.id, &repo.read_commit(&entry.id)?));
+        }
+        Ok(commits)
+    })
+    .await?;
+    Ok(Json(commits))
+}
+
+/// 列出目录内容
+#[utoipa::path(
+    get,
+    path = "/api/v1/tree",
+    params(PathQuery),
+    responses((status = 200, body = TreeInfo), (status = 400, body = ApiError), (status = 404, body = ApiError))
+)]
+async fn get_tree(
+    State(repo): State<Arc<Repository>>,
+    Extension(access): Extension<Access>,
+    Query(query): Query<PathQuery>,
+) -> ApiResult<Json<TreeInfo>> {
+    let tree = blocking(move || {
+        let view = ReadView::new(&repo, access)?;
+        let entry = find(&repo, &view, query.rev.as_deref(), query.path.as_deref())?;
+        if !entry.mode.is_tree() {
+            return Err(MonoError::usage(format!("{} is not a directory", entry.name)));
+        }
+        let mut tree = repo.read_tree(&entry.id)?;
+        view.filter_entries(query.path.as_deref().unwrap_or_default(), &mut tree.entries);
+        Ok(TreeInfo {
+            id: entry.id,
+            entries: tree.entries.iter().map(EntryInfo::from).collect(),
+        })
+    })
+    .await?;
+    Ok(Json(tree))
+}
+
+/// 读取文件内容，符号链接返回链接目标
+#[utoipa::path(
+    get,
+    path = "/api/v1/blob",
+    params(PathQuery),
+    responses(
+        (status = 200, content_type = "application/octet-stream", body = Vec<u8>),
+        (status = 400, body = ApiError),
+        (status = 404, body = ApiError)
+    )
+)]
+async fn get_blob(
+    State(repo): State<Arc<Repository>>,
+    Extension(access): Extension<Access>,
+    Query(query): Query<PathQuery>,
+) -> ApiResult<Response> {
+    let (id, data) = blocking(move || {
+        let view = ReadView::new(&repo, access)?;
+        let entry = find(&repo, &view, query.rev.as_deref(), query.path.as_deref())?;
+        let object = repo.read_object(&entry.id)?;
+        if object.object_type != ObjectType::Blob {
+            return Err(MonoError::usage(format!("{} is not a file", query.path.unwrap_or_default())));
+        }
+        Ok((entry.id, object.data))
+    })
+    .await?;
+    Ok((
+        [(header::CONTENT_TYPE, "application/octet-stream".to_string()), (header::ETAG, format!("\"{}\"", id))],
+        data,
+    )
+        .into_response())
+}
+
+/// 两个修订之间改动的文件，按路径排序
+#[utoipa::path(
+    get,
+    path = "/api/v1/diff",
+    params(DiffQuery),
+    responses((status = 200, body = DiffInfo), (status = 404, body = ApiError))
+)]
+async fn get_diff(
+    State(repo): State<Arc<Repository>>,
+    Extension(access): Extension<Access>,
+    Query(query): Query<DiffQuery>,
+) -> ApiResult<Json<DiffInfo>> {
+    let diff = blocking(move || {
+        let view = ReadView::new(&repo, access)?;
+        diff(&repo, &view, &query.base, query.head.as_deref().unwrap_or(refs::HEAD))
+    })
+    .await?;
+    Ok(Json(diff))
+}
+
+/// 文件每一行的来源提交，结果按提交缓存，热点文件的重复查询很快
+#[utoipa::path(
+    get,
+    path = "/api/v1/blame",
+    params(PathQuery),
+    responses((status = 200, body = BlameInfo), (status = 404, body = ApiError))
+)]
+async fn get_blame(
+    State(repo): State<Arc<Repository>>,
+    Extension(access): Extension<Access>,
+    Query(query): Query<PathQuery>,
+) -> ApiResult<Json<BlameInfo>> {
+    let blame = blocking(move || {
+        let view = ReadView::new(&repo, access)?;
+        let id = resolve(&repo, &view, query.rev.as_deref())?;
+        let path = query.path.as_deref().unwrap_or_default();
+        view.check_path(path)?;
+        let blame = Blamer::new(&repo)?.blame(&id, path)?;
+        let mut commits: Vec<CommitInfo> = Vec::new();
+        for range in &blame.ranges {
+            if !commits.iter().any(|commit| commit.id == range.commit) {
+                commits.push(CommitInfo::new(range.commit, &repo.read_commit(&range.commit)?));
+            }
+        }
+        Ok(BlameInfo {
+            commit: blame.commit,
+            path: blame.path,
+            ranges: blame.ranges.iter().map(BlameRangeInfo::from).collect(),
+            commits,
+        })
+    })
+    .await?;
+    Ok(Json(blame))
+}
+
+/// 把提交挑选到分支上，创建的提交以分支的最新提交为父提交；冲突时返回 409，分支不变
+#[utoipa::path(
+    post,
+    path = "/api/v1/cherry-pick",
+    request_body = PickRequest,
+    responses(
+        (status = 200, body = PickInfo),
+        (status = 409, body = PickConflictInfo),
+        (status = 403, body = ApiError),
+        (status = 404, body = ApiError)
+    )
+)]
+async fn cherry_pick(
+    State(repo): State<Arc<Repository>>,
+    Extension(access): Extension<Access>,
+    Json(request): Json<PickRequest>,
+) -> ApiResult<Response> {
+    pick(repo, access, PickKind::CherryPick, request).await
+}
+
+/// 在分支上撤销提交；冲突时返回 409，分支不变
+#[utoipa::path(
+    post,
+    path = "/api/v1/revert",
+    request_body = PickRequest,
+    responses(
+        (status = 200, body = PickInfo),
+        (status = 409, body = PickConflictInfo),
+        (status = 403, body = ApiError),
+        (status = 404, body = ApiError)
+    )
+)]
+async fn revert(
+    State(repo): State<Arc<Repository>>,
+    Extension(access): Extension<Access>,
+    Json(request): Json<PickRequest>,
+) -> ApiResult<Response> {
+    pick(repo, access, PickKind::Revert, request).await
+}
+
+async fn pick(repo: Arc<Repository>, access: Access, kind: PickKind, request: PickRequest) -> ApiResult<Response> {
+    let response = blocking(move || {
+        if request.committer.name.is_empty() || request.committer.email.is_empty() {
+            return Err(MonoError::usage("committer name and email are required"));
+        }
+        let committer = Signature::new(request.committer.name, request.committer.email, chrono::Utc::now().timestamp());
+        let commit = repo.resolve_rev(&request.commit)?;
+        let options = PickOptions {
+            mainline: request.mainline,
+            record_origin: request.record_origin,
+            ..PickOptions::new(kind)
+        };
+        match pick_onto_branch(&repo, &access, &commit, &request.branch, &committer, &options)? {
+            PickOutcome::Picked(id) => {
+                let branch = format!("{}{}", refs::HEADS_PREFIX, refs::short_name(&request.branch));
+                let old = repo.read_commit(&id)?.parents[0];
+                Ok(Json(PickInfo { branch, old, commit: id }).into_response())
+            }
+            PickOutcome::Empty => Err(MonoError::usage(format!(
+                "{} of {} onto {} is empty, the changes are already there",
+                kind, request.commit, request.branch
+            ))),
+            PickOutcome::Conflict(conflicts) => {
+                let conflicts = conflicts.iter().map(ConflictInfo::from).collect();
+                Ok((StatusCode::CONFLICT, Json(PickConflictInfo { conflicts })).into_response())
+            }
+        }
+    })
+    .await?;
+    Ok(response)
+}
+
+/// 提交上的 CI 检查，按检查名排序
+#[utoipa::path(
+    get,
+    path = "/api/v1/checks",
+    params(RevQuery),
+    responses((status = 200, body = [CheckInfo]), (status = 404, body = ApiError))
+)]
+async fn list_checks(
+    State(repo): State<Arc<Repository>>,
+    Extension(access): Extension<Access>,
+    Query(query): Query<RevQuery>,
+) -> ApiResult<Json<Vec<CheckInfo>>> {
+    let checks = blocking(move || {
+        let view = ReadView::new(&repo, access)?;
+        let id = resolve(&repo, &view, query.rev.as_deref())?;
+        Ok(Checks::new(&repo).list(&id)?.into_iter().map(CheckInfo::from).collect())
+    })
+    .await?;
+    Ok(Json(checks))
+}
+
+/// 验证提交或附注标签的签名，修订为标签名时验证标签本身
+#[utoipa::path(
+    get,
+    path = "/api/v1/verify",
+    params(RevQuery),
+    responses((status = 200, body = VerificationInfo), (status = 404, body = ApiError))
+)]
+async fn verify(
+    State(repo): State<Arc<Repository>>,
+    Extension(access): Extension<Access>,
+    Query(query): Query<RevQuery>,
+) -> ApiResult<Json<VerificationInfo>> {
+    let info = blocking(move || {
+        let view = ReadView::new(&repo, access)?;
+        let id = resolve(&repo, &view, query.rev.as_deref())?;
+        let verification = Verifier::load(&repo)?.verify(&repo, &id)?;
+        Ok(VerificationInfo::new(id, repo.read_object(&id)?.object_type, verification))
+    })
+    .await?;
+    Ok(Json(info))
+}
+
+/// 提交尾注中的元数据
+#[utoipa::path(
+    get,
+    path = "/api/v1/metadata",
+    params(RevQuery),
+    responses((status = 200, body = MetadataInfo), (status = 404, body = ApiError))
+)]
+async fn get_metadata(
+    State(repo): State<Arc<Repository>>,
+    Extension(access): Extension<Access>,
+    Query(query): Query<RevQuery>,
+) -> ApiResult<Json<MetadataInfo>> {
+    let info = blocking(move || {
+        let view = ReadView::new(&repo, access)?;
+        let id = repo.peel(&resolve(&repo, &view, query.rev.as_deref())?)?.0;
+        let index = TrailerIndex::load(&repo)?;
+        Ok(MetadataInfo::new(id, trailers::metadata(&repo, index.as_ref(), &id)?))
+    })
+    .await?;
+    Ok(Json(info))
+}
+
+/// 带有该尾注的提交，按提交时间从新到旧排列；只覆盖最近一次写入提交图时可达的提交
+#[utoipa::path(
+    get,
+    path = "/api/v1/trailers",
+    params(TrailersQuery),
+    responses((status = 200, body = [MetadataInfo]))
+)]
+async fn find_trailers(State(repo): State<Arc<Repository>>, Query(query): Query<TrailersQuery>) -> ApiResult<Json<Vec<MetadataInfo>>> {
+    let found = blocking(move || {
+        let Some(index) = TrailerIndex::load(&repo)? else {
+            return Ok(Vec::new());
+        };
+        let mut found = Vec::new();
+        for id in index.find(&query.key, &query.value) {
+            let time = repo.read_commit(&id)?.committer.timestamp;
+            found.push((time, MetadataInfo::new(id, trailers::metadata(&repo, Some(&index), &id)?)));
+        }
+        found.sort_by_key(|(time, _)| std::cmp::Reverse(*time));
+        Ok(found.into_iter().map(|(_, info)| info).collect())
+    })
+    .await?;
+    Ok(Json(found))
+}
+
+/// 该目录或其下项目的发布，按创建时间从新到旧排列
+#[utoipa::path(
+    get,
+    path = "/api/v1/releases",
+    params(ReleasesQuery),
+    responses((status = 200, body = [ReleaseInfo]))
+)]
+async fn list_releases(State(repo): State<Arc<Repository>>, Query(query): Query<ReleasesQuery>) -> ApiResult<Json<Vec<ReleaseInfo>>> {
+    let releases = blocking(move || {
+        let releases = Releases::new(&repo).list(query.path.as_deref().unwrap_or_default())?;
+        Ok(releases.into_iter().map(ReleaseInfo::from).collect())
+    })
+    .await?;
+    Ok(Json(releases))
+}
+
+/// 发布附件的原始内容
+#[utoipa::path(
+    get,
+    path = "/api/v1/releases/artifact",
+    params(ArtifactQuery),
+    responses((status = 200, content_type = "application/octet-stream", body = Vec<u8>), (status = 404, body = ApiError))
+)]
+async fn get_artifact(State(repo): State<Arc<Repository>>, Query(query): Query<ArtifactQuery>) -> ApiResult<Response> {
+    let name = query.name.clone();
+    let data = blocking(move || Releases::new(&repo).artifact(&query.tag, &query.name)).await?;
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/octet-stream".to_string()),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", name.replace('"', ""))),
+        ],
+        data,
+    )
+        .into_response())
+}
+
+/// 上报提交的检查状态，上报者为请求的身份
+#[utoipa::path(
+    post,
+    path = "/api/v1/checks",
+    request_body = CheckRequest,
+    responses(
+        (status = 200, body = CheckInfo),
+        (status = 400, body = ApiError),
+        (status = 403, body = ApiError),
+        (status = 404, body = ApiError)
+    )
+)]
+async fn post_check(
+    State(repo): State<Arc<Repository>>,
+    Extension(access): Extension<Access>,
+    Json(request): Json<CheckRequest>,
+) -> ApiResult<Json<CheckInfo>> {
+    let check = blocking(move || {
+        let commit = repo.resolve_rev(&request.commit)?;
+        let status = CheckStatus {
+            context: request.context,
+            state: request.state,
+            details_url: request.details_url,
+            description: request.description,
+            reporter: access.principal,
+            updated_at: chrono::Utc::now().timestamp(),
+        };
+        Checks::new(&repo).post(&commit, status.clone())?;
+        Ok(CheckInfo::from(status))
+    })
+    .await?;
+    Ok(Json(check))
+}
+
+/// 列出变更请求，按编号排列
+#[utoipa::path(
+    get,
+    path = "/api/v1/reviews",
+    params(ReviewsQuery),
+    responses((status = 200, body = [ReviewInfo]))
+)]
+async fn list_reviews(State(repo): State<Arc<Repository>>, Query(query): Query<ReviewsQuery>) -> ApiResult<Json<Vec<ReviewInfo>>> {
+    let reviews = blocking(move || {
+        let store = ReviewStore::new(&repo);
+        let now = chrono::Utc::now().timestamp();
+        let mut reviews = Vec::new();
+        for review in store.list()? {
+            let review = store.refresh(review.id, now)?;
+            if query.state.is_none_or(|state| state == review.state) {
+                reviews.push(ReviewInfo::from(review));
+            }
+        }
+        Ok(reviews)
+    })
+    .await?;
+    Ok(Json(reviews))
+}
+
+/// 读取变更请求，源分支更新后评论锚点随之移动
+#[utoipa::path(
+    get,
+    path = "/api/v1/reviews/{id}",
+    params(("id" = u64, Path, description = "变更请求编号")),
+    responses((status = 200, body = ReviewInfo), (status = 404, body = ApiError))
+)]
+async fn get_review(State(repo): State<Arc<Repository>>, Path(id): Path<u64>) -> ApiResult<Json<ReviewInfo>> {
+    let review = blocking(move || ReviewStore::new(&repo).refresh(id, chrono::Utc::now().timestamp())).await?;
+    Ok(Json(review.into()))
+}
+
+/// 新建变更请求，作者为请求的身份
+#[utoipa::path(
+    post,
+    path = "/api/v1/reviews",
+    request_body = ReviewRequest,
+    responses(
+        (status = 200, body = ReviewInfo),
+        (status = 400, body = ApiError),
+        (status = 403, body = ApiError),
+        (status = 404, body = ApiError)
+    )
+)]
+async fn create_review(
+    State(repo): State<Arc<Repository>>,
+    Extension(access): Extension<Access>,
+    Json(request): Json<ReviewRequest>,
+) -> ApiResult<Json<ReviewInfo>> {
+    let review = blocking(move || {
+        let request = NewChangeRequest {
+            title: request.title,
+            description: request.description,
+            author: access.principal,
+            reviewers: request.reviewers,
+            source: request.source,
+            target: request.target,
+        };
+        ReviewStore::new(&repo).create(request, chrono::Utc::now().timestamp())
+    })
+    .await?;
+    Ok(Json(review.into()))
+}
+
+/// 添加评论，行内评论锚定到源分支当前的提交
+#[utoipa::path(
+    post,
+    path = "/api/v1/reviews/{id}/comments",
+    params(("id" = u64, Path, description = "变更请求编号")),
+    request_body = CommentRequest,
+    responses(
+        (status = 200, body = CommentInfo),
+        (status = 400, body = ApiError),
+        (status = 403, body = ApiError),
+        (status = 404, body = ApiError)
+    )
+)]
+async fn comment_review(
+    State(repo): State<Arc<Repository>>,
+    Extension(access): Extension<Access>,
+    Path(id): Path<u64>,
+    Json(request): Json<CommentRequest>,
+) -> ApiResult<Json<CommentInfo>> {
+    let comment = blocking(move || {
+        let anchor = match (request.path.as_deref(), request.line) {
+            (Some(path), Some(line)) => Some((path, line)),
+            (None, None) => None,
+            _ => return Err(MonoError::usage("inline comments need both path and line")),
+        };
+        let store = ReviewStore::new(&repo);
+        let now = chrono::Utc::now().timestamp();
+        store.refresh(id, now)?;
+        store.comment(id, &access.principal, &request.body, anchor, now)
+    })
+    .await?;
+    Ok(Json(comment.into()))
+}
+
+/// 以请求的身份批准源分支当前的提交
+#[utoipa::path(
+    post,
+    path = "/api/v1/reviews/{id}/approvals",
+    params(("id" = u64, Path, description = "变更请求编号")),
+    responses(
+        (status = 200, body = ReviewInfo),
+        (status = 400, body = ApiError),
+        (status = 403, body = ApiError),
+        (status = 404, body = ApiError)
+    )
+)]
+async fn approve_review(
+    State(repo): State<Arc<Repository>>,
+    Extension(access): Extension<Access>,
+    Path(id): Path<u64>,
+) -> ApiResult<Json<ReviewInfo>> {
+    let review = blocking(move || {
+        let store = ReviewStore::new(&repo);
+        let now = chrono::Utc::now().timestamp();
+        store.refresh(id, now)?;
+        store.approve(id, &access.principal, now)
+    })
+    .await?;
+    Ok(Json(review.into()))
+}
+
+/// 关闭或重新打开变更请求
+#[utoipa::path(
+    post,
+    path = "/api/v1/reviews/{id}/state",
+    params(("id" = u64, Path, description = "变更请求编号")),
+    request_body = ReviewStateRequest,
+    responses(
+        (status = 200, body = ReviewInfo),
+        (status = 400, body = ApiError),
+        (status = 403, body = ApiError),
+        (status = 404, body = ApiError)
+    )
+)]
+async fn set_review_state(
+    State(repo): State<Arc<Repository>>,
+    Path(id): Path<u64>,
+    Json(request): Json<ReviewStateRequest>,
+) -> ApiResult<Json<ReviewInfo>> {
+    let review = blocking(move || ReviewStore::new(&repo).set_state(id, request.state, chrono::Utc::now().timestamp())).await?;
+    Ok(Json(review.into()))
+}
+
+/// 比较两个修订的树，只列出请求身份可读的路径
+fn diff(repo: &Repository, view: &ReadView, base: &str, head: &str) -> MonoResult<DiffInfo> {
+    let base = view.resolve(repo, base)?;
+    let head = view.resolve(repo, head)?;
+    let old_tree = repo.read_commit(&base)?.tree;
+    let new_tree = repo.read_commit(&head)?.tree;
+    let blob = |tree: &ObjectId, path: &str| -> MonoResult<Option<ObjectId>> {
+        Ok(repo.find_path(tree, path)?.filter(|e| !e.mode.is_tree()).map(|e| e.id))
+    };
+    let mut files = Vec::new();
+    for path in repo.changed_paths(Some(&old_tree), Some(&new_tree))? {
+        if !view.can_read_path(&path) {
+            continue;
+        }
+        let old = blob(&old_tree, &path)?;
+        let new = blob(&new_tree, &path)?;
+        let status = match (old, new) {
+            (None, _) => ChangeStatus::Added,
+            (_, None) => ChangeStatus::Deleted,
+            _ => ChangeStatus::Modified,
+        };
+        files.push(FileChange { path, status, old, new });
+    }
+    Ok(DiffInfo { base, head, files })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::refs::RefUpdate;
+    use crate::release::NewRelease;
+    use crate::test_utils::{commit_files, init_repo};
+    use axum::body::Body;
+    use axum::http::Request;
+    use tower::ServiceExt;
+
+    fn get(repo: &Arc<Repository>, uri: &str) -> (StatusCode, Vec<u8>) {
+        get_as(repo, Access::full("test"), uri)
+    }
+
+    fn get_as(repo: &Arc<Repository>, access: Access, uri: &str) -> (StatusCode, Vec<u8>) {
+        let app = router().layer(Extension(access)).with_state(repo.clone());
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(async {
+            let response = app.oneshot(Request::get(uri).body(Body::empty()).unwrap()).await.unwrap();
+            let status = response.status();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            (status, body.to_vec())
+        })
+    }
+
+    fn post(repo: &Arc<Repository>, uri: &str, body: serde_json::Value) -> (StatusCode, serde_json::Value) {
+        let app = router().layer(Extension(Access::full("test"))).with_state(repo.clone());
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(async {
+            let request = Request::post(uri)
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap();
+            let response = app.oneshot(request).await.unwrap();
+            let status = response.status();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            (status, serde_json::from_slice(&body).unwrap())
+        })
+    }
+
+    fn json(repo: &Arc<Repository>, uri: &str) -> (StatusCode, serde_json::Value) {
+        let (status, body) = get(repo, uri);
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    /// 测试引用、提交、历史、目录、文件与差异接口，以及错误响应
+    #[test]
+    fn test_api() {
+        let (_dir, repo) = init_repo();
+        let first = commit_files(&repo, &[("README.md", b"hello"), ("src/lib.rs", b"fn a() {}")], &[], "first");
+        let second = commit_files(
+            &repo,
+            &[("README.md", b"hello"), ("src/lib.rs", b"fn b() {}"), ("src/main.rs", b"fn main() {}")],
+            &[first],
+            "second",
+        );
+        repo.refs()
+            .update(&[RefUpdate {
+                name: "refs/heads/feature/x".to_string(),
+                old: ObjectId::ZERO,
+                new: second,
+            }])
+            .unwrap();
+        let repo = Arc::new(repo);
+
+        let (status, refs) = json(&repo, "/api/v1/refs?prefix=refs/heads/feature/");
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(refs, serde_json::json!([{"name": "refs/heads/feature/x", "target": second.to_hex()}]));
+
+        let (_, commit) = json(&repo, "/api/v1/commit?rev=feature/x");
+        assert_eq!(commit["id"], second.to_hex());
+        assert_eq!(commit["parents"][0], first.to_hex());
+        assert_eq!(commit["message"], "second\n");
+
+        let (_, log) = json(&repo, "/api/v1/log?rev=feature/x&path=README.md");
+        assert_eq!(log.as_array().unwrap().len(), 1);
+        assert_eq!(log[0]["id"], first.to_hex());
+
+        let (_, tree) = json(&repo, &format!("/api/v1/tree?rev={}", second));
+        let names: Vec<_> = tree["entries"].as_array().unwrap().iter().map(|e| e["name"].clone()).collect();
+        assert_eq!(names, ["README.md", "src"]);
+        assert_eq!(tree["entries"][1]["kind"], "directory");
+        assert_eq!(tree["entries"][1]["mode"], "040000");
+
+        let (status, body) = get(&repo, "/api/v1/blob?rev=feature/x&path=src/main.rs");
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, b"fn main() {}");
+
+        let changes = |uri: &str| -> Vec<(String, String)> {
+            let (_, diff) = json(&repo, uri);
+            diff["files"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|f| (f["path"].as_str().unwrap().to_string(), f["status"].as_str().unwrap().to_string()))
+                .collect()
+        };
+        let change = |path: &str, status: &str| (path.to_string(), status.to_string());
+        assert_eq!(
+            changes(&format!("/api/v1/diff?base={}&head=feature/x", first)),
+            [change("src/lib.rs", "modified"), change("src/main.rs", "added")]
+        );
+        assert_eq!(
+            changes(&format!("/api/v1/diff?base=feature/x&head={}", first)),
+            [change("src/lib.rs", "modified"), change("src/main.rs", "deleted")]
+        );
+
+        let (status, error) = json(&repo, "/api/v1/blob?rev=feature/x&path=missing");
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(error["id"], "not_found");
+        let (status, _) = json(&repo, "/api/v1/tree?rev=feature/x&path=src/lib.rs");
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let (status, verification) = json(&repo, "/api/v1/verify?rev=feature/x");
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(verification, serde_json::json!({"id": second.to_hex(), "object_type": "commit", "status": "unsigned"}));
+    }
+
+    /// 测试提交元数据与按尾注反查提交的接口
+    #[test]
+    fn test_trailers_api() {
+        let (_dir, repo) = init_repo();
+        let base = commit_files(&repo, &[("a", b"1")], &[], "base\n\nChange-Id: I1111\n");
+        let fix = commit_files(&repo, &[("a", b"2")], &[base], &format!("fix\n\nFixes: {}\nReviewed-by: Alice\n", &base.to_hex()[..12]));
+        repo.refs().write("refs/heads/main", &fix).unwrap();
+        crate::graph::write_commit_graph(&repo).unwrap();
+        let repo = Arc::new(repo);
+
+        let (status, meta) = json(&repo, "/api/v1/metadata?rev=main");
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(meta["reviewed_by"], serde_json::json!(["Alice"]));
+        assert_eq!(meta["trailers"][0]["key"], "Fixes");
+        let (_, meta) = json(&repo, &format!("/api/v1/metadata?rev={}", base));
+        assert_eq!(meta["change_id"], "I1111");
+
+        let (status, found) = json(&repo, &format!("/api/v1/trailers?key=fixes&value={}", base));
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(found.as_array().unwrap().len(), 1);
+        assert_eq!(found[0]["id"], fix.to_hex());
+        let (_, found) = json(&repo, "/api/v1/trailers?key=Change-Id&value=I9999");
+        assert_eq!(found, serde_json::json!([]));
+    }
+
+    /// 测试按项目目录列出发布与下载附件
+    #[test]
+    fn test_releases_api() {
+        let (_dir, repo) = init_repo();
+        let commit = commit_files(&repo, &[("services/api/main.rs", b"fn main() {}"), ("web/index.html", b"")], &[], "init");
+        let releases = Releases::new(&repo);
+        let tagger = Signature::new("Bot", "bot@example.com", 1_700_000_000);
+        let api = NewRelease {
+            tag: "api-v1".to_string(),
+            rev: commit.to_hex(),
+            project: Some("services/api".to_string()),
+            artifacts: vec![("api.tar.gz".to_string(), b"archive".to_vec())],
+            ..NewRelease::default()
+        };
+        releases.create(api, &tagger).unwrap();
+        let web = NewRelease { tag: "web-v1".to_string(), rev: commit.to_hex(), project: Some("web".to_string()), ..NewRelease::default() };
+        releases.create(web, &Signature { timestamp: 1_700_000_100, ..tagger }).unwrap();
+        let repo = Arc::new(repo);
+
+        let (status, list) = json(&repo, "/api/v1/releases");
+        assert_eq!(status, StatusCode::OK);
+        let tags: Vec<&str> = list.as_array().unwrap().iter().map(|r| r["tag"].as_str().unwrap()).collect();
+        assert_eq!(tags, ["web-v1", "api-v1"]);
+        let (_, list) = json(&repo, "/api/v1/releases?path=services");
+        assert_eq!(list.as_array().unwrap().len(), 1);
+        assert_eq!(list[0]["project"], "services/api");
+        assert_eq!(list[0]["artifacts"][0]["size"], 7);
+
+        assert_eq!(get(&repo, "/api/v1/releases/artifact?tag=api-v1&name=api.tar.gz"), (StatusCode::OK, b"archive".to_vec()));
+        assert_eq!(get(&repo, "/api/v1/releases/artifact?tag=api-v1&name=missing").0, StatusCode::NOT_FOUND);
+    }
+
+    /// 测试 cherry-pick 与 revert 接口更新分支，冲突时返回 409 且分支不变
+    #[test]
+    fn test_pick_api() {
+        let (_dir, repo) = init_repo();
+        let base = commit_files(&repo, &[("a", b"1\n2\n3\n")], &[], "base");
+        let fix = commit_files(&repo, &[("a", b"1\n2\nfixed\n")], &[base], "fix");
+        let other = commit_files(&repo, &[("a", b"1\n2\nother\n")], &[base], "other");
+        repo.refs().write("refs/heads/release", &base).unwrap();
+        let repo = Arc::new(repo);
+        let committer = serde_json::json!({"name": "Bot", "email": "bot@example.com"});
+
+        let request = serde_json::json!({"commit": fix.to_hex(), "branch": "release", "committer": committer});
+        let (status, picked) = post(&repo, "/api/v1/cherry-pick", request);
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(picked["branch"], "refs/heads/release");
+        assert_eq!(picked["old"], base.to_hex());
+        let tip = repo.refs().resolve("refs/heads/release").unwrap().unwrap();
+        assert_eq!(picked["commit"], tip.to_hex());
+
+        let request = serde_json::json!({"commit": other.to_hex(), "branch": "release", "committer": committer});
+        let (status, conflict) = post(&repo, "/api/v1/cherry-pick", request);
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(conflict["conflicts"], serde_json::json!([{"kind": "content", "path": "a"}]));
+        assert_eq!(repo.refs().resolve("refs/heads/release").unwrap(), Some(tip));
+
+        let request = serde_json::json!({"commit": tip.to_hex(), "branch": "release", "committer": committer});
+        let (status, reverted) = post(&repo, "/api/v1/revert", request);
+        assert_eq!(status, StatusCode::OK);
+        let commit = repo.read_commit(&reverted["commit"].as_str().unwrap().parse().unwrap()).unwrap();
+        assert_eq!(commit.tree, repo.read_commit(&base).unwrap().tree);
+    }
+
+    /// 测试上报与读取检查状态
+    #[test]
+    fn test_checks_api() {
+        let (_dir, repo) = init_repo();
+        let commit = commit_files(&repo, &[("README.md", b"hello")], &[], "init");
+        let repo = Arc::new(repo);
+        let request = serde_json::json!({
+            "commit": commit.to_hex(),
+            "context": "ci/build",
+            "state": "success",
+            "details_url": "https://ci.example.com/builds/1",
+        });
+        let (status, check) = post(&repo, "/api/v1/checks", request);
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(check["reporter"], "test");
+
+        let (status, checks) = json(&repo, &format!("/api/v1/checks?rev={}", commit));
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(checks[0]["context"], "ci/build");
+        assert_eq!(checks[0]["state"], "success");
+        assert_eq!(checks[0]["details_url"], "https://ci.example.com/builds/1");
+
+        let request = serde_json::json!({"commit": commit.to_hex(), "context": "ci/build", "state": "success", "details_url": "ftp://x"});
+        assert_eq!(post(&repo, "/api/v1/checks", request).0, StatusCode::BAD_REQUEST);
+    }
+
+    /// 测试新建变更请求、行内评论与批准
+    #[test]
+    fn test_review_api() {
+        let (_dir, repo) = init_repo();
+        let base = commit_files(&repo, &[("a.txt", b"one\n")], &[], "base");
+        let head = commit_files(&repo, &[("a.txt", b"one\ntwo\n")], &[base], "head");
+        repo.refs().write("refs/heads/main", &base).unwrap();
+        repo.refs().write("refs/heads/feature", &head).unwrap();
+        let repo = Arc::new(repo);
+
+        let request = serde_json::json!({"title": "Add two", "source": "feature", "target": "main"});
+        let (status, review) = post(&repo, "/api/v1/reviews", request);
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(review["id"], 1);
+        assert_eq!(review["author"], "test");
+
+        let request = serde_json::json!({"body": "why?", "path": "a.txt", "line": 2});
+        let (status, comment) = post(&repo, "/api/v1/reviews/1/comments", request);
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(comment["anchor"]["commit"], head.to_hex());
+        let (status, _) = post(&repo, "/api/v1/reviews/1/comments", serde_json::json!({"body": "x", "line": 2}));
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        // 作者不能批准自己的变更请求
+        assert_eq!(post(&repo, "/api/v1/reviews/1/approvals", serde_json::json!({})).0, StatusCode::BAD_REQUEST);
+
+        let (status, reviews) = json(&repo, "/api/v1/reviews?state=open");
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(reviews[0]["comments"][0]["body"], "why?");
+        assert_eq!(json(&repo, "/api/v1/reviews/2").0, StatusCode::NOT_FOUND);
+
+        let (status, review) = post(&repo, "/api/v1/reviews/1/state", serde_json::json!({"state": "closed"}));
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(review["state"], "closed");
+    }
+
+    /// 测试发起二分查找并等待后台查找完成
+    #[test]
+    fn test_bisect_api() {
+        let (_dir, repo) = init_repo();
+        let good = commit_files(&repo, &[("status", b"ok")], &[], "good");
+        let still_good = commit_files(&repo, &[("status", b"ok"), ("a", b"a")], &[good], "still good");
+        let first_bad = commit_files(&repo, &[("status", b"bad"), ("a", b"a")], &[still_good], "broken");
+        let bad = commit_files(&repo, &[("status", b"bad"), ("a", b"b")], &[first_bad], "still broken");
+        let repo = Arc::new(repo);
+
+        let request = serde_json::json!({"good": [good.to_hex()], "bad": bad.to_hex(), "command": "grep -q ok status"});
+        let (status, session) = post(&repo, "/api/v1/bisect", request);
+        assert_eq!(status, StatusCode::OK, "{}", session);
+        assert_eq!(session["requested_by"], "test");
+        let uri = format!("/api/v1/bisect/{}", session["id"]);
+        let mut session = session;
+        for _ in 0..100 {
+            session = json(&repo, &uri).1;
+            if session["state"] != "running" {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(100));
+        }
+        assert_eq!(session["state"], "found", "{}", session);
+        assert_eq!(session["first_bad"], first_bad.to_hex());
+        assert_eq!(json(&repo, "/api/v1/bisect").1.as_array().unwrap().len(), 1);
+
+        let request = serde_json::json!({"good": [bad.to_hex()], "bad": good.to_hex(), "command": "true"});
+        assert_eq!(post(&repo, "/api/v1/bisect", request).0, StatusCode::BAD_REQUEST);
+        assert_eq!(get(&repo, "/api/v1/bisect/9").0, StatusCode::NOT_FOUND);
+    }
+
+    /// 测试以 tar 与 zip 格式下载子目录的快照
+    #[test]
+    fn test_archive_api() {
+        let (_dir, repo) = init_repo();
+        let commit = commit_files(&repo, &[("README.md", b"hello"), ("src/lib.rs", b"fn a() {}")], &[], "init");
+        let repo = Arc::new(repo);
+
+        let (status, body) = get(&repo, &format!("/api/v1/archive?rev={}&path=//src", commit));
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(&body[..6], b"lib.rs");
+        assert_eq!(&body[257..262], b"ustar");
+        assert_eq!(&body[512..521], b"fn a() {}");
+
+        let (status, body) = get(&repo, &format!("/api/v1/archive?rev={}&format=zip", commit));
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(&body[..4], b"PK\x03\x04");
+
+        assert_eq!(get(&repo, "/api/v1/archive?format=dir").0, StatusCode::BAD_REQUEST);
+        assert_eq!(get(&repo, &format!("/api/v1/archive?rev={}&path=missing", commit)).0, StatusCode::NOT_FOUND);
+    }
+
+    /// 测试受 `[[acl]]` 限制的身份读不到隐藏的引用与路径，也不能以对象 ID 绕过
+    #[test]
+    fn test_api_acl() {
+        let (_dir, mut repo) = init_repo();
+        let (main, private) = crate::test_utils::restrict_secrets(&mut repo);
+        let repo = Arc::new(repo);
+        let bob = || crate::test_utils::reader("user:bob");
+        let status = |access: Access, uri: &str| get_as(&repo, access, uri).0;
+
+        let (_, refs) = get_as(&repo, bob(), "/api/v1/refs");
+        let refs: serde_json::Value = serde_json::from_slice(&refs).unwrap();
+        assert_eq!(refs, serde_json::json!([{"name": "refs/heads/main", "target": main.to_hex()}]));
+        let (_, tree) = get_as(&repo, bob(), "/api/v1/tree?rev=main");
+        let tree: serde_json::Value = serde_json::from_slice(&tree).unwrap();
+        assert_eq!(tree["entries"].as_array().unwrap().len(), 1);
+        assert_eq!(tree["entries"][0]["name"], "README.md");
+
+        for uri in [
+            "/api/v1/blob?rev=main&path=secrets/key".to_string(),
+            "/api/v1/tree?rev=main&path=//secrets".to_string(),
+            "/api/v1/log?rev=main&path=secrets".to_string(),
+            "/api/v1/blame?rev=main&path=secrets/key".to_string(),
+            "/api/v1/archive?rev=main&path=//secrets".to_string(),
+            "/api/v1/commit?rev=private".to_string(),
+            "/api/v1/commit?rev=refs/heads/private".to_string(),
+            format!("/api/v1/commit?rev={}", private),
+            format!("/api/v1/diff?base=main&head={}", private),
+        ] {
+            assert_eq!(status(bob(), &uri), StatusCode::NOT_FOUND, "{}", uri);
+            assert_eq!(status(crate::test_utils::reader("user:alice"), &uri), StatusCode::OK, "{}", uri);
+        }
+        assert_eq!(status(bob(), &format!("/api/v1/commit?rev={}", main)), StatusCode::OK);
+
+
+        let next = commit_files(&repo, &[("README.md", b"hi"), ("secrets/key", b"changed")], &[main], "next");
+        repo.refs().write("refs/heads/next", &next).unwrap();
+        let (_, diff) = get_as(&repo, bob(), "/api/v1/diff?base=main&head=next");
+        let diff: serde_json::Value = serde_json::from_slice(&diff).unwrap();
+        let paths: Vec<_> = diff["files"].as_array().unwrap().iter().map(|f| f["path"].clone()).collect();
+        assert_eq!(paths, ["README.md"]);
+    }
+
+    /// 测试 OpenAPI 文档包含全部接口
+    #[test]
+    fn test_openapi() {
+        let (_dir, repo) = init_repo();
+        let (status, spec) = json(&Arc::new(repo), "/api/v1/openapi.json");
+        assert_eq!(status, StatusCode::OK);
+        for path in ["refs", "commit", "log", "tree", "blob", "diff", "archive", "verify", "metadata", "trailers", "releases", "releases/artifact"] {
+            assert!(spec["paths"][format!("/api/v1/{}", path)]["get"].is_object(), "{}", path);
+        }
+        for path in ["cherry-pick", "revert", "checks", "reviews", "bisect"] {
+            assert!(spec["paths"][format!("/api/v1/{}", path)]["post"].is_object(), "{}", path);
+        }
+        assert!(spec["components"]["schemas"]["CommitInfo"].is_object());
+    }
+}
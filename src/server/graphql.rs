@@ -0,0 +1,465 @@
+//! GraphQL 浏览接口
+//!
+//! 网页文件浏览器需要的数据往往跨越多个对象，例如目录内容以及每个条目最后修改的提交，
+//! 通过 GraphQL 可以在一次请求中取回：
+//!
+//! ```graphql
+//! {
+//!   tree(rev: "main", path: "src") {
+//!     entries { name kind lastCommit { id message committer { timestamp } } }
+//!   }
+//! }
+//! ```
+//!
+//! 请求 `lastCommit` 时对整个目录只遍历一次历史（见 [`History::last_commits`]）。`[[acl]]` 规则对请求的
+//! 身份隐藏的引用与路径按不存在处理（见 [`ReadView`]）。
+//! `POST /api/graphql` 执行查询，`GET /api/graphql/schema` 返回 SDL 形式的 schema。
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use async_graphql::{Context, EmptyMutation, EmptySubscription, ErrorExtensions, Object, Schema};
+use axum::extract::State;
+use axum::routing::{get, post};
+use axum::{Extension, Json, Router};
+
+use crate::auth::acl::ReadView;
+use crate::auth::Access;
+use crate::common::errors::{MonoError, MonoErrorKind};
+use crate::common::MonoResult;
+use crate::graph::history::History;
+use crate::object::commit::Commit;
+use crate::object::tree::TreeEntry;
+use crate::object::{ObjectId, ObjectType};
+use crate::refs;
+use crate::repo::Repository;
+use crate::server::api::{EntryKind, SignatureInfo};
+use crate::server::blocking;
+
+/// 查询的最大嵌套深度，避免沿父提交无限展开
+const MAX_DEPTH: usize = 16;
+
+/// `history` 未指定数量时返回的提交数
+const DEFAULT_HISTORY_LIMIT: usize = 20;
+
+/// `history` 单次最多返回的提交数
+const MAX_HISTORY_LIMIT: usize = 1000;
+
+/// 仓库的 GraphQL schema
+pub type RepoSchema = Schema<QueryRoot, EmptyMutation, EmptySubscription>;
+
+/// 构建 schema，仓库作为查询上下文；每个请求还需要携带请求身份的 [`ReadView`]
+pub fn schema(repo: Arc<Repository>) -> RepoSchema {
+    Schema::build(QueryRoot, EmptyMutation, EmptySubscription)
+        .data(repo)
+        .limit_depth(MAX_DEPTH)
+        .finish()
+}
+
+/// 构建 GraphQL 接口的路由
+pub fn router() -> Router<Arc<Repository>> {
+    Router::new()
+        .route("/api/graphql", post(execute))
+        .route("/api/graphql/schema", get(sdl))
+}
+
+async fn execute(
+    State(repo): State<Arc<Repository>>,
+    Extension(access): Extension<Access>,
+    Json(request): Json<async_graphql::Request>,
+) -> Json<async_graphql::Response> {
+    let view = match ReadView::new(&repo, access) {
+        Ok(view) => view,
+        Err(e) => return Json(async_graphql::Response::from_errors(vec![to_error(e).into_server_error(Default::default())])),
+    };
+    Json(schema(repo).execute(request.data(Arc::new(view))).await)
+}
+
+async fn sdl(State(repo): State<Arc<Repository>>) -> String {
+    schema(repo).sdl()
+}
+
+/// 将错误转为 GraphQL 错误，`extensions.code` 为稳定的错误标识符
+fn to_error(err: MonoError) -> async_graphql::Error {
+    if !matches!(
+        err.kind(),
+        MonoErrorKind::NotFound(_) | MonoErrorKind::Usage(_) | MonoErrorKind::Protocol(_)
+    ) {
+        tracing::error!(error = %err, "graphql request failed");
+    }
+    let id = err.kind().id();
+    async_graphql::Error::new(err.to_string()).extend_with(|_, extensions| extensions.set("code", id))
+}
+
+/// 在专用线程中以请求身份的可见范围读取仓库
+async fn with_repo<T, F>(ctx: &Context<'_>, f: F) -> async_graphql::Result<T>
+where
+    F: FnOnce(&Repository, &ReadView) -> MonoResult<T> + Send + 'static,
+    T: Send + 'static,
+{
+    let repo = ctx.data::<Arc<Repository>>()?.clone();
+    let view = ctx.data::<Arc<ReadView>>()?.clone();
+    blocking(move || f(&repo, &view)).await.map_err(to_error)
+}
+
+/// 找不到的对象作为 null 返回，而不是错误
+fn optional<T>(result: MonoResult<T>) -> MonoResult<Option<T>> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(e) if matches!(e.kind(), MonoErrorKind::NotFound(_)) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// 查询入口
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    /// 以 `prefix` 开头的引用，默认列出全部引用
+    async fn refs(&self, ctx: &Context<'_>, prefix: Option<String>) -> async_graphql::Result<Vec<RefNode>> {
+        with_repo(ctx, move |repo, view| {
+            let refs = repo.refs().list(prefix.as_deref().unwrap_or("refs/"))?;
+            Ok(refs
+                .into_iter()
+                .filter(|(name, _)| view.can_read_ref(name))
+                .map(|(name, target)| RefNode { name, target })
+                .collect())
+        })
+        .await
+    }
+
+    /// 修订指向的提交，省略时为 HEAD
+    async fn commit(&self, ctx: &Context<'_>, rev: Option<String>) -> async_graphql::Result<Option<CommitNode>> {
+        with_repo(ctx, move |repo, view| {
+            optional(view.resolve(repo, rev.as_deref().unwrap_or(refs::HEAD)).and_then(|id| CommitNode::load(repo, id)))
+        })
+        .await
+    }
+
+    /// 修订中的目录，路径省略时为根目录
+    async fn tree(
+        &self,
+        ctx: &Context<'_>,
+        rev: Option<String>,
+        path: Option<String>,
+    ) -> async_graphql::Result<Option<TreeNode>> {
+        with_repo(ctx, move |repo, view| {
+            let Some(id) = optional(view.resolve(repo, rev.as_deref().unwrap_or(refs::HEAD)))? else {
+                return Ok(None);
+            };
+            TreeNode::load(repo, view, id, path.as_deref().unwrap_or_default())
+        })
+        .await
+    }
+}
+
+/// 引用
+pub struct RefNode {
+    name: String,
+    target: ObjectId,
+}
+
+#[Object(name = "Ref")]
+impl RefNode {
+    /// 完整引用名
+    async fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn target(&self) -> String {
+        self.target.to_hex()
+    }
+
+    /// 引用指向的提交，指向其他类型的对象时为 null
+    async fn commit(&self, ctx: &Context<'_>) -> async_graphql::Result<Option<CommitNode>> {
+        let target = self.target;
+        with_repo(ctx, move |repo, _| {
+            let (id, object_type) = repo.peel(&target)?;
+            match object_type {
+                ObjectType::Commit => CommitNode::load(repo, id).map(Some),
+                _ => Ok(None),
+            }
+        })
+        .await
+    }
+}
+
+/// 提交
+pub struct CommitNode {
+    id: ObjectId,
+    commit: Commit,
+}
+
+impl CommitNode {
+    fn load(repo: &Repository, id: ObjectId) -> MonoResult<CommitNode> {
+        Ok(CommitNode {
+            id,
+            commit: repo.read_commit(&id)?,
+        })
+    }
+}
+
+#[Object(name = "Commit")]
+impl CommitNode {
+    async fn id(&self) -> String {
+        self.id.to_hex()
+    }
+
+    async fn message(&self) -> &str {
+        &self.commit.message
+    }
+
+    /// 提交说明的第一行
+    async fn summary(&self) -> &str {
+        self.commit.message.lines().next().unwrap_or_default()
+    }
+
+    async fn author(&self) -> SignatureInfo {
+        SignatureInfo::from(&self.commit.author)
+    }
+
+    async fn committer(&self) -> SignatureInfo {
+        SignatureInfo::from(&self.commit.committer)
+    }
+
+    async fn parents(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<CommitNode>> {
+        let parents = self.commit.parents.clone();
+        with_repo(ctx, move |repo, _| parents.into_iter().map(|id| CommitNode::load(repo, id)).collect()).await
+    }
+
+    /// 提交中的目录，路径省略时为根目录；路径不存在或不是目录时为 null
+    async fn tree(&self, ctx: &Context<'_>, path: Option<String>) -> async_graphql::Result<Option<TreeNode>> {
+        let id = self.id;
+        with_repo(ctx, move |repo, view| TreeNode::load(repo, view, id, path.as_deref().unwrap_or_default())).await
+    }
+
+    /// 从该提交开始的历史，可以只包含修改了 `path` 的提交；默认 20 个，至多 1000 个
+    async fn history(
+        &self,
+        ctx: &Context<'_>,
+        path: Option<String>,
+        limit: Option<usize>,
+    ) -> async_graphql::Result<Vec<CommitNode>> {
+        let id = self.id;
+        let limit = limit.unwrap_or(DEFAULT_HISTORY_LIMIT).min(MAX_HISTORY_LIMIT);
+        with_repo(ctx, move |repo, view| {
+            let path = path.as_deref().unwrap_or_default();
+            if !view.can_read_path(path) {
+                return Ok(Vec::new());
+            }
+            let log = History::new(repo)?.log_path(&[id], path, Some(limit))?;
+            log.into_iter().map(|commit| CommitNode::load(repo, commit.id)).collect()
+        })
+        .await
+    }
+}
+
+/// 某个提交中的目录
+pub struct TreeNode {
+    commit: ObjectId,
+    path: String,
+    id: ObjectId,
+    entries: Vec<TreeEntry>,
+}
+
+impl TreeNode {
+    /// 读取提交中的目录，不存在、不可读或不是目录时返回 None；不可读的条目被去掉
+    fn load(repo: &Repository, view: &ReadView, commit: ObjectId, path: &str) -> MonoResult<Option<TreeNode>> {
+        let path = path.trim_matches('/');
+        if !view.can_read_path(path) {
+            return Ok(None);
+        }
+        let tree = repo.read_commit(&commit)?.tree;
+        let Some(entry) = repo.find_path(&tree, path)?.filter(|e| e.mode.is_tree()) else {
+            return Ok(None);
+        };
+        let mut entries = repo.read_tree(&entry.id)?.entries;
+        view.filter_entries(path, &mut entries);
+        Ok(Some(TreeNode {
+            commit,
+            path: path.to_string(),
+            id: entry.id,
+            entries,
+        }))
+    }
+}
+
+#[Object(name = "Tree")]
+impl TreeNode {
+    async fn id(&self) -> String {
+        self.id.to_hex()
+    }
+
+    /// 相对仓库根目录的路径，根目录为空字符串
+    async fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// 目录条目；请求 `lastCommit` 时对整个目录只遍历一次历史
+    async fn entries(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<EntryNode>> {
+        let last_commits = match ctx.look_ahead().field("lastCommit").exists() {
+            true => {
+                let (commit, path) = (self.commit, self.path.clone());
+                let last = with_repo(ctx, move |repo, _| {
+                    // 多个条目可能由同一个提交最后修改，每个提交只读取一次
+                    let mut commits: HashMap<ObjectId, Arc<CommitNode>> = HashMap::new();
+                    let mut last = BTreeMap::new();
+                    for (name, id) in History::new(repo)?.last_commits(&commit, &path)? {
+                        let node = match commits.get(&id) {
+                            Some(node) => node.clone(),
+                            None => {
+                                let node = Arc::new(CommitNode::load(repo, id)?);
+                                commits.insert(id, node.clone());
+                                node
+                            }
+                        };
+                        last.insert(name, node);
+                    }
+                    Ok(last)
+                })
+                .await?;
+                Some(last)
+            }
+            false => None,
+        };
+        Ok(self
+            .entries
+            .iter()
+            .map(|entry| EntryNode {
+                commit: self.commit,
+                path: match self.path.is_empty() {
+                    true => entry.name.clone(),
+                    false => format!("{}/{}", self.path, entry.name),
+                },
+                last_commit: last_commits.as_ref().and_then(|last| last.get(&entry.name).cloned()),
+                entry: entry.clone(),
+            })
+            .collect())
+    }
+}
+
+/// 目录条目
+pub struct EntryNode {
+    commit: ObjectId,
+    path: String,
+    entry: TreeEntry,
+    last_commit: Option<Arc<CommitNode>>,
+}
+
+#[Object(name = "TreeEntry")]
+impl EntryNode {
+    async fn name(&self) -> &str {
+        &self.entry.name
+    }
+
+    /// 相对仓库根目录的路径
+    async fn path(&self) -> &str {
+        &self.path
+    }
+
+    async fn kind(&self) -> EntryKind {
+        EntryKind::of(self.entry.mode)
+    }
+
+    /// 八进制文件模式，例如 `100644`
+    async fn mode(&self) -> String {
+        format!("{:06o}", self.entry.mode.0)
+    }
+
+    async fn id(&self) -> String {
+        self.entry.id.to_hex()
+    }
+
+    /// 最后修改该条目的提交
+    async fn last_commit(&self) -> Option<&CommitNode> {
+        self.last_commit.as_deref()
+    }
+
+    /// 条目是目录时为其内容
+    async fn tree(&self, ctx: &Context<'_>) -> async_graphql::Result<Option<TreeNode>> {
+        if !self.entry.mode.is_tree() {
+            return Ok(None);
+        }
+        let (commit, path) = (self.commit, self.path.clone());
+        with_repo(ctx, move |repo, view| TreeNode::load(repo, view, commit, &path)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{commit_files, init_repo};
+
+    fn query(repo: &Arc<Repository>, query: &str) -> serde_json::Value {
+        query_as(repo, Access::full("test"), query)
+    }
+
+    fn query_as(repo: &Arc<Repository>, access: Access, query: &str) -> serde_json::Value {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        let view = Arc::new(ReadView::new(repo, access).unwrap());
+        let request = async_graphql::Request::new(query).data(view);
+        let response = runtime.block_on(schema(repo.clone()).execute(request));
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        serde_json::to_value(response.data).unwrap()
+    }
+
+    /// 测试一次查询取回目录条目及其最后修改的提交
+    #[test]
+    fn test_tree_last_commits() {
+        let (_dir, repo) = init_repo();
+        let first = commit_files(&repo, &[("src/a.rs", b"a"), ("src/b.rs", b"b")], &[], "first\n\nbody");
+        let second = commit_files(&repo, &[("src/a.rs", b"a2"), ("src/b.rs", b"b")], &[first], "second");
+        repo.refs().write("refs/heads/main", &second).unwrap();
+        let repo = Arc::new(repo);
+
+        let data = query(
+            &repo,
+            r#"{ tree(rev: "main", path: "/src") { path entries { name path kind lastCommit { id summary } } } }"#,
+        );
+        let tree = &data["tree"];
+        assert_eq!(tree["path"], "src");
+        assert_eq!(tree["entries"][0]["path"], "src/a.rs");
+        assert_eq!(tree["entries"][0]["kind"], "FILE");
+        assert_eq!(tree["entries"][0]["lastCommit"]["id"], second.to_hex());
+        assert_eq!(tree["entries"][1]["lastCommit"]["summary"], "first");
+
+        let data = query(&repo, r#"{ commit(rev: "main") { parents { id } history(path: "src/b.rs") { id } } }"#);
+        assert_eq!(data["commit"]["parents"][0]["id"], first.to_hex());
+        assert_eq!(data["commit"]["history"], serde_json::json!([{ "id": first.to_hex() }]));
+
+        let data = query(&repo, r#"{ missing: commit(rev: "nope") { id } file: tree(path: "src/a.rs") { id } }"#);
+        assert!(data["missing"].is_null());
+        assert!(data["file"].is_null());
+
+        let data = query(&repo, r#"{ refs(prefix: "refs/heads/") { name commit { id } } }"#);
+        assert_eq!(data["refs"][0]["commit"]["id"], second.to_hex());
+    }
+
+    /// 测试受 `[[acl]]` 限制的身份查不到隐藏的引用、提交与目录
+    #[test]
+    fn test_acl() {
+        let (_dir, mut repo) = init_repo();
+        let (main, private) = crate::test_utils::restrict_secrets(&mut repo);
+        let repo = Arc::new(repo);
+        let text = format!(
+            r#"{{ refs {{ name }} private: commit(rev: "private") {{ id }} byId: commit(rev: "{}") {{ id }}
+                 root: tree(rev: "main") {{ entries {{ name }} }} secrets: tree(rev: "main", path: "secrets") {{ id }}
+                 main: commit(rev: "main") {{ history(path: "secrets") {{ id }} tree(path: "//secrets") {{ id }} }} }}"#,
+            private
+        );
+
+        let data = query_as(&repo, crate::test_utils::reader("user:bob"), &text);
+        assert_eq!(data["refs"], serde_json::json!([{ "name": "refs/heads/main" }]));
+        assert!(data["private"].is_null() && data["byId"].is_null() && data["secrets"].is_null());
+        assert_eq!(data["root"]["entries"], serde_json::json!([{ "name": "README.md" }]));
+        assert_eq!(data["main"]["history"], serde_json::json!([]));
+        assert!(data["main"]["tree"].is_null());
+
+        let data = query_as(&repo, crate::test_utils::reader("user:alice"), &text);
+        assert_eq!(data["refs"].as_array().unwrap().len(), 2);
+        assert_eq!(data["byId"]["id"], private.to_hex());
+        assert!(data["secrets"]["id"].is_string());
+        assert_eq!(data["main"]["history"], serde_json::json!([{ "id": main.to_hex() }]));
+    }
+}
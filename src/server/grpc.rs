@@ -0,0 +1,686 @@
+//! gRPC 仓库服务
+//!
+//! 服务定义见 `proto/monoengine.proto`：构建系统与机器人可以直接解析修订、读取目录与文件、
+//! 查询历史以及创建提交，而不需要启动 git 或检出工作区。`CreateCommit` 与推送一样检查推送
+//! 策略并执行服务端钩子，不能借此绕过分支保护。
+//!
+//! 请求通过 `authorization` 元数据携带 [`auth`] 签发的令牌，读取需要 `read` 权限，
+//! 创建提交需要 `write` 权限。`[[acl]]` 规则对请求的身份隐藏的引用与路径按不存在处理（见 [`ReadView`]）。超过 `[rate_limit]` 的请求返回 `RESOURCE_EXHAUSTED`，
+//! 元数据 `retry-after` 为建议等待的秒数。只读维护模式（见 [`crate::freeze`]）下创建提交返回 `UNAVAILABLE`。
+//!
+//! 配置了租户时请求必须通过 `repository` 元数据指定租户，只能访问该租户命名空间中的引用；
+//! 缺少或不存在的租户返回 `NOT_FOUND`。
+
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Instant;
+
+use tokio_stream::Stream;
+use tonic::{Request, Response, Status};
+use tracing::Instrument;
+
+use crate::audit::{AuditAction, AuditLog};
+use crate::auth::acl::ReadView;
+use crate::auth::{self, Access};
+use crate::common::config::Scope;
+use crate::common::errors::{MonoError, MonoErrorKind};
+use crate::common::MonoResult;
+use crate::freeze;
+use crate::graph::history::History;
+use crate::hooks::Hooks;
+use crate::metrics;
+use crate::object::commit::{Commit, Signature};
+use crate::object::tree::{FileMode, Tree, TreeEdit, TreeEntry};
+use crate::object::{ObjectId, ObjectType};
+use crate::policy::Policy;
+use crate::refs::{self, RefUpdate};
+use crate::replication;
+use crate::repo::Repository;
+use crate::server::ratelimit::{self, Client, Throttled};
+use crate::server::{blocking, shutdown, tenant_repo};
+use crate::server::reload::SharedRepo;
+
+#[allow(clippy::all)]
+pub mod proto {
+    tonic::include_proto!("monoengine.v1");
+}
+
+use proto::repository_server::{Repository as RepositoryApi, RepositoryServer};
+
+/// `ReadBlob` 每个响应携带的数据大小
+const BLOB_CHUNK_SIZE: usize = 1 << 20;
+
+/// 将错误按类别映射为 gRPC 状态码
+fn status(err: MonoError) -> Status {
+    let message = err.to_string();
+    match err.kind() {
+        MonoErrorKind::NotFound(_) => Status::not_found(message),
+        MonoErrorKind::Usage(_) | MonoErrorKind::Protocol(_) => Status::invalid_argument(message),
+        MonoErrorKind::Auth(_) | MonoErrorKind::Policy(_) => Status::permission_denied(message),
+        MonoErrorKind::Unavailable(_) => Status::unavailable(message),
+        _ => {
+            tracing::error!(error = %message, "grpc request failed");
+            Status::internal(message)
+        }
+    }
+}
+
+/// 被限流的请求返回 `RESOURCE_EXHAUSTED`，元数据 `retry-after` 为建议等待的秒数
+fn resource_exhausted(throttled: Throttled) -> Status {
+    let mut status = Status::resource_exhausted(throttled.to_string());
+    status.metadata_mut().insert("retry-after", throttled.retry_after_secs().into());
+    status
+}
+
+/// 按方法记录请求数与耗时，结果为 gRPC 状态码
+fn observe<T>(method: &str, start: Instant, result: &Result<T, Status>) {
+    let code = match result {
+        Ok(_) => tonic::Code::Ok,
+        Err(status) => status.code(),
+    };
+    metrics::observe_request("grpc", method, &format!("{:?}", code), start.elapsed());
+}
+
+/// 解析请求身份可见的修订，空修订表示 HEAD
+fn resolve(repo: &Repository, view: &ReadView, revision: &str) -> MonoResult<ObjectId> {
+    view.resolve(repo, if revision.is_empty() { refs::HEAD } else { revision })
+}
+
+/// 查找修订中请求身份可读的路径，路径可以带 `//` 前缀
+fn find(repo: &Repository, view: &ReadView, revision: &str, path: &str) -> MonoResult<TreeEntry> {
+    let tree = repo.read_commit(&resolve(repo, view, revision)?)?.tree;
+    view.check_path(path)?;
+    repo.find_path(&tree, path.trim_start_matches('/'))?
+        .ok_or_else(|| MonoError::not_found(format!("path {} in {}", path, revision)))
+}
+
+fn to_signature(signature: &Signature) -> proto::Signature {
+    proto::Signature {
+        name: signature.name.clone(),
+        email: signature.email.clone(),
+        timestamp: signature.timestamp,
+    }
+}
+
+fn to_commit(id: &ObjectId, commit: &Commit) -> proto::Commit {
+    proto::Commit {
+        id: id.to_hex(),
+        tree: commit.tree.to_hex(),
+        parents: commit.parents.iter().map(ObjectId::to_hex).collect(),
+        author: Some(to_signature(&commit.author)),
+        committer: Some(to_signature(&commit.committer)),
+        message: commit.message.clone(),
+    }
+}
+
+fn to_entry(entry: &TreeEntry) -> proto::TreeEntry {
+    use proto::tree_entry::Kind;
+
+    let kind = if entry.mode.is_tree() {
+        Kind::Directory
+    } else if entry.mode.is_gitlink() {
+        Kind::Submodule
+    } else if entry.mode == FileMode::SYMLINK {
+        Kind::Symlink
+    } else {
+        Kind::File
+    };
+    proto::TreeEntry {
+        name: entry.name.clone(),
+        kind: kind.into(),
+        mode: entry.mode.0,
+        object_id: entry.id.to_hex(),
+    }
+}
+
+/// 在分支上创建提交，只读镜像、权限不足、推送策略或钩子拒绝、分支位置不符时返回 `Ok(Err(状态))`
+fn create_commit(
+    repo: &Repository,
+    request: proto::CreateCommitRequest,
+    access: &Access,
+) -> MonoResult<Result<ObjectId, Status>> {
+    let branch = if request.branch.starts_with(refs::HEADS_PREFIX) {
+        request.branch.clone()
+    } else {
+        format!("{}{}", refs::HEADS_PREFIX, request.branch)
+    };
+    if !refs::check_ref_format(&branch) {
+        return Err(MonoError::usage(format!("invalid branch name: {}", request.branch)));
+    }
+    let author = request
+        .author
+        .filter(|author| !author.name.is_empty() && !author.email.is_empty())
+        .ok_or_else(|| MonoError::usage("author name and email are required"))?;
+    if request.message.trim().is_empty() {
+        return Err(MonoError::usage("commit message must not be empty"));
+    }
+    if let Some(reason) = replication::read_only_reason(repo) {
+        return Ok(Err(Status::failed_precondition(reason)));
+    }
+    freeze::check(repo)?;
+
+    let current = repo.refs().resolve(&branch)?;
+    if !request.expected_head.is_empty() {
+        let expected: ObjectId = request
+            .expected_head
+            .parse()
+            .map_err(|_| MonoError::usage(format!("invalid expected_head: {}", request.expected_head)))?;
+        if current.unwrap_or(ObjectId::ZERO) != expected {
+            return Ok(Err(Status::failed_precondition(format!(
+                "{} is at {}, expected {}",
+                refs::short_name(&branch),
+                current.unwrap_or(ObjectId::ZERO),
+                expected
+            ))));
+        }
+    }
+
+    let mut edits = Vec::with_capacity(request.changes.len());
+    for change in request.changes {
+        let entry = match change.action {
+            Some(proto::file_change::Action::Content(content)) => {
+                let mode = if change.executable { FileMode::EXECUTABLE } else { FileMode::BLOB };
+                Some((mode, repo.write_object(ObjectType::Blob, &content)?))
+            }
+            Some(proto::file_change::Action::Delete(true)) => None,
+            _ => return Err(MonoError::usage(format!("no action for {}", change.path))),
+        };
+        edits.push(TreeEdit {
+            path: change.path,
+            entry,
+        });
+    }
+    let base = match current {
+        Some(head) => Some(repo.read_commit(&head)?.tree),
+        None => None,
+    };
+    let tree = match repo.edit_tree(base.as_ref(), &edits)? {
+        Some(tree) => tree,
+        None => repo.write_object(ObjectType::Tree, &Tree::default().encode())?,
+    };
+
+    let timestamp = match author.timestamp {
+        0 => chrono::Utc::now().timestamp(),
+        timestamp => timestamp,
+    };
+    let author = Signature::new(author.name, author.email, timestamp);
+    let mut message = request.message;
+    if !message.ends_with('\n') {
+        message.push('\n');
+    }
+    let commit = Commit {
+        tree,
+        parents: current.into_iter().collect(),
+        author: author.clone(),
+        committer: author,
+        extra_headers: Vec::new(),
+        message,
+    };
+    let id = repo.write_object(ObjectType::Commit, &commit.encode())?;
+
+    let update = RefUpdate {
+        name: branch,
+        old: current.unwrap_or(ObjectId::ZERO),
+        new: id,
+    };
+    if let Err(reason) = access.check_update(repo, &update)? {
+        return Ok(Err(Status::permission_denied(reason)));
+    }
+    Policy::load(repo)?.check(repo, &update, &[])?;
+    let hooks = Hooks::load(repo);
+    let updates = std::slice::from_ref(&update);
+    if let Err(reason) = hooks.pre_receive(repo, updates, &[]).and_then(|()| hooks.update(repo, &update, &[])) {
+        return Ok(Err(Status::permission_denied(reason)));
+    }
+    repo.with_reflog_identity(&access.principal, "create commit").refs().update(updates)?;
+    AuditLog::new(repo).record_ref_updates(repo, &access.principal, AuditAction::RefUpdate, updates, chrono::Utc::now().timestamp());
+    hooks.post_receive(repo, updates, &[]);
+    tracing::info!(branch = %update.name, commit = %id, "created commit over grpc");
+    Ok(Ok(id))
+}
+
+/// 实现 `monoengine.v1.Repository` 服务，每个调用使用开始时 `repo` 中的仓库句柄
+pub struct RepositoryService {
+    repo: SharedRepo,
+}
+
+impl RepositoryService {
+    pub fn new(repo: impl Into<SharedRepo>) -> RepositoryService {
+        RepositoryService { repo: repo.into() }
+    }
+
+    /// 按请求元数据中的 `authorization` 认证并检查权限与限额，格式与 HTTP 的 `Authorization` 头相同
+    ///
+    /// 返回请求元数据中 `repository` 选择的租户仓库（见 [`tenant_repo`]）与请求的身份。
+    async fn authorize<T>(&self, request: &Request<T>, scope: Scope) -> Result<(Arc<Repository>, Access), Status> {
+        let token = request
+            .metadata()
+            .get("authorization")
+            .and_then(|v| v.to_str().ok())
+            .and_then(auth::parse_authorization);
+        let tenant = request.metadata().get("repository").and_then(|v| v.to_str().ok());
+        let root = self.repo.current();
+        let repo = root.clone();
+        let config = repo.config().rate_limit.clone();
+        let repo_key = ratelimit::repo_key(&repo, tenant);
+        // 在认证之前按 IP 计数，认证失败的尝试同样消耗令牌
+        ratelimit::limiter()
+            .check_ip(&config, request.remote_addr().map(|addr| addr.ip()))
+            .map_err(resource_exhausted)?;
+        let access = blocking(move || auth::authenticate(&repo, token.as_deref(), chrono::Utc::now().timestamp()))
+            .await
+            .map_err(|e| match e.kind() {
+                MonoErrorKind::Auth(reason) => Status::unauthenticated(reason.clone()),
+                _ => status(e),
+            })?;
+        match access.require(scope) {
+            Ok(()) => {}
+            Err(e) if access.is_anonymous() => return Err(Status::unauthenticated(e.to_string())),
+            Err(e) => return Err(status(e)),
+        }
+        let client = Client { principal: &access.principal, repo: &repo_key };
+        ratelimit::limiter().check(&config, &client).map_err(resource_exhausted)?;
+        Ok((tenant_repo(&root, tenant).map_err(status)?, access))
+    }
+}
+
+type BlobStream = Pin<Box<dyn Stream<Item = Result<proto::ReadBlobResponse, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl RepositoryApi for RepositoryService {
+    async fn resolve_ref(
+        &self,
+        request: Request<proto::ResolveRefRequest>,
+    ) -> Result<Response<proto::ResolveRefResponse>, Status> {
+        let start = Instant::now();
+        let result: Result<Response<proto::ResolveRefResponse>, Status> = async {
+            let (repo, access) = self.authorize(&request, Scope::Read).await?;
+            let request = request.into_inner();
+            let id = blocking(move || {
+                let view = ReadView::new(&repo, access)?;
+                let id = resolve(&repo, &view, &request.revision)?;
+                repo.read_commit(&id)?;
+                Ok(id)
+            })
+            .await
+            .map_err(status)?;
+            Ok(Response::new(proto::ResolveRefResponse { commit_id: id.to_hex() }))
+        }
+        .instrument(tracing::info_span!("grpc.request", method = "ResolveRef"))
+        .await;
+        observe("ResolveRef", start, &result);
+        result
+    }
+
+    async fn read_tree(&self, request: Request<proto::ReadTreeRequest>) -> Result<Response<proto::ReadTreeResponse>, Status> {
+        let start = Instant::now();
+        let result: Result<Response<proto::ReadTreeResponse>, Status> = async {
+            let (repo, access) = self.authorize(&request, Scope::Read).await?;
+            let request = request.into_inner();
+            let response = blocking(move || {
+                let view = ReadView::new(&repo, access)?;
+                let entry = find(&repo, &view, &request.revision, &request.path)?;
+                if !entry.mode.is_tree() {
+                    return Err(MonoError::usage(format!("{} is not a directory", request.path)));
+                }
+                let mut entries = repo.read_tree(&entry.id)?.entries;
+                view.filter_entries(&request.path, &mut entries);
+                Ok(proto::ReadTreeResponse {
+                    tree_id: entry.id.to_hex(),
+                    entries: entries.iter().map(to_entry).collect(),
+                })
+            })
+            .await
+            .map_err(status)?;
+            Ok(Response::new(response))
+        }
+        .instrument(tracing::info_span!("grpc.request", method = "ReadTree"))
+        .await;
+        observe("ReadTree", start, &result);
+        result
+    }
+
+    type ReadBlobStream = BlobStream;
+
+    async fn read_blob(&self, request: Request<proto::ReadBlobRequest>) -> Result<Response<BlobStream>, Status> {
+        let start = Instant::now();
+        let result: Result<Response<BlobStream>, Status> = async {
+            let (repo, access) = self.authorize(&request, Scope::Read).await?;
+            let request = request.into_inner();
+            let (id, data) = blocking(move || {
+                let view = ReadView::new(&repo, access)?;
+                let entry = find(&repo, &view, &request.revision, &request.path)?;
+                if !entry.mode.is_blob() {
+                    return Err(MonoError::usage(format!("{} is not a file", request.path)));
+                }
+                Ok((entry.id, repo.read_object(&entry.id)?.data))
+            })
+            .await
+            .map_err(status)?;
+
+            let size = data.len() as u64;
+            let mut responses: Vec<Result<proto::ReadBlobResponse, Status>> = data
+                .chunks(BLOB_CHUNK_SIZE)
+                .map(|chunk| {
+                    Ok(proto::ReadBlobResponse {
+                        data: chunk.to_vec(),
+                        ..Default::default()
+                    })
+                })
+                .collect();
+            if responses.is_empty() {
+                responses.push(Ok(proto::ReadBlobResponse::default()));
+            }
+            if let Some(Ok(first)) = responses.first_mut() {
+                first.object_id = id.to_hex();
+                first.size = size;
+            }
+            Ok(Response::new(Box::pin(tokio_stream::iter(responses)) as BlobStream))
+        }
+        .instrument(tracing::info_span!("grpc.request", method = "ReadBlob"))
+        .await;
+        observe("ReadBlob", start, &result);
+        result
+    }
+
+    async fn list_history(
+        &self,
+        request: Request<proto::ListHistoryRequest>,
+    ) -> Result<Response<proto::ListHistoryResponse>, Status> {
+        let start = Instant::now();
+        let result: Result<Response<proto::ListHistoryResponse>, Status> = async {
+            let (repo, access) = self.authorize(&request, Scope::Read).await?;
+            let request = request.into_inner();
+            let commits = blocking(move || {
+                let view = ReadView::new(&repo, access)?;
+                let tip = resolve(&repo, &view, &request.revision)?;
+                view.check_path(&request.path)?;
+                let limit = (request.limit > 0).then_some(request.limit as usize);
+                let log = History::new(&repo)?.log_path(&[tip], &request.path, limit)?;
+                let mut commits = Vec::with_capacity(log.len());
+                for entry in log {
+                    commits.push(to_commit(&entry.id, &repo.read_commit(&entry.id)?));
+                }
+                Ok(commits)
+            })
+            .await
+            .map_err(status)?;
+            Ok(Response::new(proto::ListHistoryResponse { commits }))
+        }
+        .instrument(tracing::info_span!("grpc.request", method = "ListHistory"))
+        .await;
+        observe("ListHistory", start, &result);
+        result
+    }
+
+    async fn create_commit(
+        &self,
+        request: Request<proto::CreateCommitRequest>,
+    ) -> Result<Response<proto::CreateCommitResponse>, Status> {
+        let start = Instant::now();
+        let result: Result<Response<proto::CreateCommitResponse>, Status> = async {
+            let (repo, access) = self.authorize(&request, Scope::Write).await?;
+            let request = request.into_inner();
+            let id = blocking(move || create_commit(&repo, request, &access)).await.map_err(status)??;
+            Ok(Response::new(proto::CreateCommitResponse { commit_id: id.to_hex() }))
+        }
+        .instrument(tracing::info_span!("grpc.request", method = "CreateCommit"))
+        .await;
+        observe("CreateCommit", start, &result);
+        result
+    }
+}
+
+/// 在 `addr` 上提供 gRPC 服务，直到出错退出或排空结束；排空时等进行中的请求完成
+pub async fn serve(repo: SharedRepo, addr: SocketAddr) -> MonoResult<()> {
+    tracing::info!(%addr, root = %repo.current().root().display(), "serving grpc");
+    tonic::transport::Server::builder()
+        .add_service(RepositoryServer::new(RepositoryService::new(repo)))
+        .serve_with_shutdown(addr, shutdown::drain().draining())
+        .await
+        .map_err(|e| MonoError::from(anyhow::Error::from(e)).context(format!("serving grpc on {}", addr)))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{commit_files, init_repo};
+
+    fn run<T>(future: impl std::future::Future<Output = T>) -> T {
+        tokio::runtime::Runtime::new().unwrap().block_on(future)
+    }
+
+    /// 测试解析修订、读取目录与文件，以及按路径过滤历史
+    #[test]
+    fn test_read() {
+        let (_dir, repo) = init_repo();
+        let first = commit_files(&repo, &[("a/x.txt", b"x"), ("b", b"1")], &[], "first");
+        let second = commit_files(&repo, &[("a/x.txt", b"x"), ("b", b"2")], &[first], "second");
+        repo.refs().write("refs/heads/main", &second).unwrap();
+        let service = RepositoryService::new(Arc::new(repo));
+
+        run(async {
+            let resolved = service
+                .resolve_ref(Request::new(proto::ResolveRefRequest {
+                    revision: "main".to_string(),
+                }))
+                .await
+                .unwrap();
+            assert_eq!(resolved.into_inner().commit_id, second.to_hex());
+
+            let tree = service
+                .read_tree(Request::new(proto::ReadTreeRequest {
+                    revision: String::new(),
+                    path: "//a".to_string(),
+                }))
+                .await
+                .unwrap()
+                .into_inner();
+            assert_eq!(tree.entries.len(), 1);
+            assert_eq!(tree.entries[0].name, "x.txt");
+            assert_eq!(tree.entries[0].kind(), proto::tree_entry::Kind::File);
+
+            let mut blob = service
+                .read_blob(Request::new(proto::ReadBlobRequest {
+                    revision: first.to_hex(),
+                    path: "b".to_string(),
+                }))
+                .await
+                .unwrap()
+                .into_inner();
+            let chunk = tokio_stream::StreamExt::next(&mut blob).await.unwrap().unwrap();
+            assert_eq!((chunk.size, chunk.data), (1, b"1".to_vec()));
+
+            let missing = service
+                .read_blob(Request::new(proto::ReadBlobRequest {
+                    revision: "main".to_string(),
+                    path: "missing".to_string(),
+                }))
+                .await;
+            assert_eq!(missing.err().unwrap().code(), tonic::Code::NotFound);
+
+            let history = |path: &str| proto::ListHistoryRequest {
+                revision: "main".to_string(),
+                path: path.to_string(),
+                limit: 0,
+            };
+            let ids = |response: proto::ListHistoryResponse| response.commits.into_iter().map(|c| c.id).collect::<Vec<_>>();
+            let all = service.list_history(Request::new(history(""))).await.unwrap().into_inner();
+            assert_eq!(ids(all), [second.to_hex(), first.to_hex()]);
+            let a = service.list_history(Request::new(history("a"))).await.unwrap().into_inner();
+            assert_eq!(ids(a), [first.to_hex()]);
+        });
+    }
+
+    /// 测试受 `[[acl]]` 限制的匿名请求读不到隐藏的引用与路径，admin 令牌不受限制
+    #[test]
+    fn test_read_acl() {
+        let (_dir, mut repo) = init_repo();
+        let (_, private) = crate::test_utils::restrict_secrets(&mut repo);
+        let (_, admin) = auth::TokenStore::new(&repo).create("admin", &[Scope::Admin], &[], None, 0).unwrap();
+        let service = RepositoryService::new(Arc::new(repo));
+        fn with_token<T>(request: T, token: Option<&str>) -> Request<T> {
+            let mut request = Request::new(request);
+            if let Some(token) = token {
+                request
+                    .metadata_mut()
+                    .insert("authorization", format!("Bearer {}", token).parse().unwrap());
+            }
+            request
+        }
+        fn code<T>(result: Result<T, Status>) -> tonic::Code {
+            result.err().map_or(tonic::Code::Ok, |status| status.code())
+        }
+
+        run(async {
+            for token in [None, Some(admin.as_str())] {
+                let expected = if token.is_none() { tonic::Code::NotFound } else { tonic::Code::Ok };
+                for revision in ["private".to_string(), private.to_hex()] {
+                    let request = proto::ResolveRefRequest { revision };
+                    assert_eq!(code(service.resolve_ref(with_token(request, token)).await), expected);
+                }
+                let request = proto::ReadBlobRequest {
+                    revision: "main".to_string(),
+                    path: "secrets/key".to_string(),
+                };
+                assert_eq!(code(service.read_blob(with_token(request, token)).await), expected);
+                let request = proto::ReadTreeRequest {
+                    revision: "main".to_string(),
+                    path: "//secrets".to_string(),
+                };
+                assert_eq!(code(service.read_tree(with_token(request, token)).await), expected);
+                let request = proto::ListHistoryRequest {
+                    revision: "main".to_string(),
+                    path: "secrets".to_string(),
+                    limit: 0,
+                };
+                assert_eq!(code(service.list_history(with_token(request, token)).await), expected);
+            }
+
+            let request = proto::ReadTreeRequest {
+                revision: "main".to_string(),
+                path: String::new(),
+            };
+            let tree = service.read_tree(Request::new(request)).await.unwrap().into_inner();
+            let names: Vec<_> = tree.entries.iter().map(|entry| entry.name.as_str()).collect();
+            assert_eq!(names, ["README.md"]);
+        });
+    }
+
+    /// 测试配置了租户时按 `repository` 元数据路由到租户的命名空间，未指定租户的请求被拒绝
+    #[test]
+    fn test_tenant() {
+        let (_dir, mut repo) = init_repo();
+        repo.config_mut().namespaces.tenants = vec!["payments".to_string(), "search".to_string()];
+        let payments = repo.with_namespace("payments").unwrap();
+        let commit = commit_files(&payments, &[("pay.txt", b"pay")], &[], "payments");
+        payments.refs().write("refs/heads/main", &commit).unwrap();
+        let service = RepositoryService::new(Arc::new(repo));
+        let resolve = |tenant: Option<&str>| {
+            let mut request = Request::new(proto::ResolveRefRequest {
+                revision: "main".to_string(),
+            });
+            if let Some(tenant) = tenant {
+                request.metadata_mut().insert("repository", tenant.parse().unwrap());
+            }
+            service.resolve_ref(request)
+        };
+
+        run(async {
+            let resolved = resolve(Some("payments")).await.unwrap().into_inner();
+            assert_eq!(resolved.commit_id, commit.to_hex());
+            assert_eq!(resolve(Some("search")).await.unwrap_err().code(), tonic::Code::NotFound);
+            assert_eq!(resolve(Some("billing")).await.unwrap_err().code(), tonic::Code::NotFound);
+            assert_eq!(resolve(None).await.unwrap_err().code(), tonic::Code::NotFound);
+        });
+    }
+
+    /// 测试创建提交：写入与删除文件、检查分支位置，以及被推送策略拒绝
+    #[test]
+    fn test_create_commit() {
+        let (_dir, mut repo) = init_repo();
+        repo.config_mut().policy.push(crate::common::config::PolicyConfig {
+            name: "frozen".to_string(),
+            refs: vec!["refs/heads/main".to_string()],
+            paths: vec!["//frozen/...".to_string()],
+            require_approvals: 0,
+            deny_direct_push: true,
+            require_checks: Vec::new(),
+            bypass_option: None,
+        });
+        let service = RepositoryService::new(Arc::new(repo));
+        let change = |path: &str, content: Option<&[u8]>| proto::FileChange {
+            path: path.to_string(),
+            action: Some(match content {
+                Some(content) => proto::file_change::Action::Content(content.to_vec()),
+                None => proto::file_change::Action::Delete(true),
+            }),
+            executable: false,
+        };
+        let request = |expected_head: String, changes: Vec<proto::FileChange>| proto::CreateCommitRequest {
+            branch: "main".to_string(),
+            expected_head,
+            changes,
+            message: "bot update".to_string(),
+            author: Some(proto::Signature {
+                name: "Bot".to_string(),
+                email: "bot@example.com".to_string(),
+                timestamp: 1_800_000_000,
+            }),
+        };
+
+        run(async {
+            let first = service
+                .create_commit(Request::new(request(
+                    ObjectId::ZERO.to_hex(),
+                    vec![change("a/x.txt", Some(b"x")), change("b", Some(b"b"))],
+                )))
+                .await
+                .unwrap()
+                .into_inner()
+                .commit_id;
+            let stale = service
+                .create_commit(Request::new(request(ObjectId::ZERO.to_hex(), vec![change("b", None)])))
+                .await;
+            assert_eq!(stale.err().unwrap().code(), tonic::Code::FailedPrecondition);
+            let second = service
+                .create_commit(Request::new(request(first.clone(), vec![change("b", None)])))
+                .await
+                .unwrap()
+                .into_inner()
+                .commit_id;
+
+            let repo = &service.repo.current();
+            let commit = repo.read_commit(&second.parse().unwrap()).unwrap();
+            assert_eq!(commit.parents, [first.parse().unwrap()]);
+            assert_eq!(commit.message, "bot update\n");
+            assert!(repo.find_path(&commit.tree, "b").unwrap().is_none());
+            assert!(repo.find_path(&commit.tree, "a/x.txt").unwrap().is_some());
+
+            let denied = service
+                .create_commit(Request::new(request(String::new(), vec![change("frozen/f", Some(b"f"))])))
+                .await;
+            assert_eq!(denied.err().unwrap().code(), tonic::Code::PermissionDenied);
+            assert_eq!(repo.refs().resolve("refs/heads/main").unwrap(), Some(second.parse().unwrap()));
+
+            // 令牌只能修改 a/ 下的文件
+            let (_, secret) = auth::TokenStore::new(repo)
+                .create("bot", &[Scope::Write], &["a".to_string()], None, 0)
+                .unwrap();
+            let with_token = |token: &str, changes| {
+                let mut request = Request::new(request(String::new(), changes));
+                request
+                    .metadata_mut()
+                    .insert("authorization", format!("Bearer {}", token).parse().unwrap());
+                request
+            };
+            let outside = service.create_commit(with_token(&secret, vec![change("b", Some(b"c"))])).await;
+            assert_eq!(outside.err().unwrap().code(), tonic::Code::PermissionDenied);
+            let invalid = service.create_commit(with_token("mono_pat_bad", vec![change("a/y.txt", Some(b"y"))])).await;
+            assert_eq!(invalid.err().unwrap().code(), tonic::Code::Unauthenticated);
+            assert!(service.create_commit(with_token(&secret, vec![change("a/y.txt", Some(b"y"))])).await.is_ok());
+        });
+    }
+}
@@ -0,0 +1,603 @@
+//! git smart HTTP 协议
+//!
+//! - `GET /info/refs?service=git-upload-pack`：协议 v2 能力声明
+//! - `POST /git-upload-pack`：`ls-refs` 与 `fetch` 命令
+//! - `GET /info/refs?service=git-receive-pack` 与 `POST /git-receive-pack`：推送
+//! - Git LFS 的 batch API 与对象传输，见 [`lfs`]
+//! - `/api/v1/` 下的 REST/JSON 浏览接口，见 [`api`]；`/api/graphql` 的 GraphQL 接口，见 [`graphql`]
+//! - `GET /metrics`：Prometheus 指标，见 [`metrics`]
+//!
+//! 请求按 [`auth`] 中的令牌认证，推送需要 `write` 权限；超过 `[rate_limit]` 的请求返回 429，见 [`ratelimit`]。
+//! 仓库处于只读维护模式时写请求返回 503 与 `Retry-After`，见 [`freeze`]。
+//!
+//! 路径前可以带一级仓库名（如 `/mono.git/info/refs`），便于客户端使用常见的 URL 形式；
+//! 配置了租户时仓库名选择租户的引用命名空间，见 [`tenant_repo`]；REST 与 GraphQL 接口只在租户前缀下提供，
+//! 如 `/payments/api/v1/refs`。
+
+use std::io::{self, Read, Write};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Instant;
+
+use axum::body::{Body, Bytes};
+use axum::extract::{ConnectInfo, DefaultBodyLimit, MatchedPath, Path, Query, Request, State};
+use axum::http::{header, HeaderMap, Method, StatusCode, Uri};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Extension, Router};
+use flate2::read::GzDecoder;
+use serde::Deserialize;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::StreamExt;
+use tower::ServiceExt;
+use tracing::field::Empty;
+use tracing::Instrument;
+
+use crate::auth::{self, Access};
+use crate::common::config::Scope;
+use crate::common::errors::{MonoError, MonoErrorKind};
+use crate::common::MonoResult;
+use crate::freeze;
+use crate::metrics;
+use crate::pktline::PktWriter;
+use crate::repo::Repository;
+use crate::server::reload::SharedRepo;
+use crate::server::ratelimit::{self, Client, Throttled};
+use crate::server::{api, blocking, graphql, lfs, receive_pack, shutdown, tenant_repo, upload_pack, ChannelReader, Chunk};
+
+/// 推送请求体的默认上限
+pub const DEFAULT_MAX_BODY_SIZE: usize = 1 << 30;
+
+/// 流式响应中每个数据块的大小
+const STREAM_CHUNK_SIZE: usize = 64 * 1024;
+
+#[derive(Deserialize)]
+struct InfoRefsQuery {
+    service: Option<String>,
+}
+
+/// 处理请求时的错误，按错误类型映射为 HTTP 状态码
+struct HttpError(MonoError);
+
+impl From<MonoError> for HttpError {
+    fn from(err: MonoError) -> HttpError {
+        HttpError(err)
+    }
+}
+
+impl IntoResponse for HttpError {
+    fn into_response(self) -> Response {
+        let status = match self.0.kind() {
+            MonoErrorKind::NotFound(_) => StatusCode::NOT_FOUND,
+            MonoErrorKind::Usage(_) | MonoErrorKind::Protocol(_) => StatusCode::BAD_REQUEST,
+            MonoErrorKind::Auth(_) | MonoErrorKind::Policy(_) => StatusCode::FORBIDDEN,
+            MonoErrorKind::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        if status.is_server_error() {
+            tracing::error!(error = %self.0, "git http request failed");
+        }
+        (status, format!("{}\n", self.0)).into_response()
+    }
+}
+
+type HttpResult = Result<Response, HttpError>;
+
+/// 构建服务单个仓库的路由
+pub fn router(repo: Arc<Repository>, max_body_size: usize) -> Router {
+    let router = Router::new()
+        .route("/info/refs", get(info_refs))
+        .route("/git-upload-pack", post(upload_pack))
+        .route("/git-receive-pack", post(receive_pack))
+        .route("/{repo}/info/refs", get(info_refs))
+        .route("/{repo}/git-upload-pack", post(upload_pack))
+        .route("/{repo}/git-receive-pack", post(receive_pack))
+        .merge(lfs::router());
+    let tenants = &repo.config().namespaces.tenants;
+    // 配置了租户时浏览接口只在租户前缀下提供，按租户的命名空间读写引用
+    let router = if tenants.is_empty() {
+        router.merge(api::router()).merge(graphql::router())
+    } else {
+        tenants.iter().fold(router, |router, name| match tenant_repo(&repo, Some(name)) {
+            Ok(tenant) => router.nest(&format!("/{}", name), api::router().merge(graphql::router()).with_state(tenant)),
+            Err(e) => {
+                tracing::warn!(tenant = %name, error = %e, "skipping api routes for tenant");
+                router
+            }
+        })
+    };
+    #[cfg(feature = "metrics")]
+    let router = router.route("/metrics", get(export_metrics));
+    router
+        .layer(middleware::from_fn_with_state(repo.clone(), authorize))
+        .layer(middleware::from_fn(observe))
+        .layer(DefaultBodyLimit::max(max_body_size))
+        .with_state(repo)
+}
+
+/// 在 `addr` 上提供服务，直到进程退出或排空结束
+///
+/// 每个请求按 `repo` 当前的仓库句柄路由：重新加载配置后，之后的请求使用按新配置构建的路由，
+/// 进行中的请求不受影响。开始排空后停止接受连接，等进行中的请求与流式响应完成后返回。
+pub async fn serve(repo: SharedRepo, addr: SocketAddr, max_body_size: usize) -> MonoResult<()> {
+    let listener = tokio::net::TcpListener::bind(addr)
+        .await
+        .map_err(|e| MonoError::from(e).context(format!("binding {}", addr)))?;
+    let current = repo.current();
+    tracing::info!(%addr, root = %current.root().display(), "serving git over http");
+    let routes = Arc::new(Mutex::new((current.clone(), router(current, max_body_size))));
+    let app = Router::new().fallback_service(tower::service_fn(move |request: Request| {
+        let current = repo.current();
+        let mut routes = routes.lock().unwrap_or_else(PoisonError::into_inner);
+        if !Arc::ptr_eq(&routes.0, &current) {
+            *routes = (current.clone(), router(current, max_body_size));
+        }
+        routes.1.clone().oneshot(request)
+    }));
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
+        .with_graceful_shutdown(shutdown::drain().draining())
+        .await?;
+    Ok(())
+}
+
+/// 认证请求并检查权限：推送、上传 LFS 对象与 REST 接口的写操作需要 `write`，其余请求需要 `read`
+///
+/// 认证之前先按客户端 IP 限流，猜测令牌的请求同样计数。
+///
+/// 未携带有效令牌时返回 401 与 `WWW-Authenticate`，git 客户端据此向用户询问凭据；
+/// 令牌有效但权限不足时返回 403，超过限额时返回 429 与 `Retry-After`，写请求遇到只读维护模式时返回 503
+/// 与 `Retry-After`。
+async fn authorize(State(repo): State<Arc<Repository>>, mut request: Request, next: Next) -> Response {
+    let required = required_scope(request.method(), request.uri());
+    let ip = request.extensions().get::<ConnectInfo<SocketAddr>>().map(|info| info.0.ip());
+    let tenant = request_tenant(&repo, &request);
+    let repo_key = ratelimit::repo_key(&repo, tenant);
+    let limits = repo.config().rate_limit.clone();
+    // 地址中的租户不存在时由处理函数报告
+    let frozen = match required {
+        Scope::Read => None,
+        _ => tenant_repo(&repo, tenant)
+            .and_then(|repo| freeze::frozen(&repo))
+            .unwrap_or_default(),
+    };
+    // 在认证之前按 IP 计数，认证失败的尝试同样消耗令牌
+    if let Err(throttled) = ratelimit::limiter().check_ip(&limits, ip) {
+        tracing::info!(?ip, reason = %throttled, "http request throttled");
+        return too_many_requests(throttled);
+    }
+    let token = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(auth::parse_authorization);
+    let result = blocking(move || auth::authenticate(&repo, token.as_deref(), chrono::Utc::now().timestamp())).await;
+    let access = match result {
+        Ok(access) => access,
+        Err(e) if matches!(e.kind(), MonoErrorKind::Auth(_)) => return unauthorized(e),
+        Err(e) => return HttpError(e).into_response(),
+    };
+    if let Err(e) = access.require(required) {
+        if access.is_anonymous() {
+            return unauthorized(e);
+        }
+        tracing::info!(principal = %access.principal, error = %e, "http request denied");
+        return HttpError(e).into_response();
+    }
+    tracing::Span::current().record("principal", access.principal.as_str());
+    let client = Client { principal: &access.principal, repo: &repo_key };
+    if let Err(throttled) = ratelimit::limiter().check(&limits, &client) {
+        tracing::info!(principal = %access.principal, reason = %throttled, "http request throttled");
+        return too_many_requests(throttled);
+    }
+    if let Some(frozen) = frozen {
+        tracing::info!(principal = %access.principal, reason = %frozen, "http write rejected");
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            [(header::RETRY_AFTER, frozen.freeze.retry_after_secs.to_string())],
+            format!("{}\n", frozen),
+        )
+            .into_response();
+    }
+    request.extensions_mut().insert(access);
+    next.run(request).await
+}
+
+/// 请求地址中的仓库名：带仓库名的 git 路由，或租户前缀下的浏览接口
+fn request_tenant<'a>(repo: &Repository, request: &'a Request) -> Option<&'a str> {
+    let first = request.uri().path().split('/').nth(1)?;
+    let routed = request
+        .extensions()
+        .get::<MatchedPath>()
+        .is_some_and(|path| path.as_str().starts_with("/{repo}/"));
+    (routed || repo.config().namespaces.tenants.iter().any(|tenant| tenant == first)).then_some(first)
+}
+
+/// 为请求创建 span 并按路由记录请求数与耗时，带仓库名的路由与不带仓库名的路由计为同一操作
+///
+/// 流式响应的耗时截止到开始发送响应体。
+async fn observe(request: Request, next: Next) -> Response {
+    let operation = match request.extensions().get::<MatchedPath>() {
+        Some(path) => {
+            let path = path.as_str();
+            let path = path.strip_prefix("/{repo}").unwrap_or(path);
+            // 租户前缀下的浏览接口与不带前缀时计为同一操作
+            path.find("/api/").map_or(path, |i| &path[i..]).to_string()
+        }
+        None => "unmatched".to_string(),
+    };
+    let span = tracing::info_span!("http.request", method = %request.method(), route = %operation, principal = Empty, status = Empty);
+    let start = Instant::now();
+    let response = next.run(request).instrument(span.clone()).await;
+    span.record("status", response.status().as_u16());
+    metrics::observe_request("http", &operation, response.status().as_str(), start.elapsed());
+    response
+}
+
+/// 以 Prometheus 文本格式导出指标
+#[cfg(feature = "metrics")]
+async fn export_metrics() -> Response {
+    match metrics::render() {
+        Some(text) => ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], text).into_response(),
+        None => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+/// 请求需要的权限
+fn required_scope(method: &Method, uri: &Uri) -> Scope {
+    let push = uri.path().ends_with("/git-receive-pack")
+        || uri.query().is_some_and(|q| q.split('&').any(|p| p == "service=git-receive-pack"));
+    // REST 接口中的 POST 请求都会修改仓库：创建提交并更新分支，或上报检查状态
+    let api_write = method == Method::POST && uri.path().contains("/api/v1/");
+    if push || api_write || method == Method::PUT {
+        Scope::Write
+    } else {
+        Scope::Read
+    }
+}
+
+fn too_many_requests(throttled: Throttled) -> Response {
+    (
+        StatusCode::TOO_MANY_REQUESTS,
+        [(header::RETRY_AFTER, throttled.retry_after_secs().to_string())],
+        format!("{}\n", throttled),
+    )
+        .into_response()
+}
+
+fn unauthorized(err: MonoError) -> Response {
+    (
+        StatusCode::UNAUTHORIZED,
+        [(header::WWW_AUTHENTICATE, "Basic realm=\"mono\"")],
+        format!("{}\n", err),
+    )
+        .into_response()
+}
+
+async fn info_refs(
+    State(repo): State<Arc<Repository>>,
+    tenant: Option<Path<String>>,
+    Query(query): Query<InfoRefsQuery>,
+    headers: HeaderMap,
+) -> HttpResult {
+    let repo = tenant_repo(&repo, tenant.as_deref().map(String::as_str))?;
+    match query.service.as_deref() {
+        Some("git-upload-pack") => {
+            // 协议 v2 的能力声明前没有 `# service=` 行
+            if !protocol_v2(&headers) {
+                return Err(MonoError::usage(
+                    "only git protocol version 2 is supported; run `git config --global protocol.version 2`",
+                )
+                .into());
+            }
+            let requested = headers
+                .get("git-protocol")
+                .and_then(|v| v.to_str().ok())
+                .and_then(upload_pack::requested_format);
+            let advertisement = blocking(move || upload_pack::advertise(&repo, requested)).await?;
+            Ok(git_response("application/x-git-upload-pack-advertisement", advertisement))
+        }
+        Some("git-receive-pack") => {
+            let advertisement = blocking(move || receive_pack::advertise(&repo)).await?;
+            let mut out = PktWriter::new();
+            out.write_line("# service=git-receive-pack")?;
+            out.flush();
+            let mut body = out.into_inner();
+            body.extend_from_slice(&advertisement);
+            Ok(git_response("application/x-git-receive-pack-advertisement", body))
+        }
+        _ => Err(MonoError::not_found("dumb http protocol is not supported").into()),
+    }
+}
+
+/// fetch 的响应可能很大，边生成边发送；第一块数据发出前的错误仍以状态码报告
+async fn upload_pack(
+    State(repo): State<Arc<Repository>>,
+    tenant: Option<Path<String>>,
+    Extension(access): Extension<Access>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> HttpResult {
+    let repo = tenant_repo(&repo, tenant.as_deref().map(String::as_str))?;
+    let session = shutdown::drain().session()?;
+    let request = decode_body(&headers, body)?;
+    let body = stream_body("upload-pack", move |writer| {
+        let _session = session;
+        upload_pack::serve_to(&repo, &request, &access, writer)
+    })
+    .await?;
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/x-git-upload-pack-result"),
+            (header::CACHE_CONTROL, "no-cache"),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+/// 在阻塞线程中运行 `f`，把写出的数据作为流式响应体边生成边发送
+///
+/// 第一块数据发出前的错误直接返回，由调用方以状态码报告；之后的错误中断连接，
+/// 客户端不会把截断的响应当作完整响应。`what` 出现在日志中。
+pub(crate) async fn stream_body<F>(what: &'static str, f: F) -> MonoResult<Body>
+where
+    F: FnOnce(&mut ChannelWriter) -> MonoResult<()> + Send + 'static,
+{
+    let (tx, mut rx) = mpsc::channel::<Chunk>(4);
+    let task = tokio::task::spawn_blocking(move || {
+        let mut writer = ChannelWriter::new(tx.clone());
+        let result = f(&mut writer).and_then(|()| Ok(writer.flush()?));
+        if let Err(e) = &result {
+            let _ = tx.blocking_send(Err(io::Error::other(e.to_string())));
+        }
+        result
+    });
+    let first = match rx.recv().await {
+        Some(Ok(first)) => first,
+        Some(Err(_)) | None => {
+            task.await.map_err(anyhow::Error::from)??;
+            return Ok(Body::empty());
+        }
+    };
+    tokio::spawn(async move {
+        match task.await {
+            Ok(Err(e)) => tracing::warn!(error = %e, "{} failed while streaming", what),
+            Err(e) => tracing::error!(error = %e, "{} task panicked", what),
+            Ok(Ok(())) => {}
+        }
+    });
+    let stream = tokio_stream::once(Ok(first)).chain(ReceiverStream::new(rx));
+    Ok(Body::from_stream(stream))
+}
+
+/// 在阻塞线程中写出响应，按块交给异步的响应体
+pub(crate) struct ChannelWriter {
+    tx: mpsc::Sender<Chunk>,
+    buf: Vec<u8>,
+}
+
+impl ChannelWriter {
+    fn new(tx: mpsc::Sender<Chunk>) -> ChannelWriter {
+        ChannelWriter {
+            tx,
+            buf: Vec::with_capacity(STREAM_CHUNK_SIZE),
+        }
+    }
+
+    fn send(&mut self) -> io::Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        let chunk = std::mem::replace(&mut self.buf, Vec::with_capacity(STREAM_CHUNK_SIZE));
+        self.tx
+            .blocking_send(Ok(Bytes::from(chunk)))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "client disconnected"))
+    }
+}
+
+impl Write for ChannelWriter {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(data);
+        if self.buf.len() >= STREAM_CHUNK_SIZE {
+            self.send()?;
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.send()
+    }
+}
+
+/// 推送的请求体不受 `max_body_size` 限制，边接收边转存到隔离区
+async fn receive_pack(
+    State(repo): State<Arc<Repository>>,
+    tenant: Option<Path<String>>,
+    Extension(access): Extension<Access>,
+    headers: HeaderMap,
+    body: Body,
+) -> HttpResult {
+    let repo = tenant_repo(&repo, tenant.as_deref().map(String::as_str))?;
+    let session = shutdown::drain().session()?;
+    let gzip = gzip_encoded(&headers);
+    let (tx, rx) = mpsc::channel::<Chunk>(4);
+    let mut stream = body.into_data_stream();
+    tokio::spawn(async move {
+        while let Some(chunk) = stream.next().await {
+            // 处理端提前结束时不再继续读取请求体
+            if tx.send(chunk.map_err(io::Error::other)).await.is_err() {
+                break;
+            }
+        }
+    });
+    let response = blocking(move || {
+        let _session = session;
+        let mut reader = ChannelReader::new(rx);
+        if gzip {
+            receive_pack::serve_stream(&repo, &mut GzDecoder::new(reader), &access)
+        } else {
+            receive_pack::serve_stream(&repo, &mut reader, &access)
+        }
+    })
+    .await?;
+    Ok(git_response("application/x-git-receive-pack-result", response))
+}
+
+/// 客户端是否通过 `Git-Protocol` 请求头要求协议 v2
+fn protocol_v2(headers: &HeaderMap) -> bool {
+    headers
+        .get("git-protocol")
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.split(':').any(|p| p == "version=2"))
+}
+
+/// 请求体是否经过 gzip 压缩
+fn gzip_encoded(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::CONTENT_ENCODING)
+        .is_some_and(|v| v.as_bytes().eq_ignore_ascii_case(b"gzip"))
+}
+
+/// 请求体较大时 git 会使用 gzip 压缩
+fn decode_body(headers: &HeaderMap, body: Bytes) -> MonoResult<Vec<u8>> {
+    if !gzip_encoded(headers) {
+        return Ok(body.to_vec());
+    }
+    let mut data = Vec::new();
+    GzDecoder::new(&body[..])
+        .read_to_end(&mut data)
+        .map_err(|e| MonoError::protocol(format!("invalid gzip request body: {}", e)))?;
+    Ok(data)
+}
+
+fn git_response(content_type: &'static str, body: Vec<u8>) -> Response {
+    (
+        [(header::CONTENT_TYPE, content_type), (header::CACHE_CONTROL, "no-cache")],
+        body,
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 测试 Git-Protocol 请求头的解析
+    #[test]
+    fn test_protocol_v2() {
+        let mut headers = HeaderMap::new();
+        assert!(!protocol_v2(&headers));
+        headers.insert("Git-Protocol", "version=2".parse().unwrap());
+        assert!(protocol_v2(&headers));
+        headers.insert("Git-Protocol", "object-format=sha1:version=2".parse().unwrap());
+        assert!(protocol_v2(&headers));
+    }
+
+    /// 测试按请求区分需要的权限
+    #[test]
+    fn test_required_scope() {
+        let scope = |method: Method, uri: &str| required_scope(&method, &uri.parse().unwrap());
+        assert_eq!(scope(Method::GET, "/mono.git/info/refs?service=git-upload-pack"), Scope::Read);
+        assert_eq!(scope(Method::POST, "/git-upload-pack"), Scope::Read);
+        assert_eq!(scope(Method::GET, "/info/refs?service=git-receive-pack"), Scope::Write);
+        assert_eq!(scope(Method::POST, "/mono.git/git-receive-pack"), Scope::Write);
+        assert_eq!(scope(Method::PUT, "/info/lfs/objects/abc"), Scope::Write);
+        assert_eq!(scope(Method::POST, "/api/graphql"), Scope::Read);
+        assert_eq!(scope(Method::POST, "/api/v1/cherry-pick"), Scope::Write);
+        assert_eq!(scope(Method::POST, "/payments/api/v1/cherry-pick"), Scope::Write);
+    }
+
+    /// 测试解压 gzip 请求体
+    #[test]
+    fn test_decode_gzip_body() {
+        use flate2::write::GzEncoder;
+        use std::io::Write;
+
+        let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(b"0000").unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert(header::CONTENT_ENCODING, "gzip".parse().unwrap());
+        let body = Bytes::from(encoder.finish().unwrap());
+        assert_eq!(decode_body(&headers, body).unwrap(), b"0000");
+        assert!(decode_body(&headers, Bytes::from_static(b"not gzip")).is_err());
+    }
+
+    /// 测试配置了租户时浏览接口只在租户前缀下提供，只能看到该租户的引用
+    #[test]
+    fn test_tenant_api() {
+        use tower::ServiceExt;
+
+        let (_dir, mut repo) = crate::test_utils::init_repo();
+        repo.config_mut().namespaces.tenants = vec!["payments".to_string(), "search".to_string()];
+        let payments = repo.with_namespace("payments").unwrap();
+        let commit = crate::test_utils::commit_files(&payments, &[("pay.txt", b"pay")], &[], "payments");
+        payments.refs().write("refs/heads/main", &commit).unwrap();
+        let app = router(Arc::new(repo), DEFAULT_MAX_BODY_SIZE);
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(async {
+            let get = |uri: &str| {
+                let request = Request::get(uri).body(Body::empty()).unwrap();
+                app.clone().oneshot(request)
+            };
+            let response = get("/payments/api/v1/refs").await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            assert!(String::from_utf8_lossy(&body).contains(&commit.to_hex()));
+            let response = get("/search/api/v1/refs").await.unwrap();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            assert!(!String::from_utf8_lossy(&body).contains(&commit.to_hex()));
+            let response = get(&format!("/search/api/v1/commit?rev={}", commit)).await.unwrap();
+            assert_eq!(response.status(), StatusCode::NOT_FOUND);
+            assert_eq!(get("/api/v1/refs").await.unwrap().status(), StatusCode::NOT_FOUND);
+        });
+    }
+
+    /// 测试认证失败的请求按 IP 计数，超过限额后在认证之前返回 429
+    #[test]
+    fn test_rate_limit_failed_auth() {
+        use crate::common::config::RateLimit;
+
+        let (_dir, mut repo) = crate::test_utils::init_repo();
+        repo.config_mut().rate_limit.ip = Some(RateLimit { rate: 0.001, burst: 2 });
+        let app = router(Arc::new(repo), DEFAULT_MAX_BODY_SIZE);
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(async {
+            let attempt = || {
+                let mut request = Request::get("/mono.git/info/refs?service=git-receive-pack")
+                    .header(header::AUTHORIZATION, "Bearer guessed")
+                    .body(Body::empty())
+                    .unwrap();
+                request.extensions_mut().insert(ConnectInfo(SocketAddr::from(([192, 0, 2, 83], 40000))));
+                app.clone().oneshot(request)
+            };
+            assert_eq!(attempt().await.unwrap().status(), StatusCode::UNAUTHORIZED);
+            assert_eq!(attempt().await.unwrap().status(), StatusCode::UNAUTHORIZED);
+            let response = attempt().await.unwrap();
+            assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+            assert!(response.headers().contains_key(header::RETRY_AFTER));
+        });
+    }
+
+    /// 测试 `/metrics` 导出按路由统计的请求
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn test_metrics() {
+        use tower::ServiceExt;
+
+        let (_dir, repo) = crate::test_utils::init_repo();
+        let app = router(Arc::new(repo), DEFAULT_MAX_BODY_SIZE);
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        let body = runtime.block_on(async {
+            let request = |uri: &str| Request::get(uri).body(Body::empty()).unwrap();
+            let response = app.clone().oneshot(request("/mono.git/info/refs?service=git-upload-pack")).await.unwrap();
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+            let response = app.oneshot(request("/metrics")).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap()
+        });
+        let text = String::from_utf8(body.to_vec()).unwrap();
+        assert!(text.contains(r#"mono_requests_total{operation="/info/refs",protocol="http",status="400"}"#));
+    }
+}
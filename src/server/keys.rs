@@ -0,0 +1,191 @@
+//! SSH 服务使用的密钥
+//!
+//! 允许访问的公钥以 OpenSSH 格式保存在 `.mono/authorized_keys` 中，每行一个，
+//! 注释部分作为密钥名；主机密钥在首次启动时生成并保存在 `.mono/ssh_host_ed25519_key`。
+
+use std::path::Path;
+
+use russh::keys::ssh_key::LineEnding;
+use russh::keys::{Algorithm, HashAlg, PrivateKey, PublicKey};
+
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::repo::Repository;
+
+/// 公钥列表文件名，位于 `.mono` 目录下
+pub const AUTHORIZED_KEYS_FILE: &str = "authorized_keys";
+/// 主机私钥文件名，位于 `.mono` 目录下
+pub const HOST_KEY_FILE: &str = "ssh_host_ed25519_key";
+
+/// 一把允许访问的公钥
+#[derive(Debug, Clone)]
+pub struct AuthorizedKey {
+    pub name: String,
+    pub key: PublicKey,
+}
+
+impl AuthorizedKey {
+    /// SHA256 指纹，与 `ssh-keygen -l` 的输出一致
+    pub fn fingerprint(&self) -> String {
+        self.key.fingerprint(HashAlg::Sha256).to_string()
+    }
+}
+
+/// 允许访问的公钥集合
+#[derive(Debug, Clone, Default)]
+pub struct AuthorizedKeys {
+    keys: Vec<AuthorizedKey>,
+}
+
+impl AuthorizedKeys {
+    /// 读取仓库中保存的公钥，文件不存在时返回空集合
+    pub fn load(repo: &Repository) -> MonoResult<AuthorizedKeys> {
+        let path = repo.mono_dir().join(AUTHORIZED_KEYS_FILE);
+        if !path.exists() {
+            return Ok(AuthorizedKeys::default());
+        }
+        let content = std::fs::read_to_string(&path)?;
+        AuthorizedKeys::parse(&content).map_err(|e| e.context(path.display().to_string()))
+    }
+
+    /// 解析 authorized_keys 格式的文本，忽略空行与 `#` 注释
+    pub fn parse(content: &str) -> MonoResult<AuthorizedKeys> {
+        let mut keys = AuthorizedKeys::default();
+        for (i, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let key = PublicKey::from_openssh(line)
+                .map_err(|e| MonoError::config(format!("line {}: invalid public key: {}", i + 1, e)))?;
+            let name = key.comment().as_str_lossy().to_string();
+            keys.add(&name, key)
+                .map_err(|e| MonoError::config(format!("line {}: {}", i + 1, e)))?;
+        }
+        Ok(keys)
+    }
+
+    /// 写回仓库
+    pub fn save(&self, repo: &Repository) -> MonoResult<()> {
+        let mut content = String::new();
+        for entry in &self.keys {
+            let line = entry
+                .key
+                .to_openssh()
+                .map_err(|e| MonoError::config(format!("failed to encode key {}: {}", entry.name, e)))?;
+            content.push_str(&line);
+            content.push('\n');
+        }
+        std::fs::write(repo.mono_dir().join(AUTHORIZED_KEYS_FILE), content)?;
+        Ok(())
+    }
+
+    /// 添加公钥，名称与密钥都不能重复
+    pub fn add(&mut self, name: &str, mut key: PublicKey) -> MonoResult<()> {
+        if name.is_empty() || name.chars().any(char::is_whitespace) {
+            return Err(MonoError::usage(format!("invalid key name: {:?}", name)));
+        }
+        if self.keys.iter().any(|k| k.name == name) {
+            return Err(MonoError::usage(format!("key {} already exists", name)));
+        }
+        if let Some(existing) = self.find(&key) {
+            return Err(MonoError::usage(format!("key is already added as {}", existing.name)));
+        }
+        key.set_comment(name);
+        self.keys.push(AuthorizedKey {
+            name: name.to_string(),
+            key,
+        });
+        Ok(())
+    }
+
+    /// 按名称删除公钥
+    pub fn remove(&mut self, name: &str) -> Option<AuthorizedKey> {
+        let index = self.keys.iter().position(|k| k.name == name)?;
+        Some(self.keys.remove(index))
+    }
+
+    /// 查找与给定公钥相同的条目，只比较密钥本身而忽略注释
+    pub fn find(&self, key: &PublicKey) -> Option<&AuthorizedKey> {
+        self.keys.iter().find(|k| k.key.key_data() == key.key_data())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &AuthorizedKey> {
+        self.keys.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+}
+
+/// 读取主机密钥，不存在时生成新的 ed25519 密钥
+pub fn load_or_create_host_key(repo: &Repository) -> MonoResult<PrivateKey> {
+    let path = repo.mono_dir().join(HOST_KEY_FILE);
+    if path.exists() {
+        return PrivateKey::read_openssh_file(&path)
+            .map_err(|e| MonoError::config(format!("{}: invalid host key: {}", path.display(), e)));
+    }
+    let key = PrivateKey::random(&mut rand::rng(), Algorithm::Ed25519)
+        .map_err(|e| MonoError::config(format!("failed to generate host key: {}", e)))?;
+    write_private_key(&path, &key)?;
+    tracing::info!(path = %path.display(), "generated ssh host key");
+    Ok(key)
+}
+
+fn write_private_key(path: &Path, key: &PrivateKey) -> MonoResult<()> {
+    let pem = key
+        .to_openssh(LineEnding::LF)
+        .map_err(|e| MonoError::config(format!("failed to encode host key: {}", e)))?;
+    std::fs::write(path, pem.as_bytes())?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::init_repo;
+
+    fn random_key() -> PublicKey {
+        PrivateKey::random(&mut rand::rng(), Algorithm::Ed25519)
+            .unwrap()
+            .public_key()
+            .clone()
+    }
+
+    /// 测试公钥的添加、查找、删除与持久化
+    #[test]
+    fn test_authorized_keys() {
+        let (_dir, repo) = init_repo();
+        assert!(AuthorizedKeys::load(&repo).unwrap().is_empty());
+
+        let (alice, bob) = (random_key(), random_key());
+        let mut keys = AuthorizedKeys::default();
+        keys.add("alice", alice.clone()).unwrap();
+        keys.add("bob", bob.clone()).unwrap();
+        assert!(keys.add("alice", random_key()).is_err());
+        assert!(keys.add("carol", alice.clone()).is_err());
+        assert!(keys.add("bad name", random_key()).is_err());
+        keys.save(&repo).unwrap();
+
+        let mut keys = AuthorizedKeys::load(&repo).unwrap();
+        assert_eq!(keys.find(&bob).unwrap().name, "bob");
+        assert_eq!(keys.remove("alice").unwrap().name, "alice");
+        assert!(keys.find(&alice).is_none());
+        assert!(keys.remove("alice").is_none());
+    }
+
+    /// 测试主机密钥只在首次使用时生成
+    #[test]
+    fn test_host_key() {
+        let (_dir, repo) = init_repo();
+        let first = load_or_create_host_key(&repo).unwrap();
+        let second = load_or_create_host_key(&repo).unwrap();
+        assert_eq!(first.public_key(), second.public_key());
+    }
+}
@@ -0,0 +1,356 @@
+//! Git LFS 服务端
+//!
+//! 实现 LFS 的 batch API 与 basic 传输，对象保存在仓库的对象存储中，经由
+//! [`AsyncObjectStore`] 访问，S3 等远程后端不阻塞 tokio 的工作线程：
+//!
+//! - `POST <lfs>/objects/batch`：客户端列出要上传或下载的对象，服务端返回每个对象的传输地址
+//! - `GET <lfs>/objects/<oid>`：下载对象
+//! - `PUT <lfs>/objects/<oid>`：上传对象，服务端校验内容的 SHA-256
+//!
+//! `<lfs>` 可以是根路径，也可以是 git-lfs 由远端 URL 推导出的 `/info/lfs` 或 `/<仓库名>/info/lfs`。
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use axum::body::Bytes;
+use axum::extract::{Path, State};
+use axum::http::{header, HeaderMap, StatusCode, Uri};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+
+use crate::common::errors::{MonoError, MonoErrorKind};
+use crate::common::MonoResult;
+use crate::lfs::LfsOid;
+use crate::repo::Repository;
+use crate::server::blocking;
+use crate::storage::scheduler::AsyncObjectStore;
+
+/// LFS API 的内容类型
+pub const LFS_CONTENT_TYPE: &str = "application/vnd.git-lfs+json";
+
+/// 传输地址的有效期（秒）
+const ACTION_EXPIRES_IN: u64 = 3600;
+
+/// batch 请求
+#[derive(Debug, Clone, Deserialize)]
+pub struct BatchRequest {
+    /// `download` 或 `upload`
+    pub operation: String,
+    /// 客户端支持的传输方式，省略时视为 `basic`
+    #[serde(default)]
+    pub transfers: Vec<String>,
+    pub objects: Vec<BatchObject>,
+    #[serde(default)]
+    pub hash_algo: Option<String>,
+}
+
+/// batch 请求中的一个对象
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct BatchObject {
+    pub oid: String,
+    pub size: u64,
+}
+
+/// batch 响应
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BatchResponse {
+    pub transfer: String,
+    pub objects: Vec<ObjectResponse>,
+    pub hash_algo: String,
+}
+
+/// batch 响应中的一个对象：需要传输时带 `actions`，对象有问题时带 `error`，
+/// 上传时服务端已有的对象两者都没有
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ObjectResponse {
+    pub oid: String,
+    pub size: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub authenticated: Option<bool>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub actions: BTreeMap<String, Action>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<ObjectError>,
+}
+
+/// 客户端执行传输时请求的地址与附加请求头
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Action {
+    pub href: String,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub header: BTreeMap<String, String>,
+    pub expires_in: u64,
+}
+
+/// 单个对象的错误，`code` 沿用 HTTP 状态码
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ObjectError {
+    pub code: u16,
+    pub message: String,
+}
+
+impl ObjectResponse {
+    fn new(object: &BatchObject) -> ObjectResponse {
+        ObjectResponse {
+            oid: object.oid.clone(),
+            size: object.size,
+            authenticated: None,
+            actions: BTreeMap::new(),
+            error: None,
+        }
+    }
+
+    fn error(object: &BatchObject, code: StatusCode, message: &str) -> ObjectResponse {
+        ObjectResponse {
+            error: Some(ObjectError {
+                code: code.as_u16(),
+                message: message.to_string(),
+            }),
+            ..ObjectResponse::new(object)
+        }
+    }
+}
+
+/// 处理 batch 请求
+///
+/// `base` 为 LFS 端点的绝对地址（不含结尾的 `/`），传输地址为 `<base>/objects/<oid>`；
+/// `authorization` 为客户端请求中的凭证，原样附加到传输请求上。
+pub async fn batch(
+    store: &dyn AsyncObjectStore,
+    request: &BatchRequest,
+    base: &str,
+    authorization: Option<&str>,
+) -> MonoResult<BatchResponse> {
+    if !request.transfers.is_empty() && !request.transfers.iter().any(|t| t == "basic") {
+        return Err(MonoError::usage("only the basic transfer adapter is supported"));
+    }
+    if request.hash_algo.as_deref().is_some_and(|algo| algo != "sha256") {
+        return Err(MonoError::usage("only the sha256 hash algorithm is supported"));
+    }
+    let upload = match request.operation.as_str() {
+        "download" => false,
+        "upload" => true,
+        other => return Err(MonoError::usage(format!("unsupported lfs operation: {}", other))),
+    };
+
+    let mut header = BTreeMap::new();
+    if let Some(authorization) = authorization {
+        header.insert("Authorization".to_string(), authorization.to_string());
+    }
+    let oids: Vec<Option<LfsOid>> = request.objects.iter().map(|object| LfsOid::from_hex(&object.oid).ok()).collect();
+    let valid: Vec<LfsOid> = oids.iter().flatten().copied().collect();
+    let mut sizes = store.lfs_sizes(&valid).await?.into_iter();
+    let mut objects = Vec::with_capacity(request.objects.len());
+    for (object, oid) in request.objects.iter().zip(oids) {
+        let Some(oid) = oid else {
+            objects.push(ObjectResponse::error(object, StatusCode::UNPROCESSABLE_ENTITY, "invalid object id"));
+            continue;
+        };
+        let stored = sizes.next().flatten();
+        let action = Action {
+            href: format!("{}/objects/{}", base, oid),
+            header: header.clone(),
+            expires_in: ACTION_EXPIRES_IN,
+        };
+        let response = match (upload, stored) {
+            (_, Some(size)) if size != object.size => {
+                ObjectResponse::error(object, StatusCode::UNPROCESSABLE_ENTITY, "object size does not match")
+            }
+            // 服务端已有的对象不需要上传
+            (true, Some(_)) => ObjectResponse::new(object),
+            (true, None) => ObjectResponse {
+                actions: BTreeMap::from([("upload".to_string(), action)]),
+                ..ObjectResponse::new(object)
+            },
+            (false, Some(_)) => ObjectResponse {
+                actions: BTreeMap::from([("download".to_string(), action)]),
+                ..ObjectResponse::new(object)
+            },
+            (false, None) => ObjectResponse::error(object, StatusCode::NOT_FOUND, "object does not exist"),
+        };
+        objects.push(response);
+    }
+    Ok(BatchResponse {
+        transfer: "basic".to_string(),
+        objects,
+        hash_algo: "sha256".to_string(),
+    })
+}
+
+/// 保存上传的对象，内容与 `oid` 不符时拒绝
+pub async fn upload(store: &dyn AsyncObjectStore, oid: &LfsOid, data: Vec<u8>) -> MonoResult<()> {
+    // 大文件的哈希计算同样不占用 tokio 的工作线程
+    let expected = *oid;
+    let data = blocking(move || {
+        let actual = LfsOid::hash(&data);
+        if actual != expected {
+            return Err(MonoError::usage(format!("lfs object content hashes to {}, not {}", actual, expected)));
+        }
+        Ok(data)
+    })
+    .await?;
+    let size = data.len();
+    if store.lfs_size(oid).await? != Some(size as u64) {
+        store.write_lfs(oid, data).await?;
+        tracing::info!(%oid, size, "stored lfs object");
+    }
+    Ok(())
+}
+
+/// LFS 请求的错误，以 LFS 规定的 JSON 格式返回
+struct LfsError(MonoError);
+
+impl From<MonoError> for LfsError {
+    fn from(err: MonoError) -> LfsError {
+        LfsError(err)
+    }
+}
+
+impl IntoResponse for LfsError {
+    fn into_response(self) -> Response {
+        let status = match self.0.kind() {
+            MonoErrorKind::NotFound(_) => StatusCode::NOT_FOUND,
+            MonoErrorKind::Usage(_) | MonoErrorKind::Protocol(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            MonoErrorKind::Auth(_) | MonoErrorKind::Policy(_) => StatusCode::FORBIDDEN,
+            MonoErrorKind::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        if status.is_server_error() {
+            tracing::error!(error = %self.0, "lfs request failed");
+        }
+        let body = serde_json::json!({ "message": self.0.to_string() });
+        (status, [(header::CONTENT_TYPE, LFS_CONTENT_TYPE)], body.to_string()).into_response()
+    }
+}
+
+type LfsResult = Result<Response, LfsError>;
+
+/// LFS 路由，由 [`crate::server::http::router`] 合并
+pub fn router() -> Router<Arc<Repository>> {
+    Router::new()
+        .route("/objects/batch", post(batch_handler))
+        .route("/objects/{oid}", get(download_handler).put(upload_handler))
+        .route("/info/lfs/objects/batch", post(batch_handler))
+        .route("/info/lfs/objects/{oid}", get(download_handler).put(upload_handler))
+        .route("/{repo}/info/lfs/objects/batch", post(batch_handler))
+        .route("/{repo}/info/lfs/objects/{oid}", get(download_handler).put(upload_handler))
+}
+
+async fn batch_handler(State(repo): State<Arc<Repository>>, uri: Uri, headers: HeaderMap, body: Bytes) -> LfsResult {
+    let request: BatchRequest =
+        serde_json::from_slice(&body).map_err(|e| MonoError::usage(format!("invalid lfs batch request: {}", e)))?;
+    let base = endpoint(&uri, &headers)?;
+    let authorization = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .map(String::from);
+    let response = batch(&repo.async_objects(), &request, &base, authorization.as_deref()).await?;
+    Ok(([(header::CONTENT_TYPE, LFS_CONTENT_TYPE)], Json(response)).into_response())
+}
+
+async fn download_handler(State(repo): State<Arc<Repository>>, Path(params): Path<Vec<(String, String)>>) -> LfsResult {
+    let oid = oid_param(&params)?;
+    let data = repo.async_objects().read_lfs(&oid).await?;
+    let data = data.ok_or_else(|| MonoError::not_found(format!("lfs object {}", oid)))?;
+    Ok(([(header::CONTENT_TYPE, "application/octet-stream")], data).into_response())
+}
+
+async fn upload_handler(
+    State(repo): State<Arc<Repository>>,
+    Path(params): Path<Vec<(String, String)>>,
+    body: Bytes,
+) -> LfsResult {
+    let oid = oid_param(&params)?;
+    upload(&repo.async_objects(), &oid, Vec::from(body)).await?;
+    Ok(StatusCode::OK.into_response())
+}
+
+/// 路径中的 `oid` 参数；带仓库名的路由还有一个 `repo` 参数
+fn oid_param(params: &[(String, String)]) -> MonoResult<LfsOid> {
+    let (_, oid) = params
+        .iter()
+        .find(|(name, _)| name == "oid")
+        .ok_or_else(|| MonoError::usage("missing lfs object id"))?;
+    LfsOid::from_hex(oid)
+}
+
+/// 由 batch 请求的地址推导 LFS 端点的绝对地址，反向代理可以通过 `X-Forwarded-Proto` 声明协议
+fn endpoint(uri: &Uri, headers: &HeaderMap) -> MonoResult<String> {
+    let host = headers
+        .get(header::HOST)
+        .and_then(|v| v.to_str().ok())
+        .ok_or_else(|| MonoError::usage("lfs batch request without a Host header"))?;
+    let scheme = headers
+        .get("x-forwarded-proto")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("http");
+    let prefix = uri.path().strip_suffix("/objects/batch").unwrap_or_default();
+    Ok(format!("{}://{}{}", scheme, host, prefix))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::memory::MemoryStore;
+    use crate::storage::scheduler::{FetchScheduler, ScheduledStore};
+
+    fn request(operation: &str, objects: &[(&LfsOid, u64)]) -> BatchRequest {
+        BatchRequest {
+            operation: operation.to_string(),
+            transfers: vec!["basic".to_string()],
+            objects: objects
+                .iter()
+                .map(|(oid, size)| BatchObject {
+                    oid: oid.to_hex(),
+                    size: *size,
+                })
+                .collect(),
+            hash_algo: None,
+        }
+    }
+
+    /// 测试上传与下载的 batch 响应、内容校验以及不支持的请求
+    #[tokio::test]
+    async fn test_batch() {
+        let store = ScheduledStore::new(Arc::new(MemoryStore::new()), FetchScheduler::new(4));
+        let base = "http://example.com/mono.git/info/lfs";
+        let oid = LfsOid::hash(b"large file");
+        let other = LfsOid::hash(b"other");
+
+        let response = batch(&store, &request("upload", &[(&oid, 10)]), base, Some("Basic abc")).await.unwrap();
+        let action = &response.objects[0].actions["upload"];
+        assert_eq!(action.href, format!("{}/objects/{}", base, oid));
+        assert_eq!(action.header["Authorization"], "Basic abc");
+
+        assert!(upload(&store, &oid, b"tampered!!".to_vec()).await.is_err());
+        upload(&store, &oid, b"large file".to_vec()).await.unwrap();
+        let response = batch(&store, &request("upload", &[(&oid, 10)]), base, None).await.unwrap();
+        assert_eq!(response.objects[0], ObjectResponse::new(&request("upload", &[(&oid, 10)]).objects[0]));
+
+        let response = batch(&store, &request("download", &[(&oid, 10), (&other, 5), (&oid, 3)]), base, None).await.unwrap();
+        assert!(response.objects[0].actions["download"].header.is_empty());
+        assert_eq!(response.objects[1].error.as_ref().unwrap().code, 404);
+        assert_eq!(response.objects[2].error.as_ref().unwrap().code, 422);
+
+        let mut invalid = request("download", &[(&oid, 10)]);
+        invalid.objects[0].oid = "../../etc/passwd".to_string();
+        assert_eq!(batch(&store, &invalid, base, None).await.unwrap().objects[0].error.as_ref().unwrap().code, 422);
+        invalid.transfers = vec!["tus".to_string()];
+        assert!(batch(&store, &invalid, base, None).await.is_err());
+        assert!(batch(&store, &request("delete", &[]), base, None).await.is_err());
+    }
+
+    /// 测试由请求地址推导 LFS 端点
+    #[test]
+    fn test_endpoint() {
+        let mut headers = HeaderMap::new();
+        let uri: Uri = "/mono.git/info/lfs/objects/batch".parse().unwrap();
+        assert!(endpoint(&uri, &headers).is_err());
+        headers.insert(header::HOST, "example.com:8000".parse().unwrap());
+        assert_eq!(endpoint(&uri, &headers).unwrap(), "http://example.com:8000/mono.git/info/lfs");
+        headers.insert("x-forwarded-proto", "https".parse().unwrap());
+        assert_eq!(endpoint(&"/objects/batch".parse().unwrap(), &headers).unwrap(), "https://example.com:8000");
+    }
+}
@@ -0,0 +1,120 @@
+//! 内置 git 服务端
+//!
+//! 让标准 git 客户端直接对引擎的对象存储执行 clone、fetch 与 push：
+//! [`upload_pack`] 实现协议 v2 的 `ls-refs` 与 `fetch`，[`receive_pack`] 实现推送，
+//! 二者只处理请求与响应的字节流，由 [`http`] 与 [`ssh`] 传输层负责承载。
+//! [`lfs`] 通过 HTTP 提供 Git LFS 大文件的上传与下载，[`api`] 与 [`graphql`] 提供只读的浏览接口。[`grpc`] 为构建系统与机器人提供
+//! 不经过 git 协议的仓库读写接口。
+//!
+//! 各传输层在连接或请求开始时从 [`reload::SharedRepo`] 取出仓库句柄，重新加载配置不会中断进行中的连接。
+//! 退出时由 [`shutdown`] 排空进行中的 upload-pack 与 receive-pack 会话。认证之后按 [`ratelimit`] 中的令牌桶限流，
+//! 估算成本过高的 fetch 由 [`admission`] 排队或拒绝。
+//!
+//! 配置了 `[namespaces] tenants` 时，一个服务进程托管多个租户：地址中的仓库名（如
+//! `https://host/payments.git` 或 `git@host:payments`）选择同名的引用命名空间，见 [`tenant_repo`]。
+
+pub mod admission;
+pub mod api;
+pub mod graphql;
+pub mod grpc;
+pub mod http;
+pub mod keys;
+pub mod lfs;
+pub mod push_cert;
+pub mod ratelimit;
+pub mod receive_pack;
+pub mod reload;
+pub mod shutdown;
+pub mod ssh;
+pub mod upload_pack;
+
+use std::io::{self, Read};
+use std::sync::Arc;
+
+use axum::body::Bytes;
+use tokio::sync::mpsc;
+
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::repo::Repository;
+
+/// 向客户端声明的 agent
+pub const AGENT: &str = concat!("mono/", env!("CARGO_PKG_VERSION"));
+
+/// 仓库读写都是阻塞操作，放到专用线程池执行，在当前 span 之下继续记录
+pub(crate) async fn blocking<T, F>(f: F) -> MonoResult<T>
+where
+    F: FnOnce() -> MonoResult<T> + Send + 'static,
+    T: Send + 'static,
+{
+    let span = tracing::Span::current();
+    tokio::task::spawn_blocking(move || span.in_scope(f)).await.map_err(anyhow::Error::from)?
+}
+
+/// 流式请求或响应中的一个数据块
+pub(crate) type Chunk = Result<Bytes, io::Error>;
+
+/// 在阻塞线程中读取请求体，数据块由异步任务从 HTTP 请求体或 SSH 通道转发过来
+pub(crate) struct ChannelReader {
+    rx: mpsc::Receiver<Chunk>,
+    chunk: Bytes,
+}
+
+impl ChannelReader {
+    pub(crate) fn new(rx: mpsc::Receiver<Chunk>) -> ChannelReader {
+        ChannelReader { rx, chunk: Bytes::new() }
+    }
+}
+
+impl Read for ChannelReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.chunk.is_empty() {
+            match self.rx.blocking_recv() {
+                Some(chunk) => self.chunk = chunk?,
+                None => return Ok(0),
+            }
+        }
+        let len = buf.len().min(self.chunk.len());
+        buf[..len].copy_from_slice(&self.chunk[..len]);
+        self.chunk = self.chunk.slice(len..);
+        Ok(len)
+    }
+}
+
+/// 按地址中的仓库名选择租户的仓库
+///
+/// 未配置租户时仓库名只是地址的装饰，所有请求都使用 `repo`；配置后仓库名（去掉开头的 `/`
+/// 与结尾的 `.git`）必须是已配置的租户，请求只能看到该租户命名空间中的引用。
+pub fn tenant_repo(repo: &Arc<Repository>, name: Option<&str>) -> MonoResult<Arc<Repository>> {
+    let tenants = &repo.config().namespaces.tenants;
+    if tenants.is_empty() {
+        return Ok(repo.clone());
+    }
+    let name = name.unwrap_or_default().trim_start_matches('/');
+    let name = name.strip_suffix(".git").unwrap_or(name);
+    if !tenants.iter().any(|tenant| tenant == name) {
+        return Err(MonoError::not_found(format!("repository {}", name)));
+    }
+    Ok(Arc::new(repo.with_namespace(name)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::init_repo;
+
+    /// 测试按仓库名路由到租户命名空间，未配置的租户被拒绝
+    #[test]
+    fn test_tenant_repo() {
+        let (_dir, mut repo) = init_repo();
+        let plain = Arc::new(repo.clone());
+        assert!(tenant_repo(&plain, Some("anything.git")).unwrap().namespace().is_none());
+
+        repo.config_mut().namespaces.tenants = vec!["payments".to_string()];
+        let repo = Arc::new(repo);
+        assert_eq!(tenant_repo(&repo, Some("/payments.git")).unwrap().namespace(), Some("payments"));
+        assert_eq!(tenant_repo(&repo, Some("payments")).unwrap().namespace(), Some("payments"));
+        assert!(tenant_repo(&repo, Some("search")).is_err());
+        assert!(tenant_repo(&repo, None).is_err());
+    }
+}
@@ -0,0 +1,337 @@
+//! 推送证书（`git push --signed`）
+//!
+//! 配置 `[signing] push_cert = true` 时 receive-pack 声明 `push-cert=<nonce>` 能力，签名推送以推送证书
+//! 代替更新命令：证书包含推送者、推送地址、nonce、推送选项与更新命令，末尾附有推送者的 GPG 或 SSH 签名。
+//! 服务端按 `[signing]` 中的可信公钥验证签名（见 [`crate::signing`]），并要求 nonce 由本服务端签发、未超过
+//! `push_cert_slop` 且没有用过，证书中的推送地址指向本仓库，推送选项与随后发送的一致，任一不满足时拒绝
+//! 整个推送；`require_push_cert` 时同样拒绝没有证书的推送。
+//!
+//! 推送地址与 `push_cert_urls` 中的一项相同时指向本仓库；未配置 `push_cert_urls` 时地址的最后一段
+//! （去掉 `.git`）必须是仓库名，即租户名或仓库根目录名。
+//!
+//! 通过验证的证书保存在 `.mono/push-certs/<ID>`（ID 为证书作为 blob 的对象 ID），审计日志中每条更新
+//! 记录证书 ID 与签名者（见 [`crate::audit`]）。
+//!
+//! nonce 为 `<时间戳>-<随机数>-<HMAC>`，HMAC 的密钥保存在 `.mono/push-cert-seed`，首次使用时生成。HTTP 推送的
+//! 引用声明与推送是两个请求，nonce 本身即可验证，服务端不需要保存已签发的 nonce；用过的 nonce 记录在
+//! `.mono/push-cert-nonces` 下直到过期，重放的证书被拒绝。
+
+use std::io::Write;
+use std::path::Path;
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::object::{ObjectId, ObjectType};
+use crate::refs::RefUpdate;
+use crate::repo::Repository;
+use crate::signing::{self, Verification, Verifier};
+
+/// 推送证书目录，相对于 `.mono`
+pub const PUSH_CERTS_DIR: &str = "push-certs";
+
+/// nonce 的 HMAC 密钥文件，相对于 `.mono`
+pub const NONCE_SEED_FILE: &str = "push-cert-seed";
+
+/// 用过的 nonce 目录，相对于 `.mono`
+pub const USED_NONCES_DIR: &str = "push-cert-nonces";
+
+/// nonce 中 HMAC 的字节数
+const NONCE_MAC_LEN: usize = 20;
+
+/// 支持的证书版本
+const VERSION: &str = "certificate version 0.1";
+
+/// 解析后的推送证书
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PushCert {
+    /// 证书原文，包括末尾的签名
+    pub text: String,
+    /// 推送者，格式与提交的 committer 相同
+    pub pusher: String,
+    /// 推送的目标地址
+    pub pushee: Option<String>,
+    pub nonce: Option<String>,
+    pub push_options: Vec<String>,
+    pub updates: Vec<RefUpdate>,
+}
+
+impl PushCert {
+    /// 解析证书原文
+    pub fn parse(text: String) -> MonoResult<PushCert> {
+        let payload = match signing::split_tag(text.as_bytes()) {
+            Some((payload, _)) => String::from_utf8_lossy(payload).into_owned(),
+            None => text.clone(),
+        };
+        let mut lines = payload.lines();
+        if lines.next() != Some(VERSION) {
+            return Err(MonoError::protocol("unsupported push certificate version"));
+        }
+        let mut pusher = None;
+        let mut pushee = None;
+        let mut nonce = None;
+        let mut push_options = Vec::new();
+        for line in lines.by_ref() {
+            if line.is_empty() {
+                break;
+            }
+            match line.split_once(' ') {
+                Some(("pusher", value)) => pusher = Some(value.to_string()),
+                Some(("pushee", value)) => pushee = Some(value.to_string()),
+                Some(("nonce", value)) => nonce = Some(value.to_string()),
+                Some(("push-option", value)) => push_options.push(value.to_string()),
+                _ => {}
+            }
+        }
+        let mut updates = Vec::new();
+        for line in lines {
+            let mut parts = line.splitn(3, ' ');
+            let (Some(old), Some(new), Some(name)) = (parts.next(), parts.next(), parts.next()) else {
+                return Err(MonoError::protocol(format!("invalid command in push certificate: {}", line)));
+            };
+            updates.push(RefUpdate { name: name.to_string(), old: old.parse()?, new: new.parse()? });
+        }
+        Ok(PushCert {
+            pusher: pusher.ok_or_else(|| MonoError::protocol("push certificate without pusher"))?,
+            text,
+            pushee,
+            nonce,
+            push_options,
+            updates,
+        })
+    }
+
+    /// 证书作为 blob 的对象 ID
+    pub fn id(&self, repo: &Repository) -> ObjectId {
+        repo.object_format().hash_object(ObjectType::Blob, self.text.as_bytes())
+    }
+
+    /// 检查证书，返回签名的验证结果；证书无效时返回拒绝原因
+    pub fn verify(&self, repo: &Repository, push_options: &[String], now: i64) -> Result<Verification, String> {
+        let config = &repo.config().signing;
+        if !config.push_cert {
+            return Err("push certificates are not enabled".to_string());
+        }
+        let Some(nonce) = &self.nonce else {
+            return Err("push certificate has no nonce".to_string());
+        };
+        let valid = check_nonce(repo, nonce, now).map_err(|e| {
+            tracing::error!(error = %e, "failed to check push certificate nonce");
+            "failed to check push certificate".to_string()
+        })?;
+        if !valid {
+            return Err("push certificate nonce is invalid or expired".to_string());
+        }
+        if !self.pushee.as_deref().is_some_and(|pushee| is_pushee(repo, pushee)) {
+            return Err("push certificate is for another repository".to_string());
+        }
+        if self.push_options != push_options {
+            return Err("push options do not match the push certificate".to_string());
+        }
+        let verifier = Verifier::load(repo).map_err(|e| {
+            tracing::error!(error = %e, "invalid signing configuration");
+            "invalid signing configuration".to_string()
+        })?;
+        let verification = verifier.verify_tag(self.text.as_bytes());
+        if !verification.is_good() {
+            return Err(format!("push certificate {}", verification.problem()));
+        }
+        let fresh = use_nonce(repo, nonce, now).map_err(|e| {
+            tracing::error!(error = %e, "failed to record push certificate nonce");
+            "failed to check push certificate".to_string()
+        })?;
+        match fresh {
+            true => Ok(verification),
+            false => Err("push certificate nonce has already been used".to_string()),
+        }
+    }
+
+    /// 保存证书，返回其 ID
+    pub fn save(&self, repo: &Repository) -> MonoResult<ObjectId> {
+        let id = self.id(repo);
+        let dir = repo.mono_dir().join(PUSH_CERTS_DIR);
+        std::fs::create_dir_all(&dir)?;
+        std::fs::write(dir.join(id.to_hex()), &self.text)?;
+        Ok(id)
+    }
+}
+
+/// 证书中的推送地址是否指向本仓库
+fn is_pushee(repo: &Repository, pushee: &str) -> bool {
+    let pushee = pushee.trim_end_matches('/');
+    let urls = &repo.config().signing.push_cert_urls;
+    if !urls.is_empty() {
+        return urls.iter().any(|url| url.trim_end_matches('/') == pushee);
+    }
+    let name = pushee.rsplit(['/', ':']).next().unwrap_or_default();
+    let name = name.strip_suffix(".git").unwrap_or(name);
+    match repo.namespace() {
+        Some(namespace) => name == namespace,
+        None => repo.root().file_name().is_some_and(|root| root.to_string_lossy() == name),
+    }
+}
+
+/// 签发 nonce，`now` 为 Unix 时间戳
+pub fn nonce(repo: &Repository, now: i64) -> MonoResult<String> {
+    let salt: String = rand::random::<[u8; 8]>().iter().map(|b| format!("{:02x}", b)).collect();
+    let mac = nonce_mac(repo, now, &salt)?.finalize().into_bytes();
+    let mac: String = mac.iter().take(NONCE_MAC_LEN).map(|b| format!("{:02x}", b)).collect();
+    Ok(format!("{}-{}-{}", now, salt, mac))
+}
+
+/// nonce 是否由本服务端为该仓库签发且未过期，HMAC 按常数时间比较
+pub fn check_nonce(repo: &Repository, nonce: &str, now: i64) -> MonoResult<bool> {
+    let mut parts = nonce.splitn(3, '-');
+    let (Some(stamp), Some(salt), Some(mac)) = (parts.next(), parts.next(), parts.next()) else {
+        return Ok(false);
+    };
+    let Ok(stamp) = stamp.parse::<i64>() else {
+        return Ok(false);
+    };
+    if now.abs_diff(stamp) > repo.config().signing.push_cert_slop {
+        return Ok(false);
+    }
+    let Some(mac) = decode_hex(mac).filter(|mac| mac.len() == NONCE_MAC_LEN) else {
+        return Ok(false);
+    };
+    Ok(nonce_mac(repo, stamp, salt)?.verify_truncated_left(&mac).is_ok())
+}
+
+/// 记录用过的 nonce，已经用过时返回 false；同时清理已过期的记录
+fn use_nonce(repo: &Repository, nonce: &str, now: i64) -> MonoResult<bool> {
+    let dir = repo.mono_dir().join(USED_NONCES_DIR);
+    std::fs::create_dir_all(&dir)?;
+    let slop = repo.config().signing.push_cert_slop;
+    for entry in std::fs::read_dir(&dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let stamp = name.to_string_lossy().split('-').next().and_then(|stamp| stamp.parse::<i64>().ok());
+        if stamp.is_none_or(|stamp| now.abs_diff(stamp) > slop) {
+            let _ = std::fs::remove_file(entry.path());
+        }
+    }
+    // 已经验证过 nonce，其中只有数字、十六进制字符与 `-`
+    match std::fs::OpenOptions::new().write(true).create_new(true).open(dir.join(nonce)) {
+        Ok(_) => Ok(true),
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok()).collect()
+}
+
+/// 时间戳、随机数与命名空间的 HMAC，不同租户的 nonce 不能互用
+fn nonce_mac(repo: &Repository, stamp: i64, salt: &str) -> MonoResult<Hmac<Sha256>> {
+    let seed = load_or_create_seed(&repo.mono_dir().join(NONCE_SEED_FILE))?;
+    let mut mac = Hmac::<Sha256>::new_from_slice(seed.as_bytes()).expect("hmac accepts keys of any length");
+    mac.update(format!("{}:{}:{}", repo.namespace().unwrap_or_default(), stamp, salt).as_bytes());
+    Ok(mac)
+}
+
+fn load_or_create_seed(path: &Path) -> MonoResult<String> {
+    match std::fs::read_to_string(path) {
+        Ok(seed) => return Ok(seed.trim().to_string()),
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+        Err(_) => {}
+    }
+    let seed: String = rand::random::<[u8; 32]>().iter().map(|b| format!("{:02x}", b)).collect();
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    // 并发的请求同时生成时以先写入的为准
+    match options.open(path) {
+        Ok(mut file) => file.write_all(seed.as_bytes())?,
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => return Ok(std::fs::read_to_string(path)?.trim().to_string()),
+        Err(e) => return Err(e.into()),
+    }
+    tracing::info!(path = %path.display(), "generated push certificate nonce seed");
+    Ok(seed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::init_repo;
+
+    /// 测试解析推送证书
+    #[test]
+    fn test_parse() {
+        let id = ObjectId::hash_object(ObjectType::Blob, b"a");
+        let payload = format!(
+            "{}\npusher A U Thor <author@example.com> 1700000000 +0000\npushee https://example.com/mono.git\nnonce 1700000000-abc\npush-option ci.skip\n\n{} {} refs/heads/main\n",
+            VERSION,
+            ObjectId::ZERO,
+            id
+        );
+        let text = format!("{}-----BEGIN SSH SIGNATURE-----\nxyz\n-----END SSH SIGNATURE-----\n", payload);
+        let cert = PushCert::parse(text.clone()).unwrap();
+        assert_eq!(cert.pusher, "A U Thor <author@example.com> 1700000000 +0000");
+        assert_eq!(cert.pushee.as_deref(), Some("https://example.com/mono.git"));
+        assert_eq!(cert.nonce.as_deref(), Some("1700000000-abc"));
+        assert_eq!(cert.push_options, ["ci.skip"]);
+        assert_eq!(cert.updates, [RefUpdate { name: "refs/heads/main".to_string(), old: ObjectId::ZERO, new: id }]);
+        assert_eq!(cert.text, text);
+        assert!(PushCert::parse(payload.replace("0.1", "0.2")).is_err());
+        assert!(PushCert::parse(format!("{}\n\n", VERSION)).is_err());
+    }
+
+    /// 测试 nonce 在有效期内可以验证，过期、被改动或属于其他租户时无效
+    #[test]
+    fn test_nonce() {
+        let (_dir, repo) = init_repo();
+        let now = 1_700_000_000;
+        let nonce = nonce(&repo, now).unwrap();
+        assert!(check_nonce(&repo, &nonce, now + 60).unwrap());
+        assert!(!check_nonce(&repo, &nonce, now + 3600).unwrap());
+        assert!(!check_nonce(&repo, &nonce.replacen("1700000000", "1700000001", 1), now).unwrap());
+        assert!(!check_nonce(&repo, &format!("{}0", nonce), now).unwrap());
+        assert!(!check_nonce(&repo, "garbage", now).unwrap());
+        assert!(!check_nonce(&repo, "1700000000-00-zz", now).unwrap());
+        assert_ne!(nonce, super::nonce(&repo, now).unwrap());
+        let tenant = repo.with_namespace("payments").unwrap();
+        assert!(!check_nonce(&tenant, &nonce, now).unwrap());
+        assert_eq!(std::fs::read_to_string(repo.mono_dir().join(NONCE_SEED_FILE)).unwrap().len(), 64);
+    }
+
+    /// 测试 nonce 只能用一次，过期的记录被清理
+    #[test]
+    fn test_use_nonce() {
+        let (_dir, repo) = init_repo();
+        let now = 1_700_000_000;
+        let first = nonce(&repo, now).unwrap();
+        assert!(use_nonce(&repo, &first, now).unwrap());
+        assert!(!use_nonce(&repo, &first, now + 1).unwrap());
+        let later = nonce(&repo, now + 3600).unwrap();
+        assert!(use_nonce(&repo, &later, now + 3600).unwrap());
+        let used = std::fs::read_dir(repo.mono_dir().join(USED_NONCES_DIR)).unwrap();
+        assert_eq!(used.map(|entry| entry.unwrap().file_name()).collect::<Vec<_>>(), [later.as_str()]);
+    }
+
+    /// 测试推送地址按 `push_cert_urls` 或仓库名匹配
+    #[test]
+    fn test_pushee() {
+        let (_dir, mut repo) = init_repo();
+        let name = repo.root().file_name().unwrap().to_string_lossy().into_owned();
+        assert!(is_pushee(&repo, &format!("https://example.com/{}.git", name)));
+        assert!(is_pushee(&repo, &format!("git@example.com:{}", name)));
+        assert!(!is_pushee(&repo, "https://example.com/other.git"));
+        let tenant = repo.with_namespace("payments").unwrap();
+        assert!(is_pushee(&tenant, "https://example.com/payments.git/"));
+        assert!(!is_pushee(&tenant, &format!("https://example.com/{}.git", name)));
+
+        repo.config_mut().signing.push_cert_urls = vec!["https://git.example.com/mono.git".to_string()];
+        assert!(is_pushee(&repo, "https://git.example.com/mono.git/"));
+        assert!(!is_pushee(&repo, &format!("https://example.com/{}.git", name)));
+    }
+}
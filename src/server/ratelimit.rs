@@ -0,0 +1,292 @@
+//! 按客户端限流
+//!
+//! 令牌桶算法：每个客户端在每个维度（身份、IP、仓库）上各有一个容量为 `burst` 的桶，按 `rate` 每秒补充，
+//! 每个请求消耗一个令牌，所有已配置维度的桶都有令牌时请求才被放行。被拒绝时各传输层按协议返回：
+//! HTTP 为 429 与 `Retry-After`，SSH 拒绝认证或把原因写到标准错误并拒绝 exec，gRPC 为 `RESOURCE_EXHAUSTED`。
+//!
+//! IP 维度在认证之前检查（[`RateLimiter::check_ip`]），认证失败的尝试同样消耗令牌，猜测令牌或密码的
+//! 客户端会被限流；身份与仓库维度在认证之后检查（[`RateLimiter::check`]）。
+//!
+//! 限额在每次检查时从当前配置读取，重新加载配置后立即生效，已有的桶保留剩余的令牌。
+//! 一次 clone 通常包含能力声明与若干个 fetch 请求，`burst` 不宜小于 5。
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{LazyLock, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use crate::common::config::{RateLimit, RateLimitConfig};
+use crate::common::errors::MonoError;
+use crate::metrics;
+use crate::repo::Repository;
+
+/// 一个维度上超过这么多个桶时清理已经补满的桶，补满的桶与新建的桶没有区别
+const MAX_BUCKETS: usize = 10_000;
+
+/// 服务进程的限流状态，各传输层共用
+static LIMITER: LazyLock<RateLimiter> = LazyLock::new(RateLimiter::default);
+
+/// 服务进程共用的限流状态
+pub fn limiter() -> &'static RateLimiter {
+    &LIMITER
+}
+
+/// 限流维度
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Dimension {
+    User,
+    Ip,
+    Repo,
+}
+
+impl Dimension {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Dimension::User => "user",
+            Dimension::Ip => "ip",
+            Dimension::Repo => "repo",
+        }
+    }
+}
+
+/// 通过认证的客户端
+#[derive(Debug, Clone, Copy)]
+pub struct Client<'a> {
+    /// 认证后的身份，见 [`crate::auth::Access::principal`]
+    pub principal: &'a str,
+    /// 请求的仓库，见 [`repo_key`]
+    pub repo: &'a str,
+}
+
+/// 请求被限流
+#[derive(Debug, Clone, PartialEq)]
+pub struct Throttled {
+    pub dimension: Dimension,
+    pub key: String,
+    /// 至少等待多久才会有令牌
+    pub retry_after: Duration,
+}
+
+impl Throttled {
+    /// 向上取整的等待秒数，用于 `Retry-After`
+    pub fn retry_after_secs(&self) -> u64 {
+        self.retry_after.as_secs_f64().ceil().max(1.0) as u64
+    }
+}
+
+impl std::fmt::Display for Throttled {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "rate limit exceeded for {} {}; retry after {}s",
+            self.dimension.as_str(),
+            self.key,
+            self.retry_after_secs()
+        )
+    }
+}
+
+impl From<Throttled> for MonoError {
+    fn from(throttled: Throttled) -> MonoError {
+        MonoError::unavailable(throttled.to_string())
+    }
+}
+
+/// 请求在 `repo` 维度上计入的仓库：配置了租户时为地址中的租户名，否则为仓库目录名
+pub fn repo_key(repo: &Repository, name: Option<&str>) -> String {
+    let name = name.unwrap_or_default().trim_start_matches('/');
+    let name = name.strip_suffix(".git").unwrap_or(name);
+    if repo.config().namespaces.tenants.iter().any(|tenant| tenant == name) {
+        return name.to_string();
+    }
+    repo.root()
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// 一个客户端在一个维度上的令牌桶
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    /// 按当时的限额补满的时刻
+    full_at: Instant,
+}
+
+impl Bucket {
+    fn refill(&mut self, limit: &RateLimit, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * limit.rate).min(limit.burst as f64);
+        self.updated = now;
+    }
+
+    fn take(&mut self, limit: &RateLimit) {
+        self.tokens -= 1.0;
+        self.full_at = self.updated + Duration::from_secs_f64((limit.burst as f64 - self.tokens) / limit.rate);
+    }
+}
+
+/// 各客户端的令牌桶
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    buckets: Mutex<HashMap<Dimension, HashMap<String, Bucket>>>,
+}
+
+impl RateLimiter {
+    /// 认证之前检查并记录来自 `ip` 的一个请求或认证尝试，无论之后认证是否成功都消耗令牌
+    pub fn check_ip(&self, config: &RateLimitConfig, ip: Option<IpAddr>) -> Result<(), Throttled> {
+        self.check_ip_at(config, ip, Instant::now())
+    }
+
+    /// 检查并记录通过认证的 `client` 的一个请求；被拒绝的请求不消耗任何维度的令牌
+    pub fn check(&self, config: &RateLimitConfig, client: &Client) -> Result<(), Throttled> {
+        self.check_at(config, client, Instant::now())
+    }
+
+    fn check_ip_at(&self, config: &RateLimitConfig, ip: Option<IpAddr>, now: Instant) -> Result<(), Throttled> {
+        match (&config.ip, ip) {
+            (Some(limit), Some(ip)) => self.take(vec![(Dimension::Ip, ip.to_string(), limit)], now),
+            _ => Ok(()),
+        }
+    }
+
+    fn check_at(&self, config: &RateLimitConfig, client: &Client, now: Instant) -> Result<(), Throttled> {
+        let mut limits = Vec::new();
+        if let Some(limit) = config.clients.get(client.principal).or(config.user.as_ref()) {
+            limits.push((Dimension::User, client.principal.to_string(), limit));
+        }
+        if let Some(limit) = &config.repo {
+            limits.push((Dimension::Repo, client.repo.to_string(), limit));
+        }
+        self.take(limits, now)
+    }
+
+    /// 所有维度都有令牌时各消耗一个
+    fn take(&self, limits: Vec<(Dimension, String, &RateLimit)>, now: Instant) -> Result<(), Throttled> {
+        if limits.is_empty() {
+            return Ok(());
+        }
+
+        let mut buckets = self.buckets.lock().unwrap_or_else(PoisonError::into_inner);
+        for (dimension, key, limit) in &limits {
+            let buckets = buckets.entry(*dimension).or_default();
+            let bucket = buckets
+                .entry(key.clone())
+                .or_insert_with(|| Bucket { tokens: limit.burst as f64, updated: now, full_at: now });
+            bucket.refill(limit, now);
+            if bucket.tokens < 1.0 {
+                metrics::rate_limit_checked(dimension.as_str(), false);
+                return Err(Throttled {
+                    dimension: *dimension,
+                    key: key.clone(),
+                    retry_after: Duration::from_secs_f64((1.0 - bucket.tokens) / limit.rate),
+                });
+            }
+        }
+        for (dimension, key, limit) in &limits {
+            let buckets = buckets.entry(*dimension).or_default();
+            if let Some(bucket) = buckets.get_mut(key) {
+                bucket.take(limit);
+            }
+            if buckets.len() > MAX_BUCKETS {
+                buckets.retain(|_, bucket| bucket.full_at > now);
+            }
+            metrics::rate_limit_checked(dimension.as_str(), true);
+            metrics::rate_limit_clients(dimension.as_str(), buckets.len());
+        }
+        Ok(())
+    }
+
+    /// 正在计数的桶数
+    pub fn len(&self) -> usize {
+        self.buckets.lock().unwrap_or_else(PoisonError::into_inner).values().map(HashMap::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 测试令牌按持续速率补充、突发容量用尽后被拒绝，以及按身份覆盖限额
+    #[test]
+    fn test_token_bucket() {
+        let limiter = RateLimiter::default();
+        let mut config = RateLimitConfig {
+            user: Some(RateLimit { rate: 1.0, burst: 2 }),
+            ..Default::default()
+        };
+        let alice = Client { principal: "user:alice", repo: "mono" };
+        let start = Instant::now();
+        assert!(limiter.check_at(&config, &alice, start).is_ok());
+        assert!(limiter.check_at(&config, &alice, start).is_ok());
+        let throttled = limiter.check_at(&config, &alice, start).unwrap_err();
+        assert_eq!(throttled.dimension, Dimension::User);
+        assert_eq!(throttled.key, "user:alice");
+        assert_eq!(throttled.retry_after_secs(), 1);
+        assert_eq!(throttled.to_string(), "rate limit exceeded for user user:alice; retry after 1s");
+
+        assert!(limiter.check_at(&config, &alice, start + Duration::from_millis(500)).is_err());
+        assert!(limiter.check_at(&config, &alice, start + Duration::from_secs(1)).is_ok());
+
+        let bot = Client { principal: "token:ci", ..alice };
+        config.clients.insert("token:ci".to_string(), RateLimit { rate: 1.0, burst: 5 });
+        for _ in 0..5 {
+            assert!(limiter.check_at(&config, &bot, start).is_ok());
+        }
+        assert!(limiter.check_at(&config, &bot, start).is_err());
+        assert!(RateLimiter::default().check_at(&RateLimitConfig::default(), &alice, start).is_ok());
+    }
+
+    /// 测试所有维度都有令牌时才放行，被拒绝的请求不消耗其他维度的令牌
+    #[test]
+    fn test_dimensions() {
+        let limiter = RateLimiter::default();
+        let config = RateLimitConfig {
+            user: Some(RateLimit { rate: 1.0, burst: 1 }),
+            repo: Some(RateLimit { rate: 1.0, burst: 3 }),
+            ..Default::default()
+        };
+        let start = Instant::now();
+        let alice = Client { principal: "user:alice", repo: "mono" };
+        assert!(limiter.check_at(&config, &alice, start).is_ok());
+        assert_eq!(limiter.check_at(&config, &alice, start).unwrap_err().dimension, Dimension::User);
+        let bob = Client { principal: "user:bob", ..alice };
+        assert!(limiter.check_at(&config, &bob, start).is_ok());
+        let carol = Client { principal: "user:carol", ..alice };
+        assert!(limiter.check_at(&config, &carol, start).is_ok());
+        let dave = Client { principal: "user:dave", ..alice };
+        assert_eq!(limiter.check_at(&config, &dave, start).unwrap_err().dimension, Dimension::Repo);
+        assert_eq!(limiter.len(), 5);
+    }
+
+    /// 测试认证之前按 IP 计数，补满的桶在超过上限时被清理
+    #[test]
+    fn test_check_ip() {
+        let limiter = RateLimiter::default();
+        let config = RateLimitConfig {
+            ip: Some(RateLimit { rate: 1.0, burst: 2 }),
+            ..Default::default()
+        };
+        let start = Instant::now();
+        let ip = Some("10.0.0.1".parse().unwrap());
+        assert!(limiter.check_ip_at(&config, ip, start).is_ok());
+        assert!(limiter.check_ip_at(&config, ip, start).is_ok());
+        let throttled = limiter.check_ip_at(&config, ip, start).unwrap_err();
+        assert_eq!((throttled.dimension, throttled.key.as_str()), (Dimension::Ip, "10.0.0.1"));
+        assert!(limiter.check_ip_at(&config, Some("10.0.0.2".parse().unwrap()), start).is_ok());
+        assert!(limiter.check_ip_at(&config, None, start).is_ok());
+        assert!(limiter.check_ip_at(&RateLimitConfig::default(), ip, start).is_ok());
+
+        let later = start + Duration::from_secs(60);
+        for i in 0..MAX_BUCKETS {
+            let ip = IpAddr::from([10, 1, (i >> 8) as u8, i as u8]);
+            assert!(limiter.check_ip_at(&config, Some(ip), later).is_ok());
+        }
+        assert_eq!(limiter.len(), MAX_BUCKETS);
+    }
+}
@@ -0,0 +1,920 @@
+//! receive-pack：处理 `git push`
+//!
+//! 推送没有协议 v2 版本，沿用 v0 格式：服务端先列出引用与能力，客户端随后发送
+//! `<old> <new> <ref>` 形式的更新命令和 pack，服务端以 report-status 报告每个引用的结果。
+//! 引用更新前检查仓库的推送策略（见 [`crate::policy`]），违反规则的更新以规则说明作为拒绝原因，
+//! 随后执行 `pre-receive` 与 `update` 钩子，更新完成后执行 `post-receive` 钩子（见 [`crate::hooks`]）。
+//! 配置了 `[quota]` 时，超过硬配额且增加用量的推送被拒绝（见 [`crate::quota`]）。
+//! 只读镜像（见 [`crate::replication`]）拒绝全部更新；只读维护模式（见 [`crate::freeze`]）下拒绝整个推送。
+//!
+//! 客户端请求 `push-options` 能力时，命令之后、pack 之前是以 flush 结束的推送选项
+//! （`git push -o skip-ci`），推送选项传给钩子与推送策略。
+//!
+//! `zstd` 能力表示接受头部带 zstd 扩展标记的 pack（见 [`crate::pack`]）。
+//!
+//! 启用推送证书时声明 `push-cert` 能力，`git push --signed` 发送的证书在更新前验证（见 [`push_cert`]）。
+//!
+//! 推送的 pack 先转存到隔离区（见 [`crate::storage::quarantine`]），经 fsck 检查、推送策略与钩子
+//! 校验后才移入主存储；被拒绝的推送不会在主存储中留下对象。
+
+use std::io::Read;
+
+use tracing::field::Empty;
+
+use crate::audit::{AuditAction, AuditLog};
+use crate::auth::Access;
+use crate::common::config::{NotificationKind, Scope};
+use crate::common::errors::{MonoError, MonoErrorKind};
+use crate::common::MonoResult;
+use crate::freeze;
+use crate::fsck::{self, Severity};
+use crate::hooks::Hooks;
+use crate::notifications::{Notification, Notifications};
+use crate::pktline::{self, Packet, PktReader, PktWriter};
+use crate::policy::Policy;
+use crate::quota;
+use crate::refs::{self, RefUpdate};
+use crate::replication;
+use crate::repo::Repository;
+use crate::review::ReviewStore;
+use crate::server::push_cert::{self, PushCert};
+use crate::server::AGENT;
+use crate::storage::quarantine::Quarantine;
+
+/// 一次推送最多的推送选项数
+pub const MAX_PUSH_OPTIONS: usize = 100;
+
+/// 列出引用与能力，客户端据此计算需要推送的对象
+pub fn advertise(repo: &Repository) -> MonoResult<Vec<u8>> {
+    freeze::check(repo)?;
+    let mut capabilities = format!(
+        "report-status delete-refs atomic push-options ofs-delta zstd object-format={} agent={}",
+        repo.object_format(),
+        AGENT
+    );
+    if repo.config().signing.push_cert {
+        capabilities.push_str(&format!(" push-cert={}", push_cert::nonce(repo, chrono::Utc::now().timestamp())?));
+    }
+    let refs = repo.refs().list("refs/")?;
+    let mut out = PktWriter::new();
+    if refs.is_empty() {
+        out.write_line(&format!("{} capabilities^{{}}\0{}", repo.object_format().zero(), capabilities))?;
+    }
+    for (i, (name, id)) in refs.iter().enumerate() {
+        if i == 0 {
+            out.write_line(&format!("{} {}\0{}", id, name, capabilities))?;
+        } else {
+            out.write_line(&format!("{} {}", id, name))?;
+        }
+    }
+    out.flush();
+    Ok(out.into_inner())
+}
+
+/// 推送请求中 flush 之前的命令部分
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PushCommands {
+    pub updates: Vec<RefUpdate>,
+    /// 客户端是否请求原子推送
+    pub atomic: bool,
+    /// 客户端是否在命令之后发送推送选项
+    pub push_options: bool,
+    /// 签名推送的推送证书，其中的命令即 `updates`
+    pub cert: Option<PushCert>,
+}
+
+impl PushCommands {
+    /// 是否需要随后的 pack 数据；只删除引用时客户端不会发送 pack
+    pub fn needs_pack(&self) -> bool {
+        self.updates.iter().any(|u| !u.is_delete())
+    }
+}
+
+/// 读取更新命令，直到 flush
+pub fn parse_commands(reader: &mut PktReader) -> MonoResult<PushCommands> {
+    let mut commands = PushCommands::default();
+    loop {
+        let packet = reader.next_packet()?;
+        if packet == Packet::Flush {
+            return Ok(commands);
+        }
+        let line = packet
+            .text()
+            .ok_or_else(|| MonoError::protocol("invalid command line"))?;
+        let command = match line.split_once('\0') {
+            Some((command, capabilities)) => {
+                commands.atomic = capabilities.split(' ').any(|c| c == "atomic");
+                commands.push_options = capabilities.split(' ').any(|c| c == "push-options");
+                command
+            }
+            None => line,
+        };
+        if command.starts_with("shallow ") {
+            continue;
+        }
+        if command == "push-cert" {
+            let cert = PushCert::parse(read_push_cert(reader)?)?;
+            commands.updates = cert.updates.clone();
+            commands.cert = Some(cert);
+            continue;
+        }
+        let mut parts = command.splitn(3, ' ');
+        let (Some(old), Some(new), Some(name)) = (parts.next(), parts.next(), parts.next()) else {
+            return Err(MonoError::protocol(format!("invalid command: {}", command)));
+        };
+        commands.updates.push(RefUpdate {
+            name: name.to_string(),
+            old: old.parse()?,
+            new: new.parse()?,
+        });
+    }
+}
+
+/// 读取推送证书的各行，直到 `push-cert-end`
+fn read_push_cert(reader: &mut PktReader) -> MonoResult<String> {
+    let mut text = String::new();
+    loop {
+        let line = reader
+            .next_packet()?
+            .text()
+            .ok_or_else(|| MonoError::protocol("unterminated push certificate"))?;
+        if line == "push-cert-end" {
+            return Ok(text);
+        }
+        text.push_str(line);
+        text.push('\n');
+    }
+}
+
+/// 读取推送选项，直到 flush
+pub fn parse_push_options(reader: &mut PktReader) -> MonoResult<Vec<String>> {
+    let mut options = Vec::new();
+    loop {
+        let packet = reader.next_packet()?;
+        if packet == Packet::Flush {
+            return Ok(options);
+        }
+        if options.len() >= MAX_PUSH_OPTIONS {
+            return Err(MonoError::protocol(format!("too many push options, at most {} are allowed", MAX_PUSH_OPTIONS)));
+        }
+        let option = packet
+            .text()
+            .ok_or_else(|| MonoError::protocol("invalid push option"))?;
+        options.push(option.to_string());
+    }
+}
+
+/// 处理推送请求：写入 pack 中的对象并更新引用，返回 report-status
+///
+/// 按 `access` 检查每条更新（见 [`Access::check_update`]），并执行仓库配置中的钩子。
+pub fn serve(repo: &Repository, request: &[u8], access: &Access) -> MonoResult<Vec<u8>> {
+    serve_stream(repo, &mut &request[..], access)
+}
+
+/// 与 [`serve`] 相同，但从流中读取请求，pack 数据直接转存到隔离区而不读入内存
+pub fn serve_stream(repo: &Repository, input: &mut dyn Read, access: &Access) -> MonoResult<Vec<u8>> {
+    serve_with_hooks(repo, input, access, &Hooks::load(repo))
+}
+
+/// 与 [`serve_stream`] 相同，但执行给定的钩子
+#[tracing::instrument(name = "receive_pack", skip_all, fields(updates = Empty))]
+pub fn serve_with_hooks(repo: &Repository, input: &mut dyn Read, access: &Access, hooks: &Hooks) -> MonoResult<Vec<u8>> {
+    let commands = parse_commands(&mut PktReader::new(&pktline::read_until_flush(input)?))?;
+    let push_options = match commands.push_options {
+        true => parse_push_options(&mut PktReader::new(&pktline::read_until_flush(input)?))?,
+        false => Vec::new(),
+    };
+    let (updates, atomic) = (&commands.updates, commands.atomic);
+    if updates.is_empty() {
+        return Ok(Vec::new());
+    }
+    tracing::Span::current().record("updates", updates.len());
+    if !push_options.is_empty() {
+        tracing::info!(options = ?push_options, "received push options");
+    }
+
+    // 推送开始之后才冻结时，在写入对象之前拒绝
+    freeze::check(repo)?;
+    let mut out = PktWriter::new();
+    // 只读镜像的引用只能由复制修改，不写入推送的对象
+    // 推送证书无效时不写入推送的对象
+    let signed = check_push_cert(repo, &commands, &push_options);
+    if let Some(reason) = replication::read_only_reason(repo).or(signed.as_ref().err().cloned()) {
+        out.write_line("unpack ok")?;
+        for update in updates {
+            out.write_line(&format!("ng {} {}", update.name, reason))?;
+        }
+        out.flush();
+        return Ok(out.into_inner());
+    }
+    let quarantine = Quarantine::create(repo)?;
+    if let Err(e) = unpack(&quarantine, repo, input, commands.needs_pack()) {
+        tracing::warn!(error = %e, "failed to unpack pushed objects");
+        out.write_line(&format!("unpack {}", e))?;
+        for update in updates {
+            out.write_line(&format!("ng {} unpacker error", update.name))?;
+        }
+        out.flush();
+        return Ok(out.into_inner());
+    }
+    out.write_line("unpack ok")?;
+    // 检查与钩子看到推送的对象，引用更新前才移入主存储
+    let view = quarantine.repo(repo);
+
+    let policy = Policy::load(repo);
+    let mut results: Vec<Result<(), String>> = updates
+        .iter()
+        .map(|update| {
+            check_update(&view, update)?;
+            check_access(&view, access, update)?;
+            check_policy(&view, policy.as_ref(), access, update, &push_options)
+        })
+        .collect();
+    let accepted: Vec<RefUpdate> = updates
+        .iter()
+        .zip(&results)
+        .filter(|(_, result)| result.is_ok())
+        .map(|(update, _)| update.clone())
+        .collect();
+    if !accepted.is_empty() {
+        if let Err(reason) = hooks.pre_receive(&view, &accepted, &push_options) {
+            for result in results.iter_mut().filter(|r| r.is_ok()) {
+                *result = Err(reason.clone());
+            }
+        }
+    }
+    for (update, result) in updates.iter().zip(results.iter_mut()) {
+        if result.is_ok() {
+            *result = hooks.update(&view, update, &push_options);
+        }
+    }
+    if let Err(reason) = check_quota(repo, &view, updates, &results) {
+        for result in results.iter_mut().filter(|r| r.is_ok()) {
+            *result = Err(reason.clone());
+        }
+    }
+    if atomic && results.iter().any(Result::is_err) {
+        for result in results.iter_mut().filter(|r| r.is_ok()) {
+            *result = Err("atomic push failed".to_string());
+        }
+    }
+    if results.iter().any(Result::is_ok) {
+        if let Err(e) = quarantine.migrate() {
+            tracing::error!(error = %e, "failed to migrate pushed objects");
+            for result in results.iter_mut().filter(|r| r.is_ok()) {
+                *result = Err("failed to store objects".to_string());
+            }
+        }
+    }
+    // 引用存储在应用时再次校验旧值，检查之后被其他推送修改的引用会在这里失败
+    let logged = repo.with_reflog_identity(&access.principal, "push");
+    let store = logged.refs();
+    if atomic {
+        if results.iter().all(Result::is_ok) {
+            if let Err(e) = store.update(updates) {
+                tracing::warn!(error = %e, "failed to update refs atomically");
+                results.fill(Err("failed to update ref".to_string()));
+            }
+        }
+    } else {
+        for (update, result) in updates.iter().zip(results.iter_mut()) {
+            if result.is_ok() {
+                if let Err(e) = store.update(std::slice::from_ref(update)) {
+                    tracing::warn!(name = %update.name, error = %e, "failed to update ref");
+                    *result = Err("failed to update ref".to_string());
+                }
+            }
+        }
+    }
+    let applied: Vec<RefUpdate> = updates
+        .iter()
+        .zip(&results)
+        .filter(|(_, result)| result.is_ok())
+        .map(|(update, _)| update.clone())
+        .collect();
+    if !applied.is_empty() {
+        let now = chrono::Utc::now().timestamp();
+        let audit = AuditLog::new(repo);
+        match signed.ok().flatten().map(|(cert, signer)| cert.save(repo).map(|id| (id, signer))) {
+            Some(Ok((cert, signer))) => audit.record_signed_push(repo, &access.principal, &applied, &cert, &signer, now),
+            Some(Err(e)) => {
+                tracing::warn!(error = %e, "failed to save push certificate");
+                audit.record_ref_updates(repo, &access.principal, AuditAction::Push, &applied, now);
+            }
+            None => audit.record_ref_updates(repo, &access.principal, AuditAction::Push, &applied, now),
+        }
+        hooks.post_receive(repo, &applied, &push_options);
+        if let Err(e) = ReviewStore::new(repo).refresh_updated(&applied, now) {
+            tracing::warn!(error = %e, "failed to refresh change requests");
+        }
+    }
+    for (update, result) in updates.iter().zip(&results) {
+        match result {
+            Ok(()) => out.write_line(&format!("ok {}", update.name))?,
+            Err(reason) => out.write_line(&format!("ng {} {}", update.name, reason))?,
+        }
+    }
+    out.flush();
+    Ok(out.into_inner())
+}
+
+/// 把 pack 写入隔离区并检查收到的对象，thin pack 的基对象从本地存储读取
+///
+/// 只删除引用时客户端不发送 pack，SSH 上也不关闭输入，不读取输入。
+fn unpack(quarantine: &Quarantine, repo: &Repository, input: &mut dyn Read, needs_pack: bool) -> MonoResult<()> {
+    if !needs_pack {
+        return Ok(());
+    }
+    if quarantine.receive(input)?.is_none() {
+        return Err(MonoError::protocol("missing pack data"));
+    }
+    let report = fsck::fsck_incoming(&quarantine.repo(repo), &quarantine.objects()?)?;
+    match report.issues.iter().find(|issue| issue.severity == Severity::Error) {
+        Some(issue) => Err(MonoError::protocol(format!("{} ({} errors)", issue, report.count(Severity::Error)))),
+        None => Ok(()),
+    }
+}
+
+/// 检查单条更新命令，失败时返回 report-status 中的原因
+fn check_update(repo: &Repository, update: &RefUpdate) -> Result<(), String> {
+    if !update.name.starts_with("refs/") || !refs::check_ref_format(&update.name) {
+        return Err("funny refname".to_string());
+    }
+    if !update.is_delete() && !repo.objects().contains(&update.new).map_err(|e| e.to_string())? {
+        return Err("missing necessary objects".to_string());
+    }
+    let current = repo.refs().resolve(&update.name).map_err(|e| e.to_string())?;
+    if !update.expects(current.as_ref()) {
+        return Err("fetch first".to_string());
+    }
+    Ok(())
+}
+
+/// 验证推送证书，返回证书与签名者；证书无效，或要求推送证书而推送没有证书时返回拒绝原因
+fn check_push_cert<'c>(repo: &Repository, commands: &'c PushCommands, push_options: &[String]) -> Result<Option<(&'c PushCert, String)>, String> {
+    let Some(cert) = &commands.cert else {
+        return match repo.config().signing.require_push_cert {
+            true => Err("push certificate required; use git push --signed".to_string()),
+            false => Ok(None),
+        };
+    };
+    match cert.verify(repo, push_options, chrono::Utc::now().timestamp()) {
+        Ok(verification) => {
+            let signer = verification.signer.or(verification.key).unwrap_or_else(|| cert.pusher.clone());
+            tracing::info!(pusher = %cert.pusher, %signer, "verified push certificate");
+            Ok(Some((cert, signer)))
+        }
+        Err(reason) => {
+            tracing::warn!(pusher = %cert.pusher, %reason, "push certificate rejected");
+            Err(reason)
+        }
+    }
+}
+
+/// 检查推送者能否执行这条更新
+fn check_access(repo: &Repository, access: &Access, update: &RefUpdate) -> Result<(), String> {
+    let result = access.check_update(repo, update).map_err(|e| e.to_string())?;
+    if let Err(reason) = &result {
+        tracing::warn!(name = %update.name, principal = %access.principal, reason = %reason, "push rejected");
+    }
+    result
+}
+
+/// 检查通过的更新应用后的存储配额，超过硬配额时返回拒绝原因；统计失败时拒绝，避免在配额失效时放行
+fn check_quota(repo: &Repository, view: &Repository, updates: &[RefUpdate], results: &[Result<(), String>]) -> Result<(), String> {
+    if !repo.config().quota.is_enabled() {
+        return Ok(());
+    }
+    let accepted: Vec<RefUpdate> = updates
+        .iter()
+        .zip(results)
+        .filter(|(_, result)| result.is_ok())
+        .map(|(update, _)| update.clone())
+        .collect();
+    let check = || {
+        let mut sizes = quota::stored_sizes(repo)?;
+        sizes.extend(quota::stored_sizes(view)?);
+        quota::check_push(view, &sizes, &accepted)
+    };
+    match check() {
+        Ok(exceeded) => match exceeded.first() {
+            Some(exceeded) => {
+                tracing::warn!(error = %exceeded, "push rejected by storage quota");
+                Err(exceeded.to_string())
+            }
+            None => Ok(()),
+        },
+        Err(e) => {
+            tracing::error!(error = %e, "failed to check storage quota");
+            Err("failed to check storage quota".to_string())
+        }
+    }
+}
+
+/// 检查推送策略；策略配置无效时拒绝所有更新，避免在保护失效时放行
+///
+/// 只有 admin 权限的推送者可以用推送选项跳过规则。
+fn check_policy(
+    repo: &Repository,
+    policy: Result<&Policy, &MonoError>,
+    access: &Access,
+    update: &RefUpdate,
+    push_options: &[String],
+) -> Result<(), String> {
+    let policy = policy.map_err(|e| {
+        tracing::error!(error = %e, "invalid push policy");
+        "invalid push policy".to_string()
+    })?;
+    let push_options = match access.scope {
+        Some(Scope::Admin) => push_options,
+        _ => &[],
+    };
+    policy.check(repo, update, push_options).map_err(|e| {
+        tracing::warn!(name = %update.name, error = %e, "push rejected by policy");
+        match e.kind() {
+            MonoErrorKind::Policy(violation) => {
+                let notification = Notification {
+                    kind: NotificationKind::PolicyViolation,
+                    subject: format!("Push to {} rejected by policy '{}'", refs::short_name(&update.name), violation.rule),
+                    body: format!("{} pushed {}: {}", access.principal, update.new, violation),
+                    recipients: vec![access.principal.clone()],
+                    actor: None,
+                };
+                Notifications::notify(repo, notification);
+                violation.to_string()
+            }
+            _ => e.to_string(),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::ObjectId;
+    use crate::pack::encode_pack;
+    use crate::test_utils::{commit_files, init_repo};
+
+    fn request(commands: &[String], pack: &[u8]) -> Vec<u8> {
+        request_with_options(commands, &[], pack)
+    }
+
+    fn request_with_options(commands: &[String], push_options: &[&str], pack: &[u8]) -> Vec<u8> {
+        let mut out = PktWriter::new();
+        for (i, command) in commands.iter().enumerate() {
+            if i == 0 && !push_options.is_empty() {
+                out.write_line(&format!("{}\0report-status push-options", command)).unwrap();
+            } else if i == 0 {
+                out.write_line(&format!("{}\0report-status", command)).unwrap();
+            } else {
+                out.write_line(command).unwrap();
+            }
+        }
+        out.flush();
+        if !push_options.is_empty() {
+            for option in push_options {
+                out.write_line(option).unwrap();
+            }
+            out.flush();
+        }
+        let mut request = out.into_inner();
+        request.extend_from_slice(pack);
+        request
+    }
+
+    /// 测试推送对象并创建、更新与删除引用
+    #[test]
+    fn test_push() {
+        let (_source_dir, source) = init_repo();
+        let commit = commit_files(&source, &[("a.txt", b"a")], &[], "init");
+        let objects: Vec<_> = source
+            .objects()
+            .list()
+            .unwrap()
+            .iter()
+            .map(|id| source.read_object(id).unwrap())
+            .collect();
+        let pack = encode_pack(objects.iter()).unwrap();
+
+        let (_dir, repo) = init_repo();
+        let advertisement = advertise(&repo).unwrap();
+        assert!(String::from_utf8_lossy(&advertisement).contains("capabilities^{}"));
+
+        let create = format!("{} {} refs/heads/main", ObjectId::ZERO, commit);
+        let response = serve(&repo, &request(std::slice::from_ref(&create), &pack), &Access::full("test")).unwrap();
+        assert_eq!(lines(&response), vec!["unpack ok", "ok refs/heads/main"]);
+        assert_eq!(repo.refs().resolve("refs/heads/main").unwrap(), Some(commit));
+
+        // 旧值不符时拒绝
+        let response = serve(&repo, &request(&[create], &pack), &Access::full("test")).unwrap();
+        assert_eq!(lines(&response), vec!["unpack ok", "ng refs/heads/main fetch first"]);
+
+        let delete = format!("{} {} refs/heads/main", commit, ObjectId::ZERO);
+        let response = serve(&repo, &request(&[delete], &[]), &Access::full("test")).unwrap();
+        assert_eq!(lines(&response), vec!["unpack ok", "ok refs/heads/main"]);
+        assert!(repo.refs().resolve("refs/heads/main").unwrap().is_none());
+    }
+
+    /// 测试原子推送中任一失败时全部拒绝
+    #[test]
+    fn test_atomic_push() {
+        let (_dir, repo) = init_repo();
+        let commit = commit_files(&repo, &[("a.txt", b"a")], &[], "init");
+        let missing = ObjectId::hash_object(crate::object::ObjectType::Commit, b"missing");
+        let mut out = PktWriter::new();
+        out.write_line(&format!("{} {} refs/heads/a\0report-status atomic", ObjectId::ZERO, commit))
+            .unwrap();
+        out.write_line(&format!("{} {} refs/heads/b", ObjectId::ZERO, missing)).unwrap();
+        out.flush();
+        let mut request = out.into_inner();
+        request.extend_from_slice(&encode_pack([].iter()).unwrap());
+
+        let response = serve(&repo, &request, &Access::full("test")).unwrap();
+        assert_eq!(
+            lines(&response),
+            vec![
+                "unpack ok",
+                "ng refs/heads/a atomic push failed",
+                "ng refs/heads/b missing necessary objects"
+            ]
+        );
+        assert!(repo.refs().resolve("refs/heads/a").unwrap().is_none());
+    }
+
+    /// 测试违反推送策略的更新被拒绝，原因中包含规则说明；admin 可以用推送选项跳过规则
+    #[test]
+    fn test_push_policy() {
+        let (_dir, mut repo) = init_repo();
+        repo.config_mut().policy.push(crate::common::config::PolicyConfig {
+            name: "infra-review".to_string(),
+            refs: vec!["refs/heads/main".to_string()],
+            paths: vec!["//infra/...".to_string()],
+            require_approvals: 1,
+            deny_direct_push: false,
+            require_checks: Vec::new(),
+            bypass_option: Some("emergency".to_string()),
+        });
+        let commit = commit_files(&repo, &[("infra/main.tf", b"a")], &[], "init");
+        let pack = encode_pack([].iter()).unwrap();
+        let commands = [
+            format!("{} {} refs/heads/main", ObjectId::ZERO, commit),
+            format!("{} {} refs/heads/feature", ObjectId::ZERO, commit),
+        ];
+        let response = serve(&repo, &request(&commands, &pack), &Access::full("test")).unwrap();
+        assert_eq!(
+            lines(&response),
+            vec![
+                "unpack ok",
+                "ng refs/heads/main refs/heads/main violates rule 'infra-review': requires 1 approvals, found 0 (infra/main.tf)",
+                "ok refs/heads/feature",
+            ]
+        );
+        assert!(repo.refs().resolve("refs/heads/main").unwrap().is_none());
+
+        let commands = [format!("{} {} refs/heads/main", ObjectId::ZERO, commit)];
+        let request = request_with_options(&commands, &["emergency"], &pack);
+        let writer = Access {
+            principal: "token:1234".to_string(),
+            scope: Some(crate::common::config::Scope::Write),
+            paths: Vec::new(),
+        };
+        let response = serve(&repo, &request, &writer).unwrap();
+        assert!(lines(&response)[1].starts_with("ng refs/heads/main "), "{:?}", lines(&response));
+        let response = serve(&repo, &request, &Access::full("test")).unwrap();
+        assert_eq!(lines(&response), vec!["unpack ok", "ok refs/heads/main"]);
+    }
+
+    /// 测试配置的钩子：pre-receive 拒绝整个推送，update 拒绝单个引用，post-receive 收到已更新的引用
+    #[test]
+    fn test_push_hooks() {
+        use crate::common::config::{HookConfig, HookStage};
+
+        let (_dir, mut repo) = init_repo();
+        let hook = |name: &str, stage, command: &str| HookConfig {
+            name: name.to_string(),
+            stage,
+            command: command.to_string(),
+            timeout_secs: 10,
+        };
+        repo.config_mut().hooks = vec![
+            hook("freeze", HookStage::PreReceive, "! grep -q refs/heads/frozen || { echo 'repository is frozen' >&2; exit 1; }"),
+            hook("no-tags", HookStage::Update, "! grep -q refs/tags/ || { echo 'tags are managed by CI'; exit 1; }"),
+            hook("notify", HookStage::PostReceive, "cat > pushed.json"),
+        ];
+        let commit = commit_files(&repo, &[("a.txt", b"a")], &[], "init");
+        let pack = encode_pack([].iter()).unwrap();
+
+        let commands = [
+            format!("{} {} refs/heads/main", ObjectId::ZERO, commit),
+            format!("{} {} refs/tags/v1", ObjectId::ZERO, commit),
+        ];
+        let response = serve(&repo, &request_with_options(&commands, &["skip-ci"], &pack), &Access::full("test")).unwrap();
+        assert_eq!(
+            lines(&response),
+            vec![
+                "unpack ok",
+                "ok refs/heads/main",
+                "ng refs/tags/v1 update hook no-tags declined: tags are managed by CI",
+            ]
+        );
+        let pushed: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(repo.root().join("pushed.json")).unwrap()).unwrap();
+        assert_eq!(pushed["stage"], "post-receive");
+        assert_eq!(pushed["updates"].as_array().unwrap().len(), 1);
+        assert_eq!(pushed["updates"][0]["new"], commit.to_string());
+        assert_eq!(pushed["push_options"], serde_json::json!(["skip-ci"]));
+
+        let commands = [
+            format!("{} {} refs/heads/frozen", ObjectId::ZERO, commit),
+            format!("{} {} refs/heads/other", ObjectId::ZERO, commit),
+        ];
+        let response = serve(&repo, &request(&commands, &pack), &Access::full("test")).unwrap();
+        let reason = "pre-receive hook freeze declined: repository is frozen";
+        assert_eq!(
+            lines(&response),
+            vec![
+                "unpack ok".to_string(),
+                format!("ng refs/heads/frozen {}", reason),
+                format!("ng refs/heads/other {}", reason),
+            ]
+        );
+        assert!(repo.refs().resolve("refs/heads/other").unwrap().is_none());
+    }
+
+    /// 测试解析推送选项：按顺序读到 flush 为止，超过上限或不是文本时报错
+    #[test]
+    fn test_parse_push_options() {
+        let mut out = PktWriter::new();
+        out.write_line("skip-ci").unwrap();
+        out.write_line("queue=priority\n").unwrap();
+        out.flush();
+        out.write_line("after-flush").unwrap();
+        let data = out.into_inner();
+        let mut reader = PktReader::new(&data);
+        assert_eq!(parse_push_options(&mut reader).unwrap(), ["skip-ci", "queue=priority"]);
+        assert_eq!(reader.read().unwrap().and_then(|packet| packet.text().map(str::to_string)).as_deref(), Some("after-flush"));
+
+        let mut out = PktWriter::new();
+        for i in 0..=MAX_PUSH_OPTIONS {
+            out.write_line(&format!("option-{}", i)).unwrap();
+        }
+        out.flush();
+        let data = out.into_inner();
+        let err = parse_push_options(&mut PktReader::new(&data)).unwrap_err();
+        assert!(err.to_string().contains("too many push options"), "{}", err);
+
+        let mut out = PktWriter::new();
+        out.write(&[0xff, 0xfe]).unwrap();
+        out.flush();
+        assert!(parse_push_options(&mut PktReader::new(&out.into_inner())).is_err());
+    }
+
+    /// 测试客户端没有协商 `push-options` 能力却发送推送选项时整个推送被拒绝，选项不会传给钩子
+    #[test]
+    fn test_push_options_not_negotiated() {
+        use crate::common::config::{HookConfig, HookStage};
+
+        let (_dir, mut repo) = init_repo();
+        repo.config_mut().hooks = vec![HookConfig {
+            name: "record".to_string(),
+            stage: HookStage::PreReceive,
+            command: "echo \"$GIT_PUSH_OPTION_COUNT\" > options.txt".to_string(),
+            timeout_secs: 10,
+        }];
+        let commit = commit_files(&repo, &[("a.txt", b"a")], &[], "init");
+        let mut out = PktWriter::new();
+        out.write_line(&format!("{} {} refs/heads/main\0report-status", ObjectId::ZERO, commit)).unwrap();
+        out.flush();
+        out.write_line("skip-ci").unwrap();
+        out.flush();
+        let mut request = out.into_inner();
+        request.extend_from_slice(&encode_pack([].iter()).unwrap());
+
+        let response = lines(&serve(&repo, &request, &Access::full("test")).unwrap());
+        assert!(response[0].starts_with("unpack ") && response[0] != "unpack ok", "{:?}", response);
+        assert!(response[1].starts_with("ng refs/heads/main "), "{:?}", response);
+        assert!(repo.refs().resolve("refs/heads/main").unwrap().is_none());
+        assert!(!repo.root().join("options.txt").exists());
+    }
+
+    /// 测试推送选项通过 `GIT_PUSH_OPTION_COUNT` 与 `GIT_PUSH_OPTION_<序号>` 传给每个阶段的钩子
+    #[test]
+    fn test_push_options_hook_env() {
+        use crate::common::config::{HookConfig, HookStage};
+
+        let (_dir, mut repo) = init_repo();
+        let record = |stage, file: &str| HookConfig {
+            name: file.to_string(),
+            stage,
+            command: format!("echo \"$GIT_PUSH_OPTION_COUNT $GIT_PUSH_OPTION_0 $GIT_PUSH_OPTION_1\" > {}", file),
+            timeout_secs: 10,
+        };
+        repo.config_mut().hooks = vec![
+            record(HookStage::PreReceive, "pre-receive.txt"),
+            record(HookStage::Update, "update.txt"),
+            record(HookStage::PostReceive, "post-receive.txt"),
+        ];
+        let commit = commit_files(&repo, &[("a.txt", b"a")], &[], "init");
+        let commands = [format!("{} {} refs/heads/main", ObjectId::ZERO, commit)];
+        let request = request_with_options(&commands, &["skip-ci", "queue=priority"], &encode_pack([].iter()).unwrap());
+
+        let response = serve(&repo, &request, &Access::full("test")).unwrap();
+        assert_eq!(lines(&response), vec!["unpack ok", "ok refs/heads/main"]);
+        for file in ["pre-receive.txt", "update.txt", "post-receive.txt"] {
+            assert_eq!(std::fs::read_to_string(repo.root().join(file)).unwrap(), "2 skip-ci queue=priority\n", "{}", file);
+        }
+    }
+
+    /// 测试按推送者的权限拒绝更新
+    #[test]
+    fn test_push_access() {
+        let (_dir, repo) = init_repo();
+        let commit = commit_files(&repo, &[("docs/a.md", b"a")], &[], "init");
+        let pack = encode_pack([].iter()).unwrap();
+        let commands = [format!("{} {} refs/heads/main", ObjectId::ZERO, commit)];
+        let access = Access {
+            principal: "token:1234".to_string(),
+            scope: Some(crate::common::config::Scope::Write),
+            paths: vec!["src".to_string()],
+        };
+        let response = serve(&repo, &request(&commands, &pack), &access).unwrap();
+        assert_eq!(
+            lines(&response),
+            vec!["unpack ok", "ng refs/heads/main token:1234 may not modify docs/a.md"]
+        );
+        assert!(repo.refs().resolve("refs/heads/main").unwrap().is_none());
+    }
+
+    /// 测试超过硬配额的推送被拒绝，不增加用量的推送照常接受
+    #[test]
+    fn test_push_quota() {
+        let (_source_dir, source) = init_repo();
+        let first = commit_files(&source, &[("src/a.rs", b"fn main() {}")], &[], "init");
+        let second = commit_files(&source, &[("assets/big.bin", &[7u8; 4096])], &[first], "add asset");
+        let objects: Vec<_> = source
+            .objects()
+            .list()
+            .unwrap()
+            .iter()
+            .map(|id| source.read_object(id).unwrap())
+            .collect();
+        let pack = encode_pack(objects.iter()).unwrap();
+
+        let (_dir, mut repo) = init_repo();
+        repo.config_mut().quota.paths.push(crate::common::config::PathQuotaConfig {
+            prefix: "assets".to_string(),
+            soft_bytes: 0,
+            hard_bytes: 1,
+        });
+        let commands = [
+            format!("{} {} refs/heads/main", ObjectId::ZERO, first),
+            format!("{} {} refs/heads/feature", ObjectId::ZERO, second),
+        ];
+        let response = lines(&serve(&repo, &request(&commands, &pack), &Access::full("test")).unwrap());
+        assert!(response[1].starts_with("ng refs/heads/main hard quota for assets/ exceeded: "), "{:?}", response);
+        assert!(response[2].starts_with("ng refs/heads/feature hard quota for assets/ exceeded: "), "{:?}", response);
+        assert!(!repo.objects().contains(&second).unwrap());
+
+        let commands = [format!("{} {} refs/heads/main", ObjectId::ZERO, first)];
+        let response = serve(&repo, &request(&commands, &pack), &Access::full("test")).unwrap();
+        assert_eq!(lines(&response), vec!["unpack ok", "ok refs/heads/main"]);
+    }
+
+    /// 测试损坏的 pack 与被拒绝的推送都不会在主存储中留下对象
+    #[test]
+    fn test_push_quarantine() {
+        use crate::object::{ObjectType, RawObject};
+
+        let (_dir, repo) = init_repo();
+        let tree = ObjectId::hash_object(ObjectType::Tree, b"missing");
+        let broken = RawObject {
+            object_type: ObjectType::Commit,
+            data: format!("tree {}\nauthor A <a@example.com> 0 +0000\ncommitter A <a@example.com> 0 +0000\n\nbroken\n", tree)
+                .into_bytes(),
+        };
+        let commit = ObjectId::hash_object(ObjectType::Commit, &broken.data);
+        let pack = encode_pack([broken].iter()).unwrap();
+        let commands = [format!("{} {} refs/heads/main", ObjectId::ZERO, commit)];
+        let response = lines(&serve(&repo, &request(&commands, &pack), &Access::full("test")).unwrap());
+        assert!(response[0].starts_with("unpack ") && response[0].contains("missing-object"), "{:?}", response);
+        assert_eq!(response[1], "ng refs/heads/main unpacker error");
+        assert!(!repo.objects().contains(&commit).unwrap());
+
+        let (_source_dir, source) = init_repo();
+        let commit = commit_files(&source, &[("docs/a.md", b"a")], &[], "init");
+        let objects: Vec<_> = source
+            .objects()
+            .list()
+            .unwrap()
+            .iter()
+            .map(|id| source.read_object(id).unwrap())
+            .collect();
+        let pack = encode_pack(objects.iter()).unwrap();
+        let commands = [format!("{} {} refs/heads/main", ObjectId::ZERO, commit)];
+        let access = Access {
+            principal: "token:1234".to_string(),
+            scope: Some(crate::common::config::Scope::Write),
+            paths: vec!["src".to_string()],
+        };
+        let response = serve(&repo, &request(&commands, &pack), &access).unwrap();
+        assert_eq!(
+            lines(&response),
+            vec!["unpack ok", "ng refs/heads/main token:1234 may not modify docs/a.md"]
+        );
+        assert!(!repo.objects().contains(&commit).unwrap());
+        assert_eq!(std::fs::read_dir(repo.mono_dir().join("quarantine")).unwrap().count(), 0);
+
+        let response = serve(&repo, &request(&commands, &pack), &Access::full("test")).unwrap();
+        assert_eq!(lines(&response), vec!["unpack ok", "ok refs/heads/main"]);
+        assert!(repo.read_commit(&commit).is_ok());
+    }
+
+    /// 测试签名推送：有效的推送证书被接受并记入审计日志，nonce 过期或重放、推送地址或签名不符时拒绝，
+    /// 要求证书时拒绝未签名的推送
+    #[test]
+    fn test_signed_push() {
+        use crate::audit::AuditFilter;
+        use crate::common::config::SigningConfig;
+        use russh::keys::{Algorithm, HashAlg, PrivateKey};
+
+        let (_dir, mut repo) = init_repo();
+        let alice = PrivateKey::random(&mut rand::rng(), Algorithm::Ed25519).unwrap();
+        let signers = format!("alice@example.com {}\n", alice.public_key().to_openssh().unwrap());
+        std::fs::write(repo.mono_dir().join("allowed_signers"), signers).unwrap();
+        repo.config_mut().signing = SigningConfig {
+            allowed_signers: Some("allowed_signers".into()),
+            push_cert: true,
+            ..SigningConfig::default()
+        };
+        let advertised_nonce = || {
+            let advertisement = String::from_utf8_lossy(&advertise(&repo).unwrap()).into_owned();
+            advertisement.split(' ').find_map(|c| c.strip_prefix("push-cert=")).unwrap().trim_end().to_string()
+        };
+        let nonce = advertised_nonce();
+        let pushee = format!("https://example.com/{}.git", repo.root().file_name().unwrap().to_string_lossy());
+        let commit = commit_files(&repo, &[("a.txt", b"a")], &[], "init");
+        let pack = encode_pack([].iter()).unwrap();
+
+        let signed_request = |nonce: &str, pushee: &str, name: &str, signed_name: &str| {
+            let payload = |name: &str| {
+                format!(
+                    "certificate version 0.1\npusher Alice <alice@example.com> 1700000000 +0000\npushee {}\nnonce {}\n\n{} {} {}\n",
+                    pushee,
+                    nonce,
+                    ObjectId::ZERO,
+                    commit,
+                    name
+                )
+            };
+            let signature = alice.sign("git", HashAlg::Sha512, payload(signed_name).as_bytes()).unwrap();
+            let mut out = PktWriter::new();
+            out.write_line("push-cert\0report-status").unwrap();
+            for line in payload(name).lines().chain(signature.to_pem(Default::default()).unwrap().lines()) {
+                out.write_line(&format!("{}\n", line)).unwrap();
+            }
+            out.write_line("push-cert-end").unwrap();
+            out.flush();
+            let mut request = out.into_inner();
+            request.extend_from_slice(&pack);
+            request
+        };
+
+        let signed = signed_request(&nonce, &pushee, "refs/heads/main", "refs/heads/main");
+        let response = serve(&repo, &signed, &Access::full("alice")).unwrap();
+        assert_eq!(lines(&response), vec!["unpack ok", "ok refs/heads/main"]);
+        let events = AuditLog::new(&repo).query(&AuditFilter::default()).unwrap();
+        assert_eq!(events[0].detail.as_deref(), Some("signed by alice@example.com"));
+        let cert = events[0].cert.unwrap();
+        let saved = std::fs::read_to_string(repo.mono_dir().join(push_cert::PUSH_CERTS_DIR).join(cert.to_hex())).unwrap();
+        assert!(saved.starts_with("certificate version 0.1\n"));
+
+        let response = serve(&repo, &signed, &Access::full("alice")).unwrap();
+        assert_eq!(lines(&response), vec!["unpack ok", "ng refs/heads/main push certificate nonce has already been used"]);
+
+        let response = serve(&repo, &signed_request(&advertised_nonce(), &pushee, "refs/heads/other", "refs/heads/main"), &Access::full("alice")).unwrap();
+        assert_eq!(lines(&response), vec!["unpack ok", "ng refs/heads/other push certificate has a bad signature"]);
+        let elsewhere = "https://example.com/other.git";
+        let response = serve(&repo, &signed_request(&advertised_nonce(), elsewhere, "refs/heads/other", "refs/heads/other"), &Access::full("alice")).unwrap();
+        assert_eq!(lines(&response), vec!["unpack ok", "ng refs/heads/other push certificate is for another repository"]);
+        let stale = push_cert::nonce(&repo, chrono::Utc::now().timestamp() - 3600).unwrap();
+        let response = serve(&repo, &signed_request(&stale, &pushee, "refs/heads/other", "refs/heads/other"), &Access::full("alice")).unwrap();
+        assert_eq!(lines(&response), vec!["unpack ok", "ng refs/heads/other push certificate nonce is invalid or expired"]);
+
+        repo.config_mut().signing.require_push_cert = true;
+        let create = format!("{} {} refs/heads/other", ObjectId::ZERO, commit);
+        let response = serve(&repo, &request(&[create], &pack), &Access::full("alice")).unwrap();
+        assert_eq!(lines(&response), vec!["unpack ok", "ng refs/heads/other push certificate required; use git push --signed"]);
+        assert!(repo.refs().resolve("refs/heads/other").unwrap().is_none());
+    }
+
+    fn lines(response: &[u8]) -> Vec<String> {
+        let mut reader = PktReader::new(response);
+        let mut lines = Vec::new();
+        while let Some(packet) = reader.read().unwrap() {
+            if let Some(text) = packet.text() {
+                lines.push(text.to_string());
+            }
+        }
+        lines
+    }
+}
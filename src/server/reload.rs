@@ -0,0 +1,172 @@
+//! 不中断连接地重新加载服务端配置
+//!
+//! `mono serve` 收到 SIGHUP（或 `mono admin reload`）时重新读取各配置层，替换 [`SharedRepo`] 中的仓库
+//! 句柄：之后建立的连接与请求使用新配置，进行中的连接继续使用连接开始时取出的句柄，不会被断开。
+//! 对象存储、引用数据库与锁服务沿用原来的句柄，只替换 [`RELOADABLE`] 中的配置段；其他配置段的
+//! 修改需要重启才能生效，重新加载时在日志中列出。
+
+use std::path::Path;
+use std::sync::{Arc, PoisonError, RwLock};
+
+use crate::common::config::{Config, RepoConfig};
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::logging;
+use crate::repo::Repository;
+
+/// 可以不重启重新加载的配置段：日志过滤、推送策略、服务端钩子、webhook、身份认证、访问控制、限流与准入控制
+pub const RELOADABLE: &[&str] = &["log", "policy", "hooks", "webhooks", "auth", "acl", "rate_limit", "admission"];
+
+/// 运行中的 `mono serve` 写在 `.mono` 下的进程号文件，供 `mono admin reload` 发送信号
+pub const PID_FILE: &str = "serve.pid";
+
+/// 服务端当前使用的仓库句柄，重新加载配置时整体替换
+#[derive(Debug, Clone)]
+pub struct SharedRepo(Arc<RwLock<Arc<Repository>>>);
+
+impl SharedRepo {
+    pub fn new(repo: Arc<Repository>) -> SharedRepo {
+        SharedRepo(Arc::new(RwLock::new(repo)))
+    }
+
+    /// 当前的仓库句柄，连接或请求开始时取出，之后的重新加载不影响已取出的句柄
+    pub fn current(&self) -> Arc<Repository> {
+        self.0.read().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+
+    /// 重新读取配置并替换仓库句柄；配置不合法时保留原配置并返回错误
+    pub fn reload(&self) -> MonoResult<Reload> {
+        let current = self.current();
+        let config = Config::load(Some(current.mono_dir()))?.repo_config()?;
+        let (repo, reload) = reloaded(&current, config)?;
+        if reload.changed.iter().any(|section| section == "log") {
+            logging::reload_filter(&repo.config().log)?;
+        }
+        *self.0.write().unwrap_or_else(PoisonError::into_inner) = Arc::new(repo);
+        Ok(reload)
+    }
+}
+
+impl From<Arc<Repository>> for SharedRepo {
+    fn from(repo: Arc<Repository>) -> SharedRepo {
+        SharedRepo::new(repo)
+    }
+}
+
+/// 一次重新加载的结果
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Reload {
+    /// 已生效的配置段
+    pub changed: Vec<String>,
+    /// 有修改但需要重启才能生效的配置段
+    pub restart_required: Vec<String>,
+}
+
+/// 把新配置中可以重新加载的配置段应用到 `current` 的副本上
+fn reloaded(current: &Repository, config: RepoConfig) -> MonoResult<(Repository, Reload)> {
+    let sections = |config: &RepoConfig| toml::Table::try_from(config).map_err(|e| MonoError::config(e.to_string()));
+    let mut applied = sections(current.config())?;
+    let previous = applied.clone();
+    let next = sections(&config)?;
+    let mut reload = Reload::default();
+    for key in previous.keys().chain(next.keys().filter(|key| !previous.contains_key(*key))) {
+        if previous.get(key) == next.get(key) {
+            continue;
+        }
+        if !RELOADABLE.contains(&key.as_str()) {
+            reload.restart_required.push(key.clone());
+            continue;
+        }
+        match next.get(key) {
+            Some(section) => applied.insert(key.clone(), section.clone()),
+            None => applied.remove(key),
+        };
+        reload.changed.push(key.clone());
+    }
+    let mut repo = current.clone();
+    *repo.config_mut() = toml::Value::Table(applied)
+        .try_into()
+        .map_err(|e: toml::de::Error| MonoError::config(e.message().to_string()))?;
+    Ok((repo, reload))
+}
+
+/// 在 `.mono` 下写入当前进程号，返回的 guard 释放时删除
+pub fn write_pid_file(mono_dir: &Path) -> MonoResult<PidFile> {
+    let path = mono_dir.join(PID_FILE);
+    std::fs::write(&path, format!("{}\n", std::process::id()))?;
+    Ok(PidFile(path))
+}
+
+/// 读取运行中的 `mono serve` 的进程号
+pub fn read_pid_file(mono_dir: &Path) -> MonoResult<u32> {
+    let path = mono_dir.join(PID_FILE);
+    let content = match std::fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err(MonoError::not_found("no running mono serve for this repository"));
+        }
+        Err(e) => return Err(MonoError::from(e).context(format!("reading {}", path.display()))),
+    };
+    content
+        .trim()
+        .parse()
+        .map_err(|_| MonoError::config(format!("{}: invalid process id", path.display())))
+}
+
+/// `mono serve` 退出时删除进程号文件
+#[derive(Debug)]
+pub struct PidFile(std::path::PathBuf);
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::config::PolicyConfig;
+    use crate::test_utils::init_repo;
+
+    /// 测试只替换可以重新加载的配置段，其他修改列为需要重启，已取出的句柄不受影响
+    #[test]
+    fn test_reload() {
+        let (_dir, mut repo) = init_repo();
+        let shared = SharedRepo::new(Arc::new(repo.clone()));
+        let before = shared.current();
+
+        repo.config_mut().policy.push(PolicyConfig {
+            name: "protect".to_string(),
+            refs: vec!["refs/heads/main".to_string()],
+            paths: vec![],
+            require_approvals: 1,
+            deny_direct_push: false,
+            require_checks: vec![],
+            bypass_option: None,
+        });
+        repo.config_mut().log.filter = "debug".to_string();
+        repo.config_mut().gc.grace_days = 1;
+        repo.save_config().unwrap();
+
+        let reload = shared.reload().unwrap();
+        assert_eq!(reload.changed, ["log", "policy"]);
+        assert_eq!(reload.restart_required, ["gc"]);
+        let after = shared.current();
+        assert_eq!(after.config().policy.len(), 1);
+        assert_eq!(after.config().log.filter, "debug");
+        assert_ne!(after.config().gc.grace_days, 1);
+        assert!(before.config().policy.is_empty());
+        assert_eq!(shared.reload().unwrap(), Reload { changed: vec![], restart_required: vec!["gc".to_string()] });
+
+        repo.config_mut().index.version = 9;
+        repo.save_config().unwrap();
+        assert!(shared.reload().is_err());
+        assert_eq!(shared.current().config().policy.len(), 1);
+
+        let pid = write_pid_file(repo.mono_dir()).unwrap();
+        assert_eq!(read_pid_file(repo.mono_dir()).unwrap(), std::process::id());
+        drop(pid);
+        assert!(read_pid_file(repo.mono_dir()).is_err());
+    }
+}
@@ -0,0 +1,128 @@
+//! 优雅退出与连接排空
+//!
+//! `mono serve` 收到 SIGTERM 时调用 [`Drain::start`]：各传输层停止接受新连接，已开始的 upload-pack 与
+//! receive-pack 会话继续执行到结束，新的会话被拒绝。会话在开始时通过 [`Drain::session`] 登记，
+//! 返回的 guard 释放时注销，[`Drain::idle`] 等到所有会话结束。
+
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, LazyLock};
+
+use tokio::sync::Notify;
+use tokio_util::sync::CancellationToken;
+
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+
+/// 服务进程的排空状态，各传输层共用
+static DRAIN: LazyLock<Drain> = LazyLock::new(Drain::default);
+
+/// 服务进程共用的排空状态
+pub fn drain() -> &'static Drain {
+    &DRAIN
+}
+
+/// 进行中的会话计数与排空信号
+#[derive(Debug, Clone, Default)]
+pub struct Drain(Arc<Inner>);
+
+#[derive(Debug, Default)]
+struct Inner {
+    token: CancellationToken,
+    sessions: AtomicUsize,
+    idle: Notify,
+}
+
+impl Drain {
+    /// 开始排空：之后 [`Drain::session`] 返回错误，等待 [`Drain::draining`] 的传输层停止接受连接
+    pub fn start(&self) {
+        self.0.token.cancel();
+    }
+
+    pub fn is_draining(&self) -> bool {
+        self.0.token.is_cancelled()
+    }
+
+    /// 开始排空时完成
+    pub fn draining(&self) -> impl Future<Output = ()> + Send + 'static {
+        self.0.token.clone().cancelled_owned()
+    }
+
+    /// 登记一个会话；已开始排空时拒绝，客户端可以稍后重试
+    pub fn session(&self) -> MonoResult<Session> {
+        if self.is_draining() {
+            return Err(MonoError::unavailable("server is shutting down"));
+        }
+        self.0.sessions.fetch_add(1, Ordering::SeqCst);
+        Ok(Session(self.clone()))
+    }
+
+    /// 进行中的会话数
+    pub fn sessions(&self) -> usize {
+        self.0.sessions.load(Ordering::SeqCst)
+    }
+
+    /// 等到没有进行中的会话
+    pub async fn idle(&self) {
+        loop {
+            let notified = self.0.idle.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+            if self.sessions() == 0 {
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    fn release(&self) {
+        if self.0.sessions.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.0.idle.notify_waiters();
+        }
+    }
+}
+
+/// 进行中的会话，释放时注销
+#[derive(Debug)]
+pub struct Session(Drain);
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        self.0.release();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    /// 测试排空后拒绝新会话，并等到已登记的会话全部结束
+    #[test]
+    fn test_drain() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let drain = Drain::default();
+            drain.idle().await;
+            let first = drain.session().unwrap();
+            let second = drain.session().unwrap();
+            assert_eq!(drain.sessions(), 2);
+
+            drain.start();
+            drain.draining().await;
+            assert!(drain.session().unwrap_err().is_retryable());
+            drop(first);
+            assert!(tokio::time::timeout(Duration::from_millis(50), drain.idle()).await.is_err());
+
+            let waiter = tokio::spawn({
+                let drain = drain.clone();
+                async move { drain.idle().await }
+            });
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            drop(second);
+            waiter.await.unwrap();
+            assert_eq!(drain.sessions(), 0);
+        });
+    }
+}
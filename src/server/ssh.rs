@@ -0,0 +1,534 @@
+//! git over SSH
+//!
+//! 客户端使用 `git@host:repo` 形式的地址，服务端以 [`keys`](crate::server::keys) 中保存的公钥
+//! 认证，也可以用 [`auth`](crate::auth) 签发的令牌作为密码登录，再根据 exec 请求中的命令
+//! 分派到 upload-pack 或 receive-pack。公钥登录拥有全部权限，令牌登录按令牌的权限检查。每个服务进程只提供
+//! 一个仓库；配置了租户时命令中的仓库路径选择租户的引用命名空间，否则被忽略。
+//!
+//! 与 HTTP 不同，SSH 上的会话是一条双向字节流：upload-pack 在同一通道上连续处理多个
+//! 协议 v2 请求，直到客户端发送单独的 flush 或关闭输入；receive-pack 的输入边收边交给
+//! [`receive_pack::serve_stream`] 转存到隔离区，处理完后返回 report-status。缓存的请求或推送的数据
+//! 超过 `max_body_size` 时拒绝。
+
+use std::collections::HashMap;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use russh::keys::PublicKey;
+use russh::server::{Auth, ChannelOpenHandle, Config, Handler, Msg, Server, Session};
+use axum::body::Bytes;
+use russh::{Channel, ChannelId, MethodKind, MethodSet};
+use tokio::sync::mpsc;
+use tracing::Instrument;
+
+use crate::auth::{self, Access};
+use crate::common::config::Scope;
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::metrics;
+use crate::repo::Repository;
+use crate::server::keys::{load_or_create_host_key, AuthorizedKeys};
+use crate::server::reload::SharedRepo;
+use crate::server::ratelimit::{self, Client};
+use crate::server::{blocking, receive_pack, shutdown, tenant_repo, upload_pack, ChannelReader, Chunk};
+
+/// 服务出错时返回给 ssh 客户端的退出码，与 git 的 `die()` 一致
+const EXIT_FAILURE: u32 = 128;
+
+/// 在 `addr` 上提供服务，直到进程退出或排空结束
+///
+/// 每个连接使用建立时 `repo` 中的仓库句柄，重新加载配置只影响之后建立的连接。开始排空后新的 exec
+/// 请求被拒绝，等进行中的会话结束再断开所有连接。`max_body_size` 限制单个请求或一次推送的数据量。
+pub async fn serve(repo: SharedRepo, addr: SocketAddr, max_body_size: usize) -> MonoResult<()> {
+    let current = repo.current();
+    let host_key = {
+        let repo = current.clone();
+        blocking(move || load_or_create_host_key(&repo)).await?
+    };
+    let config = Config {
+        keys: vec![host_key],
+        methods: MethodSet::from(&[MethodKind::PublicKey, MethodKind::Password][..]),
+        auth_rejection_time: Duration::from_secs(1),
+        auth_rejection_time_initial: Some(Duration::ZERO),
+        inactivity_timeout: Some(Duration::from_secs(600)),
+        ..Default::default()
+    };
+    let listener = tokio::net::TcpListener::bind(addr)
+        .await
+        .map_err(|e| MonoError::from(e).context(format!("binding {}", addr)))?;
+    tracing::info!(%addr, root = %current.root().display(), "serving git over ssh");
+    let mut server = SshServer { repo, max_body_size };
+    let mut running = server.run_on_socket(Arc::new(config), &listener);
+    let drain = shutdown::drain();
+    tokio::select! {
+        result = &mut running => return Ok(result?),
+        () = drain.draining() => {}
+    }
+    drain.idle().await;
+    running.handle().shutdown("server is shutting down".to_string());
+    running.await?;
+    Ok(())
+}
+
+/// exec 请求可以调用的服务
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Service {
+    UploadPack,
+    ReceivePack,
+}
+
+/// 解析 exec 命令，例如 `git-upload-pack '/mono.git'` 或 `git receive-pack 'mono.git'`，返回服务与仓库路径
+fn parse_command(command: &str) -> MonoResult<(Service, Option<&str>)> {
+    let rest = command
+        .strip_prefix("git-")
+        .or_else(|| command.strip_prefix("git "))
+        .ok_or_else(|| MonoError::auth(format!("command not allowed: {}", command)))?;
+    let mut parts = rest.split_whitespace();
+    let service = match parts.next() {
+        Some("upload-pack") => Service::UploadPack,
+        Some("receive-pack") => Service::ReceivePack,
+        _ => return Err(MonoError::auth(format!("command not allowed: {}", command))),
+    };
+    Ok((service, parts.next().map(|path| path.trim_matches('\''))))
+}
+
+/// 返回缓冲区开头第一个以 flush 结束的完整请求的长度，数据不完整时返回 `None`
+fn request_len(data: &[u8]) -> MonoResult<Option<usize>> {
+    let mut pos = 0;
+    while let Some(header) = data.get(pos..pos + 4) {
+        let len = std::str::from_utf8(header)
+            .ok()
+            .and_then(|h| usize::from_str_radix(h, 16).ok())
+            .ok_or_else(|| MonoError::protocol("invalid pkt-line length"))?;
+        match len {
+            0 => return Ok(Some(pos + 4)),
+            1 | 2 => pos += 4,
+            3 => return Err(MonoError::protocol("invalid pkt-line length")),
+            len => pos += len,
+        }
+    }
+    Ok(None)
+}
+
+/// 记录一次 upload-pack 或 receive-pack 请求的耗时
+fn observe<T>(operation: &str, start: Instant, result: &MonoResult<T>) {
+    let status = if result.is_ok() { "ok" } else { "error" };
+    metrics::observe_request("ssh", operation, status, start.elapsed());
+}
+
+struct SshServer {
+    repo: SharedRepo,
+    max_body_size: usize,
+}
+
+impl Server for SshServer {
+    type Handler = SshSession;
+
+    fn new_client(&mut self, peer: Option<SocketAddr>) -> SshSession {
+        SshSession {
+            repo: self.repo.current(),
+            peer,
+            access: None,
+            channels: HashMap::new(),
+            max_body_size: self.max_body_size,
+        }
+    }
+
+    fn handle_session_error(&mut self, error: anyhow::Error) {
+        tracing::warn!(error = %error, "ssh session failed");
+    }
+}
+
+/// 一个通道上的会话状态
+#[derive(Default)]
+struct GitChannel {
+    /// 客户端通过 `GIT_PROTOCOL` 环境变量声明的协议参数
+    protocol: Option<String>,
+    service: Option<Service>,
+    /// exec 命令选择的仓库
+    repo: Option<Arc<Repository>>,
+    /// 尚未处理的输入
+    input: Vec<u8>,
+    /// receive-pack 的输入转发给处理推送的阻塞线程，客户端关闭输入或推送处理结束后为 None
+    pack: Option<mpsc::Sender<Chunk>>,
+    /// receive-pack 已收到的字节数
+    received: usize,
+    /// exec 之后登记的会话，通道结束时注销
+    session: Option<shutdown::Session>,
+}
+
+impl GitChannel {
+    /// 把 receive-pack 的输入转发给处理推送的线程，累计超过 `max_body_size` 时转发错误并停止转发
+    async fn forward(&mut self, data: &[u8], max_body_size: usize) {
+        let Some(pack) = &self.pack else {
+            return;
+        };
+        self.received += data.len();
+        let chunk = match self.received > max_body_size {
+            true => Err(io::Error::other(format!("push exceeds the maximum size of {} bytes", max_body_size))),
+            false => Ok(Bytes::copy_from_slice(data)),
+        };
+        let oversized = chunk.is_err();
+        // 推送处理提前结束时丢弃之后的输入
+        if pack.send(chunk).await.is_err() || oversized {
+            self.pack = None;
+        }
+    }
+}
+
+/// 单个 SSH 连接
+struct SshSession {
+    repo: Arc<Repository>,
+    peer: Option<SocketAddr>,
+    /// 认证通过后的访问权限
+    access: Option<Access>,
+    channels: HashMap<ChannelId, GitChannel>,
+    max_body_size: usize,
+}
+
+impl SshSession {
+    /// 认证之前按客户端 IP 限流，认证失败的尝试同样消耗令牌；超过限额时返回 false
+    fn admit(&self) -> bool {
+        match ratelimit::limiter().check_ip(&self.repo.config().rate_limit, self.peer.map(|peer| peer.ip())) {
+            Ok(()) => true,
+            Err(throttled) => {
+                tracing::info!(peer = ?self.peer, reason = %throttled, "ssh authentication throttled");
+                false
+            }
+        }
+    }
+
+    async fn authorize(&self, key: &PublicKey) -> MonoResult<Option<String>> {
+        let repo = self.repo.clone();
+        let keys = blocking(move || AuthorizedKeys::load(&repo)).await?;
+        Ok(keys.find(key).map(|k| k.name.clone()))
+    }
+
+    /// 处理通道上已收到的输入，返回 `Some(status)` 表示会话已结束
+    async fn process(&mut self, channel: ChannelId, session: &mut Session, eof: bool) -> MonoResult<Option<u32>> {
+        let Some(state) = self.channels.get_mut(&channel) else {
+            return Ok(None);
+        };
+        let Some(repo) = state.repo.clone() else {
+            return Ok(None);
+        };
+        match state.service {
+            Some(Service::UploadPack) => {
+                while let Some(len) = request_len(&state.input)? {
+                    let request: Vec<u8> = state.input.drain(..len).collect();
+                    // 单独的 flush 表示客户端不再发送请求
+                    if len == 4 {
+                        return Ok(Some(0));
+                    }
+                    let repo = repo.clone();
+                    let access = self.access.clone().ok_or_else(|| MonoError::auth("not authenticated"))?;
+                    let start = Instant::now();
+                    let span = tracing::info_span!("ssh.request", service = "git-upload-pack", principal = %access.principal);
+                    let response = blocking(move || upload_pack::serve(&repo, &request, &access)).instrument(span).await;
+                    observe("git-upload-pack", start, &response);
+                    session.data(channel, response?)?;
+                }
+                Ok(eof.then_some(0))
+            }
+            // 推送由 `receive_pack` 启动的任务处理并结束通道
+            Some(Service::ReceivePack) | None => Ok(None),
+        }
+    }
+
+    /// 结束通道：出错时将错误写入客户端的标准错误
+    fn finish(&mut self, channel: ChannelId, session: &mut Session, result: MonoResult<Option<u32>>) -> MonoResult<()> {
+        let status = match result {
+            Ok(None) => return Ok(()),
+            Ok(Some(status)) => status,
+            Err(e) => {
+                tracing::warn!(peer = ?self.peer, error = %e, "git ssh request failed");
+                session.extended_data(channel, 1, format!("fatal: {}\n", e).into_bytes())?;
+                EXIT_FAILURE
+            }
+        };
+        self.channels.remove(&channel);
+        session.exit_status_request(channel, status)?;
+        session.eof(channel)?;
+        session.close(channel)?;
+        Ok(())
+    }
+
+    async fn exec(&mut self, channel: ChannelId, command: &str, session: &mut Session) -> MonoResult<Option<u32>> {
+        let (service, path) = parse_command(command)?;
+        let repo = tenant_repo(&self.repo, path)?;
+        let state = self
+            .channels
+            .get_mut(&channel)
+            .ok_or_else(|| MonoError::protocol("exec on unknown channel"))?;
+        let access = self.access.as_ref().ok_or_else(|| MonoError::auth("not authenticated"))?;
+        access.require(match service {
+            Service::UploadPack => Scope::Read,
+            Service::ReceivePack => Scope::Write,
+        })?;
+        tracing::info!(peer = ?self.peer, principal = %access.principal, ?service, "git ssh request");
+        let repo_key = ratelimit::repo_key(&self.repo, path);
+        let client = Client { principal: &access.principal, repo: &repo_key };
+        ratelimit::limiter().check(&self.repo.config().rate_limit, &client)?;
+        state.session = Some(shutdown::drain().session()?);
+        let advertisement = match service {
+            Service::UploadPack => {
+                let v2 = state
+                    .protocol
+                    .as_deref()
+                    .is_some_and(|p| p.split(':').any(|p| p == "version=2"));
+                if !v2 {
+                    return Err(MonoError::usage(
+                        "only git protocol version 2 is supported; run `git config --global protocol.version 2`",
+                    ));
+                }
+                let requested = state.protocol.as_deref().and_then(upload_pack::requested_format);
+                let repo = repo.clone();
+                blocking(move || upload_pack::advertise(&repo, requested)).await?
+            }
+            Service::ReceivePack => {
+                let repo = repo.clone();
+                blocking(move || receive_pack::advertise(&repo)).await?
+            }
+        };
+        state.service = Some(service);
+        state.repo = Some(repo.clone());
+        session.data(channel, advertisement)?;
+        if service == Service::ReceivePack {
+            self.receive_pack(channel, session, repo)?;
+            return Ok(None);
+        }
+        // exec 之前可能已经收到输入
+        self.process(channel, session, false).await
+    }
+
+    /// 在阻塞线程中处理推送，之后收到的输入由 [`GitChannel::forward`] 转发，处理完后写回 report-status 并结束通道
+    fn receive_pack(&mut self, channel: ChannelId, session: &mut Session, repo: Arc<Repository>) -> MonoResult<()> {
+        let access = self.access.clone().ok_or_else(|| MonoError::auth("not authenticated"))?;
+        let state = self
+            .channels
+            .get_mut(&channel)
+            .ok_or_else(|| MonoError::protocol("exec on unknown channel"))?;
+        let (tx, rx) = mpsc::channel::<Chunk>(4);
+        // exec 之前可能已经收到输入，新建的通道一定有空位
+        state.received = state.input.len();
+        if !state.input.is_empty() {
+            let _ = tx.try_send(Ok(Bytes::from(std::mem::take(&mut state.input))));
+        }
+        state.pack = Some(tx);
+        let drain_session = state.session.take();
+        let handle = session.handle();
+        let peer = self.peer;
+        let span = tracing::info_span!("ssh.request", service = "git-receive-pack", principal = %access.principal);
+        tokio::spawn(async move {
+            let _session = drain_session;
+            let start = Instant::now();
+            let response = blocking(move || receive_pack::serve_stream(&repo, &mut ChannelReader::new(rx), &access))
+                .instrument(span)
+                .await;
+            observe("git-receive-pack", start, &response);
+            let status = match response {
+                Ok(response) => match handle.data(channel, response).await {
+                    Ok(()) => 0,
+                    Err(_) => return,
+                },
+                Err(e) => {
+                    tracing::warn!(?peer, error = %e, "git ssh request failed");
+                    let _ = handle.extended_data(channel, 1, format!("fatal: {}\n", e).into_bytes()).await;
+                    EXIT_FAILURE
+                }
+            };
+            let _ = handle.exit_status_request(channel, status).await;
+            let _ = handle.eof(channel).await;
+            let _ = handle.close(channel).await;
+        });
+        Ok(())
+    }
+}
+
+impl Handler for SshSession {
+    type Error = anyhow::Error;
+
+    async fn auth_publickey_offered(&mut self, _user: &str, key: &PublicKey) -> Result<Auth, Self::Error> {
+        match self.authorize(key).await? {
+            Some(_) => Ok(Auth::Accept),
+            None => Ok(Auth::reject()),
+        }
+    }
+
+    async fn auth_publickey(&mut self, _user: &str, key: &PublicKey) -> Result<Auth, Self::Error> {
+        if !self.admit() {
+            return Ok(Auth::reject());
+        }
+        match self.authorize(key).await? {
+            Some(name) => {
+                self.access = Some(Access::full(name));
+                Ok(Auth::Accept)
+            }
+            None => {
+                tracing::info!(peer = ?self.peer, "rejected ssh public key");
+                Ok(Auth::reject())
+            }
+        }
+    }
+
+    async fn auth_password(&mut self, _user: &str, password: &str) -> Result<Auth, Self::Error> {
+        if !self.admit() {
+            return Ok(Auth::reject());
+        }
+        let repo = self.repo.clone();
+        let password = password.to_string();
+        let access = blocking(move || auth::authenticate(&repo, Some(&password), chrono::Utc::now().timestamp())).await;
+        match access {
+            Ok(access) => {
+                self.access = Some(access);
+                Ok(Auth::Accept)
+            }
+            Err(e) => {
+                tracing::info!(peer = ?self.peer, error = %e, "rejected ssh password");
+                Ok(Auth::reject())
+            }
+        }
+    }
+
+    async fn channel_open_session(
+        &mut self,
+        channel: Channel<Msg>,
+        reply: ChannelOpenHandle,
+        _session: &mut Session,
+    ) -> Result<(), Self::Error> {
+        self.channels.insert(channel.id(), GitChannel::default());
+        reply.accept().await;
+        Ok(())
+    }
+
+    async fn env_request(
+        &mut self,
+        channel: ChannelId,
+        name: &str,
+        value: &str,
+        session: &mut Session,
+    ) -> Result<(), Self::Error> {
+        match self.channels.get_mut(&channel) {
+            Some(state) if name == "GIT_PROTOCOL" => {
+                state.protocol = Some(value.to_string());
+                session.channel_success(channel)?;
+            }
+            _ => session.channel_failure(channel)?,
+        }
+        Ok(())
+    }
+
+    async fn exec_request(&mut self, channel: ChannelId, data: &[u8], session: &mut Session) -> Result<(), Self::Error> {
+        session.channel_success(channel)?;
+        let result = match std::str::from_utf8(data) {
+            Ok(command) => self.exec(channel, command, session).await,
+            Err(_) => Err(MonoError::protocol("invalid exec command")),
+        };
+        self.finish(channel, session, result)?;
+        Ok(())
+    }
+
+    async fn shell_request(&mut self, channel: ChannelId, session: &mut Session) -> Result<(), Self::Error> {
+        session.channel_success(channel)?;
+        let result = Err(MonoError::auth("interactive shell is not supported"));
+        self.finish(channel, session, result)?;
+        Ok(())
+    }
+
+    async fn data(&mut self, channel: ChannelId, data: &[u8], session: &mut Session) -> Result<(), Self::Error> {
+        let Some(state) = self.channels.get_mut(&channel) else {
+            return Ok(());
+        };
+        if state.service == Some(Service::ReceivePack) {
+            state.forward(data, self.max_body_size).await;
+            return Ok(());
+        }
+        state.input.extend_from_slice(data);
+        let result = match state.input.len() > self.max_body_size {
+            true => Err(MonoError::protocol(format!("request exceeds the maximum size of {} bytes", self.max_body_size))),
+            false => self.process(channel, session, false).await,
+        };
+        self.finish(channel, session, result)?;
+        Ok(())
+    }
+
+    async fn channel_eof(&mut self, channel: ChannelId, session: &mut Session) -> Result<(), Self::Error> {
+        // 关闭 receive-pack 的输入，推送处理读到输入结束
+        if let Some(state) = self.channels.get_mut(&channel) {
+            state.pack = None;
+        }
+        let result = self.process(channel, session, true).await;
+        self.finish(channel, session, result)?;
+        Ok(())
+    }
+
+    async fn channel_close(&mut self, channel: ChannelId, _session: &mut Session) -> Result<(), Self::Error> {
+        self.channels.remove(&channel);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pktline::PktWriter;
+
+    /// 测试 exec 命令的解析
+    #[test]
+    fn test_parse_command() {
+        assert_eq!(parse_command("git-upload-pack '/mono.git'").unwrap(), (Service::UploadPack, Some("/mono.git")));
+        assert_eq!(parse_command("git-receive-pack 'mono.git'").unwrap(), (Service::ReceivePack, Some("mono.git")));
+        assert_eq!(parse_command("git upload-pack 'mono'").unwrap(), (Service::UploadPack, Some("mono")));
+        assert_eq!(parse_command("git-upload-pack").unwrap(), (Service::UploadPack, None));
+        assert!(parse_command("git-upload-archive 'mono.git'").is_err());
+        assert!(parse_command("rm -rf /").is_err());
+    }
+
+    /// 测试 receive-pack 的输入边收边转发给读取端，超过上限时读取端收到错误并停止转发
+    #[test]
+    fn test_forward_pack() {
+        use std::io::Read;
+
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        let (tx, rx) = mpsc::channel::<Chunk>(4);
+        let mut state = GitChannel { pack: Some(tx), ..Default::default() };
+        runtime.block_on(async {
+            state.forward(b"0000", 8).await;
+            state.forward(b"PACK", 8).await;
+            assert!(state.pack.is_some());
+            state.forward(b"x", 8).await;
+            assert!(state.pack.is_none());
+            state.forward(b"y", 8).await;
+        });
+        assert_eq!(state.received, 9);
+        let reader = std::thread::spawn(move || {
+            let mut data = Vec::new();
+            let err = ChannelReader::new(rx).read_to_end(&mut data).unwrap_err();
+            (data, err.to_string())
+        });
+        let (data, err) = reader.join().unwrap();
+        assert_eq!(data, b"0000PACK");
+        assert_eq!(err, "push exceeds the maximum size of 8 bytes");
+    }
+
+    /// 测试从输入流中切分完整请求
+    #[test]
+    fn test_request_len() {
+        let mut out = PktWriter::new();
+        out.write_line("command=ls-refs").unwrap();
+        out.delim();
+        out.write_line("peel").unwrap();
+        out.flush();
+        let request = out.into_inner();
+
+        let mut data = request.clone();
+        data.extend_from_slice(b"0000");
+        assert_eq!(request_len(&data).unwrap(), Some(request.len()));
+        assert_eq!(request_len(&request[..request.len() - 1]).unwrap(), None);
+        assert_eq!(request_len(b"0000").unwrap(), Some(4));
+        assert_eq!(request_len(b"").unwrap(), None);
+        assert!(request_len(b"zzzz").is_err());
+    }
+}
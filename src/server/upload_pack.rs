@@ -0,0 +1,758 @@
+//! upload-pack：git 协议 v2 的 `ls-refs`、`fetch` 与 `bundle-uri` 命令
+//!
+//! 每个请求包含一条命令，格式为：
+//!
+//! ```text
+//! command=<name>
+//! <capability>*
+//! 0001
+//! <argument>*
+//! 0000
+//! ```
+//!
+//! `[[acl]]` 规则（见 [`acl`](crate::auth::acl)）对请求的身份隐藏的引用不会出现在 ls-refs 中，
+//! 不可读路径下的树和 blob 不会被发送。
+//!
+//! 所有租户共享同一个对象存储，配置了 `[namespaces] tenants` 时 want 的对象必须能从租户命名空间中的
+//! 引用到达，知道其他租户对象 ID 的客户端也取不到这些对象。
+//!
+//! 配置了 bundle 地址时声明 `bundle-uri`（见 [`crate::bundle`]），克隆的客户端先从 CDN 下载 bundle。
+//! bundle 包含全部分支与标签，受 ACL 限制的身份与多租户仓库得到空的 bundle 列表。
+//!
+//! 配置了 `[offload]` 时 fetch 支持 `packfile-uris`（见 [`crate::offload`]），已上传到 S3/CDN 的 pack
+//! 以签名地址代替内联发送。
+//!
+//! 开始写 pack 之前按 `[admission]` 估算成本，过大的 fetch 被拒绝或排队，见 [`admission`]。
+//!
+//! 客户端声明的 `object-format` 是仓库的兼容格式时，响应中的对象名与 pack 都经
+//! [`CompatMap`] 转换为该格式，请求中的对象名按映射查回原名。
+//!
+//! fetch 声明 `zstd` 特性，带 `zstd` 参数的请求得到以 zstd 压缩的 pack（见 [`Codec`]），客户端解压更快；
+//! git 不会发送该参数，总是得到 zlib 压缩的 pack。
+
+use std::collections::HashSet;
+use std::io::Write;
+
+use tracing::field::Empty;
+
+use crate::auth::acl::Acl;
+use crate::auth::Access;
+use crate::bundle::BundleStore;
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::graph::bitmap::ReachabilityBitmaps;
+use crate::object::filter::ObjectFilter;
+use crate::object::shallow::{shallow_update, Deepen};
+use crate::object::tag::Tag;
+use crate::object::walk::{
+    collect_named_objects, collect_objects_excluding, collect_shallow_objects, collect_visible_objects,
+};
+use crate::metrics;
+use crate::object::codec::Codec;
+use crate::object::compat::CompatMap;
+use crate::offload::Offload;
+use crate::object::{ObjectFormat, ObjectId, ObjectType};
+use crate::pack::deltify::{self, DeltaOptions, PackObject};
+use crate::pack::PackWriter;
+use crate::pktline::{Packet, PktReader, PktWriter, SidebandWriter, BAND_DATA};
+use crate::refs::{self, RefTarget, HEAD};
+use crate::repo::Repository;
+use crate::server::admission::{self, Cost};
+use crate::server::AGENT;
+
+/// 协议 v2 的能力声明
+///
+/// `requested` 为客户端在协议参数中提示的对象格式，是仓库的兼容格式时声明该格式，否则声明仓库的格式。
+pub fn advertise(repo: &Repository, requested: Option<ObjectFormat>) -> MonoResult<Vec<u8>> {
+    let format = match requested {
+        Some(format) if Some(format) == repo.config().core.compat_object_format => format,
+        _ => repo.object_format(),
+    };
+    let mut out = PktWriter::new();
+    out.write_line("version 2")?;
+    out.write_line(&format!("agent={}", AGENT))?;
+    out.write_line("ls-refs=unborn")?;
+    match Offload::new(repo) {
+        Some(_) => out.write_line("fetch=shallow filter zstd packfile-uris")?,
+        None => out.write_line("fetch=shallow filter zstd")?,
+    }
+    if !BundleStore::new(repo).advertisement()?.is_empty() {
+        out.write_line("bundle-uri")?;
+    }
+    out.write_line(&format!("object-format={}", format))?;
+    out.flush();
+    Ok(out.into_inner())
+}
+
+/// 从 `Git-Protocol` 请求头或 `GIT_PROTOCOL` 环境变量（冒号分隔的参数）中读取客户端提示的对象格式
+pub fn requested_format(protocol: &str) -> Option<ObjectFormat> {
+    protocol
+        .split(':')
+        .find_map(|param| param.strip_prefix("object-format="))
+        .and_then(|format| format.parse().ok())
+}
+
+/// 以 `access` 的身份处理一个协议 v2 请求并返回完整的响应
+pub fn serve(repo: &Repository, request: &[u8], access: &Access) -> MonoResult<Vec<u8>> {
+    let mut out = Vec::new();
+    serve_to(repo, request, access, &mut out)?;
+    Ok(out)
+}
+
+/// 处理一个协议 v2 请求，将响应写入 `out`；`fetch` 的 pack 边生成边写出
+///
+/// 在写出任何数据之前发生的错误不会产生输出，调用方仍可以向客户端报告错误。
+pub fn serve_to(repo: &Repository, request: &[u8], access: &Access, out: &mut dyn Write) -> MonoResult<()> {
+    let mut reader = PktReader::new(request);
+    let mut command = None;
+    let mut format = repo.object_format();
+    // 能力部分：客户端声明的 agent、object-format 等，只需要命令名与对象格式
+    loop {
+        match reader.read()? {
+            None | Some(Packet::Flush) if command.is_none() => return Ok(()),
+            Some(Packet::Delim) | Some(Packet::Flush) | None => break,
+            Some(packet) => {
+                let line = packet
+                    .text()
+                    .ok_or_else(|| MonoError::protocol("invalid capability line"))?;
+                if let Some(name) = line.strip_prefix("command=") {
+                    command = Some(name.to_string());
+                } else if let Some(value) = line.strip_prefix("object-format=") {
+                    format = value.parse()?;
+                }
+            }
+        }
+    }
+    let mut args = Vec::new();
+    while let Some(packet) = reader.read()? {
+        match packet {
+            Packet::Flush => break,
+            packet => args.push(
+                packet
+                    .text()
+                    .ok_or_else(|| MonoError::protocol("invalid argument line"))?
+                    .to_string(),
+            ),
+        }
+    }
+
+    let compat = match CompatMap::open(repo)? {
+        _ if format == repo.object_format() => None,
+        Some(map) if map.compat_format() == format => Some(map),
+        _ => return Err(MonoError::protocol(format!("unsupported object format: {}", format))),
+    };
+    let names = Names { repo, compat };
+    match command.as_deref() {
+        Some("ls-refs") => Ok(out.write_all(&ls_refs(repo, access, &args, &names)?)?),
+        Some("fetch") => fetch(repo, access, &args, &names, out),
+        Some("bundle-uri") => Ok(out.write_all(&bundle_uri(repo, access)?)?),
+        Some(other) => Err(MonoError::protocol(format!("unknown command: {}", other))),
+        None => Err(MonoError::protocol("missing command")),
+    }
+}
+
+/// 请求使用的对象名：仓库的对象格式，或经兼容映射转换的兼容格式
+struct Names<'a> {
+    repo: &'a Repository,
+    compat: Option<CompatMap>,
+}
+
+impl Names<'_> {
+    /// 响应中使用的对象名
+    fn output(&self, id: &ObjectId) -> MonoResult<ObjectId> {
+        match &self.compat {
+            Some(map) => map.to_compat(self.repo, id),
+            None => Ok(*id),
+        }
+    }
+
+    /// 请求中的对象名对应的本地对象，未知的兼容名返回 None
+    fn input(&self, value: &str) -> MonoResult<Option<ObjectId>> {
+        let id: ObjectId = value.parse()?;
+        Ok(match &self.compat {
+            Some(map) => map.to_native(&id),
+            None => Some(id),
+        })
+    }
+
+    /// pack 使用的对象格式
+    fn format(&self) -> ObjectFormat {
+        match &self.compat {
+            Some(map) => map.compat_format(),
+            None => self.repo.object_format(),
+        }
+    }
+}
+
+/// `ls-refs`：列出引用，支持 `symrefs`、`peel`、`unborn` 与 `ref-prefix`
+#[tracing::instrument(skip_all)]
+fn ls_refs(repo: &Repository, access: &Access, args: &[String], names: &Names) -> MonoResult<Vec<u8>> {
+    let symrefs = args.iter().any(|a| a == "symrefs");
+    let peel = args.iter().any(|a| a == "peel");
+    let unborn = args.iter().any(|a| a == "unborn");
+    let prefixes: Vec<&str> = args.iter().filter_map(|a| a.strip_prefix("ref-prefix ")).collect();
+    let acl = Acl::load(repo)?;
+    let wanted = |name: &str| {
+        (prefixes.is_empty() || prefixes.iter().any(|p| name.starts_with(p))) && acl.can_read_ref(access, name)
+    };
+
+    let store = repo.refs();
+    let mut out = PktWriter::new();
+    if wanted(HEAD) {
+        let target = match store.read(HEAD)? {
+            Some(RefTarget::Symbolic(target)) => Some(target),
+            _ => None,
+        };
+        let visible = target.as_deref().is_none_or(|target| acl.can_read_ref(access, target));
+        let attrs = match (&target, symrefs) {
+            (Some(target), true) => format!(" symref-target:{}", target),
+            _ => String::new(),
+        };
+        match store.resolve(HEAD)? {
+            _ if !visible => {}
+            Some(id) => out.write_line(&format!("{} {}{}", names.output(&id)?, HEAD, attrs))?,
+            None if unborn && target.is_some() => out.write_line(&format!("unborn {}{}", HEAD, attrs))?,
+            None => {}
+        }
+    }
+    for (name, id) in store.list("refs/")? {
+        if !wanted(&name) {
+            continue;
+        }
+        let mut line = format!("{} {}", names.output(&id)?, name);
+        if peel {
+            let (peeled, _) = repo.peel(&id)?;
+            if peeled != id {
+                line.push_str(&format!(" peeled:{}", names.output(&peeled)?));
+            }
+        }
+        out.write_line(&line)?;
+    }
+    out.flush();
+    Ok(out.into_inner())
+}
+
+/// `fetch`：根据 want/have 协商并返回 pack
+///
+/// 服务端不做多轮协商：未收到 `done` 时确认已有的 have 后直接声明 ready 并发送 pack。
+/// 浅获取（`deepen`、`deepen-relative`、`deepen-since`）或浅仓库（`shallow`）的请求在 pack 之前
+/// 返回 `shallow-info`，列出新的浅提交与不再浅的提交，pack 只包含新边界之上的对象。
+#[tracing::instrument(skip_all, fields(wants = Empty, haves = Empty, filter = Empty, objects = Empty))]
+fn fetch(repo: &Repository, access: &Access, args: &[String], names: &Names, output: &mut dyn Write) -> MonoResult<()> {
+    let mut wants = Vec::new();
+    let mut haves = Vec::new();
+    let mut shallow = HashSet::new();
+    let mut depth = None;
+    let mut relative = false;
+    let mut since = None;
+    let mut done = false;
+    let mut include_tag = false;
+    let mut filter = ObjectFilter::None;
+    let mut ofs_delta = false;
+    let mut codec = Codec::Zlib;
+    let mut uri_protocols = Vec::new();
+    for arg in args {
+        let (key, value) = arg.split_once(' ').unwrap_or((arg.as_str(), ""));
+        match key {
+            "want" => wants.push(
+                names
+                    .input(value)?
+                    .ok_or_else(|| MonoError::protocol(format!("not our ref {}", value)))?,
+            ),
+            // 兼容映射中没有的对象不可能在本地，与不认识的 have 一样忽略
+            "have" => haves.extend(names.input(value)?),
+            "shallow" => shallow.extend(names.input(value)?),
+            "deepen" => {
+                let value = value
+                    .parse::<u32>()
+                    .ok()
+                    .filter(|depth| *depth > 0)
+                    .ok_or_else(|| MonoError::protocol(format!("invalid deepen: {}", value)))?;
+                depth = Some(value);
+            }
+            "deepen-relative" => relative = true,
+            "deepen-since" => {
+                since = Some(
+                    value
+                        .parse::<i64>()
+                        .map_err(|_| MonoError::protocol(format!("invalid deepen-since: {}", value)))?,
+                );
+            }
+            "done" => done = true,
+            "include-tag" => include_tag = true,
+            "filter" => {
+                filter = value
+                    .parse()
+                    .map_err(|_| MonoError::protocol(format!("unsupported filter: {}", value)))?;
+            }
+            "ofs-delta" => ofs_delta = true,
+            "zstd" => codec = Codec::Zstd,
+            "packfile-uris" => uri_protocols.extend(value.split(',').map(str::to_string)),
+            // delta 的基对象总在同一个 pack 中，不发送 thin pack；不输出进度
+            "thin-pack" | "no-progress" => {}
+            _ => return Err(MonoError::protocol(format!("unsupported fetch argument: {}", arg))),
+        }
+    }
+    if wants.is_empty() {
+        return Err(MonoError::protocol("fetch without want"));
+    }
+    let deepen = match (depth, since) {
+        (Some(_), Some(_)) => return Err(MonoError::protocol("deepen and deepen-since cannot be combined")),
+        (Some(depth), None) if relative => Some(Deepen::Relative(depth)),
+        (Some(depth), None) => Some(Deepen::Depth(depth)),
+        (None, _) if relative => return Err(MonoError::protocol("deepen-relative requires deepen")),
+        (None, Some(since)) => Some(Deepen::Since(since)),
+        (None, None) => None,
+    };
+    let span = tracing::Span::current();
+    span.record("wants", wants.len());
+    span.record("haves", haves.len());
+    span.record("filter", tracing::field::display(&filter));
+    let store = repo.objects();
+    for want in &wants {
+        if !store.contains(want)? {
+            return Err(MonoError::protocol(format!("not our ref {}", want)));
+        }
+    }
+    let acl = Acl::load(repo)?;
+    let restricted = acl.restricts(access);
+    let hidden = |path: &str| !acl.can_read_path(access, path);
+    if restricted || !repo.config().namespaces.tenants.is_empty() {
+        check_wants(repo, access, &acl, &wants, &hidden)?;
+    }
+
+    let mut out = PktWriter::new();
+    if !done {
+        out.write_line("acknowledgments")?;
+        let mut common = Vec::new();
+        for id in &haves {
+            if store.contains(id)? {
+                common.push(id);
+            }
+        }
+        if common.is_empty() {
+            out.write_line("NAK")?;
+        }
+        for id in common {
+            out.write_line(&format!("ACK {}", names.output(id)?))?;
+        }
+        out.write_line("ready")?;
+        out.delim();
+    }
+
+    let mut reader = |id: &ObjectId| repo.read_object(id);
+    let update = match deepen {
+        Some(deepen) => Some(shallow_update(&wants, deepen, &shallow, &mut reader)?),
+        None => None,
+    };
+    let shallow_fetch = update.is_some() || !shallow.is_empty();
+    if shallow_fetch {
+        out.write_line("shallow-info")?;
+        for id in update.iter().flat_map(|update| &update.shallow) {
+            out.write_line(&format!("shallow {}", names.output(id)?))?;
+        }
+        for id in update.iter().flat_map(|update| &update.unshallow) {
+            out.write_line(&format!("unshallow {}", names.output(id)?))?;
+        }
+        out.delim();
+    }
+    let walk = tracing::info_span!("collect_objects", bitmaps = tracing::field::Empty).entered();
+    // 有可达性位图时由位图算出要发送的对象，不必遍历整个对象图；过滤对象或限制路径时
+    // 仍需遍历 want 一侧，只由位图算出对端已有的对象。位图不考虑浅提交边界，浅获取总是遍历
+    let bitmaps = if shallow_fetch { None } else { ReachabilityBitmaps::load(repo)? };
+    walk.record("bitmaps", bitmaps.is_some());
+    let hidden_paths: Option<&dyn Fn(&str) -> bool> = if restricted { Some(&hidden) } else { None };
+    let (mut objects, skipped) = match bitmaps {
+        None if shallow_fetch => {
+            let update = update.unwrap_or_default();
+            let mut tips = wants.clone();
+            tips.extend(update.extra_tips(&mut reader)?);
+            let boundary = update.apply(&shallow);
+            collect_shallow_objects(&tips, &haves, boundary, shallow, &filter, hidden_paths, &mut reader)?
+        }
+        Some(bitmaps) if !restricted && !filter.is_partial() => {
+            let have = bitmaps.reachable(&haves, &mut reader)?;
+            let want = bitmaps.reachable(&wants, &mut reader)?;
+            (bitmaps.objects(&want, &have), false)
+        }
+        Some(bitmaps) if !haves.is_empty() => {
+            let uninteresting = bitmaps.ids(&bitmaps.reachable(&haves, &mut reader)?);
+            collect_objects_excluding(&wants, uninteresting, &filter, hidden_paths, &mut reader)?
+        }
+        _ if restricted => collect_visible_objects(&wants, &haves, &filter, &hidden, &mut reader)?,
+        _ => (collect_named_objects(&wants, &haves, &filter, &mut reader)?, false),
+    };
+    // 缺少对象的 pack 只有部分克隆的客户端才能接受
+    if skipped && !filter.is_partial() {
+        return Err(MonoError::auth(format!(
+            "{} may not read some paths in the requested commits; use a partial clone (--filter=blob:none) with a sparse index that excludes them",
+            access.principal
+        )));
+    }
+    drop(walk);
+    if include_tag {
+        let sent: HashSet<ObjectId> = objects.iter().map(|(id, _, _)| *id).collect();
+        for (_, id) in repo.refs().list(refs::TAGS_PREFIX)? {
+            if sent.contains(&id) {
+                continue;
+            }
+            let object = repo.read_object(&id)?;
+            if object.object_type == ObjectType::Tag && sent.contains(&Tag::parse(&object.data)?.object) {
+                objects.push((id, ObjectType::Tag, 0));
+            }
+        }
+    }
+
+    // 转换格式的对象与存储中的 pack 不同，不能卸载
+    let offload = Offload::new(repo).filter(|_| names.compat.is_none() && uri_protocols.iter().any(|p| p == "https"));
+    if let Some(offload) = offload {
+        let sent: HashSet<ObjectId> = objects.iter().map(|(id, _, _)| *id).collect();
+        let (packs, covered) = offload.select(&sent)?;
+        if !packs.is_empty() {
+            let now = chrono::Utc::now().timestamp();
+            out.write_line("packfile-uris")?;
+            for pack in &packs {
+                out.write_line(&format!("{} {}", pack.checksum, offload.url(pack, now)?))?;
+            }
+            out.delim();
+            objects.retain(|(id, _, _)| !covered.contains(id));
+            tracing::info!(packs = packs.len(), objects = covered.len(), "offloaded packs to signed urls");
+        }
+    }
+
+    span.record("objects", objects.len());
+    let mut pack_objects = Vec::with_capacity(objects.len());
+    for (id, object_type, name_hash) in objects {
+        // 大小只影响 delta 候选的顺序，部分克隆中本地缺失的对象按 0 处理，写入时再从远端获取
+        let size = store.read_header(&id)?.map_or(0, |(_, size)| size);
+        pack_objects.push(PackObject {
+            id,
+            object_type,
+            name_hash,
+            size,
+        });
+    }
+    let cost = Cost {
+        objects: pack_objects.len() as u64,
+        bytes: pack_objects.iter().map(|object| object.size as u64).sum(),
+    };
+    let _admitted = admission::admit(&repo.config().admission, &access.principal, cost)?;
+
+    out.write_line("packfile")?;
+    output.write_all(&out.into_inner())?;
+    let _write = tracing::info_span!("write_pack").entered();
+    let options = DeltaOptions {
+        ofs_delta,
+        ..DeltaOptions::default()
+    };
+    let count = pack_objects.len() as u32;
+    let format = names.format();
+    let mut pack = PackWriter::with_codec(SidebandWriter::new(&mut *output, BAND_DATA), count, format, codec)?;
+    match &names.compat {
+        // 转换后的对象不在存储中，无法计算 delta，逐个写入完整对象
+        Some(map) => {
+            for object in &pack_objects {
+                map.to_compat(repo, &object.id)?;
+                let converted = map.convert(&repo.read_object(&object.id)?)?;
+                pack.write(converted.object_type, &converted.data)?;
+            }
+        }
+        None => {
+            deltify::write_objects(&mut pack, pack_objects, &mut reader, &options)?;
+        }
+    }
+    metrics::pack_served(pack.bytes_written() + format.id_len() as u64, u64::from(count));
+    pack.finish()?.finish()?;
+    let mut end = PktWriter::new();
+    end.flush();
+    output.write_all(&end.into_inner())?;
+    Ok(())
+}
+
+/// 列出 bundle 的地址，客户端按 creationToken 顺序下载
+fn bundle_uri(repo: &Repository, access: &Access) -> MonoResult<Vec<u8>> {
+    let mut out = PktWriter::new();
+    if !Acl::load(repo)?.restricts(access) && repo.config().namespaces.tenants.is_empty() {
+        for line in BundleStore::new(repo).advertisement()? {
+            out.write_line(&line)?;
+        }
+    }
+    out.flush();
+    Ok(out.into_inner())
+}
+
+/// 受规则限制的身份或租户只能获取从可读引用（租户仓库中即该租户命名空间中的引用）出发、经可读路径可达的对象
+fn check_wants(
+    repo: &Repository,
+    access: &Access,
+    acl: &Acl,
+    wants: &[ObjectId],
+    hidden: &dyn Fn(&str) -> bool,
+) -> MonoResult<()> {
+    let tips: Vec<ObjectId> = repo
+        .refs()
+        .list("refs/")?
+        .into_iter()
+        .filter(|(name, _)| acl.can_read_ref(access, name))
+        .map(|(_, id)| id)
+        .collect();
+    if wants.iter().all(|want| tips.contains(want)) {
+        return Ok(());
+    }
+    let mut reader = |id: &ObjectId| repo.read_object(id);
+    let (visible, _) = collect_visible_objects(&tips, &[], &ObjectFilter::None, hidden, &mut reader)?;
+    let visible: HashSet<ObjectId> = visible.into_iter().map(|(id, _, _)| id).collect();
+    match wants.iter().find(|want| !visible.contains(want)) {
+        Some(want) => Err(MonoError::protocol(format!("not our ref {}", want))),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::commit::Signature;
+    use crate::pack::decode_pack;
+    use crate::test_utils::{commit_files, init_repo};
+
+    fn full() -> Access {
+        Access::full("test")
+    }
+
+    fn request(command: &str, args: &[&str]) -> Vec<u8> {
+        let mut out = PktWriter::new();
+        out.write_line(&format!("command={}", command)).unwrap();
+        out.write_line("object-format=sha1").unwrap();
+        out.delim();
+        for arg in args {
+            out.write_line(arg).unwrap();
+        }
+        out.flush();
+        out.into_inner()
+    }
+
+    fn lines(response: &[u8]) -> Vec<String> {
+        let mut reader = PktReader::new(response);
+        let mut lines = Vec::new();
+        while let Some(packet) = reader.read().unwrap() {
+            lines.push(packet.text().unwrap_or("<special>").to_string());
+        }
+        lines
+    }
+
+    /// 测试 ls-refs 输出符号引用、剥离后的标签以及未出生的 HEAD
+    #[test]
+    fn test_ls_refs() {
+        let (_dir, repo) = init_repo();
+        let response = serve(&repo, &request("ls-refs", &["symrefs", "unborn"]), &full()).unwrap();
+        assert_eq!(lines(&response), vec!["unborn HEAD symref-target:refs/heads/main", "<special>"]);
+
+        let commit = commit_files(&repo, &[("a.txt", b"a")], &[], "init");
+        repo.refs().write("refs/heads/main", &commit).unwrap();
+        let tag = Tag {
+            object: commit,
+            object_type: ObjectType::Commit,
+            name: "v1".to_string(),
+            tagger: Some(Signature::new("A", "a@example.com", 1)),
+            message: "release\n".to_string(),
+        };
+        let tag_id = repo.write_object(ObjectType::Tag, &tag.encode()).unwrap();
+        repo.refs().write("refs/tags/v1", &tag_id).unwrap();
+
+        let response = serve(&repo, &request("ls-refs", &["symrefs", "peel"]), &full()).unwrap();
+        assert_eq!(
+            lines(&response),
+            vec![
+                format!("{} HEAD symref-target:refs/heads/main", commit),
+                format!("{} refs/heads/main", commit),
+                format!("{} refs/tags/v1 peeled:{}", tag_id, commit),
+                "<special>".to_string(),
+            ]
+        );
+
+        let response = serve(&repo, &request("ls-refs", &["ref-prefix refs/tags/"]), &full()).unwrap();
+        assert_eq!(lines(&response).len(), 2);
+    }
+
+    /// 解出响应中 packfile 部分的对象
+    fn unpack(response: &[u8]) -> Vec<crate::object::RawObject> {
+        let mut reader = PktReader::new(response);
+        while reader.next_packet().unwrap().text() != Some("packfile") {}
+        let mut pack = Vec::new();
+        while let Packet::Data(data) = reader.next_packet().unwrap() {
+            assert_eq!(data[0], BAND_DATA);
+            pack.extend_from_slice(&data[1..]);
+        }
+        decode_pack(&pack, |_| Ok(None)).unwrap()
+    }
+
+    /// 测试 fetch 返回增量 pack，并在未结束协商时确认共同对象；声明 zstd 时 pack 以 zstd 压缩；
+    /// 超过准入上限的 fetch 被拒绝
+    #[test]
+    fn test_fetch() {
+        let (_dir, mut repo) = init_repo();
+        let first = commit_files(&repo, &[("a.txt", b"a")], &[], "first");
+        let second = commit_files(&repo, &[("a.txt", b"a"), ("b.txt", b"b")], &[first], "second");
+
+        let want = format!("want {}", second);
+        let response = serve(&repo, &request("fetch", &[&want, "done"]), &full()).unwrap();
+        assert_eq!(unpack(&response).len(), 6);
+
+        let have = format!("have {}", first);
+        let response = serve(&repo, &request("fetch", &[&want, &have]), &full()).unwrap();
+        let text = lines(&response);
+        assert_eq!(&text[..3], &["acknowledgments".to_string(), format!("ACK {}", first), "ready".to_string()]);
+        // 新提交、新的根树以及 b.txt
+        assert_eq!(unpack(&response).len(), 3);
+
+        let response = serve(&repo, &request("fetch", &[&want, "filter blob:none", "done"]), &full()).unwrap();
+        assert!(unpack(&response).iter().all(|o| o.object_type != ObjectType::Blob));
+
+        let response = serve(&repo, &request("fetch", &[&want, "zstd", "done"]), &full()).unwrap();
+        let start = response.windows(4).position(|w| w == crate::pack::PACK_SIGNATURE).unwrap();
+        assert_eq!(crate::pack::header_codec(&response[start..start + 12]), Some(Codec::Zstd));
+        assert_eq!(unpack(&response).len(), 6);
+
+        let missing = format!("want {}", ObjectId::hash_object(ObjectType::Commit, b"missing"));
+        assert!(serve(&repo, &request("fetch", &[&missing, "done"]), &full()).is_err());
+
+        repo.config_mut().admission.max_objects = 5;
+        let err = serve(&repo, &request("fetch", &[&want, "done"]), &full()).unwrap_err();
+        assert!(err.to_string().contains("fetch of 6 objects"), "{}", err);
+        assert_eq!(unpack(&serve(&repo, &request("fetch", &[&want, &have, "done"]), &full()).unwrap()).len(), 3);
+    }
+
+    /// 测试浅获取：返回 shallow-info，pack 只包含边界之上的对象，加深时把旧边界标为 unshallow
+    #[test]
+    fn test_fetch_shallow() {
+        let (_dir, repo) = init_repo();
+        let c1 = commit_files(&repo, &[("a.txt", b"1")], &[], "1");
+        let c2 = commit_files(&repo, &[("a.txt", b"2")], &[c1], "2");
+        let c3 = commit_files(&repo, &[("a.txt", b"3")], &[c2], "3");
+
+        let want = format!("want {}", c3);
+        let response = serve(&repo, &request("fetch", &[&want, "deepen 1", "done"]), &full()).unwrap();
+        let text = lines(&response);
+        assert_eq!(&text[..2], &["shallow-info".to_string(), format!("shallow {}", c3)]);
+        assert_eq!(unpack(&response).len(), 3);
+
+        let shallow = format!("shallow {}", c3);
+        let have = format!("have {}", c3);
+        let args = [want.as_str(), &have, &shallow, "deepen 1", "deepen-relative", "done"];
+        let response = serve(&repo, &request("fetch", &args), &full()).unwrap();
+        let text = lines(&response);
+        assert!(text.contains(&format!("shallow {}", c2)) && text.contains(&format!("unshallow {}", c3)));
+        // 只有 c2 及其树与 blob
+        let objects = unpack(&response);
+        assert_eq!(objects.len(), 3);
+        assert!(objects.iter().any(|o| o.id() == c2));
+
+        assert!(serve(&repo, &request("fetch", &[&want, "deepen 0", "done"]), &full()).is_err());
+        assert!(serve(&repo, &request("fetch", &[&want, "deepen-relative", "done"]), &full()).is_err());
+    }
+
+    /// 测试 ACL 隐藏引用与路径：ls-refs 不列出不可读的引用，fetch 不发送不可读路径下的对象
+    #[test]
+    fn test_fetch_acl() {
+        let (_dir, mut repo) = init_repo();
+        let main = commit_files(&repo, &[("src/a.txt", b"a"), ("secrets/key", b"k")], &[], "main");
+        let private = commit_files(&repo, &[("src/a.txt", b"p")], &[], "private");
+        repo.refs().write("refs/heads/main", &main).unwrap();
+        repo.refs().write("refs/heads/private", &private).unwrap();
+        let rule = |name: &str, refs: &[&str], paths: &[&str]| crate::common::config::AclConfig {
+            name: name.to_string(),
+            refs: refs.iter().map(|r| r.to_string()).collect(),
+            paths: paths.iter().map(|p| p.to_string()).collect(),
+            read: vec!["user:alice".to_string()],
+            write: Vec::new(),
+        };
+        repo.config_mut().acl = vec![rule("secrets", &[], &["//secrets/..."]), rule("private", &["refs/heads/private"], &[])];
+        let bob = Access {
+            principal: "user:bob".to_string(),
+            scope: Some(crate::common::config::Scope::Write),
+            paths: Vec::new(),
+        };
+        let alice = Access {
+            principal: "user:alice".to_string(),
+            ..bob.clone()
+        };
+
+        let refs = lines(&serve(&repo, &request("ls-refs", &[]), &bob).unwrap());
+        assert_eq!(refs, vec![format!("{} HEAD", main), format!("{} refs/heads/main", main), "<special>".to_string()]);
+        assert_eq!(lines(&serve(&repo, &request("ls-refs", &[]), &alice).unwrap()).len(), 4);
+
+        let want = format!("want {}", main);
+        let error = serve(&repo, &request("fetch", &[&want, "done"]), &bob).unwrap_err();
+        assert!(error.to_string().contains("partial clone"));
+        let objects = unpack(&serve(&repo, &request("fetch", &[&want, "filter blob:none", "done"]), &bob).unwrap());
+        // 提交、根树与 src，不含 secrets 树
+        assert_eq!(objects.len(), 3);
+        assert_eq!(unpack(&serve(&repo, &request("fetch", &[&want, "done"]), &alice).unwrap()).len(), 6);
+
+        let hidden = format!("want {}", private);
+        assert!(serve(&repo, &request("fetch", &[&hidden, "done"]), &bob).is_err());
+        let tree = repo.read_commit(&main).unwrap().tree;
+        let root = crate::object::tree::Tree::parse(&repo.read_object(&tree).unwrap().data, tree.format()).unwrap();
+        let secrets = root.entries.iter().find(|entry| entry.name == "secrets").unwrap().id;
+        assert!(serve(&repo, &request("fetch", &[&format!("want {}", secrets), "done"]), &bob).is_err());
+        let src = root.entries.iter().find(|entry| entry.name == "src").unwrap().id;
+        assert!(serve(&repo, &request("fetch", &[&format!("want {}", src), "done"]), &bob).is_ok());
+    }
+
+    /// 测试租户只能获取从自己命名空间中的引用可达的对象，即使知道其他租户的对象 ID
+    #[test]
+    fn test_fetch_cross_tenant() {
+        let (_dir, mut repo) = init_repo();
+        repo.config_mut().namespaces.tenants = vec!["payments".to_string(), "search".to_string()];
+        let payments = repo.with_namespace("payments").unwrap();
+        let search = repo.with_namespace("search").unwrap();
+        let secret = commit_files(&payments, &[("key", b"payments only")], &[], "payments");
+        payments.refs().write("refs/heads/main", &secret).unwrap();
+        let own = commit_files(&search, &[("index", b"search")], &[], "search");
+        search.refs().write("refs/heads/main", &own).unwrap();
+
+        let fetch = |repo: &Repository, id: &ObjectId| serve(repo, &request("fetch", &[&format!("want {}", id), "done"]), &full());
+        assert_eq!(unpack(&fetch(&search, &own).unwrap()).len(), 3);
+        let error = fetch(&search, &secret).unwrap_err();
+        assert!(error.to_string().contains("not our ref"), "{}", error);
+        let blob = ObjectId::hash_object(ObjectType::Blob, b"payments only");
+        assert!(fetch(&search, &blob).is_err());
+        assert!(fetch(&payments, &secret).is_ok());
+    }
+
+    /// 测试 SHA-256 仓库通过兼容映射以 SHA-1 名字列出引用并发送 pack，未配置的格式被拒绝
+    #[test]
+    fn test_fetch_compat() {
+        let dir = tempfile::tempdir().unwrap();
+        let options = crate::repo::InitOptions {
+            object_format: ObjectFormat::Sha256,
+            compat_object_format: Some(ObjectFormat::Sha1),
+            ..Default::default()
+        };
+        let repo = Repository::init(dir.path(), &options).unwrap();
+        let first = commit_files(&repo, &[("a.txt", b"a")], &[], "first");
+        let second = commit_files(&repo, &[("a.txt", b"b")], &[first], "second");
+        repo.refs().write("refs/heads/main", &second).unwrap();
+
+        let refs = lines(&serve(&repo, &request("ls-refs", &[]), &full()).unwrap());
+        let line = refs.iter().find(|line| line.ends_with(" refs/heads/main")).unwrap();
+        let tip: ObjectId = line.split(' ').next().unwrap().parse().unwrap();
+        assert_eq!(tip.format(), ObjectFormat::Sha1);
+
+        let map = CompatMap::open(&repo).unwrap().unwrap();
+        assert_eq!(map.to_native(&tip), Some(second));
+        let have = format!("have {}", map.to_compat(&repo, &first).unwrap());
+        let response = serve(&repo, &request("fetch", &[&format!("want {}", tip), &have, "done"]), &full()).unwrap();
+        // 新的提交、树与 blob，pack 中的对象按 SHA-1 计算名字
+        let objects = unpack(&response);
+        assert_eq!(objects.len(), 3);
+        assert!(objects.iter().any(|object| object.id() == tip));
+
+        let (_dir, sha1_repo) = init_repo();
+        let mut sha256 = PktWriter::new();
+        sha256.write_line("command=ls-refs").unwrap();
+        sha256.write_line("object-format=sha256").unwrap();
+        sha256.flush();
+        assert!(serve(&sha1_repo, &sha256.into_inner(), &full()).is_err());
+    }
+}
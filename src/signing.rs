@@ -0,0 +1,656 @@
+//! 提交与标签签名的验证
+//!
+//! 提交的签名保存在 `gpgsig` 头部（SHA-256 仓库为 `gpgsig-sha256`），签名的内容为去掉签名头部后的提交；
+//! 附注标签的签名附在标签信息末尾。SSH 签名（`-----BEGIN SSH SIGNATURE-----`）按 `[signing] allowed_signers`
+//! 中的可信公钥在进程内验证，命名空间必须为 `git`；GPG 签名调用 `gpgv` 按 `gpg_keyring` 验证。
+//!
+//! 验证结果通过 `GET /api/v1/verify` 提供（见 [`crate::server::api`]）。`require_signed` 中的引用在推送时
+//! 作为推送策略（见 [`crate::policy`]）检查：更新引入的每个提交都必须带有有效签名，推送的附注标签本身必须带有有效签名。
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use russh::keys::ssh_key::SshSig;
+use russh::keys::{HashAlg, PublicKey};
+use serde::Serialize;
+
+use crate::common::config::SigningConfig;
+use crate::common::errors::{MonoError, PolicyViolation};
+use crate::common::MonoResult;
+use crate::graph::history::History;
+use crate::object::{ObjectFormat, ObjectId, ObjectType};
+use crate::policy;
+use crate::refs::RefUpdate;
+use crate::repo::Repository;
+
+/// SSH 签名使用的命名空间，与 git 一致
+pub const SSH_NAMESPACE: &str = "git";
+
+/// 推送策略中的规则名
+const RULE: &str = "signed-commits";
+
+const PGP_BEGIN: &str = "-----BEGIN PGP SIGNATURE-----";
+const SSH_BEGIN: &str = "-----BEGIN SSH SIGNATURE-----";
+
+/// 临时签名文件目录，相对于 `.mono`
+const TMP_DIR: &str = "tmp";
+
+/// 签名的格式
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SignatureFormat {
+    Ssh,
+    Gpg,
+}
+
+/// 验证的结果
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum SignatureStatus {
+    /// 没有签名
+    Unsigned,
+    /// 签名有效且公钥可信
+    Good,
+    /// 签名与内容不符，或公钥已过期、吊销
+    Bad,
+    /// 签名的公钥不在可信公钥中
+    UnknownKey,
+    /// 未配置该格式的可信公钥，或无法运行验证程序
+    Unverified,
+}
+
+/// 一个对象的签名验证结果
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Verification {
+    pub status: SignatureStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub format: Option<SignatureFormat>,
+    /// 签名者：SSH 为 allowed signers 中的 principal，GPG 为公钥的用户 ID
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signer: Option<String>,
+    /// 签名的公钥：SSH 为 SHA256 指纹，GPG 为指纹或长 ID
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+}
+
+impl Verification {
+    fn new(status: SignatureStatus, format: Option<SignatureFormat>) -> Verification {
+        Verification { status, format, signer: None, key: None }
+    }
+
+    pub fn is_good(&self) -> bool {
+        self.status == SignatureStatus::Good
+    }
+
+    /// 拒绝推送时的原因，接在对象名之后
+    pub(crate) fn problem(&self) -> String {
+        match self.status {
+            SignatureStatus::Unsigned => "is not signed".to_string(),
+            SignatureStatus::Good => "has a good signature".to_string(),
+            SignatureStatus::Bad => "has a bad signature".to_string(),
+            SignatureStatus::UnknownKey => match &self.key {
+                Some(key) => format!("is signed by an untrusted key {}", key),
+                None => "is signed by an untrusted key".to_string(),
+            },
+            SignatureStatus::Unverified => "has a signature that could not be verified".to_string(),
+        }
+    }
+}
+
+/// allowed signers 文件中的一条可信公钥
+#[derive(Debug, Clone)]
+struct AllowedSigner {
+    principals: Vec<String>,
+    /// `namespaces=` 选项，None 表示不限
+    namespaces: Option<Vec<String>>,
+    key: PublicKey,
+}
+
+/// 按 `[signing]` 配置验证签名
+#[derive(Debug, Clone)]
+pub struct Verifier {
+    /// 签名头部名，取决于仓库的对象格式
+    header: &'static str,
+    /// 未配置 `allowed_signers` 时为 None
+    allowed_signers: Option<Vec<AllowedSigner>>,
+    /// `gpgv` 程序与公钥环
+    gpg: Option<(String, PathBuf)>,
+    /// 交给 `gpgv` 的签名文件所在的私有目录
+    tmp_dir: PathBuf,
+    require_signed: Vec<String>,
+    bypass_option: Option<String>,
+}
+
+impl Verifier {
+    /// 读取仓库的 `[signing]` 配置与 allowed signers 文件
+    pub fn load(repo: &Repository) -> MonoResult<Verifier> {
+        Verifier::from_config(&repo.config().signing, repo.mono_dir(), repo.object_format())
+    }
+
+    /// 按配置创建，配置中的相对路径相对于 `dir`
+    pub fn from_config(config: &SigningConfig, dir: &Path, format: ObjectFormat) -> MonoResult<Verifier> {
+        let resolve = |path: &Path| std::path::absolute(dir.join(path));
+        let allowed_signers = match &config.allowed_signers {
+            Some(path) => {
+                let path = resolve(path)?;
+                let content = std::fs::read_to_string(&path)
+                    .map_err(|e| MonoError::config(format!("signing: cannot read {}: {}", path.display(), e)))?;
+                Some(parse_allowed_signers(&content, &path)?)
+            }
+            None => None,
+        };
+        let gpg = match &config.gpg_keyring {
+            Some(keyring) => Some((config.gpg_program.clone(), resolve(keyring)?)),
+            None => None,
+        };
+        Ok(Verifier {
+            header: match format {
+                ObjectFormat::Sha1 => "gpgsig",
+                ObjectFormat::Sha256 => "gpgsig-sha256",
+            },
+            allowed_signers,
+            gpg,
+            tmp_dir: dir.join(TMP_DIR),
+            require_signed: config.require_signed.clone(),
+            bypass_option: config.bypass_option.clone(),
+        })
+    }
+
+    /// 推送时是否要求该引用上的新提交带有签名
+    pub fn requires(&self, refname: &str) -> bool {
+        self.require_signed.iter().any(|pattern| match pattern.strip_suffix('/') {
+            Some(_) => refname.starts_with(pattern.as_str()),
+            None => refname == pattern,
+        })
+    }
+
+    /// 验证提交或附注标签的签名
+    pub fn verify(&self, repo: &Repository, id: &ObjectId) -> MonoResult<Verification> {
+        let object = repo.read_object(id)?;
+        match object.object_type {
+            ObjectType::Commit => Ok(self.verify_commit(&object.data)),
+            ObjectType::Tag => Ok(self.verify_tag(&object.data)),
+            other => Err(MonoError::usage(format!("{} is a {}, not a commit or tag", id, other))),
+        }
+    }
+
+    /// 验证提交对象内容中的签名
+    pub fn verify_commit(&self, data: &[u8]) -> Verification {
+        match split_commit(data, self.header) {
+            Some((payload, signature)) => self.verify_signature(&payload, &signature),
+            None => Verification::new(SignatureStatus::Unsigned, None),
+        }
+    }
+
+    /// 验证标签对象内容中的签名；推送证书的签名同样附在末尾，也用此验证
+    pub fn verify_tag(&self, data: &[u8]) -> Verification {
+        match split_tag(data) {
+            Some((payload, signature)) => self.verify_signature(payload, signature),
+            None => Verification::new(SignatureStatus::Unsigned, None),
+        }
+    }
+
+    fn verify_signature(&self, payload: &[u8], signature: &str) -> Verification {
+        if signature.starts_with(SSH_BEGIN) {
+            self.verify_ssh(payload, signature)
+        } else if signature.starts_with(PGP_BEGIN) {
+            self.verify_gpg(payload, signature)
+        } else {
+            Verification::new(SignatureStatus::Unverified, None)
+        }
+    }
+
+    fn verify_ssh(&self, payload: &[u8], signature: &str) -> Verification {
+        let mut verification = Verification::new(SignatureStatus::Bad, Some(SignatureFormat::Ssh));
+        let Ok(sig) = SshSig::from_pem(signature) else {
+            return verification;
+        };
+        verification.key = Some(PublicKey::from(sig.public_key().clone()).fingerprint(HashAlg::Sha256).to_string());
+        if sig.namespace() != SSH_NAMESPACE {
+            return verification;
+        }
+        let Some(allowed) = &self.allowed_signers else {
+            verification.status = SignatureStatus::Unverified;
+            return verification;
+        };
+        let signer = allowed.iter().find(|signer| {
+            signer.key.key_data() == sig.public_key()
+                && signer.namespaces.as_ref().is_none_or(|namespaces| namespaces.iter().any(|n| n == SSH_NAMESPACE))
+        });
+        let Some(signer) = signer else {
+            verification.status = SignatureStatus::UnknownKey;
+            return verification;
+        };
+        if signer.key.verify(SSH_NAMESPACE, payload, &sig).is_ok() {
+            verification.status = SignatureStatus::Good;
+            verification.signer = signer.principals.first().cloned();
+        }
+        verification
+    }
+
+    fn verify_gpg(&self, payload: &[u8], signature: &str) -> Verification {
+        let Some((program, keyring)) = &self.gpg else {
+            return Verification::new(SignatureStatus::Unverified, Some(SignatureFormat::Gpg));
+        };
+        match run_gpgv(program, keyring, &self.tmp_dir, payload, signature) {
+            Ok(status) => parse_gpg_status(&status),
+            Err(e) => {
+                tracing::warn!(%program, error = %e, "cannot verify GPG signature");
+                Verification::new(SignatureStatus::Unverified, Some(SignatureFormat::Gpg))
+            }
+        }
+    }
+
+    /// 检查一条引用更新，引入没有有效签名的提交或推送没有有效签名的标签时返回 [`MonoError::policy`]，
+    /// 只报告最旧的一个提交
+    ///
+    /// `push_options` 为推送者的推送选项，包含 `bypass_option` 时跳过检查。
+    pub fn check(&self, repo: &Repository, update: &RefUpdate, push_options: &[String]) -> MonoResult<()> {
+        if update.is_delete() || !self.requires(&update.name) {
+            return Ok(());
+        }
+        let Some((object, kind, verification)) = self.find_unverified(repo, &update.new)? else {
+            return Ok(());
+        };
+        if let Some(option) = self.bypass_option.as_ref().filter(|option| push_options.contains(option)) {
+            tracing::warn!(name = %update.name, %object, %option, "signature requirement bypassed by push option");
+            return Ok(());
+        }
+        Err(MonoError::policy(PolicyViolation {
+            rule: RULE.to_string(),
+            refname: update.name.clone(),
+            paths: Vec::new(),
+            reason: format!("{} {} {}", kind, &object.to_hex()[..7], verification.problem()),
+        }))
+    }
+
+    /// 推送的标签或新引入的提交中第一个没有有效签名的对象
+    fn find_unverified(&self, repo: &Repository, id: &ObjectId) -> MonoResult<Option<(ObjectId, ObjectType, Verification)>> {
+        let object = repo.read_object(id)?;
+        if object.object_type == ObjectType::Tag {
+            let verification = self.verify_tag(&object.data);
+            return Ok((!verification.is_good()).then_some((*id, ObjectType::Tag, verification)));
+        }
+        if object.object_type != ObjectType::Commit {
+            return Ok(None);
+        }
+        let commits = History::new(repo)?.range(&[*id], &policy::ref_commits(repo)?)?;
+        for commit in commits.iter().rev() {
+            let verification = self.verify_commit(&repo.read_object(&commit.id)?.data);
+            if !verification.is_good() {
+                return Ok(Some((commit.id, ObjectType::Commit, verification)));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// 从提交对象的内容中分离签名，返回签名的内容与签名；`header` 为签名头部名，
+/// 其他格式的签名头部同样从签名的内容中去掉
+pub fn split_commit(data: &[u8], header: &str) -> Option<(Vec<u8>, String)> {
+    let end = data.windows(2).position(|w| w == b"\n\n").map_or(data.len(), |pos| pos + 1);
+    let mut payload = Vec::with_capacity(data.len());
+    let mut signature: Option<Vec<u8>> = None;
+    // 当前是否在签名头部的续行中：Some(true) 为所需的签名，Some(false) 为其他签名
+    let mut continuing = None;
+    for line in data[..end].split_inclusive(|&b| b == b'\n') {
+        if let (Some(cont), Some(wanted)) = (line.strip_prefix(b" "), continuing) {
+            if wanted {
+                signature.get_or_insert_with(Vec::new).extend_from_slice(cont);
+            }
+            continue;
+        }
+        let key = line.split(|&b| b == b' ').next().unwrap_or_default();
+        continuing = None;
+        if key == header.as_bytes() && signature.is_none() {
+            signature = Some(line[key.len() + 1..].to_vec());
+            continuing = Some(true);
+        } else if key == b"gpgsig" || key == b"gpgsig-sha256" {
+            continuing = Some(false);
+        } else {
+            payload.extend_from_slice(line);
+        }
+    }
+    payload.extend_from_slice(&data[end..]);
+    let signature = String::from_utf8(signature?).ok()?;
+    Some((payload, signature))
+}
+
+/// 从标签对象的内容中分离末尾的签名，返回签名的内容与签名
+pub fn split_tag(data: &[u8]) -> Option<(&[u8], &str)> {
+    let mut start = None;
+    let mut pos = 0;
+    for line in data.split_inclusive(|&b| b == b'\n') {
+        if line.starts_with(PGP_BEGIN.as_bytes()) || line.starts_with(SSH_BEGIN.as_bytes()) {
+            start = Some(pos);
+        }
+        pos += line.len();
+    }
+    let (payload, signature) = data.split_at(start?);
+    Some((payload, std::str::from_utf8(signature).ok()?))
+}
+
+/// 解析 allowed signers 文件：每行为 `principals [options] keytype base64 [comment]`，
+/// 选项中只使用 `namespaces=`，`cert-authority` 条目不支持，忽略
+fn parse_allowed_signers(content: &str, path: &Path) -> MonoResult<Vec<AllowedSigner>> {
+    let mut signers = Vec::new();
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let invalid = || MonoError::config(format!("{}:{}: invalid allowed signers entry", path.display(), i + 1));
+        let fields = split_fields(line);
+        let (options, key) = match fields.get(1..3).and_then(|key| PublicKey::from_openssh(&key.join(" ")).ok()) {
+            Some(key) => (None, key),
+            None => {
+                let key = fields.get(2..4).ok_or_else(invalid)?;
+                (Some(fields[1].as_str()), PublicKey::from_openssh(&key.join(" ")).map_err(|_| invalid())?)
+            }
+        };
+        let mut namespaces = None;
+        let mut certificate = false;
+        for option in options.map(split_options).unwrap_or_default() {
+            match option.split_once('=') {
+                Some((name, value)) if name.eq_ignore_ascii_case("namespaces") => {
+                    namespaces = Some(value.trim_matches('"').split(',').map(str::to_string).collect());
+                }
+                None if option.eq_ignore_ascii_case("cert-authority") => certificate = true,
+                _ => {}
+            }
+        }
+        if certificate {
+            continue;
+        }
+        let principals = fields[0].trim_matches('"').split(',').map(str::to_string).collect();
+        signers.push(AllowedSigner { principals, namespaces, key });
+    }
+    Ok(signers)
+}
+
+/// 按空白分隔字段，双引号中的空白不分隔
+fn split_fields(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    for c in line.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                field.push(c);
+            }
+            c if c.is_whitespace() && !quoted => {
+                if !field.is_empty() {
+                    fields.push(std::mem::take(&mut field));
+                }
+            }
+            c => field.push(c),
+        }
+    }
+    if !field.is_empty() {
+        fields.push(field);
+    }
+    fields
+}
+
+/// 按逗号分隔选项，双引号中的逗号不分隔
+fn split_options(options: &str) -> Vec<&str> {
+    let mut out = Vec::new();
+    let mut start = 0;
+    let mut quoted = false;
+    for (i, c) in options.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                out.push(&options[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    out.push(&options[start..]);
+    out
+}
+
+/// 运行 `gpgv`，签名写入 `tmp_dir` 下新建的临时文件，签名的内容从标准输入传入，返回 `--status-fd` 的输出
+///
+/// 临时文件名随机且只有当前用户可读写，其他用户不能预先创建同名文件或替换其中的签名。
+fn run_gpgv(program: &str, keyring: &Path, tmp_dir: &Path, payload: &[u8], signature: &str) -> std::io::Result<String> {
+    let mut builder = std::fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+    builder.create(tmp_dir)?;
+    let name: String = rand::random::<[u8; 16]>().iter().map(|b| format!("{:02x}", b)).collect();
+    let path = tmp_dir.join(format!("signature-{}.asc", name));
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let written = options.open(&path).and_then(|mut file| file.write_all(signature.as_bytes()));
+    let output = written.and_then(|()| {
+        Command::new(program)
+            .arg("--status-fd=1")
+            .arg("--keyring")
+            .arg(keyring)
+            .arg(&path)
+            .arg("-")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .and_then(|mut child| {
+                if let Some(mut stdin) = child.stdin.take() {
+                    stdin.write_all(payload)?;
+                }
+                child.wait_with_output()
+            })
+    });
+    let _ = std::fs::remove_file(&path);
+    Ok(String::from_utf8_lossy(&output?.stdout).into_owned())
+}
+
+/// 解析 `gpgv --status-fd` 的输出
+fn parse_gpg_status(output: &str) -> Verification {
+    let mut verification = Verification::new(SignatureStatus::Unverified, Some(SignatureFormat::Gpg));
+    let mut good = false;
+    let mut bad = false;
+    for line in output.lines() {
+        let Some(status) = line.strip_prefix("[GNUPG:] ") else { continue };
+        let (keyword, rest) = status.split_once(' ').unwrap_or((status, ""));
+        let (key, user) = rest.split_once(' ').unwrap_or((rest, ""));
+        match keyword {
+            "GOODSIG" => good = true,
+            "BADSIG" | "EXPSIG" | "EXPKEYSIG" | "REVKEYSIG" => bad = true,
+            "VALIDSIG" => {
+                verification.key = Some(key.to_string());
+                continue;
+            }
+            "NO_PUBKEY" => verification.status = SignatureStatus::UnknownKey,
+            _ => continue,
+        }
+        verification.key.get_or_insert_with(|| key.to_string());
+        if !user.is_empty() {
+            verification.signer = Some(user.to_string());
+        }
+    }
+    if bad {
+        verification.status = SignatureStatus::Bad;
+    } else if good {
+        verification.status = SignatureStatus::Good;
+    }
+    verification
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::errors::MonoErrorKind;
+    use crate::object::commit::Commit;
+    use crate::object::tag::Tag;
+    use crate::test_utils::{commit_files, init_repo};
+    use russh::keys::ssh_key::LineEnding;
+    use russh::keys::{Algorithm, PrivateKey};
+
+    /// 用 SSH 私钥签名提交，返回签名后的提交 ID
+    fn sign_commit(repo: &Repository, id: &ObjectId, key: &PrivateKey) -> ObjectId {
+        let mut commit = repo.read_commit(id).unwrap();
+        let sig = key.sign(SSH_NAMESPACE, HashAlg::Sha512, &commit.encode()).unwrap();
+        let armored = sig.to_pem(LineEnding::LF).unwrap();
+        commit.extra_headers.push(("gpgsig".to_string(), armored.trim_end().to_string()));
+        repo.write_object(ObjectType::Commit, &commit.encode()).unwrap()
+    }
+
+    fn verifier(repo: &Repository, signers: &str, require_signed: &[&str]) -> Verifier {
+        std::fs::write(repo.mono_dir().join("allowed_signers"), signers).unwrap();
+        let config = SigningConfig {
+            allowed_signers: Some(PathBuf::from("allowed_signers")),
+            require_signed: require_signed.iter().map(|r| r.to_string()).collect(),
+            ..SigningConfig::default()
+        };
+        Verifier::from_config(&config, repo.mono_dir(), repo.object_format()).unwrap()
+    }
+
+    fn verifier_for_namespaces(repo: &Repository, key: &PrivateKey, namespaces: &str) -> Verifier {
+        let entry = format!("alice@example.com namespaces=\"{}\" {}\n", namespaces, key.public_key().to_openssh().unwrap());
+        verifier(repo, &entry, &[])
+    }
+
+    /// 测试分离提交与标签中的签名
+    #[test]
+    fn test_split() {
+        let data = b"tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904\n\
+            author A <a@example.com> 1 +0000\n\
+            committer A <a@example.com> 1 +0000\n\
+            gpgsig -----BEGIN PGP SIGNATURE-----\n \n abc\n -----END PGP SIGNATURE-----\n\
+            gpgsig-sha256 -----BEGIN PGP SIGNATURE-----\n def\n -----END PGP SIGNATURE-----\n\
+            \n\
+            message\n";
+        let (payload, signature) = split_commit(data, "gpgsig").unwrap();
+        assert_eq!(signature, "-----BEGIN PGP SIGNATURE-----\n\nabc\n-----END PGP SIGNATURE-----\n");
+        let commit = Commit::parse(data).unwrap();
+        let unsigned = Commit { extra_headers: Vec::new(), ..commit };
+        assert_eq!(payload, unsigned.encode());
+        assert_eq!(split_commit(data, "gpgsig-sha256").unwrap().1, "-----BEGIN PGP SIGNATURE-----\ndef\n-----END PGP SIGNATURE-----\n");
+        assert!(split_commit(&payload, "gpgsig").is_none());
+
+        let tag = "object 4b825dc642cb6eb9a060e54bf8d69288fbee4904\ntype tree\ntag v1\n\nrelease\n";
+        let signed = format!("{}{}\nxyz\n-----END SSH SIGNATURE-----\n", tag, SSH_BEGIN);
+        let (payload, signature) = split_tag(signed.as_bytes()).unwrap();
+        assert_eq!(payload, tag.as_bytes());
+        assert!(signature.starts_with(SSH_BEGIN));
+        assert!(split_tag(tag.as_bytes()).is_none());
+    }
+
+    /// 测试 SSH 签名：可信公钥、未知公钥、内容被改动与 namespaces 选项
+    #[test]
+    fn test_verify_ssh() {
+        let (_dir, repo) = init_repo();
+        let alice = PrivateKey::random(&mut rand::rng(), Algorithm::Ed25519).unwrap();
+        let mallory = PrivateKey::random(&mut rand::rng(), Algorithm::Ed25519).unwrap();
+        let entry = format!("alice@example.com,alice namespaces=\"git,file\" {}\n", alice.public_key().to_openssh().unwrap());
+        let verifier = verifier(&repo, &format!("# trusted signers\n{}", entry), &[]);
+
+        let base = commit_files(&repo, &[("a", b"1")], &[], "base");
+        let signed = sign_commit(&repo, &base, &alice);
+        let verification = verifier.verify(&repo, &signed).unwrap();
+        assert_eq!(verification.status, SignatureStatus::Good);
+        assert_eq!(verification.format, Some(SignatureFormat::Ssh));
+        assert_eq!(verification.signer.as_deref(), Some("alice@example.com"));
+        assert_eq!(verification.key, Some(alice.public_key().fingerprint(HashAlg::Sha256).to_string()));
+        assert_eq!(verifier.verify(&repo, &base).unwrap().status, SignatureStatus::Unsigned);
+        assert_eq!(verifier.verify(&repo, &sign_commit(&repo, &base, &mallory)).unwrap().status, SignatureStatus::UnknownKey);
+
+        let mut tampered = repo.read_commit(&signed).unwrap();
+        tampered.message = "tampered\n".to_string();
+        assert_eq!(verifier.verify_commit(&tampered.encode()).status, SignatureStatus::Bad);
+
+        let file_only = verifier_for_namespaces(&repo, &alice, "file");
+        assert_eq!(file_only.verify(&repo, &signed).unwrap().status, SignatureStatus::UnknownKey);
+
+        let tag = Tag { object: signed, object_type: ObjectType::Commit, name: "v1".to_string(), tagger: None, message: "release\n".to_string() };
+        let mut data = tag.encode();
+        data.extend(alice.sign(SSH_NAMESPACE, HashAlg::Sha512, &tag.encode()).unwrap().to_pem(LineEnding::LF).unwrap().as_bytes());
+        let tag_id = repo.write_object(ObjectType::Tag, &data).unwrap();
+        assert!(verifier.verify(&repo, &tag_id).unwrap().is_good());
+    }
+
+    /// 测试交给 gpgv 的签名文件只有当前用户可读写，验证后被删除
+    #[cfg(unix)]
+    #[test]
+    fn test_run_gpgv_signature_file() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let program = dir.path().join("gpgv");
+        // 由子进程写入脚本，避免并发测试派生的进程继承写入句柄而无法执行
+        let script = "#!/bin/sh\ncat \"$4\"\nstat -c %a \"$4\"\ncat > /dev/null\n";
+        let status = Command::new("sh")
+            .arg("-c")
+            .arg("printf '%s' \"$1\" > \"$2\" && chmod 755 \"$2\"")
+            .arg("sh")
+            .arg(script)
+            .arg(&program)
+            .status()
+            .unwrap();
+        assert!(status.success());
+        let tmp_dir = dir.path().join(TMP_DIR);
+        let output = run_gpgv(program.to_str().unwrap(), Path::new("keyring"), &tmp_dir, b"payload", "signature\n").unwrap();
+        assert_eq!(output, "signature\n600\n");
+        assert_eq!(std::fs::metadata(&tmp_dir).unwrap().permissions().mode() & 0o777, 0o700);
+        assert_eq!(std::fs::read_dir(&tmp_dir).unwrap().count(), 0);
+    }
+
+    /// 测试解析 gpgv 的状态输出
+    #[test]
+    fn test_parse_gpg_status() {
+        let good = "[GNUPG:] NEWSIG\n\
+            [GNUPG:] GOODSIG 1234ABCD5678EF90 Alice <alice@example.com>\n\
+            [GNUPG:] VALIDSIG 0123456789ABCDEF0123456789ABCDEF01234567 2026-01-01 1767225600 0 4 0 22 10 00 0123456789ABCDEF0123456789ABCDEF01234567\n";
+        let verification = parse_gpg_status(good);
+        assert_eq!(verification.status, SignatureStatus::Good);
+        assert_eq!(verification.signer.as_deref(), Some("Alice <alice@example.com>"));
+        assert_eq!(verification.key.as_deref(), Some("0123456789ABCDEF0123456789ABCDEF01234567"));
+
+        let bad = parse_gpg_status("[GNUPG:] BADSIG 1234ABCD5678EF90 Alice <alice@example.com>\n");
+        assert_eq!((bad.status, bad.key.as_deref()), (SignatureStatus::Bad, Some("1234ABCD5678EF90")));
+        let unknown = parse_gpg_status("[GNUPG:] ERRSIG 1234ABCD5678EF90 22 10 00 1767225600 9 -\n[GNUPG:] NO_PUBKEY 1234ABCD5678EF90\n");
+        assert_eq!(unknown.status, SignatureStatus::UnknownKey);
+        assert_eq!(parse_gpg_status("").status, SignatureStatus::Unverified);
+    }
+
+    /// 测试推送时要求受保护引用上新引入的提交带有有效签名
+    #[test]
+    fn test_check() {
+        let (_dir, repo) = init_repo();
+        let alice = PrivateKey::random(&mut rand::rng(), Algorithm::Ed25519).unwrap();
+        let entry = format!("alice@example.com {}\n", alice.public_key().to_openssh().unwrap());
+        let verifier = verifier(&repo, &entry, &["refs/heads/main"]);
+        let legacy = commit_files(&repo, &[("a", b"1")], &[], "legacy commit");
+        repo.refs()
+            .update(&[RefUpdate { name: "refs/heads/main".to_string(), old: ObjectId::ZERO, new: legacy }])
+            .unwrap();
+        let unsigned = commit_files(&repo, &[("a", b"2")], &[legacy], "unsigned");
+        let signed = sign_commit(&repo, &commit_files(&repo, &[("a", b"3")], &[unsigned], "signed"), &alice);
+
+        let update = RefUpdate { name: "refs/heads/main".to_string(), old: legacy, new: signed };
+        let err = verifier.check(&repo, &update, &[]).unwrap_err();
+        let MonoErrorKind::Policy(violation) = err.kind() else {
+            panic!("unexpected error: {}", err);
+        };
+        assert_eq!(violation.rule, RULE);
+        assert_eq!(violation.reason, format!("commit {} is not signed", &unsigned.to_hex()[..7]));
+        let feature = RefUpdate { name: "refs/heads/feature".to_string(), ..update };
+        verifier.check(&repo, &feature, &[]).unwrap();
+
+        let signed_only = sign_commit(&repo, &commit_files(&repo, &[("a", b"4")], &[legacy], "signed only"), &alice);
+        verifier.check(&repo, &RefUpdate { new: signed_only, ..update }, &[]).unwrap();
+    }
+}
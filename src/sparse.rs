@@ -0,0 +1,330 @@
+//! 稀疏检出
+//!
+//! 团队在仓库根目录的 `sparse-profiles.toml` 中定义命名的稀疏检出配置，
+//! 开发者只物化自己关心的目录：
+//!
+//! ```toml
+//! [profiles.payments]
+//! description = "支付服务及其依赖"
+//! patterns = ["//services/payments/...", "//libs/common/..."]
+//! ```
+//!
+//! 路径模式以 `//` 开头，`//dir/...` 表示目录下的全部内容，`//dir/file` 表示单个路径。
+//! 与 git 的 cone 模式一致，根目录下的文件总是会被检出。
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::object::tree::check_entry_name;
+use crate::object::ObjectId;
+use crate::repo::{is_reserved_name, Repository, SparseState};
+use crate::worktree;
+
+/// 仓库内稀疏检出配置文件名
+pub const PROFILES_FILE: &str = "sparse-profiles.toml";
+
+/// 稀疏检出路径模式
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SparsePattern {
+    /// 不含 `//` 前缀和 `/...` 后缀的相对路径，空字符串表示整个仓库
+    prefix: String,
+}
+
+impl SparsePattern {
+    /// 路径是否位于模式之内
+    pub fn matches(&self, path: &str) -> bool {
+        self.prefix.is_empty()
+            || path == self.prefix
+            || path
+                .strip_prefix(self.prefix.as_str())
+                .is_some_and(|rest| rest.starts_with('/'))
+    }
+
+    /// 目录是否为模式的祖先目录，需要进入该目录才能检出模式下的内容
+    pub fn is_ancestor(&self, dir: &str) -> bool {
+        self.prefix
+            .strip_prefix(dir)
+            .is_some_and(|rest| rest.starts_with('/'))
+    }
+}
+
+impl FromStr for SparsePattern {
+    type Err = MonoError;
+
+    fn from_str(s: &str) -> MonoResult<SparsePattern> {
+        let path = s
+            .strip_prefix("//")
+            .ok_or_else(|| MonoError::usage(format!("sparse pattern must start with '//': {}", s)))?;
+        let path = path.strip_suffix("...").unwrap_or(path).trim_end_matches('/');
+        if path.split('/').any(|c| c == ".." || c == ".") || path.contains("//") {
+            return Err(MonoError::usage(format!("invalid sparse pattern: {}", s)));
+        }
+        Ok(SparsePattern {
+            prefix: path.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for SparsePattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.prefix.is_empty() {
+            f.write_str("//...")
+        } else {
+            write!(f, "//{}/...", self.prefix)
+        }
+    }
+}
+
+/// 一组生效的稀疏检出模式
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SparseSpec {
+    pub patterns: Vec<SparsePattern>,
+}
+
+impl SparseSpec {
+    /// 解析一组模式字符串
+    pub fn parse<S: AsRef<str>>(patterns: &[S]) -> MonoResult<SparseSpec> {
+        Ok(SparseSpec {
+            patterns: patterns
+                .iter()
+                .map(|p| p.as_ref().parse())
+                .collect::<MonoResult<_>>()?,
+        })
+    }
+
+    /// 路径是否需要检出
+    pub fn includes(&self, path: &str, is_dir: bool) -> bool {
+        if !is_dir && !path.contains('/') {
+            return true;
+        }
+        self.patterns
+            .iter()
+            .any(|p| p.matches(path) || (is_dir && p.is_ancestor(path)))
+    }
+}
+
+/// 命名的稀疏检出配置
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct SparseProfile {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub patterns: Vec<String>,
+}
+
+/// 仓库中定义的全部稀疏检出配置
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct SparseProfiles {
+    #[serde(default)]
+    pub profiles: BTreeMap<String, SparseProfile>,
+}
+
+impl SparseProfiles {
+    /// 解析配置文件内容
+    pub fn parse(content: &str) -> MonoResult<SparseProfiles> {
+        toml::from_str(content)
+            .map_err(|e| MonoError::config(format!("{}: {}", PROFILES_FILE, e.message())))
+    }
+
+    /// 加载仓库中的配置
+    ///
+    /// 优先读取 HEAD 提交中的版本，稀疏检出时该文件位于根目录因而也会出现在工作区中，
+    /// 尚无提交时退回到工作区中的文件。
+    pub fn load(repo: &Repository) -> MonoResult<SparseProfiles> {
+        if let Some(head) = repo.head_commit()? {
+            let tree = repo.read_commit(&head)?.tree;
+            if let Some(entry) = repo.find_path(&tree, PROFILES_FILE)? {
+                let data = repo.read_object(&entry.id)?.data;
+                return SparseProfiles::parse(&String::from_utf8_lossy(&data));
+            }
+        }
+        match std::fs::read_to_string(repo.root().join(PROFILES_FILE)) {
+            Ok(content) => SparseProfiles::parse(&content),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(SparseProfiles::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// 根据工作区状态计算生效的模式：配置中的模式加上额外模式
+pub fn resolve_spec(repo: &Repository, state: &SparseState) -> MonoResult<SparseSpec> {
+    let mut patterns = Vec::new();
+    if let Some(name) = &state.profile {
+        let profiles = SparseProfiles::load(repo)?;
+        let profile = profiles
+            .profiles
+            .get(name)
+            .ok_or_else(|| MonoError::not_found(format!("sparse profile {}", name)))?;
+        patterns.extend(profile.patterns.iter().cloned());
+    }
+    patterns.extend(state.patterns.iter().cloned());
+    SparseSpec::parse(&patterns)
+}
+
+/// 一次应用的统计信息
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ApplyStats {
+    /// 检出的文件数
+    pub checked_out: usize,
+    /// 从工作区移除的文件数
+    pub removed: usize,
+}
+
+/// 按工作区的稀疏检出状态更新工作区
+///
+/// 移除不再包含的已跟踪文件（未跟踪文件保留），并检出包含的文件。未启用稀疏检出时检出全部文件。
+pub fn apply(repo: &Repository) -> MonoResult<ApplyStats> {
+    let Some(head) = repo.head_commit()? else {
+        return Ok(ApplyStats::default());
+    };
+    let spec = match repo.workspace()?.sparse {
+        Some(state) => Some(resolve_spec(repo, &state)?),
+        None => None,
+    };
+    let includes = |path: &str, is_dir: bool| spec.as_ref().is_none_or(|s| s.includes(path, is_dir));
+
+    let tree = repo.read_commit(&head)?.tree;
+    let mut stats = ApplyStats::default();
+    remove_excluded(repo, &tree, "", &includes, &mut stats)?;
+    stats.checked_out = worktree::checkout_tree(repo, &tree, includes)?.files;
+    Ok(stats)
+}
+
+/// 删除树中不再包含的已跟踪文件，并清理因此变空的目录
+fn remove_excluded(
+    repo: &Repository,
+    tree: &ObjectId,
+    prefix: &str,
+    includes: &dyn Fn(&str, bool) -> bool,
+    stats: &mut ApplyStats,
+) -> MonoResult<()> {
+    for entry in repo.read_tree(tree)?.entries {
+        check_entry_name(tree, &entry.name)?;
+        if is_reserved_name(&entry.name) {
+            continue;
+        }
+        let path = if prefix.is_empty() {
+            entry.name.clone()
+        } else {
+            format!("{}/{}", prefix, entry.name)
+        };
+        let full = repo.root().join(&path);
+        if entry.mode.is_tree() {
+            if !full.is_dir() {
+                continue;
+            }
+            if includes(&path, true) {
+                remove_excluded(repo, &entry.id, &path, includes, stats)?;
+            } else {
+                remove_excluded(repo, &entry.id, &path, &|_: &str, _: bool| false, stats)?;
+                remove_if_empty(&full);
+            }
+        } else if !includes(&path, false) && full.symlink_metadata().is_ok() {
+            std::fs::remove_file(&full)?;
+            stats.removed += 1;
+        }
+    }
+    Ok(())
+}
+
+fn remove_if_empty(dir: &Path) {
+    if std::fs::read_dir(dir).is_ok_and(|mut entries| entries.next().is_none()) {
+        let _ = std::fs::remove_dir(dir);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{commit_files, init_repo};
+
+    /// 测试路径模式的解析与匹配
+    #[test]
+    fn test_pattern() {
+        let pattern: SparsePattern = "//services/payments/...".parse().unwrap();
+        assert!(pattern.matches("services/payments"));
+        assert!(pattern.matches("services/payments/api/main.rs"));
+        assert!(!pattern.matches("services/payments-v2"));
+        assert!(pattern.is_ancestor("services"));
+        assert!(!pattern.is_ancestor("serv"));
+        assert_eq!(pattern.to_string(), "//services/payments/...");
+
+        let all: SparsePattern = "//...".parse().unwrap();
+        assert!(all.matches("anything/at/all"));
+
+        assert!("services/payments".parse::<SparsePattern>().is_err());
+        assert!("//services/../secrets".parse::<SparsePattern>().is_err());
+    }
+
+    /// 测试根目录文件总是包含，其余按模式匹配
+    #[test]
+    fn test_spec_includes() {
+        let spec = SparseSpec::parse(&["//services/payments/...", "//tools/lint.sh"]).unwrap();
+        assert!(spec.includes("README.md", false));
+        assert!(spec.includes("services", true));
+        assert!(spec.includes("services/payments/main.rs", false));
+        assert!(!spec.includes("services/search", true));
+        assert!(spec.includes("tools/lint.sh", false));
+        assert!(!spec.includes("tools/other.sh", false));
+    }
+
+    /// 测试应用稀疏检出配置以及切换配置时移除文件
+    #[test]
+    fn test_apply_profile() {
+        let (dir, repo) = init_repo();
+        let profiles = b"[profiles.payments]\npatterns = [\"//services/payments/...\"]\n\n\
+            [profiles.search]\npatterns = [\"//services/search/...\"]\n";
+        let commit = commit_files(
+            &repo,
+            &[
+                (PROFILES_FILE, profiles),
+                ("services/payments/main.rs", b"pay"),
+                ("services/search/main.rs", b"search"),
+                ("libs/common/lib.rs", b"lib"),
+            ],
+            &[],
+            "init",
+        );
+        repo.refs().write("refs/heads/main", &commit).unwrap();
+
+        let mut manifest = repo.workspace().unwrap();
+        manifest.sparse = Some(SparseState {
+            profile: Some("payments".to_string()),
+            patterns: vec![],
+        });
+        repo.save_workspace(&manifest).unwrap();
+        let stats = apply(&repo).unwrap();
+        assert_eq!(stats.checked_out, 2);
+        assert!(dir.path().join("services/payments/main.rs").exists());
+        assert!(!dir.path().join("services/search").exists());
+        assert!(!dir.path().join("libs").exists());
+
+        manifest.sparse = Some(SparseState {
+            profile: Some("search".to_string()),
+            patterns: vec!["//libs/...".to_string()],
+        });
+        repo.save_workspace(&manifest).unwrap();
+        let stats = apply(&repo).unwrap();
+        assert_eq!(stats.removed, 1);
+        assert!(!dir.path().join("services/payments").exists());
+        assert!(dir.path().join("services/search/main.rs").exists());
+        assert!(dir.path().join("libs/common/lib.rs").exists());
+    }
+
+    /// 测试引用不存在的配置时报错
+    #[test]
+    fn test_unknown_profile() {
+        let (_dir, repo) = init_repo();
+        let state = SparseState {
+            profile: Some("missing".to_string()),
+            patterns: vec![],
+        };
+        assert!(resolve_spec(&repo, &state).is_err());
+    }
+}
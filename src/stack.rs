@@ -0,0 +1,310 @@
+//! 堆叠分支
+//!
+//! 大的功能拆成一串相互依赖的小分支分别评审：每个分支建立在父分支之上，最底下的分支
+//! 建立在主干上。分支的父分支与基础提交（创建或上次变基时父分支的位置）记录在
+//! `refs/stack/<branch>` 指向的 blob 中：
+//!
+//! ```text
+//! parent refs/heads/feature-1
+//! base 4b825dc642cb6eb9a060e54bf8d69288fbee4904
+//! ```
+//!
+//! 父分支前进（例如评审后追加了提交）后，`restack` 将 `base..branch` 的提交变基到父分支
+//! 的新位置，并自下而上逐层处理整个堆叠。元数据与普通引用一样可以推送，评审工具据此
+//! 将一组分支展示为一个系列。
+
+use std::collections::BTreeMap;
+
+use crate::audit::{self, AuditAction, AuditLog};
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::object::commit::Signature;
+use crate::object::{ObjectId, ObjectType};
+use crate::refs::{self, RefUpdate};
+use crate::repo::Repository;
+use crate::rewrite::rebase::{commits_to_rebase, rebase_onto, RebaseOutcome};
+use crate::transport::{self, PushStats};
+
+/// 堆叠元数据引用前缀
+pub const STACK_PREFIX: &str = "refs/stack/";
+
+/// 堆叠中的一个分支
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StackBranch {
+    /// 分支全名
+    pub branch: String,
+    /// 父分支全名
+    pub parent: String,
+    /// 创建或上次变基时父分支的位置，分支独有的提交为 `base..branch`
+    pub base: ObjectId,
+}
+
+impl StackBranch {
+    /// 保存元数据的引用名
+    pub fn meta_ref(branch: &str) -> String {
+        format!("{}{}", STACK_PREFIX, refs::short_name(branch))
+    }
+
+    pub fn encode(&self) -> String {
+        format!("parent {}\nbase {}\n", self.parent, self.base)
+    }
+
+    pub fn parse(branch: &str, content: &str) -> MonoResult<StackBranch> {
+        let corrupt = || MonoError::storage(format!("corrupt stack metadata for {}", branch));
+        let mut parent = None;
+        let mut base = None;
+        for line in content.lines() {
+            match line.split_once(' ') {
+                Some(("parent", value)) => parent = Some(value.to_string()),
+                Some(("base", value)) => base = Some(value.parse().map_err(|_| corrupt())?),
+                _ => {}
+            }
+        }
+        Ok(StackBranch {
+            branch: branch.to_string(),
+            parent: parent.ok_or_else(corrupt)?,
+            base: base.ok_or_else(corrupt)?,
+        })
+    }
+
+    /// 分支独有的非合并提交数
+    pub fn commit_count(&self, repo: &Repository) -> MonoResult<usize> {
+        let tip = resolve_branch(repo, &self.branch)?;
+        Ok(commits_to_rebase(repo, &self.base, &tip)?.len())
+    }
+}
+
+fn resolve_branch(repo: &Repository, branch: &str) -> MonoResult<ObjectId> {
+    repo.refs()
+        .resolve(branch)?
+        .ok_or_else(|| MonoError::not_found(format!("branch {}", refs::short_name(branch))))
+}
+
+/// 在审计日志中记录本地命令对分支的更新
+fn record_update(repo: &Repository, update: RefUpdate) {
+    let now = chrono::Utc::now().timestamp();
+    AuditLog::new(repo).record_ref_updates(repo, &audit::local_actor(), AuditAction::RefUpdate, &[update], now);
+}
+
+/// 读取分支的堆叠元数据，不在堆叠中的分支返回 None
+pub fn load(repo: &Repository, branch: &str) -> MonoResult<Option<StackBranch>> {
+    let Some(id) = repo.refs().resolve(&StackBranch::meta_ref(branch))? else {
+        return Ok(None);
+    };
+    let data = repo.read_object(&id)?.data;
+    StackBranch::parse(branch, &String::from_utf8_lossy(&data)).map(Some)
+}
+
+/// 写入分支的堆叠元数据
+pub fn save(repo: &Repository, meta: &StackBranch) -> MonoResult<()> {
+    let id = repo.write_object(ObjectType::Blob, meta.encode().as_bytes())?;
+    repo.refs().write(&StackBranch::meta_ref(&meta.branch), &id)
+}
+
+/// 全部堆叠分支，按分支名排序
+pub fn all(repo: &Repository) -> MonoResult<Vec<StackBranch>> {
+    let mut branches = Vec::new();
+    for (name, _) in repo.refs().list(STACK_PREFIX)? {
+        let branch = format!("{}{}", refs::HEADS_PREFIX, &name[STACK_PREFIX.len()..]);
+        branches.extend(load(repo, &branch)?);
+    }
+    Ok(branches)
+}
+
+/// 在父分支的当前位置创建堆叠分支
+pub fn create(repo: &Repository, name: &str, parent: &str) -> MonoResult<StackBranch> {
+    let branch = format!("{}{}", refs::HEADS_PREFIX, name);
+    if !refs::check_ref_format(&branch) {
+        return Err(MonoError::usage(format!("invalid branch name: {}", name)));
+    }
+    let base = resolve_branch(repo, parent)?;
+    let update = RefUpdate {
+        name: branch.clone(),
+        old: ObjectId::ZERO,
+        new: base,
+    };
+    repo.with_reflog_identity(&audit::local_actor(), "stack create")
+        .refs()
+        .update(std::slice::from_ref(&update))?;
+    record_update(repo, update);
+    let meta = StackBranch {
+        branch,
+        parent: parent.to_string(),
+        base,
+    };
+    save(repo, &meta)?;
+    Ok(meta)
+}
+
+/// `branch` 所在的堆叠，父分支在前
+///
+/// 从 `branch` 向上找到建立在主干上的最底层分支，返回它及其全部后代；
+/// `branch` 本身不在堆叠中时返回以它为父分支的全部堆叠分支及其后代。
+pub fn stack_of(repo: &Repository, branch: &str) -> MonoResult<Vec<StackBranch>> {
+    let branches: BTreeMap<String, StackBranch> = all(repo)?.into_iter().map(|b| (b.branch.clone(), b)).collect();
+    let mut children: BTreeMap<&str, Vec<&StackBranch>> = BTreeMap::new();
+    for meta in branches.values() {
+        children.entry(meta.parent.as_str()).or_default().push(meta);
+    }
+
+    let mut roots: Vec<&StackBranch> = Vec::new();
+    let mut current = branch;
+    let mut seen = 0;
+    while let Some(meta) = branches.get(current) {
+        roots = vec![meta];
+        current = &meta.parent;
+        seen += 1;
+        if seen > branches.len() {
+            return Err(MonoError::storage(format!("stack of {} has a parent cycle", refs::short_name(branch))));
+        }
+    }
+    if roots.is_empty() {
+        roots = children.get(branch).cloned().unwrap_or_default();
+    }
+
+    let mut stack = Vec::new();
+    let mut queue: Vec<&StackBranch> = roots.into_iter().rev().collect();
+    while let Some(meta) = queue.pop() {
+        stack.push(meta.clone());
+        queue.extend(children.get(meta.branch.as_str()).into_iter().flatten().rev());
+    }
+    Ok(stack)
+}
+
+/// 一个分支的变基结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Restacked {
+    pub branch: String,
+    pub old: ObjectId,
+    pub new: ObjectId,
+}
+
+/// 将堆叠中父分支已移动的分支变基到父分支的新位置，返回被更新的分支
+///
+/// 按父分支在前的顺序处理，因此父分支变基后其子分支随之变基。遇到冲突时返回错误，
+/// 之前已处理的分支保持更新后的状态，解决冲突后重新执行即可继续。
+pub fn restack(repo: &Repository, stack: &[StackBranch], committer: &Signature) -> MonoResult<Vec<Restacked>> {
+    let mut restacked = Vec::new();
+    for meta in stack {
+        let onto = resolve_branch(repo, &meta.parent)?;
+        if onto == meta.base {
+            continue;
+        }
+        let old = resolve_branch(repo, &meta.branch)?;
+        let new = match rebase_onto(repo, &meta.base, &old, &onto, committer)? {
+            RebaseOutcome::Rebased(new) => new,
+            RebaseOutcome::Conflict { commit, paths } => {
+                return Err(MonoError::usage(format!(
+                    "conflict in {} while restacking {} at {}",
+                    paths.join(", "),
+                    refs::short_name(&meta.branch),
+                    commit
+                )));
+            }
+        };
+        let update = RefUpdate {
+            name: meta.branch.clone(),
+            old,
+            new,
+        };
+        repo.with_reflog_identity(&audit::local_actor(), "stack restack")
+            .refs()
+            .update(std::slice::from_ref(&update))?;
+        record_update(repo, update);
+        save(
+            repo,
+            &StackBranch {
+                base: onto,
+                ..meta.clone()
+            },
+        )?;
+        restacked.push(Restacked {
+            branch: meta.branch.clone(),
+            old,
+            new,
+        });
+    }
+    Ok(restacked)
+}
+
+/// 将堆叠中的分支及其元数据推送到远端
+///
+/// 以远端当前的值作为旧值更新，变基后的分支会被强制更新，但推送期间远端被其他人修改时失败。
+pub fn submit(repo: &Repository, stack: &[StackBranch], url: &str) -> MonoResult<PushStats> {
+    let transport = transport::open(url)?;
+    let remote: BTreeMap<String, ObjectId> = transport.list_refs()?.refs.into_iter().collect();
+    let mut updates = Vec::new();
+    for meta in stack {
+        let meta_ref = StackBranch::meta_ref(&meta.branch);
+        for name in [&meta.branch, &meta_ref] {
+            let new = repo.refs().resolve(name)?.unwrap_or(ObjectId::ZERO);
+            let old = remote.get(name.as_str()).copied().unwrap_or(ObjectId::ZERO);
+            if old != new {
+                updates.push(RefUpdate {
+                    name: name.clone(),
+                    old,
+                    new,
+                });
+            }
+        }
+    }
+    if updates.is_empty() {
+        return Ok(PushStats::default());
+    }
+    transport.push(&updates, repo.objects())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{commit_files, init_repo};
+
+    /// 测试创建堆叠、父分支前进后逐层变基，以及推送分支与元数据
+    #[test]
+    fn test_stack() {
+        let (_dir, repo) = init_repo();
+        let base = commit_files(&repo, &[("a", b"1")], &[], "base");
+        repo.refs().write("refs/heads/main", &base).unwrap();
+
+        create(&repo, "one", "refs/heads/main").unwrap();
+        let one = commit_files(&repo, &[("a", b"1"), ("one", b"1")], &[base], "one");
+        repo.refs().write("refs/heads/one", &one).unwrap();
+        create(&repo, "two", "refs/heads/one").unwrap();
+        let two = commit_files(&repo, &[("a", b"1"), ("one", b"1"), ("two", b"1")], &[one], "two");
+        repo.refs().write("refs/heads/two", &two).unwrap();
+        create(&repo, "other", "refs/heads/main").unwrap();
+        assert!(create(&repo, "one", "refs/heads/main").is_err());
+
+        let names = |stack: &[StackBranch]| stack.iter().map(|b| b.branch.clone()).collect::<Vec<_>>();
+        assert_eq!(names(&stack_of(&repo, "refs/heads/two").unwrap()), ["refs/heads/one", "refs/heads/two"]);
+        assert_eq!(names(&stack_of(&repo, "refs/heads/one").unwrap()), ["refs/heads/one", "refs/heads/two"]);
+        assert_eq!(
+            names(&stack_of(&repo, "refs/heads/main").unwrap()),
+            ["refs/heads/one", "refs/heads/two", "refs/heads/other"]
+        );
+        let stack = stack_of(&repo, "refs/heads/two").unwrap();
+        assert_eq!(stack[1].commit_count(&repo).unwrap(), 1);
+
+        // 评审后父分支追加提交，子分支需要变基
+        let committer = Signature::new("Dev", "dev@example.com", 1_800_000_000);
+        assert!(restack(&repo, &stack, &committer).unwrap().is_empty());
+        let fixup = commit_files(&repo, &[("a", b"1"), ("one", b"2")], &[one], "fixup");
+        repo.refs().write("refs/heads/one", &fixup).unwrap();
+        let restacked = restack(&repo, &stack, &committer).unwrap();
+        assert_eq!(restacked.len(), 1);
+        assert_eq!(restacked[0].old, two);
+        let new_two = repo.read_commit(&restacked[0].new).unwrap();
+        assert_eq!(new_two.parents, [fixup]);
+        assert_eq!(new_two.message, "two\n");
+        assert_eq!(load(&repo, "refs/heads/two").unwrap().unwrap().base, fixup);
+
+        let (_remote_dir, remote) = init_repo();
+        let url = remote.root().to_string_lossy().into_owned();
+        let stack = stack_of(&repo, "refs/heads/two").unwrap();
+        let stats = submit(&repo, &stack, &url).unwrap();
+        assert!(stats.objects > 0);
+        assert_eq!(remote.refs().resolve("refs/heads/two").unwrap(), Some(restacked[0].new));
+        assert_eq!(load(&remote, "refs/heads/two").unwrap().unwrap().parent, "refs/heads/one");
+        assert_eq!(submit(&repo, &stack, &url).unwrap(), PushStats::default());
+    }
+}
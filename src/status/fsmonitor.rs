@@ -0,0 +1,183 @@
+//! 文件变化监视
+//!
+//! 在数百万文件的工作区中逐个检查文件的状态代价很高。配置 `[status] fsmonitor` 后，
+//! `mono status` 向监视程序询问自上次查询以来变化的路径，只检查这些路径：
+//!
+//! - `fsmonitor = "watchman"`：通过 `watchman -j` 查询 [Watchman](https://facebook.github.io/watchman/)，
+//!   首次使用时自动监视工作区；
+//! - 其他值作为 git fsmonitor 钩子的命令运行（协议版本 2，与 `core.fsmonitor` 相同）：参数为
+//!   `2 <上次的令牌>`，标准输出为以 NUL 分隔的新令牌与变化的路径，路径 `/` 表示全部变化。
+//!
+//! 监视程序无法确定变化（首次查询、监视程序重启等）时退回到完整扫描。
+
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use serde_json::json;
+
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::repo::Repository;
+
+/// `[status] fsmonitor` 中表示 Watchman 的值
+pub const WATCHMAN: &str = "watchman";
+
+/// 一次查询的结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MonitorResult {
+    /// 下次查询时传回的令牌
+    pub token: String,
+    /// 自上次令牌以来变化的路径（相对工作区根目录，目录可能以 `/` 结尾），None 表示需要完整扫描
+    pub changed: Option<Vec<String>>,
+}
+
+/// 文件变化监视程序
+pub trait FsMonitor {
+    /// 自 `token` 以来变化的路径；`token` 为 None 时只取得令牌
+    fn query(&self, root: &Path, token: Option<&str>) -> MonoResult<MonitorResult>;
+}
+
+/// 按仓库配置创建监视程序，未配置时返回 None
+pub fn from_config(repo: &Repository) -> Option<Box<dyn FsMonitor>> {
+    match repo.config().status.fsmonitor.as_deref() {
+        None => None,
+        Some(WATCHMAN) => Some(Box::new(Watchman::new("watchman"))),
+        Some(command) => Some(Box::new(HookMonitor::new(command))),
+    }
+}
+
+/// 通过命令行客户端查询 Watchman
+pub struct Watchman {
+    program: String,
+}
+
+impl Watchman {
+    pub fn new(program: impl Into<String>) -> Watchman {
+        Watchman { program: program.into() }
+    }
+
+    /// 发送一条 JSON 命令并读取响应
+    fn command(&self, request: serde_json::Value) -> MonoResult<serde_json::Value> {
+        let mut child = Command::new(&self.program)
+            .args(["-j", "--no-pretty"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| MonoError::unavailable(format!("cannot run {}: {}", self.program, e)))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(request.to_string().as_bytes())?;
+        }
+        let output = child.wait_with_output()?;
+        let response: serde_json::Value = serde_json::from_slice(&output.stdout).map_err(|e| {
+            MonoError::unavailable(format!("invalid watchman response: {} ({})", e, String::from_utf8_lossy(&output.stderr).trim()))
+        })?;
+        if let Some(error) = response["error"].as_str() {
+            return Err(MonoError::unavailable(format!("watchman: {}", error)));
+        }
+        Ok(response)
+    }
+}
+
+impl FsMonitor for Watchman {
+    fn query(&self, root: &Path, token: Option<&str>) -> MonoResult<MonitorResult> {
+        let watch = self.command(json!(["watch-project", root]))?;
+        let watch_root = watch["watch"]
+            .as_str()
+            .ok_or_else(|| MonoError::unavailable("watchman did not report the watched root"))?
+            .to_string();
+        let Some(token) = token else {
+            let clock = self.command(json!(["clock", watch_root]))?;
+            let token = clock["clock"].as_str().ok_or_else(|| MonoError::unavailable("watchman did not report a clock"))?;
+            return Ok(MonitorResult {
+                token: token.to_string(),
+                changed: None,
+            });
+        };
+
+        let mut query = json!({"since": token, "fields": ["name"], "empty_on_fresh_instance": true});
+        if let Some(relative) = watch["relative_path"].as_str() {
+            query["relative_root"] = json!(relative);
+        }
+        let response = self.command(json!(["query", watch_root, query]))?;
+        let token = response["clock"].as_str().ok_or_else(|| MonoError::unavailable("watchman did not report a clock"))?;
+        let changed = if response["is_fresh_instance"].as_bool().unwrap_or(false) {
+            None
+        } else {
+            let files = response["files"].as_array().map(Vec::as_slice).unwrap_or_default();
+            Some(files.iter().filter_map(|file| file.as_str().map(str::to_string)).collect())
+        };
+        Ok(MonitorResult {
+            token: token.to_string(),
+            changed,
+        })
+    }
+}
+
+/// git fsmonitor 钩子（协议版本 2）
+pub struct HookMonitor {
+    command: String,
+}
+
+impl HookMonitor {
+    pub fn new(command: impl Into<String>) -> HookMonitor {
+        HookMonitor { command: command.into() }
+    }
+}
+
+impl FsMonitor for HookMonitor {
+    fn query(&self, root: &Path, token: Option<&str>) -> MonoResult<MonitorResult> {
+        let output = Command::new("sh")
+            .arg("-c")
+            .arg(format!("{} \"$@\"", self.command))
+            .arg("fsmonitor")
+            .arg("2")
+            .arg(token.unwrap_or_default())
+            .current_dir(root)
+            .stdin(Stdio::null())
+            .stderr(Stdio::inherit())
+            .output()
+            .map_err(|e| MonoError::unavailable(format!("cannot run fsmonitor hook: {}", e)))?;
+        if !output.status.success() {
+            return Err(MonoError::unavailable(format!("fsmonitor hook failed ({})", output.status)));
+        }
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let mut fields = stdout.split('\0');
+        let new_token = fields.next().unwrap_or_default();
+        if new_token.is_empty() {
+            return Err(MonoError::unavailable("fsmonitor hook did not report a token"));
+        }
+        let paths: Vec<String> = fields.filter(|path| !path.is_empty()).map(str::to_string).collect();
+        let changed = (token.is_some() && !paths.iter().any(|path| path == "/")).then_some(paths);
+        Ok(MonitorResult {
+            token: new_token.to_string(),
+            changed,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 测试钩子协议：新令牌、变化的路径与表示全部变化的 `/`
+    #[test]
+    fn test_hook_monitor() {
+        let dir = tempfile::tempdir().unwrap();
+        let hook = dir.path().join("hook.sh");
+        std::fs::write(
+            &hook,
+            "if [ \"$2\" = all ]; then printf 'next\\0/\\0'; else printf 'next-%s\\0a.txt\\0src/\\0' \"$2\"; fi\n",
+        )
+        .unwrap();
+        let monitor = HookMonitor::new(format!("sh {}", hook.display()));
+
+        let result = monitor.query(dir.path(), Some("t1")).unwrap();
+        assert_eq!(result.token, "next-t1");
+        assert_eq!(result.changed, Some(vec!["a.txt".to_string(), "src/".to_string()]));
+        assert_eq!(monitor.query(dir.path(), Some("all")).unwrap().changed, None);
+        assert_eq!(monitor.query(dir.path(), None).unwrap().changed, None);
+        assert!(HookMonitor::new("false").query(dir.path(), None).is_err());
+    }
+}
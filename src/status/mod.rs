@@ -0,0 +1,674 @@
+//! 工作区状态
+//!
+//! `mono status` 比较工作区与 HEAD：已跟踪的文件被修改（`M`）或删除（`D`），以及未跟踪的
+//! 文件（`??`，整个目录中都没有已跟踪文件时只列出该目录）。稀疏检出排除的路径不参与比较。
+//!
+//! 上次的结果保存在状态缓存 `.mono/status-cache` 中：每个已跟踪文件的 blob、大小、修改时间
+//! 与状态，以及未跟踪的路径。大小与修改时间都没有变化的文件沿用缓存中的状态，不再读取内容；
+//! 配置了文件变化监视（见 [`fsmonitor`]）时只检查监视程序报告变化的路径，其余文件连 stat
+//! 也不需要。HEAD 或稀疏检出模式改变后重建缓存，blob 未变的文件仍沿用原有的记录。
+
+pub mod fsmonitor;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use serde::Serialize;
+
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::object::tree::FileMode;
+use crate::object::{ObjectId, ObjectType};
+use crate::queue::LockFile;
+use crate::repo::{is_reserved_name, Repository};
+use crate::sparse::{resolve_spec, SparseSpec};
+use fsmonitor::FsMonitor;
+
+/// 状态缓存文件名，相对于 `.mono`
+pub const CACHE_FILE: &str = "status-cache";
+const CACHE_MAGIC: &[u8; 4] = b"MSTC";
+const CACHE_VERSION: u32 = 1;
+
+/// 路径的状态
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FileStatus {
+    Modified,
+    Deleted,
+    Untracked,
+}
+
+impl FileStatus {
+    /// 与 `git status --short` 相同的状态码
+    pub fn code(&self) -> &'static str {
+        match self {
+            FileStatus::Modified => "M",
+            FileStatus::Deleted => "D",
+            FileStatus::Untracked => "??",
+        }
+    }
+}
+
+/// 一个有变化的路径，未跟踪的目录以 `/` 结尾
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct StatusEntry {
+    pub path: String,
+    pub status: FileStatus,
+}
+
+/// 一次状态查询的结果
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StatusReport {
+    /// 按路径排列
+    pub entries: Vec<StatusEntry>,
+    /// 是否只检查了文件变化监视报告的路径
+    pub fsmonitor: bool,
+    /// 读取了内容的已跟踪文件数
+    pub hashed: usize,
+}
+
+/// 文件的大小与修改时间
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Stat {
+    size: u64,
+    mtime: i64,
+    mtime_nanos: u32,
+}
+
+impl Stat {
+    fn from_metadata(meta: &std::fs::Metadata) -> Stat {
+        let mtime = meta.modified().ok().and_then(|time| time.duration_since(UNIX_EPOCH).ok()).unwrap_or_default();
+        Stat {
+            size: meta.len(),
+            mtime: mtime.as_secs() as i64,
+            mtime_nanos: mtime.subsec_nanos(),
+        }
+    }
+}
+
+/// 已跟踪文件在缓存中的记录
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EntryState {
+    Clean = 0,
+    Modified = 1,
+    Deleted = 2,
+}
+
+#[derive(Debug, Clone)]
+struct Tracked {
+    mode: FileMode,
+    id: ObjectId,
+    /// 上次检查时的大小与修改时间，None 表示尚未检查或文件不存在
+    stat: Option<Stat>,
+    state: EntryState,
+}
+
+/// 状态缓存
+struct Cache {
+    /// HEAD 的树，没有 HEAD 时为零值
+    tree: ObjectId,
+    /// 生效的稀疏检出模式
+    sparse: String,
+    /// 文件变化监视的令牌
+    token: Option<String>,
+    /// 上次扫描开始的时间（秒），修改时间不早于它的文件下次必须重新读取内容
+    scanned_at: i64,
+    tracked: BTreeMap<String, Tracked>,
+    untracked: BTreeSet<String>,
+}
+
+/// 查询工作区状态，`monitor` 为文件变化监视程序
+pub fn status(repo: &Repository, monitor: Option<&dyn FsMonitor>) -> MonoResult<StatusReport> {
+    let tree = match repo.head_commit()? {
+        Some(head) => repo.read_commit(&head)?.tree,
+        None => repo.object_format().zero(),
+    };
+    let spec = match repo.workspace()?.sparse {
+        Some(state) => Some(resolve_spec(repo, &state)?),
+        None => None,
+    };
+    let sparse = match &spec {
+        Some(spec) => spec.patterns.iter().map(|pattern| format!("{}\n", pattern)).collect(),
+        None => String::new(),
+    };
+    let path = repo.mono_dir().join(CACHE_FILE);
+    let previous = match Cache::load(&path) {
+        Ok(cache) => cache,
+        Err(e) => {
+            tracing::warn!(error = %e, "ignoring unreadable status cache");
+            None
+        }
+    };
+    let valid = previous.as_ref().is_some_and(|cache| cache.tree == tree && cache.sparse == sparse);
+
+    // 先向监视程序查询，扫描期间发生的变化留到下次查询
+    let query = monitor.and_then(|monitor| {
+        let token = previous.as_ref().filter(|_| valid).and_then(|cache| cache.token.as_deref());
+        monitor
+            .query(repo.root(), token)
+            .inspect_err(|e| tracing::warn!(error = %e, "fsmonitor unavailable, scanning the worktree"))
+            .ok()
+    });
+    let started = chrono::Utc::now().timestamp();
+    let mut cache = match previous {
+        Some(cache) if valid => cache,
+        previous => Cache::build(repo, tree, sparse, spec.as_ref(), previous)?,
+    };
+    let mut scan = Scan {
+        root: repo.root(),
+        format: repo.object_format(),
+        spec: spec.as_ref(),
+        scanned_at: cache.scanned_at,
+        hashed: 0,
+    };
+    let changed = query.as_ref().and_then(|query| query.changed.as_ref()).filter(|_| valid);
+    match changed {
+        Some(changed) => {
+            for path in changed {
+                scan.refresh(&mut cache, path)?;
+            }
+        }
+        None => scan.full(&mut cache)?,
+    }
+    let report = StatusReport {
+        entries: cache.entries(),
+        fsmonitor: changed.is_some(),
+        hashed: scan.hashed,
+    };
+    cache.token = query.map(|query| query.token);
+    cache.scanned_at = started;
+    // 另一个进程正在写缓存时放弃本次写入
+    if let Ok(_lock) = LockFile::acquire(path.with_extension("lock")) {
+        cache.save(&path)?;
+    }
+    Ok(report)
+}
+
+/// 检查工作区中的文件
+struct Scan<'a> {
+    root: &'a Path,
+    format: crate::object::ObjectFormat,
+    spec: Option<&'a SparseSpec>,
+    scanned_at: i64,
+    hashed: usize,
+}
+
+impl Scan<'_> {
+    fn includes(&self, path: &str, is_dir: bool) -> bool {
+        self.spec.is_none_or(|spec| spec.includes(path, is_dir))
+    }
+
+    /// 检查全部已跟踪文件并重新查找未跟踪的路径
+    fn full(&mut self, cache: &mut Cache) -> MonoResult<()> {
+        for (path, entry) in cache.tracked.iter_mut() {
+            self.check(path, entry)?;
+        }
+        let mut untracked = BTreeSet::new();
+        self.find_untracked(cache, "", &mut untracked)?;
+        cache.untracked = untracked;
+        Ok(())
+    }
+
+    /// 只检查监视程序报告变化的路径及其下的内容
+    fn refresh(&mut self, cache: &mut Cache, path: &str) -> MonoResult<()> {
+        let path = path.trim_matches('/');
+        if path.is_empty() {
+            return self.full(cache);
+        }
+        if path.split('/').any(is_reserved_name) {
+            return Ok(());
+        }
+        let prefix = format!("{}/", path);
+        let keys: Vec<String> = cache
+            .tracked
+            .range(prefix.clone()..)
+            .map(|(key, _)| key)
+            .take_while(|key| key.starts_with(&prefix))
+            .chain(cache.tracked.get_key_value(path).map(|(key, _)| key))
+            .cloned()
+            .collect();
+        for key in keys {
+            let entry = cache.tracked.get_mut(&key).expect("key from the same map");
+            self.check(&key, entry)?;
+        }
+
+        // 祖先目录中没有已跟踪文件时，未跟踪的是该祖先目录本身
+        let mut end = 0;
+        while let Some(slash) = path[end..].find('/') {
+            let dir = &path[..end + slash];
+            if !self.includes(dir, true) {
+                return Ok(());
+            }
+            if !cache.has_tracked_under(dir) {
+                let name = format!("{}/", dir);
+                if self.root.join(dir).is_dir() {
+                    cache.untracked.insert(name);
+                } else {
+                    cache.untracked.remove(&name);
+                }
+                return Ok(());
+            }
+            end += slash + 1;
+        }
+
+        let stale: Vec<String> = cache
+            .untracked
+            .range(path.to_string()..)
+            .take_while(|name| name.starts_with(path))
+            .filter(|name| *name == path || name.starts_with(&prefix))
+            .cloned()
+            .collect();
+        for name in stale {
+            cache.untracked.remove(&name);
+        }
+        let meta = match std::fs::symlink_metadata(self.root.join(path)) {
+            Ok(meta) => meta,
+            Err(e) if is_missing(&e) => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+        if meta.is_dir() {
+            if cache.has_tracked_under(path) {
+                let mut untracked = BTreeSet::new();
+                self.find_untracked(cache, path, &mut untracked)?;
+                cache.untracked.extend(untracked);
+            } else if self.includes(path, true) {
+                cache.untracked.insert(prefix);
+            }
+        } else if !cache.tracked.contains_key(path) && self.includes(path, false) {
+            cache.untracked.insert(path.to_string());
+        }
+        Ok(())
+    }
+
+    /// 比较已跟踪文件与缓存的记录，大小或修改时间变化时读取内容计算 blob
+    fn check(&mut self, path: &str, entry: &mut Tracked) -> MonoResult<()> {
+        let full = self.root.join(path);
+        let meta = match std::fs::symlink_metadata(&full) {
+            Ok(meta) if !meta.is_dir() => meta,
+            Ok(_) => {
+                entry.state = EntryState::Deleted;
+                entry.stat = None;
+                return Ok(());
+            }
+            Err(e) if is_missing(&e) => {
+                entry.state = EntryState::Deleted;
+                entry.stat = None;
+                return Ok(());
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let stat = Stat::from_metadata(&meta);
+        if entry.stat == Some(stat) && stat.mtime < self.scanned_at {
+            return Ok(());
+        }
+        self.hashed += 1;
+        let (mode, data) = if meta.file_type().is_symlink() {
+            (FileMode::SYMLINK, std::fs::read_link(&full)?.to_string_lossy().into_owned().into_bytes())
+        } else {
+            (file_mode(&meta, entry.mode), std::fs::read(&full)?)
+        };
+        let id = self.format.hash_object(ObjectType::Blob, &data);
+        entry.state = if id == entry.id && mode == entry.mode {
+            EntryState::Clean
+        } else {
+            EntryState::Modified
+        };
+        entry.stat = Some(stat);
+        Ok(())
+    }
+
+    /// 查找 `dir` 下未跟踪的路径，只进入包含已跟踪文件的目录
+    fn find_untracked(&self, cache: &Cache, dir: &str, out: &mut BTreeSet<String>) -> MonoResult<()> {
+        let entries = match std::fs::read_dir(self.root.join(dir)) {
+            Ok(entries) => entries,
+            Err(e) if is_missing(&e) => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+        for entry in entries {
+            let entry = entry?;
+            let Ok(name) = entry.file_name().into_string() else {
+                continue;
+            };
+            if is_reserved_name(&name) {
+                continue;
+            }
+            let path = if dir.is_empty() { name } else { format!("{}/{}", dir, name) };
+            if entry.file_type()?.is_dir() {
+                if !self.includes(&path, true) {
+                    continue;
+                }
+                if cache.has_tracked_under(&path) {
+                    self.find_untracked(cache, &path, out)?;
+                } else {
+                    out.insert(format!("{}/", path));
+                }
+            } else if !cache.tracked.contains_key(&path) && self.includes(&path, false) {
+                out.insert(path);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn is_missing(e: &std::io::Error) -> bool {
+    matches!(e.kind(), std::io::ErrorKind::NotFound | std::io::ErrorKind::NotADirectory)
+}
+
+/// 工作区文件对应的模式，不支持可执行位的平台上沿用树中的模式
+fn file_mode(meta: &std::fs::Metadata, tracked: FileMode) -> FileMode {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let _ = tracked;
+        if meta.permissions().mode() & 0o111 != 0 {
+            FileMode::EXECUTABLE
+        } else {
+            FileMode::BLOB
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = meta;
+        if tracked == FileMode::SYMLINK {
+            FileMode::BLOB
+        } else {
+            tracked
+        }
+    }
+}
+
+impl Cache {
+    /// 按 HEAD 的树重建缓存，blob 与模式都未变的文件沿用 `previous` 中的记录
+    fn build(repo: &Repository, tree: ObjectId, sparse: String, spec: Option<&SparseSpec>, previous: Option<Cache>) -> MonoResult<Cache> {
+        let mut previous = previous.map(|cache| cache.tracked).unwrap_or_default();
+        let mut tracked = BTreeMap::new();
+        if !tree.is_zero() {
+            let mut stack = vec![(String::new(), tree)];
+            while let Some((prefix, tree_id)) = stack.pop() {
+                for entry in repo.read_tree(&tree_id)?.entries {
+                    if is_reserved_name(&entry.name) || entry.mode.is_gitlink() {
+                        continue;
+                    }
+                    let path = format!("{}{}", prefix, entry.name);
+                    if !spec.is_none_or(|spec| spec.includes(&path, entry.mode.is_tree())) {
+                        continue;
+                    }
+                    if entry.mode.is_tree() {
+                        stack.push((format!("{}/", path), entry.id));
+                        continue;
+                    }
+                    let record = match previous.remove(&path) {
+                        Some(old) if old.id == entry.id && old.mode == entry.mode => old,
+                        _ => Tracked {
+                            mode: entry.mode,
+                            id: entry.id,
+                            stat: None,
+                            state: EntryState::Clean,
+                        },
+                    };
+                    tracked.insert(path, record);
+                }
+            }
+        }
+        Ok(Cache {
+            tree,
+            sparse,
+            token: None,
+            scanned_at: 0,
+            tracked,
+            untracked: BTreeSet::new(),
+        })
+    }
+
+    fn has_tracked_under(&self, dir: &str) -> bool {
+        let prefix = format!("{}/", dir);
+        self.tracked.range(prefix.clone()..).next().is_some_and(|(path, _)| path.starts_with(&prefix))
+    }
+
+    fn entries(&self) -> Vec<StatusEntry> {
+        let mut entries: Vec<StatusEntry> = self
+            .tracked
+            .iter()
+            .filter_map(|(path, entry)| {
+                let status = match entry.state {
+                    EntryState::Clean => return None,
+                    EntryState::Modified => FileStatus::Modified,
+                    EntryState::Deleted => FileStatus::Deleted,
+                };
+                Some(StatusEntry { path: path.clone(), status })
+            })
+            .chain(self.untracked.iter().map(|path| StatusEntry {
+                path: path.clone(),
+                status: FileStatus::Untracked,
+            }))
+            .collect();
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        entries
+    }
+
+    /// 读取缓存，不存在时返回 None
+    fn load(path: &Path) -> MonoResult<Option<Cache>> {
+        let data = match std::fs::read(path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        Cache::decode(&data).map(Some).ok_or_else(|| MonoError::storage(format!("corrupt status cache {}", path.display())))
+    }
+
+    fn save(&self, path: &PathBuf) -> MonoResult<()> {
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, self.encode())?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(CACHE_MAGIC);
+        out.extend_from_slice(&CACHE_VERSION.to_be_bytes());
+        put_bytes(&mut out, self.tree.as_bytes());
+        put_bytes(&mut out, self.sparse.as_bytes());
+        put_bytes(&mut out, self.token.as_deref().unwrap_or_default().as_bytes());
+        out.extend_from_slice(&self.scanned_at.to_be_bytes());
+        out.extend_from_slice(&(self.tracked.len() as u32).to_be_bytes());
+        for (path, entry) in &self.tracked {
+            put_bytes(&mut out, path.as_bytes());
+            out.extend_from_slice(&entry.mode.0.to_be_bytes());
+            put_bytes(&mut out, entry.id.as_bytes());
+            out.push(entry.state as u8);
+            match entry.stat {
+                Some(stat) => {
+                    out.push(1);
+                    out.extend_from_slice(&stat.size.to_be_bytes());
+                    out.extend_from_slice(&stat.mtime.to_be_bytes());
+                    out.extend_from_slice(&stat.mtime_nanos.to_be_bytes());
+                }
+                None => out.push(0),
+            }
+        }
+        out.extend_from_slice(&(self.untracked.len() as u32).to_be_bytes());
+        for path in &self.untracked {
+            put_bytes(&mut out, path.as_bytes());
+        }
+        out
+    }
+
+    fn decode(data: &[u8]) -> Option<Cache> {
+        let mut reader = Reader { data };
+        if reader.take(4)? != CACHE_MAGIC || reader.u32()? != CACHE_VERSION {
+            return None;
+        }
+        let tree = ObjectId::from_bytes(reader.bytes()?).ok()?;
+        let sparse = reader.string()?;
+        let token = Some(reader.string()?).filter(|token| !token.is_empty());
+        let scanned_at = reader.u64()? as i64;
+        let mut tracked = BTreeMap::new();
+        for _ in 0..reader.u32()? {
+            let path = reader.string()?;
+            let mode = FileMode(reader.u32()?);
+            let id = ObjectId::from_bytes(reader.bytes()?).ok()?;
+            let state = match reader.take(1)?[0] {
+                0 => EntryState::Clean,
+                1 => EntryState::Modified,
+                2 => EntryState::Deleted,
+                _ => return None,
+            };
+            let stat = match reader.take(1)?[0] {
+                0 => None,
+                _ => Some(Stat {
+                    size: reader.u64()?,
+                    mtime: reader.u64()? as i64,
+                    mtime_nanos: reader.u32()?,
+                }),
+            };
+            tracked.insert(path, Tracked { mode, id, stat, state });
+        }
+        let mut untracked = BTreeSet::new();
+        for _ in 0..reader.u32()? {
+            untracked.insert(reader.string()?);
+        }
+        reader.data.is_empty().then_some(Cache {
+            tree,
+            sparse,
+            token,
+            scanned_at,
+            tracked,
+            untracked,
+        })
+    }
+}
+
+/// 写入以 4 字节长度开头的字节串
+fn put_bytes(out: &mut Vec<u8>, data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    out.extend_from_slice(data);
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        if self.data.len() < n {
+            return None;
+        }
+        let (head, rest) = self.data.split_at(n);
+        self.data = rest;
+        Some(head)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_be_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_be_bytes(self.take(8)?.try_into().ok()?))
+    }
+
+    fn bytes(&mut self) -> Option<&'a [u8]> {
+        let len = self.u32()? as usize;
+        self.take(len)
+    }
+
+    fn string(&mut self) -> Option<String> {
+        String::from_utf8(self.bytes()?.to_vec()).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sparse;
+    use crate::test_utils::{commit_files, init_repo};
+    use fsmonitor::MonitorResult;
+    use std::cell::RefCell;
+
+    /// 按预先设定的结果回答查询的监视程序
+    struct FakeMonitor {
+        changed: RefCell<Option<Vec<String>>>,
+        tokens: RefCell<Vec<Option<String>>>,
+    }
+
+    impl FsMonitor for FakeMonitor {
+        fn query(&self, _root: &Path, token: Option<&str>) -> MonoResult<MonitorResult> {
+            self.tokens.borrow_mut().push(token.map(str::to_string));
+            Ok(MonitorResult {
+                token: format!("t{}", self.tokens.borrow().len()),
+                changed: token.and_then(|_| self.changed.borrow_mut().take()),
+            })
+        }
+    }
+
+    fn summary(report: &StatusReport) -> Vec<String> {
+        report.entries.iter().map(|entry| format!("{} {}", entry.status.code(), entry.path)).collect()
+    }
+
+    /// 测试完整扫描识别修改、删除与未跟踪的文件，未变化的文件不重新读取内容
+    #[test]
+    fn test_status() {
+        let (dir, repo) = init_repo();
+        let commit = commit_files(&repo, &[("a.txt", b"a"), ("src/lib.rs", b"lib"), ("src/main.rs", b"main")], &[], "init");
+        repo.refs().write("refs/heads/main", &commit).unwrap();
+        sparse::apply(&repo).unwrap();
+        let root = dir.path();
+
+        let report = status(&repo, None).unwrap();
+        assert!(report.entries.is_empty(), "{:?}", report.entries);
+        assert_eq!(report.hashed, 3);
+
+        std::fs::write(root.join("src/lib.rs"), b"changed").unwrap();
+        std::fs::remove_file(root.join("src/main.rs")).unwrap();
+        std::fs::write(root.join("new.txt"), b"new").unwrap();
+        std::fs::create_dir_all(root.join("build/out")).unwrap();
+        std::fs::write(root.join("build/out/x.o"), b"x").unwrap();
+        let report = status(&repo, None).unwrap();
+        assert_eq!(summary(&report), ["?? build/", "?? new.txt", "M src/lib.rs", "D src/main.rs"]);
+        assert!(!report.fsmonitor);
+
+        std::fs::write(root.join("src/lib.rs"), b"lib").unwrap();
+        let report = status(&repo, None).unwrap();
+        assert_eq!(summary(&report), ["?? build/", "?? new.txt", "D src/main.rs"]);
+    }
+
+    /// 测试配置了监视程序时只检查报告变化的路径
+    #[test]
+    fn test_status_fsmonitor() {
+        let (dir, repo) = init_repo();
+        let commit = commit_files(&repo, &[("a.txt", b"a"), ("src/lib.rs", b"lib")], &[], "init");
+        repo.refs().write("refs/heads/main", &commit).unwrap();
+        sparse::apply(&repo).unwrap();
+        let root = dir.path();
+        let monitor = FakeMonitor {
+            changed: RefCell::new(None),
+            tokens: RefCell::new(Vec::new()),
+        };
+
+        // 首次查询没有令牌，完整扫描
+        let report = status(&repo, Some(&monitor)).unwrap();
+        assert!(!report.fsmonitor && report.entries.is_empty());
+
+        // 监视程序只报告了 src/ 的变化，a.txt 的修改不会被检查
+        std::fs::write(root.join("a.txt"), b"changed").unwrap();
+        std::fs::write(root.join("src/lib.rs"), b"changed").unwrap();
+        std::fs::write(root.join("src/new.rs"), b"new").unwrap();
+        *monitor.changed.borrow_mut() = Some(vec!["src/".to_string()]);
+        let report = status(&repo, Some(&monitor)).unwrap();
+        assert!(report.fsmonitor);
+        assert_eq!(report.hashed, 1);
+        assert_eq!(summary(&report), ["M src/lib.rs", "?? src/new.rs"]);
+        assert_eq!(*monitor.tokens.borrow(), [None, Some("t1".to_string())]);
+
+        *monitor.changed.borrow_mut() = Some(vec!["a.txt".to_string(), "src/new.rs".to_string()]);
+        std::fs::remove_file(root.join("src/new.rs")).unwrap();
+        let report = status(&repo, Some(&monitor)).unwrap();
+        assert_eq!(summary(&report), ["M a.txt", "M src/lib.rs"]);
+
+        // 没有报告变化时直接沿用缓存的结果
+        *monitor.changed.borrow_mut() = Some(Vec::new());
+        let report = status(&repo, Some(&monitor)).unwrap();
+        assert_eq!((report.hashed, summary(&report).len()), (0, 2));
+    }
+}
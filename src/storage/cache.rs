@@ -0,0 +1,449 @@
+//! 对象缓存：在任意后端之前缓存最近读取的对象
+//!
+//! 服务端反复读取相同的提交与树（每次 fetch 都要从引用出发遍历），远程后端上每次读取
+//! 都是一次网络请求。缓存分两层，都按字节数限制大小、淘汰最久未读取的对象：
+//!
+//! - 内存层：进程内，只缓存 git 对象
+//! - 磁盘层：`.mono/cache/` 下的松散对象与 LFS 对象文件，进程重启后仍然有效，
+//!   同一仓库的多个进程共享；最近读取时间记录为文件的修改时间
+//!
+//! git 对象内容不可变，缓存不会过时，只有被删除的对象需要失效：LFS 垃圾回收经
+//! [`ObjectStore::delete_lfs`] 删除对象时同时从磁盘层删除。
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Instant, SystemTime};
+
+use crate::common::MonoResult;
+use crate::lfs::{LfsObject, LfsOid};
+use crate::metrics;
+use crate::object::loose::LooseStore;
+use crate::object::{ObjectFormat, ObjectId, ObjectType, RawObject};
+use crate::pack::index::PackIndex;
+use crate::storage::{ObjectStore, StoredObject};
+
+/// 磁盘缓存相对于 `.mono` 的目录
+pub const CACHE_DIR: &str = "cache";
+
+/// 磁盘层超出容量时淘汰到容量的这一比例，避免每次写入都扫描目录
+const DISK_LOW_WATERMARK: f64 = 0.9;
+
+/// 按最近读取时间淘汰的内存缓存
+struct ObjectCache {
+    capacity: usize,
+    used: usize,
+    /// 递增的读取序号，越小越久未读取
+    clock: u64,
+    order: BTreeMap<u64, ObjectId>,
+    objects: HashMap<ObjectId, (RawObject, u64)>,
+}
+
+impl ObjectCache {
+    fn new(capacity: usize) -> ObjectCache {
+        ObjectCache {
+            capacity,
+            used: 0,
+            clock: 0,
+            order: BTreeMap::new(),
+            objects: HashMap::new(),
+        }
+    }
+
+    /// 读取对象并记为最近使用
+    fn get(&mut self, id: &ObjectId) -> Option<RawObject> {
+        self.clock += 1;
+        let (object, tick) = self.objects.get_mut(id)?;
+        self.order.remove(tick);
+        *tick = self.clock;
+        self.order.insert(self.clock, *id);
+        Some(object.clone())
+    }
+
+    fn insert(&mut self, id: ObjectId, object: RawObject) {
+        if object.data.len() > self.capacity || self.objects.contains_key(&id) {
+            return;
+        }
+        while self.used + object.data.len() > self.capacity {
+            let Some((_, evicted)) = self.order.pop_first() else {
+                break;
+            };
+            if let Some((object, _)) = self.objects.remove(&evicted) {
+                self.used -= object.data.len();
+            }
+        }
+        self.clock += 1;
+        self.used += object.data.len();
+        self.order.insert(self.clock, id);
+        self.objects.insert(id, (object, self.clock));
+    }
+
+    fn remove(&mut self, id: &ObjectId) {
+        if let Some((object, tick)) = self.objects.remove(id) {
+            self.order.remove(&tick);
+            self.used -= object.data.len();
+        }
+    }
+}
+
+/// 磁盘缓存层
+#[derive(Debug)]
+struct DiskCache {
+    dir: PathBuf,
+    objects: LooseStore,
+    capacity: u64,
+    /// 已占用的字节数，第一次写入时扫描目录得到；其他进程写入的文件在下次淘汰时计入
+    used: Mutex<Option<u64>>,
+}
+
+impl DiskCache {
+    fn new(dir: PathBuf, capacity: u64, format: ObjectFormat) -> DiskCache {
+        DiskCache {
+            objects: LooseStore::new(dir.join("objects")).with_format(format),
+            dir,
+            capacity,
+            used: Mutex::new(None),
+        }
+    }
+
+    fn lfs_path(&self, oid: &LfsOid) -> PathBuf {
+        self.dir.join("lfs").join(oid.path())
+    }
+
+    fn read(&self, id: &ObjectId) -> Option<RawObject> {
+        // 缓存文件损坏时当作未命中，随后从后端重新读取并覆盖
+        let object = self.objects.read(id).ok().flatten();
+        if object.is_some() {
+            touch(&self.objects.object_path(id));
+        }
+        metrics::object_cache_lookup("disk", object.is_some());
+        object
+    }
+
+    fn insert(&self, object: &RawObject) {
+        let path = self.objects.object_path(&object.id_in(self.objects.format()));
+        if path.exists() {
+            return;
+        }
+        if let Err(e) = self.objects.write(object.object_type, &object.data) {
+            tracing::warn!(error = %e, "failed to write object cache");
+            return;
+        }
+        self.added(file_size(&path));
+    }
+
+    fn read_lfs(&self, oid: &LfsOid) -> Option<Vec<u8>> {
+        let path = self.lfs_path(oid);
+        let data = std::fs::read(&path).ok();
+        if data.is_some() {
+            touch(&path);
+        }
+        metrics::object_cache_lookup("disk", data.is_some());
+        data
+    }
+
+    fn insert_lfs(&self, oid: &LfsOid, data: &[u8]) {
+        if data.len() as u64 > self.capacity {
+            return;
+        }
+        let path = self.lfs_path(oid);
+        let written = std::fs::create_dir_all(path.parent().expect("lfs cache path has a parent")).and_then(|_| {
+            let tmp = path.with_file_name(format!(".tmp-{}-{}", std::process::id(), oid));
+            std::fs::write(&tmp, data)?;
+            std::fs::rename(&tmp, &path)
+        });
+        match written {
+            Ok(()) => self.added(data.len() as u64),
+            Err(e) => tracing::warn!(error = %e, "failed to write lfs object cache"),
+        }
+    }
+
+    fn remove(&self, id: &ObjectId) {
+        self.remove_file(&self.objects.object_path(id));
+    }
+
+    fn remove_lfs(&self, oid: &LfsOid) {
+        self.remove_file(&self.lfs_path(oid));
+    }
+
+    fn remove_file(&self, path: &Path) {
+        let size = file_size(path);
+        if std::fs::remove_file(path).is_ok() {
+            if let Some(used) = self.lock().as_mut() {
+                *used = used.saturating_sub(size);
+            }
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Option<u64>> {
+        self.used.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// 记录新写入的文件，超出容量时淘汰最久未读取的文件
+    fn added(&self, size: u64) {
+        let mut used = self.lock();
+        let total = match *used {
+            Some(used) => used + size,
+            None => cache_files(&self.dir).iter().map(|(_, size, _)| size).sum(),
+        };
+        *used = Some(if total > self.capacity { self.evict() } else { total });
+    }
+
+    /// 删除最久未读取的文件直到低于低水位，返回剩余的字节数
+    fn evict(&self) -> u64 {
+        let mut files = cache_files(&self.dir);
+        files.sort_by_key(|(_, _, modified)| *modified);
+        let mut total: u64 = files.iter().map(|(_, size, _)| size).sum();
+        let target = (self.capacity as f64 * DISK_LOW_WATERMARK) as u64;
+        for (path, size, _) in files {
+            if total <= target {
+                break;
+            }
+            if std::fs::remove_file(&path).is_ok() {
+                total -= size;
+            }
+        }
+        total
+    }
+}
+
+/// 把文件的修改时间更新为当前时间，作为最近读取时间
+fn touch(path: &Path) {
+    if let Ok(file) = std::fs::File::options().write(true).open(path) {
+        let _ = file.set_modified(SystemTime::now());
+    }
+}
+
+fn file_size(path: &Path) -> u64 {
+    std::fs::metadata(path).map_or(0, |metadata| metadata.len())
+}
+
+/// 缓存目录下的全部文件及其大小与修改时间，忽略写入中的临时文件
+fn cache_files(dir: &Path) -> Vec<(PathBuf, u64, SystemTime)> {
+    let mut files = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            if metadata.is_dir() {
+                pending.push(entry.path());
+            } else if !entry.file_name().to_string_lossy().starts_with(".tmp-") {
+                files.push((entry.path(), metadata.len(), metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH)));
+            }
+        }
+    }
+    files
+}
+
+/// 带对象缓存的存储，其余操作直接交给内部存储
+pub struct CachedStore {
+    inner: Arc<dyn ObjectStore>,
+    cache: Mutex<ObjectCache>,
+    disk: Option<DiskCache>,
+}
+
+impl std::fmt::Debug for CachedStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("CachedStore")
+            .field("inner", &self.inner)
+            .field("capacity", &self.lock().capacity)
+            .field("disk", &self.disk)
+            .finish()
+    }
+}
+
+impl CachedStore {
+    /// 在 `inner` 之前加一层至多 `capacity` 字节的内存缓存
+    pub fn new(inner: Arc<dyn ObjectStore>, capacity: usize) -> CachedStore {
+        CachedStore {
+            inner,
+            cache: Mutex::new(ObjectCache::new(capacity)),
+            disk: None,
+        }
+    }
+
+    /// 在内存缓存之后再加一层保存在 `dir` 下、至多 `capacity` 字节的磁盘缓存
+    pub fn with_disk_cache(mut self, dir: impl Into<PathBuf>, capacity: u64) -> CachedStore {
+        self.disk = Some(DiskCache::new(dir.into(), capacity, self.inner.format()));
+        self
+    }
+
+    fn lock(&self) -> MutexGuard<'_, ObjectCache> {
+        self.cache.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn cached(&self, id: &ObjectId) -> Option<RawObject> {
+        let object = self.lock().get(id);
+        metrics::object_cache_lookup("memory", object.is_some());
+        if object.is_some() {
+            return object;
+        }
+        let object = self.disk.as_ref()?.read(id)?;
+        self.lock().insert(*id, object.clone());
+        Some(object)
+    }
+}
+
+impl ObjectStore for CachedStore {
+    fn format(&self) -> ObjectFormat {
+        self.inner.format()
+    }
+
+    fn contains(&self, id: &ObjectId) -> MonoResult<bool> {
+        if self.lock().objects.contains_key(id) {
+            return Ok(true);
+        }
+        if self.disk.as_ref().is_some_and(|disk| disk.objects.contains(id)) {
+            return Ok(true);
+        }
+        self.inner.contains(id)
+    }
+
+    fn read(&self, id: &ObjectId) -> MonoResult<Option<RawObject>> {
+        if let Some(object) = self.cached(id) {
+            return Ok(Some(object));
+        }
+        let start = Instant::now();
+        let object = self.inner.read(id)?;
+        metrics::observe_object_read(start.elapsed());
+        if let Some(object) = &object {
+            if let Some(disk) = &self.disk {
+                disk.insert(object);
+            }
+            self.lock().insert(*id, object.clone());
+        }
+        Ok(object)
+    }
+
+    fn read_header(&self, id: &ObjectId) -> MonoResult<Option<(ObjectType, usize)>> {
+        if let Some((object, _)) = self.lock().objects.get(id) {
+            return Ok(Some((object.object_type, object.data.len())));
+        }
+        if let Some(header) = self.disk.as_ref().and_then(|disk| disk.objects.read_header(id).ok().flatten()) {
+            return Ok(Some(header));
+        }
+        self.inner.read_header(id)
+    }
+
+    fn write(&self, object_type: ObjectType, data: &[u8]) -> MonoResult<ObjectId> {
+        self.inner.write(object_type, data)
+    }
+
+    fn list(&self) -> MonoResult<Vec<ObjectId>> {
+        self.inner.list()
+    }
+
+    fn list_stored(&self) -> MonoResult<Vec<StoredObject>> {
+        self.inner.list_stored()
+    }
+
+    fn delete(&self, ids: &[ObjectId]) -> MonoResult<()> {
+        let mut cache = self.lock();
+        for id in ids {
+            cache.remove(id);
+            if let Some(disk) = &self.disk {
+                disk.remove(id);
+            }
+        }
+        drop(cache);
+        self.inner.delete(ids)
+    }
+
+    fn write_pack(&self, pack: &[u8]) -> MonoResult<usize> {
+        self.inner.write_pack(pack)
+    }
+
+    fn write_pack_file(&self, path: &Path, index: Option<&PackIndex>) -> MonoResult<usize> {
+        self.inner.write_pack_file(path, index)
+    }
+
+    fn read_lfs(&self, oid: &LfsOid) -> MonoResult<Option<Vec<u8>>> {
+        let Some(disk) = &self.disk else {
+            return self.inner.read_lfs(oid);
+        };
+        if let Some(data) = disk.read_lfs(oid) {
+            return Ok(Some(data));
+        }
+        let data = self.inner.read_lfs(oid)?;
+        if let Some(data) = &data {
+            disk.insert_lfs(oid, data);
+        }
+        Ok(data)
+    }
+
+    fn lfs_size(&self, oid: &LfsOid) -> MonoResult<Option<u64>> {
+        self.inner.lfs_size(oid)
+    }
+
+    fn write_lfs(&self, oid: &LfsOid, data: &[u8]) -> MonoResult<()> {
+        self.inner.write_lfs(oid, data)
+    }
+
+    fn list_lfs(&self) -> MonoResult<Vec<LfsObject>> {
+        self.inner.list_lfs()
+    }
+
+    fn delete_lfs(&self, oid: &LfsOid) -> MonoResult<()> {
+        if let Some(disk) = &self.disk {
+            disk.remove_lfs(oid);
+        }
+        self.inner.delete_lfs(oid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::memory::MemoryStore;
+
+    /// 测试读取经过两层缓存，内存层淘汰最久未读取的对象，磁盘层超出容量时淘汰并在删除 LFS 对象时失效
+    #[test]
+    fn test_cached_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let inner = Arc::new(MemoryStore::new());
+        let store = CachedStore::new(inner.clone(), 10).with_disk_cache(dir.path(), 1 << 20);
+        let a = inner.write(ObjectType::Blob, b"aaaa").unwrap();
+        let b = inner.write(ObjectType::Blob, b"bbbbbb").unwrap();
+        let c = inner.write(ObjectType::Blob, b"cc").unwrap();
+        let large = inner.write(ObjectType::Blob, b"0123456789x").unwrap();
+
+        assert_eq!(store.read(&a).unwrap().unwrap().data, b"aaaa");
+        assert_eq!(store.read(&b).unwrap().unwrap().data, b"bbbbbb");
+        assert_eq!(store.read_header(&b).unwrap(), Some((ObjectType::Blob, 6)));
+        // a 最近被读取过，淘汰的是 b
+        store.read(&a).unwrap();
+        store.read(&c).unwrap();
+        assert!(store.lock().objects.contains_key(&a) && !store.lock().objects.contains_key(&b));
+        assert_eq!(store.lock().used, 6);
+        store.read(&large).unwrap();
+        assert!(!store.lock().objects.contains_key(&large));
+
+        // 新的实例从磁盘层读取，不访问后端
+        let empty = Arc::new(MemoryStore::new());
+        let reopened = CachedStore::new(empty, 10).with_disk_cache(dir.path(), 1 << 20);
+        assert_eq!(reopened.read(&large).unwrap().unwrap().data, b"0123456789x");
+        assert_eq!(reopened.read_header(&b).unwrap(), Some((ObjectType::Blob, 6)));
+
+        let oid = LfsOid::from_hex(&"ab".repeat(32)).unwrap();
+        inner.write_lfs(&oid, &[7; 100]).unwrap();
+        assert_eq!(store.read_lfs(&oid).unwrap().unwrap().len(), 100);
+        assert!(dir.path().join("lfs").join(oid.path()).is_file());
+        store.delete_lfs(&oid).unwrap();
+        assert!(!dir.path().join("lfs").join(oid.path()).exists());
+        assert_eq!(store.read_lfs(&oid).unwrap(), None);
+
+        let small = CachedStore::new(inner.clone(), 0).with_disk_cache(dir.path(), 1);
+        let e = inner.write(ObjectType::Blob, b"e").unwrap();
+        assert_eq!(small.read(&e).unwrap().unwrap().data, b"e");
+        assert!(cache_files(dir.path()).is_empty());
+
+        assert_eq!(store.read(&ObjectId::ZERO).unwrap(), None);
+        let d = store.write(ObjectType::Blob, b"d").unwrap();
+        assert!(store.contains(&d).unwrap() && inner.contains(&d).unwrap());
+    }
+}
@@ -0,0 +1,489 @@
+//! 分块存储（实验性）：大 blob 按内容切块，相似的文件共享相同的块
+//!
+//! 同一个大文件在不同分支上的各个版本通常只有局部不同，整体保存时每个版本都占用完整的空间。
+//! 配置 `[storage.chunking]` 后，[`ChunkedStore`] 位于后端之前：不小于 `min_blob_size` 的 blob
+//! 用 FastCDC 按内容切块，插入或删除数据只影响附近的块，未改动的部分与其他版本共享。
+//!
+//! - 块以 SHA-256 寻址，按松散对象格式保存在 `.mono/chunks/data/` 下，压缩编码与 `[storage] compression` 相同；
+//! - 每个分块的 blob 在 `.mono/chunks/manifests/<前两位>/<其余十六进制位>` 有一份清单，
+//!   首行为 `blob <大小>`，之后每行一个块的 ID 与长度。
+//!
+//! 读取时按清单拼接各块，并校验拼接结果的对象 ID。分块的 blob 不写入后端，也不参与重新打包；
+//! 删除 blob 时删除其清单，不再被任何清单引用、且超过 [`CHUNK_GRACE`] 未写入的块随后被删除。
+//! [`ObjectStore::list_stored`] 中分块 blob 的大小只计清单，共享的块不计入任何一个 blob。
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, SystemTime};
+
+use crate::common::config::ChunkingConfig;
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::lfs::{LfsObject, LfsOid};
+use crate::object::codec::Codec;
+use crate::object::loose::LooseStore;
+use crate::object::{ObjectFormat, ObjectId, ObjectType, RawObject};
+use crate::pack::{decode_pack, PackWriter};
+use crate::storage::{ObjectStore, StoredObject};
+
+/// 分块存储相对于 `.mono` 的目录
+pub const CHUNKS_DIR: &str = "chunks";
+
+/// 不被引用的块至少保留的时长，保护正在写入、清单尚未写出的 blob 的块
+pub const CHUNK_GRACE: Duration = Duration::from_secs(60 * 60);
+
+/// FastCDC 切块参数：最小块长为平均值的 1/4，最大块长为平均值的 4 倍
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Chunker {
+    min: usize,
+    avg: usize,
+    max: usize,
+    /// 未达到平均块长时使用的掩码，位数更多，不容易切断
+    mask_small: u64,
+    /// 超过平均块长后使用的掩码，位数更少，容易切断
+    mask_large: u64,
+}
+
+impl Chunker {
+    /// 平均块长为 `avg` 字节，`avg` 必须是不小于 256 的 2 的幂
+    pub fn new(avg: usize) -> Chunker {
+        assert!(avg.is_power_of_two() && avg >= 256, "average chunk size must be a power of two");
+        let bits = avg.trailing_zeros();
+        // gear 哈希每次左移一位，低位只取决于最近几个字节，掩码取高位
+        let mask = |bits: u32| ((1u64 << bits) - 1) << (64 - bits);
+        Chunker {
+            min: avg / 4,
+            avg,
+            max: avg * 4,
+            mask_small: mask(bits + 2),
+            mask_large: mask(bits - 2),
+        }
+    }
+
+    /// 第一个块的长度
+    pub fn cut(&self, data: &[u8]) -> usize {
+        if data.len() <= self.min {
+            return data.len();
+        }
+        let end = data.len().min(self.max);
+        let normal = end.min(self.avg);
+        let gear = gear();
+        let mut hash = 0u64;
+        for (i, byte) in data.iter().enumerate().take(end).skip(self.min) {
+            hash = (hash << 1).wrapping_add(gear[*byte as usize]);
+            let mask = if i < normal { self.mask_small } else { self.mask_large };
+            if hash & mask == 0 {
+                return i + 1;
+            }
+        }
+        end
+    }
+
+    /// 把 `data` 切为若干块
+    pub fn chunks<'a>(&self, mut data: &'a [u8]) -> Vec<&'a [u8]> {
+        let mut chunks = Vec::with_capacity(data.len() / self.avg + 1);
+        while !data.is_empty() {
+            let (chunk, rest) = data.split_at(self.cut(data));
+            chunks.push(chunk);
+            data = rest;
+        }
+        chunks
+    }
+}
+
+/// FastCDC 的 gear 表，由固定种子的 splitmix64 生成，切块结果在不同进程与版本之间保持一致
+fn gear() -> &'static [u64; 256] {
+    static GEAR: OnceLock<[u64; 256]> = OnceLock::new();
+    GEAR.get_or_init(|| {
+        let mut state = 0x6d6f_6e6f_6364_6300_u64;
+        std::array::from_fn(|_| {
+            state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+            let mut z = state;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+            z ^ (z >> 31)
+        })
+    })
+}
+
+/// 一个分块 blob 的清单
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Manifest {
+    size: usize,
+    chunks: Vec<(ObjectId, usize)>,
+}
+
+impl Manifest {
+    fn encode(&self) -> String {
+        let mut out = format!("blob {}\n", self.size);
+        for (id, len) in &self.chunks {
+            out.push_str(&format!("{} {}\n", id, len));
+        }
+        out
+    }
+
+    fn parse(text: &str) -> Option<Manifest> {
+        let mut lines = text.lines();
+        let size = lines.next()?.strip_prefix("blob ")?.parse().ok()?;
+        let chunks = lines
+            .map(|line| {
+                let (id, len) = line.split_once(' ')?;
+                Some((ObjectId::from_hex(id).ok()?, len.parse().ok()?))
+            })
+            .collect::<Option<Vec<_>>>()?;
+        (chunks.iter().map(|(_, len)| len).sum::<usize>() == size).then_some(Manifest { size, chunks })
+    }
+}
+
+/// 在任意后端之前按块保存大 blob 的对象存储
+#[derive(Debug)]
+pub struct ChunkedStore {
+    inner: Arc<dyn ObjectStore>,
+    dir: PathBuf,
+    chunks: LooseStore,
+    chunker: Chunker,
+    min_blob_size: usize,
+}
+
+impl ChunkedStore {
+    /// 块与清单保存在 `dir` 下，块以 `codec` 压缩
+    pub fn new(inner: Arc<dyn ObjectStore>, dir: impl Into<PathBuf>, config: &ChunkingConfig, codec: Codec) -> ChunkedStore {
+        let dir = dir.into();
+        ChunkedStore {
+            inner,
+            chunks: LooseStore::new(dir.join("data"))
+                .with_format(ObjectFormat::Sha256)
+                .with_codec(codec),
+            dir,
+            chunker: Chunker::new(config.avg_chunk_size as usize),
+            min_blob_size: usize::try_from(config.min_blob_size).unwrap_or(usize::MAX),
+        }
+    }
+
+    fn manifest_path(&self, id: &ObjectId) -> PathBuf {
+        let hex = id.to_hex();
+        self.dir.join("manifests").join(&hex[..2]).join(&hex[2..])
+    }
+
+    fn manifest(&self, id: &ObjectId) -> MonoResult<Option<Manifest>> {
+        let path = self.manifest_path(id);
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        Manifest::parse(&text)
+            .map(Some)
+            .ok_or_else(|| MonoError::storage(format!("corrupt chunk manifest {}", path.display())))
+    }
+
+    /// 全部分块 blob 的 ID
+    fn manifest_ids(&self) -> MonoResult<Vec<ObjectId>> {
+        let mut ids = Vec::new();
+        let entries = match std::fs::read_dir(self.dir.join("manifests")) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(ids),
+            Err(e) => return Err(e.into()),
+        };
+        for entry in entries {
+            let entry = entry?;
+            let prefix = entry.file_name().to_string_lossy().into_owned();
+            if prefix.len() != 2 || !entry.file_type()?.is_dir() {
+                continue;
+            }
+            for manifest in std::fs::read_dir(entry.path())? {
+                let name = manifest?.file_name().to_string_lossy().into_owned();
+                if let Ok(id) = ObjectId::from_hex(&format!("{}{}", prefix, name)) {
+                    ids.push(id);
+                }
+            }
+        }
+        ids.sort();
+        Ok(ids)
+    }
+
+    /// 切块写入 blob，已有的块只刷新写入时间，最后写出清单
+    fn write_chunked(&self, id: &ObjectId, data: &[u8]) -> MonoResult<()> {
+        let mut manifest = Manifest { size: data.len(), chunks: Vec::new() };
+        for chunk in self.chunker.chunks(data) {
+            let chunk_id = ObjectFormat::Sha256.hash_object(ObjectType::Blob, chunk);
+            let path = self.chunks.object_path(&chunk_id);
+            if path.is_file() {
+                touch(&path);
+            } else {
+                self.chunks.write(ObjectType::Blob, chunk)?;
+            }
+            manifest.chunks.push((chunk_id, chunk.len()));
+        }
+        let path = self.manifest_path(id);
+        let parent = path.parent().expect("manifest path always has a parent");
+        std::fs::create_dir_all(parent)?;
+        let tmp = parent.join(format!(".tmp-{}-{}", std::process::id(), &id.to_hex()[2..]));
+        std::fs::write(&tmp, manifest.encode())?;
+        std::fs::rename(&tmp, &path)?;
+        tracing::debug!(%id, size = data.len(), chunks = manifest.chunks.len(), "stored chunked blob");
+        Ok(())
+    }
+
+    /// 按清单拼接 blob 并校验对象 ID
+    fn reassemble(&self, id: &ObjectId, manifest: &Manifest) -> MonoResult<RawObject> {
+        let mut data = Vec::with_capacity(manifest.size);
+        for (chunk_id, len) in &manifest.chunks {
+            let chunk = self
+                .chunks
+                .read(chunk_id)?
+                .ok_or_else(|| MonoError::storage(format!("chunked blob {} is missing chunk {}", id, chunk_id)))?;
+            if chunk.data.len() != *len {
+                return Err(MonoError::storage(format!("chunk {} of blob {} has the wrong size", chunk_id, id)));
+            }
+            data.extend_from_slice(&chunk.data);
+        }
+        let object = RawObject::new(ObjectType::Blob, data);
+        if object.id_in(id.format()) != *id {
+            return Err(MonoError::storage(format!("chunked blob {} does not match its content", id)));
+        }
+        Ok(object)
+    }
+
+    /// 删除不被任何清单引用且超过宽限期的块，返回删除的块数
+    fn sweep(&self) -> MonoResult<usize> {
+        let mut referenced = HashSet::new();
+        for id in self.manifest_ids()? {
+            if let Some(manifest) = self.manifest(&id)? {
+                referenced.extend(manifest.chunks.into_iter().map(|(chunk, _)| chunk));
+            }
+        }
+        let now = SystemTime::now();
+        let mut removed = 0;
+        for chunk in self.chunks.list()? {
+            if referenced.contains(&chunk) {
+                continue;
+            }
+            let path = self.chunks.object_path(&chunk);
+            let modified = std::fs::metadata(&path)?.modified()?;
+            if now.duration_since(modified).unwrap_or_default() < CHUNK_GRACE {
+                continue;
+            }
+            match std::fs::remove_file(&path) {
+                Ok(()) => removed += 1,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(removed)
+    }
+
+    fn chunked(&self, object_type: ObjectType, size: usize) -> bool {
+        object_type == ObjectType::Blob && size >= self.min_blob_size
+    }
+}
+
+fn touch(path: &Path) {
+    if let Ok(file) = std::fs::File::options().write(true).open(path) {
+        let _ = file.set_modified(SystemTime::now());
+    }
+}
+
+impl ObjectStore for ChunkedStore {
+    fn format(&self) -> ObjectFormat {
+        self.inner.format()
+    }
+
+    fn contains(&self, id: &ObjectId) -> MonoResult<bool> {
+        Ok(self.manifest_path(id).is_file() || self.inner.contains(id)?)
+    }
+
+    fn read(&self, id: &ObjectId) -> MonoResult<Option<RawObject>> {
+        match self.manifest(id)? {
+            Some(manifest) => self.reassemble(id, &manifest).map(Some),
+            None => self.inner.read(id),
+        }
+    }
+
+    fn read_header(&self, id: &ObjectId) -> MonoResult<Option<(ObjectType, usize)>> {
+        match self.manifest(id)? {
+            Some(manifest) => Ok(Some((ObjectType::Blob, manifest.size))),
+            None => self.inner.read_header(id),
+        }
+    }
+
+    fn write(&self, object_type: ObjectType, data: &[u8]) -> MonoResult<ObjectId> {
+        if !self.chunked(object_type, data.len()) {
+            return self.inner.write(object_type, data);
+        }
+        let id = self.format().hash_object(object_type, data);
+        if !self.manifest_path(&id).is_file() {
+            self.write_chunked(&id, data)?;
+        }
+        Ok(id)
+    }
+
+    fn list(&self) -> MonoResult<Vec<ObjectId>> {
+        let mut ids = self.inner.list()?;
+        ids.extend(self.manifest_ids()?);
+        ids.sort();
+        ids.dedup();
+        Ok(ids)
+    }
+
+    fn list_stored(&self) -> MonoResult<Vec<StoredObject>> {
+        let mut objects = self.inner.list_stored()?;
+        for id in self.manifest_ids()? {
+            let metadata = std::fs::metadata(self.manifest_path(&id))?;
+            objects.push(StoredObject { id, size: metadata.len(), modified: metadata.modified()? });
+        }
+        objects.sort_by_key(|object| object.id);
+        objects.dedup_by_key(|object| object.id);
+        Ok(objects)
+    }
+
+    fn delete(&self, ids: &[ObjectId]) -> MonoResult<()> {
+        let mut removed = false;
+        for id in ids {
+            match std::fs::remove_file(self.manifest_path(id)) {
+                Ok(()) => removed = true,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        self.inner.delete(ids)?;
+        if removed {
+            let chunks = self.sweep()?;
+            tracing::info!(chunks, "removed unreferenced chunks");
+        }
+        Ok(())
+    }
+
+    /// 大 blob 分块保存，其余对象重新打包后交给后端
+    fn write_pack(&self, pack: &[u8]) -> MonoResult<usize> {
+        let objects = decode_pack(pack, |id| self.read(id))?;
+        if !objects.iter().any(|object| self.chunked(object.object_type, object.data.len())) {
+            return self.inner.write_pack(pack);
+        }
+        let (large, small): (Vec<&RawObject>, Vec<&RawObject>) =
+            objects.iter().partition(|object| self.chunked(object.object_type, object.data.len()));
+        for object in &large {
+            self.write(object.object_type, &object.data)?;
+        }
+        if !small.is_empty() {
+            let mut writer = PackWriter::with_format(Vec::new(), small.len() as u32, self.format())?;
+            for object in &small {
+                writer.write(object.object_type, &object.data)?;
+            }
+            self.inner.write_pack(&writer.finish()?)?;
+        }
+        Ok(objects.len())
+    }
+
+    fn read_lfs(&self, oid: &LfsOid) -> MonoResult<Option<Vec<u8>>> {
+        self.inner.read_lfs(oid)
+    }
+
+    fn lfs_size(&self, oid: &LfsOid) -> MonoResult<Option<u64>> {
+        self.inner.lfs_size(oid)
+    }
+
+    fn write_lfs(&self, oid: &LfsOid, data: &[u8]) -> MonoResult<()> {
+        self.inner.write_lfs(oid, data)
+    }
+
+    fn list_lfs(&self) -> MonoResult<Vec<LfsObject>> {
+        self.inner.list_lfs()
+    }
+
+    fn delete_lfs(&self, oid: &LfsOid) -> MonoResult<()> {
+        self.inner.delete_lfs(oid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pack::encode_pack;
+    use crate::storage::memory::MemoryStore;
+
+    /// 生成不可压缩的伪随机数据
+    fn noise(len: usize, seed: u64) -> Vec<u8> {
+        let mut state = seed | 1;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect()
+    }
+
+    /// 测试切块长度在范围内，插入数据只影响附近的块
+    #[test]
+    fn test_chunker() {
+        let chunker = Chunker::new(1024);
+        let data = noise(64 << 10, 7);
+        let chunks = chunker.chunks(&data);
+        assert_eq!(chunks.concat(), data);
+        assert!(chunks[..chunks.len() - 1].iter().all(|chunk| (256..=4096).contains(&chunk.len())));
+        assert!((32..=128).contains(&chunks.len()), "{} chunks", chunks.len());
+
+        let mut edited = data.clone();
+        edited.splice(30_000..30_000, b"inserted".iter().copied());
+        let before: HashSet<&[u8]> = chunks.into_iter().collect();
+        let after = chunker.chunks(&edited);
+        let changed = after.iter().filter(|chunk| !before.contains(*chunk)).count();
+        assert!(changed <= 3, "{} of {} chunks changed", changed, after.len());
+    }
+
+    /// 测试大 blob 分块保存、相似的版本共享块、读取时拼接，以及删除后清理不再引用的块
+    #[test]
+    fn test_chunked_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let inner = Arc::new(MemoryStore::new());
+        let config = ChunkingConfig { min_blob_size: 4096, avg_chunk_size: 1024 };
+        let store = ChunkedStore::new(inner.clone(), dir.path(), &config, Codec::Zstd);
+
+        let v1 = noise(32 << 10, 11);
+        let mut v2 = v1.clone();
+        v2[20_000..20_010].copy_from_slice(b"0123456789");
+        let small = store.write(ObjectType::Blob, b"small").unwrap();
+        let id1 = store.write(ObjectType::Blob, &v1).unwrap();
+        assert!(inner.contains(&small).unwrap());
+        assert!(!inner.contains(&id1).unwrap());
+        let after_v1 = store.chunks.list().unwrap().len();
+
+        // 以 pack 写入的大 blob 同样分块保存
+        let blob2 = RawObject::new(ObjectType::Blob, v2.clone());
+        assert_eq!(store.write_pack(&encode_pack([blob2.clone()].iter()).unwrap()).unwrap(), 1);
+        let id2 = blob2.id();
+        let added = store.chunks.list().unwrap().len() - after_v1;
+        assert!(added <= 3, "{} new chunks for a 10 byte edit", added);
+
+        assert_eq!(store.read(&id1).unwrap().unwrap().data, v1);
+        assert_eq!(store.read(&id2).unwrap().unwrap().data, v2);
+        assert_eq!(store.read_header(&id2).unwrap(), Some((ObjectType::Blob, v2.len())));
+        assert!(store.contains(&id2).unwrap());
+        assert_eq!(store.list().unwrap().len(), 3);
+        assert_eq!(store.list_stored().unwrap().len(), 3);
+
+        // 新写入的块在宽限期内保留，过期后删除只被已删除 blob 引用的块
+        store.delete(&[id2]).unwrap();
+        assert!(!store.contains(&id2).unwrap());
+        assert_eq!(store.chunks.list().unwrap().len(), after_v1 + added);
+        let old = SystemTime::now() - CHUNK_GRACE * 2;
+        for chunk in store.chunks.list().unwrap() {
+            let file = std::fs::File::options().write(true).open(store.chunks.object_path(&chunk)).unwrap();
+            file.set_modified(old).unwrap();
+        }
+        assert_eq!(store.sweep().unwrap(), added);
+        assert_eq!(store.read(&id1).unwrap().unwrap().data, v1);
+
+        // 块损坏时读取失败而不是返回错误的内容
+        let chunk = store.manifest(&id1).unwrap().unwrap().chunks[0].0;
+        std::fs::write(
+            store.chunks.object_path(&chunk),
+            crate::object::loose::compress(Codec::Zlib, ObjectType::Blob, &vec![0; 256]).unwrap(),
+        )
+        .unwrap();
+        assert!(store.read(&id1).is_err());
+    }
+}
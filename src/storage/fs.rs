@@ -0,0 +1,900 @@
+//! 本地文件系统后端：对象以 git 兼容的格式保存在 `.mono/objects` 下
+//!
+//! 单独写入的对象保存为松散对象；推送收到的 pack 连同索引原样保存在 `objects/pack` 中，
+//! 读取时先查松散对象再查 pack。带 `.keep` 标记的 pack（见 [`FsStore::write_kept_pack`]）不参与增量与几何
+//! 重新打包。新写入的松散对象与重新打包的 pack 按 [`FsStore::with_codec`] 压缩，读取时两种编码都能识别。LFS 对象按 git-lfs 的布局保存在 `.mono/lfs/objects/<ab>/<cd>/<oid>`。
+
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock, RwLock};
+use std::time::SystemTime;
+
+use crate::common::errors::MonoError;
+use crate::common::progress::Progress;
+use crate::common::MonoResult;
+use crate::lfs::{LfsObject, LfsOid};
+use crate::object::codec::Codec;
+use crate::object::loose::LooseStore;
+use crate::object::{ObjectFormat, ObjectId, ObjectType, RawObject};
+use crate::pack::file::PackFile;
+use crate::pack::index::PackIndex;
+use crate::pack::midx::{MultiPackIndex, MIDX_FILE};
+use crate::pack::deltify::{self, DeltaOptions, PackObject};
+use crate::pack::indexer::index_pack_file;
+use crate::pack::{index_pack, PackWriter};
+use crate::storage::{ObjectStore, StoredObject};
+
+/// 多包索引及其覆盖的 pack，pack 在首次访问其中的对象时才打开
+#[derive(Debug)]
+struct Midx {
+    dir: PathBuf,
+    index: MultiPackIndex,
+    packs: Vec<OnceLock<Arc<PackFile>>>,
+}
+
+impl Midx {
+    fn pack(&self, id: u32) -> MonoResult<Arc<PackFile>> {
+        let cell = &self.packs[id as usize];
+        if let Some(pack) = cell.get() {
+            return Ok(pack.clone());
+        }
+        let idx = self.dir.join(&self.index.pack_names()[id as usize]);
+        let pack = Arc::new(PackFile::open(idx.with_extension("pack"))?);
+        Ok(cell.get_or_init(|| pack).clone())
+    }
+}
+
+/// 已加载的 pack 以及扫描时 pack 目录的修改时间
+#[derive(Debug, Clone, Default)]
+struct PackList {
+    midx: Option<Arc<Midx>>,
+    /// 未被多包索引覆盖的 pack
+    files: Vec<Arc<PackFile>>,
+    scanned: Option<SystemTime>,
+}
+
+impl PackList {
+    /// pack 是否被多包索引覆盖
+    fn in_midx(&self, path: &Path) -> bool {
+        let idx = path.with_extension("idx");
+        let idx = idx.file_name().unwrap_or_default().to_string_lossy();
+        self.midx
+            .as_ref()
+            .is_some_and(|midx| midx.index.pack_names().iter().any(|name| *name == idx))
+    }
+
+    /// pack 是否已在列表中或被多包索引覆盖
+    fn contains_pack(&self, path: &Path) -> bool {
+        self.in_midx(path) || self.files.iter().any(|pack| pack.path() == path)
+    }
+
+    /// 查找对象所在的 pack 与偏移，先查多包索引
+    fn locate(&self, id: &ObjectId) -> MonoResult<Option<(Arc<PackFile>, u64)>> {
+        if let Some(midx) = &self.midx {
+            if let Some(entry) = midx.index.find(id) {
+                return Ok(Some((midx.pack(entry.pack)?, entry.offset)));
+            }
+        }
+        Ok(self
+            .files
+            .iter()
+            .find_map(|pack| pack.index().find(id).map(|entry| (pack.clone(), entry.offset))))
+    }
+}
+
+/// [`FsStore::repack_incremental`] 与 [`FsStore::repack_geometric`] 的结果
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RepackStats {
+    /// 打包的松散对象数
+    pub loose: usize,
+    /// 合并的 pack 数
+    pub packs: usize,
+    /// 新 pack 中的对象数
+    pub objects: usize,
+}
+
+/// 基于本地目录的对象存储
+#[derive(Debug)]
+pub struct FsStore {
+    loose: LooseStore,
+    format: ObjectFormat,
+    codec: Codec,
+    packs: RwLock<Option<PackList>>,
+}
+
+impl FsStore {
+    /// 以 `objects` 目录创建 SHA-1 格式的存储
+    pub fn new(dir: impl Into<PathBuf>) -> FsStore {
+        FsStore {
+            loose: LooseStore::new(dir),
+            format: ObjectFormat::Sha1,
+            codec: Codec::Zlib,
+            packs: RwLock::new(None),
+        }
+    }
+
+    /// 使用 `format` 格式计算对象 ID 与 pack 校验和
+    pub fn with_format(mut self, format: ObjectFormat) -> FsStore {
+        self.loose = self.loose.with_format(format);
+        self.format = format;
+        self
+    }
+
+    /// 以 `codec` 压缩新写入的松散对象与重新打包的 pack
+    pub fn with_codec(mut self, codec: Codec) -> FsStore {
+        self.loose = self.loose.with_codec(codec);
+        self.codec = codec;
+        self
+    }
+
+    /// 存储根目录
+    pub fn dir(&self) -> &Path {
+        self.loose.dir()
+    }
+
+    /// 保存 pack 文件的目录
+    pub fn pack_dir(&self) -> PathBuf {
+        self.dir().join("pack")
+    }
+
+    /// 与 [`ObjectStore::write_pack`] 相同，但 thin pack 的基对象由 `base` 读取，
+    /// 例如推送的隔离区从仓库的主存储读取基对象
+    pub fn write_pack_with<F>(&self, pack: &[u8], base: F) -> MonoResult<usize>
+    where
+        F: FnMut(&ObjectId) -> MonoResult<Option<RawObject>>,
+    {
+        let indexed = index_pack(pack, base)?;
+        let count = indexed.objects.len();
+        if count == 0 {
+            return Ok(0);
+        }
+        if indexed.index.format() != self.format {
+            return Err(MonoError::protocol(format!(
+                "pack uses the {} object format, repository uses {}",
+                indexed.index.format(),
+                self.format
+            )));
+        }
+        let (data, index) = if indexed.thin {
+            let mut writer = PackWriter::with_codec(Vec::new(), count as u32, self.format, self.codec)?;
+            for object in &indexed.objects {
+                writer.write(object.object_type, &object.data)?;
+            }
+            let (data, index) = writer.finish_indexed()?;
+            (Cow::Owned(data), index)
+        } else {
+            (Cow::Borrowed(pack), indexed.index)
+        };
+
+        let path = self.install_pack(&data, &index)?;
+        tracing::info!(path = %path.display(), objects = count, thin = indexed.thin, "stored pack");
+        Ok(count)
+    }
+
+    /// 与 [`ObjectStore::write_pack_file`] 相同，但 thin pack 的基对象由 `base` 读取
+    ///
+    /// 没有给出 `index` 时直接在磁盘上建立索引（见 [`crate::pack::indexer`]），pack 文件随后被移入 pack 目录，
+    /// 整个过程不把 pack 读入内存。
+    pub fn write_pack_file_with<F>(&self, path: &Path, index: Option<&PackIndex>, base: F) -> MonoResult<usize>
+    where
+        F: FnMut(&ObjectId) -> MonoResult<Option<RawObject>>,
+    {
+        let (index, thin) = match index {
+            Some(index) => (Cow::Borrowed(index), false),
+            None => {
+                let indexed = index_pack_file(path, self.format, base)?;
+                (Cow::Owned(indexed.index), indexed.thin)
+            }
+        };
+        if index.is_empty() {
+            return Ok(0);
+        }
+        if index.format() != self.format {
+            return Err(MonoError::protocol(format!(
+                "pack uses the {} object format, repository uses {}",
+                index.format(),
+                self.format
+            )));
+        }
+        let path = self.install_pack_file(path, &index)?;
+        tracing::info!(path = %path.display(), objects = index.len(), thin, "stored pack file");
+        Ok(index.len())
+    }
+
+    /// 保存 LFS 对象的目录，与 `objects` 目录同级
+    pub fn lfs_dir(&self) -> PathBuf {
+        self.dir().with_file_name("lfs").join("objects")
+    }
+
+    /// 全部 pack，包括多包索引覆盖的 pack
+    pub fn packs(&self) -> MonoResult<Vec<Arc<PackFile>>> {
+        let list = self.pack_list()?;
+        let mut packs = Vec::new();
+        if let Some(midx) = &list.midx {
+            for id in 0..midx.index.pack_names().len() {
+                packs.push(midx.pack(id as u32)?);
+            }
+        }
+        packs.extend(list.files);
+        Ok(packs)
+    }
+
+    /// 为 pack 目录中的全部 pack 写入多包索引，返回写入的索引；没有 pack 时删除已有的多包索引
+    ///
+    /// 同一对象出现在多个 pack 中时优先使用较新的 pack。
+    pub fn write_multi_pack_index(&self) -> MonoResult<Option<MultiPackIndex>> {
+        if self.format != ObjectFormat::Sha1 {
+            return Err(MonoError::unavailable(format!(
+                "multi-pack-index is not supported for the {} object format",
+                self.format
+            )));
+        }
+        let dir = self.pack_dir();
+        let path = dir.join(MIDX_FILE);
+        let mut packs = Vec::new();
+        for (idx, mtime) in list_packs(&dir)? {
+            let name = idx.file_name().expect("pack index has a file name").to_string_lossy().into_owned();
+            let index = PackIndex::parse(&std::fs::read(&idx)?).map_err(|e| e.context(idx.display().to_string()))?;
+            packs.push((mtime, name, index));
+        }
+        if packs.is_empty() {
+            match std::fs::remove_file(&path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+                _ => return Ok(None),
+            }
+        }
+        packs.sort_by_key(|(mtime, _, _)| std::cmp::Reverse(*mtime));
+        let midx = MultiPackIndex::new(packs.iter().map(|(_, name, index)| (name.clone(), index)));
+        write_atomic(&path, &midx.encode())?;
+        // 下次访问时重新扫描，改用新的多包索引
+        *self.packs.write().unwrap_or_else(|e| e.into_inner()) = None;
+        tracing::info!(packs = packs.len(), objects = midx.len(), "wrote multi-pack-index");
+        Ok(Some(midx))
+    }
+
+    /// 写入带 `.keep` 标记的 pack，再从其他 pack 与松散对象中删除其中的对象，返回 pack 的路径
+    ///
+    /// 用于按特定方式 delta 压缩的 pack（见 [`crate::binary_delta`]），增量与几何重新打包不会合并它；
+    /// 垃圾回收删除其中的对象时仍会重写它。
+    pub fn write_kept_pack(&self, data: &[u8], index: &PackIndex) -> MonoResult<PathBuf> {
+        let path = self.install_pack(data, index)?;
+        write_atomic(&path.with_extension("keep"), b"")?;
+        let moved: HashSet<ObjectId> = index.entries().iter().map(|entry| entry.id).collect();
+        let loose: Vec<ObjectId> = self.loose.list()?.into_iter().filter(|id| moved.contains(id)).collect();
+        let mut packs = Vec::new();
+        for (idx, _) in list_packs(&self.pack_dir())? {
+            if idx.with_extension("pack") == path {
+                continue;
+            }
+            let other = PackIndex::parse(&std::fs::read(&idx)?).map_err(|e| e.context(idx.display().to_string()))?;
+            if other.entries().iter().any(|entry| moved.contains(&entry.id)) {
+                packs.push(idx);
+            }
+        }
+        if !loose.is_empty() || !packs.is_empty() {
+            self.repack(&loose, &packs, &moved)?;
+        }
+        Ok(path)
+    }
+
+    /// 增量重新打包：把松散对象与最小的若干 pack 合并为一个新 pack，使 pack 数不超过 `max_packs`
+    ///
+    /// 较大的 pack 保持不动，每次只重写少量数据。新 pack 写入后才删除被合并的 pack 与松散对象，
+    /// 已有多包索引时随后重新生成。
+    pub fn repack_incremental(&self, max_packs: usize) -> MonoResult<RepackStats> {
+        let loose = self.loose.list()?;
+        let mut packs = Vec::new();
+        for (idx, _) in list_packs(&self.pack_dir())? {
+            if !is_kept(&idx) {
+                packs.push((std::fs::metadata(idx.with_extension("pack"))?.len(), idx));
+            }
+        }
+        if loose.is_empty() && packs.len() <= max_packs {
+            return Ok(RepackStats::default());
+        }
+        // 新 pack 占一个名额
+        packs.sort();
+        packs.truncate(packs.len().saturating_sub(max_packs.max(1) - 1));
+        let packs: Vec<PathBuf> = packs.into_iter().map(|(_, idx)| idx).collect();
+        self.repack(&loose, &packs, &HashSet::new())
+    }
+
+    /// 几何重新打包：合并松散对象与较小的 pack，使按对象数排序后每个 pack 至少是前一个的 `factor` 倍
+    ///
+    /// 与 git 的 `repack --geometric` 相同：从最大的 pack 往下找到第一个不满足几何级数的位置，
+    /// 把它之下的 pack 连同松散对象合并，合并结果不小于上一级的 `1/factor` 时继续向上合并。
+    /// 稳态下每次只重写最小的几个 pack，总写入量与新增对象数成正比，不需要全量重新打包。
+    pub fn repack_geometric(&self, factor: u32) -> MonoResult<RepackStats> {
+        if factor < 2 {
+            return Err(MonoError::usage("geometric factor must be at least 2"));
+        }
+        let factor = u64::from(factor);
+        let loose = self.loose.list()?;
+        let mut packs = Vec::new();
+        for (idx, _) in list_packs(&self.pack_dir())? {
+            if is_kept(&idx) {
+                continue;
+            }
+            let index = PackIndex::parse(&std::fs::read(&idx)?).map_err(|e| e.context(idx.display().to_string()))?;
+            packs.push((index.len() as u64, idx));
+        }
+        packs.sort();
+
+        let mut split = 0;
+        for i in (1..packs.len()).rev() {
+            if packs[i].0 < factor * packs[i - 1].0 {
+                split = i;
+                break;
+            }
+        }
+        let mut total = loose.len() as u64 + packs[..split].iter().map(|(count, _)| count).sum::<u64>();
+        while split < packs.len() && total * factor > packs[split].0 {
+            total += packs[split].0;
+            split += 1;
+        }
+        if loose.is_empty() && split <= 1 {
+            return Ok(RepackStats::default());
+        }
+        let packs: Vec<PathBuf> = packs.into_iter().take(split).map(|(_, idx)| idx).collect();
+        self.repack(&loose, &packs, &HashSet::new())
+    }
+
+    /// 把松散对象与 `packs`（索引路径）中的对象写入一个新 pack，随后删除它们
+    ///
+    /// `deleted` 中的对象不写入新 pack，用于垃圾回收；没有剩余对象时不生成新 pack。
+    fn repack(&self, loose: &[ObjectId], packs: &[PathBuf], deleted: &HashSet<ObjectId>) -> MonoResult<RepackStats> {
+        let mut ids = loose.to_vec();
+        for idx in packs {
+            let index = PackIndex::parse(&std::fs::read(idx)?).map_err(|e| e.context(idx.display().to_string()))?;
+            ids.extend(index.entries().iter().map(|entry| entry.id));
+        }
+        ids.sort();
+        ids.dedup();
+        ids.retain(|id| !deleted.contains(id));
+
+        let mut objects = Vec::with_capacity(ids.len());
+        for id in ids {
+            let (object_type, size) = self
+                .read_header(&id)?
+                .ok_or_else(|| MonoError::storage(format!("object {} disappeared during repack", id)))?;
+            objects.push(PackObject { id, object_type, name_hash: 0, size });
+        }
+        let mut written = 0;
+        let mut path = None;
+        if !objects.is_empty() {
+            let progress = Progress::new("Writing objects", objects.len() as u64);
+            let mut reader = |id: &ObjectId| {
+                let object = self.read(id)?.ok_or_else(|| MonoError::not_found(format!("object {}", id)))?;
+                progress.inc(1);
+                progress.add_bytes(object.data.len() as u64);
+                Ok(object)
+            };
+            let mut writer = PackWriter::with_codec(Vec::new(), objects.len() as u32, self.format, self.codec)?;
+            written = deltify::write_objects(&mut writer, objects, &mut reader, &DeltaOptions::default())?.objects;
+            progress.finish();
+            let (data, index) = writer.finish_indexed()?;
+            path = Some(self.install_pack(&data, &index)?);
+        }
+
+        for idx in packs {
+            if path.as_ref() != Some(&idx.with_extension("pack")) {
+                std::fs::remove_file(idx)?;
+                std::fs::remove_file(idx.with_extension("pack"))?;
+                if is_kept(idx) {
+                    std::fs::remove_file(idx.with_extension("keep"))?;
+                }
+            }
+        }
+        for id in loose {
+            let object = self.loose.object_path(id);
+            std::fs::remove_file(&object)?;
+            // 扇出目录为空时顺便删除，非空时失败无妨
+            let _ = std::fs::remove_dir(object.parent().expect("loose object has a parent"));
+        }
+        if self.pack_dir().join(MIDX_FILE).is_file() {
+            self.write_multi_pack_index()?;
+        }
+        *self.packs.write().unwrap_or_else(|e| e.into_inner()) = None;
+        tracing::info!(?path, loose = loose.len(), packs = packs.len(), objects = written, "repacked");
+        Ok(RepackStats {
+            loose: loose.len(),
+            packs: packs.len(),
+            objects: written,
+        })
+    }
+
+    /// 将 pack 与索引写入 pack 目录并加入 pack 列表，返回 pack 的路径
+    fn install_pack(&self, data: &[u8], index: &PackIndex) -> MonoResult<PathBuf> {
+        let dir = self.pack_dir();
+        std::fs::create_dir_all(&dir)?;
+        let path = dir.join(format!("pack-{}.pack", index.pack_checksum()));
+        write_atomic(&path, data)?;
+        write_atomic(&path.with_extension("idx"), &index.encode())?;
+        self.add_pack(&path)?;
+        Ok(path)
+    }
+
+    /// 把 `source` 处的 pack 文件移入 pack 目录并写入索引，返回 pack 的路径；不在同一文件系统时复制
+    fn install_pack_file(&self, source: &Path, index: &PackIndex) -> MonoResult<PathBuf> {
+        let dir = self.pack_dir();
+        std::fs::create_dir_all(&dir)?;
+        let path = dir.join(format!("pack-{}.pack", index.pack_checksum()));
+        if std::fs::rename(source, &path).is_err() {
+            let tmp = dir.join(format!(".tmp-{}-pack-{}.pack", std::process::id(), index.pack_checksum()));
+            std::fs::copy(source, &tmp)?;
+            std::fs::rename(&tmp, &path)?;
+            std::fs::remove_file(source)?;
+        }
+        write_atomic(&path.with_extension("idx"), &index.encode())?;
+        self.add_pack(&path)?;
+        Ok(path)
+    }
+
+    /// 把已写好的 pack 加入 pack 列表
+    fn add_pack(&self, path: &Path) -> MonoResult<()> {
+        let file = Arc::new(PackFile::open(path)?);
+        self.pack_list()?;
+        let mut packs = self.packs.write().unwrap_or_else(|e| e.into_inner());
+        let list = packs.get_or_insert_with(PackList::default);
+        if !list.contains_pack(path) {
+            list.files.push(file);
+        }
+        Ok(())
+    }
+
+    /// 当前的 pack 列表，首次调用时扫描 pack 目录
+    fn pack_list(&self) -> MonoResult<PackList> {
+        if let Some(list) = self.packs.read().unwrap_or_else(|e| e.into_inner()).as_ref() {
+            return Ok(list.clone());
+        }
+        self.rescan()?;
+        self.pack_list()
+    }
+
+    /// pack 目录自上次扫描后有变化时重新扫描，加入其他进程新写入的 pack 与多包索引，返回是否有变化
+    #[tracing::instrument(level = "debug", skip_all)]
+    fn rescan(&self) -> MonoResult<bool> {
+        let dir = self.pack_dir();
+        let mtime = match std::fs::metadata(&dir) {
+            Ok(metadata) => Some(metadata.modified()?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+        let mut packs = self.packs.write().unwrap_or_else(|e| e.into_inner());
+        let list = packs.get_or_insert_with(PackList::default);
+        if list.scanned.is_some() && list.scanned == mtime {
+            return Ok(false);
+        }
+        list.scanned = mtime;
+        if mtime.is_none() {
+            return Ok(false);
+        }
+
+        let midx_path = dir.join(MIDX_FILE);
+        if midx_path.is_file() {
+            match std::fs::read(&midx_path).map_err(Into::into).and_then(|data| MultiPackIndex::parse(&data)) {
+                Ok(index) if list.midx.as_ref().is_none_or(|midx| midx.index != index) => {
+                    list.midx = Some(Arc::new(Midx {
+                        dir: dir.clone(),
+                        packs: index.pack_names().iter().map(|_| OnceLock::new()).collect(),
+                        index,
+                    }));
+                }
+                Ok(_) => {}
+                Err(e) => tracing::warn!(path = %midx_path.display(), error = %e, "ignoring unreadable multi-pack-index"),
+            }
+        }
+        let mut files = Vec::new();
+        for (idx, _) in list_packs(&dir)? {
+            let path = idx.with_extension("pack");
+            if list.in_midx(&path) {
+                continue;
+            }
+            if let Some(pack) = list.files.iter().find(|pack| pack.path() == path) {
+                files.push(pack.clone());
+                continue;
+            }
+            match PackFile::open(&path) {
+                Ok(pack) => files.push(Arc::new(pack)),
+                Err(e) => tracing::warn!(path = %path.display(), error = %e, "skipping unreadable pack"),
+            }
+        }
+        list.files = files;
+        Ok(true)
+    }
+
+    /// 查找对象所在的 pack 与偏移，找不到时重新扫描一次 pack 目录
+    fn locate(&self, id: &ObjectId) -> MonoResult<Option<(Arc<PackFile>, u64)>> {
+        if let Some(found) = self.pack_list()?.locate(id)? {
+            return Ok(Some(found));
+        }
+        if self.rescan()? {
+            return self.pack_list()?.locate(id);
+        }
+        Ok(None)
+    }
+}
+
+/// pack 目录中已写完（索引存在）的 pack 的索引路径与修改时间
+pub(crate) fn list_packs(dir: &Path) -> MonoResult<Vec<(PathBuf, SystemTime)>> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut packs = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        // 索引最后写入，没有索引的 pack 还未写完
+        if path.extension().is_none_or(|ext| ext != "pack") {
+            continue;
+        }
+        let idx = path.with_extension("idx");
+        if let Ok(metadata) = std::fs::metadata(&idx) {
+            packs.push((idx, metadata.modified()?));
+        }
+    }
+    packs.sort();
+    Ok(packs)
+}
+
+/// pack 是否带有 `.keep` 标记
+fn is_kept(idx: &Path) -> bool {
+    idx.with_extension("keep").is_file()
+}
+
+/// 目录中的条目路径，按名称排序；目录不存在时返回空列表
+fn read_dir_sorted(dir: &Path) -> MonoResult<Vec<PathBuf>> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if matches!(e.kind(), std::io::ErrorKind::NotFound | std::io::ErrorKind::NotADirectory) => {
+            return Ok(Vec::new())
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let mut paths = entries.map(|entry| entry.map(|entry| entry.path())).collect::<Result<Vec<_>, _>>()?;
+    paths.sort();
+    Ok(paths)
+}
+
+/// 先写临时文件再重命名，避免并发读到不完整的文件
+fn write_atomic(path: &Path, data: &[u8]) -> MonoResult<()> {
+    let name = path.file_name().expect("path has a file name").to_string_lossy();
+    let tmp = path.with_file_name(format!(".tmp-{}-{}", std::process::id(), name));
+    std::fs::write(&tmp, data)?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+impl ObjectStore for FsStore {
+    fn format(&self) -> ObjectFormat {
+        self.format
+    }
+
+    fn contains(&self, id: &ObjectId) -> MonoResult<bool> {
+        Ok(self.loose.contains(id) || self.locate(id)?.is_some())
+    }
+
+    fn read(&self, id: &ObjectId) -> MonoResult<Option<RawObject>> {
+        if let Some(object) = self.loose.read(id)? {
+            return Ok(Some(object));
+        }
+        match self.locate(id)? {
+            Some((pack, offset)) => pack.read_at(offset).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn read_header(&self, id: &ObjectId) -> MonoResult<Option<(ObjectType, usize)>> {
+        if let Some(header) = self.loose.read_header(id)? {
+            return Ok(Some(header));
+        }
+        match self.locate(id)? {
+            Some((pack, offset)) => pack.read_header_at(offset).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn write(&self, object_type: ObjectType, data: &[u8]) -> MonoResult<ObjectId> {
+        self.loose.write(object_type, data)
+    }
+
+    fn list(&self) -> MonoResult<Vec<ObjectId>> {
+        let mut ids = self.loose.list()?;
+        let list = self.pack_list()?;
+        if let Some(midx) = &list.midx {
+            ids.extend(midx.index.entries().iter().map(|entry| entry.id));
+        }
+        for pack in &list.files {
+            ids.extend(pack.index().entries().iter().map(|entry| entry.id));
+        }
+        ids.sort();
+        ids.dedup();
+        Ok(ids)
+    }
+
+    /// 松散对象取文件的大小与修改时间；pack 中的对象以索引中相邻偏移之差作为大小，写入时间取 pack 的修改时间
+    fn list_stored(&self) -> MonoResult<Vec<StoredObject>> {
+        let mut objects = Vec::new();
+        for id in self.loose.list()? {
+            let metadata = std::fs::metadata(self.loose.object_path(&id))?;
+            objects.push(StoredObject {
+                id,
+                size: metadata.len(),
+                modified: metadata.modified()?,
+            });
+        }
+        for (idx, _) in list_packs(&self.pack_dir())? {
+            let metadata = std::fs::metadata(idx.with_extension("pack"))?;
+            let modified = metadata.modified()?;
+            let index = PackIndex::parse(&std::fs::read(&idx)?).map_err(|e| e.context(idx.display().to_string()))?;
+            let mut entries: Vec<(u64, ObjectId)> = index.entries().iter().map(|entry| (entry.offset, entry.id)).collect();
+            entries.sort();
+            // 最后一个对象之后是 20 字节的校验和
+            let mut end = metadata.len().saturating_sub(20);
+            for (offset, id) in entries.into_iter().rev() {
+                objects.push(StoredObject {
+                    id,
+                    size: end.saturating_sub(offset),
+                    modified,
+                });
+                end = offset;
+            }
+        }
+        // 同一对象可能既是松散对象又在多个 pack 中，保留最近写入的一份
+        objects.sort_by_key(|object| (object.id, std::cmp::Reverse(object.modified)));
+        objects.dedup_by_key(|object| object.id);
+        Ok(objects)
+    }
+
+    /// 删除松散对象文件，并把包含被删除对象的 pack 重写为只含其余对象的新 pack
+    fn delete(&self, ids: &[ObjectId]) -> MonoResult<()> {
+        let deleted: HashSet<ObjectId> = ids.iter().copied().collect();
+        let loose: Vec<ObjectId> = ids.iter().filter(|id| self.loose.contains(id)).copied().collect();
+        let mut packs = Vec::new();
+        for (idx, _) in list_packs(&self.pack_dir())? {
+            let index = PackIndex::parse(&std::fs::read(&idx)?).map_err(|e| e.context(idx.display().to_string()))?;
+            if index.entries().iter().any(|entry| deleted.contains(&entry.id)) {
+                packs.push(idx);
+            }
+        }
+        if loose.is_empty() && packs.is_empty() {
+            return Ok(());
+        }
+        self.repack(&loose, &packs, &deleted)?;
+        Ok(())
+    }
+
+    /// 将 pack 与生成的索引写入 pack 目录；thin pack 依赖本地的基对象，先重新编码为自包含的 pack
+    #[tracing::instrument(skip_all, fields(bytes = pack.len()))]
+    fn write_pack(&self, pack: &[u8]) -> MonoResult<usize> {
+        self.write_pack_with(pack, |id| self.read(id))
+    }
+
+    fn write_pack_file(&self, path: &Path, index: Option<&PackIndex>) -> MonoResult<usize> {
+        self.write_pack_file_with(path, index, |id| self.read(id))
+    }
+
+    fn read_lfs(&self, oid: &LfsOid) -> MonoResult<Option<Vec<u8>>> {
+        match std::fs::read(self.lfs_dir().join(oid.path())) {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn lfs_size(&self, oid: &LfsOid) -> MonoResult<Option<u64>> {
+        match std::fs::metadata(self.lfs_dir().join(oid.path())) {
+            Ok(metadata) => Ok(Some(metadata.len())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn write_lfs(&self, oid: &LfsOid, data: &[u8]) -> MonoResult<()> {
+        let path = self.lfs_dir().join(oid.path());
+        std::fs::create_dir_all(path.parent().expect("lfs object path has a parent"))?;
+        write_atomic(&path, data)
+    }
+
+    /// 遍历 `lfs/objects/<ab>/<cd>/`，忽略不符合布局的文件（例如写入中断留下的临时文件）
+    fn list_lfs(&self) -> MonoResult<Vec<LfsObject>> {
+        let mut objects = Vec::new();
+        for first in read_dir_sorted(&self.lfs_dir())? {
+            for second in read_dir_sorted(&first)? {
+                for path in read_dir_sorted(&second)? {
+                    let Some(oid) = path.file_name().and_then(|name| LfsOid::from_hex(&name.to_string_lossy()).ok())
+                    else {
+                        continue;
+                    };
+                    if self.lfs_dir().join(oid.path()) != path {
+                        continue;
+                    }
+                    let metadata = std::fs::metadata(&path)?;
+                    objects.push(LfsObject {
+                        oid,
+                        size: metadata.len(),
+                        modified: metadata.modified()?,
+                    });
+                }
+            }
+        }
+        objects.sort_by_key(|object| object.oid);
+        Ok(objects)
+    }
+
+    fn delete_lfs(&self, oid: &LfsOid) -> MonoResult<()> {
+        match std::fs::remove_file(self.lfs_dir().join(oid.path())) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pack::encode_pack;
+
+    /// 测试收到的 pack 原样保存，thin pack 重新编码后保存，并且新实例能读到其中的对象
+    #[test]
+    fn test_write_pack() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FsStore::new(dir.path());
+        let base = RawObject::new(ObjectType::Blob, b"hello world".to_vec());
+        let other = RawObject::new(ObjectType::Blob, b"other".to_vec());
+        let pack = encode_pack([base.clone(), other.clone()].iter()).unwrap();
+        assert_eq!(store.write_pack(&pack).unwrap(), 2);
+        assert!(store.contains(&base.id()).unwrap());
+        assert!(!store.loose.contains(&base.id()));
+
+        // 只包含一个以 base 为基的 REF_DELTA 的 thin pack
+        let derived = RawObject::new(ObjectType::Blob, b"hello rust!".to_vec());
+        let mut writer = PackWriter::new(1);
+        writer
+            .write_ref_delta(derived.id(), &base.id(), &[11, 11, 0x90, 6, 5, b'r', b'u', b's', b't', b'!'])
+            .unwrap();
+        assert_eq!(store.write_pack(&writer.finish().unwrap()).unwrap(), 1);
+        assert_eq!(store.packs().unwrap().len(), 2);
+
+        let reopened = FsStore::new(dir.path());
+        assert_eq!(reopened.read(&derived.id()).unwrap(), Some(derived.clone()));
+        assert_eq!(reopened.read_header(&other.id()).unwrap(), Some((ObjectType::Blob, 5)));
+        let mut expected = vec![base.id(), other.id(), derived.id()];
+        expected.sort();
+        assert_eq!(reopened.list().unwrap(), expected);
+    }
+
+    /// 测试 LFS 对象保存在与 objects 同级的 lfs 目录中
+    #[test]
+    fn test_lfs_objects() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FsStore::new(dir.path().join("objects"));
+        let oid = LfsOid::hash(b"large file");
+        assert_eq!(store.read_lfs(&oid).unwrap(), None);
+        assert_eq!(store.lfs_size(&oid).unwrap(), None);
+        store.write_lfs(&oid, b"large file").unwrap();
+        assert!(dir.path().join("lfs/objects").join(oid.path()).is_file());
+        assert_eq!(store.read_lfs(&oid).unwrap().unwrap(), b"large file");
+        assert_eq!(store.lfs_size(&oid).unwrap(), Some(10));
+        assert!(store.list().unwrap().is_empty());
+
+        // 中断的写入留下的临时文件不会被列出
+        let stray = dir.path().join("lfs/objects").join(oid.path()).with_file_name(".tmp-1-stray");
+        std::fs::write(stray, b"partial").unwrap();
+        let listed = store.list_lfs().unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!((listed[0].oid, listed[0].size), (oid, 10));
+        store.delete_lfs(&oid).unwrap();
+        store.delete_lfs(&oid).unwrap();
+        assert!(store.list_lfs().unwrap().is_empty());
+    }
+
+    /// 测试多包索引覆盖已有的 pack，之后写入的 pack 仍然可以读到
+    #[test]
+    fn test_multi_pack_index() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FsStore::new(dir.path());
+        assert!(store.write_multi_pack_index().unwrap().is_none());
+
+        let objects: Vec<RawObject> = (0..3)
+            .map(|i| RawObject::new(ObjectType::Blob, format!("object {}", i).into_bytes()))
+            .collect();
+        store.write_pack(&encode_pack(objects[..2].iter()).unwrap()).unwrap();
+        store.write_pack(&encode_pack(objects[1..2].iter()).unwrap()).unwrap();
+        let midx = store.write_multi_pack_index().unwrap().unwrap();
+        assert_eq!((midx.len(), midx.pack_names().len()), (2, 2));
+        assert!(dir.path().join("pack").join(MIDX_FILE).is_file());
+
+        store.write_pack(&encode_pack(objects[2..].iter()).unwrap()).unwrap();
+        let reopened = FsStore::new(dir.path());
+        let list = reopened.pack_list().unwrap();
+        assert_eq!(list.files.len(), 1);
+        for object in &objects {
+            assert_eq!(reopened.read(&object.id()).unwrap().as_ref(), Some(object));
+        }
+        assert_eq!(reopened.list().unwrap().len(), 3);
+        assert_eq!(reopened.packs().unwrap().len(), 3);
+    }
+
+    /// 测试增量重新打包合并松散对象与最小的 pack，保留较大的 pack，并更新多包索引
+    #[test]
+    fn test_repack_incremental() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FsStore::new(dir.path());
+        let large: Vec<RawObject> = (0..20)
+            .map(|i| RawObject::new(ObjectType::Blob, format!("large object {}", i).repeat(50).into_bytes()))
+            .collect();
+        let small = [RawObject::new(ObjectType::Blob, b"small".to_vec())];
+        store.write_pack(&encode_pack(large.iter()).unwrap()).unwrap();
+        store.write_pack(&encode_pack(small.iter()).unwrap()).unwrap();
+        store.write_multi_pack_index().unwrap();
+        let loose = store.write(ObjectType::Blob, b"loose").unwrap();
+        assert_eq!(store.repack_incremental(4).unwrap().loose, 1);
+        assert!(!store.loose.contains(&loose));
+        assert_eq!(store.packs().unwrap().len(), 3);
+
+        let stats = store.repack_incremental(2).unwrap();
+        assert_eq!(stats, RepackStats { loose: 0, packs: 2, objects: 2 });
+        assert_eq!(store.repack_incremental(2).unwrap(), RepackStats::default());
+        let reopened = FsStore::new(dir.path());
+        assert_eq!(reopened.packs().unwrap().len(), 2);
+        assert_eq!(reopened.pack_list().unwrap().midx.unwrap().index.pack_names().len(), 2);
+        assert_eq!(reopened.read(&loose).unwrap().unwrap().data, b"loose");
+        assert_eq!(reopened.list().unwrap().len(), 22);
+    }
+
+    /// 测试几何重新打包只合并破坏几何级数的小 pack，满足级数后不再重写
+    #[test]
+    fn test_repack_geometric() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FsStore::new(dir.path());
+        let blobs = |prefix: &str, n: usize| -> Vec<RawObject> {
+            (0..n)
+                .map(|i| RawObject::new(ObjectType::Blob, format!("{} {}", prefix, i).into_bytes()))
+                .collect()
+        };
+        for (prefix, n) in [("a", 16), ("b", 4), ("c", 1), ("d", 1)] {
+            store.write_pack(&encode_pack(blobs(prefix, n).iter()).unwrap()).unwrap();
+        }
+
+        assert!(store.repack_geometric(1).is_err());
+        // 两个单对象 pack 合并后为 2、4、16
+        assert_eq!(store.repack_geometric(2).unwrap(), RepackStats { loose: 0, packs: 2, objects: 2 });
+        assert_eq!(store.packs().unwrap().len(), 3);
+        assert_eq!(store.repack_geometric(2).unwrap(), RepackStats::default());
+
+        // 单个松散对象不足以与最小的 pack 合并，单独成为新 pack
+        store.write(ObjectType::Blob, b"loose").unwrap();
+        assert_eq!(store.repack_geometric(2).unwrap(), RepackStats { loose: 1, packs: 0, objects: 1 });
+        assert_eq!(store.repack_geometric(2).unwrap(), RepackStats::default());
+        let reopened = FsStore::new(dir.path());
+        assert_eq!(reopened.packs().unwrap().len(), 4);
+        assert_eq!(reopened.list().unwrap().len(), 23);
+    }
+
+    /// 测试以 zstd 压缩的存储：松散对象与重新打包的 pack 都使用 zstd，已有的 zlib pack 照常读取
+    #[test]
+    fn test_zstd() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FsStore::new(dir.path()).with_codec(Codec::Zstd);
+        let objects: Vec<RawObject> = (0..3)
+            .map(|i| RawObject::new(ObjectType::Blob, format!("object {}", i).repeat(20).into_bytes()))
+            .collect();
+        store.write_pack(&encode_pack(objects.iter()).unwrap()).unwrap();
+        assert_eq!(store.packs().unwrap()[0].codec(), Codec::Zlib);
+        let loose = store.write(ObjectType::Blob, b"loose").unwrap();
+        assert_eq!(Codec::detect(&std::fs::read(store.loose.object_path(&loose)).unwrap()), Codec::Zstd);
+
+        assert_eq!(store.repack_incremental(1).unwrap(), RepackStats { loose: 1, packs: 1, objects: 4 });
+        let reopened = FsStore::new(dir.path());
+        let packs = reopened.packs().unwrap();
+        assert_eq!(packs.len(), 1);
+        assert_eq!(packs[0].codec(), Codec::Zstd);
+        assert_eq!(reopened.read(&loose).unwrap().unwrap().data, b"loose");
+        for object in &objects {
+            assert_eq!(reopened.read(&object.id()).unwrap().as_ref(), Some(object));
+        }
+    }
+}
@@ -0,0 +1,153 @@
+//! 内存后端：对象只保存在进程内，用于测试以及不需要持久化的临时仓库
+
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+use std::time::SystemTime;
+
+use crate::common::MonoResult;
+use crate::lfs::{LfsObject, LfsOid};
+use crate::object::{ObjectFormat, ObjectId, ObjectType, RawObject};
+use crate::storage::{ObjectStore, StoredObject};
+
+/// 基于内存的对象存储
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    format: ObjectFormat,
+    /// 对象与写入时间
+    objects: RwLock<BTreeMap<ObjectId, (RawObject, SystemTime)>>,
+    /// LFS 对象的内容与写入时间
+    lfs: RwLock<BTreeMap<LfsOid, (Vec<u8>, SystemTime)>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> MemoryStore {
+        MemoryStore::default()
+    }
+
+    /// 使用 `format` 格式计算写入对象的 ID
+    pub fn with_format(mut self, format: ObjectFormat) -> MemoryStore {
+        self.format = format;
+        self
+    }
+
+    /// 存储中的对象数
+    pub fn len(&self) -> usize {
+        self.objects.read().unwrap_or_else(|e| e.into_inner()).len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl ObjectStore for MemoryStore {
+    fn format(&self) -> ObjectFormat {
+        self.format
+    }
+
+    fn contains(&self, id: &ObjectId) -> MonoResult<bool> {
+        Ok(self.objects.read().unwrap_or_else(|e| e.into_inner()).contains_key(id))
+    }
+
+    fn read(&self, id: &ObjectId) -> MonoResult<Option<RawObject>> {
+        Ok(self.objects.read().unwrap_or_else(|e| e.into_inner()).get(id).map(|(object, _)| object.clone()))
+    }
+
+    fn write(&self, object_type: ObjectType, data: &[u8]) -> MonoResult<ObjectId> {
+        let id = self.format.hash_object(object_type, data);
+        self.objects
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(id)
+            .or_insert_with(|| (RawObject::new(object_type, data.to_vec()), SystemTime::now()));
+        Ok(id)
+    }
+
+    fn list(&self) -> MonoResult<Vec<ObjectId>> {
+        Ok(self.objects.read().unwrap_or_else(|e| e.into_inner()).keys().copied().collect())
+    }
+
+    fn list_stored(&self) -> MonoResult<Vec<StoredObject>> {
+        let objects = self.objects.read().unwrap_or_else(|e| e.into_inner());
+        Ok(objects
+            .iter()
+            .map(|(id, (object, modified))| StoredObject {
+                id: *id,
+                size: object.data.len() as u64,
+                modified: *modified,
+            })
+            .collect())
+    }
+
+    fn delete(&self, ids: &[ObjectId]) -> MonoResult<()> {
+        let mut objects = self.objects.write().unwrap_or_else(|e| e.into_inner());
+        for id in ids {
+            objects.remove(id);
+        }
+        Ok(())
+    }
+
+    fn read_lfs(&self, oid: &LfsOid) -> MonoResult<Option<Vec<u8>>> {
+        Ok(self.lfs.read().unwrap_or_else(|e| e.into_inner()).get(oid).map(|(data, _)| data.clone()))
+    }
+
+    fn write_lfs(&self, oid: &LfsOid, data: &[u8]) -> MonoResult<()> {
+        self.lfs
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(*oid, (data.to_vec(), SystemTime::now()));
+        Ok(())
+    }
+
+    fn list_lfs(&self) -> MonoResult<Vec<LfsObject>> {
+        let lfs = self.lfs.read().unwrap_or_else(|e| e.into_inner());
+        Ok(lfs
+            .iter()
+            .map(|(oid, (data, modified))| LfsObject {
+                oid: *oid,
+                size: data.len() as u64,
+                modified: *modified,
+            })
+            .collect())
+    }
+
+    fn delete_lfs(&self, oid: &LfsOid) -> MonoResult<()> {
+        self.lfs.write().unwrap_or_else(|e| e.into_inner()).remove(oid);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 测试内存存储的读写与列举
+    #[test]
+    fn test_memory_store() {
+        let store = MemoryStore::new();
+        let id = store.write(ObjectType::Blob, b"hello").unwrap();
+        assert_eq!(id.to_hex(), "b6fc4c620b67d95f953a5c1c1230aaab5db5a1b0");
+        assert_eq!(store.write(ObjectType::Blob, b"hello").unwrap(), id);
+        assert!(store.contains(&id).unwrap());
+        assert_eq!(store.read(&id).unwrap().unwrap().data, b"hello");
+        assert_eq!(store.read_header(&id).unwrap(), Some((ObjectType::Blob, 5)));
+        assert_eq!(store.list().unwrap(), vec![id]);
+        assert_eq!(store.len(), 1);
+
+        let missing = ObjectId::hash_object(ObjectType::Blob, b"missing");
+        assert!(!store.contains(&missing).unwrap());
+        assert!(store.read(&missing).unwrap().is_none());
+
+        let oid = LfsOid::hash(b"large");
+        assert_eq!(store.lfs_size(&oid).unwrap(), None);
+        store.write_lfs(&oid, b"large").unwrap();
+        assert_eq!(store.read_lfs(&oid).unwrap().unwrap(), b"large");
+        assert_eq!(store.lfs_size(&oid).unwrap(), Some(5));
+        let listed = store.list_lfs().unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!((listed[0].oid, listed[0].size), (oid, 5));
+        store.delete_lfs(&oid).unwrap();
+        store.delete_lfs(&oid).unwrap();
+        assert!(store.list_lfs().unwrap().is_empty());
+    }
+}
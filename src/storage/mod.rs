@@ -0,0 +1,173 @@
+//! 可插拔的对象存储后端
+//!
+//! 仓库的所有对象读写都经过 [`ObjectStore`]，具体实现由 `mono.toml` 中的
+//! `[storage] backend` 选择。新增后端（如 S3、数据库）只需实现该 trait 并在 [`open`] 中注册，
+//! 调用方无需改动。引用数据库同样可以替换，见 [`open_refs`]。后端的并发调用数由
+//! [`scheduler::FetchScheduler`] 限制，服务端在 tokio 中经由 [`scheduler::AsyncObjectStore`] 或阻塞线程访问对象存储，见 [`scheduler`]。
+//!
+//! Git LFS 的大文件对象按 SHA-256 寻址，同样由对象存储保存，但与 git 对象分开存放。
+
+pub mod cache;
+pub mod chunked;
+pub mod fs;
+pub mod memory;
+pub mod pg;
+pub mod quarantine;
+pub mod s3;
+pub mod scheduler;
+
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use crate::common::config::{StorageBackend, StorageConfig};
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::journal::JournaledRefStore;
+use crate::lfs::{LfsObject, LfsOid};
+use crate::object::{ObjectFormat, ObjectId, ObjectType, RawObject};
+use crate::pack::decode_pack;
+use crate::pack::index::PackIndex;
+use crate::refs::{FileRefStore, RefStore};
+
+/// 对象存储
+///
+/// 对象以内容寻址，写入是幂等的；实现需要支持多线程并发访问。接口是同步的，远程后端的调用会阻塞，
+/// 在 tokio 中只能从阻塞线程调用，或经由 [`scheduler::AsyncObjectStore`] 访问。
+pub trait ObjectStore: Send + Sync + fmt::Debug {
+    /// 写入对象时计算 ID 使用的对象格式
+    fn format(&self) -> ObjectFormat;
+
+    /// 是否存在指定对象
+    fn contains(&self, id: &ObjectId) -> MonoResult<bool>;
+
+    /// 读取对象，不存在时返回 None
+    fn read(&self, id: &ObjectId) -> MonoResult<Option<RawObject>>;
+
+    /// 读取对象类型与大小，不存在时返回 None
+    ///
+    /// 默认读取整个对象，能够廉价获取对象头的后端应当覆盖该方法。
+    fn read_header(&self, id: &ObjectId) -> MonoResult<Option<(ObjectType, usize)>> {
+        Ok(self.read(id)?.map(|object| (object.object_type, object.data.len())))
+    }
+
+    /// 写入对象并返回其 ID，对象已存在时直接返回
+    fn write(&self, object_type: ObjectType, data: &[u8]) -> MonoResult<ObjectId>;
+
+    /// 列出存储中的所有对象 ID，按 ID 排序
+    fn list(&self) -> MonoResult<Vec<ObjectId>>;
+
+    /// 列出全部对象及其占用的空间与最后写入时间，按 ID 排序，供垃圾回收使用
+    fn list_stored(&self) -> MonoResult<Vec<StoredObject>>;
+
+    /// 删除一组对象，对象不存在时不报错
+    ///
+    /// 调用方负责确认这些对象不再可达，删除后仍被引用的对象会使仓库损坏。
+    fn delete(&self, ids: &[ObjectId]) -> MonoResult<()>;
+
+    /// 写入 pack 中的全部对象并返回对象数，thin pack 的基对象从存储中读取
+    ///
+    /// 默认逐个写入还原后的对象，能够直接保存 pack 的后端应当覆盖该方法。
+    fn write_pack(&self, pack: &[u8]) -> MonoResult<usize> {
+        let objects = decode_pack(pack, |id| self.read(id))?;
+        for object in &objects {
+            self.write(object.object_type, &object.data)?;
+        }
+        Ok(objects.len())
+    }
+
+    /// 与 [`ObjectStore::write_pack`] 相同，但 pack 保存在文件 `path` 中，返回对象数
+    ///
+    /// `index` 为已经为该 pack 建立的自包含索引，为 None 时由实现建立。实现可以把文件移入自己的存储，
+    /// 调用方之后不应再使用该文件。默认把文件读入内存后调用 `write_pack`，能够直接保存 pack 文件的后端
+    /// 应当覆盖该方法。
+    fn write_pack_file(&self, path: &Path, index: Option<&PackIndex>) -> MonoResult<usize> {
+        let _ = index;
+        self.write_pack(&std::fs::read(path)?)
+    }
+
+    /// 读取 LFS 对象，不存在时返回 None
+    fn read_lfs(&self, oid: &LfsOid) -> MonoResult<Option<Vec<u8>>>;
+
+    /// LFS 对象的大小，不存在时返回 None
+    ///
+    /// 默认读取整个对象，能够廉价获取大小的后端应当覆盖该方法。
+    fn lfs_size(&self, oid: &LfsOid) -> MonoResult<Option<u64>> {
+        Ok(self.read_lfs(oid)?.map(|data| data.len() as u64))
+    }
+
+    /// 保存 LFS 对象，调用方负责确认内容的 SHA-256 与 `oid` 一致
+    fn write_lfs(&self, oid: &LfsOid, data: &[u8]) -> MonoResult<()>;
+
+    /// 列出全部 LFS 对象及其大小与最后修改时间，按 oid 排序
+    fn list_lfs(&self) -> MonoResult<Vec<LfsObject>>;
+
+    /// 删除 LFS 对象，对象不存在时不报错
+    fn delete_lfs(&self, oid: &LfsOid) -> MonoResult<()>;
+}
+
+/// 存储中的一个对象，见 [`ObjectStore::list_stored`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StoredObject {
+    pub id: ObjectId,
+    /// 在存储中占用的字节数，即压缩后（pack 中可能是 delta）的大小
+    pub size: u64,
+    /// 最后写入时间，pack 中的对象取 pack 的写入时间
+    pub modified: SystemTime,
+}
+
+/// 按配置打开仓库的引用数据库：配置了 `[storage.pg]` 时引用保存在 PostgreSQL 中，
+/// 否则使用 `.mono` 下的文件，多引用更新通过预写日志保证原子性（见 [`crate::journal`]）
+pub fn open_refs(config: &StorageConfig, mono_dir: &Path, objects: Arc<dyn ObjectStore>) -> MonoResult<Arc<dyn RefStore>> {
+    match &config.pg {
+        Some(pg) => Ok(Arc::new(pg::PgStore::connect(pg, objects)?)),
+        None => {
+            let files = Arc::new(FileRefStore::new(mono_dir));
+            Ok(Arc::new(JournaledRefStore::open(files, objects, mono_dir)?))
+        }
+    }
+}
+
+/// 按配置打开仓库的对象存储，对象 ID 使用 `format` 格式；后端的每次调用占用 `scheduler` 的名额，
+/// 配置 `[storage.chunking]` 时大 blob 分块保存，`cache_size` 或 `disk_cache_size` 非 0 时在后端之前加一层对象缓存
+pub fn open(
+    config: &StorageConfig,
+    format: ObjectFormat,
+    mono_dir: &Path,
+    scheduler: &scheduler::FetchScheduler,
+) -> MonoResult<Arc<dyn ObjectStore>> {
+    let mut store: Arc<dyn ObjectStore> = match config.backend {
+        StorageBackend::Fs => Arc::new(
+            fs::FsStore::new(mono_dir.join("objects"))
+                .with_format(format)
+                .with_codec(config.compression),
+        ),
+        StorageBackend::S3 => {
+            let s3 = config
+                .s3
+                .clone()
+                .ok_or_else(|| MonoError::config("storage backend s3 requires a [storage.s3] section"))?;
+            Arc::new(s3::S3Store::from_env(s3)?.with_format(format).with_codec(config.compression))
+        }
+    };
+    store = Arc::new(scheduler::LimitedStore::new(store, scheduler.clone()));
+    if let Some(chunking) = &config.chunking {
+        let dir = mono_dir.join(chunked::CHUNKS_DIR);
+        store = Arc::new(chunked::ChunkedStore::new(store, dir, chunking, config.compression));
+    }
+    let capacity = usize::try_from(config.cache_size)
+        .map_err(|_| MonoError::config(format!("storage cache_size {} is too large", config.cache_size)))?;
+    let disk_capacity = match config.backend {
+        StorageBackend::Fs => 0,
+        _ => config.disk_cache_size,
+    };
+    if capacity == 0 && disk_capacity == 0 {
+        return Ok(store);
+    }
+    let mut cached = cache::CachedStore::new(store, capacity);
+    if disk_capacity > 0 {
+        cached = cached.with_disk_cache(mono_dir.join(cache::CACHE_DIR), disk_capacity);
+    }
+    Ok(Arc::new(cached))
+}
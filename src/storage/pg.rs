@@ -0,0 +1,603 @@
+//! PostgreSQL 元数据后端
+//!
+//! 引用、提交图元数据（父提交、generation number、提交时间）以及目录索引（树条目）保存在
+//! PostgreSQL 中，对象内容仍由 [`ObjectStore`] 保存。引用更新在单个事务中完成，多个无状态
+//! 服务节点可以安全地共享同一个仓库。
+//!
+//! 引用指向新的提交时，同一事务内会索引从该提交可达、尚未入库的提交及其树，因此元数据
+//! 始终覆盖所有引用可达的历史，历史查询无需再读取对象。
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use postgres::error::SqlState;
+use postgres::{Client, NoTls, Row, Transaction};
+
+use crate::common::config::PgConfig;
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::object::commit::Commit;
+use crate::object::tag::Tag;
+use crate::object::tree::{FileMode, Tree, TreeEntry};
+use crate::object::{ObjectId, ObjectType};
+use crate::refs::{self, RefStore, RefTarget, RefUpdate};
+use crate::storage::ObjectStore;
+
+/// 表结构，重复执行是安全的
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS mono_refs (
+    repository TEXT NOT NULL,
+    name TEXT NOT NULL,
+    target BYTEA,
+    symbolic TEXT,
+    PRIMARY KEY (repository, name),
+    CHECK ((target IS NULL) <> (symbolic IS NULL))
+);
+CREATE TABLE IF NOT EXISTS mono_commits (
+    repository TEXT NOT NULL,
+    id BYTEA NOT NULL,
+    tree BYTEA NOT NULL,
+    generation INTEGER NOT NULL,
+    commit_time BIGINT NOT NULL,
+    PRIMARY KEY (repository, id)
+);
+CREATE TABLE IF NOT EXISTS mono_commit_parents (
+    repository TEXT NOT NULL,
+    id BYTEA NOT NULL,
+    position INTEGER NOT NULL,
+    parent BYTEA NOT NULL,
+    PRIMARY KEY (repository, id, position)
+);
+CREATE INDEX IF NOT EXISTS mono_commit_parents_parent ON mono_commit_parents (repository, parent);
+CREATE TABLE IF NOT EXISTS mono_trees (
+    repository TEXT NOT NULL,
+    id BYTEA NOT NULL,
+    PRIMARY KEY (repository, id)
+);
+CREATE TABLE IF NOT EXISTS mono_tree_entries (
+    repository TEXT NOT NULL,
+    tree BYTEA NOT NULL,
+    name TEXT NOT NULL,
+    mode INTEGER NOT NULL,
+    id BYTEA NOT NULL,
+    PRIMARY KEY (repository, tree, name)
+);
+";
+
+/// 提交图中的一个提交
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommitInfo {
+    pub id: ObjectId,
+    pub tree: ObjectId,
+    pub parents: Vec<ObjectId>,
+    /// 根提交为 1，其余为父提交的最大值加 1
+    pub generation: u32,
+    /// 提交者时间戳（秒）
+    pub commit_time: i64,
+}
+
+/// 基于 PostgreSQL 的引用数据库与提交图索引
+pub struct PgStore {
+    url: String,
+    repository: String,
+    client: Mutex<Client>,
+    objects: Arc<dyn ObjectStore>,
+}
+
+impl fmt::Debug for PgStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // 连接串中可能包含密码
+        f.debug_struct("PgStore")
+            .field("repository", &self.repository)
+            .finish_non_exhaustive()
+    }
+}
+
+fn pg_error(err: postgres::Error) -> MonoError {
+    if err.is_closed() {
+        MonoError::unavailable(format!("postgres: {}", err))
+    } else {
+        MonoError::storage(format!("postgres: {}", err))
+    }
+}
+
+fn object_id(row: &Row, column: &str) -> MonoResult<ObjectId> {
+    ObjectId::from_bytes(row.get::<_, &[u8]>(column))
+}
+
+impl PgStore {
+    /// 连接数据库并确保表结构存在
+    pub fn connect(config: &PgConfig, objects: Arc<dyn ObjectStore>) -> MonoResult<PgStore> {
+        let mut client = Self::open_client(&config.url)?;
+        let mut tx = client.transaction().map_err(pg_error)?;
+        // 多个节点同时启动时串行化建表
+        tx.execute("SELECT pg_advisory_xact_lock(hashtext('mono_schema'))", &[])
+            .map_err(pg_error)?;
+        tx.batch_execute(SCHEMA).map_err(pg_error)?;
+        tx.commit().map_err(pg_error)?;
+        Ok(PgStore {
+            url: config.url.clone(),
+            repository: config.repository.clone(),
+            client: Mutex::new(client),
+            objects,
+        })
+    }
+
+    fn open_client(url: &str) -> MonoResult<Client> {
+        Client::connect(url, NoTls).map_err(|e| MonoError::unavailable(format!("connecting to postgres: {}", e)))
+    }
+
+    /// 取得连接，连接已断开时重新连接
+    fn client(&self) -> MonoResult<MutexGuard<'_, Client>> {
+        let mut client = self.client.lock().unwrap_or_else(|e| e.into_inner());
+        if client.is_closed() {
+            tracing::warn!(repository = %self.repository, "postgres connection lost, reconnecting");
+            *client = Self::open_client(&self.url)?;
+        }
+        Ok(client)
+    }
+
+    /// 读取已索引的提交
+    #[tracing::instrument(level = "debug", name = "pg", skip(self))]
+    pub fn commit_info(&self, id: &ObjectId) -> MonoResult<Option<CommitInfo>> {
+        let mut client = self.client()?;
+        let row = client
+            .query_opt(
+                "SELECT id, tree, generation, commit_time FROM mono_commits WHERE repository = $1 AND id = $2",
+                &[&self.repository, &id.as_bytes()],
+            )
+            .map_err(pg_error)?;
+        match row {
+            Some(row) => self.commit_from_row(&mut client, &row).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// 从 `tip` 可达的提交，按提交时间从新到旧排列，最多 `limit` 个
+    #[tracing::instrument(level = "debug", name = "pg", skip(self))]
+    pub fn log(&self, tip: &ObjectId, limit: usize) -> MonoResult<Vec<CommitInfo>> {
+        let mut client = self.client()?;
+        let rows = client
+            .query(
+                "WITH RECURSIVE ancestors(id) AS (
+                     SELECT $2::bytea
+                     UNION
+                     SELECT p.parent FROM mono_commit_parents p JOIN ancestors a ON p.id = a.id
+                     WHERE p.repository = $1
+                 )
+                 SELECT c.id, c.tree, c.generation, c.commit_time
+                 FROM mono_commits c JOIN ancestors a ON c.id = a.id
+                 WHERE c.repository = $1
+                 ORDER BY c.commit_time DESC, c.generation DESC
+                 LIMIT $3",
+                &[&self.repository, &tip.as_bytes(), &(limit.min(i64::MAX as usize) as i64)],
+            )
+            .map_err(pg_error)?;
+        rows.iter().map(|row| self.commit_from_row(&mut client, row)).collect()
+    }
+
+    /// 两个提交的最近公共祖先（generation 最大者），没有公共历史时返回 None
+    #[tracing::instrument(level = "debug", name = "pg", skip(self))]
+    pub fn merge_base(&self, a: &ObjectId, b: &ObjectId) -> MonoResult<Option<ObjectId>> {
+        let mut client = self.client()?;
+        let row = client
+            .query_opt(
+                "WITH RECURSIVE
+                 left_side(id) AS (
+                     SELECT $2::bytea
+                     UNION
+                     SELECT p.parent FROM mono_commit_parents p JOIN left_side l ON p.id = l.id
+                     WHERE p.repository = $1
+                 ),
+                 right_side(id) AS (
+                     SELECT $3::bytea
+                     UNION
+                     SELECT p.parent FROM mono_commit_parents p JOIN right_side r ON p.id = r.id
+                     WHERE p.repository = $1
+                 )
+                 SELECT c.id FROM mono_commits c
+                 JOIN left_side l ON c.id = l.id
+                 JOIN right_side r ON c.id = r.id
+                 WHERE c.repository = $1
+                 ORDER BY c.generation DESC, c.commit_time DESC
+                 LIMIT 1",
+                &[&self.repository, &a.as_bytes(), &b.as_bytes()],
+            )
+            .map_err(pg_error)?;
+        row.map(|row| object_id(&row, "id")).transpose()
+    }
+
+    /// 从目录索引读取树的条目，树尚未索引时返回 None
+    #[tracing::instrument(level = "debug", name = "pg", skip(self))]
+    pub fn list_tree(&self, tree: &ObjectId) -> MonoResult<Option<Vec<TreeEntry>>> {
+        let mut client = self.client()?;
+        let indexed = client
+            .query_opt(
+                "SELECT 1 FROM mono_trees WHERE repository = $1 AND id = $2",
+                &[&self.repository, &tree.as_bytes()],
+            )
+            .map_err(pg_error)?;
+        if indexed.is_none() {
+            return Ok(None);
+        }
+        let rows = client
+            .query(
+                "SELECT name, mode, id FROM mono_tree_entries WHERE repository = $1 AND tree = $2",
+                &[&self.repository, &tree.as_bytes()],
+            )
+            .map_err(pg_error)?;
+        let mut entries = Vec::with_capacity(rows.len());
+        for row in rows {
+            let mode: i32 = row.get("mode");
+            entries.push(TreeEntry::new(FileMode(mode as u32), row.get::<_, String>("name"), object_id(&row, "id")?));
+        }
+        let mut tree = Tree { entries };
+        tree.sort();
+        Ok(Some(tree.entries))
+    }
+
+    fn commit_from_row(&self, client: &mut Client, row: &Row) -> MonoResult<CommitInfo> {
+        let id = object_id(row, "id")?;
+        let parents = client
+            .query(
+                "SELECT parent FROM mono_commit_parents WHERE repository = $1 AND id = $2 ORDER BY position",
+                &[&self.repository, &id.as_bytes()],
+            )
+            .map_err(pg_error)?
+            .iter()
+            .map(|row| object_id(row, "parent"))
+            .collect::<MonoResult<_>>()?;
+        Ok(CommitInfo {
+            id,
+            tree: object_id(row, "tree")?,
+            parents,
+            generation: row.get::<_, i32>("generation") as u32,
+            commit_time: row.get("commit_time"),
+        })
+    }
+
+    /// 在事务中索引从 `tip` 可达、尚未入库的提交以及它们的树，返回新索引的提交数
+    fn index_commits(&self, tx: &mut Transaction<'_>, tip: &ObjectId) -> MonoResult<usize> {
+        let Some(tip) = self.peel_to_commit(tip)? else {
+            return Ok(0);
+        };
+        let lookup = tx
+            .prepare("SELECT generation FROM mono_commits WHERE repository = $1 AND id = $2")
+            .map_err(pg_error)?;
+        let insert_commit = tx
+            .prepare(
+                "INSERT INTO mono_commits (repository, id, tree, generation, commit_time)
+                 VALUES ($1, $2, $3, $4, $5) ON CONFLICT DO NOTHING",
+            )
+            .map_err(pg_error)?;
+        let insert_parent = tx
+            .prepare(
+                "INSERT INTO mono_commit_parents (repository, id, position, parent)
+                 VALUES ($1, $2, $3, $4) ON CONFLICT DO NOTHING",
+            )
+            .map_err(pg_error)?;
+
+        // 后序遍历，保证写入提交时父提交的 generation 已知
+        let mut generations: HashMap<ObjectId, i32> = HashMap::new();
+        let mut expanded: HashMap<ObjectId, Commit> = HashMap::new();
+        let mut stack = vec![(tip, false)];
+        let mut indexed = 0;
+        while let Some((id, ready)) = stack.pop() {
+            if generations.contains_key(&id) {
+                continue;
+            }
+            if !ready {
+                if let Some(row) = tx.query_opt(&lookup, &[&self.repository, &id.as_bytes()]).map_err(pg_error)? {
+                    generations.insert(id, row.get(0));
+                    continue;
+                }
+                let object = self
+                    .objects
+                    .read(&id)?
+                    .ok_or_else(|| MonoError::not_found(format!("commit {}", id)))?;
+                let commit = Commit::parse(&object.data)?;
+                stack.push((id, true));
+                stack.extend(commit.parents.iter().map(|parent| (*parent, false)));
+                expanded.insert(id, commit);
+                continue;
+            }
+            let commit = expanded.remove(&id).expect("commit was expanded before");
+            let generation = 1 + commit.parents.iter().map(|p| generations[p]).max().unwrap_or(0);
+            tx.execute(
+                &insert_commit,
+                &[&self.repository, &id.as_bytes(), &commit.tree.as_bytes(), &generation, &commit.committer.timestamp],
+            )
+            .map_err(pg_error)?;
+            for (position, parent) in commit.parents.iter().enumerate() {
+                tx.execute(&insert_parent, &[&self.repository, &id.as_bytes(), &(position as i32), &parent.as_bytes()])
+                    .map_err(pg_error)?;
+            }
+            self.index_tree(tx, &commit.tree)?;
+            generations.insert(id, generation);
+            indexed += 1;
+        }
+        if indexed > 0 {
+            tracing::debug!(repository = %self.repository, %tip, commits = indexed, "indexed commits");
+        }
+        Ok(indexed)
+    }
+
+    /// 索引树及其尚未入库的子树
+    fn index_tree(&self, tx: &mut Transaction<'_>, root: &ObjectId) -> MonoResult<()> {
+        let insert_tree = tx
+            .prepare("INSERT INTO mono_trees (repository, id) VALUES ($1, $2) ON CONFLICT DO NOTHING")
+            .map_err(pg_error)?;
+        let insert_entry = tx
+            .prepare(
+                "INSERT INTO mono_tree_entries (repository, tree, name, mode, id)
+                 VALUES ($1, $2, $3, $4, $5) ON CONFLICT DO NOTHING",
+            )
+            .map_err(pg_error)?;
+        let mut pending = vec![*root];
+        while let Some(id) = pending.pop() {
+            // 插入成功说明该树此前未被索引
+            if tx.execute(&insert_tree, &[&self.repository, &id.as_bytes()]).map_err(pg_error)? == 0 {
+                continue;
+            }
+            let object = self
+                .objects
+                .read(&id)?
+                .ok_or_else(|| MonoError::not_found(format!("tree {}", id)))?;
+            for entry in Tree::parse(&object.data, id.format())?.entries {
+                tx.execute(
+                    &insert_entry,
+                    &[&self.repository, &id.as_bytes(), &entry.name, &(entry.mode.0 as i32), &entry.id.as_bytes()],
+                )
+                .map_err(pg_error)?;
+                if entry.mode.is_tree() {
+                    pending.push(entry.id);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// 解开附注标签，目标不是提交时返回 None
+    fn peel_to_commit(&self, id: &ObjectId) -> MonoResult<Option<ObjectId>> {
+        let mut id = *id;
+        loop {
+            let Some(object) = self.objects.read(&id)? else {
+                return Err(MonoError::not_found(format!("object {}", id)));
+            };
+            match object.object_type {
+                ObjectType::Commit => return Ok(Some(id)),
+                ObjectType::Tag => id = Tag::parse(&object.data)?.object,
+                _ => return Ok(None),
+            }
+        }
+    }
+
+    /// 写入直接引用，`create_only` 时引用已存在会因主键冲突失败
+    fn put_ref(&self, tx: &mut Transaction<'_>, name: &str, id: &ObjectId, create_only: bool) -> MonoResult<()> {
+        self.index_commits(tx, id)?;
+        let sql = if create_only {
+            "INSERT INTO mono_refs (repository, name, target) VALUES ($1, $2, $3)"
+        } else {
+            "INSERT INTO mono_refs (repository, name, target) VALUES ($1, $2, $3)
+             ON CONFLICT (repository, name) DO UPDATE SET target = EXCLUDED.target, symbolic = NULL"
+        };
+        tx.execute(sql, &[&self.repository, &name, &id.as_bytes()]).map_err(pg_error)?;
+        Ok(())
+    }
+}
+
+impl RefStore for PgStore {
+    fn read(&self, name: &str) -> MonoResult<Option<RefTarget>> {
+        refs::check_name(name)?;
+        let row = self
+            .client()?
+            .query_opt(
+                "SELECT target, symbolic FROM mono_refs WHERE repository = $1 AND name = $2",
+                &[&self.repository, &name],
+            )
+            .map_err(pg_error)?;
+        let Some(row) = row else {
+            return Ok(None);
+        };
+        match row.get::<_, Option<String>>("symbolic") {
+            Some(target) => Ok(Some(RefTarget::Symbolic(target))),
+            None => Ok(Some(RefTarget::Direct(object_id(&row, "target")?))),
+        }
+    }
+
+    fn write(&self, name: &str, id: &ObjectId) -> MonoResult<()> {
+        refs::check_name(name)?;
+        let mut client = self.client()?;
+        let mut tx = client.transaction().map_err(pg_error)?;
+        self.put_ref(&mut tx, name, id, false)?;
+        tx.commit().map_err(pg_error)
+    }
+
+    fn write_symbolic(&self, name: &str, target: &str) -> MonoResult<()> {
+        refs::check_name(name)?;
+        refs::check_name(target)?;
+        self.client()?
+            .execute(
+                "INSERT INTO mono_refs (repository, name, symbolic) VALUES ($1, $2, $3)
+                 ON CONFLICT (repository, name) DO UPDATE SET symbolic = EXCLUDED.symbolic, target = NULL",
+                &[&self.repository, &name, &target],
+            )
+            .map_err(pg_error)?;
+        Ok(())
+    }
+
+    fn delete(&self, name: &str) -> MonoResult<()> {
+        refs::check_name(name)?;
+        self.client()?
+            .execute(
+                "DELETE FROM mono_refs WHERE repository = $1 AND name = $2",
+                &[&self.repository, &name],
+            )
+            .map_err(pg_error)?;
+        Ok(())
+    }
+
+    fn list(&self, prefix: &str) -> MonoResult<Vec<(String, ObjectId)>> {
+        let rows = self
+            .client()?
+            .query(
+                "SELECT name, target FROM mono_refs
+                 WHERE repository = $1 AND target IS NOT NULL AND starts_with(name, $2)
+                 ORDER BY name COLLATE \"C\"",
+                &[&self.repository, &prefix],
+            )
+            .map_err(pg_error)?;
+        rows.iter()
+            .map(|row| Ok((row.get("name"), object_id(row, "target")?)))
+            .collect()
+    }
+
+    /// 先锁定全部相关行并校验旧值，再在同一事务中写入；并发创建同名引用时主键冲突使其中一方失败
+    #[tracing::instrument(level = "debug", name = "pg", skip_all, fields(updates = updates.len()))]
+    fn update(&self, updates: &[RefUpdate]) -> MonoResult<()> {
+        for update in updates {
+            refs::check_name(&update.name)?;
+        }
+        // 按名称顺序加锁，避免并发事务死锁
+        let mut ordered: Vec<&RefUpdate> = updates.iter().collect();
+        ordered.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let mut client = self.client()?;
+        let mut tx = client.transaction().map_err(pg_error)?;
+        for update in &ordered {
+            let current = tx
+                .query_opt(
+                    "SELECT target FROM mono_refs WHERE repository = $1 AND name = $2 FOR UPDATE",
+                    &[&self.repository, &update.name],
+                )
+                .map_err(pg_error)?
+                .and_then(|row| row.get::<_, Option<&[u8]>>("target").map(ObjectId::from_bytes))
+                .transpose()?;
+            if !update.expects(current.as_ref()) {
+                return Err(refs::stale_ref_error(update, current.as_ref()));
+            }
+        }
+        for update in &ordered {
+            let applied = if update.is_delete() {
+                tx.execute(
+                    "DELETE FROM mono_refs WHERE repository = $1 AND name = $2",
+                    &[&self.repository, &update.name],
+                )
+                .map(|_| ())
+                .map_err(pg_error)
+            } else {
+                self.put_ref(&mut tx, &update.name, &update.new, update.old.is_zero())
+            };
+            if let Err(e) = applied {
+                let unique_violation = e.to_string().contains(SqlState::UNIQUE_VIOLATION.code());
+                return Err(if unique_violation {
+                    refs::stale_ref_error(update, None)
+                } else {
+                    e
+                });
+            }
+        }
+        tx.commit().map_err(pg_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::memory::MemoryStore;
+    use crate::test_utils::{commit_files, init_repo};
+
+    /// 需要通过 `MONO_TEST_PG_URL` 提供测试数据库，未设置时跳过；每次使用独立的仓库名
+    fn test_store(objects: Arc<dyn ObjectStore>) -> Option<PgStore> {
+        let url = std::env::var("MONO_TEST_PG_URL").ok()?;
+        let mut config = PgConfig::new(url);
+        config.repository = format!("test-{}", ObjectId::hash_object(ObjectType::Blob, &rand::random::<[u8; 16]>()));
+        Some(PgStore::connect(&config, objects).unwrap())
+    }
+
+    /// 测试引用的读写与带旧值校验的事务更新
+    #[test]
+    fn test_pg_refs() {
+        let objects = Arc::new(MemoryStore::new());
+        let Some(store) = test_store(objects.clone()) else {
+            return;
+        };
+        let (_dir, repo) = init_repo();
+        let repo = repo.with_object_store(objects);
+        let first = commit_files(&repo, &[("a.txt", b"a")], &[], "first");
+        let second = commit_files(&repo, &[("a.txt", b"b")], &[first], "second");
+
+        store.write_symbolic(refs::HEAD, "refs/heads/main").unwrap();
+        assert_eq!(store.resolve(refs::HEAD).unwrap(), None);
+        store.write("refs/heads/main", &first).unwrap();
+        assert_eq!(store.resolve(refs::HEAD).unwrap(), Some(first));
+
+        let update = |name: &str, old, new| RefUpdate {
+            name: name.to_string(),
+            old,
+            new,
+        };
+        // 旧值不符时整个事务回滚
+        let err = store
+            .update(&[
+                update("refs/heads/feature", ObjectId::ZERO, second),
+                update("refs/heads/main", second, first),
+            ])
+            .unwrap_err();
+        assert!(err.to_string().contains("changed concurrently"));
+        assert_eq!(store.read("refs/heads/feature").unwrap(), None);
+        // 引用已存在时不能按创建处理
+        assert!(store.update(&[update("refs/heads/main", ObjectId::ZERO, second)]).is_err());
+
+        store
+            .update(&[
+                update("refs/heads/feature", ObjectId::ZERO, second),
+                update("refs/heads/main", first, second),
+            ])
+            .unwrap();
+        assert_eq!(
+            store.list("refs/heads/").unwrap(),
+            vec![("refs/heads/feature".to_string(), second), ("refs/heads/main".to_string(), second)]
+        );
+        store.update(&[update("refs/heads/feature", second, ObjectId::ZERO)]).unwrap();
+        assert_eq!(store.list("refs/").unwrap().len(), 1);
+        assert!(store.write("refs/heads/bad..name", &first).is_err());
+    }
+
+    /// 测试更新引用时索引提交图与目录，并基于索引查询历史
+    #[test]
+    fn test_pg_commit_graph() {
+        let objects = Arc::new(MemoryStore::new());
+        let Some(store) = test_store(objects.clone()) else {
+            return;
+        };
+        let (_dir, repo) = init_repo();
+        let repo = repo.with_object_store(objects);
+        let root = commit_files(&repo, &[("a.txt", b"a")], &[], "root");
+        let left = commit_files(&repo, &[("a.txt", b"a"), ("dir/l.txt", b"l")], &[root], "left");
+        let right = commit_files(&repo, &[("a.txt", b"r")], &[root], "right");
+        let merge = commit_files(&repo, &[("a.txt", b"r"), ("dir/l.txt", b"l")], &[left, right], "merge");
+
+        store.write("refs/heads/main", &merge).unwrap();
+        let info = store.commit_info(&merge).unwrap().unwrap();
+        assert_eq!(info.parents, vec![left, right]);
+        assert_eq!(info.generation, 3);
+        assert_eq!(store.commit_info(&root).unwrap().unwrap().generation, 1);
+
+        let log: Vec<_> = store.log(&merge, 10).unwrap().iter().map(|c| c.id).collect();
+        assert_eq!(log.len(), 4);
+        assert_eq!(log[0], merge);
+        assert_eq!(log[3], root);
+        assert_eq!(store.log(&merge, 2).unwrap().len(), 2);
+        assert_eq!(store.merge_base(&left, &right).unwrap(), Some(root));
+        assert_eq!(store.merge_base(&merge, &left).unwrap(), Some(left));
+
+        let entries = store.list_tree(&info.tree).unwrap().unwrap();
+        let names: Vec<_> = entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, vec!["a.txt", "dir"]);
+        assert!(entries[1].mode.is_tree());
+        assert_eq!(store.list_tree(&entries[1].id).unwrap().unwrap()[0].name, "l.txt");
+        assert!(store.list_tree(&root).unwrap().is_none());
+    }
+}
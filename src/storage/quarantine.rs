@@ -0,0 +1,236 @@
+//! 推送的隔离区
+//!
+//! 与 git 的 quarantine 相同：receive-pack 把推送的 pack 直接从连接转存到
+//! `.mono/quarantine/<编号>/` 下，在磁盘上建立索引（见 [`crate::pack::indexer`]）后移入隔离区自己的
+//! 对象存储，pack 不会整个读入内存。校验（fsck、推送策略、
+//! 钩子）通过 [`Quarantine::repo`] 看到隔离区与主存储的并集；全部通过后 [`Quarantine::migrate`]
+//! 才把对象移入主存储，引用随后更新；主存储为 `fs` 后端时 pack 文件与索引直接移入 pack 目录。校验失败或进程中途退出时隔离区被删除，
+//! 未经校验的对象不会出现在主存储中，也就不会被 fetch 到。
+//!
+//! 进程崩溃留下的隔离区目录不影响仓库，可以直接删除。
+
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::lfs::{LfsObject, LfsOid};
+use crate::metrics;
+use crate::object::{ObjectFormat, ObjectId, ObjectType, RawObject};
+use crate::repo::Repository;
+use crate::pack::index::PackIndex;
+use crate::storage::fs::FsStore;
+use crate::storage::{ObjectStore, StoredObject};
+
+/// 隔离区目录，相对于 `.mono`
+pub const QUARANTINE_DIR: &str = "quarantine";
+/// 转存的 pack 文件名
+const INCOMING_PACK: &str = "incoming.pack";
+
+/// 同一进程中的隔离区编号
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// 隔离区与主存储的并集：读取时先查隔离区，写入只进入隔离区
+#[derive(Debug)]
+pub struct QuarantineStore {
+    incoming: FsStore,
+    main: Arc<dyn ObjectStore>,
+}
+
+impl ObjectStore for QuarantineStore {
+    fn format(&self) -> ObjectFormat {
+        self.main.format()
+    }
+
+    fn contains(&self, id: &ObjectId) -> MonoResult<bool> {
+        Ok(self.incoming.contains(id)? || self.main.contains(id)?)
+    }
+
+    fn read(&self, id: &ObjectId) -> MonoResult<Option<RawObject>> {
+        match self.incoming.read(id)? {
+            Some(object) => Ok(Some(object)),
+            None => self.main.read(id),
+        }
+    }
+
+    fn read_header(&self, id: &ObjectId) -> MonoResult<Option<(ObjectType, usize)>> {
+        match self.incoming.read_header(id)? {
+            Some(header) => Ok(Some(header)),
+            None => self.main.read_header(id),
+        }
+    }
+
+    fn write(&self, object_type: ObjectType, data: &[u8]) -> MonoResult<ObjectId> {
+        self.incoming.write(object_type, data)
+    }
+
+    /// 只列出隔离区中的对象
+    fn list(&self) -> MonoResult<Vec<ObjectId>> {
+        self.incoming.list()
+    }
+
+    fn list_stored(&self) -> MonoResult<Vec<StoredObject>> {
+        self.incoming.list_stored()
+    }
+
+    fn delete(&self, ids: &[ObjectId]) -> MonoResult<()> {
+        self.incoming.delete(ids)
+    }
+
+    /// thin pack 的基对象可以在主存储中
+    fn write_pack(&self, pack: &[u8]) -> MonoResult<usize> {
+        self.incoming.write_pack_with(pack, |id| self.read(id))
+    }
+
+    fn write_pack_file(&self, path: &Path, index: Option<&PackIndex>) -> MonoResult<usize> {
+        self.incoming.write_pack_file_with(path, index, |id| self.read(id))
+    }
+
+    fn read_lfs(&self, oid: &LfsOid) -> MonoResult<Option<Vec<u8>>> {
+        self.main.read_lfs(oid)
+    }
+
+    fn lfs_size(&self, oid: &LfsOid) -> MonoResult<Option<u64>> {
+        self.main.lfs_size(oid)
+    }
+
+    fn write_lfs(&self, oid: &LfsOid, data: &[u8]) -> MonoResult<()> {
+        self.main.write_lfs(oid, data)
+    }
+
+    fn list_lfs(&self) -> MonoResult<Vec<LfsObject>> {
+        self.main.list_lfs()
+    }
+
+    fn delete_lfs(&self, oid: &LfsOid) -> MonoResult<()> {
+        self.main.delete_lfs(oid)
+    }
+}
+
+/// 一次推送的隔离区，丢弃时删除目录
+pub struct Quarantine {
+    dir: PathBuf,
+    store: Arc<QuarantineStore>,
+    main: Arc<dyn ObjectStore>,
+}
+
+impl Quarantine {
+    /// 在仓库的 `.mono/quarantine/` 下创建新的隔离区
+    pub fn create(repo: &Repository) -> MonoResult<Quarantine> {
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let dir = repo.mono_dir().join(QUARANTINE_DIR).join(format!("{}-{}", std::process::id(), id));
+        std::fs::create_dir_all(&dir)?;
+        let main = repo.object_store();
+        let store = Arc::new(QuarantineStore {
+            incoming: FsStore::new(dir.join("objects")).with_format(main.format()),
+            main: main.clone(),
+        });
+        Ok(Quarantine { dir, store, main })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// 以隔离区与主存储的并集为对象存储的仓库视图，供校验使用
+    pub fn repo(&self, repo: &Repository) -> Repository {
+        repo.clone().with_object_store(self.store.clone())
+    }
+
+    /// 把 `input` 中的 pack 数据转存到磁盘再建立索引，返回收到的对象数；没有数据时返回 None
+    #[tracing::instrument(name = "quarantine.receive", skip_all)]
+    pub fn receive(&self, input: &mut dyn Read) -> MonoResult<Option<usize>> {
+        let path = self.dir.join(INCOMING_PACK);
+        let bytes = std::io::copy(input, &mut File::create(&path)?)?;
+        metrics::pack_received(bytes);
+        if bytes == 0 {
+            return Ok(None);
+        }
+        let count = self.store.write_pack_file(&path, None)?;
+        tracing::info!(bytes, objects = count, "received objects into quarantine");
+        Ok(Some(count))
+    }
+
+    /// 隔离区中的对象
+    pub fn objects(&self) -> MonoResult<Vec<ObjectId>> {
+        self.store.list()
+    }
+
+    /// 把隔离区中的对象移入主存储并删除隔离区，返回移入的对象数
+    ///
+    /// 隔离区中的 pack 连同索引整体交给主存储，其余的松散对象逐个写入。
+    #[tracing::instrument(name = "quarantine.migrate", skip_all)]
+    pub fn migrate(self) -> MonoResult<usize> {
+        let ids = self.store.list()?;
+        for pack in self.store.incoming.packs()? {
+            self.main.write_pack_file(pack.path(), Some(pack.index()))?;
+        }
+        for id in &ids {
+            if !self.main.contains(id)? {
+                let object = self.store.incoming.read(id)?.ok_or_else(|| {
+                    MonoError::storage(format!("quarantined object {} disappeared", id))
+                })?;
+                self.main.write(object.object_type, &object.data)?;
+            }
+        }
+        tracing::info!(objects = ids.len(), "migrated quarantined objects");
+        Ok(ids.len())
+    }
+}
+
+impl Drop for Quarantine {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_dir_all(&self.dir) {
+            tracing::warn!(dir = %self.dir.display(), error = %e, "failed to remove quarantine");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pack::encode_pack;
+    use crate::test_utils::{commit_files, init_repo};
+
+    /// 测试收到的对象只在隔离区视图中可见，移入后出现在主存储中，丢弃的隔离区被删除
+    #[test]
+    fn test_quarantine() {
+        let (_source_dir, source) = init_repo();
+        let commit = commit_files(&source, &[("a.txt", b"a")], &[], "init");
+        let objects: Vec<_> = source
+            .objects()
+            .list()
+            .unwrap()
+            .iter()
+            .map(|id| source.read_object(id).unwrap())
+            .collect();
+        let pack = encode_pack(objects.iter()).unwrap();
+
+        let (_dir, repo) = init_repo();
+        let quarantine = Quarantine::create(&repo).unwrap();
+        assert_eq!(quarantine.receive(&mut &pack[..]).unwrap(), Some(objects.len()));
+        assert!(quarantine.repo(&repo).read_commit(&commit).is_ok());
+        assert!(!repo.objects().contains(&commit).unwrap());
+        assert_eq!(quarantine.objects().unwrap().len(), objects.len());
+        let dir = quarantine.dir().to_path_buf();
+        drop(quarantine);
+        assert!(!dir.exists());
+        assert!(!repo.objects().contains(&commit).unwrap());
+
+        // 转存的文件原样移入隔离区的 pack 目录，移入主存储时同样整体移动，不重新编码
+        let quarantine = Quarantine::create(&repo).unwrap();
+        quarantine.receive(&mut &pack[..]).unwrap();
+        let name = format!("pack-{}.pack", ObjectId::from_bytes(&pack[pack.len() - 20..]).unwrap());
+        let incoming = quarantine.dir().join("objects/pack").join(&name);
+        assert_eq!(std::fs::read(&incoming).unwrap(), pack);
+        assert!(!quarantine.dir().join(INCOMING_PACK).exists());
+        assert_eq!(quarantine.migrate().unwrap(), objects.len());
+        assert!(!incoming.exists());
+        assert_eq!(std::fs::read(repo.objects_dir().join("pack").join(&name)).unwrap(), pack);
+        assert!(repo.read_commit(&commit).is_ok());
+        assert_eq!(std::fs::read_dir(repo.mono_dir().join(QUARANTINE_DIR)).unwrap().count(), 0);
+    }
+}
@@ -0,0 +1,512 @@
+//! 对象存储的异步接口与有界并发的调度
+//!
+//! [`ObjectStore`] 及其后端保持同步接口：pack 生成、对象遍历与 receive-pack 都是逐个对象的同步代码，
+//! 全部改写为异步的代价大于收益。S3 后端的每次调用是一次 HTTP 请求，PostgreSQL 后端同样要等待数据库，
+//! 因此同步接口只能在阻塞线程中调用，不能在 tokio 的工作线程中直接调用，否则会阻塞同一线程上的其他连接。
+//!
+//! [`AsyncObjectStore`] 是对象存储的异步接口，[`ScheduledStore`] 把任意后端适配为该接口：调用在阻塞线程池中
+//! 执行，同一仓库同时进行的调用数由 [`FetchScheduler`] 按 `[storage] max_concurrency` 限制，慢后端不会占满
+//! 阻塞线程池，超出的调用排队等待。[`AsyncObjectStore::read_many`] 与 [`AsyncObjectStore::lfs_sizes`]
+//! 在限制内并发执行一批调用，远程后端上一批请求的耗时接近单次请求而不是逐个相加。
+//!
+//! 逐个对象遍历的协议处理（能力声明、upload-pack、receive-pack 以及 REST、GraphQL、gRPC 接口）在
+//! [`crate::server::blocking`] 中同步访问对象存储。[`crate::storage::open`] 把后端包装为 [`LimitedStore`]，
+//! 这些同步调用同样占用 [`FetchScheduler`] 的名额，名额用完时阻塞等待，因此上限覆盖了服务端的所有存储调用。
+//! 经由 [`ScheduledStore`] 进入的调用已经持有名额，不会重复获取；命中缓存的读取不经过后端，也不占用名额。
+//! 万一在多线程运行时的工作线程中等待名额，等待经由 [`tokio::task::block_in_place`] 进行，
+//! 该线程上的其他任务会转移到别的工作线程。
+
+use std::cell::RefCell;
+use std::fmt;
+use std::future::Future;
+use std::path::Path;
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+
+use async_trait::async_trait;
+use tokio::runtime::RuntimeFlavor;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore, TryAcquireError};
+use tokio::task::JoinSet;
+
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::lfs::{LfsObject, LfsOid};
+use crate::object::{ObjectFormat, ObjectId, ObjectType, RawObject};
+use crate::pack::index::PackIndex;
+use crate::storage::{ObjectStore, StoredObject};
+
+thread_local! {
+    /// 当前线程已持有名额的调度器，嵌套的存储调用不重复获取
+    static HELD: RefCell<Vec<usize>> = const { RefCell::new(Vec::new()) };
+}
+
+/// 对象存储的异步接口，语义与 [`ObjectStore`] 的同名方法相同
+#[async_trait]
+pub trait AsyncObjectStore: Send + Sync + fmt::Debug {
+    /// 写入对象时计算 ID 使用的对象格式
+    fn format(&self) -> ObjectFormat;
+
+    /// 是否存在指定对象
+    async fn contains(&self, id: &ObjectId) -> MonoResult<bool>;
+
+    /// 读取对象，不存在时返回 None
+    async fn read(&self, id: &ObjectId) -> MonoResult<Option<RawObject>>;
+
+    /// 读取对象的类型与大小
+    async fn read_header(&self, id: &ObjectId) -> MonoResult<Option<(ObjectType, usize)>>;
+
+    /// 写入对象并返回对象 ID
+    async fn write(&self, object_type: ObjectType, data: Vec<u8>) -> MonoResult<ObjectId>;
+
+    /// 并发读取一批对象，结果与 `ids` 的顺序一致
+    async fn read_many(&self, ids: &[ObjectId]) -> MonoResult<Vec<Option<RawObject>>>;
+
+    /// 读取 LFS 对象，不存在时返回 None
+    async fn read_lfs(&self, oid: &LfsOid) -> MonoResult<Option<Vec<u8>>>;
+
+    /// LFS 对象的大小，不存在时返回 None
+    async fn lfs_size(&self, oid: &LfsOid) -> MonoResult<Option<u64>>;
+
+    /// 并发查询一批 LFS 对象的大小，结果与 `oids` 的顺序一致
+    async fn lfs_sizes(&self, oids: &[LfsOid]) -> MonoResult<Vec<Option<u64>>>;
+
+    /// 写入 LFS 对象，调用方负责校验内容的 SHA-256
+    async fn write_lfs(&self, oid: &LfsOid, data: Vec<u8>) -> MonoResult<()>;
+}
+
+/// 限制同时进行的存储调用数，并在阻塞线程池中执行调用
+#[derive(Debug, Clone)]
+pub struct FetchScheduler {
+    permits: Arc<Semaphore>,
+    limit: usize,
+}
+
+impl FetchScheduler {
+    /// 最多同时进行 `limit` 个调用，至少为 1
+    pub fn new(limit: usize) -> FetchScheduler {
+        let limit = limit.max(1);
+        FetchScheduler { permits: Arc::new(Semaphore::new(limit)), limit }
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// 正在进行的调用数
+    pub fn in_flight(&self) -> usize {
+        self.limit - self.permits.available_permits()
+    }
+
+    /// 等到有空闲名额后在阻塞线程池中执行 `f`，沿用当前的 tracing span
+    pub async fn run<T, F>(&self, f: F) -> MonoResult<T>
+    where
+        F: FnOnce() -> MonoResult<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let permit = self
+            .permits
+            .clone()
+            .acquire_owned()
+            .await
+            .map_err(|_| MonoError::unavailable("storage scheduler is closed"))?;
+        let span = tracing::Span::current();
+        let key = self.key();
+        tokio::task::spawn_blocking(move || {
+            let _held = Held::enter(key, permit);
+            span.in_scope(f)
+        })
+        .await
+        .map_err(anyhow::Error::from)?
+    }
+
+    /// 在当前线程上阻塞直到有空闲名额；当前线程已持有名额时返回 None
+    ///
+    /// 有空闲名额时直接取得。需要等待且当前线程属于多线程运行时时，经由 `block_in_place` 等待，
+    /// 不会让工作线程上的其他任务一起停住。
+    fn hold(&self) -> MonoResult<Option<Held>> {
+        let key = self.key();
+        if HELD.with(|held| held.borrow().contains(&key)) {
+            return Ok(None);
+        }
+        let permit = match self.permits.clone().try_acquire_owned() {
+            Ok(permit) => permit,
+            Err(TryAcquireError::Closed) => return Err(MonoError::unavailable("storage scheduler is closed")),
+            Err(TryAcquireError::NoPermits) => {
+                let acquire = || block_on(self.permits.clone().acquire_owned());
+                let multi_thread = tokio::runtime::Handle::try_current()
+                    .is_ok_and(|handle| handle.runtime_flavor() == RuntimeFlavor::MultiThread);
+                match multi_thread {
+                    true => tokio::task::block_in_place(acquire),
+                    false => acquire(),
+                }
+                .map_err(|_| MonoError::unavailable("storage scheduler is closed"))?
+            }
+        };
+        Ok(Some(Held::enter(key, permit)))
+    }
+
+    /// 区分不同调度器的标识，克隆的调度器共享名额，标识也相同
+    fn key(&self) -> usize {
+        Arc::as_ptr(&self.permits) as usize
+    }
+}
+
+/// 当前线程持有的名额，释放时从 [`HELD`] 中移除
+struct Held {
+    key: usize,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl Held {
+    fn enter(key: usize, permit: OwnedSemaphorePermit) -> Held {
+        HELD.with(|held| held.borrow_mut().push(key));
+        Held { key, _permit: permit }
+    }
+}
+
+impl Drop for Held {
+    fn drop(&mut self) {
+        HELD.with(|held| {
+            let mut held = held.borrow_mut();
+            if let Some(pos) = held.iter().rposition(|&key| key == self.key) {
+                held.remove(pos);
+            }
+        });
+    }
+}
+
+/// 在当前线程上等待 `future` 完成，不依赖 tokio 运行时
+fn block_on<F: Future>(future: F) -> F::Output {
+    struct Unpark(std::thread::Thread);
+
+    impl Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    let waker = Waker::from(Arc::new(Unpark(std::thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    let mut future = std::pin::pin!(future);
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+        std::thread::park();
+    }
+}
+
+/// 每次调用都占用 [`FetchScheduler`] 名额的同步 [`ObjectStore`]，没有空闲名额时阻塞等待
+#[derive(Debug)]
+pub struct LimitedStore {
+    inner: Arc<dyn ObjectStore>,
+    scheduler: FetchScheduler,
+}
+
+impl LimitedStore {
+    pub fn new(inner: Arc<dyn ObjectStore>, scheduler: FetchScheduler) -> LimitedStore {
+        LimitedStore { inner, scheduler }
+    }
+
+    /// 持有名额执行一次存储调用
+    fn call<T>(&self, f: impl FnOnce(&dyn ObjectStore) -> MonoResult<T>) -> MonoResult<T> {
+        let _held = self.scheduler.hold()?;
+        f(self.inner.as_ref())
+    }
+}
+
+impl ObjectStore for LimitedStore {
+    fn format(&self) -> ObjectFormat {
+        self.inner.format()
+    }
+
+    fn contains(&self, id: &ObjectId) -> MonoResult<bool> {
+        self.call(|store| store.contains(id))
+    }
+
+    fn read(&self, id: &ObjectId) -> MonoResult<Option<RawObject>> {
+        self.call(|store| store.read(id))
+    }
+
+    fn read_header(&self, id: &ObjectId) -> MonoResult<Option<(ObjectType, usize)>> {
+        self.call(|store| store.read_header(id))
+    }
+
+    fn write(&self, object_type: ObjectType, data: &[u8]) -> MonoResult<ObjectId> {
+        self.call(|store| store.write(object_type, data))
+    }
+
+    fn list(&self) -> MonoResult<Vec<ObjectId>> {
+        self.call(|store| store.list())
+    }
+
+    fn list_stored(&self) -> MonoResult<Vec<StoredObject>> {
+        self.call(|store| store.list_stored())
+    }
+
+    fn delete(&self, ids: &[ObjectId]) -> MonoResult<()> {
+        self.call(|store| store.delete(ids))
+    }
+
+    fn write_pack(&self, pack: &[u8]) -> MonoResult<usize> {
+        self.call(|store| store.write_pack(pack))
+    }
+
+    fn write_pack_file(&self, path: &Path, index: Option<&PackIndex>) -> MonoResult<usize> {
+        self.call(|store| store.write_pack_file(path, index))
+    }
+
+    fn read_lfs(&self, oid: &LfsOid) -> MonoResult<Option<Vec<u8>>> {
+        self.call(|store| store.read_lfs(oid))
+    }
+
+    fn lfs_size(&self, oid: &LfsOid) -> MonoResult<Option<u64>> {
+        self.call(|store| store.lfs_size(oid))
+    }
+
+    fn write_lfs(&self, oid: &LfsOid, data: &[u8]) -> MonoResult<()> {
+        self.call(|store| store.write_lfs(oid, data))
+    }
+
+    fn list_lfs(&self) -> MonoResult<Vec<LfsObject>> {
+        self.call(|store| store.list_lfs())
+    }
+
+    fn delete_lfs(&self, oid: &LfsOid) -> MonoResult<()> {
+        self.call(|store| store.delete_lfs(oid))
+    }
+}
+
+/// 经由 [`FetchScheduler`] 访问阻塞后端的 [`AsyncObjectStore`]
+#[derive(Debug, Clone)]
+pub struct ScheduledStore {
+    store: Arc<dyn ObjectStore>,
+    scheduler: FetchScheduler,
+}
+
+impl ScheduledStore {
+    pub fn new(store: Arc<dyn ObjectStore>, scheduler: FetchScheduler) -> ScheduledStore {
+        ScheduledStore { store, scheduler }
+    }
+
+    /// 在调度器中执行一次存储调用
+    async fn call<T, F>(&self, f: F) -> MonoResult<T>
+    where
+        F: FnOnce(&dyn ObjectStore) -> MonoResult<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let store = self.store.clone();
+        self.scheduler.run(move || f(store.as_ref())).await
+    }
+
+    /// 对每一项并发执行 `f`，结果与 `items` 的顺序一致；任一调用失败时返回该错误
+    async fn call_many<I, T>(&self, items: Vec<I>, f: fn(&dyn ObjectStore, I) -> MonoResult<T>) -> MonoResult<Vec<T>>
+    where
+        I: Send + 'static,
+        T: Send + 'static,
+    {
+        let mut tasks = JoinSet::new();
+        for (index, item) in items.into_iter().enumerate() {
+            let store = self.clone();
+            tasks.spawn(async move { (index, store.call(move |store| f(store, item)).await) });
+        }
+        let mut results: Vec<Option<T>> = std::iter::repeat_with(|| None).take(tasks.len()).collect();
+        while let Some(joined) = tasks.join_next().await {
+            let (index, result) = joined.map_err(anyhow::Error::from)?;
+            results[index] = Some(result?);
+        }
+        Ok(results.into_iter().flatten().collect())
+    }
+}
+
+#[async_trait]
+impl AsyncObjectStore for ScheduledStore {
+    fn format(&self) -> ObjectFormat {
+        self.store.format()
+    }
+
+    async fn contains(&self, id: &ObjectId) -> MonoResult<bool> {
+        let id = *id;
+        self.call(move |store| store.contains(&id)).await
+    }
+
+    async fn read(&self, id: &ObjectId) -> MonoResult<Option<RawObject>> {
+        let id = *id;
+        self.call(move |store| store.read(&id)).await
+    }
+
+    async fn read_header(&self, id: &ObjectId) -> MonoResult<Option<(ObjectType, usize)>> {
+        let id = *id;
+        self.call(move |store| store.read_header(&id)).await
+    }
+
+    async fn write(&self, object_type: ObjectType, data: Vec<u8>) -> MonoResult<ObjectId> {
+        self.call(move |store| store.write(object_type, &data)).await
+    }
+
+    async fn read_many(&self, ids: &[ObjectId]) -> MonoResult<Vec<Option<RawObject>>> {
+        self.call_many(ids.to_vec(), |store, id| store.read(&id)).await
+    }
+
+    async fn read_lfs(&self, oid: &LfsOid) -> MonoResult<Option<Vec<u8>>> {
+        let oid = *oid;
+        self.call(move |store| store.read_lfs(&oid)).await
+    }
+
+    async fn lfs_size(&self, oid: &LfsOid) -> MonoResult<Option<u64>> {
+        let oid = *oid;
+        self.call(move |store| store.lfs_size(&oid)).await
+    }
+
+    async fn lfs_sizes(&self, oids: &[LfsOid]) -> MonoResult<Vec<Option<u64>>> {
+        self.call_many(oids.to_vec(), |store, oid| store.lfs_size(&oid)).await
+    }
+
+    async fn write_lfs(&self, oid: &LfsOid, data: Vec<u8>) -> MonoResult<()> {
+        let oid = *oid;
+        self.call(move |store| store.write_lfs(&oid, &data)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    use super::*;
+    use crate::storage::memory::MemoryStore;
+
+    /// 记录同时进行的读取数的存储
+    #[derive(Debug, Default)]
+    struct SlowStore {
+        inner: MemoryStore,
+        active: AtomicUsize,
+        peak: AtomicUsize,
+    }
+
+    impl ObjectStore for SlowStore {
+        fn format(&self) -> ObjectFormat {
+            self.inner.format()
+        }
+
+        fn contains(&self, id: &ObjectId) -> MonoResult<bool> {
+            self.inner.contains(id)
+        }
+
+        fn read(&self, id: &ObjectId) -> MonoResult<Option<RawObject>> {
+            let active = self.active.fetch_add(1, Ordering::SeqCst) + 1;
+            self.peak.fetch_max(active, Ordering::SeqCst);
+            std::thread::sleep(Duration::from_millis(20));
+            self.active.fetch_sub(1, Ordering::SeqCst);
+            self.inner.read(id)
+        }
+
+        fn write(&self, object_type: ObjectType, data: &[u8]) -> MonoResult<ObjectId> {
+            self.inner.write(object_type, data)
+        }
+
+        fn list(&self) -> MonoResult<Vec<ObjectId>> {
+            self.inner.list()
+        }
+
+        fn list_stored(&self) -> MonoResult<Vec<StoredObject>> {
+            self.inner.list_stored()
+        }
+
+        fn delete(&self, ids: &[ObjectId]) -> MonoResult<()> {
+            self.inner.delete(ids)
+        }
+
+        fn read_lfs(&self, oid: &LfsOid) -> MonoResult<Option<Vec<u8>>> {
+            self.inner.read_lfs(oid)
+        }
+
+        fn write_lfs(&self, oid: &LfsOid, data: &[u8]) -> MonoResult<()> {
+            self.inner.write_lfs(oid, data)
+        }
+
+        fn list_lfs(&self) -> MonoResult<Vec<LfsObject>> {
+            self.inner.list_lfs()
+        }
+
+        fn delete_lfs(&self, oid: &LfsOid) -> MonoResult<()> {
+            self.inner.delete_lfs(oid)
+        }
+    }
+
+    /// 测试批量读取的结果顺序与并发上限
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_read_many() {
+        let slow = Arc::new(SlowStore::default());
+        let store = ScheduledStore::new(slow.clone(), FetchScheduler::new(3));
+        let mut ids = Vec::new();
+        for i in 0..12 {
+            ids.push(store.write(ObjectType::Blob, format!("blob {}", i).into_bytes()).await.unwrap());
+        }
+        let missing = ObjectId::hash_object(ObjectType::Blob, b"missing");
+        ids.insert(5, missing);
+
+        let objects = store.read_many(&ids).await.unwrap();
+        assert_eq!(objects.len(), 13);
+        assert!(objects[5].is_none());
+        assert_eq!(objects[0].as_ref().unwrap().data, b"blob 0");
+        assert_eq!(objects[12].as_ref().unwrap().data, b"blob 11");
+        assert_eq!(slow.peak.load(Ordering::SeqCst), 3);
+        assert_eq!(store.scheduler.in_flight(), 0);
+
+        let oid = LfsOid::hash(b"large");
+        store.write_lfs(&oid, b"large".to_vec()).await.unwrap();
+        let sizes = store.lfs_sizes(&[oid, LfsOid::hash(b"other")]).await.unwrap();
+        assert_eq!(sizes, [Some(5), None]);
+        assert_eq!(store.read_lfs(&oid).await.unwrap().unwrap(), b"large");
+    }
+
+    /// 测试多个线程同步调用时不超过并发上限，经由异步接口嵌套进入时不重复获取名额
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_limited_store() {
+        let slow = Arc::new(SlowStore::default());
+        let scheduler = FetchScheduler::new(2);
+        let limited = Arc::new(LimitedStore::new(slow.clone(), scheduler.clone()));
+        let id = limited.write(ObjectType::Blob, b"blob").unwrap();
+
+        let threads: Vec<_> = (0..6)
+            .map(|_| {
+                let limited = limited.clone();
+                std::thread::spawn(move || limited.read(&id).unwrap().unwrap().data)
+            })
+            .collect();
+        for thread in threads {
+            assert_eq!(thread.join().unwrap(), b"blob");
+        }
+        assert_eq!(slow.peak.load(Ordering::SeqCst), 2);
+        assert_eq!(scheduler.in_flight(), 0);
+
+        // 上限为 1 时，已持有名额的调用再经过 LimitedStore 不会死锁
+        let scheduler = FetchScheduler::new(1);
+        let limited = Arc::new(LimitedStore::new(slow.clone(), scheduler.clone()));
+        let store = ScheduledStore::new(limited, scheduler.clone());
+        let objects = store.read_many(&[id, id, id]).await.unwrap();
+        assert!(objects.iter().all(|object| object.as_ref().unwrap().data == b"blob"));
+        assert_eq!(scheduler.in_flight(), 0);
+    }
+
+    /// 测试在工作线程中同步调用并等待名额时，同一工作线程上的其他任务仍能执行
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn test_limited_store_on_worker() {
+        let scheduler = FetchScheduler::new(1);
+        let limited = Arc::new(LimitedStore::new(Arc::new(MemoryStore::new()), scheduler.clone()));
+        let id = limited.write(ObjectType::Blob, b"blob").unwrap();
+        let permit = scheduler.permits.clone().try_acquire_owned().unwrap();
+
+        let read = tokio::spawn(async move { limited.read(&id).unwrap().unwrap().data });
+        // 唯一的工作线程等待名额时，释放名额的任务必须能在别的线程上执行
+        let release = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            drop(permit);
+        });
+        let data = tokio::time::timeout(Duration::from_secs(5), read).await.unwrap().unwrap();
+        assert_eq!(data, b"blob");
+        release.await.unwrap();
+        assert_eq!(scheduler.in_flight(), 0);
+    }
+}
@@ -0,0 +1,332 @@
+//! 把子模块迁移为子树
+//!
+//! 从 git 导入的仓库可能用子模块引用其他仓库，单仓库中的检出、搜索与 blame 都看不到子模块的内容。
+//! [`migrate`] 把分支上 `.gitmodules` 列出的每个子模块换成其固定提交的树，子模块的历史按
+//! [`crate::compose`] 的方式改写到子目录下，作为迁移提交的其他父提交接入分支，`log` 与 `blame`
+//! 仍能看到原始的作者信息。
+//!
+//! 迁移后的子模块成为 `[[compose.components]]` 中的组件，并记录固定提交为上次同步的位置，
+//! 之后用 `mono compose sync` 与原仓库双向同步。子模块中嵌套的子模块保持原样。
+
+use crate::audit::{self, AuditAction, AuditLog};
+use crate::common::config::ComponentConfig;
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::compose::{Composer, SyncPoint};
+use crate::object::commit::{Commit, Signature};
+use crate::object::filter::ObjectFilter;
+use crate::object::tree::{FileMode, TreeEdit};
+use crate::object::{ObjectId, ObjectType};
+use crate::refs::{self, RefUpdate};
+use crate::repo::Repository;
+use crate::rewrite::normalize_prefix;
+use crate::transport;
+
+/// 子模块的配置文件
+pub const GITMODULES: &str = ".gitmodules";
+
+/// `.gitmodules` 中的一个子模块
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Submodule {
+    pub name: String,
+    pub path: String,
+    pub url: String,
+    /// 跟踪的分支，未指定时为 None
+    pub branch: Option<String>,
+}
+
+/// 一个迁移完成的子模块
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MigratedSubmodule {
+    pub path: String,
+    /// 解析相对地址后的仓库地址
+    pub url: String,
+    /// 子模块固定的提交
+    pub commit: ObjectId,
+    /// 导入的提交数
+    pub imported: usize,
+}
+
+/// 一次迁移的结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Migration {
+    /// 迁移提交
+    pub commit: ObjectId,
+    pub submodules: Vec<MigratedSubmodule>,
+}
+
+/// 解析 `.gitmodules`：`[submodule "名称"]` 段中的 `path`、`url` 与 `branch`
+///
+/// 缺少 `path` 或 `url` 的子模块报错；其他键被忽略。
+pub fn parse_gitmodules(content: &str) -> MonoResult<Vec<Submodule>> {
+    let mut submodules: Vec<Submodule> = Vec::new();
+    let mut current: Option<usize> = None;
+    for (number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        let invalid = || MonoError::usage(format!("{}:{}: invalid line: {}", GITMODULES, number + 1, line));
+        if let Some(section) = line.strip_prefix('[') {
+            let section = section.strip_suffix(']').ok_or_else(invalid)?.trim();
+            current = match section.strip_prefix("submodule") {
+                Some(name) => {
+                    submodules.push(Submodule {
+                        name: unquote(name.trim()).to_string(),
+                        path: String::new(),
+                        url: String::new(),
+                        branch: None,
+                    });
+                    Some(submodules.len() - 1)
+                }
+                None => None,
+            };
+            continue;
+        }
+        let (key, value) = line.split_once('=').ok_or_else(invalid)?;
+        let Some(index) = current else {
+            continue;
+        };
+        let value = unquote(value.trim()).to_string();
+        match key.trim().to_ascii_lowercase().as_str() {
+            "path" => submodules[index].path = value,
+            "url" => submodules[index].url = value,
+            "branch" => submodules[index].branch = Some(value),
+            _ => {}
+        }
+    }
+    for submodule in &submodules {
+        if submodule.path.is_empty() || submodule.url.is_empty() {
+            return Err(MonoError::usage(format!("submodule {} has no path or url", submodule.name)));
+        }
+    }
+    Ok(submodules)
+}
+
+fn unquote(value: &str) -> &str {
+    value.strip_prefix('"').and_then(|v| v.strip_suffix('"')).unwrap_or(value)
+}
+
+/// 解析子模块的地址：`./` 或 `../` 开头的相对地址相对于上级仓库的地址 `base`
+pub fn resolve_url(url: &str, base: Option<&str>) -> MonoResult<String> {
+    if !url.starts_with("./") && !url.starts_with("../") {
+        return Ok(url.to_string());
+    }
+    let base = base.ok_or_else(|| MonoError::usage(format!("relative submodule url {} needs a base url", url)))?;
+    let mut resolved = base.trim_end_matches('/').to_string();
+    let mut rest = url;
+    loop {
+        if let Some(next) = rest.strip_prefix("./") {
+            rest = next;
+        } else if let Some(next) = rest.strip_prefix("../") {
+            let cut = resolved
+                .rfind('/')
+                .ok_or_else(|| MonoError::usage(format!("submodule url {} goes above {}", url, base)))?;
+            resolved.truncate(cut);
+            rest = next;
+        } else {
+            break;
+        }
+    }
+    Ok(format!("{}/{}", resolved, rest))
+}
+
+/// 把分支 `branch` 上的子模块迁移为子树，并登记为 compose 组件
+///
+/// 相对地址的子模块以 `base_url` 为上级仓库的地址。分支上的 `.gitmodules` 被删除，列出但树中
+/// 没有对应子模块的条目被忽略。compose 已经登记了其他分支的组件、或路径与已有组件重叠时报错，
+/// 此时分支与配置都不修改。
+pub fn migrate(
+    repo: &mut Repository,
+    branch: &str,
+    base_url: Option<&str>,
+    committer: &Signature,
+) -> MonoResult<Migration> {
+    let branch_ref = format!("{}{}", refs::HEADS_PREFIX, branch);
+    let head = repo
+        .refs()
+        .resolve(&branch_ref)?
+        .ok_or_else(|| MonoError::not_found(format!("branch {}", branch)))?;
+    let tree = repo.read_commit(&head)?.tree;
+    let gitmodules = repo
+        .find_path(&tree, GITMODULES)?
+        .ok_or_else(|| MonoError::not_found(format!("{} on {}", GITMODULES, branch)))?;
+    let content = repo.read_object(&gitmodules.id)?.data;
+    let submodules = parse_gitmodules(&String::from_utf8_lossy(&content))?;
+
+    let compose = &repo.config().compose;
+    if !compose.components.is_empty() && compose.branch != branch {
+        return Err(MonoError::usage(format!("compose components are already registered on {}", compose.branch)));
+    }
+    let mut migrated = Vec::new();
+    let mut components = Vec::new();
+    let mut edits = vec![TreeEdit {
+        path: GITMODULES.to_string(),
+        entry: None,
+    }];
+    let mut parents = vec![head];
+    let composer = Composer::new(repo);
+    for submodule in submodules {
+        let path = normalize_prefix(&submodule.path)?;
+        for component in &compose.components {
+            let other = normalize_prefix(&component.path)?;
+            let nested = |a: &str, b: &str| a == b || b.starts_with(&format!("{}/", a));
+            if nested(&other, &path) || nested(&path, &other) {
+                return Err(MonoError::usage(format!("//{} overlaps compose component //{}", path, other)));
+            }
+        }
+        let commit = match repo.find_path(&tree, &path)? {
+            Some(entry) if entry.mode == FileMode::GITLINK => entry.id,
+            _ => {
+                tracing::warn!(path, "submodule is not in the tree, skipping");
+                continue;
+            }
+        };
+        let url = resolve_url(&submodule.url, base_url)?;
+        if !repo.objects().contains(&commit)? {
+            let stats = transport::open(&url)?.fetch(&[commit], &[], &ObjectFilter::None, repo.objects())?;
+            tracing::info!(path, objects = stats.objects, "fetched submodule");
+        }
+        let (imported, count) = composer.adopt(&path, &commit)?;
+        edits.push(TreeEdit {
+            path: path.clone(),
+            entry: Some((FileMode::TREE, repo.read_commit(&commit)?.tree)),
+        });
+        parents.push(imported);
+        components.push(ComponentConfig {
+            path: path.clone(),
+            url: url.clone(),
+            branch: submodule.branch.clone(),
+        });
+        migrated.push(MigratedSubmodule {
+            path,
+            url,
+            commit,
+            imported: count,
+        });
+    }
+    if migrated.is_empty() {
+        return Err(MonoError::not_found(format!("submodules on {}", branch)));
+    }
+
+    let mut message = String::from("Migrate submodules to subtrees\n\n");
+    for submodule in &migrated {
+        message.push_str(&format!("//{}: {} at {}\n", submodule.path, submodule.url, submodule.commit));
+    }
+    let migration = Commit {
+        tree: repo.edit_tree(Some(&tree), &edits)?.ok_or_else(|| MonoError::usage("migration leaves an empty tree"))?,
+        parents,
+        author: committer.clone(),
+        committer: committer.clone(),
+        extra_headers: Vec::new(),
+        message,
+    };
+    let new = repo.write_object(ObjectType::Commit, &migration.encode())?;
+
+    let config = repo.config_mut();
+    config.compose.branch = branch.to_string();
+    config.compose.components.extend(components);
+    repo.save_config()?;
+
+    let update = RefUpdate {
+        name: branch_ref,
+        old: head,
+        new,
+    };
+    let actor = audit::local_actor();
+    repo.with_reflog_identity(&actor, "migrate submodules")
+        .refs()
+        .update(std::slice::from_ref(&update))?;
+    let now = chrono::Utc::now().timestamp();
+    AuditLog::new(repo).record_ref_updates(repo, &actor, AuditAction::RefUpdate, &[update], now);
+    let composer = Composer::new(repo);
+    for submodule in &migrated {
+        composer.save_point(
+            &submodule.path,
+            &SyncPoint {
+                upstream: submodule.commit,
+                mono: new,
+                synced_at: now,
+            },
+        )?;
+    }
+    Ok(Migration {
+        commit: new,
+        submodules: migrated,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::InitOptions;
+    use crate::test_utils::{commit_files, init_repo, write_tree};
+
+    /// 测试 `.gitmodules` 的解析与相对地址
+    #[test]
+    fn test_parse_gitmodules() {
+        let content = "# vendored\n[submodule \"lib\"]\n\tpath = third_party/lib\n\turl = ../lib.git\n\tbranch = stable\n[core]\n\tbare = false\n";
+        let submodules = parse_gitmodules(content).unwrap();
+        assert_eq!(
+            submodules,
+            [Submodule {
+                name: "lib".to_string(),
+                path: "third_party/lib".to_string(),
+                url: "../lib.git".to_string(),
+                branch: Some("stable".to_string()),
+            }]
+        );
+        assert!(parse_gitmodules("[submodule \"x\"]\n\turl = a\n").is_err());
+
+        let base = Some("https://git.example.com/org/app.git");
+        assert_eq!(resolve_url("../lib.git", base).unwrap(), "https://git.example.com/org/lib.git");
+        assert_eq!(resolve_url("./sub", base).unwrap(), "https://git.example.com/org/app.git/sub");
+        assert_eq!(resolve_url("/srv/lib", None).unwrap(), "/srv/lib");
+        assert!(resolve_url("../lib.git", None).is_err());
+    }
+
+    /// 测试子模块换成固定提交的树、历史接入分支，并登记为可同步的 compose 组件
+    #[test]
+    fn test_migrate() {
+        let (dir, mut repo) = init_repo();
+        let lib_root = dir.path().join("lib");
+        let lib = Repository::init(&lib_root, &InitOptions::default()).unwrap();
+        let first = commit_files(&lib, &[("lib.rs", b"1")], &[], "first");
+        let pinned = commit_files(&lib, &[("lib.rs", b"2")], &[first], "second");
+        lib.refs().write("refs/heads/main", &pinned).unwrap();
+
+        let gitmodules = format!("[submodule \"lib\"]\n\tpath = vendor/lib\n\turl = {}\n", lib_root.display());
+        let tree = write_tree(&repo, &[("README", b"app"), (GITMODULES, gitmodules.as_bytes())]);
+        let edit = TreeEdit {
+            path: "vendor/lib".to_string(),
+            entry: Some((FileMode::GITLINK, pinned)),
+        };
+        let tree = repo.edit_tree(Some(&tree), &[edit]).unwrap().unwrap();
+        let sig = Signature::new("Bot", "bot@example.com", 1_800_000_000);
+        let head = Commit {
+            tree,
+            parents: Vec::new(),
+            author: sig.clone(),
+            committer: sig.clone(),
+            extra_headers: Vec::new(),
+            message: "app\n".to_string(),
+        };
+        let head = repo.write_object(ObjectType::Commit, &head.encode()).unwrap();
+        repo.refs().write("refs/heads/main", &head).unwrap();
+
+        let migration = migrate(&mut repo, "main", None, &sig).unwrap();
+        assert_eq!((migration.submodules[0].commit, migration.submodules[0].imported), (pinned, 2));
+        assert_eq!(repo.refs().resolve("refs/heads/main").unwrap(), Some(migration.commit));
+        let commit = repo.read_commit(&migration.commit).unwrap();
+        assert_eq!(commit.parents[0], head);
+        assert_eq!(repo.read_commit(&commit.parents[1]).unwrap().message, "second\n");
+        assert!(repo.find_path(&commit.tree, GITMODULES).unwrap().is_none());
+        let file = repo.find_path(&commit.tree, "vendor/lib/lib.rs").unwrap().unwrap();
+        assert_eq!(repo.read_object(&file.id).unwrap().data, b"2");
+
+        assert_eq!(repo.config().compose.components[0].path, "vendor/lib");
+        let status = Composer::new(&repo).status().unwrap();
+        assert_eq!((status[0].local_changes, status[0].upstream_changes), (Some(false), Some(false)));
+        assert!(migrate(&mut repo, "main", None, &sig).is_err());
+    }
+}
@@ -0,0 +1,219 @@
+//! tracing 订阅器与 OpenTelemetry 导出
+//!
+//! 命令执行、协议处理与存储访问都记录在 `tracing` span 中：每个命令一个 `command` span，
+//! 服务端每个请求一个 `http.request`、`ssh.request` 或 `grpc.request` span，其下有 `ls_refs`、
+//! `fetch`、`receive_pack`、对象遍历与 pack 生成等阶段，存储后端的单次读写为 `debug` 级别。
+//!
+//! 在 `mono.toml` 中配置收集器后，span 通过 OTLP/HTTP 批量导出，运维可以据此端到端地
+//! 追踪慢 fetch：
+//!
+//! ```toml
+//! [telemetry]
+//! otlp_endpoint = "http://localhost:4318"
+//! service_name = "mono-eu"
+//! sample_ratio = 0.1
+//! ```
+//!
+//! 导出由 `otlp` feature 控制（默认开启），关闭后配置被忽略。启用调试信息（见
+//! [`debug_enabled`]）时订阅器同时采集错误的 span trace。日志事件按 [`crate::logging`]
+//! 的配置输出到标准错误或日志文件。
+
+use std::path::{Path, PathBuf};
+
+use tracing_appender::non_blocking::WorkerGuard;
+
+use crate::common::config::{Config, RepoConfig, TelemetryConfig};
+use crate::common::errors::debug_enabled;
+use crate::logging::LogOptions;
+use crate::repo::{CONFIG_FILE, MONO_DIR};
+
+/// 已安装的订阅器，释放时导出尚未发送的 span 并写完缓冲的日志
+#[must_use = "spans are only exported while the guard is alive"]
+#[derive(Default)]
+pub struct Telemetry {
+    #[cfg(feature = "otlp")]
+    provider: Option<opentelemetry_sdk::trace::SdkTracerProvider>,
+    /// 只在释放时使用
+    _log: Option<WorkerGuard>,
+}
+
+impl Drop for Telemetry {
+    fn drop(&mut self) {
+        #[cfg(feature = "otlp")]
+        if let Some(provider) = self.provider.take() {
+            if let Err(e) = provider.shutdown() {
+                tracing::warn!(error = %e, "failed to export traces");
+            }
+        }
+    }
+}
+
+/// 读取 `start` 所在工作区合并各配置层后的配置，返回 `.mono` 目录与配置；不在工作区中或配置无法
+/// 解析时返回 None
+///
+/// 配置错误由命令本身打开仓库时报告，这里不重复。
+pub fn find_config(start: &Path) -> Option<(PathBuf, RepoConfig)> {
+    let mono_dir = start
+        .ancestors()
+        .map(|dir| dir.join(MONO_DIR))
+        .find(|dir| dir.join(CONFIG_FILE).is_file())?;
+    let config = Config::load(Some(&mono_dir)).and_then(|config| config.repo_config()).ok()?;
+    Some((mono_dir, config))
+}
+
+/// OTLP/HTTP 的 trace 接收地址：未指定路径时追加 `/v1/traces`
+pub fn traces_url(endpoint: &str) -> String {
+    let has_path = endpoint
+        .split_once("://")
+        .map_or(endpoint, |(_, rest)| rest)
+        .split_once('/')
+        .is_some_and(|(_, path)| !path.is_empty());
+    if has_path {
+        endpoint.to_string()
+    } else {
+        format!("{}/v1/traces", endpoint.trim_end_matches('/'))
+    }
+}
+
+/// 安装全局订阅器，进程中只有第一次调用生效
+///
+/// 日志无法写入配置的目录时改为输出到标准错误。
+pub fn init(config: Option<&TelemetryConfig>, log: &LogOptions) -> Telemetry {
+    use tracing_subscriber::layer::SubscriberExt;
+
+    let errors = debug_enabled().then(tracing_error::ErrorLayer::default);
+    let mut warnings = Vec::new();
+    let (logs, guard) = match log.layer() {
+        Ok(layer) => layer,
+        Err(e) => {
+            warnings.push(format!("logging to stderr: {}", e));
+            let stderr = LogOptions {
+                output: crate::logging::LogOutput::Stderr,
+                ..log.clone()
+            };
+            stderr.layer().expect("stderr log layer cannot fail")
+        }
+    };
+    #[cfg(feature = "otlp")]
+    let provider = {
+        let (provider, export) = match config.map(otlp::layer) {
+            Some(Ok(Some((provider, layer)))) => (Some(provider), Some(layer)),
+            Some(Err(e)) => {
+                warnings.push(format!("trace export disabled: {}", e));
+                (None, None)
+            }
+            _ => (None, None),
+        };
+        let subscriber = tracing_subscriber::registry().with(errors).with(logs).with(export);
+        if tracing::subscriber::set_global_default(subscriber).is_err() {
+            // 已安装过订阅器，本次创建的导出器不会收到 span
+            return Telemetry::default();
+        }
+        provider
+    };
+    #[cfg(not(feature = "otlp"))]
+    {
+        let _ = config;
+        let subscriber = tracing_subscriber::registry().with(errors).with(logs);
+        if tracing::subscriber::set_global_default(subscriber).is_err() {
+            return Telemetry::default();
+        }
+    }
+    for warning in warnings {
+        tracing::warn!("{}", warning);
+    }
+    Telemetry {
+        #[cfg(feature = "otlp")]
+        provider,
+        _log: guard,
+    }
+}
+
+#[cfg(feature = "otlp")]
+mod otlp {
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    use opentelemetry::trace::TracerProvider;
+    use opentelemetry_otlp::{WithExportConfig, WithHttpConfig};
+    use opentelemetry_sdk::trace::{Sampler, SdkTracerProvider};
+    use opentelemetry_sdk::Resource;
+    use tracing_subscriber::filter::LevelFilter;
+    use tracing_subscriber::registry::LookupSpan;
+    use tracing_subscriber::Layer;
+
+    use crate::common::config::TelemetryConfig;
+    use crate::common::errors::MonoError;
+    use crate::common::MonoResult;
+
+    /// 导出请求的超时时间
+    const EXPORT_TIMEOUT: Duration = Duration::from_secs(10);
+
+    type OtlpLayer<S> = Box<dyn Layer<S> + Send + Sync>;
+
+    /// 按配置创建导出 span 的层，未配置收集器时返回 None
+    pub fn layer<S>(config: &TelemetryConfig) -> MonoResult<Option<(SdkTracerProvider, OtlpLayer<S>)>>
+    where
+        S: tracing::Subscriber + for<'a> LookupSpan<'a> + Send + Sync,
+    {
+        let Some(endpoint) = &config.otlp_endpoint else {
+            return Ok(None);
+        };
+        let level: LevelFilter = config
+            .level
+            .parse()
+            .map_err(|_| MonoError::config(format!("invalid telemetry level: {}", config.level)))?;
+        if !(0.0..=1.0).contains(&config.sample_ratio) {
+            return Err(MonoError::config(format!(
+                "telemetry sample_ratio must be between 0 and 1, got {}",
+                config.sample_ratio
+            )));
+        }
+        let headers: HashMap<String, String> = config.headers.clone().into_iter().collect();
+        let exporter = opentelemetry_otlp::SpanExporter::builder()
+            .with_http()
+            .with_endpoint(super::traces_url(endpoint))
+            .with_headers(headers)
+            .with_timeout(EXPORT_TIMEOUT)
+            .build()
+            .map_err(|e| MonoError::config(format!("otlp exporter: {}", e)))?;
+        let provider = SdkTracerProvider::builder()
+            .with_batch_exporter(exporter)
+            .with_sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(config.sample_ratio))))
+            .with_resource(
+                Resource::builder()
+                    .with_service_name(config.service_name.clone())
+                    .build(),
+            )
+            .build();
+        let tracer = provider.tracer(env!("CARGO_PKG_NAME"));
+        let layer = tracing_opentelemetry::layer().with_tracer(tracer).with_filter(level).boxed();
+        Ok(Some((provider, layer)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::init_repo;
+
+    /// 测试收集器地址的补全与配置的查找
+    #[test]
+    fn test_telemetry_config() {
+        assert_eq!(traces_url("http://localhost:4318"), "http://localhost:4318/v1/traces");
+        assert_eq!(traces_url("http://localhost:4318/"), "http://localhost:4318/v1/traces");
+        assert_eq!(traces_url("https://otel.example.com/api/traces"), "https://otel.example.com/api/traces");
+
+        let (dir, mut repo) = init_repo();
+        let (mono_dir, config) = find_config(dir.path()).unwrap();
+        assert_eq!(mono_dir, dir.path().join(MONO_DIR));
+        assert_eq!(config.telemetry, TelemetryConfig::default());
+        repo.config_mut().telemetry.otlp_endpoint = Some("http://localhost:4318".to_string());
+        repo.save_config().unwrap();
+        let nested = dir.path().join("a/b");
+        std::fs::create_dir_all(&nested).unwrap();
+        let config = find_config(&nested).unwrap().1.telemetry;
+        assert_eq!(config.otlp_endpoint.as_deref(), Some("http://localhost:4318"));
+        assert_eq!(config.service_name, "monoengine");
+    }
+}
@@ -0,0 +1,103 @@
+//! 单元测试共用的辅助函数
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicI64, Ordering};
+
+use crate::auth::Access;
+use crate::common::config::{AclConfig, Scope};
+use crate::object::commit::{Commit, Signature};
+use crate::object::tree::{FileMode, Tree, TreeEntry};
+use crate::object::{ObjectId, ObjectType};
+use crate::repo::{InitOptions, Repository};
+
+/// 在临时目录中初始化仓库，返回的 TempDir 需要在测试期间保持存活
+pub fn init_repo() -> (tempfile::TempDir, Repository) {
+    let dir = tempfile::tempdir().unwrap();
+    let repo = Repository::init(dir.path(), &InitOptions::default()).unwrap();
+    (dir, repo)
+}
+
+/// 由 `path -> content` 列表写入嵌套的树对象，返回根树 ID
+pub fn write_tree(repo: &Repository, files: &[(&str, &[u8])]) -> ObjectId {
+    #[derive(Default)]
+    struct Dir {
+        files: BTreeMap<String, ObjectId>,
+        dirs: BTreeMap<String, Dir>,
+    }
+
+    fn write(repo: &Repository, dir: &Dir) -> ObjectId {
+        let mut tree = Tree::default();
+        for (name, id) in &dir.files {
+            tree.entries.push(TreeEntry::new(FileMode::BLOB, name.clone(), *id));
+        }
+        for (name, sub) in &dir.dirs {
+            tree.entries.push(TreeEntry::new(FileMode::TREE, name.clone(), write(repo, sub)));
+        }
+        tree.sort();
+        repo.write_object(ObjectType::Tree, &tree.encode()).unwrap()
+    }
+
+    let mut root = Dir::default();
+    for (path, content) in files {
+        let blob = repo.write_object(ObjectType::Blob, content).unwrap();
+        let mut components: Vec<&str> = path.split('/').collect();
+        let name = components.pop().unwrap();
+        let mut dir = &mut root;
+        for component in components {
+            dir = dir.dirs.entry(component.to_string()).or_default();
+        }
+        dir.files.insert(name.to_string(), blob);
+    }
+    write(repo, &root)
+}
+
+/// [`commit_files`] 下一次使用的时间戳
+static NEXT_TIMESTAMP: AtomicI64 = AtomicI64::new(1_700_000_000);
+
+/// 写入一个包含给定文件的提交，时间戳依次递增以保证提交 ID 不同
+pub fn commit_files(
+    repo: &Repository,
+    files: &[(&str, &[u8])],
+    parents: &[ObjectId],
+    message: &str,
+) -> ObjectId {
+    let tree = write_tree(repo, files);
+    let timestamp = NEXT_TIMESTAMP.fetch_add(60, Ordering::Relaxed);
+    let sig = Signature::new("A U Thor", "author@example.com", timestamp);
+    let commit = Commit {
+        tree,
+        parents: parents.to_vec(),
+        author: sig.clone(),
+        committer: sig,
+        extra_headers: Vec::new(),
+        message: format!("{}\n", message),
+    };
+    repo.write_object(ObjectType::Commit, &commit.encode()).unwrap()
+}
+
+/// 只有 `user:alice` 能读取 `//secrets/...` 与 `refs/heads/private`；返回 main 与 private 分支指向的提交，
+/// private 的提交不能从 main 到达
+pub fn restrict_secrets(repo: &mut Repository) -> (ObjectId, ObjectId) {
+    let main = commit_files(repo, &[("README.md", b"hello"), ("secrets/key", b"hunter2")], &[], "main");
+    let private = commit_files(repo, &[("README.md", b"hello"), ("private.txt", b"p")], &[main], "private");
+    repo.refs().write("refs/heads/main", &main).unwrap();
+    repo.refs().write("refs/heads/private", &private).unwrap();
+    let rule = |name: &str, refs: &[&str], paths: &[&str]| AclConfig {
+        name: name.to_string(),
+        refs: refs.iter().map(|s| s.to_string()).collect(),
+        paths: paths.iter().map(|s| s.to_string()).collect(),
+        read: vec!["user:alice".to_string()],
+        write: Vec::new(),
+    };
+    repo.config_mut().acl = vec![rule("secrets", &[], &["//secrets/..."]), rule("private", &["refs/heads/private"], &[])];
+    (main, private)
+}
+
+/// 只有 `read` 权限的身份
+pub fn reader(principal: &str) -> Access {
+    Access {
+        principal: principal.to_string(),
+        scope: Some(Scope::Read),
+        paths: Vec::new(),
+    }
+}
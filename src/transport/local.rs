@@ -0,0 +1,220 @@
+//! 本地路径传输：直接读取另一个仓库的对象和引用
+//!
+//! 除 MonoEngine 仓库外也支持普通的 git 仓库（含 `.git` 的工作目录或裸仓库），
+//! 两者的对象与引用格式相同，可以直接读取，便于将现有仓库导入 monorepo。
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use crate::audit::{self, AuditAction, AuditLog};
+use crate::common::errors::MonoError;
+use crate::common::progress::Progress;
+use crate::common::MonoResult;
+use crate::object::filter::ObjectFilter;
+use crate::object::shallow::{shallow_update, Deepen, ShallowUpdate};
+use crate::object::walk::{collect_objects, collect_shallow_objects};
+use crate::object::{ObjectFormat, ObjectId, ObjectType, RawObject};
+use crate::refs::{FileRefStore, RefStore, RefUpdate};
+use crate::repo::{Repository, MONO_DIR};
+use crate::storage::fs::FsStore;
+use crate::storage::ObjectStore;
+use crate::transport::{FetchStats, PushStats, RemoteRefs, Transport};
+
+/// 本地仓库的对象与引用
+#[derive(Debug)]
+enum Source {
+    Mono(Box<Repository>),
+    Git { objects: FsStore, refs: FileRefStore },
+}
+
+/// 指向本地仓库的传输
+#[derive(Debug)]
+pub struct LocalTransport {
+    source: Source,
+}
+
+impl LocalTransport {
+    /// 打开本地路径上的仓库
+    pub fn open(path: &str) -> MonoResult<LocalTransport> {
+        let path = Path::new(path.strip_prefix("file://").unwrap_or(path));
+        let source = match git_dir(path) {
+            Some(git_dir) if !path.join(MONO_DIR).is_dir() => Source::Git {
+                objects: FsStore::new(git_dir.join("objects")).with_format(git_object_format(&git_dir)?),
+                refs: FileRefStore::new(git_dir),
+            },
+            _ => Source::Mono(Box::new(Repository::open(path)?)),
+        };
+        Ok(LocalTransport { source })
+    }
+
+    fn refs(&self) -> &dyn RefStore {
+        match &self.source {
+            Source::Mono(repo) => repo.refs(),
+            Source::Git { refs, .. } => refs,
+        }
+    }
+
+    fn objects(&self) -> &dyn ObjectStore {
+        match &self.source {
+            Source::Mono(repo) => repo.objects(),
+            Source::Git { objects, .. } => objects,
+        }
+    }
+
+    fn read_object(&self, id: &ObjectId) -> MonoResult<RawObject> {
+        match &self.source {
+            Source::Mono(repo) => repo.read_object(id),
+            Source::Git { objects, .. } => objects
+                .read(id)?
+                .ok_or_else(|| MonoError::not_found(format!("object {}", id))),
+        }
+    }
+
+    /// 把本地存储中还没有的对象从远端复制过来
+    fn copy_objects(
+        &self,
+        objects: impl IntoIterator<Item = (ObjectId, ObjectType)>,
+        store: &dyn ObjectStore,
+    ) -> MonoResult<FetchStats> {
+        check_format(self.objects().format(), store.format())?;
+        let objects: Vec<_> = objects.into_iter().collect();
+        let progress = Progress::new("Receiving objects", objects.len() as u64);
+        let mut stats = FetchStats::default();
+        for (id, _) in objects {
+            if !store.contains(&id)? {
+                let object = self.read_object(&id)?;
+                store.write(object.object_type, &object.data)?;
+                stats.objects += 1;
+                progress.add_bytes(object.data.len() as u64);
+            }
+            progress.inc(1);
+        }
+        progress.finish();
+        Ok(stats)
+    }
+}
+
+/// git 仓库的对象格式，由 `config` 中的 `extensions.objectformat` 指定
+fn git_object_format(git_dir: &Path) -> MonoResult<ObjectFormat> {
+    let config = match std::fs::read_to_string(git_dir.join("config")) {
+        Ok(config) => config,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(ObjectFormat::Sha1),
+        Err(e) => return Err(e.into()),
+    };
+    let mut extensions = false;
+    for line in config.lines().map(str::trim) {
+        if line.starts_with('[') {
+            extensions = line.eq_ignore_ascii_case("[extensions]");
+        } else if let Some((key, value)) = line.split_once('=') {
+            if extensions && key.trim().eq_ignore_ascii_case("objectformat") {
+                return value.trim().to_ascii_lowercase().parse();
+            }
+        }
+    }
+    Ok(ObjectFormat::Sha1)
+}
+
+/// 两端的对象格式必须相同，本地传输不做兼容格式的转换
+fn check_format(source: ObjectFormat, destination: ObjectFormat) -> MonoResult<()> {
+    if source != destination {
+        return Err(MonoError::usage(format!(
+            "object format mismatch: source uses {}, destination uses {}",
+            source, destination
+        )));
+    }
+    Ok(())
+}
+
+/// git 仓库的元数据目录：工作目录下的 `.git`，或裸仓库本身
+fn git_dir(path: &Path) -> Option<PathBuf> {
+    let dot_git = path.join(".git");
+    if dot_git.is_dir() {
+        return Some(dot_git);
+    }
+    (path.join("HEAD").is_file() && path.join("objects").is_dir()).then(|| path.to_path_buf())
+}
+
+impl Transport for LocalTransport {
+    fn list_refs(&self) -> MonoResult<RemoteRefs> {
+        let store = self.refs();
+        Ok(RemoteRefs {
+            head: store.head_target()?,
+            refs: store.list("refs/")?,
+        })
+    }
+
+    #[tracing::instrument(name = "local.fetch", skip_all, fields(wants = wants.len()))]
+    fn fetch(
+        &self,
+        wants: &[ObjectId],
+        haves: &[ObjectId],
+        filter: &ObjectFilter,
+        store: &dyn ObjectStore,
+    ) -> MonoResult<FetchStats> {
+        let mut reader = |id: &ObjectId| self.read_object(id);
+        let objects = collect_objects(wants, haves, filter, &mut reader)?;
+        self.copy_objects(objects, store)
+    }
+
+    #[tracing::instrument(name = "local.fetch_shallow", skip_all, fields(wants = wants.len(), deepen = ?deepen))]
+    fn fetch_shallow(
+        &self,
+        wants: &[ObjectId],
+        haves: &[ObjectId],
+        shallow: &HashSet<ObjectId>,
+        deepen: Option<Deepen>,
+        filter: &ObjectFilter,
+        store: &dyn ObjectStore,
+    ) -> MonoResult<(FetchStats, ShallowUpdate)> {
+        let mut reader = |id: &ObjectId| self.read_object(id);
+        let update = match deepen {
+            Some(deepen) => shallow_update(wants, deepen, shallow, &mut reader)?,
+            None => ShallowUpdate::default(),
+        };
+        let mut tips = wants.to_vec();
+        tips.extend(update.extra_tips(&mut reader)?);
+        let boundary = update.apply(shallow);
+        let (objects, _) = collect_shallow_objects(&tips, haves, boundary, shallow.clone(), filter, None, &mut reader)?;
+        let stats = self.copy_objects(objects.into_iter().map(|(id, object_type, _)| (id, object_type)), store)?;
+        Ok((stats, update))
+    }
+
+    fn fetch_object(&self, id: &ObjectId) -> MonoResult<RawObject> {
+        self.read_object(id)
+    }
+
+    #[tracing::instrument(name = "local.push", skip_all, fields(updates = updates.len()))]
+    fn push(&self, updates: &[RefUpdate], store: &dyn ObjectStore) -> MonoResult<PushStats> {
+        check_format(store.format(), self.objects().format())?;
+        let wants: Vec<ObjectId> = updates.iter().filter(|u| !u.is_delete()).map(|u| u.new).collect();
+        // 远端已有且本地也有的提交作为边界，不必遍历远端已有的历史
+        let mut haves = Vec::new();
+        for (_, id) in self.refs().list("refs/")? {
+            if store.contains(&id)? {
+                haves.push(id);
+            }
+        }
+        let read = |id: &ObjectId| store.read(id)?.ok_or_else(|| MonoError::not_found(format!("object {}", id)));
+        let objects = collect_objects(&wants, &haves, &ObjectFilter::None, &mut |id: &ObjectId| read(id))?;
+        let remote = self.objects();
+        let mut stats = PushStats::default();
+        for (id, _) in objects {
+            if remote.contains(&id)? {
+                continue;
+            }
+            let object = read(&id)?;
+            remote.write(object.object_type, &object.data)?;
+            stats.objects += 1;
+        }
+        match &self.source {
+            Source::Mono(repo) => {
+                let actor = audit::local_actor();
+                repo.with_reflog_identity(&actor, "push").refs().update(updates)?;
+                let now = chrono::Utc::now().timestamp();
+                AuditLog::new(repo).record_ref_updates(repo, &actor, AuditAction::Push, updates, now);
+            }
+            Source::Git { refs, .. } => refs.update(updates)?,
+        }
+        Ok(stats)
+    }
+}
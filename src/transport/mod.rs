@@ -0,0 +1,77 @@
+//! 与远端仓库通信的传输层
+//!
+//! 不同的远端地址（本地路径、HTTP 等）通过 [`Transport`] 提供统一的引用列举、对象获取与推送能力。
+
+pub mod local;
+
+use std::collections::HashSet;
+
+use crate::common::MonoResult;
+use crate::object::filter::ObjectFilter;
+use crate::object::shallow::{Deepen, ShallowUpdate};
+use crate::object::{ObjectId, RawObject};
+use crate::refs::RefUpdate;
+use crate::storage::ObjectStore;
+
+/// 远端的引用快照
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RemoteRefs {
+    /// 远端 HEAD 指向的分支全名
+    pub head: Option<String>,
+    /// 远端的全部直接引用
+    pub refs: Vec<(String, ObjectId)>,
+}
+
+/// 一次获取的统计信息
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FetchStats {
+    /// 本地新写入的对象数
+    pub objects: usize,
+}
+
+/// 一次推送的统计信息
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PushStats {
+    /// 远端新写入的对象数
+    pub objects: usize,
+}
+
+/// 远端传输
+pub trait Transport {
+    /// 列出远端的引用
+    fn list_refs(&self) -> MonoResult<RemoteRefs>;
+
+    /// 获取从 `wants` 可达、从 `haves` 不可达的对象，并写入本地存储
+    fn fetch(
+        &self,
+        wants: &[ObjectId],
+        haves: &[ObjectId],
+        filter: &ObjectFilter,
+        store: &dyn ObjectStore,
+    ) -> MonoResult<FetchStats>;
+
+    /// 浅获取：按 `deepen` 限制历史深度，`shallow` 为本地现有的浅提交
+    ///
+    /// 本地是浅仓库时即使不限制深度也要使用该方法，否则远端会以为本地有浅提交之下的历史。
+    /// 返回统计信息与本地浅提交的变化。
+    fn fetch_shallow(
+        &self,
+        wants: &[ObjectId],
+        haves: &[ObjectId],
+        shallow: &HashSet<ObjectId>,
+        deepen: Option<Deepen>,
+        filter: &ObjectFilter,
+        store: &dyn ObjectStore,
+    ) -> MonoResult<(FetchStats, ShallowUpdate)>;
+
+    /// 获取单个对象，用于部分克隆按需补全缺失的对象
+    fn fetch_object(&self, id: &ObjectId) -> MonoResult<RawObject>;
+
+    /// 将本地存储中新值可达的对象写入远端，再按旧值校验原子地更新远端引用
+    fn push(&self, updates: &[RefUpdate], store: &dyn ObjectStore) -> MonoResult<PushStats>;
+}
+
+/// 根据远端地址打开传输
+pub fn open(url: &str) -> MonoResult<Box<dyn Transport>> {
+    Ok(Box::new(local::LocalTransport::open(url)?))
+}
@@ -0,0 +1,306 @@
+//! 交互式终端界面
+//!
+//! `mono tui` 在一个屏幕上显示引用、所选引用的提交历史、所选提交的差异与合并队列，运维人员
+//! 不必打开 Web 界面即可查看引擎的状态。
+//!
+//! 本模块只维护界面状态并处理按键，与具体的终端库无关；启用 `tui` 特性后由 `terminal` 子模块
+//! 基于 ratatui 绘制界面并读取按键。
+
+#[cfg(feature = "tui")]
+pub mod terminal;
+
+use chrono::DateTime;
+
+use crate::common::MonoResult;
+use crate::diff::tree::{diff_files, write_patch, DiffOptions};
+use crate::graph::history::History;
+use crate::object::ObjectId;
+use crate::queue::{MergeQueue, QueueEntry};
+use crate::refs::{self, RefTarget};
+use crate::repo::Repository;
+
+/// 提交历史面板最多加载的提交数
+pub const HISTORY_LIMIT: usize = 500;
+
+/// 翻页时移动的行数
+const PAGE: usize = 10;
+
+/// 面板，按 Tab 切换的顺序排列
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pane {
+    Refs,
+    History,
+    Diff,
+    Queue,
+}
+
+impl Pane {
+    pub const ALL: [Pane; 4] = [Pane::Refs, Pane::History, Pane::Diff, Pane::Queue];
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            Pane::Refs => "Refs",
+            Pane::History => "History",
+            Pane::Diff => "Diff",
+            Pane::Queue => "Merge queue",
+        }
+    }
+
+    fn offset(self, delta: isize) -> Pane {
+        let i = Pane::ALL.iter().position(|pane| *pane == self).expect("pane is listed");
+        Pane::ALL[(i as isize + delta).rem_euclid(Pane::ALL.len() as isize) as usize]
+    }
+}
+
+/// 与终端库无关的按键
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    Home,
+    End,
+    Tab,
+    BackTab,
+    Enter,
+    Esc,
+    Char(char),
+}
+
+/// 处理按键后的动作
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Continue,
+    Quit,
+}
+
+/// 提交历史中的一行
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommitRow {
+    pub id: ObjectId,
+    /// 作者日期，`YYYY-MM-DD`
+    pub date: String,
+    pub author: String,
+    pub summary: String,
+}
+
+/// 界面状态
+pub struct App<'a> {
+    repo: &'a Repository,
+    pub pane: Pane,
+    pub refs: Vec<(String, ObjectId)>,
+    pub selected_ref: usize,
+    pub commits: Vec<CommitRow>,
+    pub selected_commit: usize,
+    /// 所选提交与第一个父提交之间的补丁，按行拆分
+    pub diff: Vec<String>,
+    pub diff_scroll: usize,
+    pub queue: Vec<QueueEntry>,
+    pub selected_entry: usize,
+    /// 底部显示的最近一条消息，例如加载失败的原因
+    pub message: Option<String>,
+}
+
+impl<'a> App<'a> {
+    /// 加载全部面板，初始选中 HEAD 指向的分支
+    pub fn new(repo: &'a Repository) -> MonoResult<App<'a>> {
+        let mut app = App {
+            repo,
+            pane: Pane::Refs,
+            refs: Vec::new(),
+            selected_ref: 0,
+            commits: Vec::new(),
+            selected_commit: 0,
+            diff: Vec::new(),
+            diff_scroll: 0,
+            queue: Vec::new(),
+            selected_entry: 0,
+            message: None,
+        };
+        app.refs = repo.refs().list("refs/")?;
+        if let Some(RefTarget::Symbolic(head)) = repo.refs().read(refs::HEAD)? {
+            app.selected_ref = app.refs.iter().position(|(name, _)| *name == head).unwrap_or(0);
+        }
+        app.load_history()?;
+        app.refresh_queue()?;
+        Ok(app)
+    }
+
+    /// 当前选中的提交
+    pub fn commit(&self) -> Option<&CommitRow> {
+        self.commits.get(self.selected_commit)
+    }
+
+    /// 处理一次按键；加载失败时把错误显示在底部，不退出界面
+    pub fn handle(&mut self, key: Key) -> Action {
+        self.message = None;
+        match self.try_handle(key) {
+            Ok(action) => action,
+            Err(e) => {
+                self.message = Some(e.to_string());
+                Action::Continue
+            }
+        }
+    }
+
+    fn try_handle(&mut self, key: Key) -> MonoResult<Action> {
+        match key {
+            Key::Char('q') | Key::Esc => return Ok(Action::Quit),
+            Key::Tab => self.pane = self.pane.offset(1),
+            Key::BackTab => self.pane = self.pane.offset(-1),
+            Key::Char(c @ '1'..='4') => self.pane = Pane::ALL[c as usize - '1' as usize],
+            Key::Char('r') => self.refresh()?,
+            Key::Enter if self.pane == Pane::Refs => self.pane = Pane::History,
+            Key::Enter if self.pane == Pane::History => self.pane = Pane::Diff,
+            Key::Up | Key::Char('k') => self.move_by(-1)?,
+            Key::Down | Key::Char('j') => self.move_by(1)?,
+            Key::PageUp => self.move_by(-(PAGE as isize))?,
+            Key::PageDown => self.move_by(PAGE as isize)?,
+            Key::Home | Key::Char('g') => self.move_by(isize::MIN)?,
+            Key::End | Key::Char('G') => self.move_by(isize::MAX)?,
+            _ => {}
+        }
+        Ok(Action::Continue)
+    }
+
+    /// 在当前面板中移动选中项，切换引用或提交时重新加载下游面板
+    fn move_by(&mut self, delta: isize) -> MonoResult<()> {
+        match self.pane {
+            Pane::Refs => {
+                if step(&mut self.selected_ref, self.refs.len(), delta) {
+                    self.load_history()?;
+                }
+            }
+            Pane::History => {
+                if step(&mut self.selected_commit, self.commits.len(), delta) {
+                    self.load_diff()?;
+                }
+            }
+            Pane::Diff => {
+                step(&mut self.diff_scroll, self.diff.len(), delta);
+            }
+            Pane::Queue => {
+                step(&mut self.selected_entry, self.queue.len(), delta);
+            }
+        }
+        Ok(())
+    }
+
+    /// 重新读取引用、历史与合并队列，尽量保持原来的选中项
+    pub fn refresh(&mut self) -> MonoResult<()> {
+        let selected = self.refs.get(self.selected_ref).map(|(name, _)| name.clone());
+        self.refs = self.repo.refs().list("refs/")?;
+        self.selected_ref = selected
+            .and_then(|selected| self.refs.iter().position(|(name, _)| *name == selected))
+            .unwrap_or(0);
+        let commit = self.commit().map(|commit| commit.id);
+        self.load_history()?;
+        if let Some(i) = commit.and_then(|id| self.commits.iter().position(|row| row.id == id)) {
+            self.selected_commit = i;
+            self.load_diff()?;
+        }
+        self.refresh_queue()
+    }
+
+    /// 重新读取合并队列，队列由服务端在后台处理，界面定期调用
+    pub fn refresh_queue(&mut self) -> MonoResult<()> {
+        let mut queue = MergeQueue::new(self.repo).entries()?;
+        // 最新的条目在前
+        queue.reverse();
+        self.queue = queue;
+        self.selected_entry = self.selected_entry.min(self.queue.len().saturating_sub(1));
+        Ok(())
+    }
+
+    fn load_history(&mut self) -> MonoResult<()> {
+        self.commits.clear();
+        self.selected_commit = 0;
+        if let Some((_, tip)) = self.refs.get(self.selected_ref) {
+            let history = History::new(self.repo)?;
+            for entry in history.log(&[*tip], Some(HISTORY_LIMIT))? {
+                let commit = self.repo.read_commit(&entry.id)?;
+                let date = DateTime::from_timestamp(commit.author.timestamp, 0)
+                    .map_or_else(String::new, |time| time.format("%Y-%m-%d").to_string());
+                self.commits.push(CommitRow {
+                    id: entry.id,
+                    date,
+                    author: commit.author.name.clone(),
+                    summary: commit.summary().to_string(),
+                });
+            }
+        }
+        self.load_diff()
+    }
+
+    fn load_diff(&mut self) -> MonoResult<()> {
+        self.diff.clear();
+        self.diff_scroll = 0;
+        let Some(id) = self.commit().map(|commit| commit.id) else {
+            return Ok(());
+        };
+        let commit = self.repo.read_commit(&id)?;
+        let parent = match commit.parents.first() {
+            Some(parent) => Some(self.repo.read_commit(parent)?.tree),
+            None => None,
+        };
+        let diffs = diff_files(self.repo, parent.as_ref(), Some(&commit.tree), &DiffOptions::default())?;
+        let mut patch = String::new();
+        for diff in &diffs {
+            write_patch(&mut patch, diff);
+        }
+        self.diff = patch.lines().map(str::to_string).collect();
+        Ok(())
+    }
+}
+
+/// 在 `0..len` 内移动 `index`，返回是否变化
+fn step(index: &mut usize, len: usize, delta: isize) -> bool {
+    let old = *index;
+    *index = index.saturating_add_signed(delta).min(len.saturating_sub(1));
+    *index != old
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{commit_files, init_repo};
+
+    /// 测试按键在面板间切换并联动加载历史与差异
+    #[test]
+    fn test_app_navigation() {
+        let (_dir, repo) = init_repo();
+        let first = commit_files(&repo, &[("a.txt", b"one\n")], &[], "first");
+        let second = commit_files(&repo, &[("a.txt", b"two\n")], &[first], "second");
+        repo.refs().write("refs/heads/main", &second).unwrap();
+        repo.refs().write("refs/heads/old", &first).unwrap();
+        let mut app = App::new(&repo).unwrap();
+
+        assert_eq!(app.refs[app.selected_ref].0, "refs/heads/main");
+        let summaries: Vec<&str> = app.commits.iter().map(|row| row.summary.as_str()).collect();
+        assert_eq!(summaries, ["second", "first"]);
+        assert!(app.diff.contains(&"-one".to_string()) && app.diff.contains(&"+two".to_string()));
+
+        // 在历史中选中根提交，差异与空树比较
+        assert_eq!(app.handle(Key::Enter), Action::Continue);
+        assert_eq!(app.pane, Pane::History);
+        app.handle(Key::End);
+        assert_eq!(app.commit().unwrap().id, first);
+        assert!(app.diff.contains(&"+one".to_string()));
+
+        // 切换引用后历史从新的引用开始
+        app.handle(Key::BackTab);
+        app.handle(Key::Down);
+        assert_eq!(app.refs[app.selected_ref].0, "refs/heads/old");
+        assert_eq!(app.commits.len(), 1);
+        app.handle(Key::Down);
+        assert_eq!(app.selected_ref, 1);
+
+        app.handle(Key::Char('4'));
+        assert_eq!(app.pane, Pane::Queue);
+        assert!(app.queue.is_empty());
+        assert_eq!(app.handle(Key::Tab), Action::Continue);
+        assert_eq!(app.pane, Pane::Refs);
+        assert_eq!(app.handle(Key::Char('q')), Action::Quit);
+    }
+}
@@ -0,0 +1,151 @@
+//! 基于 ratatui 的界面绘制与按键读取
+
+use std::time::Duration;
+
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph};
+use ratatui::Frame;
+
+use super::{Action, App, Key, Pane};
+use crate::common::MonoResult;
+use crate::queue::EntryState;
+use crate::refs;
+
+/// 没有按键时重新读取合并队列的间隔
+const QUEUE_REFRESH: Duration = Duration::from_secs(2);
+
+const HELP: &str = "q quit  tab/1-4 pane  j/k move  pgup/pgdn page  enter open  r refresh";
+
+/// 接管终端运行界面，直到按下 `q`
+pub fn run(mut app: App) -> MonoResult<()> {
+    let mut terminal = ratatui::init();
+    let result = event_loop(&mut terminal, &mut app);
+    ratatui::restore();
+    result
+}
+
+fn event_loop(terminal: &mut ratatui::DefaultTerminal, app: &mut App) -> MonoResult<()> {
+    loop {
+        terminal.draw(|frame| draw(frame, app))?;
+        if !event::poll(QUEUE_REFRESH)? {
+            if let Err(e) = app.refresh_queue() {
+                app.message = Some(e.to_string());
+            }
+            continue;
+        }
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        let key = match key.code {
+            KeyCode::Up => Key::Up,
+            KeyCode::Down => Key::Down,
+            KeyCode::PageUp => Key::PageUp,
+            KeyCode::PageDown => Key::PageDown,
+            KeyCode::Home => Key::Home,
+            KeyCode::End => Key::End,
+            KeyCode::Tab => Key::Tab,
+            KeyCode::BackTab => Key::BackTab,
+            KeyCode::Enter => Key::Enter,
+            KeyCode::Esc => Key::Esc,
+            KeyCode::Char(c) => Key::Char(c),
+            _ => continue,
+        };
+        if app.handle(key) == Action::Quit {
+            return Ok(());
+        }
+    }
+}
+
+/// 左侧为引用与合并队列，右侧为提交历史与差异，底部为帮助或消息
+fn draw(frame: &mut Frame, app: &App) {
+    let [main, footer] = Layout::vertical([Constraint::Fill(1), Constraint::Length(1)]).areas(frame.area());
+    let [left, right] = Layout::horizontal([Constraint::Percentage(30), Constraint::Fill(1)]).areas(main);
+    let [refs_area, queue_area] = Layout::vertical([Constraint::Percentage(50), Constraint::Fill(1)]).areas(left);
+    let [history_area, diff_area] = Layout::vertical([Constraint::Percentage(40), Constraint::Fill(1)]).areas(right);
+
+    let refs = app.refs.iter().map(|(name, id)| {
+        ListItem::new(Line::from(vec![
+            Span::styled(format!("{} ", &id.to_hex()[..7]), Style::new().fg(Color::Yellow)),
+            Span::raw(refs::short_name(name).to_string()),
+        ]))
+    });
+    draw_list(frame, app, Pane::Refs, refs_area, refs.collect(), app.selected_ref);
+
+    let commits = app.commits.iter().map(|row| {
+        ListItem::new(Line::from(vec![
+            Span::styled(format!("{} ", &row.id.to_hex()[..7]), Style::new().fg(Color::Yellow)),
+            Span::styled(format!("{} ", row.date), Style::new().fg(Color::Blue)),
+            Span::styled(format!("{:<16.16} ", row.author), Style::new().fg(Color::Green)),
+            Span::raw(row.summary.clone()),
+        ]))
+    });
+    draw_list(frame, app, Pane::History, history_area, commits.collect(), app.selected_commit);
+
+    let entries = app.queue.iter().map(|entry| {
+        let color = match entry.state {
+            EntryState::Queued => Color::Yellow,
+            EntryState::Landed => Color::Green,
+            EntryState::Failed => Color::Red,
+            EntryState::Cancelled => Color::DarkGray,
+        };
+        ListItem::new(Line::from(vec![
+            Span::raw(format!("#{:<5} ", entry.id)),
+            Span::styled(format!("{:<9} ", entry.state), Style::new().fg(color)),
+            Span::raw(format!("{} <- {}", refs::short_name(&entry.target), entry.source)),
+        ]))
+    });
+    draw_list(frame, app, Pane::Queue, queue_area, entries.collect(), app.selected_entry);
+
+    let lines: Vec<Line> = app.diff.iter().map(|line| Line::styled(line.as_str(), diff_style(line))).collect();
+    let diff = Paragraph::new(lines)
+        .block(block(app, Pane::Diff))
+        .scroll((app.diff_scroll.min(u16::MAX as usize) as u16, 0));
+    frame.render_widget(diff, diff_area);
+
+    let footer_line = match &app.message {
+        Some(message) => Line::styled(message.as_str(), Style::new().fg(Color::Red)),
+        None => Line::styled(HELP, Style::new().fg(Color::DarkGray)),
+    };
+    frame.render_widget(Paragraph::new(footer_line), footer);
+}
+
+fn draw_list(frame: &mut Frame, app: &App, pane: Pane, area: Rect, items: Vec<ListItem>, selected: usize) {
+    let list = List::new(items)
+        .block(block(app, pane))
+        .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+    let mut state = ListState::default().with_selected(Some(selected));
+    frame.render_stateful_widget(list, area, &mut state);
+}
+
+/// 带标题的边框，当前面板高亮
+fn block(app: &App, pane: Pane) -> Block<'static> {
+    let number = Pane::ALL.iter().position(|p| *p == pane).expect("pane is listed") + 1;
+    let style = if app.pane == pane {
+        Style::new().fg(Color::Cyan).add_modifier(Modifier::BOLD)
+    } else {
+        Style::new()
+    };
+    Block::bordered()
+        .title(format!(" {} {} ", number, pane.title()))
+        .border_style(style)
+}
+
+fn diff_style(line: &str) -> Style {
+    if line.starts_with("+++") || line.starts_with("---") || line.starts_with("diff ") {
+        Style::new().add_modifier(Modifier::BOLD)
+    } else if line.starts_with("@@") {
+        Style::new().fg(Color::Cyan)
+    } else if line.starts_with('+') {
+        Style::new().fg(Color::Green)
+    } else if line.starts_with('-') {
+        Style::new().fg(Color::Red)
+    } else {
+        Style::new()
+    }
+}
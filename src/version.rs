@@ -0,0 +1,250 @@
+//! 按项目的语义化版本
+//!
+//! 单仓库中每个项目目录独立发布版本，版本号记在以目录为前缀的标签上：`services/foo` 的 1.2.3 版本
+//! 为标签 `services/foo/v1.2.3`，仓库根目录为 `v1.2.3`。`mono version bump //services/foo --minor`
+//! 找到该目录下最大的版本，计算下一个版本，收集上一个版本之后修改了该目录的提交生成变更日志，
+//! 然后以此创建发布（见 [`crate::release`]），标签创建后不可移动。变更日志的格式见 [`crate::changelog`]。
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde::Serialize;
+
+use crate::changelog::Changelog;
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::object::commit::Signature;
+use crate::object::{ObjectId, ObjectType};
+use crate::refs;
+use crate::release::{NewRelease, Release, Releases};
+use crate::repo::Repository;
+use crate::rewrite::normalize_prefix;
+
+/// 语义化版本号，不支持预发布与构建元数据
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Version {
+    pub major: u64,
+    pub minor: u64,
+    pub patch: u64,
+}
+
+impl Version {
+    /// 按级别递增，低位归零
+    pub fn bump(self, bump: Bump) -> Version {
+        match bump {
+            Bump::Major => Version { major: self.major + 1, minor: 0, patch: 0 },
+            Bump::Minor => Version { minor: self.minor + 1, patch: 0, ..self },
+            Bump::Patch => Version { patch: self.patch + 1, ..self },
+        }
+    }
+}
+
+impl FromStr for Version {
+    type Err = MonoError;
+
+    /// 解析 `1.2.3`，可以带 `v` 前缀
+    fn from_str(s: &str) -> MonoResult<Version> {
+        let invalid = || MonoError::usage(format!("invalid version: {}", s));
+        let mut parts = s.strip_prefix('v').unwrap_or(s).split('.');
+        let mut next = || -> MonoResult<u64> {
+            let part = parts.next().ok_or_else(invalid)?;
+            if part.is_empty() || !part.bytes().all(|b| b.is_ascii_digit()) {
+                return Err(invalid());
+            }
+            part.parse().map_err(|_| invalid())
+        };
+        let version = Version { major: next()?, minor: next()?, patch: next()? };
+        match parts.next() {
+            Some(_) => Err(invalid()),
+            None => Ok(version),
+        }
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// 版本递增的级别
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bump {
+    Major,
+    Minor,
+    Patch,
+}
+
+/// 一次版本递增的计划
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Plan {
+    /// 项目目录，仓库根目录为空
+    pub project: String,
+    pub previous: Option<Version>,
+    pub next: Version,
+    /// 新版本的标签名，不含 `refs/tags/`
+    pub tag: String,
+    /// 新版本的提交
+    pub commit: ObjectId,
+    /// 上一个版本之后修改了项目目录的提交
+    pub changelog: Changelog,
+}
+
+impl Plan {
+    /// 发布标题，如 `services/foo v1.3.0`
+    pub fn title(&self) -> String {
+        match self.project.is_empty() {
+            true => format!("v{}", self.next),
+            false => format!("{} v{}", self.project, self.next),
+        }
+    }
+}
+
+/// 项目版本的标签名
+pub fn tag_name(project: &str, version: &Version) -> String {
+    match project.is_empty() {
+        true => format!("v{}", version),
+        false => format!("{}/v{}", project, version),
+    }
+}
+
+/// 规范化项目目录，`//services/foo/` 为 `services/foo`，`//` 为仓库根目录
+pub fn normalize_project(project: &str) -> MonoResult<String> {
+    match project.trim_matches('/') {
+        "" => Ok(String::new()),
+        _ => normalize_prefix(project),
+    }
+}
+
+/// 仓库中各项目的版本
+pub struct Versions<'a> {
+    repo: &'a Repository,
+}
+
+impl<'a> Versions<'a> {
+    pub fn new(repo: &'a Repository) -> Versions<'a> {
+        Versions { repo }
+    }
+
+    /// 项目已有的版本及其标签指向的提交，从大到小排列；不是版本号的标签忽略
+    pub fn list(&self, project: &str) -> MonoResult<Vec<(Version, ObjectId)>> {
+        let prefix = match project.is_empty() {
+            true => format!("{}v", refs::TAGS_PREFIX),
+            false => format!("{}{}/v", refs::TAGS_PREFIX, project),
+        };
+        let mut versions = Vec::new();
+        for (name, id) in self.repo.refs().list(&prefix)? {
+            let Ok(version) = name[prefix.len()..].parse::<Version>() else {
+                continue;
+            };
+            let (commit, object_type) = self.repo.peel(&id)?;
+            if object_type == ObjectType::Commit {
+                versions.push((version, commit));
+            }
+        }
+        versions.sort_by_key(|(version, _)| std::cmp::Reverse(*version));
+        Ok(versions)
+    }
+
+    /// 计算项目在 `rev` 上的下一个版本与变更日志，上一个版本之后没有修改该目录时返回错误
+    pub fn plan(&self, project: &str, rev: &str, bump: Bump) -> MonoResult<Plan> {
+        let project = normalize_project(project)?;
+        let (commit, object_type) = self.repo.peel(&self.repo.resolve_rev(rev)?)?;
+        if object_type != ObjectType::Commit {
+            return Err(MonoError::usage(format!("{} is not a commit", rev)));
+        }
+        let previous = self.list(&project)?.into_iter().next();
+        let exclude: Vec<ObjectId> = previous.iter().map(|(_, id)| *id).collect();
+        let changelog = Changelog::generate(self.repo, &[commit], &exclude, &project)?;
+        let display = if project.is_empty() { "//".to_string() } else { format!("//{}", project) };
+        if changelog.is_empty() {
+            return Err(match &previous {
+                Some((version, _)) => MonoError::usage(format!("no changes in {} since v{}", display, version)),
+                None => MonoError::usage(format!("no commits touch {} in {}", display, rev)),
+            });
+        }
+        let next = previous.map(|(version, _)| version).unwrap_or_default().bump(bump);
+        Ok(Plan {
+            tag: tag_name(&project, &next),
+            project,
+            previous: previous.map(|(version, _)| version),
+            next,
+            commit,
+            changelog,
+        })
+    }
+
+    /// 按计划创建发布，发布说明为变更日志
+    pub fn bump(&self, plan: &Plan, tagger: &Signature) -> MonoResult<Release> {
+        let release = NewRelease {
+            tag: plan.tag.clone(),
+            rev: plan.commit.to_hex(),
+            project: (!plan.project.is_empty()).then(|| plan.project.clone()),
+            title: Some(plan.title()),
+            notes: plan.changelog.markdown(),
+            artifacts: Vec::new(),
+        };
+        Releases::new(self.repo).create(release, tagger)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{commit_files, init_repo};
+
+    /// 测试版本号的解析、比较与递增
+    #[test]
+    fn test_version() {
+        let version: Version = "v1.9.3".parse().unwrap();
+        assert_eq!(version, Version { major: 1, minor: 9, patch: 3 });
+        assert!(version < "1.10.0".parse().unwrap());
+        assert_eq!(version.bump(Bump::Major).to_string(), "2.0.0");
+        assert_eq!(version.bump(Bump::Minor).to_string(), "1.10.0");
+        assert_eq!(version.bump(Bump::Patch).to_string(), "1.9.4");
+        for invalid in ["1.2", "1.2.3.4", "1.2.3-rc.1", "v1..3", "+1.2.3"] {
+            assert!(invalid.parse::<Version>().is_err(), "{}", invalid);
+        }
+        assert_eq!(tag_name("services/foo", &version), "services/foo/v1.9.3");
+        assert_eq!(tag_name("", &version), "v1.9.3");
+    }
+
+    /// 测试按目录前缀的标签计算下一个版本，变更日志只包含修改了该目录的提交
+    #[test]
+    fn test_bump() {
+        let (_dir, repo) = init_repo();
+        let tagger = Signature::new("Release Bot", "release@example.com", 1_700_000_000);
+        let versions = Versions::new(&repo);
+        let first = commit_files(&repo, &[("services/foo/a", b"1"), ("services/bar/b", b"1")], &[], "feat(foo): initial import");
+        let plan = versions.plan("//services/foo", &first.to_hex(), Bump::Minor).unwrap();
+        assert_eq!((plan.previous, plan.tag.as_str()), (None, "services/foo/v0.1.0"));
+        versions.bump(&plan, &tagger).unwrap();
+        // 其他项目与不是版本号的标签不影响版本
+        repo.refs().write("refs/tags/services/foobar/v9.0.0", &first).unwrap();
+        repo.refs().write("refs/tags/services/foo/vnext", &first).unwrap();
+
+        let fix = commit_files(&repo, &[("services/foo/a", b"2"), ("services/bar/b", b"1")], &[first], "fix(foo): handle empty input");
+        let bar = commit_files(&repo, &[("services/foo/a", b"2"), ("services/bar/b", b"2")], &[fix], "feat(bar): unrelated");
+        let docs = commit_files(&repo, &[("services/foo/a", b"3"), ("services/bar/b", b"2")], &[bar], "Update docs");
+        let plan = versions.plan("services/foo/", &docs.to_hex(), Bump::Minor).unwrap();
+        assert_eq!(plan.previous, Some(Version { major: 0, minor: 1, patch: 0 }));
+        assert_eq!(plan.tag, "services/foo/v0.2.0");
+        assert_eq!(plan.changelog.changes.iter().map(|c| c.commit).collect::<Vec<_>>(), [docs, fix]);
+        assert_eq!(
+            plan.changelog.markdown(),
+            format!(
+                "### Bug Fixes\n\n- **foo:** handle empty input ({})\n\n### Other Changes\n\n- Update docs ({})\n",
+                &fix.to_hex()[..7],
+                &docs.to_hex()[..7]
+            )
+        );
+        let release = versions.bump(&plan, &tagger).unwrap();
+        assert_eq!((release.title.as_str(), release.project.as_deref()), ("services/foo v0.2.0", Some("services/foo")));
+        assert_eq!(versions.list("services/foo").unwrap()[0], (Version { major: 0, minor: 2, patch: 0 }, docs));
+
+        let err = versions.plan("services/foo", &docs.to_hex(), Bump::Patch).unwrap_err();
+        assert!(err.to_string().contains("no changes in //services/foo since v0.2.0"), "{}", err);
+        let root = versions.plan("//", &docs.to_hex(), Bump::Major).unwrap();
+        assert_eq!((root.tag.as_str(), root.changelog.changes.len()), ("v1.0.0", 4));
+    }
+}
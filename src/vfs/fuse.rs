@@ -0,0 +1,161 @@
+//! 基于 fuser 的 FUSE 适配层
+
+use std::ffi::OsStr;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use fuser::{
+    Config, Errno, FileAttr, FileHandle, FileType, Filesystem, Generation, INodeNo, LockOwner,
+    MountOption, OpenFlags, ReplyAttr, ReplyData, ReplyDirectory, ReplyEntry, Request,
+};
+
+use crate::common::errors::{MonoError, MonoErrorKind};
+use crate::common::MonoResult;
+use crate::vfs::{NodeAttr, NodeKind, Vfs};
+
+/// 提交内容不可变，内核可以长期缓存属性与目录项
+const TTL: Duration = Duration::from_secs(3600);
+
+/// 挂载到内核的只读文件系统
+struct MonoFs {
+    vfs: Vfs,
+    mtime: SystemTime,
+    uid: u32,
+    gid: u32,
+}
+
+impl MonoFs {
+    fn attr(&self, attr: &NodeAttr) -> FileAttr {
+        let (kind, perm, nlink) = match attr.kind {
+            NodeKind::Directory => (FileType::Directory, 0o555, 2),
+            NodeKind::Symlink => (FileType::Symlink, 0o777, 1),
+            NodeKind::File if attr.executable => (FileType::RegularFile, 0o555, 1),
+            NodeKind::File => (FileType::RegularFile, 0o444, 1),
+        };
+        FileAttr {
+            ino: INodeNo(attr.ino),
+            size: attr.size,
+            blocks: attr.size.div_ceil(512),
+            atime: self.mtime,
+            mtime: self.mtime,
+            ctime: self.mtime,
+            crtime: self.mtime,
+            kind,
+            perm,
+            nlink,
+            uid: self.uid,
+            gid: self.gid,
+            rdev: 0,
+            blksize: 4096,
+            flags: 0,
+        }
+    }
+}
+
+fn file_type(kind: NodeKind) -> FileType {
+    match kind {
+        NodeKind::Directory => FileType::Directory,
+        NodeKind::File => FileType::RegularFile,
+        NodeKind::Symlink => FileType::Symlink,
+    }
+}
+
+/// 将错误转换为 errno，并记录无法由 errno 表达的细节
+fn errno(err: MonoError) -> Errno {
+    match err.kind() {
+        MonoErrorKind::NotFound(_) => Errno::ENOENT,
+        MonoErrorKind::Usage(_) => Errno::EINVAL,
+        _ => {
+            tracing::warn!(error = %err, "vfs request failed");
+            Errno::EIO
+        }
+    }
+}
+
+impl Filesystem for MonoFs {
+    fn lookup(&self, _req: &Request, parent: INodeNo, name: &OsStr, reply: ReplyEntry) {
+        let Some(name) = name.to_str() else {
+            return reply.error(Errno::ENOENT);
+        };
+        match self.vfs.lookup(parent.0, name) {
+            Ok(Some(attr)) => reply.entry(&TTL, &self.attr(&attr), Generation(0)),
+            Ok(None) => reply.error(Errno::ENOENT),
+            Err(e) => reply.error(errno(e)),
+        }
+    }
+
+    fn getattr(&self, _req: &Request, ino: INodeNo, _fh: Option<FileHandle>, reply: ReplyAttr) {
+        match self.vfs.getattr(ino.0) {
+            Ok(attr) => reply.attr(&TTL, &self.attr(&attr)),
+            Err(e) => reply.error(errno(e)),
+        }
+    }
+
+    fn readlink(&self, _req: &Request, ino: INodeNo, reply: ReplyData) {
+        match self.vfs.readlink(ino.0) {
+            Ok(target) => reply.data(&target),
+            Err(e) => reply.error(errno(e)),
+        }
+    }
+
+    fn read(
+        &self,
+        _req: &Request,
+        ino: INodeNo,
+        _fh: FileHandle,
+        offset: u64,
+        size: u32,
+        _flags: OpenFlags,
+        _lock_owner: Option<LockOwner>,
+        reply: ReplyData,
+    ) {
+        match self.vfs.read(ino.0, offset, size as usize) {
+            Ok(data) => reply.data(&data),
+            Err(e) => reply.error(errno(e)),
+        }
+    }
+
+    fn readdir(&self, _req: &Request, ino: INodeNo, _fh: FileHandle, offset: u64, mut reply: ReplyDirectory) {
+        let listing = self.vfs.parent(ino.0).and_then(|parent| Ok((parent, self.vfs.readdir(ino.0)?)));
+        let (parent, children) = match listing {
+            Ok(listing) => listing,
+            Err(e) => return reply.error(errno(e)),
+        };
+        let entries = [
+            (ino.0, FileType::Directory, ".".to_string()),
+            (parent, FileType::Directory, "..".to_string()),
+        ]
+        .into_iter()
+        .chain(children.into_iter().map(|e| (e.ino, file_type(e.kind), e.name)));
+        // 偏移量为下一项的序号
+        for (i, (ino, kind, name)) in entries.enumerate().skip(offset as usize) {
+            if reply.add(INodeNo(ino), (i + 1) as u64, kind, name) {
+                break;
+            }
+        }
+        reply.ok();
+    }
+}
+
+/// 将视图只读挂载到 `mountpoint`，直到文件系统被卸载才返回
+pub fn mount(vfs: Vfs, mountpoint: &Path) -> MonoResult<()> {
+    use std::os::unix::fs::MetadataExt;
+
+    let metadata = std::fs::metadata(mountpoint)?;
+    let mtime = UNIX_EPOCH + Duration::from_secs(vfs.timestamp().max(0) as u64);
+    let fs = MonoFs {
+        vfs,
+        mtime,
+        uid: metadata.uid(),
+        gid: metadata.gid(),
+    };
+    let mut config = Config::default();
+    config.mount_options = vec![
+        MountOption::RO,
+        MountOption::FSName("mono".to_string()),
+        MountOption::Subtype("mono".to_string()),
+        MountOption::DefaultPermissions,
+    ];
+    fuser::mount(fs, mountpoint, &config)
+        .map_err(|e| MonoError::from(e).context(format!("mounting {}", mountpoint.display())))
+}
@@ -0,0 +1,369 @@
+//! 虚拟文件系统
+//!
+//! 将某个提交的树以只读文件系统的形式暴露：目录在首次访问时展开，文件内容在首次读取时
+//! 才从对象存储加载，部分克隆时由 promisor 远端按需获取。挂载多 GB 的仓库无需预先检出任何文件。
+//!
+//! 本模块只维护 inode 表与内容缓存，与具体的内核接口无关；启用 `fuse` 特性后
+//! 由 `fuse` 子模块对接 Linux / macOS 的 FUSE。
+
+#[cfg(all(feature = "fuse", any(target_os = "linux", target_os = "macos")))]
+pub mod fuse;
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::common::errors::MonoError;
+use crate::common::MonoResult;
+use crate::object::commit::Commit;
+use crate::object::tree::FileMode;
+use crate::object::{ObjectId, ObjectType};
+use crate::repo::{is_reserved_name, Repository};
+
+/// 根目录的 inode 编号，与 FUSE 约定一致
+pub const ROOT_INO: u64 = 1;
+
+/// 默认的文件内容缓存上限
+pub const DEFAULT_CACHE_BYTES: usize = 256 << 20;
+
+/// 节点类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeKind {
+    Directory,
+    File,
+    Symlink,
+}
+
+/// 节点属性
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NodeAttr {
+    pub ino: u64,
+    pub kind: NodeKind,
+    /// 文件大小，目录为 0
+    pub size: u64,
+    pub executable: bool,
+}
+
+/// 目录项
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirEntry {
+    pub ino: u64,
+    pub kind: NodeKind,
+    pub name: String,
+}
+
+struct Node {
+    parent: u64,
+    mode: FileMode,
+    id: ObjectId,
+    /// 已知的文件大小
+    size: Option<u64>,
+    /// 已展开的子节点，按树中的顺序排列
+    children: Option<Vec<(String, u64)>>,
+}
+
+impl Node {
+    fn kind(&self) -> NodeKind {
+        // 子模块没有可读的内容，表现为空目录
+        if self.mode.is_tree() || self.mode.is_gitlink() {
+            NodeKind::Directory
+        } else if self.mode == FileMode::SYMLINK {
+            NodeKind::Symlink
+        } else {
+            NodeKind::File
+        }
+    }
+}
+
+/// 按写入顺序淘汰的 blob 缓存
+struct BlobCache {
+    capacity: usize,
+    used: usize,
+    order: VecDeque<ObjectId>,
+    blobs: HashMap<ObjectId, Arc<Vec<u8>>>,
+}
+
+impl BlobCache {
+    fn get(&self, id: &ObjectId) -> Option<Arc<Vec<u8>>> {
+        self.blobs.get(id).cloned()
+    }
+
+    fn insert(&mut self, id: ObjectId, data: Arc<Vec<u8>>) {
+        if data.len() > self.capacity || self.blobs.contains_key(&id) {
+            return;
+        }
+        while self.used + data.len() > self.capacity {
+            let Some(evicted) = self.order.pop_front() else {
+                break;
+            };
+            if let Some(blob) = self.blobs.remove(&evicted) {
+                self.used -= blob.len();
+            }
+        }
+        self.used += data.len();
+        self.order.push_back(id);
+        self.blobs.insert(id, data);
+    }
+}
+
+/// 提交树的只读视图
+pub struct Vfs {
+    repo: Repository,
+    /// 提交时间（秒），用作所有节点的修改时间
+    timestamp: i64,
+    /// 以 `ino - 1` 为下标的节点表，节点只增不减
+    nodes: Mutex<Vec<Node>>,
+    cache: Mutex<BlobCache>,
+}
+
+impl Vfs {
+    /// 以提交或树对象为根创建视图
+    pub fn new(repo: Repository, rev: &ObjectId) -> MonoResult<Vfs> {
+        let object = repo.read_object(rev)?;
+        let (tree, timestamp) = match object.object_type {
+            ObjectType::Commit => {
+                let commit = Commit::parse(&object.data)?;
+                (commit.tree, commit.committer.timestamp)
+            }
+            ObjectType::Tree => (*rev, 0),
+            other => {
+                return Err(MonoError::usage(format!("{} is a {}, not a commit or tree", rev, other)));
+            }
+        };
+        let root = Node {
+            parent: ROOT_INO,
+            mode: FileMode::TREE,
+            id: tree,
+            size: Some(0),
+            children: None,
+        };
+        Ok(Vfs {
+            repo,
+            timestamp,
+            nodes: Mutex::new(vec![root]),
+            cache: Mutex::new(BlobCache {
+                capacity: DEFAULT_CACHE_BYTES,
+                used: 0,
+                order: VecDeque::new(),
+                blobs: HashMap::new(),
+            }),
+        })
+    }
+
+    /// 设置文件内容缓存上限
+    pub fn with_cache_capacity(self, capacity: usize) -> Vfs {
+        self.lock_cache().capacity = capacity;
+        self
+    }
+
+    /// 提交时间（秒）
+    pub fn timestamp(&self) -> i64 {
+        self.timestamp
+    }
+
+    /// 读取节点属性
+    ///
+    /// 文件大小优先从本地对象头读取，对象不在本地时会触发一次按需获取。
+    pub fn getattr(&self, ino: u64) -> MonoResult<NodeAttr> {
+        let (kind, mode, id, size) = {
+            let nodes = self.lock_nodes();
+            let node = node(&nodes, ino)?;
+            (node.kind(), node.mode, node.id, node.size)
+        };
+        let size = match size {
+            Some(size) => size,
+            None if kind == NodeKind::Directory => 0,
+            None => {
+                let size = match self.repo.objects().read_header(&id)? {
+                    Some((_, size)) => size as u64,
+                    None => self.blob(&id)?.len() as u64,
+                };
+                self.lock_nodes()[index(ino)].size = Some(size);
+                size
+            }
+        };
+        Ok(NodeAttr {
+            ino,
+            kind,
+            size,
+            executable: mode == FileMode::EXECUTABLE,
+        })
+    }
+
+    /// 在目录中查找名为 `name` 的子节点
+    pub fn lookup(&self, parent: u64, name: &str) -> MonoResult<Option<NodeAttr>> {
+        let ino = self
+            .children(parent)?
+            .into_iter()
+            .find(|(child, _)| child == name)
+            .map(|(_, ino)| ino);
+        ino.map(|ino| self.getattr(ino)).transpose()
+    }
+
+    /// 父目录的 inode，根目录的父目录是其自身
+    pub fn parent(&self, ino: u64) -> MonoResult<u64> {
+        Ok(node(&self.lock_nodes(), ino)?.parent)
+    }
+
+    /// 列出目录内容，不包含 `.` 与 `..`
+    pub fn readdir(&self, ino: u64) -> MonoResult<Vec<DirEntry>> {
+        let children = self.children(ino)?;
+        let nodes = self.lock_nodes();
+        Ok(children
+            .into_iter()
+            .map(|(name, ino)| DirEntry {
+                ino,
+                kind: nodes[index(ino)].kind(),
+                name,
+            })
+            .collect())
+    }
+
+    /// 读取文件从 `offset` 开始的至多 `size` 字节
+    pub fn read(&self, ino: u64, offset: u64, size: usize) -> MonoResult<Vec<u8>> {
+        let data = self.content(ino)?;
+        let start = (offset as usize).min(data.len());
+        let end = start.saturating_add(size).min(data.len());
+        Ok(data[start..end].to_vec())
+    }
+
+    /// 读取符号链接的目标
+    pub fn readlink(&self, ino: u64) -> MonoResult<Vec<u8>> {
+        Ok(self.content(ino)?.to_vec())
+    }
+
+    fn content(&self, ino: u64) -> MonoResult<Arc<Vec<u8>>> {
+        let (kind, id) = {
+            let nodes = self.lock_nodes();
+            let node = node(&nodes, ino)?;
+            (node.kind(), node.id)
+        };
+        if kind == NodeKind::Directory {
+            return Err(MonoError::usage(format!("inode {} is a directory", ino)));
+        }
+        let data = self.blob(&id)?;
+        self.lock_nodes()[index(ino)].size = Some(data.len() as u64);
+        Ok(data)
+    }
+
+    /// 读取 blob，读取期间不持有节点表的锁，避免按需获取阻塞其他请求
+    fn blob(&self, id: &ObjectId) -> MonoResult<Arc<Vec<u8>>> {
+        if let Some(data) = self.lock_cache().get(id) {
+            return Ok(data);
+        }
+        let data = Arc::new(self.repo.read_object(id)?.data);
+        self.lock_cache().insert(*id, data.clone());
+        Ok(data)
+    }
+
+    /// 目录的子节点，首次访问时读取树对象并分配 inode
+    fn children(&self, ino: u64) -> MonoResult<Vec<(String, u64)>> {
+        let (mode, id) = {
+            let nodes = self.lock_nodes();
+            let node = node(&nodes, ino)?;
+            if let Some(children) = &node.children {
+                return Ok(children.clone());
+            }
+            match node.kind() {
+                NodeKind::Directory => (node.mode, node.id),
+                _ => return Err(MonoError::usage(format!("inode {} is not a directory", ino))),
+            }
+        };
+        let entries = if mode.is_gitlink() {
+            Vec::new()
+        } else {
+            self.repo.read_tree(&id)?.entries
+        };
+
+        let mut nodes = self.lock_nodes();
+        // 其他请求可能已抢先展开该目录
+        if let Some(children) = &nodes[index(ino)].children {
+            return Ok(children.clone());
+        }
+        let mut children = Vec::with_capacity(entries.len());
+        for entry in entries {
+            if is_reserved_name(&entry.name) {
+                continue;
+            }
+            nodes.push(Node {
+                parent: ino,
+                mode: entry.mode,
+                id: entry.id,
+                size: None,
+                children: None,
+            });
+            children.push((entry.name, nodes.len() as u64));
+        }
+        nodes[index(ino)].children = Some(children.clone());
+        Ok(children)
+    }
+
+    fn lock_nodes(&self) -> MutexGuard<'_, Vec<Node>> {
+        self.nodes.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn lock_cache(&self) -> MutexGuard<'_, BlobCache> {
+        self.cache.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+fn index(ino: u64) -> usize {
+    (ino - 1) as usize
+}
+
+fn node(nodes: &[Node], ino: u64) -> MonoResult<&Node> {
+    ino.checked_sub(1)
+        .and_then(|i| nodes.get(i as usize))
+        .ok_or_else(|| MonoError::not_found(format!("inode {}", ino)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::errors::MonoErrorKind;
+    use crate::test_utils::{commit_files, init_repo};
+
+    /// 测试逐级查找与列出目录
+    #[test]
+    fn test_lookup_readdir() {
+        let (_dir, repo) = init_repo();
+        let commit = commit_files(&repo, &[("a.txt", b"a"), ("dir/b.txt", b"bb")], &[], "init");
+        let vfs = Vfs::new(repo, &commit).unwrap();
+
+        let names: Vec<_> = vfs.readdir(ROOT_INO).unwrap().into_iter().map(|e| e.name).collect();
+        assert_eq!(names, vec!["a.txt", "dir"]);
+        let dir = vfs.lookup(ROOT_INO, "dir").unwrap().unwrap();
+        assert_eq!(dir.kind, NodeKind::Directory);
+        assert_eq!(vfs.parent(dir.ino).unwrap(), ROOT_INO);
+        let file = vfs.lookup(dir.ino, "b.txt").unwrap().unwrap();
+        assert_eq!((file.kind, file.size), (NodeKind::File, 2));
+        assert!(vfs.lookup(ROOT_INO, "missing").unwrap().is_none());
+        // 重复展开返回相同的 inode
+        assert_eq!(vfs.lookup(ROOT_INO, "dir").unwrap().unwrap().ino, dir.ino);
+    }
+
+    /// 测试按偏移读取文件内容以及对目录读取报错
+    #[test]
+    fn test_read() {
+        let (_dir, repo) = init_repo();
+        let commit = commit_files(&repo, &[("hello.txt", b"hello world")], &[], "init");
+        let vfs = Vfs::new(repo, &commit).unwrap().with_cache_capacity(4);
+
+        let file = vfs.lookup(ROOT_INO, "hello.txt").unwrap().unwrap();
+        assert_eq!(vfs.read(file.ino, 6, 100).unwrap(), b"world");
+        assert_eq!(vfs.read(file.ino, 0, 5).unwrap(), b"hello");
+        assert!(vfs.read(file.ino, 100, 5).unwrap().is_empty());
+        assert!(vfs.read(ROOT_INO, 0, 5).is_err());
+        let err = vfs.getattr(999).unwrap_err();
+        assert!(matches!(err.kind(), MonoErrorKind::NotFound(_)));
+    }
+
+    /// 测试不暴露保留路径
+    #[test]
+    fn test_skips_reserved() {
+        let (_dir, repo) = init_repo();
+        let commit = commit_files(&repo, &[(".mono/mono.toml", b"evil"), ("a.txt", b"a")], &[], "init");
+        let vfs = Vfs::new(repo, &commit).unwrap();
+        assert_eq!(vfs.readdir(ROOT_INO).unwrap().len(), 1);
+        assert!(vfs.lookup(ROOT_INO, ".mono").unwrap().is_none());
+    }
+}
@@ -0,0 +1,484 @@
+//! Webhook 投递
+//!
+//! 推送与合并队列的事件以 HTTP POST 通知外部服务（CI、聊天机器人等），在 `mono.toml` 中配置：
+//!
+//! ```toml
+//! [[webhooks]]
+//! name = "ci"
+//! url = "https://ci.example.com/hooks/mono"
+//! secret = "s3cr3t"
+//! events = ["push", "merge-queue"]
+//! ```
+//!
+//! 事件先写入 `.mono/webhooks/deliveries` 下的投递记录（每条一个文件），再由 `mono serve`
+//! 的后台任务或 `mono webhooks deliver` 发送，服务不可用时不会丢失事件。非 2xx 响应或网络错误
+//! 后按指数退避重试，超过 `max_attempts` 次后标记为失败，可用 `mono webhooks deliveries` 查看、
+//! `mono webhooks redeliver` 重新投递。
+//!
+//! 请求带有 `X-Mono-Event` 与 `X-Mono-Delivery` 头；配置了密钥时 `X-Mono-Signature-256`
+//! 为 `sha256=<请求体的 HMAC-SHA256>`，接收方据此校验请求来源。
+
+use std::fmt;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use crate::common::config::{HookStage, WebhookConfig, WebhookEventKind};
+use crate::common::errors::MonoError;
+use crate::common::retry::RetryPolicy;
+use crate::common::MonoResult;
+use crate::hooks::{Hook, HookInput};
+use crate::queue::{LockFile, QueueEntry};
+use crate::refs::RefUpdate;
+use crate::repo::Repository;
+
+/// webhook 数据目录，相对于 `.mono`
+pub const WEBHOOKS_DIR: &str = "webhooks";
+/// 投递记录目录
+const DELIVERIES_DIR: &str = "deliveries";
+/// 发送投递期间持有的锁
+const DELIVER_LOCK_FILE: &str = "deliver.lock";
+/// 事件类型请求头
+pub const EVENT_HEADER: &str = "X-Mono-Event";
+/// 投递 ID 请求头，重试时保持不变，接收方可据此去重
+pub const DELIVERY_HEADER: &str = "X-Mono-Delivery";
+/// 签名请求头
+pub const SIGNATURE_HEADER: &str = "X-Mono-Signature-256";
+/// 单次请求的超时时间
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// 失败原因中保留的响应体长度
+const RESPONSE_EXCERPT_LEN: usize = 200;
+/// 已送达的投递记录保留的时间（秒）
+const RETENTION_SECS: i64 = 7 * 24 * 60 * 60;
+
+/// 投递失败后的重试间隔：30 秒起每次翻倍，最长 6 小时
+fn retry_policy() -> RetryPolicy {
+    RetryPolicy {
+        initial_backoff: Duration::from_secs(30),
+        max_backoff: Duration::from_secs(6 * 60 * 60),
+        multiplier: 2.0,
+        jitter: 0.0,
+        ..Default::default()
+    }
+}
+
+/// 触发 webhook 的事件
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum WebhookEvent {
+    Push {
+        updates: Vec<RefUpdate>,
+        /// 推送选项（`git push -o`）
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        push_options: Vec<String>,
+    },
+    RefUpdate { update: RefUpdate },
+    MergeQueue { entry: QueueEntry },
+}
+
+impl WebhookEvent {
+    pub fn kind(&self) -> WebhookEventKind {
+        match self {
+            WebhookEvent::Push { .. } => WebhookEventKind::Push,
+            WebhookEvent::RefUpdate { .. } => WebhookEventKind::RefUpdate,
+            WebhookEvent::MergeQueue { .. } => WebhookEventKind::MergeQueue,
+        }
+    }
+}
+
+/// 请求体
+#[derive(Serialize)]
+struct Payload<'a> {
+    repository: &'a str,
+    #[serde(flatten)]
+    event: &'a WebhookEvent,
+}
+
+/// 投递状态
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DeliveryState {
+    /// 等待发送或重试
+    Pending,
+    Delivered,
+    /// 达到最多投递次数或 webhook 已被删除
+    Failed,
+}
+
+impl fmt::Display for DeliveryState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(match self {
+            DeliveryState::Pending => "pending",
+            DeliveryState::Delivered => "delivered",
+            DeliveryState::Failed => "failed",
+        })
+    }
+}
+
+/// 一次投递的记录
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Delivery {
+    /// 投递 ID，以创建时间开头，按字典序排列大致为创建顺序
+    pub id: String,
+    /// webhook 名
+    pub webhook: String,
+    pub event: WebhookEventKind,
+    /// 请求体，重试时原样发送
+    pub payload: String,
+    /// 创建时间（Unix 时间戳）
+    pub created_at: i64,
+    pub state: DeliveryState,
+    /// 已发送的次数
+    pub attempts: u32,
+    /// 下次发送的时间（Unix 时间戳）
+    pub next_attempt_at: i64,
+    /// 最近一次响应的状态码
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_status: Option<u16>,
+    /// 最近一次失败的原因
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+}
+
+/// 发送 webhook 请求
+pub trait WebhookSender {
+    /// 发送 POST 请求，返回响应状态码与响应体；无法得到响应时返回错误
+    fn send(&self, url: &str, headers: &[(&str, String)], body: &[u8]) -> MonoResult<(u16, String)>;
+}
+
+impl<F> WebhookSender for F
+where
+    F: Fn(&str, &[(&str, String)], &[u8]) -> MonoResult<(u16, String)>,
+{
+    fn send(&self, url: &str, headers: &[(&str, String)], body: &[u8]) -> MonoResult<(u16, String)> {
+        self(url, headers, body)
+    }
+}
+
+/// 通过 HTTP 发送请求
+pub struct HttpSender {
+    agent: ureq::Agent,
+}
+
+impl Default for HttpSender {
+    fn default() -> Self {
+        let agent = ureq::Agent::config_builder()
+            .http_status_as_error(false)
+            .timeout_global(Some(REQUEST_TIMEOUT))
+            .build()
+            .into();
+        HttpSender { agent }
+    }
+}
+
+impl WebhookSender for HttpSender {
+    fn send(&self, url: &str, headers: &[(&str, String)], body: &[u8]) -> MonoResult<(u16, String)> {
+        let mut request = self.agent.post(url);
+        for (name, value) in headers {
+            request = request.header(*name, value);
+        }
+        let mut response = request
+            .send(body)
+            .map_err(|e| MonoError::unavailable(format!("{}: {}", url, e)))?;
+        let mut excerpt = Vec::new();
+        let _ = response
+            .body_mut()
+            .as_reader()
+            .take(RESPONSE_EXCERPT_LEN as u64)
+            .read_to_end(&mut excerpt);
+        Ok((response.status().as_u16(), String::from_utf8_lossy(&excerpt).into_owned()))
+    }
+}
+
+/// 请求体的签名，即 `X-Mono-Signature-256` 头的值
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("hmac accepts keys of any length");
+    mac.update(body);
+    let digest: String = mac.finalize().into_bytes().iter().map(|b| format!("{:02x}", b)).collect();
+    format!("sha256={}", digest)
+}
+
+/// 仓库的 webhook 投递队列
+pub struct WebhookQueue {
+    dir: PathBuf,
+    repository: String,
+    webhooks: Vec<WebhookConfig>,
+}
+
+impl WebhookQueue {
+    pub fn new(repo: &Repository) -> WebhookQueue {
+        let repository = repo
+            .root()
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        WebhookQueue {
+            dir: repo.mono_dir().join(WEBHOOKS_DIR),
+            repository,
+            webhooks: repo.config().webhooks.clone(),
+        }
+    }
+
+    /// 是否没有配置任何 webhook
+    pub fn is_empty(&self) -> bool {
+        self.webhooks.is_empty()
+    }
+
+    fn deliveries_dir(&self) -> PathBuf {
+        self.dir.join(DELIVERIES_DIR)
+    }
+
+    fn path(&self, id: &str) -> PathBuf {
+        self.deliveries_dir().join(format!("{}.json", id))
+    }
+
+    fn save(&self, delivery: &Delivery) -> MonoResult<()> {
+        std::fs::create_dir_all(self.deliveries_dir())?;
+        let data = serde_json::to_vec_pretty(delivery).map_err(|e| MonoError::storage(e.to_string()))?;
+        let path = self.path(&delivery.id);
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, data)?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    fn load(path: &Path) -> MonoResult<Delivery> {
+        let data = std::fs::read(path)?;
+        serde_json::from_slice(&data)
+            .map_err(|e| MonoError::storage(format!("corrupt webhook delivery {}: {}", path.display(), e)))
+    }
+
+    /// 为订阅该事件的每个 webhook 创建投递记录，立即可以发送
+    pub fn enqueue(&self, event: &WebhookEvent, now: i64) -> MonoResult<Vec<Delivery>> {
+        let payload = Payload {
+            repository: &self.repository,
+            event,
+        };
+        let payload = serde_json::to_string(&payload).map_err(anyhow::Error::from)?;
+        let mut deliveries = Vec::new();
+        for webhook in self.webhooks.iter().filter(|webhook| webhook.subscribes(event.kind())) {
+            let delivery = Delivery {
+                id: format!("{:010}-{:09}-{:04x}", now, chrono::Utc::now().timestamp_subsec_nanos(), rand::random::<u16>()),
+                webhook: webhook.name.clone(),
+                event: event.kind(),
+                payload: payload.clone(),
+                created_at: now,
+                state: DeliveryState::Pending,
+                attempts: 0,
+                next_attempt_at: now,
+                last_status: None,
+                last_error: None,
+            };
+            self.save(&delivery)?;
+            deliveries.push(delivery);
+        }
+        Ok(deliveries)
+    }
+
+    /// 全部投递记录，按创建顺序排列
+    pub fn deliveries(&self) -> MonoResult<Vec<Delivery>> {
+        let entries = match std::fs::read_dir(self.deliveries_dir()) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut deliveries = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "json") {
+                deliveries.push(WebhookQueue::load(&path)?);
+            }
+        }
+        deliveries.sort_by(|a, b| a.id.cmp(&b.id));
+        Ok(deliveries)
+    }
+
+    /// 将投递重新标记为待发送，重新计算投递次数
+    pub fn redeliver(&self, id: &str, now: i64) -> MonoResult<Delivery> {
+        let path = self.path(id);
+        if !path.exists() {
+            return Err(MonoError::not_found(format!("webhook delivery {}", id)));
+        }
+        let mut delivery = WebhookQueue::load(&path)?;
+        delivery.state = DeliveryState::Pending;
+        delivery.attempts = 0;
+        delivery.next_attempt_at = now;
+        self.save(&delivery)?;
+        Ok(delivery)
+    }
+
+    /// 发送全部到期的投递，返回本次发送过的投递；同时清理过期的已送达记录
+    ///
+    /// 同一时间只允许一个进程发送，其他进程正在发送时返回 `Unavailable` 错误。
+    pub fn deliver_due(&self, sender: &dyn WebhookSender, now: i64) -> MonoResult<Vec<Delivery>> {
+        std::fs::create_dir_all(&self.dir)?;
+        let _lock = LockFile::acquire(self.dir.join(DELIVER_LOCK_FILE))?;
+        let mut attempted = Vec::new();
+        for mut delivery in self.deliveries()? {
+            match delivery.state {
+                DeliveryState::Pending if delivery.next_attempt_at <= now => {
+                    self.attempt(&mut delivery, sender, now);
+                    self.save(&delivery)?;
+                    attempted.push(delivery);
+                }
+                DeliveryState::Delivered if delivery.created_at + RETENTION_SECS < now => {
+                    std::fs::remove_file(self.path(&delivery.id))?;
+                }
+                _ => {}
+            }
+        }
+        Ok(attempted)
+    }
+
+    fn attempt(&self, delivery: &mut Delivery, sender: &dyn WebhookSender, now: i64) {
+        let Some(webhook) = self.webhooks.iter().find(|webhook| webhook.name == delivery.webhook) else {
+            delivery.state = DeliveryState::Failed;
+            delivery.last_error = Some("webhook is no longer configured".to_string());
+            return;
+        };
+        let mut headers = vec![
+            ("Content-Type", "application/json".to_string()),
+            (EVENT_HEADER, delivery.event.to_string()),
+            (DELIVERY_HEADER, delivery.id.clone()),
+        ];
+        if let Some(secret) = &webhook.secret {
+            headers.push((SIGNATURE_HEADER, sign(secret, delivery.payload.as_bytes())));
+        }
+        delivery.attempts += 1;
+        let error = match sender.send(&webhook.url, &headers, delivery.payload.as_bytes()) {
+            Ok((status, _)) if (200..300).contains(&status) => {
+                delivery.state = DeliveryState::Delivered;
+                delivery.last_status = Some(status);
+                delivery.last_error = None;
+                return;
+            }
+            Ok((status, body)) => {
+                delivery.last_status = Some(status);
+                format!("HTTP {}: {}", status, body.trim())
+            }
+            Err(e) => {
+                delivery.last_status = None;
+                e.to_string()
+            }
+        };
+        tracing::warn!(delivery = %delivery.id, webhook = %webhook.name, attempts = delivery.attempts, error = %error, "webhook delivery failed");
+        delivery.last_error = Some(error);
+        if delivery.attempts >= webhook.max_attempts {
+            delivery.state = DeliveryState::Failed;
+        } else {
+            delivery.next_attempt_at = now + retry_policy().base_backoff(delivery.attempts).as_secs() as i64;
+        }
+    }
+
+    /// 记录事件，失败只记录到日志，不影响触发事件的操作
+    pub fn notify(&self, event: &WebhookEvent) {
+        if self.is_empty() {
+            return;
+        }
+        if let Err(e) = self.enqueue(event, chrono::Utc::now().timestamp()) {
+            tracing::error!(event = %event.kind(), error = %e, "failed to enqueue webhook delivery");
+        }
+    }
+}
+
+/// 推送完成后记录 `push` 与 `ref-update` 事件
+impl Hook for WebhookQueue {
+    fn name(&self) -> &str {
+        "webhooks"
+    }
+
+    fn stage(&self) -> HookStage {
+        HookStage::PostReceive
+    }
+
+    fn run(&self, input: &HookInput) -> MonoResult<Result<(), String>> {
+        self.notify(&WebhookEvent::Push {
+            updates: input.updates.clone(),
+            push_options: input.push_options.clone(),
+        });
+        for update in &input.updates {
+            self.notify(&WebhookEvent::RefUpdate { update: update.clone() });
+        }
+        Ok(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+    use crate::object::{ObjectId, ObjectType};
+    use crate::test_utils::init_repo;
+
+    /// 测试按订阅创建投递、签名请求、失败后指数退避重试，以及超过次数后标记失败与重新投递
+    #[test]
+    fn test_deliver() {
+        let (_dir, mut repo) = init_repo();
+        let webhook = |name: &str, events: Vec<WebhookEventKind>| WebhookConfig {
+            name: name.to_string(),
+            url: format!("http://{}.example.com/hook", name),
+            secret: Some("secret".to_string()),
+            events,
+            max_attempts: 2,
+        };
+        repo.config_mut().webhooks = vec![
+            webhook("ci", vec![WebhookEventKind::Push]),
+            webhook("chat", vec![WebhookEventKind::MergeQueue]),
+        ];
+        let webhooks = WebhookQueue::new(&repo);
+        let update = RefUpdate {
+            name: "refs/heads/main".to_string(),
+            old: ObjectId::ZERO,
+            new: ObjectId::hash_object(ObjectType::Blob, b"main"),
+        };
+        let push = WebhookEvent::Push {
+            updates: vec![update.clone()],
+            push_options: Vec::new(),
+        };
+        let deliveries = webhooks.enqueue(&push, 1000).unwrap();
+        assert_eq!(deliveries.len(), 1);
+        assert_eq!(deliveries[0].webhook, "ci");
+        assert!(webhooks.enqueue(&WebhookEvent::RefUpdate { update }, 1000).unwrap().is_empty());
+        let payload: serde_json::Value = serde_json::from_str(&deliveries[0].payload).unwrap();
+        assert_eq!(payload["event"], "push");
+        assert_eq!(payload["updates"][0]["name"], "refs/heads/main");
+
+        // 第一次返回 503，第二次无法连接
+        let requests = RefCell::new(Vec::new());
+        let failing = |url: &str, headers: &[(&str, String)], body: &[u8]| -> MonoResult<(u16, String)> {
+            let signature = headers.iter().find(|(name, _)| *name == SIGNATURE_HEADER).unwrap().1.clone();
+            assert_eq!(signature, sign("secret", body));
+            requests.borrow_mut().push(url.to_string());
+            match requests.borrow().len() {
+                1 => Ok((503, "try later".to_string())),
+                _ => Err(MonoError::unavailable("connection refused")),
+            }
+        };
+        let attempted = webhooks.deliver_due(&failing, 1000).unwrap();
+        assert_eq!(attempted[0].state, DeliveryState::Pending);
+        assert_eq!(attempted[0].last_error.as_deref(), Some("HTTP 503: try later"));
+        assert_eq!(attempted[0].next_attempt_at, 1030);
+        assert!(webhooks.deliver_due(&failing, 1029).unwrap().is_empty());
+        let attempted = webhooks.deliver_due(&failing, 1030).unwrap();
+        assert_eq!(attempted[0].state, DeliveryState::Failed);
+        assert_eq!(attempted[0].attempts, 2);
+        assert_eq!(*requests.borrow(), ["http://ci.example.com/hook"; 2]);
+
+        let id = attempted[0].id.clone();
+        webhooks.redeliver(&id, 2000).unwrap();
+        let ok = |_: &str, _: &[(&str, String)], _: &[u8]| -> MonoResult<(u16, String)> { Ok((204, String::new())) };
+        let attempted = webhooks.deliver_due(&ok, 2000).unwrap();
+        assert_eq!(attempted[0].state, DeliveryState::Delivered);
+        assert_eq!(webhooks.deliveries().unwrap()[0].last_status, Some(204));
+
+        // 已送达的记录过期后被清理
+        assert!(webhooks.deliver_due(&ok, 1000 + RETENTION_SECS + 1).unwrap().is_empty());
+        assert!(webhooks.deliveries().unwrap().is_empty());
+        assert!(webhooks.redeliver(&id, 0).is_err());
+    }
+}